@@ -0,0 +1,21 @@
+// Shows notifications pushed by the Forge server. Payloads are JSON:
+// { tag, kind, title, url, createdAt }
+self.addEventListener("push", (event) => {
+	const data = event.data ? event.data.json() : {};
+	event.waitUntil(
+		self.registration.showNotification(data.title ?? "Forge", {
+			tag: data.tag,
+			body: data.kind,
+			data: { url: data.url },
+			timestamp: data.createdAt ? data.createdAt * 1000 : undefined,
+		}),
+	);
+});
+
+self.addEventListener("notificationclick", (event) => {
+	event.notification.close();
+	const url = event.notification.data?.url;
+	if (url) {
+		event.waitUntil(self.clients.openWindow(url));
+	}
+});
@@ -0,0 +1,16 @@
+const DEFAULT_ENDPOINT = "http://localhost:8000/graphql";
+
+/**
+ * Absolute URL of the preview image the API renders for a repository or an issue
+ * (`path` is `group/repo` or `group/repo/12`). Crawlers need an absolute URL, so a
+ * relative API endpoint is resolved against the page being rendered.
+ */
+export function ogImageUrl(
+	kind: "repository" | "issue",
+	path: string,
+	page: URL,
+): string {
+	const endpoint = import.meta.env.PUBLIC_FORGE_GRAPHQL_URL ?? DEFAULT_ENDPOINT;
+	const base = endpoint.replace(/\/graphql\/?$/, "");
+	return new URL(`${base}/og/${kind}/${encodeURI(path)}.png`, page).toString();
+}
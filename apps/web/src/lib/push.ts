@@ -0,0 +1,62 @@
+import { graphqlRequest } from "./graphql";
+
+const SERVICE_WORKER = "/push-sw.js";
+
+export function pushSupported(): boolean {
+	return (
+		typeof window !== "undefined" &&
+		"serviceWorker" in navigator &&
+		"PushManager" in window &&
+		"Notification" in window
+	);
+}
+
+function decodeKey(base64url: string): Uint8Array {
+	const base64 = base64url.replace(/-/g, "+").replace(/_/g, "/");
+	const raw = atob(base64.padEnd(Math.ceil(base64.length / 4) * 4, "="));
+	return Uint8Array.from(raw, (c) => c.charCodeAt(0));
+}
+
+/**
+ * Asks for permission, subscribes this browser and registers the subscription for the
+ * signed-in user. Returns false when the server has push disabled or the user declines.
+ */
+export async function enablePushNotifications(): Promise<boolean> {
+	if (!pushSupported()) return false;
+	const { webPushPublicKey } = await graphqlRequest<{
+		webPushPublicKey: string | null;
+	}>({ query: "query { webPushPublicKey }" });
+	if (!webPushPublicKey) return false;
+	if ((await Notification.requestPermission()) !== "granted") return false;
+
+	const registration = await navigator.serviceWorker.register(SERVICE_WORKER);
+	const subscription = await registration.pushManager.subscribe({
+		userVisibleOnly: true,
+		applicationServerKey: decodeKey(webPushPublicKey),
+	});
+	const { endpoint, expirationTime, keys } = subscription.toJSON();
+	await graphqlRequest({
+		query: `mutation Register($endpoint: String!, $keys: PushSubscriptionKeysInput!, $expiresAt: Int) {
+			registerPushSubscription(endpoint: $endpoint, keys: $keys, expiresAt: $expiresAt) { id }
+		}`,
+		variables: {
+			endpoint,
+			keys: { p256dh: keys?.p256dh, auth: keys?.auth },
+			expiresAt: expirationTime ? Math.floor(expirationTime / 1000) : null,
+		},
+	});
+	return true;
+}
+
+/** Unsubscribes this browser and forgets its subscription on the server. */
+export async function disablePushNotifications(): Promise<void> {
+	if (!pushSupported()) return;
+	const registration = await navigator.serviceWorker.getRegistration(SERVICE_WORKER);
+	const subscription = await registration?.pushManager.getSubscription();
+	if (!subscription) return;
+	await graphqlRequest({
+		query: "mutation Unregister($endpoint: String!) { unregisterPushSubscription(endpoint: $endpoint) }",
+		variables: { endpoint: subscription.endpoint },
+	});
+	await subscription.unsubscribe();
+}
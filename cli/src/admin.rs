@@ -0,0 +1,264 @@
+//! `forge-admin`: operator commands that call the administrator parts of the GraphQL API.
+//!
+//! Requests are sent with the personal access token in `FORGE_TOKEN`, which must belong
+//! to one of the server's `auth.admin_dids`.
+
+mod api;
+mod manifest;
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use api::{Api, TOKEN_ENV};
+
+const DEFAULT_GRAPHQL_ENDPOINT: &str = "http://localhost:8000/graphql";
+
+/// Exit status when a repository has problems, or `apply --plan` finds changes, so
+/// monitoring can tell them apart from failed requests (status 1).
+const EXIT_UNHEALTHY: u8 = 2;
+
+const REPORT_FIELDS: &str = "path healthy checkedAt refsChecked objectsChecked \
+     problems { kind oid reference detail }";
+
+#[derive(Parser)]
+#[command(name = "forge-admin")]
+#[command(about = "Forgepoint administration via the admin API", long_about = None)]
+struct Cli {
+    /// GraphQL API endpoint URL
+    #[arg(long, default_value = DEFAULT_GRAPHQL_ENDPOINT)]
+    api_url: String,
+
+    /// Print the report as JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    #[command(subcommand)]
+    Repo(RepoCommands),
+    /// Bring groups and repositories in line with a declarative manifest
+    Apply {
+        /// Manifest file (RON)
+        #[arg(short = 'f', long = "file")]
+        file: PathBuf,
+        /// Only show the changes that would be made
+        #[arg(long)]
+        plan: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum RepoCommands {
+    /// Check that every object reachable from the repository's refs is present and intact
+    Fsck {
+        /// Repository path (e.g., my-group/my-project)
+        path: String,
+    },
+    /// Refetch missing or corrupt objects from the remote cache or the mirrored upstream
+    Repair {
+        /// Repository path (e.g., my-group/my-project)
+        path: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct IntegrityReport {
+    path: String,
+    healthy: bool,
+    checked_at: i64,
+    refs_checked: u64,
+    objects_checked: u64,
+    problems: Vec<IntegrityProblem>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct IntegrityProblem {
+    kind: String,
+    oid: Option<String>,
+    reference: Option<String>,
+    detail: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RepairReport {
+    repaired: bool,
+    before: IntegrityReport,
+    after: IntegrityReport,
+    quarantined: Vec<String>,
+    attempts: Vec<RepairAttempt>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RepairAttempt {
+    source: String,
+    location: String,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FsckResponse {
+    #[serde(rename = "repositoryFsck")]
+    repository_fsck: IntegrityReport,
+}
+
+#[derive(Deserialize)]
+struct RepairResponse {
+    #[serde(rename = "repairRepository")]
+    repair_repository: RepairReport,
+}
+
+#[tokio::main]
+async fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+    let api = Api::new(&cli.api_url);
+    if std::env::var_os(TOKEN_ENV).is_none() {
+        eprintln!(
+            "note: {} is not set; admin requests will be refused",
+            TOKEN_ENV
+        );
+    }
+
+    let healthy = match cli.command {
+        Commands::Repo(RepoCommands::Fsck { path }) => fsck(&api, &path, cli.json).await?,
+        Commands::Repo(RepoCommands::Repair { path }) => repair(&api, &path, cli.json).await?,
+        Commands::Apply { file, plan } => apply(&api, &file, plan, cli.json).await?,
+    };
+    Ok(if healthy {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_UNHEALTHY)
+    })
+}
+
+/// Returns whether the server matches the manifest when done; always the case after a
+/// successful apply.
+async fn apply(api: &Api, file: &Path, plan_only: bool, as_json: bool) -> Result<bool> {
+    let manifest = manifest::load(file)?;
+    let current = manifest::fetch(api, &manifest)
+        .await
+        .with_context(|| format!("reading the current state via {} failed", api.url()))?;
+    let changes = manifest::plan(&manifest, &current)?;
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&changes)?);
+    } else if changes.is_empty() {
+        println!("✓ {} matches {}", api.url(), file.display());
+        return Ok(true);
+    } else if plan_only {
+        for change in &changes {
+            println!("{}", change);
+        }
+        println!("{} change(s) to apply", changes.len());
+    }
+    if plan_only {
+        return Ok(changes.is_empty());
+    }
+    manifest::apply(api, &current, &changes, |change| {
+        if !as_json {
+            println!("✓ {}", change);
+        }
+    })
+    .await
+    .with_context(|| format!("applying {} via {} failed", file.display(), api.url()))?;
+    Ok(true)
+}
+
+async fn fsck(api: &Api, path: &str, as_json: bool) -> Result<bool> {
+    let query = format!(
+        "query RepositoryFsck($path: String!) {{ repositoryFsck(path: $path) {{ {} }} }}",
+        REPORT_FIELDS
+    );
+    let data: FsckResponse = api
+        .execute(&query, json!({ "path": path }))
+        .await
+        .with_context(|| format!("fsck of {} via {} failed", path, api.url()))?;
+    let report = data.repository_fsck;
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report);
+    }
+    Ok(report.healthy)
+}
+
+async fn repair(api: &Api, path: &str, as_json: bool) -> Result<bool> {
+    let query = format!(
+        "mutation RepairRepository($path: String!) {{ repairRepository(path: $path) {{ \
+         repaired before {{ {fields} }} after {{ {fields} }} quarantined \
+         attempts {{ source location error }} }} }}",
+        fields = REPORT_FIELDS
+    );
+    let data: RepairResponse = api
+        .execute(&query, json!({ "path": path }))
+        .await
+        .with_context(|| format!("repair of {} via {} failed", path, api.url()))?;
+    let report = data.repair_repository;
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(report.repaired);
+    }
+
+    if report.before.healthy {
+        println!("✓ {} is healthy; nothing to repair.", report.before.path);
+        return Ok(true);
+    }
+    for oid in &report.quarantined {
+        println!("  quarantined corrupt object {}", oid);
+    }
+    if report.attempts.is_empty() {
+        println!("  no remote cache or mirror to recover from");
+    }
+    for attempt in &report.attempts {
+        match &attempt.error {
+            None => println!(
+                "  fetched from {} {}",
+                attempt.source.to_lowercase(),
+                attempt.location
+            ),
+            Some(error) => println!(
+                "  fetch from {} {} failed: {}",
+                attempt.source.to_lowercase(),
+                attempt.location,
+                error
+            ),
+        }
+    }
+    print_report(&report.after);
+    Ok(report.repaired)
+}
+
+fn print_report(report: &IntegrityReport) {
+    if report.healthy {
+        println!(
+            "✓ {}: {} objects reachable from {} refs are intact.",
+            report.path, report.objects_checked, report.refs_checked
+        );
+        return;
+    }
+    println!(
+        "✗ {}: {} problem(s) in {} objects reachable from {} refs:",
+        report.path,
+        report.problems.len(),
+        report.objects_checked,
+        report.refs_checked
+    );
+    for problem in &report.problems {
+        let oid = problem.oid.as_deref().unwrap_or("-");
+        match &problem.reference {
+            Some(reference) => println!(
+                "  {} {} (from {}): {}",
+                problem.kind, oid, reference, problem.detail
+            ),
+            None => println!("  {} {}: {}", problem.kind, oid, problem.detail),
+        }
+    }
+}
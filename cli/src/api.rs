@@ -0,0 +1,126 @@
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+struct GraphQLRequest<'a, V> {
+    query: &'a str,
+    variables: V,
+}
+
+#[derive(Deserialize, Debug)]
+struct GraphQLResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQLError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GraphQLError {
+    message: String,
+}
+
+/// Why a request failed. Only `Unreachable` is worth retrying unchanged later.
+#[derive(Debug)]
+pub enum RequestError {
+    /// The server could not be reached or is temporarily unavailable.
+    Unreachable(String),
+    /// The server answered and refused the request.
+    Rejected(String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Unreachable(message) => write!(f, "server unreachable: {}", message),
+            RequestError::Rejected(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// Environment variable holding a personal access token to send with every request.
+pub const TOKEN_ENV: &str = "FORGE_TOKEN";
+
+pub struct Api {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl Api {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            token: std::env::var(TOKEN_ENV)
+                .ok()
+                .filter(|token| !token.is_empty()),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub async fn execute<V, T>(&self, query: &str, variables: V) -> Result<T, RequestError>
+    where
+        V: Serialize,
+        T: DeserializeOwned,
+    {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json");
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .json(&GraphQLRequest { query, variables })
+            .send()
+            .await
+            .map_err(|err| {
+                if err.is_connect() || err.is_timeout() {
+                    RequestError::Unreachable(err.to_string())
+                } else {
+                    RequestError::Rejected(format!(
+                        "Failed to send request to GraphQL API: {}",
+                        err
+                    ))
+                }
+            })?;
+
+        let status = response.status();
+        if matches!(status.as_u16(), 502..=504) {
+            return Err(RequestError::Unreachable(format!("status {}", status)));
+        }
+        if !status.is_success() {
+            return Err(RequestError::Rejected(format!(
+                "GraphQL request failed with status: {}",
+                status
+            )));
+        }
+
+        let graphql_response: GraphQLResponse<T> = response.json().await.map_err(|err| {
+            RequestError::Rejected(format!("Failed to parse GraphQL response: {}", err))
+        })?;
+
+        if !graphql_response.errors.is_empty() {
+            let error_messages: Vec<String> = graphql_response
+                .errors
+                .into_iter()
+                .map(|e| e.message)
+                .collect();
+            return Err(RequestError::Rejected(format!(
+                "GraphQL errors: {}",
+                error_messages.join(", ")
+            )));
+        }
+
+        graphql_response
+            .data
+            .ok_or_else(|| RequestError::Rejected("No data returned from GraphQL".to_string()))
+    }
+}
@@ -1,6 +1,15 @@
-use anyhow::{Context, Result};
+mod api;
+mod operations;
+mod queue;
+
+use std::collections::HashSet;
+
+use anyhow::{Result, bail};
 use clap::{Parser, Subcommand};
-use serde::{Deserialize, Serialize};
+
+use api::{Api, RequestError};
+use operations::Operation;
+use queue::Queue;
 
 const DEFAULT_GRAPHQL_ENDPOINT: &str = "http://localhost:8000/graphql";
 
@@ -12,6 +21,10 @@ struct Cli {
     #[arg(long, default_value = DEFAULT_GRAPHQL_ENDPOINT)]
     api_url: String,
 
+    /// Queue mutating commands locally instead of sending them; replay with `forge sync`
+    #[arg(long)]
+    offline: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -20,6 +33,17 @@ struct Cli {
 enum Commands {
     #[command(subcommand)]
     Repo(RepoCommands),
+    #[command(subcommand)]
+    Issue(IssueCommands),
+    /// Replay queued commands against the servers they were meant for
+    Sync {
+        /// Also replay commands the server refused before
+        #[arg(long)]
+        retry_conflicts: bool,
+    },
+    /// Inspect the offline queue
+    #[command(subcommand)]
+    Queue(QueueCommands),
 }
 
 #[derive(Subcommand)]
@@ -39,65 +63,30 @@ enum RepoCommands {
     },
 }
 
-#[derive(Serialize)]
-struct GraphQLRequest<T> {
-    query: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    variables: Option<T>,
-}
-
-#[derive(Deserialize, Debug)]
-struct GraphQLResponse<T> {
-    #[serde(default)]
-    data: Option<T>,
-    #[serde(default)]
-    errors: Vec<GraphQLError>,
-}
-
-#[derive(Deserialize, Debug)]
-struct GraphQLError {
-    message: String,
-}
-
-#[derive(Serialize)]
-struct CreateRepositoryVariables {
-    input: CreateRepositoryInput,
-}
-
-#[derive(Serialize)]
-struct CreateRepositoryInput {
-    slug: String,
-    group: Option<String>,
-}
-
-#[derive(Deserialize, Debug, Default)]
-struct CreateRepositoryResponse {
-    #[serde(rename = "createRepository")]
-    create_repository: RepositoryNode,
-}
-
-#[derive(Deserialize, Debug, Default)]
-struct RepositoryNode {
-    id: String,
-    slug: String,
-    group: Option<GroupNode>,
-}
-
-#[derive(Deserialize, Debug)]
-struct GroupNode {
-    id: String,
-    slug: String,
-}
-
-#[derive(Serialize)]
-struct LinkRepositoryVariables {
-    url: String,
+#[derive(Subcommand)]
+enum IssueCommands {
+    /// Open an issue
+    Create {
+        /// Repository path (e.g., my-group/my-project)
+        repository: String,
+        /// Issue title
+        #[arg(short, long)]
+        title: String,
+        /// Issue description (Markdown)
+        #[arg(short, long)]
+        description: Option<String>,
+    },
 }
 
-#[derive(Deserialize, Debug, Default)]
-struct LinkRepositoryResponse {
-    #[serde(rename = "linkRemoteRepository")]
-    link_remote_repository: RepositoryNode,
+#[derive(Subcommand)]
+enum QueueCommands {
+    /// List queued commands
+    List,
+    /// Remove a queued command without sending it
+    Drop {
+        /// Queue entry id, as shown by `forge queue list`
+        id: u64,
+    },
 }
 
 #[tokio::main]
@@ -105,146 +94,153 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Repo(repo_cmd) => match repo_cmd {
-            RepoCommands::Create { slug, group } => {
-                create_repository(&cli.api_url, slug, group).await?
-            }
-            RepoCommands::Link { url } => link_repository(&cli.api_url, url).await?,
-        },
+        Commands::Repo(repo_cmd) => {
+            let operation = match repo_cmd {
+                RepoCommands::Create { slug, group } => Operation::CreateRepository { slug, group },
+                RepoCommands::Link { url } => Operation::LinkRepository { url },
+            };
+            submit(&cli.api_url, cli.offline, operation).await?
+        }
+        Commands::Issue(IssueCommands::Create {
+            repository,
+            title,
+            description,
+        }) => {
+            let operation = Operation::CreateIssue {
+                repository,
+                title,
+                description,
+            };
+            submit(&cli.api_url, cli.offline, operation).await?
+        }
+        Commands::Sync { retry_conflicts } => sync(retry_conflicts).await?,
+        Commands::Queue(QueueCommands::List) => list_queue()?,
+        Commands::Queue(QueueCommands::Drop { id }) => drop_queued(id)?,
     }
 
     Ok(())
 }
 
-async fn create_repository(api_url: &str, slug: String, group: Option<String>) -> Result<()> {
-    let query = r#"
-        mutation CreateRepository($input: CreateRepositoryInput!) {
-            createRepository(input: $input) {
-                id
-                slug
-                group {
-                    id
-                    slug
-                }
+/// Runs `operation`, or queues it when offline or when the server cannot be reached.
+async fn submit(api_url: &str, offline: bool, operation: Operation) -> Result<()> {
+    let path = Queue::default_path()?;
+    let mut queue = Queue::load(&path)?;
+
+    if !offline {
+        let pending = queue
+            .entries()
+            .iter()
+            .filter(|entry| entry.api_url == api_url)
+            .count();
+        if pending > 0 {
+            eprintln!(
+                "note: {} queued command(s) for this server have not been synced yet",
+                pending
+            );
+        }
+        match operation.run(&Api::new(api_url)).await {
+            Ok(report) => {
+                println!("{}", report);
+                return Ok(());
+            }
+            Err(RequestError::Unreachable(message)) => {
+                eprintln!("Server unreachable ({}).", message);
             }
+            Err(err) => return Err(err.into()),
         }
-    "#;
-
-    let variables = CreateRepositoryVariables {
-        input: CreateRepositoryInput { slug, group },
-    };
-
-    let request = GraphQLRequest {
-        query: query.to_string(),
-        variables: Some(variables),
-    };
-
-    let client = reqwest::Client::new();
-    let response = client
-        .post(api_url)
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .context("Failed to send request to GraphQL API")?;
-
-    if !response.status().is_success() {
-        return Err(anyhow::anyhow!(
-            "GraphQL request failed with status: {}",
-            response.status()
-        ));
     }
 
-    let graphql_response: GraphQLResponse<CreateRepositoryResponse> = response
-        .json()
-        .await
-        .context("Failed to parse GraphQL response")?;
+    let id = queue.push(api_url, operation);
+    queue.save()?;
+    println!("⧗ Queued as #{}. Run `forge sync` to send it.", id);
+    Ok(())
+}
 
-    if !graphql_response.errors.is_empty() {
-        let error_messages: Vec<String> = graphql_response
-            .errors
-            .iter()
-            .map(|e| e.message.clone())
-            .collect();
-        return Err(anyhow::anyhow!(
-            "GraphQL errors: {}",
-            error_messages.join(", ")
-        ));
+async fn sync(retry_conflicts: bool) -> Result<()> {
+    let path = Queue::default_path()?;
+    let mut queue = Queue::load(&path)?;
+    if queue.entries().is_empty() {
+        println!("Nothing to sync.");
+        return Ok(());
     }
 
-    let data = graphql_response
-        .data
-        .context("No data returned from GraphQL")?;
-    let repo = data.create_repository;
+    let mut unreachable = HashSet::new();
+    let (mut replayed, mut conflicts, mut waiting) = (0, 0, 0);
+    for entry in queue.entries().to_vec() {
+        if entry.conflict.is_some() && !retry_conflicts {
+            conflicts += 1;
+            continue;
+        }
+        // Later commands may depend on earlier ones, so a server that is down keeps
+        // everything queued for it in order.
+        if unreachable.contains(&entry.api_url) {
+            waiting += 1;
+            continue;
+        }
 
-    println!("✓ Repository created successfully!");
-    println!("  ID:   {}", repo.id);
-    println!("  Slug: {}", repo.slug);
-    if let Some(group) = repo.group {
-        println!("  Group: {} ({})", group.slug, group.id);
+        let api = Api::new(&entry.api_url);
+        match entry.operation.run(&api).await {
+            Ok(report) => {
+                println!("#{} {}", entry.id, entry.operation);
+                println!("{}", report);
+                queue.remove(entry.id);
+                replayed += 1;
+            }
+            Err(RequestError::Unreachable(message)) => {
+                eprintln!(
+                    "#{} {}: {} is unreachable ({})",
+                    entry.id,
+                    entry.operation,
+                    api.url(),
+                    message
+                );
+                unreachable.insert(entry.api_url.clone());
+                waiting += 1;
+            }
+            Err(RequestError::Rejected(message)) => {
+                eprintln!("✗ #{} {}: conflict: {}", entry.id, entry.operation, message);
+                queue.set_conflict(entry.id, Some(message));
+                conflicts += 1;
+            }
+        }
+        // Saved after every entry, so an interrupted sync never replays a command twice.
+        queue.save()?;
     }
 
+    println!("Replayed {} queued command(s).", replayed);
+    if conflicts > 0 || waiting > 0 {
+        bail!(
+            "{} conflict(s) and {} command(s) waiting for their server; see `forge queue list`",
+            conflicts,
+            waiting
+        );
+    }
     Ok(())
 }
 
-async fn link_repository(api_url: &str, url: String) -> Result<()> {
-    let query = r#"
-        mutation LinkRemoteRepository($url: String!) {
-            linkRemoteRepository(url: $url) {
-                id
-                slug
-            }
-        }
-    "#;
-
-    let variables = LinkRepositoryVariables { url };
-
-    let request = GraphQLRequest {
-        query: query.to_string(),
-        variables: Some(variables),
-    };
-
-    let client = reqwest::Client::new();
-    let response = client
-        .post(api_url)
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .context("Failed to send request to GraphQL API")?;
-
-    if !response.status().is_success() {
-        return Err(anyhow::anyhow!(
-            "GraphQL request failed with status: {}",
-            response.status()
-        ));
+fn list_queue() -> Result<()> {
+    let path = Queue::default_path()?;
+    let queue = Queue::load(&path)?;
+    if queue.entries().is_empty() {
+        println!("The offline queue is empty.");
+        return Ok(());
     }
-
-    let graphql_response: GraphQLResponse<LinkRepositoryResponse> = response
-        .json()
-        .await
-        .context("Failed to parse GraphQL response")?;
-
-    if !graphql_response.errors.is_empty() {
-        let error_messages: Vec<String> = graphql_response
-            .errors
-            .iter()
-            .map(|e| e.message.clone())
-            .collect();
-        return Err(anyhow::anyhow!(
-            "GraphQL errors: {}",
-            error_messages.join(", ")
-        ));
+    for entry in queue.entries() {
+        println!("#{} {}  ({})", entry.id, entry.operation, entry.api_url);
+        if let Some(conflict) = &entry.conflict {
+            println!("  conflict: {}", conflict);
+        }
     }
+    Ok(())
+}
 
-    let data = graphql_response
-        .data
-        .context("No data returned from GraphQL")?;
-    let repo = data.link_remote_repository;
-
-    println!("✓ Remote repository linked successfully!");
-    println!("  ID:   {}", repo.id);
-    println!("  Slug: {}", repo.slug);
-
+fn drop_queued(id: u64) -> Result<()> {
+    let path = Queue::default_path()?;
+    let mut queue = Queue::load(&path)?;
+    let Some(entry) = queue.remove(id) else {
+        bail!("no queued command #{}", id);
+    };
+    queue.save()?;
+    println!("Dropped #{} {}", entry.id, entry.operation);
     Ok(())
 }
@@ -0,0 +1,783 @@
+//! Declarative server configuration for `forge-admin apply`.
+//!
+//! A manifest is a RON file listing groups and repositories with the settings they should
+//! have. [`plan`] compares it with what the server reports and returns the mutations that
+//! bring the server in line, so applying the same manifest twice changes nothing the
+//! second time. Groups and repositories the manifest does not mention are left alone;
+//! nothing is ever deleted.
+//!
+//! A group's settings are its own defaults and are declared in full: a setting the
+//! manifest leaves out is cleared, so the group inherits it from its parent again. A
+//! repository setting the manifest leaves out is not managed and keeps whatever value the
+//! repository has.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::Api;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub groups: Vec<GroupSpec>,
+    #[serde(default)]
+    pub repositories: Vec<RepositorySpec>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GroupSpec {
+    /// Full path, e.g. `platform/tools`. The parent group must exist or be listed too.
+    pub path: String,
+    #[serde(default)]
+    pub labels: Option<Vec<Label>>,
+    #[serde(default)]
+    pub branch_protection: Option<BranchProtection>,
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RepositorySpec {
+    /// Full path, e.g. `platform/tools/cli`.
+    pub path: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub labels: Option<Vec<Label>>,
+    #[serde(default)]
+    pub branch_protection: Option<BranchProtection>,
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Label {
+    pub name: String,
+    pub color: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl Label {
+    /// Compares the way the server stores labels: trimmed, colours in lowercase.
+    fn same_as(&self, other: &Label) -> bool {
+        let description = |label: &Label| {
+            label
+                .description
+                .as_deref()
+                .map(str::trim)
+                .filter(|d| !d.is_empty())
+                .map(str::to_string)
+        };
+        self.name.trim() == other.name.trim()
+            && self.color.trim().eq_ignore_ascii_case(other.color.trim())
+            && description(self) == description(other)
+    }
+}
+
+fn same_labels(a: &[Label], b: &[Label]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.same_as(b))
+}
+
+/// The push policy of a repository or the template of a group, in the server's RON
+/// policy format.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BranchProtection {
+    pub max_file_size: Option<u64>,
+    pub forbidden_paths: Vec<String>,
+    pub commit_message_pattern: Option<String>,
+    pub branch_name_pattern: Option<String>,
+    pub require_signed_commits: bool,
+    pub forbid_merge_commits: bool,
+}
+
+impl BranchProtection {
+    fn source(&self) -> Result<String> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .context("failed to encode branch protection")
+    }
+}
+
+/// Who can see a repository; the only access control the admin API has.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    #[serde(alias = "PUBLIC")]
+    Public,
+    #[serde(alias = "PRIVATE")]
+    Private,
+}
+
+impl Visibility {
+    fn as_graphql(self) -> &'static str {
+        match self {
+            Visibility::Public => "PUBLIC",
+            Visibility::Private => "PRIVATE",
+        }
+    }
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Visibility::Public => "public",
+            Visibility::Private => "private",
+        })
+    }
+}
+
+pub fn load(path: &Path) -> Result<Manifest> {
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let manifest: Manifest = ron::from_str(&source)
+        .with_context(|| format!("{} is not a valid manifest", path.display()))?;
+    manifest.validate()?;
+    Ok(manifest)
+}
+
+impl Manifest {
+    fn validate(&self) -> Result<()> {
+        let mut seen = HashSet::new();
+        let paths = self.groups.iter().map(|g| ("group", &g.path));
+        let paths = paths.chain(self.repositories.iter().map(|r| ("repository", &r.path)));
+        for (kind, path) in paths {
+            if path.is_empty() || path.split('/').any(|segment| segment.trim().is_empty()) {
+                bail!("{} path {:?} is not valid", kind, path);
+            }
+            if !seen.insert((kind, path.as_str())) {
+                bail!("{} {} is listed more than once", kind, path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Every group the plan needs to know about: the listed ones and the parents of all
+    /// listed groups and repositories.
+    fn group_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = Vec::new();
+        let listed = self.groups.iter().map(|g| g.path.as_str());
+        let parents = self
+            .groups
+            .iter()
+            .map(|g| g.path.as_str())
+            .chain(self.repositories.iter().map(|r| r.path.as_str()))
+            .filter_map(parent_of);
+        for path in listed.chain(parents) {
+            let mut prefix = String::new();
+            for segment in path.split('/') {
+                if !prefix.is_empty() {
+                    prefix.push('/');
+                }
+                prefix.push_str(segment);
+                if !paths.contains(&prefix) {
+                    paths.push(prefix.clone());
+                }
+            }
+        }
+        paths
+    }
+}
+
+fn parent_of(path: &str) -> Option<&str> {
+    path.rsplit_once('/').map(|(parent, _)| parent)
+}
+
+fn slug_of(path: &str) -> &str {
+    path.rsplit_once('/').map_or(path, |(_, slug)| slug)
+}
+
+/// A group's own settings; `None` inherits from the parent group.
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupSettings {
+    pub labels: Option<Vec<Label>>,
+    pub branch_protection: Option<BranchProtection>,
+    pub visibility: Option<Visibility>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GroupState {
+    pub id: String,
+    pub settings: GroupSettings,
+}
+
+#[derive(Debug, Clone)]
+pub struct RepositoryState {
+    /// The description without a language.
+    pub description: Option<String>,
+    pub labels: Vec<Label>,
+    pub branch_protection: BranchProtection,
+    pub visibility: Visibility,
+}
+
+/// What the server has for the paths a manifest mentions. Paths that are missing do not
+/// exist on the server.
+#[derive(Debug, Default)]
+pub struct Current {
+    pub groups: HashMap<String, GroupState>,
+    pub repositories: HashMap<String, RepositoryState>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum Change {
+    CreateGroup {
+        path: String,
+    },
+    SetGroupSettings {
+        path: String,
+        settings: GroupSettings,
+        /// The settings that differ, for the plan.
+        changed: Vec<String>,
+    },
+    CreateRepository {
+        path: String,
+    },
+    SetRepositoryDescription {
+        path: String,
+        description: Option<String>,
+    },
+    SetRepositorySettings {
+        path: String,
+        labels: Option<Vec<Label>>,
+        visibility: Option<Visibility>,
+    },
+    SetBranchProtection {
+        path: String,
+        rules: BranchProtection,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::CreateGroup { path } => write!(f, "+ group {}", path),
+            Change::SetGroupSettings { path, changed, .. } => {
+                write!(f, "~ group {}: {}", path, changed.join(", "))
+            }
+            Change::CreateRepository { path } => write!(f, "+ repository {}", path),
+            Change::SetRepositoryDescription { path, description } => match description {
+                Some(description) => {
+                    write!(f, "~ repository {}: description {:?}", path, description)
+                }
+                None => write!(f, "~ repository {}: description cleared", path),
+            },
+            Change::SetRepositorySettings {
+                path,
+                labels,
+                visibility,
+            } => {
+                let mut changed = Vec::new();
+                if let Some(labels) = labels {
+                    changed.push(format!("labels ({})", labels.len()));
+                }
+                if let Some(visibility) = visibility {
+                    changed.push(format!("visibility {}", visibility));
+                }
+                write!(f, "~ repository {}: {}", path, changed.join(", "))
+            }
+            Change::SetBranchProtection { path, .. } => {
+                write!(f, "~ repository {}: branch protection", path)
+            }
+        }
+    }
+}
+
+/// The changes that make the server match `manifest`, parents before children.
+pub fn plan(manifest: &Manifest, current: &Current) -> Result<Vec<Change>> {
+    let mut changes = Vec::new();
+    let listed: HashSet<&str> = manifest.groups.iter().map(|g| g.path.as_str()).collect();
+    let exists = |path: &str| current.groups.contains_key(path) || listed.contains(path);
+
+    let mut groups: Vec<&GroupSpec> = manifest.groups.iter().collect();
+    groups.sort_by_key(|g| g.path.matches('/').count());
+    for group in groups {
+        if let Some(parent) = parent_of(&group.path)
+            && !exists(parent)
+        {
+            bail!("group {} needs its parent group {}", group.path, parent);
+        }
+        let desired = GroupSettings {
+            labels: group.labels.clone(),
+            branch_protection: group.branch_protection.clone(),
+            visibility: group.visibility,
+        };
+        let have = match current.groups.get(&group.path) {
+            Some(state) => state.settings.clone(),
+            None => {
+                changes.push(Change::CreateGroup {
+                    path: group.path.clone(),
+                });
+                GroupSettings::default()
+            }
+        };
+        let mut changed = Vec::new();
+        let labels_match = match (&desired.labels, &have.labels) {
+            (Some(a), Some(b)) => same_labels(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        if !labels_match {
+            changed.push(match &desired.labels {
+                Some(labels) => format!("labels ({})", labels.len()),
+                None => "labels inherited".to_string(),
+            });
+        }
+        if desired.branch_protection != have.branch_protection {
+            changed.push(match &desired.branch_protection {
+                Some(_) => "branch protection".to_string(),
+                None => "branch protection inherited".to_string(),
+            });
+        }
+        if desired.visibility != have.visibility {
+            changed.push(match desired.visibility {
+                Some(visibility) => format!("visibility {}", visibility),
+                None => "visibility inherited".to_string(),
+            });
+        }
+        if !changed.is_empty() {
+            changes.push(Change::SetGroupSettings {
+                path: group.path.clone(),
+                settings: desired,
+                changed,
+            });
+        }
+    }
+
+    for repo in &manifest.repositories {
+        if let Some(group) = parent_of(&repo.path)
+            && !exists(group)
+        {
+            bail!("repository {} needs its group {}", repo.path, group);
+        }
+        let have = current.repositories.get(&repo.path);
+        if have.is_none() {
+            changes.push(Change::CreateRepository {
+                path: repo.path.clone(),
+            });
+        }
+
+        if let Some(description) = &repo.description {
+            let description = Some(description.trim().to_string()).filter(|d| !d.is_empty());
+            if have.is_none_or(|have| have.description != description) {
+                changes.push(Change::SetRepositoryDescription {
+                    path: repo.path.clone(),
+                    description,
+                });
+            }
+        }
+        let labels = repo
+            .labels
+            .clone()
+            .filter(|labels| have.is_none_or(|have| !same_labels(labels, &have.labels)));
+        let visibility = repo
+            .visibility
+            .filter(|visibility| have.is_none_or(|have| have.visibility != *visibility));
+        if labels.is_some() || visibility.is_some() {
+            changes.push(Change::SetRepositorySettings {
+                path: repo.path.clone(),
+                labels,
+                visibility,
+            });
+        }
+        if let Some(rules) = &repo.branch_protection
+            && have.is_none_or(|have| have.branch_protection != *rules)
+        {
+            changes.push(Change::SetBranchProtection {
+                path: repo.path.clone(),
+                rules: rules.clone(),
+            });
+        }
+    }
+    Ok(changes)
+}
+
+const LABEL_FIELDS: &str = "labels { name color description }";
+const POLICY_FIELDS: &str = "branchProtection { maxFileSize forbiddenPaths commitMessagePattern \
+     branchNamePattern requireSignedCommits forbidMergeCommits }";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PolicyNode {
+    max_file_size: Option<u64>,
+    forbidden_paths: Vec<String>,
+    commit_message_pattern: Option<String>,
+    branch_name_pattern: Option<String>,
+    require_signed_commits: bool,
+    forbid_merge_commits: bool,
+}
+
+impl From<PolicyNode> for BranchProtection {
+    fn from(node: PolicyNode) -> Self {
+        BranchProtection {
+            max_file_size: node.max_file_size,
+            forbidden_paths: node.forbidden_paths,
+            commit_message_pattern: node.commit_message_pattern,
+            branch_name_pattern: node.branch_name_pattern,
+            require_signed_commits: node.require_signed_commits,
+            forbid_merge_commits: node.forbid_merge_commits,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GroupSettingsNode {
+    labels: Option<Vec<Label>>,
+    branch_protection: Option<PolicyNode>,
+    visibility: Option<Visibility>,
+}
+
+#[derive(Deserialize)]
+struct GroupNode {
+    id: String,
+    settings: GroupSettingsNode,
+}
+
+#[derive(Deserialize)]
+struct GroupResponse {
+    #[serde(rename = "getGroup")]
+    group: Option<GroupNode>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EffectiveSettingsNode {
+    labels: Vec<Label>,
+    branch_protection: PolicyNode,
+    visibility: Visibility,
+}
+
+#[derive(Deserialize)]
+struct DescriptionNode {
+    language: Option<String>,
+    description: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RepositoryNode {
+    descriptions: Vec<DescriptionNode>,
+    effective_settings: EffectiveSettingsNode,
+}
+
+#[derive(Deserialize)]
+struct RepositoryResponse {
+    #[serde(rename = "getRepository")]
+    repository: Option<RepositoryNode>,
+}
+
+#[derive(Deserialize)]
+struct CreatedNode {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct CreateGroupResponse {
+    #[serde(rename = "createGroup")]
+    group: CreatedNode,
+}
+
+/// Reads the current state of every group and repository `manifest` mentions.
+pub async fn fetch(api: &Api, manifest: &Manifest) -> Result<Current> {
+    let mut current = Current::default();
+    let query = format!(
+        "query ManifestGroup($path: String!) {{ getGroup(path: $path) {{ id settings {{ {} {} visibility }} }} }}",
+        LABEL_FIELDS, POLICY_FIELDS
+    );
+    for path in manifest.group_paths() {
+        let data: GroupResponse = api
+            .execute(&query, json!({ "path": path }))
+            .await
+            .with_context(|| format!("failed to read group {}", path))?;
+        if let Some(group) = data.group {
+            let settings = GroupSettings {
+                labels: group.settings.labels,
+                branch_protection: group.settings.branch_protection.map(Into::into),
+                visibility: group.settings.visibility,
+            };
+            current.groups.insert(
+                path,
+                GroupState {
+                    id: group.id,
+                    settings,
+                },
+            );
+        }
+    }
+
+    let query = format!(
+        "query ManifestRepository($path: String!) {{ getRepository(path: $path) {{ \
+         descriptions {{ language description }} effectiveSettings {{ {} {} visibility }} }} }}",
+        LABEL_FIELDS, POLICY_FIELDS
+    );
+    for repo in &manifest.repositories {
+        let data: RepositoryResponse = api
+            .execute(&query, json!({ "path": repo.path }))
+            .await
+            .with_context(|| format!("failed to read repository {}", repo.path))?;
+        if let Some(node) = data.repository {
+            let description = node
+                .descriptions
+                .into_iter()
+                .find(|d| d.language.is_none())
+                .map(|d| d.description);
+            let settings = node.effective_settings;
+            current.repositories.insert(
+                repo.path.clone(),
+                RepositoryState {
+                    description,
+                    labels: settings.labels,
+                    branch_protection: settings.branch_protection.into(),
+                    visibility: settings.visibility,
+                },
+            );
+        }
+    }
+    Ok(current)
+}
+
+/// Runs `changes` in order, stopping at the first one the server refuses because later
+/// changes may depend on it.
+pub async fn apply(
+    api: &Api,
+    current: &Current,
+    changes: &[Change],
+    mut on_applied: impl FnMut(&Change),
+) -> Result<()> {
+    let mut group_ids: HashMap<String, String> = current
+        .groups
+        .iter()
+        .map(|(path, group)| (path.clone(), group.id.clone()))
+        .collect();
+    let group_id = |ids: &HashMap<String, String>, path: &str| -> Result<Option<String>> {
+        match parent_of(path) {
+            None => Ok(None),
+            Some(parent) => ids
+                .get(parent)
+                .cloned()
+                .map(Some)
+                .with_context(|| format!("group {} was not found", parent)),
+        }
+    };
+
+    for change in changes {
+        match change {
+            Change::CreateGroup { path } => {
+                let parent = group_id(&group_ids, path)?;
+                let data: CreateGroupResponse = api
+                    .execute(
+                        "mutation CreateGroup($input: CreateGroupInput!) { createGroup(input: $input) { id } }",
+                        json!({ "input": { "slug": slug_of(path), "parent": parent } }),
+                    )
+                    .await?;
+                group_ids.insert(path.clone(), data.group.id);
+            }
+            Change::SetGroupSettings { path, settings, .. } => {
+                let branch_protection = settings
+                    .branch_protection
+                    .as_ref()
+                    .map(BranchProtection::source)
+                    .transpose()?;
+                let _: serde_json::Value = api
+                    .execute(
+                        "mutation SetGroupSettings($path: String!, $settings: GroupSettingsInput!) { \
+                         setGroupSettings(path: $path, settings: $settings) { id } }",
+                        json!({
+                            "path": path,
+                            "settings": {
+                                "labels": settings.labels,
+                                "branchProtection": branch_protection,
+                                "visibility": settings.visibility.map(Visibility::as_graphql),
+                            },
+                        }),
+                    )
+                    .await?;
+            }
+            Change::CreateRepository { path } => {
+                let group = group_id(&group_ids, path)?;
+                let _: serde_json::Value = api
+                    .execute(
+                        "mutation CreateRepository($input: CreateRepositoryInput!) { \
+                         createRepository(input: $input) { id } }",
+                        json!({ "input": { "slug": slug_of(path), "group": group } }),
+                    )
+                    .await?;
+            }
+            Change::SetRepositoryDescription { path, description } => {
+                let _: serde_json::Value = api
+                    .execute(
+                        "mutation SetRepositoryDescription($path: String!, $description: String) { \
+                         setRepositoryDescription(path: $path, description: $description) { id } }",
+                        json!({ "path": path, "description": description }),
+                    )
+                    .await?;
+            }
+            Change::SetRepositorySettings {
+                path,
+                labels,
+                visibility,
+            } => {
+                let _: serde_json::Value = api
+                    .execute(
+                        "mutation SetRepositorySettings($path: String!, $labels: [LabelInput!], \
+                         $visibility: RepositoryVisibility) { setRepositorySettings(path: $path, \
+                         labels: $labels, visibility: $visibility) { id } }",
+                        json!({
+                            "path": path,
+                            "labels": labels,
+                            "visibility": visibility.map(Visibility::as_graphql),
+                        }),
+                    )
+                    .await?;
+            }
+            Change::SetBranchProtection { path, rules } => {
+                let _: serde_json::Value = api
+                    .execute(
+                        "mutation SetRepositoryPolicy($path: String!, $rules: String!) { \
+                         setRepositoryPolicy(path: $path, rules: $rules) { source } }",
+                        json!({ "path": path, "rules": rules.source()? }),
+                    )
+                    .await?;
+            }
+        }
+        on_applied(change);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r##"(
+        groups: [
+            (path: "platform/tools"),
+            (
+                path: "platform",
+                visibility: Some(Private),
+                labels: Some([(name: "bug", color: "#D73A4A")]),
+            ),
+        ],
+        repositories: [
+            (
+                path: "platform/tools/cli",
+                description: Some("Command line tools"),
+                branch_protection: Some((max_file_size: Some(1048576), forbid_merge_commits: true)),
+            ),
+        ],
+    )"##;
+
+    fn manifest() -> Manifest {
+        let manifest: Manifest = ron::from_str(MANIFEST).unwrap();
+        manifest.validate().unwrap();
+        manifest
+    }
+
+    #[test]
+    fn an_empty_server_gets_everything_parents_first() {
+        let changes = plan(&manifest(), &Current::default()).unwrap();
+        let lines: Vec<String> = changes.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "+ group platform",
+                "~ group platform: labels (1), visibility private",
+                "+ group platform/tools",
+                "+ repository platform/tools/cli",
+                "~ repository platform/tools/cli: description \"Command line tools\"",
+                "~ repository platform/tools/cli: branch protection",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_server_that_matches_needs_no_changes() {
+        let manifest = manifest();
+        let mut current = Current::default();
+        current.groups.insert(
+            "platform".into(),
+            GroupState {
+                id: "g1".into(),
+                settings: GroupSettings {
+                    labels: Some(vec![Label {
+                        name: "bug".into(),
+                        color: "#d73a4a".into(),
+                        description: None,
+                    }]),
+                    branch_protection: None,
+                    visibility: Some(Visibility::Private),
+                },
+            },
+        );
+        current.groups.insert(
+            "platform/tools".into(),
+            GroupState {
+                id: "g2".into(),
+                settings: GroupSettings::default(),
+            },
+        );
+        let repository = RepositoryState {
+            description: Some("Command line tools".into()),
+            labels: Vec::new(),
+            branch_protection: BranchProtection {
+                max_file_size: Some(1048576),
+                forbid_merge_commits: true,
+                ..BranchProtection::default()
+            },
+            visibility: Visibility::Public,
+        };
+        current
+            .repositories
+            .insert("platform/tools/cli".into(), repository.clone());
+        assert!(plan(&manifest, &current).unwrap().is_empty());
+
+        // Only the drifted setting is planned; group settings the manifest leaves out are
+        // handed back to the parent.
+        current.repositories.insert(
+            "platform/tools/cli".into(),
+            RepositoryState {
+                branch_protection: BranchProtection::default(),
+                ..repository
+            },
+        );
+        current
+            .groups
+            .get_mut("platform/tools")
+            .unwrap()
+            .settings
+            .visibility = Some(Visibility::Public);
+        let lines: Vec<String> = plan(&manifest, &current)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "~ group platform/tools: visibility inherited",
+                "~ repository platform/tools/cli: branch protection",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_parents_and_duplicates_are_rejected() {
+        let orphan: Manifest = ron::from_str(r#"(repositories: [(path: "nowhere/app")])"#).unwrap();
+        assert!(plan(&orphan, &Current::default()).is_err());
+
+        let twice: Manifest =
+            ron::from_str(r#"(groups: [(path: "a"), (path: "a")], repositories: [(path: "a")])"#)
+                .unwrap();
+        assert!(twice.validate().is_err());
+
+        assert!(ron::from_str::<Manifest>(r#"(groups: [(path: "a", acl: [])])"#).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+//! Mutating commands, in a form that can be run now or queued and replayed later.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::{Api, RequestError};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Operation {
+    CreateRepository {
+        slug: String,
+        group: Option<String>,
+    },
+    LinkRepository {
+        url: String,
+    },
+    CreateIssue {
+        /// Repository path, resolved when the operation runs so that an issue can be
+        /// queued for a repository whose creation is still queued.
+        repository: String,
+        title: String,
+        description: Option<String>,
+    },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::CreateRepository { slug, group: None } => {
+                write!(f, "repo create {}", slug)
+            }
+            Operation::CreateRepository {
+                slug,
+                group: Some(group),
+            } => write!(f, "repo create {} --group {}", slug, group),
+            Operation::LinkRepository { url } => write!(f, "repo link {}", url),
+            Operation::CreateIssue {
+                repository, title, ..
+            } => write!(f, "issue create {} {:?}", repository, title),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct RepositoryNode {
+    id: String,
+    slug: String,
+    group: Option<GroupNode>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GroupNode {
+    id: String,
+    slug: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct CreateRepositoryResponse {
+    #[serde(rename = "createRepository")]
+    create_repository: RepositoryNode,
+}
+
+#[derive(Deserialize, Debug)]
+struct LinkRepositoryResponse {
+    #[serde(rename = "linkRemoteRepository")]
+    link_remote_repository: RepositoryNode,
+}
+
+#[derive(Deserialize, Debug)]
+struct GetRepositoryResponse {
+    #[serde(rename = "getRepository")]
+    get_repository: Option<RepositoryNode>,
+}
+
+#[derive(Deserialize, Debug)]
+struct IssueNode {
+    id: String,
+    number: i64,
+    title: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct CreateIssueResponse {
+    #[serde(rename = "createIssue")]
+    create_issue: IssueNode,
+}
+
+impl Operation {
+    /// Runs the operation and returns the report to print.
+    pub async fn run(&self, api: &Api) -> Result<String, RequestError> {
+        match self {
+            Operation::CreateRepository { slug, group } => {
+                let query = r#"
+                    mutation CreateRepository($input: CreateRepositoryInput!) {
+                        createRepository(input: $input) {
+                            id
+                            slug
+                            group {
+                                id
+                                slug
+                            }
+                        }
+                    }
+                "#;
+                let data: CreateRepositoryResponse = api
+                    .execute(query, json!({ "input": { "slug": slug, "group": group } }))
+                    .await?;
+                let repo = data.create_repository;
+                let mut report = format!(
+                    "✓ Repository created successfully!\n  ID:   {}\n  Slug: {}",
+                    repo.id, repo.slug
+                );
+                if let Some(group) = repo.group {
+                    report.push_str(&format!("\n  Group: {} ({})", group.slug, group.id));
+                }
+                Ok(report)
+            }
+            Operation::LinkRepository { url } => {
+                let query = r#"
+                    mutation LinkRemoteRepository($url: String!) {
+                        linkRemoteRepository(url: $url) {
+                            id
+                            slug
+                        }
+                    }
+                "#;
+                let data: LinkRepositoryResponse =
+                    api.execute(query, json!({ "url": url })).await?;
+                let repo = data.link_remote_repository;
+                Ok(format!(
+                    "✓ Remote repository linked successfully!\n  ID:   {}\n  Slug: {}",
+                    repo.id, repo.slug
+                ))
+            }
+            Operation::CreateIssue {
+                repository,
+                title,
+                description,
+            } => {
+                let query = r#"
+                    query GetRepository($path: String!) {
+                        getRepository(path: $path) {
+                            id
+                            slug
+                        }
+                    }
+                "#;
+                let data: GetRepositoryResponse =
+                    api.execute(query, json!({ "path": repository })).await?;
+                let repo = data.get_repository.ok_or_else(|| {
+                    RequestError::Rejected(format!("repository {} not found", repository))
+                })?;
+
+                let mutation = r#"
+                    mutation CreateIssue($repositoryId: ID!, $input: CreateIssueInput!) {
+                        createIssue(repositoryId: $repositoryId, input: $input) {
+                            id
+                            number
+                            title
+                        }
+                    }
+                "#;
+                let variables = json!({
+                    "repositoryId": repo.id,
+                    "input": { "title": title, "description": description },
+                });
+                let data: CreateIssueResponse = api.execute(mutation, variables).await?;
+                let issue = data.create_issue;
+                Ok(format!(
+                    "✓ Issue created successfully!\n  Issue: {}#{}\n  ID:    {}\n  Title: {}",
+                    repository, issue.number, issue.id, issue.title
+                ))
+            }
+        }
+    }
+}
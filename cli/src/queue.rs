@@ -0,0 +1,175 @@
+//! Offline queue of mutating commands.
+//!
+//! A command that cannot reach the server, or that runs with `--offline`, is appended
+//! to a JSON file together with the API URL it was meant for. `forge sync` replays the
+//! queue in order. An entry the server refuses is kept as a conflict and reported; it is
+//! skipped by later syncs until it is retried with `--retry-conflicts` or dropped with
+//! `forge queue drop`.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::operations::Operation;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub id: u64,
+    pub api_url: String,
+    /// Unix seconds.
+    pub queued_at: u64,
+    pub operation: Operation,
+    /// Why the server refused the last replay.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conflict: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct QueueFile {
+    next_id: u64,
+    entries: Vec<Entry>,
+}
+
+pub struct Queue {
+    path: PathBuf,
+    file: QueueFile,
+}
+
+impl Queue {
+    /// `FORGE_QUEUE_FILE`, or `forge/queue.json` in the user's data directory.
+    pub fn default_path() -> Result<PathBuf> {
+        if let Some(path) = std::env::var_os("FORGE_QUEUE_FILE") {
+            return Ok(PathBuf::from(path));
+        }
+        let data_dir = match std::env::var_os("XDG_DATA_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(|home| PathBuf::from(home).join(".local").join("share"))
+                .context("cannot locate the offline queue: set FORGE_QUEUE_FILE or HOME")?,
+        };
+        Ok(data_dir.join("forge").join("queue.json"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("offline queue {} is corrupt", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => QueueFile::default(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read offline queue {}", path.display()));
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    /// Writes the queue through a temporary file, so an interrupted write never loses
+    /// queued commands.
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&self.file)?)
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("failed to write {}", self.path.display()))?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.file.entries
+    }
+
+    pub fn push(&mut self, api_url: &str, operation: Operation) -> u64 {
+        self.file.next_id += 1;
+        let queued_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.file.entries.push(Entry {
+            id: self.file.next_id,
+            api_url: api_url.to_string(),
+            queued_at,
+            operation,
+            conflict: None,
+        });
+        self.file.next_id
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<Entry> {
+        let index = self.file.entries.iter().position(|entry| entry.id == id)?;
+        Some(self.file.entries.remove(index))
+    }
+
+    pub fn set_conflict(&mut self, id: u64, conflict: Option<String>) {
+        if let Some(entry) = self.file.entries.iter_mut().find(|entry| entry.id == id) {
+            entry.conflict = conflict;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("forge-queue-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join("queue.json")
+    }
+
+    #[test]
+    fn entries_survive_a_reload_in_order() {
+        let path = scratch_path("reload");
+        let mut queue = Queue::load(&path).unwrap();
+        assert!(queue.entries().is_empty());
+
+        let repo = Operation::CreateRepository {
+            slug: "app".to_string(),
+            group: None,
+        };
+        let issue = Operation::CreateIssue {
+            repository: "app".to_string(),
+            title: "Crash on start".to_string(),
+            description: None,
+        };
+        assert_eq!(queue.push("http://forge.test/graphql", repo.clone()), 1);
+        assert_eq!(queue.push("http://forge.test/graphql", issue.clone()), 2);
+        queue.set_conflict(2, Some("repository app not found".to_string()));
+        queue.save().unwrap();
+
+        let mut queue = Queue::load(&path).unwrap();
+        let operations: Vec<&Operation> = queue.entries().iter().map(|e| &e.operation).collect();
+        assert_eq!(operations, vec![&repo, &issue]);
+        assert_eq!(
+            queue.entries()[1].conflict.as_deref(),
+            Some("repository app not found")
+        );
+
+        // Ids are never reused, even after the newest entry is removed.
+        assert!(queue.remove(2).is_some());
+        assert!(queue.remove(2).is_none());
+        queue.save().unwrap();
+        let mut queue = Queue::load(&path).unwrap();
+        assert_eq!(queue.push("http://forge.test/graphql", issue), 3);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn a_corrupt_queue_is_not_overwritten() {
+        let path = scratch_path("corrupt");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "{ not json").unwrap();
+        assert!(Queue::load(&path).is_err());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+//! Operator-facing diagnostics for the upload-pack backend.
+//!
+//! These handlers skip the `git-daemon-export-ok` gate, so the embedding server must
+//! mount them behind its own admin authentication.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+
+use serde::Deserialize;
+
+use crate::policy::{self, PolicyRules, RefUpdate};
+use crate::repo::{resolve_repo_dir, split_repository_path};
+use crate::v2::SyntheticFetch;
+use crate::{GitHttpState, pack};
+
+/// Body of a policy dry-run. Without `rules`, the repository's configured policy is used.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PolicyCheck {
+    pub rules: Option<PolicyRules>,
+    pub updates: Vec<RefUpdate>,
+}
+
+// POST /admin/git/dry-run/{*repo}
+pub async fn fetch_dry_run<S>(
+    State(state): State<S>,
+    Path(repo): Path<String>,
+    Json(fetch): Json<SyntheticFetch>,
+) -> Response
+where
+    S: GitHttpState,
+{
+    let repo_dir = match admin_repo_dir(&state, &repo) {
+        Ok(p) => p,
+        Err(resp) => return resp.into_response(),
+    };
+
+    let req = fetch.to_fetch_request();
+    match pack::dry_run_fetch(&repo_dir, &req, fetch.verify).await {
+        Ok(summary) => Json(summary).into_response(),
+        Err(e) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("planning failed: {e}"),
+        )
+            .into_response(),
+    }
+}
+
+// POST /admin/git/policy-check/{*repo}
+pub async fn policy_check<S>(
+    State(state): State<S>,
+    Path(repo): Path<String>,
+    Json(check): Json<PolicyCheck>,
+) -> Response
+where
+    S: GitHttpState,
+{
+    let repo_dir = match admin_repo_dir(&state, &repo) {
+        Ok(p) => p,
+        Err(resp) => return resp.into_response(),
+    };
+    let rules = check
+        .rules
+        .unwrap_or_else(|| state.push_policy(&split_repository_path(&repo)));
+    match policy::check_push(&repo_dir, &rules, &check.updates).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("policy check failed: {e}"),
+        )
+            .into_response(),
+    }
+}
+
+fn admin_repo_dir<S>(state: &S, repo: &str) -> Result<std::path::PathBuf, (StatusCode, String)>
+where
+    S: GitHttpState,
+{
+    let segments = split_repository_path(repo);
+    if segments.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "repository path required".to_string(),
+        ));
+    }
+    for s in &segments {
+        if let Err(e) = state.validate_slug(s) {
+            return Err((StatusCode::BAD_REQUEST, e.to_string()));
+        }
+    }
+    resolve_repo_dir(state.storage(), &segments)
+        .map_err(|_| (StatusCode::NOT_FOUND, "repo not found".to_string()))
+}
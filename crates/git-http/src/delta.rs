@@ -0,0 +1,274 @@
+//! Git delta encoding (the instruction stream of `OBJ_OFS_DELTA` and `OBJ_REF_DELTA`).
+//!
+//! A delta starts with the base and result sizes as little-endian base-128 varints and
+//! continues with instructions: a copy (high bit set) takes up to four offset bytes and
+//! three size bytes selected by the low seven bits, an insert (1..=127) is followed by
+//! that many literal bytes. [`DeltaIndex`] hashes the base in fixed blocks so matches can
+//! be found with one lookup per position of the target, like git's `diff-delta.c`.
+
+use std::collections::HashMap;
+
+/// Bytes per indexed block; shorter matches are sent as literals.
+const BLOCK: usize = 16;
+/// Candidate offsets kept per block hash, so repetitive bases stay cheap to search.
+const MAX_CANDIDATES: usize = 8;
+/// Largest size a single copy instruction can carry.
+const MAX_COPY: usize = 0xff_ffff;
+const MAX_INSERT: usize = 0x7f;
+
+/// A base object prepared for delta searches.
+pub struct DeltaIndex {
+    base: Vec<u8>,
+    blocks: HashMap<u64, Vec<u32>>,
+}
+
+impl DeltaIndex {
+    pub fn new(base: Vec<u8>) -> Self {
+        let mut blocks: HashMap<u64, Vec<u32>> = HashMap::new();
+        // later blocks first, so a target that appends to the base copies long runs from the end
+        for offset in (0..base.len().saturating_sub(BLOCK - 1))
+            .step_by(BLOCK)
+            .rev()
+        {
+            let candidates = blocks
+                .entry(block_hash(&base[offset..offset + BLOCK]))
+                .or_default();
+            if candidates.len() < MAX_CANDIDATES {
+                candidates.push(offset as u32);
+            }
+        }
+        Self { base, blocks }
+    }
+
+    pub fn base(&self) -> &[u8] {
+        &self.base
+    }
+
+    /// Encodes `target` against the base, or `None` when the delta would be larger than
+    /// `max_size` bytes.
+    pub fn encode(&self, target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+        let mut out = Vec::with_capacity((target.len() / 4).min(max_size) + 16);
+        push_varint(&mut out, self.base.len() as u64);
+        push_varint(&mut out, target.len() as u64);
+        let mut literal_start = 0usize;
+        let mut pos = 0usize;
+        while pos + BLOCK <= target.len() {
+            let Some((base_offset, len)) = self.longest_match(target, pos) else {
+                pos += 1;
+                continue;
+            };
+            // grow the match backwards over literals that still agree with the base
+            let mut start = pos;
+            let mut base_start = base_offset;
+            while start > literal_start
+                && base_start > 0
+                && target[start - 1] == self.base[base_start - 1]
+            {
+                start -= 1;
+                base_start -= 1;
+            }
+            push_inserts(&mut out, &target[literal_start..start]);
+            push_copy(&mut out, base_start, len + (pos - start));
+            pos += len;
+            literal_start = pos;
+            if out.len() > max_size {
+                return None;
+            }
+        }
+        push_inserts(&mut out, &target[literal_start..]);
+        (out.len() <= max_size).then_some(out)
+    }
+
+    fn longest_match(&self, target: &[u8], pos: usize) -> Option<(usize, usize)> {
+        let candidates = self.blocks.get(&block_hash(&target[pos..pos + BLOCK]))?;
+        let mut best: Option<(usize, usize)> = None;
+        for &offset in candidates {
+            let offset = offset as usize;
+            let len = self.base[offset..]
+                .iter()
+                .zip(&target[pos..])
+                .take(MAX_COPY)
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len >= BLOCK && best.is_none_or(|(_, best_len)| len > best_len) {
+                best = Some((offset, len));
+            }
+        }
+        best
+    }
+}
+
+fn block_hash(block: &[u8]) -> u64 {
+    // FNV-1a; blocks are short and the table is rebuilt per base, so speed matters more
+    // than distribution
+    block.iter().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+fn push_varint(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn push_inserts(out: &mut Vec<u8>, mut data: &[u8]) {
+    while !data.is_empty() {
+        let take = data.len().min(MAX_INSERT);
+        out.push(take as u8);
+        out.extend_from_slice(&data[..take]);
+        data = &data[take..];
+    }
+}
+
+fn push_copy(out: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+    while len > 0 {
+        let take = len.min(MAX_COPY);
+        let at = out.len();
+        let mut op = 0x80u8;
+        out.push(op);
+        for (i, byte) in (offset as u32).to_le_bytes().into_iter().enumerate() {
+            if byte != 0 {
+                op |= 1 << i;
+                out.push(byte);
+            }
+        }
+        // a size of zero would mean 0x10000, so at least one size byte is always non-zero
+        for (i, byte) in (take as u32).to_le_bytes().into_iter().take(3).enumerate() {
+            if byte != 0 {
+                op |= 0x10 << i;
+                out.push(byte);
+            }
+        }
+        out[at] = op;
+        offset += take;
+        len -= take;
+    }
+}
+
+/// Rebuilds the target of `delta` from `base`; the inverse of [`DeltaIndex::encode`].
+pub fn apply(base: &[u8], delta: &[u8]) -> anyhow::Result<Vec<u8>> {
+    use anyhow::{Context as _, bail};
+    fn varint(data: &[u8], pos: &mut usize) -> anyhow::Result<u64> {
+        let mut n = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = *data.get(*pos).context("truncated delta header")?;
+            *pos += 1;
+            n |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+            if shift > 63 {
+                bail!("delta size overflows");
+            }
+        }
+    }
+    let mut pos = 0;
+    if varint(delta, &mut pos)? != base.len() as u64 {
+        bail!("delta base size mismatch");
+    }
+    let size = varint(delta, &mut pos)? as usize;
+    let mut out = Vec::with_capacity(size);
+    while let Some(&op) = delta.get(pos) {
+        pos += 1;
+        if op & 0x80 != 0 {
+            let mut read = |bits: std::ops::Range<u8>| -> anyhow::Result<usize> {
+                let mut value = 0usize;
+                for (i, bit) in bits.enumerate() {
+                    if op & (1 << bit) != 0 {
+                        value |= (*delta.get(pos).context("truncated copy")? as usize) << (8 * i);
+                        pos += 1;
+                    }
+                }
+                Ok(value)
+            };
+            let offset = read(0..4)?;
+            let len = match read(4..7)? {
+                0 => 0x10000,
+                len => len,
+            };
+            out.extend_from_slice(
+                base.get(offset..offset + len)
+                    .context("copy outside the base")?,
+            );
+        } else if op != 0 {
+            out.extend_from_slice(
+                delta
+                    .get(pos..pos + op as usize)
+                    .context("truncated insert")?,
+            );
+            pos += op as usize;
+        } else {
+            bail!("reserved delta instruction");
+        }
+    }
+    if out.len() != size {
+        bail!("delta result size mismatch");
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(base: &[u8], target: &[u8]) -> Vec<u8> {
+        let delta = DeltaIndex::new(base.to_vec())
+            .encode(target, usize::MAX)
+            .unwrap();
+        assert_eq!(apply(base, &delta).unwrap(), target);
+        delta
+    }
+
+    #[test]
+    fn edits_are_encoded_as_copies_around_the_change() {
+        let base: Vec<u8> = (0..4000u32)
+            .flat_map(|i| format!("line {i}\n").into_bytes())
+            .collect();
+        let mut target = base.clone();
+        target.splice(20_000..20_010, b"an edited line\n".iter().copied());
+        target.extend_from_slice(b"appended\n");
+        let delta = roundtrip(&base, &target);
+        assert!(delta.len() < 100, "delta of {} bytes", delta.len());
+
+        // unrelated and short inputs still round-trip, as literals
+        roundtrip(&base, b"nothing in common");
+        roundtrip(b"", &base[..100]);
+        roundtrip(&base[..10], b"");
+    }
+
+    #[test]
+    fn large_copies_and_offsets_are_split_and_encoded() {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let base: Vec<u8> = (0..MAX_COPY + 70_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let target = [&base[1..], b"tail".as_slice()].concat();
+        let delta = roundtrip(&base, &target);
+        assert!(delta.len() < 64);
+
+        let mut copy = Vec::new();
+        push_copy(&mut copy, 0x0100_0000, 0x1_0000);
+        assert_eq!(copy, vec![0x80 | 0x08 | 0x40, 0x01, 0x01]);
+    }
+
+    #[test]
+    fn oversized_deltas_are_abandoned() {
+        let index = DeltaIndex::new(b"0123456789abcdef0123456789abcdef".to_vec());
+        assert!(index.encode(&[b'x'; 200], 50).is_none());
+        assert!(apply(b"base", &[4, 2, 0x90, 9]).is_err());
+    }
+}
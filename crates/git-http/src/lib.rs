@@ -1,15 +1,26 @@
-//! Git Smart HTTP (protocol v2) server scaffolding.
+//! Git Smart HTTP server.
 //!
-//! This module will implement read-only Smart HTTP (upload-pack) end-to-end in Rust.
-//! For now, handlers return 501 until filled in incrementally.
+//! Fetches speak protocol v2 (upload-pack, see [`v2`]); pushes are received by
+//! [`receive`] when the embedder grants [`PushAccess`].
 
+pub mod admin;
+pub mod delta;
 pub mod errors;
 pub mod negotiation;
 pub mod pack;
 pub mod pkt;
+pub mod policy;
+pub mod receive;
+pub mod refs;
 pub mod repo;
+pub mod routes;
+pub mod scheduler;
 pub mod state;
+#[cfg(test)]
+mod test_helpers;
+pub mod trace;
 pub mod v2;
 
 pub use repo::RepositoryProvider;
-pub use state::GitHttpState;
+pub use scheduler::{GitPermit, GitPriority, GitScheduler};
+pub use state::{GitHttpState, PushAccess};
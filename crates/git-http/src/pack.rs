@@ -1,25 +1,64 @@
 //! Packfile assembly and streaming via gix (pure-Rust backend).
 
 use axum::body::Body;
-use axum::http::{header, HeaderMap, StatusCode};
+use axum::http::{HeaderMap, StatusCode, header};
 use axum::response::Response;
 use bytes::Bytes;
-use std::collections::{HashSet, VecDeque};
+use futures::StreamExt;
+use metrics::{counter, histogram};
+use serde::Serialize;
+use sha1::Digest;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{Result as IoResult, Write};
 use std::path::PathBuf;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
-use futures::StreamExt;
-use sha1::Digest;
-use metrics::{counter, histogram};
 
-use crate::pkt::{encode_pkt_line, PKT_FLUSH, PKT_DELIM};
+use crate::delta::DeltaIndex;
+use crate::pkt::{PKT_DELIM, PKT_FLUSH, encode_pkt_line};
 use crate::v2::FetchRequest;
 
-#[allow(dead_code)]
+/// Objects of the same kind tried as delta bases for each object, like git's `pack.window`.
+const DELTA_WINDOW: usize = 10;
+/// Longest delta chain a client has to resolve to rebuild one object (`pack.depth`).
+const MAX_DELTA_DEPTH: u32 = 50;
+/// Smaller objects are not worth a delta; larger ones take too long to diff.
+const DELTA_SIZE_RANGE: std::ops::Range<usize> = 64..(16 << 20);
+/// `have` commits whose trees serve as ref-delta bases in a thin pack.
+const THIN_BASE_COMMITS: usize = 4;
+/// Bounds the walk of those trees in large repositories.
+const THIN_BASE_ENTRIES: usize = 50_000;
+/// Client objects tried per name hash in a thin pack.
+const THIN_BASE_CANDIDATES: usize = 2;
+
+#[derive(Debug, Default)]
 pub struct PackBuildStats {
     pub objects: usize,
+    /// Pack bytes including header and trailer, before side-band framing.
     pub bytes: u64,
+    pub deltas: usize,
+    /// Deltas against objects the client has but the pack does not contain.
+    pub thin_deltas: usize,
+    /// Objects copied from an on-disk pack without recompressing them.
+    pub reused: usize,
+    /// Of those, deltas kept from the on-disk pack instead of searching for a new one.
+    pub reused_deltas: usize,
+}
+
+/// What a fetch would send, computed without building or streaming the pack.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackPlanSummary {
+    pub commits: usize,
+    pub trees: usize,
+    pub blobs: usize,
+    pub tags: usize,
+    /// Sum of uncompressed object sizes; the streamed pack is usually smaller.
+    pub estimated_bytes: u64,
+    pub shallows: Vec<String>,
+    pub verified: bool,
+    /// Planned objects that could not be read or parsed (only filled in verify mode).
+    pub unreadable: Vec<String>,
 }
 
 #[derive(Clone)]
@@ -27,7 +66,18 @@ struct PackPlan {
     commits: Vec<gix::hash::ObjectId>,
     trees: Vec<gix::hash::ObjectId>,
     blobs: Vec<gix::hash::ObjectId>,
+    /// Annotated tags the client asked for, including tags of tags.
+    tags: Vec<gix::hash::ObjectId>,
     shallows: Vec<gix::hash::ObjectId>,
+    /// [`name_hash`] of the entry name trees and blobs were found under; objects with
+    /// similar names are tried as delta bases for each other.
+    names: HashMap<gix::hash::ObjectId, u32>,
+}
+
+impl PackPlan {
+    fn object_count(&self) -> usize {
+        self.commits.len() + self.trees.len() + self.blobs.len() + self.tags.len()
+    }
 }
 
 /// Serve a Smart HTTP v2 `fetch` response by building a pack in-process and streaming it.
@@ -160,16 +210,23 @@ pub async fn serve_fetch(repo_dir: &PathBuf, req: &FetchRequest, _headers: &Head
     let req_clone = req_effective.clone();
     let plan_clone = plan.clone();
 
-    // Spawn blocking task to build and stream the packfile
-    let pack_task = tokio::task::spawn_blocking(move || {
-        if let Err(err) = build_and_stream_pack_with_plan(repo_path, &req_clone, sideband_64k, plan_clone, tx) {
-            tracing::warn!("pack streaming failed: {}", err);
+    // Build and stream the packfile in the background. The channel is bounded, so the
+    // task only finishes once the response body below has been drained.
+    tokio::task::spawn_blocking(move || {
+        match build_and_stream_pack_with_plan(repo_path, &req_clone, sideband_64k, plan_clone, tx) {
+            Ok(stats) => tracing::debug!(
+                objects = stats.objects,
+                deltas = stats.deltas,
+                thin_deltas = stats.thin_deltas,
+                reused = stats.reused,
+                reused_deltas = stats.reused_deltas,
+                bytes = stats.bytes,
+                "pack streamed"
+            ),
+            Err(err) => tracing::warn!("pack streaming failed: {}", err),
         }
     });
 
-    // Wait for the packfile streaming to complete before returning the response
-    let _ = pack_task.await;
-
     let stream = ReceiverStream::new(rx).map(Ok::<Bytes, std::convert::Infallible>);
     Response::builder()
         .status(StatusCode::OK)
@@ -180,195 +237,6 @@ pub async fn serve_fetch(repo_dir: &PathBuf, req: &FetchRequest, _headers: &Head
         .expect("response")
 }
 
-fn build_and_stream_pack(
-    repo_dir: PathBuf,
-    req: &FetchRequest,
-    sideband_64k: bool,
-    tx: mpsc::Sender<Bytes>,
-) -> anyhow::Result<()> {
-    let repo = gix::open(repo_dir)?;
-
-    // Collect reachable objects starting from wants (commits), stop at direct 'have' commits.
-    let mut want_commits: VecDeque<gix::hash::ObjectId> = VecDeque::new();
-    let mut seen: HashSet<gix::hash::ObjectId> = HashSet::new();
-    for w in req.wants() {
-        if let Ok(oid) = gix::hash::ObjectId::from_hex(w.as_bytes()) { want_commits.push_back(oid); }
-    }
-    let mut have_set: HashSet<gix::hash::ObjectId> = HashSet::new();
-    for h in req.haves() {
-        if let Ok(oid) = gix::hash::ObjectId::from_hex(h.as_bytes()) { have_set.insert(oid); }
-    }
-
-    let mut commits = Vec::new();
-    let mut trees = VecDeque::new();
-    let mut blobs: Vec<gix::hash::ObjectId> = Vec::new();
-
-    while let Some(cid) = want_commits.pop_front() {
-        if have_set.contains(&cid) { continue; } // boundary: client already has this commit
-        if !seen.insert(cid) { continue; }
-        let commit = match repo.find_object(cid) { Ok(o) => o, Err(_) => continue };
-        if commit.kind != gix::objs::Kind::Commit { continue; }
-        let (tree_id, parents) = parse_commit_raw(commit.data.as_ref())?;
-        commits.push(cid);
-        trees.push_back(tree_id);
-        for parent in parents { want_commits.push_back(parent); }
-    }
-
-    // walk tree recursively to collect all trees and blobs
-    let mut seen_tree = HashSet::new();
-    while let Some(tid) = trees.pop_front() {
-        if !seen_tree.insert(tid) { continue; }
-        let tree = repo.find_object(tid)?;
-        let t = gix::objs::TreeRef::from_bytes(tree.data.as_ref())?;
-        for entry in t.entries.iter() {
-            if entry.mode.is_tree() {
-                trees.push_back(entry.oid.into());
-            } else if entry.mode.is_blob() || entry.mode.is_link() {
-                if !req.filter_blob_none() {
-                    blobs.push(entry.oid.into());
-                }
-            }
-        }
-    }
-
-    // Prepare pack writer with header and SHA1 trailer
-    let mut out = SidebandPktWriter::new(tx.clone(), sideband_64k, req.no_progress());
-    let start = std::time::Instant::now();
-    let mut hasher = sha1::Sha1::new();
-
-    // Minimal progress (band 2) for client UX when no-progress is off
-    let total_objects = (commits.len() + seen_tree.len() + blobs.len()) as u32;
-    if sideband_64k {
-        let _ = out.progress_line(format!("Enumerating objects: {}", total_objects));
-        if req.filter_requested() {
-            let _ = out.progress_line("Filter requested; sending full objects".to_string());
-        }
-    }
-
-    // Write pack header: 'PACK' + version(2) + num_objects
-    let mut header = Vec::with_capacity(12);
-    header.extend_from_slice(b"PACK");
-    header.extend_from_slice(&2u32.to_be_bytes());
-    header.extend_from_slice(&total_objects.to_be_bytes());
-    hasher.update(&header);
-    out.send_chunk(&header)?;
-
-    fn encode_varint(mut n: u64) -> Vec<u8> {
-        let mut v = Vec::new();
-        loop {
-            let mut byte = (n & 0x7f) as u8;
-            n >>= 7;
-            if n != 0 { byte |= 0x80; }
-            v.push(byte);
-            if n == 0 { break; }
-        }
-        v
-    }
-
-    fn write_full_object(
-        repo: &gix::Repository,
-        out: &mut SidebandPktWriter,
-        hasher: &mut sha1::Sha1,
-        oid: gix::hash::ObjectId,
-    ) -> anyhow::Result<()> {
-        let obj = repo.find_object(oid)?;
-        let (kind, data) = match obj.kind {
-            gix::objs::Kind::Commit => (1u8, obj.data.to_vec()),
-            gix::objs::Kind::Tree => (2u8, obj.data.to_vec()),
-            gix::objs::Kind::Blob => (3u8, obj.data.to_vec()),
-            gix::objs::Kind::Tag => (4u8, obj.data.to_vec()),
-        };
-        let hdr = encode_obj_header(kind, data.len() as u64);
-        hasher.update(&hdr);
-        out.send_chunk(&hdr)?;
-        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
-        encoder.write_all(&data)?;
-        let compressed = encoder.finish()?;
-        hasher.update(&compressed);
-        out.send_chunk(&compressed)?;
-        Ok(())
-    }
-
-    fn write_commit_ref_delta(
-        repo: &gix::Repository,
-        out: &mut SidebandPktWriter,
-        hasher: &mut sha1::Sha1,
-        target_oid: gix::hash::ObjectId,
-        base_oid: gix::hash::ObjectId,
-    ) -> anyhow::Result<()> {
-        let base_obj = repo.find_object(base_oid)?;
-        if base_obj.kind != gix::objs::Kind::Commit { anyhow::bail!("delta base is not a commit"); }
-        let base_size = base_obj.data.len() as u64;
-
-        let targ_obj = repo.find_object(target_oid)?;
-        if targ_obj.kind != gix::objs::Kind::Commit { anyhow::bail!("target is not a commit"); }
-        let data = targ_obj.data.to_vec();
-
-        let mut delta = Vec::with_capacity(16 + data.len());
-        delta.extend_from_slice(&encode_varint(base_size));
-        delta.extend_from_slice(&encode_varint(data.len() as u64));
-        let mut i = 0;
-        while i < data.len() {
-            let take = (data.len() - i).min(127);
-            delta.push(take as u8);
-            delta.extend_from_slice(&data[i..i + take]);
-            i += take;
-        }
-
-        let hdr = encode_obj_header(7u8, data.len() as u64);
-        hasher.update(&hdr);
-        out.send_chunk(&hdr)?;
-
-        let base_raw = base_oid.as_bytes();
-        hasher.update(base_raw);
-        out.send_chunk(base_raw)?;
-
-        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
-        encoder.write_all(&delta)?;
-        let compressed = encoder.finish()?;
-        hasher.update(&compressed);
-        out.send_chunk(&compressed)?;
-        Ok(())
-    }
-
-    let mut have_set: HashSet<gix::hash::ObjectId> = HashSet::new();
-    for h in req.haves() {
-        if let Ok(oid) = gix::hash::ObjectId::from_hex(h.as_bytes()) { have_set.insert(oid); }
-    }
-
-    for id in &commits {
-        // For correctness first: emit full objects even if the client requested thin-pack.
-        // Proper delta encoding (copy/insert ops) can be added later.
-        write_full_object(&repo, &mut out, &mut hasher, (*id).into())?;
-    }
-
-    for id in &seen_tree {
-        write_full_object(&repo, &mut out, &mut hasher, (*id).into())?;
-    }
-    for id in &blobs {
-        write_full_object(&repo, &mut out, &mut hasher, (*id).into())?;
-    }
-
-    let trailer = hasher.finalize();
-    out.send_chunk(trailer.as_slice())?;
-
-    if sideband_64k && !req.no_progress() {
-        let _ = out.progress_line("Done".to_string());
-    }
-
-    // metrics
-    counter!("git_http.pack.objects").increment(total_objects as u64);
-    // best-effort byte count: header + objects + trailer were all sent through SidebandPktWriter; we can't know exact framed bytes here.
-    // Still, record logical pack bytes: header.len() + sum(compressed) + 20 trailer
-    // Not tracked precisely; emit 0 to ensure metric exists
-    histogram!("git_http.pack.logical_bytes").record(0.0);
-    histogram!("git_http.pack.build_ms").record(start.elapsed().as_millis() as f64);
-
-    // Final flush for the whole fetch response
-    tx.blocking_send(Bytes::from_static(PKT_FLUSH)).ok();
-    Ok(())
-}
-
 struct SidebandPktWriter {
     tx: mpsc::Sender<Bytes>,
     max_payload: usize,
@@ -495,14 +363,25 @@ fn plan_pack(repo_dir: PathBuf, req: &FetchRequest) -> anyhow::Result<PackPlan>
     let mut seen: HashSet<gix::hash::ObjectId> = HashSet::new();
     let mut direct_blobs: Vec<gix::hash::ObjectId> = Vec::new();
     let mut tree_queue: VecDeque<(gix::hash::ObjectId, u32)> = VecDeque::new();
+    let mut tags: Vec<gix::hash::ObjectId> = Vec::new();
     for w in req.wants() {
         if let Ok(oid) = gix::hash::ObjectId::from_hex(w.as_bytes()) {
-            if let Ok(obj) = repo.find_object(oid) {
+            if let Ok(mut obj) = repo.find_object(oid) {
+                // Send annotated tags themselves, then plan whatever they point at.
+                while obj.kind == gix::objs::Kind::Tag {
+                    if !tags.contains(&obj.id) {
+                        tags.push(obj.id);
+                    }
+                    let target = gix::objs::TagRef::from_bytes(obj.data.as_ref())
+                        .map_err(|e| anyhow::anyhow!(e.to_string()))?
+                        .target();
+                    obj = repo.find_object(target)?;
+                }
                 match obj.kind {
-                    gix::objs::Kind::Commit => want_q.push_back((oid, 0)),
-                    gix::objs::Kind::Tree => tree_queue.push_back((oid, 0)),
-                    gix::objs::Kind::Blob => direct_blobs.push(oid),
-                    gix::objs::Kind::Tag => (),
+                    gix::objs::Kind::Commit => want_q.push_back((obj.id, 0)),
+                    gix::objs::Kind::Tree => tree_queue.push_back((obj.id, 0)),
+                    gix::objs::Kind::Blob => direct_blobs.push(obj.id),
+                    gix::objs::Kind::Tag => unreachable!("tags are peeled above"),
                 }
             } else {
                 want_q.push_back((oid, 0));
@@ -580,11 +459,15 @@ fn plan_pack(repo_dir: PathBuf, req: &FetchRequest) -> anyhow::Result<PackPlan>
 
     // Walk trees to collect all referenced trees and blobs
     let mut seen_tree = HashSet::new();
+    let mut names: HashMap<gix::hash::ObjectId, u32> = HashMap::new();
     while let Some((tid, depth)) = tree_queue.pop_front() {
         if !seen_tree.insert(tid) { continue; }
         let tree = repo.find_object(tid)?;
         let t = gix::objs::TreeRef::from_bytes(tree.data.as_ref())?;
         for entry in t.entries.iter() {
+            names
+                .entry(entry.oid.into())
+                .or_insert_with(|| name_hash(entry.filename.as_ref()));
             if entry.mode.is_tree() {
                 if tree_depth_limit.map(|lim| depth < lim).unwrap_or(true) {
                     tree_queue.push_back((entry.oid.into(), depth + 1));
@@ -616,14 +499,81 @@ fn plan_pack(repo_dir: PathBuf, req: &FetchRequest) -> anyhow::Result<PackPlan>
         commits,
         trees: seen_tree.iter().cloned().collect(),
         blobs,
+        tags,
         shallows: shallows.iter().cloned().collect(),
+        names,
+    })
+}
+
+/// Runs the same planning step as [`serve_fetch`] and reports the result instead of a pack.
+///
+/// With `verify` set, every planned object is also read back and commits/trees are parsed,
+/// which surfaces corrupt or missing objects that would otherwise abort a clone mid-stream.
+pub async fn dry_run_fetch(
+    repo_dir: &PathBuf,
+    req: &FetchRequest,
+    verify: bool,
+) -> anyhow::Result<PackPlanSummary> {
+    let mut req_effective = req.clone();
+    if !req.want_refs().is_empty() {
+        resolve_want_refs(repo_dir, &mut req_effective).await?;
+    }
+
+    let repo_path = repo_dir.clone();
+    tokio::task::spawn_blocking(move || -> anyhow::Result<PackPlanSummary> {
+        let plan = plan_pack(repo_path.clone(), &req_effective)?;
+        let repo = gix::open(repo_path)?;
+
+        let mut summary = PackPlanSummary {
+            commits: plan.commits.len(),
+            trees: plan.trees.len(),
+            blobs: plan.blobs.len(),
+            tags: plan.tags.len(),
+            shallows: plan.shallows.iter().map(|oid| oid.to_string()).collect(),
+            verified: verify,
+            ..Default::default()
+        };
+
+        for oid in plan
+            .commits
+            .iter()
+            .chain(&plan.trees)
+            .chain(&plan.blobs)
+            .chain(&plan.tags)
+        {
+            let obj = match repo.find_object(*oid) {
+                Ok(obj) => obj,
+                Err(_) => {
+                    if verify {
+                        summary.unreadable.push(oid.to_string());
+                    }
+                    continue;
+                }
+            };
+            summary.estimated_bytes += obj.data.len() as u64;
+            if verify {
+                let parsed = match obj.kind {
+                    gix::objs::Kind::Commit => parse_commit_raw(obj.data.as_ref()).map(|_| ()),
+                    gix::objs::Kind::Tree => gix::objs::TreeRef::from_bytes(obj.data.as_ref())
+                        .map(|_| ())
+                        .map_err(|e| anyhow::anyhow!(e.to_string())),
+                    _ => Ok(()),
+                };
+                if parsed.is_err() {
+                    summary.unreadable.push(oid.to_string());
+                }
+            }
+        }
+        Ok(summary)
     })
+    .await?
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::pkt::{decode_pkt_lines, Pkt};
+    use crate::test_helpers::git;
 
     #[test]
     fn encode_obj_header_small_sizes() {
@@ -662,6 +612,179 @@ mod tests {
         match &pkts2[0] { Pkt::Data(d) => { assert_eq!(d[0], 2); assert!(std::str::from_utf8(&d[1..]).unwrap().starts_with("Counting objects")); }, _ => panic!("expected progress pkt") }
     }
 
+    #[test]
+    fn ofs_distances_and_name_hashes_match_git() {
+        assert_eq!(encode_ofs_distance(127), vec![0x7f]);
+        assert_eq!(encode_ofs_distance(128), vec![0x80, 0x00]);
+        assert_eq!(encode_ofs_distance(16511), vec![0xff, 0x7f]);
+        assert_eq!(encode_ofs_distance(16512), vec![0x80, 0x80, 0x00]);
+        // the last characters weigh most, whitespace is skipped
+        assert_eq!(name_hash(b"lib.rs") >> 24, name_hash(b"main.rs") >> 24);
+        assert_ne!(name_hash(b"lib.rs") >> 24, name_hash(b"lib.c") >> 24);
+        assert_eq!(name_hash(b"x y"), name_hash(b"xy"));
+    }
+
+    fn fetch_request(lines: &[String]) -> FetchRequest {
+        let mut buf = encode_pkt_line(b"command=fetch\n");
+        buf.extend_from_slice(PKT_DELIM);
+        for line in lines {
+            buf.extend_from_slice(&encode_pkt_line(format!("{line}\n").as_bytes()));
+        }
+        buf.extend_from_slice(PKT_FLUSH);
+        crate::v2::parse_fetch(&decode_pkt_lines(&buf).unwrap()).unwrap()
+    }
+
+    /// Builds the pack for `req` without side-band framing and returns it with its stats.
+    fn build_pack(repo: &std::path::Path, req: FetchRequest) -> (Vec<u8>, PackBuildStats) {
+        let plan = plan_pack(repo.to_path_buf(), &req).unwrap();
+        let (tx, mut rx) = mpsc::channel::<Bytes>(4);
+        let repo = repo.to_path_buf();
+        let builder = std::thread::spawn(move || {
+            build_and_stream_pack_with_plan(repo, &req, false, plan, tx).unwrap()
+        });
+        let mut pack = Vec::new();
+        while let Some(chunk) = rx.blocking_recv() {
+            pack.extend_from_slice(&chunk);
+        }
+        assert!(pack.ends_with(PKT_FLUSH));
+        pack.truncate(pack.len() - PKT_FLUSH.len());
+        (pack, builder.join().unwrap())
+    }
+
+    fn index_pack(repo: &std::path::Path, pack: &[u8], fix_thin: bool) -> bool {
+        let mut cmd = std::process::Command::new("git");
+        cmd.current_dir(repo).args(["index-pack", "--stdin"]);
+        if fix_thin {
+            cmd.arg("--fix-thin");
+        }
+        let mut child = cmd
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(pack).unwrap();
+        child.wait().unwrap().success()
+    }
+
+    #[test]
+    fn packs_delta_similar_objects_and_thin_packs_use_client_bases() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let work = tmp.path().join("work");
+        std::fs::create_dir(&work).unwrap();
+        git(&work, &["init", "-q"]);
+        let mut text: String = (0..3000)
+            .map(|i| format!("line {i} of a file that changes a little\n"))
+            .collect();
+        for round in 0..3 {
+            text.push_str(&format!("round {round}\n"));
+            std::fs::write(work.join("notes.txt"), &text).unwrap();
+            git(&work, &["add", "."]);
+            git(&work, &["commit", "-qm", &format!("round {round}")]);
+            if round == 1 {
+                git(&work, &["branch", "old"]);
+            }
+        }
+        let head = git(&work, &["rev-parse", "HEAD"]);
+        let old = git(&work, &["rev-parse", "old"]);
+        let repo = work.join(".git");
+
+        let (pack, stats) = build_pack(
+            &repo,
+            fetch_request(&[format!("want {head}"), "ofs-delta".into()]),
+        );
+        assert_eq!(stats.objects, 9);
+        assert!(stats.deltas >= 2, "{stats:?}");
+        assert_eq!(stats.thin_deltas, 0);
+        assert_eq!(stats.bytes, pack.len() as u64);
+        assert!(pack.len() < text.len() / 4, "pack of {} bytes", pack.len());
+        let clone = tmp.path().join("clone.git");
+        git(tmp.path(), &["init", "-q", "--bare", "clone.git"]);
+        assert!(index_pack(&clone, &pack, false));
+        assert!(
+            git(&clone, &["cat-file", "-p", &format!("{head}:notes.txt")]).ends_with("round 2")
+        );
+
+        // The client has `old`; the new version of the file is sent against its copy.
+        let client = tmp.path().join("client.git");
+        git(
+            tmp.path(),
+            &[
+                "clone",
+                "-q",
+                "--bare",
+                "--single-branch",
+                "-b",
+                "old",
+                work.to_str().unwrap(),
+                "client.git",
+            ],
+        );
+        let req = fetch_request(&[
+            format!("want {head}"),
+            format!("have {old}"),
+            "thin-pack".into(),
+            "done".into(),
+        ]);
+        let (pack, stats) = build_pack(&repo, req);
+        assert_eq!(stats.objects, 3);
+        assert!(stats.thin_deltas >= 1, "{stats:?}");
+        assert!(index_pack(&client, &pack, true));
+        assert!(
+            git(&client, &["cat-file", "-p", &format!("{head}:notes.txt")]).ends_with("round 2")
+        );
+    }
+
+    #[test]
+    fn packed_objects_and_deltas_are_copied_from_disk() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let work = tmp.path().join("work");
+        std::fs::create_dir(&work).unwrap();
+        git(&work, &["init", "-q"]);
+        let mut text: String = (0..3000)
+            .map(|i| format!("line {i} of a file that changes a little\n"))
+            .collect();
+        for round in 0..4 {
+            text.push_str(&format!("round {round}\n"));
+            std::fs::write(work.join("notes.txt"), &text).unwrap();
+            git(&work, &["add", "."]);
+            git(&work, &["commit", "-qm", &format!("round {round}")]);
+        }
+        let repo = work.join(".git");
+        let head = git(&work, &["rev-parse", "HEAD"]);
+        let want = || fetch_request(&[format!("want {head}"), "ofs-delta".into()]);
+
+        // loose objects are compressed here
+        let (_, stats) = build_pack(&repo, want());
+        assert_eq!((stats.reused, stats.reused_deltas), (0, 0));
+
+        git(&work, &["repack", "-adq"]);
+        let (pack, stats) = build_pack(&repo, want());
+        assert_eq!(stats.objects, 12);
+        // everything is copied, except objects a new delta was found for
+        assert_eq!(
+            stats.reused + stats.deltas - stats.reused_deltas,
+            stats.objects,
+            "{stats:?}"
+        );
+        assert_eq!(stats.reused_deltas, 3, "{stats:?}");
+        let clone = tmp.path().join("clone.git");
+        git(tmp.path(), &["init", "-q", "--bare", "clone.git"]);
+        assert!(index_pack(&clone, &pack, false));
+        assert!(
+            git(&clone, &["cat-file", "-p", &format!("{head}:notes.txt")]).ends_with("round 3")
+        );
+        assert!(
+            git(&clone, &["cat-file", "-p", &format!("{head}~3:notes.txt")]).ends_with("round 0")
+        );
+
+        // without ofs-delta the kept deltas name their base instead
+        let (pack, stats) = build_pack(&repo, fetch_request(&[format!("want {head}")]));
+        assert_eq!(stats.reused_deltas, 3, "{stats:?}");
+        let clone = tmp.path().join("ref.git");
+        git(tmp.path(), &["init", "-q", "--bare", "ref.git"]);
+        assert!(index_pack(&clone, &pack, false));
+    }
+
     #[test]
     fn sideband_pkt_writer_respects_no_sideband_or_no_progress() {
         // No sideband: raw bytes, not pkt-framed
@@ -679,65 +802,477 @@ mod tests {
     }
 }
 
+/// Streams pack bytes, keeping the trailer checksum and the offset of the next object.
+struct PackStream {
+    out: SidebandPktWriter,
+    hasher: sha1::Sha1,
+    offset: u64,
+}
+
+impl PackStream {
+    fn write(&mut self, data: &[u8]) -> IoResult<()> {
+        self.hasher.update(data);
+        self.offset += data.len() as u64;
+        self.out.send_chunk(data)
+    }
+
+    /// Writes one pack entry: its header (with the delta base, if any) and the compressed payload.
+    fn write_entry(&mut self, header: &[u8], payload: &[u8]) -> anyhow::Result<()> {
+        self.write(header)?;
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(payload)?;
+        self.write(&encoder.finish()?)?;
+        Ok(())
+    }
+
+    /// Writes an entry whose payload is already compressed, as copied from another pack.
+    fn write_raw_entry(&mut self, header: &[u8], compressed: &[u8]) -> anyhow::Result<()> {
+        self.write(header)?;
+        self.write(compressed)?;
+        Ok(())
+    }
+}
+
+/// An object's entry in an on-disk pack. Its compressed bytes can be copied into the
+/// pack being written as they are: whole objects always, deltas when their base is sent
+/// before them or the client has it.
+struct StoredEntry {
+    /// Pack id and offset, which identify the entry's base for `OBJ_OFS_DELTA` entries.
+    source: (u32, u64),
+    header: gix::odb::pack::data::entry::Header,
+    /// Inflated size of the payload: the object, or the delta instruction stream.
+    size: u64,
+    compressed: Vec<u8>,
+}
+
+impl StoredEntry {
+    fn read(
+        odb: &gix::odb::Handle,
+        location: &gix::odb::pack::data::entry::Location,
+        hash_len: usize,
+    ) -> Option<Self> {
+        use gix::odb::pack::Find as _;
+        let mut data = odb.entry_by_location(location)?.data;
+        let entry =
+            gix::odb::pack::data::Entry::from_bytes(&data, location.pack_offset, hash_len).ok()?;
+        data.drain(..entry.header_size());
+        Some(Self {
+            source: (location.pack_id, location.pack_offset),
+            header: entry.header,
+            size: entry.decompressed_size,
+            compressed: data,
+        })
+    }
+
+    /// The on-disk location of the delta base, for `OBJ_OFS_DELTA` entries.
+    fn ofs_base(&self) -> Option<(u32, u64)> {
+        match self.header {
+            gix::odb::pack::data::entry::Header::OfsDelta { base_distance } => {
+                gix::odb::pack::data::entry::Header::verified_base_pack_offset(
+                    self.source.1,
+                    base_distance,
+                )
+                .map(|offset| (self.source.0, offset))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Where an object went in the pack being written.
+#[derive(Clone, Copy)]
+struct Written {
+    oid: gix::hash::ObjectId,
+    offset: u64,
+    depth: u32,
+}
+
+/// A recently written object offered as a delta base to the next objects of its kind.
+struct WindowEntry {
+    oid: gix::hash::ObjectId,
+    offset: u64,
+    depth: u32,
+    index: DeltaIndex,
+}
+
+enum DeltaBase {
+    /// Written earlier in this pack, at this offset.
+    InPack(gix::hash::ObjectId, u64),
+    /// Only in the client's repository; allowed in thin packs.
+    Client(gix::hash::ObjectId),
+}
+
 fn build_and_stream_pack_with_plan(
     repo_dir: PathBuf,
     req: &FetchRequest,
     sideband_64k: bool,
     plan: PackPlan,
     tx: mpsc::Sender<Bytes>,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<PackBuildStats> {
     let repo = gix::open(repo_dir)?;
+    let hash_len = repo.object_hash().len_in_bytes();
+    // Entries are read straight from pack files, which must stay mapped while in use.
+    let mut odb = repo.objects.clone().into_inner();
+    odb.prevent_pack_unload();
 
-    let mut out = SidebandPktWriter::new(tx.clone(), sideband_64k, req.no_progress());
+    let out = SidebandPktWriter::new(tx.clone(), sideband_64k, req.no_progress());
     let start = std::time::Instant::now();
-    let mut hasher = sha1::Sha1::new();
+    let mut pack = PackStream {
+        out,
+        hasher: sha1::Sha1::new(),
+        offset: 0,
+    };
+    let mut stats = PackBuildStats {
+        objects: plan.object_count(),
+        ..Default::default()
+    };
 
     // Pack header
     let mut header = Vec::with_capacity(12);
     header.extend_from_slice(b"PACK");
     header.extend_from_slice(&2u32.to_be_bytes());
-    header.extend_from_slice(&(
-        (plan.commits.len() + plan.trees.len() + plan.blobs.len()) as u32
-    ).to_be_bytes());
-    hasher.update(&header);
-    out.send_chunk(&header)?;
-
-    let mut write_obj = |oid: gix::hash::ObjectId| -> anyhow::Result<()> {
-        let obj = repo.find_object(oid)?;
-        let (kind, data) = match obj.kind {
-            gix::objs::Kind::Commit => (1u8, obj.data.to_vec()),
-            gix::objs::Kind::Tree => (2u8, obj.data.to_vec()),
-            gix::objs::Kind::Blob => (3u8, obj.data.to_vec()),
-            gix::objs::Kind::Tag => (4u8, obj.data.to_vec()),
-        };
-        let mut hdr = encode_obj_header(kind, data.len() as u64);
-        hasher.update(&hdr);
-        out.send_chunk(&hdr)?;
-        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
-        encoder.write_all(&data)?;
-        let compressed = encoder.finish()?;
-        hasher.update(&compressed);
-        out.send_chunk(&compressed)?;
-        Ok(())
+    header.extend_from_slice(&(plan.object_count() as u32).to_be_bytes());
+    pack.write(&header)?;
+
+    // Objects with similar names go next to each other so they meet in the delta window.
+    let by_name = |oids: &[gix::hash::ObjectId]| {
+        let mut oids = oids.to_vec();
+        oids.sort_by_key(|oid| plan.names.get(oid).copied().unwrap_or(0));
+        oids
+    };
+    let client_bases = if req.thin_pack() {
+        thin_pack_bases(&repo, req)
+    } else {
+        HashMap::new()
     };
+    let client_objects: HashSet<gix::hash::ObjectId> =
+        client_bases.values().flatten().copied().collect();
+    let mut written: HashMap<gix::hash::ObjectId, Written> = HashMap::new();
+    let mut written_from: HashMap<(u32, u64), Written> = HashMap::new();
+    let mut delta_time = std::time::Duration::ZERO;
+    let mut saved_bytes = 0u64;
+    let mut buf = Vec::new();
+
+    for objects in [
+        plan.commits.clone(),
+        by_name(&plan.trees),
+        by_name(&plan.blobs),
+    ] {
+        let mut window: VecDeque<WindowEntry> = VecDeque::with_capacity(DELTA_WINDOW + 1);
+        for oid in objects {
+            let (kind, data, stored) = {
+                use gix::odb::pack::Find as _;
+                let (obj, location) = odb
+                    .try_find(&oid, &mut buf)
+                    .map_err(|err| anyhow::anyhow!("failed to read object {}: {}", oid, err))?
+                    .ok_or_else(|| anyhow::anyhow!("object {} not found", oid))?;
+                let stored =
+                    location.and_then(|location| StoredEntry::read(&odb, &location, hash_len));
+                (pack_kind(obj.kind), obj.data.to_vec(), stored)
+            };
+            let offset = pack.offset;
+
+            // A stored delta whose base is already in this pack (or on the client) is sent as is.
+            let stored_base = stored.as_ref().and_then(|entry| match &entry.header {
+                gix::odb::pack::data::entry::Header::RefDelta { base_id } => match written
+                    .get(base_id)
+                {
+                    Some(base) => Some(DeltaBase::InPack(base.oid, base.offset)),
+                    None if client_objects.contains(base_id) => Some(DeltaBase::Client(*base_id)),
+                    None => None,
+                },
+                gix::odb::pack::data::entry::Header::OfsDelta { .. } => entry
+                    .ofs_base()
+                    .and_then(|source| written_from.get(&source))
+                    .map(|base| DeltaBase::InPack(base.oid, base.offset)),
+                _ => None,
+            });
+            let stored_depth = match &stored_base {
+                Some(DeltaBase::InPack(base_oid, _)) => written[base_oid].depth + 1,
+                _ => 1,
+            };
+            if let (Some(entry), Some(base)) = (&stored, stored_base)
+                && stored_depth <= MAX_DELTA_DEPTH
+            {
+                let label = write_delta_header(
+                    &mut pack,
+                    req,
+                    &base,
+                    offset,
+                    entry.size,
+                    &mut stats,
+                    |pack, header| pack.write_raw_entry(header, &entry.compressed),
+                )?;
+                counter!("git_http.pack.deltas", "base" => label).increment(1);
+                counter!("git_http.pack.reused", "entry" => "delta").increment(1);
+                stats.deltas += 1;
+                stats.reused += 1;
+                stats.reused_deltas += 1;
+                saved_bytes += data.len().saturating_sub(entry.size as usize) as u64;
+                let this = Written {
+                    oid,
+                    offset,
+                    depth: stored_depth,
+                };
+                written.insert(oid, this);
+                written_from.insert(entry.source, this);
+                if DELTA_SIZE_RANGE.contains(&data.len()) {
+                    window.push_front(WindowEntry {
+                        oid,
+                        offset,
+                        depth: stored_depth,
+                        index: DeltaIndex::new(data),
+                    });
+                    window.truncate(DELTA_WINDOW);
+                }
+                continue;
+            }
+            // Whole objects stored on disk are copied instead of compressed again.
+            let whole = stored
+                .as_ref()
+                .filter(|entry| entry.header.as_kind().is_some());
+            let write_whole = |pack: &mut PackStream,
+                               stats: &mut PackBuildStats,
+                               data: &[u8]|
+             -> anyhow::Result<()> {
+                let header = encode_obj_header(kind, data.len() as u64);
+                match whole {
+                    Some(entry) => {
+                        counter!("git_http.pack.reused", "entry" => "object").increment(1);
+                        stats.reused += 1;
+                        pack.write_raw_entry(&header, &entry.compressed)
+                    }
+                    None => pack.write_entry(&header, data),
+                }
+            };
+            let mut remember = |depth: u32| {
+                let this = Written { oid, offset, depth };
+                written.insert(oid, this);
+                if let Some(entry) = &stored {
+                    written_from.insert(entry.source, this);
+                }
+            };
+            if !DELTA_SIZE_RANGE.contains(&data.len()) {
+                write_whole(&mut pack, &mut stats, &data)?;
+                remember(0);
+                continue;
+            }
 
-    for id in &plan.commits { write_obj(*id)?; }
-    for id in &plan.trees { write_obj(*id)?; }
-    for id in &plan.blobs { write_obj(*id)?; }
+            let searched = std::time::Instant::now();
+            let mut best: Option<(DeltaBase, u32, Vec<u8>)> = None;
+            // git takes a delta only when it is at most half the size of the object
+            let mut max_size = data.len() / 2;
+            for base in window.iter().filter(|base| base.depth < MAX_DELTA_DEPTH) {
+                if let Some(delta) = base.index.encode(&data, max_size) {
+                    max_size = delta.len().saturating_sub(1);
+                    best = Some((
+                        DeltaBase::InPack(base.oid, base.offset),
+                        base.depth + 1,
+                        delta,
+                    ));
+                }
+            }
+            let candidates = plan.names.get(&oid).and_then(|name| client_bases.get(name));
+            for base_oid in candidates
+                .into_iter()
+                .flatten()
+                .filter(|base| **base != oid)
+            {
+                let Ok(base) = repo.find_object(*base_oid) else {
+                    continue;
+                };
+                if pack_kind(base.kind) != kind || !DELTA_SIZE_RANGE.contains(&base.data.len()) {
+                    continue;
+                }
+                if let Some(delta) = DeltaIndex::new(base.data.to_vec()).encode(&data, max_size) {
+                    max_size = delta.len().saturating_sub(1);
+                    best = Some((DeltaBase::Client(*base_oid), 1, delta));
+                }
+            }
+            delta_time += searched.elapsed();
+
+            let depth = match best {
+                Some((base, depth, delta)) => {
+                    let label = write_delta_header(
+                        &mut pack,
+                        req,
+                        &base,
+                        offset,
+                        delta.len() as u64,
+                        &mut stats,
+                        |pack, header| pack.write_entry(header, &delta),
+                    )?;
+                    counter!("git_http.pack.deltas", "base" => label).increment(1);
+                    stats.deltas += 1;
+                    saved_bytes += (data.len() - delta.len()) as u64;
+                    depth
+                }
+                None => {
+                    write_whole(&mut pack, &mut stats, &data)?;
+                    0
+                }
+            };
+            remember(depth);
+            window.push_front(WindowEntry {
+                oid,
+                offset,
+                depth,
+                index: DeltaIndex::new(data),
+            });
+            window.truncate(DELTA_WINDOW);
+        }
+    }
+    for id in &plan.tags {
+        let obj = repo.find_object(*id)?;
+        pack.write_entry(
+            &encode_obj_header(pack_kind(obj.kind), obj.data.len() as u64),
+            &obj.data,
+        )?;
+    }
 
-    let trailer = hasher.finalize();
-    out.send_chunk(trailer.as_slice())?;
+    let trailer = pack.hasher.clone().finalize();
+    pack.write(trailer.as_slice())?;
+    stats.bytes = pack.offset;
 
     if sideband_64k && !req.no_progress() {
-        let _ = out.progress_line("Done".to_string());
+        let _ = pack.out.progress_line(format!(
+            "Total {} (delta {}), reused {} (delta {})",
+            stats.objects, stats.deltas, stats.reused, stats.reused_deltas
+        ));
+        let _ = pack.out.progress_line("Done".to_string());
     }
 
-    counter!("git_http.pack.objects").increment((plan.commits.len() + plan.trees.len() + plan.blobs.len()) as u64);
-    histogram!("git_http.pack.logical_bytes").record(0.0);
+    counter!("git_http.pack.objects").increment(plan.object_count() as u64);
+    counter!("git_http.pack.delta_saved_bytes").increment(saved_bytes);
+    histogram!("git_http.pack.logical_bytes").record(stats.bytes as f64);
+    histogram!("git_http.pack.delta_ms").record(delta_time.as_millis() as f64);
     histogram!("git_http.pack.build_ms").record(start.elapsed().as_millis() as f64);
 
     tx.blocking_send(Bytes::from_static(PKT_FLUSH)).ok();
-    Ok(())
+    Ok(stats)
+}
+
+/// Writes a delta entry against `base`: `OBJ_OFS_DELTA` for objects earlier in this pack
+/// when the client accepts them, `OBJ_REF_DELTA` otherwise. Returns the metrics label.
+fn write_delta_header(
+    pack: &mut PackStream,
+    req: &FetchRequest,
+    base: &DeltaBase,
+    offset: u64,
+    delta_size: u64,
+    stats: &mut PackBuildStats,
+    payload: impl FnOnce(&mut PackStream, &[u8]) -> anyhow::Result<()>,
+) -> anyhow::Result<&'static str> {
+    match base {
+        DeltaBase::InPack(_, base_offset) if req.ofs_delta() => {
+            let mut header = encode_obj_header(6, delta_size);
+            header.extend_from_slice(&encode_ofs_distance(offset - base_offset));
+            payload(pack, &header)?;
+            Ok("ofs")
+        }
+        DeltaBase::InPack(base_oid, _) | DeltaBase::Client(base_oid) => {
+            let mut header = encode_obj_header(7, delta_size);
+            header.extend_from_slice(base_oid.as_bytes());
+            payload(pack, &header)?;
+            if matches!(base, DeltaBase::Client(_)) {
+                stats.thin_deltas += 1;
+                Ok("thin")
+            } else {
+                Ok("ref")
+            }
+        }
+    }
+}
+
+fn pack_kind(kind: gix::objs::Kind) -> u8 {
+    match kind {
+        gix::objs::Kind::Commit => 1,
+        gix::objs::Kind::Tree => 2,
+        gix::objs::Kind::Blob => 3,
+        gix::objs::Kind::Tag => 4,
+    }
+}
+
+/// The distance back to an `OBJ_OFS_DELTA` base: big-endian base-128 where every
+/// continuation adds one, so each length has its own range.
+fn encode_ofs_distance(mut distance: u64) -> Vec<u8> {
+    let mut out = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance != 0 {
+        distance -= 1;
+        out.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    out.reverse();
+    out
+}
+
+/// git's `pack_name_hash`: weighted towards the last characters of a name, so files with
+/// the same name or extension sort together.
+fn name_hash(name: &[u8]) -> u32 {
+    name.iter()
+        .filter(|c| !c.is_ascii_whitespace())
+        .fold(0u32, |hash, c| (hash >> 2).wrapping_add((*c as u32) << 24))
+}
+
+/// Trees and blobs of the client's `have` commits, by the [`name_hash`] of their entry
+/// name (root trees under 0). A thin pack can send deltas against them; the client
+/// completes the pack from its own objects with `index-pack --fix-thin`.
+fn thin_pack_bases(
+    repo: &gix::Repository,
+    req: &FetchRequest,
+) -> HashMap<u32, Vec<gix::hash::ObjectId>> {
+    let mut bases: HashMap<u32, Vec<gix::hash::ObjectId>> = HashMap::new();
+    let mut queue: VecDeque<(gix::hash::ObjectId, u32)> = VecDeque::new();
+    for have in req.haves().iter().take(THIN_BASE_COMMITS) {
+        let Ok(oid) = gix::hash::ObjectId::from_hex(have.as_bytes()) else {
+            continue;
+        };
+        let Ok(commit) = repo.find_object(oid) else {
+            continue;
+        };
+        if commit.kind != gix::objs::Kind::Commit {
+            continue;
+        }
+        if let Ok((tree, _)) = parse_commit_raw(commit.data.as_ref()) {
+            queue.push_back((tree, 0));
+        }
+    }
+
+    let mut seen: HashSet<gix::hash::ObjectId> = HashSet::new();
+    let mut entries = 0usize;
+    let add = |bases: &mut HashMap<u32, Vec<gix::hash::ObjectId>>, oid, name| {
+        let candidates = bases.entry(name).or_default();
+        if candidates.len() < THIN_BASE_CANDIDATES {
+            candidates.push(oid);
+        }
+    };
+    while let Some((tid, name)) = queue.pop_front() {
+        if entries >= THIN_BASE_ENTRIES {
+            break;
+        }
+        if !seen.insert(tid) {
+            continue;
+        }
+        add(&mut bases, tid, name);
+        let Ok(tree) = repo.find_object(tid) else {
+            continue;
+        };
+        let Ok(t) = gix::objs::TreeRef::from_bytes(tree.data.as_ref()) else {
+            continue;
+        };
+        for entry in t.entries.iter() {
+            entries += 1;
+            let name = name_hash(entry.filename.as_ref());
+            if entry.mode.is_tree() {
+                queue.push_back((entry.oid.into(), name));
+            } else if entry.mode.is_blob() && seen.insert(entry.oid.into()) {
+                add(&mut bases, entry.oid.into(), name);
+            }
+        }
+    }
+    bases
 }
 
 // Returns true if an 'ACK <oid> ready' was emitted, false otherwise
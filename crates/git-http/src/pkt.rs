@@ -44,6 +44,18 @@ pub fn decode_pkt_lines(mut buf: &[u8]) -> anyhow::Result<Vec<Pkt>> {
     Ok(out)
 }
 
+/// Frames `data` as side-band pkt-lines on `band`, keeping each under the 64k limit.
+pub fn encode_side_band(band: u8, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 8);
+    for chunk in data.chunks(65515) {
+        let mut framed = Vec::with_capacity(chunk.len() + 1);
+        framed.push(band);
+        framed.extend_from_slice(chunk);
+        out.extend_from_slice(&encode_pkt_line(&framed));
+    }
+    out
+}
+
 #[derive(Debug, Clone)]
 pub enum Pkt {
     Data(Vec<u8>),
@@ -0,0 +1,664 @@
+//! Pre-receive policy engine.
+//!
+//! Rules are evaluated against the commits a push would introduce, before any ref is
+//! updated. Only commits not already reachable from an existing ref are inspected, so
+//! tightening a policy never blocks pushes on top of old history. Results can be encoded
+//! as a `report-status` response (with side-band diagnostics) or returned as JSON from
+//! the dry-run endpoint. Rules that only look at a commit message or a branch name can
+//! also be checked on their own ([`check_commit_message`], [`check_branch_name`]), so
+//! clients can validate input before anything is pushed.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::pkt::{PKT_FLUSH, encode_pkt_line, encode_side_band};
+
+/// Per-repository push rules. Every rule is opt-in; the default allows everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolicyRules {
+    /// Reject blobs added or modified with more bytes than this.
+    pub max_file_size: Option<u64>,
+    /// Glob patterns (`*` within a segment, `**` across segments, trailing `/` for directories).
+    pub forbidden_paths: Vec<String>,
+    /// Regex every commit message must match.
+    pub commit_message_pattern: Option<String>,
+    /// Regex the names of new branches must match, without `refs/heads/`. Branches that
+    /// already exist can still be updated.
+    pub branch_name_pattern: Option<String>,
+    /// Require a `gpgsig` header on every commit. Signatures are not verified against keys.
+    pub require_signed_commits: bool,
+    /// Reject commits with more than one parent.
+    pub forbid_merge_commits: bool,
+}
+
+impl PolicyRules {
+    pub fn is_empty(&self) -> bool {
+        self == &PolicyRules::default()
+    }
+
+    /// Checks that the rules can be evaluated (currently: the regexes compile).
+    pub fn validate(&self) -> Result<()> {
+        if let Some(pattern) = &self.commit_message_pattern {
+            regex::Regex::new(pattern).context("invalid commit_message_pattern")?;
+        }
+        if let Some(pattern) = &self.branch_name_pattern {
+            regex::Regex::new(pattern).context("invalid branch_name_pattern")?;
+        }
+        for pattern in &self.forbidden_paths {
+            if pattern.trim().is_empty() {
+                anyhow::bail!("forbidden_paths entries must not be empty");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single `<old> <new> <ref>` command from a push.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefUpdate {
+    pub ref_name: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl RefUpdate {
+    pub fn is_delete(&self) -> bool {
+        is_zero_oid(&self.new)
+    }
+
+    pub fn is_create(&self) -> bool {
+        is_zero_oid(&self.old)
+    }
+}
+
+/// A rule broken by a commit message or branch name checked on its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuleViolation {
+    pub rule: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyViolation {
+    pub ref_name: String,
+    pub commit: Option<String>,
+    pub rule: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyReport {
+    pub commits_checked: usize,
+    pub violations: Vec<PolicyViolation>,
+}
+
+impl PolicyReport {
+    pub fn allowed(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    fn rejection_for(&self, ref_name: &str) -> Option<&PolicyViolation> {
+        self.violations.iter().find(|v| v.ref_name == ref_name)
+    }
+
+    /// Encodes a `report-status` response. With side-band, each violation is sent on
+    /// band 2 so clients print it as `remote: ...` and the status goes on band 1.
+    pub fn encode_report_status(&self, updates: &[RefUpdate], sideband_64k: bool) -> Vec<u8> {
+        let mut status = Vec::new();
+        status.extend_from_slice(&encode_pkt_line(b"unpack ok\n"));
+        for update in updates {
+            let line = match self.rejection_for(&update.ref_name) {
+                Some(v) => format!("ng {} policy violation: {}\n", update.ref_name, v.rule),
+                None => format!("ok {}\n", update.ref_name),
+            };
+            status.extend_from_slice(&encode_pkt_line(line.as_bytes()));
+        }
+        status.extend_from_slice(PKT_FLUSH);
+
+        if !sideband_64k {
+            return status;
+        }
+
+        let mut out = Vec::new();
+        for v in &self.violations {
+            out.extend_from_slice(&encode_side_band(2, v.diagnostic().as_bytes()));
+        }
+        out.extend_from_slice(&encode_side_band(1, &status));
+        out.extend_from_slice(PKT_FLUSH);
+        out
+    }
+}
+
+impl PolicyViolation {
+    /// The line a push client prints as `remote: ...` for this violation.
+    pub(crate) fn diagnostic(&self) -> String {
+        let commit = self
+            .commit
+            .as_deref()
+            .map(|c| &c[..c.len().min(12)])
+            .unwrap_or("-");
+        format!(
+            "error: [{}] {} {}: {}\n",
+            self.rule, self.ref_name, commit, self.message
+        )
+    }
+}
+
+fn is_zero_oid(hex: &str) -> bool {
+    !hex.is_empty() && hex.bytes().all(|b| b == b'0')
+}
+
+/// Checks `message` against the rules a push applies to each commit message.
+pub fn check_commit_message(rules: &PolicyRules, message: &str) -> Result<Vec<RuleViolation>> {
+    let message_re = rules
+        .commit_message_pattern
+        .as_deref()
+        .map(regex::Regex::new)
+        .transpose()?;
+    Ok(message_re
+        .and_then(|re| message_violation(&re, message))
+        .into_iter()
+        .collect())
+}
+
+fn message_violation(re: &regex::Regex, message: &str) -> Option<RuleViolation> {
+    if re.is_match(message.trim_end()) {
+        return None;
+    }
+    let subject = message.lines().next().unwrap_or_default();
+    Some(RuleViolation {
+        rule: "commit-message",
+        message: format!("message {subject:?} does not match {}", re.as_str()),
+    })
+}
+
+/// Checks a new branch `name` (with or without `refs/heads/`) against the rules a push
+/// applies when it creates a branch.
+pub fn check_branch_name(rules: &PolicyRules, name: &str) -> Result<Vec<RuleViolation>> {
+    let name = name.strip_prefix("refs/heads/").unwrap_or(name);
+    let mut violations = Vec::new();
+    if gix::refs::FullName::try_from(format!("refs/heads/{name}")).is_err() {
+        violations.push(RuleViolation {
+            rule: "invalid-branch-name",
+            message: format!("{name:?} is not a valid git branch name"),
+        });
+    }
+    if let Some(pattern) = &rules.branch_name_pattern {
+        let re = regex::Regex::new(pattern)?;
+        if !re.is_match(name) {
+            violations.push(RuleViolation {
+                rule: "branch-name",
+                message: format!("branch {name:?} does not match {}", re.as_str()),
+            });
+        }
+    }
+    Ok(violations)
+}
+
+/// Evaluates `rules` against the commits introduced by `updates`.
+pub async fn check_push(
+    repo_dir: &Path,
+    rules: &PolicyRules,
+    updates: &[RefUpdate],
+) -> Result<PolicyReport> {
+    rules.validate()?;
+    let mut created = Vec::new();
+    let mut per_ref = Vec::with_capacity(updates.len());
+    for update in updates {
+        if update.is_delete() {
+            continue;
+        }
+        if update.is_create()
+            && let Some(name) = update.ref_name.strip_prefix("refs/heads/")
+        {
+            for v in check_branch_name(rules, name)? {
+                created.push(PolicyViolation {
+                    ref_name: update.ref_name.clone(),
+                    commit: None,
+                    rule: v.rule,
+                    message: v.message,
+                });
+            }
+        }
+        gix::hash::ObjectId::from_hex(update.new.as_bytes())
+            .with_context(|| format!("invalid object id for {}", update.ref_name))?;
+        let commits = new_commits(repo_dir, &update.new).await?;
+        per_ref.push((update.ref_name.clone(), commits));
+    }
+
+    let repo_dir: PathBuf = repo_dir.to_path_buf();
+    let rules = rules.clone();
+    let mut report =
+        tokio::task::spawn_blocking(move || evaluate(&repo_dir, &rules, per_ref)).await??;
+    report.violations.splice(0..0, created);
+    Ok(report)
+}
+
+/// Commits reachable from `new` but not from any existing ref, oldest first.
+async fn new_commits(repo_dir: &Path, new: &str) -> Result<Vec<gix::hash::ObjectId>> {
+    let output = tokio::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo_dir)
+        .args(["rev-list", "--reverse", new, "--not", "--all"])
+        .output()
+        .await
+        .context("failed to spawn git rev-list")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git rev-list failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| {
+            gix::hash::ObjectId::from_hex(line.trim().as_bytes()).context("parse rev-list output")
+        })
+        .collect()
+}
+
+struct CommitInfo {
+    tree: gix::hash::ObjectId,
+    parents: Vec<gix::hash::ObjectId>,
+    signed: bool,
+    message: String,
+}
+
+fn parse_commit(data: &[u8]) -> Result<CommitInfo> {
+    let mut tree = None;
+    let mut parents = Vec::new();
+    let mut signed = false;
+    let mut rest = data;
+    while let Some(pos) = rest.iter().position(|b| *b == b'\n') {
+        let line = &rest[..pos];
+        rest = &rest[pos + 1..];
+        if line.is_empty() {
+            break;
+        }
+        if let Some(hex) = line.strip_prefix(b"tree ") {
+            tree = Some(gix::hash::ObjectId::from_hex(hex).context("parse commit tree")?);
+        } else if let Some(hex) = line.strip_prefix(b"parent ") {
+            parents.push(gix::hash::ObjectId::from_hex(hex).context("parse commit parent")?);
+        } else if line.starts_with(b"gpgsig ") || line.starts_with(b"gpgsig-sha256 ") {
+            signed = true;
+        }
+    }
+    Ok(CommitInfo {
+        tree: tree.context("commit missing tree")?,
+        parents,
+        signed,
+        message: String::from_utf8_lossy(rest).into_owned(),
+    })
+}
+
+fn evaluate(
+    repo_dir: &Path,
+    rules: &PolicyRules,
+    per_ref: Vec<(String, Vec<gix::hash::ObjectId>)>,
+) -> Result<PolicyReport> {
+    let repo = gix::open(repo_dir)?;
+    let message_re = rules
+        .commit_message_pattern
+        .as_deref()
+        .map(regex::Regex::new)
+        .transpose()?;
+    let needs_tree_diff = rules.max_file_size.is_some() || !rules.forbidden_paths.is_empty();
+
+    let mut report = PolicyReport::default();
+    let mut checked = HashSet::new();
+    for (ref_name, commits) in per_ref {
+        for cid in commits {
+            checked.insert(cid);
+            let object = repo.find_object(cid)?;
+            let commit = parse_commit(object.data.as_ref())?;
+            let mut violate = |rule: &'static str, message: String| {
+                report.violations.push(PolicyViolation {
+                    ref_name: ref_name.clone(),
+                    commit: Some(cid.to_string()),
+                    rule,
+                    message,
+                })
+            };
+
+            if rules.forbid_merge_commits && commit.parents.len() > 1 {
+                violate(
+                    "no-merge-commits",
+                    format!("merge commit with {} parents", commit.parents.len()),
+                );
+            }
+            if rules.require_signed_commits && !commit.signed {
+                violate(
+                    "signed-commits-required",
+                    "commit is not signed".to_string(),
+                );
+            }
+            if let Some(v) = message_re
+                .as_ref()
+                .and_then(|re| message_violation(re, &commit.message))
+            {
+                violate(v.rule, v.message);
+            }
+            if needs_tree_diff {
+                let base = match commit.parents.first() {
+                    Some(parent) => {
+                        Some(parse_commit(repo.find_object(*parent)?.data.as_ref())?.tree)
+                    }
+                    None => None,
+                };
+                let mut changed = Vec::new();
+                diff_trees(&repo, base, commit.tree, "", &mut changed)?;
+                for (path, blob) in changed {
+                    if let Some(pattern) = rules
+                        .forbidden_paths
+                        .iter()
+                        .find(|p| path_matches(p, &path))
+                    {
+                        violate(
+                            "forbidden-path",
+                            format!("{path} matches forbidden pattern {pattern}"),
+                        );
+                    }
+                    if let Some(limit) = rules.max_file_size {
+                        let size = repo.find_object(blob)?.data.len() as u64;
+                        if size > limit {
+                            violate(
+                                "max-file-size",
+                                format!("{path} is {size} bytes (limit {limit})"),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+    report.commits_checked = checked.len();
+    Ok(report)
+}
+
+/// Collects blobs that are new or modified in `new` relative to `old`, descending only
+/// into subtrees whose ids differ.
+fn diff_trees(
+    repo: &gix::Repository,
+    old: Option<gix::hash::ObjectId>,
+    new: gix::hash::ObjectId,
+    prefix: &str,
+    out: &mut Vec<(String, gix::hash::ObjectId)>,
+) -> Result<()> {
+    if old == Some(new) {
+        return Ok(());
+    }
+    let old_entries = match old {
+        Some(tid) => tree_entries(repo, tid)?,
+        None => Vec::new(),
+    };
+    for (name, is_tree, oid) in tree_entries(repo, new)? {
+        let previous = old_entries.iter().find(|(n, _, _)| *n == name);
+        if previous.is_some_and(|(_, _, prev)| *prev == oid) {
+            continue;
+        }
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{prefix}/{name}")
+        };
+        if is_tree {
+            let base = previous
+                .filter(|(_, was_tree, _)| *was_tree)
+                .map(|(_, _, prev)| *prev);
+            diff_trees(repo, base, oid, &path, out)?;
+        } else {
+            out.push((path, oid));
+        }
+    }
+    Ok(())
+}
+
+fn tree_entries(
+    repo: &gix::Repository,
+    tid: gix::hash::ObjectId,
+) -> Result<Vec<(String, bool, gix::hash::ObjectId)>> {
+    let tree = repo.find_object(tid)?;
+    let parsed = gix::objs::TreeRef::from_bytes(tree.data.as_ref())?;
+    Ok(parsed
+        .entries
+        .iter()
+        // submodule commits carry no blob content to check
+        .filter(|e| !e.mode.is_commit())
+        .map(|e| (e.filename.to_string(), e.mode.is_tree(), e.oid.into()))
+        .collect())
+}
+
+/// Matches a repository-relative path against a forbidden-path pattern.
+pub fn path_matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.trim_start_matches('/');
+    if let Some(dir) = pattern.strip_suffix('/') {
+        return path == dir || path.starts_with(&format!("{dir}/"));
+    }
+    // patterns without a slash match the file name at any depth, like .gitignore
+    if !pattern.contains('/') {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        return glob_segment(pattern.as_bytes(), name.as_bytes());
+    }
+    let pat: Vec<&str> = pattern.split('/').collect();
+    let segs: Vec<&str> = path.split('/').collect();
+    glob_path(&pat, &segs)
+}
+
+fn glob_path(pat: &[&str], segs: &[&str]) -> bool {
+    match pat.split_first() {
+        None => segs.is_empty(),
+        Some((&"**", rest)) => (0..=segs.len()).any(|skip| glob_path(rest, &segs[skip..])),
+        Some((first, rest)) => match segs.split_first() {
+            Some((seg, tail)) => {
+                glob_segment(first.as_bytes(), seg.as_bytes()) && glob_path(rest, tail)
+            }
+            None => false,
+        },
+    }
+}
+
+fn glob_segment(pat: &[u8], name: &[u8]) -> bool {
+    match pat.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| glob_segment(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && glob_segment(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && glob_segment(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkt::{Pkt, decode_pkt_lines};
+    use crate::test_helpers::{commit_file, git};
+
+    #[test]
+    fn forbidden_path_patterns() {
+        assert!(path_matches("*.pem", "config/certs/server.pem"));
+        assert!(path_matches("secrets/", "secrets/prod.env"));
+        assert!(!path_matches("secrets/", "app/secrets.rs"));
+        assert!(path_matches("vendor/**/*.so", "vendor/lib/x86/libfoo.so"));
+        assert!(!path_matches("vendor/*.so", "vendor/lib/libfoo.so"));
+    }
+
+    #[test]
+    fn report_status_rejects_violating_refs() {
+        let updates = vec![
+            RefUpdate {
+                ref_name: "refs/heads/main".into(),
+                old: "0".repeat(40),
+                new: "1".repeat(40),
+            },
+            RefUpdate {
+                ref_name: "refs/heads/dev".into(),
+                old: "0".repeat(40),
+                new: "2".repeat(40),
+            },
+        ];
+        let report = PolicyReport {
+            commits_checked: 2,
+            violations: vec![PolicyViolation {
+                ref_name: "refs/heads/main".into(),
+                commit: Some("1".repeat(40)),
+                rule: "no-merge-commits",
+                message: "merge commit with 2 parents".into(),
+            }],
+        };
+        let pkts = decode_pkt_lines(&report.encode_report_status(&updates, false)).unwrap();
+        let lines: Vec<String> = pkts
+            .iter()
+            .filter_map(|p| match p {
+                Pkt::Data(d) => Some(String::from_utf8_lossy(d).into_owned()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(lines[0], "unpack ok\n");
+        assert_eq!(
+            lines[1],
+            "ng refs/heads/main policy violation: no-merge-commits\n"
+        );
+        assert_eq!(lines[2], "ok refs/heads/dev\n");
+
+        let banded = decode_pkt_lines(&report.encode_report_status(&updates, true)).unwrap();
+        assert!(matches!(&banded[0], Pkt::Data(d) if d[0] == 2));
+        assert!(matches!(&banded[1], Pkt::Data(d) if d[0] == 1));
+    }
+
+    #[tokio::test]
+    async fn check_push_inspects_only_new_commits() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let work = tmp.path();
+        git(work, &["init", "-q", "-b", "main"]);
+        let base = commit_file(work, "big.bin", &"0".repeat(2048), "wip");
+        let pushed = commit_file(work, "secrets/prod.env", "KEY=1\n", "add env");
+        // rewind the branch so `pushed` looks like an incoming, not-yet-referenced commit
+        git(work, &["update-ref", "refs/heads/pushing", &base]);
+        git(work, &["checkout", "-q", "pushing"]);
+        git(work, &["branch", "-D", "-q", "main"]);
+
+        let rules = PolicyRules {
+            max_file_size: Some(1024),
+            forbidden_paths: vec!["secrets/".into()],
+            commit_message_pattern: Some("^(feat|fix): ".into()),
+            ..Default::default()
+        };
+        let updates = vec![RefUpdate {
+            ref_name: "refs/heads/pushing".into(),
+            old: base.clone(),
+            new: pushed.clone(),
+        }];
+        let report = check_push(&work.join(".git"), &rules, &updates)
+            .await
+            .unwrap();
+
+        // the oversized blob lives in already-referenced history and is not re-checked
+        assert_eq!(report.commits_checked, 1);
+        let rules_hit: Vec<&str> = report.violations.iter().map(|v| v.rule).collect();
+        assert_eq!(rules_hit, vec!["commit-message", "forbidden-path"]);
+        assert_eq!(
+            report.violations[0].commit.as_deref(),
+            Some(pushed.as_str())
+        );
+
+        // branch names are only checked when a push creates the branch
+        let rules = PolicyRules {
+            branch_name_pattern: Some("^feature/".into()),
+            ..Default::default()
+        };
+        let create = |ref_name: &str| RefUpdate {
+            ref_name: ref_name.into(),
+            old: "0".repeat(40),
+            new: pushed.clone(),
+        };
+        let creates = vec![
+            create("refs/heads/wip"),
+            create("refs/heads/feature/env"),
+            create("refs/tags/v1"),
+        ];
+        let report = check_push(&work.join(".git"), &rules, &creates)
+            .await
+            .unwrap();
+        let rejected: Vec<(&str, &str)> = report
+            .violations
+            .iter()
+            .map(|v| (v.ref_name.as_str(), v.rule))
+            .collect();
+        assert_eq!(rejected, vec![("refs/heads/wip", "branch-name")]);
+        assert!(report.violations[0].commit.is_none());
+        assert!(
+            check_push(&work.join(".git"), &rules, &updates)
+                .await
+                .unwrap()
+                .allowed()
+        );
+    }
+
+    #[test]
+    fn message_and_branch_rules_check_on_their_own() {
+        let rules = PolicyRules {
+            commit_message_pattern: Some("^(feat|fix): ".into()),
+            branch_name_pattern: Some("^(feature|fix)/[a-z0-9-]+$".into()),
+            ..Default::default()
+        };
+        assert!(
+            check_commit_message(&rules, "fix: parser\n")
+                .unwrap()
+                .is_empty()
+        );
+        let bad = check_commit_message(&rules, "wip\n\nmore").unwrap();
+        assert_eq!(bad.len(), 1);
+        assert_eq!(bad[0].rule, "commit-message");
+        assert_eq!(
+            bad[0].message,
+            "message \"wip\" does not match ^(feat|fix): "
+        );
+        assert!(
+            check_commit_message(&PolicyRules::default(), "anything")
+                .unwrap()
+                .is_empty()
+        );
+
+        assert!(
+            check_branch_name(&rules, "feature/login")
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            check_branch_name(&rules, "refs/heads/fix/crash-12")
+                .unwrap()
+                .is_empty()
+        );
+        let rules_hit = |name: &str| -> Vec<&'static str> {
+            check_branch_name(&rules, name)
+                .unwrap()
+                .into_iter()
+                .map(|v| v.rule)
+                .collect()
+        };
+        assert_eq!(rules_hit("Feature/Login"), vec!["branch-name"]);
+        assert_eq!(
+            rules_hit("feature/a..b"),
+            vec!["invalid-branch-name", "branch-name"]
+        );
+        assert_eq!(
+            check_branch_name(&PolicyRules::default(), "topic.lock").unwrap()[0].rule,
+            "invalid-branch-name"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_message_pattern() {
+        let rules = PolicyRules {
+            commit_message_pattern: Some("(unclosed".into()),
+            ..Default::default()
+        };
+        assert!(rules.validate().is_err());
+    }
+}
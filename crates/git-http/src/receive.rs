@@ -0,0 +1,446 @@
+//! Push ingestion for `git-receive-pack`.
+//!
+//! A push request is a list of `<old> <new> <ref>` commands followed by a packfile. The
+//! pack is indexed into the repository with `git index-pack --fix-thin`, each new tip is
+//! checked to be fully connected, the push policy is evaluated, and only then are refs
+//! moved, every one with a compare-and-swap on its old value. Commands are applied
+//! independently, like `git receive-pack` does without `atomic`. Objects of a rejected
+//! push stay unreferenced in the repository until `git gc` prunes them.
+
+use std::path::Path;
+
+use anyhow::{Context as _, Result, bail};
+use tokio::io::AsyncWriteExt;
+
+use crate::pkt::{PKT_FLUSH, encode_pkt_line, encode_side_band};
+use crate::policy::{PolicyRules, RefUpdate, check_push};
+use crate::refs::RefPolicy;
+
+/// Capabilities advertised on the first ref line of a push advertisement.
+const CAPABILITIES: &str =
+    "report-status delete-refs side-band-64k quiet ofs-delta object-format=sha1";
+
+const ZERO_OID: &str = "0000000000000000000000000000000000000000";
+
+/// The commands of a push request and the capabilities the client asked for.
+#[derive(Debug, Default)]
+pub struct PushRequest {
+    pub updates: Vec<RefUpdate>,
+    pub report_status: bool,
+    pub side_band_64k: bool,
+    /// Where the packfile starts in the request body.
+    pack_offset: usize,
+}
+
+impl PushRequest {
+    pub fn pack<'a>(&self, body: &'a [u8]) -> &'a [u8] {
+        &body[self.pack_offset.min(body.len())..]
+    }
+}
+
+/// Parses the command list of a push; the packfile that follows is left in place.
+pub fn parse_push(body: &[u8]) -> Result<PushRequest> {
+    let mut req = PushRequest::default();
+    let mut pos = 0usize;
+    loop {
+        let len_hex = body.get(pos..pos + 4).context("truncated push commands")?;
+        let len = usize::from_str_radix(std::str::from_utf8(len_hex)?, 16)?;
+        pos += 4;
+        if len == 0 {
+            break;
+        }
+        if len < 4 {
+            bail!("unexpected pkt-line in push commands");
+        }
+        let line = body
+            .get(pos..pos + len - 4)
+            .context("truncated push command")?;
+        pos += len - 4;
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let (command, caps) = match line.iter().position(|b| *b == 0) {
+            Some(nul) => (&line[..nul], Some(&line[nul + 1..])),
+            None => (line, None),
+        };
+        for cap in caps
+            .map(String::from_utf8_lossy)
+            .iter()
+            .flat_map(|caps| caps.split(' '))
+        {
+            match cap {
+                "report-status" => req.report_status = true,
+                "side-band-64k" => req.side_band_64k = true,
+                c if c.starts_with("object-format=") && c != "object-format=sha1" => {
+                    bail!("unsupported {c}")
+                }
+                _ => {}
+            }
+        }
+        let command = std::str::from_utf8(command).context("push command is not UTF-8")?;
+        if command.starts_with("shallow ") {
+            bail!("pushing from a shallow clone is not supported");
+        }
+        let mut parts = command.splitn(3, ' ');
+        let (Some(old), Some(new), Some(ref_name)) = (parts.next(), parts.next(), parts.next())
+        else {
+            bail!("malformed push command {command:?}");
+        };
+        for oid in [old, new] {
+            if oid.len() != 40
+                || !oid
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+            {
+                bail!("invalid object id {oid:?}");
+            }
+        }
+        if !ref_name.starts_with("refs/") || gix::refs::FullName::try_from(ref_name).is_err() {
+            bail!("invalid ref name {ref_name:?}");
+        }
+        if req.updates.iter().any(|u| u.ref_name == ref_name) {
+            bail!("{ref_name} is updated more than once");
+        }
+        req.updates.push(RefUpdate {
+            ref_name: ref_name.to_string(),
+            old: old.to_string(),
+            new: new.to_string(),
+        });
+    }
+    if req.updates.is_empty() {
+        bail!("push without commands");
+    }
+    req.pack_offset = pos;
+    Ok(req)
+}
+
+/// The `info/refs?service=git-receive-pack` body: every ref the client may see, with the
+/// capabilities on the first line.
+pub async fn advertise_refs(repo_dir: &Path, refs: &RefPolicy) -> Result<Vec<u8>> {
+    let output = tokio::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo_dir)
+        .args(["for-each-ref", "--format=%(objectname) %(refname)"])
+        .output()
+        .await
+        .context("failed to spawn git for-each-ref")?;
+    if !output.status.success() {
+        bail!(
+            "git for-each-ref failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let caps = format!("{CAPABILITIES} agent=forge/{}", env!("CARGO_PKG_VERSION"));
+    let listing = String::from_utf8_lossy(&output.stdout);
+    let mut lines: Vec<String> = listing
+        .lines()
+        .filter(|line| {
+            line.split_once(' ')
+                .is_some_and(|(_, name)| !refs.is_hidden(name))
+        })
+        .map(str::to_string)
+        .collect();
+    if lines.is_empty() {
+        lines.push(format!("{ZERO_OID} capabilities^{{}}"));
+    }
+
+    let mut body = encode_pkt_line(b"# service=git-receive-pack\n");
+    body.extend_from_slice(PKT_FLUSH);
+    for (i, line) in lines.iter().enumerate() {
+        let line = if i == 0 {
+            format!("{line}\0{caps}\n")
+        } else {
+            format!("{line}\n")
+        };
+        body.extend_from_slice(&encode_pkt_line(line.as_bytes()));
+    }
+    body.extend_from_slice(PKT_FLUSH);
+    Ok(body)
+}
+
+/// What happened to a push: whether the pack was accepted and, per command, `None` when
+/// the ref moved or the reason it did not.
+#[derive(Debug)]
+pub struct PushOutcome {
+    pub unpack: std::result::Result<(), String>,
+    pub refs: Vec<(RefUpdate, Option<String>)>,
+    /// Sent on side-band 2, so clients print them as `remote: ...`.
+    pub diagnostics: Vec<String>,
+}
+
+impl PushOutcome {
+    /// The commands that were applied.
+    pub fn applied(&self) -> Vec<RefUpdate> {
+        self.refs
+            .iter()
+            .filter(|(_, reason)| reason.is_none())
+            .map(|(update, _)| update.clone())
+            .collect()
+    }
+
+    /// Encodes a `report-status` response, framed on band 1 with side-band.
+    pub fn encode_report_status(&self, side_band_64k: bool) -> Vec<u8> {
+        let mut status = Vec::new();
+        let unpack = match &self.unpack {
+            Ok(()) => "unpack ok\n".to_string(),
+            Err(reason) => format!("unpack {reason}\n"),
+        };
+        status.extend_from_slice(&encode_pkt_line(unpack.as_bytes()));
+        for (update, reason) in &self.refs {
+            let line = match reason {
+                Some(reason) => format!("ng {} {reason}\n", update.ref_name),
+                None => format!("ok {}\n", update.ref_name),
+            };
+            status.extend_from_slice(&encode_pkt_line(line.as_bytes()));
+        }
+        status.extend_from_slice(PKT_FLUSH);
+        if !side_band_64k {
+            return status;
+        }
+
+        let mut out = Vec::new();
+        for line in &self.diagnostics {
+            out.extend_from_slice(&encode_side_band(2, line.as_bytes()));
+        }
+        out.extend_from_slice(&encode_side_band(1, &status));
+        out.extend_from_slice(PKT_FLUSH);
+        out
+    }
+}
+
+/// Ingests the pack of `req` into `repo_dir` and applies every command that passes the
+/// connectivity check, `refs` and `rules`.
+pub async fn receive_push(
+    repo_dir: &Path,
+    rules: &PolicyRules,
+    refs: &RefPolicy,
+    req: &PushRequest,
+    body: &[u8],
+) -> Result<PushOutcome> {
+    let mut outcome = PushOutcome {
+        unpack: Ok(()),
+        refs: req
+            .updates
+            .iter()
+            .map(|update| (update.clone(), None))
+            .collect(),
+        diagnostics: Vec::new(),
+    };
+    let pack = req.pack(body);
+    if (req.updates.iter().any(|update| !update.is_delete()) || !pack.is_empty())
+        && let Err(e) = index_pack(repo_dir, pack).await
+    {
+        tracing::debug!("push to {} was not unpacked: {:#}", repo_dir.display(), e);
+        outcome.unpack = Err("index-pack failed".to_string());
+        outcome.diagnostics.push(format!("error: {e:#}\n"));
+        for (_, reason) in &mut outcome.refs {
+            *reason = Some("unpacker error".to_string());
+        }
+        return Ok(outcome);
+    }
+
+    let head = current_branch(repo_dir).await;
+    for (update, reason) in &mut outcome.refs {
+        *reason = if refs.is_hidden(&update.ref_name) {
+            Some("deny updating a hidden ref".to_string())
+        } else if update.is_delete() && head.as_deref() == Some(update.ref_name.as_str()) {
+            Some("deletion of the current branch prohibited".to_string())
+        } else if !update.is_delete() && !is_connected(repo_dir, &update.new).await? {
+            Some("missing necessary objects".to_string())
+        } else {
+            None
+        };
+    }
+
+    let checked: Vec<RefUpdate> = outcome
+        .refs
+        .iter()
+        .filter(|(_, reason)| reason.is_none())
+        .map(|(update, _)| update.clone())
+        .collect();
+    if !rules.is_empty() && !checked.is_empty() {
+        let report = check_push(repo_dir, rules, &checked).await?;
+        for v in &report.violations {
+            outcome.diagnostics.push(v.diagnostic());
+            if let Some((_, reason)) = outcome
+                .refs
+                .iter_mut()
+                .find(|(update, _)| update.ref_name == v.ref_name)
+            {
+                reason.get_or_insert_with(|| format!("policy violation: {}", v.rule));
+            }
+        }
+    }
+
+    for (update, reason) in &mut outcome.refs {
+        if reason.is_some() {
+            continue;
+        }
+        if let Err(e) = update_ref(repo_dir, update).await {
+            tracing::debug!(
+                "{} was not updated in {}: {:#}",
+                update.ref_name,
+                repo_dir.display(),
+                e
+            );
+            *reason = Some("failed to update ref".to_string());
+        }
+    }
+    Ok(outcome)
+}
+
+async fn index_pack(repo_dir: &Path, pack: &[u8]) -> Result<()> {
+    let mut child = tokio::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo_dir)
+        .args(["index-pack", "--stdin", "--fix-thin"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .context("failed to spawn git index-pack")?;
+    let mut stdin = child.stdin.take().context("missing git stdin")?;
+    stdin
+        .write_all(pack)
+        .await
+        .context("failed to write the pack to git")?;
+    drop(stdin);
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        bail!(
+            "git index-pack failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Whether every object reachable from `oid` is in the repository. Walking stops at
+/// existing refs, which are connected already.
+async fn is_connected(repo_dir: &Path, oid: &str) -> Result<bool> {
+    let status = tokio::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo_dir)
+        .args(["rev-list", "--objects", "--quiet", oid, "--not", "--all"])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await
+        .context("failed to spawn git rev-list")?;
+    Ok(status.success())
+}
+
+/// The full name of the branch `HEAD` points at.
+async fn current_branch(repo_dir: &Path) -> Option<String> {
+    let head = tokio::fs::read_to_string(repo_dir.join("HEAD"))
+        .await
+        .ok()?;
+    head.trim().strip_prefix("ref: ").map(str::to_string)
+}
+
+async fn update_ref(repo_dir: &Path, update: &RefUpdate) -> Result<()> {
+    let mut cmd = tokio::process::Command::new("git");
+    cmd.arg("--git-dir")
+        .arg(repo_dir)
+        .args(["update-ref", "-m", "push"]);
+    // a zero old value makes git check that the ref does not exist yet
+    if update.is_delete() {
+        cmd.args(["-d", &update.ref_name, &update.old]);
+    } else {
+        cmd.args([&update.ref_name, &update.new, &update.old]);
+    }
+    let output = cmd
+        .output()
+        .await
+        .context("failed to spawn git update-ref")?;
+    if !output.status.success() {
+        bail!(
+            "git update-ref failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkt::{Pkt, decode_pkt_lines};
+
+    fn command(line: &str) -> Vec<u8> {
+        encode_pkt_line(line.as_bytes())
+    }
+
+    #[test]
+    fn parses_commands_capabilities_and_pack_offset() {
+        let old = "1".repeat(40);
+        let new = "2".repeat(40);
+        let mut body = command(&format!(
+            "{old} {new} refs/heads/main\0report-status side-band-64k agent=git/2\n"
+        ));
+        body.extend(command(&format!("{old} {ZERO_OID} refs/heads/old\n")));
+        body.extend_from_slice(PKT_FLUSH);
+        body.extend_from_slice(b"PACK...");
+
+        let req = parse_push(&body).unwrap();
+        assert!(req.report_status && req.side_band_64k);
+        assert_eq!(req.updates.len(), 2);
+        assert_eq!(req.updates[0].ref_name, "refs/heads/main");
+        assert!(req.updates[1].is_delete());
+        assert_eq!(req.pack(&body), b"PACK...");
+
+        let invalid = |line: String| {
+            let mut body = command(&line);
+            body.extend_from_slice(PKT_FLUSH);
+            parse_push(&body).is_err()
+        };
+        assert!(invalid(format!("{old} {new} HEAD\n")));
+        assert!(invalid(format!("{old} {new} refs/heads/a..b\n")));
+        assert!(invalid(format!(
+            "{old} {} refs/heads/main\n",
+            "Z".repeat(40)
+        )));
+        assert!(invalid(format!("shallow {old}\n")));
+        assert!(parse_push(PKT_FLUSH).is_err());
+    }
+
+    #[test]
+    fn report_status_lists_every_command() {
+        let update = |name: &str| RefUpdate {
+            ref_name: name.into(),
+            old: ZERO_OID.into(),
+            new: "1".repeat(40),
+        };
+        let outcome = PushOutcome {
+            unpack: Ok(()),
+            refs: vec![
+                (update("refs/heads/main"), None),
+                (
+                    update("refs/heads/wip"),
+                    Some("missing necessary objects".into()),
+                ),
+            ],
+            diagnostics: vec!["error: something\n".into()],
+        };
+        let lines: Vec<String> = decode_pkt_lines(&outcome.encode_report_status(false))
+            .unwrap()
+            .into_iter()
+            .filter_map(|p| match p {
+                Pkt::Data(d) => Some(String::from_utf8(d).unwrap()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "unpack ok\n",
+                "ok refs/heads/main\n",
+                "ng refs/heads/wip missing necessary objects\n"
+            ]
+        );
+        assert_eq!(outcome.applied().len(), 1);
+
+        let banded = decode_pkt_lines(&outcome.encode_report_status(true)).unwrap();
+        assert!(matches!(&banded[0], Pkt::Data(d) if d[0] == 2));
+        assert!(matches!(&banded[1], Pkt::Data(d) if d[0] == 1));
+        assert!(matches!(banded[2], Pkt::Flush));
+    }
+}
@@ -0,0 +1,434 @@
+//! Ref visibility and fetch-by-id allowances.
+//!
+//! A [`RefPolicy`] decides which refs `ls-refs` advertises and which objects a `fetch`
+//! may name in `want` lines. It mirrors git's `uploadpack.hideRefs`,
+//! `uploadpack.allowTipSHA1InWant`, `uploadpack.allowReachableSHA1InWant` and
+//! `uploadpack.allowAnySHA1InWant`. `git upload-pack` only applies the want allowances
+//! to protocol v0 requests and serves any existing object over v2, so [`check_wants`]
+//! runs before either backend sees a fetch. The git backend also receives the policy
+//! as `-c` options (see [`RefPolicy::git_config_args`]), which hide refs from its
+//! `ls-refs`. Both backends therefore answer the same request the same way:
+//!
+//! - Hidden refs are never advertised and cannot be fetched by name (`want-ref`).
+//! - With `fetch_hidden`, the tips of hidden refs may still be fetched by object id.
+//!   This is how `refs/pull/*` heads stay fetchable without cluttering every clone.
+//! - Anything else a client wants by id must be allowed by [`WantPolicy`].
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::v2::FetchRequest;
+
+/// Objects a client may ask for by id, beyond the tips of advertised refs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WantPolicy {
+    /// Only ref tips, git's default.
+    #[default]
+    Advertised,
+    /// Anything reachable from an advertised ref (`allowReachableSHA1InWant`).
+    Reachable,
+    /// Any object in the repository (`allowAnySHA1InWant`).
+    Any,
+}
+
+impl WantPolicy {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "advertised" => Some(Self::Advertised),
+            "reachable" => Some(Self::Reachable),
+            "any" => Some(Self::Any),
+            _ => None,
+        }
+    }
+}
+
+/// Per-repository ref visibility. The default advertises every ref and only serves ref tips.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RefPolicy {
+    pub want: WantPolicy,
+    /// Refs left out of `ls-refs`: `refs/pull/*`, `refs/pull/` and `refs/pull` all hide
+    /// every ref under `refs/pull/`; a full name hides that ref.
+    pub hidden_refs: Vec<String>,
+    /// Let clients fetch the tips of hidden refs by object id (`allowTipSHA1InWant`).
+    pub fetch_hidden: bool,
+}
+
+impl RefPolicy {
+    /// Reads `FORGE_GIT_WANT_POLICY` (`advertised`, `reachable` or `any`),
+    /// `FORGE_GIT_HIDDEN_REFS` (comma-separated patterns) and `FORGE_GIT_FETCH_HIDDEN_REFS`.
+    /// Invalid values are logged and ignored.
+    pub fn from_env() -> Self {
+        let mut policy = RefPolicy::default();
+        if let Ok(value) = std::env::var("FORGE_GIT_WANT_POLICY") {
+            match WantPolicy::parse(&value) {
+                Some(want) => policy.want = want,
+                None => tracing::warn!("ignoring FORGE_GIT_WANT_POLICY={value:?}"),
+            }
+        }
+        if let Ok(value) = std::env::var("FORGE_GIT_HIDDEN_REFS") {
+            for pattern in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                match validate_pattern(pattern) {
+                    Ok(()) => policy.hidden_refs.push(pattern.to_string()),
+                    Err(e) => tracing::warn!("ignoring hidden ref pattern: {e}"),
+                }
+            }
+        }
+        policy.fetch_hidden =
+            std::env::var("FORGE_GIT_FETCH_HIDDEN_REFS").ok().as_deref() == Some("true");
+        policy
+    }
+
+    /// Checks that every hidden ref pattern can be enforced by both backends.
+    pub fn validate(&self) -> Result<()> {
+        self.hidden_refs
+            .iter()
+            .try_for_each(|p| validate_pattern(p))
+    }
+
+    pub fn is_hidden(&self, name: &str) -> bool {
+        self.hidden_prefixes().any(|prefix| {
+            name.strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+
+    /// `-c` options that make `git upload-pack` apply this policy; pass them before the
+    /// subcommand.
+    pub fn git_config_args(&self) -> Vec<String> {
+        let mut config: Vec<String> = self
+            .hidden_prefixes()
+            .map(|p| format!("uploadpack.hideRefs={p}"))
+            .collect();
+        if self.fetch_hidden {
+            config.push("uploadpack.allowTipSHA1InWant=true".to_string());
+        }
+        match self.want {
+            WantPolicy::Advertised => {}
+            WantPolicy::Reachable => {
+                config.push("uploadpack.allowReachableSHA1InWant=true".to_string())
+            }
+            WantPolicy::Any => config.push("uploadpack.allowAnySHA1InWant=true".to_string()),
+        }
+        config
+            .into_iter()
+            .flat_map(|c| ["-c".to_string(), c])
+            .collect()
+    }
+
+    /// git matches `hideRefs` as a prefix ending at a `/` boundary, which is what the
+    /// trailing `/*` spells.
+    fn hidden_prefixes(&self) -> impl Iterator<Item = &str> {
+        self.hidden_refs
+            .iter()
+            .map(|p| p.trim_end_matches('*').trim_end_matches('/'))
+            .filter(|p| !p.is_empty())
+    }
+}
+
+fn validate_pattern(pattern: &str) -> Result<()> {
+    let prefix = pattern.strip_suffix("/*").unwrap_or(pattern);
+    if prefix.trim_end_matches('/').is_empty() || prefix.contains('*') || prefix.starts_with('!') {
+        anyhow::bail!("{pattern:?} is not a ref name or a `refs/<prefix>/*` pattern");
+    }
+    Ok(())
+}
+
+/// Checks the wants of a fetch against `policy`, like git's upload-pack does before
+/// negotiating. The error is the message for the client's `ERR` line.
+pub async fn check_wants(
+    repo_dir: &Path,
+    policy: &RefPolicy,
+    req: &FetchRequest,
+) -> Result<(), String> {
+    if let Some(name) = req.want_refs().iter().find(|name| policy.is_hidden(name)) {
+        return Err(format!("unknown ref {name}"));
+    }
+    if policy.want == WantPolicy::Any || req.wants().is_empty() {
+        return Ok(());
+    }
+
+    let (advertised, hidden) = {
+        let repo_dir = repo_dir.to_path_buf();
+        let policy = policy.clone();
+        tokio::task::spawn_blocking(move || ref_tips(&repo_dir, &policy))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| format!("upload-pack: {e}"))?
+    };
+    let outside: Vec<&String> = req
+        .wants()
+        .iter()
+        .filter(|w| !advertised.contains(w.as_str()) && !hidden.contains(w.as_str()))
+        .collect();
+    let Some(first) = outside.first() else {
+        return Ok(());
+    };
+    if policy.want == WantPolicy::Advertised {
+        return Err(format!("upload-pack: not our ref {first}"));
+    }
+
+    let unreachable = unreachable_objects(repo_dir, &outside, &advertised)
+        .await
+        .map_err(|e| format!("upload-pack: {e}"))?;
+    match outside.iter().find(|w| unreachable.contains(w.as_str())) {
+        Some(want) => Err(format!("upload-pack: not our ref {want}")),
+        None => Ok(()),
+    }
+}
+
+/// Ids a client may want as ref tips: advertised refs and, with `fetch_hidden`, hidden
+/// ones. Annotated tags count with every object they peel to.
+fn ref_tips(repo_dir: &Path, policy: &RefPolicy) -> Result<(HashSet<String>, HashSet<String>)> {
+    let repo = gix::open(repo_dir)?;
+    let mut advertised = HashSet::new();
+    let mut hidden = HashSet::new();
+    let mut refs = repo.references()?;
+    for reference in refs.all()? {
+        let Ok(reference) = reference else { continue };
+        let name = reference.name().as_bstr().to_string();
+        let tips = if !policy.is_hidden(&name) {
+            &mut advertised
+        } else if policy.fetch_hidden {
+            &mut hidden
+        } else {
+            continue;
+        };
+        let Some(id) = reference.try_id() else {
+            continue;
+        };
+        let mut object = match repo.find_object(id.detach()) {
+            Ok(object) => object,
+            Err(_) => continue,
+        };
+        tips.insert(object.id.to_string());
+        while object.kind == gix::objs::Kind::Tag {
+            let target = gix::objs::TagRef::from_bytes(object.data.as_ref())
+                .map_err(|e| anyhow::anyhow!(e.to_string()))?
+                .target();
+            tips.insert(target.to_string());
+            object = match repo.find_object(target) {
+                Ok(object) => object,
+                Err(_) => break,
+            };
+        }
+    }
+    if let Ok(head) = repo.head_id()
+        && !policy.is_hidden("HEAD")
+    {
+        advertised.insert(head.to_string());
+    }
+    Ok((advertised, hidden))
+}
+
+/// The subset of `wants` not reachable from `tips`; unknown objects count as unreachable.
+async fn unreachable_objects(
+    repo_dir: &Path,
+    wants: &[&String],
+    tips: &HashSet<String>,
+) -> Result<HashSet<String>> {
+    if wants
+        .iter()
+        .any(|w| gix::hash::ObjectId::from_hex(w.as_bytes()).is_err())
+    {
+        return Ok(wants.iter().map(|w| w.to_string()).collect());
+    }
+    let mut child = tokio::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo_dir)
+        .args(["rev-list", "--objects", "--stdin"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .context("failed to spawn git rev-list")?;
+    let mut input = String::new();
+    for want in wants {
+        input.push_str(&format!("{want}\n"));
+    }
+    for tip in tips {
+        input.push_str(&format!("^{tip}\n"));
+    }
+    let mut stdin = child.stdin.take().context("missing git stdin")?;
+    stdin.write_all(input.as_bytes()).await?;
+    drop(stdin);
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        // A want that does not exist makes rev-list fail.
+        return Ok(wants.iter().map(|w| w.to_string()).collect());
+    }
+    let listed: HashSet<&str> = std::str::from_utf8(&output.stdout)?
+        .lines()
+        .filter_map(|line| line.split(' ').next())
+        .collect();
+    Ok(wants
+        .iter()
+        .filter(|w| listed.contains(w.as_str()))
+        .map(|w| w.to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{commit_file, git};
+    use crate::v2::SyntheticFetch;
+
+    /// Two commits on `main` and a `refs/pull/1/head` commit on top of them; returns the
+    /// bare repository and the three commit ids, oldest first.
+    fn repo_with_pull_ref(root: &Path) -> (std::path::PathBuf, [String; 3]) {
+        let work = root.join("work");
+        let bare = root.join("bare.git");
+        std::fs::create_dir_all(&work).unwrap();
+        git(root, &["init", "-q", "--bare", bare.to_str().unwrap()]);
+        git(&work, &["init", "-q", "-b", "main"]);
+        let mut commits = Vec::new();
+        for name in ["one", "two", "three"] {
+            commits.push(commit_file(&work, name, name, name));
+        }
+        let bare_path = bare.to_str().unwrap();
+        git(
+            &work,
+            &[
+                "push",
+                "-q",
+                bare_path,
+                &format!("{}:refs/heads/main", commits[1]),
+            ],
+        );
+        git(&work, &["push", "-q", bare_path, "HEAD:refs/pull/1/head"]);
+        git(&bare, &["symbolic-ref", "HEAD", "refs/heads/main"]);
+        (bare, commits.try_into().unwrap())
+    }
+
+    fn fetch(wants: &[&String], want_refs: &[&str]) -> FetchRequest {
+        SyntheticFetch {
+            wants: wants.iter().map(|w| w.to_string()).collect(),
+            want_refs: want_refs.iter().map(|r| r.to_string()).collect(),
+            ..Default::default()
+        }
+        .to_fetch_request()
+    }
+
+    #[test]
+    fn hidden_ref_patterns() {
+        let policy = RefPolicy {
+            hidden_refs: vec![
+                "refs/pull/*".into(),
+                "refs/keep-around/".into(),
+                "refs/heads/wip".into(),
+            ],
+            ..Default::default()
+        };
+        assert!(policy.is_hidden("refs/pull/12/head"));
+        assert!(policy.is_hidden("refs/keep-around/abc"));
+        assert!(policy.is_hidden("refs/heads/wip"));
+        assert!(policy.is_hidden("refs/heads/wip/deep"));
+        assert!(!policy.is_hidden("refs/heads/wip-2"));
+        assert!(!policy.is_hidden("refs/pulls"));
+        assert!(!policy.is_hidden("HEAD"));
+        policy.validate().unwrap();
+
+        for bad in ["refs/heads/wip-*", "*", "!refs/pull/", "/"] {
+            let policy = RefPolicy {
+                hidden_refs: vec![bad.into()],
+                ..Default::default()
+            };
+            assert!(policy.validate().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn git_backend_receives_matching_config() {
+        let policy = RefPolicy {
+            want: WantPolicy::Reachable,
+            hidden_refs: vec!["refs/pull/*".into()],
+            fetch_hidden: true,
+        };
+        assert_eq!(
+            policy.git_config_args(),
+            [
+                "-c",
+                "uploadpack.hideRefs=refs/pull",
+                "-c",
+                "uploadpack.allowTipSHA1InWant=true",
+                "-c",
+                "uploadpack.allowReachableSHA1InWant=true",
+            ]
+        );
+        assert!(RefPolicy::default().git_config_args().is_empty());
+    }
+
+    #[tokio::test]
+    async fn wants_are_checked_against_the_policy() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (bare, [first, main, pull]) = repo_with_pull_ref(tmp.path());
+        let hidden = RefPolicy {
+            hidden_refs: vec!["refs/pull/*".into()],
+            ..Default::default()
+        };
+
+        check_wants(&bare, &hidden, &fetch(&[&main], &[]))
+            .await
+            .unwrap();
+        let err = check_wants(&bare, &hidden, &fetch(&[&pull], &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(err, format!("upload-pack: not our ref {pull}"));
+        assert!(
+            check_wants(&bare, &hidden, &fetch(&[&first], &[]))
+                .await
+                .is_err()
+        );
+        let err = check_wants(&bare, &hidden, &fetch(&[], &["refs/pull/1/head"]))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "unknown ref refs/pull/1/head");
+
+        let fetchable = RefPolicy {
+            fetch_hidden: true,
+            ..hidden.clone()
+        };
+        check_wants(&bare, &fetchable, &fetch(&[&pull], &[]))
+            .await
+            .unwrap();
+        assert!(
+            check_wants(&bare, &fetchable, &fetch(&[], &["refs/pull/1/head"]))
+                .await
+                .is_err()
+        );
+
+        // Reachability counts advertised refs only, so the hidden commit stays out.
+        let reachable = RefPolicy {
+            want: WantPolicy::Reachable,
+            ..hidden.clone()
+        };
+        check_wants(&bare, &reachable, &fetch(&[&first], &[]))
+            .await
+            .unwrap();
+        assert!(
+            check_wants(&bare, &reachable, &fetch(&[&pull], &[]))
+                .await
+                .is_err()
+        );
+        let missing = "1".repeat(40);
+        assert!(
+            check_wants(&bare, &reachable, &fetch(&[&missing], &[]))
+                .await
+                .is_err()
+        );
+
+        let any = RefPolicy {
+            want: WantPolicy::Any,
+            ..hidden
+        };
+        check_wants(&bare, &any, &fetch(&[&pull], &[]))
+            .await
+            .unwrap();
+    }
+}
@@ -5,6 +5,20 @@ pub trait RepositoryProvider {
     fn ensure_local_repository(&self, segments: &[String]) -> anyhow::Result<PathBuf>;
 }
 
+/// Splits a URL path such as `/group/sub/repo.git` into `["group", "sub", "repo"]`.
+///
+/// Shared by the HTTP handlers and the server's path resolver so both agree on how
+/// repository paths are spelled.
+pub fn split_repository_path(path: &str) -> Vec<String> {
+    let trimmed = path.trim_matches('/');
+    let trimmed = trimmed.strip_suffix(".git").unwrap_or(trimmed);
+    trimmed
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
 pub fn resolve_repo_dir<P>(
     storage: &P,
     segments: &[String],
@@ -36,3 +50,18 @@ pub fn is_public_repo(dir: &PathBuf) -> bool {
     let marker = dir.join("git-daemon-export-ok");
     marker.exists()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_repository_path_handles_depth_and_suffix() {
+        assert_eq!(split_repository_path("/alpha.git"), vec!["alpha"]);
+        assert_eq!(
+            split_repository_path("team/infra/tools/"),
+            vec!["team", "infra", "tools"]
+        );
+        assert!(split_repository_path("//").is_empty());
+    }
+}
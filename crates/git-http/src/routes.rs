@@ -0,0 +1,404 @@
+//! Wildcard routing for repositories nested under arbitrarily deep groups.
+//!
+//! Axum wildcards must be the last path segment, so `/{*path}` captures the whole
+//! request path and [`parse_git_route`] splits it into repository segments and the
+//! git endpoint being addressed. Browser-facing endpoints use a GitLab-style `/-/`
+//! separator so file paths can never be mistaken for group names:
+//!
+//! - `.../repo(.git)/info/refs`
+//! - `.../repo(.git)/git-upload-pack`
+//! - `.../repo(.git)/git-receive-pack`
+//! - `.../repo/-/archive/<ref>.tar.gz` (also `.tar`, `.zip`)
+//! - `.../repo/-/raw/<ref>/<file path>`
+
+use std::process::Stdio;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use tokio_util::io::ReaderStream;
+
+use crate::GitHttpState;
+use crate::repo::{resolve_repo_dir, split_repository_path};
+use crate::scheduler::{GitPermit, GitPriority};
+use crate::v2::{self, ServiceQuery};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GitRoute {
+    InfoRefs,
+    UploadPack,
+    ReceivePack,
+    Archive {
+        reference: String,
+        format: ArchiveFormat,
+    },
+    Raw {
+        reference: String,
+        path: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    fn split(name: &str) -> Option<(&str, ArchiveFormat)> {
+        if let Some(reference) = name.strip_suffix(".tar.gz") {
+            Some((reference, ArchiveFormat::TarGz))
+        } else if let Some(reference) = name.strip_suffix(".tar") {
+            Some((reference, ArchiveFormat::Tar))
+        } else {
+            name.strip_suffix(".zip")
+                .map(|reference| (reference, ArchiveFormat::Zip))
+        }
+    }
+
+    fn git_format(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "application/x-tar",
+            ArchiveFormat::TarGz => "application/gzip",
+            ArchiveFormat::Zip => "application/zip",
+        }
+    }
+}
+
+/// Splits a request path into repository segments (without `.git`) and the endpoint.
+pub fn parse_git_route(path: &str) -> Option<(Vec<String>, GitRoute)> {
+    let path = path.trim_start_matches('/');
+
+    if let Some((repo, rest)) = path.split_once("/-/") {
+        let segments = split_repository_path(repo);
+        if segments.is_empty() {
+            return None;
+        }
+        if let Some(name) = rest.strip_prefix("archive/") {
+            let (reference, format) = ArchiveFormat::split(name)?;
+            if reference.is_empty() {
+                return None;
+            }
+            return Some((
+                segments,
+                GitRoute::Archive {
+                    reference: reference.to_string(),
+                    format,
+                },
+            ));
+        }
+        if let Some(rest) = rest.strip_prefix("raw/") {
+            let (reference, file) = rest.split_once('/')?;
+            if reference.is_empty() || file.is_empty() {
+                return None;
+            }
+            return Some((
+                segments,
+                GitRoute::Raw {
+                    reference: reference.to_string(),
+                    path: file.to_string(),
+                },
+            ));
+        }
+        return None;
+    }
+
+    let (repo, route) = if let Some(repo) = path.strip_suffix("/info/refs") {
+        (repo, GitRoute::InfoRefs)
+    } else if let Some(repo) = path.strip_suffix("/git-upload-pack") {
+        (repo, GitRoute::UploadPack)
+    } else if let Some(repo) = path.strip_suffix("/git-receive-pack") {
+        (repo, GitRoute::ReceivePack)
+    } else {
+        return None;
+    };
+    let segments = split_repository_path(repo);
+    if segments.is_empty() {
+        return None;
+    }
+    Some((segments, route))
+}
+
+// GET /{*path}
+pub async fn dispatch_get<S>(
+    State(state): State<S>,
+    Path(path): Path<String>,
+    Query(q): Query<ServiceQuery>,
+) -> Response
+where
+    S: GitHttpState,
+{
+    let Some((segments, route)) = parse_git_route(&path) else {
+        return (StatusCode::NOT_FOUND, "not found").into_response();
+    };
+    match route {
+        GitRoute::InfoRefs => {
+            v2::info_refs_for(&state, segments, q.service.as_deref(), "nested").await
+        }
+        GitRoute::Archive { reference, format } => {
+            serve_archive(&state, segments, reference, format).await
+        }
+        GitRoute::Raw { reference, path } => serve_raw(&state, segments, reference, path).await,
+        GitRoute::UploadPack | GitRoute::ReceivePack => {
+            (StatusCode::METHOD_NOT_ALLOWED, "use POST").into_response()
+        }
+    }
+}
+
+// POST /{*path}
+pub async fn dispatch_post<S>(
+    State(state): State<S>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+    body: axum::body::Body,
+) -> Response
+where
+    S: GitHttpState,
+{
+    match parse_git_route(&path) {
+        Some((segments, GitRoute::UploadPack)) => {
+            v2::handle_upload_pack(state, segments, headers, body).await
+        }
+        Some((segments, GitRoute::ReceivePack)) => {
+            v2::handle_receive_pack(state, segments, body).await
+        }
+        Some(_) => (StatusCode::METHOD_NOT_ALLOWED, "use GET").into_response(),
+        None => (StatusCode::NOT_FOUND, "not found").into_response(),
+    }
+}
+
+/// Validates segments and resolves an exported repository directory.
+fn public_repo_dir<S>(
+    state: &S,
+    segments: &[String],
+) -> Result<std::path::PathBuf, (StatusCode, String)>
+where
+    S: GitHttpState,
+{
+    for s in segments {
+        if let Err(e) = state.validate_slug(s) {
+            return Err((StatusCode::BAD_REQUEST, e.to_string()));
+        }
+    }
+    match resolve_repo_dir(state.storage(), segments) {
+        Ok(dir) if state.is_exported(segments, &dir) => Ok(dir),
+        _ => Err((StatusCode::NOT_FOUND, "repo not found".to_string())),
+    }
+}
+
+fn valid_reference(reference: &str) -> bool {
+    !reference.starts_with('-') && !reference.contains("..") && !reference.contains(':')
+}
+
+async fn serve_archive<S>(
+    state: &S,
+    segments: Vec<String>,
+    reference: String,
+    format: ArchiveFormat,
+) -> Response
+where
+    S: GitHttpState,
+{
+    let repo_dir = match public_repo_dir(state, &segments) {
+        Ok(d) => d,
+        Err(resp) => return resp.into_response(),
+    };
+    if !valid_reference(&reference) {
+        return (StatusCode::BAD_REQUEST, "invalid reference").into_response();
+    }
+    // Archives are whole snapshots, so they share the clone budget
+    let permit = match state.git_scheduler().acquire(GitPriority::Clone).await {
+        Ok(p) => p,
+        Err(shed) => return shed.into_response(),
+    };
+    let tree = format!("{reference}^{{tree}}");
+    match git_query(
+        state,
+        &repo_dir,
+        &["rev-parse", "--verify", "--quiet", &tree],
+    )
+    .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "reference not found").into_response(),
+        Err(resp) => return resp,
+    }
+    let prefix = format!(
+        "{}-{}/",
+        segments.last().map(String::as_str).unwrap_or("repo"),
+        reference.replace('/', "-")
+    );
+    let mut cmd = tokio::process::Command::new("git");
+    cmd.arg("--git-dir")
+        .arg(&repo_dir)
+        .arg("archive")
+        .arg(format!("--format={}", format.git_format()))
+        .arg(format!("--prefix={prefix}"))
+        .arg("--")
+        .arg(&reference);
+    let filename = format!("{}.{}", prefix.trim_end_matches('/'), format.git_format());
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{filename}\""),
+        );
+    stream_git(cmd, permit, response)
+}
+
+async fn serve_raw<S>(state: &S, segments: Vec<String>, reference: String, path: String) -> Response
+where
+    S: GitHttpState,
+{
+    let repo_dir = match public_repo_dir(state, &segments) {
+        Ok(d) => d,
+        Err(resp) => return resp.into_response(),
+    };
+    if !valid_reference(&reference) || path.split('/').any(|p| p == "..") {
+        return (StatusCode::BAD_REQUEST, "invalid path").into_response();
+    }
+    let permit = match state
+        .git_scheduler()
+        .acquire(GitPriority::Interactive)
+        .await
+    {
+        Ok(p) => p,
+        Err(shed) => return shed.into_response(),
+    };
+    let object = format!("{reference}:{path}");
+    match git_query(state, &repo_dir, &["cat-file", "-t", &object]).await {
+        Ok(Some(kind)) if kind.trim() == "blob" => {}
+        Ok(_) => return (StatusCode::NOT_FOUND, "file not found").into_response(),
+        Err(resp) => return resp,
+    }
+    let mut cmd = tokio::process::Command::new("git");
+    cmd.arg("--git-dir")
+        .arg(&repo_dir)
+        .arg("cat-file")
+        .arg("blob")
+        .arg(&object);
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header("X-Content-Type-Options", "nosniff");
+    stream_git(cmd, permit, response)
+}
+
+/// Runs a short `git` query within the request timeout. `Ok(None)` when git exits with an
+/// error, e.g. because the object does not exist.
+async fn git_query<S>(
+    state: &S,
+    repo_dir: &std::path::Path,
+    args: &[&str],
+) -> Result<Option<String>, Response>
+where
+    S: GitHttpState,
+{
+    let output = tokio::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo_dir)
+        .args(args)
+        .output();
+    match tokio::time::timeout(
+        std::time::Duration::from_millis(state.git_timeout_ms()),
+        output,
+    )
+    .await
+    {
+        Ok(Ok(output)) if output.status.success() => {
+            Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+        }
+        Ok(Ok(_)) => Ok(None),
+        Ok(Err(e)) => {
+            Err((StatusCode::BAD_GATEWAY, format!("failed to spawn git: {e}")).into_response())
+        }
+        Err(_) => Err((StatusCode::REQUEST_TIMEOUT, "git timed out").into_response()),
+    }
+}
+
+/// Streams the output of `cmd` as the body of `response`, like upload-pack, so large
+/// archives and blobs are never held in memory. `permit` is held until the body has been
+/// sent, and git is killed if the client goes away first.
+fn stream_git(
+    mut cmd: tokio::process::Command,
+    permit: GitPermit,
+    response: axum::http::response::Builder,
+) -> Response {
+    cmd.stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    let mut child = match cmd.spawn() {
+        Ok(c) => c,
+        Err(e) => {
+            return (StatusCode::BAD_GATEWAY, format!("failed to spawn git: {e}")).into_response();
+        }
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return (StatusCode::BAD_GATEWAY, "missing git stdout").into_response();
+    };
+    let stream = ReaderStream::new(stdout).map(move |chunk| {
+        let _running = (&child, &permit);
+        chunk
+    });
+    response
+        .body(Body::from_stream(stream))
+        .expect("response build")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_git_endpoints() {
+        let (segments, route) = parse_git_route("team/infra/tools.git/info/refs").unwrap();
+        assert_eq!(segments, vec!["team", "infra", "tools"]);
+        assert_eq!(route, GitRoute::InfoRefs);
+
+        let (segments, route) = parse_git_route("/a/b/c/d/git-upload-pack").unwrap();
+        assert_eq!(segments.len(), 4);
+        assert_eq!(route, GitRoute::UploadPack);
+
+        assert!(parse_git_route("info/refs").is_none());
+        assert!(parse_git_route("team/tools/tree/main").is_none());
+    }
+
+    #[test]
+    fn parses_archive_and_raw_endpoints() {
+        let (segments, route) = parse_git_route("team/tools/-/archive/v1.0.tar.gz").unwrap();
+        assert_eq!(segments, vec!["team", "tools"]);
+        assert_eq!(
+            route,
+            GitRoute::Archive {
+                reference: "v1.0".into(),
+                format: ArchiveFormat::TarGz
+            }
+        );
+
+        let (_, route) = parse_git_route("team/tools/-/raw/main/src/lib.rs").unwrap();
+        assert_eq!(
+            route,
+            GitRoute::Raw {
+                reference: "main".into(),
+                path: "src/lib.rs".into()
+            }
+        );
+
+        assert!(parse_git_route("team/tools/-/raw/main").is_none());
+        assert!(parse_git_route("team/tools/-/archive/main.rar").is_none());
+    }
+}
@@ -0,0 +1,310 @@
+//! Concurrency budgets for git work, by priority class.
+//!
+//! A clone of a large repository can hold a slot for minutes, so sharing one budget
+//! lets a burst of clones stall the small fetches, pushes and file reads that people
+//! wait on interactively. Each [`GitPriority`] has its own budget and its own queue:
+//! work waits for a slot of its class only, and is refused once `max_queued` requests
+//! of that class are waiting or it has waited `queue_timeout`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use metrics::{counter, gauge, histogram};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GitPriority {
+    /// Incremental fetches, ref advertisements, pushes and raw file reads
+    Interactive,
+    /// Fetches without haves, and archive downloads
+    Clone,
+    /// Fetches from linked remotes: initial imports and mirror syncs
+    Sync,
+}
+
+impl GitPriority {
+    pub fn label(self) -> &'static str {
+        match self {
+            GitPriority::Interactive => "interactive",
+            GitPriority::Clone => "clone",
+            GitPriority::Sync => "sync",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            GitPriority::Interactive => 0,
+            GitPriority::Clone => 1,
+            GitPriority::Sync => 2,
+        }
+    }
+}
+
+/// Slots per class; `0` leaves a class unlimited.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GitLimits {
+    pub interactive: usize,
+    pub clone: usize,
+    pub sync: usize,
+    /// Requests of one class allowed to wait before new ones are refused
+    pub max_queued: usize,
+    pub queue_timeout: Duration,
+}
+
+impl Default for GitLimits {
+    fn default() -> Self {
+        Self {
+            interactive: 48,
+            clone: 8,
+            sync: 4,
+            max_queued: 256,
+            queue_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Why work was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shed {
+    QueueFull,
+    Timeout,
+}
+
+impl Shed {
+    fn label(self) -> &'static str {
+        match self {
+            Shed::QueueFull => "queue_full",
+            Shed::Timeout => "timeout",
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            Shed::QueueFull => "server is busy: too many queued git requests, retry shortly",
+            Shed::Timeout => "server is busy: timed out waiting for a git slot, retry shortly",
+        }
+    }
+}
+
+/// Refused requests get `503`, which git reports and clients may retry.
+impl IntoResponse for Shed {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, "5")],
+            self.message(),
+        )
+            .into_response()
+    }
+}
+
+struct Budget {
+    /// `None` when the class is unlimited.
+    slots: Option<Arc<Semaphore>>,
+    queued: AtomicUsize,
+}
+
+pub struct GitScheduler {
+    budgets: [Budget; 3],
+    max_queued: usize,
+    queue_timeout: Duration,
+}
+
+/// Held while git work runs; dropping it frees the slot.
+pub struct GitPermit {
+    class: GitPriority,
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl GitPermit {
+    fn new(class: GitPriority, slot: Option<OwnedSemaphorePermit>) -> Self {
+        gauge!("git_http.in_flight", "class" => class.label()).increment(1.0);
+        Self { class, _slot: slot }
+    }
+}
+
+impl Drop for GitPermit {
+    fn drop(&mut self) {
+        gauge!("git_http.in_flight", "class" => self.class.label()).decrement(1.0);
+    }
+}
+
+/// Counts a request as queued until dropped, including when the client goes away.
+/// The caller has already added it to `queued`.
+struct QueueEntry<'a> {
+    queued: &'a AtomicUsize,
+    class: GitPriority,
+    started: Instant,
+}
+
+impl<'a> QueueEntry<'a> {
+    fn enter(queued: &'a AtomicUsize, class: GitPriority) -> Self {
+        gauge!("git_http.queue_depth", "class" => class.label()).increment(1.0);
+        Self {
+            queued,
+            class,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for QueueEntry<'_> {
+    fn drop(&mut self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        gauge!("git_http.queue_depth", "class" => self.class.label()).decrement(1.0);
+        histogram!("git_http.queue_wait_ms", "class" => self.class.label())
+            .record(self.started.elapsed().as_secs_f64() * 1000.0);
+    }
+}
+
+impl GitScheduler {
+    pub fn new(limits: &GitLimits) -> Self {
+        let budget = |slots: usize| Budget {
+            slots: (slots > 0).then(|| Arc::new(Semaphore::new(slots))),
+            queued: AtomicUsize::new(0),
+        };
+        Self {
+            budgets: [
+                budget(limits.interactive),
+                budget(limits.clone),
+                budget(limits.sync),
+            ],
+            max_queued: limits.max_queued,
+            queue_timeout: limits.queue_timeout,
+        }
+    }
+
+    /// Waits for a slot of `class`, or refuses the work.
+    pub async fn acquire(&self, class: GitPriority) -> Result<GitPermit, Shed> {
+        let budget = &self.budgets[class.index()];
+        let Some(slots) = &budget.slots else {
+            return Ok(GitPermit::new(class, None));
+        };
+        if let Ok(slot) = slots.clone().try_acquire_owned() {
+            return Ok(GitPermit::new(class, Some(slot)));
+        }
+
+        if budget.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queued {
+            budget.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(self.shed(class, Shed::QueueFull));
+        }
+        let entry = QueueEntry::enter(&budget.queued, class);
+        let waited = tokio::time::timeout(self.queue_timeout, slots.clone().acquire_owned()).await;
+        drop(entry);
+        match waited {
+            Ok(slot) => Ok(GitPermit::new(class, slot.ok())),
+            Err(_) => Err(self.shed(class, Shed::Timeout)),
+        }
+    }
+
+    /// Waits for a slot of `class` however long it takes, for background work that has
+    /// no client to refuse. The wait still shows in the queue metrics.
+    pub async fn wait(&self, class: GitPriority) -> GitPermit {
+        let budget = &self.budgets[class.index()];
+        let Some(slots) = &budget.slots else {
+            return GitPermit::new(class, None);
+        };
+        if let Ok(slot) = slots.clone().try_acquire_owned() {
+            return GitPermit::new(class, Some(slot));
+        }
+
+        budget.queued.fetch_add(1, Ordering::SeqCst);
+        let entry = QueueEntry::enter(&budget.queued, class);
+        let slot = slots.clone().acquire_owned().await;
+        drop(entry);
+        GitPermit::new(class, slot.ok())
+    }
+
+    fn shed(&self, class: GitPriority, reason: Shed) -> Shed {
+        counter!("git_http.shed", "class" => class.label(), "reason" => reason.label())
+            .increment(1);
+        tracing::warn!(
+            class = class.label(),
+            reason = reason.label(),
+            "git request refused"
+        );
+        reason
+    }
+}
+
+impl Default for GitScheduler {
+    fn default() -> Self {
+        Self::new(&GitLimits::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler(interactive: usize, clone: usize, max_queued: usize) -> GitScheduler {
+        GitScheduler::new(&GitLimits {
+            interactive,
+            clone,
+            sync: 1,
+            max_queued,
+            queue_timeout: Duration::from_millis(50),
+        })
+    }
+
+    #[tokio::test]
+    async fn busy_clones_leave_interactive_slots_free() {
+        let scheduler = scheduler(1, 1, 4);
+        let _clone = scheduler.acquire(GitPriority::Clone).await.unwrap();
+        assert_eq!(
+            scheduler.acquire(GitPriority::Clone).await.err(),
+            Some(Shed::Timeout)
+        );
+        let interactive = scheduler.acquire(GitPriority::Interactive).await;
+        assert!(interactive.is_ok());
+        let _sync = scheduler.acquire(GitPriority::Sync).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn full_queue_sheds_immediately_and_freed_slots_are_reused() {
+        let scheduler = Arc::new(scheduler(1, 1, 1));
+        let held = scheduler.acquire(GitPriority::Interactive).await.unwrap();
+
+        let waiter = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move { scheduler.acquire(GitPriority::Interactive).await.is_ok() })
+        };
+        while scheduler.budgets[0].queued.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            scheduler.acquire(GitPriority::Interactive).await.err(),
+            Some(Shed::QueueFull)
+        );
+
+        drop(held);
+        assert!(waiter.await.unwrap());
+        assert_eq!(scheduler.budgets[0].queued.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn background_work_waits_past_the_queue_timeout() {
+        let scheduler = Arc::new(scheduler(1, 1, 0));
+        let held = scheduler.acquire(GitPriority::Sync).await.unwrap();
+        let waiter = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move { drop(scheduler.wait(GitPriority::Sync).await) })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!waiter.is_finished());
+        drop(held);
+        waiter.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn zero_leaves_a_class_unlimited() {
+        let scheduler = scheduler(0, 1, 0);
+        let permits: Vec<_> =
+            futures::future::join_all((0..10).map(|_| scheduler.acquire(GitPriority::Interactive)))
+                .await;
+        assert!(permits.iter().all(Result::is_ok));
+    }
+}
@@ -1,17 +1,59 @@
-use std::sync::Arc;
+use std::path::Path;
 
 use anyhow::Result;
-use tokio::sync::Semaphore;
 
-use crate::repo::RepositoryProvider;
+use crate::policy::{PolicyRules, RefUpdate};
+use crate::refs::RefPolicy;
+use crate::repo::{RepositoryProvider, is_public_repo};
+use crate::scheduler::GitScheduler;
+
+/// Whether a request may push to a repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushAccess {
+    /// Answered with `401` so git asks for credentials and retries.
+    Unauthenticated,
+    Denied,
+    Allowed,
+}
 
 /// Abstraction over the state required by Git HTTP handlers.
 pub trait GitHttpState: Clone + Send + Sync + 'static {
     type Storage: RepositoryProvider + Send + Sync;
 
     fn storage(&self) -> &Self::Storage;
-    fn git_semaphore(&self) -> &Arc<Semaphore>;
+    /// Concurrency budgets shared by every git request; see [`GitScheduler`].
+    fn git_scheduler(&self) -> &GitScheduler;
     fn git_max_body(&self) -> usize;
     fn git_timeout_ms(&self) -> u64;
     fn validate_slug(&self, slug: &str) -> Result<()>;
+
+    /// Push policy for a repository; embedders backed by a database override this.
+    fn push_policy(&self, _segments: &[String]) -> PolicyRules {
+        PolicyRules::default()
+    }
+
+    /// Ref visibility for fetches; defaults to [`RefPolicy::from_env`].
+    fn ref_policy(&self, _segments: &[String]) -> RefPolicy {
+        RefPolicy::from_env()
+    }
+
+    /// Whether a repository may be read over HTTP; defaults to [`is_public_repo`].
+    /// Embedders that grant access to unexported repositories per request override this.
+    fn is_exported(&self, _segments: &[String], repo_dir: &Path) -> bool {
+        is_public_repo(&repo_dir.to_path_buf())
+    }
+
+    /// Called for each clone of a repository, once its wants have been accepted. Must not
+    /// block; embedders that count clones hand the work off.
+    fn record_clone(&self, _segments: &[String]) {}
+
+    /// Who may push to a repository over HTTP. Defaults to nobody; embedders check the
+    /// request's credentials and the repository's permissions.
+    fn push_access(&self, _segments: &[String]) -> PushAccess {
+        PushAccess::Denied
+    }
+
+    /// Called after a push with the ref updates that were applied. Must not block, like
+    /// [`GitHttpState::record_clone`].
+    fn record_push(&self, _segments: &[String], _updates: &[RefUpdate]) {}
 }
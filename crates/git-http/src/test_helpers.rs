@@ -0,0 +1,35 @@
+//! Git fixtures shared by the unit tests.
+
+use std::path::Path;
+
+/// Runs `git` in `dir` as the test author and returns its trimmed stdout. Panics with
+/// git's stderr when it fails.
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=Tess", "-c", "user.email=tess@example.org"])
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .expect("failed to spawn git");
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Writes `name` into the work tree `dir` and commits it. Returns the commit id.
+pub fn commit_file(dir: &Path, name: &str, contents: &str, message: &str) -> String {
+    let path = dir.join(name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).unwrap();
+    }
+    std::fs::write(path, contents).unwrap();
+    git(dir, &["add", name]);
+    git(dir, &["commit", "-q", "-m", message]);
+    git(dir, &["rev-parse", "HEAD"])
+}
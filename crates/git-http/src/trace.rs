@@ -0,0 +1,329 @@
+//! Normalized, human-readable transcripts of Smart HTTP exchanges.
+//!
+//! A transcript lists every request a client made and the server's reply as one pkt-line
+//! per text line, so two runs can be compared with a plain diff. Anything that varies
+//! between runs without being a protocol difference is masked: `agent=` and
+//! `session-id=` values, side-band progress messages, and the bytes of the packfile,
+//! which is summarized by its object count because git and the Rust packer are free to
+//! pick different deltas. Transcripts double as golden files: [`parse_transcript`]
+//! recovers each request so it can be replayed against another backend.
+//!
+//! ```text
+//! == POST /alpha.git/git-upload-pack 200
+//! C "command=ls-refs\n"
+//! C 0001
+//! C "peel\n"
+//! C 0000
+//! S "0123...cdef HEAD symref-target:refs/heads/main\n"
+//! S 0000
+//! ```
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::pkt::encode_pkt_line;
+
+/// One HTTP request/response pair as seen on the wire.
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub method: String,
+    /// Path and query, e.g. `/alpha.git/info/refs?service=git-upload-pack`.
+    pub target: String,
+    pub request: Vec<u8>,
+    pub status: u16,
+    pub response: Vec<u8>,
+}
+
+/// An exchange read back from a transcript.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedExchange {
+    pub method: String,
+    pub target: String,
+    /// Request body re-encoded from the transcript (masked values stay masked).
+    pub request: Vec<u8>,
+    /// The exchange as rendered by [`render_exchange`], header included.
+    pub rendered: Vec<String>,
+}
+
+/// Renders exchanges as a transcript.
+pub fn render_transcript(exchanges: &[Exchange]) -> String {
+    let mut out = String::new();
+    for exchange in exchanges {
+        for line in render_exchange(exchange) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+pub fn render_exchange(exchange: &Exchange) -> Vec<String> {
+    let mut lines = vec![format!(
+        "== {} {} {}",
+        exchange.method, exchange.target, exchange.status
+    )];
+    render_stream(&exchange.request, 'C', &mut lines);
+    render_stream(&exchange.response, 'S', &mut lines);
+    lines
+}
+
+/// Splits a transcript into exchanges. Lines starting with `#` and blank lines are
+/// comments; lines starting with `!!` note how a client run ended and belong to no
+/// exchange.
+pub fn parse_transcript(text: &str) -> Result<Vec<RecordedExchange>> {
+    let mut exchanges: Vec<RecordedExchange> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') || line.starts_with("!!") {
+            continue;
+        }
+        let context = || format!("transcript line {}", number + 1);
+        if let Some(header) = line.strip_prefix("== ") {
+            let mut parts = header.splitn(3, ' ');
+            let (Some(method), Some(target), Some(_status)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                return Err(anyhow!("malformed exchange header")).with_context(context);
+            };
+            exchanges.push(RecordedExchange {
+                method: method.to_string(),
+                target: target.to_string(),
+                request: Vec::new(),
+                rendered: vec![line.to_string()],
+            });
+            continue;
+        }
+        let exchange = exchanges
+            .last_mut()
+            .ok_or_else(|| anyhow!("pkt-line before the first exchange header"))
+            .with_context(context)?;
+        if let Some(pkt) = line.strip_prefix("C ") {
+            exchange
+                .request
+                .extend(parse_pkt(pkt).with_context(context)?);
+        }
+        exchange.rendered.push(line.to_string());
+    }
+    Ok(exchanges)
+}
+
+/// Masks values that legitimately differ between runs, clients and servers.
+pub fn normalize_line(line: &str) -> String {
+    for key in ["agent=", "session-id="] {
+        if let Some(value) = line.strip_prefix(key) {
+            let end = value.find(['\n', ' ']).unwrap_or(value.len());
+            return format!("{}<masked>{}", key, &value[end..]);
+        }
+    }
+    line.to_string()
+}
+
+fn render_stream(bytes: &[u8], side: char, lines: &mut Vec<String>) {
+    let mut rest = bytes;
+    // Set once the `packfile` section starts; later data pkts are side-band framed.
+    let mut sideband = false;
+    let mut pack: Option<Vec<u8>> = None;
+    while !rest.is_empty() {
+        let Some(len) = rest
+            .get(..4)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+        else {
+            lines.push(format!("{} raw {}", side, quote(rest)));
+            return;
+        };
+        if len < 4 {
+            if let Some(pack) = pack.take() {
+                lines.push(format!("{} [pack] {}", side, summarize_pack(&pack)));
+            }
+            lines.push(format!("{} {:04x}", side, len));
+            rest = &rest[4..];
+            continue;
+        }
+        let Some(data) = rest.get(4..len) else {
+            lines.push(format!("{} truncated {}", side, quote(rest)));
+            return;
+        };
+        rest = &rest[len..];
+        if sideband {
+            match data.split_first() {
+                Some((1, payload)) => pack.get_or_insert_with(Vec::new).extend(payload),
+                // Progress text depends on timing and client verbosity.
+                Some((2, _)) => {}
+                Some((3, message)) => lines.push(format!("{} [error] {}", side, quote(message))),
+                _ => lines.push(format!("{} [bad-band] {}", side, quote(data))),
+            }
+            continue;
+        }
+        if data == b"packfile\n" {
+            sideband = true;
+        }
+        let rendered = match std::str::from_utf8(data) {
+            Ok(text) => quote(normalize_line(text).as_bytes()),
+            Err(_) => quote(data),
+        };
+        lines.push(format!("{} {}", side, rendered));
+    }
+    if let Some(pack) = pack {
+        lines.push(format!("{} [pack] {}", side, summarize_pack(&pack)));
+    }
+}
+
+fn summarize_pack(pack: &[u8]) -> String {
+    if pack.len() < 12 || &pack[..4] != b"PACK" {
+        return format!("invalid ({} bytes)", pack.len());
+    }
+    let version = u32::from_be_bytes([pack[4], pack[5], pack[6], pack[7]]);
+    let objects = u32::from_be_bytes([pack[8], pack[9], pack[10], pack[11]]);
+    format!("version={} objects={}", version, objects)
+}
+
+/// Quotes bytes as a double-quoted string with C-style escapes.
+fn quote(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() + 2);
+    out.push('"');
+    for &b in bytes {
+        match b {
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\0' => out.push_str("\\0"),
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn unquote(text: &str) -> Result<Vec<u8>> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .ok_or_else(|| anyhow!("expected a quoted string"))?;
+    let mut out = Vec::with_capacity(inner.len());
+    let mut bytes = inner.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'n') => out.push(b'\n'),
+            Some(b't') => out.push(b'\t'),
+            Some(b'0') => out.push(0),
+            Some(b'"') => out.push(b'"'),
+            Some(b'\\') => out.push(b'\\'),
+            Some(b'x') => {
+                let hex = [bytes.next(), bytes.next()];
+                let [Some(hi), Some(lo)] = hex else {
+                    bail!("truncated \\x escape");
+                };
+                let hex = std::str::from_utf8(&[hi, lo])?.to_string();
+                out.push(u8::from_str_radix(&hex, 16)?);
+            }
+            other => bail!("unknown escape {:?}", other.map(char::from)),
+        }
+    }
+    Ok(out)
+}
+
+/// Re-encodes one rendered request pkt.
+fn parse_pkt(text: &str) -> Result<Vec<u8>> {
+    match text {
+        "0000" | "0001" | "0002" => Ok(text.as_bytes().to_vec()),
+        quoted if quoted.starts_with('"') => Ok(encode_pkt_line(&unquote(quoted)?)),
+        other => bail!("cannot replay request pkt `{}`", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkt::{PKT_DELIM, PKT_FLUSH};
+
+    fn pkts(lines: &[&[u8]]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for line in lines {
+            match *line {
+                b"0000" => out.extend_from_slice(PKT_FLUSH),
+                b"0001" => out.extend_from_slice(PKT_DELIM),
+                data => out.extend(encode_pkt_line(data)),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn masks_agents_and_summarizes_packs() {
+        let mut pack = vec![1u8];
+        pack.extend_from_slice(b"PACK\0\0\0\x02\0\0\0\x03");
+        pack.extend_from_slice(&[0x95; 20]);
+        let exchange = Exchange {
+            method: "POST".into(),
+            target: "/alpha.git/git-upload-pack".into(),
+            request: pkts(&[
+                b"command=fetch\n",
+                b"agent=git/2.45.1\n",
+                b"0001",
+                b"done\n",
+                b"0000",
+            ]),
+            status: 200,
+            response: pkts(&[
+                b"packfile\n",
+                b"\x02Counting objects: 3\r",
+                &pack,
+                b"\x03oops\n",
+                b"0000",
+            ]),
+        };
+        assert_eq!(
+            render_exchange(&exchange),
+            vec![
+                "== POST /alpha.git/git-upload-pack 200",
+                "C \"command=fetch\\n\"",
+                "C \"agent=<masked>\\n\"",
+                "C 0001",
+                "C \"done\\n\"",
+                "C 0000",
+                "S \"packfile\\n\"",
+                "S [error] \"oops\\n\"",
+                "S [pack] version=2 objects=3",
+                "S 0000",
+            ]
+        );
+        assert_eq!(
+            normalize_line("session-id=0a1b2c\n"),
+            "session-id=<masked>\n"
+        );
+    }
+
+    #[test]
+    fn transcripts_round_trip_requests() {
+        let request = pkts(&[
+            b"command=ls-refs\n",
+            b"0001",
+            b"ref-prefix \"q\"\\\x01\0\n",
+            b"0000",
+        ]);
+        let exchange = Exchange {
+            method: "POST".into(),
+            target: "/alpha/git-upload-pack".into(),
+            request: request.clone(),
+            status: 200,
+            response: pkts(&[b"0000"]),
+        };
+        let text = format!(
+            "# comment\n\n{}",
+            render_transcript(std::slice::from_ref(&exchange))
+        );
+        let parsed = parse_transcript(&text).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].request, request);
+        assert_eq!(parsed[0].target, "/alpha/git-upload-pack");
+        assert_eq!(parsed[0].rendered, render_exchange(&exchange));
+
+        assert!(parse_transcript("C 0000\n").is_err());
+        assert!(parse_transcript("== GET /x 200\nC [pack] objects=1\n").is_err());
+    }
+}
@@ -5,17 +5,27 @@ use std::time::Instant;
 use tokio::io::AsyncWriteExt;
 use tokio_util::io::ReaderStream;
 
-use crate::pkt::{decode_pkt_lines, encode_pkt_line, Pkt, PKT_FLUSH};
-use crate::repo::{is_public_repo, resolve_repo_dir};
-use crate::{pack, GitHttpState};
+use crate::pkt::{PKT_FLUSH, Pkt, decode_pkt_lines, encode_pkt_line};
+use crate::refs::check_wants;
+use crate::repo::resolve_repo_dir;
+use crate::scheduler::GitPriority;
+use crate::{GitHttpState, PushAccess, pack, receive};
 
 #[derive(Debug, Deserialize)]
-pub struct ServiceQuery { pub service: Option<String> }
+pub struct ServiceQuery {
+    pub service: Option<String>,
+}
 
-enum AdvertiseMode { Git, Rust }
+enum AdvertiseMode {
+    Git,
+    Rust,
+}
 
 fn select_advertise_mode() -> AdvertiseMode {
-    match std::env::var("FORGE_GIT_SMART_V2_ADVERTISE").ok().as_deref() {
+    match std::env::var("FORGE_GIT_SMART_V2_ADVERTISE")
+        .ok()
+        .as_deref()
+    {
         Some("rust") => AdvertiseMode::Rust,
         Some("git") => AdvertiseMode::Git,
         _ => AdvertiseMode::Git,
@@ -31,22 +41,7 @@ pub async fn info_refs_root<S>(
 where
     S: GitHttpState,
 {
-    let start = Instant::now();
-    if q.service.as_deref() != Some("git-upload-pack") {
-        return (StatusCode::BAD_REQUEST, "unsupported service").into_response();
-    }
-    // Gating: repo must be public
-    let segments = vec![repo];
-    let repo_dir = match resolve_repo_dir(state.storage(), &segments) { Ok(p) => p, Err(e) => { tracing::debug!("resolve_repo_dir failed: {}", e); return (StatusCode::NOT_FOUND, "repo not found").into_response() } };
-    if !is_public_repo(&repo_dir) { tracing::debug!("repo not public: {}", repo_dir.display()); return (StatusCode::NOT_FOUND, "repo not found").into_response(); }
-
-    let resp = match select_advertise_mode() {
-        AdvertiseMode::Rust => advertise_v2_rust(&state, &segments, &HeaderMap::new()).await,
-        AdvertiseMode::Git => advertise_v2_via_git(&state, &segments, &HeaderMap::new()).await,
-    };
-    counter!("git_http.info_refs", "scope" => "root").increment(1);
-    histogram!("git_http.info_refs_ms").record(start.elapsed().as_millis() as f64);
-    resp
+    info_refs_for(&state, vec![repo], q.service.as_deref(), "root").await
 }
 
 // GET /:group/:repo(.git)?/info/refs?service=git-upload-pack
@@ -55,22 +50,57 @@ pub async fn info_refs_group<S>(
     Path((group, repo)): Path<(String, String)>,
     Query(q): Query<ServiceQuery>,
 ) -> Response
+where
+    S: GitHttpState,
+{
+    info_refs_for(&state, vec![group, repo], q.service.as_deref(), "group").await
+}
+
+/// Shared body of the `info/refs` handlers; `scope` only labels metrics.
+pub(crate) async fn info_refs_for<S>(
+    state: &S,
+    mut segments: Vec<String>,
+    service: Option<&str>,
+    scope: &'static str,
+) -> Response
 where
     S: GitHttpState,
 {
     let start = Instant::now();
-    if q.service.as_deref() != Some("git-upload-pack") {
+    if service != Some("git-upload-pack") && service != Some("git-receive-pack") {
         return (StatusCode::BAD_REQUEST, "unsupported service").into_response();
     }
-    let segments = vec![group, repo];
-    let repo_dir = match resolve_repo_dir(state.storage(), &segments) { Ok(p) => p, Err(e) => { tracing::debug!("resolve_repo_dir failed: {}", e); return (StatusCode::NOT_FOUND, "repo not found").into_response() } };
-    if !is_public_repo(&repo_dir) { tracing::debug!("repo not public: {}", repo_dir.display()); return (StatusCode::NOT_FOUND, "repo not found").into_response(); }
+    if let Some(last) = segments.last_mut() {
+        if let Some(stripped) = last.strip_suffix(".git") {
+            *last = stripped.to_string();
+        }
+    }
+    for s in &segments {
+        if let Err(e) = state.validate_slug(s) {
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+    }
+    if service == Some("git-receive-pack") {
+        return advertise_receive_pack(state, &segments).await;
+    }
+    // Gating: repo must be public
+    let repo_dir = match resolve_repo_dir(state.storage(), &segments) {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::debug!("resolve_repo_dir failed: {}", e);
+            return (StatusCode::NOT_FOUND, "repo not found").into_response();
+        }
+    };
+    if !state.is_exported(&segments, &repo_dir) {
+        tracing::debug!("repo not public: {}", repo_dir.display());
+        return (StatusCode::NOT_FOUND, "repo not found").into_response();
+    }
 
     let resp = match select_advertise_mode() {
-        AdvertiseMode::Rust => advertise_v2_rust(&state, &segments, &HeaderMap::new()).await,
-        AdvertiseMode::Git => advertise_v2_via_git(&state, &segments, &HeaderMap::new()).await,
+        AdvertiseMode::Rust => advertise_v2_rust(state, &segments, &HeaderMap::new()).await,
+        AdvertiseMode::Git => advertise_v2_via_git(state, &segments, &HeaderMap::new()).await,
     };
-    counter!("git_http.info_refs", "scope" => "group").increment(1);
+    counter!("git_http.info_refs", "scope" => scope).increment(1);
     histogram!("git_http.info_refs_ms").record(start.elapsed().as_millis() as f64);
     resp
 }
@@ -101,9 +131,146 @@ where
     handle_upload_pack(state, vec![group, repo], headers, body).await
 }
 
-// POST /.../git-receive-pack (explicitly blocked)
-pub async fn receive_pack_blocked() -> impl IntoResponse {
-    (StatusCode::FORBIDDEN, "push over HTTP is disabled")
+/// `None` when the request may push, otherwise the response refusing it.
+fn push_refused<S>(state: &S, segments: &[String]) -> Option<Response>
+where
+    S: GitHttpState,
+{
+    match state.push_access(segments) {
+        PushAccess::Allowed => None,
+        PushAccess::Denied => Some((StatusCode::FORBIDDEN, "push access denied").into_response()),
+        PushAccess::Unauthenticated => Some(
+            Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(header::WWW_AUTHENTICATE, "Basic realm=\"forge\"")
+                .body(axum::body::Body::from("authentication required"))
+                .expect("response build"),
+        ),
+    }
+}
+
+// GET /.../info/refs?service=git-receive-pack
+async fn advertise_receive_pack<S>(state: &S, segments: &[String]) -> Response
+where
+    S: GitHttpState,
+{
+    if let Some(resp) = push_refused(state, segments) {
+        return resp;
+    }
+    // pushing does not require the repository to be exported
+    let repo_dir = match resolve_repo_dir(state.storage(), segments) {
+        Ok(p) => p,
+        Err(_) => return (StatusCode::NOT_FOUND, "repo not found").into_response(),
+    };
+    match receive::advertise_refs(&repo_dir, &state.ref_policy(segments)).await {
+        Ok(body) => Response::builder()
+            .status(StatusCode::OK)
+            .header(
+                header::CONTENT_TYPE,
+                "application/x-git-receive-pack-advertisement",
+            )
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(axum::body::Body::from(body))
+            .expect("response build"),
+        Err(e) => (StatusCode::BAD_GATEWAY, format!("{e:#}")).into_response(),
+    }
+}
+
+// POST /.../git-receive-pack
+pub(crate) async fn handle_receive_pack<S>(
+    state: S,
+    mut segments: Vec<String>,
+    body: axum::body::Body,
+) -> Response
+where
+    S: GitHttpState,
+{
+    for s in &mut segments {
+        if let Some(stripped) = s.strip_suffix(".git") {
+            *s = stripped.to_string();
+        }
+    }
+    for s in &segments {
+        if let Err(e) = state.validate_slug(s) {
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+    }
+    if let Some(resp) = push_refused(&state, &segments) {
+        return resp;
+    }
+    let repo_dir = match resolve_repo_dir(state.storage(), &segments) {
+        Ok(p) => p,
+        Err(_) => return (StatusCode::NOT_FOUND, "repo not found").into_response(),
+    };
+
+    let _permit = match state
+        .git_scheduler()
+        .acquire(GitPriority::Interactive)
+        .await
+    {
+        Ok(p) => p,
+        Err(shed) => return shed.into_response(),
+    };
+    let bytes = match axum::body::to_bytes(body, state.git_max_body()).await {
+        Ok(b) => b,
+        Err(_) => return (StatusCode::BAD_REQUEST, "invalid request body").into_response(),
+    };
+    let result = |body: Vec<u8>| {
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(
+                header::CONTENT_TYPE,
+                "application/x-git-receive-pack-result",
+            )
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(axum::body::Body::from(body))
+            .expect("response build")
+    };
+    // git probes with a lone flush before sending a large push, so credentials are asked
+    // for before the pack is streamed
+    if bytes.as_ref() == PKT_FLUSH {
+        return result(Vec::new());
+    }
+    let req = match receive::parse_push(&bytes) {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("bad push: {e}")).into_response(),
+    };
+
+    let start = Instant::now();
+    let (rules, refs) = (state.push_policy(&segments), state.ref_policy(&segments));
+    let fut = receive::receive_push(&repo_dir, &rules, &refs, &req, &bytes);
+    let outcome = match tokio::time::timeout(
+        std::time::Duration::from_millis(state.git_timeout_ms()),
+        fut,
+    )
+    .await
+    {
+        Ok(Ok(outcome)) => outcome,
+        Ok(Err(e)) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("push failed: {e:#}"),
+            )
+                .into_response();
+        }
+        Err(_) => return (StatusCode::REQUEST_TIMEOUT, "push timed out").into_response(),
+    };
+    let applied = outcome.applied();
+    if !applied.is_empty() {
+        state.record_push(&segments, &applied);
+    }
+    let label = if applied.len() == req.updates.len() {
+        "ok"
+    } else {
+        "rejected"
+    };
+    counter!("git_http.receive_pack", "result" => label).increment(1);
+    histogram!("git_http.receive_pack_ms").record(start.elapsed().as_millis() as f64);
+    result(if req.report_status {
+        outcome.encode_report_status(req.side_band_64k)
+    } else {
+        Vec::new()
+    })
 }
 
 async fn advertise_v2_rust<S>(state: &S, segments: &[String], _headers: &HeaderMap) -> Response
@@ -111,14 +278,18 @@ where
     S: GitHttpState,
 {
     // Validate repo exists and is exported
-    let repo_dir = match resolve_repo_dir(state.storage(), segments) { Ok(p) => p, Err(_) => return (StatusCode::NOT_FOUND, "repo not found").into_response() };
-    if !is_public_repo(&repo_dir) { return (StatusCode::NOT_FOUND, "repo not found").into_response(); }
+    let repo_dir = match resolve_repo_dir(state.storage(), segments) {
+        Ok(p) => p,
+        Err(_) => return (StatusCode::NOT_FOUND, "repo not found").into_response(),
+    };
+    if !state.is_exported(&segments, &repo_dir) {
+        return (StatusCode::NOT_FOUND, "repo not found").into_response();
+    }
 
     // Compose a protocol v2 advertisement matching git http-backend semantics closely.
     let mut body = Vec::with_capacity(256);
     // version banner
     body.extend_from_slice(&encode_pkt_line(b"version 2\n"));
-    body.extend_from_slice(PKT_FLUSH);
     // Capability and command advertisement. Ordering chosen to mirror common git output.
     // agent (value masked in our trace normalizer)
     body.extend_from_slice(&encode_pkt_line(format!("agent=forge/{}\n", env!("CARGO_PKG_VERSION")).as_bytes()));
@@ -130,13 +301,11 @@ where
     // allow server options passthrough
     body.extend_from_slice(&encode_pkt_line(b"server-option\n"));
     // commands
-    body.extend_from_slice(&encode_pkt_line(b"ls-refs\n"));
-    // fetch features we implement or parse today
-    body.extend_from_slice(&encode_pkt_line(b"fetch=shallow\n"));
-    body.extend_from_slice(&encode_pkt_line(b"fetch=filter\n"));
-    body.extend_from_slice(&encode_pkt_line(b"fetch=ref-in-want\n"));
-    body.extend_from_slice(&encode_pkt_line(b"fetch=deepen-since\n"));
-    body.extend_from_slice(&encode_pkt_line(b"fetch=deepen-not\n"));
+    body.extend_from_slice(&encode_pkt_line(b"ls-refs=unborn\n"));
+    // fetch features we implement today; clients only read the first `fetch=` line, so
+    // they must share one (deepen-since/deepen-not ride along with shallow). ref-in-want
+    // stays unadvertised until fetch answers with a wanted-refs section.
+    body.extend_from_slice(&encode_pkt_line(b"fetch=shallow filter\n"));
     body.extend_from_slice(PKT_FLUSH);
 
     Response::builder()
@@ -151,10 +320,19 @@ async fn advertise_v2_via_git<S>(state: &S, segments: &[String], headers: &Heade
 where
     S: GitHttpState,
 {
-    let repo_dir = match resolve_repo_dir(state.storage(), segments) { Ok(p) => p, Err(_) => return (StatusCode::NOT_FOUND, "repo not found").into_response() };
-    if !is_public_repo(&repo_dir) { return (StatusCode::NOT_FOUND, "repo not found").into_response(); }
+    let repo_dir = match resolve_repo_dir(state.storage(), segments) {
+        Ok(p) => p,
+        Err(_) => return (StatusCode::NOT_FOUND, "repo not found").into_response(),
+    };
+    if !state.is_exported(&segments, &repo_dir) {
+        return (StatusCode::NOT_FOUND, "repo not found").into_response();
+    }
     let mut cmd = tokio::process::Command::new("git");
-    cmd.arg("upload-pack").arg("--stateless-rpc").arg("--advertise-refs").arg(repo_dir);
+    cmd.args(state.ref_policy(segments).git_config_args());
+    cmd.arg("upload-pack")
+        .arg("--stateless-rpc")
+        .arg("--advertise-refs")
+        .arg(repo_dir);
     cmd.stdout(std::process::Stdio::piped());
     if let Some(v) = headers.get("Git-Protocol").and_then(|v| v.to_str().ok()) {
         cmd.env("GIT_PROTOCOL", v);
@@ -215,7 +393,12 @@ where
     }
 }
 
-async fn handle_upload_pack<S>(state: S, mut segments: Vec<String>, headers: HeaderMap, body: axum::body::Body) -> Response
+pub(crate) async fn handle_upload_pack<S>(
+    state: S,
+    mut segments: Vec<String>,
+    headers: HeaderMap,
+    body: axum::body::Body,
+) -> Response
 where
     S: GitHttpState,
 {
@@ -223,9 +406,6 @@ where
     for s in &mut segments { if let Some(stripped) = s.strip_suffix(".git") { *s = stripped.to_string(); } }
     for s in &segments { if let Err(e) = state.validate_slug(s) { return (StatusCode::BAD_REQUEST, e.to_string()).into_response(); } }
 
-    // Concurrency limit per request
-    let _permit = state.git_semaphore().clone().acquire_owned().await.ok();
-
     let max = state.git_max_body();
     let bytes = match axum::body::to_bytes(body, max).await {
         Ok(b) => b,
@@ -239,16 +419,70 @@ where
     let mut ls = LsRefsOptions::default();
     for pkt in pkts.iter() {
         if let Pkt::Data(line) = pkt {
-            if let Some(rest) = line.strip_prefix(b"command=") { command = Some(String::from_utf8_lossy(rest).trim_end_matches('\n').to_string()); continue; }
-            if let Some(rest) = line.strip_prefix(b"ref-prefix ") { ls.ref_prefix.push(String::from_utf8_lossy(rest).trim_end_matches('\n').to_string()); continue; }
-            if line == b"peel\n" { ls.peel = true; continue; }
-            if line == b"symrefs\n" { ls.symrefs = true; continue; }
+            if let Some(rest) = line.strip_prefix(b"command=") {
+                command = Some(
+                    String::from_utf8_lossy(rest)
+                        .trim_end_matches('\n')
+                        .to_string(),
+                );
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix(b"ref-prefix ") {
+                ls.ref_prefix.push(
+                    String::from_utf8_lossy(rest)
+                        .trim_end_matches('\n')
+                        .to_string(),
+                );
+                continue;
+            }
+            if line == b"peel\n" {
+                ls.peel = true;
+                continue;
+            }
+            if line == b"symrefs\n" {
+                ls.symrefs = true;
+                continue;
+            }
+            if line == b"unborn\n" {
+                ls.unborn = true;
+                continue;
+            }
         }
     }
 
     // Resolve repository directory for subsequent operations
-    let repo_dir = match resolve_repo_dir(state.storage(), &segments) { Ok(p) => p, Err(_) => return (StatusCode::NOT_FOUND, "repo not found").into_response() };
-    if !is_public_repo(&repo_dir) { return (StatusCode::NOT_FOUND, "repo not found").into_response(); }
+    let repo_dir = match resolve_repo_dir(state.storage(), &segments) {
+        Ok(p) => p,
+        Err(_) => return (StatusCode::NOT_FOUND, "repo not found").into_response(),
+    };
+    if !state.is_exported(&segments, &repo_dir) {
+        return (StatusCode::NOT_FOUND, "repo not found").into_response();
+    }
+
+    // Clones get their own concurrency budget so they cannot crowd out incremental fetches
+    let fetch = match command.as_deref() {
+        Some("fetch") => parse_fetch(&pkts).ok(),
+        _ => None,
+    };
+    let class = match &fetch {
+        Some(req) if req.is_clone() => GitPriority::Clone,
+        _ => GitPriority::Interactive,
+    };
+    let _permit = match state.git_scheduler().acquire(class).await {
+        Ok(p) => p,
+        Err(shed) => return shed.into_response(),
+    };
+
+    // git only enforces the want allowances for protocol v0, so both backends rely on this
+    // check; malformed requests are left to the backend to reject.
+    if let Some(req) = &fetch {
+        if let Err(msg) = check_wants(&repo_dir, &state.ref_policy(&segments), req).await {
+            return respond_fetch_error(&msg);
+        }
+        if req.is_clone() && !req.wants_nothing() {
+            state.record_clone(&segments);
+        }
+    }
 
     // Select backend and apply timeout per request
     match (std::env::var("FORGE_GIT_SMART_V2_BACKEND").ok().as_deref().unwrap_or("git"), command.as_deref()) {
@@ -272,6 +506,8 @@ where
         }
         ("rust", Some("fetch")) => {
             match parse_fetch(&pkts) {
+                // Cloning an empty repository; git upload-pack answers with a bare flush too
+                Ok(req) if req.wants_nothing() => respond_empty_fetch(),
                 Ok(req) => {
                     tracing::info!(
                         wants = %req.wants().len(),
@@ -299,35 +535,52 @@ where
 }
 
 #[derive(Debug, Default, Clone)]
-struct LsRefsOptions { ref_prefix: Vec<String>, peel: bool, symrefs: bool }
+struct LsRefsOptions {
+    ref_prefix: Vec<String>,
+    peel: bool,
+    symrefs: bool,
+    unborn: bool,
+}
 
 async fn respond_ls_refs<S>(state: &S, segments: &[String], opts: &LsRefsOptions) -> Response
 where
     S: GitHttpState,
 {
     use gix::prelude::*;
-    let repo_dir = match resolve_repo_dir(state.storage(), segments) { Ok(p) => p, Err(_) => return (StatusCode::NOT_FOUND, "repo not found").into_response() };
-    if !is_public_repo(&repo_dir) { return (StatusCode::NOT_FOUND, "repo not found").into_response(); }
-    let repo = match gix::open(&repo_dir) { Ok(r) => r, Err(_) => return (StatusCode::NOT_FOUND, "invalid repository").into_response() };
+    let repo_dir = match resolve_repo_dir(state.storage(), segments) {
+        Ok(p) => p,
+        Err(_) => return (StatusCode::NOT_FOUND, "repo not found").into_response(),
+    };
+    if !state.is_exported(&segments, &repo_dir) {
+        return (StatusCode::NOT_FOUND, "repo not found").into_response();
+    }
+    let repo = match gix::open(&repo_dir) {
+        Ok(r) => r,
+        Err(_) => return (StatusCode::NOT_FOUND, "invalid repository").into_response(),
+    };
+    let policy = state.ref_policy(segments);
 
     let mut body = Vec::with_capacity(2048);
 
-    let mut push_ref_line = |oid: gix::hash::ObjectId, name: &str, symref_target: Option<&str>, peeled: Option<gix::hash::ObjectId>| {
-        // <oid> SP <refname> NUL [ "symref-target:" <target> NUL ] [ "peeled:" <oid> NUL ] LF
+    let mut push_ref_line = |oid: Option<gix::hash::ObjectId>,
+                             name: &str,
+                             symref_target: Option<&str>,
+                             peeled: Option<gix::hash::ObjectId>| {
+        // (<oid> | "unborn") SP <refname> [ SP "symref-target:" <target> ] [ SP "peeled:" <oid> ] LF
         let mut line = Vec::with_capacity(64 + name.len());
-        line.extend_from_slice(oid.to_string().as_bytes());
+        match oid {
+            Some(oid) => line.extend_from_slice(oid.to_string().as_bytes()),
+            None => line.extend_from_slice(b"unborn"),
+        }
         line.push(b' ');
         line.extend_from_slice(name.as_bytes());
-        line.push(0); // NUL
         if let Some(t) = symref_target {
-            line.extend_from_slice(b"symref-target:");
+            line.extend_from_slice(b" symref-target:");
             line.extend_from_slice(t.as_bytes());
-            line.push(0);
         }
         if let Some(p) = peeled {
-            line.extend_from_slice(b"peeled:");
+            line.extend_from_slice(b" peeled:");
             line.extend_from_slice(p.to_string().as_bytes());
-            line.push(0);
         }
         line.push(b'\n');
         body.extend_from_slice(&encode_pkt_line(&line));
@@ -349,11 +602,18 @@ where
             Some(idref) => Some(idref.detach()),
             None => head.clone().peel_to_commit().ok().map(|c| c.id().detach()),
         };
-        if let Some(oid) = resolved_id {
+        // A symbolic HEAD that resolves to nothing points at a branch without commits yet,
+        // as in a fresh repository; only clients that asked for `unborn` are told about it.
+        let unborn = resolved_id.is_none()
+            && opts.unborn
+            && matches!(head.target(), gix::refs::TargetRef::Symbolic(_));
+        if resolved_id.is_some() || unborn {
             let mut include = opts.ref_prefix.is_empty();
-            if !include { include = opts.ref_prefix.iter().any(|p| "HEAD".starts_with(p)); }
-            if include {
-                push_ref_line(oid, "HEAD", symref_target.as_deref(), None);
+            if !include {
+                include = opts.ref_prefix.iter().any(|p| "HEAD".starts_with(p));
+            }
+            if include && !policy.is_hidden("HEAD") {
+                push_ref_line(resolved_id, "HEAD", symref_target.as_deref(), None);
             }
         }
     }
@@ -367,10 +627,14 @@ where
                     let b = reference.name().as_bstr().as_bytes();
                     std::str::from_utf8(b).unwrap_or("")
                 };
-                if name.is_empty() { continue; }
+                if name.is_empty() || policy.is_hidden(name) {
+                    continue;
+                }
 
                 // filter by ref-prefix if provided
-                if !opts.ref_prefix.is_empty() && !opts.ref_prefix.iter().any(|p| name.starts_with(p)) {
+                if !opts.ref_prefix.is_empty()
+                    && !opts.ref_prefix.iter().any(|p| name.starts_with(p))
+                {
                     continue;
                 }
 
@@ -409,7 +673,7 @@ where
                     }
                 }
 
-                push_ref_line(oid, name, symref_target.as_deref(), peeled_attr);
+                push_ref_line(Some(oid), name, symref_target.as_deref(), peeled_attr);
             }
         }
     }
@@ -456,23 +720,65 @@ pub struct FetchRequest {
 }
 
 impl FetchRequest {
-    pub fn wants(&self) -> &[String] { &self.wants }
-    pub fn push_want(&mut self, oid: String) { self.wants.push(oid); }
-    pub fn extend_wants<I: IntoIterator<Item = String>>(&mut self, iter: I) { self.wants.extend(iter); }
-    pub fn side_band_64k(&self) -> bool { self.side_band_64k }
-    pub fn has_haves(&self) -> bool { !self.haves.is_empty() }
-    pub fn shallow_requested(&self) -> bool { self.deepen.is_some() || self.deepen_since.is_some() || !self.deepen_not.is_empty() }
-    pub fn filter_requested(&self) -> bool { self.filter.is_some() }
-    pub fn haves(&self) -> &[String] { &self.haves }
-    pub fn no_progress(&self) -> bool { self.no_progress }
-    pub fn thin_pack(&self) -> bool { self.thin_pack }
-    pub fn ofs_delta(&self) -> bool { self.ofs_delta }
-    pub fn want_refs(&self) -> &[String] { &self.want_refs }
-    pub fn client_shallows(&self) -> &[String] { &self.client_shallows }
-    pub fn done(&self) -> bool { self.done }
-    pub fn deepen(&self) -> Option<u32> { self.deepen }
-    pub fn deepen_since(&self) -> Option<i64> { self.deepen_since }
-    pub fn deepen_not(&self) -> &[String] { &self.deepen_not }
+    pub fn wants(&self) -> &[String] {
+        &self.wants
+    }
+    pub fn push_want(&mut self, oid: String) {
+        self.wants.push(oid);
+    }
+    pub fn extend_wants<I: IntoIterator<Item = String>>(&mut self, iter: I) {
+        self.wants.extend(iter);
+    }
+    pub fn side_band_64k(&self) -> bool {
+        self.side_band_64k
+    }
+    pub fn has_haves(&self) -> bool {
+        !self.haves.is_empty()
+    }
+    /// No `want` or `want-ref` lines, as git sends when cloning an empty repository.
+    pub fn wants_nothing(&self) -> bool {
+        self.wants.is_empty() && self.want_refs.is_empty()
+    }
+    /// A clone sends neither `have` nor `shallow` lines; fetches into an existing repository do.
+    pub fn is_clone(&self) -> bool {
+        self.haves.is_empty() && self.client_shallows.is_empty()
+    }
+    pub fn shallow_requested(&self) -> bool {
+        self.deepen.is_some() || self.deepen_since.is_some() || !self.deepen_not.is_empty()
+    }
+    pub fn filter_requested(&self) -> bool {
+        self.filter.is_some()
+    }
+    pub fn haves(&self) -> &[String] {
+        &self.haves
+    }
+    pub fn no_progress(&self) -> bool {
+        self.no_progress
+    }
+    pub fn thin_pack(&self) -> bool {
+        self.thin_pack
+    }
+    pub fn ofs_delta(&self) -> bool {
+        self.ofs_delta
+    }
+    pub fn want_refs(&self) -> &[String] {
+        &self.want_refs
+    }
+    pub fn client_shallows(&self) -> &[String] {
+        &self.client_shallows
+    }
+    pub fn done(&self) -> bool {
+        self.done
+    }
+    pub fn deepen(&self) -> Option<u32> {
+        self.deepen
+    }
+    pub fn deepen_since(&self) -> Option<i64> {
+        self.deepen_since
+    }
+    pub fn deepen_not(&self) -> &[String] {
+        &self.deepen_not
+    }
     pub fn filter_blob_none(&self) -> bool {
         match self.filter.as_deref() {
             Some(s) => s.trim() == "blob:none",
@@ -503,7 +809,40 @@ impl FetchRequest {
     }
 }
 
-fn parse_fetch(pkts: &[Pkt]) -> anyhow::Result<FetchRequest> {
+/// JSON form of a fetch request, used to drive [`pack::dry_run_fetch`] from the admin API.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SyntheticFetch {
+    pub wants: Vec<String>,
+    pub want_refs: Vec<String>,
+    pub haves: Vec<String>,
+    pub shallows: Vec<String>,
+    pub deepen: Option<u32>,
+    pub deepen_since: Option<i64>,
+    pub deepen_not: Vec<String>,
+    pub filter: Option<String>,
+    pub verify: bool,
+}
+
+impl SyntheticFetch {
+    pub fn to_fetch_request(&self) -> FetchRequest {
+        FetchRequest {
+            object_format: Some("sha1".to_string()),
+            wants: self.wants.clone(),
+            want_refs: self.want_refs.clone(),
+            haves: self.haves.clone(),
+            client_shallows: self.shallows.clone(),
+            done: true,
+            deepen: self.deepen,
+            deepen_since: self.deepen_since,
+            deepen_not: self.deepen_not.clone(),
+            filter: self.filter.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+pub(crate) fn parse_fetch(pkts: &[Pkt]) -> anyhow::Result<FetchRequest> {
     use anyhow::Context;
     let mut req = FetchRequest::default();
     for pkt in pkts {
@@ -527,10 +866,19 @@ fn parse_fetch(pkts: &[Pkt]) -> anyhow::Result<FetchRequest> {
         if s == "done" { req.done = true; continue; }
     }
     if let Some(fmt) = &req.object_format { if fmt != "sha1" { anyhow::bail!("unsupported object-format {fmt}"); } }
-    if req.wants.is_empty() { anyhow::bail!("no wants provided"); }
     Ok(req)
 }
 
+/// The reply to a fetch without wants: no sections, only a flush.
+fn respond_empty_fetch() -> Response {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-git-upload-pack-result")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(axum::body::Body::from(PKT_FLUSH))
+        .expect("response build")
+}
+
 fn respond_fetch_not_implemented(_req: &FetchRequest) -> Response {
     respond_fetch_error("fetch not implemented yet")
 }
@@ -539,9 +887,15 @@ async fn proxy_to_git_upload_pack<S>(state: &S, segments: &[String], request_bod
 where
     S: GitHttpState,
 {
-    let repo_dir = match resolve_repo_dir(state.storage(), segments) { Ok(p) => p, Err(_) => return (StatusCode::NOT_FOUND, "repo not found").into_response() };
-    if !is_public_repo(&repo_dir) { return (StatusCode::NOT_FOUND, "repo not found").into_response(); }
+    let repo_dir = match resolve_repo_dir(state.storage(), segments) {
+        Ok(p) => p,
+        Err(_) => return (StatusCode::NOT_FOUND, "repo not found").into_response(),
+    };
+    if !state.is_exported(&segments, &repo_dir) {
+        return (StatusCode::NOT_FOUND, "repo not found").into_response();
+    }
     let mut cmd = tokio::process::Command::new("git");
+    cmd.args(state.ref_policy(segments).git_config_args());
     cmd.arg("upload-pack").arg("--stateless-rpc").arg(repo_dir);
     cmd.stdin(std::process::Stdio::piped());
     cmd.stdout(std::process::Stdio::piped());
@@ -574,9 +928,13 @@ mod tests {
     use std::path::{Path, PathBuf};
     use std::sync::Arc;
     use tempfile::TempDir;
-    use tokio::sync::Semaphore;
 
-    use crate::pkt::encode_pkt_line;
+    use crate::RepositoryProvider;
+    use crate::pkt::{PKT_DELIM, encode_pkt_line};
+    use crate::policy::{PolicyRules, RefUpdate};
+    use crate::refs::RefPolicy;
+    use crate::scheduler::GitScheduler;
+    use crate::test_helpers::{commit_file, git};
 
     #[derive(Clone)]
     struct TestStorage {
@@ -602,7 +960,11 @@ mod tests {
         storage: TestStorage,
         max_body: usize,
         timeout_ms: u64,
-        semaphore: Arc<Semaphore>,
+        scheduler: Arc<GitScheduler>,
+        ref_policy: RefPolicy,
+        push_access: PushAccess,
+        policy: PolicyRules,
+        pushed: Arc<std::sync::Mutex<Vec<RefUpdate>>>,
     }
 
     impl GitHttpState for TestState {
@@ -612,8 +974,8 @@ mod tests {
             &self.storage
         }
 
-        fn git_semaphore(&self) -> &Arc<Semaphore> {
-            &self.semaphore
+        fn git_scheduler(&self) -> &GitScheduler {
+            &self.scheduler
         }
 
         fn git_max_body(&self) -> usize {
@@ -627,6 +989,22 @@ mod tests {
         fn validate_slug(&self, slug: &str) -> anyhow::Result<()> {
             validate_slug(slug)
         }
+
+        fn ref_policy(&self, _segments: &[String]) -> RefPolicy {
+            self.ref_policy.clone()
+        }
+
+        fn push_policy(&self, _segments: &[String]) -> PolicyRules {
+            self.policy.clone()
+        }
+
+        fn push_access(&self, _segments: &[String]) -> PushAccess {
+            self.push_access
+        }
+
+        fn record_push(&self, _segments: &[String], updates: &[RefUpdate]) {
+            self.pushed.lock().unwrap().extend_from_slice(updates);
+        }
     }
 
     fn validate_slug(slug: &str) -> anyhow::Result<()> {
@@ -653,39 +1031,26 @@ mod tests {
             },
             max_body: 64 * 1024 * 1024,
             timeout_ms: 60_000,
-            semaphore: Arc::new(Semaphore::new(64)),
+            scheduler: Arc::default(),
+            ref_policy: RefPolicy::default(),
+            push_access: PushAccess::Denied,
+            policy: PolicyRules::default(),
+            pushed: Arc::default(),
         };
         Ok((state, local_dir))
     }
 
     async fn init_bare_repo(path: &Path) {
-        std::fs::create_dir_all(path).ok();
-        let _ = std::process::Command::new("git").arg("init").arg("--bare").arg(path).status();
+        std::fs::create_dir_all(path).unwrap();
+        git(path, &["init", "-q", "--bare"]);
     }
 
     async fn seed_main_branch(bare: &Path) {
         let tmp = TempDir::new().unwrap();
         let work = tmp.path();
-        let _ = std::process::Command::new("git").current_dir(work).arg("init").status();
-        std::fs::write(work.join("README.md"), b"hello\n").ok();
-        let _ = std::process::Command::new("git").current_dir(work).args(["add", "README.md"]).status();
-        let _ = std::process::Command::new("git").current_dir(work).args([
-            "-c",
-            "user.email=t@e",
-            "-c",
-            "user.name=t",
-            "commit",
-            "-m",
-            "init",
-        ]).status();
-        let _ = std::process::Command::new("git").current_dir(work).args(["branch", "-M", "main"]).status();
-        let _ = std::process::Command::new("git").current_dir(work).args([
-            "remote",
-            "add",
-            "origin",
-            &bare.to_string_lossy(),
-        ]).status();
-        let _ = std::process::Command::new("git").current_dir(work).args(["push", "origin", "main"]).status();
+        git(work, &["init", "-q", "-b", "main"]);
+        commit_file(work, "README.md", "hello\n", "init");
+        git(work, &["push", "-q", &bare.to_string_lossy(), "main"]);
     }
 
     #[test]
@@ -699,6 +1064,7 @@ mod tests {
         let req = parse_fetch(&pkts).unwrap();
         assert_eq!(req.wants.len(), 1);
         assert_eq!(req.object_format.as_deref(), Some("sha1"));
+        assert!(req.is_clone());
     }
 
     #[test]
@@ -737,6 +1103,7 @@ mod tests {
         assert_eq!(req.wants.len(), 1);
         assert_eq!(req.want_refs.len(), 3);
         assert_eq!(req.haves.len(), 1);
+        assert!(!req.is_clone());
         assert!(req.done);
         assert_eq!(req.server_options.len(), 1);
     }
@@ -745,18 +1112,13 @@ mod tests {
     fn advertise_v2_shape() {
         let mut body = Vec::new();
         body.extend_from_slice(&encode_pkt_line(b"version 2\n"));
-        body.extend_from_slice(PKT_FLUSH);
         let adv = vec![
             encode_pkt_line(b"agent=forge/x.y.z\n"),
             encode_pkt_line(b"session-id=abc\n"),
             encode_pkt_line(b"object-format=sha1\n"),
             encode_pkt_line(b"server-option\n"),
             encode_pkt_line(b"ls-refs\n"),
-            encode_pkt_line(b"fetch=shallow\n"),
-            encode_pkt_line(b"fetch=filter\n"),
-            encode_pkt_line(b"fetch=ref-in-want\n"),
-            encode_pkt_line(b"fetch=deepen-since\n"),
-            encode_pkt_line(b"fetch=deepen-not\n"),
+            encode_pkt_line(b"fetch=shallow filter\n"),
         ];
         for a in adv {
             body.extend_from_slice(&a);
@@ -771,7 +1133,7 @@ mod tests {
         }
         assert!(s.contains("version 2") || s.contains("ls-refs"));
         assert!(s.contains("object-format=sha1"));
-        assert!(s.contains("fetch=filter"));
+        assert!(s.contains("fetch=shallow filter"));
     }
 
     #[tokio::test]
@@ -786,6 +1148,67 @@ mod tests {
         assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
     }
 
+    #[tokio::test]
+    async fn nested_group_routes_resolve_info_refs_and_raw() {
+        let (state, local_dir) = mk_app_state().await.unwrap();
+        let repo = local_dir
+            .path()
+            .join("team")
+            .join("infra")
+            .join("tools.git");
+        init_bare_repo(&repo).await;
+        seed_main_branch(&repo).await;
+        std::fs::write(repo.join("git-daemon-export-ok"), b"").unwrap();
+
+        let resp = crate::routes::dispatch_get(
+            AxState(state.clone()),
+            AxPath("team/infra/tools.git/info/refs".to_string()),
+            AxQuery(ServiceQuery {
+                service: Some("git-upload-pack".to_string()),
+            }),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = crate::routes::dispatch_get(
+            AxState(state.clone()),
+            AxPath("team/infra/tools/-/raw/main/README.md".to_string()),
+            AxQuery(ServiceQuery { service: None }),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"hello\n");
+
+        let resp = crate::routes::dispatch_get(
+            AxState(state.clone()),
+            AxPath("team/infra/tools/-/raw/main/missing.md".to_string()),
+            AxQuery(ServiceQuery { service: None }),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = crate::routes::dispatch_get(
+            AxState(state.clone()),
+            AxPath("team/infra/tools/-/archive/main.tar".to_string()),
+            AxQuery(ServiceQuery { service: None }),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert!(body.windows(b"tools-main/README.md".len()).any(|w| w == b"tools-main/README.md"));
+
+        let resp = crate::routes::dispatch_get(
+            AxState(state),
+            AxPath("team/In fra/tools/info/refs".to_string()),
+            AxQuery(ServiceQuery {
+                service: Some("git-upload-pack".to_string()),
+            }),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn info_refs_gated_and_content_type() {
         unsafe {
@@ -818,9 +1241,126 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn receive_pack_is_forbidden() {
-        let resp = receive_pack_blocked().await.into_response();
-        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    async fn receive_pack_requires_push_access() {
+        let (mut state, local_dir) = mk_app_state().await.unwrap();
+        init_bare_repo(&local_dir.path().join("alpha")).await;
+        let push = |state: TestState| {
+            crate::routes::dispatch_post(
+                AxState(state),
+                AxPath("alpha.git/git-receive-pack".to_string()),
+                AxHeaderMap::new(),
+                axum::body::Body::from(PKT_FLUSH),
+            )
+        };
+        let advertise = |state: TestState| {
+            info_refs_root(
+                AxState(state),
+                AxPath("alpha".to_string()),
+                AxQuery(ServiceQuery {
+                    service: Some("git-receive-pack".to_string()),
+                }),
+            )
+        };
+        assert_eq!(push(state.clone()).await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            advertise(state.clone()).await.status(),
+            StatusCode::FORBIDDEN
+        );
+
+        state.push_access = PushAccess::Unauthenticated;
+        let resp = push(state.clone()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            resp.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+            "Basic realm=\"forge\""
+        );
+
+        // unexported repositories can be pushed to
+        state.push_access = PushAccess::Allowed;
+        let resp = advertise(state.clone()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/x-git-receive-pack-advertisement"
+        );
+        let body = body_text(resp).await;
+        assert!(body.starts_with("001f# service=git-receive-pack\n0000"));
+        assert!(body.contains("capabilities^{}\0report-status delete-refs side-band-64k"));
+        assert_eq!(push(state).await.status(), StatusCode::OK);
+    }
+
+    /// Pushes run on the runtime so the in-process server can answer them.
+    async fn push_to(dir: &Path, url: &str, refspecs: &[&str]) -> std::process::Output {
+        tokio::process::Command::new("git")
+            .current_dir(dir)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .args(["-c", "protocol.version=2", "push", url])
+            .args(refspecs)
+            .output()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn push_over_http_moves_refs_and_reports_rejections() {
+        let (mut state, local_dir) = mk_app_state().await.unwrap();
+        state.push_access = PushAccess::Allowed;
+        state.policy = PolicyRules {
+            forbidden_paths: vec!["*.pem".into()],
+            ..Default::default()
+        };
+        let repo = local_dir.path().join("alpha.git");
+        init_bare_repo(&repo).await;
+        git(&repo, &["symbolic-ref", "HEAD", "refs/heads/main"]);
+
+        let app = axum::Router::new()
+            .route(
+                "/{*path}",
+                axum::routing::get(crate::routes::dispatch_get::<TestState>)
+                    .post(crate::routes::dispatch_post::<TestState>),
+            )
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/alpha.git", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let tmp = TempDir::new().unwrap();
+        let work = tmp.path();
+        git(work, &["init", "-q", "-b", "main"]);
+        let head = commit_file(work, "README.md", "hello\n", "init");
+        let out = push_to(work, &url, &["main", "main:refs/heads/topic"]).await;
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        assert_eq!(git(&repo, &["rev-parse", "refs/heads/main"]), head);
+        assert_eq!(state.pushed.lock().unwrap().len(), 2);
+
+        // a thin pack on top of existing history, rejected by the push policy
+        commit_file(work, "key.pem", "secret\n", "add key");
+        let out = push_to(work, &url, &["main"]).await;
+        assert!(!out.status.success());
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(
+            stderr.contains("policy violation: forbidden-path"),
+            "{stderr}"
+        );
+        assert!(
+            stderr.contains("remote: error: [forbidden-path] refs/heads/main"),
+            "{stderr}"
+        );
+        assert_eq!(git(&repo, &["rev-parse", "refs/heads/main"]), head);
+
+        let out = push_to(work, &url, &[":main", ":topic"]).await;
+        assert!(!out.status.success());
+        assert!(
+            String::from_utf8_lossy(&out.stderr)
+                .contains("deletion of the current branch prohibited")
+        );
+        assert!(git(&repo, &["for-each-ref", "refs/heads/topic"]).is_empty());
+        assert_eq!(state.pushed.lock().unwrap().len(), 3);
+        server.abort();
     }
 
     #[tokio::test]
@@ -856,6 +1396,63 @@ mod tests {
         assert!(s.contains("refs/heads/main"));
     }
 
+    #[tokio::test]
+    async fn empty_repository_has_unborn_head_and_fetches_nothing() {
+        unsafe {
+            std::env::set_var("FORGE_GIT_SMART_V2_BACKEND", "rust");
+        }
+        let (state, local_dir) = mk_app_state().await.unwrap();
+        let repo = local_dir.path().join("alpha.git");
+        init_bare_repo(&repo).await;
+        std::fs::write(repo.join("git-daemon-export-ok"), b"").unwrap();
+        std::fs::write(repo.join("HEAD"), b"ref: refs/heads/trunk\n").unwrap();
+
+        let ls_refs = |args: &[&[u8]]| {
+            let mut req = Vec::new();
+            req.extend_from_slice(&encode_pkt_line(b"command=ls-refs\n"));
+            req.extend_from_slice(&encode_pkt_line(b"ref-prefix HEAD\n"));
+            for arg in args {
+                req.extend_from_slice(&encode_pkt_line(arg));
+            }
+            req.extend_from_slice(PKT_FLUSH);
+            req
+        };
+        let post = |req: Vec<u8>| {
+            let state = state.clone();
+            async move {
+                let resp = upload_pack_root(
+                    AxState(state),
+                    AxPath("alpha".to_string()),
+                    AxHeaderMap::new(),
+                    axum::body::Body::from(req),
+                )
+                .await;
+                assert_eq!(resp.status(), StatusCode::OK);
+                axum::body::to_bytes(resp.into_body(), 16 << 20)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let unborn = post(ls_refs(&[b"symrefs\n", b"unborn\n"])).await;
+        let mut expected = encode_pkt_line(b"unborn HEAD symref-target:refs/heads/trunk\n");
+        expected.extend_from_slice(PKT_FLUSH);
+        assert_eq!(unborn.as_ref(), expected.as_slice());
+        let bare = post(ls_refs(&[b"unborn\n"])).await;
+        assert!(bare.starts_with(&encode_pkt_line(b"unborn HEAD\n")));
+        // Clients that did not ask for it never see the unborn line
+        assert_eq!(post(ls_refs(&[b"symrefs\n"])).await.as_ref(), PKT_FLUSH);
+
+        let mut fetch = Vec::new();
+        fetch.extend_from_slice(&encode_pkt_line(b"command=fetch\n"));
+        fetch.extend_from_slice(&encode_pkt_line(b"object-format=sha1\n"));
+        fetch.extend_from_slice(crate::pkt::PKT_DELIM);
+        fetch.extend_from_slice(&encode_pkt_line(b"ofs-delta\n"));
+        fetch.extend_from_slice(&encode_pkt_line(b"done\n"));
+        fetch.extend_from_slice(PKT_FLUSH);
+        assert_eq!(post(fetch).await.as_ref(), PKT_FLUSH);
+    }
+
     #[tokio::test]
     async fn upload_pack_unknown_command_400() {
         unsafe {
@@ -905,4 +1502,111 @@ mod tests {
         .await;
         assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
     }
+
+    #[tokio::test]
+    async fn dry_run_reports_plan_without_streaming() {
+        let (state, root) = mk_app_state().await.unwrap();
+        let bare = root.path().join("alpha");
+        init_bare_repo(&bare).await;
+        seed_main_branch(&bare).await;
+
+        let fetch = SyntheticFetch {
+            want_refs: vec!["refs/heads/main".to_string()],
+            verify: true,
+            ..Default::default()
+        };
+        let resp = crate::admin::fetch_dry_run(
+            AxState(state.clone()),
+            AxPath("alpha".to_string()),
+            axum::Json(fetch),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains("\"commits\":1"), "{text}");
+        assert!(text.contains("\"trees\":1"), "{text}");
+        assert!(text.contains("\"blobs\":1"), "{text}");
+        assert!(text.contains("\"unreadable\":[]"), "{text}");
+
+        let missing = crate::admin::fetch_dry_run(
+            AxState(state),
+            AxPath("beta".to_string()),
+            axum::Json(SyntheticFetch::default()),
+        )
+        .await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    async fn body_text(resp: Response) -> String {
+        let bytes = axum::body::to_bytes(resp.into_body(), 16 << 20)
+            .await
+            .unwrap();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    #[tokio::test]
+    async fn ref_policy_is_enforced_by_both_backends() {
+        let (mut state, local_dir) = mk_app_state().await.unwrap();
+        let repo = local_dir.path().join("alpha.git");
+        init_bare_repo(&repo).await;
+        seed_main_branch(&repo).await;
+        std::fs::write(repo.join("git-daemon-export-ok"), b"").unwrap();
+        let pull = git(
+            &repo,
+            &["commit-tree", "main^{tree}", "-p", "main", "-m", "pr"],
+        );
+        git(&repo, &["update-ref", "refs/pull/1/head", &pull]);
+        state.ref_policy = RefPolicy {
+            hidden_refs: vec!["refs/pull/*".into()],
+            ..Default::default()
+        };
+        let segments = vec!["alpha".to_string()];
+
+        let mut ls = Vec::new();
+        ls.extend_from_slice(&encode_pkt_line(b"command=ls-refs\n"));
+        ls.extend_from_slice(&encode_pkt_line(b"object-format=sha1\n"));
+        ls.extend_from_slice(PKT_FLUSH);
+        let rust =
+            body_text(respond_ls_refs(&state, &segments, &LsRefsOptions::default()).await).await;
+        let git_ls =
+            body_text(proxy_to_git_upload_pack(&state, &segments, &ls, &AxHeaderMap::new()).await)
+                .await;
+        for listing in [&rust, &git_ls] {
+            assert!(listing.contains("refs/heads/main"), "{listing}");
+            assert!(!listing.contains("refs/pull/1/head"), "{listing}");
+        }
+
+        let mut fetch = Vec::new();
+        fetch.extend_from_slice(&encode_pkt_line(b"command=fetch\n"));
+        fetch.extend_from_slice(&encode_pkt_line(b"object-format=sha1\n"));
+        fetch.extend_from_slice(PKT_DELIM);
+        fetch.extend_from_slice(&encode_pkt_line(format!("want {pull}\n").as_bytes()));
+        fetch.extend_from_slice(&encode_pkt_line(b"done\n"));
+        fetch.extend_from_slice(PKT_FLUSH);
+        // Checked before either backend runs.
+        let resp = upload_pack_root(
+            AxState(state.clone()),
+            AxPath("alpha".to_string()),
+            AxHeaderMap::new(),
+            axum::body::Body::from(fetch.clone()),
+        )
+        .await;
+        let rejected = body_text(resp).await;
+        assert!(
+            rejected.ends_with(&format!("ERR upload-pack: not our ref {pull}\n0000")),
+            "{rejected}"
+        );
+
+        state.ref_policy.fetch_hidden = true;
+        let req = parse_fetch(&decode_pkt_lines(&fetch).unwrap()).unwrap();
+        check_wants(&repo, &state.ref_policy, &req).await.unwrap();
+        let git_fetch = body_text(
+            proxy_to_git_upload_pack(&state, &segments, &fetch, &AxHeaderMap::new()).await,
+        )
+        .await;
+        assert!(git_fetch.contains("packfile"), "{git_fetch}");
+    }
 }
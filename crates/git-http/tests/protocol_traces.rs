@@ -0,0 +1,517 @@
+//! Golden-file tests for the Smart HTTP v2 wire protocol.
+//!
+//! Every scenario builds the same deterministic fixture repository and serves it with
+//! the `git` backend (which proxies to `git upload-pack`) and the `rust` backend. Golden
+//! transcripts live in `tests/golden/<scenario>/<backend>.trace`; see `git_http::trace`
+//! for the format. `FORGE_GIT_TRACE` selects what a run does:
+//!
+//! - unset or `replay`: resend the recorded client requests to the router in-process and
+//!   compare the normalized responses. Only `git` on `PATH` is needed.
+//! - `live`: drive real `git` clients against a loopback server and compare the full
+//!   transcript, so changes in what clients send are caught too.
+//! - `record`: like `live`, but rewrite the golden files instead of comparing.
+//!
+//! Backends are chosen through process-wide environment variables, so scenarios take
+//! [`ENV_LOCK`] and never run concurrently.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::extract::{Request, State};
+use axum::http::header;
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::routing::get;
+use git_http::routes::{dispatch_get, dispatch_post};
+use git_http::trace::{Exchange, parse_transcript, render_exchange, render_transcript};
+use git_http::{GitHttpState, GitScheduler, RepositoryProvider};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+static ENV_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+const BACKENDS: [&str; 2] = ["git", "rust"];
+const REPO: &str = "alpha";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Replay,
+    Live,
+    Record,
+}
+
+fn mode() -> Mode {
+    match std::env::var("FORGE_GIT_TRACE").ok().as_deref() {
+        Some("record") => Mode::Record,
+        Some("live") => Mode::Live,
+        None | Some("") | Some("replay") => Mode::Replay,
+        Some(other) => panic!("FORGE_GIT_TRACE must be replay, live or record, not `{other}`"),
+    }
+}
+
+enum Step {
+    /// Run a git client with tracing on; `{url}` and `{dest}` are substituted.
+    Traced(&'static [&'static str]),
+    /// Run a git client whose traffic is not part of the transcript.
+    Untraced(&'static [&'static str]),
+    /// Push one more commit to the fixture.
+    Advance,
+}
+
+struct Scenario {
+    name: &'static str,
+    steps: &'static [Step],
+}
+
+const SIMPLE_CLONE: Scenario = Scenario {
+    name: "simple-clone",
+    steps: &[
+        Step::Traced(&["ls-remote", "{url}"]),
+        Step::Traced(&["clone", "{url}", "{dest}"]),
+    ],
+};
+
+const SHALLOW_CLONE: Scenario = Scenario {
+    name: "shallow-clone",
+    steps: &[Step::Traced(&["clone", "--depth=1", "{url}", "{dest}"])],
+};
+
+const INCREMENTAL_FETCH: Scenario = Scenario {
+    name: "incremental-fetch",
+    steps: &[
+        Step::Untraced(&["clone", "{url}", "{dest}"]),
+        Step::Advance,
+        Step::Traced(&["-C", "{dest}", "fetch", "origin"]),
+    ],
+};
+
+const PARTIAL_BLOB_NONE: Scenario = Scenario {
+    name: "partial-blob-none",
+    steps: &[Step::Traced(&[
+        "clone",
+        "--filter=blob:none",
+        "--no-checkout",
+        "{url}",
+        "{dest}",
+    ])],
+};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn simple_clone() {
+    run(&SIMPLE_CLONE).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shallow_clone() {
+    run(&SHALLOW_CLONE).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn incremental_fetch() {
+    run(&INCREMENTAL_FETCH).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partial_blob_none() {
+    run(&PARTIAL_BLOB_NONE).await;
+}
+
+async fn run(scenario: &Scenario) {
+    let _guard = ENV_LOCK.lock().await;
+    for backend in BACKENDS {
+        select_backend(backend);
+        let golden = golden_path(scenario.name, backend);
+        match mode() {
+            Mode::Replay => replay(scenario, &golden).await,
+            Mode::Live => {
+                let actual = live(scenario, backend).await;
+                let expected = read_golden(&golden);
+                assert_same(&golden, &lines(&expected), &lines(&actual));
+            }
+            Mode::Record => {
+                let actual = live(scenario, backend).await;
+                std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+                std::fs::write(&golden, actual).unwrap();
+            }
+        }
+    }
+}
+
+fn select_backend(backend: &str) {
+    // SAFETY: scenarios hold ENV_LOCK, and nothing else in this binary reads these.
+    unsafe {
+        std::env::set_var("FORGE_GIT_SMART_V2_BACKEND", backend);
+        std::env::set_var("FORGE_GIT_SMART_V2_ADVERTISE", backend);
+    }
+}
+
+fn golden_path(scenario: &str, backend: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(scenario)
+        .join(format!("{backend}.trace"))
+}
+
+fn read_golden(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|e| {
+        panic!(
+            "{}: {e}; record it with FORGE_GIT_TRACE=record cargo test -p git-http --test protocol_traces",
+            path.display()
+        )
+    })
+}
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Sends each recorded request to the router and compares the responses.
+async fn replay(scenario: &Scenario, golden: &Path) {
+    let recorded = parse_transcript(&read_golden(golden)).unwrap();
+    let fixture = Fixture::new().await;
+    if scenario
+        .steps
+        .iter()
+        .any(|step| matches!(step, Step::Advance))
+    {
+        fixture.advance().await;
+    }
+    let app = router(fixture.state(), None);
+
+    let mut expected = Vec::new();
+    let mut actual = Vec::new();
+    for exchange in recorded {
+        let mut request = Request::builder()
+            .method(exchange.method.as_str())
+            .uri(exchange.target.as_str())
+            .header("Git-Protocol", "version=2");
+        if exchange.method == "POST" {
+            request = request.header(
+                header::CONTENT_TYPE,
+                "application/x-git-upload-pack-request",
+            );
+        }
+        let request = request.body(Body::from(exchange.request.clone())).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status().as_u16();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        actual.extend(render_exchange(&Exchange {
+            method: exchange.method,
+            target: exchange.target,
+            request: exchange.request,
+            status,
+            response: body.to_vec(),
+        }));
+        expected.extend(exchange.rendered);
+    }
+    assert_same(golden, &expected, &actual);
+}
+
+/// Runs the scenario's git clients against a loopback server and returns the transcript.
+async fn live(scenario: &Scenario, backend: &str) -> String {
+    let fixture = Fixture::new().await;
+    let log = Arc::new(Mutex::new(None::<Vec<Exchange>>));
+    let app = router(fixture.state(), Some(log.clone()));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/{REPO}.git", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let client = TempDir::new().unwrap();
+    let dest = client.path().join("clone");
+    let mut transcript = format!(
+        "# scenario: {}\n# backend: {backend}\n# regenerate: FORGE_GIT_TRACE=record cargo test -p git-http --test protocol_traces\n",
+        scenario.name
+    );
+    for step in scenario.steps {
+        let (Step::Traced(template) | Step::Untraced(template)) = step else {
+            fixture.advance().await;
+            continue;
+        };
+        let traced = matches!(step, Step::Traced(_));
+        *log.lock().unwrap() = traced.then(Vec::new);
+        let mut args = vec!["-c".to_string(), "protocol.version=2".to_string()];
+        args.extend(template.iter().map(|arg| {
+            arg.replace("{url}", &url)
+                .replace("{dest}", &dest.to_string_lossy())
+        }));
+        let output = git_command(client.path(), &args).output().await.unwrap();
+        let exchanges = log.lock().unwrap().take().unwrap_or_default();
+        if !traced {
+            assert!(
+                output.status.success(),
+                "setup step git {template:?} failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            continue;
+        }
+        // A client that gives up is part of the trace, not a harness failure.
+        transcript.push_str(&render_transcript(&exchanges));
+        transcript.push_str(&format!(
+            "!! git {} exit={}\n",
+            template.join(" "),
+            output.status.code().unwrap_or(-1)
+        ));
+    }
+    server.abort();
+    transcript
+}
+
+fn assert_same(golden: &Path, expected: &[String], actual: &[String]) {
+    if expected == actual {
+        return;
+    }
+    let first = expected
+        .iter()
+        .zip(actual)
+        .position(|(e, a)| e != a)
+        .unwrap_or(expected.len().min(actual.len()));
+    let from = first.saturating_sub(3);
+    let mut report = String::new();
+    for (label, side) in [("expected", expected), ("actual", actual)] {
+        report.push_str(&format!("--- {label}\n"));
+        for (i, line) in side.iter().enumerate().skip(from).take(10) {
+            let marker = if i == first { '>' } else { ' ' };
+            report.push_str(&format!("{marker} {line}\n"));
+        }
+    }
+    panic!(
+        "protocol trace differs from {} at line {}\n{report}\
+         If the change is intended, rerun with FORGE_GIT_TRACE=record and review the diff.",
+        golden.display(),
+        first + 1
+    );
+}
+
+type Log = Arc<Mutex<Option<Vec<Exchange>>>>;
+
+fn router(state: TestState, log: Option<Log>) -> Router {
+    let app = Router::new()
+        .route(
+            "/{*path}",
+            get(dispatch_get::<TestState>).post(dispatch_post::<TestState>),
+        )
+        .with_state(state);
+    match log {
+        Some(log) => app.layer(middleware::from_fn_with_state(log, record)),
+        None => app,
+    }
+}
+
+/// Buffers both bodies so the exchange can be logged, then passes them through.
+async fn record(State(log): State<Log>, request: Request, next: Next) -> Response {
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body, usize::MAX).await.unwrap();
+    let target = parts
+        .uri
+        .path_and_query()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_default();
+    let method = parts.method.to_string();
+    // Clients gzip larger requests; the transcript shows what the server decodes.
+    let gzipped = parts
+        .headers
+        .get(header::CONTENT_ENCODING)
+        .is_some_and(|v| v == "gzip");
+    let request_bytes = if gzipped {
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(&body[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        decoded
+    } else {
+        body.to_vec()
+    };
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let (parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX).await.unwrap();
+    if let Some(exchanges) = log.lock().unwrap().as_mut() {
+        exchanges.push(Exchange {
+            method,
+            target,
+            request: request_bytes,
+            status: parts.status.as_u16(),
+            response: body.to_vec(),
+        });
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// An exported bare repository whose object ids never change: authors, dates and
+/// contents are fixed and user configuration is ignored.
+struct Fixture {
+    root: TempDir,
+    work: TempDir,
+}
+
+impl Fixture {
+    async fn new() -> Self {
+        let root = TempDir::new().unwrap();
+        let work = TempDir::new().unwrap();
+        let bare = root.path().join(format!("{REPO}.git"));
+        git(
+            root.path(),
+            &[
+                "init",
+                "--bare",
+                "--initial-branch=main",
+                bare.to_str().unwrap(),
+            ],
+        )
+        .await;
+        std::fs::write(bare.join("git-daemon-export-ok"), b"").unwrap();
+        // Deployments serving partial clones through `git upload-pack` must opt in.
+        git(&bare, &["config", "uploadpack.allowFilter", "true"]).await;
+
+        let fixture = Self { root, work };
+        git(fixture.work.path(), &["init", "--initial-branch=main"]).await;
+        git(
+            fixture.work.path(),
+            &["remote", "add", "origin", bare.to_str().unwrap()],
+        )
+        .await;
+        for (date, message, line) in [
+            ("2023-01-01T00:00:00Z", "init", "hello"),
+            ("2023-06-01T00:00:00Z", "mid", "a"),
+            ("2024-01-01T00:00:00Z", "latest", "b"),
+        ] {
+            fixture.commit("README.md", line, message, date).await;
+        }
+        fixture
+            .git_at("2024-01-01T00:00:00Z", &["tag", "-a", "v1", "-m", "v1"])
+            .await;
+        fixture
+            .git_at("2024-01-01T00:00:00Z", &["push", "origin", "main", "v1"])
+            .await;
+        fixture
+    }
+
+    async fn advance(&self) {
+        self.commit("CHANGELOG", "bump", "chore: bump", "2024-02-01T00:00:00Z")
+            .await;
+        self.git_at("2024-02-01T00:00:00Z", &["push", "origin", "main"])
+            .await;
+    }
+
+    async fn commit(&self, file: &str, line: &str, message: &str, date: &str) {
+        let path = self.work.path().join(file);
+        let mut contents = std::fs::read_to_string(&path).unwrap_or_default();
+        contents.push_str(line);
+        contents.push('\n');
+        std::fs::write(&path, contents).unwrap();
+        self.git_at(date, &["add", file]).await;
+        self.git_at(date, &["commit", "-m", message]).await;
+    }
+
+    async fn git_at(&self, date: &str, args: &[&str]) {
+        let output = git_command(self.work.path(), args)
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_DATE", date)
+            .output()
+            .await
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    fn state(&self) -> TestState {
+        TestState {
+            storage: TestStorage {
+                root: self.root.path().to_path_buf(),
+            },
+            scheduler: Arc::default(),
+        }
+    }
+}
+
+async fn git<S: AsRef<std::ffi::OsStr>>(dir: &Path, args: &[S]) {
+    let output = git_command(dir, args).output().await.unwrap();
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args.iter()
+            .map(|a| a.as_ref().to_owned())
+            .collect::<Vec<_>>(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn git_command<S: AsRef<std::ffi::OsStr>>(dir: &Path, args: &[S]) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("git");
+    command
+        .current_dir(dir)
+        .args(args)
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_AUTHOR_NAME", "Forge Fixture")
+        .env("GIT_AUTHOR_EMAIL", "fixture@forge.test")
+        .env("GIT_COMMITTER_NAME", "Forge Fixture")
+        .env("GIT_COMMITTER_EMAIL", "fixture@forge.test");
+    command
+}
+
+#[derive(Clone)]
+struct TestStorage {
+    root: PathBuf,
+}
+
+impl RepositoryProvider for TestStorage {
+    fn ensure_local_repository(&self, segments: &[String]) -> anyhow::Result<PathBuf> {
+        let path = segments
+            .iter()
+            .fold(self.root.clone(), |path, s| path.join(s));
+        if path.is_dir() {
+            Ok(path)
+        } else {
+            Err(anyhow::anyhow!("repository directory not found"))
+        }
+    }
+}
+
+#[derive(Clone)]
+struct TestState {
+    storage: TestStorage,
+    scheduler: Arc<GitScheduler>,
+}
+
+impl GitHttpState for TestState {
+    type Storage = TestStorage;
+
+    fn storage(&self) -> &Self::Storage {
+        &self.storage
+    }
+
+    fn git_scheduler(&self) -> &GitScheduler {
+        &self.scheduler
+    }
+
+    fn git_max_body(&self) -> usize {
+        64 * 1024 * 1024
+    }
+
+    fn git_timeout_ms(&self) -> u64 {
+        60_000
+    }
+
+    fn validate_slug(&self, slug: &str) -> anyhow::Result<()> {
+        if !slug.is_empty()
+            && slug
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("invalid slug"))
+        }
+    }
+}
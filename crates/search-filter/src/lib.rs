@@ -0,0 +1,586 @@
+//! Filter query language shared by the server and extensions.
+//!
+//! Saved searches are validated and normalised by the server but executed by whichever
+//! component owns the data (the issues extension, for example). Both sides depend on
+//! this crate so a query means the same thing everywhere.
+//!
+//! Syntax, in the style of GitHub's issue search:
+//!
+//! - `key:value` qualifiers, e.g. `is:open`, `status:open,in_progress`
+//! - comparisons for numbers and dates: `number:>10`, `created:<=2024-06-01`
+//! - `-` negates a term: `-is:closed`, `-"wont fix"`
+//! - `sort:created-desc` picks the ordering
+//! - everything else is free text, quoted when it contains spaces
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError(String);
+
+impl FilterError {
+    fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Comparison {
+    fn prefix(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+        }
+    }
+
+    fn sql(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    Qualifier {
+        negated: bool,
+        key: String,
+        comparison: Comparison,
+        value: String,
+    },
+    Text {
+        negated: bool,
+        text: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sort {
+    pub key: String,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub terms: Vec<Term>,
+    pub sort: Option<Sort>,
+}
+
+/// Parses a filter query. Qualifier names are not checked here; see [`Filter::validate`].
+pub fn parse(input: &str) -> Result<Filter, FilterError> {
+    let mut filter = Filter::default();
+    for token in tokenize(input)? {
+        let (negated, body) = match token.strip_prefix('-') {
+            Some(rest) if !rest.is_empty() => (true, rest.to_string()),
+            _ => (false, token),
+        };
+
+        let qualifier = body.split_once(':').filter(|(key, value)| {
+            !key.is_empty()
+                && !value.is_empty()
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        let Some((key, value)) = qualifier else {
+            filter.terms.push(Term::Text {
+                negated,
+                text: unquote(&body),
+            });
+            continue;
+        };
+
+        let key = key.to_ascii_lowercase();
+        if key == "sort" {
+            if negated {
+                return Err(FilterError::new("sort cannot be negated"));
+            }
+            let (sort_key, descending) = match value.rsplit_once('-') {
+                Some((k, "desc")) => (k, true),
+                Some((k, "asc")) => (k, false),
+                _ => (value, true),
+            };
+            filter.sort = Some(Sort {
+                key: sort_key.to_ascii_lowercase(),
+                descending,
+            });
+            continue;
+        }
+
+        let (comparison, value) = split_comparison(value);
+        let value = unquote(value);
+        if value.is_empty() {
+            return Err(FilterError::new(format!("qualifier `{key}` needs a value")));
+        }
+        filter.terms.push(Term::Qualifier {
+            negated,
+            key,
+            comparison,
+            value,
+        });
+    }
+    Ok(filter)
+}
+
+fn tokenize(input: &str) -> Result<Vec<String>, FilterError> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in input.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(FilterError::new("unterminated quote"));
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+fn unquote(value: &str) -> String {
+    value.replace('"', "")
+}
+
+fn split_comparison(value: &str) -> (Comparison, &str) {
+    for (prefix, comparison) in [
+        (">=", Comparison::Ge),
+        ("<=", Comparison::Le),
+        (">", Comparison::Gt),
+        ("<", Comparison::Lt),
+    ] {
+        if let Some(rest) = value.strip_prefix(prefix) {
+            return (comparison, rest);
+        }
+    }
+    (Comparison::Eq, value)
+}
+
+fn quote_if_needed(value: &str) -> String {
+    if value.chars().any(char::is_whitespace) {
+        format!("\"{value}\"")
+    } else {
+        value.to_string()
+    }
+}
+
+/// Canonical form: lowercase qualifier names, quotes only where needed, sort last.
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::with_capacity(self.terms.len() + 1);
+        for term in &self.terms {
+            parts.push(match term {
+                Term::Qualifier {
+                    negated,
+                    key,
+                    comparison,
+                    value,
+                } => format!(
+                    "{}{}:{}{}",
+                    if *negated { "-" } else { "" },
+                    key,
+                    comparison.prefix(),
+                    quote_if_needed(value)
+                ),
+                Term::Text { negated, text } => {
+                    format!(
+                        "{}{}",
+                        if *negated { "-" } else { "" },
+                        quote_if_needed(text)
+                    )
+                }
+            });
+        }
+        if let Some(sort) = &self.sort {
+            parts.push(format!(
+                "sort:{}-{}",
+                sort.key,
+                if sort.descending { "desc" } else { "asc" }
+            ));
+        }
+        f.write_str(&parts.join(" "))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    /// One of a fixed set of (uppercase) values; comma-separated lists match any.
+    Enum(&'static [&'static str]),
+    Integer,
+    /// `YYYY-MM-DD`, compared against `date(column)`.
+    Date,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Field {
+    pub qualifier: &'static str,
+    pub column: &'static str,
+    pub kind: FieldKind,
+}
+
+/// Describes how a filter maps onto one table.
+#[derive(Debug, Clone, Copy)]
+pub struct Schema {
+    pub fields: &'static [Field],
+    /// Columns searched by free text with `LIKE`.
+    pub text_columns: &'static [&'static str],
+    /// `(sort key, column)` pairs; the first one is the default, descending.
+    pub sort_keys: &'static [(&'static str, &'static str)],
+}
+
+impl Schema {
+    fn field(&self, qualifier: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.qualifier == qualifier)
+    }
+
+    fn sort_column(&self, key: &str) -> Option<&'static str> {
+        self.sort_keys
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, c)| *c)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SqlValue {
+    Text(String),
+    Integer(i64),
+}
+
+/// SQL fragments with positional `?` parameters, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqlFilter {
+    /// `AND`-joined conditions, or `1 = 1` for an empty filter.
+    pub where_clause: String,
+    pub params: Vec<SqlValue>,
+    pub order_by: String,
+}
+
+impl Filter {
+    /// Rejects unknown qualifiers, sort keys and malformed values for `schema`.
+    pub fn validate(&self, schema: &Schema) -> Result<(), FilterError> {
+        self.to_sql(schema).map(|_| ())
+    }
+
+    pub fn to_sql(&self, schema: &Schema) -> Result<SqlFilter, FilterError> {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+
+        for term in &self.terms {
+            let (negated, condition) = match term {
+                Term::Text { negated, text } => {
+                    if schema.text_columns.is_empty() {
+                        return Err(FilterError::new("free text search is not supported here"));
+                    }
+                    let pattern = format!("%{}%", escape_like(text));
+                    let ors: Vec<String> = schema
+                        .text_columns
+                        .iter()
+                        .map(|c| {
+                            params.push(SqlValue::Text(pattern.clone()));
+                            format!("{c} LIKE ? ESCAPE '\\'")
+                        })
+                        .collect();
+                    (*negated, format!("({})", ors.join(" OR ")))
+                }
+                Term::Qualifier {
+                    negated,
+                    key,
+                    comparison,
+                    value,
+                } => {
+                    let field = schema
+                        .field(key)
+                        .ok_or_else(|| FilterError::new(format!("unknown qualifier `{key}`")))?;
+                    (
+                        *negated,
+                        field_condition(field, *comparison, value, &mut params)?,
+                    )
+                }
+            };
+            conditions.push(if negated {
+                format!("NOT {condition}")
+            } else {
+                condition
+            });
+        }
+
+        let (sort_key, descending) = match &self.sort {
+            Some(sort) => (sort.key.as_str(), sort.descending),
+            None => (
+                schema
+                    .sort_keys
+                    .first()
+                    .map(|(k, _)| *k)
+                    .unwrap_or_default(),
+                true,
+            ),
+        };
+        let order_by = match schema.sort_column(sort_key) {
+            Some(column) => format!("{column} {}", if descending { "DESC" } else { "ASC" }),
+            None if self.sort.is_none() => String::new(),
+            None => return Err(FilterError::new(format!("unknown sort key `{sort_key}`"))),
+        };
+
+        Ok(SqlFilter {
+            where_clause: if conditions.is_empty() {
+                "1 = 1".to_string()
+            } else {
+                conditions.join(" AND ")
+            },
+            params,
+            order_by,
+        })
+    }
+}
+
+fn field_condition(
+    field: &Field,
+    comparison: Comparison,
+    value: &str,
+    params: &mut Vec<SqlValue>,
+) -> Result<String, FilterError> {
+    let column = field.column;
+    match field.kind {
+        FieldKind::Enum(allowed) => {
+            if comparison != Comparison::Eq {
+                return Err(FilterError::new(format!(
+                    "`{}` does not support comparisons",
+                    field.qualifier
+                )));
+            }
+            let mut values = Vec::new();
+            for raw in value.split(',').filter(|v| !v.is_empty()) {
+                let upper = raw.to_ascii_uppercase();
+                if !allowed.contains(&upper.as_str()) {
+                    return Err(FilterError::new(format!(
+                        "`{}` must be one of {}",
+                        field.qualifier,
+                        allowed.join(", ").to_ascii_lowercase()
+                    )));
+                }
+                values.push(upper);
+            }
+            let placeholders = vec!["?"; values.len()].join(", ");
+            params.extend(values.into_iter().map(SqlValue::Text));
+            Ok(format!("{column} IN ({placeholders})"))
+        }
+        FieldKind::Integer => {
+            let number: i64 = value
+                .parse()
+                .map_err(|_| FilterError::new(format!("`{}` expects a number", field.qualifier)))?;
+            params.push(SqlValue::Integer(number));
+            Ok(format!("{column} {} ?", comparison.sql()))
+        }
+        FieldKind::Date => {
+            if !is_iso_date(value) {
+                return Err(FilterError::new(format!(
+                    "`{}` expects a YYYY-MM-DD date",
+                    field.qualifier
+                )));
+            }
+            params.push(SqlValue::Text(value.to_string()));
+            Ok(format!("date({column}) {} ?", comparison.sql()))
+        }
+    }
+}
+
+fn is_iso_date(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    bytes.len() == 10
+        && bytes[4] == b'-'
+        && bytes[7] == b'-'
+        && bytes
+            .iter()
+            .enumerate()
+            .all(|(i, b)| i == 4 || i == 7 || b.is_ascii_digit())
+}
+
+fn escape_like(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Filters over the issues extension's `issues` table.
+pub mod issues {
+    use super::{Field, FieldKind, Schema};
+
+    pub const STATUSES: &[&str] = &["OPEN", "CLOSED", "IN_PROGRESS"];
+
+    pub const SCHEMA: Schema = Schema {
+        fields: &[
+            Field {
+                qualifier: "is",
+                column: "status",
+                kind: FieldKind::Enum(STATUSES),
+            },
+            Field {
+                qualifier: "status",
+                column: "status",
+                kind: FieldKind::Enum(STATUSES),
+            },
+            Field {
+                qualifier: "number",
+                column: "number",
+                kind: FieldKind::Integer,
+            },
+            Field {
+                qualifier: "created",
+                column: "created_at",
+                kind: FieldKind::Date,
+            },
+        ],
+        text_columns: &["title", "description"],
+        sort_keys: &[
+            ("number", "number"),
+            ("created", "created_at"),
+            ("title", "title"),
+        ],
+    };
+}
+
+/// Filters for pull requests. Nothing stores pull requests yet; saved searches in this
+/// scope are validated so they keep working once a provider exists.
+pub mod pull_requests {
+    use super::{Field, FieldKind, Schema};
+
+    pub const STATUSES: &[&str] = &["OPEN", "CLOSED", "MERGED"];
+
+    pub const SCHEMA: Schema = Schema {
+        fields: &[
+            Field {
+                qualifier: "is",
+                column: "status",
+                kind: FieldKind::Enum(STATUSES),
+            },
+            Field {
+                qualifier: "status",
+                column: "status",
+                kind: FieldKind::Enum(STATUSES),
+            },
+            Field {
+                qualifier: "number",
+                column: "number",
+                kind: FieldKind::Integer,
+            },
+            Field {
+                qualifier: "created",
+                column: "created_at",
+                kind: FieldKind::Date,
+            },
+        ],
+        text_columns: &["title", "description"],
+        sort_keys: &[
+            ("number", "number"),
+            ("created", "created_at"),
+            ("title", "title"),
+        ],
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_normalises() {
+        let filter =
+            parse(r#"IS:open  -label:"wont fix" "crash on start" number:>=10 sort:created-asc"#)
+                .unwrap();
+        assert_eq!(filter.terms.len(), 4);
+        assert_eq!(
+            filter.to_string(),
+            r#"is:open -label:"wont fix" "crash on start" number:>=10 sort:created-asc"#
+        );
+        assert_eq!(parse(&filter.to_string()).unwrap(), filter);
+        assert!(parse(r#"title:"unterminated"#).is_err());
+    }
+
+    #[test]
+    fn builds_issue_sql() {
+        let sql = parse("is:open,in_progress -crash number:>3 created:<2024-06-01")
+            .unwrap()
+            .to_sql(&issues::SCHEMA)
+            .unwrap();
+        assert_eq!(
+            sql.where_clause,
+            "status IN (?, ?) AND NOT (title LIKE ? ESCAPE '\\' OR description LIKE ? ESCAPE '\\') \
+             AND number > ? AND date(created_at) < ?"
+        );
+        assert_eq!(sql.params[0], SqlValue::Text("OPEN".into()));
+        assert_eq!(sql.params[2], SqlValue::Text("%crash%".into()));
+        assert_eq!(sql.params[4], SqlValue::Integer(3));
+        assert_eq!(sql.order_by, "number DESC");
+    }
+
+    #[test]
+    fn rejects_unknown_qualifiers_and_values() {
+        assert!(
+            parse("assignee:me")
+                .unwrap()
+                .validate(&issues::SCHEMA)
+                .is_err()
+        );
+        assert!(
+            parse("is:merged")
+                .unwrap()
+                .validate(&issues::SCHEMA)
+                .is_err()
+        );
+        assert!(
+            parse("is:merged")
+                .unwrap()
+                .validate(&pull_requests::SCHEMA)
+                .is_ok()
+        );
+        assert!(
+            parse("created:yesterday")
+                .unwrap()
+                .validate(&issues::SCHEMA)
+                .is_err()
+        );
+        assert!(
+            parse("sort:votes")
+                .unwrap()
+                .validate(&issues::SCHEMA)
+                .is_err()
+        );
+    }
+}
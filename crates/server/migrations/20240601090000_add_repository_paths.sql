@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS repository_paths (
+    repository_id TEXT PRIMARY KEY REFERENCES repositories(id) ON DELETE CASCADE,
+    layout TEXT NOT NULL,
+    previous_path TEXT NOT NULL,
+    current_path TEXT NOT NULL,
+    moved_at INTEGER NOT NULL
+);
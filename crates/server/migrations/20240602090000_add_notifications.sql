@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS notifications (
+    id TEXT PRIMARY KEY,
+    recipient_did TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    title TEXT NOT NULL,
+    url TEXT,
+    created_at INTEGER NOT NULL,
+    read_at INTEGER,
+    digested_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_recipient_unread
+    ON notifications(recipient_did, created_at)
+    WHERE read_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_did TEXT PRIMARY KEY,
+    digest_frequency TEXT NOT NULL DEFAULT 'immediate',
+    last_digest_at INTEGER
+);
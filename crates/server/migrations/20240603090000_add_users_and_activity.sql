@@ -0,0 +1,35 @@
+CREATE TABLE IF NOT EXISTS users (
+    did TEXT PRIMARY KEY,
+    handle TEXT NOT NULL,
+    display_name TEXT,
+    avatar TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_handle ON users(handle COLLATE NOCASE);
+
+CREATE TABLE IF NOT EXISTS repository_owners (
+    repository_id TEXT PRIMARY KEY REFERENCES repositories(id) ON DELETE CASCADE,
+    owner_did TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_repository_owners_owner ON repository_owners(owner_did);
+
+CREATE TABLE IF NOT EXISTS repository_stars (
+    user_did TEXT NOT NULL,
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    starred_at INTEGER NOT NULL,
+    PRIMARY KEY (user_did, repository_id)
+);
+
+CREATE TABLE IF NOT EXISTS activity_events (
+    id TEXT PRIMARY KEY,
+    actor_did TEXT NOT NULL,
+    repository_id TEXT REFERENCES repositories(id) ON DELETE SET NULL,
+    kind TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_activity_events_actor ON activity_events(actor_did, created_at);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS repository_policies (
+    repository_id TEXT PRIMARY KEY REFERENCES repositories(id) ON DELETE CASCADE,
+    rules TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
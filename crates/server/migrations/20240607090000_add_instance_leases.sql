@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS instance_leases (
+    name TEXT PRIMARY KEY,
+    holder TEXT NOT NULL,
+    acquired_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL
+);
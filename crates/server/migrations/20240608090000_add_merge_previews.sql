@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS merge_previews (
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    base_oid TEXT NOT NULL,
+    head_oid TEXT NOT NULL,
+    mergeable INTEGER NOT NULL,
+    conflicts TEXT NOT NULL,
+    computed_at INTEGER NOT NULL,
+    PRIMARY KEY (repository_id, base_oid, head_oid)
+);
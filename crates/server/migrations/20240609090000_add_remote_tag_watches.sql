@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS remote_tag_watches (
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    subscriber_did TEXT NOT NULL,
+    semver_range TEXT,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (repository_id, subscriber_did)
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS mirror_webhooks (
+    repository_id TEXT PRIMARY KEY REFERENCES repositories(id) ON DELETE CASCADE,
+    secret TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    last_delivery_at INTEGER
+);
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS compliance_archive (
+    id TEXT PRIMARY KEY,
+    subject_kind TEXT NOT NULL,
+    subject_id TEXT NOT NULL,
+    source TEXT NOT NULL,
+    actor_did TEXT,
+    summary TEXT NOT NULL,
+    deleted_at INTEGER NOT NULL,
+    expires_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_compliance_archive_deleted ON compliance_archive(deleted_at);
+CREATE INDEX IF NOT EXISTS idx_compliance_archive_expires ON compliance_archive(expires_at)
+    WHERE expires_at IS NOT NULL;
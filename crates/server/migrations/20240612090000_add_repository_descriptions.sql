@@ -0,0 +1,8 @@
+-- The default description is stored with an empty language.
+CREATE TABLE IF NOT EXISTS repository_descriptions (
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    language TEXT NOT NULL DEFAULT '',
+    description TEXT NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (repository_id, language)
+);
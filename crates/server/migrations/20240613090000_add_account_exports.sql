@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS account_exports (
+    id TEXT PRIMARY KEY,
+    user_did TEXT NOT NULL,
+    status TEXT NOT NULL,
+    archive BLOB,
+    size_bytes INTEGER,
+    error TEXT,
+    created_at INTEGER NOT NULL,
+    completed_at INTEGER,
+    expires_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_account_exports_user ON account_exports(user_did, created_at);
+CREATE INDEX IF NOT EXISTS idx_account_exports_expires ON account_exports(expires_at)
+    WHERE expires_at IS NOT NULL;
+
+-- Keys generated once per instance, such as the key that signs export download links.
+CREATE TABLE IF NOT EXISTS instance_secrets (
+    name TEXT PRIMARY KEY,
+    value BLOB NOT NULL,
+    created_at INTEGER NOT NULL
+);
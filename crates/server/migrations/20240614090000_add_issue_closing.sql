@@ -0,0 +1,8 @@
+-- Per-repository settings for closing issues from pushed commit messages. Repositories
+-- without a row close issues on pushes to their default branch.
+CREATE TABLE IF NOT EXISTS repository_issue_closing (
+    repository_id TEXT PRIMARY KEY REFERENCES repositories(id) ON DELETE CASCADE,
+    enabled INTEGER NOT NULL,
+    branches TEXT NOT NULL DEFAULT '[]',
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,22 @@
+-- Instance-wide banners managed by administrators. A missing start or end leaves that
+-- side of the window open.
+CREATE TABLE IF NOT EXISTS announcements (
+    id TEXT PRIMARY KEY,
+    message TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    starts_at INTEGER,
+    ends_at INTEGER,
+    created_by TEXT,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_announcements_window ON announcements (starts_at, ends_at);
+
+-- Maintenance mode is a single row shared by every instance using the database.
+CREATE TABLE IF NOT EXISTS maintenance_mode (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    enabled INTEGER NOT NULL,
+    message TEXT,
+    updated_by TEXT,
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,25 @@
+-- Resolved DID documents, with the profile links they list and whether each link
+-- confirmed the DID back. Rows older than `federation.did_cache_ttl_secs` are refreshed.
+CREATE TABLE IF NOT EXISTS did_documents (
+    did TEXT PRIMARY KEY,
+    document TEXT NOT NULL,
+    links TEXT NOT NULL DEFAULT '[]',
+    fetched_at INTEGER NOT NULL
+);
+
+-- Issues on other Forge instances that mention an issue here, reported through signed
+-- requests from those instances.
+CREATE TABLE IF NOT EXISTS federated_issue_references (
+    id TEXT PRIMARY KEY,
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    issue_number INTEGER NOT NULL,
+    instance_did TEXT NOT NULL,
+    source_url TEXT NOT NULL,
+    title TEXT,
+    actor_did TEXT,
+    created_at INTEGER NOT NULL,
+    UNIQUE (repository_id, issue_number, source_url)
+);
+
+CREATE INDEX IF NOT EXISTS idx_federated_issue_references_issue
+    ON federated_issue_references (repository_id, issue_number);
@@ -0,0 +1,26 @@
+-- Encrypted secrets that repository owners hand to extensions. `extensions` is a JSON
+-- array of the extension names allowed to read the value.
+CREATE TABLE IF NOT EXISTS repository_secrets (
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    nonce BLOB NOT NULL,
+    ciphertext BLOB NOT NULL,
+    extensions TEXT NOT NULL DEFAULT '[]',
+    updated_by TEXT,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (repository_id, name)
+);
+
+-- Every change and every read attempt. Kept after the secret or repository is gone.
+CREATE TABLE IF NOT EXISTS repository_secret_audit (
+    id TEXT PRIMARY KEY,
+    repository_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    action TEXT NOT NULL,
+    extension TEXT,
+    actor_did TEXT,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_repository_secret_audit_repository
+    ON repository_secret_audit (repository_id, created_at);
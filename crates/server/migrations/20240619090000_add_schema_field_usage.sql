@@ -0,0 +1,11 @@
+-- How often each schema field was selected, per client, since it was first seen.
+CREATE TABLE IF NOT EXISTS schema_field_usage (
+    coordinate TEXT NOT NULL,
+    client TEXT NOT NULL,
+    count INTEGER NOT NULL DEFAULT 0,
+    first_used_at INTEGER NOT NULL,
+    last_used_at INTEGER NOT NULL,
+    PRIMARY KEY (coordinate, client)
+);
+
+CREATE INDEX IF NOT EXISTS idx_schema_field_usage_last_used ON schema_field_usage(last_used_at);
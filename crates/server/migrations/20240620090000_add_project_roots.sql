@@ -0,0 +1,7 @@
+-- Directories of a repository the owner marked as projects, in addition to the ones
+-- detected from manifest files. Repositories without a row only use detection.
+CREATE TABLE IF NOT EXISTS repository_project_roots (
+    repository_id TEXT PRIMARY KEY REFERENCES repositories(id) ON DELETE CASCADE,
+    roots TEXT NOT NULL DEFAULT '[]',
+    updated_at INTEGER NOT NULL
+);
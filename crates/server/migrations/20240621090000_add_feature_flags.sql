@@ -0,0 +1,18 @@
+-- Feature flags toggled by administrators at runtime. An enabled flag applies to
+-- `rollout_percent` percent of signed-in users, picked by a hash of their DID.
+CREATE TABLE IF NOT EXISTS feature_flags (
+    key TEXT PRIMARY KEY,
+    description TEXT,
+    enabled INTEGER NOT NULL,
+    rollout_percent INTEGER NOT NULL CHECK (rollout_percent BETWEEN 0 AND 100),
+    updated_by TEXT,
+    updated_at INTEGER NOT NULL
+);
+
+-- Forces a flag on or off inside one repository, whatever the rollout says.
+CREATE TABLE IF NOT EXISTS feature_flag_overrides (
+    flag_key TEXT NOT NULL REFERENCES feature_flags(key) ON DELETE CASCADE,
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    enabled INTEGER NOT NULL,
+    PRIMARY KEY (flag_key, repository_id)
+);
@@ -0,0 +1,4 @@
+-- Connections page through groups and repositories by (slug, id), so each page is an
+-- index range scan instead of a sort of the whole table.
+CREATE INDEX IF NOT EXISTS idx_groups_slug_id ON groups (slug, id);
+CREATE INDEX IF NOT EXISTS idx_repositories_slug_id ON repositories (slug, id);
@@ -0,0 +1,24 @@
+-- The extension version that last initialised each extension database, so a new version
+-- can be detected and given the chance to migrate the data.
+CREATE TABLE IF NOT EXISTS extension_installs (
+    name TEXT PRIMARY KEY,
+    version TEXT NOT NULL,
+    installed_at INTEGER NOT NULL
+);
+
+-- One row per upgrade attempt. `RUNNING` rows left behind by a crash are rolled back on
+-- the next start.
+CREATE TABLE IF NOT EXISTS extension_upgrades (
+    id TEXT PRIMARY KEY,
+    extension TEXT NOT NULL,
+    from_version TEXT NOT NULL,
+    to_version TEXT NOT NULL,
+    status TEXT NOT NULL,
+    backup_path TEXT NOT NULL,
+    error TEXT,
+    started_at INTEGER NOT NULL,
+    finished_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_extension_upgrades_extension
+    ON extension_upgrades (extension, started_at DESC);
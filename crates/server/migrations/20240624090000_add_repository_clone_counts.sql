@@ -0,0 +1,8 @@
+-- Clones served over Smart HTTP, per repository and UTC day (days since the Unix
+-- epoch). Only counts are kept: nothing identifies who cloned.
+CREATE TABLE IF NOT EXISTS repository_clone_counts (
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    day INTEGER NOT NULL,
+    clones INTEGER NOT NULL,
+    PRIMARY KEY (repository_id, day)
+);
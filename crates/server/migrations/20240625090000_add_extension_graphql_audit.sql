@@ -0,0 +1,16 @@
+-- GraphQL queries extensions ran through `host-graphql`, including refused ones.
+-- `fields` is a JSON array of the schema coordinates the query selected.
+CREATE TABLE IF NOT EXISTS extension_graphql_audit (
+    id TEXT PRIMARY KEY,
+    extension TEXT NOT NULL,
+    actor_did TEXT,
+    repository_id TEXT,
+    query TEXT NOT NULL,
+    fields TEXT NOT NULL,
+    allowed INTEGER NOT NULL,
+    error TEXT,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_extension_graphql_audit_extension
+    ON extension_graphql_audit (extension, created_at DESC);
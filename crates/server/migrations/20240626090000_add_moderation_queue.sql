@@ -0,0 +1,21 @@
+-- Content an abuse check flagged, waiting for an administrator. `removal` is a JSON
+-- object with the GraphQL mutation (`query`, `variables`) that deletes extension content.
+CREATE TABLE IF NOT EXISTS moderation_queue (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    source TEXT NOT NULL,
+    subject_id TEXT,
+    actor_did TEXT,
+    repository_id TEXT,
+    title TEXT,
+    body TEXT,
+    reason TEXT,
+    removal TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    created_at INTEGER NOT NULL,
+    resolved_at INTEGER,
+    resolved_by TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_moderation_queue_status
+    ON moderation_queue (status, created_at DESC);
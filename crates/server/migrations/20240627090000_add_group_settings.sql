@@ -0,0 +1,24 @@
+-- Defaults a group hands to repositories created inside it. NULL columns fall through to
+-- the parent group. `labels` is a JSON array, `branch_protection` a RON push policy.
+CREATE TABLE IF NOT EXISTS group_settings (
+    group_id TEXT PRIMARY KEY REFERENCES groups(id) ON DELETE CASCADE,
+    labels TEXT,
+    branch_protection TEXT,
+    visibility TEXT,
+    updated_at INTEGER NOT NULL
+);
+
+-- Settings a repository holds itself. `*_from` names the group a value was copied from
+-- at creation and is cleared once the repository changes that value. The branch
+-- protection itself lives in `repository_policies`.
+CREATE TABLE IF NOT EXISTS repository_settings (
+    repository_id TEXT PRIMARY KEY REFERENCES repositories(id) ON DELETE CASCADE,
+    labels TEXT,
+    labels_from TEXT,
+    visibility TEXT,
+    visibility_from TEXT,
+    branch_protection_from TEXT,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_repository_settings_visibility ON repository_settings(visibility);
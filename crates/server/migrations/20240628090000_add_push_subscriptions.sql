@@ -0,0 +1,23 @@
+-- Browser Web Push subscriptions. `p256dh` and `auth` are the subscription's base64url
+-- keys; `failures` counts consecutive failed deliveries.
+CREATE TABLE IF NOT EXISTS push_subscriptions (
+    id TEXT PRIMARY KEY,
+    user_did TEXT NOT NULL,
+    endpoint TEXT NOT NULL UNIQUE,
+    p256dh TEXT NOT NULL,
+    auth TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER,
+    last_success_at INTEGER,
+    failures INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS idx_push_subscriptions_user ON push_subscriptions(user_did);
+
+-- When the push worker handled a notification. Existing notifications are not pushed.
+ALTER TABLE notifications ADD COLUMN pushed_at INTEGER;
+UPDATE notifications SET pushed_at = created_at;
+
+CREATE INDEX IF NOT EXISTS idx_notifications_unpushed
+    ON notifications(created_at)
+    WHERE pushed_at IS NULL;
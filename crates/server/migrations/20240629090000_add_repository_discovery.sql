@@ -0,0 +1,35 @@
+-- Sort keys for `findRepositories`. Zero means unknown: repositories created before this
+-- migration have no creation time, and pushed_at stays zero until the first push.
+ALTER TABLE repositories ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE repositories ADD COLUMN pushed_at INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE repositories ADD COLUMN star_count INTEGER NOT NULL DEFAULT 0;
+
+UPDATE repositories SET star_count = (
+    SELECT COUNT(*) FROM repository_stars WHERE repository_stars.repository_id = repositories.id
+);
+
+CREATE TRIGGER IF NOT EXISTS repository_stars_count_insert AFTER INSERT ON repository_stars
+BEGIN
+    UPDATE repositories SET star_count = star_count + 1 WHERE id = NEW.repository_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS repository_stars_count_delete AFTER DELETE ON repository_stars
+BEGIN
+    UPDATE repositories SET star_count = star_count - 1 WHERE id = OLD.repository_id;
+END;
+
+-- Each sort pages by (key DESC, id), so every page is an index range scan.
+CREATE INDEX IF NOT EXISTS idx_repositories_star_count_id ON repositories (star_count DESC, id);
+CREATE INDEX IF NOT EXISTS idx_repositories_pushed_at_id ON repositories (pushed_at DESC, id);
+CREATE INDEX IF NOT EXISTS idx_repositories_created_at_id ON repositories (created_at DESC, id);
+
+-- Trigrams of the words in a repository's name and descriptions, for typo-tolerant
+-- matching. Kept up to date by `repository::discovery::index_repository`.
+CREATE TABLE IF NOT EXISTS repository_search_trigrams (
+    trigram TEXT NOT NULL,
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    PRIMARY KEY (trigram, repository_id)
+) WITHOUT ROWID;
+
+CREATE INDEX IF NOT EXISTS idx_repository_search_trigrams_repository
+    ON repository_search_trigrams (repository_id);
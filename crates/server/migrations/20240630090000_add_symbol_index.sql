@@ -0,0 +1,34 @@
+-- Definitions on each repository's default branch, extracted by
+-- `repository::symbols::SymbolIndexJob`.
+CREATE TABLE IF NOT EXISTS repository_symbols (
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    file_path TEXT NOT NULL,
+    name TEXT NOT NULL COLLATE NOCASE,
+    kind TEXT NOT NULL,
+    language TEXT NOT NULL,
+    start_line INTEGER NOT NULL,
+    start_column INTEGER NOT NULL,
+    end_line INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_repository_symbols_name
+    ON repository_symbols (repository_id, name);
+CREATE INDEX IF NOT EXISTS idx_repository_symbols_file
+    ON repository_symbols (repository_id, file_path, start_line);
+
+-- The blob each indexed file had, so unchanged files are not parsed again.
+CREATE TABLE IF NOT EXISTS repository_symbol_files (
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    file_path TEXT NOT NULL,
+    blob_oid TEXT NOT NULL,
+    PRIMARY KEY (repository_id, file_path)
+) WITHOUT ROWID;
+
+-- commit_oid and indexed_at stay NULL until the first index is built.
+CREATE TABLE IF NOT EXISTS repository_symbol_index (
+    repository_id TEXT PRIMARY KEY REFERENCES repositories(id) ON DELETE CASCADE,
+    commit_oid TEXT,
+    branch TEXT,
+    indexed_at INTEGER,
+    requested_at INTEGER NOT NULL
+);
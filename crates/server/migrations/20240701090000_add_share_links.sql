@@ -0,0 +1,32 @@
+-- Links that grant read access to one repository until they expire or are revoked.
+-- Only a SHA-256 hash of each token is kept.
+CREATE TABLE IF NOT EXISTS repository_share_links (
+    id TEXT PRIMARY KEY,
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_by TEXT,
+    hint TEXT NOT NULL,
+    allow_clone INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    revoked_at INTEGER,
+    use_count INTEGER NOT NULL DEFAULT 0,
+    last_used_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_repository_share_links_repository
+    ON repository_share_links (repository_id, created_at);
+
+-- Every creation, use and revocation. Kept after the link or repository is gone.
+CREATE TABLE IF NOT EXISTS repository_share_link_audit (
+    id TEXT PRIMARY KEY,
+    link_id TEXT NOT NULL,
+    repository_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    actor_did TEXT,
+    client TEXT,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_repository_share_link_audit_repository
+    ON repository_share_link_audit (repository_id, created_at);
@@ -0,0 +1,42 @@
+-- Every published event, kept for the retention of its category (the first word of
+-- `action`). `id` is the event id. `repository_id` has no foreign key so entries outlive
+-- the repository they are about.
+CREATE TABLE IF NOT EXISTS audit_log (
+    id TEXT PRIMARY KEY,
+    action TEXT NOT NULL,
+    category TEXT NOT NULL,
+    source TEXT NOT NULL,
+    actor_did TEXT,
+    repository_id TEXT,
+    data TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_created ON audit_log (created_at, id);
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor ON audit_log (actor_did, created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_repository ON audit_log (repository_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_action ON audit_log (action, created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_expires ON audit_log (expires_at)
+    WHERE expires_at IS NOT NULL;
+
+-- CSV or JSON exports of the audit log requested by administrators. `filter` is the
+-- JSON of the filter the export was requested with.
+CREATE TABLE IF NOT EXISTS audit_log_exports (
+    id TEXT PRIMARY KEY,
+    requested_by TEXT NOT NULL,
+    format TEXT NOT NULL,
+    filter TEXT NOT NULL,
+    status TEXT NOT NULL,
+    archive BLOB,
+    row_count INTEGER,
+    size_bytes INTEGER,
+    error TEXT,
+    created_at INTEGER NOT NULL,
+    completed_at INTEGER,
+    expires_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_exports_created ON audit_log_exports (created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_exports_expires ON audit_log_exports (expires_at)
+    WHERE expires_at IS NOT NULL;
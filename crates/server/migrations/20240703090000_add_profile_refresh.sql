@@ -0,0 +1,6 @@
+-- When a user's DID document and profile were last resolved (or the last refresh gave
+-- up), and the refresh job queued for them, so the scheduler skips users in flight.
+ALTER TABLE users ADD COLUMN profile_checked_at INTEGER;
+ALTER TABLE users ADD COLUMN profile_job_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_users_profile_checked ON users (profile_checked_at);
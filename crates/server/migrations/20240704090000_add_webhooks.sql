@@ -0,0 +1,46 @@
+-- Outgoing webhooks. Repository hooks are removed with their repository; hooks without
+-- a repository receive the events of the whole instance. Secrets are encrypted with the
+-- repository secrets key.
+CREATE TABLE IF NOT EXISTS webhooks (
+    id TEXT PRIMARY KEY,
+    repository_id TEXT REFERENCES repositories(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    secret_nonce BLOB NOT NULL,
+    secret_ciphertext BLOB NOT NULL,
+    events TEXT NOT NULL DEFAULT '[]',
+    active INTEGER NOT NULL DEFAULT 1,
+    created_by TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhooks_repository ON webhooks (repository_id);
+
+-- One row per delivery, updated by every attempt. Headers and bodies are cleared once a
+-- delivery is old or falls out of its webhook's most recent ones; the summary stays.
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id TEXT PRIMARY KEY,
+    webhook_id TEXT NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    event TEXT NOT NULL,
+    event_id TEXT NOT NULL,
+    redelivery_of TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempt INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER,
+    request_url TEXT NOT NULL,
+    request_headers TEXT,
+    request_body TEXT,
+    response_status INTEGER,
+    response_headers TEXT,
+    response_body TEXT,
+    duration_ms INTEGER,
+    error TEXT,
+    truncated INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    completed_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook
+    ON webhook_deliveries (webhook_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due
+    ON webhook_deliveries (status, next_attempt_at);
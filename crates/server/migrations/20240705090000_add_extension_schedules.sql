@@ -0,0 +1,32 @@
+-- Schedules extensions register through host-scheduler. `next_run_at` includes the
+-- jitter of the next run.
+CREATE TABLE IF NOT EXISTS extension_schedules (
+    extension TEXT NOT NULL,
+    name TEXT NOT NULL,
+    cron TEXT NOT NULL,
+    jitter_secs INTEGER NOT NULL DEFAULT 0,
+    next_run_at INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (extension, name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_extension_schedules_due ON extension_schedules (next_run_at);
+
+-- The most recent runs of each scheduled task, including skipped occurrences. Runs are
+-- kept when their schedule is removed.
+CREATE TABLE IF NOT EXISTS extension_task_runs (
+    id TEXT PRIMARY KEY,
+    extension TEXT NOT NULL,
+    task TEXT NOT NULL,
+    scheduled_for INTEGER NOT NULL,
+    started_at INTEGER NOT NULL,
+    finished_at INTEGER,
+    status TEXT NOT NULL,
+    error TEXT,
+    duration_ms INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_extension_task_runs_task
+    ON extension_task_runs (extension, task, started_at DESC);
+CREATE INDEX IF NOT EXISTS idx_extension_task_runs_status ON extension_task_runs (status);
@@ -0,0 +1,24 @@
+-- Author and line counts of each non-merge commit on a repository's default branch,
+-- recorded by `repository::contributor_stats::ContributorStatsJob`.
+CREATE TABLE IF NOT EXISTS repository_commit_stats (
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    commit_oid TEXT NOT NULL,
+    author_name TEXT NOT NULL,
+    author_email TEXT NOT NULL,
+    authored_at INTEGER NOT NULL,
+    additions INTEGER NOT NULL,
+    deletions INTEGER NOT NULL,
+    PRIMARY KEY (repository_id, commit_oid)
+) WITHOUT ROWID;
+
+CREATE INDEX IF NOT EXISTS idx_repository_commit_stats_authored
+    ON repository_commit_stats (repository_id, authored_at);
+
+-- commit_oid and computed_at stay NULL until the statistics were first computed.
+CREATE TABLE IF NOT EXISTS repository_contributor_stats (
+    repository_id TEXT PRIMARY KEY REFERENCES repositories(id) ON DELETE CASCADE,
+    commit_oid TEXT,
+    branch TEXT,
+    computed_at INTEGER,
+    requested_at INTEGER NOT NULL
+);
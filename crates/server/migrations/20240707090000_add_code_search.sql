@@ -0,0 +1,29 @@
+-- Where each repository's code search index stands. commit_oid and indexed_at stay NULL
+-- until the first index is built.
+CREATE TABLE IF NOT EXISTS code_search_index (
+    repository_id TEXT PRIMARY KEY REFERENCES repositories(id) ON DELETE CASCADE,
+    commit_oid TEXT,
+    branch TEXT,
+    indexed_at INTEGER,
+    requested_at INTEGER NOT NULL
+);
+
+-- Files on the default branch, indexed by `search::code::CodeIndexJob`. Binary and
+-- oversized files are listed without content.
+CREATE TABLE IF NOT EXISTS code_search_files (
+    id INTEGER PRIMARY KEY,
+    repository_id TEXT NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+    file_path TEXT NOT NULL,
+    blob_oid TEXT NOT NULL,
+    language TEXT,
+    UNIQUE (repository_id, file_path)
+);
+
+-- File contents, keyed by code_search_files.id. Trigrams match any substring of three
+-- or more characters, ignoring case.
+CREATE VIRTUAL TABLE IF NOT EXISTS code_search_content USING fts5(content, tokenize = 'trigram');
+
+CREATE TRIGGER IF NOT EXISTS code_search_files_delete AFTER DELETE ON code_search_files
+BEGIN
+    DELETE FROM code_search_content WHERE rowid = OLD.id;
+END;
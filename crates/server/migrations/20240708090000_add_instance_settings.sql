@@ -0,0 +1,18 @@
+-- The instance's settings, in a single row that is created on first write. Until then
+-- the defaults in `instance::InstanceSettings` apply. setup_token_hash holds the hash of
+-- the setup token printed at startup and is cleared when setup is completed.
+CREATE TABLE IF NOT EXISTS instance_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    name TEXT NOT NULL,
+    description TEXT,
+    default_visibility TEXT NOT NULL,
+    setup_token_hash TEXT,
+    setup_completed_at INTEGER,
+    updated_at INTEGER
+);
+
+-- Administrators added with `completeSetup`, in addition to those in `auth.admin_dids`.
+CREATE TABLE IF NOT EXISTS instance_admins (
+    did TEXT PRIMARY KEY,
+    created_at INTEGER NOT NULL
+);
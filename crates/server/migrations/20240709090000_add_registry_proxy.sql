@@ -0,0 +1,23 @@
+-- Blobs and manifests held by the registry proxy (`extensions.registry_proxy`), keyed by
+-- digest. The bytes live under the proxy's cache directory; last_used_at decides what is
+-- evicted when the cache is over its quota.
+CREATE TABLE IF NOT EXISTS registry_proxy_blobs (
+    digest TEXT PRIMARY KEY,
+    media_type TEXT,
+    size INTEGER NOT NULL,
+    fetched_at INTEGER NOT NULL,
+    last_used_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_registry_proxy_blobs_last_used
+    ON registry_proxy_blobs(last_used_at);
+
+-- The manifest digest each upstream tag resolved to when it was last fetched.
+CREATE TABLE IF NOT EXISTS registry_proxy_tags (
+    upstream TEXT NOT NULL,
+    image TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    digest TEXT NOT NULL,
+    fetched_at INTEGER NOT NULL,
+    PRIMARY KEY (upstream, image, tag)
+);
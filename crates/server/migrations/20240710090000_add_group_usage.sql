@@ -0,0 +1,37 @@
+-- Usage of top-level groups per UTC day (see `metering`). Counted resources are summed
+-- over the day; `storage` holds the day's last sample. Rows are not tied to `groups` so
+-- usage of deleted groups can still be exported and billed.
+CREATE TABLE IF NOT EXISTS group_usage (
+    group_id TEXT NOT NULL,
+    day INTEGER NOT NULL,
+    resource TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    PRIMARY KEY (group_id, day, resource)
+);
+
+CREATE INDEX IF NOT EXISTS idx_group_usage_day ON group_usage (day);
+
+-- Quota warnings and overruns already announced, once per group, resource, level and
+-- month (`YYYY-MM`).
+CREATE TABLE IF NOT EXISTS group_quota_alerts (
+    group_id TEXT NOT NULL,
+    resource TEXT NOT NULL,
+    level TEXT NOT NULL,
+    period TEXT NOT NULL,
+    raised_at INTEGER NOT NULL,
+    PRIMARY KEY (group_id, resource, level, period)
+);
+
+-- One usage export per UTC day, for external billing systems.
+CREATE TABLE IF NOT EXISTS usage_exports (
+    id TEXT PRIMARY KEY,
+    day INTEGER NOT NULL UNIQUE,
+    format TEXT NOT NULL,
+    archive BLOB NOT NULL,
+    row_count INTEGER NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_usage_exports_expires ON usage_exports (expires_at);
@@ -0,0 +1,5 @@
+-- Progress that long jobs, such as search index rebuilds, record between batches, as a
+-- JSON object (see `jobs::JobProgress`). Administrators read it through `job(id:)`.
+ALTER TABLE jobs ADD COLUMN progress TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_jobs_created ON jobs(created_at, id);
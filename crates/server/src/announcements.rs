@@ -0,0 +1,366 @@
+//! Instance-wide announcements and maintenance mode.
+//!
+//! Administrators publish banners with a severity and an optional time window; clients
+//! poll `activeAnnouncements` to show the ones currently in effect. Maintenance mode is a
+//! single database row so every instance sharing the database switches together. While
+//! it is on, `api::maintenance` answers non-admin requests with a 503 and keeps git
+//! fetches working. Each instance caches the row for [`MAINTENANCE_CACHE_TTL`], so
+//! switching is visible everywhere within a few seconds.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail};
+use sqlx::{Row, SqlitePool};
+
+use crate::user::db::unix_now;
+
+/// Announcements are banners, not documents.
+pub const MAX_MESSAGE_LEN: usize = 1000;
+pub const MAINTENANCE_CACHE_TTL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_graphql(&self) -> &'static str {
+        match self {
+            Severity::Info => "INFO",
+            Severity::Warning => "WARNING",
+            Severity::Critical => "CRITICAL",
+        }
+    }
+
+    pub fn from_graphql(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "INFO" => Ok(Severity::Info),
+            "WARNING" => Ok(Severity::Warning),
+            "CRITICAL" => Ok(Severity::Critical),
+            other => Err(anyhow!("unknown announcement severity `{}`", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Announcement {
+    pub id: String,
+    pub message: String,
+    pub severity: Severity,
+    pub starts_at: Option<i64>,
+    pub ends_at: Option<i64>,
+    pub created_by: Option<String>,
+    pub created_at: i64,
+}
+
+pub struct NewAnnouncement {
+    pub message: String,
+    pub severity: Severity,
+    pub starts_at: Option<i64>,
+    pub ends_at: Option<i64>,
+}
+
+pub async fn create_announcement(
+    pool: &SqlitePool,
+    input: NewAnnouncement,
+    actor_did: Option<&str>,
+) -> anyhow::Result<Announcement> {
+    let message = input.message.trim().to_string();
+    if message.is_empty() {
+        bail!("announcement message cannot be empty");
+    }
+    if message.chars().count() > MAX_MESSAGE_LEN {
+        bail!(
+            "announcement message is longer than {} characters",
+            MAX_MESSAGE_LEN
+        );
+    }
+    if let (Some(starts_at), Some(ends_at)) = (input.starts_at, input.ends_at)
+        && ends_at <= starts_at
+    {
+        bail!("announcement must end after it starts");
+    }
+
+    let announcement = Announcement {
+        id: cuid2::create_id(),
+        message,
+        severity: input.severity,
+        starts_at: input.starts_at,
+        ends_at: input.ends_at,
+        created_by: actor_did.map(str::to_string),
+        created_at: unix_now(),
+    };
+    sqlx::query(
+        "INSERT INTO announcements (id, message, severity, starts_at, ends_at, created_by, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&announcement.id)
+    .bind(&announcement.message)
+    .bind(announcement.severity.as_graphql())
+    .bind(announcement.starts_at)
+    .bind(announcement.ends_at)
+    .bind(&announcement.created_by)
+    .bind(announcement.created_at)
+    .execute(pool)
+    .await?;
+    Ok(announcement)
+}
+
+/// Returns `false` when no announcement has that id.
+pub async fn delete_announcement(pool: &SqlitePool, id: &str) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM announcements WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Every announcement, including scheduled and expired ones, newest first.
+pub async fn list_announcements(pool: &SqlitePool) -> anyhow::Result<Vec<Announcement>> {
+    let rows = sqlx::query(
+        "SELECT id, message, severity, starts_at, ends_at, created_by, created_at \
+         FROM announcements ORDER BY created_at DESC, id",
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(announcement_from_row).collect()
+}
+
+/// Announcements whose window contains `now`, most severe first.
+pub async fn active_announcements(
+    pool: &SqlitePool,
+    now: i64,
+) -> anyhow::Result<Vec<Announcement>> {
+    let rows = sqlx::query(
+        "SELECT id, message, severity, starts_at, ends_at, created_by, created_at \
+         FROM announcements \
+         WHERE (starts_at IS NULL OR starts_at <= ?) AND (ends_at IS NULL OR ends_at > ?) \
+         ORDER BY created_at DESC, id",
+    )
+    .bind(now)
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+    let mut announcements = rows
+        .iter()
+        .map(announcement_from_row)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    announcements.sort_by_key(|announcement| std::cmp::Reverse(announcement.severity as u8));
+    Ok(announcements)
+}
+
+fn announcement_from_row(row: &sqlx::sqlite::SqliteRow) -> anyhow::Result<Announcement> {
+    Ok(Announcement {
+        id: row.try_get("id")?,
+        message: row.try_get("message")?,
+        severity: Severity::from_graphql(row.try_get("severity")?)?,
+        starts_at: row.try_get("starts_at")?,
+        ends_at: row.try_get("ends_at")?,
+        created_by: row.try_get("created_by")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MaintenanceMode {
+    pub enabled: bool,
+    /// Shown to users in the 503 payload
+    pub message: Option<String>,
+    pub updated_by: Option<String>,
+    pub updated_at: Option<i64>,
+}
+
+impl MaintenanceMode {
+    pub fn display_message(&self) -> &str {
+        self.message
+            .as_deref()
+            .unwrap_or("Forge is down for maintenance and will be back shortly.")
+    }
+}
+
+static MAINTENANCE: Mutex<Option<(Instant, MaintenanceMode)>> = Mutex::new(None);
+
+/// The maintenance state, at most [`MAINTENANCE_CACHE_TTL`] old.
+pub async fn maintenance_mode(pool: &SqlitePool) -> anyhow::Result<MaintenanceMode> {
+    {
+        let cached = MAINTENANCE.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((loaded_at, mode)) = cached.as_ref()
+            && loaded_at.elapsed() < MAINTENANCE_CACHE_TTL
+        {
+            return Ok(mode.clone());
+        }
+    }
+    let mode = load_maintenance_mode(pool).await?;
+    *MAINTENANCE.lock().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), mode.clone()));
+    Ok(mode)
+}
+
+pub async fn load_maintenance_mode(pool: &SqlitePool) -> anyhow::Result<MaintenanceMode> {
+    let row = sqlx::query(
+        "SELECT enabled, message, updated_by, updated_at FROM maintenance_mode WHERE id = 1",
+    )
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else {
+        return Ok(MaintenanceMode::default());
+    };
+    Ok(MaintenanceMode {
+        enabled: row.try_get::<i64, _>("enabled")? != 0,
+        message: row.try_get("message")?,
+        updated_by: row.try_get("updated_by")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+pub async fn set_maintenance_mode(
+    pool: &SqlitePool,
+    enabled: bool,
+    message: Option<String>,
+    actor_did: Option<&str>,
+) -> anyhow::Result<MaintenanceMode> {
+    let message = message
+        .map(|message| message.trim().to_string())
+        .filter(|message| !message.is_empty());
+    if message
+        .as_ref()
+        .is_some_and(|message| message.chars().count() > MAX_MESSAGE_LEN)
+    {
+        bail!(
+            "maintenance message is longer than {} characters",
+            MAX_MESSAGE_LEN
+        );
+    }
+    let mode = MaintenanceMode {
+        enabled,
+        message,
+        updated_by: actor_did.map(str::to_string),
+        updated_at: Some(unix_now()),
+    };
+    sqlx::query(
+        "INSERT INTO maintenance_mode (id, enabled, message, updated_by, updated_at) VALUES (1, ?, ?, ?, ?) \
+         ON CONFLICT(id) DO UPDATE SET enabled = excluded.enabled, message = excluded.message, \
+         updated_by = excluded.updated_by, updated_at = excluded.updated_at",
+    )
+    .bind(mode.enabled)
+    .bind(&mode.message)
+    .bind(&mode.updated_by)
+    .bind(mode.updated_at)
+    .execute(pool)
+    .await?;
+    // This instance switches immediately; others pick it up when their cache expires.
+    *MAINTENANCE.lock().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), mode.clone()));
+    Ok(mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    fn banner(
+        message: &str,
+        severity: Severity,
+        window: (Option<i64>, Option<i64>),
+    ) -> NewAnnouncement {
+        NewAnnouncement {
+            message: message.to_string(),
+            severity,
+            starts_at: window.0,
+            ends_at: window.1,
+        }
+    }
+
+    #[tokio::test]
+    async fn active_announcements_follow_their_window() {
+        let pool = create_test_pool().await.unwrap();
+        create_announcement(&pool, banner("always", Severity::Info, (None, None)), None)
+            .await
+            .unwrap();
+        create_announcement(
+            &pool,
+            banner(
+                "upgrade tonight",
+                Severity::Critical,
+                (Some(100), Some(200)),
+            ),
+            None,
+        )
+        .await
+        .unwrap();
+        create_announcement(
+            &pool,
+            banner("later", Severity::Warning, (Some(500), None)),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let messages = |announcements: Vec<Announcement>| {
+            announcements
+                .into_iter()
+                .map(|announcement| announcement.message)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            messages(active_announcements(&pool, 50).await.unwrap()),
+            ["always"]
+        );
+        assert_eq!(
+            messages(active_announcements(&pool, 150).await.unwrap()),
+            ["upgrade tonight", "always"]
+        );
+        assert_eq!(
+            messages(active_announcements(&pool, 200).await.unwrap()),
+            ["always"]
+        );
+        assert_eq!(
+            messages(active_announcements(&pool, 600).await.unwrap()),
+            ["later", "always"]
+        );
+        assert_eq!(list_announcements(&pool).await.unwrap().len(), 3);
+
+        assert!(
+            create_announcement(&pool, banner("  ", Severity::Info, (None, None)), None)
+                .await
+                .is_err()
+        );
+        assert!(
+            create_announcement(
+                &pool,
+                banner("backwards", Severity::Info, (Some(10), Some(5))),
+                None
+            )
+            .await
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn maintenance_mode_round_trips() {
+        let pool = create_test_pool().await.unwrap();
+        assert!(!load_maintenance_mode(&pool).await.unwrap().enabled);
+
+        set_maintenance_mode(
+            &pool,
+            true,
+            Some("Upgrading storage".into()),
+            Some("did:plc:admin"),
+        )
+        .await
+        .unwrap();
+        let mode = load_maintenance_mode(&pool).await.unwrap();
+        assert!(mode.enabled);
+        assert_eq!(mode.display_message(), "Upgrading storage");
+        assert_eq!(mode.updated_by.as_deref(), Some("did:plc:admin"));
+
+        set_maintenance_mode(&pool, false, Some(" ".into()), None)
+            .await
+            .unwrap();
+        let mode = load_maintenance_mode(&pool).await.unwrap();
+        assert!(!mode.enabled);
+        assert_eq!(mode.message, None);
+    }
+}
@@ -0,0 +1,323 @@
+//! Structured HTTP access logging.
+//!
+//! One record per request: method, matched route template (never the raw path, which
+//! can carry repository names or tokens), status, latency, response size and the
+//! viewer's DID. Client addresses go through [`IpPrivacy`] before they are recorded.
+//! Records are emitted on the `forge::access` tracing target, or appended as JSON
+//! lines to a dedicated file when `path` is configured.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::body::HttpBody;
+use axum::extract::{ConnectInfo, MatchedPath, Request, State};
+use axum::http::{Extensions, HeaderMap, header};
+use axum::middleware::Next;
+use axum::response::Response;
+use git_http::routes::{GitRoute, parse_git_route};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+use super::server::{AppState, parse_cookie};
+use crate::config::{AccessLogConfig, IpPrivacy};
+
+/// Records buffered for the file writer before new ones are dropped.
+const FILE_QUEUE_CAPACITY: usize = 4096;
+
+#[derive(Debug, Serialize)]
+pub struct AccessRecord {
+    pub timestamp: i64,
+    pub method: String,
+    pub route: String,
+    pub status: u16,
+    pub latency_ms: f64,
+    pub bytes: Option<u64>,
+    pub user: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+}
+
+pub struct AccessLogger {
+    ip: IpPrivacy,
+    salt: String,
+    trust_forwarded_for: bool,
+    git_sample_rate: f64,
+    file: Option<mpsc::Sender<String>>,
+}
+
+impl AccessLogger {
+    /// Builds the logger, spawning the file writer when a path is configured.
+    pub async fn from_config(config: &AccessLogConfig) -> anyhow::Result<Self> {
+        let salt = match (&config.hash_salt_env, config.ip) {
+            (Some(var), _) => std::env::var(var)
+                .map_err(|_| anyhow::anyhow!("access log salt variable {} is not set", var))?,
+            (None, IpPrivacy::Hash) => {
+                tracing::warn!("access log hashes IPs without a salt; set hash_salt_env");
+                String::new()
+            }
+            (None, _) => String::new(),
+        };
+
+        let file = match &config.path {
+            Some(path) => {
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                let mut out = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await?;
+                let (tx, mut rx) = mpsc::channel::<String>(FILE_QUEUE_CAPACITY);
+                tokio::spawn(async move {
+                    while let Some(line) = rx.recv().await {
+                        if let Err(e) = out.write_all(line.as_bytes()).await {
+                            tracing::warn!("access log write failed: {}", e);
+                        }
+                    }
+                    let _ = out.flush().await;
+                });
+                Some(tx)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            ip: config.ip,
+            salt,
+            trust_forwarded_for: config.trust_forwarded_for,
+            git_sample_rate: config.git_sample_rate.clamp(0.0, 1.0),
+            file,
+        })
+    }
+
+    pub fn client_field(&self, ip: Option<IpAddr>) -> Option<String> {
+        let ip = ip?;
+        match self.ip {
+            IpPrivacy::Full => Some(ip.to_string()),
+            IpPrivacy::Truncate => Some(truncate_ip(ip).to_string()),
+            IpPrivacy::Hash => {
+                let mut hasher = Sha256::new();
+                hasher.update(self.salt.as_bytes());
+                hasher.update(ip.to_string().as_bytes());
+                let digest = hasher.finalize();
+                Some(digest[..8].iter().map(|b| format!("{b:02x}")).collect())
+            }
+            IpPrivacy::Omit => None,
+        }
+    }
+
+    /// Successful git traffic is sampled; everything else is always logged.
+    fn should_log(&self, route: &str, status: u16) -> bool {
+        if status >= 400 || !is_git_route(route) {
+            return true;
+        }
+        self.git_sample_rate >= 1.0 || rand::random::<f64>() < self.git_sample_rate
+    }
+
+    fn emit(&self, record: &AccessRecord) {
+        if let Some(file) = &self.file {
+            match serde_json::to_string(record) {
+                Ok(mut line) => {
+                    line.push('\n');
+                    if file.try_send(line).is_err() {
+                        tracing::debug!("access log queue full; dropping record");
+                    }
+                }
+                Err(e) => tracing::warn!("failed to encode access record: {}", e),
+            }
+            return;
+        }
+        tracing::info!(
+            target: "forge::access",
+            method = %record.method,
+            route = %record.route,
+            status = record.status,
+            latency_ms = record.latency_ms,
+            bytes = record.bytes,
+            user = %record.user,
+            client = record.client.as_deref(),
+        );
+    }
+}
+
+/// Zeroes the host part of an address: IPv4 keeps /24, IPv6 keeps /48.
+pub fn truncate_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            IpAddr::V6(Ipv6Addr::new(s[0], s[1], s[2], 0, 0, 0, 0, 0))
+        }
+    }
+}
+
+/// Smart HTTP endpoints, which dominate request volume on busy instances.
+pub fn is_git_route(route: &str) -> bool {
+    route.ends_with("/info/refs")
+        || route.ends_with("/git-upload-pack")
+        || route.ends_with("/git-receive-pack")
+}
+
+/// Every Smart HTTP endpoint matches the repository catch-all (see `api::git`), so the
+/// endpoint is named from the path, with the repository left out.
+fn route_template(matched: &str, path: &str) -> String {
+    if matched != "/{*path}" {
+        return matched.to_string();
+    }
+    let template = match parse_git_route(path) {
+        Some((_, GitRoute::InfoRefs)) => "/{*repo}/info/refs",
+        Some((_, GitRoute::UploadPack)) => "/{*repo}/git-upload-pack",
+        Some((_, GitRoute::ReceivePack)) => "/{*repo}/git-receive-pack",
+        Some((_, GitRoute::Archive { .. })) => "/{*repo}/-/archive/{ref}",
+        Some((_, GitRoute::Raw { .. })) => "/{*repo}/-/raw/{ref}/{*file}",
+        None => matched,
+    };
+    template.to_string()
+}
+
+pub async fn access_log_middleware(
+    State(app_state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(logger) = app_state.access_log.clone() else {
+        return next.run(request).await;
+    };
+
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(matched) => route_template(matched.as_str(), request.uri().path()),
+        // Raw paths are not logged; they may contain private repository names.
+        None => "<unmatched>".to_string(),
+    };
+    let client_ip = client_ip(
+        logger.trust_forwarded_for,
+        request.headers(),
+        request.extensions(),
+    );
+    let user = viewer_did(&app_state, &request).unwrap_or_else(|| "anon".to_string());
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16();
+    if logger.should_log(&route, status) {
+        let bytes = response.body().size_hint().exact().or_else(|| {
+            response
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+        });
+        let record = AccessRecord {
+            timestamp: crate::user::db::unix_now(),
+            method,
+            route,
+            status,
+            latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+            bytes,
+            user,
+            client: logger.client_field(client_ip),
+        };
+        logger.emit(&record);
+    }
+    response
+}
+
+/// The requesting address, taken from `X-Forwarded-For` only when the proxy is trusted.
+pub(crate) fn client_ip(
+    trust_forwarded_for: bool,
+    headers: &HeaderMap,
+    extensions: &Extensions,
+) -> Option<IpAddr> {
+    if trust_forwarded_for
+        && let Some(forwarded) = headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|v| v.trim().parse().ok())
+    {
+        return Some(forwarded);
+    }
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+fn viewer_did(app_state: &AppState, request: &Request) -> Option<String> {
+    let auth_state = app_state.auth.as_ref()?;
+    let cookie_hdr = request.headers().get(header::COOKIE)?.to_str().ok()?;
+    let session_id = parse_cookie(cookie_hdr, "forge_session")?;
+    auth_state
+        .session_manager
+        .get_user(&session_id)
+        .ok()
+        .flatten()
+        .map(|user| user.did)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logger(ip: IpPrivacy, salt: &str) -> AccessLogger {
+        AccessLogger {
+            ip,
+            salt: salt.to_string(),
+            trust_forwarded_for: false,
+            git_sample_rate: 0.0,
+            file: None,
+        }
+    }
+
+    #[test]
+    fn client_addresses_are_truncated_or_hashed() {
+        let v4: IpAddr = "203.0.113.77".parse().unwrap();
+        let v6: IpAddr = "2001:db8:85a3:1:2:3:4:5".parse().unwrap();
+
+        let truncate = logger(IpPrivacy::Truncate, "");
+        assert_eq!(truncate.client_field(Some(v4)).unwrap(), "203.0.113.0");
+        assert_eq!(truncate.client_field(Some(v6)).unwrap(), "2001:db8:85a3::");
+
+        let a = logger(IpPrivacy::Hash, "salt-a")
+            .client_field(Some(v4))
+            .unwrap();
+        let b = logger(IpPrivacy::Hash, "salt-b")
+            .client_field(Some(v4))
+            .unwrap();
+        assert_eq!(a.len(), 16);
+        assert_ne!(a, b);
+        assert!(!a.contains("203"));
+
+        assert!(logger(IpPrivacy::Omit, "").client_field(Some(v4)).is_none());
+    }
+
+    #[test]
+    fn sampling_only_applies_to_successful_git_traffic() {
+        let logger = logger(IpPrivacy::Omit, "");
+        assert!(!logger.should_log("/{*repo}/info/refs", 200));
+        assert!(logger.should_log("/{*repo}/git-upload-pack", 500));
+        assert!(logger.should_log("/graphql", 200));
+    }
+
+    #[test]
+    fn git_routes_are_named_without_the_repository() {
+        assert_eq!(
+            route_template("/{*path}", "/team/app.git/git-upload-pack"),
+            "/{*repo}/git-upload-pack"
+        );
+        assert_eq!(
+            route_template("/{*path}", "/team/app/-/raw/main/src/lib.rs"),
+            "/{*repo}/-/raw/{ref}/{*file}"
+        );
+        assert_eq!(route_template("/{*path}", "/team/app"), "/{*path}");
+        assert_eq!(route_template("/graphql", "/graphql"), "/graphql");
+    }
+}
@@ -0,0 +1,51 @@
+//! `GET /account/exports/{id}`: downloads an account export through its signed link.
+
+use axum::extract::{Path, Query, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+
+use super::server::AppState;
+use crate::user::db::unix_now;
+use crate::user::export::open_signed_download;
+
+#[derive(Deserialize)]
+pub struct SignedLink {
+    expires: i64,
+    signature: String,
+}
+
+pub async fn account_export_download_handler(
+    State(app_state): State<AppState>,
+    Path(id): Path<String>,
+    Query(link): Query<SignedLink>,
+) -> Response {
+    // Bad signatures, expired links and deleted archives are indistinguishable to the caller.
+    let opened = open_signed_download(
+        &app_state.pool,
+        &id,
+        link.expires,
+        &link.signature,
+        unix_now(),
+    )
+    .await;
+    match opened {
+        Ok(Some((export, archive))) => (
+            [
+                (header::CONTENT_TYPE, "application/json".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"forge-export-{}.json\"", export.id),
+                ),
+                (header::CACHE_CONTROL, "no-store".to_string()),
+            ],
+            archive,
+        )
+            .into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "export link is invalid or expired").into_response(),
+        Err(err) => {
+            tracing::error!("account export download failed: {}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "internal error").into_response()
+        }
+    }
+}
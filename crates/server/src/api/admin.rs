@@ -0,0 +1,48 @@
+//! Middleware for HTTP endpoints reserved to instance administrators, such as the git
+//! diagnostics under `/admin/git/`. Administrators are recognised by session cookie or
+//! access token, like GraphQL viewers.
+
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use super::server::{AppState, request_viewer};
+use crate::auth::User;
+
+pub async fn require_admin(
+    State(app_state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let viewer = match request_viewer(&app_state, request.headers()).await {
+        Ok((viewer, _)) => viewer,
+        Err(response) => return response,
+    };
+    match admin_refusal(viewer.as_ref()) {
+        Some(refusal) => refusal,
+        None => next.run(request).await,
+    }
+}
+
+/// The answer for a viewer who is not an administrator.
+fn admin_refusal(viewer: Option<&User>) -> Option<Response> {
+    match viewer {
+        Some(viewer) if crate::instance::is_admin(&viewer.did) => None,
+        Some(_) => Some((StatusCode::FORBIDDEN, "administrator access required").into_response()),
+        None => Some((StatusCode::UNAUTHORIZED, "sign in as an administrator").into_response()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_administrators_pass() {
+        let status = |viewer: Option<&User>| admin_refusal(viewer).map(|r| r.status());
+        assert_eq!(status(None), Some(StatusCode::UNAUTHORIZED));
+        let user = User::new("did:plc:not-an-admin".into(), "someone.test".into());
+        assert_eq!(status(Some(&user)), Some(StatusCode::FORBIDDEN));
+    }
+}
@@ -0,0 +1,296 @@
+//! Admission control for GraphQL execution.
+//!
+//! Every operation takes a slot before it runs, and at most `queries.max_concurrent`
+//! run at once. Without this cap, a burst of heavy queries could hold every SQLite
+//! connection and leave git traffic waiting. Operations are interactive or batch. Batch
+//! operations are admin exports and requests sent with `X-Forge-Priority: batch`. They
+//! may hold at most `queries.batch_max_concurrent` slots, so the remaining slots are
+//! always free for interactive traffic. Operations that find no free slot wait in a
+//! queue. An operation is refused with HTTP 429 when `queries.max_queued` operations are
+//! already waiting, or when it has waited `queries.queue_timeout_ms`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use metrics::{counter, gauge, histogram};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::QueryLimitsConfig;
+
+/// Request header a client uses to mark its operations as batch work.
+pub const PRIORITY_HEADER: &str = "x-forge-priority";
+
+/// Admin-only root fields that scan whole tables or repositories.
+const BATCH_FIELDS: &[&str] = &[
+    "complianceArchive",
+    "extensionFieldStats",
+    "repositoryFsck",
+    "repairRepository",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryClass {
+    Interactive,
+    Batch,
+}
+
+impl QueryClass {
+    /// Batch when the client asks for it or the operation selects an admin export.
+    pub fn classify(priority_header: Option<&str>, root_fields: &[String]) -> Self {
+        let requested =
+            priority_header.is_some_and(|value| value.trim().eq_ignore_ascii_case("batch"));
+        if requested
+            || root_fields
+                .iter()
+                .any(|field| BATCH_FIELDS.contains(&field.as_str()))
+        {
+            QueryClass::Batch
+        } else {
+            QueryClass::Interactive
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            QueryClass::Interactive => "interactive",
+            QueryClass::Batch => "batch",
+        }
+    }
+}
+
+/// Why an operation was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shed {
+    QueueFull,
+    Timeout,
+}
+
+impl Shed {
+    fn label(self) -> &'static str {
+        match self {
+            Shed::QueueFull => "queue_full",
+            Shed::Timeout => "timeout",
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            Shed::QueueFull => "server is busy: too many queued operations, retry shortly",
+            Shed::Timeout => {
+                "server is busy: timed out waiting for an execution slot, retry shortly"
+            }
+        }
+    }
+}
+
+pub struct QueryAdmission {
+    /// `None` when limiting is disabled.
+    slots: Option<Arc<Semaphore>>,
+    batch_slots: Arc<Semaphore>,
+    queued: AtomicUsize,
+    max_queued: usize,
+    queue_timeout: Duration,
+}
+
+/// Held for the duration of an operation; dropping it frees the slot.
+pub struct QueryPermit {
+    class: QueryClass,
+    _batch: Option<OwnedSemaphorePermit>,
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl QueryPermit {
+    fn new(
+        class: QueryClass,
+        batch: Option<OwnedSemaphorePermit>,
+        slot: Option<OwnedSemaphorePermit>,
+    ) -> Self {
+        gauge!("forge_graphql_in_flight", "class" => class.label()).increment(1.0);
+        Self {
+            class,
+            _batch: batch,
+            _slot: slot,
+        }
+    }
+}
+
+impl Drop for QueryPermit {
+    fn drop(&mut self) {
+        gauge!("forge_graphql_in_flight", "class" => self.class.label()).decrement(1.0);
+    }
+}
+
+/// Counts an operation as queued until dropped, including when the client goes away.
+struct QueueEntry<'a> {
+    queued: &'a AtomicUsize,
+    class: QueryClass,
+}
+
+impl Drop for QueueEntry<'_> {
+    fn drop(&mut self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        gauge!("forge_graphql_queue_depth", "class" => self.class.label()).decrement(1.0);
+    }
+}
+
+impl QueryAdmission {
+    pub fn from_config(config: &QueryLimitsConfig) -> Self {
+        let slots =
+            (config.max_concurrent > 0).then(|| Arc::new(Semaphore::new(config.max_concurrent)));
+        let batch = config
+            .batch_max_concurrent
+            .clamp(1, config.max_concurrent.max(1));
+        Self {
+            slots,
+            batch_slots: Arc::new(Semaphore::new(batch)),
+            queued: AtomicUsize::new(0),
+            max_queued: config.max_queued,
+            queue_timeout: Duration::from_millis(config.queue_timeout_ms),
+        }
+    }
+
+    /// Waits for a slot for an operation of `class`, or refuses it.
+    pub async fn acquire(&self, class: QueryClass) -> Result<QueryPermit, Shed> {
+        let Some(slots) = &self.slots else {
+            return Ok(QueryPermit::new(class, None, None));
+        };
+        if let Some(permit) = self.try_acquire(slots, class) {
+            return Ok(permit);
+        }
+
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queued {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(self.shed(class, Shed::QueueFull));
+        }
+        gauge!("forge_graphql_queue_depth", "class" => class.label()).increment(1.0);
+        let entry = QueueEntry {
+            queued: &self.queued,
+            class,
+        };
+
+        let started = Instant::now();
+        let waited = tokio::time::timeout(self.queue_timeout, self.wait(slots, class)).await;
+        drop(entry);
+        histogram!("forge_graphql_queue_wait_ms", "class" => class.label())
+            .record(started.elapsed().as_secs_f64() * 1000.0);
+        waited.map_err(|_| self.shed(class, Shed::Timeout))
+    }
+
+    fn try_acquire(&self, slots: &Arc<Semaphore>, class: QueryClass) -> Option<QueryPermit> {
+        let batch = match class {
+            QueryClass::Batch => Some(self.batch_slots.clone().try_acquire_owned().ok()?),
+            QueryClass::Interactive => None,
+        };
+        let slot = slots.clone().try_acquire_owned().ok()?;
+        Some(QueryPermit::new(class, batch, Some(slot)))
+    }
+
+    async fn wait(&self, slots: &Arc<Semaphore>, class: QueryClass) -> QueryPermit {
+        let batch = match class {
+            QueryClass::Batch => self.batch_slots.clone().acquire_owned().await.ok(),
+            QueryClass::Interactive => None,
+        };
+        let slot = slots.clone().acquire_owned().await.ok();
+        QueryPermit::new(class, batch, slot)
+    }
+
+    fn shed(&self, class: QueryClass, reason: Shed) -> Shed {
+        counter!("forge_graphql_shed_total", "class" => class.label(), "reason" => reason.label())
+            .increment(1);
+        tracing::warn!(
+            class = class.label(),
+            reason = reason.label(),
+            "GraphQL operation refused"
+        );
+        reason
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admission(
+        max_concurrent: usize,
+        batch_max_concurrent: usize,
+        max_queued: usize,
+    ) -> QueryAdmission {
+        QueryAdmission::from_config(&QueryLimitsConfig {
+            max_concurrent,
+            batch_max_concurrent,
+            max_queued,
+            queue_timeout_ms: 50,
+        })
+    }
+
+    #[test]
+    fn admin_exports_and_opt_in_requests_are_batch() {
+        let fields = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            QueryClass::classify(None, &fields(&["repositories"])),
+            QueryClass::Interactive
+        );
+        assert_eq!(
+            QueryClass::classify(Some("Batch"), &fields(&["repositories"])),
+            QueryClass::Batch
+        );
+        assert_eq!(
+            QueryClass::classify(None, &fields(&["complianceArchive"])),
+            QueryClass::Batch
+        );
+    }
+
+    #[tokio::test]
+    async fn batch_work_cannot_take_interactive_slots() {
+        let admission = admission(3, 1, 4);
+        let _batch = admission.acquire(QueryClass::Batch).await.unwrap();
+        assert_eq!(
+            admission.acquire(QueryClass::Batch).await.err(),
+            Some(Shed::Timeout)
+        );
+
+        let _first = admission.acquire(QueryClass::Interactive).await.unwrap();
+        let _second = admission.acquire(QueryClass::Interactive).await.unwrap();
+        assert_eq!(
+            admission.acquire(QueryClass::Interactive).await.err(),
+            Some(Shed::Timeout)
+        );
+    }
+
+    #[tokio::test]
+    async fn full_queue_sheds_immediately_and_freed_slots_are_reused() {
+        let admission = Arc::new(admission(1, 1, 1));
+        let held = admission.acquire(QueryClass::Interactive).await.unwrap();
+
+        let waiter = {
+            let admission = admission.clone();
+            tokio::spawn(async move { admission.acquire(QueryClass::Interactive).await.is_ok() })
+        };
+        tokio::task::yield_now().await;
+        while admission.queued.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            admission.acquire(QueryClass::Interactive).await.err(),
+            Some(Shed::QueueFull)
+        );
+
+        drop(held);
+        assert!(waiter.await.unwrap());
+        assert_eq!(admission.queued.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn zero_disables_limiting() {
+        let admission = admission(0, 0, 0);
+        let permits: Vec<_> =
+            futures::future::join_all((0..10).map(|_| admission.acquire(QueryClass::Batch))).await;
+        assert!(permits.iter().all(Result::is_ok));
+    }
+}
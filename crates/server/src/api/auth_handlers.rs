@@ -9,9 +9,11 @@ use axum::{
 use axum::http::{HeaderMap, header};
 use url::Url;
 use serde::Deserialize;
+use sqlx::SqlitePool;
 use std::sync::Arc;
 
 use crate::auth::{AtProtoAuthClient, SessionManager, SqliteAuthStore};
+use crate::user::db::upsert_user;
 
 /// Shared auth state containing OAuth client and session manager
 pub struct AuthState {
@@ -167,7 +169,7 @@ pub async fn client_metadata_handler(State(auth_state): State<Arc<AuthState>>) -
 pub async fn auth_health_handler(State(auth_state): State<Arc<AuthState>>) -> impl IntoResponse {
     let client_id = auth_state.oauth_client.client_id().to_string();
     let redirect_uri = auth_state.oauth_client.redirect_uri().to_string();
-    let public_base = std::env::var("FORGE_PUBLIC_BASE_URL").unwrap_or_else(|_| "http://localhost:8000".to_string());
+    let public_base = crate::config::current().server.public_base_url.clone();
 
     let mut issues: Vec<String> = Vec::new();
     let mut ok = true;
@@ -293,9 +295,11 @@ pub async fn authorize_handler(
 
 /// Handler for OAuth callback
 ///
-/// This exchanges the authorization code for an access token and creates a session
+/// This exchanges the authorization code for an access token, stores the user's profile
+/// in `pool` and creates a session
 pub async fn callback_handler(
     State(auth_state): State<Arc<AuthState>>,
+    pool: SqlitePool,
     Query(params): Query<OAuthCallback>,
     headers_in: HeaderMap,
 ) -> impl IntoResponse {
@@ -353,6 +357,18 @@ pub async fn callback_handler(
         }
     };
 
+    // Profiles, ownership and activity refer to the user's row. A read-only standby
+    // leaves the database to the instance holding the lease.
+    if !crate::coordination::is_read_only()
+        && let Err(err) = upsert_user(&pool, &user).await
+    {
+        tracing::error!("Failed to store user {}: {}", user.did, err);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Html("<h1>Authentication Failed</h1><p>Failed to store your profile.</p>".to_string())
+        ).into_response();
+    }
+
     // Create session
     let session_id = match auth_state
         .session_manager
@@ -374,6 +390,17 @@ pub async fn callback_handler(
         }
     };
 
+    let user_agent = headers_in
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    if let Err(err) = auth_state
+        .session_manager
+        .set_user_agent(&session_id, user_agent)
+    {
+        tracing::warn!("Failed to record session device: {}", err);
+    }
+
     // Cleanup flow record now that session is established
     let _ = auth_state.auth_store.delete(&params.state).await;
 
@@ -397,12 +424,17 @@ pub async fn callback_handler(
         .ok()
         .and_then(|v| v.parse::<bool>().ok())
         .unwrap_or_else(|| {
-            std::env::var("FORGE_PUBLIC_BASE_URL")
-                .map(|u| u.starts_with("https://"))
-                .unwrap_or(false)
+            crate::config::current()
+                .server
+                .public_base_url
+                .starts_with("https://")
         });
-    if cookie_secure { cookie.push_str("; Secure"); }
-    if let Some(ref domain) = cookie_domain { cookie.push_str(&format!("; Domain={}", domain)); }
+    if cookie_secure {
+        cookie.push_str("; Secure");
+    }
+    if let Some(ref domain) = cookie_domain {
+        cookie.push_str(&format!("; Domain={}", domain));
+    }
     // Do NOT set Domain for IP literals like 127.0.0.1. Many browsers ignore or reject
     // cookies with a Domain attribute that is an IP address (RFC 6265). Omitting Domain
     // yields a host-only cookie which works across all ports for the host.
@@ -411,19 +443,47 @@ pub async fn callback_handler(
 
     let mut headers = HeaderMap::new();
     tracing::debug!(target: "auth", secure = cookie_secure, domain = %cookie_domain.as_deref().unwrap_or("<host-only>"), "callback_handler: setting forge_session cookie");
-    headers.insert(header::SET_COOKIE, header::HeaderValue::from_str(&cookie).unwrap_or(header::HeaderValue::from_static("")));
+    headers.insert(
+        header::SET_COOKIE,
+        header::HeaderValue::from_str(&cookie).unwrap_or(header::HeaderValue::from_static("")),
+    );
     // Optional debug cookie (non-HttpOnly) to verify presence in devtools
-    if std::env::var("FORGE_DEBUG_COOKIES").ok().and_then(|v| v.parse::<bool>().ok()).unwrap_or(false) {
-        let dbg = format!("forge_session_dbg={}; Path=/; SameSite=Lax{}{}",
+    if std::env::var("FORGE_DEBUG_COOKIES")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false)
+    {
+        let dbg = format!(
+            "forge_session_dbg={}; Path=/; SameSite=Lax{}{}",
             session_id,
             if cookie_secure { "; Secure" } else { "" },
-            if cookie_domain.is_some() { "" } else if std::env::var("FORGE_PUBLIC_BASE_URL").map(|u| u.contains("127.0.0.1")).unwrap_or(false) { "; Domain=127.0.0.1" } else { "" }
+            if cookie_domain.is_some() {
+                ""
+            } else if crate::config::current()
+                .server
+                .public_base_url
+                .contains("127.0.0.1")
+            {
+                "; Domain=127.0.0.1"
+            } else {
+                ""
+            }
+        );
+        headers.append(
+            header::SET_COOKIE,
+            header::HeaderValue::from_str(&dbg).unwrap_or(header::HeaderValue::from_static("")),
         );
-        headers.append(header::SET_COOKIE, header::HeaderValue::from_str(&dbg).unwrap_or(header::HeaderValue::from_static("")));
     }
     // Clear return_to cookie
-    headers.append(header::SET_COOKIE, header::HeaderValue::from_static("forge_return_to=; Path=/; Max-Age=0; SameSite=Lax"));
-    headers.insert(header::LOCATION, header::HeaderValue::from_str(&after_login).unwrap_or(header::HeaderValue::from_static("/")));
+    headers.append(
+        header::SET_COOKIE,
+        header::HeaderValue::from_static("forge_return_to=; Path=/; Max-Age=0; SameSite=Lax"),
+    );
+    headers.insert(
+        header::LOCATION,
+        header::HeaderValue::from_str(&after_login)
+            .unwrap_or(header::HeaderValue::from_static("/")),
+    );
     (StatusCode::FOUND, headers).into_response()
 }
 
@@ -455,9 +515,10 @@ pub async fn logout_handler(
             .ok()
             .and_then(|v| v.parse::<bool>().ok())
             .unwrap_or_else(|| {
-                std::env::var("FORGE_PUBLIC_BASE_URL")
-                    .map(|u| u.starts_with("https://"))
-                    .unwrap_or(false)
+                crate::config::current()
+                    .server
+                    .public_base_url
+                    .starts_with("https://")
             });
         let clear = if cookie_secure {
             "forge_session=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Lax"
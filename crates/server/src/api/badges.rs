@@ -0,0 +1,247 @@
+//! `GET /badge/{*path}`: SVG status badges to embed in README files.
+//!
+//! `/badge/team/app/clones.svg` shows how often `team/app` was cloned over Smart HTTP in
+//! the last 30 days, and `/badge/team/app/issues.svg` how many of its issues are open.
+//! Badges are public, like the repositories they describe. Counts are cached per
+//! instance for five minutes, and clients and image proxies may keep a badge as long.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode, Uri, header};
+use axum::response::{IntoResponse, Response};
+use serde_json::json;
+
+use super::server::{AppState, GraphQLRequest};
+use super::{canonical_redirect, internal_error};
+use crate::repository::traffic::clone_count;
+use crate::router::GraphQLExecutionRequest;
+use crate::user::db::unix_now;
+
+const CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+const CACHE_CONTROL: &str = "public, max-age=300";
+/// Cached counts are dropped wholesale beyond this many repositories and kinds.
+const MAX_CACHED: usize = 4096;
+const CLONE_WINDOW_DAYS: i64 = 30;
+
+const OPEN_ISSUES_QUERY: &str = "query BadgeOpenIssues($repositoryId: ID!) { \
+     getIssuesForRepository(repositoryId: $repositoryId, filter: \"is:open\") { id } }";
+
+const LABEL_COLOR: &str = "#555";
+const UNKNOWN_COLOR: &str = "#9f9f9f";
+/// Verdana at 11px averages close to 7px per character.
+const CHAR_WIDTH: u32 = 7;
+const PADDING: u32 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum BadgeKind {
+    Clones,
+    Issues,
+}
+
+impl BadgeKind {
+    /// Splits `team/app/clones.svg` into the repository path and the badge.
+    fn split(path: &str) -> Option<(&str, Self)> {
+        let (repository, name) = path.rsplit_once('/')?;
+        let kind = match name {
+            "clones.svg" => BadgeKind::Clones,
+            "issues.svg" => BadgeKind::Issues,
+            _ => return None,
+        };
+        Some((repository, kind))
+    }
+
+    /// Message and colour for a count; `None` when it could not be read.
+    fn message(&self, count: Option<i64>) -> (String, &'static str) {
+        match (self, count) {
+            (_, None) => ("unknown".to_string(), UNKNOWN_COLOR),
+            (BadgeKind::Clones, Some(n)) => (format!("{}/month", compact(n)), "#007ec6"),
+            (BadgeKind::Issues, Some(0)) => ("0 open".to_string(), "#4c1"),
+            (BadgeKind::Issues, Some(n)) => (format!("{} open", compact(n)), "#dfb317"),
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            BadgeKind::Clones => "clones",
+            BadgeKind::Issues => "issues",
+        }
+    }
+}
+
+/// When each count was read, by repository id and badge.
+type CountCache = HashMap<(String, BadgeKind), (Instant, Option<i64>)>;
+
+static COUNTS: LazyLock<Mutex<CountCache>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub async fn badge_handler(
+    State(app_state): State<AppState>,
+    Path(path): Path<String>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    let Some((repository, kind)) = BadgeKind::split(&path) else {
+        return (StatusCode::NOT_FOUND, "unknown badge").into_response();
+    };
+    let resolved = match app_state.resolver.resolve_repository(repository).await {
+        Ok(Some(resolved)) => resolved,
+        Ok(None) => return (StatusCode::NOT_FOUND, "repository not found").into_response(),
+        Err(err) => return internal_error("badge", err),
+    };
+    if let Some(redirect) = canonical_redirect(&uri, repository, &resolved.canonical_path()) {
+        return redirect;
+    }
+    let count = match cached_count(&app_state, &resolved.record.id, kind).await {
+        Ok(count) => count,
+        Err(err) => return internal_error("badge", err),
+    };
+
+    let (message, color) = kind.message(count);
+    let etag = format!("\"{}-{}\"", kind.label(), message.replace('/', "-"));
+    let cache_headers = [
+        (header::CACHE_CONTROL, CACHE_CONTROL.to_string()),
+        (header::ETAG, etag.clone()),
+    ];
+    let revalidated = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag));
+    if revalidated {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+
+    (
+        cache_headers,
+        [(header::CONTENT_TYPE, "image/svg+xml")],
+        render(kind.label(), &message, color),
+    )
+        .into_response()
+}
+
+async fn cached_count(
+    app_state: &AppState,
+    repository_id: &str,
+    kind: BadgeKind,
+) -> anyhow::Result<Option<i64>> {
+    let key = (repository_id.to_string(), kind);
+    if let Some((at, count)) = COUNTS.lock().unwrap_or_else(|e| e.into_inner()).get(&key)
+        && at.elapsed() < CACHE_TTL
+    {
+        return Ok(*count);
+    }
+
+    let count = match kind {
+        BadgeKind::Clones => Some(
+            clone_count(
+                &app_state.pool,
+                repository_id,
+                CLONE_WINDOW_DAYS,
+                unix_now(),
+            )
+            .await?,
+        ),
+        BadgeKind::Issues => open_issues(app_state, repository_id).await?,
+    };
+
+    let mut counts = COUNTS.lock().unwrap_or_else(|e| e.into_inner());
+    if counts.len() >= MAX_CACHED {
+        counts.clear();
+    }
+    counts.insert(key, (Instant::now(), count));
+    Ok(count)
+}
+
+/// Open issues, read through the router from the issues extension. `None` when the
+/// extension is not loaded.
+async fn open_issues(app_state: &AppState, repository_id: &str) -> anyhow::Result<Option<i64>> {
+    let request = GraphQLExecutionRequest::from_payload(&GraphQLRequest {
+        query: OPEN_ISSUES_QUERY.to_string(),
+        operation_name: None,
+        variables: json!({ "repositoryId": repository_id }),
+    })?;
+    let response = match app_state.router.current().execute(request).await {
+        Ok(response) => response,
+        Err(err) => {
+            tracing::debug!("issue badge for {} unavailable: {:#}", repository_id, err);
+            return Ok(None);
+        }
+    };
+    Ok(response["data"]["getIssuesForRepository"]
+        .as_array()
+        .map(|issues| issues.len() as i64))
+}
+
+/// `1234` as `1.2k`, `56789` as `57k`: badges stay narrow as counts grow.
+fn compact(n: i64) -> String {
+    if n < 1_000 {
+        return n.to_string();
+    }
+    // Tenths of a thousand, or of a million once that would read `1000k`
+    let (tenths, suffix) = match (n * 10 + 500) / 1_000 {
+        tenths if tenths < 9_995 => (tenths, "k"),
+        _ => ((n * 10 + 500_000) / 1_000_000, "M"),
+    };
+    if tenths < 100 && tenths % 10 != 0 {
+        format!("{}.{}{}", tenths / 10, tenths % 10, suffix)
+    } else {
+        format!("{}{}", (tenths + 5) / 10, suffix)
+    }
+}
+
+/// A flat two-part badge in the common README style. `label` and `message` are
+/// generated here, so they need no escaping.
+fn render(label: &str, message: &str, color: &str) -> String {
+    let label_width = label.chars().count() as u32 * CHAR_WIDTH + 2 * PADDING;
+    let message_width = message.chars().count() as u32 * CHAR_WIDTH + 2 * PADDING;
+    let width = label_width + message_width;
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}"><title>{label}: {message}</title><linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient><clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath><g clip-path="url(#r)"><rect width="{label_width}" height="20" fill="{LABEL_COLOR}"/><rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/><rect width="{width}" height="20" fill="url(#s)"/></g><g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11"><text x="{}" y="14">{label}</text><text x="{}" y="14">{message}</text></g></svg>"##,
+        label_width / 2,
+        label_width + message_width / 2,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn badge_paths_name_a_repository_and_a_kind() {
+        assert_eq!(
+            BadgeKind::split("team/infra/app/clones.svg"),
+            Some(("team/infra/app", BadgeKind::Clones))
+        );
+        assert_eq!(
+            BadgeKind::split("app/issues.svg"),
+            Some(("app", BadgeKind::Issues))
+        );
+        assert_eq!(BadgeKind::split("app/stars.svg"), None);
+        assert_eq!(BadgeKind::split("clones.svg"), None);
+    }
+
+    #[test]
+    fn counts_are_abbreviated() {
+        assert_eq!(compact(0), "0");
+        assert_eq!(compact(999), "999");
+        assert_eq!(compact(1_000), "1k");
+        assert_eq!(compact(1_234), "1.2k");
+        assert_eq!(compact(56_789), "57k");
+        assert_eq!(compact(999_000), "999k");
+        assert_eq!(compact(999_999), "1M");
+        assert_eq!(compact(2_500_000), "2.5M");
+    }
+
+    #[test]
+    fn badges_grow_with_their_text() {
+        let (message, color) = BadgeKind::Issues.message(Some(3));
+        assert_eq!((message.as_str(), color), ("3 open", "#dfb317"));
+        let short = render("issues", &message, color);
+        let long = render("issues", "1000 open", color);
+        assert!(short.contains("<title>issues: 3 open</title>"));
+        assert!(short.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="108""#));
+        assert!(long.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="129""#));
+        assert_eq!(BadgeKind::Clones.message(None).0, "unknown");
+    }
+}
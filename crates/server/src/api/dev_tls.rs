@@ -0,0 +1,281 @@
+//! HTTPS for local development.
+//!
+//! When `server.dev_tls.enabled` is set, the API is also served over HTTPS on
+//! `server.dev_tls.bind_addr`. It uses a self-signed certificate for
+//! `server.dev_tls.hostnames`. The certificate is generated on first start and kept in
+//! `server.dev_tls.cert_dir`, so a browser exception only has to be accepted once. It is
+//! regenerated when the host names change. The plain HTTP listener on
+//! `server.bind_addr` keeps running. With `redirect_http` it answers every request with a
+//! permanent redirect to the HTTPS address.
+//!
+//! [`apply_dev_urls`] moves `server.public_base_url` to HTTPS before authentication is
+//! set up, so session cookies are marked secure and the OAuth client metadata is served
+//! from the HTTPS origin. Loopback redirect URIs (`http://127.0.0.1/...`) are left alone
+//! because the AT Protocol localhost client requires plain HTTP. The callback reaches the
+//! HTTPS listener through the redirect.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::Router;
+use axum::http::{HeaderMap, Uri, header};
+use axum::response::Redirect;
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use url::Url;
+
+use crate::config::{Config, DevTlsConfig};
+
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+/// Host names the stored certificate was issued for, one per line.
+const HOSTNAMES_FILE: &str = "hostnames";
+
+pub struct DevCertificate {
+    pub cert_pem: String,
+    pub key_pem: String,
+}
+
+/// Reads the stored certificate, or generates and stores a new one when it is missing or
+/// was issued for other host names.
+pub fn load_or_generate(config: &DevTlsConfig) -> Result<DevCertificate> {
+    let dir = &config.cert_dir;
+    let wanted = config.hostnames.join("\n");
+    if let Some(stored) = read_stored(dir, &wanted) {
+        return Ok(stored);
+    }
+
+    let certified = rcgen::generate_simple_self_signed(config.hostnames.clone())
+        .context("failed to generate development certificate")?;
+    let certificate = DevCertificate {
+        cert_pem: certified.cert.pem(),
+        key_pem: certified.key_pair.serialize_pem(),
+    };
+    std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    write_private(&dir.join(KEY_FILE), &certificate.key_pem)?;
+    std::fs::write(dir.join(CERT_FILE), &certificate.cert_pem)?;
+    std::fs::write(dir.join(HOSTNAMES_FILE), &wanted)?;
+    tracing::info!(
+        "Generated development certificate for {} in {}",
+        config.hostnames.join(", "),
+        dir.display()
+    );
+    Ok(certificate)
+}
+
+fn read_stored(dir: &Path, wanted: &str) -> Option<DevCertificate> {
+    let hostnames = std::fs::read_to_string(dir.join(HOSTNAMES_FILE)).ok()?;
+    if hostnames != wanted {
+        return None;
+    }
+    Some(DevCertificate {
+        cert_pem: std::fs::read_to_string(dir.join(CERT_FILE)).ok()?,
+        key_pem: std::fs::read_to_string(dir.join(KEY_FILE)).ok()?,
+    })
+}
+
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    std::fs::write(path, contents)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// Points the public and OAuth URLs at the HTTPS listener when dev TLS is enabled.
+pub fn apply_dev_urls(config: &mut Config) -> Result<()> {
+    let dev_tls = &config.server.dev_tls;
+    if !dev_tls.enabled {
+        return Ok(());
+    }
+    let port = tls_port(dev_tls)?;
+
+    config.server.public_base_url = to_https(&config.server.public_base_url, port)
+        .context("server.public_base_url is not a valid URL")?;
+    let redirect =
+        Url::parse(&config.auth.redirect_uri).context("auth.redirect_uri is not a valid URL")?;
+    let loopback_ip = match redirect.host() {
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback(),
+        _ => false,
+    };
+    if !loopback_ip {
+        config.auth.redirect_uri = to_https(&config.auth.redirect_uri, port)?;
+    }
+    Ok(())
+}
+
+fn tls_port(config: &DevTlsConfig) -> Result<u16> {
+    let addr: SocketAddr = config
+        .bind_addr
+        .parse()
+        .with_context(|| format!("server.dev_tls.bind_addr `{}` is invalid", config.bind_addr))?;
+    Ok(addr.port())
+}
+
+/// Rewrites an `http` URL to `https` on `port`; other schemes are returned unchanged.
+fn to_https(raw: &str, port: u16) -> Result<String> {
+    let mut url = Url::parse(raw)?;
+    if url.scheme() != "http" {
+        return Ok(raw.to_string());
+    }
+    // Both calls only fail for URLs that cannot have a host, ruled out by the scheme.
+    let _ = url.set_scheme("https");
+    let _ = url.set_port((port != 443).then_some(port));
+    let mut rewritten = url.to_string();
+    if !raw.ends_with('/') && url.path() == "/" {
+        rewritten.pop();
+    }
+    Ok(rewritten)
+}
+
+/// Where a plain HTTP request for `host` and `path_and_query` is sent.
+fn https_location(host: Option<&str>, port: u16, path_and_query: &str) -> String {
+    let host = host.unwrap_or("localhost");
+    // Drop the HTTP port, keeping IPv6 brackets intact.
+    let name = match host.rfind(':') {
+        Some(colon) if !host[colon..].contains(']') => &host[..colon],
+        _ => host,
+    };
+    let path = if path_and_query.is_empty() {
+        "/"
+    } else {
+        path_and_query
+    };
+    if port == 443 {
+        format!("https://{}{}", name, path)
+    } else {
+        format!("https://{}:{}{}", name, port, path)
+    }
+}
+
+fn redirect_router(port: u16) -> Router {
+    Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        let host = headers
+            .get(header::HOST)
+            .and_then(|value| value.to_str().ok());
+        let path = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+        Redirect::permanent(&https_location(host, port, path))
+    })
+}
+
+/// Serves `app` over HTTPS, and `http_listener` with either `app` or a redirect to it.
+pub async fn serve(
+    app: Router,
+    http_listener: TcpListener,
+    config: &DevTlsConfig,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    // reqwest links rustls as well, so the provider cannot be chosen from features alone.
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let certificate = load_or_generate(config)?;
+    let tls = RustlsConfig::from_pem(
+        certificate.cert_pem.into_bytes(),
+        certificate.key_pem.into_bytes(),
+    )
+    .await
+    .context("failed to load development certificate")?;
+    let addr: SocketAddr = config.bind_addr.parse()?;
+    let port = addr.port();
+
+    let handle = axum_server::Handle::new();
+    tokio::spawn({
+        let handle = handle.clone();
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown.cancelled().await;
+            handle.graceful_shutdown(Some(Duration::from_secs(10)));
+        }
+    });
+    tracing::info!(
+        "Forge API listening on https://{} (development certificate)",
+        addr
+    );
+    let https = axum_server::bind_rustls(addr, tls).handle(handle).serve(
+        app.clone()
+            .into_make_service_with_connect_info::<SocketAddr>(),
+    );
+
+    let http = async move {
+        if config.redirect_http {
+            axum::serve(http_listener, redirect_router(port))
+                .with_graceful_shutdown(shutdown.cancelled_owned())
+                .await
+        } else {
+            axum::serve(
+                http_listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await
+        }
+    };
+
+    tokio::try_join!(https, http)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_urls_move_to_https_but_loopback_callbacks_stay() {
+        let mut config = Config::default();
+        config.server.dev_tls.enabled = true;
+        apply_dev_urls(&mut config).unwrap();
+        assert_eq!(config.server.public_base_url, "https://localhost:8443");
+        assert_eq!(
+            config.auth.redirect_uri,
+            "http://127.0.0.1:8000/auth/callback"
+        );
+
+        config.server.public_base_url = "http://forge.test:8000".into();
+        config.auth.redirect_uri = "http://forge.test:8000/auth/callback".into();
+        config.server.dev_tls.bind_addr = "0.0.0.0:443".into();
+        apply_dev_urls(&mut config).unwrap();
+        assert_eq!(config.server.public_base_url, "https://forge.test");
+        assert_eq!(config.auth.redirect_uri, "https://forge.test/auth/callback");
+    }
+
+    #[test]
+    fn redirects_keep_host_and_path() {
+        assert_eq!(
+            https_location(Some("localhost:8000"), 8443, "/auth/callback?code=x"),
+            "https://localhost:8443/auth/callback?code=x"
+        );
+        assert_eq!(
+            https_location(Some("[::1]:8000"), 443, "/"),
+            "https://[::1]/"
+        );
+        assert_eq!(
+            https_location(Some("[::1]"), 8443, ""),
+            "https://[::1]:8443/"
+        );
+        assert_eq!(
+            https_location(None, 8443, "/graphql"),
+            "https://localhost:8443/graphql"
+        );
+    }
+
+    #[test]
+    fn certificate_is_reused_until_hostnames_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = DevTlsConfig {
+            cert_dir: dir.path().join("tls"),
+            ..DevTlsConfig::default()
+        };
+        let first = load_or_generate(&config).unwrap();
+        assert_eq!(load_or_generate(&config).unwrap().cert_pem, first.cert_pem);
+
+        config.hostnames.push("forge.test".into());
+        assert_ne!(load_or_generate(&config).unwrap().cert_pem, first.cert_pem);
+    }
+}
@@ -0,0 +1,99 @@
+//! HTTP endpoints served by extensions.
+//!
+//! - `/extensions/{name}/hooks/{*path}`: requests for extensions that export the
+//!   `webhooks` interface, such as CI status callbacks. The extension checks the sender.
+//! - `GET /extensions/ui-manifests`: the `ui-manifest` exports of all loaded extensions,
+//!   keyed by extension name, for the web app to build its navigation from.
+
+use axum::Json;
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, RawQuery, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use super::server::AppState;
+use crate::extensions::wit_bindings::WebhookRequest;
+
+/// Webhook payloads are small; anything bigger is refused before reaching the extension.
+pub const MAX_WEBHOOK_BYTES: usize = 1024 * 1024;
+
+/// Response headers an extension may not set.
+const RESERVED_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "set-cookie",
+    "transfer-encoding",
+];
+
+pub async fn extension_webhook_handler(
+    State(app_state): State<AppState>,
+    Path(params): Path<HashMap<String, String>>,
+    RawQuery(query): RawQuery,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(name) = params.get("name") else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let extensions = app_state.extensions.get_extensions();
+    let Some(extension) = extensions
+        .get(name)
+        .filter(|extension| extension.runtime.interfaces().webhooks)
+    else {
+        return (StatusCode::NOT_FOUND, "no webhook handler at this path").into_response();
+    };
+
+    let request = WebhookRequest {
+        method: method.to_string(),
+        path: format!("/{}", params.get("path").map(String::as_str).unwrap_or("")),
+        query,
+        headers: headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        body: body.to_vec(),
+    };
+    let response = match extension.runtime.handle_webhook(request).await {
+        Ok(response) => response,
+        Err(err) => {
+            tracing::error!("webhook of extension {} failed: {:#}", name, err);
+            return (StatusCode::BAD_GATEWAY, "extension failed").into_response();
+        }
+    };
+
+    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::BAD_GATEWAY);
+    let mut out = Response::builder().status(status);
+    for (name, value) in response.headers {
+        if RESERVED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+            continue;
+        }
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            out = out.header(name, value);
+        }
+    }
+    out.body(Body::from(response.body))
+        .unwrap_or_else(|_| StatusCode::BAD_GATEWAY.into_response())
+}
+
+pub async fn ui_manifests_handler(State(app_state): State<AppState>) -> Response {
+    let manifests: Map<String, Value> = app_state
+        .extensions
+        .get_extensions()
+        .iter()
+        .filter_map(|(name, extension)| {
+            Some((name.clone(), extension.runtime.ui_manifest()?.clone()))
+        })
+        .collect();
+    // Extensions only change on restart or reload
+    (
+        [(header::CACHE_CONTROL, "public, max-age=60")],
+        Json(Value::Object(manifests)),
+    )
+        .into_response()
+}
@@ -0,0 +1,171 @@
+//! Federation endpoints (see [`crate::federation`]).
+//!
+//! - `GET /.well-known/did.json`: this instance's DID document with its signing key
+//! - `GET /federation/users/{did}`: confirms a DID has an account here, for link checks
+//! - `POST /federation/issue-references`: signed reports of issues elsewhere that
+//!   reference an issue here
+
+use std::sync::Arc;
+
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use serde_json::json;
+
+use super::internal_error;
+use super::server::AppState;
+use crate::config::FederationConfig;
+use crate::events::{Event, publish};
+use crate::federation::did::DidResolver;
+use crate::federation::instance_did;
+use crate::federation::references::{IssueReferenceRequest, record_reference};
+use crate::federation::signing::{InstanceKey, Rejection, verify_request};
+use crate::user::db::fetch_user_by_did;
+
+/// Signed request bodies are small JSON documents.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+pub struct FederationState {
+    pub key: InstanceKey,
+    pub resolver: DidResolver,
+    pub public_base_url: String,
+}
+
+impl FederationState {
+    pub fn from_config(config: &FederationConfig, public_base_url: &str) -> anyhow::Result<Self> {
+        let did = instance_did(public_base_url)?;
+        Ok(Self {
+            key: InstanceKey::load_or_generate(&config.key_path, did)?,
+            resolver: DidResolver::from_config(config)?,
+            public_base_url: public_base_url.to_string(),
+        })
+    }
+}
+
+fn federation(app_state: &AppState) -> Result<&Arc<FederationState>, Response> {
+    app_state
+        .federation
+        .as_ref()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "federation is not enabled").into_response())
+}
+
+pub async fn did_document_handler(State(app_state): State<AppState>) -> Response {
+    let federation = match federation(&app_state) {
+        Ok(federation) => federation,
+        Err(response) => return response,
+    };
+    Json(federation.key.document(&federation.public_base_url)).into_response()
+}
+
+pub async fn federation_user_handler(
+    State(app_state): State<AppState>,
+    Path(did): Path<String>,
+) -> Response {
+    if let Err(response) = federation(&app_state) {
+        return response;
+    }
+    match fetch_user_by_did(&app_state.pool, &did).await {
+        Ok(Some(user)) => Json(json!({ "did": user.did, "handle": user.handle })).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "unknown user").into_response(),
+        Err(err) => internal_error("federation request", err),
+    }
+}
+
+pub async fn issue_reference_handler(
+    State(app_state): State<AppState>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let federation = match federation(&app_state) {
+        Ok(federation) => federation,
+        Err(response) => return response,
+    };
+    if body.len() > MAX_BODY_BYTES {
+        return (StatusCode::PAYLOAD_TOO_LARGE, "request body too large").into_response();
+    }
+    let path_and_query = uri
+        .path_and_query()
+        .map(|value| value.as_str())
+        .unwrap_or_else(|| uri.path());
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let instance = match verify_request(
+        &app_state.pool,
+        &federation.resolver,
+        &crate::config::current().federation,
+        header,
+        method.as_str(),
+        path_and_query,
+        &body,
+    )
+    .await
+    {
+        Ok(instance) => instance,
+        Err(rejection) => {
+            tracing::warn!("federated request rejected: {}", rejection);
+            let status = match rejection {
+                Rejection::Untrusted(_) => StatusCode::FORBIDDEN,
+                _ => StatusCode::UNAUTHORIZED,
+            };
+            return (status, rejection.to_string()).into_response();
+        }
+    };
+
+    let request: IssueReferenceRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(err) => {
+            return (StatusCode::BAD_REQUEST, format!("invalid body: {}", err)).into_response();
+        }
+    };
+    let pool = &app_state.pool;
+    let resolved = match app_state
+        .resolver
+        .resolve_repository(&request.repository)
+        .await
+    {
+        Ok(Some(resolved)) => resolved,
+        Ok(None) => return (StatusCode::NOT_FOUND, "unknown repository").into_response(),
+        Err(err) => return internal_error("federation request", err),
+    };
+    let (reference, created) =
+        match record_reference(pool, &resolved.record.id, &instance, &request).await {
+            Ok(recorded) => recorded,
+            Err(err) => {
+                return (StatusCode::UNPROCESSABLE_ENTITY, format!("{:#}", err)).into_response();
+            }
+        };
+    if !created {
+        return (StatusCode::OK, Json(json!({ "id": reference.id }))).into_response();
+    }
+
+    let event = Event {
+        kind: "issue.federated_reference",
+        source: "core",
+        actor_did: reference.actor_did.as_deref(),
+        repository_id: Some(&resolved.record.id),
+        data: json!({
+            "issueNumber": reference.issue_number,
+            "instance": reference.instance_did,
+            "sourceUrl": reference.source_url,
+            "title": reference.title,
+        }),
+    };
+    if let Err(err) = publish(pool, crate::config::current().event_sink.as_ref(), event).await {
+        tracing::warn!("federated reference event was not exported: {:#}", err);
+    }
+    tracing::info!(
+        %instance,
+        repository = %request.repository,
+        issue = reference.issue_number,
+        "federated issue reference recorded"
+    );
+    (StatusCode::CREATED, Json(json!({ "id": reference.id }))).into_response()
+}
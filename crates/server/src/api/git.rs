@@ -0,0 +1,699 @@
+//! Git Smart HTTP, served from `/{*path}` when `FORGE_GIT_HTTP_MODE=smart`.
+//!
+//! The protocol lives in the `git_http` crate; this module supplies its state: repository
+//! storage, the limits of the `git` config section and the clone counter behind the
+//! `clones.svg` badge. Share
+//! links created with `allow_clone` (see [`crate::repository::share_links`]) may fetch
+//! repositories that are not exported, with the token as the basic auth password.
+//! Otherwise a fetch needs both the `git-daemon-export-ok` marker and a requester who may
+//! read the repository (see [`crate::repository::visibility`]): anyone for a public
+//! repository, its owner or an administrator for a private one.
+//!
+//! Pushes are authenticated with a `forge_session` cookie or a personal access token,
+//! sent as a bearer token or as the basic auth password. Like the branch mutations, only
+//! the repository owner may push, or an administrator when the repository has no owner;
+//! linked remote repositories only change through syncs. Pushes are checked against the
+//! repository's stored push policy (see [`crate::repository::policy`]). A push records
+//! the push time, queues symbol indexing and issue closing, and publishes
+//! `repository.pushed`.
+//!
+//! With usage metering on, the bytes of every response count towards the bandwidth of
+//! the repository's top-level group. Fetches are refused once the group reaches its hard
+//! bandwidth limit, and pushes once it reaches its hard storage limit (see
+//! [`crate::metering`]).
+
+use std::path::Path as FsPath;
+use std::sync::Arc;
+
+use axum::Json;
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{Extensions, HeaderMap, StatusCode, Uri, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use futures::TryStreamExt;
+use git_http::admin::{PolicyCheck, fetch_dry_run, policy_check};
+use git_http::policy::{PolicyRules, RefUpdate};
+use git_http::repo::{is_public_repo, split_repository_path};
+use git_http::routes::{GitRoute, dispatch_get, dispatch_post, parse_git_route};
+use git_http::v2::ServiceQuery;
+use git_http::{GitHttpState, GitScheduler, PushAccess};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+use super::auth_handlers::AuthState;
+use super::canonical_redirect;
+use super::server::{AppState, bearer_credential, parse_cookie};
+use super::share_links::{basic_auth_share_token, basic_auth_token, share_client};
+use crate::auth::User;
+use crate::events::{Event, publish};
+use crate::metering::{self, Resource};
+use crate::repository::closing::{BranchUpdate, default_branch, enqueue_issue_closing};
+use crate::repository::contributor_stats::enqueue_contributor_stats;
+use crate::repository::discovery::record_push;
+use crate::repository::policy::load_policy;
+use crate::repository::queries::{get_repository_by_id, reconstruct_repository_path};
+use crate::repository::share_links::{
+    ShareGrant, ShareLinkAction, record_share_use, verify_share_token,
+};
+use crate::repository::storage::git_scheduler;
+use crate::repository::symbols::enqueue_symbol_index;
+use crate::repository::traffic::record_clone;
+use crate::repository::visibility::can_view_repository;
+use crate::repository::{PathResolver, RepositoryStorage};
+use crate::search::code::enqueue_code_index;
+use crate::user::db::{fetch_repository_owner, unix_now};
+use crate::user::security::{TOKEN_PREFIX, authenticate_access_token};
+use crate::validation::slug::validate_slug;
+
+/// Attempts for the issue-closing job of a push, the default of remote syncs.
+const CLOSING_ATTEMPTS: i64 = 5;
+
+/// The repository a share link lets the current request fetch.
+#[derive(Clone)]
+struct SharedFetch {
+    grant: ShareGrant,
+    path: String,
+    client: Option<String>,
+}
+
+/// Who is pushing to which repository with the current request.
+#[derive(Clone)]
+struct PushGrant {
+    path: String,
+    pusher: Option<User>,
+    access: PushAccess,
+    rules: PolicyRules,
+}
+
+tokio::task_local! {
+    static SHARED: Option<SharedFetch>;
+    static PUSH: Option<PushGrant>;
+    static READABLE: bool;
+}
+
+#[derive(Clone)]
+pub struct GitHttp {
+    storage: RepositoryStorage,
+    pool: SqlitePool,
+    resolver: PathResolver,
+    auth: Option<Arc<AuthState>>,
+    scheduler: Arc<GitScheduler>,
+    max_body: usize,
+    timeout_ms: u64,
+}
+
+impl GitHttp {
+    /// `None` unless `FORGE_GIT_HTTP_MODE=smart`. Limits come from the `git` config
+    /// section; the concurrency budgets are shared with remote syncs.
+    pub fn from_env(
+        storage: RepositoryStorage,
+        pool: SqlitePool,
+        resolver: PathResolver,
+        auth: Option<Arc<AuthState>>,
+    ) -> Option<Self> {
+        if std::env::var("FORGE_GIT_HTTP_MODE").ok().as_deref() != Some("smart") {
+            return None;
+        }
+        let config = &crate::config::current().git;
+        Some(Self {
+            storage,
+            pool,
+            resolver,
+            auth,
+            scheduler: git_scheduler().clone(),
+            max_body: config.max_request_bytes,
+            timeout_ms: config.request_timeout_ms,
+        })
+    }
+
+    pub fn routes(self) -> Router<AppState> {
+        Router::new()
+            .route("/{*path}", get(serve_get).post(serve_post))
+            .with_state(self)
+    }
+
+    /// Fetch and push-policy dry runs. They skip the export gate, so the caller mounts
+    /// them behind administrator authentication.
+    pub fn admin_routes(self) -> Router<AppState> {
+        Router::new()
+            .route("/admin/git/dry-run/{*repo}", post(fetch_dry_run::<Self>))
+            .route("/admin/git/policy-check/{*repo}", post(serve_policy_check))
+            .with_state(self)
+    }
+
+    /// The share link in the request's credentials, if it is valid and allows cloning.
+    async fn shared_fetch(
+        &self,
+        headers: &HeaderMap,
+        extensions: &Extensions,
+    ) -> Option<SharedFetch> {
+        let token = basic_auth_share_token(headers)?;
+        let shared = async {
+            let Some(grant) = verify_share_token(&self.pool, &token).await? else {
+                return anyhow::Ok(None);
+            };
+            if !grant.allow_clone {
+                return Ok(None);
+            }
+            let Some(record) = get_repository_by_id(&self.pool, &grant.repository_id).await? else {
+                return Ok(None);
+            };
+            let path = reconstruct_repository_path(&self.pool, &record).await?;
+            Ok(Some(SharedFetch {
+                grant,
+                path,
+                client: share_client(headers, extensions),
+            }))
+        };
+        shared.await.unwrap_or_else(|err| {
+            tracing::warn!("share link check failed: {:#}", err);
+            None
+        })
+    }
+
+    /// The user behind the request's credentials. git sends a personal access token as
+    /// the basic auth password; a session cookie works for clients configured to send it.
+    async fn pusher(&self, headers: &HeaderMap) -> anyhow::Result<Option<User>> {
+        let session_id = headers
+            .get(header::COOKIE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|cookies| parse_cookie(cookies, "forge_session"));
+        if let (Some(auth), Some(session_id)) = (&self.auth, session_id)
+            && let Some(user) = auth.session_manager.touch(&session_id)?
+        {
+            return Ok(Some(user));
+        }
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(bearer_credential)
+            .map(str::to_string)
+            .or_else(|| basic_auth_token(headers, TOKEN_PREFIX));
+        match token {
+            Some(token) => authenticate_access_token(&self.pool, &token).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Whether the request may read the repository `path` addresses. Repositories the
+    /// database does not know are left to the export marker alone.
+    async fn readable(&self, path: &str, headers: &HeaderMap) -> bool {
+        let Some((segments, _)) = parse_git_route(path) else {
+            return true;
+        };
+        let path = segments.join("/");
+        let readable = async {
+            let Some(resolved) = self.resolver.resolve_repository(&path).await? else {
+                return anyhow::Ok(true);
+            };
+            let repository_id = resolved.record.id.as_str();
+            // Public repositories need no credentials, so they are not looked up.
+            if can_view_repository(&self.pool, None, repository_id).await? {
+                return Ok(true);
+            }
+            let Some(viewer) = self.pusher(headers).await? else {
+                return Ok(false);
+            };
+            Ok(crate::instance::is_admin(&viewer.did)
+                || can_view_repository(&self.pool, Some(&viewer.did), repository_id).await?)
+        };
+        readable.await.unwrap_or_else(|err| {
+            tracing::warn!("read access check for {} failed: {:#}", path, err);
+            false
+        })
+    }
+
+    /// A redirect to the stored spelling of the repository `path` addresses, when it was
+    /// requested in another case. Git follows it for `info/refs` and fetches the rest from
+    /// the new location.
+    async fn canonical_redirect(&self, uri: &Uri, path: &str) -> Option<Response> {
+        let (segments, _) = parse_git_route(path)?;
+        let path = segments.join("/");
+        let resolved = self
+            .resolver
+            .resolve_repository(&path)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::warn!("failed to resolve {}: {:#}", path, err);
+                None
+            })?;
+        canonical_redirect(uri, &path, &resolved.canonical_path())
+    }
+
+    /// Decides whether the request may push, when `path` and `service` address a push.
+    async fn push_grant(
+        &self,
+        path: &str,
+        service: Option<&str>,
+        headers: &HeaderMap,
+    ) -> Option<PushGrant> {
+        let (segments, route) = parse_git_route(path)?;
+        let pushing = match route {
+            GitRoute::ReceivePack => true,
+            GitRoute::InfoRefs => service == Some("git-receive-pack"),
+            _ => false,
+        };
+        if !pushing {
+            return None;
+        }
+        let path = segments.join("/");
+        let granted = async {
+            let Some(pusher) = self.pusher(headers).await? else {
+                return anyhow::Ok((None, PushAccess::Unauthenticated, PolicyRules::default()));
+            };
+            // A read-only standby leaves refs to the instance holding the lease.
+            if crate::coordination::is_read_only() {
+                return Ok((Some(pusher), PushAccess::Denied, PolicyRules::default()));
+            }
+            let Some(resolved) = self.resolver.resolve_repository(&path).await? else {
+                return Ok((Some(pusher), PushAccess::Denied, PolicyRules::default()));
+            };
+            let allowed = resolved.record.remote_url.is_none()
+                && match fetch_repository_owner(&self.pool, &resolved.record.id).await? {
+                    Some(owner) => owner == pusher.did,
+                    None => crate::instance::is_admin(&pusher.did),
+                };
+            if !allowed {
+                return Ok((Some(pusher), PushAccess::Denied, PolicyRules::default()));
+            }
+            let rules = load_policy(&self.pool, &resolved.record.id).await?;
+            Ok((Some(pusher), PushAccess::Allowed, rules))
+        };
+        let (pusher, access, rules) = granted.await.unwrap_or_else(|err| {
+            tracing::warn!("push access check for {} failed: {:#}", path, err);
+            (None, PushAccess::Denied, PolicyRules::default())
+        });
+        Some(PushGrant {
+            path,
+            pusher,
+            access,
+            rules,
+        })
+    }
+
+    /// The stored push policy of the repository `path` addresses; `None` when the
+    /// database does not know the repository.
+    async fn stored_policy(&self, path: &str) -> anyhow::Result<Option<PolicyRules>> {
+        let path = split_repository_path(path).join("/");
+        let Some(resolved) = self.resolver.resolve_repository(&path).await? else {
+            return Ok(None);
+        };
+        Ok(Some(load_policy(&self.pool, &resolved.record.id).await?))
+    }
+
+    /// The top-level group the request's repository is metered under, when metering is
+    /// enabled.
+    async fn metered_group(&self, path: &str) -> Option<String> {
+        if !metering::enabled() {
+            return None;
+        }
+        let (segments, _) = parse_git_route(path)?;
+        let path = segments.join("/");
+        let resolved = self
+            .resolver
+            .resolve_repository(&path)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::warn!("failed to resolve {} for metering: {:#}", path, err);
+                None
+            })?;
+        metering::metered_group(&resolved.segments).map(str::to_string)
+    }
+}
+
+/// Refuses pushes when the group is at its hard storage limit, and fetches when it is at
+/// its hard bandwidth limit.
+fn quota_refusal(group: &str, pushing: bool) -> Option<Response> {
+    let resource = if pushing {
+        Resource::Storage
+    } else {
+        Resource::Bandwidth
+    };
+    if !metering::is_blocked(group, resource) {
+        return None;
+    }
+    let message = format!("{} has used up its {} quota\n", group, resource.as_str());
+    Some((StatusCode::FORBIDDEN, message).into_response())
+}
+
+/// Counts the response body towards the group's bandwidth as it is sent.
+fn metered(group: Option<String>, response: Response) -> Response {
+    let Some(group) = group else {
+        return response;
+    };
+    let (parts, body) = response.into_parts();
+    let body = body.into_data_stream().inspect_ok(move |chunk| {
+        metering::record(&group, Resource::Bandwidth, chunk.len() as u64);
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// The push-policy dry run, checked against the repository's stored policy unless the
+/// request brings its own rules.
+async fn serve_policy_check(
+    State(git): State<GitHttp>,
+    Path(repo): Path<String>,
+    Json(mut check): Json<PolicyCheck>,
+) -> Response {
+    if check.rules.is_none() {
+        match git.stored_policy(&repo).await {
+            Ok(rules) => check.rules = rules,
+            Err(err) => {
+                tracing::warn!("failed to load the push policy of {}: {:#}", repo, err);
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "failed to load the push policy",
+                )
+                    .into_response();
+            }
+        }
+    }
+    policy_check(State(git), Path(repo), Json(check)).await
+}
+
+async fn serve_get(
+    State(git): State<GitHttp>,
+    path: Path<String>,
+    query: Query<ServiceQuery>,
+    uri: Uri,
+    headers: HeaderMap,
+    extensions: Extensions,
+) -> Response {
+    let shared = git.shared_fetch(&headers, &extensions).await;
+    let readable = git.readable(&path.0, &headers).await;
+    // Only readers learn the stored spelling of a private repository.
+    if readable && let Some(redirect) = git.canonical_redirect(&uri, &path.0).await {
+        return redirect;
+    }
+    let push = git
+        .push_grant(&path.0, query.0.service.as_deref(), &headers)
+        .await;
+    let group = git.metered_group(&path.0).await;
+    if let Some(refusal) = group
+        .as_deref()
+        .and_then(|group| quota_refusal(group, push.is_some()))
+    {
+        return refusal;
+    }
+    let served = SHARED.scope(shared, dispatch_get(State(git), path, query));
+    let served = READABLE.scope(readable, served);
+    metered(group, PUSH.scope(push, served).await)
+}
+
+async fn serve_post(
+    State(git): State<GitHttp>,
+    path: Path<String>,
+    headers: HeaderMap,
+    extensions: Extensions,
+    body: Body,
+) -> Response {
+    let shared = git.shared_fetch(&headers, &extensions).await;
+    let readable = git.readable(&path.0, &headers).await;
+    let push = git.push_grant(&path.0, None, &headers).await;
+    let group = git.metered_group(&path.0).await;
+    if let Some(refusal) = group
+        .as_deref()
+        .and_then(|group| quota_refusal(group, push.is_some()))
+    {
+        return refusal;
+    }
+    let served = SHARED.scope(shared, dispatch_post(State(git), path, headers, body));
+    let served = READABLE.scope(readable, served);
+    metered(group, PUSH.scope(push, served).await)
+}
+
+impl GitHttpState for GitHttp {
+    type Storage = RepositoryStorage;
+
+    fn storage(&self) -> &Self::Storage {
+        &self.storage
+    }
+
+    fn git_scheduler(&self) -> &GitScheduler {
+        &self.scheduler
+    }
+
+    fn git_max_body(&self) -> usize {
+        self.max_body
+    }
+
+    fn git_timeout_ms(&self) -> u64 {
+        self.timeout_ms
+    }
+
+    fn validate_slug(&self, slug: &str) -> anyhow::Result<()> {
+        validate_slug(slug)
+    }
+
+    fn push_policy(&self, segments: &[String]) -> PolicyRules {
+        let path = segments.join("/");
+        PUSH.try_with(|push| {
+            push.as_ref()
+                .filter(|push| push.path == path)
+                .map(|push| push.rules.clone())
+        })
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+    }
+
+    fn is_exported(&self, segments: &[String], repo_dir: &FsPath) -> bool {
+        let readable = READABLE.try_with(|readable| *readable).unwrap_or(false);
+        (readable && is_public_repo(&repo_dir.to_path_buf()))
+            || SHARED
+                .try_with(|shared| {
+                    shared
+                        .as_ref()
+                        .is_some_and(|shared| shared.path == segments.join("/"))
+                })
+                .unwrap_or(false)
+    }
+
+    fn record_clone(&self, segments: &[String]) {
+        // A read-only standby leaves the database to the instance holding the lease.
+        if crate::coordination::is_read_only() {
+            return;
+        }
+        let pool = self.pool.clone();
+        let path = segments.join("/");
+        let shared = SHARED
+            .try_with(|shared| shared.clone())
+            .ok()
+            .flatten()
+            .filter(|shared| shared.path == path);
+        if let Some(shared) = shared {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                let client = shared.client.as_deref();
+                if let Err(err) =
+                    record_share_use(&pool, &shared.grant, ShareLinkAction::Fetch, client).await
+                {
+                    tracing::warn!(
+                        "failed to record share link fetch of {}: {:#}",
+                        shared.path,
+                        err
+                    );
+                }
+            });
+        }
+        let resolver = self.resolver.clone();
+        tokio::spawn(async move {
+            let recorded = async {
+                if let Some(resolved) = resolver.resolve_repository(&path).await? {
+                    record_clone(&pool, &resolved.record.id, unix_now()).await?;
+                }
+                anyhow::Ok(())
+            };
+            if let Err(err) = recorded.await {
+                tracing::warn!("failed to count clone of {}: {:#}", path, err);
+            }
+        });
+    }
+
+    fn push_access(&self, segments: &[String]) -> PushAccess {
+        let path = segments.join("/");
+        PUSH.try_with(|push| {
+            push.as_ref()
+                .filter(|push| push.path == path)
+                .map(|push| push.access)
+        })
+        .ok()
+        .flatten()
+        .unwrap_or(PushAccess::Denied)
+    }
+
+    fn record_push(&self, segments: &[String], updates: &[RefUpdate]) {
+        let pool = self.pool.clone();
+        let dir = self.storage.repository_path(segments);
+        let segments = segments.to_vec();
+        let path = segments.join("/");
+        let updates = updates.to_vec();
+        let actor_did = PUSH
+            .try_with(|push| {
+                push.as_ref()
+                    .and_then(|push| push.pusher.as_ref())
+                    .map(|u| u.did.clone())
+            })
+            .ok()
+            .flatten();
+        let resolver = self.resolver.clone();
+        tokio::spawn(async move {
+            let recorded = async {
+                let Some(resolved) = resolver.resolve_repository(&path).await? else {
+                    return anyhow::Ok(());
+                };
+                let repository_id = resolved.record.id.as_str();
+                record_push(&pool, repository_id, unix_now()).await?;
+
+                let default_ref = default_branch(&dir)
+                    .await
+                    .map(|branch| format!("refs/heads/{}", branch));
+                if updates.iter().any(|update| {
+                    Some(&update.ref_name) == default_ref.as_ref() && !update.is_delete()
+                }) {
+                    if let Err(e) = enqueue_symbol_index(&pool, repository_id).await {
+                        tracing::warn!(repository = %repository_id, "symbol indexing was not queued: {}", e);
+                    }
+                    if let Err(e) = enqueue_contributor_stats(&pool, repository_id).await {
+                        tracing::warn!(repository = %repository_id, "contributor stats were not queued: {}", e);
+                    }
+                    if let Err(e) = enqueue_code_index(&pool, repository_id).await {
+                        tracing::warn!(repository = %repository_id, "code indexing was not queued: {}", e);
+                    }
+                }
+
+                let refs: Vec<_> = updates
+                    .iter()
+                    .map(|update| {
+                        json!({
+                            "name": update.ref_name,
+                            "before": (!update.is_create()).then_some(&update.old),
+                            "after": (!update.is_delete()).then_some(&update.new),
+                        })
+                    })
+                    .collect();
+                publish(
+                    &pool,
+                    crate::config::current().event_sink.as_ref(),
+                    Event {
+                        kind: "repository.pushed",
+                        source: "core",
+                        actor_did: actor_did.as_deref(),
+                        repository_id: Some(repository_id),
+                        data: json!({ "via": "http", "refs": refs }),
+                    },
+                )
+                .await?;
+
+                let branches: Vec<BranchUpdate> = updates
+                    .iter()
+                    .filter(|update| !update.is_delete())
+                    .map(|update| BranchUpdate {
+                        ref_name: update.ref_name.clone(),
+                        before: (!update.is_create()).then(|| update.old.clone()),
+                        after: update.new.clone(),
+                    })
+                    .collect();
+                enqueue_issue_closing(&pool, repository_id, &segments, branches, CLOSING_ATTEMPTS)
+                    .await?;
+                Ok(())
+            };
+            if let Err(err) = recorded.await {
+                tracing::warn!("failed to record push to {}: {:#}", path, err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::policy::save_policy;
+    use crate::test_helpers::{commit_file, create_test_pool, git};
+    use crate::user::db::set_repository_owner;
+    use crate::user::security::create_access_token;
+
+    /// Pushes run on the runtime so the in-process server can answer them.
+    async fn push(dir: &FsPath, url: &str) -> std::process::Output {
+        tokio::process::Command::new("git")
+            .current_dir(dir)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .args(["push", url, "main"])
+            .output()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn pushes_are_checked_against_the_stored_policy() {
+        let pool = create_test_pool().await.unwrap();
+        sqlx::query("INSERT INTO repositories (id, slug) VALUES ('r1', 'app')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        set_repository_owner(&pool, "r1", "did:plc:owner")
+            .await
+            .unwrap();
+        save_policy(&pool, "r1", "(forbid_merge_commits: true)")
+            .await
+            .unwrap();
+        let (_, token) = create_access_token(&pool, "did:plc:owner", "push", None)
+            .await
+            .unwrap();
+
+        let root = tempfile::TempDir::new().unwrap();
+        let storage = RepositoryStorage::new(root.path().to_path_buf(), root.path().join("cache"));
+        let repo = root.path().join("app.git");
+        git(root.path(), &["init", "-q", "--bare", "app.git"]);
+        git(&repo, &["symbolic-ref", "HEAD", "refs/heads/main"]);
+
+        let git_http = GitHttp {
+            storage,
+            pool: pool.clone(),
+            resolver: PathResolver::new(pool.clone()),
+            auth: None,
+            scheduler: Arc::default(),
+            max_body: 64 * 1024 * 1024,
+            timeout_ms: 60_000,
+        };
+        let app = Router::new()
+            .route("/{*path}", get(serve_get).post(serve_post))
+            .with_state(git_http);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://push:{}@{}/app.git",
+            token,
+            listener.local_addr().unwrap()
+        );
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let work = tmp.path();
+        git(work, &["init", "-q", "-b", "main"]);
+        let head = commit_file(work, "README.md", "hello\n", "init");
+        let out = push(work, &url).await;
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+
+        git(work, &["checkout", "-qb", "topic"]);
+        commit_file(work, "topic.txt", "topic\n", "topic");
+        git(work, &["checkout", "-q", "main"]);
+        commit_file(work, "main.txt", "main\n", "main");
+        git(work, &["merge", "-q", "--no-ff", "-m", "merge", "topic"]);
+
+        let out = push(work, &url).await;
+        assert!(!out.status.success());
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(
+            stderr.contains("policy violation: no-merge-commits"),
+            "{stderr}"
+        );
+        assert_eq!(git(&repo, &["rev-parse", "refs/heads/main"]), head);
+        server.abort();
+    }
+}
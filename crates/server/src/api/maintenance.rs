@@ -0,0 +1,143 @@
+//! Request gate for maintenance mode (see [`crate::announcements`]).
+//!
+//! While maintenance is on, administrators keep full access and everyone else gets a
+//! 503 with a GraphQL-shaped body, so the web app can show the message wherever it would
+//! show an error. Git fetches keep working, pushes are refused for everyone, and sign-in,
+//! health and metrics stay reachable so operators can get in and monitoring stays green.
+
+use axum::Json;
+use axum::extract::{Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use super::server::{AppState, parse_cookie};
+use crate::announcements::{MaintenanceMode, maintenance_mode};
+
+/// Seconds clients are asked to wait before retrying.
+const RETRY_AFTER_SECS: &str = "60";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Access {
+    Always,
+    AdminOnly,
+    Never,
+}
+
+fn access(path: &str, query: Option<&str>) -> Access {
+    let service = query
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("service="));
+    if path.ends_with("/git-receive-pack")
+        || (path.ends_with("/info/refs") && service == Some("git-receive-pack"))
+    {
+        return Access::Never;
+    }
+    if path.ends_with("/git-upload-pack")
+        || path.ends_with("/info/refs")
+        || path.starts_with("/auth/")
+        || path.starts_with("/health/")
+        || path == "/client-metadata.json"
+        || path == "/metrics"
+    {
+        return Access::Always;
+    }
+    Access::AdminOnly
+}
+
+pub async fn maintenance_middleware(
+    State(app_state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mode = match maintenance_mode(&app_state.pool).await {
+        Ok(mode) => mode,
+        Err(e) => {
+            // Failing open: an unreadable flag should not take the instance down.
+            tracing::warn!("failed to read maintenance mode: {:#}", e);
+            return next.run(request).await;
+        }
+    };
+    if !mode.enabled {
+        return next.run(request).await;
+    }
+
+    match access(request.uri().path(), request.uri().query()) {
+        Access::Always => next.run(request).await,
+        Access::AdminOnly if is_admin(&app_state, &request) => next.run(request).await,
+        _ => unavailable(&mode),
+    }
+}
+
+fn is_admin(app_state: &AppState, request: &Request) -> bool {
+    let Some(auth_state) = app_state.auth.as_ref() else {
+        return false;
+    };
+    let did = request
+        .headers()
+        .get(header::COOKIE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|cookie_hdr| parse_cookie(cookie_hdr, "forge_session"))
+        .and_then(|session_id| {
+            auth_state
+                .session_manager
+                .get_user(&session_id)
+                .ok()
+                .flatten()
+        })
+        .map(|user| user.did);
+    did.is_some_and(|did| crate::instance::is_admin(&did))
+}
+
+fn unavailable(mode: &MaintenanceMode) -> Response {
+    let body = serde_json::json!({
+        "errors": [{
+            "message": mode.display_message(),
+            "extensions": {
+                "code": "MAINTENANCE",
+                "since": mode.updated_at,
+            },
+        }],
+    });
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [
+            (header::RETRY_AFTER, RETRY_AFTER_SECS),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        Json(body),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn git_fetches_stay_available() {
+        assert_eq!(
+            access("/team/app.git/git-upload-pack", None),
+            Access::Always
+        );
+        assert_eq!(
+            access("/team/app.git/info/refs", Some("service=git-upload-pack")),
+            Access::Always
+        );
+        assert_eq!(
+            access("/team/app.git/git-receive-pack", None),
+            Access::Never
+        );
+        assert_eq!(
+            access("/team/app.git/info/refs", Some("service=git-receive-pack")),
+            Access::Never
+        );
+        assert_eq!(access("/auth/login", None), Access::Always);
+        assert_eq!(access("/graphql", None), Access::AdminOnly);
+        assert_eq!(
+            access("/team/app/-/raw/main/README.md", None),
+            Access::AdminOnly
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! `POST /hooks/mirror/{*path}`: upstream push notifications for linked remotes.
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+use super::internal_error;
+use super::server::AppState;
+use crate::repository::clone::enqueue_remote_sync;
+use crate::repository::mirror_hook::{
+    Delivery, Rejection, fetch_mirror_webhook_secret, record_delivery, verify_delivery,
+};
+
+pub async fn mirror_webhook_handler(
+    State(app_state): State<AppState>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let pool = &app_state.pool;
+    let resolved = match app_state.resolver.resolve_repository(&path).await {
+        Ok(Some(resolved)) if resolved.record.remote_url.is_some() => resolved,
+        Ok(_) => return (StatusCode::NOT_FOUND, "no linked remote at this path").into_response(),
+        Err(err) => return internal_error("mirror webhook", err),
+    };
+    let repository_id = resolved.record.id;
+
+    // Unknown repositories and repositories without a hook look the same to the sender.
+    let secret = match fetch_mirror_webhook_secret(pool, &repository_id).await {
+        Ok(Some(secret)) => secret,
+        Ok(None) => {
+            return (StatusCode::NOT_FOUND, "no linked remote at this path").into_response();
+        }
+        Err(err) => return internal_error("mirror webhook", err),
+    };
+
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let provider = match verify_delivery(header, &body, &secret) {
+        Ok(Delivery::Ignored(_)) => return StatusCode::NO_CONTENT.into_response(),
+        Ok(Delivery::Push(provider)) => provider,
+        Err(Rejection::UnknownSender) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "expected a GitHub or GitLab webhook",
+            )
+                .into_response();
+        }
+        Err(Rejection::BadSecret) => {
+            tracing::warn!(repository = %path, "mirror webhook rejected: bad signature");
+            return (StatusCode::UNAUTHORIZED, "invalid webhook secret").into_response();
+        }
+    };
+
+    // The writer instance runs clone jobs; standbys cannot record them.
+    if crate::coordination::is_read_only() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "instance is read-only").into_response();
+    }
+
+    if let Err(err) = record_delivery(pool, &repository_id).await {
+        return internal_error("mirror webhook", err);
+    }
+    let max_attempts = crate::config::current().clone.max_attempts;
+    match enqueue_remote_sync(pool, &repository_id, max_attempts).await {
+        Ok(queued) => {
+            tracing::info!(repository = %path, ?provider, queued, "mirror webhook received");
+            StatusCode::ACCEPTED.into_response()
+        }
+        Err(err) => internal_error("mirror webhook", err),
+    }
+}
@@ -1,5 +1,74 @@
+pub mod access_log;
+pub mod account_exports;
+pub mod admin;
+pub mod admission;
+pub mod audit_exports;
 pub mod auth_handlers;
+pub mod badges;
+pub mod dev_tls;
+pub mod extensions;
+pub mod federation;
+pub mod git;
+pub mod maintenance;
+pub mod mirror_hooks;
+pub mod og_images;
 pub mod playground;
+pub mod registry_proxy;
 pub mod server;
+pub mod share_links;
+pub mod subscriptions;
+pub mod uploads;
+pub mod usage_exports;
 
 pub use server::run_api;
+
+use axum::http::{StatusCode, Uri};
+use axum::response::{IntoResponse, Redirect, Response};
+
+/// Logs why `what` failed and answers with a bare 500, so handlers do not leak internals.
+pub(crate) fn internal_error(what: &str, err: impl Into<anyhow::Error>) -> Response {
+    tracing::error!("{} failed: {:#}", what, err.into());
+    (StatusCode::INTERNAL_SERVER_ERROR, "internal error").into_response()
+}
+
+/// Redirects a request naming the repository `requested` to the same URI with the path
+/// spelled as stored (`canonical`). Slugs match case-insensitively, so `/badge/Team/App/…`
+/// finds `team/app`; `None` when the request already uses the stored spelling.
+pub(crate) fn canonical_redirect(uri: &Uri, requested: &str, canonical: &str) -> Option<Response> {
+    let requested = git_http::repo::split_repository_path(requested).join("/");
+    if requested == canonical {
+        return None;
+    }
+    let path = uri.path().replacen(&requested, canonical, 1);
+    if path == uri.path() {
+        return None;
+    }
+    let location = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+    Some(Redirect::permanent(&location).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header;
+
+    use super::*;
+
+    #[test]
+    fn lookups_in_another_case_redirect_to_the_stored_path() {
+        let uri: Uri = "/Team/App.git/info/refs?service=git-upload-pack"
+            .parse()
+            .unwrap();
+        let response = canonical_redirect(&uri, "Team/App", "team/app").unwrap();
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "/team/app.git/info/refs?service=git-upload-pack"
+        );
+
+        let uri: Uri = "/badge/team/app/clones.svg".parse().unwrap();
+        assert!(canonical_redirect(&uri, "team/app", "team/app").is_none());
+    }
+}
@@ -0,0 +1,155 @@
+//! `GET /og/{kind}/{*path}`: OpenGraph preview images (see `og`).
+//!
+//! `/og/repository/team/app.png` shows a repository and `/og/issue/team/app/12.png` one
+//! of its issues; the `.png` suffix is optional. Like the pages they preview, the images
+//! are public. The card's content hash is sent as ETag, so crawlers that revalidate get a
+//! `304` until the repository or issue changes.
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode, Uri, header};
+use axum::response::{IntoResponse, Response};
+use serde_json::json;
+
+use super::server::{AppState, GraphQLRequest};
+use super::{canonical_redirect, internal_error};
+use crate::og::{Card, plain_text};
+use crate::repository::descriptions::{list_repository_descriptions, select_description};
+use crate::router::GraphQLExecutionRequest;
+
+const CACHE_CONTROL: &str = "public, max-age=3600";
+
+const ISSUE_QUERY: &str = "query OgIssue($repositoryId: ID!, $issueNumber: Int!) { \
+     getIssue(repositoryId: $repositoryId, issueNumber: $issueNumber) { title description status } }";
+
+pub async fn og_image_handler(
+    State(app_state): State<AppState>,
+    Path((kind, path)): Path<(String, String)>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    let Some(renderer) = app_state.og.clone() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let path = path.strip_suffix(".png").unwrap_or(&path);
+    let repository = match kind.as_str() {
+        "repository" => Some(path),
+        "issue" => path.rsplit_once('/').map(|(repository, _)| repository),
+        _ => None,
+    };
+    if let Some(repository) = repository {
+        match app_state.resolver.resolve_repository(repository).await {
+            Ok(Some(resolved)) => {
+                let canonical = resolved.canonical_path();
+                if let Some(redirect) = canonical_redirect(&uri, repository, &canonical) {
+                    return redirect;
+                }
+            }
+            Ok(None) => {}
+            Err(err) => return internal_error("preview image", err),
+        }
+    }
+    let card = match kind.as_str() {
+        "repository" => repository_card(&app_state, path).await,
+        "issue" => issue_card(&app_state, path).await,
+        _ => Ok(None),
+    };
+    let card = match card {
+        Ok(Some(card)) => card,
+        Ok(None) => {
+            return (StatusCode::NOT_FOUND, "nothing to preview at this path").into_response();
+        }
+        Err(err) => return internal_error("preview image", err),
+    };
+
+    let etag = format!("\"{}\"", renderer.hash(&card));
+    let cache_headers = [
+        (header::CACHE_CONTROL, CACHE_CONTROL.to_string()),
+        (header::ETAG, etag.clone()),
+    ];
+    let revalidated = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag));
+    if revalidated {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+
+    match renderer.render(&card).await {
+        Ok(image) => (
+            cache_headers,
+            [(header::CONTENT_TYPE, "image/png")],
+            image.png,
+        )
+            .into_response(),
+        Err(err) => internal_error("preview image", err),
+    }
+}
+
+async fn repository_card(app_state: &AppState, path: &str) -> anyhow::Result<Option<Card>> {
+    let Some(resolved) = app_state.resolver.resolve_repository(path).await? else {
+        return Ok(None);
+    };
+    let descriptions = list_repository_descriptions(&app_state.pool, &resolved.record.id).await?;
+    let group = &resolved.segments[..resolved.segments.len() - 1];
+    Ok(Some(Card {
+        eyebrow: if group.is_empty() {
+            "Repository".to_string()
+        } else {
+            group.join("/")
+        },
+        title: resolved.record.slug.clone(),
+        description: select_description(&descriptions, &[]).map(|d| d.description.clone()),
+        badge: resolved
+            .record
+            .remote_url
+            .as_ref()
+            .map(|_| "Mirror".to_string()),
+    }))
+}
+
+async fn issue_card(app_state: &AppState, path: &str) -> anyhow::Result<Option<Card>> {
+    let Some((repository, number)) = path.rsplit_once('/') else {
+        return Ok(None);
+    };
+    let Ok(number) = number.parse::<u32>() else {
+        return Ok(None);
+    };
+    let Some(resolved) = app_state.resolver.resolve_repository(repository).await? else {
+        return Ok(None);
+    };
+
+    // Issues belong to the issues extension, so they are read through the router like any
+    // other client would.
+    let request = GraphQLExecutionRequest::from_payload(&GraphQLRequest {
+        query: ISSUE_QUERY.to_string(),
+        operation_name: None,
+        variables: json!({ "repositoryId": resolved.record.id, "issueNumber": number }),
+    })?;
+    let response = match app_state.router.current().execute(request).await {
+        Ok(response) => response,
+        Err(err) => {
+            // Most likely the issues extension is not loaded.
+            tracing::debug!("issue preview for {} unavailable: {:#}", path, err);
+            return Ok(None);
+        }
+    };
+    let issue = &response["data"]["getIssue"];
+    let Some(title) = issue["title"].as_str() else {
+        return Ok(None);
+    };
+    let status = match issue["status"].as_str() {
+        Some("OPEN") => Some("Open"),
+        Some("CLOSED") => Some("Closed"),
+        Some("IN_PROGRESS") => Some("In progress"),
+        _ => None,
+    };
+    Ok(Some(Card {
+        eyebrow: format!("{}#{}", resolved.canonical_path(), number),
+        title: title.to_string(),
+        description: issue["description"]
+            .as_str()
+            .map(plain_text)
+            .filter(|text| !text.is_empty()),
+        badge: status.map(str::to_string),
+    }))
+}
@@ -0,0 +1,224 @@
+//! `GET /v2/...`: the pull side of the OCI distribution API, served by the registry
+//! proxy.
+//!
+//! Clients authenticate with a personal access token, as a bearer token or as either half
+//! of basic credentials, unless `allow_anonymous` is set. Errors use the registry's JSON
+//! error shape so OCI clients can report them. See [`crate::extensions::registry_proxy`].
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde_json::json;
+
+use super::server::{AppState, bearer_credential};
+use super::share_links::basic_auth_token;
+use crate::extensions::registry_proxy::{ProxyObject, RegistryProxy, parse_route};
+use crate::user::security::{TOKEN_PREFIX, authenticate_access_token};
+
+const API_VERSION_HEADER: HeaderName = HeaderName::from_static("docker-distribution-api-version");
+const DIGEST_HEADER: HeaderName = HeaderName::from_static("docker-content-digest");
+
+fn registry_error(status: StatusCode, code: &str, message: &str) -> Response {
+    let mut response = (
+        status,
+        axum::Json(json!({ "errors": [{ "code": code, "message": message }] })),
+    )
+        .into_response();
+    let headers = response.headers_mut();
+    headers.insert(API_VERSION_HEADER, HeaderValue::from_static("registry/2.0"));
+    if status == StatusCode::UNAUTHORIZED {
+        headers.insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"forge\""),
+        );
+    }
+    response
+}
+
+/// Checks the caller's credentials; `Err` holds the response to send instead.
+async fn authorize(
+    app_state: &AppState,
+    proxy: &RegistryProxy,
+    headers: &HeaderMap,
+) -> Result<(), Response> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(bearer_credential)
+        .map(str::to_string)
+        .or_else(|| basic_auth_token(headers, TOKEN_PREFIX));
+    let user = match token {
+        Some(token) => match authenticate_access_token(&app_state.pool, &token).await {
+            Ok(user) => user,
+            Err(err) => {
+                tracing::error!("registry proxy authentication failed: {:#}", err);
+                return Err(registry_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "UNKNOWN",
+                    "internal error",
+                ));
+            }
+        },
+        None => None,
+    };
+    match user {
+        Some(user) if proxy.allows(&user.did) => Ok(()),
+        Some(_) => Err(registry_error(
+            StatusCode::FORBIDDEN,
+            "DENIED",
+            "this account may not pull through the registry proxy",
+        )),
+        None if proxy.allows_anonymous() => Ok(()),
+        None => Err(registry_error(
+            StatusCode::UNAUTHORIZED,
+            "UNAUTHORIZED",
+            "a personal access token is required",
+        )),
+    }
+}
+
+/// `GET /v2/`: tells clients the API is here and whether they need credentials.
+pub async fn registry_base_handler(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(proxy) = app_state.registry_proxy.clone() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(response) = authorize(&app_state, &proxy, &headers).await {
+        return response;
+    }
+    (
+        [(API_VERSION_HEADER, "registry/2.0")],
+        axum::Json(json!({})),
+    )
+        .into_response()
+}
+
+/// `GET /v2/<upstream>/<image>/manifests/<reference>` and
+/// `GET /v2/<upstream>/<image>/blobs/<digest>`. Blobs honour an open-ended byte range so
+/// interrupted downloads can resume.
+pub async fn registry_proxy_handler(
+    State(app_state): State<AppState>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(proxy) = app_state.registry_proxy.clone() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(response) = authorize(&app_state, &proxy, &headers).await {
+        return response;
+    }
+    let Some(route) = parse_route(&path) else {
+        return registry_error(StatusCode::NOT_FOUND, "NAME_INVALID", "unsupported path");
+    };
+    if !proxy.has_upstream(&route.upstream) {
+        return registry_error(
+            StatusCode::NOT_FOUND,
+            "NAME_UNKNOWN",
+            &format!("no upstream registry is proxied as `{}`", route.upstream),
+        );
+    }
+
+    let (fetched, is_blob) = match &route.object {
+        ProxyObject::Manifest(reference) => (
+            proxy
+                .manifest(&route.upstream, &route.image, reference)
+                .await,
+            false,
+        ),
+        ProxyObject::Blob(digest) => (
+            proxy.blob(&route.upstream, &route.image, digest).await,
+            true,
+        ),
+    };
+    let object = match fetched {
+        Ok(object) => object,
+        Err(err) => {
+            tracing::warn!("registry proxy could not serve {}: {:#}", path, err);
+            return registry_error(
+                StatusCode::BAD_GATEWAY,
+                "UNKNOWN",
+                "the upstream registry could not provide this object",
+            );
+        }
+    };
+
+    let total = object.data.len();
+    let start = if is_blob {
+        match requested_offset(&headers) {
+            Some(start) if start >= total => {
+                let mut response = StatusCode::RANGE_NOT_SATISFIABLE.into_response();
+                if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", total)) {
+                    response.headers_mut().insert(header::CONTENT_RANGE, value);
+                }
+                return response;
+            }
+            Some(start) => start,
+            None => 0,
+        }
+    } else {
+        0
+    };
+    let content_type = object
+        .media_type
+        .clone()
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let status = if start > 0 {
+        StatusCode::PARTIAL_CONTENT
+    } else {
+        StatusCode::OK
+    };
+    let mut response = (status, object.data[start..].to_vec()).into_response();
+    let response_headers = response.headers_mut();
+    for (name, value) in [
+        (header::CONTENT_TYPE, content_type),
+        (DIGEST_HEADER, object.digest.clone()),
+        (API_VERSION_HEADER, "registry/2.0".to_string()),
+    ] {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response_headers.insert(name, value);
+        }
+    }
+    if is_blob {
+        response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    }
+    if start > 0
+        && let Ok(value) =
+            HeaderValue::from_str(&format!("bytes {}-{}/{}", start, total - 1, total))
+    {
+        response_headers.insert(header::CONTENT_RANGE, value);
+    }
+    response
+}
+
+/// The start of a `Range: bytes=<start>-` request. Other ranges are ignored and the
+/// whole blob is sent.
+fn requested_offset(headers: &HeaderMap) -> Option<usize> {
+    let range = headers.get(header::RANGE)?.to_str().ok()?;
+    range
+        .trim()
+        .strip_prefix("bytes=")?
+        .strip_suffix('-')?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_open_ended_ranges_resume() {
+        let range = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::RANGE, HeaderValue::from_str(value).unwrap());
+            requested_offset(&headers)
+        };
+        assert_eq!(range("bytes=1024-"), Some(1024));
+        assert_eq!(range("bytes=0-"), Some(0));
+        assert_eq!(range("bytes=0-99"), None);
+        assert_eq!(range("bytes=-500"), None);
+        assert_eq!(requested_offset(&HeaderMap::new()), None);
+    }
+}
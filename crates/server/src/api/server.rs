@@ -1,27 +1,71 @@
 use anyhow::Result;
-use axum::extract::State;
-use axum::http::{HeaderMap, Method, StatusCode, header};
-use axum::routing::{get, post};
+use axum::extract::{DefaultBodyLimit, FromRequest, Multipart, Request, State};
+use axum::http::HeaderValue;
+use axum::http::{Extensions, HeaderMap, Method, StatusCode, header};
+use axum::routing::{any, get, post};
 use axum::{Json, Router};
+use graphql_parser::query::{Definition, Field, OperationDefinition, Selection};
 use serde::Deserialize;
 use serde_json::Value as JsonValue;
+use sqlx::SqlitePool;
 use std::sync::Arc;
 use tokio_util::sync::CancellationToken;
 use tower_http::cors::{Any, CorsLayer};
-use axum::http::HeaderValue;
-use graphql_parser::query::{Definition, OperationDefinition, Selection, Field};
 
+use super::access_log::{AccessLogger, access_log_middleware, client_ip};
+use super::account_exports::account_export_download_handler;
+use super::admin::require_admin;
+use super::admission::{PRIORITY_HEADER, QueryAdmission, QueryClass};
+use super::audit_exports::audit_export_download_handler;
 use super::auth_handlers::{self, AuthState};
+use super::badges::badge_handler;
+use super::extensions::{MAX_WEBHOOK_BYTES, extension_webhook_handler, ui_manifests_handler};
+use super::federation::{
+    FederationState, did_document_handler, federation_user_handler, issue_reference_handler,
+};
+use super::git::GitHttp;
+use super::maintenance::maintenance_middleware;
+use super::mirror_hooks::mirror_webhook_handler;
+use super::og_images::og_image_handler;
+use super::uploads::{UploadBatch, UploadLimits, parse_multipart};
 use super::playground::graphql_playground;
-use crate::router::{GraphQLExecutionRequest, RouterState};
+use super::registry_proxy::{registry_base_handler, registry_proxy_handler};
+use super::share_links::{share_link_handler, share_token};
+use super::subscriptions::graphql_ws_handler;
+use super::usage_exports::usage_export_download_handler;
+use crate::auth::User;
+use crate::auth::viewer::{ViewerSession, with_session, with_viewer};
+use crate::config::AccessLogConfig;
+use crate::extensions::ExtensionManager;
+use crate::extensions::registry_proxy::RegistryProxy;
+use crate::og::OgRenderer;
+use crate::repository::RepositoryStorage;
+use crate::repository::share_links::{verify_share_token, with_share_grant};
+use crate::router::{GraphQLExecutionRequest, LiveRouter};
+use crate::user::security::{TOKEN_PREFIX, authenticate_access_token};
 use axum::response::IntoResponse;
 use std::io;
+use std::net::SocketAddr;
 
 /// Combined application state
 #[derive(Clone)]
 pub struct AppState {
-    pub router: Arc<RouterState>,
+    pub router: Arc<LiveRouter>,
     pub auth: Option<Arc<AuthState>>,
+    pub access_log: Option<Arc<AccessLogger>>,
+    pub pool: SqlitePool,
+    /// The server's path resolver; handlers share its cache and its invalidations
+    pub resolver: PathResolver,
+    pub admission: Arc<QueryAdmission>,
+    /// Present when `federation.enabled` is set
+    pub federation: Option<Arc<FederationState>>,
+    /// Present when `og.enabled` is set
+    pub og: Option<Arc<OgRenderer>>,
+    /// Present when `FORGE_GIT_HTTP_MODE=smart`
+    pub git: Option<GitHttp>,
+    pub extensions: Arc<ExtensionManager>,
+    /// Present when `extensions.registry_proxy` is set
+    pub registry_proxy: Option<Arc<RegistryProxy>>,
 }
 
 /// GraphQL request structure
@@ -34,13 +78,99 @@ pub struct GraphQLRequest {
     pub variables: serde_json::Value,
 }
 
+/// A GraphQL request sent as JSON or as a multipart upload form.
+///
+/// The upload batch must outlive execution: dropping it deletes the uploaded files.
+pub struct GraphQLPayload(pub GraphQLRequest, pub Option<UploadBatch>);
+
+impl<S: Send + Sync> FromRequest<S> for GraphQLPayload {
+    type Rejection = axum::response::Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_multipart = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("multipart/form-data"));
+
+        if !is_multipart {
+            let Json(request) = Json::<GraphQLRequest>::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            return Ok(Self(request, None));
+        }
+
+        let reject = |message: String| {
+            (StatusCode::BAD_REQUEST, Json(graphql_error_body(message))).into_response()
+        };
+        // Browsers send multipart forms cross-site without a CORS preflight; requiring a
+        // custom header forces one, which keeps cookie-authenticated uploads CSRF-safe.
+        let preflighted = ["graphql-preflight", "apollo-require-preflight"]
+            .iter()
+            .any(|name| req.headers().contains_key(*name));
+        if !preflighted {
+            return Err(reject(
+                "multipart requests must set a `GraphQL-Preflight` header".to_string(),
+            ));
+        }
+        let multipart = Multipart::from_request(req, state)
+            .await
+            .map_err(|e| reject(e.body_text()))?;
+        let (operations, batch) = parse_multipart(multipart, UploadLimits::from_env())
+            .await
+            .map_err(|e| reject(format!("invalid multipart request: {e:#}")))?;
+        let request = serde_json::from_value(operations)
+            .map_err(|e| reject(format!("invalid `operations`: {e}")))?;
+        Ok(Self(request, Some(batch)))
+    }
+}
+
 pub async fn graphql_handler(
     State(app_state): State<AppState>,
     headers: HeaderMap,
-    Json(req): Json<GraphQLRequest>,
-) -> Json<JsonValue> {
+    extensions: Extensions,
+    GraphQLPayload(req, _uploads): GraphQLPayload,
+) -> axum::response::Response {
+    let class = QueryClass::classify(
+        headers.get(PRIORITY_HEADER).and_then(|v| v.to_str().ok()),
+        &root_fields(&req.query),
+    );
+    let _permit = match app_state.admission.acquire(class).await {
+        Ok(permit) => permit,
+        Err(shed) => {
+            let mut body = graphql_error_body(shed.message().to_string());
+            body["errors"][0]["extensions"] = serde_json::json!({ "code": "SERVER_BUSY" });
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, "1")],
+                Json(body),
+            )
+                .into_response();
+        }
+    };
+    execute_graphql(app_state, headers, extensions, req).await
+}
+
+async fn execute_graphql(
+    app_state: AppState,
+    headers: HeaderMap,
+    extensions: Extensions,
+    req: GraphQLRequest,
+) -> axum::response::Response {
+    // Standby instances serve reads only; see `coordination`.
+    if let Err(err) = crate::coordination::ensure_writable()
+        && is_mutation(&req.query)
+    {
+        return Json(graphql_error_body(err.to_string())).into_response();
+    }
+
+    let (viewer, session) = match request_viewer(&app_state, &headers).await {
+        Ok(viewer) => viewer,
+        Err(response) => return response,
+    };
+
     // If auth is configured, enforce authentication for protected mutations
-    if let Some(auth_state) = &app_state.auth {
+    if app_state.auth.is_some() {
         if let Ok(document) = graphql_parser::parse_query::<String>(&req.query) {
             // Find the first operation (or matching by name)
             let op = document.definitions.iter().find_map(|d| match d {
@@ -55,34 +185,90 @@ pub async fn graphql_handler(
                     if let Selection::Field(Field { name, .. }) = sel { requested_fields.push(name.clone()); }
                 }
                 // Mutations that require an authenticated session
-                let protected: [&str; 5] = [
+                const PROTECTED: &[&str] = &[
                     "createRepository",
                     "linkRemoteRepository",
+                    "importRepositoryFromPath",
+                    "importRepositoryBundle",
                     "createGroup",
                     "createIssue",
                     "updateIssue",
+                    "deleteIssue",
+                    "saveIssueTemplate",
+                    "deleteIssueTemplate",
+                    "createLabel",
+                    "assignLabel",
+                    "unassignLabel",
+                    "createMilestone",
+                    "addComment",
+                    "editComment",
+                    "deleteComment",
+                    "starRepository",
+                    "unstarRepository",
+                    "setRepositoryPolicy",
+                    "setRepositorySettings",
+                    "setGroupSettings",
+                    "setIssueClosing",
+                    "setRepositoryDescription",
+                    "setRepositoryProjectRoots",
+                    "saveSearch",
+                    "deleteSavedSearch",
+                    "watchRemoteTags",
+                    "unwatchRemoteTags",
+                    "rotateMirrorWebhookSecret",
+                    "disableMirrorWebhook",
+                    "setRepositorySecret",
+                    "deleteRepositorySecret",
+                    "deleteRepository",
+                    "renameRepository",
+                    "transferRepository",
+                    "cherryPickCommit",
+                    "revertCommit",
+                    "applySuggestion",
+                    "requestAccountExport",
+                    "requestAuditLogExport",
+                    "revokeSession",
+                    "addSshKey",
+                    "removeSshKey",
+                    "createAccessToken",
+                    "revokeAccessToken",
+                    "registerPushSubscription",
+                    "unregisterPushSubscription",
+                    "setNotificationDigestFrequency",
+                    "createAnnouncement",
+                    "deleteAnnouncement",
+                    "setMaintenanceMode",
+                    "setFeatureFlag",
+                    "deleteFeatureFlag",
+                    "setFeatureFlagOverride",
+                    "updateInstanceSettings",
+                    "repairRepository",
+                    "rebuildSearchIndex",
+                    "reloadExtension",
+                    "approveModerationItem",
+                    "removeModerationItem",
+                    "createShareLink",
+                    "revokeShareLink",
+                    "createWebhook",
+                    "updateWebhook",
+                    "deleteWebhook",
+                    "testWebhook",
+                    "redeliverWebhookDelivery",
                 ];
-                let needs_auth = requested_fields.iter().any(|f| protected.contains(&f.as_str()));
+                let needs_auth = requested_fields
+                    .iter()
+                    .any(|f| PROTECTED.contains(&f.as_str()));
                 if needs_auth {
-                    // Extract forge_session cookie and verify session exists
-                    let mut authed = false;
-                    if let Some(cookie_hdr) = headers.get(header::COOKIE).and_then(|v| v.to_str().ok()) {
-                        if let Some(session_id) = parse_cookie(cookie_hdr, "forge_session") {
-                            if auth_state.session_manager.get_session(&session_id).ok().flatten().is_some() {
-                                authed = true;
-                            }
-                        }
-                    }
-                    if !authed {
+                    if viewer.is_none() {
                         let msg = format!(
                             "Authentication required for mutations: {}",
                             requested_fields
                                 .into_iter()
-                                .filter(|f| protected.contains(&f.as_str()))
+                                .filter(|f| PROTECTED.contains(&f.as_str()))
                                 .collect::<Vec<_>>()
                                 .join(", ")
                         );
-                        return Json(graphql_error_body(msg));
+                        return Json(graphql_error_body(msg)).into_response();
                     }
                 }
             }
@@ -90,12 +276,115 @@ pub async fn graphql_handler(
     }
     let exec_request = match GraphQLExecutionRequest::from_payload(&req) {
         Ok(req) => req,
-        Err(err) => return Json(graphql_error_body(err.to_string())),
+        Err(err) => return Json(graphql_error_body(err.to_string())).into_response(),
     };
 
-    match app_state.router.execute(exec_request).await {
-        Ok(json) => Json(json),
-        Err(err) => Json(graphql_error_body(err.to_string())),
+    // Signed-in viewers are limited per account, everyone else per address.
+    let caller = match &viewer {
+        Some(user) => user.did.clone(),
+        None => {
+            let trust_forwarded_for = crate::config::current()
+                .access_log
+                .as_ref()
+                .is_some_and(|log| log.trust_forwarded_for);
+            client_ip(trust_forwarded_for, &headers, &extensions)
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|| "anonymous".to_string())
+        }
+    };
+    // The whole request runs against one state, even if an extension is reloaded meanwhile
+    let router = app_state.router.current();
+    let policy = router.operation_policy(&exec_request);
+    if let Err(limited) = router.charge(&caller, &policy) {
+        let mut body = graphql_error_body(limited.to_string());
+        body["errors"][0]["extensions"] = serde_json::json!({
+            "code": "RATE_LIMITED",
+            "field": limited.coordinate,
+            "retryAfter": limited.retry_after_secs,
+        });
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, limited.retry_after_secs.to_string())],
+            Json(body),
+        )
+            .into_response();
+    }
+
+    let client = crate::router::usage::client_label(
+        headers
+            .get(crate::router::usage::CLIENT_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    );
+    // A share link lets the request read the repository it was made for
+    let share = match share_token(&headers) {
+        Some(token) => match verify_share_token(&app_state.pool, &token).await {
+            Ok(grant) => grant,
+            Err(err) => return Json(graphql_error_body(err.to_string())).into_response(),
+        },
+        None => None,
+    };
+    let execution = with_session(session, router.execute_raw(exec_request));
+    // Counts an API call for each metered group whose repositories the request resolves
+    let execution = crate::metering::metered_calls(execution);
+    let result = with_share_grant(share, with_viewer(viewer, execution)).await;
+    if result.is_ok() {
+        crate::router::usage::recorder().record(&client, &policy, crate::user::db::unix_now());
+    }
+    match result {
+        // The executor's bytes are sent as they are; large lists are not parsed again.
+        // Partial results and errors are never cached.
+        Ok(response) => {
+            let json = [(header::CONTENT_TYPE, "application/json")];
+            match policy.cache {
+                Some(cache) if !response.has_errors() => (
+                    json,
+                    [(header::CACHE_CONTROL, cache.header_value())],
+                    response.into_bytes(),
+                )
+                    .into_response(),
+                _ => (json, response.into_bytes()).into_response(),
+            }
+        }
+        Err(err) => Json(graphql_error_body(err.to_string())).into_response(),
+    }
+}
+
+/// Who a request is made by. Browser sessions come from the `forge_session` cookie; API
+/// clients send a personal access token instead. A token that is not valid is refused
+/// with a response to send back.
+pub(crate) async fn request_viewer(
+    app_state: &AppState,
+    headers: &HeaderMap,
+) -> Result<(Option<User>, Option<ViewerSession>), axum::response::Response> {
+    let session = app_state.auth.as_ref().and_then(|auth_state| {
+        let cookie_hdr = headers.get(header::COOKIE)?.to_str().ok()?;
+        let session_id = parse_cookie(cookie_hdr, "forge_session")?;
+        let user = auth_state
+            .session_manager
+            .touch(&session_id)
+            .ok()
+            .flatten()?;
+        Some((
+            user,
+            ViewerSession {
+                id: session_id,
+                sessions: auth_state.session_manager.clone(),
+            },
+        ))
+    });
+    match session {
+        Some((user, session)) => Ok((Some(user), Some(session))),
+        None => match bearer_token(headers) {
+            Some(token) => match authenticate_access_token(&app_state.pool, token).await {
+                Ok(Some(user)) => Ok((Some(user), None)),
+                Ok(None) => {
+                    let body = graphql_error_body("invalid or expired access token".to_string());
+                    Err((StatusCode::UNAUTHORIZED, Json(body)).into_response())
+                }
+                Err(err) => Err(Json(graphql_error_body(err.to_string())).into_response()),
+            },
+            None => Ok((None, None)),
+        },
     }
 }
 
@@ -103,10 +392,46 @@ pub async fn graphql_options() -> StatusCode {
     StatusCode::NO_CONTENT
 }
 
+/// Prometheus text exposition of everything recorded through the `metrics` crate.
+pub async fn metrics_handler() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        crate::metrics_exporter::render_metrics(),
+    )
+}
+
 pub fn build_api_router(app_state: AppState) -> Router {
     let mut router = Router::new()
         .route("/", get(graphql_playground))
-        .route("/graphql", post(graphql_handler).options(graphql_options));
+        .route("/metrics", get(metrics_handler))
+        .route("/hooks/mirror/{*path}", post(mirror_webhook_handler))
+        .route(
+            "/account/exports/{id}",
+            get(account_export_download_handler),
+        )
+        .route("/audit/exports/{id}", get(audit_export_download_handler))
+        .route("/usage/exports/{id}", get(usage_export_download_handler))
+        .route("/share/{token}", get(share_link_handler))
+        .route("/og/{kind}/{*path}", get(og_image_handler))
+        .route("/badge/{*path}", get(badge_handler))
+        .route("/extensions/ui-manifests", get(ui_manifests_handler))
+        .route(
+            "/extensions/{name}/hooks",
+            any(extension_webhook_handler).layer(DefaultBodyLimit::max(MAX_WEBHOOK_BYTES)),
+        )
+        .route(
+            "/extensions/{name}/hooks/{*path}",
+            any(extension_webhook_handler).layer(DefaultBodyLimit::max(MAX_WEBHOOK_BYTES)),
+        )
+        .route(
+            "/graphql",
+            post(graphql_handler)
+                .get(graphql_ws_handler)
+                .options(graphql_options)
+                .layer(DefaultBodyLimit::max(
+                    UploadLimits::from_env().max_body_bytes(),
+                )),
+        );
 
     // Add auth routes if auth is configured
     if app_state.auth.is_some() {
@@ -120,15 +445,40 @@ pub fn build_api_router(app_state: AppState) -> Router {
             .route("/admin/auth/vacuum", get(auth_vacuum_handler));
     }
 
+    if app_state.federation.is_some() {
+        router = router
+            .route("/.well-known/did.json", get(did_document_handler))
+            .route("/federation/users/{did}", get(federation_user_handler))
+            .route(
+                "/federation/issue-references",
+                post(issue_reference_handler),
+            );
+    }
+
+    if app_state.registry_proxy.is_some() {
+        router = router
+            .route("/v2/", get(registry_base_handler))
+            .route("/v2/{*path}", get(registry_proxy_handler));
+    }
+
     // Client metadata endpoint for dynamic OAuth public client
     if app_state.auth.is_some() {
         router = router.route("/client-metadata.json", get(auth_client_metadata_handler));
     }
 
-    // Configure CORS from env (comma-separated origins). Default: allow Any for dev.
-    let cors_layer = if let Ok(origins) = std::env::var("FORGE_CORS_ORIGINS") {
+    // Static routes take precedence over the repository catch-all.
+    if let Some(git) = app_state.git.clone() {
+        let admin_only = axum::middleware::from_fn_with_state(app_state.clone(), require_admin);
+        router = router
+            .merge(git.clone().admin_routes().layer(admin_only))
+            .merge(git.routes());
+    }
+
+    // Configure CORS from `server.cors_origins`. Default: allow Any for dev.
+    let origins = &crate::config::current().server.cors_origins;
+    let cors_layer = if !origins.is_empty() {
         let values: Vec<HeaderValue> = origins
-            .split(',')
+            .iter()
             .filter_map(|s| HeaderValue::from_str(s.trim()).ok())
             .collect();
         CorsLayer::new()
@@ -138,7 +488,7 @@ pub fn build_api_router(app_state: AppState) -> Router {
             .allow_credentials(true)
     } else {
         // Default permissive CORS for development; cookies may not be accepted by browsers
-        // unless FORGE_CORS_ORIGINS is set to a specific origin.
+        // unless server.cors_origins lists specific origins.
         CorsLayer::new()
             .allow_origin(Any)
             .allow_headers(Any)
@@ -146,7 +496,14 @@ pub fn build_api_router(app_state: AppState) -> Router {
             .allow_credentials(true)
     };
 
-    router.layer(cors_layer).with_state(app_state)
+    let maintenance =
+        axum::middleware::from_fn_with_state(app_state.clone(), maintenance_middleware);
+    let access_log = axum::middleware::from_fn_with_state(app_state.clone(), access_log_middleware);
+    router
+        .layer(maintenance)
+        .layer(cors_layer)
+        .layer(access_log)
+        .with_state(app_state)
 }
 // Wrapper handlers that extract auth state from AppState
 async fn auth_login_handler(
@@ -188,7 +545,9 @@ async fn auth_callback_handler(
     headers: axum::http::HeaderMap,
 ) -> axum::response::Response {
     if let Some(auth_state) = app_state.auth {
-        auth_handlers::callback_handler(State(auth_state), query, headers).await.into_response()
+        auth_handlers::callback_handler(State(auth_state), app_state.pool, query, headers)
+            .await
+            .into_response()
     } else {
         (StatusCode::NOT_FOUND, "Authentication not configured").into_response()
     }
@@ -215,23 +574,56 @@ async fn auth_client_metadata_handler(State(app_state): State<AppState>) -> axum
 }
 
 pub async fn run_api(
-    router_state: Arc<RouterState>,
+    router_state: Arc<LiveRouter>,
     auth_state: Option<Arc<AuthState>>,
+    access_log: Option<AccessLogConfig>,
+    pool: SqlitePool,
+    storage: RepositoryStorage,
+    resolver: PathResolver,
+    extensions: Arc<ExtensionManager>,
     shutdown: CancellationToken,
 ) -> Result<()> {
+    let access_log = match access_log {
+        Some(config) => Some(Arc::new(AccessLogger::from_config(&config).await?)),
+        None => None,
+    };
+    let config = crate::config::current();
+    let federation = if config.federation.enabled {
+        let state =
+            FederationState::from_config(&config.federation, &config.server.public_base_url)?;
+        tracing::info!("Federation enabled as {}", state.key.did());
+        Some(Arc::new(state))
+    } else {
+        None
+    };
+    let og = config
+        .og
+        .enabled
+        .then(|| Arc::new(OgRenderer::from_config(&config.og)));
+    let git = GitHttp::from_env(storage, pool.clone(), resolver.clone(), auth_state.clone());
+    let registry_proxy =
+        RegistryProxy::from_config(&config.extensions, pool.clone())?.map(Arc::new);
+    if let Some(proxy) = &config.extensions.registry_proxy {
+        tracing::info!(
+            "Registry proxy serving {} upstream(s) under /v2/",
+            proxy.upstreams.len()
+        );
+    }
     let app_state = AppState {
         router: router_state,
         auth: auth_state,
+        access_log,
+        pool,
+        resolver,
+        admission: Arc::new(QueryAdmission::from_config(&config.queries)),
+        federation,
+        og,
+        git,
+        extensions,
+        registry_proxy,
     };
 
-    let default_addr = "0.0.0.0:8000".to_string();
-
-    let configured_addr = std::env::var("FORGE_API_ADDR")
-        .or_else(|_| {
-            std::env::var("FORGE_API_PORT").map(|port| format!("0.0.0.0:{}", port))
-        })
-        .or_else(|_| std::env::var("PORT").map(|port| format!("0.0.0.0:{}", port)))
-        .unwrap_or(default_addr);
+    let configured_addr = crate::config::current().server.bind_addr.clone();
 
     let listener = match tokio::net::TcpListener::bind(&configured_addr).await {
         Ok(listener) => listener,
@@ -249,7 +641,17 @@ pub async fn run_api(
         tracing::info!("Forge API listening on {}", addr);
     }
 
-    axum::serve(listener, build_api_router(app_state))
+    let app = build_api_router(app_state);
+    let dev_tls = &crate::config::current().server.dev_tls;
+    if dev_tls.enabled {
+        return super::dev_tls::serve(app, listener, dev_tls, shutdown).await;
+    }
+
+    // Connection info feeds the client address in the access log
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
         .with_graceful_shutdown(shutdown.cancelled_owned())
         .await?;
     Ok(())
@@ -265,12 +667,60 @@ fn graphql_error_body(message: String) -> JsonValue {
     )]))
 }
 
-fn parse_cookie(cookies: &str, name: &str) -> Option<String> {
+fn is_mutation(query: &str) -> bool {
+    graphql_parser::parse_query::<String>(query)
+        .map(|document| {
+            document
+                .definitions
+                .iter()
+                .any(|d| matches!(d, Definition::Operation(OperationDefinition::Mutation(_))))
+        })
+        .unwrap_or(false)
+}
+
+/// Top-level field names of the first operation, or none if the query does not parse.
+fn root_fields(query: &str) -> Vec<String> {
+    let Ok(document) = graphql_parser::parse_query::<String>(query) else {
+        return Vec::new();
+    };
+    let selection_set = document.definitions.iter().find_map(|d| match d {
+        Definition::Operation(OperationDefinition::Query(query)) => Some(&query.selection_set),
+        Definition::Operation(OperationDefinition::Mutation(mutation)) => {
+            Some(&mutation.selection_set)
+        }
+        Definition::Operation(OperationDefinition::SelectionSet(set)) => Some(set),
+        _ => None,
+    });
+    selection_set
+        .map(|set| {
+            set.items
+                .iter()
+                .filter_map(|sel| match sel {
+                    Selection::Field(Field { name, .. }) => Some(name.clone()),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn parse_cookie(cookies: &str, name: &str) -> Option<String> {
     cookies
         .split(';')
         .map(|c| c.trim())
         .find_map(|c| c.strip_prefix(&format!("{}=", name)).map(|v| v.to_string()))
 }
+
+/// A personal access token sent as `Authorization: Bearer forge_pat_...`.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    bearer_credential(headers.get(header::AUTHORIZATION)?.to_str().ok()?)
+}
+
+/// The personal access token in an `Authorization` value.
+pub(crate) fn bearer_credential(value: &str) -> Option<&str> {
+    let token = value.strip_prefix("Bearer ")?.trim();
+    token.starts_with(TOKEN_PREFIX).then_some(token)
+}
 async fn auth_me_handler(State(app_state): State<AppState>, headers: axum::http::HeaderMap) -> axum::response::Response {
     if let Some(auth_state) = app_state.auth {
         auth_handlers::me_handler(State(auth_state), headers).await.into_response()
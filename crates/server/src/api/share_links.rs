@@ -0,0 +1,172 @@
+//! `GET /share/{token}`: opens a repository share link.
+//!
+//! The token is checked, the use is counted, and the browser is sent to the repository
+//! with a `forge_share` cookie that lasts as long as the link. See
+//! [`crate::repository::share_links`].
+
+use axum::extract::{Path, State};
+use axum::http::{Extensions, HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use super::access_log::client_ip;
+use super::server::{AppState, parse_cookie};
+use crate::repository::queries::{get_repository_by_id, reconstruct_repository_path};
+use crate::repository::share_links::{
+    COOKIE_NAME, HEADER_NAME, ShareLinkAction, TOKEN_PREFIX, fetch_share_link, record_share_use,
+    verify_share_token,
+};
+use crate::user::db::unix_now;
+
+/// A share token sent as the `forge_share` cookie or the `X-Forge-Share-Token` header.
+pub(crate) fn share_token(headers: &HeaderMap) -> Option<String> {
+    if let Some(token) = headers.get(HEADER_NAME).and_then(|v| v.to_str().ok()) {
+        return Some(token.trim().to_string());
+    }
+    let cookies = headers.get(header::COOKIE)?.to_str().ok()?;
+    parse_cookie(cookies, COOKIE_NAME)
+}
+
+/// A share token sent by git as either half of HTTP basic credentials.
+pub(crate) fn basic_auth_share_token(headers: &HeaderMap) -> Option<String> {
+    basic_auth_token(headers, TOKEN_PREFIX)
+}
+
+/// Either half of HTTP basic credentials that starts with `prefix`.
+pub(crate) fn basic_auth_token(headers: &HeaderMap, prefix: &str) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let decoded = STANDARD.decode(value.strip_prefix("Basic ")?.trim()).ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let (user, password) = credentials.split_once(':')?;
+    [password, user]
+        .into_iter()
+        .find(|part| part.starts_with(prefix))
+        .map(str::to_string)
+}
+
+/// The caller's address as the access log would record it.
+pub(crate) fn share_client(headers: &HeaderMap, extensions: &Extensions) -> Option<String> {
+    let trust_forwarded_for = crate::config::current()
+        .access_log
+        .as_ref()
+        .is_some_and(|log| log.trust_forwarded_for);
+    client_ip(trust_forwarded_for, headers, extensions).map(|ip| ip.to_string())
+}
+
+pub async fn share_link_handler(
+    State(app_state): State<AppState>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+    extensions: Extensions,
+) -> Response {
+    let opened = async {
+        let Some(grant) = verify_share_token(&app_state.pool, &token).await? else {
+            return anyhow::Ok(None);
+        };
+        let Some(link) = fetch_share_link(&app_state.pool, &grant.link_id).await? else {
+            return Ok(None);
+        };
+        let Some(record) = get_repository_by_id(&app_state.pool, &grant.repository_id).await?
+        else {
+            return Ok(None);
+        };
+        let path = reconstruct_repository_path(&app_state.pool, &record).await?;
+        if !crate::coordination::is_read_only() {
+            let client = share_client(&headers, &extensions);
+            record_share_use(
+                &app_state.pool,
+                &grant,
+                ShareLinkAction::Open,
+                client.as_deref(),
+            )
+            .await?;
+        }
+        Ok(Some((path, link.expires_at)))
+    };
+    // Unknown, expired and revoked links are indistinguishable to the caller.
+    let (path, expires_at) = match opened.await {
+        Ok(Some(opened)) => opened,
+        Ok(None) => {
+            return (StatusCode::NOT_FOUND, "share link is invalid or expired").into_response();
+        }
+        Err(err) => {
+            tracing::error!("opening share link failed: {:#}", err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "internal error").into_response();
+        }
+    };
+
+    let secure = std::env::var("FORGE_COOKIE_SECURE")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or_else(|| {
+            crate::config::current()
+                .server
+                .public_base_url
+                .starts_with("https://")
+        });
+    let mut cookie = format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}",
+        COOKIE_NAME,
+        token,
+        (expires_at - unix_now()).max(0)
+    );
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    // The web UI may be served from another origin than the API
+    let web_base = std::env::var("FORGE_WEB_BASE_URL").unwrap_or_default();
+    let location = format!("{}/{}", web_base.trim_end_matches('/'), path);
+    let mut response = StatusCode::SEE_OTHER.into_response();
+    let headers = response.headers_mut();
+    for (name, value) in [
+        (header::SET_COOKIE, cookie),
+        (header::LOCATION, location),
+        (header::CACHE_CONTROL, "no-store".to_string()),
+    ] {
+        match HeaderValue::from_str(&value) {
+            Ok(value) => {
+                headers.insert(name, value);
+            }
+            Err(_) => return (StatusCode::BAD_REQUEST, "invalid share link").into_response(),
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_read_from_either_credential() {
+        let token = format!("{}abc", TOKEN_PREFIX);
+        let mut headers = HeaderMap::new();
+        let basic = STANDARD.encode(format!("share:{}", token));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Basic {}", basic)).unwrap(),
+        );
+        assert_eq!(basic_auth_share_token(&headers), Some(token.clone()));
+
+        let basic = STANDARD.encode(format!("{}:", token));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Basic {}", basic)).unwrap(),
+        );
+        assert_eq!(basic_auth_share_token(&headers), Some(token.clone()));
+
+        let basic = STANDARD.encode("alice:hunter2");
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Basic {}", basic)).unwrap(),
+        );
+        assert_eq!(basic_auth_share_token(&headers), None);
+
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_str(&format!("forge_session=s; forge_share={}", token)).unwrap(),
+        );
+        assert_eq!(share_token(&headers), Some(token));
+    }
+}
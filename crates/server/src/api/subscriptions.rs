@@ -0,0 +1,300 @@
+//! `GET /graphql`: GraphQL subscriptions over WebSocket.
+//!
+//! Speaks `graphql-transport-ws`, the protocol of the `graphql-ws` client library. The
+//! socket is authenticated at the upgrade like `POST /graphql`, by session cookie, access
+//! token or share link. Browsers cannot set headers on a WebSocket, so `connection_init`
+//! may carry `{"authorization": "Bearer forge_pat_..."}` instead. Only subscriptions are
+//! served here; queries and mutations are posted. See [`crate::router::subscriptions`].
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{Value as JsonValue, json};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use super::server::{AppState, GraphQLRequest, bearer_credential, request_viewer};
+use super::share_links::share_token;
+use crate::auth::User;
+use crate::auth::viewer::with_viewer;
+use crate::repository::share_links::{ShareGrant, verify_share_token, with_share_grant};
+use crate::router::{GraphQLExecutionRequest, RouterState};
+use crate::user::security::authenticate_access_token;
+
+pub const PROTOCOL: &str = "graphql-transport-ws";
+
+/// How long a client has to send `connection_init`.
+const INIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Subscriptions one socket may run at once.
+const MAX_SUBSCRIPTIONS: usize = 64;
+
+/// Messages a client sends.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    ConnectionInit {
+        #[serde(default)]
+        payload: Option<JsonValue>,
+    },
+    Ping {
+        #[serde(default)]
+        payload: Option<JsonValue>,
+    },
+    Pong {
+        #[serde(default)]
+        payload: Option<JsonValue>,
+    },
+    Subscribe {
+        id: String,
+        payload: SubscribePayload,
+    },
+    Complete {
+        id: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SubscribePayload {
+    query: String,
+    #[serde(default)]
+    operation_name: Option<String>,
+    #[serde(default)]
+    variables: JsonValue,
+}
+
+pub async fn graphql_ws_handler(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let (viewer, _session) = match request_viewer(&app_state, &headers).await {
+        Ok(viewer) => viewer,
+        Err(response) => return response,
+    };
+    let share = match share_token(&headers) {
+        Some(token) => match verify_share_token(&app_state.pool, &token).await {
+            Ok(grant) => grant,
+            Err(err) => {
+                tracing::error!("checking share link failed: {:#}", err);
+                return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        },
+        None => None,
+    };
+    upgrade
+        .protocols([PROTOCOL])
+        .on_upgrade(move |socket| serve(app_state, socket, viewer, share))
+}
+
+async fn serve(
+    app_state: AppState,
+    socket: WebSocket,
+    mut viewer: Option<User>,
+    share: Option<ShareGrant>,
+) {
+    let (mut sink, mut stream) = socket.split();
+    // Subscription tasks send through here, so only this loop writes to the socket
+    let (outgoing, mut queued) = mpsc::channel::<Message>(MAX_SUBSCRIPTIONS);
+    let mut running: HashMap<String, JoinHandle<()>> = HashMap::new();
+    let mut acknowledged = false;
+    let init_timeout = tokio::time::sleep(INIT_TIMEOUT);
+    tokio::pin!(init_timeout);
+
+    loop {
+        let reply = tokio::select! {
+            _ = &mut init_timeout, if !acknowledged => {
+                Some(close(4408, "Connection initialisation timeout"))
+            }
+            Some(message) = queued.recv() => Some(message),
+            incoming = stream.next() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    // axum answers WebSocket pings itself
+                    Some(Ok(_)) => continue,
+                };
+                match serde_json::from_str::<ClientMessage>(&text) {
+                    Err(err) => Some(close(4400, &format!("Invalid message: {}", err))),
+                    Ok(ClientMessage::ConnectionInit { .. }) if acknowledged => {
+                        Some(close(4429, "Too many initialisation requests"))
+                    }
+                    Ok(ClientMessage::ConnectionInit { payload }) => {
+                        match init_viewer(&app_state, payload.as_ref()).await {
+                            Ok(user) => {
+                                viewer = user.or(viewer.take());
+                                acknowledged = true;
+                                Some(text_message(json!({ "type": "connection_ack" })))
+                            }
+                            Err(()) => Some(close(4403, "Forbidden")),
+                        }
+                    }
+                    Ok(ClientMessage::Ping { .. }) => {
+                        Some(text_message(json!({ "type": "pong" })))
+                    }
+                    Ok(ClientMessage::Pong { .. }) => None,
+                    Ok(ClientMessage::Subscribe { .. }) if !acknowledged => {
+                        Some(close(4401, "Unauthorized"))
+                    }
+                    Ok(ClientMessage::Subscribe { id, payload }) => {
+                        running.retain(|_, task| !task.is_finished());
+                        if running.contains_key(&id) {
+                            let reason = format!("Subscriber for {} already exists", id);
+                            Some(close(4409, &reason))
+                        } else if running.len() >= MAX_SUBSCRIPTIONS {
+                            let reason = "too many subscriptions on this connection";
+                            Some(error_message(&id, reason))
+                        } else {
+                            let task = run_subscription(
+                                app_state.router.current(),
+                                id.clone(),
+                                payload,
+                                outgoing.clone(),
+                            );
+                            let task = with_viewer(viewer.clone(), task);
+                            let task = with_share_grant(share.clone(), task);
+                            running.insert(id, tokio::spawn(task));
+                            None
+                        }
+                    }
+                    Ok(ClientMessage::Complete { id }) => {
+                        if let Some(task) = running.remove(&id) {
+                            task.abort();
+                        }
+                        None
+                    }
+                }
+            }
+        };
+        let Some(reply) = reply else {
+            continue;
+        };
+        let closing = matches!(reply, Message::Close(_));
+        if sink.send(reply).await.is_err() || closing {
+            break;
+        }
+    }
+
+    for task in running.values() {
+        task.abort();
+    }
+}
+
+/// The viewer a `connection_init` payload authenticates, if it carries a token. `Err`
+/// when the token is not valid.
+async fn init_viewer(
+    app_state: &AppState,
+    payload: Option<&JsonValue>,
+) -> Result<Option<User>, ()> {
+    let Some(value) = payload
+        .and_then(|payload| {
+            payload
+                .get("authorization")
+                .or_else(|| payload.get("Authorization"))
+        })
+        .and_then(JsonValue::as_str)
+    else {
+        return Ok(None);
+    };
+    let token = bearer_credential(value).ok_or(())?;
+    match authenticate_access_token(&app_state.pool, token).await {
+        Ok(Some(user)) => Ok(Some(user)),
+        Ok(None) => Err(()),
+        Err(err) => {
+            tracing::error!("authenticating WebSocket connection failed: {:#}", err);
+            Err(())
+        }
+    }
+}
+
+/// Streams one subscription's payloads until it ends, the client completes it or the
+/// socket closes.
+async fn run_subscription(
+    router: Arc<RouterState>,
+    id: String,
+    payload: SubscribePayload,
+    outgoing: mpsc::Sender<Message>,
+) {
+    let request = GraphQLRequest {
+        query: payload.query,
+        operation_name: payload.operation_name,
+        variables: payload.variables,
+    };
+    let started = match GraphQLExecutionRequest::from_payload(&request) {
+        Ok(request) => router.subscribe(request).await,
+        Err(err) => Err(err),
+    };
+    let mut subscription = match started {
+        Ok(subscription) => subscription,
+        Err(err) => {
+            let _ = outgoing.send(error_message(&id, &err.to_string())).await;
+            return;
+        }
+    };
+    while let Some(result) = subscription.next().await {
+        let payload =
+            result.unwrap_or_else(|err| json!({ "errors": [{ "message": err.to_string() }] }));
+        let message = text_message(json!({ "type": "next", "id": id, "payload": payload }));
+        if outgoing.send(message).await.is_err() {
+            return;
+        }
+    }
+    let _ = outgoing
+        .send(text_message(json!({ "type": "complete", "id": id })))
+        .await;
+}
+
+fn text_message(value: JsonValue) -> Message {
+    Message::Text(value.to_string().into())
+}
+
+fn error_message(id: &str, message: &str) -> Message {
+    text_message(json!({ "type": "error", "id": id, "payload": [{ "message": message }] }))
+}
+
+fn close(code: u16, reason: &str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.to_string().into(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_messages_follow_the_protocol() {
+        let message: ClientMessage = serde_json::from_str(
+            r#"{"type":"subscribe","id":"1","payload":{"query":"subscription { issueCreated(path: \"a/b\") { number } }","operationName":null}}"#,
+        )
+        .unwrap();
+        let ClientMessage::Subscribe { id, payload } = message else {
+            panic!("expected subscribe");
+        };
+        assert_eq!(id, "1");
+        assert!(payload.query.starts_with("subscription"));
+        assert!(payload.variables.is_null());
+
+        let message: ClientMessage =
+            serde_json::from_str(r#"{"type":"connection_init","payload":{"authorization":"x"}}"#)
+                .unwrap();
+        assert!(matches!(
+            message,
+            ClientMessage::ConnectionInit { payload: Some(_) }
+        ));
+        assert!(matches!(
+            serde_json::from_str(r#"{"type":"ping"}"#).unwrap(),
+            ClientMessage::Ping { payload: None }
+        ));
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"type":"start","id":"1"}"#).is_err());
+    }
+}
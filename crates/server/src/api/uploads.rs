@@ -0,0 +1,246 @@
+//! GraphQL multipart request support (<https://github.com/jaydenseric/graphql-multipart-request-spec>).
+//!
+//! File parts are streamed to temporary files as they arrive. Each file is then
+//! registered under an opaque id, and the `map` paths in `operations` are replaced with
+//! that id, which is the runtime value of the `Upload` scalar. Core resolvers resolve
+//! ids with [`lookup_upload`]; extensions use the `host-uploads` WIT interface. Files
+//! live exactly as long as the request's [`UploadBatch`].
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+
+use anyhow::{Context, anyhow, bail};
+use axum::extract::Multipart;
+use serde_json::Value as JsonValue;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Clone, Debug)]
+pub struct UploadedFile {
+    pub id: String,
+    pub filename: String,
+    pub content_type: Option<String>,
+    pub size: u64,
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct UploadLimits {
+    pub max_file_bytes: u64,
+    pub max_files: usize,
+}
+
+impl UploadLimits {
+    /// Reads `FORGE_UPLOAD_MAX_FILE_BYTES` (default 25 MiB) and `FORGE_UPLOAD_MAX_FILES`
+    /// (default 10).
+    pub fn from_env() -> Self {
+        Self {
+            max_file_bytes: std::env::var("FORGE_UPLOAD_MAX_FILE_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(25 * 1024 * 1024),
+            max_files: std::env::var("FORGE_UPLOAD_MAX_FILES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+        }
+    }
+
+    /// Request body limit covering every file plus the JSON parts.
+    pub fn max_body_bytes(&self) -> usize {
+        (self.max_file_bytes as usize)
+            .saturating_mul(self.max_files)
+            .saturating_add(1024 * 1024)
+    }
+}
+
+static REGISTRY: LazyLock<Mutex<HashMap<String, UploadedFile>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Uploads received with one request; dropping it deletes the files.
+#[derive(Debug, Default)]
+pub struct UploadBatch {
+    ids: Vec<String>,
+}
+
+impl UploadBatch {
+    fn register(&mut self, file: UploadedFile) {
+        self.ids.push(file.id.clone());
+        if let Ok(mut registry) = REGISTRY.lock() {
+            registry.insert(file.id.clone(), file);
+        }
+    }
+}
+
+impl Drop for UploadBatch {
+    fn drop(&mut self) {
+        let Ok(mut registry) = REGISTRY.lock() else {
+            return;
+        };
+        for id in &self.ids {
+            if let Some(file) = registry.remove(id) {
+                let _ = std::fs::remove_file(&file.path);
+            }
+        }
+    }
+}
+
+/// Metadata for an `Upload` value received by the current request.
+pub fn lookup_upload(id: &str) -> Option<UploadedFile> {
+    REGISTRY.lock().ok()?.get(id).cloned()
+}
+
+fn upload_dir() -> PathBuf {
+    std::env::temp_dir().join("forge-uploads")
+}
+
+/// Parses a multipart GraphQL request into the `operations` JSON with uploads substituted.
+pub async fn parse_multipart(
+    mut multipart: Multipart,
+    limits: UploadLimits,
+) -> anyhow::Result<(JsonValue, UploadBatch)> {
+    let mut operations: Option<JsonValue> = None;
+    let mut map: Option<HashMap<String, Vec<String>>> = None;
+    let mut batch = UploadBatch::default();
+    let mut received = 0usize;
+
+    while let Some(mut field) = multipart.next_field().await? {
+        let name = field.name().unwrap_or_default().to_string();
+        match name.as_str() {
+            "operations" => {
+                let value: JsonValue = serde_json::from_str(&field.text().await?)
+                    .context("`operations` is not valid JSON")?;
+                if value.is_array() {
+                    bail!("batched multipart operations are not supported");
+                }
+                operations = Some(value);
+            }
+            "map" => {
+                map = Some(
+                    serde_json::from_str(&field.text().await?)
+                        .context("`map` must be an object of file keys to paths")?,
+                );
+            }
+            key => {
+                let (Some(operations), Some(map)) = (operations.as_mut(), map.as_ref()) else {
+                    bail!("`operations` and `map` must precede file parts");
+                };
+                let Some(paths) = map.get(key) else {
+                    bail!("file part `{}` is missing from `map`", key);
+                };
+                received += 1;
+                if received > limits.max_files {
+                    bail!("too many files; at most {} per request", limits.max_files);
+                }
+
+                let id = cuid2::create_id();
+                let path = upload_dir().join(&id);
+                tokio::fs::create_dir_all(upload_dir()).await?;
+                let mut out = tokio::fs::File::create(&path).await?;
+                let filename = field.file_name().unwrap_or(key).to_string();
+                let content_type = field.content_type().map(str::to_string);
+
+                // Register before writing so an aborted stream still gets cleaned up
+                batch.register(UploadedFile {
+                    id: id.clone(),
+                    filename,
+                    content_type,
+                    size: 0,
+                    path: path.clone(),
+                });
+                let mut size = 0u64;
+                while let Some(chunk) = field.chunk().await? {
+                    size += chunk.len() as u64;
+                    if size > limits.max_file_bytes {
+                        bail!("file `{}` exceeds {} bytes", key, limits.max_file_bytes);
+                    }
+                    out.write_all(&chunk).await?;
+                }
+                out.flush().await?;
+                if let Ok(mut registry) = REGISTRY.lock()
+                    && let Some(entry) = registry.get_mut(&id)
+                {
+                    entry.size = size;
+                }
+
+                for target in paths {
+                    set_path(operations, target, JsonValue::String(id.clone()))?;
+                }
+            }
+        }
+    }
+
+    let operations = operations.ok_or_else(|| anyhow!("missing `operations` part"))?;
+    Ok((operations, batch))
+}
+
+/// Replaces the value at a dotted path such as `variables.files.1`.
+fn set_path(root: &mut JsonValue, path: &str, value: JsonValue) -> anyhow::Result<()> {
+    let mut current = root;
+    for segment in path.split('.') {
+        current = match current {
+            JsonValue::Object(map) => map
+                .get_mut(segment)
+                .ok_or_else(|| anyhow!("map path `{}` not found in operations", path))?,
+            JsonValue::Array(items) => {
+                let index: usize = segment
+                    .parse()
+                    .map_err(|_| anyhow!("map path `{}` has a non-numeric list index", path))?;
+                items
+                    .get_mut(index)
+                    .ok_or_else(|| anyhow!("map path `{}` not found in operations", path))?
+            }
+            _ => bail!("map path `{}` not found in operations", path),
+        };
+    }
+    if !current.is_null() {
+        bail!("map path `{}` must point at a null placeholder", path);
+    }
+    *current = value;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn map_paths_replace_null_placeholders() {
+        let mut operations = json!({
+            "query": "mutation($file: Upload!, $files: [Upload!]!) { a }",
+            "variables": { "file": null, "files": [null, null] }
+        });
+        set_path(&mut operations, "variables.file", json!("u1")).unwrap();
+        set_path(&mut operations, "variables.files.1", json!("u2")).unwrap();
+        assert_eq!(
+            operations["variables"],
+            json!({ "file": "u1", "files": [null, "u2"] })
+        );
+
+        assert!(set_path(&mut operations, "variables.file", json!("u3")).is_err());
+        assert!(set_path(&mut operations, "variables.missing", json!("u3")).is_err());
+        assert!(set_path(&mut operations, "variables.files.x", json!("u3")).is_err());
+    }
+
+    #[test]
+    fn dropping_a_batch_removes_its_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("upload");
+        std::fs::write(&path, b"data").unwrap();
+
+        let mut batch = UploadBatch::default();
+        batch.register(UploadedFile {
+            id: "test-upload".into(),
+            filename: "a.txt".into(),
+            content_type: None,
+            size: 4,
+            path: path.clone(),
+        });
+        assert_eq!(lookup_upload("test-upload").unwrap().size, 4);
+
+        drop(batch);
+        assert!(lookup_upload("test-upload").is_none());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,50 @@
+//! `GET /usage/exports/{id}`: downloads a daily usage export for billing. Only
+//! administrators may download, by session cookie or access token.
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+use super::server::{AppState, request_viewer};
+use crate::metering::open_usage_export;
+use crate::user::db::unix_now;
+
+pub async fn usage_export_download_handler(
+    State(app_state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let viewer = match request_viewer(&app_state, &headers).await {
+        Ok((viewer, _)) => viewer,
+        Err(response) => return response,
+    };
+    match viewer {
+        Some(viewer) if crate::instance::is_admin(&viewer.did) => {}
+        Some(_) => return (StatusCode::FORBIDDEN, "administrator access required").into_response(),
+        None => return (StatusCode::UNAUTHORIZED, "sign in to download exports").into_response(),
+    }
+
+    match open_usage_export(&app_state.pool, &id, unix_now()).await {
+        Ok(Some((export, archive))) => (
+            [
+                (header::CONTENT_TYPE, export.content_type().to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!(
+                        "attachment; filename=\"forge-usage-{}.{}\"",
+                        export.date(),
+                        export.format
+                    ),
+                ),
+                (header::CACHE_CONTROL, "no-store".to_string()),
+            ],
+            archive,
+        )
+            .into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "export not found or expired").into_response(),
+        Err(err) => {
+            tracing::error!("usage export download failed: {}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "internal error").into_response()
+        }
+    }
+}
@@ -0,0 +1,733 @@
+//! Audit log of what happened on the instance.
+//!
+//! Every event passed to [`crate::events::publish`] is also written to `audit_log`,
+//! through the same connection, so an entry commits or rolls back with the change it
+//! describes. Data fields listed in `compliance.redact_fields` are stored only as a
+//! digest and a length. Entries are kept for the retention of their category, the first
+//! word of the action (`issue` for `issue.created`), and are purged with the compliance
+//! archive.
+//!
+//! Administrators page through the log with `auditLog`, filtered by actor, action,
+//! repository and time. `requestAuditLogExport` queues an `audit.export` job that writes
+//! the matching entries as CSV or JSON, downloaded from `GET /audit/exports/{id}` until
+//! `audit_log.export_retention_secs` has passed.
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sqlx::{SqliteExecutor, SqlitePool};
+
+use crate::compliance::redact_summary;
+use crate::config::AuditLogConfig;
+use crate::events::{Envelope, is_valid_kind};
+use crate::graphql::pagination::{Direction, Page, PageRequest, encode_cursor};
+use crate::jobs::{JobHandler, JobRecord, enqueue_in};
+use crate::user::db::unix_now;
+
+pub const JOB_KIND: &str = "audit.export";
+/// Cursors for audit log entries are tagged with this kind.
+pub const AUDIT_CURSOR_KIND: &str = "audit";
+pub const ARCHIVE_FORMAT: &str = "forge-audit-log";
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+const MAX_LISTED_EXPORTS: i64 = 100;
+
+const ENTRY_COLUMNS: &str =
+    "id, action, category, source, actor_did, repository_id, data, created_at, expires_at";
+const EXPORT_COLUMNS: &str = "id, requested_by, format, filter, status, row_count, size_bytes, \
+     error, created_at, completed_at, expires_at";
+
+/// Matches the filter bound as `?1` to `?5` (see [`AuditFilter`]). An action pattern
+/// ending in `.*` matches every action starting with what comes before the `*`.
+const FILTER_SQL: &str = "(?1 IS NULL OR actor_did = ?1) \
+     AND (?2 IS NULL OR repository_id = ?2) \
+     AND (?3 IS NULL OR created_at >= ?3) \
+     AND (?4 IS NULL OR created_at < ?4) \
+     AND (?5 = '[]' OR EXISTS (SELECT 1 FROM json_each(?5) AS pattern WHERE \
+         action = pattern.value \
+         OR (pattern.value LIKE '%.*' \
+             AND substr(action, 1, length(pattern.value) - 1) \
+                 = substr(pattern.value, 1, length(pattern.value) - 1))))";
+
+#[derive(Clone, Debug, PartialEq, sqlx::FromRow)]
+pub struct AuditEntry {
+    /// The event id.
+    pub id: String,
+    /// The event type: `repository.created`, `issue.updated`, ...
+    pub action: String,
+    pub category: String,
+    /// `core` or the name of the extension that raised the event.
+    pub source: String,
+    pub actor_did: Option<String>,
+    pub repository_id: Option<String>,
+    /// Redacted JSON event data.
+    pub data: String,
+    pub created_at: i64,
+    /// `None` when the entry is kept forever.
+    pub expires_at: Option<i64>,
+}
+
+/// Which entries to list or export. Empty fields match everything.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AuditFilter {
+    pub actor_did: Option<String>,
+    /// Exact actions or `category.*` patterns.
+    pub actions: Vec<String>,
+    pub repository_id: Option<String>,
+    /// Unix seconds, inclusive.
+    pub since: Option<i64>,
+    /// Unix seconds, exclusive.
+    pub until: Option<i64>,
+}
+
+impl AuditFilter {
+    pub fn validate(&self) -> Result<()> {
+        for action in &self.actions {
+            let valid = match action.strip_suffix(".*") {
+                Some(prefix) => is_valid_kind(&format!("{}.x", prefix)),
+                None => is_valid_kind(action),
+            };
+            if !valid {
+                bail!("`{}` is not an action or a `category.*` pattern", action);
+            }
+        }
+        if let (Some(since), Some(until)) = (self.since, self.until)
+            && since >= until
+        {
+            bail!("`since` must be before `until`");
+        }
+        Ok(())
+    }
+
+    fn bind<'q, O>(
+        &'q self,
+        query: sqlx::query::QueryAs<'q, sqlx::Sqlite, O, sqlx::sqlite::SqliteArguments<'q>>,
+    ) -> sqlx::query::QueryAs<'q, sqlx::Sqlite, O, sqlx::sqlite::SqliteArguments<'q>> {
+        query
+            .bind(self.actor_did.as_deref())
+            .bind(self.repository_id.as_deref())
+            .bind(self.since)
+            .bind(self.until)
+            .bind(Value::from(self.actions.clone()).to_string())
+    }
+}
+
+/// The retention category of an action: its first word.
+pub fn category_of(action: &str) -> &str {
+    action.split('.').next().unwrap_or(action)
+}
+
+/// Writes the entry for a published event, applying the configured redaction and
+/// retention.
+pub async fn record_event(
+    executor: impl SqliteExecutor<'_>,
+    config: &AuditLogConfig,
+    redact_fields: &[String],
+    envelope: &Envelope,
+) -> Result<(), sqlx::Error> {
+    let category = category_of(&envelope.kind);
+    let retention_days = config.retention_days_for(category);
+    let expires_at =
+        (retention_days > 0).then(|| envelope.time + retention_days as i64 * SECONDS_PER_DAY);
+    sqlx::query(
+        "INSERT INTO audit_log (id, action, category, source, actor_did, repository_id, data, \
+         created_at, expires_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&envelope.id)
+    .bind(&envelope.kind)
+    .bind(category)
+    .bind(&envelope.source)
+    .bind(&envelope.actor)
+    .bind(&envelope.repository_id)
+    .bind(redact_summary(&envelope.data, redact_fields).to_string())
+    .bind(envelope.time)
+    .bind(expires_at)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+impl AuditEntry {
+    pub fn cursor(&self) -> String {
+        encode_cursor(AUDIT_CURSOR_KIND, &self.created_at.to_string(), &self.id)
+    }
+}
+
+/// One page of matching entries, newest first.
+pub async fn page_audit_log(
+    pool: &SqlitePool,
+    filter: &AuditFilter,
+    request: &PageRequest,
+) -> Result<Page<AuditEntry>> {
+    let (after, order) = match request.direction {
+        Direction::Forward => (
+            "(created_at, id) < (?6, ?7)",
+            "ORDER BY created_at DESC, id DESC",
+        ),
+        Direction::Backward => ("(created_at, id) > (?6, ?7)", "ORDER BY created_at, id"),
+    };
+    let sql = format!(
+        "SELECT {ENTRY_COLUMNS} FROM audit_log WHERE {FILTER_SQL} \
+         AND (?6 IS NULL OR {after}) {order} LIMIT ?8"
+    );
+    let query = filter.bind(sqlx::query_as::<_, AuditEntry>(&sql));
+    let query = match &request.cursor {
+        Some(cursor) => {
+            let created_at: i64 = cursor
+                .slug
+                .parse()
+                .map_err(|_| anyhow!("invalid audit log cursor"))?;
+            query.bind(Some(created_at)).bind(Some(cursor.id.as_str()))
+        }
+        None => query.bind(None::<i64>).bind(None::<&str>),
+    };
+    let rows = query.bind(request.fetch_limit()).fetch_all(pool).await?;
+    Ok(Page::from_rows(request, rows))
+}
+
+/// How many entries match `filter`.
+pub async fn count_audit_log(pool: &SqlitePool, filter: &AuditFilter) -> Result<i64> {
+    let sql = format!("SELECT COUNT(*) FROM audit_log WHERE {FILTER_SQL}");
+    let (count,) = filter
+        .bind(sqlx::query_as::<_, (i64,)>(&sql))
+        .fetch_one(pool)
+        .await?;
+    Ok(count)
+}
+
+/// Matching entries, oldest first, at most `limit`.
+async fn fetch_audit_log(
+    pool: &SqlitePool,
+    filter: &AuditFilter,
+    limit: i64,
+) -> Result<Vec<AuditEntry>> {
+    let sql = format!(
+        "SELECT {ENTRY_COLUMNS} FROM audit_log WHERE {FILTER_SQL} \
+         ORDER BY created_at, id LIMIT ?6"
+    );
+    let entries = filter
+        .bind(sqlx::query_as::<_, AuditEntry>(&sql))
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+    Ok(entries)
+}
+
+/// Retention in days of each category that has entries or an override; `0` is forever.
+pub async fn retention_by_category(
+    pool: &SqlitePool,
+    config: &AuditLogConfig,
+) -> Result<Vec<(String, u64)>> {
+    let mut categories: Vec<String> = sqlx::query_scalar("SELECT DISTINCT category FROM audit_log")
+        .fetch_all(pool)
+        .await?;
+    categories.extend(config.retention_overrides.keys().cloned());
+    categories.sort();
+    categories.dedup();
+    Ok(categories
+        .into_iter()
+        .map(|category| {
+            let days = config.retention_days_for(&category);
+            (category, days)
+        })
+        .collect())
+}
+
+/// Deletes entries whose retention ended before `now`. Returns how many were removed.
+pub async fn prune_expired(pool: &SqlitePool, now: i64) -> Result<u64, sqlx::Error> {
+    let result =
+        sqlx::query("DELETE FROM audit_log WHERE expires_at IS NOT NULL AND expires_at <= ?")
+            .bind(now)
+            .execute(pool)
+            .await?;
+    Ok(result.rows_affected())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            other => bail!("unknown audit log export format `{}`", other),
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Json => "application/json",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, sqlx::FromRow)]
+pub struct AuditExport {
+    pub id: String,
+    pub requested_by: String,
+    /// `csv` or `json`.
+    pub format: String,
+    /// JSON of the [`AuditFilter`].
+    pub filter: String,
+    /// `pending`, `ready` or `failed`.
+    pub status: String,
+    pub row_count: Option<i64>,
+    pub size_bytes: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub completed_at: Option<i64>,
+    /// When the file is deleted; set once it is ready.
+    pub expires_at: Option<i64>,
+}
+
+impl AuditExport {
+    pub fn is_downloadable(&self, now: i64) -> bool {
+        self.status == "ready" && self.expires_at.is_some_and(|at| at > now)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportJob {
+    export_id: String,
+}
+
+/// Queues an export of the entries matching `filter`. Entries recorded after the request
+/// are left out.
+pub async fn request_audit_export(
+    pool: &SqlitePool,
+    requested_by: &str,
+    filter: &AuditFilter,
+    format: ExportFormat,
+    config: &AuditLogConfig,
+) -> Result<AuditExport> {
+    filter.validate()?;
+    let created_at = unix_now();
+    let mut filter = filter.clone();
+    filter.until = Some(
+        filter
+            .until
+            .map_or(created_at + 1, |until| until.min(created_at + 1)),
+    );
+    let matching = count_audit_log(pool, &filter).await?;
+    if matching > config.max_export_rows {
+        bail!(
+            "{} entries match; exports hold at most {}, narrow the filter",
+            matching,
+            config.max_export_rows
+        );
+    }
+
+    let export = AuditExport {
+        id: cuid2::create_id(),
+        requested_by: requested_by.to_string(),
+        format: format.as_str().to_string(),
+        filter: serde_json::to_string(&filter)?,
+        status: "pending".to_string(),
+        row_count: None,
+        size_bytes: None,
+        error: None,
+        created_at,
+        completed_at: None,
+        expires_at: None,
+    };
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO audit_log_exports (id, requested_by, format, filter, status, created_at) \
+         VALUES (?, ?, ?, ?, 'pending', ?)",
+    )
+    .bind(&export.id)
+    .bind(&export.requested_by)
+    .bind(&export.format)
+    .bind(&export.filter)
+    .bind(export.created_at)
+    .execute(&mut *tx)
+    .await?;
+    let job = ExportJob {
+        export_id: export.id.clone(),
+    };
+    enqueue_in(&mut *tx, JOB_KIND, &job, config.max_attempts).await?;
+    tx.commit().await?;
+    Ok(export)
+}
+
+/// Recent exports, newest first.
+pub async fn list_audit_exports(pool: &SqlitePool) -> Result<Vec<AuditExport>> {
+    let exports = sqlx::query_as::<_, AuditExport>(&format!(
+        "SELECT {} FROM audit_log_exports ORDER BY created_at DESC, id DESC LIMIT ?",
+        EXPORT_COLUMNS
+    ))
+    .bind(MAX_LISTED_EXPORTS)
+    .fetch_all(pool)
+    .await?;
+    Ok(exports)
+}
+
+pub async fn fetch_audit_export(pool: &SqlitePool, id: &str) -> Result<Option<AuditExport>> {
+    let export = sqlx::query_as::<_, AuditExport>(&format!(
+        "SELECT {} FROM audit_log_exports WHERE id = ?",
+        EXPORT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(export)
+}
+
+/// The export's file, or `None` when it is not (or no longer) available.
+pub async fn open_audit_export(
+    pool: &SqlitePool,
+    id: &str,
+    now: i64,
+) -> Result<Option<(AuditExport, Vec<u8>)>> {
+    let Some(export) = fetch_audit_export(pool, id).await? else {
+        return Ok(None);
+    };
+    if !export.is_downloadable(now) {
+        return Ok(None);
+    }
+    let archive: Option<Vec<u8>> =
+        sqlx::query_scalar("SELECT archive FROM audit_log_exports WHERE id = ?")
+            .bind(id)
+            .fetch_one(pool)
+            .await?;
+    Ok(archive.map(|archive| (export, archive)))
+}
+
+fn entry_json(entry: &AuditEntry) -> Value {
+    json!({
+        "id": entry.id,
+        "action": entry.action,
+        "source": entry.source,
+        "actor": entry.actor_did,
+        "repositoryId": entry.repository_id,
+        "data": serde_json::from_str::<Value>(&entry.data).unwrap_or(Value::String(entry.data.clone())),
+        "createdAt": entry.created_at,
+    })
+}
+
+/// Quotes a CSV field. Fields that a spreadsheet would read as a formula get a leading
+/// `'`.
+pub(crate) fn csv_field(value: &str) -> String {
+    let guarded = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    format!("\"{}\"", guarded.replace('"', "\"\""))
+}
+
+fn render_csv(entries: &[AuditEntry]) -> Vec<u8> {
+    let mut out = String::from("id,created_at,action,source,actor,repository_id,data\r\n");
+    for entry in entries {
+        let fields = [
+            entry.id.as_str(),
+            &entry.created_at.to_string(),
+            &entry.action,
+            &entry.source,
+            entry.actor_did.as_deref().unwrap_or(""),
+            entry.repository_id.as_deref().unwrap_or(""),
+            &entry.data,
+        ]
+        .map(csv_field);
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out.into_bytes()
+}
+
+/// Job handler for [`JOB_KIND`].
+pub struct AuditExportJob {
+    pool: SqlitePool,
+    config: AuditLogConfig,
+}
+
+impl AuditExportJob {
+    pub fn new(pool: SqlitePool, config: AuditLogConfig) -> Self {
+        Self { pool, config }
+    }
+}
+
+#[async_trait]
+impl JobHandler for AuditExportJob {
+    async fn run(&self, job: &JobRecord) -> Result<()> {
+        let payload: ExportJob = job.payload()?;
+        let export = fetch_audit_export(&self.pool, &payload.export_id)
+            .await?
+            .ok_or_else(|| anyhow!("audit export {} no longer exists", payload.export_id))?;
+        if export.status != "pending" {
+            return Ok(());
+        }
+        let filter: AuditFilter = serde_json::from_str(&export.filter)?;
+        let entries = fetch_audit_log(&self.pool, &filter, self.config.max_export_rows).await?;
+        let bytes = match ExportFormat::parse(&export.format)? {
+            ExportFormat::Csv => render_csv(&entries),
+            ExportFormat::Json => serde_json::to_vec_pretty(&json!({
+                "format": ARCHIVE_FORMAT,
+                "version": ARCHIVE_VERSION,
+                "exportId": export.id,
+                "generatedAt": unix_now(),
+                "filter": filter,
+                "entries": entries.iter().map(entry_json).collect::<Vec<_>>(),
+            }))?,
+        };
+
+        let now = unix_now();
+        sqlx::query(
+            "UPDATE audit_log_exports SET status = 'ready', archive = ?, row_count = ?, \
+             size_bytes = ?, error = NULL, completed_at = ?, expires_at = ? WHERE id = ?",
+        )
+        .bind(&bytes)
+        .bind(entries.len() as i64)
+        .bind(bytes.len() as i64)
+        .bind(now)
+        .bind(now.saturating_add(self.config.export_retention_secs as i64))
+        .bind(&export.id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn exhausted(&self, job: &JobRecord, error: &str) {
+        let Ok(payload) = job.payload::<ExportJob>() else {
+            return;
+        };
+        let result = sqlx::query(
+            "UPDATE audit_log_exports SET status = 'failed', error = ?, completed_at = ? \
+             WHERE id = ?",
+        )
+        .bind(error)
+        .bind(unix_now())
+        .bind(&payload.export_id)
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = result {
+            tracing::warn!(export = %payload.export_id, "failed to record export failure: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{Event, publish};
+    use crate::jobs::JobQueue;
+    use crate::test_helpers::create_test_pool;
+    use crate::user::export::prune_expired_exports;
+
+    async fn record(pool: &SqlitePool, kind: &str, actor: &str, repository: Option<&str>) {
+        let event = Event {
+            kind,
+            source: "core",
+            actor_did: Some(actor),
+            repository_id: repository,
+            data: json!({ "title": "=HYPERLINK(\"x\")", "body": "private text" }),
+        };
+        publish(pool, None, event).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn entries_are_filtered_paged_and_redacted() {
+        let pool = create_test_pool().await.unwrap();
+        record(&pool, "repository.created", "did:plc:alice", Some("r1")).await;
+        record(&pool, "issue.created", "did:plc:alice", Some("r1")).await;
+        record(&pool, "issue.updated", "did:plc:bob", Some("r2")).await;
+        record(&pool, "user.token_created", "did:plc:bob", None).await;
+
+        let everything = AuditFilter::default();
+        assert_eq!(count_audit_log(&pool, &everything).await.unwrap(), 4);
+        let issues = AuditFilter {
+            actions: vec!["issue.*".to_string()],
+            ..AuditFilter::default()
+        };
+        assert_eq!(count_audit_log(&pool, &issues).await.unwrap(), 2);
+        let alice_in_r1 = AuditFilter {
+            actor_did: Some("did:plc:alice".to_string()),
+            repository_id: Some("r1".to_string()),
+            actions: vec!["repository.created".to_string(), "user.*".to_string()],
+            ..AuditFilter::default()
+        };
+        assert_eq!(count_audit_log(&pool, &alice_in_r1).await.unwrap(), 1);
+        let future = AuditFilter {
+            since: Some(unix_now() + 60),
+            ..AuditFilter::default()
+        };
+        assert_eq!(count_audit_log(&pool, &future).await.unwrap(), 0);
+
+        let first = PageRequest::from_args(AUDIT_CURSOR_KIND, Some(3), None, None, None).unwrap();
+        let page = page_audit_log(&pool, &everything, &first).await.unwrap();
+        assert_eq!(page.items.len(), 3);
+        assert!(page.has_next_page);
+        let after = page.items[2].cursor();
+        let next =
+            PageRequest::from_args(AUDIT_CURSOR_KIND, Some(3), Some(&after), None, None).unwrap();
+        let rest = page_audit_log(&pool, &everything, &next).await.unwrap();
+        assert_eq!(rest.items.len(), 1);
+        assert!(!rest.has_next_page);
+        let before = rest.items[0].cursor();
+        let back =
+            PageRequest::from_args(AUDIT_CURSOR_KIND, None, None, Some(3), Some(&before)).unwrap();
+        assert_eq!(
+            page_audit_log(&pool, &everything, &back)
+                .await
+                .unwrap()
+                .items,
+            page.items
+        );
+
+        let entry = &page.items[0];
+        assert!(!entry.data.contains("private text"));
+        assert!(entry.data.contains("HYPERLINK"));
+        assert_eq!(
+            entry.expires_at,
+            Some(entry.created_at + 365 * SECONDS_PER_DAY)
+        );
+
+        assert!(
+            AuditFilter {
+                actions: vec!["issue".to_string()],
+                ..AuditFilter::default()
+            }
+            .validate()
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn retention_is_set_per_category() {
+        let pool = create_test_pool().await.unwrap();
+        let mut config = AuditLogConfig {
+            retention_days: 30,
+            ..AuditLogConfig::default()
+        };
+        config.retention_overrides.insert("issue".to_string(), 0);
+        config.retention_overrides.insert("user".to_string(), 7);
+        for kind in ["repository.created", "issue.created"] {
+            let envelope = Envelope {
+                version: 1,
+                id: cuid2::create_id(),
+                kind: kind.to_string(),
+                source: "core".to_string(),
+                time: 1_000,
+                actor: None,
+                repository_id: None,
+                data: json!({}),
+            };
+            record_event(&pool, &config, &[], &envelope).await.unwrap();
+        }
+        assert_eq!(
+            retention_by_category(&pool, &config).await.unwrap(),
+            vec![
+                ("issue".to_string(), 0),
+                ("repository".to_string(), 30),
+                ("user".to_string(), 7),
+            ]
+        );
+        let later = 1_000 + 31 * SECONDS_PER_DAY;
+        assert_eq!(prune_expired(&pool, later).await.unwrap(), 1);
+        let left: Vec<String> = sqlx::query_scalar("SELECT action FROM audit_log")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(left, vec!["issue.created".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn exports_are_rendered_by_the_job() {
+        let pool = create_test_pool().await.unwrap();
+        record(&pool, "issue.created", "did:plc:alice", Some("r1")).await;
+        record(&pool, "repository.created", "did:plc:alice", Some("r1")).await;
+        let config = AuditLogConfig::default();
+        let filter = AuditFilter {
+            actions: vec!["issue.*".to_string()],
+            ..AuditFilter::default()
+        };
+        let csv = request_audit_export(&pool, "did:plc:admin", &filter, ExportFormat::Csv, &config)
+            .await
+            .unwrap();
+        let json_export =
+            request_audit_export(&pool, "did:plc:admin", &filter, ExportFormat::Json, &config)
+                .await
+                .unwrap();
+        assert_eq!(csv.status, "pending");
+        assert!(
+            open_audit_export(&pool, &csv.id, unix_now())
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let handler = AuditExportJob::new(pool.clone(), config.clone());
+        let queue = JobQueue::new(pool.clone());
+        while let Some(job) = queue.claim_next(unix_now()).await.unwrap() {
+            assert_eq!(job.kind, JOB_KIND);
+            handler.run(&job).await.unwrap();
+        }
+
+        let (export, bytes) = open_audit_export(&pool, &csv.id, unix_now())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(export.row_count, Some(1));
+        let text = String::from_utf8(bytes).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(
+            lines.next(),
+            Some("id,created_at,action,source,actor,repository_id,data")
+        );
+        let row = lines.next().unwrap();
+        assert!(row.contains(r#""issue.created""#));
+        // Data is JSON, so the title's formula is quoted inside it and never leads a cell.
+        assert!(row.contains(r#"""title"":""=HYPERLINK"#));
+        assert_eq!(lines.next(), None);
+        assert_eq!(csv_field("=1+1"), r#""'=1+1""#);
+
+        let (_, bytes) = open_audit_export(&pool, &json_export.id, unix_now())
+            .await
+            .unwrap()
+            .unwrap();
+        let archive: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(archive["format"], ARCHIVE_FORMAT);
+        assert_eq!(archive["entries"].as_array().unwrap().len(), 1);
+        assert_eq!(archive["entries"][0]["action"], "issue.created");
+        assert_eq!(archive["entries"][0]["data"]["title"], "=HYPERLINK(\"x\")");
+
+        let limited = AuditLogConfig {
+            max_export_rows: 1,
+            ..config
+        };
+        assert!(
+            request_audit_export(
+                &pool,
+                "did:plc:admin",
+                &AuditFilter::default(),
+                ExportFormat::Csv,
+                &limited
+            )
+            .await
+            .is_err()
+        );
+        let expired = unix_now() + limited.export_retention_secs as i64 + 1;
+        assert_eq!(
+            prune_expired_exports(&pool, "audit_log_exports", expired)
+                .await
+                .unwrap(),
+            2
+        );
+        assert!(list_audit_exports(&pool).await.unwrap().is_empty());
+    }
+}
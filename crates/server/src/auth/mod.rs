@@ -6,6 +6,7 @@
 pub mod session;
 pub mod atproto;
 pub mod store;
+pub mod viewer;
 
 pub use session::{Session, SessionManager};
 pub use atproto::{AtProtoAuthClient, AuthConfig};
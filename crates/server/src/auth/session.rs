@@ -5,6 +5,7 @@
 
 use super::User;
 use anyhow::Result;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use uuid::Uuid;
@@ -24,12 +25,33 @@ pub struct Session {
     pub dpop_pkcs8: Option<Vec<u8>>,
     /// DPoP public JWK (optional)
     pub dpop_jwk: Option<String>,
+    /// Unix seconds when the user signed in
+    pub created_at: i64,
+    /// Unix seconds of the last request made with this session
+    pub last_active_at: i64,
+    /// `User-Agent` of the browser that signed in
+    pub user_agent: Option<String>,
 }
 
+impl Session {
+    /// Stable public name for the session. The id itself is a bearer credential and is
+    /// never shown, not even to its owner.
+    pub fn fingerprint(&self) -> String {
+        Sha256::digest(self.id.as_bytes())[..8]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+const MAX_USER_AGENT_LEN: usize = 256;
+
 /// Session manager for single-tenant forge
 ///
 /// This is a simple in-memory store that holds multiple active sessions.
 /// For a single-tenant forge, multiple users can be logged in simultaneously.
+/// Clones share the same sessions.
+#[derive(Clone)]
 pub struct SessionManager {
     sessions: Arc<RwLock<HashMap<String, Session>>>,
 }
@@ -51,6 +73,7 @@ impl SessionManager {
         dpop_jwk: Option<String>,
     ) -> Result<String> {
         let session_id = Uuid::new_v4().to_string();
+        let now = crate::user::db::unix_now();
         let session = Session {
             id: session_id.clone(),
             user,
@@ -58,6 +81,9 @@ impl SessionManager {
             refresh_token,
             dpop_pkcs8,
             dpop_jwk,
+            created_at: now,
+            last_active_at: now,
+            user_agent: None,
         };
 
         let mut sessions = self.sessions.write()
@@ -83,6 +109,32 @@ impl SessionManager {
         Ok(sessions.get(session_id).map(|s| s.user.clone()))
     }
 
+    /// Get the user of a session and mark the session as active now
+    pub fn touch(&self, session_id: &str) -> Result<Option<User>> {
+        let mut sessions = self
+            .sessions
+            .write()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire session lock: {}", e))?;
+
+        Ok(sessions.get_mut(session_id).map(|s| {
+            s.last_active_at = crate::user::db::unix_now();
+            s.user.clone()
+        }))
+    }
+
+    /// Record the device a session signed in from
+    pub fn set_user_agent(&self, session_id: &str, user_agent: Option<String>) -> Result<()> {
+        let mut sessions = self
+            .sessions
+            .write()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire session lock: {}", e))?;
+
+        if let Some(session) = sessions.get_mut(session_id) {
+            session.user_agent = user_agent.map(|ua| ua.chars().take(MAX_USER_AGENT_LEN).collect());
+        }
+        Ok(())
+    }
+
     /// Delete a specific session (logout)
     pub fn delete_session(&self, session_id: &str) -> Result<()> {
         let mut sessions = self.sessions.write()
@@ -99,6 +151,34 @@ impl SessionManager {
         Ok(sessions.values().cloned().collect())
     }
 
+    /// Active sessions of one user
+    pub fn sessions_for(&self, did: &str) -> Result<Vec<Session>> {
+        let sessions = self
+            .sessions
+            .read()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire session lock: {}", e))?;
+
+        Ok(sessions
+            .values()
+            .filter(|s| s.user.did == did)
+            .cloned()
+            .collect())
+    }
+
+    /// Delete the session of `did` with the given [`Session::fingerprint`]
+    pub fn revoke_by_fingerprint(&self, did: &str, fingerprint: &str) -> Result<Option<Session>> {
+        let mut sessions = self
+            .sessions
+            .write()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire session lock: {}", e))?;
+
+        let id = sessions
+            .values()
+            .find(|s| s.user.did == did && s.fingerprint() == fingerprint)
+            .map(|s| s.id.clone());
+        Ok(id.and_then(|id| sessions.remove(&id)))
+    }
+
     /// Get count of active sessions
     pub fn session_count(&self) -> Result<usize> {
         let sessions = self.sessions.read()
@@ -211,4 +291,34 @@ mod tests {
         let all_sessions = manager.get_all_sessions().unwrap();
         assert_eq!(all_sessions.len(), 2);
     }
+
+    #[test]
+    fn test_revoke_by_fingerprint() {
+        let manager = SessionManager::new();
+        let user1 = User::new("did:plc:user1".to_string(), "user1.bsky.social".to_string());
+        let user2 = User::new("did:plc:user2".to_string(), "user2.bsky.social".to_string());
+
+        let session_id = manager
+            .create_session(user1.clone(), "token1".to_string(), None, None, None)
+            .unwrap();
+        manager
+            .set_user_agent(&session_id, Some("Firefox".to_string()))
+            .unwrap();
+        let session = manager.get_session(&session_id).unwrap().unwrap();
+        assert_eq!(session.user_agent.as_deref(), Some("Firefox"));
+        assert_eq!(session.fingerprint().len(), 16);
+
+        // Another user cannot revoke it, even knowing the fingerprint
+        assert!(
+            manager
+                .revoke_by_fingerprint(&user2.did, &session.fingerprint())
+                .unwrap()
+                .is_none()
+        );
+        let revoked = manager
+            .revoke_by_fingerprint(&user1.did, &session.fingerprint())
+            .unwrap();
+        assert_eq!(revoked.map(|s| s.id), Some(session_id.clone()));
+        assert!(manager.touch(&session_id).unwrap().is_none());
+    }
 }
@@ -21,11 +21,19 @@ pub struct SqliteAuthStore {
 impl SqliteAuthStore {
     pub async fn new(db_path: &str) -> Result<Self> {
         if let Some(dir) = Path::new(db_path).parent() { std::fs::create_dir_all(dir)?; }
-        let options = SqliteConnectOptions::new()
+        let options = crate::encryption::keyed(SqliteConnectOptions::new())
             .filename(db_path)
             .create_if_missing(true)
             .journal_mode(SqliteJournalMode::Wal);
-        let pool = SqlitePool::connect_with(options).await?;
+        Self::with_pool(SqlitePool::connect_with(options).await?).await
+    }
+
+    /// A store that lives only as long as the process, for in-memory mode.
+    pub async fn in_memory() -> Result<Self> {
+        Self::with_pool(crate::db::memory_pool(&cuid2::create_id(), 2).await?).await
+    }
+
+    async fn with_pool(pool: SqlitePool) -> Result<Self> {
         sqlx::query(
             r#"CREATE TABLE IF NOT EXISTS auth_flows (
                 state TEXT PRIMARY KEY,
@@ -152,4 +160,25 @@ mod tests {
         store.delete("state1").await.unwrap();
         assert!(store.get("state1").await.unwrap().is_none());
     }
+
+    #[tokio::test]
+    async fn in_memory_store_keeps_flows() {
+        let store = SqliteAuthStore::in_memory().await.unwrap();
+        store
+            .insert(AuthFlowRecord {
+                state: "state1".into(),
+                issuer: "https://bsky.social".into(),
+                pds_url: "https://bsky.social".into(),
+                code_verifier: "verifier".into(),
+                dpop_pkcs8: vec![1, 2, 3],
+                dpop_jwk: "{\"kty\":\"EC\"}".into(),
+                dpop_nonce: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get("state1").await.unwrap().unwrap().issuer,
+            "https://bsky.social"
+        );
+    }
 }
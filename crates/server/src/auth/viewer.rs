@@ -0,0 +1,70 @@
+//! Request-scoped access to the authenticated user.
+//!
+//! The GraphQL handler resolves the session cookie once and runs the whole execution
+//! inside [`with_viewer`]; subgraph executors read it back with [`current_viewer`]
+//! instead of threading the user through the Hive Router plan executor.
+//!
+//! Requests authenticated with a session cookie also carry the session ([`with_session`]).
+//! Requests made with a personal access token have a viewer but no session.
+
+use std::future::Future;
+
+use super::User;
+use super::session::SessionManager;
+
+tokio::task_local! {
+    static VIEWER: Option<User>;
+    static SESSION: Option<ViewerSession>;
+}
+
+/// The browser session a request was made with.
+#[derive(Clone)]
+pub struct ViewerSession {
+    pub id: String,
+    pub sessions: SessionManager,
+}
+
+pub async fn with_viewer<F>(viewer: Option<User>, fut: F) -> F::Output
+where
+    F: Future,
+{
+    VIEWER.scope(viewer, fut).await
+}
+
+pub async fn with_session<F>(session: Option<ViewerSession>, fut: F) -> F::Output
+where
+    F: Future,
+{
+    SESSION.scope(session, fut).await
+}
+
+/// The user the current request is executing on behalf of, if any.
+pub fn current_viewer() -> Option<User> {
+    VIEWER.try_with(|viewer| viewer.clone()).ok().flatten()
+}
+
+/// Like [`current_viewer`], but fails with a GraphQL-friendly message when anonymous.
+pub fn require_viewer() -> anyhow::Result<User> {
+    current_viewer().ok_or_else(|| anyhow::anyhow!("authentication required"))
+}
+
+/// Like [`require_viewer`], but only for instance administrators: DIDs listed in
+/// `auth.admin_dids` and the account bound by setup (see [`crate::instance`]).
+pub fn require_admin() -> anyhow::Result<User> {
+    let viewer = require_viewer()?;
+    if crate::instance::is_admin(&viewer.did) {
+        Ok(viewer)
+    } else {
+        Err(anyhow::anyhow!("administrator access required"))
+    }
+}
+
+/// The session behind the current request. Security settings can only be changed from a
+/// session, so a leaked access token cannot mint more tokens or register SSH keys.
+pub fn require_session() -> anyhow::Result<ViewerSession> {
+    SESSION
+        .try_with(|session| session.clone())
+        .ok()
+        .flatten()
+        .ok_or_else(|| anyhow::anyhow!("this requires signing in with a browser session"))
+}
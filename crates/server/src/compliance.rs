@@ -0,0 +1,270 @@
+//! Archive of deleted data for compliance.
+//!
+//! Deleting a repository, or an extension deleting one of its records, writes an
+//! archival record: what was deleted, who deleted it, when, and a summary of the
+//! deleted data. Summary fields listed in `compliance.redact_fields` are stored only as
+//! a SHA-256 digest and a length. That is enough to confirm what was deleted without
+//! keeping the content. Records expire after the configured retention period and are
+//! purged by a background task. Only administrators can export them.
+
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use sqlx::{SqliteExecutor, SqlitePool};
+
+use crate::config::ComplianceConfig;
+use crate::repository::lifecycle::{LifecycleEvent, LifecycleKind, enqueue_lifecycle_event};
+use crate::user::db::unix_now;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+pub const MAX_EXPORT: i64 = 1000;
+
+#[derive(Clone, Debug, PartialEq, sqlx::FromRow)]
+pub struct ArchivalRecord {
+    pub id: String,
+    /// `repository`, `issue`, ...
+    pub subject_kind: String,
+    pub subject_id: String,
+    /// `core` or the name of the extension that deleted the data.
+    pub source: String,
+    pub actor_did: Option<String>,
+    /// Redacted JSON summary.
+    pub summary: String,
+    pub deleted_at: i64,
+    /// `None` when the record is kept forever.
+    pub expires_at: Option<i64>,
+}
+
+pub struct Deletion<'a> {
+    pub subject_kind: &'a str,
+    pub subject_id: &'a str,
+    pub source: &'a str,
+    pub actor_did: Option<&'a str>,
+    pub summary: Value,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ExportFilter {
+    pub subject_kind: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub limit: i64,
+}
+
+/// Replaces the listed fields, at any depth, with `{ "sha256": ..., "length": ... }`.
+pub fn redact_summary(value: &Value, fields: &[String]) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, child)| {
+                    let redacted = if fields.iter().any(|field| field == key) && !child.is_null() {
+                        digest(child)
+                    } else {
+                        redact_summary(child, fields)
+                    };
+                    (key.clone(), redacted)
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| redact_summary(item, fields))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn digest(value: &Value) -> Value {
+    let text = match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    let hash = Sha256::digest(text.as_bytes());
+    serde_json::json!({
+        "sha256": hash.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+        "length": text.len(),
+    })
+}
+
+/// Writes the archival record for a deletion, applying the configured redaction and
+/// retention. Pass the transaction that performs the deletion, so the data is never
+/// removed without a record.
+pub async fn archive_deletion(
+    executor: impl SqliteExecutor<'_>,
+    config: &ComplianceConfig,
+    deletion: Deletion<'_>,
+) -> Result<ArchivalRecord, sqlx::Error> {
+    let deleted_at = unix_now();
+    let retention_days = config.retention_days_for(deletion.subject_kind);
+    let record = ArchivalRecord {
+        id: cuid2::create_id(),
+        subject_kind: deletion.subject_kind.to_string(),
+        subject_id: deletion.subject_id.to_string(),
+        source: deletion.source.to_string(),
+        actor_did: deletion.actor_did.map(str::to_string),
+        summary: redact_summary(&deletion.summary, &config.redact_fields).to_string(),
+        deleted_at,
+        expires_at: (retention_days > 0)
+            .then(|| deleted_at + retention_days as i64 * SECONDS_PER_DAY),
+    };
+
+    sqlx::query(
+        "INSERT INTO compliance_archive \
+         (id, subject_kind, subject_id, source, actor_did, summary, deleted_at, expires_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&record.id)
+    .bind(&record.subject_kind)
+    .bind(&record.subject_id)
+    .bind(&record.source)
+    .bind(&record.actor_did)
+    .bind(&record.summary)
+    .bind(record.deleted_at)
+    .bind(record.expires_at)
+    .execute(executor)
+    .await?;
+    Ok(record)
+}
+
+/// Deletes records whose retention ended before `now`. Returns how many were removed.
+/// When the record of a deleted repository goes, extensions get a `purged` lifecycle
+/// event and must drop anything they kept about the repository.
+pub async fn prune_expired(pool: &SqlitePool, now: i64) -> anyhow::Result<u64> {
+    let mut tx = pool.begin().await?;
+    let repositories: Vec<String> = sqlx::query_scalar(
+        "SELECT DISTINCT subject_id FROM compliance_archive \
+         WHERE subject_kind = 'repository' AND source = 'core' \
+           AND expires_at IS NOT NULL AND expires_at <= ?",
+    )
+    .bind(now)
+    .fetch_all(&mut *tx)
+    .await?;
+    for repository_id in &repositories {
+        let event = LifecycleEvent::new(LifecycleKind::Purged, repository_id, None, None);
+        enqueue_lifecycle_event(&mut *tx, &event).await?;
+    }
+    let result = sqlx::query(
+        "DELETE FROM compliance_archive WHERE expires_at IS NOT NULL AND expires_at <= ?",
+    )
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+/// Archived records, newest first.
+pub async fn export_archive(
+    pool: &SqlitePool,
+    filter: &ExportFilter,
+) -> Result<Vec<ArchivalRecord>, sqlx::Error> {
+    sqlx::query_as::<_, ArchivalRecord>(
+        "SELECT id, subject_kind, subject_id, source, actor_did, summary, deleted_at, expires_at \
+         FROM compliance_archive \
+         WHERE (? IS NULL OR subject_kind = ?) \
+           AND (? IS NULL OR deleted_at >= ?) \
+           AND (? IS NULL OR deleted_at < ?) \
+         ORDER BY deleted_at DESC, id DESC \
+         LIMIT ?",
+    )
+    .bind(&filter.subject_kind)
+    .bind(&filter.subject_kind)
+    .bind(filter.since)
+    .bind(filter.since)
+    .bind(filter.until)
+    .bind(filter.until)
+    .bind(filter.limit.clamp(1, MAX_EXPORT))
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+    use serde_json::json;
+
+    #[test]
+    fn listed_fields_are_replaced_by_digests() {
+        let fields = vec!["body".to_string()];
+        let summary = json!({
+            "title": "Crash on start",
+            "body": "secret stack trace",
+            "comments": [{ "body": "me too" }],
+        });
+        let redacted = redact_summary(&summary, &fields);
+        assert_eq!(redacted["title"], "Crash on start");
+        assert_eq!(redacted["body"]["length"], 18);
+        assert_eq!(redacted["body"]["sha256"].as_str().unwrap().len(), 64);
+        assert!(redacted["comments"][0]["body"].is_object());
+        assert!(!redacted.to_string().contains("stack trace"));
+    }
+
+    #[tokio::test]
+    async fn records_expire_per_kind() {
+        let pool = create_test_pool().await.unwrap();
+        let mut config = ComplianceConfig {
+            retention_days: 30,
+            ..ComplianceConfig::default()
+        };
+        config.retention_overrides.insert("issue".to_string(), 0);
+
+        let repository = archive_deletion(
+            &pool,
+            &config,
+            Deletion {
+                subject_kind: "repository",
+                subject_id: "r1",
+                source: "core",
+                actor_did: Some("did:plc:alice"),
+                summary: json!({ "path": "team/app" }),
+            },
+        )
+        .await
+        .unwrap();
+        let issue = archive_deletion(
+            &pool,
+            &config,
+            Deletion {
+                subject_kind: "issue",
+                subject_id: "i1",
+                source: "issues",
+                actor_did: None,
+                summary: json!({ "title": "t" }),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            repository.expires_at,
+            Some(repository.deleted_at + 30 * SECONDS_PER_DAY)
+        );
+        assert_eq!(issue.expires_at, None);
+
+        let everything = ExportFilter {
+            limit: 10,
+            ..ExportFilter::default()
+        };
+        assert_eq!(export_archive(&pool, &everything).await.unwrap().len(), 2);
+        let issues = ExportFilter {
+            subject_kind: Some("issue".to_string()),
+            ..everything.clone()
+        };
+        assert_eq!(export_archive(&pool, &issues).await.unwrap(), vec![issue]);
+
+        let later = repository.deleted_at + 31 * SECONDS_PER_DAY;
+        assert_eq!(prune_expired(&pool, later).await.unwrap(), 1);
+        assert_eq!(export_archive(&pool, &everything).await.unwrap().len(), 1);
+
+        // Extensions are told that the deleted repository is gone for good.
+        let purged: Vec<String> = sqlx::query_scalar("SELECT payload FROM jobs WHERE kind = ?")
+            .bind(crate::repository::lifecycle::JOB_KIND)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(purged.len(), 1);
+        assert!(purged[0].contains(r#""kind":"purged""#));
+        assert!(purged[0].contains(r#""repository_id":"r1""#));
+    }
+}
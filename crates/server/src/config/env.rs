@@ -0,0 +1,270 @@
+//! Environment overrides layered on top of the RON configuration.
+//!
+//! A variable named `FORGE_<SECTION>_<FIELD>` sets the config field at that path. For
+//! example, `FORGE_CLONE_MAX_BYTES` sets `clone.max_bytes` and
+//! `FORGE_EXTENSIONS_SETTINGS_OFFLINE_MODE` sets `extensions.settings.offline_mode`.
+//! Names are matched against the fields that actually exist, so underscores inside
+//! field names need no escaping. The existing value decides how the text is parsed:
+//! numbers and booleans are parsed, lists are split on commas, and unset optional
+//! values take the text as is. Variables that match no field are ignored, because
+//! many `FORGE_*` variables are read elsewhere and are not configuration fields.
+//!
+//! A few older variable names are still accepted. They have lower precedence than the
+//! canonical names (see [`LEGACY_ALIASES`]).
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::Value;
+
+use super::Config;
+
+const PREFIX: &str = "FORGE_";
+
+/// Older variable names and the config path they feed.
+const LEGACY_ALIASES: &[(&str, &[&str])] = &[
+    ("FORGE_API_ADDR", &["server", "bind_addr"]),
+    ("FORGE_PUBLIC_BASE_URL", &["server", "public_base_url"]),
+    ("FORGE_CORS_ORIGINS", &["server", "cors_origins"]),
+    ("FORGE_IN_MEMORY_DB", &["db", "in_memory"]),
+    ("FORGE_ADMIN_DIDS", &["auth", "admin_dids"]),
+    ("ATPROTO_REDIRECT_URI", &["auth", "redirect_uri"]),
+    ("ATPROTO_OAUTH_SCOPE", &["auth", "oauth_scope"]),
+    ("ATPROTO_CLIENT_SECRET", &["auth", "client_secret"]),
+    (
+        "FORGE_GIT_MAX_CONCURRENCY",
+        &["git", "interactive_max_concurrent"],
+    ),
+    (
+        "FORGE_DIGEST_INTERVAL_SECS",
+        &["push", "digest_interval_secs"],
+    ),
+    (
+        "FORGE_SLUG_TRANSLITERATE",
+        &["repos", "transliterate_slugs"],
+    ),
+];
+
+/// Port-only variables, expanded to `0.0.0.0:<port>` for `server.bind_addr`.
+const LEGACY_PORT_VARS: &[&str] = &["PORT", "FORGE_API_PORT"];
+
+/// Applies `vars` (usually `std::env::vars()`) on top of `config`.
+pub fn apply_env_overrides<I>(config: Config, vars: I) -> Result<Config>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let vars: Vec<(String, String)> = vars.into_iter().collect();
+    let lookup = |name: &str| {
+        vars.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let mut tree = serde_json::to_value(&config).context("failed to serialize configuration")?;
+
+    // Lowest precedence first: port-only vars, then legacy names, then canonical names.
+    for name in LEGACY_PORT_VARS {
+        if let Some(port) = lookup(name) {
+            set_path(
+                &mut tree,
+                &["server", "bind_addr"],
+                &format!("0.0.0.0:{}", port.trim()),
+                name,
+            )?;
+        }
+    }
+    for (name, path) in LEGACY_ALIASES {
+        if let Some(value) = lookup(name) {
+            set_path(&mut tree, path, value, name)?;
+        }
+    }
+
+    let mut canonical: Vec<&(String, String)> = vars
+        .iter()
+        .filter(|(key, _)| key.starts_with(PREFIX))
+        .collect();
+    canonical.sort();
+    for (name, value) in canonical {
+        let rest = &name[PREFIX.len()..];
+        match resolve_path(&tree, rest) {
+            Some(path) => {
+                let path: Vec<&str> = path.iter().map(String::as_str).collect();
+                set_path(&mut tree, &path, value, name)?;
+            }
+            None => tracing::trace!("{} does not name a config field", name),
+        }
+    }
+
+    serde_json::from_value(tree).context("environment overrides produced an invalid configuration")
+}
+
+/// Finds the field path spelled by `rest` (e.g. `CLONE_MAX_BYTES`), if any.
+fn resolve_path(tree: &Value, rest: &str) -> Option<Vec<String>> {
+    let Value::Object(map) = tree else {
+        return None;
+    };
+    // Longer keys first so `max_bytes_per_sec` is not mistaken for `max_bytes`.
+    let mut keys: Vec<&String> = map.keys().collect();
+    keys.sort_by_key(|key| std::cmp::Reverse(key.len()));
+    for key in keys {
+        let upper = key.to_ascii_uppercase();
+        if rest == upper && !map[key].is_object() {
+            return Some(vec![key.clone()]);
+        }
+        if let Some(tail) = rest.strip_prefix(&upper).and_then(|t| t.strip_prefix('_'))
+            && let Some(mut path) = resolve_path(&map[key], tail)
+        {
+            path.insert(0, key.clone());
+            return Some(path);
+        }
+    }
+    None
+}
+
+fn set_path(tree: &mut Value, path: &[&str], raw: &str, var: &str) -> Result<()> {
+    let (last, parents) = path
+        .split_last()
+        .ok_or_else(|| anyhow!("empty config path"))?;
+    let mut node = tree;
+    for segment in parents {
+        node = node
+            .as_object_mut()
+            .and_then(|map| map.get_mut(*segment))
+            .ok_or_else(|| anyhow!("{}: config section `{}` is not set", var, segment))?;
+    }
+    let map = node
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("{}: `{}` is not a config section", var, path.join(".")))?;
+    let current = map.get(*last).cloned().unwrap_or(Value::Null);
+    map.insert(last.to_string(), parse_like(&current, raw, var)?);
+    Ok(())
+}
+
+/// Parses `raw` into the JSON type of the value it replaces.
+fn parse_like(current: &Value, raw: &str, var: &str) -> Result<Value> {
+    let raw = raw.trim();
+    Ok(match current {
+        Value::Bool(_) => match raw.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" => Value::Bool(true),
+            "false" | "0" | "no" => Value::Bool(false),
+            _ => bail!("{} must be true or false, got `{}`", var, raw),
+        },
+        Value::Number(_) => serde_json::from_str::<serde_json::Number>(raw)
+            .map(Value::Number)
+            .map_err(|_| anyhow!("{} must be a number, got `{}`", var, raw))?,
+        Value::Array(_) => Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| Value::String(item.to_string()))
+                .collect(),
+        ),
+        Value::Object(_) => bail!("{} names a config section, not a field", var),
+        Value::String(_) | Value::Null => Value::String(raw.to_string()),
+    })
+}
+
+/// The configuration as JSON with secret values replaced, for logging.
+pub fn redacted(config: &Config) -> Value {
+    fn walk(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, child) in map.iter_mut() {
+                    let secret = ["secret", "password", "token"]
+                        .iter()
+                        .any(|word| key.ends_with(word));
+                    if secret && !child.is_null() {
+                        *child = Value::String("<redacted>".to_string());
+                    } else {
+                        walk(child);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(walk),
+            _ => {}
+        }
+    }
+    let mut tree = serde_json::to_value(config).unwrap_or(Value::Null);
+    walk(&mut tree);
+    tree
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn env_overrides_beat_file_values() {
+        let file: Config = ron::from_str(
+            r#"Config(clone: CloneConfig(max_attempts: 2), server: ServerConfig(bind_addr: "127.0.0.1:9000"))"#,
+        )
+        .unwrap();
+        let config = apply_env_overrides(
+            file,
+            vars(&[
+                ("FORGE_CLONE_MAX_BYTES_PER_SEC", "1024"),
+                ("FORGE_CLONE_MAX_BYTES", "4096"),
+                ("FORGE_EXTENSIONS_SETTINGS_OFFLINE_MODE", "true"),
+                ("FORGE_AUTH_ADMIN_DIDS", "did:plc:a, did:plc:b"),
+                ("FORGE_REPOS_PATH", "/srv/repos"),
+                ("FORGE_LEASE_TTL_SECS", "10"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.clone.max_attempts, 2);
+        assert_eq!(config.clone.max_bytes, 4096);
+        assert_eq!(config.clone.max_bytes_per_sec, 1024);
+        assert_eq!(config.server.bind_addr, "127.0.0.1:9000");
+        assert!(config.extensions.settings.offline_mode);
+        assert_eq!(config.auth.admin_dids, vec!["did:plc:a", "did:plc:b"]);
+        assert_eq!(
+            config.repos.path.as_deref(),
+            Some(std::path::Path::new("/srv/repos"))
+        );
+    }
+
+    #[test]
+    fn legacy_names_lose_to_canonical_ones() {
+        let config = apply_env_overrides(
+            Config::default(),
+            vars(&[
+                ("PORT", "3000"),
+                ("FORGE_IN_MEMORY_DB", "true"),
+                ("FORGE_PUBLIC_BASE_URL", "https://old.example"),
+                ("FORGE_SERVER_PUBLIC_BASE_URL", "https://forge.example"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.server.bind_addr, "0.0.0.0:3000");
+        assert!(config.db.in_memory);
+        assert_eq!(config.server.public_base_url, "https://forge.example");
+    }
+
+    #[test]
+    fn malformed_values_name_the_variable() {
+        let err = apply_env_overrides(
+            Config::default(),
+            vars(&[("FORGE_CLONE_MAX_ATTEMPTS", "lots")]),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("FORGE_CLONE_MAX_ATTEMPTS"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        let mut config = Config::default();
+        config.auth.client_secret = Some("hunter2".to_string());
+        let printed = redacted(&config).to_string();
+        assert!(!printed.contains("hunter2"));
+        assert!(printed.contains("<redacted>"));
+    }
+}
@@ -1,25 +1,1082 @@
 //! Configuration management for Forge
 //!
-//! This module handles loading and parsing of configuration files, primarily
-//! for OCI-based extension distribution. Configuration is stored in RON format
-//! for better Rust type expressiveness.
+//! Configuration is layered: built-in defaults, then the RON file, then `FORGE_*`
+//! environment overrides (see [`env`]). Configuration is stored in RON format for
+//! better Rust type expressiveness. `main` installs the effective configuration with
+//! [`init`]; the rest of the server reads it through [`current`].
 
+pub mod env;
 pub mod loader;
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::{LazyLock, OnceLock};
+
+static EFFECTIVE: OnceLock<Config> = OnceLock::new();
+static DEFAULTS: LazyLock<Config> = LazyLock::new(Config::default);
+
+/// Installs the effective configuration; later calls are ignored.
+pub fn init(config: Config) {
+    let _ = EFFECTIVE.set(config);
+}
+
+/// The effective configuration, or the defaults before [`init`] (e.g. in tests).
+pub fn current() -> &'static Config {
+    EFFECTIVE.get().unwrap_or(&DEFAULTS)
+}
 
 /// Top-level configuration for Forge
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
 pub struct Config {
+    #[serde(default)]
+    pub server: ServerConfig,
+
+    #[serde(default)]
+    pub repos: ReposConfig,
+
+    #[serde(default)]
+    pub db: DbConfig,
+
+    #[serde(default)]
+    pub auth: AuthSettings,
+
+    /// Limits for cloning linked remote repositories
+    #[serde(default)]
+    pub clone: CloneConfig,
+
+    /// Git Smart HTTP request limits and concurrency budgets
+    #[serde(default)]
+    pub git: GitConfig,
+
     #[serde(default)]
     pub extensions: Extensions,
+
+    /// Optional publishing of the composed supergraph to an external schema registry
+    #[serde(default)]
+    pub schema_registry: Option<SchemaRegistryConfig>,
+
+    /// Structured HTTP access logging; disabled when absent
+    #[serde(default)]
+    pub access_log: Option<AccessLogConfig>,
+
+    /// Archive of deleted data and how long it is kept
+    #[serde(default)]
+    pub compliance: ComplianceConfig,
+
+    /// Concurrency limits for GraphQL execution
+    #[serde(default)]
+    pub queries: QueryLimitsConfig,
+
+    /// Global object IDs and the deprecation window for legacy ones
+    #[serde(default)]
+    pub ids: IdsConfig,
+
+    /// Export of forge events to NATS or Kafka; disabled when absent
+    #[serde(default)]
+    pub event_sink: Option<EventSinkConfig>,
+
+    /// Self-service exports of a user's own data
+    #[serde(default)]
+    pub account_export: AccountExportConfig,
+
+    /// Retention and exports of the audit log
+    #[serde(default)]
+    pub audit_log: AuditLogConfig,
+
+    /// DID resolution and signed requests between Forge instances
+    #[serde(default)]
+    pub federation: FederationConfig,
+
+    /// Periodic re-resolution of users' handles, display names and avatars
+    #[serde(default)]
+    pub profile_refresh: ProfileRefreshConfig,
+
+    /// Encryption of repository secrets
+    #[serde(default)]
+    pub secrets: SecretsConfig,
+
+    /// Preview images for links shared on social media and chat
+    #[serde(default)]
+    pub og: OgConfig,
+
+    /// Web Push delivery of notifications to browsers
+    #[serde(default)]
+    pub push: PushConfig,
+
+    /// Outgoing repository and instance webhooks
+    #[serde(default)]
+    pub webhooks: WebhooksConfig,
+
+    /// Scheduled rebuilds and consistency checks of the code and symbol search indexes
+    #[serde(default)]
+    pub search: SearchConfig,
+
+    /// Spam and abuse screening of new issues, comments and repositories; disabled when
+    /// absent
+    #[serde(default)]
+    pub abuse_check: Option<AbuseCheckConfig>,
+
+    /// Per-group usage accounting, quotas and billing exports for hosted instances;
+    /// disabled when absent
+    #[serde(default)]
+    pub metering: Option<MeteringConfig>,
+
+    /// Test data applied at startup for local development
+    #[serde(default)]
+    pub seed: SeedConfig,
+}
+
+/// HTTP listener settings
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Address the API listens on
+    pub bind_addr: String,
+
+    /// URL clients reach the server at; used for OAuth client metadata and cookies
+    pub public_base_url: String,
+
+    /// Allowed CORS origins; empty allows any origin, which suits local development only
+    pub cors_origins: Vec<String>,
+
+    /// Self-signed HTTPS for local development
+    pub dev_tls: DevTlsConfig,
+
+    /// Generated GraphQL responses for frontend development
+    pub mock: MockConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: "0.0.0.0:8000".to_string(),
+            public_base_url: "http://localhost:8000".to_string(),
+            cors_origins: Vec::new(),
+            dev_tls: DevTlsConfig::default(),
+            mock: MockConfig::default(),
+        }
+    }
+}
+
+/// Local HTTPS with a generated certificate (see `api::dev_tls`); not for production
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct DevTlsConfig {
+    pub enabled: bool,
+
+    /// Address the HTTPS listener binds
+    pub bind_addr: String,
+
+    /// Names the certificate is valid for
+    pub hostnames: Vec<String>,
+
+    /// Where the generated certificate and key are kept between runs
+    pub cert_dir: PathBuf,
+
+    /// Answer plain HTTP on `server.bind_addr` with a redirect to HTTPS
+    pub redirect_http: bool,
+}
+
+impl Default for DevTlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_addr: "127.0.0.1:8443".to_string(),
+            hostnames: ["localhost", "127.0.0.1", "::1"].map(String::from).to_vec(),
+            cert_dir: PathBuf::from("server/.forge/dev-tls"),
+            redirect_http: true,
+        }
+    }
+}
+
+/// Serve generated data for the composed schema instead of resolving it (see
+/// `router::mock_executor`); not for production
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct MockConfig {
+    pub enabled: bool,
+
+    /// Changing the seed changes every generated value
+    pub seed: u64,
+
+    /// Longest list a field returns; lengths vary between 1 and this
+    pub list_length: usize,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            seed: 0,
+            list_length: 5,
+        }
+    }
+}
+
+/// Repository storage settings
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+#[serde(default)]
+pub struct ReposConfig {
+    /// Root directory for bare repositories; required unless `db.in_memory` is set
+    pub path: Option<PathBuf>,
+
+    /// `flat` or `sharded`; defaults to flat
+    pub layout: Option<String>,
+
+    /// Directories `importRepositoryFromPath` may read from; imports from disk are
+    /// disabled while this is empty
+    pub import_roots: Vec<PathBuf>,
+
+    /// Transliterate new group and repository slugs to kebab-case ASCII, so `Café Crème`
+    /// becomes `cafe-creme`
+    pub transliterate_slugs: bool,
+}
+
+/// Metadata database settings
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+#[serde(default)]
+pub struct DbConfig {
+    /// Directory holding `forge.db`; required unless `in_memory` is set
+    pub path: Option<PathBuf>,
+
+    /// Use a throwaway in-memory database and per-process scratch storage for repositories
+    pub in_memory: bool,
+
+    /// Encryption at rest for every SQLite database; needs the `sqlcipher` build
+    pub encryption: DbEncryptionConfig,
+
+    /// Continuous backup of `forge.db` to object storage
+    pub replication: ReplicationConfig,
+}
+
+/// Where the database encryption key comes from. Set at most one source.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+#[serde(default)]
+pub struct DbEncryptionConfig {
+    /// File holding the key as 64 hex characters, such as a mounted secret
+    pub key_file: Option<PathBuf>,
+
+    /// Program and arguments that print the key, such as a secrets manager CLI
+    pub key_command: Option<Vec<String>>,
+}
+
+/// Write-ahead log shipping (see `replication`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct ReplicationConfig {
+    pub enabled: bool,
+
+    /// S3-compatible endpoint such as `https://s3.eu-west-1.amazonaws.com`, or
+    /// `file:///path` to write to a directory
+    pub endpoint: String,
+
+    pub bucket: String,
+
+    pub region: String,
+
+    /// Key prefix for everything this server writes, so a bucket can hold several
+    pub prefix: String,
+
+    /// Environment variable holding `access_key_id:secret_access_key`
+    pub credentials_env: Option<String>,
+
+    /// How often newly committed transactions are shipped
+    pub sync_interval_secs: u64,
+
+    /// How often a new generation starts from a fresh snapshot
+    pub snapshot_interval_secs: u64,
+
+    /// How far back a restore can go; older generations are deleted
+    pub retention_secs: u64,
+
+    /// Log length, in pages, after which shipped frames are checkpointed
+    pub checkpoint_pages: u64,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: String::new(),
+            bucket: String::new(),
+            region: "us-east-1".to_string(),
+            prefix: "forge".to_string(),
+            credentials_env: None,
+            sync_interval_secs: 1,
+            snapshot_interval_secs: 24 * 60 * 60,
+            retention_secs: 7 * 24 * 60 * 60,
+            checkpoint_pages: 1000,
+        }
+    }
+}
+
+/// ATProto OAuth and session settings
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct AuthSettings {
+    pub redirect_uri: String,
+
+    pub oauth_scope: String,
+
+    /// Only needed for confidential clients; the server is a public client by default
+    pub client_secret: Option<String>,
+
+    /// SQLite file for OAuth flows and sessions
+    pub db_path: PathBuf,
+
+    /// Age after which unfinished login flows are discarded
+    pub flow_ttl_secs: u64,
+
+    pub clean_interval_secs: u64,
+
+    pub vacuum_interval_secs: u64,
+
+    /// DIDs allowed to run administrative queries
+    pub admin_dids: Vec<String>,
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        Self {
+            redirect_uri: "http://127.0.0.1:8000/auth/callback".to_string(),
+            oauth_scope: "atproto".to_string(),
+            client_secret: None,
+            db_path: PathBuf::from("server/.forge/auth.db"),
+            flow_ttl_secs: 30 * 60,
+            clean_interval_secs: 5 * 60,
+            vacuum_interval_secs: 6 * 60 * 60,
+            admin_dids: Vec::new(),
+        }
+    }
+}
+
+/// Remote clone limits; `0` means unlimited for the byte limits
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct CloneConfig {
+    /// Wall-clock limit for one attempt
+    pub timeout_secs: u64,
+
+    /// Transfer budget per attempt
+    pub max_bytes: u64,
+
+    /// Average bandwidth cap per clone
+    pub max_bytes_per_sec: u64,
+
+    /// Attempts before a clone is marked failed
+    pub max_attempts: i64,
+}
+
+impl Default for CloneConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30 * 60,
+            max_bytes: 0,
+            max_bytes_per_sec: 0,
+            max_attempts: 5,
+        }
+    }
+}
+
+/// Git request limits. Work is split into priority classes, each with its own number of
+/// slots, so a burst of clones cannot stall interactive fetches and pushes. `0` leaves a
+/// class unlimited.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct GitConfig {
+    /// Largest request body Smart HTTP accepts
+    pub max_request_bytes: usize,
+
+    /// Wall-clock limit for one Smart HTTP request
+    pub request_timeout_ms: u64,
+
+    /// Slots for incremental fetches, ref advertisements, pushes and raw file reads
+    pub interactive_max_concurrent: usize,
+
+    /// Slots for fetches without haves (initial clones) and archive downloads
+    pub clone_max_concurrent: usize,
+
+    /// Slots for initial imports and mirror syncs of linked remote repositories
+    pub sync_max_concurrent: usize,
+
+    /// Requests of one class allowed to wait for a slot before new ones get `503`
+    pub max_queued: usize,
+
+    /// How long a request waits for a slot before it gets `503`
+    pub queue_timeout_ms: u64,
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            max_request_bytes: 64 * 1024 * 1024,
+            request_timeout_ms: 120_000,
+            interactive_max_concurrent: 48,
+            clone_max_concurrent: 8,
+            sync_max_concurrent: 4,
+            max_queued: 256,
+            queue_timeout_ms: 30_000,
+        }
+    }
+}
+
+impl GitConfig {
+    pub fn limits(&self) -> git_http::scheduler::GitLimits {
+        git_http::scheduler::GitLimits {
+            interactive: self.interactive_max_concurrent,
+            clone: self.clone_max_concurrent,
+            sync: self.sync_max_concurrent,
+            max_queued: self.max_queued,
+            queue_timeout: std::time::Duration::from_millis(self.queue_timeout_ms),
+        }
+    }
+}
+
+/// Retention of the deletion archive (see `compliance`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct ComplianceConfig {
+    /// Days an archival record is kept; `0` keeps records forever
+    pub retention_days: u64,
+
+    /// Per-kind retention in days (`repository`, `issue`, ...), overriding `retention_days`
+    pub retention_overrides: HashMap<String, u64>,
+
+    /// Summary fields stored only as a digest and length
+    pub redact_fields: Vec<String>,
+
+    /// How often expired records are purged
+    pub prune_interval_secs: u64,
+}
+
+impl Default for ComplianceConfig {
+    fn default() -> Self {
+        Self {
+            retention_days: 365,
+            retention_overrides: HashMap::new(),
+            redact_fields: ["body", "description", "content", "text", "email"]
+                .map(String::from)
+                .to_vec(),
+            prune_interval_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+impl ComplianceConfig {
+    pub fn retention_days_for(&self, kind: &str) -> u64 {
+        self.retention_overrides
+            .get(kind)
+            .copied()
+            .unwrap_or(self.retention_days)
+    }
+}
+
+/// Account data exports (see `user::export`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct AccountExportConfig {
+    /// How long a finished archive is kept before it is deleted
+    pub retention_secs: u64,
+
+    /// Lifetime of a signed download link; links never outlive the archive
+    pub link_ttl_secs: u64,
+
+    /// Attempts before an export is marked failed
+    pub max_attempts: i64,
+}
+
+impl Default for AccountExportConfig {
+    fn default() -> Self {
+        Self {
+            retention_secs: 7 * 24 * 60 * 60,
+            link_ttl_secs: 60 * 60,
+            max_attempts: 3,
+        }
+    }
+}
+
+/// The audit log of published events (see `audit`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct AuditLogConfig {
+    /// Days an entry is kept; `0` keeps entries forever
+    pub retention_days: u64,
+
+    /// Per-category retention in days (`repository`, `issue`, `user`, ...), overriding
+    /// `retention_days`. The category is the first word of the action.
+    pub retention_overrides: HashMap<String, u64>,
+
+    /// How long a finished export is kept before it is deleted
+    pub export_retention_secs: u64,
+
+    /// Most entries one export may contain
+    pub max_export_rows: i64,
+
+    /// Attempts before an export is marked failed
+    pub max_attempts: i64,
+}
+
+impl Default for AuditLogConfig {
+    fn default() -> Self {
+        Self {
+            retention_days: 365,
+            retention_overrides: HashMap::new(),
+            export_retention_secs: 7 * 24 * 60 * 60,
+            max_export_rows: 100_000,
+            max_attempts: 3,
+        }
+    }
+}
+
+impl AuditLogConfig {
+    pub fn retention_days_for(&self, category: &str) -> u64 {
+        self.retention_overrides
+            .get(category)
+            .copied()
+            .unwrap_or(self.retention_days)
+    }
+}
+
+/// Refreshing cached ATProto profiles (see `user::profiles`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct ProfileRefreshConfig {
+    /// Re-resolve users' DID documents and profiles in the background
+    pub enabled: bool,
+
+    /// How old a user's cached profile may get before it is refreshed
+    pub interval_secs: u64,
+
+    /// Most profiles refreshed per minute; each refresh makes up to three requests
+    pub refreshes_per_minute: u32,
+
+    /// Attempts before a refresh is given up until the next interval
+    pub max_attempts: i64,
+}
+
+impl Default for ProfileRefreshConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 24 * 60 * 60,
+            refreshes_per_minute: 30,
+            max_attempts: 3,
+        }
+    }
+}
+
+/// Identity federation (see `federation`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct FederationConfig {
+    /// Publish this instance's DID document and accept signed requests from other instances
+    pub enabled: bool,
+
+    /// PKCS#8 signing key of this instance; generated on first start
+    pub key_path: PathBuf,
+
+    /// Instance DIDs allowed to send signed requests; empty accepts any resolvable `did:web`
+    pub trusted_instances: Vec<String>,
+
+    /// Signed requests whose timestamp is further off than this are rejected
+    pub max_clock_skew_secs: u64,
+
+    /// Directory used to resolve `did:plc` identities
+    pub plc_directory: String,
+
+    /// How long resolved DID documents and link checks are reused
+    pub did_cache_ttl_secs: u64,
+}
+
+impl Default for FederationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key_path: PathBuf::from("server/.forge/federation.key"),
+            trusted_instances: Vec::new(),
+            max_clock_skew_secs: 5 * 60,
+            plc_directory: "https://plc.directory".to_string(),
+            did_cache_ttl_secs: 60 * 60,
+        }
+    }
+}
+
+/// Repository secrets (see `repository::secrets`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct SecretsConfig {
+    /// 256-bit key that encrypts secret values; generated on first use
+    pub key_path: PathBuf,
+}
+
+impl Default for SecretsConfig {
+    fn default() -> Self {
+        Self {
+            key_path: PathBuf::from("server/.forge/secrets.key"),
+        }
+    }
+}
+
+/// OpenGraph preview images (see `og`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct OgConfig {
+    /// Serve `/og/{kind}/{path}`
+    pub enabled: bool,
+
+    /// Rendered PNGs, named by content hash; safe to clear at any time
+    pub cache_dir: PathBuf,
+
+    /// Rendered images kept before the least recently written are removed
+    pub max_cached: usize,
+
+    /// Fonts loaded in addition to the system fonts
+    pub font_dirs: Vec<PathBuf>,
+
+    /// CSS-style font family list used for all text on a card
+    pub font_family: String,
+
+    /// Name printed in the footer of every card
+    pub site_name: String,
+
+    pub theme: OgTheme,
+}
+
+impl Default for OgConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cache_dir: PathBuf::from("server/.forge/og"),
+            max_cached: 10_000,
+            font_dirs: Vec::new(),
+            font_family: "Inter, DejaVu Sans, sans-serif".to_string(),
+            site_name: "Forge".to_string(),
+            theme: OgTheme::default(),
+        }
+    }
+}
+
+/// Card colours as SVG colour values; the defaults match the web UI's default theme
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct OgTheme {
+    pub background: String,
+    pub foreground: String,
+    /// Paths, descriptions and the footer
+    pub muted: String,
+    /// The stripe along the top and the status badge
+    pub accent: String,
+}
+
+impl Default for OgTheme {
+    fn default() -> Self {
+        Self {
+            background: "#faf4ed".to_string(),
+            foreground: "#575279".to_string(),
+            muted: "#9893a5".to_string(),
+            accent: "#907aa9".to_string(),
+        }
+    }
+}
+
+/// GraphQL admission control (see `api::admission`); `max_concurrent: 0` disables it
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct QueryLimitsConfig {
+    /// Operations executing at once, across both priority classes
+    pub max_concurrent: usize,
+
+    /// Share of `max_concurrent` that batch and admin operations may hold
+    pub batch_max_concurrent: usize,
+
+    /// Operations allowed to wait for a slot before new ones are refused
+    pub max_queued: usize,
+
+    /// How long an operation waits for a slot before it is refused
+    pub queue_timeout_ms: u64,
+}
+
+impl Default for QueryLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 16,
+            batch_max_concurrent: 2,
+            max_queued: 64,
+            queue_timeout_ms: 5_000,
+        }
+    }
+}
+
+/// Web Push (see `notifications::push`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct PushConfig {
+    /// Let browsers subscribe and push new notifications to them
+    pub enabled: bool,
+
+    /// PKCS#8 VAPID key identifying this server to push services; generated on first start
+    pub vapid_key_path: PathBuf,
+
+    /// Contact for push service operators (`mailto:` or `https:`); defaults to
+    /// `server.public_base_url`
+    pub subject: Option<String>,
+
+    /// How long push services hold a message for an offline browser. Notifications older
+    /// than this are not pushed at all.
+    pub ttl_secs: u64,
+
+    /// How often new notifications are looked for
+    pub poll_interval_secs: u64,
+
+    /// How often users on a daily or weekly digest are checked for one that is due
+    pub digest_interval_secs: u64,
+
+    /// Consecutive failed deliveries after which a subscription is dropped
+    pub max_failures: u32,
+}
+
+impl Default for PushConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            vapid_key_path: PathBuf::from("server/.forge/vapid.key"),
+            subject: None,
+            ttl_secs: 24 * 60 * 60,
+            poll_interval_secs: 5,
+            digest_interval_secs: 15 * 60,
+            max_failures: 5,
+        }
+    }
+}
+
+/// Outgoing webhooks (see `webhooks`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct WebhooksConfig {
+    /// Let owners register webhooks and deliver events to them
+    pub enabled: bool,
+
+    /// How often due deliveries are looked for
+    pub poll_interval_secs: u64,
+
+    /// How long a target has to answer a delivery
+    pub timeout_secs: u64,
+
+    /// Attempts before a delivery is marked failed
+    pub max_attempts: i64,
+
+    /// Deliveries sent at the same time
+    pub concurrency: usize,
+
+    /// Accept targets on `localhost`, IP addresses and single-label host names, such as a
+    /// CI server on the same network
+    pub allow_private_targets: bool,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            poll_interval_secs: 5,
+            timeout_secs: 10,
+            max_attempts: 8,
+            concurrency: 4,
+            allow_private_targets: false,
+        }
+    }
+}
+
+/// Maintenance of the code and symbol search indexes (see `search::maintenance`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// How often each index is rebuilt from Git; `0` leaves rebuilds to administrators
+    pub rebuild_interval_secs: u64,
+
+    /// How often a sample of each index is compared with Git; `0` turns checks off
+    pub check_interval_secs: u64,
+
+    /// Repositories compared per index by each check
+    pub check_sample_size: u32,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            rebuild_interval_secs: 7 * 24 * 60 * 60,
+            check_interval_secs: 60 * 60,
+            check_sample_size: 100,
+        }
+    }
+}
+
+/// Declarative test data for local development (see `seed`); not for production
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+#[serde(default)]
+pub struct SeedConfig {
+    /// RON manifest of users, groups, repositories and issues to create at startup
+    pub file: Option<PathBuf>,
+
+    /// Seed a database on disk too, not only an in-memory one
+    pub allow_persistent: bool,
+}
+
+/// Global object IDs (see `graphql::global_id`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct IdsConfig {
+    /// Accept the internal IDs the API returned before global IDs. Turn this off once
+    /// `forge_graphql_legacy_ids_total` stops growing.
+    pub accept_legacy: bool,
+}
+
+impl Default for IdsConfig {
+    fn default() -> Self {
+        Self {
+            accept_legacy: true,
+        }
+    }
+}
+
+/// Structured HTTP access log settings
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct AccessLogConfig {
+    /// How client addresses are recorded
+    #[serde(default)]
+    pub ip: IpPrivacy,
+
+    /// Environment variable holding the salt for `IpPrivacy::Hash`
+    #[serde(default)]
+    pub hash_salt_env: Option<String>,
+
+    /// Take the client address from the first `X-Forwarded-For` hop
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+
+    /// Fraction (0.0-1.0) of successful git requests to log; failures are always logged
+    #[serde(default = "default_git_sample_rate")]
+    pub git_sample_rate: f64,
+
+    /// Write JSON lines to this file instead of the application log
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
+
+/// Client address handling in the access log
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+pub enum IpPrivacy {
+    /// Log the address as received
+    Full,
+    /// Zero the host part (IPv4 /24, IPv6 /48)
+    #[default]
+    Truncate,
+    /// Log a salted SHA-256 digest of the address
+    Hash,
+    /// Do not log addresses
+    Omit,
+}
+
+fn default_git_sample_rate() -> f64 {
+    0.1
+}
+
+/// External schema registry that receives the composed supergraph
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct SchemaRegistryConfig {
+    /// Which registry API to talk to
+    pub provider: SchemaRegistryProvider,
+
+    /// API endpoint; defaults to the provider's hosted API
+    #[serde(default)]
+    pub endpoint: Option<String>,
+
+    /// Environment variable name containing the registry access token
+    pub token_env: String,
+
+    /// Apollo graph ref (`graph-id@variant`); ignored by Hive
+    #[serde(default)]
+    pub graph_ref: Option<String>,
+
+    /// Log what would be published without calling the registry
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Publish attempts before giving up (failures never block startup)
+    #[serde(default = "default_registry_max_attempts")]
+    pub max_attempts: u32,
+}
+
+/// Supported schema registry APIs
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub enum SchemaRegistryProvider {
+    Hive,
+    Apollo,
+}
+
+fn default_registry_max_attempts() -> u32 {
+    3
+}
+
+/// Broker that receives exported events (see `events`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct EventSinkConfig {
+    pub transport: EventTransport,
+
+    /// `nats://host:4222`, or the base URL of a Kafka REST Proxy
+    pub url: String,
+
+    /// Subject or topic name; `{type}` is replaced with the event type
+    #[serde(default = "default_event_topic")]
+    pub topic: String,
+
+    /// Per-type subjects or topics, overriding `topic`
+    #[serde(default)]
+    pub topics: HashMap<String, String>,
+
+    /// Event types to export (`issue.created`, or `issue.*` for a family); empty exports all
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Environment variable holding credentials: a NATS token or `user:password`
+    #[serde(default)]
+    pub credentials_env: Option<String>,
+
+    /// Delivery attempts per event before it is given up
+    #[serde(default = "default_event_max_attempts")]
+    pub max_attempts: i64,
+}
+
+/// Supported event brokers
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub enum EventTransport {
+    Nats,
+    /// Kafka through the Confluent REST Proxy (v2 API)
+    KafkaRest,
+}
+
+fn default_event_topic() -> String {
+    "forge.events.{type}".to_string()
+}
+
+fn default_event_max_attempts() -> i64 {
+    10
+}
+
+/// Spam and abuse screening of new content (see `moderation`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct AbuseCheckConfig {
+    pub checker: AbuseChecker,
+
+    /// How long to wait for a verdict
+    #[serde(default = "default_abuse_timeout_ms")]
+    pub timeout_ms: u64,
+
+    /// Allow content when the checker fails or times out; otherwise it is rejected
+    #[serde(default = "default_abuse_fail_open")]
+    pub fail_open: bool,
+}
+
+/// Who decides whether content is spam
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub enum AbuseChecker {
+    /// A loaded extension that exports `abuse-check`
+    Extension(String),
+    /// A service that is POSTed the content as JSON
+    Http {
+        url: String,
+        /// Environment variable holding a bearer token for the service
+        #[serde(default)]
+        token_env: Option<String>,
+    },
+}
+
+fn default_abuse_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_abuse_fail_open() -> bool {
+    true
+}
+
+/// Usage accounting of top-level groups (see `metering`)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct MeteringConfig {
+    /// Quotas of top-level groups without an entry in `groups`
+    pub default_quota: QuotaConfig,
+
+    /// Quotas by top-level group slug
+    pub groups: HashMap<String, QuotaConfig>,
+
+    /// How often the size of each group's repositories is measured
+    pub storage_sample_secs: u64,
+
+    /// Format of the daily usage exports
+    pub export_format: UsageExportFormat,
+
+    /// How long a daily export is kept before it is deleted
+    pub export_retention_secs: u64,
+
+    /// Days of usage kept in the database; `0` keeps it forever
+    pub retention_days: u64,
+}
+
+impl Default for MeteringConfig {
+    fn default() -> Self {
+        Self {
+            default_quota: QuotaConfig::default(),
+            groups: HashMap::new(),
+            storage_sample_secs: 60 * 60,
+            export_format: UsageExportFormat::Csv,
+            export_retention_secs: 30 * 24 * 60 * 60,
+            retention_days: 400,
+        }
+    }
+}
+
+impl MeteringConfig {
+    pub fn quota_for(&self, group: &str) -> &QuotaConfig {
+        self.groups.get(group).unwrap_or(&self.default_quota)
+    }
+}
+
+/// Limits of one group. Storage is limited by its current size, the other resources by
+/// their total in the current UTC month.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+#[serde(default)]
+pub struct QuotaConfig {
+    pub storage_bytes: QuotaLimit,
+    /// Bytes served by git fetches and clones
+    pub bandwidth_bytes: QuotaLimit,
+    /// GraphQL requests that touched the group's repositories
+    pub api_calls: QuotaLimit,
+    /// Reserved for CI; nothing reports minutes yet
+    pub ci_minutes: QuotaLimit,
+}
+
+/// A soft limit only raises a warning; reaching the hard limit also refuses further use.
+/// Unset limits are unlimited.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Default)]
+#[serde(default)]
+pub struct QuotaLimit {
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub enum UsageExportFormat {
+    Csv,
+    Json,
 }
 
 /// Extension configuration section
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
 pub struct Extensions {
+    /// Directory scanned for `.wasm` extensions when none are configured (default `./extensions`)
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+
     /// OCI-distributed extensions
     #[serde(default)]
     pub oci: Vec<OciExtension>,
@@ -32,11 +1089,66 @@ pub struct Extensions {
     #[serde(default)]
     pub auth: HashMap<String, RegistryAuth>,
 
+    /// Mirrors per registry, tried in order before the registry itself
+    /// (e.g., `{"ghcr.io": ["registry.internal:5000"]}`). Mirrors take credentials from
+    /// `auth` under their own hostname.
+    #[serde(default)]
+    pub mirrors: HashMap<String, Vec<String>>,
+
+    /// Schema coordinates each extension may query through `host-graphql`, keyed by
+    /// extension name (e.g., `["Query.getRepository", "RepositoryNode.*"]`)
+    #[serde(default)]
+    pub graphql_scopes: HashMap<String, Vec<String>>,
+
+    /// Serve cached copies of upstream registries to other Forge instances; disabled when
+    /// absent
+    #[serde(default)]
+    pub registry_proxy: Option<RegistryProxyConfig>,
+
     /// Extension system settings
     #[serde(default)]
     pub settings: Settings,
 }
 
+/// The pull-through registry served under `/v2/`
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct RegistryProxyConfig {
+    /// Upstream registries by the path segment clients pull them under
+    /// (e.g., `{"ghcr": "ghcr.io"}` serves `ghcr.io/forgepoint/ext` as
+    /// `<forge host>/ghcr/forgepoint/ext`). Upstreams take credentials from `auth` under
+    /// their own hostname.
+    pub upstreams: HashMap<String, String>,
+
+    /// Directory for cached manifests and blobs (default `<cache_dir>/proxy`)
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Most bytes the cache may hold; the blobs served least recently are evicted beyond
+    /// it
+    #[serde(default = "default_proxy_max_bytes")]
+    pub max_bytes: u64,
+
+    /// How long a manifest pulled by tag is served before the tag is resolved again
+    #[serde(default = "default_proxy_tag_ttl_secs")]
+    pub tag_ttl_secs: u64,
+
+    /// Serve pulls without credentials; otherwise a personal access token is required
+    #[serde(default)]
+    pub allow_anonymous: bool,
+
+    /// DIDs whose access tokens may pull; empty allows every user
+    #[serde(default)]
+    pub allowed_dids: Vec<String>,
+}
+
+fn default_proxy_max_bytes() -> u64 {
+    10 * 1024 * 1024 * 1024
+}
+
+fn default_proxy_tag_ttl_secs() -> u64 {
+    5 * 60
+}
+
 /// OCI-distributed extension configuration
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct OciExtension {
@@ -142,6 +1254,26 @@ pub struct Settings {
     /// If true, verify checksums of cached extensions
     #[serde(default = "default_verify_checksums")]
     pub verify_checksums: bool,
+
+    /// How often cached OCI modules are re-hashed in the background; 0 disables it
+    #[serde(default = "default_verify_interval_secs")]
+    pub verify_interval_secs: u64,
+
+    /// How long a layer download may receive nothing before it is abandoned and resumed
+    /// from another mirror or on the next attempt
+    #[serde(default = "default_download_stall_secs")]
+    pub download_stall_secs: u64,
+
+    /// Scheduled tasks of one extension that may run at once. Tasks share the
+    /// extension instance with its resolvers, so each running task holds up its GraphQL
+    /// fields.
+    #[serde(default = "default_max_concurrent_tasks")]
+    pub max_concurrent_tasks: usize,
+
+    /// How often extension modules are checked for changes, which are then reloaded
+    /// without a restart; 0 (the default) disables it
+    #[serde(default)]
+    pub watch_interval_secs: u64,
 }
 
 impl Default for Settings {
@@ -150,16 +1282,41 @@ impl Default for Settings {
             cache_dir: Some(PathBuf::from(".forge/extensions/cache")),
             offline_mode: false,
             verify_checksums: true,
+            verify_interval_secs: default_verify_interval_secs(),
+            download_stall_secs: default_download_stall_secs(),
+            max_concurrent_tasks: default_max_concurrent_tasks(),
+            watch_interval_secs: 0,
         }
     }
 }
 
+impl Settings {
+    /// The configured cache directory, or `.forge/extensions/cache`.
+    pub fn cache_dir(&self) -> PathBuf {
+        self.cache_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(".forge/extensions/cache"))
+    }
+}
+
 fn default_verify_checksums() -> bool {
     true
 }
 
+fn default_verify_interval_secs() -> u64 {
+    6 * 60 * 60
+}
+
+fn default_download_stall_secs() -> u64 {
+    60
+}
+
+fn default_max_concurrent_tasks() -> usize {
+    1
+}
+
 /// Validate extension name - must be a valid slug
-fn validate_extension_name(name: &str) -> Result<(), String> {
+pub(crate) fn validate_extension_name(name: &str) -> Result<(), String> {
     if name.is_empty() {
         return Err("Extension name cannot be empty".to_string());
     }
@@ -206,6 +1363,65 @@ mod tests {
         assert!(config.extensions.auth.is_empty());
         assert!(!config.extensions.settings.offline_mode);
         assert!(config.extensions.settings.verify_checksums);
+        assert_eq!(config.server.bind_addr, "0.0.0.0:8000");
+        assert_eq!(config.clone.max_attempts, 5);
+        assert!(!config.db.in_memory);
+    }
+
+    #[test]
+    fn test_schema_registry_defaults() {
+        let config: Config = ron::from_str(
+            r#"Config(schema_registry: Some(SchemaRegistryConfig(provider: Hive, token_env: "HIVE_TOKEN")))"#,
+        )
+        .unwrap();
+        let registry = config.schema_registry.unwrap();
+        assert_eq!(registry.provider, SchemaRegistryProvider::Hive);
+        assert_eq!(registry.max_attempts, 3);
+        assert!(!registry.dry_run);
+        assert!(registry.endpoint.is_none());
+    }
+
+    #[test]
+    fn test_access_log_defaults() {
+        let config: Config =
+            ron::from_str(r#"Config(access_log: Some(AccessLogConfig()))"#).unwrap();
+        let access_log = config.access_log.unwrap();
+        assert_eq!(access_log.ip, IpPrivacy::Truncate);
+        assert_eq!(access_log.git_sample_rate, 0.1);
+        assert!(access_log.path.is_none());
+        assert!(!access_log.trust_forwarded_for);
+    }
+
+    #[test]
+    fn test_metering_quotas() {
+        let config: Config = ron::from_str(
+            r#"Config(metering: Some(MeteringConfig(
+                default_quota: QuotaConfig(api_calls: QuotaLimit(soft: Some(800), hard: Some(1000))),
+                groups: {"acme": QuotaConfig(storage_bytes: QuotaLimit(hard: Some(1024)))},
+                export_format: Json,
+            )))"#,
+        )
+        .unwrap();
+        let metering = config.metering.unwrap();
+        assert_eq!(metering.export_format, UsageExportFormat::Json);
+        assert_eq!(metering.storage_sample_secs, 3600);
+        assert_eq!(metering.quota_for("other").api_calls.hard, Some(1000));
+        let acme = metering.quota_for("acme");
+        assert_eq!(acme.storage_bytes.hard, Some(1024));
+        assert_eq!(acme.api_calls, QuotaLimit::default());
+    }
+
+    #[test]
+    fn test_registry_mirrors() {
+        let config: Config = ron::from_str(
+            r#"Config(extensions: Extensions(mirrors: {"ghcr.io": ["registry.internal:5000", "ghcr-cache.example.com"]}))"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.extensions.mirrors["ghcr.io"],
+            vec!["registry.internal:5000", "ghcr-cache.example.com"]
+        );
+        assert_eq!(config.extensions.settings.download_stall_secs, 60);
     }
 
     #[test]
@@ -0,0 +1,131 @@
+//! Time-bounded leases stored in SQLite.
+//!
+//! A lease is a row naming its holder and an expiry. The holder renews it periodically.
+//! Anyone may take it over once it expires, so a crashed instance only blocks others for
+//! one TTL.
+
+use std::time::Duration;
+
+use sqlx::SqlitePool;
+
+use crate::user::db::unix_now;
+
+#[derive(Clone, Debug)]
+pub struct LeaseManager {
+    pool: SqlitePool,
+    holder: String,
+    ttl: Duration,
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct LeaseRecord {
+    pub holder: String,
+    pub acquired_at: i64,
+    pub expires_at: i64,
+}
+
+impl LeaseManager {
+    pub fn new(pool: SqlitePool, holder: impl Into<String>, ttl: Duration) -> Self {
+        Self {
+            pool,
+            holder: holder.into(),
+            ttl,
+        }
+    }
+
+    pub fn holder(&self) -> &str {
+        &self.holder
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Takes or renews `name`. Returns false while another holder's lease is live.
+    pub async fn try_acquire(&self, name: &str) -> Result<bool, sqlx::Error> {
+        self.try_acquire_at(name, unix_now()).await
+    }
+
+    async fn try_acquire_at(&self, name: &str, now: i64) -> Result<bool, sqlx::Error> {
+        let expires_at = now + self.ttl.as_secs().max(1) as i64;
+        let result = sqlx::query(
+            "INSERT INTO instance_leases (name, holder, acquired_at, expires_at) VALUES (?, ?, ?, ?) \
+             ON CONFLICT(name) DO UPDATE SET \
+               acquired_at = CASE WHEN instance_leases.holder = excluded.holder \
+                 THEN instance_leases.acquired_at ELSE excluded.acquired_at END, \
+               holder = excluded.holder, expires_at = excluded.expires_at \
+             WHERE instance_leases.holder = excluded.holder OR instance_leases.expires_at <= ?",
+        )
+        .bind(name)
+        .bind(&self.holder)
+        .bind(now)
+        .bind(expires_at)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn release(&self, name: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM instance_leases WHERE name = ? AND holder = ?")
+            .bind(name)
+            .bind(&self.holder)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn current(&self, name: &str) -> Result<Option<LeaseRecord>, sqlx::Error> {
+        sqlx::query_as::<_, LeaseRecord>(
+            "SELECT holder, acquired_at, expires_at FROM instance_leases WHERE name = ?",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+    }
+}
+
+/// Identifier for this process: `FORGE_INSTANCE_ID`, else hostname plus pid.
+pub fn instance_id() -> String {
+    if let Ok(id) = std::env::var("FORGE_INSTANCE_ID")
+        && !id.trim().is_empty()
+    {
+        return id.trim().to_string();
+    }
+    let host = std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "forge".to_string());
+    format!("{host}-{}", std::process::id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    #[tokio::test]
+    async fn lease_is_exclusive_until_expiry() {
+        let pool = create_test_pool().await.unwrap();
+        let a = LeaseManager::new(pool.clone(), "a", Duration::from_secs(30));
+        let b = LeaseManager::new(pool.clone(), "b", Duration::from_secs(30));
+
+        assert!(a.try_acquire_at("storage-writer", 100).await.unwrap());
+        assert!(!b.try_acquire_at("storage-writer", 110).await.unwrap());
+        // renewal by the holder keeps the original acquisition time
+        assert!(a.try_acquire_at("storage-writer", 120).await.unwrap());
+        let lease = a.current("storage-writer").await.unwrap().unwrap();
+        assert_eq!((lease.acquired_at, lease.expires_at), (100, 150));
+
+        assert!(b.try_acquire_at("storage-writer", 150).await.unwrap());
+        assert_eq!(
+            b.current("storage-writer").await.unwrap().unwrap().holder,
+            "b"
+        );
+
+        b.release("storage-writer").await.unwrap();
+        assert!(a.current("storage-writer").await.unwrap().is_none());
+    }
+}
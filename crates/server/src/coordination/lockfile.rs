@@ -0,0 +1,94 @@
+//! Advisory lock files (`flock`/`LockFileEx` through `std::fs::File`).
+//!
+//! The OS drops the lock when the process exits, so a crashed instance never leaves a
+//! stale lock behind.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Context;
+
+#[derive(Debug)]
+pub struct LockFile {
+    _file: File,
+}
+
+impl LockFile {
+    /// Takes the lock without waiting. `Ok(None)` means another process holds it.
+    pub fn try_acquire(path: impl AsRef<Path>, holder: &str) -> anyhow::Result<Option<Self>> {
+        let path = path.as_ref();
+        let mut file = open(path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("failed to lock {}", path.display()));
+            }
+        }
+        // Record the holder for operators inspecting the file; the lock itself is what counts.
+        file.set_len(0)?;
+        writeln!(file, "{holder} pid={}", std::process::id())?;
+        Ok(Some(Self { _file: file }))
+    }
+
+    /// Blocks until the lock is free. Used for short critical sections.
+    pub fn acquire(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = open(path)?;
+        file.lock()
+            .with_context(|| format!("failed to lock {}", path.display()))?;
+        Ok(Self { _file: file })
+    }
+
+    /// Holder line written by the current owner, if any.
+    pub fn describe_holder(path: impl AsRef<Path>) -> Option<String> {
+        std::fs::read_to_string(path)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    }
+}
+
+fn open(path: &Path) -> anyhow::Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("failed to open lock file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_holder_is_refused_until_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".forge.lock");
+
+        let first = LockFile::try_acquire(&path, "instance-a").unwrap().unwrap();
+        assert!(
+            LockFile::try_acquire(&path, "instance-b")
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            LockFile::describe_holder(&path)
+                .unwrap()
+                .starts_with("instance-a")
+        );
+
+        drop(first);
+        assert!(
+            LockFile::try_acquire(&path, "instance-b")
+                .unwrap()
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! Coordination between server instances that share a database and repository storage.
+//!
+//! The instance that wins the `storage-writer` lease (plus an OS lock on the repository
+//! root) is the only one allowed to write. Others either refuse to start or, with
+//! `FORGE_ALLOW_READ_ONLY=true`, serve reads and reject mutations.
+
+pub mod lease;
+pub mod lockfile;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub use lease::LeaseManager;
+pub use lockfile::LockFile;
+
+/// Lease guarding repository writes and maintenance jobs.
+pub const STORAGE_WRITER_LEASE: &str = "storage-writer";
+
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// True once this instance has lost (or never held) the writer lease.
+pub fn is_read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+pub fn set_read_only(read_only: bool) {
+    READ_ONLY.store(read_only, Ordering::Relaxed);
+}
+
+/// Rejects writes while the instance is in read-only mode.
+pub fn ensure_writable() -> anyhow::Result<()> {
+    if is_read_only() {
+        return Err(anyhow::anyhow!(
+            "this instance is read-only: another server instance holds the storage lease"
+        ));
+    }
+    Ok(())
+}
+
+/// Keeps the writer lease alive, or keeps trying to take it over while read-only.
+///
+/// Losing a renewal drops the instance to read-only rather than risking two writers;
+/// a standby instance promotes itself once both the lock file and the lease are free.
+pub async fn run_lease_keeper(
+    leases: LeaseManager,
+    lock_path: std::path::PathBuf,
+    mut lock: Option<LockFile>,
+    shutdown: tokio_util::sync::CancellationToken,
+) -> anyhow::Result<()> {
+    let mut ticker =
+        tokio::time::interval((leases.ttl() / 3).max(std::time::Duration::from_secs(1)));
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => {}
+        }
+
+        if lock.is_none() {
+            lock = LockFile::try_acquire(&lock_path, leases.holder()).unwrap_or_else(|e| {
+                tracing::warn!("storage lock check failed: {}", e);
+                None
+            });
+            if lock.is_none() {
+                continue;
+            }
+        }
+
+        match leases.try_acquire(STORAGE_WRITER_LEASE).await {
+            Ok(true) if is_read_only() => {
+                tracing::info!("acquired storage lease; leaving read-only mode");
+                set_read_only(false);
+            }
+            Ok(true) => {}
+            Ok(false) => {
+                if !is_read_only() {
+                    tracing::error!(
+                        "storage lease taken over by another instance; switching to read-only mode"
+                    );
+                    set_read_only(true);
+                }
+                lock = None;
+            }
+            Err(e) => {
+                // Can't prove we still hold it; stop writing until the database answers again.
+                if !is_read_only() {
+                    tracing::error!(
+                        "storage lease renewal failed ({}); switching to read-only mode",
+                        e
+                    );
+                    set_read_only(true);
+                }
+            }
+        }
+    }
+
+    if !is_read_only()
+        && let Err(e) = leases.release(STORAGE_WRITER_LEASE).await
+    {
+        tracing::warn!("failed to release storage lease: {}", e);
+    }
+    Ok(())
+}
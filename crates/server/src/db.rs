@@ -1,21 +1,35 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::OnceLock;
 
 use anyhow::{Context, Result};
 use sqlx::SqlitePool;
+
+use crate::config::DbConfig;
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
 
-const FORGE_DB_FILENAME: &str = "forge.db";
+pub const FORGE_DB_FILENAME: &str = "forge.db";
 
-/// Initialize the forge metadata database, running migrations as needed.
-pub async fn init_pool() -> Result<(SqlitePool, PathBuf)> {
-    // Check for in-memory mode
-    if std::env::var("FORGE_IN_MEMORY_DB").unwrap_or_default() == "true" {
-        return init_in_memory_pool().await;
-    }
+static SHARED: OnceLock<SqlitePool> = OnceLock::new();
+
+/// Registers the metadata pool for code that cannot receive it as an argument, such as
+/// extension host calls. Later calls are ignored.
+pub fn install_shared(pool: SqlitePool) {
+    let _ = SHARED.set(pool);
+}
+
+/// The pool registered with [`install_shared`].
+pub fn shared() -> Option<&'static SqlitePool> {
+    SHARED.get()
+}
 
-    let db_root =
-        std::env::var("FORGE_DB_PATH").context("FORGE_DB_PATH environment variable must be set")?;
+/// Initialize the on-disk forge metadata database, running migrations as needed. In-memory
+/// mode uses [`init_in_memory_pool`] instead.
+pub async fn init_pool(config: &DbConfig) -> Result<(SqlitePool, PathBuf)> {
+    let db_root = config
+        .path
+        .clone()
+        .context("db.path must be set (FORGE_DB_PATH)")?;
 
     let db_root_path = normalize_path(db_root)?;
     std::fs::create_dir_all(&db_root_path)
@@ -24,11 +38,15 @@ pub async fn init_pool() -> Result<(SqlitePool, PathBuf)> {
     let forge_db_path = db_root_path.join(FORGE_DB_FILENAME);
     let db_uri = format!("sqlite://{}", forge_db_path.to_string_lossy());
 
-    let connect_options = SqliteConnectOptions::from_str(&db_uri)?
+    let mut connect_options = crate::encryption::keyed(SqliteConnectOptions::from_str(&db_uri)?)
         .create_if_missing(true)
         .journal_mode(SqliteJournalMode::Wal)
         .synchronous(SqliteSynchronous::Normal)
         .foreign_keys(true);
+    if config.replication.enabled {
+        // The replicator checkpoints once it has shipped the log (see `replication`)
+        connect_options = connect_options.pragma("wal_autocheckpoint", "0");
+    }
 
     let pool = SqlitePoolOptions::new()
         .max_connections(5)
@@ -40,25 +58,39 @@ pub async fn init_pool() -> Result<(SqlitePool, PathBuf)> {
     Ok((pool, db_root_path))
 }
 
-/// Initialize an in-memory SQLite database for development/testing
-pub async fn init_in_memory_pool() -> Result<(SqlitePool, PathBuf)> {
+/// Initialize an in-memory metadata database for development and testing. Extension
+/// databases and the remote cache go under `scratch_root`, which is returned as the DB root.
+pub async fn init_in_memory_pool(scratch_root: &Path) -> Result<(SqlitePool, PathBuf)> {
     tracing::info!("Using in-memory SQLite database");
 
-    let connect_options = SqliteConnectOptions::from_str("sqlite::memory:")?
-        .journal_mode(SqliteJournalMode::Wal)
-        .foreign_keys(true);
+    let pool = memory_pool(&cuid2::create_id(), 5).await?;
+    sqlx::migrate!("./migrations").run(&pool).await?;
 
-    let pool = SqlitePoolOptions::new()
-        .max_connections(1) // In-memory databases should use a single connection
-        .connect_with(connect_options)
-        .await?;
+    std::fs::create_dir_all(scratch_root)
+        .with_context(|| format!("failed to create DB path: {}", scratch_root.display()))?;
+    Ok((pool, scratch_root.to_path_buf()))
+}
 
-    sqlx::migrate!("./migrations").run(&pool).await?;
+/// Connection options for the in-memory database called `name`. Every connection opened
+/// with the same name sees the same database, which lasts until the last one closes.
+pub fn memory_options(name: &str) -> SqliteConnectOptions {
+    SqliteConnectOptions::new()
+        .filename(format!("file:forge-{}", name))
+        .in_memory(true)
+        .shared_cache(true)
+        .foreign_keys(true)
+}
 
-    // Return a temp directory path for extension databases
-    let temp_db_path = std::env::temp_dir().join("forge-memory-db");
-    std::fs::create_dir_all(&temp_db_path)?;
-    Ok((pool, temp_db_path))
+/// A pool over [`memory_options`]. Connections are never retired for being idle or old, so
+/// the pool always keeps the database alive.
+pub async fn memory_pool(name: &str, max_connections: u32) -> Result<SqlitePool> {
+    Ok(SqlitePoolOptions::new()
+        .max_connections(max_connections)
+        .min_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(memory_options(name))
+        .await?)
 }
 
 pub fn normalize_path<P: Into<PathBuf>>(path: P) -> Result<PathBuf> {
@@ -70,3 +102,51 @@ pub fn normalize_path<P: Into<PathBuf>>(path: P) -> Result<PathBuf> {
     let cwd = std::env::current_dir().context("failed to read current working directory")?;
     Ok(cwd.join(path))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_pools_with_the_same_name_share_a_database() {
+        let name = cuid2::create_id();
+        let first = memory_pool(&name, 4).await.unwrap();
+        let second = memory_pool(&name, 2).await.unwrap();
+        sqlx::query("CREATE TABLE items (id INTEGER PRIMARY KEY)")
+            .execute(&first)
+            .await
+            .unwrap();
+
+        let mut writers = tokio::task::JoinSet::new();
+        for id in 0..20 {
+            let pool = if id % 2 == 0 {
+                first.clone()
+            } else {
+                second.clone()
+            };
+            writers.spawn(async move {
+                sqlx::query("INSERT INTO items (id) VALUES (?)")
+                    .bind(id)
+                    .execute(&pool)
+                    .await
+            });
+        }
+        while let Some(result) = writers.join_next().await {
+            result.unwrap().unwrap();
+        }
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM items")
+            .fetch_one(&second)
+            .await
+            .unwrap();
+        assert_eq!(count, 20);
+
+        let other = memory_pool(&cuid2::create_id(), 1).await.unwrap();
+        assert!(
+            sqlx::query("SELECT 1 FROM items")
+                .execute(&other)
+                .await
+                .is_err()
+        );
+    }
+}
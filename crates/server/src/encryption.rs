@@ -0,0 +1,336 @@
+//! Encryption at rest for SQLite databases.
+//!
+//! With `db.encryption` configured, every database forge opens is keyed with one 256-bit
+//! key: the metadata database, the auth store and each extension's database. Keys are
+//! applied with SQLCipher's `key` pragma, so the server must be built with the
+//! `sqlcipher` feature. Plain SQLite ignores that pragma and would keep writing
+//! plaintext, so [`install`] refuses to start on such a build.
+//!
+//! The key is read once at startup, either from a file (a Docker or Kubernetes secret, a
+//! systemd credential) or from the output of a command such as a secrets manager CLI.
+//! `server rekey-databases <new-key-file>` rotates it, and also encrypts existing
+//! plaintext databases the first time encryption is turned on.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result, bail};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+use crate::config::{Config, DbEncryptionConfig};
+
+static KEY: OnceLock<DatabaseKey> = OnceLock::new();
+
+/// A raw 256-bit SQLCipher key. `Debug` never prints it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DatabaseKey(String);
+
+impl fmt::Debug for DatabaseKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DatabaseKey(..)")
+    }
+}
+
+impl DatabaseKey {
+    /// Parses 64 hex characters; surrounding whitespace is ignored.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let hex = raw.trim();
+        if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("database encryption key must be 64 hex characters (32 bytes)");
+        }
+        Ok(Self(hex.to_ascii_lowercase()))
+    }
+
+    /// The key as SQLCipher's raw-key string, `x'...'`.
+    fn raw(&self) -> String {
+        format!("x'{}'", self.0)
+    }
+
+    /// The key as a pragma value; SQLCipher reads a quoted `x'...'` as raw key bytes.
+    fn pragma_value(&self) -> String {
+        format!("\"{}\"", self.raw())
+    }
+}
+
+/// Reads the key from the configured source; `None` when encryption is off.
+pub fn load_key(config: &DbEncryptionConfig) -> Result<Option<DatabaseKey>> {
+    match (&config.key_file, &config.key_command) {
+        (None, None) => Ok(None),
+        (Some(_), Some(_)) => bail!("set only one of db.encryption.key_file and key_command"),
+        (Some(path), None) => read_key_file(path).map(Some),
+        (None, Some(command)) => {
+            let (program, args) = command
+                .split_first()
+                .context("db.encryption.key_command must not be empty")?;
+            let output = std::process::Command::new(program)
+                .args(args)
+                .output()
+                .with_context(|| format!("failed to run key command `{}`", program))?;
+            if !output.status.success() {
+                bail!(
+                    "key command `{}` failed: {}",
+                    program,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            let stdout =
+                String::from_utf8(output.stdout).context("key command printed non-UTF-8")?;
+            DatabaseKey::parse(&stdout).map(Some)
+        }
+    }
+}
+
+pub fn read_key_file(path: &Path) -> Result<DatabaseKey> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read key file {}", path.display()))?;
+    DatabaseKey::parse(&contents).with_context(|| format!("invalid key in {}", path.display()))
+}
+
+/// Makes `key` the key for every database opened afterwards. Fails when the linked
+/// SQLite is not SQLCipher. Later calls are ignored.
+pub async fn install(key: Option<DatabaseKey>) -> Result<()> {
+    let Some(key) = key else {
+        return Ok(());
+    };
+    ensure_sqlcipher().await?;
+    let _ = KEY.set(key);
+    Ok(())
+}
+
+/// The key installed with [`install`].
+pub fn current_key() -> Option<&'static DatabaseKey> {
+    KEY.get()
+}
+
+/// Adds the installed key to `options`; unchanged when encryption is off.
+pub fn keyed(options: SqliteConnectOptions) -> SqliteConnectOptions {
+    with_key(options, current_key())
+}
+
+fn with_key(options: SqliteConnectOptions, key: Option<&DatabaseKey>) -> SqliteConnectOptions {
+    match key {
+        Some(key) => options.pragma("key", key.pragma_value()),
+        None => options,
+    }
+}
+
+async fn ensure_sqlcipher() -> Result<()> {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(SqliteConnectOptions::from_str("sqlite::memory:")?)
+        .await?;
+    let version: Option<String> = sqlx::query_scalar("PRAGMA cipher_version")
+        .fetch_optional(&pool)
+        .await?;
+    pool.close().await;
+    if version.is_none_or(|v| v.is_empty()) {
+        bail!(
+            "db.encryption is set but this server was built without SQLCipher; rebuild with `--features sqlcipher`"
+        );
+    }
+    Ok(())
+}
+
+/// Database files that exist under `config`: the metadata database, the auth store and
+/// each extension's database.
+pub fn database_files(config: &Config) -> Result<Vec<PathBuf>> {
+    if config.db.in_memory {
+        bail!("in-memory databases are never written to disk");
+    }
+    let db_root = config
+        .db
+        .path
+        .clone()
+        .context("db.path must be set (FORGE_DB_PATH)")?;
+    let mut files = vec![crate::db::normalize_path(db_root)?.join("forge.db")];
+    files.push(crate::db::normalize_path(config.auth.db_path.clone())?);
+
+    let extensions_dir = config
+        .extensions
+        .dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("./extensions"));
+    if let Ok(entries) = std::fs::read_dir(&extensions_dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let db = entry.path().join(format!("{}.db", name.to_string_lossy()));
+            files.push(db);
+        }
+    }
+    files.retain(|path| path.is_file());
+    files.sort();
+    Ok(files)
+}
+
+/// Re-encrypts `path` with `new`. `current` is the key it is encrypted with now; `None`
+/// encrypts a plaintext database. The database must not be open anywhere else.
+pub async fn rekey_database(
+    path: &Path,
+    current: Option<&DatabaseKey>,
+    new: &DatabaseKey,
+) -> Result<()> {
+    let options = with_key(SqliteConnectOptions::new().filename(path), current);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    // Fold the WAL into the main file; rekeying rewrites only the main file.
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(&pool)
+        .await?;
+    sqlx::query("PRAGMA journal_mode = DELETE")
+        .execute(&pool)
+        .await?;
+
+    if current.is_some() {
+        sqlx::query(&format!("PRAGMA rekey = {}", new.pragma_value()))
+            .execute(&pool)
+            .await?;
+        pool.close().await;
+        return Ok(());
+    }
+
+    // A plaintext database cannot be rekeyed in place; copy it into an encrypted one.
+    let encrypted = path.with_extension("db.encrypting");
+    // ATTACH opens with the main database's flags, which do not include create.
+    std::fs::File::create(&encrypted)
+        .with_context(|| format!("failed to create {}", encrypted.display()))?;
+    sqlx::query("ATTACH DATABASE ? AS encrypted KEY ?")
+        .bind(encrypted.to_string_lossy().to_string())
+        .bind(new.raw())
+        .execute(&pool)
+        .await?;
+    sqlx::query("SELECT sqlcipher_export('encrypted')")
+        .execute(&pool)
+        .await?;
+    sqlx::query("DETACH DATABASE encrypted")
+        .execute(&pool)
+        .await?;
+    pool.close().await;
+    std::fs::rename(&encrypted, path)
+        .with_context(|| format!("failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+/// `server rekey-databases <new-key-file>`: moves every database to the key in
+/// `new_key_file`. Databases are encrypted with the configured key, or are plaintext
+/// when none is configured.
+pub async fn rekey_databases(config: &Config, new_key_file: &Path) -> Result<Vec<PathBuf>> {
+    ensure_sqlcipher().await?;
+    let current = load_key(&config.db.encryption)?;
+    let new = read_key_file(new_key_file)?;
+    if current.as_ref() == Some(&new) {
+        bail!("the new key is the key already in use");
+    }
+    let files = database_files(config)?;
+    for file in &files {
+        rekey_database(file, current.as_ref(), &new)
+            .await
+            .with_context(|| format!("failed to rekey {}", file.display()))?;
+        tracing::info!(database = %file.display(), "database rekeyed");
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_HEX: &str = "00112233445566778899AABBCCDDEEFF00112233445566778899aabbccddeeff";
+
+    #[test]
+    fn keys_are_64_hex_characters_and_never_printed() {
+        let key = DatabaseKey::parse(&format!("  {}\n", KEY_HEX)).unwrap();
+        assert_eq!(key.raw(), format!("x'{}'", KEY_HEX.to_ascii_lowercase()));
+        assert_eq!(format!("{:?}", key), "DatabaseKey(..)");
+        assert!(DatabaseKey::parse("correct horse battery staple").is_err());
+        assert!(DatabaseKey::parse(&KEY_HEX[..62]).is_err());
+    }
+
+    #[test]
+    fn keys_load_from_one_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("db.key");
+        std::fs::write(&key_file, KEY_HEX).unwrap();
+
+        let from_file = DbEncryptionConfig {
+            key_file: Some(key_file.clone()),
+            key_command: None,
+        };
+        let from_command = DbEncryptionConfig {
+            key_file: None,
+            key_command: Some(vec!["echo".to_string(), KEY_HEX.to_string()]),
+        };
+        let expected = DatabaseKey::parse(KEY_HEX).unwrap();
+        assert_eq!(load_key(&from_file).unwrap(), Some(expected.clone()));
+        assert_eq!(load_key(&from_command).unwrap(), Some(expected));
+        assert_eq!(load_key(&DbEncryptionConfig::default()).unwrap(), None);
+
+        let both = DbEncryptionConfig {
+            key_file: Some(key_file),
+            key_command: from_command.key_command.clone(),
+        };
+        assert!(load_key(&both).is_err());
+        let failing = DbEncryptionConfig {
+            key_file: None,
+            key_command: Some(vec!["false".to_string()]),
+        };
+        assert!(load_key(&failing).is_err());
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[tokio::test]
+    async fn plaintext_databases_are_encrypted_then_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("forge.db");
+        let open = |key: Option<&DatabaseKey>| {
+            let options = SqliteConnectOptions::new()
+                .filename(&path)
+                .create_if_missing(true);
+            SqlitePoolOptions::new().connect_with(with_key(options, key))
+        };
+        let pool = open(None).await.unwrap();
+        sqlx::query("CREATE TABLE notes (body TEXT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO notes VALUES ('launch codes')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+
+        let first = DatabaseKey::parse(&"ab".repeat(32)).unwrap();
+        let second = DatabaseKey::parse(&"cd".repeat(32)).unwrap();
+        rekey_database(&path, None, &first).await.unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(!bytes.windows(12).any(|window| window == b"launch codes"));
+        rekey_database(&path, Some(&first), &second).await.unwrap();
+
+        let stale = open(Some(&first)).await.unwrap();
+        assert!(
+            sqlx::query_scalar::<_, String>("SELECT body FROM notes")
+                .fetch_one(&stale)
+                .await
+                .is_err()
+        );
+        stale.close().await;
+        let pool = open(Some(&second)).await.unwrap();
+        let body: String = sqlx::query_scalar("SELECT body FROM notes")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(body, "launch codes");
+    }
+
+    #[cfg(not(feature = "sqlcipher"))]
+    #[tokio::test]
+    async fn plain_sqlite_builds_refuse_a_key() {
+        let key = DatabaseKey::parse(KEY_HEX).unwrap();
+        assert!(install(Some(key)).await.is_err());
+        assert!(current_key().is_none());
+    }
+}
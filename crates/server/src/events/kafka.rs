@@ -0,0 +1,127 @@
+//! Publishing to Kafka through the Confluent REST Proxy v2 API.
+//!
+//! Talking to the proxy over HTTP avoids linking a native Kafka client. The proxy
+//! reports a failure for each record, so a successful HTTP status is not enough: every
+//! returned offset is checked too.
+
+use anyhow::{Result, anyhow, bail};
+use reqwest::StatusCode;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use serde_json::{Value, json};
+
+use super::Envelope;
+use crate::jobs::PermanentFailure;
+
+const CONTENT_TYPE_JSON_V2: &str = "application/vnd.kafka.json.v2+json";
+const ACCEPT_V2: &str = "application/vnd.kafka.v2+json";
+
+pub struct KafkaRestPublisher {
+    client: reqwest::Client,
+    base_url: String,
+    /// `user:password` for HTTP basic authentication.
+    credentials: Option<(String, String)>,
+}
+
+impl KafkaRestPublisher {
+    pub fn new(base_url: &str, credentials: Option<&str>) -> Result<Self> {
+        url::Url::parse(base_url).map_err(|e| anyhow!("invalid Kafka REST Proxy URL: {}", e))?;
+        let credentials = credentials
+            .map(|credentials| {
+                credentials
+                    .split_once(':')
+                    .map(|(user, password)| (user.to_string(), password.to_string()))
+                    .ok_or_else(|| anyhow!("Kafka REST Proxy credentials must be `user:password`"))
+            })
+            .transpose()?;
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(10))
+                .build()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            credentials,
+        })
+    }
+
+    pub async fn publish(&self, topic: &str, key: &str, envelope: &Envelope) -> Result<()> {
+        let url = format!("{}/topics/{}", self.base_url, urlencoding::encode(topic));
+        let mut request = self
+            .client
+            .post(url)
+            .header(CONTENT_TYPE, CONTENT_TYPE_JSON_V2)
+            .header(ACCEPT, ACCEPT_V2)
+            .body(serde_json::to_vec(&produce_body(key, envelope))?);
+        if let Some((user, password)) = &self.credentials {
+            request = request.basic_auth(user, Some(password));
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            let message = format!("Kafka REST Proxy returned {}: {}", status, body["message"]);
+            // The request itself is unacceptable; sending it again cannot succeed.
+            if matches!(
+                status,
+                StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY
+            ) {
+                return Err(PermanentFailure(message).into());
+            }
+            bail!(message);
+        }
+        check_offsets(&body)
+    }
+}
+
+fn produce_body(key: &str, envelope: &Envelope) -> Value {
+    json!({ "records": [{ "key": key, "value": envelope }] })
+}
+
+/// Fails when the proxy reports an error for any record.
+fn check_offsets(body: &Value) -> Result<()> {
+    let offsets = body["offsets"]
+        .as_array()
+        .ok_or_else(|| anyhow!("Kafka REST Proxy response has no offsets"))?;
+    for offset in offsets {
+        if !offset["error_code"].is_null() {
+            bail!(
+                "Kafka rejected the event ({}): {}",
+                offset["error_code"],
+                offset["error"].as_str().unwrap_or("unknown error")
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_body_and_per_record_errors() {
+        let envelope = Envelope {
+            version: 1,
+            id: "evt1".into(),
+            kind: "repository.created".into(),
+            source: "core".into(),
+            time: 1,
+            actor: None,
+            repository_id: Some("repo1".into()),
+            data: json!({}),
+        };
+        let body = produce_body("repo1", &envelope);
+        assert_eq!(body["records"][0]["key"], "repo1");
+        assert_eq!(body["records"][0]["value"]["type"], "repository.created");
+
+        let ok = json!({ "offsets": [{ "partition": 0, "offset": 7, "error_code": null, "error": null }] });
+        assert!(check_offsets(&ok).is_ok());
+        let failed = json!({ "offsets": [{ "partition": null, "offset": null, "error_code": 50003, "error": "timeout" }] });
+        assert!(
+            check_offsets(&failed)
+                .unwrap_err()
+                .to_string()
+                .contains("timeout")
+        );
+        assert!(check_offsets(&json!({})).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+//! Export of forge events to an external broker.
+//!
+//! Core code and extensions call [`publish`] when something happens that other systems
+//! may want to follow: a repository is created, linked or deleted, a linked remote picks
+//! up new commits, an issue changes. When `event_sink` is configured, each event becomes
+//! an `events.export` job, and the job worker delivers it to NATS or, through a REST
+//! Proxy, to Kafka. Failed deliveries are retried with the queue's backoff. Consumers
+//! therefore see every event at least once and must tolerate duplicates. The event `id`
+//! stays the same across retries, and NATS messages carry it as `Nats-Msg-Id` so
+//! JetStream can drop repeats. Events of one repository are not guaranteed to arrive in
+//! order.
+//!
+//! Payloads are JSON [`Envelope`]s. `version` is [`SCHEMA_VERSION`] and only changes when
+//! the envelope changes incompatibly. The shape of `data` depends on the event type.
+//!
+//! Every event is also recorded in the audit log ([`crate::audit`]), queued for the
+//! webhooks that follow it ([`crate::webhooks`]) and broadcast inside the process, sink or
+//! not, for GraphQL subscriptions ([`crate::router::subscriptions`]).
+//! The broadcast happens when [`publish`] is called, so a listener can hear of an event
+//! whose transaction has not committed yet, or is later rolled back.
+
+pub mod kafka;
+pub mod nats;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Acquire, Sqlite};
+use std::sync::{Arc, LazyLock};
+use tokio::sync::broadcast;
+
+use crate::audit;
+use crate::config::{EventSinkConfig, EventTransport};
+use crate::jobs::{JobHandler, JobRecord, enqueue_in};
+use crate::user::db::unix_now;
+
+pub const JOB_KIND: &str = "events.export";
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Events a slow listener may fall behind by before it misses some.
+const LOCAL_CAPACITY: usize = 1024;
+
+static LOCAL: LazyLock<broadcast::Sender<Arc<Envelope>>> =
+    LazyLock::new(|| broadcast::channel(LOCAL_CAPACITY).0);
+
+pub struct Event<'a> {
+    /// `repository.created`, `issue.updated`, ...
+    pub kind: &'a str,
+    /// `core` or the name of the extension that raised the event.
+    pub source: &'a str,
+    pub actor_did: Option<&'a str>,
+    pub repository_id: Option<&'a str>,
+    pub data: Value,
+}
+
+/// What is published to the broker.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    pub version: u32,
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub source: String,
+    /// Unix seconds.
+    pub time: i64,
+    pub actor: Option<String>,
+    pub repository_id: Option<String>,
+    pub data: Value,
+}
+
+/// Records `event` in the audit log, queues webhook deliveries, broadcasts it to local
+/// listeners and queues it for export, all through `db`. Nothing is exported without a
+/// sink or when the sink does not include the event type.
+pub async fn publish<'e, A>(db: A, config: Option<&EventSinkConfig>, event: Event<'_>) -> Result<()>
+where
+    A: Acquire<'e, Database = Sqlite>,
+{
+    let envelope = Envelope {
+        version: SCHEMA_VERSION,
+        id: cuid2::create_id(),
+        kind: event.kind.to_string(),
+        source: event.source.to_string(),
+        time: unix_now(),
+        actor: event.actor_did.map(str::to_string),
+        repository_id: event.repository_id.map(str::to_string),
+        data: event.data,
+    };
+    let mut connection = db.acquire().await?;
+    let settings = crate::config::current();
+    audit::record_event(
+        &mut *connection,
+        &settings.audit_log,
+        &settings.compliance.redact_fields,
+        &envelope,
+    )
+    .await?;
+    if settings.webhooks.enabled {
+        crate::webhooks::queue_deliveries(&mut connection, &envelope).await?;
+    }
+    if LOCAL.receiver_count() > 0 {
+        // Fails only when the last listener went away in the meantime.
+        let _ = LOCAL.send(Arc::new(envelope.clone()));
+    }
+    let Some(config) = config else {
+        return Ok(());
+    };
+    if !is_included(config, &envelope.kind) {
+        return Ok(());
+    }
+    enqueue_in(&mut *connection, JOB_KIND, &envelope, config.max_attempts).await?;
+    Ok(())
+}
+
+/// Events published from now on, in the order [`publish`] was called.
+pub fn listen() -> broadcast::Receiver<Arc<Envelope>> {
+    LOCAL.subscribe()
+}
+
+/// Event types are lowercase, dot-separated words: `issue.created`.
+pub fn is_valid_kind(kind: &str) -> bool {
+    kind.split('.').count() >= 2
+        && kind.split('.').all(|word| {
+            !word.is_empty()
+                && word
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_' || b == b'-')
+        })
+}
+
+fn is_included(config: &EventSinkConfig, kind: &str) -> bool {
+    matches_filter(&config.include, kind)
+}
+
+/// Whether `kind` is one of `patterns`, exact types or prefixes such as `issue.*`. An
+/// empty list matches every type.
+pub fn matches_filter(patterns: &[String], kind: &str) -> bool {
+    patterns.is_empty()
+        || patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => prefix.ends_with('.') && kind.starts_with(prefix),
+                None => pattern == kind,
+            })
+}
+
+/// Subject or topic an event of type `kind` is published to.
+pub fn topic_for(config: &EventSinkConfig, kind: &str) -> String {
+    config
+        .topics
+        .get(kind)
+        .unwrap_or(&config.topic)
+        .replace("{type}", kind)
+}
+
+enum Transport {
+    Nats(nats::NatsPublisher),
+    KafkaRest(kafka::KafkaRestPublisher),
+}
+
+impl Transport {
+    fn label(&self) -> &'static str {
+        match self {
+            Transport::Nats(_) => "nats",
+            Transport::KafkaRest(_) => "kafka_rest",
+        }
+    }
+}
+
+/// Job handler for [`JOB_KIND`].
+pub struct EventExportJob {
+    config: EventSinkConfig,
+    transport: Transport,
+}
+
+impl EventExportJob {
+    pub fn from_config(config: &EventSinkConfig) -> Result<Self> {
+        let credentials = match &config.credentials_env {
+            Some(var) => Some(std::env::var(var).with_context(|| format!("{} is not set", var))?),
+            None => None,
+        };
+        let transport = match config.transport {
+            EventTransport::Nats => Transport::Nats(nats::NatsPublisher::new(
+                &config.url,
+                credentials.as_deref(),
+            )?),
+            EventTransport::KafkaRest => Transport::KafkaRest(kafka::KafkaRestPublisher::new(
+                &config.url,
+                credentials.as_deref(),
+            )?),
+        };
+        Ok(Self {
+            config: config.clone(),
+            transport,
+        })
+    }
+}
+
+#[async_trait]
+impl JobHandler for EventExportJob {
+    async fn run(&self, job: &JobRecord) -> Result<()> {
+        let envelope: Envelope = job.payload()?;
+        let topic = topic_for(&self.config, &envelope.kind);
+        match &self.transport {
+            Transport::Nats(publisher) => {
+                let payload = serde_json::to_vec(&envelope)?;
+                publisher.publish(&topic, &envelope.id, &payload).await?
+            }
+            Transport::KafkaRest(publisher) => {
+                // Keying by repository keeps one repository's events on one partition.
+                let key = envelope.repository_id.as_deref().unwrap_or(&envelope.id);
+                publisher.publish(&topic, key, &envelope).await?
+            }
+        }
+        counter!("forge_events_exported_total", "transport" => self.transport.label()).increment(1);
+        Ok(())
+    }
+
+    async fn exhausted(&self, job: &JobRecord, error: &str) {
+        counter!("forge_events_dropped_total", "transport" => self.transport.label()).increment(1);
+        tracing::error!(job = %job.id, "event dropped after {} attempts: {}", job.attempts, error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::JobQueue;
+    use crate::test_helpers::create_test_pool;
+
+    fn sink(include: &[&str]) -> EventSinkConfig {
+        EventSinkConfig {
+            transport: EventTransport::Nats,
+            url: "nats://127.0.0.1:4222".to_string(),
+            topic: "forge.events.{type}".to_string(),
+            topics: [("issue.deleted".to_string(), "forge-audit".to_string())].into(),
+            include: include.iter().map(|s| s.to_string()).collect(),
+            credentials_env: None,
+            max_attempts: 3,
+        }
+    }
+
+    fn event(kind: &str) -> Event<'_> {
+        Event {
+            kind,
+            source: "core",
+            actor_did: Some("did:plc:alice"),
+            repository_id: Some("repo1"),
+            data: serde_json::json!({ "slug": "widgets" }),
+        }
+    }
+
+    #[test]
+    fn topics_and_filters() {
+        let config = sink(&["issue.*", "repository.created"]);
+        assert!(is_included(&config, "issue.updated"));
+        assert!(is_included(&config, "repository.created"));
+        assert!(!is_included(&config, "repository.deleted"));
+        assert!(!is_included(&config, "issues.updated"));
+        assert!(is_included(&sink(&[]), "anything.at_all"));
+
+        assert_eq!(
+            topic_for(&config, "issue.created"),
+            "forge.events.issue.created"
+        );
+        assert_eq!(topic_for(&config, "issue.deleted"), "forge-audit");
+
+        assert!(is_valid_kind("issue.created"));
+        assert!(is_valid_kind("pull_request.review-requested"));
+        assert!(!is_valid_kind("created"));
+        assert!(!is_valid_kind("Issue.created"));
+        assert!(!is_valid_kind("issue..created"));
+        assert!(!is_valid_kind("issue.created *"));
+    }
+
+    #[tokio::test]
+    async fn published_events_become_versioned_jobs() {
+        let pool = create_test_pool().await.unwrap();
+        publish(&pool, None, event("repository.created"))
+            .await
+            .unwrap();
+        publish(
+            &pool,
+            Some(&sink(&["issue.*"])),
+            event("repository.created"),
+        )
+        .await
+        .unwrap();
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM jobs")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+
+        publish(&pool, Some(&sink(&[])), event("repository.created"))
+            .await
+            .unwrap();
+        let job = JobQueue::new(pool)
+            .claim_next(unix_now())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.kind, JOB_KIND);
+        assert_eq!(job.max_attempts, 3);
+        let payload: Value = serde_json::from_str(&job.payload).unwrap();
+        assert_eq!(payload["version"], 1);
+        assert_eq!(payload["type"], "repository.created");
+        assert_eq!(payload["actor"], "did:plc:alice");
+        assert_eq!(payload["repositoryId"], "repo1");
+        assert_eq!(payload["data"]["slug"], "widgets");
+    }
+
+    #[tokio::test]
+    async fn listeners_hear_events_without_a_sink() {
+        let pool = create_test_pool().await.unwrap();
+        let mut events = listen();
+        publish(&pool, None, event("repository.pushed"))
+            .await
+            .unwrap();
+        // Other tests publish through the same channel
+        loop {
+            let envelope = events.recv().await.unwrap();
+            if envelope.kind == "repository.pushed" {
+                assert_eq!(envelope.repository_id.as_deref(), Some("repo1"));
+                assert_eq!(envelope.data["slug"], "widgets");
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Publishing to NATS over the core text protocol.
+//!
+//! Only the handful of operations export needs are implemented: `CONNECT`, `PUB`/`HPUB`
+//! and `PING`. Each publish is followed by a `PING`, and the matching `PONG` confirms the
+//! server has processed the message before the job is marked done. TLS is not
+//! supported. Run a local NATS leaf node when the broker is remote.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::Mutex;
+use url::Url;
+
+const DEFAULT_PORT: u16 = 4222;
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+enum Auth {
+    Token(String),
+    UserPassword(String, String),
+}
+
+pub struct NatsPublisher {
+    addr: String,
+    auth: Option<Auth>,
+    connection: Mutex<Option<Connection>>,
+}
+
+struct Connection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    /// Whether the server accepts `HPUB` (NATS 2.2 and later).
+    headers: bool,
+}
+
+impl NatsPublisher {
+    /// `url` is `nats://[user:password@|token@]host[:port]`; `credentials` (a token or
+    /// `user:password`) takes precedence over credentials in the URL.
+    pub fn new(url: &str, credentials: Option<&str>) -> Result<Self> {
+        let parsed = Url::parse(url).with_context(|| format!("invalid NATS URL `{}`", url))?;
+        if parsed.scheme() != "nats" {
+            bail!("NATS URL must start with nats:// (TLS is not supported)");
+        }
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| anyhow!("NATS URL `{}` has no host", url))?;
+        let addr = format!("{}:{}", host, parsed.port().unwrap_or(DEFAULT_PORT));
+
+        let from_url = match (parsed.username(), parsed.password()) {
+            ("", _) => None,
+            (user, Some(password)) => Some(format!("{}:{}", user, password)),
+            (token, None) => Some(token.to_string()),
+        };
+        let auth =
+            credentials
+                .map(str::to_string)
+                .or(from_url)
+                .map(|credentials| match credentials.split_once(':') {
+                    Some((user, password)) => {
+                        Auth::UserPassword(user.to_string(), password.to_string())
+                    }
+                    None => Auth::Token(credentials),
+                });
+        Ok(Self {
+            addr,
+            auth,
+            connection: Mutex::new(None),
+        })
+    }
+
+    /// Publishes `payload` to `subject` and waits for the server to acknowledge it.
+    pub async fn publish(&self, subject: &str, message_id: &str, payload: &[u8]) -> Result<()> {
+        if subject.is_empty() || subject.contains(char::is_whitespace) {
+            bail!("invalid NATS subject `{}`", subject);
+        }
+        let mut guard = self.connection.lock().await;
+        let result = tokio::time::timeout(IO_TIMEOUT, async {
+            if guard.is_none() {
+                *guard = Some(self.connect().await?);
+            }
+            let connection = guard.as_mut().expect("connection was just established");
+            connection.publish(subject, message_id, payload).await
+        })
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("timed out talking to NATS at {}", self.addr)));
+        // A failed exchange leaves the protocol state unknown; start over next time.
+        if result.is_err() {
+            *guard = None;
+        }
+        result
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        let stream = TcpStream::connect(&self.addr)
+            .await
+            .with_context(|| format!("failed to connect to NATS at {}", self.addr))?;
+        let (reader, writer) = stream.into_split();
+        let mut connection = Connection {
+            reader: BufReader::new(reader),
+            writer,
+            headers: false,
+        };
+
+        let info = connection.read_line().await?;
+        let info: Value = info
+            .strip_prefix("INFO ")
+            .and_then(|json| serde_json::from_str(json).ok())
+            .ok_or_else(|| anyhow!("unexpected NATS greeting `{}`", info))?;
+        connection.headers = info["headers"].as_bool().unwrap_or(false);
+
+        let mut options = json!({
+            "verbose": false,
+            "pedantic": false,
+            "lang": "rust",
+            "version": env!("CARGO_PKG_VERSION"),
+            "name": "forge",
+            "headers": connection.headers,
+        });
+        match &self.auth {
+            Some(Auth::Token(token)) => options["auth_token"] = json!(token),
+            Some(Auth::UserPassword(user, password)) => {
+                options["user"] = json!(user);
+                options["pass"] = json!(password);
+            }
+            None => {}
+        }
+        let connect = format!("CONNECT {}\r\nPING\r\n", options);
+        connection.writer.write_all(connect.as_bytes()).await?;
+        // Authentication errors arrive before the PONG.
+        connection.await_pong().await?;
+        Ok(connection)
+    }
+}
+
+impl Connection {
+    async fn publish(&mut self, subject: &str, message_id: &str, payload: &[u8]) -> Result<()> {
+        let mut frame = Vec::with_capacity(payload.len() + 128);
+        if self.headers {
+            let headers = format!("NATS/1.0\r\nNats-Msg-Id: {}\r\n\r\n", message_id);
+            frame.extend_from_slice(
+                format!(
+                    "HPUB {} {} {}\r\n",
+                    subject,
+                    headers.len(),
+                    headers.len() + payload.len()
+                )
+                .as_bytes(),
+            );
+            frame.extend_from_slice(headers.as_bytes());
+        } else {
+            frame.extend_from_slice(format!("PUB {} {}\r\n", subject, payload.len()).as_bytes());
+        }
+        frame.extend_from_slice(payload);
+        frame.extend_from_slice(b"\r\nPING\r\n");
+        self.writer.write_all(&frame).await?;
+        self.await_pong().await
+    }
+
+    async fn await_pong(&mut self) -> Result<()> {
+        loop {
+            let line = self.read_line().await?;
+            match line.as_str() {
+                "PONG" => return Ok(()),
+                "PING" => self.writer.write_all(b"PONG\r\n").await?,
+                "+OK" => {}
+                other if other.starts_with("INFO ") => {}
+                other if other.starts_with("-ERR") => bail!("NATS error: {}", other),
+                other => bail!("unexpected NATS reply `{}`", other),
+            }
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            bail!("NATS closed the connection");
+        }
+        Ok(line.trim_end().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    /// Accepts one client, acknowledges one message and returns what it received.
+    async fn fake_server(listener: TcpListener) -> (String, Vec<u8>) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        writer
+            .write_all(b"INFO {\"server_id\":\"test\",\"headers\":true}\r\n")
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        assert!(line.starts_with("CONNECT "), "{}", line);
+        assert!(line.contains("\"auth_token\":\"s3cret\""), "{}", line);
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "PING\r\n");
+        writer.write_all(b"PONG\r\n").await.unwrap();
+
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        let command = line.trim_end().to_string();
+        let total: usize = command.rsplit(' ').next().unwrap().parse().unwrap();
+        let mut message = vec![0; total + 2];
+        reader.read_exact(&mut message).await.unwrap();
+        message.truncate(total);
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "PING\r\n");
+        writer.write_all(b"PONG\r\n").await.unwrap();
+        (command, message)
+    }
+
+    #[tokio::test]
+    async fn publish_sends_message_id_header_and_waits_for_pong() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(fake_server(listener));
+
+        let publisher = NatsPublisher::new(
+            &format!("nats://ignored@127.0.0.1:{}", port),
+            Some("s3cret"),
+        )
+        .unwrap();
+        publisher
+            .publish("forge.events.issue.created", "evt1", b"{\"version\":1}")
+            .await
+            .unwrap();
+
+        let (command, message) = server.await.unwrap();
+        assert!(
+            command.starts_with("HPUB forge.events.issue.created "),
+            "{}",
+            command
+        );
+        assert_eq!(
+            String::from_utf8(message).unwrap(),
+            "NATS/1.0\r\nNats-Msg-Id: evt1\r\n\r\n{\"version\":1}"
+        );
+    }
+
+    #[test]
+    fn urls_are_validated_and_credentials_parsed() {
+        assert!(NatsPublisher::new("tls://broker:4222", None).is_err());
+        let publisher = NatsPublisher::new("nats://forge:pw@broker", None).unwrap();
+        assert_eq!(publisher.addr, "broker:4222");
+        assert!(matches!(
+            publisher.auth,
+            Some(Auth::UserPassword(ref user, ref password)) if user == "forge" && password == "pw"
+        ));
+    }
+}
@@ -3,6 +3,7 @@
 //! This module handles caching of OCI-fetched extensions using content-addressable
 //! storage with metadata tracking for provenance and validation.
 
+use crate::coordination::LockFile;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
@@ -35,6 +36,20 @@ pub struct CacheMetadata {
     pub sha256: String,
 }
 
+/// A cached module that failed verification and was moved out of the cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineRecord {
+    pub cache_key: String,
+
+    /// Why the module was set aside
+    pub reason: String,
+
+    pub quarantined_at: SystemTime,
+
+    /// Metadata of the module, when it was still readable
+    pub metadata: Option<CacheMetadata>,
+}
+
 /// Extension cache manager
 pub struct ExtensionCache {
     cache_dir: PathBuf,
@@ -107,6 +122,10 @@ impl ExtensionCache {
 
     /// Store extension in cache with metadata
     pub fn store(&self, cache_key: &str, wasm_data: &[u8], metadata: CacheMetadata) -> Result<()> {
+        // Instances may share the cache directory; serialize writers so a reader never
+        // sees a module paired with another fetch's metadata.
+        let _guard = LockFile::acquire(self.cache_dir.join(".write.lock"))?;
+
         // Write WASM file
         let wasm_path = self.wasm_path(cache_key);
         std::fs::write(&wasm_path, wasm_data)
@@ -123,6 +142,9 @@ impl ExtensionCache {
             )
         })?;
 
+        // A good copy supersedes any earlier quarantine of the same entry
+        self.release_quarantine(cache_key)?;
+
         tracing::debug!("Cached extension {} ({} bytes)", cache_key, wasm_data.len());
 
         Ok(())
@@ -137,6 +159,113 @@ impl ExtensionCache {
         Ok(computed_hash == metadata.sha256)
     }
 
+    /// Move a cached module aside so it is never loaded again.
+    ///
+    /// The module and a record of why it was moved are kept under `quarantine/` for
+    /// inspection. The entry reads as uncached until a fresh copy is stored.
+    pub fn quarantine(&self, cache_key: &str, reason: &str) -> Result<QuarantineRecord> {
+        let _guard = LockFile::acquire(self.cache_dir.join(".write.lock"))?;
+
+        let quarantine_dir = self.quarantine_dir();
+        std::fs::create_dir_all(&quarantine_dir).with_context(|| {
+            format!(
+                "Failed to create quarantine directory: {}",
+                quarantine_dir.display()
+            )
+        })?;
+
+        let record = QuarantineRecord {
+            cache_key: cache_key.to_string(),
+            reason: reason.to_string(),
+            quarantined_at: SystemTime::now(),
+            metadata: self.get_metadata(cache_key).ok(),
+        };
+
+        let wasm_path = self.wasm_path(cache_key);
+        match std::fs::rename(
+            &wasm_path,
+            quarantine_dir.join(format!("{}.wasm", cache_key)),
+        ) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to quarantine cached WASM: {}", wasm_path.display())
+                });
+            }
+        }
+        match std::fs::remove_file(self.metadata_path(cache_key)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context("Failed to remove cache metadata"),
+        }
+
+        let record_path = self.quarantine_record_path(cache_key);
+        let record_json = serde_json::to_string_pretty(&record)
+            .context("Failed to serialize quarantine record")?;
+        std::fs::write(&record_path, record_json).with_context(|| {
+            format!(
+                "Failed to write quarantine record: {}",
+                record_path.display()
+            )
+        })?;
+
+        tracing::warn!("Quarantined cached extension {}: {}", cache_key, reason);
+
+        Ok(record)
+    }
+
+    /// The quarantine record for an entry, if it has been quarantined
+    pub fn quarantine_record(&self, cache_key: &str) -> Result<Option<QuarantineRecord>> {
+        let record_path = self.quarantine_record_path(cache_key);
+        let content = match std::fs::read_to_string(&record_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "Failed to read quarantine record: {}",
+                        record_path.display()
+                    )
+                });
+            }
+        };
+        serde_json::from_str(&content).map(Some).with_context(|| {
+            format!(
+                "Failed to parse quarantine record: {}",
+                record_path.display()
+            )
+        })
+    }
+
+    /// Forget an earlier quarantine once the entry is known to be good again
+    pub fn release_quarantine(&self, cache_key: &str) -> Result<()> {
+        let quarantined = [
+            self.quarantine_record_path(cache_key),
+            self.quarantine_dir().join(format!("{}.wasm", cache_key)),
+        ];
+        for path in quarantined {
+            match std::fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to clear quarantine: {}", path.display())
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn quarantine_dir(&self) -> PathBuf {
+        self.cache_dir.join("quarantine")
+    }
+
+    fn quarantine_record_path(&self, cache_key: &str) -> PathBuf {
+        self.quarantine_dir().join(format!("{}.json", cache_key))
+    }
+
     /// List all cached extensions
     #[allow(dead_code)]
     pub fn list_cached(&self) -> Result<Vec<String>> {
@@ -163,7 +292,6 @@ impl ExtensionCache {
     }
 
     /// Get cache directory path
-    #[allow(dead_code)]
     pub fn cache_dir(&self) -> &Path {
         &self.cache_dir
     }
@@ -303,6 +431,41 @@ mod tests {
         assert!(cached.contains(&"key2".to_string()));
     }
 
+    #[test]
+    fn test_quarantine_and_recover() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = ExtensionCache::new(temp_dir.path().join("cache")).unwrap();
+
+        let cache_key = "rotten";
+        let wasm_data = b"\0asm\x01\x00\x00\x00";
+        let metadata = CacheMetadata {
+            registry: "ghcr.io".to_string(),
+            image: "test/ext".to_string(),
+            reference: "v1.0.0".to_string(),
+            content_digest: None,
+            fetched_at: SystemTime::now(),
+            size_bytes: wasm_data.len() as u64,
+            sha256: compute_sha256(wasm_data),
+        };
+        cache.store(cache_key, wasm_data, metadata.clone()).unwrap();
+
+        // Flip a byte on disk
+        std::fs::write(cache.wasm_path(cache_key), b"\0asm\x01\x00\x00\x01").unwrap();
+        assert!(!cache.verify_checksum(cache_key).unwrap());
+
+        let record = cache.quarantine(cache_key, "checksum mismatch").unwrap();
+        assert_eq!(record.metadata.unwrap().sha256, metadata.sha256);
+        assert!(!cache.is_cached(cache_key));
+        assert!(cache.list_cached().unwrap().is_empty());
+        let stored = cache.quarantine_record(cache_key).unwrap().unwrap();
+        assert_eq!(stored.reason, "checksum mismatch");
+
+        // Storing a good copy lifts the quarantine
+        cache.store(cache_key, wasm_data, metadata).unwrap();
+        assert!(cache.verify_checksum(cache_key).unwrap());
+        assert!(cache.quarantine_record(cache_key).unwrap().is_none());
+    }
+
     #[test]
     fn test_is_cached_false_for_missing() {
         let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,327 @@
+//! Resumable layer downloads for OCI extensions
+//!
+//! Layer bytes are appended to `<cache>/partial/<digest>.part` as they arrive. When a
+//! download dies part-way (a stalled connection, a registry that went away), the file
+//! stays behind and the next attempt asks only for the missing bytes with a range
+//! request. Partial files are named after the layer digest, so a download started on one
+//! mirror can be finished from another. The completed file is checked against the digest
+//! before anything uses it.
+
+use anyhow::{Context, Result};
+use futures::{Stream, StreamExt};
+use metrics::{counter, histogram};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+use super::cache::compute_sha256;
+
+/// How often progress is logged while a layer downloads
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A layer download that may already be partly on disk
+pub struct PartialDownload {
+    path: PathBuf,
+    sha256: String,
+    size: Option<u64>,
+}
+
+impl PartialDownload {
+    /// `digest` is the layer digest from the manifest (`sha256:<hex>`); `size` its
+    /// advertised length, if any.
+    pub fn open(cache_dir: &Path, digest: &str, size: Option<u64>) -> Result<Self> {
+        let sha256 = digest
+            .strip_prefix("sha256:")
+            .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .with_context(|| format!("Unsupported layer digest: {}", digest))?
+            .to_ascii_lowercase();
+        let dir = cache_dir.join("partial");
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(Self {
+            path: dir.join(format!("{}.part", sha256)),
+            sha256,
+            size,
+        })
+    }
+
+    /// Bytes already on disk. A file longer than the layer is discarded.
+    pub fn offset(&self) -> Result<u64> {
+        let len = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e).context("Failed to inspect partial download"),
+        };
+        if self.size.is_some_and(|size| len > size) {
+            self.discard();
+            return Ok(0);
+        }
+        Ok(len)
+    }
+
+    /// Whether every advertised byte is already on disk.
+    pub fn is_complete(&self, offset: u64) -> bool {
+        self.size.is_some_and(|size| offset == size)
+    }
+
+    /// Writes `stream` to the partial file, appending when `append` is set and starting
+    /// over otherwise. Fails if no chunk arrives within `stall_timeout`; whatever was
+    /// received until then is kept for the next attempt.
+    pub async fn write_stream<S, B, E>(
+        &self,
+        mut stream: S,
+        append: bool,
+        progress: &mut Progress,
+        stall_timeout: Duration,
+    ) -> Result<()>
+    where
+        S: Stream<Item = std::result::Result<B, E>> + Unpin,
+        B: AsRef<[u8]>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+
+        let received = async {
+            loop {
+                let chunk = match tokio::time::timeout(stall_timeout, stream.next()).await {
+                    Ok(Some(chunk)) => chunk.context("Layer download interrupted")?,
+                    Ok(None) => return Ok(()),
+                    Err(_) => anyhow::bail!(
+                        "Layer download stalled: no data for {}s",
+                        stall_timeout.as_secs_f32()
+                    ),
+                };
+                file.write_all(chunk.as_ref()).await?;
+                progress.advance(chunk.as_ref().len() as u64);
+            }
+        }
+        .await;
+
+        // Keep what arrived even when the transfer failed
+        file.flush().await?;
+        received
+    }
+
+    /// Reads the finished layer and checks it against its digest. The partial file is
+    /// removed either way; a corrupt one would otherwise be resumed forever.
+    pub fn finish(self) -> Result<Vec<u8>> {
+        let data = std::fs::read(&self.path)
+            .with_context(|| format!("Failed to read {}", self.path.display()))?;
+        self.discard();
+        let actual = compute_sha256(&data);
+        if actual != self.sha256 {
+            anyhow::bail!(
+                "Layer digest mismatch: expected sha256:{}, got sha256:{}",
+                self.sha256,
+                actual
+            );
+        }
+        Ok(data)
+    }
+
+    pub fn discard(&self) {
+        if let Err(e) = std::fs::remove_file(&self.path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!("Failed to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Logs and records the progress of one layer download
+pub struct Progress {
+    label: String,
+    source: String,
+    total: Option<u64>,
+    received: u64,
+    resumed_from: u64,
+    started: Instant,
+    last_report: Instant,
+}
+
+impl Progress {
+    /// `resumed_from` is the number of bytes already on disk when the transfer began.
+    pub fn new(label: &str, source: &str, total: Option<u64>, resumed_from: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            label: label.to_string(),
+            source: source.to_string(),
+            total,
+            received: resumed_from,
+            resumed_from,
+            started: now,
+            last_report: now,
+        }
+    }
+
+    fn advance(&mut self, bytes: u64) {
+        self.received += bytes;
+        counter!("forge_extension_download_bytes_total", "registry" => self.source.clone())
+            .increment(bytes);
+        if self.last_report.elapsed() >= PROGRESS_INTERVAL {
+            self.last_report = Instant::now();
+            tracing::info!("Downloading {} from {}: {}", self.label, self.source, self);
+        }
+    }
+
+    /// Records the end of the transfer.
+    pub fn finish(&self, outcome: &'static str) {
+        counter!("forge_extension_downloads_total", "registry" => self.source.clone(), "outcome" => outcome)
+            .increment(1);
+        if outcome == "ok" {
+            histogram!("forge_extension_download_duration_ms", "registry" => self.source.clone())
+                .record(self.started.elapsed().as_millis() as f64);
+            tracing::info!("Downloaded {} from {}: {}", self.label, self.source, self);
+        }
+    }
+}
+
+impl std::fmt::Display for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.total {
+            Some(total) if total > 0 => write!(
+                f,
+                "{}/{} bytes ({}%)",
+                self.received,
+                total,
+                self.received * 100 / total
+            )?,
+            _ => write!(f, "{} bytes", self.received)?,
+        }
+        let secs = self.started.elapsed().as_secs_f64();
+        if secs > 0.0 {
+            let rate = (self.received - self.resumed_from) as f64 / secs / 1024.0;
+            write!(f, ", {:.1} KiB/s", rate)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use tempfile::TempDir;
+
+    const LAYER: &[u8] = b"\0asm\x01\x00\x00\x00 a layer that arrives in pieces";
+
+    fn digest() -> String {
+        format!("sha256:{}", compute_sha256(LAYER))
+    }
+
+    fn chunks(parts: Vec<&'static [u8]>) -> impl Stream<Item = std::io::Result<&'static [u8]>> {
+        stream::iter(parts.into_iter().map(Ok))
+    }
+
+    fn progress(resumed_from: u64) -> Progress {
+        Progress::new(
+            "test/ext",
+            "ghcr.io",
+            Some(LAYER.len() as u64),
+            resumed_from,
+        )
+    }
+
+    #[tokio::test]
+    async fn interrupted_downloads_resume_where_they_stopped() {
+        let temp_dir = TempDir::new().unwrap();
+        let partial =
+            PartialDownload::open(temp_dir.path(), &digest(), Some(LAYER.len() as u64)).unwrap();
+        let (head, tail) = LAYER.split_at(12);
+
+        let broken = chunks(vec![head]).chain(stream::iter([Err(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "reset",
+        ))]));
+        let err = partial
+            .write_stream(broken, true, &mut progress(0), Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("interrupted"));
+        assert_eq!(partial.offset().unwrap(), head.len() as u64);
+
+        let offset = partial.offset().unwrap();
+        partial
+            .write_stream(
+                chunks(vec![tail]),
+                true,
+                &mut progress(offset),
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert!(partial.is_complete(partial.offset().unwrap()));
+        assert_eq!(partial.finish().unwrap(), LAYER);
+        let fresh = PartialDownload::open(temp_dir.path(), &digest(), None).unwrap();
+        assert_eq!(fresh.offset().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn full_responses_replace_what_was_on_disk() {
+        let temp_dir = TempDir::new().unwrap();
+        let partial =
+            PartialDownload::open(temp_dir.path(), &digest(), Some(LAYER.len() as u64)).unwrap();
+        partial
+            .write_stream(
+                chunks(vec![b"stale"]),
+                true,
+                &mut progress(0),
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+
+        // The registry ignored the range request and sent the whole layer
+        partial
+            .write_stream(
+                chunks(vec![LAYER]),
+                false,
+                &mut progress(0),
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(partial.finish().unwrap(), LAYER);
+    }
+
+    #[tokio::test]
+    async fn stalled_downloads_keep_their_bytes() {
+        let temp_dir = TempDir::new().unwrap();
+        let partial = PartialDownload::open(temp_dir.path(), &digest(), None).unwrap();
+        let stalled = chunks(vec![&LAYER[..8]]).chain(stream::pending());
+        let err = partial
+            .write_stream(stalled, true, &mut progress(0), Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("stalled"));
+        assert_eq!(partial.offset().unwrap(), 8);
+    }
+
+    #[tokio::test]
+    async fn corrupt_layers_are_rejected_and_removed() {
+        let temp_dir = TempDir::new().unwrap();
+        let partial = PartialDownload::open(temp_dir.path(), &digest(), None).unwrap();
+        partial
+            .write_stream(
+                chunks(vec![b"not the layer"]),
+                true,
+                &mut progress(0),
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        let err = partial.finish().unwrap_err();
+        assert!(err.to_string().contains("digest mismatch"));
+
+        let again = PartialDownload::open(temp_dir.path(), &digest(), None).unwrap();
+        assert_eq!(again.offset().unwrap(), 0);
+        assert!(PartialDownload::open(temp_dir.path(), "md5:abc", None).is_err());
+    }
+}
@@ -0,0 +1,516 @@
+//! Read-only GraphQL queries from extensions (`host-graphql`).
+//!
+//! An extension first asks for a token naming the schema coordinates it needs, such as
+//! `Query.getRepository` or `RepositoryNode.*`. Only the coordinates the operator lists
+//! for it in `extensions.graphql_scopes` can be granted; an extension without an entry
+//! gets no token. Tokens live in memory for [`TOKEN_TTL_SECS`] and only work for the
+//! extension, user and repository of the call that requested them, so one cannot be
+//! kept and replayed in a later call made for someone else.
+//!
+//! A query runs against the composed schema as the user the extension's call runs for,
+//! so the usual visibility rules apply. It is refused unless it is a query operation and
+//! every field it selects is covered by the token's scopes. Fields served by an extension
+//! that has `host-graphql` access itself are refused as well, the caller's own included:
+//! a call back into an instance that is still busy with the current call would wait on
+//! it forever. Each query counts against the field rate limits as
+//! `extension:<name>`, and allowed and refused queries alike are recorded in
+//! `extension_graphql_audit`, which administrators read with `extensionQueryAudit`.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use anyhow::{Result, anyhow, bail};
+use rand::RngCore;
+use serde_json::Value as JsonValue;
+use sqlx::{Row, SqlitePool};
+
+use crate::api::server::GraphQLRequest;
+use crate::auth::User;
+use crate::auth::viewer::with_viewer;
+use crate::router::directives::OperationPolicy;
+use crate::router::{GraphQLExecutionRequest, GraphQLResponse, RouterState};
+use crate::user::db::{fetch_user_by_did, unix_now};
+
+/// How long a token can be used after it is issued.
+pub const TOKEN_TTL_SECS: i64 = 60;
+
+pub const TOKEN_PREFIX: &str = "forge_ext_";
+
+/// The subgraph of fields the core resolves.
+const CORE_GRAPH: &str = "CORE";
+
+/// Live tokens kept before expired ones are swept.
+const MAX_TRACKED_TOKENS: usize = 10_000;
+
+/// The extension call a token is requested or used in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Caller<'a> {
+    pub extension: &'a str,
+    pub actor_did: Option<&'a str>,
+    pub repository_id: Option<&'a str>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Grant {
+    extension: String,
+    actor_did: Option<String>,
+    repository_id: Option<String>,
+    scopes: Vec<String>,
+    expires_at: i64,
+}
+
+impl Grant {
+    fn issued_to(&self, caller: &Caller<'_>) -> bool {
+        self.extension == caller.extension
+            && self.actor_did.as_deref() == caller.actor_did
+            && self.repository_id.as_deref() == caller.repository_id
+    }
+}
+
+/// Tokens issued on this instance, by token.
+#[derive(Default)]
+pub struct TokenStore {
+    grants: Mutex<HashMap<String, Grant>>,
+}
+
+static TOKENS: LazyLock<TokenStore> = LazyLock::new(TokenStore::default);
+
+/// The tokens issued to extensions on this instance.
+pub fn tokens() -> &'static TokenStore {
+    &TOKENS
+}
+
+impl TokenStore {
+    /// Issues a token for `requested`, which must all be covered by `allowed`.
+    pub fn issue(
+        &self,
+        caller: &Caller<'_>,
+        requested: &[String],
+        allowed: &[String],
+        now: i64,
+    ) -> Result<String> {
+        if requested.is_empty() {
+            bail!("request at least one scope");
+        }
+        for scope in requested {
+            validate_scope(scope)?;
+        }
+        let denied: Vec<&str> = requested
+            .iter()
+            .filter(|scope| !allowed.iter().any(|allowed| covers(allowed, scope)))
+            .map(String::as_str)
+            .collect();
+        if !denied.is_empty() {
+            bail!(
+                "extension `{}` is not allowed to query {}",
+                caller.extension,
+                denied.join(", ")
+            );
+        }
+
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let token = format!(
+            "{}{}",
+            TOKEN_PREFIX,
+            secret
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        );
+        let mut grants = self.grants.lock().unwrap_or_else(|e| e.into_inner());
+        if grants.len() >= MAX_TRACKED_TOKENS {
+            grants.retain(|_, grant| grant.expires_at > now);
+        }
+        grants.insert(
+            token.clone(),
+            Grant {
+                extension: caller.extension.to_string(),
+                actor_did: caller.actor_did.map(str::to_string),
+                repository_id: caller.repository_id.map(str::to_string),
+                scopes: requested.to_vec(),
+                expires_at: now + TOKEN_TTL_SECS,
+            },
+        );
+        Ok(token)
+    }
+
+    /// The scopes of `token`, if it is live and was issued for `caller`.
+    fn scopes(&self, token: &str, caller: &Caller<'_>, now: i64) -> Result<Vec<String>> {
+        let mut grants = self.grants.lock().unwrap_or_else(|e| e.into_inner());
+        let grant = match grants.get(token) {
+            Some(grant) if grant.expires_at <= now => {
+                grants.remove(token);
+                bail!("token expired; request a new one");
+            }
+            Some(grant) if grant.issued_to(caller) => grant,
+            _ => bail!("invalid token"),
+        };
+        Ok(grant.scopes.clone())
+    }
+}
+
+/// `Type.field` or `Type.*`.
+fn validate_scope(scope: &str) -> Result<()> {
+    let valid = scope
+        .split_once('.')
+        .is_some_and(|(type_name, field)| is_name(type_name) && (field == "*" || is_name(field)));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "invalid scope `{}`: expected `Type.field` or `Type.*`",
+            scope
+        ))
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `scope` grants `coordinate`, which may itself be a `Type.*` scope.
+fn covers(scope: &str, coordinate: &str) -> bool {
+    match scope.strip_suffix(".*") {
+        Some(type_name) => coordinate
+            .split_once('.')
+            .is_some_and(|(coordinate_type, _)| coordinate_type == type_name),
+        None => scope == coordinate,
+    }
+}
+
+/// Checks that `policy` is a query whose fields `scopes` cover, and that none of them is
+/// resolved by an extension in `callers`, the extensions with `host-graphql` access.
+fn authorize(
+    policy: &OperationPolicy,
+    scopes: &[String],
+    field_graph: impl Fn(&str) -> Option<String>,
+    callers: &[String],
+) -> Result<()> {
+    // Only queries get a cache policy.
+    if policy.cache.is_none() {
+        bail!("only query operations can be run");
+    }
+    for coordinate in &policy.fields {
+        if let Some(graph) = field_graph(coordinate)
+            && graph != CORE_GRAPH
+            && callers
+                .iter()
+                .any(|caller| caller.to_ascii_uppercase() == graph)
+        {
+            bail!(
+                "`{}` is served by an extension that queries GraphQL itself",
+                coordinate
+            );
+        }
+        if !scopes.iter().any(|scope| covers(scope, coordinate)) {
+            bail!("token does not grant `{}`", coordinate);
+        }
+    }
+    Ok(())
+}
+
+/// Issues a token for `caller` under its `extensions.graphql_scopes` entry.
+pub fn request_token(caller: &Caller<'_>, requested: &[String]) -> Result<String> {
+    let allowed = crate::config::current()
+        .extensions
+        .graphql_scopes
+        .get(caller.extension)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let issued = tokens().issue(caller, requested, allowed, unix_now());
+    match &issued {
+        Ok(_) => tracing::info!(
+            extension = caller.extension,
+            scopes = ?requested,
+            "issued GraphQL token to extension"
+        ),
+        Err(e) => tracing::warn!(
+            extension = caller.extension,
+            scopes = ?requested,
+            error = %e,
+            "refused GraphQL token to extension"
+        ),
+    }
+    issued
+}
+
+/// Runs `query` for `caller` with `token` and returns the GraphQL response.
+pub async fn run_query(
+    router: &RouterState,
+    pool: &SqlitePool,
+    caller: &Caller<'_>,
+    token: &str,
+    query: &str,
+    variables: Option<&str>,
+) -> Result<GraphQLResponse> {
+    let variables = match variables {
+        Some(variables) => serde_json::from_str(variables)
+            .map_err(|e| anyhow!("variables must be a JSON object: {}", e))?,
+        None => JsonValue::Null,
+    };
+    let request = GraphQLExecutionRequest::from_payload(&GraphQLRequest {
+        query: query.to_string(),
+        operation_name: None,
+        variables,
+    })?;
+    let policy = router.operation_policy(&request);
+
+    let config = crate::config::current();
+    let callers: Vec<String> = config.extensions.graphql_scopes.keys().cloned().collect();
+    let checked = tokens()
+        .scopes(token, caller, unix_now())
+        .and_then(|scopes| {
+            authorize(
+                &policy,
+                &scopes,
+                |coordinate| router.field_graph(coordinate).map(str::to_string),
+                &callers,
+            )
+        })
+        .and_then(|()| {
+            router
+                .charge(&format!("extension:{}", caller.extension), &policy)
+                .map_err(|limited| anyhow!(limited.to_string()))
+        });
+    record_query(pool, caller, query, &policy.fields, checked.as_ref().err()).await?;
+    if let Err(e) = checked {
+        tracing::warn!(
+            extension = caller.extension,
+            error = %e,
+            "refused GraphQL query from extension"
+        );
+        return Err(e);
+    }
+
+    let viewer = match caller.actor_did {
+        Some(did) => Some(match fetch_user_by_did(pool, did).await? {
+            Some(record) => User {
+                did: record.did,
+                handle: record.handle,
+                display_name: record.display_name,
+                avatar: record.avatar,
+            },
+            None => User::new(did.to_string(), did.to_string()),
+        }),
+        None => None,
+    };
+    with_viewer(viewer, router.execute_raw(request)).await
+}
+
+/// One query an extension ran or tried to run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryAuditRecord {
+    pub id: String,
+    pub extension: String,
+    pub actor_did: Option<String>,
+    pub repository_id: Option<String>,
+    pub query: String,
+    pub fields: Vec<String>,
+    pub allowed: bool,
+    pub error: Option<String>,
+    pub created_at: i64,
+}
+
+async fn record_query(
+    pool: &SqlitePool,
+    caller: &Caller<'_>,
+    query: &str,
+    fields: &[String],
+    error: Option<&anyhow::Error>,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO extension_graphql_audit \
+         (id, extension, actor_did, repository_id, query, fields, allowed, error, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(cuid2::create_id())
+    .bind(caller.extension)
+    .bind(caller.actor_did)
+    .bind(caller.repository_id)
+    .bind(query)
+    .bind(serde_json::to_string(fields)?)
+    .bind(error.is_none())
+    .bind(error.map(|e| e.to_string()))
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Audited queries, newest first, optionally for one extension.
+pub async fn query_audit(
+    pool: &SqlitePool,
+    extension: Option<&str>,
+    limit: i64,
+) -> Result<Vec<QueryAuditRecord>> {
+    let rows = sqlx::query(
+        "SELECT id, extension, actor_did, repository_id, query, fields, allowed, error, \
+         created_at FROM extension_graphql_audit \
+         WHERE ? IS NULL OR extension = ? ORDER BY created_at DESC, id DESC LIMIT ?",
+    )
+    .bind(extension)
+    .bind(extension)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            let fields: String = row.try_get("fields")?;
+            Ok(QueryAuditRecord {
+                id: row.try_get("id")?,
+                extension: row.try_get("extension")?,
+                actor_did: row.try_get("actor_did")?,
+                repository_id: row.try_get("repository_id")?,
+                query: row.try_get("query")?,
+                fields: serde_json::from_str(&fields)?,
+                allowed: row.try_get("allowed")?,
+                error: row.try_get("error")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::directives::CachePolicy;
+    use crate::router::directives::CacheScope;
+    use crate::test_helpers::create_test_pool;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    const CALLER: Caller<'static> = Caller {
+        extension: "ci",
+        actor_did: Some("did:plc:alice"),
+        repository_id: Some("repo-1"),
+    };
+
+    #[test]
+    fn tokens_carry_only_allowed_scopes_and_expire() {
+        let store = TokenStore::default();
+        let allowed = strings(&["Query.getRepository", "RepositoryNode.*"]);
+
+        let err = store
+            .issue(&CALLER, &strings(&["Query.user"]), &allowed, 100)
+            .unwrap_err();
+        assert!(err.to_string().contains("Query.user"), "{err}");
+        assert!(
+            store
+                .issue(&CALLER, &strings(&["Query.*"]), &allowed, 100)
+                .is_err()
+        );
+        assert!(
+            store
+                .issue(&CALLER, &strings(&["nope"]), &allowed, 100)
+                .is_err()
+        );
+
+        let requested = strings(&["Query.getRepository", "RepositoryNode.name"]);
+        let token = store.issue(&CALLER, &requested, &allowed, 100).unwrap();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(store.scopes(&token, &CALLER, 159).unwrap(), requested);
+
+        let other_user = Caller {
+            actor_did: Some("did:plc:mallory"),
+            ..CALLER
+        };
+        assert!(store.scopes(&token, &other_user, 120).is_err());
+        let other_extension = Caller {
+            extension: "issues",
+            ..CALLER
+        };
+        assert!(store.scopes(&token, &other_extension, 120).is_err());
+
+        let err = store.scopes(&token, &CALLER, 160).unwrap_err();
+        assert!(err.to_string().contains("expired"), "{err}");
+        assert!(store.scopes(&token, &CALLER, 100).is_err());
+    }
+
+    #[test]
+    fn only_queries_within_scope_and_off_querying_extensions_run() {
+        let query = |fields: &[&str]| OperationPolicy {
+            cache: Some(CachePolicy {
+                max_age: 0,
+                scope: CacheScope::Public,
+            }),
+            fields: strings(fields),
+            ..OperationPolicy::default()
+        };
+        let graph = |coordinate: &str| {
+            Some(if coordinate.starts_with("Issue") {
+                "ISSUES".to_string()
+            } else if coordinate.starts_with("Label") {
+                "LABELS".to_string()
+            } else {
+                CORE_GRAPH.to_string()
+            })
+        };
+        let scopes = strings(&[
+            "Query.getRepository",
+            "RepositoryNode.*",
+            "Issue.*",
+            "Label.*",
+        ]);
+        let callers = strings(&["ci", "issues"]);
+        let check = |policy: &OperationPolicy| authorize(policy, &scopes, graph, &callers);
+
+        assert!(check(&query(&["Query.getRepository", "RepositoryNode.name"])).is_ok());
+        assert!(check(&query(&["Query.getRepository", "Label.name"])).is_ok());
+        assert!(check(&query(&["Query.user"])).is_err());
+        assert!(check(&query(&["Issue.title"])).is_err());
+
+        let mutation = OperationPolicy {
+            cache: None,
+            ..query(&["Query.getRepository"])
+        };
+        let err = check(&mutation).unwrap_err();
+        assert!(err.to_string().contains("only query"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn queries_are_audited() {
+        let pool = create_test_pool().await.unwrap();
+        record_query(&pool, &CALLER, "{ a }", &strings(&["Query.a"]), None)
+            .await
+            .unwrap();
+        let refused = anyhow!("token does not grant `Query.b`");
+        record_query(
+            &pool,
+            &CALLER,
+            "{ b }",
+            &strings(&["Query.b"]),
+            Some(&refused),
+        )
+        .await
+        .unwrap();
+
+        let records = query_audit(&pool, Some("ci"), 10).await.unwrap();
+        assert_eq!(records.len(), 2);
+        let mut allowed: Vec<(bool, Vec<String>)> = records
+            .iter()
+            .map(|record| (record.allowed, record.fields.clone()))
+            .collect();
+        allowed.sort();
+        assert_eq!(
+            allowed,
+            vec![
+                (false, strings(&["Query.b"])),
+                (true, strings(&["Query.a"])),
+            ]
+        );
+        assert!(
+            query_audit(&pool, Some("issues"), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
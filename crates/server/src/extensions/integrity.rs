@@ -0,0 +1,281 @@
+//! Background verification of cached OCI extension modules.
+//!
+//! Cached modules are re-hashed against the SHA-256 recorded when they were fetched. A
+//! module that no longer matches is re-fetched when the registry can be reached. When it
+//! cannot, either because of offline mode or because the fetch fails, the module is
+//! quarantined: the next start refuses it instead of loading a damaged module. Running
+//! extensions are unaffected, since they were compiled when the server started. Results
+//! are logged, counted in `forge_extension_cache_verifications_total` and reported by
+//! the `extensionCacheHealth` admin query.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use metrics::counter;
+
+use super::cache::ExtensionCache;
+use super::oci_fetcher::OciExtensionFetcher;
+use super::{configured_fetcher, registry_auth};
+use crate::config::{Extensions, OciExtension};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheState {
+    /// The cached module matches its recorded checksum
+    Verified,
+    /// The cached module was damaged and has been replaced from the registry
+    Refetched,
+    /// The cached module was damaged and could not be replaced
+    Quarantined,
+    /// Nothing is cached, e.g. an offline start before the first fetch
+    Missing,
+    /// Cached, but not checked by this instance yet
+    Unverified,
+}
+
+impl CacheState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheState::Verified => "VERIFIED",
+            CacheState::Refetched => "REFETCHED",
+            CacheState::Quarantined => "QUARANTINED",
+            CacheState::Missing => "MISSING",
+            CacheState::Unverified => "UNVERIFIED",
+        }
+    }
+}
+
+/// The cache state of one configured OCI extension.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheCheck {
+    pub extension: String,
+    pub state: CacheState,
+    /// Unix seconds of the check, or of the quarantine when read back from disk
+    pub checked_at: Option<i64>,
+    /// What was wrong with the module, for `Refetched` and `Quarantined`
+    pub detail: Option<String>,
+}
+
+static LAST_CHECKS: LazyLock<Mutex<HashMap<String, CacheCheck>>> = LazyLock::new(Mutex::default);
+
+/// Re-hash every configured OCI extension's cached module and repair what it can.
+pub async fn verify_cache(config: &Extensions) -> Result<Vec<CacheCheck>> {
+    let fetcher = configured_fetcher(config, true)?;
+
+    let mut checks = Vec::with_capacity(config.oci.len());
+    for extension in &config.oci {
+        let check = verify_entry(&fetcher, config, extension).await;
+        counter!("forge_extension_cache_verifications_total", "state" => check.state.as_str())
+            .increment(1);
+        checks.push(check);
+    }
+
+    let mut last = LAST_CHECKS.lock().unwrap_or_else(|e| e.into_inner());
+    for check in &checks {
+        last.insert(check.extension.clone(), check.clone());
+    }
+    Ok(checks)
+}
+
+async fn verify_entry(
+    fetcher: &OciExtensionFetcher,
+    config: &Extensions,
+    extension: &OciExtension,
+) -> CacheCheck {
+    let cache = fetcher.cache();
+    let reference = extension.reference.as_str();
+    let cache_key =
+        ExtensionCache::compute_cache_key(&extension.registry, &extension.image, reference);
+    let check = |state, detail| CacheCheck {
+        extension: extension.name.clone(),
+        state,
+        checked_at: Some(crate::user::db::unix_now()),
+        detail,
+    };
+
+    let cached = cache.is_cached(&cache_key);
+    let problem = if cached {
+        match cache.verify_checksum(&cache_key) {
+            Ok(true) => {
+                // The module may have been restored by hand after a quarantine
+                if let Err(e) = cache.release_quarantine(&cache_key) {
+                    tracing::warn!("Failed to clear quarantine of {}: {:#}", extension.name, e);
+                }
+                return check(CacheState::Verified, None);
+            }
+            Ok(false) => "checksum mismatch".to_string(),
+            Err(e) => format!("unreadable: {:#}", e),
+        }
+    } else {
+        match cache.quarantine_record(&cache_key) {
+            Ok(Some(record)) => record.reason,
+            Ok(None) => return check(CacheState::Missing, None),
+            Err(e) => format!("unreadable quarantine record: {:#}", e),
+        }
+    };
+
+    if !config.settings.offline_mode {
+        let auth = registry_auth(config, &extension.registry);
+        match fetcher
+            .fetch_extension(
+                &extension.registry,
+                &extension.image,
+                reference,
+                auth.as_ref(),
+            )
+            .await
+        {
+            Ok(_) => {
+                tracing::warn!(
+                    "Re-fetched cached extension {} after it failed verification ({})",
+                    extension.name,
+                    problem
+                );
+                return check(CacheState::Refetched, Some(problem));
+            }
+            Err(e) => {
+                let detail = format!("{}; re-fetch failed: {:#}", problem, e);
+                return quarantine(cache, &cache_key, cached, extension, detail, check);
+            }
+        }
+    }
+    quarantine(cache, &cache_key, cached, extension, problem, check)
+}
+
+fn quarantine(
+    cache: &ExtensionCache,
+    cache_key: &str,
+    cached: bool,
+    extension: &OciExtension,
+    detail: String,
+    check: impl Fn(CacheState, Option<String>) -> CacheCheck,
+) -> CacheCheck {
+    if cached && let Err(e) = cache.quarantine(cache_key, &detail) {
+        tracing::error!(
+            "Failed to quarantine cached extension {}: {:#}",
+            extension.name,
+            e
+        );
+    }
+    tracing::error!(
+        "Cached extension {} is quarantined and will not load until it can be re-fetched: {}",
+        extension.name,
+        detail
+    );
+    check(CacheState::Quarantined, Some(detail))
+}
+
+/// The latest known state of every configured OCI extension.
+///
+/// Extensions this instance has not checked yet are described from the cache directory,
+/// so a quarantine recorded by another instance still shows up.
+pub fn cache_status(config: &Extensions) -> Result<Vec<CacheCheck>> {
+    let cache = ExtensionCache::new(config.settings.cache_dir())?;
+    let last = LAST_CHECKS.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut status = Vec::with_capacity(config.oci.len());
+    for extension in &config.oci {
+        if let Some(check) = last.get(&extension.name) {
+            status.push(check.clone());
+            continue;
+        }
+        let cache_key = ExtensionCache::compute_cache_key(
+            &extension.registry,
+            &extension.image,
+            extension.reference.as_str(),
+        );
+        let check = if cache.is_cached(&cache_key) {
+            CacheCheck {
+                extension: extension.name.clone(),
+                state: CacheState::Unverified,
+                checked_at: None,
+                detail: None,
+            }
+        } else if let Some(record) = cache.quarantine_record(&cache_key)? {
+            CacheCheck {
+                extension: extension.name.clone(),
+                state: CacheState::Quarantined,
+                checked_at: Some(unix_secs(record.quarantined_at)),
+                detail: Some(record.reason),
+            }
+        } else {
+            CacheCheck {
+                extension: extension.name.clone(),
+                state: CacheState::Missing,
+                checked_at: None,
+                detail: None,
+            }
+        };
+        status.push(check);
+    }
+    Ok(status)
+}
+
+fn unix_secs(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Reference, Settings};
+    use crate::extensions::cache::{CacheMetadata, compute_sha256};
+    use tempfile::TempDir;
+
+    fn offline_config(cache_dir: &std::path::Path) -> Extensions {
+        Extensions {
+            oci: vec![OciExtension {
+                name: "labels".to_string(),
+                registry: "ghcr.io".to_string(),
+                image: "forgepoint/extensions/labels".to_string(),
+                reference: Reference::Tag("v1.0.0".to_string()),
+            }],
+            settings: Settings {
+                cache_dir: Some(cache_dir.to_path_buf()),
+                offline_mode: true,
+                ..Settings::default()
+            },
+            ..Extensions::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn offline_verification_quarantines_damaged_modules() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = offline_config(&temp_dir.path().join("cache"));
+        let cache = ExtensionCache::new(config.settings.cache_dir()).unwrap();
+        let cache_key =
+            ExtensionCache::compute_cache_key("ghcr.io", "forgepoint/extensions/labels", "v1.0.0");
+
+        assert_eq!(cache_status(&config).unwrap()[0].state, CacheState::Missing);
+
+        let wasm_data = b"\0asm\x01\x00\x00\x00";
+        let metadata = CacheMetadata {
+            registry: "ghcr.io".to_string(),
+            image: "forgepoint/extensions/labels".to_string(),
+            reference: "v1.0.0".to_string(),
+            content_digest: None,
+            fetched_at: SystemTime::now(),
+            size_bytes: wasm_data.len() as u64,
+            sha256: compute_sha256(wasm_data),
+        };
+        cache.store(&cache_key, wasm_data, metadata).unwrap();
+        let checks = verify_cache(&config).await.unwrap();
+        assert_eq!(checks[0].state, CacheState::Verified);
+
+        std::fs::write(cache.wasm_path(&cache_key), b"\0asm\x01\x00\x00\x02").unwrap();
+        let checks = verify_cache(&config).await.unwrap();
+        assert_eq!(checks[0].state, CacheState::Quarantined);
+        assert_eq!(checks[0].detail.as_deref(), Some("checksum mismatch"));
+        assert!(!cache.is_cached(&cache_key));
+        assert!(cache.quarantine_record(&cache_key).unwrap().is_some());
+
+        // Still reported on the next pass, with the original reason
+        let checks = verify_cache(&config).await.unwrap();
+        assert_eq!(checks[0].state, CacheState::Quarantined);
+        assert_eq!(checks[0].detail.as_deref(), Some("checksum mismatch"));
+    }
+}
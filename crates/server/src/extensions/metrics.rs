@@ -0,0 +1,231 @@
+//! Per-extension, per-field metrics for WASM resolver calls.
+//!
+//! Every `resolve-field` call records its latency, outcome, fuel and the sizes of the
+//! JSON crossing the WASM boundary. Samples go to the `metrics` recorder, which
+//! `metrics_exporter` renders on `/metrics`. They are also aggregated in process for the
+//! `extensionFieldStats` admin query. Extensions choose their own field names, so the
+//! label set is capped: past [`max_label_pairs`] distinct extension/field pairs, new
+//! fields are reported under [`OVERFLOW_FIELD`].
+//!
+//! A `resolve-fields` batch counts as one call, under the name of its first field.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use metrics::{counter, histogram};
+
+/// Field label used once the label budget is spent.
+pub const OVERFLOW_FIELD: &str = "__other";
+
+/// One finished resolver call.
+#[derive(Clone, Debug)]
+pub struct CallSample<'a> {
+    pub extension: &'a str,
+    pub field: &'a str,
+    pub latency: Duration,
+    pub ok: bool,
+    /// `None` when fuel metering is disabled for the extension.
+    pub fuel_consumed: Option<u64>,
+    pub request_bytes: usize,
+    pub response_bytes: usize,
+}
+
+/// Aggregated counters for one extension field since startup.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FieldStats {
+    pub extension: String,
+    pub field: String,
+    pub calls: u64,
+    pub errors: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+    pub fuel_consumed: u64,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+impl FieldStats {
+    pub fn avg_ms(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.total_ms / self.calls as f64
+        }
+    }
+}
+
+/// Reads `FORGE_EXTENSION_METRICS_MAX_FIELDS` (default 256).
+pub fn max_label_pairs() -> usize {
+    std::env::var("FORGE_EXTENSION_METRICS_MAX_FIELDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(256)
+}
+
+#[derive(Default)]
+struct FieldRegistry {
+    fields: HashMap<(String, String), FieldStats>,
+}
+
+impl FieldRegistry {
+    /// Returns the field label to use, registering the pair while the budget lasts.
+    fn label<'a>(&mut self, extension: &str, field: &'a str, budget: usize) -> &'a str {
+        let key = (extension.to_string(), field.to_string());
+        if self.fields.contains_key(&key) {
+            return field;
+        }
+        if self.fields.len() >= budget {
+            return OVERFLOW_FIELD;
+        }
+        self.fields.insert(
+            key,
+            FieldStats {
+                extension: extension.to_string(),
+                field: field.to_string(),
+                ..FieldStats::default()
+            },
+        );
+        field
+    }
+
+    fn record(&mut self, sample: &CallSample<'_>, budget: usize) -> String {
+        let label = self
+            .label(sample.extension, sample.field, budget)
+            .to_string();
+        let key = (sample.extension.to_string(), label.clone());
+        let stats = self.fields.entry(key).or_insert_with(|| FieldStats {
+            extension: sample.extension.to_string(),
+            field: label.clone(),
+            ..FieldStats::default()
+        });
+        let ms = sample.latency.as_secs_f64() * 1000.0;
+        stats.calls += 1;
+        stats.errors += u64::from(!sample.ok);
+        stats.total_ms += ms;
+        stats.max_ms = stats.max_ms.max(ms);
+        stats.fuel_consumed += sample.fuel_consumed.unwrap_or(0);
+        stats.request_bytes += sample.request_bytes as u64;
+        stats.response_bytes += sample.response_bytes as u64;
+        label
+    }
+
+    fn slowest(&self, limit: usize) -> Vec<FieldStats> {
+        let mut all: Vec<FieldStats> = self
+            .fields
+            .values()
+            .filter(|s| s.calls > 0)
+            .cloned()
+            .collect();
+        all.sort_by(|a, b| b.avg_ms().total_cmp(&a.avg_ms()));
+        all.truncate(limit);
+        all
+    }
+}
+
+static REGISTRY: LazyLock<Mutex<FieldRegistry>> = LazyLock::new(Mutex::default);
+
+/// Records one resolver call in the metrics recorder and the in-process aggregate.
+pub fn record_call(sample: &CallSample<'_>) {
+    let Ok(mut registry) = REGISTRY.lock() else {
+        return;
+    };
+    let field = registry.record(sample, max_label_pairs());
+    drop(registry);
+
+    let extension = sample.extension.to_string();
+    let outcome = if sample.ok { "ok" } else { "error" };
+    counter!("forge_extension_calls_total", "extension" => extension.clone(), "field" => field.clone(), "outcome" => outcome)
+        .increment(1);
+    if !sample.ok {
+        counter!("forge_extension_errors_total", "extension" => extension.clone(), "field" => field.clone())
+            .increment(1);
+    }
+    histogram!("forge_extension_call_duration_ms", "extension" => extension.clone(), "field" => field.clone())
+        .record(sample.latency.as_secs_f64() * 1000.0);
+    if let Some(fuel) = sample.fuel_consumed {
+        histogram!("forge_extension_fuel_consumed", "extension" => extension.clone(), "field" => field.clone())
+            .record(fuel as f64);
+    }
+    histogram!("forge_extension_payload_bytes", "extension" => extension.clone(), "field" => field.clone(), "direction" => "request")
+        .record(sample.request_bytes as f64);
+    histogram!("forge_extension_payload_bytes", "extension" => extension, "field" => field, "direction" => "response")
+        .record(sample.response_bytes as f64);
+}
+
+/// Records a batch of fields resolved together: how many were asked for and how many
+/// were left after identical ones were merged.
+pub fn record_batch(extension: &str, requested: usize, distinct: usize) {
+    histogram!("forge_extension_batch_size", "extension" => extension.to_string())
+        .record(distinct as f64);
+    counter!("forge_extension_batch_duplicates_total", "extension" => extension.to_string())
+        .increment(requested.saturating_sub(distinct) as u64);
+}
+
+/// Fields with the highest average latency, slowest first.
+pub fn slowest_fields(limit: usize) -> Vec<FieldStats> {
+    REGISTRY
+        .lock()
+        .map(|registry| registry.slowest(limit))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample<'a>(field: &'a str, ms: u64, ok: bool) -> CallSample<'a> {
+        CallSample {
+            extension: "issues",
+            field,
+            latency: Duration::from_millis(ms),
+            ok,
+            fuel_consumed: Some(100),
+            request_bytes: 10,
+            response_bytes: 20,
+        }
+    }
+
+    #[test]
+    fn fields_past_the_budget_share_one_label() {
+        let mut registry = FieldRegistry::default();
+        assert_eq!(registry.record(&sample("getIssue", 5, true), 2), "getIssue");
+        assert_eq!(
+            registry.record(&sample("listIssues", 5, true), 2),
+            "listIssues"
+        );
+        assert_eq!(
+            registry.record(&sample("createIssue", 5, true), 2),
+            OVERFLOW_FIELD
+        );
+        assert_eq!(
+            registry.record(&sample("updateIssue", 5, true), 2),
+            OVERFLOW_FIELD
+        );
+        // already-known fields keep their label
+        assert_eq!(registry.record(&sample("getIssue", 5, true), 2), "getIssue");
+
+        let overflow = &registry.fields[&("issues".to_string(), OVERFLOW_FIELD.to_string())];
+        assert_eq!(overflow.calls, 2);
+    }
+
+    #[test]
+    fn slowest_fields_sort_by_average_latency() {
+        let mut registry = FieldRegistry::default();
+        registry.record(&sample("fast", 2, true), 10);
+        registry.record(&sample("slow", 40, true), 10);
+        registry.record(&sample("slow", 20, false), 10);
+        registry.record(&sample("medium", 10, true), 10);
+
+        let top = registry.slowest(2);
+        assert_eq!(
+            top.iter().map(|s| s.field.as_str()).collect::<Vec<_>>(),
+            vec!["slow", "medium"]
+        );
+        assert_eq!(top[0].calls, 2);
+        assert_eq!(top[0].errors, 1);
+        assert_eq!(top[0].avg_ms(), 30.0);
+        assert_eq!(top[0].max_ms, 40.0);
+        assert_eq!(top[0].fuel_consumed, 200);
+    }
+}
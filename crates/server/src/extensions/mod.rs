@@ -5,29 +5,96 @@
 //! field resolution in a secure, isolated environment.
 
 pub mod cache;
+pub mod download;
+pub mod graphql_access;
+pub mod integrity;
 pub mod interface;
 pub mod loader;
+pub mod metrics;
 pub mod oci_fetcher;
+pub mod registry_proxy;
+pub mod scheduler;
 pub mod schema;
+pub mod upgrade;
 pub mod wasm_runtime;
+pub mod watcher;
 pub mod wit_bindings;
 
 use anyhow::{Context, Result};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+
+/// Credentials configured for an OCI registry, if any
+pub(crate) fn registry_auth(
+    config: &crate::config::Extensions,
+    registry: &str,
+) -> Option<oci_distribution::secrets::RegistryAuth> {
+    config
+        .auth
+        .get(registry)
+        .and_then(|registry_auth| registry_auth.resolve_credentials())
+        .map(|(username, password)| {
+            oci_distribution::secrets::RegistryAuth::Basic(username, password)
+        })
+}
+
+/// An OCI fetcher for the configured cache, mirrors and download settings
+pub(crate) fn configured_fetcher(
+    config: &crate::config::Extensions,
+    verify_checksums: bool,
+) -> Result<oci_fetcher::OciExtensionFetcher> {
+    let mirrors = config
+        .mirrors
+        .iter()
+        .map(|(registry, hosts)| {
+            let mirrors = hosts
+                .iter()
+                .map(|host| oci_fetcher::Mirror {
+                    host: host.clone(),
+                    auth: registry_auth(config, host),
+                })
+                .collect();
+            (registry.clone(), mirrors)
+        })
+        .collect();
+    Ok(oci_fetcher::OciExtensionFetcher::new(
+        config.settings.cache_dir(),
+        config.settings.offline_mode,
+        verify_checksums,
+    )?
+    .with_mirrors(mirrors)
+    .with_stall_timeout(std::time::Duration::from_secs(
+        config.settings.download_stall_secs,
+    )))
+}
 
 /// Represents a loaded extension with its metadata and runtime state
 #[allow(dead_code)] // Will be used when extension system is fully integrated
+#[derive(Clone)]
 pub struct Extension {
     pub name: String,
     pub schema: schema::SchemaFragment,
     pub runtime: Arc<wasm_runtime::Extension>,
 }
 
+/// What [`ExtensionManager::reload`] and `reloadExtension` report
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtensionReload {
+    pub extension: String,
+    /// False when the module was gone and the extension was unloaded
+    pub loaded: bool,
+    pub version: Option<String>,
+    pub previous_version: Option<String>,
+}
+
 /// Extension manager coordinates loading and lifecycle management of extensions
 pub struct ExtensionManager {
-    extensions: HashMap<String, Extension>,
+    /// Replaced as a whole when an extension is reloaded, so callers keep a consistent
+    /// snapshot for as long as they hold it
+    extensions: RwLock<Arc<HashMap<String, Extension>>>,
+    /// The module each extension was loaded from, which reloads read again
+    sources: RwLock<HashMap<String, PathBuf>>,
     extensions_dir: PathBuf,
     #[allow(dead_code)]
     db_path: PathBuf,
@@ -51,7 +118,7 @@ mod tests {
         let manager = ExtensionManager::new(extensions_dir.clone(), db_path.clone());
         assert_eq!(manager.extensions_dir, extensions_dir);
         assert_eq!(manager.db_path, db_path);
-        assert!(manager.extensions.is_empty());
+        assert!(manager.get_extensions().is_empty());
     }
 
     #[tokio::test]
@@ -67,7 +134,22 @@ mod tests {
         let result = manager.load_extensions().await;
 
         assert!(result.is_ok());
-        assert!(manager.extensions.is_empty());
+        assert!(manager.get_extensions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reloading_a_missing_module_unloads_it() {
+        let temp_dir = TempDir::new().unwrap();
+        let extensions_dir = temp_dir.path().join("extensions");
+        let manager = ExtensionManager::new(extensions_dir.clone(), temp_dir.path().join("db"));
+        assert!(manager.reload("issues").await.is_err());
+
+        manager.remember_source("issues", &extensions_dir.join("issues.wasm"));
+        let (reload, previous) = manager.reload("issues").await.unwrap();
+        assert!(!reload.loaded);
+        assert_eq!(reload.version, None);
+        assert!(previous.is_none());
+        assert!(manager.get_extensions().is_empty());
     }
 
     #[test]
@@ -101,7 +183,8 @@ impl ExtensionManager {
     /// Create a new extension manager
     pub fn new(extensions_dir: PathBuf, db_path: PathBuf) -> Self {
         Self {
-            extensions: HashMap::new(),
+            extensions: RwLock::default(),
+            sources: RwLock::default(),
             extensions_dir,
             db_path,
         }
@@ -130,23 +213,11 @@ impl ExtensionManager {
         &mut self,
         config: &crate::config::Extensions,
     ) -> Result<()> {
-        use oci_distribution::secrets::RegistryAuth;
-
         let mut extension_paths: Vec<(String, PathBuf)> = Vec::new();
 
         // 1. Fetch OCI extensions if configured
         if !config.oci.is_empty() {
-            let cache_dir = config
-                .settings
-                .cache_dir
-                .clone()
-                .unwrap_or_else(|| PathBuf::from(".forge/extensions/cache"));
-
-            let fetcher = oci_fetcher::OciExtensionFetcher::new(
-                cache_dir,
-                config.settings.offline_mode,
-                config.settings.verify_checksums,
-            )?;
+            let fetcher = configured_fetcher(config, config.settings.verify_checksums)?;
 
             for oci_ext in &config.oci {
                 // Validate extension name
@@ -160,11 +231,7 @@ impl ExtensionManager {
                 }
 
                 // Resolve authentication
-                let auth = config
-                    .auth
-                    .get(&oci_ext.registry)
-                    .and_then(|registry_auth| registry_auth.resolve_credentials())
-                    .map(|(username, password)| RegistryAuth::Basic(username, password));
+                let auth = registry_auth(config, &oci_ext.registry);
 
                 match fetcher
                     .fetch_extension(
@@ -186,11 +253,7 @@ impl ExtensionManager {
                         extension_paths.push((oci_ext.name.clone(), path));
                     }
                     Err(e) if config.settings.offline_mode => {
-                        tracing::warn!(
-                            "Skipping {} (offline mode, not cached): {}",
-                            oci_ext.name,
-                            e
-                        );
+                        tracing::warn!("Skipping {} (offline mode): {}", oci_ext.name, e);
                     }
                     Err(e) => {
                         tracing::error!("Failed to fetch OCI extension {}: {}", oci_ext.name, e);
@@ -259,9 +322,10 @@ impl ExtensionManager {
 
         // 3. Load all extensions
         for (name, path) in extension_paths {
+            self.remember_source(&name, &path);
             match self.load_extension(&name, &path).await {
                 Ok(ext) => {
-                    self.extensions.insert(name.clone(), ext);
+                    self.install(&name, Some(ext));
                     tracing::info!("Loaded extension: {}", name);
                 }
                 Err(e) => {
@@ -270,10 +334,11 @@ impl ExtensionManager {
             }
         }
 
-        if self.extensions.is_empty() {
+        let loaded = self.get_extensions().len();
+        if loaded == 0 {
             tracing::info!("No extensions loaded");
         } else {
-            tracing::info!("Loaded {} extension(s)", self.extensions.len());
+            tracing::info!("Loaded {} extension(s)", loaded);
         }
 
         Ok(())
@@ -308,10 +373,11 @@ impl ExtensionManager {
             if path.extension().and_then(|s| s.to_str()) == Some("wasm") {
                 wasm_extensions_found = true;
                 if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                    self.remember_source(name, &path);
                     match self.load_extension(name, &path).await {
                         Ok(extension) => {
                             tracing::info!("Loaded extension: {}", name);
-                            self.extensions.insert(name.to_string(), extension);
+                            self.install(name, Some(extension));
                         }
                         Err(e) => {
                             tracing::error!("Failed to load extension {}: {:#}", name, e);
@@ -368,16 +434,110 @@ impl ExtensionManager {
         })
     }
 
-    /// Get all loaded extensions
-    pub fn get_extensions(&self) -> &HashMap<String, Extension> {
-        &self.extensions
+    /// Records the module `name` is loaded from, even if loading it fails, so a fixed
+    /// module can be reloaded.
+    fn remember_source(&self, name: &str, path: &Path) {
+        self.sources
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), path.to_path_buf());
+    }
+
+    /// Makes `extension` the one loaded as `name`, or unloads `name` when it is `None`,
+    /// and returns the extension it replaced.
+    fn install(&self, name: &str, extension: Option<Extension>) -> Option<Extension> {
+        let mut current = self.extensions.write().unwrap_or_else(|e| e.into_inner());
+        let mut extensions = HashMap::clone(&current);
+        let previous = match extension {
+            Some(extension) => extensions.insert(name.to_string(), extension),
+            None => extensions.remove(name),
+        };
+        *current = Arc::new(extensions);
+        previous
+    }
+
+    /// Loads `name` again from the module found for it at startup, or unloads it
+    /// when that module is gone. Returns the extension it replaced, which [`Self::restore`]
+    /// puts back if the new one cannot be used. Requests that already hold the old
+    /// runtime finish on it.
+    pub async fn reload(&self, name: &str) -> Result<(ExtensionReload, Option<Extension>)> {
+        let Some(path) = self.source(name) else {
+            anyhow::bail!("no module was configured or found for extension `{}`", name);
+        };
+        let extension = if path.is_file() {
+            Some(self.load_extension(name, &path).await?)
+        } else {
+            tracing::warn!("{} is gone, unloading extension {}", path.display(), name);
+            None
+        };
+        let version = extension
+            .as_ref()
+            .map(|extension| extension.runtime.version().to_string());
+        let loaded = extension.is_some();
+        let previous = self.install(name, extension);
+        let reload = ExtensionReload {
+            extension: name.to_string(),
+            loaded,
+            version,
+            previous_version: previous
+                .as_ref()
+                .map(|extension| extension.runtime.version().to_string()),
+        };
+        Ok((reload, previous))
+    }
+
+    /// Puts back the extension a [`Self::reload`] replaced.
+    pub fn restore(&self, name: &str, previous: Option<Extension>) {
+        self.install(name, previous);
+    }
+
+    /// The module found for `name` at startup
+    pub fn source(&self, name: &str) -> Option<PathBuf> {
+        self.sources
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned()
+    }
+
+    /// The module of every extension found at startup, whether or not it is loaded now
+    pub fn sources(&self) -> Vec<(String, PathBuf)> {
+        let mut sources: Vec<_> = self
+            .sources
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, path)| (name.clone(), path.clone()))
+            .collect();
+        sources.sort();
+        sources
+    }
+
+    /// Get all loaded extensions, as they are now
+    pub fn get_extensions(&self) -> Arc<HashMap<String, Extension>> {
+        self.extensions
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Extensions that export a resolver, and so take part in the composed schema, by name
+    pub fn graphql_extensions(&self) -> Vec<(String, Extension)> {
+        let mut extensions: Vec<_> = self
+            .get_extensions()
+            .iter()
+            .filter(|(_, extension)| extension.runtime.interfaces().resolver)
+            .map(|(name, extension)| (name.clone(), extension.clone()))
+            .collect();
+        extensions.sort_by(|a, b| a.0.cmp(&b.0));
+        extensions
     }
 
     /// Get merged GraphQL schema from all extensions
     pub fn get_merged_schema(&self) -> String {
         let mut merged = String::new();
 
-        for extension in self.extensions.values() {
+        for extension in self.get_extensions().values() {
             if !extension.schema.is_empty() {
                 merged.push_str(&extension.schema.to_sdl());
                 merged.push('\n');
@@ -2,14 +2,32 @@
 //!
 //! This module provides functionality to fetch WASM extensions from OCI-compliant
 //! registries with authentication, caching, and checksum verification.
+//!
+//! Configured mirrors of a registry are tried in order before the registry itself. Layer
+//! downloads resume where an earlier attempt stopped (see [`super::download`]), and a
+//! transfer is only abandoned when it stops making progress, so slow or throttled links
+//! still finish.
 
 use super::cache::{CacheMetadata, ExtensionCache, compute_sha256};
+use super::download::{PartialDownload, Progress};
 use anyhow::{Context, Result};
+use metrics::counter;
 use oci_distribution::Reference;
-use oci_distribution::client::{Client, ClientConfig, ClientProtocol};
+use oci_distribution::client::{BlobResponse, Client, ClientConfig, ClientProtocol};
 use oci_distribution::secrets::RegistryAuth;
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
+
+/// Media type of the layer holding the WASM module
+const WASM_MEDIA_TYPE: &str = "application/vnd.wasm.module.v1+wasm";
+
+/// A registry serving copies of another registry's images
+#[derive(Clone)]
+pub struct Mirror {
+    pub host: String,
+    pub auth: Option<RegistryAuth>,
+}
 
 /// OCI extension fetcher with caching support
 pub struct OciExtensionFetcher {
@@ -17,11 +35,17 @@ pub struct OciExtensionFetcher {
     cache: ExtensionCache,
     offline_mode: bool,
     verify_checksums: bool,
+    /// Mirrors keyed by the registry they stand in for
+    mirrors: HashMap<String, Vec<Mirror>>,
+    stall_timeout: Duration,
 }
 
 impl OciExtensionFetcher {
-    /// Default timeout for OCI operations (60 seconds)
-    const DEFAULT_TIMEOUT_SECS: u64 = 60;
+    /// Timeout for manifest requests and for a layer download to start (60 seconds)
+    const REQUEST_TIMEOUT_SECS: u64 = 60;
+
+    /// Default time a layer download may go without receiving data
+    pub const DEFAULT_STALL_TIMEOUT_SECS: u64 = 60;
 
     /// Maximum number of retry attempts for transient failures
     const MAX_RETRIES: u32 = 3;
@@ -44,9 +68,41 @@ impl OciExtensionFetcher {
             cache,
             offline_mode,
             verify_checksums,
+            mirrors: HashMap::new(),
+            stall_timeout: Duration::from_secs(Self::DEFAULT_STALL_TIMEOUT_SECS),
         })
     }
 
+    /// Try these mirrors, keyed by the registry they mirror, before the registry itself
+    pub fn with_mirrors(mut self, mirrors: HashMap<String, Vec<Mirror>>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Abandon a layer download after this long without data
+    pub fn with_stall_timeout(mut self, stall_timeout: Duration) -> Self {
+        self.stall_timeout = stall_timeout;
+        self
+    }
+
+    /// Hosts to pull from, in order: the registry's mirrors, then the registry.
+    fn sources<'a>(
+        &'a self,
+        registry: &'a str,
+        auth: Option<&'a RegistryAuth>,
+    ) -> Vec<(&'a str, Option<&'a RegistryAuth>)> {
+        let mirrors = self
+            .mirrors
+            .get(registry)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        mirrors
+            .iter()
+            .map(|mirror| (mirror.host.as_str(), mirror.auth.as_ref()))
+            .chain(std::iter::once((registry, auth)))
+            .collect()
+    }
+
     /// Fetch an extension from OCI registry or cache
     /// Returns the path to the cached WASM file
     pub async fn fetch_extension(
@@ -63,35 +119,48 @@ impl OciExtensionFetcher {
             tracing::info!("Cache hit for {}/{}:{}", registry, image, reference);
 
             // Verify checksum if enabled
-            if self.verify_checksums {
-                match self.cache.verify_checksum(&cache_key) {
-                    Ok(true) => {
-                        tracing::debug!("Checksum verification passed for {}", cache_key);
-                        return Ok(self.cache.wasm_path(&cache_key));
-                    }
-                    Ok(false) => {
-                        tracing::warn!(
-                            "Checksum verification failed for {}, re-fetching",
-                            cache_key
-                        );
-                        // Continue to fetch from registry
-                    }
-                    Err(e) => {
-                        tracing::warn!(
-                            "Failed to verify checksum for {}: {}, re-fetching",
-                            cache_key,
-                            e
-                        );
-                        // Continue to fetch from registry
-                    }
-                }
-            } else {
+            if !self.verify_checksums {
                 return Ok(self.cache.wasm_path(&cache_key));
             }
+            let problem = match self.cache.verify_checksum(&cache_key) {
+                Ok(true) => {
+                    tracing::debug!("Checksum verification passed for {}", cache_key);
+                    return Ok(self.cache.wasm_path(&cache_key));
+                }
+                Ok(false) => "checksum mismatch".to_string(),
+                Err(e) => format!("unreadable: {:#}", e),
+            };
+
+            // Nothing can replace a bad copy offline; keep it from being loaded again
+            if self.offline_mode {
+                self.cache.quarantine(&cache_key, &problem)?;
+                anyhow::bail!(
+                    "Cached extension {}/{}:{} failed verification ({}) and was quarantined; \
+                     offline mode is enabled, so it cannot be re-fetched",
+                    registry,
+                    image,
+                    reference,
+                    problem
+                );
+            }
+            tracing::warn!(
+                "Checksum verification failed for {} ({}), re-fetching",
+                cache_key,
+                problem
+            );
         }
 
         // If in offline mode and not cached, fail
         if self.offline_mode {
+            if let Some(record) = self.cache.quarantine_record(&cache_key)? {
+                anyhow::bail!(
+                    "Extension {}/{}:{} is quarantined ({}) and offline mode is enabled",
+                    registry,
+                    image,
+                    reference,
+                    record.reason
+                );
+            }
             anyhow::bail!(
                 "Extension {}/{}:{} not in cache and offline mode is enabled",
                 registry,
@@ -130,7 +199,8 @@ impl OciExtensionFetcher {
         Ok(self.cache.wasm_path(&cache_key))
     }
 
-    /// Fetch with exponential backoff retry for transient failures
+    /// Fetch with exponential backoff retry for transient failures. Each attempt walks
+    /// the mirrors and then the registry, stopping at the first that delivers.
     async fn fetch_with_retry(
         &self,
         registry: &str,
@@ -157,42 +227,38 @@ impl OciExtensionFetcher {
                 tokio::time::sleep(delay).await;
             }
 
-            // Try to fetch with timeout
-            let timeout = std::time::Duration::from_secs(Self::DEFAULT_TIMEOUT_SECS);
-            let result = tokio::time::timeout(
-                timeout,
-                self.pull_from_registry(registry, image, reference, auth),
-            )
-            .await;
-
-            match result {
-                Ok(Ok(data)) => return Ok(data),
-                Ok(Err(e)) => {
-                    tracing::warn!(
-                        "Attempt {}/{} failed for {}/{}:{}: {}",
-                        attempt + 1,
-                        Self::MAX_RETRIES,
-                        registry,
-                        image,
-                        reference,
-                        e
-                    );
-                    last_error = Some(e);
-                }
-                Err(_) => {
-                    let timeout_error = anyhow::anyhow!(
-                        "OCI fetch timed out after {}s",
-                        Self::DEFAULT_TIMEOUT_SECS
-                    );
-                    tracing::warn!(
-                        "Attempt {}/{} timed out for {}/{}:{}",
-                        attempt + 1,
-                        Self::MAX_RETRIES,
-                        registry,
-                        image,
-                        reference
-                    );
-                    last_error = Some(timeout_error);
+            for (host, source_auth) in self.sources(registry, auth) {
+                match self
+                    .pull_from_registry(host, image, reference, source_auth)
+                    .await
+                {
+                    Ok(data) => {
+                        if host != registry {
+                            tracing::info!(
+                                "Fetched {}/{}:{} from mirror {}",
+                                registry,
+                                image,
+                                reference,
+                                host
+                            );
+                        }
+                        return Ok(data);
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            "Attempt {}/{} failed for {}/{}:{} via {}: {:#}",
+                            attempt + 1,
+                            Self::MAX_RETRIES,
+                            registry,
+                            image,
+                            reference,
+                            host,
+                            e
+                        );
+                        counter!("forge_extension_download_failovers_total", "registry" => host.to_string())
+                            .increment(1);
+                        last_error = Some(e);
+                    }
                 }
             }
         }
@@ -206,7 +272,7 @@ impl OciExtensionFetcher {
         }))
     }
 
-    /// Pull WASM module from OCI registry
+    /// Pull WASM module from one registry host, resuming a partial layer download
     /// Returns (wasm_data, content_digest)
     async fn pull_from_registry(
         &self,
@@ -215,49 +281,92 @@ impl OciExtensionFetcher {
         reference_str: &str,
         auth: Option<&RegistryAuth>,
     ) -> Result<(Vec<u8>, String)> {
-        // Build OCI reference
-        let full_reference = format!("{}/{}:{}", registry, image, reference_str);
+        // Build OCI reference; digests are pinned with `@` so every mirror must serve
+        // the same manifest
+        let separator = if reference_str.starts_with("sha256:") {
+            '@'
+        } else {
+            ':'
+        };
+        let full_reference = format!("{}/{}{}{}", registry, image, separator, reference_str);
         let reference: Reference = full_reference
             .parse()
             .with_context(|| format!("Invalid OCI reference: {}", full_reference))?;
 
-        // Pull image manifest and layers
         let auth = auth.cloned().unwrap_or(RegistryAuth::Anonymous);
+        let request_timeout = Duration::from_secs(Self::REQUEST_TIMEOUT_SECS);
+
+        let (manifest, content_digest) = tokio::time::timeout(
+            request_timeout,
+            self.client.pull_image_manifest(&reference, &auth),
+        )
+        .await
+        .with_context(|| format!("Manifest request timed out for {}", full_reference))?
+        .with_context(|| format!("Failed to pull OCI manifest: {}", full_reference))?;
+        if separator == '@' && content_digest != reference_str {
+            anyhow::bail!(
+                "{} served manifest {} for {}",
+                registry,
+                content_digest,
+                reference_str
+            );
+        }
 
-        let image_data = self
-            .client
-            .pull(
-                &reference,
-                &auth,
-                vec!["application/vnd.wasm.module.v1+wasm"],
+        // For WASM modules, we expect a layer containing the .wasm file
+        let layer = manifest
+            .layers
+            .iter()
+            .find(|layer| layer.media_type == WASM_MEDIA_TYPE)
+            .with_context(|| format!("OCI image has no WASM layer: {}", full_reference))?;
+
+        let size = u64::try_from(layer.size).ok();
+        let partial = PartialDownload::open(self.cache.cache_dir(), &layer.digest, size)?;
+        let offset = partial.offset()?;
+        if !partial.is_complete(offset) {
+            let response = tokio::time::timeout(
+                request_timeout,
+                self.client
+                    .pull_blob_stream_partial(&reference, layer, offset, None),
             )
             .await
-            .with_context(|| format!("Failed to pull OCI image: {}", full_reference))?;
+            .with_context(|| format!("Layer request timed out for {}", full_reference))?
+            .with_context(|| format!("Failed to pull OCI layer: {}", full_reference))?;
+            let (stream, append) = match response {
+                BlobResponse::Partial(stream) => (stream, true),
+                BlobResponse::Full(stream) => (stream, false),
+            };
+            if offset > 0 {
+                if append {
+                    tracing::info!("Resuming {} at byte {}", full_reference, offset);
+                    counter!("forge_extension_download_resumes_total", "registry" => registry.to_string())
+                        .increment(1);
+                } else {
+                    tracing::info!(
+                        "{} ignored the range request; downloading {} from the start",
+                        registry,
+                        full_reference
+                    );
+                }
+            }
 
-        // Extract WASM data from layers
-        // For WASM modules, we expect a single layer containing the .wasm file
-        if image_data.layers.is_empty() {
-            anyhow::bail!("OCI image has no layers: {}", full_reference);
+            let resumed_from = if append { offset } else { 0 };
+            let mut progress = Progress::new(image, registry, size, resumed_from);
+            let written = partial
+                .write_stream(stream.stream, append, &mut progress, self.stall_timeout)
+                .await;
+            progress.finish(if written.is_ok() { "ok" } else { "error" });
+            written?;
         }
 
-        // Get the first layer (WASM module)
-        let wasm_layer = &image_data.layers[0];
-
-        // Extract content digest from the manifest
-        let content_digest = image_data
-            .digest
-            .as_ref()
-            .map(|d| d.to_string())
-            .unwrap_or_else(|| format!("sha256:{}", compute_sha256(&wasm_layer.data)));
-
+        let data = partial.finish()?;
         tracing::debug!(
             "Pulled {} bytes from {} (digest: {})",
-            wasm_layer.data.len(),
+            data.len(),
             full_reference,
             content_digest
         );
 
-        Ok((wasm_layer.data.clone(), content_digest))
+        Ok((data, content_digest))
     }
 
     /// Validate that data is a valid WASM module
@@ -327,6 +436,42 @@ mod tests {
         assert!(fetcher.validate_wasm(too_small).is_err());
     }
 
+    #[test]
+    fn test_mirrors_are_tried_before_the_registry() {
+        let temp_dir = TempDir::new().unwrap();
+        let mirror_auth = RegistryAuth::Basic("mirror".to_string(), "secret".to_string());
+        let fetcher = OciExtensionFetcher::new(temp_dir.path().join("cache"), false, true)
+            .unwrap()
+            .with_mirrors(HashMap::from([(
+                "ghcr.io".to_string(),
+                vec![
+                    Mirror {
+                        host: "mirror-a.internal".to_string(),
+                        auth: Some(mirror_auth),
+                    },
+                    Mirror {
+                        host: "mirror-b.internal".to_string(),
+                        auth: None,
+                    },
+                ],
+            )]));
+
+        let hosts: Vec<(&str, bool)> = fetcher
+            .sources("ghcr.io", None)
+            .into_iter()
+            .map(|(host, auth)| (host, auth.is_some()))
+            .collect();
+        assert_eq!(
+            hosts,
+            vec![
+                ("mirror-a.internal", true),
+                ("mirror-b.internal", false),
+                ("ghcr.io", false)
+            ]
+        );
+        assert_eq!(fetcher.sources("docker.io", None).len(), 1);
+    }
+
     #[tokio::test]
     async fn test_offline_mode_with_cache() {
         let temp_dir = TempDir::new().unwrap();
@@ -373,6 +518,42 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_offline_mode_quarantines_corrupt_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let fetcher = OciExtensionFetcher::new(temp_dir.path().join("cache"), true, true).unwrap();
+
+        let wasm_data = b"\0asm\x01\x00\x00\x00";
+        let cache_key = ExtensionCache::compute_cache_key("ghcr.io", "test/ext", "v1.0.0");
+        let metadata = CacheMetadata {
+            registry: "ghcr.io".to_string(),
+            image: "test/ext".to_string(),
+            reference: "v1.0.0".to_string(),
+            content_digest: None,
+            fetched_at: SystemTime::now(),
+            size_bytes: wasm_data.len() as u64,
+            sha256: compute_sha256(b"something else"),
+        };
+        fetcher
+            .cache()
+            .store(&cache_key, wasm_data, metadata)
+            .unwrap();
+
+        let err = fetcher
+            .fetch_extension("ghcr.io", "test/ext", "v1.0.0", None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("quarantined"));
+        assert!(!fetcher.cache().is_cached(&cache_key));
+
+        // Later attempts report the quarantine instead of a missing entry
+        let err = fetcher
+            .fetch_extension("ghcr.io", "test/ext", "v1.0.0", None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"));
+    }
+
     #[tokio::test]
     async fn test_offline_mode_without_cache() {
         let temp_dir = TempDir::new().unwrap();
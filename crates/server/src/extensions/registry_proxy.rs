@@ -0,0 +1,708 @@
+//! Pull-through registry proxy for OCI extensions
+//!
+//! With `extensions.registry_proxy` configured, the server answers the read half of the
+//! OCI distribution API under `/v2/` (see [`crate::api::registry_proxy`]) for a fixed set
+//! of upstream registries, each under an alias: `<forge host>/ghcr/forgepoint/ext` is
+//! `ghcr.io/forgepoint/ext` when `ghcr` is an alias for `ghcr.io`. Other Forge instances
+//! list that host as a mirror (`extensions.mirrors`) and never need to reach the upstream
+//! themselves.
+//!
+//! Manifests and blobs are cached by digest and checked against it before they are
+//! stored. Manifests pulled by tag are served from the cache for `tag_ttl_secs`, then
+//! resolved again; when the upstream cannot be reached the last known manifest is served
+//! instead. The cache is held to `max_bytes` by evicting whatever was served least
+//! recently. Concurrent requests for the same object share one upstream fetch.
+
+use super::cache::compute_sha256;
+use super::download::{PartialDownload, Progress};
+use crate::config::Extensions;
+use crate::user::db::unix_now;
+use anyhow::{Context, Result};
+use metrics::counter;
+use oci_distribution::client::{BlobResponse, Client, ClientConfig, ClientProtocol};
+use oci_distribution::manifest::OciDescriptor;
+use oci_distribution::secrets::RegistryAuth;
+use oci_distribution::{Reference, RegistryOperation};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::OwnedMutexGuard;
+
+/// Manifest media types requested from upstreams
+const MANIFEST_MEDIA_TYPES: &[&str] = &[
+    "application/vnd.oci.image.manifest.v1+json",
+    "application/vnd.oci.image.index.v1+json",
+    "application/vnd.docker.distribution.manifest.v2+json",
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+];
+
+/// Media type of a manifest that does not name its own
+const DEFAULT_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// Timeout for upstream manifest requests and for a blob download to start
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What a `/v2/` path asks for
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProxyObject {
+    /// A manifest by tag or digest
+    Manifest(String),
+    /// A blob by digest
+    Blob(String),
+}
+
+/// A parsed `/v2/<upstream>/<image>/{manifests,blobs}/<reference>` path
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProxyRoute {
+    pub upstream: String,
+    pub image: String,
+    pub object: ProxyObject,
+}
+
+/// Parses the part of a registry path after `/v2/`. Image names may have several
+/// segments; the first segment is always the upstream alias.
+pub fn parse_route(path: &str) -> Option<ProxyRoute> {
+    let (rest, reference) = path.trim_matches('/').rsplit_once('/')?;
+    let (rest, kind) = rest.rsplit_once('/')?;
+    let (upstream, image) = rest.split_once('/')?;
+    let valid_segment = |segment: &str| {
+        !segment.is_empty()
+            && segment != "."
+            && segment != ".."
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    };
+    if !valid_segment(upstream) || !image.split('/').all(valid_segment) {
+        return None;
+    }
+    let object = match kind {
+        "manifests" if is_digest(reference) => ProxyObject::Manifest(reference.to_string()),
+        "manifests" if is_tag(reference) => ProxyObject::Manifest(reference.to_string()),
+        "blobs" if is_digest(reference) => ProxyObject::Blob(reference.to_string()),
+        _ => return None,
+    };
+    Some(ProxyRoute {
+        upstream: upstream.to_string(),
+        image: image.to_ascii_lowercase(),
+        object,
+    })
+}
+
+/// Whether `reference` is a `sha256:<hex>` digest; the only kind the proxy stores
+pub fn is_digest(reference: &str) -> bool {
+    reference.strip_prefix("sha256:").is_some_and(|hex| {
+        hex.len() == 64 && hex.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+    })
+}
+
+fn is_tag(reference: &str) -> bool {
+    !reference.is_empty()
+        && reference.len() <= 128
+        && !reference.starts_with(['.', '-'])
+        && reference
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// A manifest or blob held by the proxy
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredObject {
+    pub digest: String,
+    pub media_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Cached manifests and blobs on disk, tracked in `registry_proxy_blobs`
+pub struct ProxyStore {
+    dir: PathBuf,
+    pool: SqlitePool,
+    max_bytes: u64,
+}
+
+impl ProxyStore {
+    pub fn new(dir: PathBuf, pool: SqlitePool, max_bytes: u64) -> Result<Self> {
+        let blobs = dir.join("blobs").join("sha256");
+        std::fs::create_dir_all(&blobs)
+            .with_context(|| format!("Failed to create {}", blobs.display()))?;
+        Ok(Self {
+            dir,
+            pool,
+            max_bytes,
+        })
+    }
+
+    fn path(&self, digest: &str) -> PathBuf {
+        let hex = digest.strip_prefix("sha256:").unwrap_or(digest);
+        self.dir.join("blobs").join("sha256").join(hex)
+    }
+
+    /// The object with `digest`, marking it as just used. A row whose file has gone
+    /// missing is dropped.
+    pub async fn get(&self, digest: &str) -> Result<Option<StoredObject>> {
+        let row = sqlx::query("SELECT media_type FROM registry_proxy_blobs WHERE digest = ?")
+            .bind(digest)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let data = match tokio::fs::read(self.path(digest)).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.forget(digest).await?;
+                return Ok(None);
+            }
+            Err(e) => return Err(e).context("Failed to read cached registry object"),
+        };
+        sqlx::query("UPDATE registry_proxy_blobs SET last_used_at = ? WHERE digest = ?")
+            .bind(unix_now())
+            .bind(digest)
+            .execute(&self.pool)
+            .await?;
+        Ok(Some(StoredObject {
+            digest: digest.to_string(),
+            media_type: row.try_get("media_type")?,
+            data,
+        }))
+    }
+
+    /// Stores `data` under `digest` after checking it, then evicts other objects until
+    /// the cache fits its quota.
+    pub async fn put(
+        &self,
+        digest: &str,
+        media_type: Option<&str>,
+        data: Vec<u8>,
+    ) -> Result<StoredObject> {
+        let actual = format!("sha256:{}", compute_sha256(&data));
+        if actual != digest {
+            anyhow::bail!("Digest mismatch: expected {}, got {}", digest, actual);
+        }
+        let size = data.len() as u64;
+        if size > self.max_bytes {
+            anyhow::bail!(
+                "{} is {} bytes, more than the registry proxy's quota of {} bytes",
+                digest,
+                size,
+                self.max_bytes
+            );
+        }
+
+        let path = self.path(digest);
+        let tmp = path.with_extension(format!("{}.tmp", cuid2::create_id()));
+        tokio::fs::write(&tmp, &data)
+            .await
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, &path)
+            .await
+            .with_context(|| format!("Failed to move {} into place", digest))?;
+
+        let now = unix_now();
+        sqlx::query(
+            "INSERT INTO registry_proxy_blobs (digest, media_type, size, fetched_at, last_used_at) \
+             VALUES (?, ?, ?, ?, ?) \
+             ON CONFLICT(digest) DO UPDATE SET media_type = excluded.media_type, \
+             fetched_at = excluded.fetched_at, last_used_at = excluded.last_used_at",
+        )
+        .bind(digest)
+        .bind(media_type)
+        .bind(size as i64)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        self.evict(digest).await?;
+
+        Ok(StoredObject {
+            digest: digest.to_string(),
+            media_type: media_type.map(str::to_string),
+            data,
+        })
+    }
+
+    /// Removes the least recently used objects other than `keep` while the cache is over
+    /// its quota.
+    async fn evict(&self, keep: &str) -> Result<()> {
+        let total: i64 =
+            sqlx::query_scalar("SELECT COALESCE(SUM(size), 0) FROM registry_proxy_blobs")
+                .fetch_one(&self.pool)
+                .await?;
+        let mut excess = total - self.max_bytes as i64;
+        if excess <= 0 {
+            return Ok(());
+        }
+        let candidates: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT digest, size FROM registry_proxy_blobs WHERE digest != ? \
+             ORDER BY last_used_at, fetched_at",
+        )
+        .bind(keep)
+        .fetch_all(&self.pool)
+        .await?;
+        for (digest, size) in candidates {
+            if excess <= 0 {
+                break;
+            }
+            self.forget(&digest).await?;
+            if let Err(e) = tokio::fs::remove_file(self.path(&digest)).await
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                tracing::warn!("Failed to remove cached registry object {}: {}", digest, e);
+            }
+            counter!("forge_registry_proxy_evictions_total").increment(1);
+            excess -= size;
+        }
+        Ok(())
+    }
+
+    /// Drops an object and the tags that point at it.
+    async fn forget(&self, digest: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM registry_proxy_blobs WHERE digest = ?")
+            .bind(digest)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM registry_proxy_tags WHERE digest = ?")
+            .bind(digest)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// The manifest digest a tag last resolved to, and when
+    pub async fn tag(
+        &self,
+        upstream: &str,
+        image: &str,
+        tag: &str,
+    ) -> Result<Option<(String, i64)>> {
+        Ok(sqlx::query_as(
+            "SELECT digest, fetched_at FROM registry_proxy_tags \
+             WHERE upstream = ? AND image = ? AND tag = ?",
+        )
+        .bind(upstream)
+        .bind(image)
+        .bind(tag)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    pub async fn set_tag(
+        &self,
+        upstream: &str,
+        image: &str,
+        tag: &str,
+        digest: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO registry_proxy_tags (upstream, image, tag, digest, fetched_at) \
+             VALUES (?, ?, ?, ?, ?) \
+             ON CONFLICT(upstream, image, tag) DO UPDATE SET digest = excluded.digest, \
+             fetched_at = excluded.fetched_at",
+        )
+        .bind(upstream)
+        .bind(image)
+        .bind(tag)
+        .bind(digest)
+        .bind(unix_now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// A registry served under an alias
+struct Upstream {
+    host: String,
+    auth: RegistryAuth,
+}
+
+/// The registry proxy configured by `extensions.registry_proxy`
+pub struct RegistryProxy {
+    client: Client,
+    store: ProxyStore,
+    upstreams: HashMap<String, Upstream>,
+    tag_ttl_secs: i64,
+    stall_timeout: Duration,
+    allow_anonymous: bool,
+    allowed_dids: Vec<String>,
+    /// Upstream fetches under way, by object
+    in_flight: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+/// Held while fetching one object; later requests for it wait, then find it cached
+struct FetchGuard<'a> {
+    proxy: &'a RegistryProxy,
+    key: String,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl Drop for FetchGuard<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self
+            .proxy
+            .in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        // Only this guard and the map hold the lock, so nobody is waiting on it
+        if in_flight
+            .get(&self.key)
+            .is_some_and(|lock| Arc::strong_count(lock) <= 2)
+        {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+impl RegistryProxy {
+    /// The proxy, when `extensions.registry_proxy` is set
+    pub fn from_config(config: &Extensions, pool: SqlitePool) -> Result<Option<Self>> {
+        let Some(proxy) = &config.registry_proxy else {
+            return Ok(None);
+        };
+        let dir = proxy
+            .cache_dir
+            .clone()
+            .unwrap_or_else(|| config.settings.cache_dir().join("proxy"));
+        let upstreams = proxy
+            .upstreams
+            .iter()
+            .map(|(alias, host)| {
+                let upstream = Upstream {
+                    host: host.clone(),
+                    auth: super::registry_auth(config, host).unwrap_or(RegistryAuth::Anonymous),
+                };
+                (alias.clone(), upstream)
+            })
+            .collect();
+        let client = Client::new(ClientConfig {
+            protocol: ClientProtocol::Https,
+            ..Default::default()
+        });
+        Ok(Some(Self {
+            client,
+            store: ProxyStore::new(dir, pool, proxy.max_bytes)?,
+            upstreams,
+            tag_ttl_secs: proxy.tag_ttl_secs as i64,
+            stall_timeout: Duration::from_secs(config.settings.download_stall_secs),
+            allow_anonymous: proxy.allow_anonymous,
+            allowed_dids: proxy.allowed_dids.clone(),
+            in_flight: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    pub fn has_upstream(&self, alias: &str) -> bool {
+        self.upstreams.contains_key(alias)
+    }
+
+    pub fn allows_anonymous(&self) -> bool {
+        self.allow_anonymous
+    }
+
+    /// Whether an authenticated user may pull
+    pub fn allows(&self, did: &str) -> bool {
+        self.allowed_dids.is_empty() || self.allowed_dids.iter().any(|allowed| allowed == did)
+    }
+
+    /// A manifest by tag or digest
+    pub async fn manifest(
+        &self,
+        alias: &str,
+        image: &str,
+        reference: &str,
+    ) -> Result<StoredObject> {
+        let upstream = self.upstream(alias)?;
+        if is_digest(reference) {
+            // Content addressed: a cached copy never goes stale
+            return self
+                .cached_or_fetch("manifest", reference, || {
+                    self.fetch_manifest(upstream, image, reference)
+                })
+                .await;
+        }
+
+        let fresh = |tag: &Option<(String, i64)>| {
+            tag.as_ref()
+                .filter(|(_, fetched_at)| unix_now() - fetched_at < self.tag_ttl_secs)
+                .map(|(digest, _)| digest.clone())
+        };
+        if let Some(digest) = fresh(&self.store.tag(alias, image, reference).await?)
+            && let Some(hit) = self.store.get(&digest).await?
+        {
+            record("manifest", "hit");
+            return Ok(hit);
+        }
+        let _fetching = self
+            .lock(format!("{}/{}:{}", alias, image, reference))
+            .await;
+        let known = self.store.tag(alias, image, reference).await?;
+        // Another request may have refreshed the tag while this one waited
+        if let Some(digest) = fresh(&known)
+            && let Some(hit) = self.store.get(&digest).await?
+        {
+            record("manifest", "hit");
+            return Ok(hit);
+        }
+        match self.fetch_manifest(upstream, image, reference).await {
+            Ok(manifest) => {
+                self.store
+                    .set_tag(alias, image, reference, &manifest.digest)
+                    .await?;
+                record("manifest", "miss");
+                Ok(manifest)
+            }
+            Err(err) => {
+                if let Some((digest, _)) = known
+                    && let Some(stale) = self.store.get(&digest).await?
+                {
+                    tracing::warn!(
+                        "Serving cached {}/{}:{} as {} is unavailable: {:#}",
+                        alias,
+                        image,
+                        reference,
+                        upstream.host,
+                        err
+                    );
+                    record("manifest", "stale");
+                    return Ok(stale);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// A blob by digest
+    pub async fn blob(&self, alias: &str, image: &str, digest: &str) -> Result<StoredObject> {
+        let upstream = self.upstream(alias)?;
+        self.cached_or_fetch("blob", digest, || self.fetch_blob(upstream, image, digest))
+            .await
+    }
+
+    fn upstream(&self, alias: &str) -> Result<&Upstream> {
+        self.upstreams
+            .get(alias)
+            .with_context(|| format!("No upstream registry is configured as `{}`", alias))
+    }
+
+    async fn lock(&self, key: String) -> FetchGuard<'_> {
+        let lock = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        FetchGuard {
+            proxy: self,
+            key,
+            _guard: lock.lock_owned().await,
+        }
+    }
+
+    async fn cached_or_fetch<F, Fut>(
+        &self,
+        kind: &'static str,
+        digest: &str,
+        fetch: F,
+    ) -> Result<StoredObject>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<StoredObject>>,
+    {
+        if let Some(hit) = self.store.get(digest).await? {
+            record(kind, "hit");
+            return Ok(hit);
+        }
+        let _fetching = self.lock(digest.to_string()).await;
+        if let Some(hit) = self.store.get(digest).await? {
+            record(kind, "hit");
+            return Ok(hit);
+        }
+        let object = fetch().await?;
+        record(kind, "miss");
+        Ok(object)
+    }
+
+    async fn fetch_manifest(
+        &self,
+        upstream: &Upstream,
+        image: &str,
+        reference: &str,
+    ) -> Result<StoredObject> {
+        let oci_reference = upstream_reference(upstream, image, reference)?;
+        let (data, _) = tokio::time::timeout(
+            REQUEST_TIMEOUT,
+            self.client
+                .pull_manifest_raw(&oci_reference, &upstream.auth, MANIFEST_MEDIA_TYPES),
+        )
+        .await
+        .with_context(|| format!("Manifest request timed out for {}", oci_reference))?
+        .with_context(|| format!("Failed to pull manifest {}", oci_reference))?;
+
+        // Digests are checked against the bytes, whatever the upstream claims
+        let digest = if is_digest(reference) {
+            reference.to_string()
+        } else {
+            format!("sha256:{}", compute_sha256(&data))
+        };
+        let media_type = serde_json::from_slice::<serde_json::Value>(&data)
+            .ok()
+            .and_then(|manifest| manifest["mediaType"].as_str().map(str::to_string))
+            .unwrap_or_else(|| DEFAULT_MANIFEST_MEDIA_TYPE.to_string());
+        self.store.put(&digest, Some(&media_type), data).await
+    }
+
+    /// Downloads a blob, resuming a partial download left by an earlier attempt
+    async fn fetch_blob(
+        &self,
+        upstream: &Upstream,
+        image: &str,
+        digest: &str,
+    ) -> Result<StoredObject> {
+        let oci_reference = upstream_reference(upstream, image, digest)?;
+        self.client
+            .auth(&oci_reference, &upstream.auth, RegistryOperation::Pull)
+            .await
+            .with_context(|| format!("Failed to authenticate with {}", upstream.host))?;
+
+        let descriptor = OciDescriptor {
+            digest: digest.to_string(),
+            ..Default::default()
+        };
+        let partial = PartialDownload::open(&self.store.dir, digest, None)?;
+        let offset = partial.offset()?;
+        let response = tokio::time::timeout(
+            REQUEST_TIMEOUT,
+            self.client
+                .pull_blob_stream_partial(&oci_reference, &descriptor, offset, None),
+        )
+        .await
+        .with_context(|| format!("Blob request timed out for {}", oci_reference));
+        let response = match response {
+            Ok(Ok(response)) => response,
+            Ok(Err(err)) => {
+                // The blob's size is unknown, so a leftover file may already hold all of
+                // it and the range is refused; start over next time
+                if offset > 0 {
+                    partial.discard();
+                }
+                return Err(err).with_context(|| format!("Failed to pull blob {}", oci_reference));
+            }
+            Err(err) => return Err(err),
+        };
+        let (stream, append) = match response {
+            BlobResponse::Partial(stream) => (stream, true),
+            BlobResponse::Full(stream) => (stream, false),
+        };
+        let resumed_from = if append { offset } else { 0 };
+        let mut progress = Progress::new(image, &upstream.host, None, resumed_from);
+        let written = partial
+            .write_stream(stream.stream, append, &mut progress, self.stall_timeout)
+            .await;
+        progress.finish(if written.is_ok() { "ok" } else { "error" });
+        written?;
+
+        let data = partial.finish()?;
+        self.store.put(digest, None, data).await
+    }
+}
+
+fn upstream_reference(upstream: &Upstream, image: &str, reference: &str) -> Result<Reference> {
+    let separator = if is_digest(reference) { '@' } else { ':' };
+    let full_reference = format!("{}/{}{}{}", upstream.host, image, separator, reference);
+    full_reference
+        .parse()
+        .with_context(|| format!("Invalid OCI reference: {}", full_reference))
+}
+
+fn record(kind: &'static str, result: &'static str) {
+    counter!("forge_registry_proxy_requests_total", "kind" => kind, "result" => result)
+        .increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+    use tempfile::TempDir;
+
+    fn digest_of(data: &[u8]) -> String {
+        format!("sha256:{}", compute_sha256(data))
+    }
+
+    #[test]
+    fn routes_split_upstream_image_and_reference() {
+        let digest = digest_of(b"layer");
+        assert_eq!(
+            parse_route(&format!(
+                "ghcr/forgepoint/extensions/labels/blobs/{}",
+                digest
+            )),
+            Some(ProxyRoute {
+                upstream: "ghcr".into(),
+                image: "forgepoint/extensions/labels".into(),
+                object: ProxyObject::Blob(digest.clone()),
+            })
+        );
+        assert_eq!(
+            parse_route("ghcr/forgepoint/labels/manifests/v1.0.0").map(|route| route.object),
+            Some(ProxyObject::Manifest("v1.0.0".into()))
+        );
+
+        assert_eq!(parse_route("ghcr/manifests/v1"), None);
+        assert_eq!(parse_route("ghcr/labels/blobs/v1"), None);
+        assert_eq!(parse_route("ghcr/labels/tags/list"), None);
+        assert_eq!(parse_route("ghcr/../labels/manifests/v1"), None);
+    }
+
+    #[tokio::test]
+    async fn the_store_checks_digests_and_keeps_to_its_quota() {
+        let pool = create_test_pool().await.unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let store = ProxyStore::new(temp_dir.path().to_path_buf(), pool.clone(), 10).unwrap();
+
+        assert!(
+            store
+                .put(&digest_of(b"other"), None, b"aaaa".to_vec())
+                .await
+                .is_err()
+        );
+        assert!(
+            store
+                .put(&digest_of(b"elevenbytes"), None, b"elevenbytes".to_vec())
+                .await
+                .is_err()
+        );
+
+        let (a, b, c) = (digest_of(b"aaaa"), digest_of(b"bbbb"), digest_of(b"cccc"));
+        store
+            .put(&a, Some("application/json"), b"aaaa".to_vec())
+            .await
+            .unwrap();
+        store.put(&b, None, b"bbbb".to_vec()).await.unwrap();
+        store.set_tag("ghcr", "labels", "v1", &b).await.unwrap();
+        // `a` was served more recently than `b`
+        sqlx::query(
+            "UPDATE registry_proxy_blobs SET last_used_at = CASE digest WHEN ? THEN 2 ELSE 1 END",
+        )
+        .bind(&a)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        store.put(&c, None, b"cccc".to_vec()).await.unwrap();
+        assert_eq!(store.get(&b).await.unwrap(), None);
+        assert_eq!(store.tag("ghcr", "labels", "v1").await.unwrap(), None);
+        let cached = store.get(&a).await.unwrap().unwrap();
+        assert_eq!(cached.data, b"aaaa");
+        assert_eq!(cached.media_type.as_deref(), Some("application/json"));
+        assert!(store.get(&c).await.unwrap().is_some());
+
+        // A file removed behind the store's back is a miss, not an error
+        std::fs::remove_file(store.path(&c)).unwrap();
+        assert_eq!(store.get(&c).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,845 @@
+//! Scheduled extension tasks.
+//!
+//! Extensions register named schedules through the `host-scheduler` WIT interface. Each
+//! schedule is a five-field cron expression, evaluated in UTC, plus an optional jitter:
+//! every run starts a random number of seconds, up to `jitter_secs`, after the time the
+//! expression names. Schedules are stored in `extension_schedules`, so they survive
+//! restarts, and the [`TaskScheduler`] worker calls the extension's `run-task` export
+//! when one is due.
+//!
+//! Runs of one extension share `extensions.settings.max_concurrent_tasks` slots. A due
+//! task that finds them taken waits for the next poll; one whose previous run has not
+//! finished skips that occurrence instead. Missed occurrences, such as while the server
+//! was down, are not caught up: the task runs once and then at its next time. Every run
+//! and skip is kept in `extension_task_runs`, the most recent [`RUNS_KEPT_PER_TASK`] of
+//! each task, and reported by the `extensionTaskRuns` admin query.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow, bail};
+use metrics::{counter, histogram};
+use rand::Rng;
+use sqlx::{Row, SqlitePool};
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
+
+use super::ExtensionManager;
+use crate::user::db::unix_now;
+
+/// How often the worker looks for due schedules
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+/// Schedules started per poll
+const BATCH_SIZE: i64 = 100;
+pub const MAX_SCHEDULES_PER_EXTENSION: i64 = 32;
+pub const MAX_JITTER_SECS: u32 = 24 * 60 * 60;
+pub const RUNS_KEPT_PER_TASK: i64 = 100;
+/// Error messages are cut to this length in the run history
+const MAX_ERROR_CHARS: usize = 2_000;
+
+/// A parsed cron expression: minute, hour, day of month, month and day of week.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// A `*` day field leaves the choice to the other one.
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl CronSchedule {
+    /// Parses `*`, single values, ranges (`1-5`), steps (`*/15`, `0-30/10`) and lists
+    /// of those. Day of week counts from Sunday as `0`; `7` is Sunday too. `@hourly`,
+    /// `@daily`, `@weekly` and `@monthly` stand for their usual expressions.
+    pub fn parse(expression: &str) -> Result<Self> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            bail!(
+                "`{}` is not a cron expression: expected 5 fields, found {}",
+                expression,
+                fields.len()
+            );
+        };
+
+        let mut days_of_week = parse_field(day_of_week, 0, 7, "day of week")?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59, "minute")?,
+            hours: parse_field(hour, 0, 23, "hour")?,
+            days_of_month: parse_field(day_of_month, 1, 31, "day of month")?,
+            months: parse_field(month, 1, 12, "month")?,
+            days_of_week,
+            any_day_of_month: day_of_month.starts_with('*'),
+            any_day_of_week: day_of_week.starts_with('*'),
+        })
+    }
+
+    /// The first matching minute after `after`, in Unix seconds. `None` when nothing
+    /// matches within five years, as for `0 0 30 2 *`.
+    pub fn next_after(&self, after: i64) -> Option<i64> {
+        let start = after.div_euclid(60) * 60 + 60;
+        let first_day = start.div_euclid(86_400);
+        let start_minute = start.rem_euclid(86_400) / 60;
+        for day in first_day..first_day + 5 * 366 {
+            let (_, month, day_of_month) = civil_from_days(day);
+            // 1970-01-01 was a Thursday
+            let weekday = (day + 4).rem_euclid(7);
+            if !bit(self.months, month) || !self.day_matches(day_of_month, weekday) {
+                continue;
+            }
+            let from = if day == first_day { start_minute } else { 0 };
+            for minute_of_day in from..24 * 60 {
+                if bit(self.hours, minute_of_day / 60) && bit(self.minutes, minute_of_day % 60) {
+                    return Some(day * 86_400 + minute_of_day * 60);
+                }
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, day_of_month: i64, weekday: i64) -> bool {
+        let by_month = bit(self.days_of_month, day_of_month);
+        let by_week = bit(self.days_of_week, weekday);
+        // As in cron: when both fields are restricted, either one is enough.
+        if self.any_day_of_month || self.any_day_of_week {
+            by_month && by_week
+        } else {
+            by_month || by_week
+        }
+    }
+}
+
+fn bit(set: u64, value: i64) -> bool {
+    set & (1 << value) != 0
+}
+
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64> {
+    let number = |text: &str| -> Result<u32> {
+        let value: u32 = text
+            .parse()
+            .map_err(|_| anyhow!("invalid {} `{}`", name, text))?;
+        if !(min..=max).contains(&value) {
+            bail!("{} `{}` is outside {}-{}", name, value, min, max);
+        }
+        Ok(value)
+    };
+
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| anyhow!("invalid {} step `{}`", name, step))?;
+                if step == 0 {
+                    bail!("{} step must be at least 1", name);
+                }
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+        let (low, high) = if range == "*" {
+            (min, max)
+        } else if let Some((low, high)) = range.split_once('-') {
+            (number(low)?, number(high)?)
+        } else {
+            let value = number(range)?;
+            // `5/15` means every 15 from 5.
+            (value, if step.is_some() { max } else { value })
+        };
+        if low > high {
+            bail!("{} range `{}` is backwards", name, range);
+        }
+        for value in (low..=high).step_by(step.unwrap_or(1) as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// Year, month and day of a day count since 1970-01-01 (Howard Hinnant's algorithm).
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// When a schedule runs next after `now`, jitter included.
+pub fn next_run_at(cron: &str, jitter_secs: u32, now: i64) -> Result<i64> {
+    let next = CronSchedule::parse(cron)?
+        .next_after(now)
+        .ok_or_else(|| anyhow!("`{}` never runs", cron))?;
+    let jitter = if jitter_secs > 0 {
+        rand::thread_rng().gen_range(0..=jitter_secs)
+    } else {
+        0
+    };
+    Ok(next + i64::from(jitter))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskSchedule {
+    pub extension: String,
+    pub name: String,
+    pub cron: String,
+    pub jitter_secs: u32,
+    pub next_run_at: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskRunStatus {
+    Running,
+    Succeeded,
+    Failed,
+    /// The previous run had not finished, so this occurrence did not run
+    Skipped,
+    /// The server stopped while the task ran
+    Interrupted,
+}
+
+impl TaskRunStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskRunStatus::Running => "RUNNING",
+            TaskRunStatus::Succeeded => "SUCCEEDED",
+            TaskRunStatus::Failed => "FAILED",
+            TaskRunStatus::Skipped => "SKIPPED",
+            TaskRunStatus::Interrupted => "INTERRUPTED",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "RUNNING" => Ok(TaskRunStatus::Running),
+            "SUCCEEDED" => Ok(TaskRunStatus::Succeeded),
+            "FAILED" => Ok(TaskRunStatus::Failed),
+            "SKIPPED" => Ok(TaskRunStatus::Skipped),
+            "INTERRUPTED" => Ok(TaskRunStatus::Interrupted),
+            other => Err(anyhow!("unknown task run status `{}`", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskRun {
+    pub id: String,
+    pub extension: String,
+    pub task: String,
+    /// When the schedule said to run, jitter included
+    pub scheduled_for: i64,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub status: TaskRunStatus,
+    pub error: Option<String>,
+    pub duration_ms: Option<i64>,
+}
+
+fn validate_task_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > 64 {
+        bail!("task names must be 1 to 64 characters long");
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        bail!(
+            "task name `{}` may only contain letters, digits, `-`, `_` and `.`",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Creates or replaces a schedule. Registering it again unchanged, as an extension does
+/// each time it starts, keeps the time it was going to run next.
+pub async fn register_schedule(
+    pool: &SqlitePool,
+    extension: &str,
+    name: &str,
+    cron: &str,
+    jitter_secs: u32,
+    now: i64,
+) -> Result<TaskSchedule> {
+    validate_task_name(name)?;
+    if jitter_secs > MAX_JITTER_SECS {
+        bail!("jitter can be at most {} seconds", MAX_JITTER_SECS);
+    }
+    let cron = cron.trim();
+    let existing = get_schedule(pool, extension, name).await?;
+    if let Some(existing) = existing {
+        if existing.cron == cron && existing.jitter_secs == jitter_secs {
+            return Ok(existing);
+        }
+    } else {
+        let count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM extension_schedules WHERE extension = ?")
+                .bind(extension)
+                .fetch_one(pool)
+                .await?;
+        if count >= MAX_SCHEDULES_PER_EXTENSION {
+            bail!(
+                "an extension can have at most {} schedules",
+                MAX_SCHEDULES_PER_EXTENSION
+            );
+        }
+    }
+
+    let next_run_at = next_run_at(cron, jitter_secs, now)?;
+    sqlx::query(
+        "INSERT INTO extension_schedules \
+         (extension, name, cron, jitter_secs, next_run_at, created_at, updated_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(extension, name) DO UPDATE SET cron = excluded.cron, \
+         jitter_secs = excluded.jitter_secs, next_run_at = excluded.next_run_at, \
+         updated_at = excluded.updated_at",
+    )
+    .bind(extension)
+    .bind(name)
+    .bind(cron)
+    .bind(i64::from(jitter_secs))
+    .bind(next_run_at)
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await?;
+    get_schedule(pool, extension, name)
+        .await?
+        .ok_or_else(|| anyhow!("schedule `{}` disappeared", name))
+}
+
+/// Returns whether there was a schedule to remove. Its run history is kept.
+pub async fn unregister_schedule(pool: &SqlitePool, extension: &str, name: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM extension_schedules WHERE extension = ? AND name = ?")
+        .bind(extension)
+        .bind(name)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+const SCHEDULE_COLUMNS: &str =
+    "extension, name, cron, jitter_secs, next_run_at, created_at, updated_at";
+
+fn schedule_from_row(row: &sqlx::sqlite::SqliteRow) -> Result<TaskSchedule> {
+    Ok(TaskSchedule {
+        extension: row.try_get("extension")?,
+        name: row.try_get("name")?,
+        cron: row.try_get("cron")?,
+        jitter_secs: u32::try_from(row.try_get::<i64, _>("jitter_secs")?)?,
+        next_run_at: row.try_get("next_run_at")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+async fn get_schedule(
+    pool: &SqlitePool,
+    extension: &str,
+    name: &str,
+) -> Result<Option<TaskSchedule>> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM extension_schedules WHERE extension = ? AND name = ?",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(extension)
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(schedule_from_row).transpose()
+}
+
+/// Schedules ordered by extension and name, optionally for one extension.
+pub async fn list_schedules(
+    pool: &SqlitePool,
+    extension: Option<&str>,
+) -> Result<Vec<TaskSchedule>> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM extension_schedules WHERE ? IS NULL OR extension = ? \
+         ORDER BY extension, name",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(extension)
+    .bind(extension)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(schedule_from_row).collect()
+}
+
+pub async fn due_schedules(pool: &SqlitePool, now: i64, limit: i64) -> Result<Vec<TaskSchedule>> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM extension_schedules WHERE next_run_at <= ? \
+         ORDER BY next_run_at LIMIT ?",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(now)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(schedule_from_row).collect()
+}
+
+/// Moves a schedule on to its first time after `now`.
+async fn advance_schedule(pool: &SqlitePool, schedule: &TaskSchedule, now: i64) -> Result<()> {
+    let next = next_run_at(&schedule.cron, schedule.jitter_secs, now)?;
+    sqlx::query("UPDATE extension_schedules SET next_run_at = ? WHERE extension = ? AND name = ?")
+        .bind(next)
+        .bind(&schedule.extension)
+        .bind(&schedule.name)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Records a run. Runs that are not `RUNNING` are recorded as finished.
+pub async fn record_run(
+    pool: &SqlitePool,
+    schedule: &TaskSchedule,
+    status: TaskRunStatus,
+    error: Option<&str>,
+    now: i64,
+) -> Result<TaskRun> {
+    let finished = status != TaskRunStatus::Running;
+    let run = TaskRun {
+        id: cuid2::create_id(),
+        extension: schedule.extension.clone(),
+        task: schedule.name.clone(),
+        scheduled_for: schedule.next_run_at,
+        started_at: now,
+        finished_at: finished.then_some(now),
+        status,
+        error: error.map(str::to_string),
+        duration_ms: finished.then_some(0),
+    };
+    sqlx::query(
+        "INSERT INTO extension_task_runs (id, extension, task, scheduled_for, started_at, \
+         finished_at, status, error, duration_ms) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&run.id)
+    .bind(&run.extension)
+    .bind(&run.task)
+    .bind(run.scheduled_for)
+    .bind(run.started_at)
+    .bind(run.finished_at)
+    .bind(run.status.as_str())
+    .bind(&run.error)
+    .bind(run.duration_ms)
+    .execute(pool)
+    .await?;
+    if finished {
+        prune_runs(pool, &run.extension, &run.task).await?;
+    }
+    Ok(run)
+}
+
+pub async fn finish_run(
+    pool: &SqlitePool,
+    run: &TaskRun,
+    status: TaskRunStatus,
+    error: Option<&str>,
+    duration_ms: i64,
+) -> Result<()> {
+    let error = error.map(|error| error.chars().take(MAX_ERROR_CHARS).collect::<String>());
+    sqlx::query(
+        "UPDATE extension_task_runs SET status = ?, error = ?, finished_at = ?, \
+         duration_ms = ? WHERE id = ?",
+    )
+    .bind(status.as_str())
+    .bind(error)
+    .bind(unix_now())
+    .bind(duration_ms)
+    .bind(&run.id)
+    .execute(pool)
+    .await?;
+    prune_runs(pool, &run.extension, &run.task).await
+}
+
+async fn prune_runs(pool: &SqlitePool, extension: &str, task: &str) -> Result<()> {
+    sqlx::query(
+        "DELETE FROM extension_task_runs WHERE extension = ? AND task = ? AND id NOT IN \
+         (SELECT id FROM extension_task_runs WHERE extension = ? AND task = ? \
+         ORDER BY started_at DESC, id DESC LIMIT ?)",
+    )
+    .bind(extension)
+    .bind(task)
+    .bind(extension)
+    .bind(task)
+    .bind(RUNS_KEPT_PER_TASK)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Marks runs a stopped server left `RUNNING` as interrupted.
+pub async fn interrupt_unfinished(pool: &SqlitePool, now: i64) -> Result<u64> {
+    let result =
+        sqlx::query("UPDATE extension_task_runs SET status = ?, finished_at = ? WHERE status = ?")
+            .bind(TaskRunStatus::Interrupted.as_str())
+            .bind(now)
+            .bind(TaskRunStatus::Running.as_str())
+            .execute(pool)
+            .await?;
+    Ok(result.rows_affected())
+}
+
+/// Runs newest first, optionally for one extension and task.
+pub async fn list_runs(
+    pool: &SqlitePool,
+    extension: Option<&str>,
+    task: Option<&str>,
+    limit: i64,
+) -> Result<Vec<TaskRun>> {
+    let rows = sqlx::query(
+        "SELECT id, extension, task, scheduled_for, started_at, finished_at, status, error, \
+         duration_ms FROM extension_task_runs \
+         WHERE (? IS NULL OR extension = ?) AND (? IS NULL OR task = ?) \
+         ORDER BY started_at DESC, id DESC LIMIT ?",
+    )
+    .bind(extension)
+    .bind(extension)
+    .bind(task)
+    .bind(task)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(TaskRun {
+                id: row.try_get("id")?,
+                extension: row.try_get("extension")?,
+                task: row.try_get("task")?,
+                scheduled_for: row.try_get("scheduled_for")?,
+                started_at: row.try_get("started_at")?,
+                finished_at: row.try_get("finished_at")?,
+                status: TaskRunStatus::parse(row.try_get("status")?)?,
+                error: row.try_get("error")?,
+                duration_ms: row.try_get("duration_ms")?,
+            })
+        })
+        .collect()
+}
+
+/// Calls the `run-task` export of extensions whose schedules are due.
+pub struct TaskScheduler {
+    pool: SqlitePool,
+    extensions: Arc<ExtensionManager>,
+    max_concurrent: usize,
+    slots: Mutex<HashMap<String, Arc<Semaphore>>>,
+    /// `(extension, task)` of the runs in progress
+    running: Arc<Mutex<HashSet<(String, String)>>>,
+}
+
+impl TaskScheduler {
+    pub fn new(pool: SqlitePool, extensions: Arc<ExtensionManager>, max_concurrent: usize) -> Self {
+        Self {
+            pool,
+            extensions,
+            max_concurrent: max_concurrent.max(1),
+            slots: Mutex::new(HashMap::new()),
+            running: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    pub async fn run(self, shutdown: CancellationToken) -> Result<()> {
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut recovered = false;
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = ticker.tick() => {
+                    // A read-only standby leaves tasks to the instance holding the lease.
+                    if crate::coordination::is_read_only() {
+                        continue;
+                    }
+                    if !recovered {
+                        match interrupt_unfinished(&self.pool, unix_now()).await {
+                            Ok(0) => {}
+                            Ok(count) => tracing::warn!("{} extension task runs were interrupted", count),
+                            Err(e) => {
+                                tracing::warn!("failed to close interrupted task runs: {:#}", e);
+                                continue;
+                            }
+                        }
+                        recovered = true;
+                    }
+                    if let Err(e) = self.start_due(unix_now()).await {
+                        tracing::warn!("extension task scheduling failed: {:#}", e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts the runs due at `now` and returns how many were started. They finish in
+    /// the background.
+    pub async fn start_due(&self, now: i64) -> Result<usize> {
+        let mut started = 0;
+        for schedule in due_schedules(&self.pool, now, BATCH_SIZE).await? {
+            let runtime = self
+                .extensions
+                .get_extensions()
+                .get(&schedule.extension)
+                .filter(|extension| extension.runtime.interfaces().tasks)
+                .map(|extension| extension.runtime.clone());
+            let Some(runtime) = runtime else {
+                // The schedule is kept for when the extension is loaded again.
+                advance_schedule(&self.pool, &schedule, now).await?;
+                continue;
+            };
+
+            let key = (schedule.extension.clone(), schedule.name.clone());
+            if self.lock_running().contains(&key) {
+                record_run(
+                    &self.pool,
+                    &schedule,
+                    TaskRunStatus::Skipped,
+                    Some("the previous run had not finished"),
+                    now,
+                )
+                .await?;
+                advance_schedule(&self.pool, &schedule, now).await?;
+                counter!("forge_extension_task_runs_total", "extension" => schedule.extension.clone(), "outcome" => "skipped")
+                    .increment(1);
+                continue;
+            }
+            // Stays due until a run of the same extension frees a slot.
+            let Ok(slot) = self.slot(&schedule.extension).try_acquire_owned() else {
+                continue;
+            };
+
+            advance_schedule(&self.pool, &schedule, now).await?;
+            let run = record_run(&self.pool, &schedule, TaskRunStatus::Running, None, now).await?;
+            self.lock_running().insert(key.clone());
+            started += 1;
+
+            let pool = self.pool.clone();
+            let running = self.running.clone();
+            tokio::spawn(async move {
+                let _slot = slot;
+                let begun = Instant::now();
+                let result = runtime.run_task(run.task.clone()).await;
+                let duration_ms = begun.elapsed().as_millis() as i64;
+                let (status, error, outcome) = match &result {
+                    Ok(()) => (TaskRunStatus::Succeeded, None, "ok"),
+                    Err(e) => (TaskRunStatus::Failed, Some(format!("{:#}", e)), "error"),
+                };
+                if let Some(error) = &error {
+                    tracing::warn!(
+                        extension = %run.extension,
+                        task = %run.task,
+                        "extension task failed: {}",
+                        error
+                    );
+                }
+                counter!("forge_extension_task_runs_total", "extension" => run.extension.clone(), "outcome" => outcome)
+                    .increment(1);
+                histogram!("forge_extension_task_duration_ms", "extension" => run.extension.clone())
+                    .record(duration_ms as f64);
+                if let Err(e) = finish_run(&pool, &run, status, error.as_deref(), duration_ms).await
+                {
+                    tracing::warn!("failed to record extension task run: {:#}", e);
+                }
+                if let Ok(mut running) = running.lock() {
+                    running.remove(&key);
+                }
+            });
+        }
+        Ok(started)
+    }
+
+    fn slot(&self, extension: &str) -> Arc<Semaphore> {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots
+            .entry(extension.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_concurrent)))
+            .clone()
+    }
+
+    fn lock_running(&self) -> std::sync::MutexGuard<'_, HashSet<(String, String)>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    /// 2024-03-15 10:07:30 UTC, a Friday
+    const NOW: i64 = 1_710_497_250;
+
+    fn next(expression: &str, after: i64) -> i64 {
+        CronSchedule::parse(expression)
+            .unwrap()
+            .next_after(after)
+            .unwrap()
+    }
+
+    #[test]
+    fn next_after_follows_each_field() {
+        // 10:08, the next whole minute
+        assert_eq!(next("* * * * *", NOW), 1_710_497_280);
+        // 10:15
+        assert_eq!(next("*/15 * * * *", NOW), 1_710_497_700);
+        // 11:00
+        assert_eq!(next("@hourly", NOW), 1_710_500_400);
+        // Saturday 2024-03-16 00:00
+        assert_eq!(next("@daily", NOW), 1_710_547_200);
+        // Sunday 2024-03-17 00:00; `7` is Sunday too
+        assert_eq!(next("@weekly", NOW), 1_710_633_600);
+        assert_eq!(next("0 0 * * 7", NOW), 1_710_633_600);
+        // Monday 2024-03-18 09:30
+        assert_eq!(next("30 9 * * 1-5", NOW), 1_710_754_200);
+        // 2024-04-01 00:00
+        assert_eq!(next("@monthly", NOW), 1_711_929_600);
+        // 2028-02-29 12:00, the next leap day
+        assert_eq!(next("0 12 29 2 *", NOW), 1_835_438_400);
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either_day() {
+        // The 20th, or any Sunday: Sunday 2024-03-17 comes first.
+        assert_eq!(next("0 0 20 * 0", NOW), 1_710_633_600);
+        // With `*` for day of week only the 20th counts.
+        assert_eq!(next("0 0 20 * *", NOW), 1_710_892_800);
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        for expression in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+            "@often",
+        ] {
+            assert!(
+                CronSchedule::parse(expression).is_err(),
+                "`{}` was accepted",
+                expression
+            );
+        }
+        assert_eq!(
+            CronSchedule::parse("0 0 30 2 *").unwrap().next_after(NOW),
+            None
+        );
+        assert_eq!(
+            CronSchedule::parse("0,30 8-18/2 * 1,6 *").unwrap(),
+            CronSchedule::parse("0,30 8,10,12,14,16,18 * 1,6 *").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn registering_again_keeps_the_next_run() {
+        let pool = create_test_pool().await.unwrap();
+        let first = register_schedule(&pool, "issues", "sync", "*/5 * * * *", 60, NOW)
+            .await
+            .unwrap();
+        assert!((1_710_497_400..=1_710_497_460).contains(&first.next_run_at));
+
+        let again = register_schedule(&pool, "issues", "sync", "*/5 * * * *", 60, NOW + 100)
+            .await
+            .unwrap();
+        assert_eq!(again, first);
+
+        let changed = register_schedule(&pool, "issues", "sync", "@hourly", 0, NOW)
+            .await
+            .unwrap();
+        assert_eq!(changed.next_run_at, 1_710_500_400);
+        assert_eq!(changed.created_at, first.created_at);
+
+        assert!(
+            register_schedule(&pool, "issues", "bad name", "@hourly", 0, NOW)
+                .await
+                .is_err()
+        );
+        assert!(
+            register_schedule(
+                &pool,
+                "issues",
+                "other",
+                "@hourly",
+                MAX_JITTER_SECS + 1,
+                NOW
+            )
+            .await
+            .is_err()
+        );
+
+        assert_eq!(due_schedules(&pool, NOW, 10).await.unwrap(), vec![]);
+        assert_eq!(
+            due_schedules(&pool, 1_710_500_400, 10).await.unwrap(),
+            vec![changed]
+        );
+        assert!(unregister_schedule(&pool, "issues", "sync").await.unwrap());
+        assert!(!unregister_schedule(&pool, "issues", "sync").await.unwrap());
+        assert!(list_schedules(&pool, None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn run_history_is_pruned_and_unfinished_runs_are_interrupted() {
+        let pool = create_test_pool().await.unwrap();
+        let schedule = register_schedule(&pool, "issues", "sync", "@hourly", 0, NOW)
+            .await
+            .unwrap();
+
+        for offset in 0..RUNS_KEPT_PER_TASK + 5 {
+            record_run(
+                &pool,
+                &schedule,
+                TaskRunStatus::Skipped,
+                Some("the previous run had not finished"),
+                NOW + offset,
+            )
+            .await
+            .unwrap();
+        }
+        let runs = list_runs(&pool, Some("issues"), Some("sync"), 1_000)
+            .await
+            .unwrap();
+        assert_eq!(runs.len() as i64, RUNS_KEPT_PER_TASK);
+        assert_eq!(runs[0].started_at, NOW + RUNS_KEPT_PER_TASK + 4);
+
+        let run = record_run(&pool, &schedule, TaskRunStatus::Running, None, NOW + 1_000)
+            .await
+            .unwrap();
+        assert_eq!(run.finished_at, None);
+        assert_eq!(interrupt_unfinished(&pool, NOW + 2_000).await.unwrap(), 1);
+        let latest = &list_runs(&pool, None, None, 1).await.unwrap()[0];
+        assert_eq!(latest.id, run.id);
+        assert_eq!(latest.status, TaskRunStatus::Interrupted);
+        assert_eq!(latest.scheduled_for, schedule.next_run_at);
+        assert!(
+            list_runs(&pool, Some("other"), None, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,394 @@
+//! Version upgrades of extension databases.
+//!
+//! The version an extension reports from `get-info` is recorded in the core database
+//! each time it initialises its own database. When a later start reports a different
+//! version, the host copies the extension database to `<extension dir>/backups`, runs
+//! `init`, then calls the extension's `upgrade(from-version)` export. If either fails,
+//! the copy is put back and the extension is not loaded. The previous version can then
+//! be deployed again and finds its data as it left it. Every attempt is logged in
+//! `extension_upgrades` and reported by the `extensionUpgrades` admin query. An attempt
+//! still `RUNNING` at the next start was interrupted by a crash, so it is rolled back
+//! before the extension loads.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use sqlx::{Row, SqlitePool};
+
+use crate::user::db::unix_now;
+
+/// Older backups of an extension are deleted after a successful upgrade.
+pub const KEEP_BACKUPS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpgradeStatus {
+    Running,
+    Succeeded,
+    /// The upgrade failed and the backup was restored
+    RolledBack,
+    /// The upgrade failed and so did the restore; the backup has to be put back by hand
+    Failed,
+}
+
+impl UpgradeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpgradeStatus::Running => "RUNNING",
+            UpgradeStatus::Succeeded => "SUCCEEDED",
+            UpgradeStatus::RolledBack => "ROLLED_BACK",
+            UpgradeStatus::Failed => "FAILED",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "RUNNING" => Ok(UpgradeStatus::Running),
+            "SUCCEEDED" => Ok(UpgradeStatus::Succeeded),
+            "ROLLED_BACK" => Ok(UpgradeStatus::RolledBack),
+            "FAILED" => Ok(UpgradeStatus::Failed),
+            other => Err(anyhow!("unknown upgrade status `{}`", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpgradeRecord {
+    pub id: String,
+    pub extension: String,
+    pub from_version: String,
+    pub to_version: String,
+    pub status: UpgradeStatus,
+    pub backup_path: String,
+    pub error: Option<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+pub async fn installed_version(pool: &SqlitePool, extension: &str) -> Result<Option<String>> {
+    Ok(
+        sqlx::query_scalar("SELECT version FROM extension_installs WHERE name = ?")
+            .bind(extension)
+            .fetch_optional(pool)
+            .await?,
+    )
+}
+
+pub async fn record_installed(pool: &SqlitePool, extension: &str, version: &str) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO extension_installs (name, version, installed_at) VALUES (?, ?, ?) \
+         ON CONFLICT(name) DO UPDATE SET version = excluded.version, \
+         installed_at = excluded.installed_at \
+         WHERE extension_installs.version != excluded.version",
+    )
+    .bind(extension)
+    .bind(version)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn start_upgrade(
+    pool: &SqlitePool,
+    extension: &str,
+    from_version: &str,
+    to_version: &str,
+    backup_path: &Path,
+) -> Result<UpgradeRecord> {
+    let record = UpgradeRecord {
+        id: cuid2::create_id(),
+        extension: extension.to_string(),
+        from_version: from_version.to_string(),
+        to_version: to_version.to_string(),
+        status: UpgradeStatus::Running,
+        backup_path: backup_path.display().to_string(),
+        error: None,
+        started_at: unix_now(),
+        finished_at: None,
+    };
+    sqlx::query(
+        "INSERT INTO extension_upgrades \
+         (id, extension, from_version, to_version, status, backup_path, started_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&record.id)
+    .bind(&record.extension)
+    .bind(&record.from_version)
+    .bind(&record.to_version)
+    .bind(record.status.as_str())
+    .bind(&record.backup_path)
+    .bind(record.started_at)
+    .execute(pool)
+    .await?;
+    Ok(record)
+}
+
+pub async fn finish_upgrade(
+    pool: &SqlitePool,
+    id: &str,
+    status: UpgradeStatus,
+    error: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE extension_upgrades SET status = ?, error = ?, finished_at = ? WHERE id = ?",
+    )
+    .bind(status.as_str())
+    .bind(error)
+    .bind(unix_now())
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Upgrade attempts, newest first, optionally for one extension.
+pub async fn list_upgrades(
+    pool: &SqlitePool,
+    extension: Option<&str>,
+    limit: i64,
+) -> Result<Vec<UpgradeRecord>> {
+    let rows = sqlx::query(
+        "SELECT id, extension, from_version, to_version, status, backup_path, error, \
+         started_at, finished_at FROM extension_upgrades \
+         WHERE ? IS NULL OR extension = ? ORDER BY started_at DESC, id DESC LIMIT ?",
+    )
+    .bind(extension)
+    .bind(extension)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(UpgradeRecord {
+                id: row.try_get("id")?,
+                extension: row.try_get("extension")?,
+                from_version: row.try_get("from_version")?,
+                to_version: row.try_get("to_version")?,
+                status: UpgradeStatus::parse(row.try_get("status")?)?,
+                backup_path: row.try_get("backup_path")?,
+                error: row.try_get("error")?,
+                started_at: row.try_get("started_at")?,
+                finished_at: row.try_get("finished_at")?,
+            })
+        })
+        .collect()
+}
+
+/// Rolls back an upgrade of `extension` that a crash left `RUNNING`. Call it before the
+/// extension database is opened.
+pub async fn recover_interrupted(pool: &SqlitePool, extension: &str, db_path: &Path) -> Result<()> {
+    let interrupted = list_upgrades(pool, Some(extension), 1)
+        .await?
+        .into_iter()
+        .find(|upgrade| upgrade.status == UpgradeStatus::Running);
+    let Some(upgrade) = interrupted else {
+        return Ok(());
+    };
+    tracing::warn!(
+        "Upgrade of extension {} from {} to {} was interrupted; restoring {}",
+        extension,
+        upgrade.from_version,
+        upgrade.to_version,
+        upgrade.backup_path
+    );
+    match restore_files(Path::new(&upgrade.backup_path), db_path).await {
+        Ok(()) => {
+            finish_upgrade(
+                pool,
+                &upgrade.id,
+                UpgradeStatus::RolledBack,
+                Some("interrupted before it finished"),
+            )
+            .await
+        }
+        Err(err) => {
+            let message = format!(
+                "interrupted, and the backup could not be restored: {:#}",
+                err
+            );
+            finish_upgrade(pool, &upgrade.id, UpgradeStatus::Failed, Some(&message)).await?;
+            Err(anyhow!(message))
+        }
+    }
+}
+
+/// Copies the extension database to `<dir>/backups` and returns the copy's path.
+pub async fn backup_database(
+    pool: &SqlitePool,
+    db_path: &Path,
+    extension: &str,
+    from_version: &str,
+) -> Result<PathBuf> {
+    // Nothing else writes while the extension loads, so after a checkpoint the main file
+    // holds the whole database.
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(pool)
+        .await
+        .context("failed to checkpoint the extension database")?;
+
+    let dir = backups_dir(db_path)?;
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("failed to create {}", dir.display()))?;
+    let version: String = from_version
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let backup = dir.join(format!("{}-{}-{}.db", extension, version, unix_now()));
+    tokio::fs::copy(db_path, &backup)
+        .await
+        .with_context(|| format!("failed to back up {}", db_path.display()))?;
+    Ok(backup)
+}
+
+/// Puts a backup back in place. Closes `pool`, which can't be used afterwards.
+pub async fn restore_database(pool: &SqlitePool, backup: &Path, db_path: &Path) -> Result<()> {
+    pool.close().await;
+    restore_files(backup, db_path).await
+}
+
+async fn restore_files(backup: &Path, db_path: &Path) -> Result<()> {
+    for suffix in ["-wal", "-shm", "-journal"] {
+        let mut side = db_path.as_os_str().to_owned();
+        side.push(suffix);
+        match tokio::fs::remove_file(&side).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to remove {:?}", side));
+            }
+        }
+    }
+    tokio::fs::copy(backup, db_path)
+        .await
+        .with_context(|| format!("failed to restore {}", backup.display()))?;
+    Ok(())
+}
+
+/// Deletes all but the newest [`KEEP_BACKUPS`] backups of `extension`.
+pub fn prune_backups(db_path: &Path, extension: &str) -> Result<()> {
+    let dir = backups_dir(db_path)?;
+    let prefix = format!("{}-", extension);
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(&prefix) && name.ends_with(".db") {
+            backups.push((entry.metadata()?.modified()?, entry.path()));
+        }
+    }
+    backups.sort();
+    let excess = backups.len().saturating_sub(KEEP_BACKUPS);
+    for (_, path) in backups.into_iter().take(excess) {
+        std::fs::remove_file(&path)
+            .with_context(|| format!("failed to remove {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn backups_dir(db_path: &Path) -> Result<PathBuf> {
+    Ok(db_path
+        .parent()
+        .ok_or_else(|| anyhow!("{} has no parent directory", db_path.display()))?
+        .join("backups"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+    async fn open(path: &Path) -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(
+                SqliteConnectOptions::new()
+                    .filename(path)
+                    .create_if_missing(true),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_failed_upgrade_restores_the_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("issues.db");
+        let pool = open(&db_path).await;
+        sqlx::query("CREATE TABLE issues (title TEXT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO issues VALUES ('kept')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let backup = backup_database(&pool, &db_path, "issues", "0.2.0")
+            .await
+            .unwrap();
+        assert!(backup.starts_with(dir.path().join("backups")));
+
+        // A half-done migration...
+        sqlx::query("DROP TABLE issues")
+            .execute(&pool)
+            .await
+            .unwrap();
+        restore_database(&pool, &backup, &db_path).await.unwrap();
+
+        let pool = open(&db_path).await;
+        let title: String = sqlx::query_scalar("SELECT title FROM issues")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(title, "kept");
+    }
+
+    #[tokio::test]
+    async fn interrupted_upgrades_are_rolled_back() {
+        let core = create_test_pool().await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("issues.db");
+        std::fs::write(&db_path, b"half migrated").unwrap();
+        let backup = dir.path().join("backup.db");
+        std::fs::write(&backup, b"before").unwrap();
+
+        assert_eq!(installed_version(&core, "issues").await.unwrap(), None);
+        record_installed(&core, "issues", "0.2.0").await.unwrap();
+        let upgrade = start_upgrade(&core, "issues", "0.2.0", "0.3.0", &backup)
+            .await
+            .unwrap();
+
+        recover_interrupted(&core, "issues", &db_path)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&db_path).unwrap(), b"before");
+        let upgrades = list_upgrades(&core, Some("issues"), 10).await.unwrap();
+        assert_eq!(upgrades[0].id, upgrade.id);
+        assert_eq!(upgrades[0].status, UpgradeStatus::RolledBack);
+        assert_eq!(
+            installed_version(&core, "issues").await.unwrap().as_deref(),
+            Some("0.2.0")
+        );
+        assert!(
+            list_upgrades(&core, Some("other"), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // Nothing left to recover.
+        std::fs::write(&db_path, b"upgraded").unwrap();
+        recover_interrupted(&core, "issues", &db_path)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&db_path).unwrap(), b"upgraded");
+    }
+}
@@ -4,13 +4,59 @@
 //! extension runtime, making it easier to use from the rest of the codebase.
 
 use anyhow::{Context, Result};
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use super::loader::ExtensionLimits;
+use super::metrics::{CallSample, record_batch, record_call};
+use super::upgrade;
 use super::wit_bindings::{
-    ComponentExtension, ExtensionConfig, ExtensionInfo, RequestContext, ResolveInfo, ResolveResult,
+    CallUsage, ComponentExtension, ExtensionConfig, ExtensionInfo, ExtensionInterfaces,
+    RequestContext, ResolveInfo, ResolveResult, WebhookRequest, WebhookResponse,
 };
+use crate::moderation::{Content, Verdict};
+use crate::repository::lifecycle::LifecycleEvent;
+
+/// An error an extension returned as `{"message": ..., "extensions": {...}}`. The
+/// `extensions` object is passed through to the GraphQL error so clients can act on it
+/// (for example a `CONFLICT` code with the latest state of the record).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtensionFieldError {
+    pub message: String,
+    pub extensions: serde_json::Value,
+}
+
+impl ExtensionFieldError {
+    /// Parses an extension's error string; plain messages return `None`.
+    pub fn parse(raw: &str) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_str(raw).ok()?;
+        let message = value.get("message")?.as_str()?.to_string();
+        let extensions = value.get("extensions").filter(|v| v.is_object())?.clone();
+        Some(Self {
+            message,
+            extensions,
+        })
+    }
+}
+
+impl std::fmt::Display for ExtensionFieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Extension error: {}", self.message)
+    }
+}
+
+impl std::error::Error for ExtensionFieldError {}
+
+/// One field of a batch passed to [`Extension::resolve_fields`]
+#[derive(Debug, Clone)]
+pub struct FieldRequest {
+    pub field_name: String,
+    pub parent_type: String,
+    pub arguments: serde_json::Value,
+    pub parent: Option<serde_json::Value>,
+}
 
 /// High-level extension wrapper with runtime management
 /// Uses Mutex to ensure Store<ExtensionState> is Send+Sync safe
@@ -21,6 +67,8 @@ pub struct Extension {
     schema: String,
     #[allow(dead_code)]
     info: ExtensionInfo,
+    interfaces: ExtensionInterfaces,
+    ui_manifest: Option<serde_json::Value>,
 }
 
 impl Extension {
@@ -29,7 +77,7 @@ impl Extension {
         wasm_path: &Path,
         extension_dir: &Path,
         name: String,
-        _limits: &ExtensionLimits,
+        limits: &ExtensionLimits,
     ) -> Result<Self> {
         // Ensure extension directory exists
         std::fs::create_dir_all(extension_dir).context("Failed to create extension directory")?;
@@ -43,14 +91,26 @@ impl Extension {
         let db_path = extension_dir_abs.join(format!("{}.db", name));
         tracing::debug!("Extension database path: {}", db_path.display());
 
+        // Upgrades are tracked in the core database; without one (tests, tools) every
+        // load is treated as a fresh start.
+        let core = crate::db::shared();
+        if let Some(core) = core {
+            upgrade::recover_interrupted(core, &name, &db_path)
+                .await
+                .with_context(|| format!("Failed to recover extension database: {}", name))?;
+        }
+        let fresh_database = !db_path.exists();
+
         // Initialize database connection BEFORE loading WASM component
         use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
         use std::str::FromStr;
 
-        let connect_options =
-            SqliteConnectOptions::from_str(&format!("sqlite://{}", db_path.display()))?
-                .create_if_missing(true)
-                .foreign_keys(true);
+        let connect_options = crate::encryption::keyed(SqliteConnectOptions::from_str(&format!(
+            "sqlite://{}",
+            db_path.display()
+        ))?)
+        .create_if_missing(true)
+        .foreign_keys(true);
 
         let pool = SqlitePoolOptions::new()
             .max_connections(5)
@@ -63,54 +123,162 @@ impl Extension {
         let extension_dir_buf = extension_dir_abs.to_path_buf();
         let name_clone = name.clone();
         let db_path_str = db_path.to_string_lossy().to_string();
+        let max_fuel = limits.max_fuel;
+        let component_pool = pool.clone();
 
-        let (component, info, schema) = tokio::task::spawn_blocking(move || {
+        let (mut component, info, interfaces) = tokio::task::spawn_blocking(move || {
             // Load the component extension and pass the pre-initialized pool
             let mut component = ComponentExtension::load(
                 &wasm_path_buf,
                 &extension_dir_buf,
-                name_clone.clone(),
-                pool,
+                name_clone,
+                component_pool,
+                max_fuel,
             )
             .context("Failed to load WASM component")?;
 
-            // Initialize the extension
-            let config = ExtensionConfig {
-                name: name_clone,
-                version: "0.1.0".to_string(),
-                database_path: db_path_str,
-                custom_config: None,
-            };
+            // The version decides whether `init` is followed by an upgrade
+            let info = component
+                .get_info()
+                .context("Failed to get extension info")?;
 
+            let interfaces = component.interfaces();
+
+            Ok::<_, anyhow::Error>((component, info, interfaces))
+        })
+        .await
+        .context("Blocking task panicked")??;
+
+        let installed = match core {
+            Some(core) => upgrade::installed_version(core, &name).await?,
+            None => None,
+        };
+        let upgrade_from = installed.filter(|version| !fresh_database && *version != info.version);
+        let attempt = match (core, &upgrade_from) {
+            (Some(core), Some(from)) => {
+                let backup = upgrade::backup_database(&pool, &db_path, &name, from)
+                    .await
+                    .with_context(|| format!("Failed to back up extension database: {}", name))?;
+                tracing::info!(
+                    "Upgrading extension {} from {} to {} (backup: {})",
+                    name,
+                    from,
+                    info.version,
+                    backup.display()
+                );
+                Some(upgrade::start_upgrade(core, &name, from, &info.version, &backup).await?)
+            }
+            _ => None,
+        };
+
+        let config = ExtensionConfig {
+            name: name.clone(),
+            version: "0.1.0".to_string(),
+            database_path: db_path_str,
+            custom_config: None,
+        };
+        let initialized = tokio::task::spawn_blocking(move || {
+            // Initialize the extension
             component
                 .init(config)
                 .context("Failed to initialize extension")?;
 
-            // Get extension info and schema
-            let info = component
-                .get_info()
-                .context("Failed to get extension info")?;
+            if let Some(from) = &upgrade_from {
+                component.upgrade(from)?;
+            }
 
             let schema = component
                 .get_schema()
                 .context("Failed to get extension schema")?;
+            let ui_manifest = component
+                .get_ui_manifest()
+                .context("Failed to get extension UI manifest")?;
 
-            Ok::<_, anyhow::Error>((component, info, schema))
+            Ok::<_, anyhow::Error>((component, schema, ui_manifest))
         })
         .await
-        .context("Blocking task panicked")??;
+        .context("Blocking task panicked")?;
+
+        let (component, schema, ui_manifest) = match (initialized, core, attempt) {
+            (Ok(loaded), Some(core), Some(attempt)) => {
+                upgrade::finish_upgrade(core, &attempt.id, upgrade::UpgradeStatus::Succeeded, None)
+                    .await?;
+                upgrade::record_installed(core, &name, &info.version).await?;
+                if let Err(e) = upgrade::prune_backups(&db_path, &name) {
+                    tracing::warn!("Failed to prune backups of extension {}: {:#}", name, e);
+                }
+                loaded
+            }
+            (Ok(loaded), core, _) => {
+                if let Some(core) = core {
+                    upgrade::record_installed(core, &name, &info.version).await?;
+                }
+                loaded
+            }
+            (Err(err), Some(core), Some(attempt)) => {
+                let message = format!("{:#}", err);
+                let backup = Path::new(&attempt.backup_path);
+                match upgrade::restore_database(&pool, backup, &db_path).await {
+                    Ok(()) => {
+                        tracing::error!(
+                            "Upgrade of extension {} to {} failed and was rolled back: {}",
+                            name,
+                            info.version,
+                            message
+                        );
+                        upgrade::finish_upgrade(
+                            core,
+                            &attempt.id,
+                            upgrade::UpgradeStatus::RolledBack,
+                            Some(&message),
+                        )
+                        .await?;
+                    }
+                    Err(restore_err) => {
+                        tracing::error!(
+                            "Upgrade of extension {} failed ({}), and restoring {} failed too: {:#}",
+                            name,
+                            message,
+                            backup.display(),
+                            restore_err
+                        );
+                        upgrade::finish_upgrade(
+                            core,
+                            &attempt.id,
+                            upgrade::UpgradeStatus::Failed,
+                            Some(&format!("{}; restore failed: {:#}", message, restore_err)),
+                        )
+                        .await?;
+                    }
+                }
+                return Err(err);
+            }
+            (Err(err), _, _) => return Err(err),
+        };
 
         tracing::info!(
-            "Loaded extension '{}' v{} with schema ({} bytes)",
+            "Loaded extension '{}' v{} with schema ({} bytes), exporting [{}]",
             info.name,
             info.version,
-            schema.len()
+            schema.len(),
+            interfaces.names().join(", ")
         );
 
+        // A broken manifest hides the extension's pages but leaves its API working
+        let ui_manifest = ui_manifest.and_then(|raw| match parse_ui_manifest(&raw) {
+            Ok(manifest) => Some(manifest),
+            Err(e) => {
+                tracing::warn!("Ignoring UI manifest of extension {}: {:#}", name, e);
+                None
+            }
+        });
+
         Ok(Self {
             component: Arc::new(Mutex::new(component)),
             schema,
             info,
+            interfaces,
+            ui_manifest,
         })
     }
 
@@ -146,13 +314,22 @@ impl Extension {
         &self.info.capabilities
     }
 
-    /// Get the extension's GraphQL schema
+    /// Optional interfaces the extension exports
+    pub fn interfaces(&self) -> ExtensionInterfaces {
+        self.interfaces
+    }
+
+    /// Get the extension's GraphQL schema; empty without a resolver
     pub fn schema(&self) -> &str {
         &self.schema
     }
 
+    /// The JSON object from the extension's `ui-manifest` export
+    pub fn ui_manifest(&self) -> Option<&serde_json::Value> {
+        self.ui_manifest.as_ref()
+    }
+
     /// Resolve a GraphQL field
-    #[allow(dead_code)]
     pub async fn resolve_field(
         &self,
         field_name: String,
@@ -161,7 +338,6 @@ impl Extension {
         context: RequestContext,
         parent: Option<serde_json::Value>,
     ) -> Result<serde_json::Value> {
-        // Create resolve info
         let resolve_info = ResolveInfo {
             field_name,
             parent_type,
@@ -169,9 +345,68 @@ impl Extension {
             context,
             parent,
         };
+        field_result(self.resolve_one(resolve_info).await?)
+    }
+
+    /// Resolve fields that share one request context, such as the same field for every
+    /// object in a list. Identical fields are resolved once. Extensions that export
+    /// `batch-resolver` get a single call; others get one `resolve-field` call per
+    /// distinct field. The outer error means the extension failed as a whole; each field
+    /// has its own result otherwise, in the order of `fields`.
+    pub async fn resolve_fields(
+        &self,
+        fields: Vec<FieldRequest>,
+        context: RequestContext,
+    ) -> Result<Vec<Result<serde_json::Value>>> {
+        let requested = fields.len();
+        let mut distinct: Vec<FieldRequest> = Vec::new();
+        let mut slots = Vec::with_capacity(requested);
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for field in fields {
+            let key = serde_json::to_string(&(
+                &field.field_name,
+                &field.parent_type,
+                &field.arguments,
+                &field.parent,
+            ))?;
+            let slot = *seen.entry(key).or_insert_with(|| {
+                distinct.push(field);
+                distinct.len() - 1
+            });
+            slots.push(slot);
+        }
+        let infos = distinct.into_iter().map(|field| ResolveInfo {
+            field_name: field.field_name,
+            parent_type: field.parent_type,
+            arguments: field.arguments,
+            context: context.clone(),
+            parent: field.parent,
+        });
+
+        let results = if self.interfaces.batch_resolver {
+            self.resolve_batch(infos.collect()).await?
+        } else {
+            let mut results = Vec::with_capacity(slots.len());
+            for info in infos {
+                results.push(self.resolve_one(info).await?);
+            }
+            results
+        };
+        record_batch(&self.info.name, requested, results.len());
+
+        Ok(slots
+            .into_iter()
+            .map(|slot| field_result(results[slot].clone()))
+            .collect())
+    }
+
+    /// One `resolve-field` call, recorded in the extension metrics
+    async fn resolve_one(&self, resolve_info: ResolveInfo) -> Result<ResolveResult> {
+        let metric_field = resolve_info.field_name.clone();
 
         // Call the component in a blocking task (Mutex ensures thread safety)
         let component = self.component.clone();
+        let started = Instant::now();
         let result = tokio::task::spawn_blocking(move || {
             let mut comp = component
                 .lock()
@@ -180,12 +415,115 @@ impl Extension {
                 .context("Failed to resolve field in extension")
         })
         .await
-        .context("Blocking task panicked")??;
+        .context("Blocking task panicked")?;
+        self.record(&metric_field, started, result)
+    }
 
-        match result {
-            ResolveResult::Success(value) => Ok(value),
-            ResolveResult::Error(err) => Err(anyhow::anyhow!("Extension error: {}", err)),
-        }
+    /// One `resolve-fields` call, recorded under the first entry's field name
+    async fn resolve_batch(&self, batch: Vec<ResolveInfo>) -> Result<Vec<ResolveResult>> {
+        let Some(first) = batch.first() else {
+            return Ok(Vec::new());
+        };
+        let metric_field = first.field_name.clone();
+
+        let component = self.component.clone();
+        let started = Instant::now();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut comp = component
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock component: {}", e))?;
+            comp.resolve_fields(batch)
+                .context("Failed to resolve fields in extension")
+        })
+        .await
+        .context("Blocking task panicked")?;
+        self.record(&metric_field, started, result)
+    }
+
+    fn record<T: ResolveOutcome>(
+        &self,
+        field: &str,
+        started: Instant,
+        result: Result<(T, CallUsage)>,
+    ) -> Result<T> {
+        // Traps (including running out of fuel) count as errors with unknown usage
+        let (result, usage) = match result {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                record_call(&CallSample {
+                    extension: &self.info.name,
+                    field,
+                    latency: started.elapsed(),
+                    ok: false,
+                    fuel_consumed: None,
+                    request_bytes: 0,
+                    response_bytes: 0,
+                });
+                return Err(err);
+            }
+        };
+        record_call(&CallSample {
+            extension: &self.info.name,
+            field,
+            latency: started.elapsed(),
+            ok: result.is_ok(),
+            fuel_consumed: usage.fuel_consumed,
+            request_bytes: usage.request_bytes,
+            response_bytes: usage.response_bytes,
+        });
+        Ok(result)
+    }
+
+    /// Deliver a repository lifecycle event
+    pub async fn handle_lifecycle_event(&self, event: LifecycleEvent) -> Result<()> {
+        let component = self.component.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut comp = component
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock component: {}", e))?;
+            comp.handle_lifecycle_event(event)
+        })
+        .await
+        .context("Blocking task panicked")?
+    }
+
+    /// Hand a request to `/extensions/<name>/hooks/...` to the extension
+    pub async fn handle_webhook(&self, request: WebhookRequest) -> Result<WebhookResponse> {
+        let component = self.component.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut comp = component
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock component: {}", e))?;
+            comp.handle_webhook(request)
+        })
+        .await
+        .context("Blocking task panicked")?
+    }
+
+    /// Ask the extension's abuse check about content before it is created
+    pub async fn check_content(&self, content: Content) -> Result<Verdict> {
+        let component = self.component.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut comp = component
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock component: {}", e))?;
+            comp.check_content(content)
+        })
+        .await
+        .context("Blocking task panicked")?
+    }
+
+    /// Run a task the extension registered through `host-scheduler`
+    pub async fn run_task(&self, name: String) -> Result<()> {
+        let component = self.component.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut comp = component
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock component: {}", e))?;
+            comp.run_task(&name)
+        })
+        .await
+        .context("Blocking task panicked")?
     }
 
     /// Shutdown the extension
@@ -199,8 +537,74 @@ impl Extension {
     }
 }
 
+/// What a resolver call returned, for the metrics
+trait ResolveOutcome {
+    fn is_ok(&self) -> bool;
+}
+
+impl ResolveOutcome for ResolveResult {
+    fn is_ok(&self) -> bool {
+        matches!(self, ResolveResult::Success(_))
+    }
+}
+
+/// A batch counts as failed when any of its fields failed
+impl ResolveOutcome for Vec<ResolveResult> {
+    fn is_ok(&self) -> bool {
+        self.iter().all(ResolveOutcome::is_ok)
+    }
+}
+
+/// Turns an extension's answer into the field's value or error.
+fn field_result(result: ResolveResult) -> Result<serde_json::Value> {
+    match result {
+        ResolveResult::Success(value) => Ok(value),
+        ResolveResult::Error(err) => match ExtensionFieldError::parse(&err) {
+            Some(structured) => Err(structured.into()),
+            None => Err(anyhow::anyhow!("Extension error: {}", err)),
+        },
+    }
+}
+
+/// Checks that a UI manifest is a JSON object.
+fn parse_ui_manifest(raw: &str) -> Result<serde_json::Value> {
+    let manifest: serde_json::Value =
+        serde_json::from_str(raw).context("UI manifest is not valid JSON")?;
+    if !manifest.is_object() {
+        anyhow::bail!("UI manifest must be a JSON object");
+    }
+    Ok(manifest)
+}
+
 #[cfg(test)]
 mod tests {
+    use super::{ExtensionFieldError, parse_ui_manifest};
+
+    #[test]
+    fn structured_errors_need_a_message_and_extensions() {
+        let parsed = ExtensionFieldError::parse(
+            r#"{"message":"stale","extensions":{"code":"CONFLICT","currentVersion":3}}"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.message, "stale");
+        assert_eq!(parsed.extensions["currentVersion"], 3);
+
+        assert!(ExtensionFieldError::parse("Database error: locked").is_none());
+        assert!(ExtensionFieldError::parse(r#"{"message":"no extensions"}"#).is_none());
+        assert!(ExtensionFieldError::parse(r#"{"message":"x","extensions":"CONFLICT"}"#).is_none());
+    }
+
+    #[test]
+    fn ui_manifests_are_objects() {
+        let manifest =
+            parse_ui_manifest(r#"{"repositoryTabs":[{"label":"Issues","path":"issues"}]}"#)
+                .unwrap();
+        assert_eq!(manifest["repositoryTabs"][0]["path"], "issues");
+
+        assert!(parse_ui_manifest(r#"["issues"]"#).is_err());
+        assert!(parse_ui_manifest("repositoryTabs: []").is_err());
+    }
+
     #[tokio::test]
     async fn test_extension_lifecycle() {
         // This test requires a real WASM file, which we'll need to build
@@ -0,0 +1,125 @@
+//! Reloads extensions whose module changes on disk.
+//!
+//! With `extensions.settings.watch_interval_secs` set, the module found for each
+//! extension at startup is looked at that often. A module that changed is reloaded
+//! through [`LiveRouter::reload_extension`] once it looks the same at two checks in a
+//! row, so a file that is still being copied is not loaded half-written. A deleted module
+//! unloads its extension, and one that comes back loads it again. A failed reload is
+//! logged and not retried until the module changes again; the previous extension keeps
+//! serving meanwhile.
+//!
+//! Only modification times and sizes are compared, so replacing a module with one of
+//! the same size within the filesystem's timestamp resolution goes unnoticed; use
+//! `reloadExtension` then.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use tokio_util::sync::CancellationToken;
+
+use crate::router::LiveRouter;
+
+/// How a module looked at a check: its modification time and size, or `None` when it
+/// was missing
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Module {
+    stamp: Stamp,
+    /// Whether the module changed and has not been reloaded since
+    changed: bool,
+}
+
+impl Module {
+    fn new(stamp: Stamp) -> Self {
+        Self {
+            stamp,
+            changed: false,
+        }
+    }
+
+    /// Records a check and tells whether the module should be reloaded now.
+    fn observe(&mut self, now: Stamp) -> bool {
+        if now != self.stamp {
+            self.stamp = now;
+            self.changed = true;
+            return false;
+        }
+        std::mem::take(&mut self.changed)
+    }
+}
+
+pub struct ExtensionWatcher {
+    router: Arc<LiveRouter>,
+    interval: Duration,
+}
+
+impl ExtensionWatcher {
+    pub fn new(router: Arc<LiveRouter>, interval: Duration) -> Self {
+        Self { router, interval }
+    }
+
+    pub async fn run(self, shutdown: CancellationToken) -> Result<()> {
+        let mut modules: HashMap<String, Module> = self
+            .router
+            .extensions()
+            .sources()
+            .into_iter()
+            .map(|(name, path)| (name, Module::new(stamp(&path))))
+            .collect();
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // The first tick completes at once; the stamps were just taken.
+        ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = ticker.tick() => {}
+            }
+            for (name, path) in self.router.extensions().sources() {
+                let now = stamp(&path);
+                let module = modules.entry(name.clone()).or_insert(Module::new(now));
+                if !module.observe(now) {
+                    continue;
+                }
+                tracing::info!("{} changed, reloading extension {}", path.display(), name);
+                if let Err(e) = self.router.reload_extension(&name).await {
+                    tracing::error!("failed to reload extension {}: {:#}", name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_modules_reload_once_they_settle() {
+        let at = |secs, len| Some((SystemTime::UNIX_EPOCH + Duration::from_secs(secs), len));
+        let mut module = Module::new(at(10, 100));
+        assert!(!module.observe(at(10, 100)));
+
+        // Still being written: the size moves between checks
+        assert!(!module.observe(at(20, 40)));
+        assert!(!module.observe(at(21, 100)));
+        assert!(module.observe(at(21, 100)));
+        assert!(!module.observe(at(21, 100)));
+
+        // Deleted, then put back
+        assert!(!module.observe(None));
+        assert!(module.observe(None));
+        assert!(!module.observe(at(30, 100)));
+        assert!(module.observe(at(30, 100)));
+    }
+}
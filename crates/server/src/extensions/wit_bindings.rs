@@ -4,6 +4,17 @@
 //! defined in packages/wit/extension.wit. It uses wit-bindgen to generate bindings
 //! and implements the host functions that extensions can import.
 
+use crate::api::uploads::lookup_upload;
+use crate::compliance::{Deletion, archive_deletion};
+use crate::events::{self, Event};
+use crate::extensions::graphql_access;
+use crate::extensions::scheduler;
+use crate::feature_flags;
+use crate::markdown::{self, parse_document, render_html};
+use crate::moderation::{Content, ContentKind, Verdict, VerdictKind};
+use crate::repository::lifecycle::{LifecycleEvent, LifecycleKind};
+use crate::repository::secrets::{mask_secrets, read_secret_for_extension, server_key};
+use crate::repository::visibility::visible_repository_ids;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use sqlx::{Row, SqlitePool};
@@ -11,39 +22,51 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use wasmtime::component::*;
 use wasmtime::{Config, Engine, Store};
-use wasmtime_wasi::p2::{add_to_linker_sync, IoView, WasiCtx, WasiCtxBuilder, WasiView};
+use wasmtime_wasi::p2::{IoView, WasiCtx, WasiCtxBuilder, WasiView, add_to_linker_sync};
 
 // Generate the host-side WIT bindings
 // Note: Using sync (not async) to avoid Send+Sync issues with WASI types in Store
+//
+// The `host` world exports every interface the host can use. Components are not
+// instantiated as that world: each export is looked up on its own, so a component only
+// has to provide `extension-api` (see `ComponentExtension::load`).
 wasmtime::component::bindgen!({
-    world: "extension",
+    world: "host",
     path: "../../packages/wit",
     async: false,
 });
 
-// Import generated types from the bindgen macro
-// The bindgen macro generates:
-// - `Extension` struct representing the instantiated world
-// - Host trait implementations for imported interfaces
-// - Types for exported interfaces
-
-// For exports (extension-api), we need the Extension struct and its associated types
-// The bindgen generates these under forge::extension module
-pub use self::Extension as WasmExtension;
+use self::exports::forge::extension::{
+    abuse_check, batch_resolver, extension_api, git_events, resolver, tasks, ui_manifest, webhooks,
+};
 
 // Import types from generated guest interface modules
-use self::exports::forge::extension::extension_api::{
-    Config as ExtConfig, ContextScope as ExtContextScope, GlobalContext as ExtGlobalContext,
-    RepositoryContext as ExtRepositoryContext, RequestContext as ExtRequestContext,
-    ResolveInfo as ExtResolveInfo, ResolveResult as ExtResolveResult,
-    UserContext as ExtUserContext,
+use self::exports::forge::extension::abuse_check::{
+    Content as ExtContent, ContentKind as ExtContentKind, VerdictKind as ExtVerdictKind,
+};
+use self::exports::forge::extension::extension_api::Config as ExtConfig;
+use self::exports::forge::extension::git_events::{
+    LifecycleEvent as ExtLifecycleEvent, LifecycleKind as ExtLifecycleKind,
 };
+use self::exports::forge::extension::resolver::{
+    ContextScope as ExtContextScope, GlobalContext as ExtGlobalContext,
+    GroupContext as ExtGroupContext, RepositoryContext as ExtRepositoryContext,
+    RequestContext as ExtRequestContext, ResolveInfo as ExtResolveInfo,
+    ResolveResult as ExtResolveResult, UserContext as ExtUserContext,
+};
+use self::exports::forge::extension::webhooks::WebhookRequest as ExtWebhookRequest;
 
 // For imports (host-*), we implement the Host traits
 use self::forge::extension::host_database::{
     ExecInfo, ExecResult, QueryResult, QueryRow, RecordValue as WitRecordValue,
 };
 use self::forge::extension::host_log::LogLevel;
+use self::forge::extension::host_markdown::{
+    CodeBlock as WitCodeBlock, Document as WitDocument, IssueRef as WitIssueRef, Link as WitLink,
+    Mention as WitMention, Span as WitSpan,
+};
+use self::forge::extension::host_scheduler::Schedule as WitSchedule;
+use self::forge::extension::host_uploads::UploadInfo;
 
 /// Result of a GraphQL field resolution
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -61,6 +84,8 @@ pub enum ContextScope {
     Repository,
     User,
     RepositoryUser,
+    Group,
+    GroupUser,
 }
 
 impl Default for ContextScope {
@@ -80,6 +105,13 @@ pub struct RepositoryContext {
     pub remote_url: Option<String>,
 }
 
+/// Group metadata exposed to extensions when a resolver operates on a group.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct GroupContext {
+    pub id: String,
+    pub path: String,
+}
+
 /// User metadata provided to extensions when a resolver executes on behalf of a user.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct UserContext {
@@ -103,6 +135,8 @@ pub struct GlobalContext {
 pub struct RequestContext {
     pub scope: ContextScope,
     pub repository: Option<RepositoryContext>,
+    #[serde(default)]
+    pub group: Option<GroupContext>,
     pub user: Option<UserContext>,
     pub global: Option<GlobalContext>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -114,6 +148,7 @@ impl Default for RequestContext {
         Self {
             scope: ContextScope::Global,
             repository: None,
+            group: None,
             user: None,
             global: None,
             extra: None,
@@ -149,12 +184,68 @@ pub struct ExtensionInfo {
     pub capabilities: Vec<String>,
 }
 
+/// Optional interfaces a component exports, found when it is instantiated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExtensionInterfaces {
+    pub resolver: bool,
+    pub batch_resolver: bool,
+    pub git_events: bool,
+    pub webhooks: bool,
+    pub ui_manifest: bool,
+    pub abuse_check: bool,
+    pub tasks: bool,
+}
+
+impl ExtensionInterfaces {
+    /// WIT names of the exported interfaces
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.resolver, "resolver"),
+            (self.batch_resolver, "batch-resolver"),
+            (self.git_events, "git-events"),
+            (self.webhooks, "webhooks"),
+            (self.ui_manifest, "ui-manifest"),
+            (self.abuse_check, "abuse-check"),
+            (self.tasks, "tasks"),
+        ]
+        .into_iter()
+        .filter_map(|(exported, name)| exported.then_some(name))
+        .collect()
+    }
+}
+
+/// An HTTP request to `/extensions/<name>/hooks/...`
+#[derive(Debug, Clone)]
+pub struct WebhookRequest {
+    pub method: String,
+    /// Path below `/extensions/<name>/hooks`, starting with `/`
+    pub path: String,
+    pub query: Option<String>,
+    /// Lower-case names
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// An extension's answer to a [`WebhookRequest`]
+#[derive(Debug, Clone)]
+pub struct WebhookResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
 /// Host state that provides functions to WASM extensions
 pub struct ExtensionHost {
     pub name: String,
     pub db_pool: Arc<std::sync::Mutex<Option<SqlitePool>>>,
     #[allow(dead_code)]
     pub extension_dir: PathBuf,
+    /// DID of the user the current resolver call runs for, if signed in
+    pub actor_did: Option<String>,
+    /// Repository the current resolver call is scoped to
+    pub repository_id: Option<String>,
+    /// Secret values handed to this instance, masked in its log output
+    pub revealed_secrets: Vec<String>,
 }
 
 impl ExtensionHost {
@@ -163,6 +254,9 @@ impl ExtensionHost {
             name,
             db_pool: Arc::new(std::sync::Mutex::new(None)),
             extension_dir,
+            actor_did: None,
+            repository_id: None,
+            revealed_secrets: Vec::new(),
         }
     }
 
@@ -173,6 +267,15 @@ impl ExtensionHost {
         Ok(())
     }
 
+    /// The current call, as `host-graphql` tokens are bound to it
+    fn caller(&self) -> graphql_access::Caller<'_> {
+        graphql_access::Caller {
+            extension: &self.name,
+            actor_did: self.actor_did.as_deref(),
+            repository_id: self.repository_id.as_deref(),
+        }
+    }
+
     /// Get database pool
     fn get_pool(&self) -> Result<SqlitePool> {
         let pool_guard = self
@@ -220,6 +323,7 @@ impl WasiView for ExtensionState {
 // Implement the host-log interface
 impl self::forge::extension::host_log::Host for ExtensionState {
     fn log(&mut self, level: LogLevel, message: String) {
+        let message = mask_secrets(&message, &self.host.revealed_secrets);
         match level {
             LogLevel::Trace => tracing::trace!("[{}] {}", self.host.name, message),
             LogLevel::Debug => tracing::debug!("[{}] {}", self.host.name, message),
@@ -230,6 +334,302 @@ impl self::forge::extension::host_log::Host for ExtensionState {
     }
 }
 
+/// Largest chunk a single `host-uploads.read` call returns.
+const MAX_UPLOAD_READ: u32 = 1024 * 1024;
+
+// Implement the host-uploads interface
+impl self::forge::extension::host_uploads::Host for ExtensionState {
+    fn stat(&mut self, id: String) -> Result<UploadInfo, String> {
+        let file = lookup_upload(&id).ok_or_else(|| format!("unknown upload `{}`", id))?;
+        Ok(UploadInfo {
+            filename: file.filename,
+            content_type: file.content_type,
+            size: file.size,
+        })
+    }
+
+    fn read(&mut self, id: String, offset: u64, length: u32) -> Result<Vec<u8>, String> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let file = lookup_upload(&id).ok_or_else(|| format!("unknown upload `{}`", id))?;
+        let mut handle = std::fs::File::open(&file.path).map_err(|e| e.to_string())?;
+        handle
+            .seek(SeekFrom::Start(offset))
+            .map_err(|e| e.to_string())?;
+        let mut buffer = Vec::new();
+        handle
+            .take(u64::from(length.min(MAX_UPLOAD_READ)))
+            .read_to_end(&mut buffer)
+            .map_err(|e| e.to_string())?;
+        Ok(buffer)
+    }
+}
+
+// Implement the host-compliance interface
+impl self::forge::extension::host_compliance::Host for ExtensionState {
+    fn archive_deletion(
+        &mut self,
+        subject_kind: String,
+        subject_id: String,
+        summary: String,
+    ) -> Result<(), String> {
+        let pool =
+            crate::db::shared().ok_or_else(|| "compliance archive is not available".to_string())?;
+        let summary: serde_json::Value =
+            serde_json::from_str(&summary).map_err(|e| format!("summary must be JSON: {}", e))?;
+        let handle = tokio::runtime::Handle::try_current()
+            .map_err(|_| "No tokio runtime available".to_string())?;
+        handle
+            .block_on(archive_deletion(
+                pool,
+                &crate::config::current().compliance,
+                Deletion {
+                    subject_kind: &subject_kind,
+                    subject_id: &subject_id,
+                    source: &self.host.name,
+                    actor_did: self.host.actor_did.as_deref(),
+                    summary,
+                },
+            ))
+            .map(|_| ())
+            .map_err(|e| format!("failed to archive deletion: {}", e))
+    }
+}
+
+// Implement the host-markdown interface
+impl self::forge::extension::host_markdown::Host for ExtensionState {
+    fn parse(&mut self, source: String) -> WitDocument {
+        let document = parse_document(&source);
+        let span = |span: markdown::Span| WitSpan {
+            start: span.start as u32,
+            end: span.end as u32,
+        };
+        WitDocument {
+            mentions: document
+                .mentions
+                .into_iter()
+                .map(|mention| WitMention {
+                    handle: mention.handle,
+                    span: span(mention.span),
+                })
+                .collect(),
+            issue_refs: document
+                .issue_refs
+                .into_iter()
+                .map(|issue_ref| WitIssueRef {
+                    repository: issue_ref.repository,
+                    number: issue_ref.number,
+                    span: span(issue_ref.span),
+                })
+                .collect(),
+            links: document
+                .links
+                .into_iter()
+                .map(|link| WitLink {
+                    url: link.url,
+                    title: link.title,
+                    text: link.text,
+                    span: span(link.span),
+                })
+                .collect(),
+            code_blocks: document
+                .code_blocks
+                .into_iter()
+                .map(|block| WitCodeBlock {
+                    language: block.language,
+                    code: block.code,
+                    span: span(block.span),
+                })
+                .collect(),
+        }
+    }
+
+    fn render_html(&mut self, source: String) -> String {
+        render_html(&source)
+    }
+}
+
+// Implement the host-repositories interface
+impl self::forge::extension::host_repositories::Host for ExtensionState {
+    fn visible_repository_ids(&mut self, group_id: Option<String>) -> Result<Vec<String>, String> {
+        let pool =
+            crate::db::shared().ok_or_else(|| "repository index is not available".to_string())?;
+        let handle = tokio::runtime::Handle::try_current()
+            .map_err(|_| "No tokio runtime available".to_string())?;
+        handle
+            .block_on(visible_repository_ids(
+                pool,
+                self.host.actor_did.as_deref(),
+                group_id.as_deref(),
+            ))
+            .map_err(|e| format!("failed to list repositories: {}", e))
+    }
+}
+
+// Implement the host-events interface
+impl self::forge::extension::host_events::Host for ExtensionState {
+    fn publish(
+        &mut self,
+        kind: String,
+        repository_id: Option<String>,
+        data: String,
+    ) -> Result<(), String> {
+        if !events::is_valid_kind(&kind) {
+            return Err(format!(
+                "invalid event kind `{}`: use lowercase dot-separated words",
+                kind
+            ));
+        }
+        let data: serde_json::Value =
+            serde_json::from_str(&data).map_err(|e| format!("data must be JSON: {}", e))?;
+        let pool =
+            crate::db::shared().ok_or_else(|| "event export is not available".to_string())?;
+        let handle = tokio::runtime::Handle::try_current()
+            .map_err(|_| "No tokio runtime available".to_string())?;
+        handle
+            .block_on(events::publish(
+                pool,
+                crate::config::current().event_sink.as_ref(),
+                Event {
+                    kind: &kind,
+                    source: &self.host.name,
+                    actor_did: self.host.actor_did.as_deref(),
+                    repository_id: repository_id.as_deref(),
+                    data,
+                },
+            ))
+            .map_err(|e| format!("failed to queue event: {}", e))
+    }
+}
+
+// Implement the host-secrets interface
+impl self::forge::extension::host_secrets::Host for ExtensionState {
+    fn get(&mut self, name: String) -> Result<Option<String>, String> {
+        let repository_id = self
+            .host
+            .repository_id
+            .clone()
+            .ok_or_else(|| "secrets are only available in a repository scope".to_string())?;
+        let pool = crate::db::shared().ok_or_else(|| "secrets are not available".to_string())?;
+        let key = server_key().map_err(|e| format!("secrets are not available: {}", e))?;
+        let handle = tokio::runtime::Handle::try_current()
+            .map_err(|_| "No tokio runtime available".to_string())?;
+        let value = handle
+            .block_on(read_secret_for_extension(
+                pool,
+                key,
+                &repository_id,
+                &name,
+                &self.host.name,
+                self.host.actor_did.as_deref(),
+            ))
+            .map_err(|e| format!("failed to read secret: {}", e))?;
+        if let Some(value) = &value
+            && !self.host.revealed_secrets.contains(value)
+        {
+            self.host.revealed_secrets.push(value.clone());
+        }
+        Ok(value)
+    }
+}
+
+// Implement the host-flags interface
+impl self::forge::extension::host_flags::Host for ExtensionState {
+    fn enabled(&mut self, key: String) -> Result<bool, String> {
+        let pool =
+            crate::db::shared().ok_or_else(|| "feature flags are not available".to_string())?;
+        let handle = tokio::runtime::Handle::try_current()
+            .map_err(|_| "No tokio runtime available".to_string())?;
+        handle
+            .block_on(feature_flags::is_enabled(
+                pool,
+                &key,
+                self.host.actor_did.as_deref(),
+                self.host.repository_id.as_deref(),
+            ))
+            .map_err(|e| format!("failed to read feature flag: {}", e))
+    }
+}
+
+// Implement the host-scheduler interface
+impl self::forge::extension::host_scheduler::Host for ExtensionState {
+    fn register(&mut self, name: String, cron: String, jitter_secs: u32) -> Result<(), String> {
+        let pool = crate::db::shared().ok_or_else(|| "scheduling is not available".to_string())?;
+        let handle = tokio::runtime::Handle::try_current()
+            .map_err(|_| "No tokio runtime available".to_string())?;
+        handle
+            .block_on(scheduler::register_schedule(
+                pool,
+                &self.host.name,
+                &name,
+                &cron,
+                jitter_secs,
+                crate::user::db::unix_now(),
+            ))
+            .map(|_| ())
+            .map_err(|e| format!("failed to register task `{}`: {}", name, e))
+    }
+
+    fn unregister(&mut self, name: String) -> Result<(), String> {
+        let pool = crate::db::shared().ok_or_else(|| "scheduling is not available".to_string())?;
+        let handle = tokio::runtime::Handle::try_current()
+            .map_err(|_| "No tokio runtime available".to_string())?;
+        handle
+            .block_on(scheduler::unregister_schedule(pool, &self.host.name, &name))
+            .map(|_| ())
+            .map_err(|e| format!("failed to unregister task `{}`: {}", name, e))
+    }
+
+    fn schedules(&mut self) -> Result<Vec<WitSchedule>, String> {
+        let pool = crate::db::shared().ok_or_else(|| "scheduling is not available".to_string())?;
+        let handle = tokio::runtime::Handle::try_current()
+            .map_err(|_| "No tokio runtime available".to_string())?;
+        let schedules = handle
+            .block_on(scheduler::list_schedules(pool, Some(&self.host.name)))
+            .map_err(|e| format!("failed to list schedules: {}", e))?;
+        Ok(schedules
+            .into_iter()
+            .map(|schedule| WitSchedule {
+                name: schedule.name,
+                cron: schedule.cron,
+                jitter_secs: schedule.jitter_secs,
+                next_run_at: schedule.next_run_at,
+            })
+            .collect())
+    }
+}
+
+// Implement the host-graphql interface
+impl self::forge::extension::host_graphql::Host for ExtensionState {
+    fn request_token(&mut self, scopes: Vec<String>) -> Result<String, String> {
+        graphql_access::request_token(&self.host.caller(), &scopes).map_err(|e| e.to_string())
+    }
+
+    fn query(
+        &mut self,
+        token: String,
+        query: String,
+        variables: Option<String>,
+    ) -> Result<String, String> {
+        let router =
+            crate::router::shared().ok_or_else(|| "GraphQL is not available".to_string())?;
+        let pool = crate::db::shared().ok_or_else(|| "GraphQL is not available".to_string())?;
+        let handle = tokio::runtime::Handle::try_current()
+            .map_err(|_| "No tokio runtime available".to_string())?;
+        let response = handle
+            .block_on(graphql_access::run_query(
+                &router,
+                pool,
+                &self.host.caller(),
+                &token,
+                &query,
+                variables.as_deref(),
+            ))
+            .map_err(|e| e.to_string())?;
+        String::from_utf8(response.into_bytes().into()).map_err(|e| e.to_string())
+    }
+}
+
 // Convert between serde_json and WIT RecordValue
 // NOTE: These helpers are reserved for future use when we need bidirectional
 // conversion between JSON and WIT values for complex extension data types.
@@ -275,11 +675,25 @@ fn wit_value_to_json(value: &WitRecordValue) -> serde_json::Value {
     }
 }
 
+fn to_wit_resolve_info(info: ResolveInfo) -> Result<ExtResolveInfo> {
+    Ok(ExtResolveInfo {
+        field_name: info.field_name,
+        parent_type: info.parent_type,
+        arguments: serde_json::to_string(&info.arguments)?,
+        context: to_wit_request_context(&info.context)?,
+        parent: info.parent.map(|p| serde_json::to_string(&p)).transpose()?,
+    })
+}
+
 fn to_wit_request_context(context: &RequestContext) -> Result<ExtRequestContext> {
     let repository = context
         .repository
         .as_ref()
         .map(|repo| to_wit_repository_context(repo));
+    let group = context.group.as_ref().map(|group| ExtGroupContext {
+        id: group.id.clone(),
+        path: group.path.clone(),
+    });
     let user = context.user.as_ref().map(|user| to_wit_user_context(user));
     let global = context
         .global
@@ -293,6 +707,7 @@ fn to_wit_request_context(context: &RequestContext) -> Result<ExtRequestContext>
     Ok(ExtRequestContext {
         scope: to_wit_context_scope(context.scope),
         repository,
+        group,
         user,
         global,
         extra_json,
@@ -305,6 +720,8 @@ fn to_wit_context_scope(scope: ContextScope) -> ExtContextScope {
         ContextScope::Repository => ExtContextScope::Repository,
         ContextScope::User => ExtContextScope::User,
         ContextScope::RepositoryUser => ExtContextScope::RepositoryUser,
+        ContextScope::Group => ExtContextScope::Group,
+        ContextScope::GroupUser => ExtContextScope::GroupUser,
     }
 }
 
@@ -471,7 +888,23 @@ impl self::forge::extension::host_database::Host for ExtensionState {
 /// Component-based extension instance
 pub struct ComponentExtension {
     store: Store<ExtensionState>,
-    bindings: WasmExtension,
+    api: extension_api::Guest,
+    resolver: Option<resolver::Guest>,
+    batch_resolver: Option<batch_resolver::Guest>,
+    git_events: Option<git_events::Guest>,
+    webhooks: Option<webhooks::Guest>,
+    ui_manifest: Option<ui_manifest::Guest>,
+    abuse_check: Option<abuse_check::Guest>,
+    tasks: Option<tasks::Guest>,
+    max_fuel: Option<u64>,
+}
+
+/// Resource usage of one `resolve-field` or `resolve-fields` call.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CallUsage {
+    pub fuel_consumed: Option<u64>,
+    pub request_bytes: usize,
+    pub response_bytes: usize,
 }
 
 impl ComponentExtension {
@@ -481,11 +914,13 @@ impl ComponentExtension {
         extension_dir: &Path,
         name: String,
         db_pool: SqlitePool,
+        max_fuel: Option<u64>,
     ) -> Result<Self> {
         // Create engine with component model support
         let mut config = Config::new();
         config.wasm_component_model(true);
         config.async_support(false); // Using sync bindings
+        config.consume_fuel(max_fuel.is_some());
 
         let engine = Engine::new(&config)?;
 
@@ -508,6 +943,9 @@ impl ComponentExtension {
 
         // Create store
         let mut store = Store::new(&engine, state);
+        if let Some(fuel) = max_fuel {
+            store.set_fuel(fuel)?;
+        }
 
         // Load component
         let component_bytes = std::fs::read(wasm_path)?;
@@ -518,12 +956,68 @@ impl ComponentExtension {
         add_to_linker_sync(&mut linker)?;
 
         // Add host interfaces using generated bindings
-        WasmExtension::add_to_linker(&mut linker, |state: &mut ExtensionState| state)?;
+        Host::add_to_linker(&mut linker, |state: &mut ExtensionState| state)?;
+
+        // Only `extension-api` is required; an optional interface the component does not
+        // export (or exports with other types) is left out.
+        let instance_pre = linker.instantiate_pre(&component)?;
+        let api = extension_api::GuestIndices::new(&instance_pre).map_err(|e| {
+            anyhow::anyhow!(
+                "component does not export forge:extension/extension-api@0.3.0 \
+                 (components built for 0.2.0 must be rebuilt): {}",
+                e
+            )
+        })?;
+        let resolver = resolver::GuestIndices::new(&instance_pre).ok();
+        let batch_resolver = batch_resolver::GuestIndices::new(&instance_pre).ok();
+        let git_events = git_events::GuestIndices::new(&instance_pre).ok();
+        let webhooks = webhooks::GuestIndices::new(&instance_pre).ok();
+        let ui_manifest = ui_manifest::GuestIndices::new(&instance_pre).ok();
+        let abuse_check = abuse_check::GuestIndices::new(&instance_pre).ok();
+        let tasks = tasks::GuestIndices::new(&instance_pre).ok();
 
         // Instantiate
-        let bindings = WasmExtension::instantiate(&mut store, &component, &linker)?;
+        let instance = instance_pre.instantiate(&mut store)?;
+
+        Ok(Self {
+            api: api.load(&mut store, &instance)?,
+            resolver: resolver
+                .map(|indices| indices.load(&mut store, &instance))
+                .transpose()?,
+            batch_resolver: batch_resolver
+                .map(|indices| indices.load(&mut store, &instance))
+                .transpose()?,
+            git_events: git_events
+                .map(|indices| indices.load(&mut store, &instance))
+                .transpose()?,
+            webhooks: webhooks
+                .map(|indices| indices.load(&mut store, &instance))
+                .transpose()?,
+            ui_manifest: ui_manifest
+                .map(|indices| indices.load(&mut store, &instance))
+                .transpose()?,
+            abuse_check: abuse_check
+                .map(|indices| indices.load(&mut store, &instance))
+                .transpose()?,
+            tasks: tasks
+                .map(|indices| indices.load(&mut store, &instance))
+                .transpose()?,
+            store,
+            max_fuel,
+        })
+    }
 
-        Ok(Self { store, bindings })
+    /// Optional interfaces this component exports
+    pub fn interfaces(&self) -> ExtensionInterfaces {
+        ExtensionInterfaces {
+            resolver: self.resolver.is_some(),
+            batch_resolver: self.batch_resolver.is_some(),
+            git_events: self.git_events.is_some(),
+            webhooks: self.webhooks.is_some(),
+            ui_manifest: self.ui_manifest.is_some(),
+            abuse_check: self.abuse_check.is_some(),
+            tasks: self.tasks.is_some(),
+        }
     }
 
     /// Initialize the extension
@@ -543,20 +1037,33 @@ impl ComponentExtension {
         };
 
         // Call the extension's init function
-        self.bindings
-            .forge_extension_extension_api()
+        self.api
             .call_init(&mut self.store, &wit_config)?
             .map_err(|e| anyhow::anyhow!("Extension init failed: {}", e))?;
 
         Ok(())
     }
 
+    /// Migrate data written by `from_version`
+    pub fn upgrade(&mut self, from_version: &str) -> Result<()> {
+        let host = &mut self.store.data_mut().host;
+        host.actor_did = None;
+        host.repository_id = None;
+
+        if let Some(fuel) = self.max_fuel {
+            self.store.set_fuel(fuel)?;
+        }
+        self.api
+            .call_upgrade(&mut self.store, from_version)?
+            .map_err(|e| {
+                let e = mask_secrets(&e, &self.store.data().host.revealed_secrets);
+                anyhow::anyhow!("Extension upgrade failed: {}", e)
+            })
+    }
+
     /// Get extension info
     pub fn get_info(&mut self) -> Result<ExtensionInfo> {
-        let info = self
-            .bindings
-            .forge_extension_extension_api()
-            .call_get_info(&mut self.store)?;
+        let info = self.api.call_get_info(&mut self.store)?;
 
         Ok(ExtensionInfo {
             name: info.name,
@@ -565,55 +1072,259 @@ impl ComponentExtension {
         })
     }
 
-    /// Get GraphQL schema
+    /// Get GraphQL schema; empty for a component without a resolver
     pub fn get_schema(&mut self) -> Result<String> {
-        let schema = self
-            .bindings
-            .forge_extension_extension_api()
-            .call_get_schema(&mut self.store)?;
+        let Some(resolver) = &self.resolver else {
+            return Ok(String::new());
+        };
+        let schema = resolver.call_get_schema(&mut self.store)?;
 
         Ok(schema)
     }
 
-    /// Resolve a GraphQL field
-    #[allow(dead_code)]
-    pub fn resolve_field(&mut self, info: ResolveInfo) -> Result<ResolveResult> {
-        let ResolveInfo {
-            field_name,
-            parent_type,
-            arguments,
-            context,
-            parent,
-        } = info;
-
-        let wit_info = ExtResolveInfo {
-            field_name,
-            parent_type,
-            arguments: serde_json::to_string(&arguments)?,
-            context: to_wit_request_context(&context)?,
-            parent: parent.map(|p| serde_json::to_string(&p)).transpose()?,
+    /// The component's UI manifest, if it exports one
+    pub fn get_ui_manifest(&mut self) -> Result<Option<String>> {
+        let Some(ui_manifest) = &self.ui_manifest else {
+            return Ok(None);
         };
+        Ok(Some(ui_manifest.call_get_ui_manifest(&mut self.store)?))
+    }
 
-        let result = self
-            .bindings
-            .forge_extension_extension_api()
-            .call_resolve_field(&mut self.store, &wit_info)?;
+    /// Resolve a GraphQL field, reporting the fuel and payload sizes it used
+    pub fn resolve_field(&mut self, info: ResolveInfo) -> Result<(ResolveResult, CallUsage)> {
+        let resolver = self
+            .resolver
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("extension does not export forge:extension/resolver"))?;
+
+        let host = &mut self.store.data_mut().host;
+        host.actor_did = info.context.user.as_ref().map(|user| user.id.clone());
+        host.repository_id = info.context.repository.as_ref().map(|repo| repo.id.clone());
 
+        let wit_info = to_wit_resolve_info(info)?;
+        let mut usage = CallUsage {
+            request_bytes: wit_info.arguments.len()
+                + wit_info.parent.as_ref().map_or(0, String::len),
+            ..CallUsage::default()
+        };
+        // Each call starts with a full tank so consumption is per call
+        if let Some(fuel) = self.max_fuel {
+            self.store.set_fuel(fuel)?;
+        }
+        let result = resolver.call_resolve_field(&mut self.store, &wit_info);
+        if let Some(fuel) = self.max_fuel {
+            usage.fuel_consumed = Some(fuel.saturating_sub(self.store.get_fuel().unwrap_or(0)));
+        }
+
+        let result = self.read_resolve_result(result?, &mut usage)?;
+        Ok((result, usage))
+    }
+
+    /// Resolve several fields in one `resolve-fields` call. All entries must carry the
+    /// same context; the usage covers the whole batch.
+    pub fn resolve_fields(
+        &mut self,
+        batch: Vec<ResolveInfo>,
+    ) -> Result<(Vec<ResolveResult>, CallUsage)> {
+        let batch_resolver = self.batch_resolver.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("extension does not export forge:extension/batch-resolver")
+        })?;
+        let Some(first) = batch.first() else {
+            return Ok((Vec::new(), CallUsage::default()));
+        };
+
+        let host = &mut self.store.data_mut().host;
+        host.actor_did = first.context.user.as_ref().map(|user| user.id.clone());
+        host.repository_id = first
+            .context
+            .repository
+            .as_ref()
+            .map(|repo| repo.id.clone());
+
+        let wit_batch = batch
+            .into_iter()
+            .map(to_wit_resolve_info)
+            .collect::<Result<Vec<_>>>()?;
+        let mut usage = CallUsage {
+            request_bytes: wit_batch
+                .iter()
+                .map(|info| info.arguments.len() + info.parent.as_ref().map_or(0, String::len))
+                .sum(),
+            ..CallUsage::default()
+        };
+        if let Some(fuel) = self.max_fuel {
+            self.store.set_fuel(fuel)?;
+        }
+        let results = batch_resolver.call_resolve_fields(&mut self.store, &wit_batch);
+        if let Some(fuel) = self.max_fuel {
+            usage.fuel_consumed = Some(fuel.saturating_sub(self.store.get_fuel().unwrap_or(0)));
+        }
+
+        let results = results?;
+        if results.len() != wit_batch.len() {
+            anyhow::bail!(
+                "extension returned {} results for {} fields",
+                results.len(),
+                wit_batch.len()
+            );
+        }
+        let results = results
+            .into_iter()
+            .map(|result| self.read_resolve_result(result, &mut usage))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((results, usage))
+    }
+
+    fn read_resolve_result(
+        &self,
+        result: ExtResolveResult,
+        usage: &mut CallUsage,
+    ) -> Result<ResolveResult> {
         match result {
             ExtResolveResult::Success(json) => {
+                usage.response_bytes += json.len();
                 let value: serde_json::Value = serde_json::from_str(&json)?;
                 Ok(ResolveResult::Success(value))
             }
-            ExtResolveResult::Error(err) => Ok(ResolveResult::Error(err)),
+            ExtResolveResult::Error(err) => {
+                usage.response_bytes += err.len();
+                // Errors reach clients and logs, so they must not carry secret values
+                let err = mask_secrets(&err, &self.store.data().host.revealed_secrets);
+                Ok(ResolveResult::Error(err))
+            }
+        }
+    }
+
+    /// Deliver a repository lifecycle event
+    pub fn handle_lifecycle_event(&mut self, event: LifecycleEvent) -> Result<()> {
+        let git_events = self.git_events.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("extension does not export forge:extension/git-events")
+        })?;
+
+        let host = &mut self.store.data_mut().host;
+        host.actor_did = None;
+        host.repository_id = Some(event.repository_id.clone());
+
+        let wit_event = ExtLifecycleEvent {
+            id: event.id,
+            kind: match event.kind {
+                LifecycleKind::Renamed => ExtLifecycleKind::Renamed,
+                LifecycleKind::Transferred => ExtLifecycleKind::Transferred,
+                LifecycleKind::Deleted => ExtLifecycleKind::Deleted,
+                LifecycleKind::Purged => ExtLifecycleKind::Purged,
+            },
+            repository_id: event.repository_id,
+            old_path: event.old_path,
+            new_path: event.new_path,
+            occurred_at: event.occurred_at,
+        };
+
+        if let Some(fuel) = self.max_fuel {
+            self.store.set_fuel(fuel)?;
+        }
+        git_events
+            .call_handle_lifecycle_event(&mut self.store, &wit_event)?
+            .map_err(|e| {
+                let e = mask_secrets(&e, &self.store.data().host.revealed_secrets);
+                anyhow::anyhow!("Extension error: {}", e)
+            })
+    }
+
+    /// Hand an HTTP request to the component's webhook handler
+    pub fn handle_webhook(&mut self, request: WebhookRequest) -> Result<WebhookResponse> {
+        let webhooks = self
+            .webhooks
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("extension does not export forge:extension/webhooks"))?;
+
+        let host = &mut self.store.data_mut().host;
+        host.actor_did = None;
+        host.repository_id = None;
+
+        let wit_request = ExtWebhookRequest {
+            method: request.method,
+            path: request.path,
+            query: request.query,
+            headers: request.headers,
+            body: request.body,
+        };
+
+        if let Some(fuel) = self.max_fuel {
+            self.store.set_fuel(fuel)?;
+        }
+        let response = webhooks.call_handle_webhook(&mut self.store, &wit_request)?;
+        Ok(WebhookResponse {
+            status: response.status,
+            headers: response.headers,
+            body: response.body,
+        })
+    }
+
+    /// Ask the component's abuse check about content before it is created
+    pub fn check_content(&mut self, content: Content) -> Result<Verdict> {
+        let abuse_check = self.abuse_check.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("extension does not export forge:extension/abuse-check")
+        })?;
+
+        let host = &mut self.store.data_mut().host;
+        host.actor_did = content.actor_did.clone();
+        host.repository_id = content.repository_id.clone();
+
+        let wit_content = ExtContent {
+            kind: match content.kind {
+                ContentKind::Issue => ExtContentKind::Issue,
+                ContentKind::Comment => ExtContentKind::Comment,
+                ContentKind::Repository => ExtContentKind::Repository,
+            },
+            actor_did: content.actor_did,
+            repository_id: content.repository_id,
+            title: content.title,
+            body: content.body,
+        };
+
+        if let Some(fuel) = self.max_fuel {
+            self.store.set_fuel(fuel)?;
         }
+        let verdict = abuse_check
+            .call_check_content(&mut self.store, &wit_content)?
+            .map_err(|e| {
+                let e = mask_secrets(&e, &self.store.data().host.revealed_secrets);
+                anyhow::anyhow!("Extension error: {}", e)
+            })?;
+        Ok(Verdict {
+            kind: match verdict.kind {
+                ExtVerdictKind::Allow => VerdictKind::Allow,
+                ExtVerdictKind::Flag => VerdictKind::Flag,
+                ExtVerdictKind::Reject => VerdictKind::Reject,
+            },
+            reason: verdict.reason,
+        })
+    }
+
+    /// Run a task registered through `host-scheduler`
+    pub fn run_task(&mut self, name: &str) -> Result<()> {
+        let tasks = self
+            .tasks
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("extension does not export forge:extension/tasks"))?;
+
+        let host = &mut self.store.data_mut().host;
+        host.actor_did = None;
+        host.repository_id = None;
+
+        if let Some(fuel) = self.max_fuel {
+            self.store.set_fuel(fuel)?;
+        }
+        tasks.call_run_task(&mut self.store, name)?.map_err(|e| {
+            let e = mask_secrets(&e, &self.store.data().host.revealed_secrets);
+            anyhow::anyhow!("Extension error: {}", e)
+        })
     }
 
     /// Shutdown the extension
     #[allow(dead_code)]
     pub fn shutdown(&mut self) -> Result<()> {
-        self.bindings
-            .forge_extension_extension_api()
-            .call_shutdown(&mut self.store)?;
+        self.api.call_shutdown(&mut self.store)?;
 
         Ok(())
     }
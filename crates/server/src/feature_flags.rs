@@ -0,0 +1,440 @@
+//! Database-backed feature flags.
+//!
+//! A flag is off until an administrator enables it. An enabled flag applies to
+//! `rollout_percent` percent of signed-in users: each DID is hashed together with the flag
+//! key into a bucket from 0 to 99, so a user keeps their answer while the percentage only
+//! grows, and different flags roll out to different users. Anonymous viewers only see a
+//! flag once it reaches 100%. A per-repository override forces a flag on or off inside
+//! that repository regardless of the rollout.
+//!
+//! Like maintenance mode, flags are read through a cache that expires after
+//! [`FLAG_CACHE_TTL`], so a flip made on one instance reaches the others within seconds
+//! without a restart.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+
+use crate::user::db::unix_now;
+
+pub const FLAG_CACHE_TTL: Duration = Duration::from_secs(5);
+pub const MAX_KEY_LEN: usize = 64;
+pub const MAX_DESCRIPTION_LEN: usize = 500;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeatureFlag {
+    pub key: String,
+    pub description: Option<String>,
+    pub enabled: bool,
+    pub rollout_percent: u8,
+    /// Repository id to forced value.
+    pub overrides: HashMap<String, bool>,
+    pub updated_by: Option<String>,
+    pub updated_at: i64,
+}
+
+impl FeatureFlag {
+    /// Whether the flag is on for `actor_did`, inside `repository_id` when given.
+    pub fn evaluate(&self, actor_did: Option<&str>, repository_id: Option<&str>) -> bool {
+        if let Some(forced) = repository_id.and_then(|id| self.overrides.get(id)) {
+            return *forced;
+        }
+        if !self.enabled {
+            return false;
+        }
+        match actor_did {
+            Some(did) => rollout_bucket(&self.key, did) < self.rollout_percent,
+            None => self.rollout_percent >= 100,
+        }
+    }
+}
+
+/// The stable bucket, from 0 to 99, that `did` falls in for flag `key`.
+pub fn rollout_bucket(key: &str, did: &str) -> u8 {
+    let digest = Sha256::new()
+        .chain_update(key.as_bytes())
+        .chain_update(b":")
+        .chain_update(did.as_bytes())
+        .finalize();
+    let mut head = [0u8; 8];
+    head.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(head) % 100) as u8
+}
+
+/// Keys are shared with clients and extensions, so they stay short and predictable.
+pub fn validate_key(key: &str) -> anyhow::Result<()> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        bail!("flag key must be 1 to {} characters", MAX_KEY_LEN);
+    }
+    if !key
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.'))
+    {
+        bail!(
+            "flag key `{}` may only contain lowercase letters, digits, `-`, `_` and `.`",
+            key
+        );
+    }
+    Ok(())
+}
+
+type FlagSet = Arc<Vec<FeatureFlag>>;
+
+static FLAGS: Mutex<Option<(Instant, FlagSet)>> = Mutex::new(None);
+
+/// Every flag, at most [`FLAG_CACHE_TTL`] old.
+pub async fn cached_flags(pool: &SqlitePool) -> anyhow::Result<FlagSet> {
+    {
+        let cached = FLAGS.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((loaded_at, flags)) = cached.as_ref()
+            && loaded_at.elapsed() < FLAG_CACHE_TTL
+        {
+            return Ok(flags.clone());
+        }
+    }
+    let flags = Arc::new(list_flags(pool).await?);
+    *FLAGS.lock().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), flags.clone()));
+    Ok(flags)
+}
+
+/// Drops this instance's cache so its own changes apply immediately.
+fn invalidate() {
+    *FLAGS.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Every flag evaluated for one viewer, sorted by key.
+pub async fn evaluate_flags(
+    pool: &SqlitePool,
+    actor_did: Option<&str>,
+    repository_id: Option<&str>,
+) -> anyhow::Result<Vec<(String, bool)>> {
+    let flags = cached_flags(pool).await?;
+    Ok(flags
+        .iter()
+        .map(|flag| (flag.key.clone(), flag.evaluate(actor_did, repository_id)))
+        .collect())
+}
+
+/// Unknown flags are off, so code can check a flag before an administrator creates it.
+pub async fn is_enabled(
+    pool: &SqlitePool,
+    key: &str,
+    actor_did: Option<&str>,
+    repository_id: Option<&str>,
+) -> anyhow::Result<bool> {
+    let flags = cached_flags(pool).await?;
+    Ok(flags
+        .iter()
+        .find(|flag| flag.key == key)
+        .is_some_and(|flag| flag.evaluate(actor_did, repository_id)))
+}
+
+/// Reads every flag from the database, bypassing the cache.
+pub async fn list_flags(pool: &SqlitePool) -> anyhow::Result<Vec<FeatureFlag>> {
+    let rows = sqlx::query(
+        "SELECT key, description, enabled, rollout_percent, updated_by, updated_at \
+         FROM feature_flags ORDER BY key",
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut flags = rows
+        .iter()
+        .map(|row| {
+            Ok(FeatureFlag {
+                key: row.try_get("key")?,
+                description: row.try_get("description")?,
+                enabled: row.try_get::<i64, _>("enabled")? != 0,
+                rollout_percent: row.try_get::<i64, _>("rollout_percent")?.clamp(0, 100) as u8,
+                overrides: HashMap::new(),
+                updated_by: row.try_get("updated_by")?,
+                updated_at: row.try_get("updated_at")?,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let overrides =
+        sqlx::query("SELECT flag_key, repository_id, enabled FROM feature_flag_overrides")
+            .fetch_all(pool)
+            .await?;
+    for row in overrides {
+        let key: String = row.try_get("flag_key")?;
+        if let Some(flag) = flags.iter_mut().find(|flag| flag.key == key) {
+            flag.overrides.insert(
+                row.try_get("repository_id")?,
+                row.try_get::<i64, _>("enabled")? != 0,
+            );
+        }
+    }
+    Ok(flags)
+}
+
+pub async fn get_flag(pool: &SqlitePool, key: &str) -> anyhow::Result<Option<FeatureFlag>> {
+    Ok(list_flags(pool)
+        .await?
+        .into_iter()
+        .find(|flag| flag.key == key))
+}
+
+/// Creates or updates a flag. A new flag rolls out to everyone unless a percentage is
+/// given; an existing one keeps its percentage and description when they are omitted.
+pub async fn set_flag(
+    pool: &SqlitePool,
+    key: &str,
+    enabled: bool,
+    rollout_percent: Option<i64>,
+    description: Option<String>,
+    actor_did: Option<&str>,
+) -> anyhow::Result<FeatureFlag> {
+    validate_key(key)?;
+    if let Some(percent) = rollout_percent
+        && !(0..=100).contains(&percent)
+    {
+        bail!("rollout percentage must be between 0 and 100");
+    }
+    let description = description.map(|description| description.trim().to_string());
+    if description
+        .as_ref()
+        .is_some_and(|description| description.chars().count() > MAX_DESCRIPTION_LEN)
+    {
+        bail!(
+            "flag description is longer than {} characters",
+            MAX_DESCRIPTION_LEN
+        );
+    }
+
+    let existing = get_flag(pool, key).await?;
+    let rollout_percent = rollout_percent
+        .or(existing
+            .as_ref()
+            .map(|flag| i64::from(flag.rollout_percent)))
+        .unwrap_or(100);
+    let description = match description {
+        Some(description) if description.is_empty() => None,
+        Some(description) => Some(description),
+        None => existing.and_then(|flag| flag.description),
+    };
+    sqlx::query(
+        "INSERT INTO feature_flags (key, description, enabled, rollout_percent, updated_by, updated_at) \
+         VALUES (?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(key) DO UPDATE SET description = excluded.description, enabled = excluded.enabled, \
+         rollout_percent = excluded.rollout_percent, updated_by = excluded.updated_by, \
+         updated_at = excluded.updated_at",
+    )
+    .bind(key)
+    .bind(&description)
+    .bind(enabled)
+    .bind(rollout_percent)
+    .bind(actor_did)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    invalidate();
+    get_flag(pool, key)
+        .await?
+        .ok_or_else(|| anyhow!("flag `{}` disappeared while saving", key))
+}
+
+/// Returns `false` when no flag has that key. Its overrides go with it.
+pub async fn delete_flag(pool: &SqlitePool, key: &str) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM feature_flags WHERE key = ?")
+        .bind(key)
+        .execute(pool)
+        .await?;
+    invalidate();
+    Ok(result.rows_affected() > 0)
+}
+
+/// Forces `key` on or off in one repository; `None` removes the override.
+pub async fn set_override(
+    pool: &SqlitePool,
+    key: &str,
+    repository_id: &str,
+    enabled: Option<bool>,
+    actor_did: Option<&str>,
+) -> anyhow::Result<FeatureFlag> {
+    if get_flag(pool, key).await?.is_none() {
+        bail!("unknown feature flag `{}`", key);
+    }
+    match enabled {
+        Some(enabled) => {
+            sqlx::query(
+                "INSERT INTO feature_flag_overrides (flag_key, repository_id, enabled) VALUES (?, ?, ?) \
+                 ON CONFLICT(flag_key, repository_id) DO UPDATE SET enabled = excluded.enabled",
+            )
+            .bind(key)
+            .bind(repository_id)
+            .bind(enabled)
+            .execute(pool)
+            .await?;
+        }
+        None => {
+            sqlx::query(
+                "DELETE FROM feature_flag_overrides WHERE flag_key = ? AND repository_id = ?",
+            )
+            .bind(key)
+            .bind(repository_id)
+            .execute(pool)
+            .await?;
+        }
+    }
+    sqlx::query("UPDATE feature_flags SET updated_by = ?, updated_at = ? WHERE key = ?")
+        .bind(actor_did)
+        .bind(unix_now())
+        .bind(key)
+        .execute(pool)
+        .await?;
+    invalidate();
+    get_flag(pool, key)
+        .await?
+        .ok_or_else(|| anyhow!("flag `{}` disappeared while saving", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    #[test]
+    fn rollout_only_grows_and_differs_between_flags() {
+        let dids: Vec<String> = (0..1000).map(|i| format!("did:plc:user{}", i)).collect();
+        let flag = |percent| FeatureFlag {
+            key: "new-editor".into(),
+            description: None,
+            enabled: true,
+            rollout_percent: percent,
+            overrides: HashMap::new(),
+            updated_by: None,
+            updated_at: 0,
+        };
+
+        let at = |percent| {
+            let flag = flag(percent);
+            dids.iter()
+                .filter(|did| flag.evaluate(Some(did.as_str()), None))
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+        };
+        assert!(at(0).is_empty());
+        assert_eq!(at(100).len(), dids.len());
+        let ten = at(10);
+        let half = at(50);
+        assert!(
+            (400..600).contains(&half.len()),
+            "{} of 1000 at 50%",
+            half.len()
+        );
+        assert!(ten.iter().all(|did| half.contains(did)));
+
+        let other: Vec<&str> = dids
+            .iter()
+            .filter(|did| rollout_bucket("dark-mode", did) < 50)
+            .map(String::as_str)
+            .collect();
+        assert_ne!(half, other);
+
+        assert!(!flag(99).evaluate(None, None));
+        assert!(flag(100).evaluate(None, None));
+    }
+
+    #[test]
+    fn keys_are_validated() {
+        assert!(validate_key("issues.new-editor_v2").is_ok());
+        assert!(validate_key("").is_err());
+        assert!(validate_key("New Editor").is_err());
+        assert!(validate_key(&"a".repeat(MAX_KEY_LEN + 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn overrides_win_over_the_rollout() {
+        let pool = create_test_pool().await.unwrap();
+        sqlx::query("INSERT INTO repositories (id, slug) VALUES ('repo1', 'widgets')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let flag = set_flag(
+            &pool,
+            "merge-queue",
+            false,
+            None,
+            Some("Queue merges".into()),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(flag.rollout_percent, 100);
+        assert!(
+            !is_enabled(&pool, "merge-queue", Some("did:plc:a"), None)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !is_enabled(&pool, "missing", Some("did:plc:a"), None)
+                .await
+                .unwrap()
+        );
+
+        set_override(
+            &pool,
+            "merge-queue",
+            "repo1",
+            Some(true),
+            Some("did:plc:admin"),
+        )
+        .await
+        .unwrap();
+        assert!(
+            is_enabled(&pool, "merge-queue", None, Some("repo1"))
+                .await
+                .unwrap()
+        );
+        assert!(!is_enabled(&pool, "merge-queue", None, None).await.unwrap());
+
+        let flag = set_flag(
+            &pool,
+            "merge-queue",
+            true,
+            Some(0),
+            None,
+            Some("did:plc:admin"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(flag.description.as_deref(), Some("Queue merges"));
+        assert!(
+            is_enabled(&pool, "merge-queue", Some("did:plc:a"), Some("repo1"))
+                .await
+                .unwrap()
+        );
+        set_override(&pool, "merge-queue", "repo1", None, None)
+            .await
+            .unwrap();
+        assert!(
+            !is_enabled(&pool, "merge-queue", Some("did:plc:a"), Some("repo1"))
+                .await
+                .unwrap()
+        );
+
+        assert!(
+            set_flag(&pool, "merge-queue", true, Some(101), None, None)
+                .await
+                .is_err()
+        );
+        assert!(
+            set_override(&pool, "missing", "repo1", Some(true), None)
+                .await
+                .is_err()
+        );
+
+        assert!(delete_flag(&pool, "merge-queue").await.unwrap());
+        let overrides: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM feature_flag_overrides")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(overrides, 0);
+    }
+}
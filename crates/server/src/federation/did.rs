@@ -0,0 +1,293 @@
+//! DID documents: parsing, resolution and the local cache.
+
+use anyhow::{Context, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Row, SqlitePool};
+
+use super::FETCH_TIMEOUT;
+use super::links::{ProfileLink, verify_links};
+use crate::config::FederationConfig;
+use crate::user::db::unix_now;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidDocument {
+    pub id: String,
+    #[serde(default)]
+    pub also_known_as: Vec<String>,
+    #[serde(default)]
+    pub verification_method: Vec<VerificationMethod>,
+    #[serde(default)]
+    pub service: Vec<Service>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationMethod {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub controller: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key_jwk: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key_multibase: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Service {
+    pub id: String,
+    /// A string or a list of strings
+    #[serde(rename = "type")]
+    pub kind: Value,
+    /// A URL, or a map or list of them; only plain URLs are used
+    pub service_endpoint: Value,
+}
+
+impl Service {
+    pub fn has_type(&self, kind: &str) -> bool {
+        match &self.kind {
+            Value::String(value) => value == kind,
+            Value::Array(values) => values.iter().any(|value| value.as_str() == Some(kind)),
+            _ => false,
+        }
+    }
+
+    pub fn endpoint(&self) -> Option<&str> {
+        self.service_endpoint.as_str()
+    }
+}
+
+impl DidDocument {
+    /// Parses a document fetched for `did`; a document for any other DID is rejected.
+    pub fn parse(did: &str, json: &str) -> anyhow::Result<Self> {
+        let document: DidDocument =
+            serde_json::from_str(json).context("DID document is not valid JSON")?;
+        if document.id != did {
+            bail!("DID document for {} describes {}", did, document.id);
+        }
+        Ok(document)
+    }
+
+    /// Finds a verification method by its fragment (`#forge`) or full id.
+    pub fn verification_method(&self, fragment: &str) -> Option<&VerificationMethod> {
+        let full = format!("{}{}", self.id, fragment);
+        self.verification_method
+            .iter()
+            .find(|method| method.id == fragment || method.id == full)
+    }
+}
+
+/// Where the document for `did` is published.
+pub fn document_url(did: &str, plc_directory: &str) -> anyhow::Result<String> {
+    if let Some(id) = did.strip_prefix("did:plc:") {
+        let valid = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || ('2'..='7').contains(&c));
+        if !valid {
+            bail!("malformed did:plc identifier {}", did);
+        }
+        return Ok(format!("{}/{}", plc_directory.trim_end_matches('/'), did));
+    }
+    if let Some(id) = did.strip_prefix("did:web:") {
+        let mut segments = id.split(':');
+        let host = segments.next().unwrap_or_default().replace("%3A", ":");
+        let path: Vec<&str> = segments.collect();
+        let valid_host = !host.is_empty()
+            && host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':'));
+        if !valid_host
+            || path
+                .iter()
+                .any(|segment| segment.is_empty() || segment.contains('/'))
+        {
+            bail!("malformed did:web identifier {}", did);
+        }
+        // Plain HTTP is only allowed for local development hosts.
+        let scheme = if host.starts_with("localhost") || host.starts_with("127.0.0.1") {
+            "http"
+        } else {
+            "https"
+        };
+        return Ok(if path.is_empty() {
+            format!("{}://{}/.well-known/did.json", scheme, host)
+        } else {
+            format!("{}://{}/{}/did.json", scheme, host, path.join("/"))
+        });
+    }
+    Err(anyhow!("unsupported DID method in {}", did))
+}
+
+/// A resolved document with its profile links, as stored in the cache.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedDid {
+    pub document: DidDocument,
+    pub links: Vec<ProfileLink>,
+    pub fetched_at: i64,
+}
+
+pub struct DidResolver {
+    http: reqwest::Client,
+    plc_directory: String,
+    ttl_secs: i64,
+}
+
+impl DidResolver {
+    pub fn from_config(config: &FederationConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .context("failed to create HTTP client")?,
+            plc_directory: config.plc_directory.clone(),
+            ttl_secs: config.did_cache_ttl_secs as i64,
+        })
+    }
+
+    pub fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    /// The cached document when it is fresh enough, otherwise a newly fetched one.
+    ///
+    /// A stale cached document is still returned when the refresh fails, so a DID host
+    /// being down does not break profiles or signed requests that worked before.
+    pub async fn resolve(&self, pool: &SqlitePool, did: &str) -> anyhow::Result<ResolvedDid> {
+        let cached = load_cached(pool, did).await?;
+        if let Some(cached) = &cached
+            && unix_now() - cached.fetched_at < self.ttl_secs
+        {
+            return Ok(cached.clone());
+        }
+
+        match self.fetch(did).await {
+            Ok(document) => {
+                let links = verify_links(&self.http, &document).await;
+                let resolved = ResolvedDid {
+                    document,
+                    links,
+                    fetched_at: unix_now(),
+                };
+                store_cached(pool, &resolved).await?;
+                Ok(resolved)
+            }
+            Err(e) => match cached {
+                Some(cached) => {
+                    tracing::warn!(%did, "keeping cached DID document: {:#}", e);
+                    Ok(cached)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Fetches the current document, bypassing the cache.
+    pub(crate) async fn fetch(&self, did: &str) -> anyhow::Result<DidDocument> {
+        let url = document_url(did, &self.plc_directory)?;
+        let response = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("failed to fetch DID document from {}", url))?;
+        if !response.status().is_success() {
+            bail!(
+                "DID document fetch from {} failed: {}",
+                url,
+                response.status()
+            );
+        }
+        let body = response.text().await?;
+        DidDocument::parse(did, &body)
+    }
+}
+
+async fn load_cached(pool: &SqlitePool, did: &str) -> anyhow::Result<Option<ResolvedDid>> {
+    let row = sqlx::query("SELECT document, links, fetched_at FROM did_documents WHERE did = ?")
+        .bind(did)
+        .fetch_optional(pool)
+        .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let document: String = row.try_get("document")?;
+    let links: String = row.try_get("links")?;
+    Ok(Some(ResolvedDid {
+        document: serde_json::from_str(&document)?,
+        links: serde_json::from_str(&links)?,
+        fetched_at: row.try_get("fetched_at")?,
+    }))
+}
+
+async fn store_cached(pool: &SqlitePool, resolved: &ResolvedDid) -> anyhow::Result<()> {
+    sqlx::query(
+        "INSERT INTO did_documents (did, document, links, fetched_at) VALUES (?, ?, ?, ?) \
+         ON CONFLICT(did) DO UPDATE SET document = excluded.document, links = excluded.links, \
+         fetched_at = excluded.fetched_at",
+    )
+    .bind(&resolved.document.id)
+    .bind(serde_json::to_string(&resolved.document)?)
+    .bind(serde_json::to_string(&resolved.links)?)
+    .bind(resolved.fetched_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    #[test]
+    fn document_urls_follow_the_did_method() {
+        assert_eq!(
+            document_url("did:plc:ewvi7nxzyoun6zhxrhs64oiz", "https://plc.directory/").unwrap(),
+            "https://plc.directory/did:plc:ewvi7nxzyoun6zhxrhs64oiz"
+        );
+        assert_eq!(
+            document_url("did:web:forge.example", "").unwrap(),
+            "https://forge.example/.well-known/did.json"
+        );
+        assert_eq!(
+            document_url("did:web:example.com:users:alice", "").unwrap(),
+            "https://example.com/users/alice/did.json"
+        );
+        assert_eq!(
+            document_url("did:web:localhost%3A8000", "").unwrap(),
+            "http://localhost:8000/.well-known/did.json"
+        );
+        assert!(document_url("did:plc:UPPER", "https://plc.directory").is_err());
+        assert!(document_url("did:web:evil.example/path", "").is_err());
+        assert!(document_url("did:key:z6Mk", "").is_err());
+    }
+
+    #[tokio::test]
+    async fn documents_must_describe_the_requested_did() {
+        let json = r##"{
+            "id": "did:plc:alice",
+            "alsoKnownAs": ["at://alice.example", "https://alice.example"],
+            "service": [{ "id": "#atproto_pds", "type": "AtprotoPersonalDataServer", "serviceEndpoint": "https://pds.example" }]
+        }"##;
+        let document = DidDocument::parse("did:plc:alice", json).unwrap();
+        assert!(document.service[0].has_type("AtprotoPersonalDataServer"));
+        assert!(DidDocument::parse("did:plc:mallory", json).is_err());
+
+        let pool = create_test_pool().await.unwrap();
+        let resolved = ResolvedDid {
+            document,
+            links: Vec::new(),
+            fetched_at: 42,
+        };
+        store_cached(&pool, &resolved).await.unwrap();
+        assert_eq!(
+            load_cached(&pool, "did:plc:alice").await.unwrap(),
+            Some(resolved)
+        );
+    }
+}
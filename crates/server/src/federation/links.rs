@@ -0,0 +1,156 @@
+//! Profile links claimed by a DID document, verified from the other side.
+//!
+//! A document can list anything, so a link only counts as verified when its target
+//! names the DID back. For a website that is the standard ATProto domain proof,
+//! `https://<host>/.well-known/atproto-did`. For another Forge instance (a service of
+//! type `ForgeInstance`) it is that instance's `/federation/users/<did>` endpoint, which
+//! answers for every user who has signed in there.
+
+use serde::{Deserialize, Serialize};
+
+use super::did::DidDocument;
+
+/// Service type marking a Forge instance the DID also uses.
+pub const FORGE_SERVICE_TYPE: &str = "ForgeInstance";
+/// Links checked per document; the rest are shown unverified.
+const MAX_VERIFIED_LINKS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkKind {
+    Website,
+    Forge,
+}
+
+impl LinkKind {
+    pub fn as_graphql(&self) -> &'static str {
+        match self {
+            LinkKind::Website => "WEBSITE",
+            LinkKind::Forge => "FORGE",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileLink {
+    pub url: String,
+    pub kind: LinkKind,
+    pub verified: bool,
+}
+
+/// HTTPS websites in `alsoKnownAs` and Forge instances in `service`, without duplicates.
+pub fn claimed_links(document: &DidDocument) -> Vec<(String, LinkKind)> {
+    let mut links: Vec<(String, LinkKind)> = Vec::new();
+    let mut push = |url: &str, kind| {
+        let url = url.trim_end_matches('/').to_string();
+        if is_https(&url) && !links.iter().any(|(existing, _)| *existing == url) {
+            links.push((url, kind));
+        }
+    };
+    for alias in &document.also_known_as {
+        push(alias, LinkKind::Website);
+    }
+    for service in &document.service {
+        if service.has_type(FORGE_SERVICE_TYPE)
+            && let Some(endpoint) = service.endpoint()
+        {
+            push(endpoint, LinkKind::Forge);
+        }
+    }
+    links
+}
+
+fn is_https(url: &str) -> bool {
+    url::Url::parse(url).is_ok_and(|url| url.scheme() == "https" && url.host_str().is_some())
+}
+
+/// Checks every claimed link; failures just leave a link unverified.
+pub async fn verify_links(http: &reqwest::Client, document: &DidDocument) -> Vec<ProfileLink> {
+    let mut links = Vec::new();
+    for (index, (url, kind)) in claimed_links(document).into_iter().enumerate() {
+        let verified = index < MAX_VERIFIED_LINKS
+            && match verify_link(http, &document.id, &url, kind).await {
+                Ok(verified) => verified,
+                Err(e) => {
+                    tracing::debug!(did = %document.id, %url, "link check failed: {:#}", e);
+                    false
+                }
+            };
+        links.push(ProfileLink {
+            url,
+            kind,
+            verified,
+        });
+    }
+    links
+}
+
+#[derive(Deserialize)]
+struct ForgeUser {
+    did: String,
+}
+
+async fn verify_link(
+    http: &reqwest::Client,
+    did: &str,
+    url: &str,
+    kind: LinkKind,
+) -> anyhow::Result<bool> {
+    let parsed = url::Url::parse(url)?;
+    match kind {
+        LinkKind::Website => {
+            let host = parsed
+                .host_str()
+                .ok_or_else(|| anyhow::anyhow!("{} has no host", url))?;
+            let response = http
+                .get(format!("https://{}/.well-known/atproto-did", host))
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Ok(false);
+            }
+            Ok(response.text().await?.trim() == did)
+        }
+        LinkKind::Forge => {
+            let response = http
+                .get(format!(
+                    "{}/federation/users/{}",
+                    url.trim_end_matches('/'),
+                    urlencoding::encode(did)
+                ))
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Ok(false);
+            }
+            Ok(response.json::<ForgeUser>().await?.did == did)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claims_https_aliases_and_forge_services() {
+        let document: DidDocument = serde_json::from_str(
+            r##"{
+                "id": "did:plc:alice",
+                "alsoKnownAs": ["at://alice.example", "https://alice.example/", "http://insecure.example"],
+                "service": [
+                    { "id": "#atproto_pds", "type": "AtprotoPersonalDataServer", "serviceEndpoint": "https://pds.example" },
+                    { "id": "#forge", "type": ["ForgeInstance"], "serviceEndpoint": "https://forge.example" },
+                    { "id": "#forge2", "type": "ForgeInstance", "serviceEndpoint": { "url": "https://ignored.example" } }
+                ]
+            }"##,
+        )
+        .unwrap();
+        assert_eq!(
+            claimed_links(&document),
+            vec![
+                ("https://alice.example".to_string(), LinkKind::Website),
+                ("https://forge.example".to_string(), LinkKind::Forge),
+            ]
+        );
+    }
+}
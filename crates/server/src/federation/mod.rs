@@ -0,0 +1,65 @@
+//! Identity federation between Forge instances.
+//!
+//! Users are ATProto DIDs, so a user's DID document is the one place other services can
+//! check what belongs to them. [`did`] resolves and caches documents (`did:plc` through
+//! the PLC directory, `did:web` over HTTPS). [`links`] turns the websites and Forge
+//! instances a document lists into profile links, and marks a link verified only when
+//! the other side confirms the DID back.
+//!
+//! Instances identify themselves with a `did:web` for their public URL. Its document
+//! carries the P-256 key the instance signs requests with ([`signing`]). A signed
+//! request names its instance, a timestamp and a signature over the method, path,
+//! timestamp and body digest. The receiving instance resolves the sender's document and
+//! checks the signature before handling the request. Cross-instance issue references
+//! ([`references`]) are the first use.
+
+pub mod did;
+pub mod links;
+pub mod references;
+pub mod signing;
+
+use std::time::Duration;
+
+/// Timeout for fetching DID documents and checking links.
+pub(crate) const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The `did:web` identifier of an instance reachable at `public_base_url`.
+///
+/// Ports are percent-encoded and paths become `:`-separated segments, as the `did:web`
+/// method specifies.
+pub fn instance_did(public_base_url: &str) -> anyhow::Result<String> {
+    let url = url::Url::parse(public_base_url)?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("public URL {} has no host", public_base_url))?;
+    let mut did = format!("did:web:{}", host);
+    if let Some(port) = url.port() {
+        did.push_str(&format!("%3A{}", port));
+    }
+    for segment in url.path().split('/').filter(|segment| !segment.is_empty()) {
+        did.push(':');
+        did.push_str(segment);
+    }
+    Ok(did)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instance_dids_follow_did_web() {
+        assert_eq!(
+            instance_did("https://forge.example").unwrap(),
+            "did:web:forge.example"
+        );
+        assert_eq!(
+            instance_did("http://localhost:8000/").unwrap(),
+            "did:web:localhost%3A8000"
+        );
+        assert_eq!(
+            instance_did("https://example.com/forge").unwrap(),
+            "did:web:example.com:forge"
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Issues on other Forge instances that reference issues here.
+//!
+//! The other instance reports a reference with a signed `POST /federation/issue-references`.
+//! Its source URL must be on the sending instance's own host, so one instance cannot
+//! claim references on behalf of another.
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use super::signing::InstanceKey;
+use crate::user::db::unix_now;
+
+/// Body of `POST /federation/issue-references`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IssueReferenceRequest {
+    /// Repository path on this instance
+    pub repository: String,
+    pub number: i64,
+    /// The referencing issue or comment on the sending instance
+    pub source_url: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Author of the reference, when the sender knows it
+    #[serde(default)]
+    pub actor_did: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, sqlx::FromRow)]
+pub struct FederatedIssueReference {
+    pub id: String,
+    pub repository_id: String,
+    pub issue_number: i64,
+    pub instance_did: String,
+    pub source_url: String,
+    pub title: Option<String>,
+    pub actor_did: Option<String>,
+    pub created_at: i64,
+}
+
+const MAX_TITLE_LEN: usize = 300;
+
+/// Checks that `source_url` is an HTTPS URL on the host `instance_did` names.
+pub fn check_source(instance_did: &str, source_url: &str) -> anyhow::Result<()> {
+    let url = url::Url::parse(source_url)?;
+    let host = match url.port() {
+        Some(port) => format!("{}%3A{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let instance_host = instance_did
+        .strip_prefix("did:web:")
+        .and_then(|id| id.split(':').next())
+        .unwrap_or_default();
+    let local = instance_host.starts_with("localhost") || instance_host.starts_with("127.0.0.1");
+    if url.scheme() != "https" && !local {
+        bail!("source URL must use HTTPS");
+    }
+    if !host.eq_ignore_ascii_case(instance_host) {
+        bail!("source URL is not on {}", instance_did);
+    }
+    Ok(())
+}
+
+/// Stores a reference and says whether it is new; reporting the same source again keeps
+/// the first record.
+pub async fn record_reference(
+    pool: &SqlitePool,
+    repository_id: &str,
+    instance_did: &str,
+    request: &IssueReferenceRequest,
+) -> anyhow::Result<(FederatedIssueReference, bool)> {
+    if request.number < 1 {
+        bail!("issue numbers start at 1");
+    }
+    check_source(instance_did, &request.source_url)?;
+    let title = request
+        .title
+        .as_deref()
+        .map(|title| title.trim().chars().take(MAX_TITLE_LEN).collect::<String>())
+        .filter(|title| !title.is_empty());
+
+    let inserted = sqlx::query(
+        "INSERT INTO federated_issue_references \
+         (id, repository_id, issue_number, instance_did, source_url, title, actor_did, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(repository_id, issue_number, source_url) DO NOTHING",
+    )
+    .bind(cuid2::create_id())
+    .bind(repository_id)
+    .bind(request.number)
+    .bind(instance_did)
+    .bind(&request.source_url)
+    .bind(&title)
+    .bind(&request.actor_did)
+    .bind(unix_now())
+    .execute(pool)
+    .await?
+    .rows_affected()
+        > 0;
+
+    let reference = sqlx::query_as::<_, FederatedIssueReference>(
+        "SELECT id, repository_id, issue_number, instance_did, source_url, title, actor_did, created_at \
+         FROM federated_issue_references \
+         WHERE repository_id = ? AND issue_number = ? AND source_url = ?",
+    )
+    .bind(repository_id)
+    .bind(request.number)
+    .bind(&request.source_url)
+    .fetch_one(pool)
+    .await?;
+    Ok((reference, inserted))
+}
+
+/// References to one issue, oldest first.
+pub async fn issue_references(
+    pool: &SqlitePool,
+    repository_id: &str,
+    issue_number: i64,
+) -> anyhow::Result<Vec<FederatedIssueReference>> {
+    Ok(sqlx::query_as::<_, FederatedIssueReference>(
+        "SELECT id, repository_id, issue_number, instance_did, source_url, title, actor_did, created_at \
+         FROM federated_issue_references \
+         WHERE repository_id = ? AND issue_number = ? ORDER BY created_at, id",
+    )
+    .bind(repository_id)
+    .bind(issue_number)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Reports a reference to the instance at `instance_url`, signed with this instance's key.
+pub async fn send_reference(
+    http: &reqwest::Client,
+    key: &InstanceKey,
+    instance_url: &str,
+    request: &IssueReferenceRequest,
+) -> anyhow::Result<()> {
+    let path = "/federation/issue-references";
+    let body = serde_json::to_vec(request)?;
+    let mut builder = http
+        .post(format!("{}{}", instance_url.trim_end_matches('/'), path))
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+    for (name, value) in key.sign_request("POST", path, unix_now(), &body)? {
+        builder = builder.header(name, value);
+    }
+    let response = builder.body(body).send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let detail = response.text().await.unwrap_or_default();
+        bail!(
+            "{} rejected the reference ({}): {}",
+            instance_url,
+            status,
+            detail
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::create_test_pool;
+
+    #[test]
+    fn sources_must_live_on_the_sending_instance() {
+        check_source(
+            "did:web:forge.example",
+            "https://forge.example/team/app/issues/3",
+        )
+        .unwrap();
+        check_source(
+            "did:web:localhost%3A8001",
+            "http://localhost:8001/team/app/issues/3",
+        )
+        .unwrap();
+        assert!(check_source("did:web:forge.example", "https://other.example/x/issues/1").is_err());
+        assert!(check_source("did:web:forge.example", "http://forge.example/x/issues/1").is_err());
+    }
+
+    #[tokio::test]
+    async fn references_are_recorded_once() {
+        let pool = create_test_pool().await.unwrap();
+        let repo = create_repository_raw(
+            &pool,
+            CreateRepositoryInput {
+                slug: "app".into(),
+                group: None,
+            },
+        )
+        .await
+        .unwrap();
+        let request = IssueReferenceRequest {
+            repository: "app".to_string(),
+            number: 4,
+            source_url: "https://forge.example/team/lib/issues/9".to_string(),
+            title: Some("Crash when parsing".to_string()),
+            actor_did: None,
+        };
+        let (first, created) = record_reference(&pool, &repo.id, "did:web:forge.example", &request)
+            .await
+            .unwrap();
+        assert!(created);
+        let (again, created) = record_reference(&pool, &repo.id, "did:web:forge.example", &request)
+            .await
+            .unwrap();
+        assert!(!created);
+        assert_eq!(first, again);
+        assert_eq!(
+            issue_references(&pool, &repo.id, 4).await.unwrap(),
+            vec![first]
+        );
+        assert!(
+            issue_references(&pool, &repo.id, 5)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! Signed requests between Forge instances.
+//!
+//! The signature covers `METHOD\npath?query\ntimestamp\nhex(sha256(body))`, signed with
+//! ECDSA P-256 (fixed-length, base64url). The sender names itself in `Forge-Instance`,
+//! and the receiver looks the key up as the `#forge` verification method of that
+//! instance's `did:web` document. Timestamps outside `federation.max_clock_skew_secs`
+//! are rejected, and each signature is accepted once within that window.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+
+use anyhow::{Context, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ring::rand::SystemRandom;
+use ring::signature::{
+    ECDSA_P256_SHA256_FIXED, ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair,
+    UnparsedPublicKey,
+};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+use super::did::{DidDocument, DidResolver, Service, VerificationMethod};
+use super::links::FORGE_SERVICE_TYPE;
+use crate::config::FederationConfig;
+use crate::key_file;
+use crate::user::db::unix_now;
+
+pub const INSTANCE_HEADER: &str = "forge-instance";
+pub const TIMESTAMP_HEADER: &str = "forge-timestamp";
+pub const SIGNATURE_HEADER: &str = "forge-signature";
+/// Fragment of the verification method holding an instance's request signing key.
+pub const KEY_FRAGMENT: &str = "#forge";
+
+pub struct InstanceKey {
+    did: String,
+    key_pair: EcdsaKeyPair,
+}
+
+impl InstanceKey {
+    /// Reads the PKCS#8 key at `path`, generating and storing one when it is missing. Any
+    /// other read error is returned: a new key would change the instance's identity.
+    pub fn load_or_generate(path: &Path, did: String) -> anyhow::Result<Self> {
+        let what = format!("federation key for {}", did);
+        let pkcs8 = key_file::load_or_generate(path, &what, || {
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(
+                &ECDSA_P256_SHA256_FIXED_SIGNING,
+                &SystemRandom::new(),
+            )
+            .map_err(|_| anyhow!("failed to generate federation key"))?;
+            Ok(pkcs8.as_ref().to_vec())
+        })?;
+        Self::from_pkcs8(&pkcs8, did)
+            .with_context(|| format!("invalid federation key in {}", path.display()))
+    }
+
+    pub fn from_pkcs8(pkcs8: &[u8], did: String) -> anyhow::Result<Self> {
+        let key_pair = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_FIXED_SIGNING,
+            pkcs8,
+            &SystemRandom::new(),
+        )
+        .map_err(|_| anyhow!("not a P-256 PKCS#8 key"))?;
+        Ok(Self { did, key_pair })
+    }
+
+    pub fn did(&self) -> &str {
+        &self.did
+    }
+
+    /// The public key as a JWK.
+    pub fn public_jwk(&self) -> Value {
+        // Uncompressed SEC1 point: 0x04 || x || y
+        let point = self.key_pair.public_key().as_ref();
+        json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+        })
+    }
+
+    /// This instance's DID document, served at `/.well-known/did.json`.
+    pub fn document(&self, public_base_url: &str) -> DidDocument {
+        DidDocument {
+            id: self.did.clone(),
+            also_known_as: vec![public_base_url.trim_end_matches('/').to_string()],
+            verification_method: vec![VerificationMethod {
+                id: format!("{}{}", self.did, KEY_FRAGMENT),
+                kind: "JsonWebKey2020".to_string(),
+                controller: Some(self.did.clone()),
+                public_key_jwk: Some(self.public_jwk()),
+                public_key_multibase: None,
+            }],
+            service: vec![Service {
+                id: KEY_FRAGMENT.to_string(),
+                kind: Value::String(FORGE_SERVICE_TYPE.to_string()),
+                service_endpoint: Value::String(public_base_url.trim_end_matches('/').to_string()),
+            }],
+        }
+    }
+
+    /// Headers for a request to another instance.
+    pub fn sign_request(
+        &self,
+        method: &str,
+        path_and_query: &str,
+        timestamp: i64,
+        body: &[u8],
+    ) -> anyhow::Result<[(&'static str, String); 3]> {
+        let input = signing_input(method, path_and_query, timestamp, body);
+        let signature = self
+            .key_pair
+            .sign(&SystemRandom::new(), input.as_bytes())
+            .map_err(|_| anyhow!("failed to sign request"))?;
+        Ok([
+            (INSTANCE_HEADER, self.did.clone()),
+            (TIMESTAMP_HEADER, timestamp.to_string()),
+            (SIGNATURE_HEADER, URL_SAFE_NO_PAD.encode(signature.as_ref())),
+        ])
+    }
+}
+
+pub fn signing_input(method: &str, path_and_query: &str, timestamp: i64, body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}\n{}\n{}\n{}",
+        method.to_ascii_uppercase(),
+        path_and_query,
+        timestamp,
+        hex
+    )
+}
+
+/// Checks an ES256 signature against a P-256 JWK.
+pub fn verify_signature(jwk: &Value, input: &[u8], signature: &str) -> anyhow::Result<()> {
+    if jwk.get("kty").and_then(Value::as_str) != Some("EC")
+        || jwk.get("crv").and_then(Value::as_str) != Some("P-256")
+    {
+        bail!("signing key is not a P-256 key");
+    }
+    let coordinate = |name: &str| -> anyhow::Result<Vec<u8>> {
+        let encoded = jwk
+            .get(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("signing key has no `{}`", name))?;
+        let bytes = URL_SAFE_NO_PAD.decode(encoded)?;
+        if bytes.len() != 32 {
+            bail!("signing key `{}` has the wrong length", name);
+        }
+        Ok(bytes)
+    };
+    let mut point = vec![0x04];
+    point.extend(coordinate("x")?);
+    point.extend(coordinate("y")?);
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .context("signature is not base64url")?;
+    UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point)
+        .verify(input, &signature)
+        .map_err(|_| anyhow!("signature does not match"))
+}
+
+/// Why a signed request was refused.
+#[derive(Debug)]
+pub enum Rejection {
+    /// Headers missing or malformed
+    Unsigned(&'static str),
+    /// The instance is not in `federation.trusted_instances`
+    Untrusted(String),
+    /// Timestamp outside the allowed skew, or a replayed signature
+    Stale,
+    /// The instance's key could not be resolved
+    UnknownKey(anyhow::Error),
+    BadSignature(anyhow::Error),
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::Unsigned(reason) => write!(f, "unsigned request: {}", reason),
+            Rejection::Untrusted(did) => write!(f, "instance {} is not trusted", did),
+            Rejection::Stale => write!(
+                f,
+                "request is outside the allowed time window or was replayed"
+            ),
+            Rejection::UnknownKey(err) => write!(f, "cannot resolve instance key: {:#}", err),
+            Rejection::BadSignature(err) => write!(f, "invalid signature: {:#}", err),
+        }
+    }
+}
+
+/// Signatures seen recently, with the time they were accepted.
+static SEEN: LazyLock<Mutex<HashMap<String, i64>>> = LazyLock::new(Mutex::default);
+
+fn first_use(signature: &str, now: i64, window: i64) -> bool {
+    let mut seen = SEEN.lock().unwrap_or_else(|e| e.into_inner());
+    seen.retain(|_, accepted_at| now - *accepted_at <= 2 * window);
+    seen.insert(signature.to_string(), now).is_none()
+}
+
+/// Verifies a signed request and returns the sending instance's DID.
+pub async fn verify_request(
+    pool: &SqlitePool,
+    resolver: &DidResolver,
+    config: &FederationConfig,
+    header: impl Fn(&str) -> Option<String>,
+    method: &str,
+    path_and_query: &str,
+    body: &[u8],
+) -> Result<String, Rejection> {
+    let now = unix_now();
+    let instance = header(INSTANCE_HEADER).ok_or(Rejection::Unsigned("missing Forge-Instance"))?;
+    let timestamp: i64 = header(TIMESTAMP_HEADER)
+        .and_then(|value| value.parse().ok())
+        .ok_or(Rejection::Unsigned("missing or malformed Forge-Timestamp"))?;
+    let signature =
+        header(SIGNATURE_HEADER).ok_or(Rejection::Unsigned("missing Forge-Signature"))?;
+    if !instance.starts_with("did:web:") {
+        return Err(Rejection::Unsigned("instances are identified by did:web"));
+    }
+    if !config.trusted_instances.is_empty() && !config.trusted_instances.contains(&instance) {
+        return Err(Rejection::Untrusted(instance));
+    }
+    let window = config.max_clock_skew_secs as i64;
+    if (now - timestamp).abs() > window {
+        return Err(Rejection::Stale);
+    }
+
+    let resolved = resolver
+        .resolve(pool, &instance)
+        .await
+        .map_err(Rejection::UnknownKey)?;
+    let jwk = resolved
+        .document
+        .verification_method(KEY_FRAGMENT)
+        .and_then(|method| method.public_key_jwk.as_ref())
+        .ok_or_else(|| Rejection::UnknownKey(anyhow!("{} publishes no #forge key", instance)))?;
+    let input = signing_input(method, path_and_query, timestamp, body);
+    verify_signature(jwk, input.as_bytes(), &signature).map_err(Rejection::BadSignature)?;
+
+    if !first_use(&signature, now, window) {
+        return Err(Rejection::Stale);
+    }
+    Ok(instance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance_key(did: &str) -> InstanceKey {
+        let pkcs8 =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+                .unwrap();
+        InstanceKey::from_pkcs8(pkcs8.as_ref(), did.to_string()).unwrap()
+    }
+
+    #[test]
+    fn signatures_cover_method_path_time_and_body() {
+        let key = instance_key("did:web:forge.example");
+        let body = br#"{"repository":"team/app","number":3}"#;
+        let headers = key
+            .sign_request("POST", "/federation/issue-references", 1_700_000_000, body)
+            .unwrap();
+        let signature = &headers[2].1;
+        let jwk = key.public_jwk();
+
+        let input = signing_input("post", "/federation/issue-references", 1_700_000_000, body);
+        verify_signature(&jwk, input.as_bytes(), signature).unwrap();
+
+        let tampered = signing_input("POST", "/federation/issue-references", 1_700_000_000, b"{}");
+        assert!(verify_signature(&jwk, tampered.as_bytes(), signature).is_err());
+        let later = signing_input("POST", "/federation/issue-references", 1_700_000_001, body);
+        assert!(verify_signature(&jwk, later.as_bytes(), signature).is_err());
+        let other = instance_key("did:web:other.example").public_jwk();
+        assert!(verify_signature(&other, input.as_bytes(), signature).is_err());
+    }
+
+    #[test]
+    fn instance_documents_publish_the_key() {
+        let key = instance_key("did:web:forge.example");
+        let document = key.document("https://forge.example/");
+        let json = serde_json::to_string(&document).unwrap();
+        let parsed = DidDocument::parse("did:web:forge.example", &json).unwrap();
+        let method = parsed.verification_method(KEY_FRAGMENT).unwrap();
+        assert_eq!(method.public_key_jwk.as_ref(), Some(&key.public_jwk()));
+        assert!(parsed.service[0].has_type(FORGE_SERVICE_TYPE));
+    }
+
+    #[test]
+    fn signatures_are_accepted_once() {
+        assert!(first_use("sig-a", 1000, 300));
+        assert!(!first_use("sig-a", 1010, 300));
+        assert!(first_use("sig-b", 1010, 300));
+    }
+}
@@ -0,0 +1,97 @@
+//! Opaque global object IDs.
+//!
+//! Every `id` the API returns is a global ID: the URL-safe base64 of `<Type>:<internal id>`,
+//! such as `Repository:tz4a98xxat96iws9zmbrgj3a`. The type prefix lets `node(id:)` find
+//! any object from its ID alone and stops the ID of one kind of object from being passed
+//! where another is expected. Clients must not build or parse global IDs.
+//!
+//! Internal IDs were returned directly before. Arguments still accept them as legacy IDs
+//! while `ids.accept_legacy` is on. Every legacy ID is counted in
+//! `forge_graphql_legacy_ids_total`, so operators can see when the last client has moved
+//! over and close the window.
+
+use anyhow::{Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use metrics::counter;
+
+/// The global ID of the `type_name` object with internal ID `id`.
+pub fn encode(type_name: &str, id: &str) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{}:{}", type_name, id))
+}
+
+/// Splits a global ID into its type name and internal ID. Anything else, including a
+/// legacy ID, gives `None`.
+pub fn decode(global_id: &str) -> Option<(String, String)> {
+    let bytes = URL_SAFE_NO_PAD.decode(global_id).ok()?;
+    let text = String::from_utf8(bytes).ok()?;
+    let (type_name, id) = text.split_once(':')?;
+    let valid_type = type_name.starts_with(|c: char| c.is_ascii_uppercase())
+        && type_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+    (valid_type && !id.is_empty()).then(|| (type_name.to_string(), id.to_string()))
+}
+
+/// The internal ID of a `type_name` object passed as an argument, which may be a global
+/// ID or, during the deprecation window, a legacy one.
+pub fn internal_id(type_name: &str, value: &str) -> Result<String> {
+    parse(type_name, value, crate::config::current().ids.accept_legacy)
+}
+
+fn parse(type_name: &str, value: &str, accept_legacy: bool) -> Result<String> {
+    match decode(value) {
+        Some((actual, id)) if actual == type_name => Ok(id),
+        Some((actual, _)) => bail!("expected a {} ID but got a {} ID", type_name, actual),
+        None if accept_legacy && !value.is_empty() => {
+            counter!("forge_graphql_legacy_ids_total", "type" => type_name.to_string())
+                .increment(1);
+            Ok(value.to_string())
+        }
+        None => bail!("`{}` is not a valid {} ID", value, type_name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_ids_round_trip() {
+        let id = encode("Repository", "tz4a98xxat96iws9zmbrgj3a");
+        assert!(!id.contains("tz4a98"));
+        assert_eq!(
+            decode(&id),
+            Some((
+                "Repository".to_string(),
+                "tz4a98xxat96iws9zmbrgj3a".to_string()
+            ))
+        );
+        // Only the first colon separates the type
+        let issue = encode("Issue", "tz4a98xxat96iws9zmbrgj3a:7");
+        assert_eq!(decode(&issue).unwrap().1, "tz4a98xxat96iws9zmbrgj3a:7");
+    }
+
+    #[test]
+    fn legacy_ids_do_not_decode() {
+        for legacy in [
+            "tz4a98xxat96iws9zmbrgj3a",
+            "550e8400-e29b-41d4-a716-446655440000",
+            "",
+        ] {
+            assert_eq!(decode(legacy), None, "{legacy}");
+        }
+        assert_eq!(decode(&URL_SAFE_NO_PAD.encode("lower:1")), None);
+    }
+
+    #[test]
+    fn arguments_accept_legacy_ids_only_during_the_window() {
+        let global = encode("Group", "g1");
+        assert_eq!(parse("Group", &global, false).unwrap(), "g1");
+        assert_eq!(parse("Group", "g1", true).unwrap(), "g1");
+        assert!(parse("Group", "g1", false).is_err());
+
+        let err = parse("Repository", &global, true).unwrap_err();
+        assert!(err.to_string().contains("expected a Repository ID"));
+    }
+}
@@ -1 +1,4 @@
+pub mod global_id;
+pub mod pagination;
+pub mod registry;
 pub mod schema_composer;
@@ -0,0 +1,206 @@
+//! Relay-style cursor pagination for core list fields.
+//!
+//! Pages are read with keyset queries: a cursor encodes the `(slug, id)` sort key of a
+//! row, and the next page starts strictly after it. Unlike offsets, cursors do not shift
+//! when rows are added or removed elsewhere in the list, and a cursor keeps working after
+//! its own row is deleted. Cursors are opaque to clients; the `kind` prefix stops a
+//! repository cursor from being replayed against the groups list.
+
+use anyhow::{anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Which end of the list a page is read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// `first`/`after`
+    Forward,
+    /// `last`/`before`
+    Backward,
+}
+
+/// A sort key decoded from a cursor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CursorKey {
+    pub slug: String,
+    pub id: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PageRequest {
+    pub direction: Direction,
+    pub limit: usize,
+    pub cursor: Option<CursorKey>,
+}
+
+impl PageRequest {
+    /// Validates Relay connection arguments. `first` and `last` cannot be combined, and
+    /// neither can `after` and `before`.
+    pub fn from_args(
+        kind: &str,
+        first: Option<i64>,
+        after: Option<&str>,
+        last: Option<i64>,
+        before: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        if first.is_some() && last.is_some() {
+            bail!("pass either `first` or `last`, not both");
+        }
+        if after.is_some() && before.is_some() {
+            bail!("pass either `after` or `before`, not both");
+        }
+        if (first.is_some() && before.is_some()) || (last.is_some() && after.is_some()) {
+            bail!("`first` pages with `after`, and `last` pages with `before`");
+        }
+        let direction = if last.is_some() || before.is_some() {
+            Direction::Backward
+        } else {
+            Direction::Forward
+        };
+        let limit = match first.or(last) {
+            Some(n) if n < 0 => bail!("page size cannot be negative"),
+            Some(n) => (n as usize).min(MAX_PAGE_SIZE),
+            None => DEFAULT_PAGE_SIZE,
+        };
+        let cursor = after
+            .or(before)
+            .map(|cursor| decode_cursor(kind, cursor))
+            .transpose()?;
+        Ok(Self {
+            direction,
+            limit,
+            cursor,
+        })
+    }
+
+    /// The `WHERE` condition (if any) and `ORDER BY` clause for a table sorted by
+    /// `slug, id`. Bind the cursor's slug and id, in that order, when a condition is
+    /// returned, and fetch [`PageRequest::fetch_limit`] rows.
+    pub fn keyset_sql(&self) -> (Option<&'static str>, &'static str) {
+        match self.direction {
+            Direction::Forward => (
+                self.cursor.as_ref().map(|_| "(slug, id) > (?, ?)"),
+                "ORDER BY slug, id",
+            ),
+            Direction::Backward => (
+                self.cursor.as_ref().map(|_| "(slug, id) < (?, ?)"),
+                "ORDER BY slug DESC, id DESC",
+            ),
+        }
+    }
+
+    /// One extra row tells whether another page follows.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit as i64 + 1
+    }
+}
+
+/// One page of a connection, in list order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub has_next_page: bool,
+    pub has_previous_page: bool,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from rows fetched with [`PageRequest::keyset_sql`].
+    ///
+    /// Only the direction being paged in is checked with an extra row. The other side
+    /// reports whether a cursor was given, which is exact unless every row before it was
+    /// deleted.
+    pub fn from_rows(request: &PageRequest, mut rows: Vec<T>) -> Self {
+        let has_more = rows.len() > request.limit;
+        rows.truncate(request.limit);
+        match request.direction {
+            Direction::Forward => Self {
+                items: rows,
+                has_next_page: has_more,
+                has_previous_page: request.cursor.is_some(),
+            },
+            Direction::Backward => {
+                rows.reverse();
+                Self {
+                    items: rows,
+                    has_next_page: request.cursor.is_some(),
+                    has_previous_page: has_more,
+                }
+            }
+        }
+    }
+}
+
+pub fn encode_cursor(kind: &str, slug: &str, id: &str) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{}:{}\n{}", kind, slug, id))
+}
+
+pub fn decode_cursor(kind: &str, cursor: &str) -> anyhow::Result<CursorKey> {
+    let invalid = || anyhow!("invalid cursor `{}`", cursor);
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+    let text = String::from_utf8(bytes).map_err(|_| invalid())?;
+    let rest = text
+        .strip_prefix(kind)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .ok_or_else(invalid)?;
+    let (slug, id) = rest.split_once('\n').ok_or_else(invalid)?;
+    Ok(CursorKey {
+        slug: slug.to_string(),
+        id: id.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursors_round_trip_and_are_bound_to_their_kind() {
+        let cursor = encode_cursor("repository", "widgets", "r1");
+        assert_eq!(
+            decode_cursor("repository", &cursor).unwrap(),
+            CursorKey {
+                slug: "widgets".into(),
+                id: "r1".into()
+            }
+        );
+        assert!(decode_cursor("group", &cursor).is_err());
+        assert!(decode_cursor("repository", "not a cursor").is_err());
+    }
+
+    #[test]
+    fn arguments_are_validated() {
+        let request = PageRequest::from_args("group", None, None, None, None).unwrap();
+        assert_eq!(request.direction, Direction::Forward);
+        assert_eq!(request.limit, DEFAULT_PAGE_SIZE);
+
+        let request = PageRequest::from_args("group", None, None, Some(1000), None).unwrap();
+        assert_eq!(request.direction, Direction::Backward);
+        assert_eq!(request.limit, MAX_PAGE_SIZE);
+
+        let after = encode_cursor("group", "a", "1");
+        assert!(PageRequest::from_args("group", Some(1), None, Some(1), None).is_err());
+        assert!(PageRequest::from_args("group", Some(-1), None, None, None).is_err());
+        assert!(PageRequest::from_args("group", None, Some(&after), Some(1), None).is_err());
+        assert!(PageRequest::from_args("group", None, Some(&after), None, Some(&after)).is_err());
+    }
+
+    #[test]
+    fn pages_report_their_neighbours() {
+        let forward = PageRequest::from_args("group", Some(2), None, None, None).unwrap();
+        let page = Page::from_rows(&forward, vec![1, 2, 3]);
+        assert_eq!(page.items, [1, 2]);
+        assert!(page.has_next_page);
+        assert!(!page.has_previous_page);
+
+        // Backward pages are fetched in reverse and returned in list order.
+        let before = encode_cursor("group", "z", "9");
+        let backward = PageRequest::from_args("group", None, None, Some(2), Some(&before)).unwrap();
+        let page = Page::from_rows(&backward, vec![5, 4]);
+        assert_eq!(page.items, [4, 5]);
+        assert!(page.has_next_page);
+        assert!(!page.has_previous_page);
+    }
+}
@@ -0,0 +1,218 @@
+//! Publishes the composed supergraph to an external schema registry (GraphQL Hive or
+//! Apollo GraphOS).
+//!
+//! The supergraph is published at startup and after every extension reload (see
+//! [`crate::router::LiveRouter::publish_schemas`]). Publishing is incremental: the
+//! SHA-256 of the last successfully published SDL is kept next to the database, and
+//! unchanged schemas are skipped. Every failure is logged and swallowed so a registry
+//! outage never keeps the server from starting or an extension from reloading.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Value as JsonValue, json};
+use sha2::{Digest, Sha256};
+
+use crate::config::{SchemaRegistryConfig, SchemaRegistryProvider};
+
+const HIVE_DEFAULT_ENDPOINT: &str = "https://app.graphql-hive.com/graphql";
+const APOLLO_DEFAULT_ENDPOINT: &str = "https://api.apollographql.com/api/graphql";
+
+const HIVE_PUBLISH_MUTATION: &str = r#"mutation schemaPublish($input: SchemaPublishInput!) {
+  schemaPublish(input: $input) { __typename }
+}"#;
+
+const APOLLO_UPLOAD_MUTATION: &str = r#"mutation uploadSchema($id: ID!, $tag: String!, $schemaDocument: String!) {
+  service(id: $id) { uploadSchema(tag: $tag, schemaDocument: $schemaDocument) { success message } }
+}"#;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PublishOutcome {
+    Unchanged,
+    DryRun,
+    Published,
+}
+
+pub struct SchemaRegistryPublisher {
+    config: SchemaRegistryConfig,
+    state_path: PathBuf,
+    client: reqwest::Client,
+}
+
+impl SchemaRegistryPublisher {
+    pub fn new(config: SchemaRegistryConfig, state_dir: PathBuf) -> Self {
+        Self {
+            config,
+            state_path: state_dir.join("schema-registry.sha256"),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn endpoint(&self) -> &str {
+        match (&self.config.endpoint, self.config.provider) {
+            (Some(endpoint), _) => endpoint,
+            (None, SchemaRegistryProvider::Hive) => HIVE_DEFAULT_ENDPOINT,
+            (None, SchemaRegistryProvider::Apollo) => APOLLO_DEFAULT_ENDPOINT,
+        }
+    }
+
+    /// Publishes `sdl` if it differs from the last published schema.
+    pub async fn publish_if_changed(&self, sdl: &str) -> Result<PublishOutcome> {
+        let digest = schema_digest(sdl);
+        let previous = tokio::fs::read_to_string(&self.state_path).await.ok();
+        if previous.as_deref().map(str::trim) == Some(digest.as_str()) {
+            return Ok(PublishOutcome::Unchanged);
+        }
+
+        let body = build_publish_body(&self.config, sdl, &digest)?;
+        if self.config.dry_run {
+            tracing::info!(
+                endpoint = self.endpoint(),
+                digest = %digest,
+                "schema registry dry run; would publish {} bytes of SDL",
+                sdl.len()
+            );
+            return Ok(PublishOutcome::DryRun);
+        }
+
+        let token = std::env::var(&self.config.token_env)
+            .with_context(|| format!("{} is not set", self.config.token_env))?;
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match self.send(&body, &token).await {
+                Ok(()) => break,
+                Err(err) if attempt < self.config.max_attempts.max(1) => {
+                    tracing::warn!(
+                        "schema registry publish attempt {} failed: {}",
+                        attempt,
+                        err
+                    );
+                    tokio::time::sleep(Duration::from_secs(1 << attempt.min(5))).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        if let Some(parent) = self.state_path.parent() {
+            tokio::fs::create_dir_all(parent).await.ok();
+        }
+        tokio::fs::write(&self.state_path, &digest).await?;
+        Ok(PublishOutcome::Published)
+    }
+
+    async fn send(&self, body: &JsonValue, token: &str) -> Result<()> {
+        let request = self.client.post(self.endpoint()).json(body);
+        let request = match self.config.provider {
+            SchemaRegistryProvider::Hive => request.bearer_auth(token),
+            SchemaRegistryProvider::Apollo => request
+                .header("x-api-key", token)
+                .header("apollographql-client-name", "forge"),
+        };
+        let response = request.send().await?;
+        let status = response.status();
+        let payload: JsonValue = response.json().await.unwrap_or(JsonValue::Null);
+        if !status.is_success() {
+            return Err(anyhow!("registry returned HTTP {}", status));
+        }
+        if let Some(errors) = payload.get("errors").filter(|e| !e.is_null()) {
+            return Err(anyhow!("registry rejected schema: {}", errors));
+        }
+        Ok(())
+    }
+}
+
+pub fn schema_digest(sdl: &str) -> String {
+    Sha256::digest(sdl.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn build_publish_body(config: &SchemaRegistryConfig, sdl: &str, digest: &str) -> Result<JsonValue> {
+    Ok(match config.provider {
+        SchemaRegistryProvider::Hive => json!({
+            "query": HIVE_PUBLISH_MUTATION,
+            "variables": {
+                "input": { "sdl": sdl, "author": "forge", "commit": digest }
+            }
+        }),
+        SchemaRegistryProvider::Apollo => {
+            let graph_ref = config
+                .graph_ref
+                .as_deref()
+                .ok_or_else(|| anyhow!("Apollo publishing requires graph_ref"))?;
+            let (graph_id, variant) = graph_ref.split_once('@').unwrap_or((graph_ref, "current"));
+            json!({
+                "query": APOLLO_UPLOAD_MUTATION,
+                "variables": { "id": graph_id, "tag": variant, "schemaDocument": sdl }
+            })
+        }
+    })
+}
+
+/// Publishes once and logs the outcome. Errors are reported but never propagated.
+pub async fn publish_supergraph(publisher: &SchemaRegistryPublisher, sdl: &str) {
+    match publisher.publish_if_changed(sdl).await {
+        Ok(PublishOutcome::Published) => tracing::info!("published supergraph to schema registry"),
+        Ok(PublishOutcome::Unchanged) => {
+            tracing::debug!("supergraph unchanged; skipping registry publish")
+        }
+        Ok(PublishOutcome::DryRun) => {}
+        Err(err) => tracing::warn!("schema registry publish failed: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(provider: SchemaRegistryProvider) -> SchemaRegistryConfig {
+        SchemaRegistryConfig {
+            provider,
+            endpoint: None,
+            token_env: "FORGE_TEST_REGISTRY_TOKEN".to_string(),
+            graph_ref: Some("forge@staging".to_string()),
+            dry_run: true,
+            max_attempts: 1,
+        }
+    }
+
+    #[test]
+    fn apollo_body_splits_graph_ref() {
+        let body = build_publish_body(
+            &config(SchemaRegistryProvider::Apollo),
+            "type Query { a: Int }",
+            "d",
+        )
+        .unwrap();
+        assert_eq!(body["variables"]["id"], "forge");
+        assert_eq!(body["variables"]["tag"], "staging");
+    }
+
+    #[tokio::test]
+    async fn unchanged_schema_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let publisher = SchemaRegistryPublisher::new(
+            config(SchemaRegistryProvider::Hive),
+            dir.path().to_path_buf(),
+        );
+        let sdl = "type Query { a: Int }";
+        assert_eq!(
+            publisher.publish_if_changed(sdl).await.unwrap(),
+            PublishOutcome::DryRun
+        );
+
+        std::fs::write(
+            dir.path().join("schema-registry.sha256"),
+            schema_digest(sdl),
+        )
+        .unwrap();
+        assert_eq!(
+            publisher.publish_if_changed(sdl).await.unwrap(),
+            PublishOutcome::Unchanged
+        );
+    }
+}
@@ -1,10 +1,19 @@
 use anyhow::{Context, Result};
 use graphql_parser::Pos;
 use graphql_parser::schema::{
-    Definition, Directive, Document, EnumType, EnumValue, InputObjectType, InterfaceType,
-    ObjectType, ScalarType, TypeDefinition, TypeExtension, UnionType, Value,
+    Definition, Directive, Document, EnumType, EnumValue, Field, InputObjectType, InterfaceType,
+    ObjectType, ScalarType, Type, TypeDefinition, TypeExtension, UnionType, Value,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+/// Which fields a composed supergraph exposes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaVisibility {
+    /// Leaves out fields marked `@internal`, and types that only those fields used.
+    Public,
+    /// Keeps every field, for administrators.
+    Internal,
+}
 
 /// Composes the core supergraph SDL with GraphQL federation fragments supplied by extensions.
 ///
@@ -38,7 +47,12 @@ impl SchemaComposer {
         Ok(())
     }
 
+    /// The public supergraph.
     pub fn compose(&self) -> Result<String> {
+        self.compose_for(SchemaVisibility::Public)
+    }
+
+    pub fn compose_for(&self, visibility: SchemaVisibility) -> Result<String> {
         let mut supergraph = self.core_schema.clone();
 
         for (name, document) in &self.subgraphs {
@@ -47,6 +61,9 @@ impl SchemaComposer {
             merge_extension_into_supergraph(&mut supergraph, &graph_name, name, document)
                 .with_context(|| format!("failed to merge extension `{name}`"))?;
         }
+        if visibility == SchemaVisibility::Public {
+            strip_internal_fields(&mut supergraph);
+        }
 
         let serialised = format!("{}", supergraph);
         tracing::debug!("Final supergraph SDL:\n{}", serialised);
@@ -60,12 +77,16 @@ impl Default for SchemaComposer {
     }
 }
 
+/// The interface of objects that `node(id:)` can resolve
+const NODE: &str = "Node";
+
 const CORE_SUPERGRAPH_SDL: &str = r#"
 schema
   @link(url: "https://specs.apollo.dev/link/v1.0")
   @link(url: "https://specs.apollo.dev/join/v0.3", for: EXECUTION) {
   query: Query
   mutation: Mutation
+  subscription: Subscription
 }
 
 directive @join__enumValue(graph: join__Graph!) repeatable on ENUM_VALUE
@@ -95,100 +116,1377 @@ directive @join__type(
   isInterfaceObject: Boolean! = false
 ) repeatable on OBJECT | INTERFACE | UNION | ENUM | INPUT_OBJECT | SCALAR
 
-directive @join__unionMember(
-  graph: join__Graph!
-  member: String!
-) repeatable on UNION
+directive @join__unionMember(
+  graph: join__Graph!
+  member: String!
+) repeatable on UNION
+
+directive @link(
+  url: String
+  as: String
+  for: link__Purpose
+  import: [link__Import]
+) repeatable on SCHEMA
+
+scalar join__FieldSet
+
+enum link__Purpose {
+  SECURITY
+  EXECUTION
+}
+
+scalar link__Import
+
+# Enforced and emitted by the router; see `router::directives`.
+directive @rateLimit(max: Int!, window: Int!) on FIELD_DEFINITION
+
+# Only administrators can query the field; see `SchemaVisibility`.
+directive @internal on FIELD_DEFINITION
+
+directive @cacheControl(
+  maxAge: Int
+  scope: CacheControlScope
+) on FIELD_DEFINITION | OBJECT | INTERFACE | UNION
+
+enum CacheControlScope @join__type(graph: CORE) {
+  PUBLIC @join__enumValue(graph: CORE)
+  PRIVATE @join__enumValue(graph: CORE)
+}
+
+scalar Upload @join__type(graph: CORE)
+
+# Objects with a global ID that `node` resolves
+interface Node @join__type(graph: CORE) {
+  id: ID!
+}
+
+type Query @join__type(graph: CORE) {
+  # Core fields
+  node(id: ID!): Node @join__field(graph: CORE)
+  groups(first: Int, after: String, last: Int, before: String): GroupConnection! @join__field(graph: CORE)
+  repositories(first: Int, after: String, last: Int, before: String): RepositoryConnection! @join__field(graph: CORE)
+  findRepositories(query: String, sort: RepositorySort, first: Int, after: String): RepositorySearchConnection! @join__field(graph: CORE) @rateLimit(max: 60, window: 60)
+  repositorySearchScore(path: String!, query: String!): RepositorySearchScore @join__field(graph: CORE)
+  getAllGroups: [GroupNode!]! @join__field(graph: CORE) @deprecated(reason: "Unbounded; page through `groups` instead.")
+  getAllRepositories: [RepositoryNode!]! @join__field(graph: CORE) @deprecated(reason: "Unbounded; page through `repositories` instead.")
+  getGroup(path: String!): GroupNode @join__field(graph: CORE)
+  getRepository(path: String!, readmeLanguage: String): RepositoryNode @join__field(graph: CORE)
+  browseRepository(path: String!, treePath: String, branch: String, project: String): RepositoryEntriesPayload @join__field(graph: CORE)
+  listRepositoryBranches(path: String!): [RepositoryBranch!] @join__field(graph: CORE)
+  listCommits(path: String!, branch: String, limit: Int, offset: Int): [Commit!] @join__field(graph: CORE)
+  getCommit(path: String!, oid: String!): Commit @join__field(graph: CORE)
+  mergePreview(path: String!, base: String!, head: String!): MergePreview @join__field(graph: CORE)
+  findFiles(path: String!, query: String!, rev: String, first: Int, project: String): [FileMatch!] @join__field(graph: CORE) @rateLimit(max: 60, window: 60)
+  symbols(path: String!, query: String!, first: Int): [CodeSymbol!] @join__field(graph: CORE) @rateLimit(max: 60, window: 60)
+  definitionsInFile(path: String!, filePath: String!): [CodeSymbol!] @join__field(graph: CORE)
+  symbolIndex(path: String!): SymbolIndex @join__field(graph: CORE)
+  contributorStats(path: String!, since: Int, until: Int): ContributorStats @join__field(graph: CORE) @rateLimit(max: 60, window: 60)
+  searchCode(query: String!, path: String, language: String, first: Int): [CodeSearchMatch!]! @join__field(graph: CORE) @rateLimit(max: 60, window: 60)
+  repositoryProjects(path: String!, rev: String): [RepositoryProject!] @join__field(graph: CORE)
+  detectProjectMetadata(path: String!): ProjectMetadata @join__field(graph: CORE)
+  commitGraph(path: String!, ref: String, limit: Int): CommitGraph @join__field(graph: CORE)
+  compareRefs(path: String!, base: String!, head: String!, limit: Int): RefComparison @join__field(graph: CORE)
+  compareAcrossForks(basePath: String!, baseRef: String!, headPath: String!, headRef: String!, limit: Int): RefComparison @join__field(graph: CORE)
+  diff(path: String!, base: String!, head: String!, mergeBase: Boolean): Diff @join__field(graph: CORE) @rateLimit(max: 60, window: 60)
+  searchCommits(path: String!, branch: String, query: String, author: String, since: Int, until: Int, first: Int, after: String): CommitSearchConnection @join__field(graph: CORE) @rateLimit(max: 60, window: 60)
+  extensionFieldStats(limit: Int): [ExtensionFieldStats!]! @join__field(graph: CORE)
+  extensionCacheHealth: [ExtensionCacheCheck!]! @join__field(graph: CORE)
+  extensionUpgrades(extension: String, first: Int): [ExtensionUpgrade!]! @join__field(graph: CORE)
+  extensionQueryAudit(extension: String, first: Int): [ExtensionQueryAudit!]! @join__field(graph: CORE)
+  extensionSchedules(extension: String): [ExtensionSchedule!]! @join__field(graph: CORE)
+  extensionTaskRuns(extension: String, task: String, first: Int): [ExtensionTaskRun!]! @join__field(graph: CORE)
+  repositoryFsck(path: String!): IntegrityReport! @join__field(graph: CORE)
+  schemaFieldUsage(unusedForDays: Int, deprecatedOnly: Boolean): [SchemaFieldUsage!]! @join__field(graph: CORE)
+  unusedSchemaTypes(unusedForDays: Int): [String!]! @join__field(graph: CORE)
+  activeAnnouncements: [Announcement!]! @join__field(graph: CORE) @cacheControl(maxAge: 30, scope: PUBLIC)
+  announcements: [Announcement!]! @join__field(graph: CORE)
+  instanceSettings: InstanceSettings! @join__field(graph: CORE)
+  maintenanceMode: MaintenanceMode! @join__field(graph: CORE)
+  featureFlags(repositoryPath: String): [FeatureFlag!]! @join__field(graph: CORE)
+  featureFlagDefinitions: [FeatureFlagDefinition!]! @join__field(graph: CORE)
+  complianceArchive(kind: String, since: Int, until: Int, first: Int): [ComplianceRecord!]! @join__field(graph: CORE)
+  auditLog(filter: AuditLogFilter, first: Int, after: String, last: Int, before: String): AuditLogConnection! @join__field(graph: CORE)
+  auditLogExports: [AuditLogExport!]! @join__field(graph: CORE)
+  auditLogRetention: [AuditLogRetention!]! @join__field(graph: CORE)
+  groupUsage(path: String!): GroupUsage @join__field(graph: CORE)
+  usageExports: [UsageExport!]! @join__field(graph: CORE)
+  moderationQueue(status: ModerationStatus, first: Int): [ModerationItem!]! @join__field(graph: CORE)
+  readRepositoryFile(path: String!, filePath: String!, branch: String): RepositoryFilePayload @join__field(graph: CORE)
+  blameRepositoryFile(path: String!, filePath: String!, branch: String): FileBlame @join__field(graph: CORE) @rateLimit(max: 60, window: 60)
+  user(handle: String!): UserProfile @join__field(graph: CORE)
+  viewerContributions: UserContributions @join__field(graph: CORE)
+  federatedIssueReferences(path: String!, number: Int!): [FederatedIssueReference!]! @join__field(graph: CORE)
+  repositorySecrets(path: String!): [RepositorySecret!]! @join__field(graph: CORE)
+  repositorySecretAudit(path: String!, first: Int): [RepositorySecretAccess!]! @join__field(graph: CORE)
+  repositoryShareLinks(path: String!): [ShareLink!]! @join__field(graph: CORE)
+  # Without a path, lists the instance webhooks
+  webhooks(path: String): [Webhook!]! @join__field(graph: CORE)
+  webhook(id: ID!): Webhook @join__field(graph: CORE)
+  # Background jobs, newest first; admin only
+  jobs(kind: String, status: JobStatus, first: Int, after: String, last: Int, before: String): JobConnection! @join__field(graph: CORE)
+  job(id: ID!): Job @join__field(graph: CORE)
+  repositoryShareLinkAudit(path: String!, first: Int): [ShareLinkEvent!]! @join__field(graph: CORE)
+  checkRepositoryPolicy(path: String!, updates: [RefUpdateInput!]!): PolicyCheckResult @join__field(graph: CORE)
+  validateCommitMessage(path: String!, message: String!): PolicyValidation @join__field(graph: CORE)
+  validateBranchName(path: String!, name: String!): PolicyValidation @join__field(graph: CORE)
+  savedSearches(scope: SearchScope): [SavedSearch!]! @join__field(graph: CORE)
+  accountExports: [AccountExport!]! @join__field(graph: CORE)
+  viewerSessions: [ViewerSession!]! @join__field(graph: CORE)
+  viewerSshKeys: [SshKey!]! @join__field(graph: CORE)
+  viewerAccessTokens: [AccessToken!]! @join__field(graph: CORE)
+  viewerPushSubscriptions: [PushSubscription!]! @join__field(graph: CORE)
+  viewerNotificationDigestFrequency: NotificationDigestFrequency! @join__field(graph: CORE)
+  webPushPublicKey: String @join__field(graph: CORE)
+}
+
+type Mutation @join__type(graph: CORE) {
+  # Core mutations
+  createGroup(input: CreateGroupInput!): GroupNode! @join__field(graph: CORE)
+  setGroupSettings(path: String!, settings: GroupSettingsInput!): GroupNode! @join__field(graph: CORE)
+  createRepository(input: CreateRepositoryInput!): RepositoryNode! @join__field(graph: CORE)
+  linkRemoteRepository(url: String!): RepositoryNode! @join__field(graph: CORE)
+  importRepositoryFromPath(localPath: String!, slug: String!, group: ID): RepositoryNode! @join__field(graph: CORE)
+  importRepositoryBundle(bundle: Upload!, slug: String!, group: ID): RepositoryNode! @join__field(graph: CORE)
+  starRepository(id: ID!): RepositoryNode! @join__field(graph: CORE)
+  unstarRepository(id: ID!): RepositoryNode! @join__field(graph: CORE)
+  setRepositoryPolicy(path: String!, rules: String!): RepositoryPolicy! @join__field(graph: CORE)
+  setRepositorySettings(path: String!, labels: [LabelInput!], visibility: RepositoryVisibility): RepositoryNode! @join__field(graph: CORE)
+  setIssueClosing(path: String!, enabled: Boolean!, branches: [String!]): RepositoryNode! @join__field(graph: CORE)
+  setRepositoryProjectRoots(path: String!, roots: [String!]!): RepositoryNode! @join__field(graph: CORE)
+  setRepositoryDescription(path: String!, description: String, language: String): RepositoryNode! @join__field(graph: CORE)
+  saveSearch(name: String!, scope: SearchScope!, query: String!): SavedSearch! @join__field(graph: CORE)
+  deleteSavedSearch(id: ID!): Boolean! @join__field(graph: CORE)
+  watchRemoteTags(path: String!, semverRange: String): RemoteTagWatch! @join__field(graph: CORE)
+  unwatchRemoteTags(path: String!): Boolean! @join__field(graph: CORE)
+  rotateMirrorWebhookSecret(path: String!): MirrorWebhook! @join__field(graph: CORE)
+  setRepositorySecret(path: String!, name: String!, value: String!, extensions: [String!]): RepositorySecret! @join__field(graph: CORE)
+  deleteRepositorySecret(path: String!, name: String!): Boolean! @join__field(graph: CORE)
+  # expiresIn is in seconds, from one minute to 90 days
+  createShareLink(path: String!, expiresIn: Int!, allowClone: Boolean): NewShareLink! @join__field(graph: CORE)
+  revokeShareLink(id: ID!): ShareLink @join__field(graph: CORE)
+  # Without a path, creates an instance webhook that receives every event
+  createWebhook(path: String, url: String!, secret: String!, events: [String!], active: Boolean): Webhook! @join__field(graph: CORE)
+  updateWebhook(id: ID!, url: String, secret: String, events: [String!], active: Boolean): Webhook! @join__field(graph: CORE)
+  deleteWebhook(id: ID!): Boolean! @join__field(graph: CORE)
+  # Sends a ping event, even to a disabled webhook; ten tests and redeliveries per webhook a minute
+  testWebhook(id: ID!): WebhookDelivery! @join__field(graph: CORE)
+  # Sends a delivery's payload again, to the webhook's current URL and signed with its current secret
+  redeliverWebhookDelivery(deliveryId: ID!): WebhookDelivery! @join__field(graph: CORE)
+  disableMirrorWebhook(path: String!): Boolean! @join__field(graph: CORE)
+  deleteRepository(path: String!): Boolean! @join__field(graph: CORE)
+  renameRepository(path: String!, slug: String!): RepositoryNode! @join__field(graph: CORE)
+  transferRepository(path: String!, group: ID): RepositoryNode! @join__field(graph: CORE)
+  cherryPickCommit(path: String!, branch: String!, oid: String!): CommitOperationResult! @join__field(graph: CORE)
+  revertCommit(path: String!, branch: String!, oid: String!): CommitOperationResult! @join__field(graph: CORE)
+  applySuggestion(commentId: ID!): SuggestionResult! @join__field(graph: CORE)
+  requestAccountExport: AccountExport! @join__field(graph: CORE)
+  requestAuditLogExport(format: AuditLogExportFormat!, filter: AuditLogFilter): AuditLogExport! @join__field(graph: CORE)
+  revokeSession(id: ID!): Boolean! @join__field(graph: CORE)
+  addSshKey(title: String!, publicKey: String!): SshKey! @join__field(graph: CORE)
+  removeSshKey(id: ID!): Boolean! @join__field(graph: CORE)
+  createAccessToken(name: String!, expiresInDays: Int): NewAccessToken! @join__field(graph: CORE)
+  revokeAccessToken(id: ID!): Boolean! @join__field(graph: CORE)
+  registerPushSubscription(endpoint: String!, keys: PushSubscriptionKeysInput!, expiresAt: Int): PushSubscription! @join__field(graph: CORE)
+  unregisterPushSubscription(endpoint: String!): Boolean! @join__field(graph: CORE)
+  setNotificationDigestFrequency(frequency: NotificationDigestFrequency!): NotificationDigestFrequency! @join__field(graph: CORE)
+  createAnnouncement(input: CreateAnnouncementInput!): Announcement! @join__field(graph: CORE)
+  deleteAnnouncement(id: ID!): Boolean! @join__field(graph: CORE)
+  completeSetup(token: String!, adminHandle: String!): InstanceSettings! @join__field(graph: CORE) @rateLimit(max: 10, window: 60)
+  updateInstanceSettings(name: String, description: String, defaultVisibility: RepositoryVisibility): InstanceSettings! @join__field(graph: CORE)
+  setMaintenanceMode(enabled: Boolean!, message: String): MaintenanceMode! @join__field(graph: CORE)
+  setFeatureFlag(key: String!, enabled: Boolean!, rolloutPercent: Int, description: String): FeatureFlagDefinition! @join__field(graph: CORE)
+  deleteFeatureFlag(key: String!): Boolean! @join__field(graph: CORE)
+  setFeatureFlagOverride(key: String!, repositoryPath: String!, enabled: Boolean): FeatureFlagDefinition! @join__field(graph: CORE)
+  repairRepository(path: String!): RepairReport! @join__field(graph: CORE)
+  # Reads every repository into the index again; returns the rebuild already queued or running, if any
+  rebuildSearchIndex(scope: SearchIndexScope!): Job! @join__field(graph: CORE)
+  # Loads the extension's module again and re-composes the schema; a missing module unloads it
+  reloadExtension(name: String!): ExtensionReload! @join__field(graph: CORE)
+  approveModerationItem(id: ID!): ModerationItem! @join__field(graph: CORE)
+  removeModerationItem(id: ID!): ModerationItem! @join__field(graph: CORE)
+}
+
+# Served over WebSocket; see `router::subscriptions`
+type Subscription @join__type(graph: CORE) {
+  repositoryUpdated(path: String!): RepositoryUpdate! @join__field(graph: CORE)
+  issueCreated(path: String!): IssueCreated! @join__field(graph: CORE)
+}
+
+type RepositoryUpdate @join__type(graph: CORE) {
+  kind: String! @join__field(graph: CORE)
+  actor: String @join__field(graph: CORE)
+  occurredAt: Int! @join__field(graph: CORE)
+  repository: RepositoryNode @join__field(graph: CORE)
+}
+
+type IssueCreated @join__type(graph: CORE) {
+  number: Int! @join__field(graph: CORE)
+  title: String! @join__field(graph: CORE)
+  status: String @join__field(graph: CORE)
+  actor: String @join__field(graph: CORE)
+  occurredAt: Int! @join__field(graph: CORE)
+  repository: RepositoryNode @join__field(graph: CORE)
+}
+
+# Core types
+type GroupNode implements Node @join__type(graph: CORE) @join__implements(graph: CORE, interface: "Node") {
+  id: ID! @join__field(graph: CORE)
+  slug: String! @join__field(graph: CORE)
+  parent: GroupSummary @join__field(graph: CORE)
+  repositories: [RepositorySummary!]! @join__field(graph: CORE)
+  settings: GroupSettings! @join__field(graph: CORE)
+}
+
+type GroupSettings @join__type(graph: CORE) {
+  labels: [RepositoryLabel!] @join__field(graph: CORE)
+  branchProtection: RepositoryPolicy @join__field(graph: CORE)
+  visibility: RepositoryVisibility @join__field(graph: CORE)
+}
+
+type PageInfo @join__type(graph: CORE) {
+  hasNextPage: Boolean! @join__field(graph: CORE)
+  hasPreviousPage: Boolean! @join__field(graph: CORE)
+  startCursor: String @join__field(graph: CORE)
+  endCursor: String @join__field(graph: CORE)
+}
+
+type GroupConnection @join__type(graph: CORE) {
+  edges: [GroupEdge!]! @join__field(graph: CORE)
+  nodes: [GroupNode!]! @join__field(graph: CORE)
+  pageInfo: PageInfo! @join__field(graph: CORE)
+  totalCount: Int! @join__field(graph: CORE)
+}
+
+type GroupEdge @join__type(graph: CORE) {
+  cursor: String! @join__field(graph: CORE)
+  node: GroupNode! @join__field(graph: CORE)
+}
+
+type RepositoryConnection @join__type(graph: CORE) {
+  edges: [RepositoryEdge!]! @join__field(graph: CORE)
+  nodes: [RepositoryNode!]! @join__field(graph: CORE)
+  pageInfo: PageInfo! @join__field(graph: CORE)
+  totalCount: Int! @join__field(graph: CORE)
+}
+
+type RepositoryEdge @join__type(graph: CORE) {
+  cursor: String! @join__field(graph: CORE)
+  node: RepositoryNode! @join__field(graph: CORE)
+}
+
+type RepositorySearchConnection @join__type(graph: CORE) {
+  edges: [RepositorySearchEdge!]! @join__field(graph: CORE)
+  nodes: [RepositoryNode!]! @join__field(graph: CORE)
+  pageInfo: PageInfo! @join__field(graph: CORE)
+}
+
+type RepositorySearchEdge @join__type(graph: CORE) {
+  cursor: String! @join__field(graph: CORE)
+  node: RepositoryNode! @join__field(graph: CORE)
+  # Set when the search had a query
+  score: RepositorySearchScore @join__field(graph: CORE)
+}
+
+# Parts of a repository's relevance to a discovery query
+type RepositorySearchScore @join__type(graph: CORE) {
+  total: Float! @join__field(graph: CORE)
+  name: Float! @join__field(graph: CORE)
+  description: Float! @join__field(graph: CORE)
+  exact: Float! @join__field(graph: CORE)
+  matches: Boolean! @join__field(graph: CORE)
+}
+
+enum RepositorySort @join__type(graph: CORE) {
+  RELEVANCE @join__enumValue(graph: CORE)
+  STARS @join__enumValue(graph: CORE)
+  PUSHED @join__enumValue(graph: CORE)
+  CREATED @join__enumValue(graph: CORE)
+  NAME @join__enumValue(graph: CORE)
+}
+
+type GroupSummary @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  slug: String! @join__field(graph: CORE)
+}
+
+type RepositoryNode implements Node @join__type(graph: CORE) @join__implements(graph: CORE, interface: "Node") {
+  id: ID! @join__field(graph: CORE)
+  slug: String! @join__field(graph: CORE)
+  fullPath: String! @join__field(graph: CORE)
+  group: GroupSummary @join__field(graph: CORE)
+  isRemote: Boolean! @join__field(graph: CORE)
+  remoteUrl: String @join__field(graph: CORE)
+  readmeHtml(branch: String, language: String): String @join__field(graph: CORE)
+  description(language: String): String @join__field(graph: CORE)
+  descriptions: [RepositoryDescription!]! @join__field(graph: CORE)
+  pushPolicy: RepositoryPolicy! @join__field(graph: CORE)
+  effectiveSettings: EffectiveSettings! @join__field(graph: CORE)
+  issueClosing: IssueClosingSettings! @join__field(graph: CORE)
+  projectRoots: [String!]! @join__field(graph: CORE)
+  cloneStatus: RemoteCloneStatus @join__field(graph: CORE)
+  tagWatch: RemoteTagWatch @join__field(graph: CORE)
+  starCount: Int! @join__field(graph: CORE)
+  createdAt: Int @join__field(graph: CORE)
+  pushedAt: Int @join__field(graph: CORE)
+}
+
+type RepositoryDescription @join__type(graph: CORE) {
+  language: String @join__field(graph: CORE)
+  description: String! @join__field(graph: CORE)
+  updatedAt: Int! @join__field(graph: CORE)
+}
+
+type RepositorySummary @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  slug: String! @join__field(graph: CORE)
+  isRemote: Boolean! @join__field(graph: CORE)
+  remoteUrl: String @join__field(graph: CORE)
+}
+
+type RepositoryEntriesPayload @join__type(graph: CORE) {
+  treePath: String @join__field(graph: CORE)
+  entries: [RepositoryEntry!]! @join__field(graph: CORE)
+}
+
+type RepositoryFilePayload @join__type(graph: CORE) {
+  path: String! @join__field(graph: CORE)
+  name: String! @join__field(graph: CORE)
+  size: Int! @join__field(graph: CORE)
+  isBinary: Boolean! @join__field(graph: CORE)
+  text: String @join__field(graph: CORE)
+  truncated: Boolean! @join__field(graph: CORE)
+}
+
+type RepositoryEntry @join__type(graph: CORE) {
+  name: String! @join__field(graph: CORE)
+  path: String! @join__field(graph: CORE)
+  type: EntryType! @join__field(graph: CORE)
+  size: Int @join__field(graph: CORE)
+}
+
+type RepositoryBranch @join__type(graph: CORE) {
+  name: String! @join__field(graph: CORE)
+  reference: String! @join__field(graph: CORE)
+  target: String @join__field(graph: CORE)
+  isDefault: Boolean! @join__field(graph: CORE)
+}
+
+type UserProfile @join__type(graph: CORE) {
+  did: ID! @join__field(graph: CORE)
+  handle: String! @join__field(graph: CORE)
+  displayName: String @join__field(graph: CORE)
+  avatar: String @join__field(graph: CORE)
+  repositories: [RepositorySummary!]! @join__field(graph: CORE)
+  starredRepositories: [RepositorySummary!]! @join__field(graph: CORE)
+  recentActivity(limit: Int): [ActivityEvent!]! @join__field(graph: CORE)
+  contributions: UserContributions! @join__field(graph: CORE)
+  links: [ProfileLink!]! @join__field(graph: CORE)
+}
+
+type ProfileLink @join__type(graph: CORE) {
+  url: String! @join__field(graph: CORE)
+  kind: ProfileLinkKind! @join__field(graph: CORE)
+  verified: Boolean! @join__field(graph: CORE)
+}
+
+type FederatedIssueReference @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  issueNumber: Int! @join__field(graph: CORE)
+  instance: String! @join__field(graph: CORE)
+  sourceUrl: String! @join__field(graph: CORE)
+  title: String @join__field(graph: CORE)
+  actorDid: String @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+}
+
+type UserContributions @join__type(graph: CORE) {
+  did: ID! @join__field(graph: CORE)
+  repositoriesOwned: Int! @join__field(graph: CORE)
+  starsGiven: Int! @join__field(graph: CORE)
+  activityCount: Int! @join__field(graph: CORE)
+  days: [ContributionDay!]! @join__field(graph: CORE)
+}
+
+type ContributionDay @join__type(graph: CORE) {
+  date: String! @join__field(graph: CORE)
+  count: Int! @join__field(graph: CORE)
+}
+
+type ActivityEvent @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  kind: String! @join__field(graph: CORE)
+  summary: String! @join__field(graph: CORE)
+  repository: RepositorySummary @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+}
+
+type MergePreview @join__type(graph: CORE) {
+  baseOid: String! @join__field(graph: CORE)
+  headOid: String! @join__field(graph: CORE)
+  mergeable: Boolean! @join__field(graph: CORE)
+  conflicts: [MergeConflict!]! @join__field(graph: CORE)
+  cached: Boolean! @join__field(graph: CORE)
+}
+
+type RepositorySecret @join__type(graph: CORE) {
+  name: String! @join__field(graph: CORE)
+  extensions: [String!]! @join__field(graph: CORE)
+  updatedBy: String @join__field(graph: CORE)
+  updatedAt: Int! @join__field(graph: CORE)
+}
+
+type RepositorySecretAccess @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  name: String! @join__field(graph: CORE)
+  action: RepositorySecretAction! @join__field(graph: CORE)
+  extension: String @join__field(graph: CORE)
+  actorDid: String @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+}
+
+type ShareLink @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  hint: String! @join__field(graph: CORE)
+  allowClone: Boolean! @join__field(graph: CORE)
+  createdBy: String @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  expiresAt: Int! @join__field(graph: CORE)
+  revokedAt: Int @join__field(graph: CORE)
+  useCount: Int! @join__field(graph: CORE)
+  lastUsedAt: Int @join__field(graph: CORE)
+  active: Boolean! @join__field(graph: CORE)
+}
+
+# The token is only returned here, when the link is created
+type NewShareLink @join__type(graph: CORE) {
+  token: String! @join__field(graph: CORE)
+  url: String! @join__field(graph: CORE)
+  # Set when the link allows cloning
+  cloneUrl: String @join__field(graph: CORE)
+  shareLink: ShareLink! @join__field(graph: CORE)
+}
+
+type ShareLinkEvent @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  linkId: ID! @join__field(graph: CORE)
+  action: ShareLinkAction! @join__field(graph: CORE)
+  actorDid: String @join__field(graph: CORE)
+  client: String @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+}
+
+enum ShareLinkAction @join__type(graph: CORE) {
+  CREATE @join__enumValue(graph: CORE)
+  OPEN @join__enumValue(graph: CORE)
+  FETCH @join__enumValue(graph: CORE)
+  REVOKE @join__enumValue(graph: CORE)
+}
+
+# The secret is write-only
+type Webhook @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  # Null for an instance webhook
+  repositoryId: ID @join__field(graph: CORE)
+  url: String! @join__field(graph: CORE)
+  # Event types and prefixes such as `issue.*`; empty follows every event
+  events: [String!]! @join__field(graph: CORE)
+  active: Boolean! @join__field(graph: CORE)
+  createdBy: String @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  updatedAt: Int! @join__field(graph: CORE)
+  deliveries(status: WebhookDeliveryStatus, event: String, first: Int, after: String, last: Int, before: String): WebhookDeliveryConnection! @join__field(graph: CORE)
+}
+
+type WebhookDeliveryConnection @join__type(graph: CORE) {
+  edges: [WebhookDeliveryEdge!]! @join__field(graph: CORE)
+  nodes: [WebhookDelivery!]! @join__field(graph: CORE)
+  pageInfo: PageInfo! @join__field(graph: CORE)
+  totalCount: Int! @join__field(graph: CORE)
+}
+
+type WebhookDeliveryEdge @join__type(graph: CORE) {
+  cursor: String! @join__field(graph: CORE)
+  node: WebhookDelivery! @join__field(graph: CORE)
+}
+
+# Headers and bodies describe the last attempt and are cleared after 30 days
+type WebhookDelivery @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  event: String! @join__field(graph: CORE)
+  eventId: String! @join__field(graph: CORE)
+  # The delivery this one repeats
+  redeliveryOf: ID @join__field(graph: CORE)
+  status: WebhookDeliveryStatus! @join__field(graph: CORE)
+  attempt: Int! @join__field(graph: CORE)
+  nextAttemptAt: Int @join__field(graph: CORE)
+  requestUrl: String! @join__field(graph: CORE)
+  # JSON objects
+  requestHeaders: String @join__field(graph: CORE)
+  requestBody: String @join__field(graph: CORE)
+  responseStatus: Int @join__field(graph: CORE)
+  responseHeaders: String @join__field(graph: CORE)
+  responseBody: String @join__field(graph: CORE)
+  durationMs: Int @join__field(graph: CORE)
+  error: String @join__field(graph: CORE)
+  truncated: Boolean! @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  completedAt: Int @join__field(graph: CORE)
+}
+
+enum WebhookDeliveryStatus @join__type(graph: CORE) {
+  PENDING @join__enumValue(graph: CORE)
+  SUCCEEDED @join__enumValue(graph: CORE)
+  FAILED @join__enumValue(graph: CORE)
+}
+
+type Job @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  kind: String! @join__field(graph: CORE)
+  status: JobStatus! @join__field(graph: CORE)
+  attempts: Int! @join__field(graph: CORE)
+  progress: JobProgress @join__field(graph: CORE)
+  lastError: String @join__field(graph: CORE)
+  runAt: Int! @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  updatedAt: Int! @join__field(graph: CORE)
+}
+
+type JobProgress @join__type(graph: CORE) {
+  processed: Int! @join__field(graph: CORE)
+  total: Int @join__field(graph: CORE)
+  # Items found out of date, for jobs that check something
+  mismatches: Int @join__field(graph: CORE)
+}
+
+type JobConnection @join__type(graph: CORE) {
+  edges: [JobEdge!]! @join__field(graph: CORE)
+  nodes: [Job!]! @join__field(graph: CORE)
+  pageInfo: PageInfo! @join__field(graph: CORE)
+  totalCount: Int! @join__field(graph: CORE)
+}
+
+type JobEdge @join__type(graph: CORE) {
+  cursor: String! @join__field(graph: CORE)
+  node: Job! @join__field(graph: CORE)
+}
+
+enum JobStatus @join__type(graph: CORE) {
+  QUEUED @join__enumValue(graph: CORE)
+  RUNNING @join__enumValue(graph: CORE)
+  SUCCEEDED @join__enumValue(graph: CORE)
+  FAILED @join__enumValue(graph: CORE)
+}
+
+enum SearchIndexScope @join__type(graph: CORE) {
+  CODE @join__enumValue(graph: CORE)
+  SYMBOLS @join__enumValue(graph: CORE)
+}
+
+type MirrorWebhook @join__type(graph: CORE) {
+  url: String! @join__field(graph: CORE)
+  secret: String! @join__field(graph: CORE)
+}
+
+type ComplianceRecord @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  subjectKind: String! @join__field(graph: CORE)
+  subjectId: String! @join__field(graph: CORE)
+  source: String! @join__field(graph: CORE)
+  actorDid: String @join__field(graph: CORE)
+  summary: String! @join__field(graph: CORE)
+  deletedAt: Int! @join__field(graph: CORE)
+  expiresAt: Int @join__field(graph: CORE)
+}
+
+type AuditLogConnection @join__type(graph: CORE) {
+  edges: [AuditLogEdge!]! @join__field(graph: CORE)
+  nodes: [AuditLogEntry!]! @join__field(graph: CORE)
+  pageInfo: PageInfo! @join__field(graph: CORE)
+  totalCount: Int! @join__field(graph: CORE)
+}
+
+type AuditLogEdge @join__type(graph: CORE) {
+  cursor: String! @join__field(graph: CORE)
+  node: AuditLogEntry! @join__field(graph: CORE)
+}
+
+type AuditLogEntry @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  action: String! @join__field(graph: CORE)
+  category: String! @join__field(graph: CORE)
+  source: String! @join__field(graph: CORE)
+  actorDid: String @join__field(graph: CORE)
+  repositoryId: ID @join__field(graph: CORE)
+  data: String! @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  expiresAt: Int @join__field(graph: CORE)
+}
+
+type AuditLogExport @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  format: AuditLogExportFormat! @join__field(graph: CORE)
+  status: AccountExportStatus! @join__field(graph: CORE)
+  requestedBy: String! @join__field(graph: CORE)
+  rowCount: Int @join__field(graph: CORE)
+  sizeBytes: Int @join__field(graph: CORE)
+  error: String @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  completedAt: Int @join__field(graph: CORE)
+  expiresAt: Int @join__field(graph: CORE)
+  downloadUrl: String @join__field(graph: CORE)
+}
+
+type AuditLogRetention @join__type(graph: CORE) {
+  category: String! @join__field(graph: CORE)
+  retentionDays: Int! @join__field(graph: CORE)
+}
+
+type GroupUsage @join__type(graph: CORE) {
+  group: String! @join__field(graph: CORE)
+  period: String! @join__field(graph: CORE)
+  resources: [ResourceUsage!]! @join__field(graph: CORE)
+}
+
+type ResourceUsage @join__type(graph: CORE) {
+  resource: UsageResource! @join__field(graph: CORE)
+  used: Int! @join__field(graph: CORE)
+  unit: String! @join__field(graph: CORE)
+  softLimit: Int @join__field(graph: CORE)
+  hardLimit: Int @join__field(graph: CORE)
+  state: QuotaState! @join__field(graph: CORE)
+}
+
+type UsageExport @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  date: String! @join__field(graph: CORE)
+  format: UsageExportFormat! @join__field(graph: CORE)
+  rowCount: Int! @join__field(graph: CORE)
+  sizeBytes: Int! @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  expiresAt: Int! @join__field(graph: CORE)
+  downloadUrl: String @join__field(graph: CORE)
+}
+
+type ModerationItem @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  kind: ModeratedContentKind! @join__field(graph: CORE)
+  source: String! @join__field(graph: CORE)
+  subjectId: String @join__field(graph: CORE)
+  actorDid: String @join__field(graph: CORE)
+  repositoryId: ID @join__field(graph: CORE)
+  title: String @join__field(graph: CORE)
+  body: String @join__field(graph: CORE)
+  reason: String @join__field(graph: CORE)
+  status: ModerationStatus! @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  resolvedAt: Int @join__field(graph: CORE)
+  resolvedBy: String @join__field(graph: CORE)
+}
+
+type FileMatch @join__type(graph: CORE) {
+  path: String! @join__field(graph: CORE)
+  name: String! @join__field(graph: CORE)
+  score: Int! @join__field(graph: CORE)
+  matchedIndices: [Int!]! @join__field(graph: CORE)
+}
+
+# A definition on the default branch. Lines and columns are 1-based.
+type CodeSymbol @join__type(graph: CORE) {
+  name: String! @join__field(graph: CORE)
+  # function, method, class, interface, module, ...
+  kind: String! @join__field(graph: CORE)
+  language: String! @join__field(graph: CORE)
+  filePath: String! @join__field(graph: CORE)
+  line: Int! @join__field(graph: CORE)
+  column: Int! @join__field(graph: CORE)
+  endLine: Int! @join__field(graph: CORE)
+}
+
+type SymbolIndex @join__type(graph: CORE) {
+  # Null until the first index is built
+  commit: String @join__field(graph: CORE)
+  branch: String @join__field(graph: CORE)
+  indexedAt: Int @join__field(graph: CORE)
+  pending: Boolean! @join__field(graph: CORE)
+}
+
+# A file on a repository's default branch that contains the query.
+type CodeSearchMatch @join__type(graph: CORE) {
+  repository: RepositoryNode @join__field(graph: CORE)
+  filePath: String! @join__field(graph: CORE)
+  # rust, python, markdown, ...; null when not known
+  language: String @join__field(graph: CORE)
+  # Matching lines in the whole file
+  matchCount: Int! @join__field(graph: CORE)
+  # The first five matching lines
+  lines: [CodeSearchLine!]! @join__field(graph: CORE)
+}
+
+type CodeSearchLine @join__type(graph: CORE) {
+  # 1-based
+  number: Int! @join__field(graph: CORE)
+  text: String! @join__field(graph: CORE)
+}
+
+type ContributorStats @join__type(graph: CORE) {
+  # Null until the statistics are first computed
+  commit: String @join__field(graph: CORE)
+  branch: String @join__field(graph: CORE)
+  computedAt: Int @join__field(graph: CORE)
+  pending: Boolean! @join__field(graph: CORE)
+  # Most commits first, at most 100
+  contributors: [ContributorStat!]! @join__field(graph: CORE)
+  # Oldest first; weeks without commits are left out
+  weeks: [CodeFrequencyWeek!]! @join__field(graph: CORE)
+}
+
+type ContributorStat @join__type(graph: CORE) {
+  name: String! @join__field(graph: CORE)
+  email: String! @join__field(graph: CORE)
+  commits: Int! @join__field(graph: CORE)
+  additions: Int! @join__field(graph: CORE)
+  deletions: Int! @join__field(graph: CORE)
+  firstCommitAt: Int! @join__field(graph: CORE)
+  lastCommitAt: Int! @join__field(graph: CORE)
+}
+
+type CodeFrequencyWeek @join__type(graph: CORE) {
+  # Monday 00:00 UTC
+  weekStart: Int! @join__field(graph: CORE)
+  commits: Int! @join__field(graph: CORE)
+  additions: Int! @join__field(graph: CORE)
+  deletions: Int! @join__field(graph: CORE)
+}
+
+type RepositoryProject @join__type(graph: CORE) {
+  root: String! @join__field(graph: CORE)
+  name: String! @join__field(graph: CORE)
+  manifests: [String!]! @join__field(graph: CORE)
+  configured: Boolean! @join__field(graph: CORE)
+}
+
+type ProjectMetadata @join__type(graph: CORE) {
+  headOid: String! @join__field(graph: CORE)
+  readme: String @join__field(graph: CORE)
+  license: LicenseFile @join__field(graph: CORE)
+  codeOfConduct: String @join__field(graph: CORE)
+  contributing: String @join__field(graph: CORE)
+  securityPolicy: String @join__field(graph: CORE)
+  ciConfigs: [CiConfig!]! @join__field(graph: CORE)
+  manifests: [String!]! @join__field(graph: CORE)
+}
+
+type LicenseFile @join__type(graph: CORE) {
+  path: String! @join__field(graph: CORE)
+  spdxId: String @join__field(graph: CORE)
+}
+
+type CiConfig @join__type(graph: CORE) {
+  provider: CiProvider! @join__field(graph: CORE)
+  path: String! @join__field(graph: CORE)
+}
+
+type Commit @join__type(graph: CORE) {
+  oid: String! @join__field(graph: CORE)
+  summary: String! @join__field(graph: CORE)
+  message: String! @join__field(graph: CORE)
+  author: CommitSignature! @join__field(graph: CORE)
+  committer: CommitSignature! @join__field(graph: CORE)
+  parents: [String!]! @join__field(graph: CORE)
+  stats: CommitStats! @join__field(graph: CORE)
+  files: [CommitFile!]! @join__field(graph: CORE)
+  filesTruncated: Boolean! @join__field(graph: CORE)
+}
+
+type CommitSignature @join__type(graph: CORE) {
+  name: String! @join__field(graph: CORE)
+  email: String! @join__field(graph: CORE)
+  time: Int! @join__field(graph: CORE)
+}
+
+type CommitStats @join__type(graph: CORE) {
+  filesChanged: Int! @join__field(graph: CORE)
+  additions: Int! @join__field(graph: CORE)
+  deletions: Int! @join__field(graph: CORE)
+}
+
+type CommitFile @join__type(graph: CORE) {
+  path: String! @join__field(graph: CORE)
+  previousPath: String @join__field(graph: CORE)
+  status: CommitFileStatus! @join__field(graph: CORE)
+  additions: Int! @join__field(graph: CORE)
+  deletions: Int! @join__field(graph: CORE)
+  binary: Boolean! @join__field(graph: CORE)
+  patch: String @join__field(graph: CORE)
+  patchTruncated: Boolean! @join__field(graph: CORE)
+}
+
+enum CommitFileStatus @join__type(graph: CORE) {
+  ADDED @join__enumValue(graph: CORE)
+  DELETED @join__enumValue(graph: CORE)
+  MODIFIED @join__enumValue(graph: CORE)
+  RENAMED @join__enumValue(graph: CORE)
+}
+
+type CommitGraph @join__type(graph: CORE) {
+  tips: [String!]! @join__field(graph: CORE)
+  nodes: [CommitGraphNode!]! @join__field(graph: CORE)
+  lanes: Int! @join__field(graph: CORE)
+  truncated: Boolean! @join__field(graph: CORE)
+}
+
+type CommitGraphNode @join__type(graph: CORE) {
+  oid: String! @join__field(graph: CORE)
+  parents: [String!]! @join__field(graph: CORE)
+  refs: [String!]! @join__field(graph: CORE)
+  lane: Int! @join__field(graph: CORE)
+  summary: String! @join__field(graph: CORE)
+  authorName: String! @join__field(graph: CORE)
+  committedAt: Int! @join__field(graph: CORE)
+}
+
+type Diff @join__type(graph: CORE) {
+  baseOid: String! @join__field(graph: CORE)
+  headOid: String! @join__field(graph: CORE)
+  mergeBase: String @join__field(graph: CORE)
+  stats: CommitStats! @join__field(graph: CORE)
+  files: [CommitFile!]! @join__field(graph: CORE)
+  filesTruncated: Boolean! @join__field(graph: CORE)
+}
+
+type FileBlame @join__type(graph: CORE) {
+  oid: String! @join__field(graph: CORE)
+  path: String! @join__field(graph: CORE)
+  lineCount: Int! @join__field(graph: CORE)
+  ranges: [BlameRange!]! @join__field(graph: CORE)
+}
+
+type BlameRange @join__type(graph: CORE) {
+  startLine: Int! @join__field(graph: CORE)
+  endLine: Int! @join__field(graph: CORE)
+  originalStartLine: Int! @join__field(graph: CORE)
+  originalPath: String @join__field(graph: CORE)
+  oid: String! @join__field(graph: CORE)
+  summary: String! @join__field(graph: CORE)
+  author: CommitSignature! @join__field(graph: CORE)
+}
+
+type RefComparison @join__type(graph: CORE) {
+  baseOid: String! @join__field(graph: CORE)
+  headOid: String! @join__field(graph: CORE)
+  mergeBase: String @join__field(graph: CORE)
+  aheadBy: Int! @join__field(graph: CORE)
+  behindBy: Int! @join__field(graph: CORE)
+  commits: [ComparedCommit!]! @join__field(graph: CORE)
+  truncated: Boolean! @join__field(graph: CORE)
+}
+
+type ComparedCommit @join__type(graph: CORE) {
+  oid: String! @join__field(graph: CORE)
+  parents: [String!]! @join__field(graph: CORE)
+  summary: String! @join__field(graph: CORE)
+  authorName: String! @join__field(graph: CORE)
+  committedAt: Int! @join__field(graph: CORE)
+}
+
+type CommitSearchConnection @join__type(graph: CORE) {
+  edges: [CommitSearchEdge!]! @join__field(graph: CORE)
+  nodes: [CommitSearchResult!]! @join__field(graph: CORE)
+  pageInfo: PageInfo! @join__field(graph: CORE)
+}
+
+type CommitSearchEdge @join__type(graph: CORE) {
+  cursor: String! @join__field(graph: CORE)
+  node: CommitSearchResult! @join__field(graph: CORE)
+}
+
+type CommitSearchResult @join__type(graph: CORE) {
+  oid: String! @join__field(graph: CORE)
+  summary: String! @join__field(graph: CORE)
+  message: String! @join__field(graph: CORE)
+  authorName: String! @join__field(graph: CORE)
+  authorEmail: String! @join__field(graph: CORE)
+  committedAt: Int! @join__field(graph: CORE)
+  highlights: [CommitSearchHighlight!]! @join__field(graph: CORE)
+}
+
+type CommitSearchHighlight @join__type(graph: CORE) {
+  field: CommitSearchField! @join__field(graph: CORE)
+  start: Int! @join__field(graph: CORE)
+  end: Int! @join__field(graph: CORE)
+}
+
+enum CommitSearchField @join__type(graph: CORE) {
+  MESSAGE @join__enumValue(graph: CORE)
+  AUTHOR_NAME @join__enumValue(graph: CORE)
+  AUTHOR_EMAIL @join__enumValue(graph: CORE)
+}
+
+type MergeConflict @join__type(graph: CORE) {
+  path: String! @join__field(graph: CORE)
+  kind: MergeConflictKind! @join__field(graph: CORE)
+}
+
+type CommitOperationResult @join__type(graph: CORE) {
+  applied: Boolean! @join__field(graph: CORE)
+  branch: String! @join__field(graph: CORE)
+  sourceOid: String! @join__field(graph: CORE)
+  previousOid: String! @join__field(graph: CORE)
+  commitOid: String @join__field(graph: CORE)
+  conflicts: [MergeConflict!]! @join__field(graph: CORE)
+  violations: [PolicyViolation!]! @join__field(graph: CORE)
+}
+
+type SuggestionResult @join__type(graph: CORE) {
+  applied: Boolean! @join__field(graph: CORE)
+  branch: String! @join__field(graph: CORE)
+  filePath: String! @join__field(graph: CORE)
+  previousOid: String! @join__field(graph: CORE)
+  commitOid: String @join__field(graph: CORE)
+  outdated: Boolean! @join__field(graph: CORE)
+  violations: [PolicyViolation!]! @join__field(graph: CORE)
+}
+
+type RepositoryLabel @join__type(graph: CORE) {
+  name: String! @join__field(graph: CORE)
+  color: String! @join__field(graph: CORE)
+  description: String @join__field(graph: CORE)
+}
+
+type EffectiveSettings @join__type(graph: CORE) {
+  labels: [RepositoryLabel!]! @join__field(graph: CORE)
+  branchProtection: RepositoryPolicy! @join__field(graph: CORE)
+  visibility: RepositoryVisibility! @join__field(graph: CORE)
+  provenance: [SettingProvenance!]! @join__field(graph: CORE)
+}
+
+type SettingProvenance @join__type(graph: CORE) {
+  setting: RepositorySetting! @join__field(graph: CORE)
+  source: SettingSource! @join__field(graph: CORE)
+  group: GroupSummary @join__field(graph: CORE)
+  overrides: GroupSummary @join__field(graph: CORE)
+}
+
+type IssueClosingSettings @join__type(graph: CORE) {
+  enabled: Boolean! @join__field(graph: CORE)
+  branches: [String!]! @join__field(graph: CORE)
+}
+
+type RepositoryPolicy @join__type(graph: CORE) {
+  source: String! @join__field(graph: CORE)
+  maxFileSize: Int @join__field(graph: CORE)
+  forbiddenPaths: [String!]! @join__field(graph: CORE)
+  commitMessagePattern: String @join__field(graph: CORE)
+  branchNamePattern: String @join__field(graph: CORE)
+  requireSignedCommits: Boolean! @join__field(graph: CORE)
+  forbidMergeCommits: Boolean! @join__field(graph: CORE)
+}
+
+type RemoteCloneStatus @join__type(graph: CORE) {
+  state: RemoteCloneState! @join__field(graph: CORE)
+  progressPercent: Int! @join__field(graph: CORE)
+  objectsReceived: Int! @join__field(graph: CORE)
+  objectsTotal: Int! @join__field(graph: CORE)
+  bytesReceived: Int! @join__field(graph: CORE)
+  attempts: Int! @join__field(graph: CORE)
+  lastError: String @join__field(graph: CORE)
+  updatedAt: Int! @join__field(graph: CORE)
+}
+
+type ExtensionFieldStats @join__type(graph: CORE) {
+  extension: String! @join__field(graph: CORE)
+  field: String! @join__field(graph: CORE)
+  calls: Int! @join__field(graph: CORE)
+  errors: Int! @join__field(graph: CORE)
+  avgMs: Float! @join__field(graph: CORE)
+  maxMs: Float! @join__field(graph: CORE)
+  fuelConsumed: Float! @join__field(graph: CORE)
+  requestBytes: Float! @join__field(graph: CORE)
+  responseBytes: Float! @join__field(graph: CORE)
+}
+
+type SchemaFieldUsage @join__type(graph: CORE) {
+  coordinate: String! @join__field(graph: CORE)
+  typeName: String! @join__field(graph: CORE)
+  fieldName: String! @join__field(graph: CORE)
+  count: Float! @join__field(graph: CORE)
+  lastUsedAt: Int @join__field(graph: CORE)
+  clients: [String!]! @join__field(graph: CORE)
+  isDeprecated: Boolean! @join__field(graph: CORE)
+  deprecationReason: String @join__field(graph: CORE)
+}
+
+type ExtensionCacheCheck @join__type(graph: CORE) {
+  extension: String! @join__field(graph: CORE)
+  state: ExtensionCacheState! @join__field(graph: CORE)
+  checkedAt: Int @join__field(graph: CORE)
+  detail: String @join__field(graph: CORE)
+}
+
+type ExtensionUpgrade @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  extension: String! @join__field(graph: CORE)
+  fromVersion: String! @join__field(graph: CORE)
+  toVersion: String! @join__field(graph: CORE)
+  status: ExtensionUpgradeStatus! @join__field(graph: CORE)
+  backupPath: String! @join__field(graph: CORE)
+  error: String @join__field(graph: CORE)
+  startedAt: Int! @join__field(graph: CORE)
+  finishedAt: Int @join__field(graph: CORE)
+}
+
+type ExtensionQueryAudit @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  extension: String! @join__field(graph: CORE)
+  actorDid: String @join__field(graph: CORE)
+  repositoryId: String @join__field(graph: CORE)
+  query: String! @join__field(graph: CORE)
+  fields: [String!]! @join__field(graph: CORE)
+  allowed: Boolean! @join__field(graph: CORE)
+  error: String @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+}
+
+type ExtensionSchedule @join__type(graph: CORE) {
+  extension: String! @join__field(graph: CORE)
+  name: String! @join__field(graph: CORE)
+  cron: String! @join__field(graph: CORE)
+  jitterSecs: Int! @join__field(graph: CORE)
+  nextRunAt: Int! @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  updatedAt: Int! @join__field(graph: CORE)
+}
+
+type ExtensionReload @join__type(graph: CORE) {
+  extension: String! @join__field(graph: CORE)
+  loaded: Boolean! @join__field(graph: CORE)
+  version: String @join__field(graph: CORE)
+  previousVersion: String @join__field(graph: CORE)
+}
+
+type ExtensionTaskRun @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  extension: String! @join__field(graph: CORE)
+  task: String! @join__field(graph: CORE)
+  status: ExtensionTaskRunStatus! @join__field(graph: CORE)
+  scheduledFor: Int! @join__field(graph: CORE)
+  startedAt: Int! @join__field(graph: CORE)
+  finishedAt: Int @join__field(graph: CORE)
+  durationMs: Int @join__field(graph: CORE)
+  error: String @join__field(graph: CORE)
+}
+
+type IntegrityReport @join__type(graph: CORE) {
+  path: String! @join__field(graph: CORE)
+  healthy: Boolean! @join__field(graph: CORE)
+  checkedAt: Int! @join__field(graph: CORE)
+  refsChecked: Int! @join__field(graph: CORE)
+  objectsChecked: Int! @join__field(graph: CORE)
+  problems: [IntegrityProblem!]! @join__field(graph: CORE)
+}
+
+type IntegrityProblem @join__type(graph: CORE) {
+  kind: IntegrityProblemKind! @join__field(graph: CORE)
+  oid: String @join__field(graph: CORE)
+  reference: String @join__field(graph: CORE)
+  detail: String! @join__field(graph: CORE)
+}
+
+type RepairReport @join__type(graph: CORE) {
+  repaired: Boolean! @join__field(graph: CORE)
+  before: IntegrityReport! @join__field(graph: CORE)
+  after: IntegrityReport! @join__field(graph: CORE)
+  quarantined: [String!]! @join__field(graph: CORE)
+  attempts: [RepairAttempt!]! @join__field(graph: CORE)
+}
+
+type RepairAttempt @join__type(graph: CORE) {
+  source: RepairSourceKind! @join__field(graph: CORE)
+  location: String! @join__field(graph: CORE)
+  error: String @join__field(graph: CORE)
+}
+
+type Announcement @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  message: String! @join__field(graph: CORE)
+  severity: AnnouncementSeverity! @join__field(graph: CORE)
+  startsAt: Int @join__field(graph: CORE)
+  endsAt: Int @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+}
+
+# The default visibility applies to repositories whose visibility is not set by the
+# repository or one of its groups.
+type InstanceSettings @join__type(graph: CORE) {
+  name: String! @join__field(graph: CORE)
+  description: String @join__field(graph: CORE)
+  defaultVisibility: RepositoryVisibility! @join__field(graph: CORE)
+  setupRequired: Boolean! @join__field(graph: CORE)
+  setupCompletedAt: Int @join__field(graph: CORE)
+  updatedAt: Int @join__field(graph: CORE)
+}
+
+type MaintenanceMode @join__type(graph: CORE) {
+  enabled: Boolean! @join__field(graph: CORE)
+  message: String! @join__field(graph: CORE)
+  updatedAt: Int @join__field(graph: CORE)
+}
+
+type FeatureFlag @join__type(graph: CORE) {
+  key: String! @join__field(graph: CORE)
+  enabled: Boolean! @join__field(graph: CORE)
+}
+
+type FeatureFlagDefinition @join__type(graph: CORE) {
+  key: String! @join__field(graph: CORE)
+  description: String @join__field(graph: CORE)
+  enabled: Boolean! @join__field(graph: CORE)
+  rolloutPercent: Int! @join__field(graph: CORE)
+  overrides: [FeatureFlagOverride!]! @join__field(graph: CORE)
+  updatedAt: Int! @join__field(graph: CORE)
+}
+
+type FeatureFlagOverride @join__type(graph: CORE) {
+  repositoryId: ID! @join__field(graph: CORE)
+  enabled: Boolean! @join__field(graph: CORE)
+}
+
+type RemoteTagWatch @join__type(graph: CORE) {
+  semverRange: String @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+}
+
+type PolicyCheckResult @join__type(graph: CORE) {
+  allowed: Boolean! @join__field(graph: CORE)
+  commitsChecked: Int! @join__field(graph: CORE)
+  violations: [PolicyViolation!]! @join__field(graph: CORE)
+}
+
+type PolicyViolation @join__type(graph: CORE) {
+  refName: String! @join__field(graph: CORE)
+  commit: String @join__field(graph: CORE)
+  rule: String! @join__field(graph: CORE)
+  message: String! @join__field(graph: CORE)
+}
+
+type PolicyValidation @join__type(graph: CORE) {
+  valid: Boolean! @join__field(graph: CORE)
+  violations: [PolicyRuleViolation!]! @join__field(graph: CORE)
+}
+
+type PolicyRuleViolation @join__type(graph: CORE) {
+  rule: String! @join__field(graph: CORE)
+  message: String! @join__field(graph: CORE)
+}
+
+type SavedSearch @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  name: String! @join__field(graph: CORE)
+  scope: SearchScope! @join__field(graph: CORE)
+  query: String! @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  updatedAt: Int! @join__field(graph: CORE)
+}
+
+type AccountExport @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  status: AccountExportStatus! @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  completedAt: Int @join__field(graph: CORE)
+  expiresAt: Int @join__field(graph: CORE)
+  sizeBytes: Int @join__field(graph: CORE)
+  error: String @join__field(graph: CORE)
+  downloadUrl: String @join__field(graph: CORE)
+}
+
+type ViewerSession @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  current: Boolean! @join__field(graph: CORE)
+  userAgent: String @join__field(graph: CORE)
+  signedInAt: Int! @join__field(graph: CORE)
+  lastActiveAt: Int! @join__field(graph: CORE)
+}
+
+type SshKey @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  title: String! @join__field(graph: CORE)
+  keyType: String! @join__field(graph: CORE)
+  publicKey: String! @join__field(graph: CORE)
+  fingerprint: String! @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  lastUsedAt: Int @join__field(graph: CORE)
+}
+
+type AccessToken @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  name: String! @join__field(graph: CORE)
+  hint: String! @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  expiresAt: Int @join__field(graph: CORE)
+  lastUsedAt: Int @join__field(graph: CORE)
+}
+
+type PushSubscription @join__type(graph: CORE) {
+  id: ID! @join__field(graph: CORE)
+  endpoint: String! @join__field(graph: CORE)
+  createdAt: Int! @join__field(graph: CORE)
+  expiresAt: Int @join__field(graph: CORE)
+  lastDeliveredAt: Int @join__field(graph: CORE)
+}
+
+type NewAccessToken @join__type(graph: CORE) {
+  token: String! @join__field(graph: CORE)
+  accessToken: AccessToken! @join__field(graph: CORE)
+}
+
+enum AccountExportStatus @join__type(graph: CORE) {
+  PENDING @join__enumValue(graph: CORE)
+  READY @join__enumValue(graph: CORE)
+  FAILED @join__enumValue(graph: CORE)
+}
+
+enum AuditLogExportFormat @join__type(graph: CORE) {
+  CSV @join__enumValue(graph: CORE)
+  JSON @join__enumValue(graph: CORE)
+}
+
+enum UsageResource @join__type(graph: CORE) {
+  STORAGE @join__enumValue(graph: CORE)
+  BANDWIDTH @join__enumValue(graph: CORE)
+  API_CALLS @join__enumValue(graph: CORE)
+  CI_MINUTES @join__enumValue(graph: CORE)
+}
+
+enum NotificationDigestFrequency @join__type(graph: CORE) {
+  IMMEDIATE @join__enumValue(graph: CORE)
+  DAILY @join__enumValue(graph: CORE)
+  WEEKLY @join__enumValue(graph: CORE)
+}
+
+enum QuotaState @join__type(graph: CORE) {
+  WITHIN_LIMITS @join__enumValue(graph: CORE)
+  WARNING @join__enumValue(graph: CORE)
+  EXCEEDED @join__enumValue(graph: CORE)
+}
+
+enum UsageExportFormat @join__type(graph: CORE) {
+  CSV @join__enumValue(graph: CORE)
+  JSON @join__enumValue(graph: CORE)
+}
+
+enum RepositoryVisibility @join__type(graph: CORE) {
+  PUBLIC @join__enumValue(graph: CORE)
+  PRIVATE @join__enumValue(graph: CORE)
+}
+
+enum RepositorySetting @join__type(graph: CORE) {
+  LABELS @join__enumValue(graph: CORE)
+  BRANCH_PROTECTION @join__enumValue(graph: CORE)
+  VISIBILITY @join__enumValue(graph: CORE)
+}
 
-directive @link(
-  url: String
-  as: String
-  for: link__Purpose
-  import: [link__Import]
-) repeatable on SCHEMA
+enum SettingSource @join__type(graph: CORE) {
+  DEFAULT @join__enumValue(graph: CORE)
+  GROUP @join__enumValue(graph: CORE)
+  REPOSITORY @join__enumValue(graph: CORE)
+}
 
-scalar join__FieldSet
+enum ModerationStatus @join__type(graph: CORE) {
+  PENDING @join__enumValue(graph: CORE)
+  APPROVED @join__enumValue(graph: CORE)
+  REMOVED @join__enumValue(graph: CORE)
+}
 
-enum link__Purpose {
-  SECURITY
-  EXECUTION
+enum ModeratedContentKind @join__type(graph: CORE) {
+  ISSUE @join__enumValue(graph: CORE)
+  COMMENT @join__enumValue(graph: CORE)
+  REPOSITORY @join__enumValue(graph: CORE)
 }
 
-scalar link__Import
+enum AnnouncementSeverity @join__type(graph: CORE) {
+  INFO @join__enumValue(graph: CORE)
+  WARNING @join__enumValue(graph: CORE)
+  CRITICAL @join__enumValue(graph: CORE)
+}
 
-type Query @join__type(graph: CORE) {
-  # Core fields
-  getAllGroups: [GroupNode!]! @join__field(graph: CORE)
-  getAllRepositories: [RepositoryNode!]! @join__field(graph: CORE)
-  getGroup(path: String!): GroupNode @join__field(graph: CORE)
-  getRepository(path: String!): RepositoryNode @join__field(graph: CORE)
-  browseRepository(path: String!, treePath: String, branch: String): RepositoryEntriesPayload @join__field(graph: CORE)
-  listRepositoryBranches(path: String!): [RepositoryBranch!] @join__field(graph: CORE)
-  readRepositoryFile(path: String!, filePath: String!, branch: String): RepositoryFilePayload @join__field(graph: CORE)
+enum ProfileLinkKind @join__type(graph: CORE) {
+  WEBSITE @join__enumValue(graph: CORE)
+  FORGE @join__enumValue(graph: CORE)
 }
 
-type Mutation @join__type(graph: CORE) {
-  # Core mutations
-  createGroup(input: CreateGroupInput!): GroupNode! @join__field(graph: CORE)
-  createRepository(input: CreateRepositoryInput!): RepositoryNode! @join__field(graph: CORE)
-  linkRemoteRepository(url: String!): RepositoryNode! @join__field(graph: CORE)
+enum RepositorySecretAction @join__type(graph: CORE) {
+  SET @join__enumValue(graph: CORE)
+  DELETE @join__enumValue(graph: CORE)
+  READ @join__enumValue(graph: CORE)
+  DENIED @join__enumValue(graph: CORE)
 }
 
-# Core types
-type GroupNode @join__type(graph: CORE) {
-  id: ID! @join__field(graph: CORE)
-  slug: String! @join__field(graph: CORE)
-  parent: GroupSummary @join__field(graph: CORE)
-  repositories: [RepositorySummary!]! @join__field(graph: CORE)
+enum ExtensionCacheState @join__type(graph: CORE) {
+  VERIFIED @join__enumValue(graph: CORE)
+  REFETCHED @join__enumValue(graph: CORE)
+  QUARANTINED @join__enumValue(graph: CORE)
+  MISSING @join__enumValue(graph: CORE)
+  UNVERIFIED @join__enumValue(graph: CORE)
 }
 
-type GroupSummary @join__type(graph: CORE) {
-  id: ID! @join__field(graph: CORE)
-  slug: String! @join__field(graph: CORE)
+enum ExtensionUpgradeStatus @join__type(graph: CORE) {
+  RUNNING @join__enumValue(graph: CORE)
+  SUCCEEDED @join__enumValue(graph: CORE)
+  ROLLED_BACK @join__enumValue(graph: CORE)
+  FAILED @join__enumValue(graph: CORE)
 }
 
-type RepositoryNode @join__type(graph: CORE) {
-  id: ID! @join__field(graph: CORE)
-  slug: String! @join__field(graph: CORE)
-  group: GroupSummary @join__field(graph: CORE)
-  isRemote: Boolean! @join__field(graph: CORE)
-  remoteUrl: String @join__field(graph: CORE)
-  readmeHtml(branch: String): String @join__field(graph: CORE)
+enum ExtensionTaskRunStatus @join__type(graph: CORE) {
+  RUNNING @join__enumValue(graph: CORE)
+  SUCCEEDED @join__enumValue(graph: CORE)
+  FAILED @join__enumValue(graph: CORE)
+  SKIPPED @join__enumValue(graph: CORE)
+  INTERRUPTED @join__enumValue(graph: CORE)
 }
 
-type RepositorySummary @join__type(graph: CORE) {
-  id: ID! @join__field(graph: CORE)
-  slug: String! @join__field(graph: CORE)
-  isRemote: Boolean! @join__field(graph: CORE)
-  remoteUrl: String @join__field(graph: CORE)
+enum SearchScope @join__type(graph: CORE) {
+  ISSUES @join__enumValue(graph: CORE)
+  PULL_REQUESTS @join__enumValue(graph: CORE)
 }
 
-type RepositoryEntriesPayload @join__type(graph: CORE) {
-  treePath: String @join__field(graph: CORE)
-  entries: [RepositoryEntry!]! @join__field(graph: CORE)
+enum RemoteCloneState @join__type(graph: CORE) {
+  PENDING @join__enumValue(graph: CORE)
+  CLONING @join__enumValue(graph: CORE)
+  READY @join__enumValue(graph: CORE)
+  FAILED @join__enumValue(graph: CORE)
 }
 
-type RepositoryFilePayload @join__type(graph: CORE) {
-  path: String! @join__field(graph: CORE)
-  name: String! @join__field(graph: CORE)
-  size: Int! @join__field(graph: CORE)
-  isBinary: Boolean! @join__field(graph: CORE)
-  text: String @join__field(graph: CORE)
-  truncated: Boolean! @join__field(graph: CORE)
+enum MergeConflictKind @join__type(graph: CORE) {
+  CONTENT @join__enumValue(graph: CORE)
+  ADD_ADD @join__enumValue(graph: CORE)
+  MODIFY_DELETE @join__enumValue(graph: CORE)
+  RENAME @join__enumValue(graph: CORE)
+  OTHER @join__enumValue(graph: CORE)
 }
 
-type RepositoryEntry @join__type(graph: CORE) {
-  name: String! @join__field(graph: CORE)
-  path: String! @join__field(graph: CORE)
-  type: EntryType! @join__field(graph: CORE)
-  size: Int @join__field(graph: CORE)
+enum CiProvider @join__type(graph: CORE) {
+  GITHUB_ACTIONS @join__enumValue(graph: CORE)
+  FORGEJO_ACTIONS @join__enumValue(graph: CORE)
+  GITEA_ACTIONS @join__enumValue(graph: CORE)
+  GITLAB_CI @join__enumValue(graph: CORE)
+  WOODPECKER @join__enumValue(graph: CORE)
+  CIRCLECI @join__enumValue(graph: CORE)
+  TRAVIS_CI @join__enumValue(graph: CORE)
+  JENKINS @join__enumValue(graph: CORE)
+  AZURE_PIPELINES @join__enumValue(graph: CORE)
+  BITBUCKET_PIPELINES @join__enumValue(graph: CORE)
+  BUILDKITE @join__enumValue(graph: CORE)
 }
 
-type RepositoryBranch @join__type(graph: CORE) {
-  name: String! @join__field(graph: CORE)
-  reference: String! @join__field(graph: CORE)
-  target: String @join__field(graph: CORE)
-  isDefault: Boolean! @join__field(graph: CORE)
+enum IntegrityProblemKind @join__type(graph: CORE) {
+  MISSING_OBJECT @join__enumValue(graph: CORE)
+  CORRUPT_OBJECT @join__enumValue(graph: CORE)
+  BROKEN_REF @join__enumValue(graph: CORE)
+}
+
+enum RepairSourceKind @join__type(graph: CORE) {
+  REMOTE_CACHE @join__enumValue(graph: CORE)
+  MIRROR @join__enumValue(graph: CORE)
 }
 
 enum EntryType @join__type(graph: CORE) {
@@ -206,6 +1504,45 @@ input CreateRepositoryInput @join__type(graph: CORE) {
   group: ID
 }
 
+input CreateAnnouncementInput @join__type(graph: CORE) {
+  message: String!
+  severity: AnnouncementSeverity!
+  startsAt: Int
+  endsAt: Int
+}
+
+input LabelInput @join__type(graph: CORE) {
+  name: String!
+  color: String!
+  description: String
+}
+
+input AuditLogFilter @join__type(graph: CORE) {
+  actor: String
+  actions: [String!]
+  repositoryPath: String
+  repositoryId: ID
+  since: Int
+  until: Int
+}
+
+input PushSubscriptionKeysInput @join__type(graph: CORE) {
+  p256dh: String!
+  auth: String!
+}
+
+input GroupSettingsInput @join__type(graph: CORE) {
+  labels: [LabelInput!]
+  branchProtection: String
+  visibility: RepositoryVisibility
+}
+
+input RefUpdateInput @join__type(graph: CORE) {
+  refName: String!
+  old: String!
+  new: String!
+}
+
 enum join__Graph {
   CORE @join__graph(name: "core", url: "internal://core")
 }
@@ -221,8 +1558,33 @@ fn merge_extension_into_supergraph(
 
     for definition in &document.definitions {
         match definition.clone() {
+            // Shared scalars such as `Upload` are declared once and joined to each graph.
+            Definition::TypeDefinition(TypeDefinition::Scalar(scalar))
+                if find_scalar_type_mut(supergraph, scalar.name.as_str()).is_some() =>
+            {
+                if let Some(existing) = find_scalar_type_mut(supergraph, scalar.name.as_str()) {
+                    ensure_join_type(&mut existing.directives, graph_name);
+                }
+            }
+            // So are interfaces such as `Node`
+            Definition::TypeDefinition(TypeDefinition::Interface(interface))
+                if find_interface_type_mut(supergraph, interface.name.as_str()).is_some() =>
+            {
+                if let Some(existing) = find_interface_type_mut(supergraph, interface.name.as_str())
+                {
+                    ensure_join_type(&mut existing.directives, graph_name);
+                }
+            }
             Definition::TypeDefinition(definition) => {
-                let decorated = decorate_type_definition(definition, graph_name);
+                let mut decorated = decorate_type_definition(definition, graph_name);
+                if let TypeDefinition::Object(object) = &mut decorated
+                    && object.implements_interfaces.iter().any(|name| name == NODE)
+                {
+                    join_node_implementation(object, graph_name);
+                    if let Some(node) = find_interface_type_mut(supergraph, NODE) {
+                        ensure_join_type(&mut node.directives, graph_name);
+                    }
+                }
                 supergraph
                     .definitions
                     .push(Definition::TypeDefinition(decorated));
@@ -230,6 +1592,9 @@ fn merge_extension_into_supergraph(
             Definition::TypeExtension(extension) => {
                 apply_type_extension(supergraph, extension, graph_name)?;
             }
+            // Extensions may redeclare directives the core already provides.
+            Definition::DirectiveDefinition(definition)
+                if has_directive_definition(supergraph, &definition.name) => {}
             Definition::DirectiveDefinition(definition) => {
                 supergraph
                     .definitions
@@ -244,6 +1609,171 @@ fn merge_extension_into_supergraph(
     Ok(())
 }
 
+/// Removes fields marked `@internal` from objects and interfaces, then every type that
+/// was only referenced through them. Objects implementing an interface stay, since
+/// `node(id:)` can still return them.
+fn strip_internal_fields(document: &mut Document<'static, String>) {
+    let referenced_before = referenced_types(document);
+    let public = |field: &Field<'static, String>| {
+        !field
+            .directives
+            .iter()
+            .any(|directive| directive.name == "internal")
+    };
+    for definition in &mut document.definitions {
+        match definition {
+            Definition::TypeDefinition(TypeDefinition::Object(object)) => {
+                object.fields.retain(public)
+            }
+            Definition::TypeDefinition(TypeDefinition::Interface(interface)) => {
+                interface.fields.retain(public)
+            }
+            _ => {}
+        }
+    }
+
+    // Each removed type may have been the last user of others.
+    loop {
+        let referenced = referenced_types(document);
+        let before = document.definitions.len();
+        document.definitions.retain(|definition| {
+            let Definition::TypeDefinition(definition) = definition else {
+                return true;
+            };
+            let (name, implements) = match definition {
+                TypeDefinition::Object(object) => {
+                    (&object.name, !object.implements_interfaces.is_empty())
+                }
+                TypeDefinition::Interface(interface) => (&interface.name, false),
+                TypeDefinition::InputObject(input) => (&input.name, false),
+                TypeDefinition::Enum(enum_type) => (&enum_type.name, false),
+                TypeDefinition::Union(union_type) => (&union_type.name, false),
+                TypeDefinition::Scalar(scalar) => (&scalar.name, false),
+            };
+            implements || !referenced_before.contains(name) || referenced.contains(name)
+        });
+        if document.definitions.len() == before {
+            break;
+        }
+    }
+}
+
+/// Names of the types that fields, arguments, unions and `implements` refer to.
+fn referenced_types(document: &Document<'static, String>) -> HashSet<String> {
+    fn named(ty: &Type<'static, String>, out: &mut HashSet<String>) {
+        match ty {
+            Type::NamedType(name) => {
+                out.insert(name.clone());
+            }
+            Type::ListType(inner) | Type::NonNullType(inner) => named(inner, out),
+        }
+    }
+    fn fields(fields: &[Field<'static, String>], out: &mut HashSet<String>) {
+        for field in fields {
+            named(&field.field_type, out);
+            for argument in &field.arguments {
+                named(&argument.value_type, out);
+            }
+        }
+    }
+
+    let mut out = HashSet::new();
+    for definition in &document.definitions {
+        match definition {
+            Definition::TypeDefinition(TypeDefinition::Object(object)) => {
+                fields(&object.fields, &mut out);
+                out.extend(object.implements_interfaces.iter().cloned());
+            }
+            Definition::TypeDefinition(TypeDefinition::Interface(interface)) => {
+                fields(&interface.fields, &mut out);
+                out.extend(interface.implements_interfaces.iter().cloned());
+            }
+            Definition::TypeDefinition(TypeDefinition::InputObject(input)) => {
+                for field in &input.fields {
+                    named(&field.value_type, &mut out);
+                }
+            }
+            Definition::TypeDefinition(TypeDefinition::Union(union_type)) => {
+                out.extend(union_type.types.iter().cloned());
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Object types in an extension schema that implement `Node`.
+pub fn extension_node_types(schema: &str) -> Result<Vec<String>> {
+    let document = graphql_parser::parse_schema::<String>(schema)
+        .context("failed to parse extension schema")?;
+    Ok(document
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::TypeDefinition(TypeDefinition::Object(object))
+                if object.implements_interfaces.iter().any(|name| name == NODE) =>
+            {
+                Some(object.name.clone())
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+/// Makes an extension type that implements `Node` an entity keyed by `id`. The core graph
+/// may return references to it from `node(id:)` but cannot resolve it; the planner fetches
+/// its other fields from the extension.
+fn join_node_implementation(object: &mut ObjectType<'static, String>, graph_name: &str) {
+    for directive in &mut object.directives {
+        let own_graph = directive.name == "join__type"
+            && directive.arguments.iter().any(|(name, value)| {
+                name == "graph" && matches!(value, Value::Enum(graph) if graph == graph_name)
+            });
+        if own_graph && !directive.arguments.iter().any(|(name, _)| name == "key") {
+            directive
+                .arguments
+                .push(("key".into(), Value::String("id".into())));
+        }
+    }
+
+    let mut reference = new_directive("join__type");
+    reference
+        .arguments
+        .push(("graph".into(), Value::Enum("CORE".into())));
+    reference
+        .arguments
+        .push(("key".into(), Value::String("id".into())));
+    reference
+        .arguments
+        .push(("resolvable".into(), Value::Boolean(false)));
+    object.directives.push(reference);
+
+    for graph in [graph_name, "CORE"] {
+        let mut implements = new_directive("join__implements");
+        implements
+            .arguments
+            .push(("graph".into(), Value::Enum(graph.into())));
+        implements
+            .arguments
+            .push(("interface".into(), Value::String(NODE.into())));
+        object.directives.push(implements);
+    }
+
+    if let Some(id) = object.fields.iter_mut().find(|field| field.name == "id") {
+        let mut directive = new_directive("join__field");
+        directive
+            .arguments
+            .push(("graph".into(), Value::Enum("CORE".into())));
+        id.directives.push(directive);
+    }
+}
+
+fn has_directive_definition(document: &Document<'static, String>, name: &str) -> bool {
+    document.definitions.iter().any(|definition| {
+        matches!(definition, Definition::DirectiveDefinition(existing) if existing.name == name)
+    })
+}
+
 fn decorate_type_definition(
     mut definition: TypeDefinition<'static, String>,
     graph_name: &str,
@@ -678,6 +2208,229 @@ enum IssueState {
         assert_eq!(arguments[1].1, Value::String("extension://issues".into()));
     }
 
+    #[test]
+    fn extensions_share_the_upload_scalar() {
+        let mut composer = SchemaComposer::new();
+        composer
+            .add_subgraph(
+                "assets".into(),
+                r#"
+scalar Upload
+
+extend type Mutation {
+  uploadAsset(file: Upload!): Boolean!
+}
+"#
+                .into(),
+            )
+            .expect("extension SDL should parse");
+
+        let supergraph_sdl = composer.compose().expect("composition should succeed");
+        let document = parse_supergraph(&supergraph_sdl);
+        let uploads: Vec<_> = document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                Definition::TypeDefinition(TypeDefinition::Scalar(scalar))
+                    if scalar.name == "Upload" =>
+                {
+                    Some(scalar)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(uploads.len(), 1);
+        let joined_graphs = uploads[0]
+            .directives
+            .iter()
+            .filter(|directive| directive.name == "join__type")
+            .count();
+        assert_eq!(joined_graphs, 2);
+    }
+
+    #[test]
+    fn extensions_can_redeclare_core_directives() {
+        let mut composer = SchemaComposer::new();
+        composer
+            .add_subgraph(
+                "stats".into(),
+                r#"
+directive @rateLimit(max: Int!, window: Int!) on FIELD_DEFINITION
+
+extend type Query {
+  stats(path: String!): Int @rateLimit(max: 5, window: 60) @cacheControl(maxAge: 30)
+}
+"#
+                .into(),
+            )
+            .expect("extension SDL should parse");
+
+        let supergraph_sdl = composer.compose().expect("composition should succeed");
+        let document = parse_supergraph(&supergraph_sdl);
+        let rate_limits = document
+            .definitions
+            .iter()
+            .filter(|definition| {
+                matches!(definition, Definition::DirectiveDefinition(d) if d.name == "rateLimit")
+            })
+            .count();
+        assert_eq!(rate_limits, 1);
+
+        let query = find_object_type(&document, "Query").expect("query type exists");
+        let stats = query
+            .fields
+            .iter()
+            .find(|field| field.name == "stats")
+            .expect("stats field present");
+        assert!(has_directive(&stats.directives, "rateLimit"));
+        assert!(has_directive(&stats.directives, "cacheControl"));
+    }
+
+    #[test]
+    fn internal_fields_are_left_out_of_the_public_supergraph() {
+        let mut composer = SchemaComposer::new();
+        composer
+            .add_subgraph(
+                "builds".into(),
+                r#"
+extend type Query {
+  builds(path: String!): [Build!]!
+  buildQueue: QueueStats! @internal
+}
+
+type Build {
+  id: ID!
+  runner: RunnerInfo @internal
+}
+
+type QueueStats {
+  depth: Int!
+  oldest: RunnerInfo
+}
+
+type RunnerInfo {
+  host: String!
+  state: RunnerState!
+}
+
+enum RunnerState {
+  IDLE
+  BUSY
+}
+"#
+                .into(),
+            )
+            .expect("extension SDL should parse");
+
+        let public = parse_supergraph(&composer.compose().expect("composition should succeed"));
+        let query = find_object_type(&public, "Query").expect("query type exists");
+        assert!(query.fields.iter().any(|field| field.name == "builds"));
+        assert!(!query.fields.iter().any(|field| field.name == "buildQueue"));
+        let build = find_object_type(&public, "Build").expect("build type exists");
+        assert_eq!(build.fields.len(), 1);
+        for hidden in ["QueueStats", "RunnerInfo", "RunnerState"] {
+            assert!(
+                !public.definitions.iter().any(|definition| matches!(
+                    definition,
+                    Definition::TypeDefinition(TypeDefinition::Object(ObjectType { name, .. }))
+                        | Definition::TypeDefinition(TypeDefinition::Enum(EnumType { name, .. }))
+                        if name == hidden
+                )),
+                "{hidden} should be left out"
+            );
+        }
+        // Types nothing referenced to begin with are kept.
+        assert!(find_object_type(&public, "Subscription").is_some());
+
+        let internal = parse_supergraph(
+            &composer
+                .compose_for(SchemaVisibility::Internal)
+                .expect("composition should succeed"),
+        );
+        let query = find_object_type(&internal, "Query").expect("query type exists");
+        let queue = query
+            .fields
+            .iter()
+            .find(|field| field.name == "buildQueue")
+            .expect("internal field kept");
+        assert!(has_directive(&queue.directives, "internal"));
+        assert!(find_object_type(&internal, "RunnerInfo").is_some());
+    }
+
+    #[test]
+    fn extension_nodes_are_entities_the_core_can_reference() {
+        let schema = r#"
+interface Node {
+  id: ID!
+}
+
+type Issue implements Node {
+  id: ID!
+  title: String!
+}
+"#;
+        assert_eq!(extension_node_types(schema).unwrap(), vec!["Issue"]);
+
+        let mut composer = SchemaComposer::new();
+        composer
+            .add_subgraph("issues".into(), schema.into())
+            .expect("extension SDL should parse");
+        let supergraph_sdl = composer.compose().expect("composition should succeed");
+        let document = parse_supergraph(&supergraph_sdl);
+
+        let interfaces = document
+            .definitions
+            .iter()
+            .filter(|definition| {
+                matches!(
+                    definition,
+                    Definition::TypeDefinition(TypeDefinition::Interface(i)) if i.name == "Node"
+                )
+            })
+            .count();
+        assert_eq!(interfaces, 1);
+
+        let issue = find_object_type(&document, "Issue").expect("issue type exists");
+        let joins: Vec<_> = issue
+            .directives
+            .iter()
+            .filter(|directive| directive.name == "join__type")
+            .map(|directive| directive.arguments.clone())
+            .collect();
+        assert_eq!(joins.len(), 2);
+        for arguments in &joins {
+            assert!(arguments.contains(&("key".into(), Value::String("id".into()))));
+        }
+        assert!(joins.iter().any(|arguments| {
+            arguments.contains(&("graph".into(), Value::Enum("CORE".into())))
+                && arguments.contains(&("resolvable".into(), Value::Boolean(false)))
+        }));
+        let implements = issue
+            .directives
+            .iter()
+            .filter(|directive| directive.name == "join__implements")
+            .count();
+        assert_eq!(implements, 2);
+
+        let id = issue
+            .fields
+            .iter()
+            .find(|field| field.name == "id")
+            .unwrap();
+        let id_graphs = id
+            .directives
+            .iter()
+            .filter(|directive| directive.name == "join__field")
+            .count();
+        assert_eq!(id_graphs, 2);
+        let title = issue
+            .fields
+            .iter()
+            .find(|field| field.name == "title")
+            .unwrap();
+        assert_eq!(title.directives.len(), 1);
+    }
+
     fn find_object_type<'a>(
         document: &'a Document<'static, String>,
         name: &str,
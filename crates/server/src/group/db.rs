@@ -17,19 +17,21 @@ pub async fn slug_conflicts_for_group(
     slug: &str,
 ) -> Result<bool, sqlx::Error> {
     if let Some(parent_id) = parent_id {
-        let exists: Option<i64> =
-            sqlx::query_scalar("SELECT 1 FROM groups WHERE slug = ? AND parent = ? LIMIT 1")
-                .bind(slug)
-                .bind(parent_id)
-                .fetch_optional(pool)
-                .await?;
+        let exists: Option<i64> = sqlx::query_scalar(
+            "SELECT 1 FROM groups WHERE slug = ? COLLATE NOCASE AND parent = ? LIMIT 1",
+        )
+        .bind(slug)
+        .bind(parent_id)
+        .fetch_optional(pool)
+        .await?;
         Ok(exists.is_some())
     } else {
-        let exists: Option<i64> =
-            sqlx::query_scalar("SELECT 1 FROM groups WHERE slug = ? AND parent IS NULL LIMIT 1")
-                .bind(slug)
-                .fetch_optional(pool)
-                .await?;
+        let exists: Option<i64> = sqlx::query_scalar(
+            "SELECT 1 FROM groups WHERE slug = ? COLLATE NOCASE AND parent IS NULL LIMIT 1",
+        )
+        .bind(slug)
+        .fetch_optional(pool)
+        .await?;
         Ok(exists.is_some())
     }
 }
@@ -49,7 +51,7 @@ pub async fn resolve_group_by_path(
     for slug in segments {
         let record = if let Some(ref parent) = parent_id {
             sqlx::query_as::<_, GroupRecord>(
-                "SELECT id, slug, parent FROM groups WHERE slug = ? AND parent = ?",
+                "SELECT id, slug, parent FROM groups WHERE slug = ? COLLATE NOCASE AND parent = ?",
             )
             .bind(slug)
             .bind(parent)
@@ -57,7 +59,7 @@ pub async fn resolve_group_by_path(
             .await
         } else {
             sqlx::query_as::<_, GroupRecord>(
-                "SELECT id, slug, parent FROM groups WHERE slug = ? AND parent IS NULL",
+                "SELECT id, slug, parent FROM groups WHERE slug = ? COLLATE NOCASE AND parent IS NULL",
             )
             .bind(slug)
             .fetch_optional(pool)
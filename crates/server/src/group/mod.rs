@@ -2,3 +2,4 @@ pub mod db;
 pub mod models;
 pub mod mutations;
 pub mod queries;
+pub mod settings;
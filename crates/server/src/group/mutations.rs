@@ -2,7 +2,7 @@ use sqlx::SqlitePool;
 
 use super::db::{fetch_group_by_id, slug_conflicts_for_group};
 use super::models::GroupRecord;
-use crate::validation::slug::validate_slug;
+use crate::validation::slug::prepare_slug;
 
 #[derive(Clone, Debug)]
 pub struct CreateGroupInput {
@@ -14,7 +14,7 @@ pub async fn create_group_raw(
     pool: &SqlitePool,
     input: CreateGroupInput,
 ) -> anyhow::Result<GroupRecord> {
-    validate_slug(&input.slug)?;
+    let slug = prepare_slug(&input.slug)?;
 
     let parent_id = match input.parent {
         Some(ref id) => {
@@ -27,21 +27,21 @@ pub async fn create_group_raw(
         None => None,
     };
 
-    if slug_conflicts_for_group(pool, parent_id.as_deref(), &input.slug).await? {
+    if slug_conflicts_for_group(pool, parent_id.as_deref(), &slug).await? {
         return Err(anyhow::anyhow!("slug already exists in this group"));
     }
 
     let id = cuid2::create_id();
     sqlx::query("INSERT INTO groups (id, slug, parent) VALUES (?, ?, ?)")
         .bind(&id)
-        .bind(&input.slug)
+        .bind(&slug)
         .bind(parent_id.as_ref())
         .execute(pool)
         .await?;
 
     Ok(GroupRecord {
         id,
-        slug: input.slug,
+        slug,
         parent: parent_id,
     })
 }
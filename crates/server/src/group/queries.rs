@@ -1,8 +1,10 @@
 use sqlx::SqlitePool;
 
-use super::db::{fetch_group_by_id, resolve_group_by_path};
+use super::db::fetch_group_by_id;
 use super::models::GroupRecord;
+use crate::graphql::pagination::{Page, PageRequest};
 use crate::repository::queries::get_repositories_for_group;
+use crate::repository::resolver::PathResolver;
 
 pub async fn get_all_groups_raw(pool: &SqlitePool) -> anyhow::Result<Vec<GroupRecord>> {
     let records =
@@ -12,10 +14,41 @@ pub async fn get_all_groups_raw(pool: &SqlitePool) -> anyhow::Result<Vec<GroupRe
     Ok(records)
 }
 
-pub async fn get_group_raw(pool: &SqlitePool, path: String) -> anyhow::Result<Option<GroupRecord>> {
-    resolve_group_by_path(pool, &path)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+/// Cursors for groups are tagged with this kind.
+pub const GROUP_CURSOR_KIND: &str = "group";
+
+/// One page of groups, ordered by slug and then id.
+pub async fn page_groups(
+    pool: &SqlitePool,
+    request: &PageRequest,
+) -> anyhow::Result<Page<GroupRecord>> {
+    let (condition, order) = request.keyset_sql();
+    let sql = format!(
+        "SELECT id, slug, parent FROM groups {} {} LIMIT ?",
+        condition
+            .map(|c| format!("WHERE {}", c))
+            .unwrap_or_default(),
+        order
+    );
+    let mut query = sqlx::query_as::<_, GroupRecord>(&sql);
+    if let Some(cursor) = &request.cursor {
+        query = query.bind(&cursor.slug).bind(&cursor.id);
+    }
+    let rows = query.bind(request.fetch_limit()).fetch_all(pool).await?;
+    Ok(Page::from_rows(request, rows))
+}
+
+pub async fn count_groups(pool: &SqlitePool) -> anyhow::Result<i64> {
+    Ok(sqlx::query_scalar("SELECT COUNT(*) FROM groups")
+        .fetch_one(pool)
+        .await?)
+}
+
+pub async fn get_group_raw(
+    resolver: &PathResolver,
+    path: String,
+) -> anyhow::Result<Option<GroupRecord>> {
+    resolver.resolve_group(&path).await
 }
 
 pub async fn get_group_parent(
@@ -28,6 +61,7 @@ pub async fn get_group_parent(
 pub async fn repositories_for_group(
     pool: &SqlitePool,
     group_id: &str,
+    viewer_did: Option<&str>,
 ) -> anyhow::Result<Vec<crate::repository::models::RepositorySummary>> {
-    get_repositories_for_group(pool, group_id).await
+    get_repositories_for_group(pool, group_id, viewer_did).await
 }
@@ -0,0 +1,643 @@
+//! Defaults a group hands to the repositories created inside it.
+//!
+//! A group can set default labels, a branch protection template (a push policy, see
+//! [`crate::repository::policy`]) and a default visibility. A value the group leaves
+//! unset falls through to its parent, and past the root to the instance default. New
+//! repositories copy the resolved values once, when they are created; editing a group
+//! later does not touch repositories that already exist. A copied value remembers the
+//! group it came from until the repository changes it, which is how `effectiveSettings`
+//! tells inherited values from overrides.
+
+use anyhow::{Context, bail};
+use git_http::policy::PolicyRules;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::instance::instance_settings;
+use crate::repository::models::RepositoryRecord;
+use crate::repository::policy::{load_policy, parse_policy, policy_source, save_policy};
+use crate::user::db::unix_now;
+
+const MAX_LABEL_NAME: usize = 50;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Label {
+    pub name: String,
+    /// `#rrggbb`, lowercase.
+    pub color: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Visibility {
+    #[default]
+    Public,
+    /// Left out of cross-repository roll-ups for everyone but the owner.
+    Private,
+}
+
+impl Visibility {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Private => "private",
+        }
+    }
+
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "public" => Ok(Visibility::Public),
+            "private" => Ok(Visibility::Private),
+            other => bail!("unknown visibility `{other}`"),
+        }
+    }
+
+    pub fn as_graphql(self) -> &'static str {
+        match self {
+            Visibility::Public => "PUBLIC",
+            Visibility::Private => "PRIVATE",
+        }
+    }
+
+    pub fn from_graphql(value: &str) -> anyhow::Result<Self> {
+        Self::parse(&value.to_ascii_lowercase())
+    }
+}
+
+/// A group's own defaults; `None` defers to the parent group.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GroupSettings {
+    pub labels: Option<Vec<Label>>,
+    pub branch_protection: Option<PolicyRules>,
+    pub visibility: Option<Visibility>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    Labels,
+    BranchProtection,
+    Visibility,
+}
+
+impl Setting {
+    pub fn as_graphql(self) -> &'static str {
+        match self {
+            Setting::Labels => "LABELS",
+            Setting::BranchProtection => "BRANCH_PROTECTION",
+            Setting::Visibility => "VISIBILITY",
+        }
+    }
+}
+
+/// Where a repository's value for a setting came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// Nothing set anywhere; the instance default applies.
+    Default,
+    /// Copied from this group when the repository was created.
+    Group(String),
+    /// Set on the repository itself.
+    Repository,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Provenance {
+    pub setting: Setting,
+    pub source: Source,
+    /// The group whose current default the repository's own value replaces.
+    pub overrides: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EffectiveSettings {
+    pub labels: Vec<Label>,
+    pub branch_protection: PolicyRules,
+    pub visibility: Visibility,
+    pub provenance: Vec<Provenance>,
+}
+
+/// A default together with the group that set it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Inherited<T> {
+    pub value: T,
+    pub group_id: String,
+}
+
+/// What a repository created in a group receives, after walking up its ancestors.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResolvedDefaults {
+    pub labels: Option<Inherited<Vec<Label>>>,
+    pub branch_protection: Option<Inherited<PolicyRules>>,
+    pub visibility: Option<Inherited<Visibility>>,
+}
+
+#[derive(sqlx::FromRow)]
+struct GroupSettingsRow {
+    group_id: String,
+    labels: Option<String>,
+    branch_protection: Option<String>,
+    visibility: Option<String>,
+}
+
+impl GroupSettingsRow {
+    fn parse(self) -> anyhow::Result<(String, GroupSettings)> {
+        let settings = GroupSettings {
+            labels: self.labels.as_deref().map(parse_labels).transpose()?,
+            branch_protection: self
+                .branch_protection
+                .as_deref()
+                .map(parse_policy)
+                .transpose()?,
+            visibility: self
+                .visibility
+                .as_deref()
+                .map(Visibility::parse)
+                .transpose()?,
+        };
+        Ok((self.group_id, settings))
+    }
+}
+
+#[derive(Debug, Default, sqlx::FromRow)]
+struct RepositorySettingsRow {
+    labels: Option<String>,
+    labels_from: Option<String>,
+    visibility: Option<String>,
+    visibility_from: Option<String>,
+    branch_protection_from: Option<String>,
+}
+
+/// Trims names, lowercases colours and rejects duplicates.
+pub fn normalize_labels(labels: Vec<Label>) -> anyhow::Result<Vec<Label>> {
+    let mut normalized: Vec<Label> = Vec::with_capacity(labels.len());
+    for label in labels {
+        let name = label.name.trim().to_string();
+        if name.is_empty() || name.chars().count() > MAX_LABEL_NAME {
+            bail!("label names must be 1 to {MAX_LABEL_NAME} characters");
+        }
+        let color = label.color.trim().to_ascii_lowercase();
+        let hex = color.strip_prefix('#').unwrap_or_default();
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("label `{name}` needs a colour like #d73a4a");
+        }
+        if normalized
+            .iter()
+            .any(|existing| existing.name.eq_ignore_ascii_case(&name))
+        {
+            bail!("label `{name}` is listed twice");
+        }
+        let description = label
+            .description
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty());
+        normalized.push(Label {
+            name,
+            color,
+            description,
+        });
+    }
+    Ok(normalized)
+}
+
+fn parse_labels(source: &str) -> anyhow::Result<Vec<Label>> {
+    serde_json::from_str(source).context("stored labels")
+}
+
+pub async fn load_group_settings(
+    pool: &SqlitePool,
+    group_id: &str,
+) -> anyhow::Result<GroupSettings> {
+    let row = sqlx::query_as::<_, GroupSettingsRow>(
+        "SELECT group_id, labels, branch_protection, visibility FROM group_settings \
+         WHERE group_id = ?",
+    )
+    .bind(group_id)
+    .fetch_optional(pool)
+    .await?;
+    match row {
+        Some(row) => Ok(row.parse()?.1),
+        None => Ok(GroupSettings::default()),
+    }
+}
+
+/// Replaces a group's defaults. Labels are normalised first.
+pub async fn save_group_settings(
+    pool: &SqlitePool,
+    group_id: &str,
+    settings: GroupSettings,
+) -> anyhow::Result<GroupSettings> {
+    let settings = GroupSettings {
+        labels: settings.labels.map(normalize_labels).transpose()?,
+        ..settings
+    };
+    if let Some(rules) = &settings.branch_protection {
+        rules.validate()?;
+    }
+    if settings == GroupSettings::default() {
+        sqlx::query("DELETE FROM group_settings WHERE group_id = ?")
+            .bind(group_id)
+            .execute(pool)
+            .await?;
+        return Ok(settings);
+    }
+    sqlx::query(
+        "INSERT INTO group_settings (group_id, labels, branch_protection, visibility, updated_at) \
+         VALUES (?, ?, ?, ?, ?) \
+         ON CONFLICT(group_id) DO UPDATE SET labels = excluded.labels, \
+         branch_protection = excluded.branch_protection, visibility = excluded.visibility, \
+         updated_at = excluded.updated_at",
+    )
+    .bind(group_id)
+    .bind(
+        settings
+            .labels
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?,
+    )
+    .bind(settings.branch_protection.as_ref().map(policy_source))
+    .bind(settings.visibility.map(Visibility::as_str))
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    Ok(settings)
+}
+
+/// The nearest value for each setting among `group_id` and its ancestors.
+pub async fn resolve_defaults(
+    pool: &SqlitePool,
+    group_id: &str,
+) -> anyhow::Result<ResolvedDefaults> {
+    let rows = sqlx::query_as::<_, GroupSettingsRow>(
+        "WITH RECURSIVE chain(id, parent, depth) AS ( \
+             SELECT id, parent, 0 FROM groups WHERE id = ? \
+             UNION ALL \
+             SELECT groups.id, groups.parent, chain.depth + 1 FROM groups JOIN chain ON groups.id = chain.parent \
+         ) \
+         SELECT chain.id AS group_id, s.labels, s.branch_protection, s.visibility \
+         FROM chain JOIN group_settings s ON s.group_id = chain.id ORDER BY chain.depth",
+    )
+    .bind(group_id)
+    .fetch_all(pool)
+    .await?;
+    let mut resolved = ResolvedDefaults::default();
+    for row in rows {
+        let (group_id, settings) = row.parse()?;
+        if let (None, Some(value)) = (&resolved.labels, settings.labels) {
+            resolved.labels = Some(Inherited {
+                value,
+                group_id: group_id.clone(),
+            });
+        }
+        if let (None, Some(value)) = (&resolved.branch_protection, settings.branch_protection) {
+            resolved.branch_protection = Some(Inherited {
+                value,
+                group_id: group_id.clone(),
+            });
+        }
+        if let (None, Some(value)) = (&resolved.visibility, settings.visibility) {
+            resolved.visibility = Some(Inherited { value, group_id });
+        }
+    }
+    Ok(resolved)
+}
+
+async fn load_repository_row(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> anyhow::Result<RepositorySettingsRow> {
+    let row = sqlx::query_as::<_, RepositorySettingsRow>(
+        "SELECT labels, labels_from, visibility, visibility_from, branch_protection_from \
+         FROM repository_settings WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.unwrap_or_default())
+}
+
+async fn store_repository_row(
+    pool: &SqlitePool,
+    repository_id: &str,
+    row: &RepositorySettingsRow,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "INSERT INTO repository_settings (repository_id, labels, labels_from, visibility, \
+         visibility_from, branch_protection_from, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(repository_id) DO UPDATE SET labels = excluded.labels, \
+         labels_from = excluded.labels_from, visibility = excluded.visibility, \
+         visibility_from = excluded.visibility_from, \
+         branch_protection_from = excluded.branch_protection_from, updated_at = excluded.updated_at",
+    )
+    .bind(repository_id)
+    .bind(&row.labels)
+    .bind(&row.labels_from)
+    .bind(&row.visibility)
+    .bind(&row.visibility_from)
+    .bind(&row.branch_protection_from)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Copies the defaults of a new repository's group onto it. Does nothing for
+/// repositories at the root or in groups without defaults.
+pub async fn inherit_group_settings(
+    pool: &SqlitePool,
+    record: &RepositoryRecord,
+) -> anyhow::Result<()> {
+    let Some(group_id) = &record.group_id else {
+        return Ok(());
+    };
+    let defaults = resolve_defaults(pool, group_id).await?;
+    if defaults == ResolvedDefaults::default() {
+        return Ok(());
+    }
+    let mut row = RepositorySettingsRow::default();
+    if let Some(labels) = defaults.labels {
+        row.labels = Some(serde_json::to_string(&labels.value)?);
+        row.labels_from = Some(labels.group_id);
+    }
+    if let Some(visibility) = defaults.visibility {
+        row.visibility = Some(visibility.value.as_str().to_string());
+        row.visibility_from = Some(visibility.group_id);
+    }
+    if let Some(rules) = defaults.branch_protection {
+        save_policy(pool, &record.id, &policy_source(&rules.value)).await?;
+        row.branch_protection_from = Some(rules.group_id);
+    }
+    store_repository_row(pool, &record.id, &row).await
+}
+
+/// Sets the given values on the repository itself; `None` leaves a setting as it is.
+pub async fn set_repository_settings(
+    pool: &SqlitePool,
+    repository_id: &str,
+    labels: Option<Vec<Label>>,
+    visibility: Option<Visibility>,
+) -> anyhow::Result<()> {
+    let mut row = load_repository_row(pool, repository_id).await?;
+    if let Some(labels) = labels {
+        row.labels = Some(serde_json::to_string(&normalize_labels(labels)?)?);
+        row.labels_from = None;
+    }
+    if let Some(visibility) = visibility {
+        row.visibility = Some(visibility.as_str().to_string());
+        row.visibility_from = None;
+    }
+    store_repository_row(pool, repository_id, &row).await
+}
+
+/// Forgets that the repository's push policy came from a group; called when the policy
+/// is replaced.
+pub async fn clear_inherited_branch_protection(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "UPDATE repository_settings SET branch_protection_from = NULL WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+fn provenance<T>(
+    setting: Setting,
+    set: bool,
+    from: Option<String>,
+    current: Option<&Inherited<T>>,
+) -> Provenance {
+    let source = match (set, from) {
+        (_, Some(group_id)) => Source::Group(group_id),
+        (true, None) => Source::Repository,
+        (false, None) => Source::Default,
+    };
+    let overrides = match source {
+        Source::Repository => current.map(|inherited| inherited.group_id.clone()),
+        _ => None,
+    };
+    Provenance {
+        setting,
+        source,
+        overrides,
+    }
+}
+
+/// The settings in force for a repository and where each one came from.
+pub async fn effective_settings(
+    pool: &SqlitePool,
+    record: &RepositoryRecord,
+) -> anyhow::Result<EffectiveSettings> {
+    let row = load_repository_row(pool, &record.id).await?;
+    let branch_protection = load_policy(pool, &record.id).await?;
+    let current = match &record.group_id {
+        Some(group_id) => resolve_defaults(pool, group_id).await?,
+        None => ResolvedDefaults::default(),
+    };
+    let provenance = vec![
+        provenance(
+            Setting::Labels,
+            row.labels.is_some(),
+            row.labels_from,
+            current.labels.as_ref(),
+        ),
+        provenance(
+            Setting::BranchProtection,
+            !branch_protection.is_empty(),
+            row.branch_protection_from,
+            current.branch_protection.as_ref(),
+        ),
+        provenance(
+            Setting::Visibility,
+            row.visibility.is_some(),
+            row.visibility_from,
+            current.visibility.as_ref(),
+        ),
+    ];
+    Ok(EffectiveSettings {
+        labels: row
+            .labels
+            .as_deref()
+            .map(parse_labels)
+            .transpose()?
+            .unwrap_or_default(),
+        branch_protection,
+        visibility: match row.visibility.as_deref() {
+            Some(visibility) => Visibility::parse(visibility)?,
+            None => instance_settings(pool).await?.default_visibility,
+        },
+        provenance,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::mutations::{CreateGroupInput, create_group_raw};
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::create_test_pool;
+
+    fn label(name: &str, color: &str) -> Label {
+        Label {
+            name: name.into(),
+            color: color.into(),
+            description: None,
+        }
+    }
+
+    async fn group(pool: &SqlitePool, slug: &str, parent: Option<&str>) -> String {
+        let input = CreateGroupInput {
+            slug: slug.into(),
+            parent: parent.map(str::to_string),
+        };
+        create_group_raw(pool, input).await.unwrap().id
+    }
+
+    async fn repository(pool: &SqlitePool, slug: &str, group: &str) -> RepositoryRecord {
+        let input = CreateRepositoryInput {
+            slug: slug.into(),
+            group: Some(group.into()),
+        };
+        create_repository_raw(pool, input).await.unwrap()
+    }
+
+    #[test]
+    fn labels_are_normalized_and_checked() {
+        let labels = normalize_labels(vec![
+            label(" bug ", "#D73A4A"),
+            Label {
+                description: Some("  ".into()),
+                ..label("docs", "#0075ca")
+            },
+        ])
+        .unwrap();
+        assert_eq!(
+            labels,
+            vec![label("bug", "#d73a4a"), label("docs", "#0075ca")]
+        );
+
+        assert!(normalize_labels(vec![label("bug", "red")]).is_err());
+        assert!(normalize_labels(vec![label("", "#ffffff")]).is_err());
+        assert!(normalize_labels(vec![label("Bug", "#ffffff"), label("bug", "#000000")]).is_err());
+    }
+
+    #[tokio::test]
+    async fn new_repositories_copy_the_nearest_defaults() {
+        let pool = create_test_pool().await.unwrap();
+        let team = group(&pool, "team", None).await;
+        let infra = group(&pool, "infra", Some(&team)).await;
+        let protection = parse_policy("(forbid_merge_commits: true)").unwrap();
+        save_group_settings(
+            &pool,
+            &team,
+            GroupSettings {
+                labels: Some(vec![label("bug", "#d73a4a")]),
+                branch_protection: Some(protection.clone()),
+                visibility: None,
+            },
+        )
+        .await
+        .unwrap();
+        save_group_settings(
+            &pool,
+            &infra,
+            GroupSettings {
+                visibility: Some(Visibility::Private),
+                ..GroupSettings::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let tools = repository(&pool, "tools", &infra).await;
+        let effective = effective_settings(&pool, &tools).await.unwrap();
+        assert_eq!(effective.labels, vec![label("bug", "#d73a4a")]);
+        assert_eq!(effective.branch_protection, protection);
+        assert_eq!(effective.visibility, Visibility::Private);
+        let sources: Vec<_> = effective
+            .provenance
+            .iter()
+            .map(|p| p.source.clone())
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                Source::Group(team.clone()),
+                Source::Group(team.clone()),
+                Source::Group(infra.clone())
+            ]
+        );
+
+        // Later edits to the group leave existing repositories alone.
+        save_group_settings(&pool, &team, GroupSettings::default())
+            .await
+            .unwrap();
+        let effective = effective_settings(&pool, &tools).await.unwrap();
+        assert_eq!(effective.labels, vec![label("bug", "#d73a4a")]);
+        let app = repository(&pool, "app", &team).await;
+        let effective = effective_settings(&pool, &app).await.unwrap();
+        assert!(effective.labels.is_empty());
+        assert!(effective.branch_protection.is_empty());
+        assert!(
+            effective
+                .provenance
+                .iter()
+                .all(|p| p.source == Source::Default)
+        );
+    }
+
+    #[tokio::test]
+    async fn repository_values_are_reported_as_overrides() {
+        let pool = create_test_pool().await.unwrap();
+        let team = group(&pool, "team", None).await;
+        save_group_settings(
+            &pool,
+            &team,
+            GroupSettings {
+                labels: Some(vec![label("bug", "#d73a4a")]),
+                branch_protection: Some(parse_policy("(require_signed_commits: true)").unwrap()),
+                visibility: Some(Visibility::Private),
+            },
+        )
+        .await
+        .unwrap();
+        let app = repository(&pool, "app", &team).await;
+
+        set_repository_settings(&pool, &app.id, None, Some(Visibility::Public))
+            .await
+            .unwrap();
+        save_policy(&pool, &app.id, "(forbid_merge_commits: true)")
+            .await
+            .unwrap();
+        clear_inherited_branch_protection(&pool, &app.id)
+            .await
+            .unwrap();
+
+        let effective = effective_settings(&pool, &app).await.unwrap();
+        assert_eq!(effective.visibility, Visibility::Public);
+        assert_eq!(
+            effective.provenance,
+            vec![
+                Provenance {
+                    setting: Setting::Labels,
+                    source: Source::Group(team.clone()),
+                    overrides: None,
+                },
+                Provenance {
+                    setting: Setting::BranchProtection,
+                    source: Source::Repository,
+                    overrides: Some(team.clone()),
+                },
+                Provenance {
+                    setting: Setting::Visibility,
+                    source: Source::Repository,
+                    overrides: Some(team.clone()),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Instance settings and first-run setup.
+//!
+//! A fresh instance has no administrator unless `auth.admin_dids` names one. Until setup
+//! is completed, each startup prints a new one-time setup token ([`prepare_setup`]), and
+//! `completeSetup` trades it for making an account that has signed in an administrator.
+//! Administrators added this way are stored in the database and count alongside
+//! `auth.admin_dids` (see [`is_admin`]). Admin checks are synchronous, so each instance
+//! keeps the stored list in memory, reloads it every [`ADMIN_REFRESH_INTERVAL`] and
+//! updates it at once when setup completes on that instance.
+//!
+//! The settings themselves are one database row: the instance's name and description,
+//! and the visibility of repositories whose visibility was never set, by the repository
+//! or by one of its groups (see [`crate::repository::visibility`]).
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::bail;
+use rand::RngCore;
+use sqlx::{Row, SqlitePool};
+
+use crate::events::{Event, publish};
+use crate::group::settings::Visibility;
+use crate::user::db::{fetch_user_by_handle, unix_now};
+use crate::user::models::UserRecord;
+use crate::user::security::token_hash;
+
+pub const SETUP_TOKEN_PREFIX: &str = "forge_setup_";
+pub const ADMIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+pub const DEFAULT_NAME: &str = "Forgepoint";
+pub const MAX_NAME_LEN: usize = 100;
+pub const MAX_DESCRIPTION_LEN: usize = 500;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstanceSettings {
+    pub name: String,
+    pub description: Option<String>,
+    pub default_visibility: Visibility,
+    pub setup_completed_at: Option<i64>,
+    pub updated_at: Option<i64>,
+}
+
+impl Default for InstanceSettings {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_NAME.to_string(),
+            description: None,
+            default_visibility: Visibility::default(),
+            setup_completed_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+impl InstanceSettings {
+    /// Whether the instance still has to be set up: setup was never completed and no
+    /// administrator is configured.
+    pub fn setup_required(&self) -> bool {
+        self.setup_completed_at.is_none() && crate::config::current().auth.admin_dids.is_empty()
+    }
+}
+
+/// Changes to the settings; `None` leaves a value as it is, and an empty description
+/// removes it.
+#[derive(Clone, Debug, Default)]
+pub struct InstanceSettingsUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub default_visibility: Option<Visibility>,
+}
+
+pub async fn instance_settings(pool: &SqlitePool) -> anyhow::Result<InstanceSettings> {
+    let row = sqlx::query(
+        "SELECT name, description, default_visibility, setup_completed_at, updated_at \
+         FROM instance_settings WHERE id = 1",
+    )
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else {
+        return Ok(InstanceSettings::default());
+    };
+    Ok(InstanceSettings {
+        name: row.try_get("name")?,
+        description: row.try_get("description")?,
+        default_visibility: Visibility::parse(row.try_get("default_visibility")?)?,
+        setup_completed_at: row.try_get("setup_completed_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+pub async fn update_instance_settings(
+    pool: &SqlitePool,
+    update: InstanceSettingsUpdate,
+) -> anyhow::Result<InstanceSettings> {
+    let mut settings = instance_settings(pool).await?;
+    if let Some(name) = update.name {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+            bail!("instance name must be 1 to {} characters", MAX_NAME_LEN);
+        }
+        settings.name = name.to_string();
+    }
+    if let Some(description) = update.description {
+        let description = description.trim();
+        if description.chars().count() > MAX_DESCRIPTION_LEN {
+            bail!(
+                "instance description is longer than {} characters",
+                MAX_DESCRIPTION_LEN
+            );
+        }
+        settings.description = (!description.is_empty()).then(|| description.to_string());
+    }
+    if let Some(visibility) = update.default_visibility {
+        settings.default_visibility = visibility;
+    }
+    settings.updated_at = Some(unix_now());
+    sqlx::query(
+        "INSERT INTO instance_settings (id, name, description, default_visibility, updated_at) \
+         VALUES (1, ?, ?, ?, ?) \
+         ON CONFLICT(id) DO UPDATE SET name = excluded.name, description = excluded.description, \
+         default_visibility = excluded.default_visibility, updated_at = excluded.updated_at",
+    )
+    .bind(&settings.name)
+    .bind(&settings.description)
+    .bind(settings.default_visibility.as_str())
+    .bind(settings.updated_at)
+    .execute(pool)
+    .await?;
+    Ok(settings)
+}
+
+/// Creates a new setup token when setup is still required, replacing any earlier one.
+/// Only its hash is stored, so the returned token is the only copy.
+pub async fn prepare_setup(pool: &SqlitePool) -> anyhow::Result<Option<String>> {
+    if !instance_settings(pool).await?.setup_required() {
+        return Ok(None);
+    }
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let token = format!(
+        "{}{}",
+        SETUP_TOKEN_PREFIX,
+        secret
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    );
+    sqlx::query(
+        "INSERT INTO instance_settings (id, name, default_visibility, setup_token_hash) \
+         VALUES (1, ?, ?, ?) \
+         ON CONFLICT(id) DO UPDATE SET setup_token_hash = excluded.setup_token_hash",
+    )
+    .bind(DEFAULT_NAME)
+    .bind(Visibility::default().as_str())
+    .bind(token_hash(&token))
+    .execute(pool)
+    .await?;
+    Ok(Some(token))
+}
+
+/// Makes the account with `admin_handle` the instance's administrator and uses up the
+/// setup token. The account must have signed in before.
+pub async fn complete_setup(
+    pool: &SqlitePool,
+    token: &str,
+    admin_handle: &str,
+) -> anyhow::Result<UserRecord> {
+    if !crate::config::current().auth.admin_dids.is_empty() {
+        bail!("this instance's administrators are configured with auth.admin_dids");
+    }
+    let handle = admin_handle.trim().trim_start_matches('@');
+    let Some(admin) = fetch_user_by_handle(pool, handle).await? else {
+        bail!(
+            "no account with the handle `{}` has signed in yet; sign in with it once, then complete setup",
+            handle
+        );
+    };
+
+    let now = unix_now();
+    let mut tx = pool.begin().await?;
+    let claimed = sqlx::query(
+        "UPDATE instance_settings SET setup_token_hash = NULL, setup_completed_at = ?, \
+         updated_at = ? WHERE id = 1 AND setup_completed_at IS NULL AND setup_token_hash = ?",
+    )
+    .bind(now)
+    .bind(now)
+    .bind(token_hash(token.trim()))
+    .execute(&mut *tx)
+    .await?;
+    if claimed.rows_affected() == 0 {
+        bail!("the setup token is not valid, or setup was already completed");
+    }
+    sqlx::query("INSERT OR IGNORE INTO instance_admins (did, created_at) VALUES (?, ?)")
+        .bind(&admin.did)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+    publish(
+        &mut *tx,
+        crate::config::current().event_sink.as_ref(),
+        Event {
+            kind: "instance.setup_completed",
+            source: "core",
+            actor_did: Some(&admin.did),
+            repository_id: None,
+            data: serde_json::json!({ "adminHandle": admin.handle }),
+        },
+    )
+    .await?;
+    tx.commit().await?;
+
+    // This instance knows at once; others pick it up on their next refresh.
+    let mut admins = ADMINS.lock().unwrap_or_else(|e| e.into_inner());
+    if !admins.contains(&admin.did) {
+        admins.push(admin.did.clone());
+    }
+    Ok(admin)
+}
+
+/// Administrators added with `completeSetup`, as last loaded.
+static ADMINS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Reloads the administrators stored in the database.
+pub async fn refresh_admins(pool: &SqlitePool) -> anyhow::Result<()> {
+    let dids: Vec<String> = sqlx::query_scalar("SELECT did FROM instance_admins ORDER BY did")
+        .fetch_all(pool)
+        .await?;
+    *ADMINS.lock().unwrap_or_else(|e| e.into_inner()) = dids;
+    Ok(())
+}
+
+/// Whether `did` is an instance administrator, from `auth.admin_dids` or setup.
+pub fn is_admin(did: &str) -> bool {
+    crate::config::current()
+        .auth
+        .admin_dids
+        .iter()
+        .any(|admin| admin == did)
+        || ADMINS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .any(|admin| admin == did)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::User;
+    use crate::group::settings::set_repository_settings;
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::repository::visibility::visible_repository_ids;
+    use crate::test_helpers::create_test_pool;
+    use crate::user::db::upsert_user;
+
+    #[tokio::test]
+    async fn setup_binds_the_first_admin_once() {
+        let pool = create_test_pool().await.unwrap();
+        upsert_user(&pool, &User::new("did:plc:ada".into(), "ada.test".into()))
+            .await
+            .unwrap();
+        assert!(instance_settings(&pool).await.unwrap().setup_required());
+        let stale = prepare_setup(&pool).await.unwrap().unwrap();
+        let token = prepare_setup(&pool).await.unwrap().unwrap();
+        assert!(token.starts_with(SETUP_TOKEN_PREFIX));
+
+        // Only the latest token works, and only for an account that has signed in.
+        assert!(complete_setup(&pool, &stale, "ada.test").await.is_err());
+        assert!(complete_setup(&pool, &token, "bob.test").await.is_err());
+        assert!(!is_admin("did:plc:ada"));
+        let admin = complete_setup(&pool, &token, "@ada.test").await.unwrap();
+        assert_eq!(admin.did, "did:plc:ada");
+        assert!(is_admin("did:plc:ada"));
+        refresh_admins(&pool).await.unwrap();
+        assert!(is_admin("did:plc:ada"));
+
+        assert!(complete_setup(&pool, &token, "ada.test").await.is_err());
+        assert!(prepare_setup(&pool).await.unwrap().is_none());
+        let settings = instance_settings(&pool).await.unwrap();
+        assert!(!settings.setup_required());
+        assert_eq!(settings.name, DEFAULT_NAME);
+    }
+
+    #[tokio::test]
+    async fn default_visibility_applies_to_repositories_without_one() {
+        let pool = create_test_pool().await.unwrap();
+        let mut ids = Vec::new();
+        for slug in ["unset", "public"] {
+            let input = CreateRepositoryInput {
+                slug: slug.into(),
+                group: None,
+            };
+            ids.push(create_repository_raw(&pool, input).await.unwrap().id);
+        }
+        set_repository_settings(&pool, &ids[1], None, Some(Visibility::Public))
+            .await
+            .unwrap();
+        assert_eq!(
+            visible_repository_ids(&pool, None, None)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let update = InstanceSettingsUpdate {
+            name: Some("  Acme Forge ".into()),
+            description: Some("Code for Acme".into()),
+            default_visibility: Some(Visibility::Private),
+        };
+        let settings = update_instance_settings(&pool, update).await.unwrap();
+        assert_eq!(settings.name, "Acme Forge");
+        assert_eq!(instance_settings(&pool).await.unwrap(), settings);
+        assert_eq!(
+            visible_repository_ids(&pool, None, None).await.unwrap(),
+            [ids[1].clone()]
+        );
+
+        let update = InstanceSettingsUpdate {
+            name: Some(" ".into()),
+            ..Default::default()
+        };
+        assert!(update_instance_settings(&pool, update).await.is_err());
+    }
+}
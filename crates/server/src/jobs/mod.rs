@@ -0,0 +1,15 @@
+//! Durable background jobs.
+//!
+//! Jobs live in the `jobs` table so work survives restarts. Failed attempts are retried
+//! with exponential backoff until `max_attempts`; handlers signal errors that retrying
+//! cannot fix by returning [`PermanentFailure`]. Long jobs record a [`JobProgress`]
+//! between batches, which administrators read with the `job` and `jobs` queries.
+
+pub mod queue;
+pub mod worker;
+
+pub use queue::{
+    JOB_CURSOR_KIND, JobFilter, JobProgress, JobQueue, JobRecord, PermanentFailure, enqueue_at_in,
+    enqueue_in, set_progress,
+};
+pub use worker::{JobHandler, JobWorker};
@@ -0,0 +1,342 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteExecutor, SqlitePool};
+
+use crate::graphql::pagination::{Direction, Page, PageRequest, encode_cursor};
+use crate::user::db::unix_now;
+
+/// Cursors for the job list are tagged with this kind.
+pub const JOB_CURSOR_KIND: &str = "job";
+const JOB_COLUMNS: &str = "id, kind, payload, status, attempts, max_attempts, run_at, last_error, \
+     progress, created_at, updated_at";
+
+const BACKOFF_BASE_SECS: i64 = 30;
+const BACKOFF_MAX_SECS: i64 = 60 * 60;
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct JobRecord {
+    pub id: String,
+    pub kind: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i64,
+    pub max_attempts: i64,
+    pub run_at: i64,
+    pub last_error: Option<String>,
+    /// JSON [`JobProgress`], for jobs that record it.
+    pub progress: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl JobRecord {
+    pub fn payload<T: serde::de::DeserializeOwned>(&self) -> anyhow::Result<T> {
+        Ok(serde_json::from_str(&self.payload)?)
+    }
+
+    /// The recorded progress; empty for a job that has not recorded any.
+    pub fn progress(&self) -> JobProgress {
+        self.progress
+            .as_deref()
+            .and_then(|progress| serde_json::from_str(progress).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn cursor(&self) -> String {
+        encode_cursor(JOB_CURSOR_KIND, &self.created_at.to_string(), &self.id)
+    }
+}
+
+/// How far a long job has got. Handlers record it between batches with [`set_progress`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobProgress {
+    pub processed: i64,
+    pub total: Option<i64>,
+    /// Items found out of date, for jobs that check something
+    pub mismatches: Option<i64>,
+    /// Where a retried or interrupted job resumes
+    pub cursor: Option<String>,
+}
+
+/// Records the progress of job `id`.
+pub async fn set_progress(
+    executor: impl SqliteExecutor<'_>,
+    id: &str,
+    progress: &JobProgress,
+) -> anyhow::Result<()> {
+    sqlx::query("UPDATE jobs SET progress = ?, updated_at = ? WHERE id = ?")
+        .bind(serde_json::to_string(progress)?)
+        .bind(unix_now())
+        .bind(id)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// Narrows the job list.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JobFilter {
+    pub kind: Option<String>,
+    /// `queued`, `running`, `succeeded` or `failed`
+    pub status: Option<String>,
+}
+
+const JOB_FILTER_SQL: &str = "(?1 IS NULL OR kind = ?1) AND (?2 IS NULL OR status = ?2)";
+
+/// One page of jobs, newest first.
+pub async fn page_jobs(
+    pool: &SqlitePool,
+    filter: &JobFilter,
+    request: &PageRequest,
+) -> anyhow::Result<Page<JobRecord>> {
+    let (after, order) = match request.direction {
+        Direction::Forward => (
+            "(created_at, id) < (?3, ?4)",
+            "ORDER BY created_at DESC, id DESC",
+        ),
+        Direction::Backward => ("(created_at, id) > (?3, ?4)", "ORDER BY created_at, id"),
+    };
+    let sql = format!(
+        "SELECT {JOB_COLUMNS} FROM jobs WHERE {JOB_FILTER_SQL} \
+         AND (?3 IS NULL OR {after}) {order} LIMIT ?5"
+    );
+    let query = sqlx::query_as::<_, JobRecord>(&sql)
+        .bind(filter.kind.as_deref())
+        .bind(filter.status.as_deref());
+    let query = match &request.cursor {
+        Some(cursor) => {
+            let created_at: i64 = cursor
+                .slug
+                .parse()
+                .map_err(|_| anyhow!("invalid job cursor"))?;
+            query.bind(Some(created_at)).bind(Some(cursor.id.as_str()))
+        }
+        None => query.bind(None::<i64>).bind(None::<&str>),
+    };
+    let rows = query.bind(request.fetch_limit()).fetch_all(pool).await?;
+    Ok(Page::from_rows(request, rows))
+}
+
+pub async fn count_jobs(pool: &SqlitePool, filter: &JobFilter) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM jobs WHERE {JOB_FILTER_SQL}"))
+        .bind(filter.kind.as_deref())
+        .bind(filter.status.as_deref())
+        .fetch_one(pool)
+        .await
+}
+
+/// Marks an error as not worth retrying (bad input, limits exceeded).
+#[derive(Debug)]
+pub struct PermanentFailure(pub String);
+
+impl std::fmt::Display for PermanentFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PermanentFailure {}
+
+/// Delay before retry number `attempt` (1-based): 30s, 60s, 120s, ... capped at an hour.
+pub fn backoff_secs(attempt: i64) -> i64 {
+    let exponent = attempt.saturating_sub(1).clamp(0, 16) as u32;
+    BACKOFF_BASE_SECS
+        .saturating_mul(2_i64.saturating_pow(exponent))
+        .min(BACKOFF_MAX_SECS)
+}
+
+/// Inserts a job through `executor`, so it can be queued inside the caller's transaction.
+pub async fn enqueue_in<'e, E, T>(
+    executor: E,
+    kind: &str,
+    payload: &T,
+    max_attempts: i64,
+) -> anyhow::Result<String>
+where
+    E: SqliteExecutor<'e>,
+    T: Serialize,
+{
+    enqueue_at_in(executor, kind, payload, max_attempts, unix_now()).await
+}
+
+/// Like [`enqueue_in`], but the job is not claimed before `run_at`.
+pub async fn enqueue_at_in<'e, E, T>(
+    executor: E,
+    kind: &str,
+    payload: &T,
+    max_attempts: i64,
+    run_at: i64,
+) -> anyhow::Result<String>
+where
+    E: SqliteExecutor<'e>,
+    T: Serialize,
+{
+    let id = cuid2::create_id();
+    let now = unix_now();
+    sqlx::query(
+        "INSERT INTO jobs (id, kind, payload, status, attempts, max_attempts, run_at, created_at, updated_at) \
+         VALUES (?, ?, ?, 'queued', 0, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(kind)
+    .bind(serde_json::to_string(payload)?)
+    .bind(max_attempts.max(1))
+    .bind(run_at)
+    .bind(now)
+    .bind(now)
+    .execute(executor)
+    .await?;
+    Ok(id)
+}
+
+#[derive(Clone)]
+pub struct JobQueue {
+    pool: SqlitePool,
+}
+
+impl JobQueue {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn enqueue<T: Serialize>(
+        &self,
+        kind: &str,
+        payload: &T,
+        max_attempts: i64,
+    ) -> anyhow::Result<String> {
+        enqueue_in(&self.pool, kind, payload, max_attempts).await
+    }
+
+    /// Claims the oldest due job, if any, and marks it running.
+    pub async fn claim_next(&self, now: i64) -> Result<Option<JobRecord>, sqlx::Error> {
+        loop {
+            let candidate: Option<String> = sqlx::query_scalar(
+                "SELECT id FROM jobs WHERE status = 'queued' AND run_at <= ? ORDER BY run_at, created_at LIMIT 1",
+            )
+            .bind(now)
+            .fetch_optional(&self.pool)
+            .await?;
+            let Some(id) = candidate else {
+                return Ok(None);
+            };
+
+            let claimed = sqlx::query(
+                "UPDATE jobs SET status = 'running', attempts = attempts + 1, updated_at = ? \
+                 WHERE id = ? AND status = 'queued'",
+            )
+            .bind(now)
+            .bind(&id)
+            .execute(&self.pool)
+            .await?;
+            // Another worker got there first; look again.
+            if claimed.rows_affected() == 0 {
+                continue;
+            }
+
+            return self.get(&id).await;
+        }
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<JobRecord>, sqlx::Error> {
+        sqlx::query_as::<_, JobRecord>(&format!("SELECT {JOB_COLUMNS} FROM jobs WHERE id = ?"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    pub async fn complete(&self, id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE jobs SET status = 'succeeded', last_error = NULL, updated_at = ? WHERE id = ?",
+        )
+        .bind(unix_now())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Records a failed attempt. Returns `true` when the job will be retried.
+    pub async fn fail(
+        &self,
+        job: &JobRecord,
+        error: &str,
+        permanent: bool,
+        now: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let retry = !permanent && job.attempts < job.max_attempts;
+        if retry {
+            sqlx::query(
+                "UPDATE jobs SET status = 'queued', run_at = ?, last_error = ?, updated_at = ? WHERE id = ?",
+            )
+            .bind(now + backoff_secs(job.attempts))
+            .bind(error)
+            .bind(now)
+            .bind(&job.id)
+            .execute(&self.pool)
+            .await?;
+        } else {
+            sqlx::query(
+                "UPDATE jobs SET status = 'failed', last_error = ?, updated_at = ? WHERE id = ?",
+            )
+            .bind(error)
+            .bind(now)
+            .bind(&job.id)
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(retry)
+    }
+
+    /// Requeues jobs left `running` by a previous process that exited mid-job.
+    pub async fn requeue_interrupted(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE jobs SET status = 'queued', updated_at = ? WHERE status = 'running'",
+        )
+        .bind(unix_now())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    #[test]
+    fn backoff_doubles_and_caps() {
+        assert_eq!(backoff_secs(1), 30);
+        assert_eq!(backoff_secs(2), 60);
+        assert_eq!(backoff_secs(4), 240);
+        assert_eq!(backoff_secs(20), BACKOFF_MAX_SECS);
+    }
+
+    #[tokio::test]
+    async fn failed_jobs_retry_until_exhausted() {
+        let pool = create_test_pool().await.unwrap();
+        let queue = JobQueue::new(pool);
+        let id = queue
+            .enqueue("noop", &serde_json::json!({}), 2)
+            .await
+            .unwrap();
+        let now = unix_now();
+
+        let job = queue.claim_next(now).await.unwrap().unwrap();
+        assert_eq!(job.id, id);
+        assert!(queue.claim_next(now).await.unwrap().is_none());
+        assert!(queue.fail(&job, "flaky", false, now).await.unwrap());
+
+        // Not due until the backoff elapses.
+        assert!(queue.claim_next(now).await.unwrap().is_none());
+        let job = queue
+            .claim_next(now + backoff_secs(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.attempts, 2);
+        assert!(!queue.fail(&job, "still flaky", false, now).await.unwrap());
+        assert_eq!(queue.get(&id).await.unwrap().unwrap().status, "failed");
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
+
+use super::queue::{JobQueue, JobRecord, PermanentFailure};
+use crate::user::db::unix_now;
+
+#[async_trait]
+pub trait JobHandler: Send + Sync {
+    async fn run(&self, job: &JobRecord) -> anyhow::Result<()>;
+
+    /// Called once the last attempt has failed so handlers can record the outcome.
+    async fn exhausted(&self, _job: &JobRecord, _error: &str) {}
+}
+
+/// Polls the queue and dispatches jobs to handlers by kind, one at a time.
+pub struct JobWorker {
+    queue: JobQueue,
+    handlers: HashMap<&'static str, Arc<dyn JobHandler>>,
+    poll_interval: Duration,
+}
+
+impl JobWorker {
+    pub fn new(queue: JobQueue) -> Self {
+        Self {
+            queue,
+            handlers: HashMap::new(),
+            poll_interval: Duration::from_secs(2),
+        }
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn register(mut self, kind: &'static str, handler: Arc<dyn JobHandler>) -> Self {
+        self.handlers.insert(kind, handler);
+        self
+    }
+
+    pub async fn run(self, shutdown: CancellationToken) -> anyhow::Result<()> {
+        match self.queue.requeue_interrupted().await {
+            Ok(n) if n > 0 => tracing::info!("requeued {} interrupted jobs", n),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("failed to requeue interrupted jobs: {}", e),
+        }
+
+        loop {
+            // A read-only standby leaves the queue to the instance holding the storage lease.
+            if crate::coordination::is_read_only() {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(self.poll_interval) => {}
+                }
+                continue;
+            }
+
+            let job = match self.queue.claim_next(unix_now()).await {
+                Ok(job) => job,
+                Err(e) => {
+                    tracing::warn!("job claim failed: {}", e);
+                    None
+                }
+            };
+
+            let Some(job) = job else {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(self.poll_interval) => {}
+                }
+                continue;
+            };
+
+            tokio::select! {
+                _ = shutdown.cancelled() => {
+                    // Leave the job `running`; it is requeued on the next start.
+                    break;
+                }
+                _ = self.execute(&job) => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn execute(&self, job: &JobRecord) {
+        let Some(handler) = self.handlers.get(job.kind.as_str()) else {
+            tracing::warn!(job = %job.id, kind = %job.kind, "no handler registered for job kind");
+            let _ = self
+                .queue
+                .fail(job, "no handler registered", true, unix_now())
+                .await;
+            return;
+        };
+
+        match handler.run(job).await {
+            Ok(()) => {
+                if let Err(e) = self.queue.complete(&job.id).await {
+                    tracing::warn!(job = %job.id, "failed to mark job complete: {}", e);
+                }
+            }
+            Err(err) => {
+                let permanent = err.downcast_ref::<PermanentFailure>().is_some();
+                let message = format!("{err:#}");
+                match self.queue.fail(job, &message, permanent, unix_now()).await {
+                    Ok(true) => {
+                        tracing::warn!(job = %job.id, kind = %job.kind, attempt = job.attempts, "job failed, will retry: {}", message)
+                    }
+                    Ok(false) => {
+                        tracing::error!(job = %job.id, kind = %job.kind, "job failed permanently: {}", message);
+                        handler.exhausted(job, &message).await;
+                    }
+                    Err(e) => tracing::warn!(job = %job.id, "failed to record job failure: {}", e),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Key files the server generates on first start: the repository secrets key, the VAPID
+//! key and the federation key.
+//!
+//! A missing file is generated once and written readable by the server's user only. Any
+//! other read error is returned instead of replacing the file, since a new key would
+//! orphan everything encrypted or signed with the old one.
+
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// The contents of the key file at `path`, writing the output of `generate` there when
+/// the file does not exist. `what` names the key in the log line.
+pub fn load_or_generate(
+    path: &Path,
+    what: &str,
+    generate: impl FnOnce() -> Result<Vec<u8>>,
+) -> Result<Vec<u8>> {
+    match std::fs::read(path) {
+        Ok(contents) => return Ok(contents),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+    let contents = generate()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    // Created owner-only, and never over a key that appeared in the meantime
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    file.write_all(&contents)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    tracing::info!("Generated {} in {}", what, path.display());
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_generated_once_and_never_replaced() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("keys/instance.key");
+        let key = load_or_generate(&path, "test key", || Ok(b"first".to_vec())).unwrap();
+        assert_eq!(key, b"first");
+        let key = load_or_generate(&path, "test key", || Ok(b"second".to_vec())).unwrap();
+        assert_eq!(key, b"first");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // An unreadable key is an error, not a reason to replace it
+        let unreadable = dir.path().join("keys");
+        assert!(load_or_generate(&unreadable, "test key", || Ok(Vec::new())).is_err());
+        assert!(unreadable.is_dir());
+    }
+}
@@ -1,16 +1,38 @@
 //! Forge GraphQL Server Library
 
+pub mod announcements;
 pub mod api;
+pub mod audit;
 pub mod auth;
+pub mod compliance;
 pub mod config;
+pub mod coordination;
 pub mod db;
+pub mod encryption;
+pub mod events;
 pub mod extensions;
+pub mod feature_flags;
+pub mod federation;
 pub mod graphql;
 pub mod group;
+pub mod instance;
+pub mod jobs;
+pub mod key_file;
+pub mod markdown;
+pub mod metering;
+pub mod moderation;
+pub mod notifications;
+pub mod og;
+pub mod replication;
 pub mod repository;
 pub mod router;
+pub mod scratch;
+pub mod search;
+pub mod seed;
 pub mod supervisor;
+pub mod user;
 pub mod validation;
+pub mod webhooks;
 
 pub mod test_helpers;
 
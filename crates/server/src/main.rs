@@ -1,14 +1,40 @@
+mod announcements;
 mod api;
+mod audit;
 mod auth;
+mod compliance;
 mod config;
+mod coordination;
 mod db;
+mod encryption;
+mod events;
 mod extensions;
+mod feature_flags;
+mod federation;
 mod graphql;
 mod group;
+mod instance;
+mod jobs;
+mod key_file;
+mod markdown;
+mod metering;
+mod metrics_exporter;
+mod moderation;
+mod notifications;
+mod og;
+mod replication;
 mod repository;
 mod router;
+mod scratch;
+mod search;
+mod seed;
 mod supervisor;
+mod user;
 mod validation;
+mod webhooks;
+
+#[cfg(test)]
+mod test_helpers;
 
 use anyhow::Context as _;
 use std::path::PathBuf;
@@ -18,22 +44,107 @@ use supervisor::Supervisor;
 use api::auth_handlers::AuthState;
 use api::run_api;
 use auth::{AtProtoAuthClient, AuthConfig, SessionManager, SqliteAuthStore};
-use repository::RepositoryStorage;
-use router::RouterState;
+use repository::{PathResolver, RepositoryStorage, StorageLayout};
+use router::LiveRouter;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
+    metrics_exporter::init_metrics();
 
-    let (pool, db_root_path) = db::init_pool().await?;
+    // Defaults < RON file < FORGE_* environment overrides
+    let file_config = config::loader::load_with_discovery().unwrap_or_else(|e| {
+        tracing::warn!("Failed to load config ({}), using defaults", e);
+        config::Config::default()
+    });
+    let mut config = config::env::apply_env_overrides(file_config, std::env::vars())
+        .context("invalid configuration override in environment")?;
+    api::dev_tls::apply_dev_urls(&mut config)?;
+    tracing::info!(
+        "effective configuration:\n{}",
+        serde_json::to_string_pretty(&config::env::redacted(&config)).unwrap_or_default()
+    );
+    config::init(config.clone());
+
+    // `server rekey-databases <new-key-file>` re-encrypts every database and exits. Run it
+    // with the server stopped, then point `db.encryption` at the new key.
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("rekey-databases") {
+        let new_key_file = args
+            .get(2)
+            .context("usage: server rekey-databases <new-key-file>")?;
+        let files =
+            encryption::rekey_databases(&config, std::path::Path::new(new_key_file)).await?;
+        tracing::info!(
+            "rekeyed {} databases; update db.encryption to the new key",
+            files.len()
+        );
+        return Ok(());
+    }
+    let key = encryption::load_key(&config.db.encryption)?;
+    if key.is_some() {
+        tracing::info!("databases are encrypted at rest");
+    }
+    encryption::install(key).await?;
+
+    // `server restore-database [--generation <name>] [--until <secs>] [--output <dir>]`
+    // rebuilds forge.db from the replica in object storage and exits
+    if args.get(1).map(String::as_str) == Some("restore-database") {
+        let options = replication::RestoreOptions::from_args(&args[2..])?;
+        let dir = match &options.output {
+            Some(dir) => dir.clone(),
+            None => db::normalize_path(
+                config
+                    .db
+                    .path
+                    .clone()
+                    .context("db.path must be set (FORGE_DB_PATH)")?,
+            )?,
+        };
+        std::fs::create_dir_all(&dir)?;
+        let store = replication::store::open(&config.db.replication)?;
+        let report = replication::restore(
+            store.as_ref(),
+            &config.db.replication.prefix,
+            &options,
+            &dir,
+        )
+        .await?;
+        tracing::info!(
+            "restored {} from generation {} ({} WAL segments, up to {})",
+            report.path.display(),
+            report.generation,
+            report.segments,
+            report.restored_to
+        );
+        return Ok(());
+    }
 
-    // Handle repository paths - use temp dir in memory mode
-    let repos_root = if std::env::var("FORGE_IN_MEMORY_DB").unwrap_or_default() == "true" {
-        tracing::info!("Using temporary directory for repositories (in-memory mode)");
-        std::env::temp_dir().join("forge-repos")
+    // In-memory mode keeps repositories and extension databases in a scratch directory of
+    // its own, removed when the server exits
+    let scratch = if config.db.in_memory {
+        Some(scratch::ScratchDir::create("memory")?)
     } else {
-        let repos_root_raw = std::env::var("FORGE_REPOS_PATH")
-            .with_context(|| "FORGE_REPOS_PATH environment variable must be set".to_string())?;
+        None
+    };
+    let (pool, db_root_path) = match &scratch {
+        Some(scratch) => db::init_in_memory_pool(&scratch.subdir("db")?).await?,
+        None => db::init_pool(&config.db).await?,
+    };
+    db::install_shared(pool.clone());
+
+    let repos_root = if let Some(scratch) = &scratch {
+        tracing::info!(
+            "Using scratch directory {} for repositories (in-memory mode)",
+            scratch.path().display()
+        );
+        scratch.subdir("repos")?
+    } else {
+        let repos_root_raw = config
+            .repos
+            .path
+            .clone()
+            .context("repos.path must be set (FORGE_REPOS_PATH)")?;
         db::normalize_path(repos_root_raw)?
     };
 
@@ -52,66 +163,172 @@ async fn main() -> anyhow::Result<()> {
         )
     })?;
 
-    let storage = RepositoryStorage::new(repos_root, remote_cache_root);
+    // Only one instance may write to shared storage; the rest refuse to start or run read-only.
+    let storage_lock_path = repos_root.join(".forge.lock");
+    let leases = coordination::LeaseManager::new(
+        pool.clone(),
+        coordination::lease::instance_id(),
+        std::time::Duration::from_secs(
+            std::env::var("FORGE_LEASE_TTL_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(30),
+        ),
+    );
+    let mut storage_lock =
+        coordination::LockFile::try_acquire(&storage_lock_path, leases.holder())?;
+    let holds_lease = storage_lock.is_some()
+        && leases
+            .try_acquire(coordination::STORAGE_WRITER_LEASE)
+            .await?;
+    if !holds_lease {
+        storage_lock = None;
+        let holder = match leases.current(coordination::STORAGE_WRITER_LEASE).await? {
+            Some(lease) => format!(
+                "{}, held since {}, expires at {}",
+                lease.holder, lease.acquired_at, lease.expires_at
+            ),
+            None => coordination::LockFile::describe_holder(&storage_lock_path)
+                .unwrap_or_else(|| "unknown".to_string()),
+        };
+        if std::env::var("FORGE_ALLOW_READ_ONLY").unwrap_or_default() != "true" {
+            anyhow::bail!(
+                "storage at {} is in use by another forge instance ({}); stop it or set FORGE_ALLOW_READ_ONLY=true to start read-only",
+                repos_root.display(),
+                holder
+            );
+        }
+        tracing::warn!("storage is held by {}; starting in read-only mode", holder);
+        coordination::set_read_only(true);
+    }
+
+    let storage = RepositoryStorage::new(repos_root, remote_cache_root)
+        .with_layout(StorageLayout::from_config(&config.repos)?);
+
+    // `server migrate-storage <flat|sharded>` moves repositories between layouts and exits.
+    if args.get(1).map(String::as_str) == Some("migrate-storage") {
+        coordination::ensure_writable()?;
+        let target = match args.get(2) {
+            Some(value) => StorageLayout::parse(value)?,
+            None => storage.layout,
+        };
+        let report = repository::layout::migrate_storage_layout(&pool, &storage, target).await?;
+        tracing::info!(
+            "storage migration to {} complete: {} moved, {} skipped, {} missing",
+            target.as_str(),
+            report.moved,
+            report.skipped,
+            report.missing
+        );
+        return Ok(());
+    }
+
+    // Repositories from before discovery search have no trigrams yet
+    if !coordination::is_read_only() {
+        let indexed = repository::discovery::index_missing(&pool).await?;
+        if indexed > 0 {
+            tracing::info!("indexed {} repositories for discovery search", indexed);
+        }
+    }
+
+    // Until setup is completed, every start prints a new one-time token for `completeSetup`
+    instance::refresh_admins(&pool).await?;
+    if !coordination::is_read_only()
+        && let Some(token) = instance::prepare_setup(&pool).await?
+    {
+        tracing::warn!(
+            "this instance has no administrator yet; sign in with the account that should administer it, then run the completeSetup mutation with the setup token {}",
+            token
+        );
+    }
 
     // Handle extensions directory - use ./extensions relative to server binary
-    let extensions_dir = std::env::var("FORGE_EXTENSIONS_DIR")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| PathBuf::from("./extensions"));
+    let extensions_dir = config
+        .extensions
+        .dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("./extensions"));
 
     let mut extension_manager =
         extensions::ExtensionManager::new(extensions_dir.clone(), db_root_path.clone());
 
-    // Load configuration and extensions
-    match config::loader::load_with_discovery() {
-        Ok(config) if !config.extensions.oci.is_empty() || !config.extensions.local.is_empty() => {
-            tracing::info!("Loading extensions from configuration");
-            if let Err(e) = extension_manager
-                .load_extensions_from_config(&config.extensions)
-                .await
-            {
-                tracing::error!("Failed to load extensions from config: {}", e);
-            }
+    if !config.extensions.oci.is_empty() || !config.extensions.local.is_empty() {
+        tracing::info!("Loading extensions from configuration");
+        if let Err(e) = extension_manager
+            .load_extensions_from_config(&config.extensions)
+            .await
+        {
+            tracing::error!("Failed to load extensions from config: {}", e);
         }
-        Ok(_) => {
-            // No extensions in config, fall back to directory scanning
-            tracing::info!("No extensions in config, scanning directory");
-            if let Err(e) = extension_manager.load_extensions().await {
-                tracing::warn!("Failed to load extensions from directory: {}", e);
-            }
-        }
-        Err(e) => {
-            tracing::warn!(
-                "Failed to load config ({}), falling back to directory scan",
-                e
-            );
-            if let Err(e) = extension_manager.load_extensions().await {
-                tracing::warn!("Failed to load extensions from directory: {}", e);
-            }
+    } else {
+        // No extensions in config, fall back to directory scanning
+        tracing::info!("No extensions in config, scanning directory");
+        if let Err(e) = extension_manager.load_extensions().await {
+            tracing::warn!("Failed to load extensions from directory: {}", e);
         }
     }
 
     let extension_manager = Arc::new(extension_manager);
+    if let Some(abuse_check) = &config.abuse_check {
+        moderation::install(abuse_check, &extension_manager)
+            .context("abuse_check is misconfigured")?;
+        tracing::info!(checker = ?abuse_check.checker, "screening new content for abuse");
+    }
+
+    // One path resolver for the whole server, so invalidating it after a rename or
+    // deletion reaches every cached resolution
+    let resolver = PathResolver::new(pool.clone());
 
     // Initialise Hive Router state
-    let router_state = Arc::new(
-        RouterState::new(pool.clone(), storage.clone(), extension_manager.clone())
-            .context("Failed to initialise router state")?,
-    );
+    let router_state = if config.server.mock.enabled {
+        tracing::warn!(
+            seed = config.server.mock.seed,
+            "GraphQL mock mode: responses are generated, not read from the database"
+        );
+        LiveRouter::mocked(extension_manager.clone(), &config.server.mock)
+    } else {
+        LiveRouter::new(
+            pool.clone(),
+            storage.clone(),
+            resolver.clone(),
+            extension_manager.clone(),
+        )
+    };
+    let router_state = Arc::new(router_state.context("Failed to initialise router state")?);
+    router::install_shared(router_state.clone());
+
+    if let Some(report) = seed::seed_from_config(
+        &pool,
+        &storage,
+        &config,
+        Some(router_state.current().as_ref()),
+    )
+    .await
+    .context("Failed to apply seed file")?
+    {
+        tracing::info!(
+            "seeded {} users, {} groups, {} repositories ({} commits) and {} issues",
+            report.users,
+            report.groups,
+            report.repositories,
+            report.commits,
+            report.issues
+        );
+    }
 
     // Initialize authentication (public client by default)
-    let auth_state = initialize_auth_async().await;
+    let auth_state = initialize_auth_async(&config.auth, &config.server, config.db.in_memory).await;
 
     let mut supervisor = Supervisor::new();
 
     // Spawn auth cleanup task if enabled
     if let Some(auth_state_arc) = auth_state.clone() {
         let store_for_clean = auth_state_arc.auth_store.clone();
+        let ttl = config.auth.flow_ttl_secs as i64;
+        let interval_ms = config.auth.clean_interval_secs * 1000;
         supervisor.spawn("auth-cleaner", move |shutdown| {
             let store = store_for_clean.clone();
             async move {
-                let ttl: i64 = std::env::var("FORGE_AUTH_FLOW_TTL_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(30 * 60);
-                let interval_ms: u64 = std::env::var("FORGE_AUTH_CLEAN_INTERVAL_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(5 * 60) * 1000;
                 let mut ticker = tokio::time::interval(std::time::Duration::from_millis(interval_ms));
                 loop {
                     tokio::select! {
@@ -128,18 +345,363 @@ async fn main() -> anyhow::Result<()> {
                 Ok(())
             }
         });
-        // Periodic VACUUM/optimize
-        let store_for_vacuum = auth_state_arc.auth_store.clone();
-        supervisor.spawn("auth-vacuum", move |shutdown| {
+        // Periodic VACUUM/optimize; there is no file to compact in in-memory mode
+        if !config.db.in_memory {
+            let store_for_vacuum = auth_state_arc.auth_store.clone();
+            let every_ms = config.auth.vacuum_interval_secs * 1000;
+            supervisor.spawn("auth-vacuum", move |shutdown| {
+                async move {
+                    let mut ticker = tokio::time::interval(std::time::Duration::from_millis(every_ms));
+                    loop {
+                        tokio::select! {
+                            _ = shutdown.cancelled() => { break; }
+                            _ = ticker.tick() => {
+                                if coordination::is_read_only() { continue; }
+                                if let Err(e) = store_for_vacuum.vacuum().await { tracing::warn!("auth vacuum failed: {}", e); }
+                                else { tracing::info!("auth db vacuumed"); }
+                            }
+                        }
+                    }
+                    Ok(())
+                }
+            });
+        }
+    }
+
+    // Push new notifications and due digests to subscribed browsers
+    if config.push.enabled {
+        let push_worker = notifications::push::PushWorker::from_config(pool.clone(), &config)
+            .context("push is misconfigured")?;
+        supervisor.spawn("web-push", move |shutdown| push_worker.run(shutdown));
+    }
+
+    // Send queued webhook deliveries
+    if config.webhooks.enabled {
+        let webhook_worker = webhooks::delivery::DeliveryWorker::from_config(pool.clone(), &config)
+            .context("webhooks are misconfigured")?;
+        supervisor.spawn("webhook-delivery", move |shutdown| {
+            webhook_worker.run(shutdown)
+        });
+    }
+
+    // Ship forge.db's write-ahead log to object storage
+    if config.db.replication.enabled && !config.db.in_memory {
+        let replicator = replication::Replicator::open(
+            &config.db.replication,
+            replication::store::open(&config.db.replication)
+                .context("db.replication is misconfigured")?,
+            &db_root_path.join(db::FORGE_DB_FILENAME),
+        )
+        .await?;
+        supervisor.spawn("db-replication", move |shutdown| replicator.run(shutdown));
+    }
+
+    // Publish the composed schema to an external registry without holding up startup, and
+    // again after each extension reload. A throwaway in-memory server has nothing to
+    // publish.
+    if let Some(registry) = config.schema_registry.clone()
+        && !coordination::is_read_only()
+        && !config.db.in_memory
+    {
+        let publisher =
+            graphql::registry::SchemaRegistryPublisher::new(registry, db_root_path.clone());
+        let router = router_state.clone();
+        supervisor.spawn("schema-registry", move |shutdown| async move {
+            tokio::select! {
+                _ = shutdown.cancelled() => {}
+                _ = router.publish_schemas(publisher) => {}
+            }
+            Ok(())
+        });
+    }
+
+    supervisor.spawn("lease-keeper", move |shutdown| {
+        coordination::run_lease_keeper(leases, storage_lock_path, storage_lock, shutdown)
+    });
+
+    // Rebuilds and checks share one handler for both search indexes
+    let search_maintenance: Arc<dyn jobs::JobHandler> =
+        Arc::new(search::maintenance::SearchMaintenanceJob::new(
+            pool.clone(),
+            Arc::new(storage.clone()),
+            config.search.clone(),
+        ));
+
+    // Background jobs: remote repository clones, issue closing, symbol indexing, account
+    // exports, repository lifecycle events for extensions, profile refreshes and event export
+    let mut job_worker = jobs::JobWorker::new(jobs::JobQueue::new(pool.clone()))
+        .register(
+            repository::clone::JOB_KIND,
+            Arc::new(repository::clone::RemoteCloneJob::new(
+                pool.clone(),
+                Arc::new(storage.clone()),
+                repository::clone::CloneLimits::from_config(&config.clone),
+            )),
+        )
+        .register(
+            repository::closing::JOB_KIND,
+            Arc::new(repository::closing::IssueClosingJob::new(
+                pool.clone(),
+                Arc::new(storage.clone()),
+                resolver.clone(),
+                extension_manager.clone(),
+            )),
+        )
+        .register(
+            repository::symbols::JOB_KIND,
+            Arc::new(repository::symbols::SymbolIndexJob::new(
+                pool.clone(),
+                Arc::new(storage.clone()),
+            )),
+        )
+        .register(
+            repository::contributor_stats::JOB_KIND,
+            Arc::new(repository::contributor_stats::ContributorStatsJob::new(
+                pool.clone(),
+                Arc::new(storage.clone()),
+            )),
+        )
+        .register(
+            search::code::JOB_KIND,
+            Arc::new(search::code::CodeIndexJob::new(
+                pool.clone(),
+                Arc::new(storage.clone()),
+            )),
+        )
+        .register(
+            search::maintenance::REBUILD_JOB_KIND,
+            search_maintenance.clone(),
+        )
+        .register(search::maintenance::CHECK_JOB_KIND, search_maintenance)
+        .register(
+            repository::lifecycle::JOB_KIND,
+            Arc::new(repository::lifecycle::LifecycleFanOutJob::new(
+                pool.clone(),
+                extension_manager.clone(),
+            )),
+        )
+        .register(
+            repository::lifecycle::DELIVERY_JOB_KIND,
+            Arc::new(repository::lifecycle::LifecycleDeliveryJob::new(
+                extension_manager.clone(),
+            )),
+        )
+        .register(
+            user::export::JOB_KIND,
+            Arc::new(user::export::AccountExportJob::new(
+                pool.clone(),
+                auth_state.as_ref().map(|auth| auth.session_manager.clone()),
+                extension_manager.clone(),
+                config.account_export.clone(),
+            )),
+        )
+        .register(
+            audit::JOB_KIND,
+            Arc::new(audit::AuditExportJob::new(
+                pool.clone(),
+                config.audit_log.clone(),
+            )),
+        );
+    if config.profile_refresh.enabled {
+        job_worker = job_worker.register(
+            user::profiles::JOB_KIND,
+            Arc::new(user::profiles::ProfileRefreshJob::new(
+                pool.clone(),
+                &config.federation,
+                &config.profile_refresh,
+            )?),
+        );
+    }
+    if let Some(sink) = &config.event_sink {
+        let exporter =
+            events::EventExportJob::from_config(sink).context("event_sink is misconfigured")?;
+        tracing::info!(transport = ?sink.transport, url = %sink.url, "exporting events");
+        job_worker = job_worker.register(events::JOB_KIND, Arc::new(exporter));
+    }
+    supervisor.spawn("job-worker", move |shutdown| job_worker.run(shutdown));
+
+    // Periodically re-fetch linked remotes; new upstream tags notify their watchers
+    let pool_for_sync = pool.clone();
+    let max_attempts = config.clone.max_attempts;
+    supervisor.spawn("remote-sync", move |shutdown| {
+        async move {
+            let every_ms: u64 = std::env::var("FORGE_REMOTE_SYNC_INTERVAL_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(60 * 60) * 1000;
+            let mut ticker = tokio::time::interval(std::time::Duration::from_millis(every_ms));
+            ticker.tick().await; // skip the immediate first tick; fresh links are cloning already
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => { break; }
+                    _ = ticker.tick() => {
+                        if coordination::is_read_only() { continue; }
+                        match repository::clone::enqueue_remote_syncs(&pool_for_sync, max_attempts).await {
+                            Ok(0) => {}
+                            Ok(count) => tracing::debug!("queued {} remote syncs", count),
+                            Err(e) => tracing::warn!("remote sync scheduling failed: {}", e),
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    });
+
+    // Re-resolve users' handles and profiles, a minute's worth of refreshes per tick
+    if config.profile_refresh.enabled {
+        let pool_for_profiles = pool.clone();
+        let refresh_config = config.profile_refresh.clone();
+        supervisor.spawn("profile-refresh", move |shutdown| {
+            async move {
+                let mut ticker = tokio::time::interval(std::time::Duration::from_secs(60));
+                loop {
+                    tokio::select! {
+                        _ = shutdown.cancelled() => { break; }
+                        _ = ticker.tick() => {
+                            if coordination::is_read_only() { continue; }
+                            match user::profiles::enqueue_profile_refreshes(&pool_for_profiles, &refresh_config, user::db::unix_now()).await {
+                                Ok(0) => {}
+                                Ok(count) => tracing::debug!("queued {} profile refreshes", count),
+                                Err(e) => tracing::warn!("profile refresh scheduling failed: {}", e),
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }
+        });
+    }
+
+    // Queue scheduled rebuilds and consistency checks of the search indexes
+    let pool_for_search = pool.clone();
+    let search_config = config.search.clone();
+    supervisor.spawn("search-maintenance", move |shutdown| {
+        async move {
+            let mut ticker = tokio::time::interval(std::time::Duration::from_secs(60));
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => { break; }
+                    _ = ticker.tick() => {
+                        if coordination::is_read_only() { continue; }
+                        match search::maintenance::schedule_maintenance(&pool_for_search, &search_config, user::db::unix_now()).await {
+                            Ok(0) => {}
+                            Ok(count) => tracing::debug!("queued {} search index jobs", count),
+                            Err(e) => tracing::warn!("search index scheduling failed: {}", e),
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    });
+
+    // Drop archival records, audit log entries and exports whose retention period has ended
+    let pool_for_prune = pool.clone();
+    let prune_every = std::time::Duration::from_secs(config.compliance.prune_interval_secs.max(60));
+    supervisor.spawn("compliance-prune", move |shutdown| {
+        async move {
+            let mut ticker = tokio::time::interval(prune_every);
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => { break; }
+                    _ = ticker.tick() => {
+                        if coordination::is_read_only() { continue; }
+                        match compliance::prune_expired(&pool_for_prune, user::db::unix_now()).await {
+                            Ok(0) => {}
+                            Ok(count) => tracing::info!("pruned {} expired compliance records", count),
+                            Err(e) => tracing::warn!("compliance pruning failed: {}", e),
+                        }
+                        match user::export::prune_expired_exports(&pool_for_prune, "account_exports", user::db::unix_now()).await {
+                            Ok(0) => {}
+                            Ok(count) => tracing::info!("deleted {} expired account exports", count),
+                            Err(e) => tracing::warn!("account export pruning failed: {}", e),
+                        }
+                        match audit::prune_expired(&pool_for_prune, user::db::unix_now()).await {
+                            Ok(0) => {}
+                            Ok(count) => tracing::info!("pruned {} expired audit log entries", count),
+                            Err(e) => tracing::warn!("audit log pruning failed: {}", e),
+                        }
+                        match user::export::prune_expired_exports(&pool_for_prune, "audit_log_exports", user::db::unix_now()).await {
+                            Ok(0) => {}
+                            Ok(count) => tracing::info!("deleted {} expired audit log exports", count),
+                            Err(e) => tracing::warn!("audit log export pruning failed: {}", e),
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    });
+
+    // Write schema field usage counted by the API; the last counts are flushed on shutdown
+    let pool_for_usage = pool.clone();
+    supervisor.spawn("schema-usage", move |shutdown| async move {
+        let recorder = router::usage::recorder();
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(
+            router::usage::FLUSH_INTERVAL_SECS,
+        ));
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => { break; }
+                _ = ticker.tick() => {
+                    if coordination::is_read_only() { continue; }
+                    if let Err(e) = recorder.flush(&pool_for_usage).await {
+                        tracing::warn!("failed to write schema usage: {}", e);
+                    }
+                }
+            }
+        }
+        if !coordination::is_read_only()
+            && let Err(e) = recorder.flush(&pool_for_usage).await
+        {
+            tracing::warn!("failed to write schema usage: {}", e);
+        }
+        Ok(())
+    });
+
+    // Account usage per top-level group, enforce quotas and export daily usage for billing
+    if let Some(metering_config) = config.metering.clone() {
+        let usage_meter = metering::UsageMeter::new(pool.clone(), storage.clone(), metering_config);
+        supervisor.spawn("usage-metering", move |shutdown| usage_meter.run(shutdown));
+    }
+
+    // Reload administrators added by setup, which may have completed on another instance
+    let pool_for_admins = pool.clone();
+    supervisor.spawn("instance-admins", move |shutdown| {
+        async move {
+            let mut ticker = tokio::time::interval(instance::ADMIN_REFRESH_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => { break; }
+                    _ = ticker.tick() => {
+                        if let Err(e) = instance::refresh_admins(&pool_for_admins).await {
+                            tracing::warn!("failed to reload instance administrators: {}", e);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    });
+
+    // Re-hash cached OCI extension modules; damaged ones are re-fetched or quarantined. The
+    // cache outlives in-memory servers, which leave it to persistent ones.
+    if !config.extensions.oci.is_empty()
+        && config.extensions.settings.verify_interval_secs > 0
+        && !config.db.in_memory
+    {
+        let extensions_config = config.extensions.clone();
+        let verify_every =
+            std::time::Duration::from_secs(extensions_config.settings.verify_interval_secs.max(60));
+        supervisor.spawn("extension-cache-verify", move |shutdown| {
             async move {
-                let every_ms: u64 = std::env::var("FORGE_AUTH_VACUUM_INTERVAL_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(6 * 60 * 60) * 1000; // default 6h
-                let mut ticker = tokio::time::interval(std::time::Duration::from_millis(every_ms));
+                let mut ticker = tokio::time::interval(verify_every);
                 loop {
                     tokio::select! {
                         _ = shutdown.cancelled() => { break; }
                         _ = ticker.tick() => {
-                            if let Err(e) = store_for_vacuum.vacuum().await { tracing::warn!("auth vacuum failed: {}", e); }
-                            else { tracing::info!("auth db vacuumed"); }
+                            if coordination::is_read_only() { continue; }
+                            if let Err(e) = extensions::integrity::verify_cache(&extensions_config).await {
+                                tracing::warn!("extension cache verification failed: {}", e);
+                            }
                         }
                     }
                 }
@@ -148,30 +710,67 @@ async fn main() -> anyhow::Result<()> {
         });
     }
 
+    // Call the `run-task` export of extensions whose schedules are due
+    let task_scheduler = extensions::scheduler::TaskScheduler::new(
+        pool.clone(),
+        extension_manager.clone(),
+        config.extensions.settings.max_concurrent_tasks,
+    );
+    supervisor.spawn("extension-tasks", move |shutdown| {
+        task_scheduler.run(shutdown)
+    });
+
+    // Reload extensions whose module changed on disk
+    if config.extensions.settings.watch_interval_secs > 0 {
+        let watcher = extensions::watcher::ExtensionWatcher::new(
+            router_state.clone(),
+            std::time::Duration::from_secs(config.extensions.settings.watch_interval_secs),
+        );
+        supervisor.spawn("extension-watcher", move |shutdown| watcher.run(shutdown));
+    }
+
+    let access_log = config.access_log.clone();
+    let pool_for_api = pool.clone();
+    let storage_for_api = storage.clone();
+    let extensions_for_api = extension_manager.clone();
     supervisor.spawn("api", move |shutdown| async move {
-        run_api(router_state, auth_state, shutdown).await
+        run_api(
+            router_state,
+            auth_state,
+            access_log,
+            pool_for_api,
+            storage_for_api,
+            resolver,
+            extensions_for_api,
+            shutdown,
+        )
+        .await
     });
 
     supervisor.run().await
 }
 
-/// Initialize authentication if environment variables are configured
-async fn initialize_auth_async() -> Option<Arc<AuthState>> {
+/// Initialize authentication from the `auth` and `server` config sections
+async fn initialize_auth_async(
+    settings: &config::AuthSettings,
+    server: &config::ServerConfig,
+    in_memory: bool,
+) -> Option<Arc<AuthState>> {
     // Public client by default, with dynamic client metadata URL as client_id
-    let mut redirect_uri = std::env::var("ATPROTO_REDIRECT_URI")
-        .unwrap_or_else(|_| "http://127.0.0.1:8000/auth/callback".to_string());
+    let mut redirect_uri = settings.redirect_uri.clone();
     if redirect_uri.contains("://localhost") {
         let fixed = redirect_uri.replace("://localhost", "://127.0.0.1");
-        tracing::warn!("ATPROTO_REDIRECT_URI uses localhost; rewriting to {} per RFC 8252 loopback guidance", fixed);
+        tracing::warn!(
+            "auth.redirect_uri uses localhost; rewriting to {} per RFC 8252 loopback guidance",
+            fixed
+        );
         redirect_uri = fixed;
     }
 
     // Compute base URL for the server to host client metadata
-    let public_base = std::env::var("FORGE_PUBLIC_BASE_URL")
-        .unwrap_or_else(|_| "http://localhost:8000".to_string());
-    let base_trimmed = public_base.trim_end_matches('/').to_string();
+    let base_trimmed = server.public_base_url.trim_end_matches('/').to_string();
     // Determine scope prior to building client_id for localhost mode
-    let scope = std::env::var("ATPROTO_OAUTH_SCOPE").unwrap_or_else(|_| "atproto".to_string());
+    let scope = settings.oauth_scope.clone();
     let client_id = if base_trimmed.contains("://localhost") || base_trimmed.contains("://127.0.0.1") {
         // Use special localhost client as per ATProto OAuth profile.
         // Declare redirect_uri (path-sensitive, port ignored) and scope in client_id query.
@@ -183,7 +782,7 @@ async fn initialize_auth_async() -> Option<Arc<AuthState>> {
     };
 
     // Optional secret if someone wants to use client_secret
-    let client_secret = std::env::var("ATPROTO_CLIENT_SECRET").ok();
+    let client_secret = settings.client_secret.clone();
 
     tracing::info!("Initializing ATProto OAuth authentication (public client)");
 
@@ -197,8 +796,13 @@ async fn initialize_auth_async() -> Option<Arc<AuthState>> {
     match AtProtoAuthClient::new(config) {
         Ok(oauth_client) => {
             let session_manager = SessionManager::new();
-            let auth_db_path = std::env::var("FORGE_AUTH_DB_PATH").unwrap_or_else(|_| "server/.forge/auth.db".to_string());
-            let auth_store = match SqliteAuthStore::new(&auth_db_path).await {
+            let auth_db_path = settings.db_path.to_string_lossy();
+            let auth_store = if in_memory {
+                SqliteAuthStore::in_memory().await
+            } else {
+                SqliteAuthStore::new(&auth_db_path).await
+            };
+            let auth_store = match auth_store {
                 Ok(s) => s,
                 Err(e) => {
                     tracing::error!("Failed to init auth store: {}", e);
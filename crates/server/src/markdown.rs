@@ -0,0 +1,428 @@
+//! Markdown parsing shared by the server and its extensions.
+//!
+//! [`parse_document`] reduces a Markdown source to the parts features cross-reference:
+//! `@` mentions, issue references (`#12`, `group/repo#12`), links and fenced or indented
+//! code blocks. Extensions reach it through the `host-markdown` WIT interface, so issues,
+//! wikis and reviews all agree on what counts as a reference. Mentions and issue
+//! references are only recognised in prose. Text inside code, links, images and raw HTML
+//! is skipped, and a backslash in front of `@` or `#` suppresses the reference.
+//!
+//! [`render_html`] turns user-written Markdown into HTML that clients can insert as is.
+
+use std::ops::Range;
+
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd, html};
+
+/// Byte offsets into the parsed source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Span {
+            start: range.start,
+            end: range.end,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mention {
+    /// Lower-cased handle, or a DID as written.
+    pub handle: String,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IssueRef {
+    /// Repository path for `group/repo#12`; `None` for a bare `#12`.
+    pub repository: Option<String>,
+    pub number: u64,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    pub url: String,
+    pub title: Option<String>,
+    /// Plain text of the link label.
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeBlock {
+    /// First word of a fenced block's info string.
+    pub language: Option<String>,
+    pub code: String,
+    pub span: Span,
+}
+
+/// Every node list is in source order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Document {
+    pub mentions: Vec<Mention>,
+    pub issue_refs: Vec<IssueRef>,
+    pub links: Vec<Link>,
+    pub code_blocks: Vec<CodeBlock>,
+}
+
+fn options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options
+}
+
+pub fn parse_document(source: &str) -> Document {
+    let mut document = Document::default();
+    // Adjacent text events are scanned as one run so a reference split by the parser
+    // (for example at `_`) is still found.
+    let mut prose: Option<Range<usize>> = None;
+    let mut link: Option<Link> = None;
+    let mut code: Option<CodeBlock> = None;
+    let mut image_depth = 0usize;
+
+    for (event, range) in Parser::new_ext(source, options()).into_offset_iter() {
+        if let Event::Text(text) = &event {
+            if let Some(block) = code.as_mut() {
+                block.code.push_str(text);
+            } else if let Some(link) = link.as_mut() {
+                link.text.push_str(text);
+            } else if image_depth == 0 {
+                prose = match prose.take() {
+                    Some(run) if run.end == range.start => Some(run.start..range.end),
+                    Some(run) => {
+                        scan_references(source, run, &mut document);
+                        Some(range)
+                    }
+                    None => Some(range),
+                };
+            }
+            continue;
+        }
+        if let Some(run) = prose.take() {
+            scan_references(source, run, &mut document);
+        }
+
+        match event {
+            Event::Code(text) => {
+                if let Some(link) = link.as_mut() {
+                    link.text.push_str(&text);
+                }
+            }
+            Event::Start(Tag::Link {
+                dest_url, title, ..
+            }) => {
+                link = Some(Link {
+                    url: dest_url.to_string(),
+                    title: (!title.is_empty()).then(|| title.to_string()),
+                    text: String::new(),
+                    span: range.into(),
+                });
+            }
+            Event::End(TagEnd::Link) => document.links.extend(link.take()),
+            Event::Start(Tag::Image { .. }) => image_depth += 1,
+            Event::End(TagEnd::Image) => image_depth = image_depth.saturating_sub(1),
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().map(|word| word.to_string())
+                    }
+                    CodeBlockKind::Indented => None,
+                };
+                code = Some(CodeBlock {
+                    language,
+                    code: String::new(),
+                    span: range.into(),
+                });
+            }
+            Event::End(TagEnd::CodeBlock) => document.code_blocks.extend(code.take()),
+            _ => {}
+        }
+    }
+    if let Some(run) = prose {
+        scan_references(source, run, &mut document);
+    }
+    document
+}
+
+/// Renders `source` as HTML that is safe to embed. Raw HTML in the source is shown as
+/// text, and links and images keep only relative, `http`, `https` and `mailto` URLs;
+/// others are emptied.
+pub fn render_html(source: &str) -> String {
+    let events = Parser::new_ext(source, options()).map(|event| match event {
+        Event::Html(text) | Event::InlineHtml(text) => Event::Text(text),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: safe_url(dest_url),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: safe_url(dest_url),
+            title,
+            id,
+        }),
+        event => event,
+    });
+    let mut output = String::new();
+    html::push_html(&mut output, events);
+    output
+}
+
+/// `url` if it is relative or uses a scheme that cannot run script, otherwise nothing.
+fn safe_url(url: CowStr<'_>) -> CowStr<'_> {
+    if is_safe_url(&url) {
+        url
+    } else {
+        CowStr::Borrowed("")
+    }
+}
+
+fn is_safe_url(url: &str) -> bool {
+    // Browsers ignore whitespace and control characters inside a scheme.
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect();
+    match url.find([':', '/', '?', '#']) {
+        Some(end) if url[end..].starts_with(':') => matches!(
+            url[..end].to_ascii_lowercase().as_str(),
+            "http" | "https" | "mailto"
+        ),
+        _ => true,
+    }
+}
+
+/// Finds mentions and issue references in `source[range]`, which is raw prose.
+fn scan_references(source: &str, range: Range<usize>, document: &mut Document) {
+    let text = source[range.clone()].as_bytes();
+    let offset = range.start;
+    let mut i = 0;
+    while i < text.len() {
+        // Look behind in the full source: the parser drops escaping backslashes from runs.
+        let starts_token = match (offset + i).checked_sub(1) {
+            Some(before) => {
+                let byte = source.as_bytes()[before];
+                !is_word_byte(byte) && !b"\\`&/.-@#".contains(&byte)
+            }
+            None => true,
+        };
+        if !starts_token {
+            i += 1;
+            continue;
+        }
+        match text[i] {
+            b'@' => {
+                if let Some((handle, end)) = scan_handle(text, i + 1) {
+                    document.mentions.push(Mention {
+                        handle,
+                        span: Span {
+                            start: offset + i,
+                            end: offset + end,
+                        },
+                    });
+                    i = end;
+                    continue;
+                }
+            }
+            b'#' => {
+                if let Some((number, end)) = scan_issue_number(text, i + 1) {
+                    document.issue_refs.push(IssueRef {
+                        repository: None,
+                        number,
+                        span: Span {
+                            start: offset + i,
+                            end: offset + end,
+                        },
+                    });
+                    i = end;
+                    continue;
+                }
+            }
+            byte if is_path_byte(byte) => {
+                let path_end = i + text[i..].iter().take_while(|b| is_path_byte(**b)).count();
+                if text.get(path_end) == Some(&b'#')
+                    && let Some((number, end)) = scan_issue_number(text, path_end + 1)
+                    && let Some(repository) = repository_path(&text[i..path_end])
+                {
+                    document.issue_refs.push(IssueRef {
+                        repository: Some(repository),
+                        number,
+                        span: Span {
+                            start: offset + i,
+                            end: offset + end,
+                        },
+                    });
+                    i = end;
+                } else {
+                    i = path_end;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// Parses a handle (`alice.example.com`) or DID (`did:plc:abc`) starting at `start`.
+fn scan_handle(text: &[u8], start: usize) -> Option<(String, usize)> {
+    let rest = &text[start..];
+    if rest.starts_with(b"did:") {
+        let len = rest
+            .iter()
+            .take_while(|b| b.is_ascii_alphanumeric() || b":._-".contains(b))
+            .count();
+        let did = std::str::from_utf8(&rest[..len]).ok()?;
+        let did = did.trim_end_matches(['.', ':', '-']);
+        // `did:<method>:<id>`
+        return (did.split(':').filter(|part| !part.is_empty()).count() >= 3)
+            .then(|| (did.to_string(), start + did.len()));
+    }
+    if !rest.first()?.is_ascii_alphanumeric() {
+        return None;
+    }
+    let len = rest
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || b".-".contains(b))
+        .count();
+    let handle = std::str::from_utf8(&rest[..len]).ok()?;
+    let handle = handle.trim_end_matches(['.', '-']);
+    if text.get(start + handle.len()).is_some_and(|b| *b == b'_') {
+        return None;
+    }
+    Some((handle.to_ascii_lowercase(), start + handle.len()))
+}
+
+/// Parses the digits of an issue number starting at `start`.
+fn scan_issue_number(text: &[u8], start: usize) -> Option<(u64, usize)> {
+    let len = text[start..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit())
+        .count();
+    let end = start + len;
+    if len == 0 || text.get(end).is_some_and(|b| is_word_byte(*b)) {
+        return None;
+    }
+    let number: u64 = std::str::from_utf8(&text[start..end]).ok()?.parse().ok()?;
+    (number > 0).then_some((number, end))
+}
+
+fn repository_path(path: &[u8]) -> Option<String> {
+    let path = std::str::from_utf8(path).ok()?;
+    path.split('/')
+        .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+        .then(|| path.to_string())
+}
+
+fn is_word_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || !byte.is_ascii()
+}
+
+fn is_path_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"._-/".contains(&byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prose_references_are_extracted_with_spans() {
+        let source = "Thanks @Alice.example.com, this fixes #12 and forge/server#7.\n\nSee [the docs](https://forge.dev/docs \"Docs\") or ping @did:plc:abc123.";
+        let document = parse_document(source);
+
+        let handles: Vec<&str> = document
+            .mentions
+            .iter()
+            .map(|m| m.handle.as_str())
+            .collect();
+        assert_eq!(handles, ["alice.example.com", "did:plc:abc123"]);
+        let mention = &document.mentions[0];
+        assert_eq!(
+            &source[mention.span.start..mention.span.end],
+            "@Alice.example.com"
+        );
+
+        assert_eq!(
+            document
+                .issue_refs
+                .iter()
+                .map(|r| (r.repository.as_deref(), r.number))
+                .collect::<Vec<_>>(),
+            [(None, 12), (Some("forge/server"), 7)]
+        );
+        let qualified = &document.issue_refs[1];
+        assert_eq!(
+            &source[qualified.span.start..qualified.span.end],
+            "forge/server#7"
+        );
+
+        assert_eq!(document.links.len(), 1);
+        assert_eq!(document.links[0].url, "https://forge.dev/docs");
+        assert_eq!(document.links[0].title.as_deref(), Some("Docs"));
+        assert_eq!(document.links[0].text, "the docs");
+    }
+
+    #[test]
+    fn code_links_and_escapes_are_not_prose() {
+        let source = "Mail me at bob@example.com, `@carol #3` \\#4 issue#x #0\n\n[@dave #5](https://x.test)\n\n```rust title\nlet a = 1; // #6 @erin\n```\n";
+        let document = parse_document(source);
+
+        assert!(document.mentions.is_empty(), "{:?}", document.mentions);
+        assert!(document.issue_refs.is_empty(), "{:?}", document.issue_refs);
+        assert_eq!(document.links[0].text, "@dave #5");
+        assert_eq!(document.code_blocks.len(), 1);
+        assert_eq!(document.code_blocks[0].language.as_deref(), Some("rust"));
+        assert_eq!(document.code_blocks[0].code, "let a = 1; // #6 @erin\n");
+    }
+
+    #[test]
+    fn rendered_html_is_sanitized() {
+        let html = render_html(
+            "**Hi** <script>alert(1)</script>\n\n<img src=x onerror=alert(1)>\n\n[a](https://forge.dev) [b](JavaScript:alert(1)) [c](java&#9;script:x) ![d](data:image/png;base64,x) [e](/docs#top)",
+        );
+        assert!(html.contains("<strong>Hi</strong>"), "{}", html);
+        assert!(!html.contains("<script"), "{}", html);
+        assert!(html.contains("&lt;script&gt;"), "{}", html);
+        assert!(!html.contains("<img src=\"x\""), "{}", html);
+        assert!(
+            html.contains(r#"<a href="https://forge.dev">a</a>"#),
+            "{}",
+            html
+        );
+        assert!(html.contains(r#"<a href="">b</a>"#), "{}", html);
+        assert!(!html.to_ascii_lowercase().contains("script:"), "{}", html);
+        assert!(html.contains(r#"<img src="" alt="d" />"#), "{}", html);
+        assert!(html.contains(r#"<a href="/docs#top">e</a>"#), "{}", html);
+    }
+
+    #[test]
+    fn references_survive_parser_text_splits() {
+        let document = parse_document("Filed as my_group/web_app#42 by @zoe.");
+        assert_eq!(
+            document.issue_refs[0].repository.as_deref(),
+            Some("my_group/web_app")
+        );
+        assert_eq!(document.issue_refs[0].number, 42);
+        assert_eq!(document.mentions[0].handle, "zoe");
+    }
+}
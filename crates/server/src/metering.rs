@@ -0,0 +1,1218 @@
+//! Usage accounting and quotas of top-level groups, for hosted instances.
+//!
+//! With a `metering` config section, each top-level group is accounted for the
+//! repositories under it at any depth; repositories outside a group are not metered.
+//! Usage is kept per UTC day in `group_usage`, for four resources:
+//!
+//! - `storage`: bytes on disk of the group's local repositories, measured every
+//!   `storage_sample_secs`. Linked remote repositories are caches and are left out.
+//! - `bandwidth`: bytes sent in answer to git Smart HTTP requests (see `api::git`).
+//! - `api_calls`: GraphQL requests that resolved a repository of the group, one per
+//!   group and request.
+//! - `ci_minutes`: reserved for CI, which reports through [`record`]; nothing does yet.
+//!
+//! Counts stay in memory in the [`Meter`] until they are written, about once a minute,
+//! like schema usage. After each write every group's quota (`metering.groups`, or
+//! `default_quota`) is checked: storage by its latest size, the other resources by their
+//! total in the current month. Passing a soft limit publishes `usage.quota_warning`, and
+//! reaching a hard one `usage.quota_exceeded`, once per group, resource and month. Until a
+//! later check finds the group under its hard limit, GraphQL requests for its repositories
+//! fail when API calls are used up (administrators excepted), fetches are refused when
+//! bandwidth is, and pushes when storage is. Usage is only checked after each write, so a
+//! group can go slightly over a hard limit.
+//!
+//! A few minutes after midnight UTC the previous day is exported as CSV or JSON into
+//! `usage_exports`, and `usage.exported` is published so billing systems can follow
+//! exports through a webhook or the event sink. Administrators download them from
+//! `GET /usage/exports/{id}`. Days missed while the server was down are exported when it
+//! is back.
+
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::future::Future;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow, bail};
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+use tokio_util::sync::CancellationToken;
+
+use crate::audit::csv_field;
+use crate::auth::viewer::current_viewer;
+use crate::config::{MeteringConfig, QuotaConfig, QuotaLimit, UsageExportFormat};
+use crate::events::{Event, publish};
+use crate::extensions::scheduler::civil_from_days;
+use crate::repository::RepositoryStorage;
+use crate::user::db::unix_now;
+use crate::user::export::prune_expired_exports;
+
+pub const ARCHIVE_FORMAT: &str = "forge-usage";
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// How often counts are written and quotas checked.
+pub const FLUSH_INTERVAL_SECS: u64 = 60;
+
+/// How long after midnight UTC the previous day is exported, so its last counts have
+/// been written.
+const EXPORT_DELAY_SECS: i64 = 5 * 60;
+/// Days exported per check while catching up.
+const MAX_EXPORT_DAYS: i64 = 31;
+const MAX_LISTED_EXPORTS: i64 = 100;
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+const EXPORT_COLUMNS: &str = "id, day, format, row_count, size_bytes, created_at, expires_at";
+
+/// The path of every local repository in a group, with the top-level group it belongs
+/// to.
+const REPOSITORY_ROOTS_SQL: &str = "WITH RECURSIVE chain (group_id, parent, path) AS ( \
+         SELECT g.id, g.parent, g.slug || '/' || r.slug FROM repositories r \
+         JOIN groups g ON g.id = r.\"group\" WHERE r.remote_url IS NULL \
+         UNION ALL \
+         SELECT g.id, g.parent, g.slug || '/' || chain.path FROM chain \
+         JOIN groups g ON g.id = chain.parent) \
+     SELECT group_id, path FROM chain WHERE parent IS NULL";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Resource {
+    Storage,
+    Bandwidth,
+    ApiCalls,
+    CiMinutes,
+}
+
+impl Resource {
+    pub const ALL: [Resource; 4] = [
+        Resource::Storage,
+        Resource::Bandwidth,
+        Resource::ApiCalls,
+        Resource::CiMinutes,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Resource::Storage => "storage",
+            Resource::Bandwidth => "bandwidth",
+            Resource::ApiCalls => "api_calls",
+            Resource::CiMinutes => "ci_minutes",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|resource| resource.as_str() == value)
+            .ok_or_else(|| anyhow!("unknown usage resource `{}`", value))
+    }
+
+    pub fn as_graphql(self) -> &'static str {
+        match self {
+            Resource::Storage => "STORAGE",
+            Resource::Bandwidth => "BANDWIDTH",
+            Resource::ApiCalls => "API_CALLS",
+            Resource::CiMinutes => "CI_MINUTES",
+        }
+    }
+
+    pub fn unit(self) -> &'static str {
+        match self {
+            Resource::Storage | Resource::Bandwidth => "bytes",
+            Resource::ApiCalls => "calls",
+            Resource::CiMinutes => "minutes",
+        }
+    }
+
+    pub fn limit(self, quota: &QuotaConfig) -> QuotaLimit {
+        match self {
+            Resource::Storage => quota.storage_bytes,
+            Resource::Bandwidth => quota.bandwidth_bytes,
+            Resource::ApiCalls => quota.api_calls,
+            Resource::CiMinutes => quota.ci_minutes,
+        }
+    }
+
+    /// Storage is measured; the other resources are counted and summed.
+    fn is_counted(self) -> bool {
+        self != Resource::Storage
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Resource::Storage => "storage",
+            Resource::Bandwidth => "bandwidth",
+            Resource::ApiCalls => "API call",
+            Resource::CiMinutes => "CI minute",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuotaState {
+    Within,
+    /// At or over the soft limit
+    Warning,
+    /// At or over the hard limit
+    Exceeded,
+}
+
+impl QuotaState {
+    pub fn of(used: u64, limit: QuotaLimit) -> Self {
+        if limit.hard.is_some_and(|hard| used >= hard) {
+            QuotaState::Exceeded
+        } else if limit.soft.is_some_and(|soft| used >= soft) {
+            QuotaState::Warning
+        } else {
+            QuotaState::Within
+        }
+    }
+
+    pub fn as_graphql(self) -> &'static str {
+        match self {
+            QuotaState::Within => "WITHIN_LIMITS",
+            QuotaState::Warning => "WARNING",
+            QuotaState::Exceeded => "EXCEEDED",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceUsage {
+    pub resource: Resource,
+    pub used: u64,
+    pub limit: QuotaLimit,
+    pub state: QuotaState,
+}
+
+/// A top-level group's usage in the current month.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupUsage {
+    pub group_id: String,
+    pub group: String,
+    /// `YYYY-MM`
+    pub period: String,
+    pub resources: Vec<ResourceUsage>,
+}
+
+impl GroupUsage {
+    fn new(
+        group_id: &str,
+        group: &str,
+        period: &str,
+        quota: &QuotaConfig,
+        used: Option<&HashMap<Resource, u64>>,
+    ) -> Self {
+        let resources = Resource::ALL
+            .into_iter()
+            .map(|resource| {
+                let used = used
+                    .and_then(|used| used.get(&resource))
+                    .copied()
+                    .unwrap_or(0);
+                let limit = resource.limit(quota);
+                ResourceUsage {
+                    resource,
+                    used,
+                    limit,
+                    state: QuotaState::of(used, limit),
+                }
+            })
+            .collect();
+        Self {
+            group_id: group_id.to_string(),
+            group: group.to_string(),
+            period: period.to_string(),
+            resources,
+        }
+    }
+}
+
+/// The UTC day of a Unix time, in days since 1970-01-01.
+pub fn day_of(time: i64) -> i64 {
+    time.div_euclid(SECS_PER_DAY)
+}
+
+/// A day as `YYYY-MM-DD`.
+pub fn date_of(day: i64) -> String {
+    let (year, month, day_of_month) = civil_from_days(day);
+    format!("{:04}-{:02}-{:02}", year, month, day_of_month)
+}
+
+/// The first day of the month `day` falls in, and the month as `YYYY-MM`.
+fn month_of(day: i64) -> (i64, String) {
+    let (year, month, day_of_month) = civil_from_days(day);
+    (
+        day - (day_of_month - 1),
+        format!("{:04}-{:02}", year, month),
+    )
+}
+
+pub fn enabled() -> bool {
+    crate::config::current().metering.is_some()
+}
+
+/// The top-level group a repository is metered under: the first segment of its
+/// canonical path, or `None` when the repository is not in a group.
+pub fn metered_group(segments: &[String]) -> Option<&str> {
+    match segments {
+        [group, _, ..] => Some(group),
+        _ => None,
+    }
+}
+
+/// Counts `amount` of a counted resource against a top-level group, when metering is
+/// enabled.
+pub fn record(group: &str, resource: Resource, amount: u64) {
+    if enabled() {
+        meter().record(group, resource, amount, unix_now());
+    }
+}
+
+/// Whether the group was at its hard limit of `resource` at the last check.
+pub fn is_blocked(group: &str, resource: Resource) -> bool {
+    enabled() && meter().is_blocked(group, resource)
+}
+
+tokio::task_local! {
+    /// Top-level groups whose repositories the current GraphQL request resolved.
+    static CALLED: RefCell<BTreeSet<String>>;
+}
+
+/// Runs a GraphQL request, then counts one API call for every group whose repositories
+/// it resolved (see [`note_repository`]).
+pub async fn metered_calls<F>(fut: F) -> F::Output
+where
+    F: Future,
+{
+    if !enabled() {
+        return fut.await;
+    }
+    let (output, groups) = CALLED
+        .scope(RefCell::default(), async {
+            let output = fut.await;
+            (output, CALLED.with(RefCell::take))
+        })
+        .await;
+    for group in groups {
+        record(&group, Resource::ApiCalls, 1);
+    }
+    output
+}
+
+/// Notes a repository resolved while serving a GraphQL request. Fails when its group has
+/// used up its API calls, unless the viewer is an administrator.
+pub fn note_repository(segments: &[String]) -> Result<()> {
+    let Some(group) = metered_group(segments) else {
+        return Ok(());
+    };
+    let Ok(known) = CALLED.try_with(|called| called.borrow().contains(group)) else {
+        return Ok(());
+    };
+    if known {
+        return Ok(());
+    }
+    if is_blocked(group, Resource::ApiCalls)
+        && !current_viewer().is_some_and(|viewer| crate::instance::is_admin(&viewer.did))
+    {
+        bail!("`{}` has used up its API call quota for this month", group);
+    }
+    CALLED.with(|called| called.borrow_mut().insert(group.to_string()));
+    Ok(())
+}
+
+static METER: LazyLock<Meter> = LazyLock::new(Meter::default);
+
+/// The process-wide meter shared by the API server, git and admin queries.
+pub fn meter() -> &'static Meter {
+    &METER
+}
+
+#[derive(Default)]
+pub struct Meter {
+    /// Counts not written yet, by top-level group slug, resource and day
+    pending: Mutex<HashMap<(String, Resource, i64), u64>>,
+    /// Groups and resources at their hard limit at the last check
+    blocked: RwLock<HashSet<(String, Resource)>>,
+}
+
+impl Meter {
+    /// Counts `amount` of `resource` for `group` at `now`. Storage is measured, not
+    /// counted, and is ignored here.
+    pub fn record(&self, group: &str, resource: Resource, amount: u64, now: i64) {
+        if amount == 0 || !resource.is_counted() {
+            return;
+        }
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        *pending
+            .entry((group.to_string(), resource, day_of(now)))
+            .or_default() += amount;
+    }
+
+    pub fn is_blocked(&self, group: &str, resource: Resource) -> bool {
+        self.blocked
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(&(group.to_string(), resource))
+    }
+
+    /// Adds pending counts to `group_usage` and returns how many rows were written.
+    /// Counts of groups that no longer exist are dropped. On failure the counts are kept
+    /// for the next flush.
+    pub async fn flush(&self, pool: &SqlitePool) -> Result<usize> {
+        let drained: Vec<_> = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            pending.drain().collect()
+        };
+        if drained.is_empty() {
+            return Ok(0);
+        }
+        match write_counts(pool, &drained).await {
+            Ok(written) => Ok(written),
+            Err(err) => {
+                let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+                for (key, amount) in drained {
+                    *pending.entry(key).or_default() += amount;
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Each group's usage in the month of `now`, by group ID: the latest storage size and
+    /// the month's totals of the counted resources, pending counts included.
+    async fn month_usage(
+        &self,
+        pool: &SqlitePool,
+        groups: &[(String, String)],
+        now: i64,
+    ) -> Result<HashMap<String, HashMap<Resource, u64>>> {
+        let (month_start, _) = month_of(day_of(now));
+        let mut usage: HashMap<String, HashMap<Resource, u64>> = HashMap::new();
+        let counted: Vec<(String, String, i64)> = sqlx::query_as(
+            "SELECT group_id, resource, SUM(amount) FROM group_usage \
+             WHERE day >= ? AND resource != 'storage' GROUP BY group_id, resource",
+        )
+        .bind(month_start)
+        .fetch_all(pool)
+        .await?;
+        let storage: Vec<(String, String, i64)> = sqlx::query_as(
+            "SELECT group_id, resource, amount FROM group_usage AS u \
+             WHERE resource = 'storage' AND day = (SELECT MAX(day) FROM group_usage \
+                 WHERE group_id = u.group_id AND resource = 'storage')",
+        )
+        .fetch_all(pool)
+        .await?;
+        for (group_id, resource, amount) in counted.into_iter().chain(storage) {
+            if let Ok(resource) = Resource::parse(&resource) {
+                *usage
+                    .entry(group_id)
+                    .or_default()
+                    .entry(resource)
+                    .or_default() += amount.max(0) as u64;
+            }
+        }
+
+        let ids: HashMap<&str, &str> = groups
+            .iter()
+            .map(|(id, slug)| (slug.as_str(), id.as_str()))
+            .collect();
+        let pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        for ((group, resource, day), amount) in pending.iter() {
+            if *day < month_start {
+                continue;
+            }
+            if let Some(id) = ids.get(group.as_str()) {
+                *usage
+                    .entry(id.to_string())
+                    .or_default()
+                    .entry(*resource)
+                    .or_default() += amount;
+            }
+        }
+        Ok(usage)
+    }
+
+    /// A top-level group's usage in the month of `now`, against its quota.
+    pub async fn group_usage(
+        &self,
+        pool: &SqlitePool,
+        config: &MeteringConfig,
+        group_id: &str,
+        group: &str,
+        now: i64,
+    ) -> Result<GroupUsage> {
+        let groups = [(group_id.to_string(), group.to_string())];
+        let usage = self.month_usage(pool, &groups, now).await?;
+        let (_, period) = month_of(day_of(now));
+        Ok(GroupUsage::new(
+            group_id,
+            group,
+            &period,
+            config.quota_for(group),
+            usage.get(group_id),
+        ))
+    }
+
+    /// Checks every top-level group against its quota: updates which groups are blocked
+    /// and announces warnings and overruns not announced yet this month. Returns the
+    /// groups at or over a limit.
+    pub async fn check_quotas(
+        &self,
+        pool: &SqlitePool,
+        config: &MeteringConfig,
+        now: i64,
+    ) -> Result<Vec<GroupUsage>> {
+        let groups = top_level_groups(pool).await?;
+        let usage = self.month_usage(pool, &groups, now).await?;
+        let (_, period) = month_of(day_of(now));
+        let over: Vec<GroupUsage> = groups
+            .iter()
+            .map(|(id, slug)| {
+                GroupUsage::new(id, slug, &period, config.quota_for(slug), usage.get(id))
+            })
+            .filter(|group| {
+                group
+                    .resources
+                    .iter()
+                    .any(|resource| resource.state != QuotaState::Within)
+            })
+            .collect();
+
+        let blocked = over
+            .iter()
+            .flat_map(|group| {
+                group
+                    .resources
+                    .iter()
+                    .filter(|resource| resource.state == QuotaState::Exceeded)
+                    .map(|resource| (group.group.clone(), resource.resource))
+            })
+            .collect();
+        *self.blocked.write().unwrap_or_else(|e| e.into_inner()) = blocked;
+
+        for group in &over {
+            for resource in &group.resources {
+                raise_alert(pool, group, resource, now).await?;
+            }
+        }
+        Ok(over)
+    }
+}
+
+/// Top-level groups as ID and slug.
+async fn top_level_groups(pool: &SqlitePool) -> Result<Vec<(String, String)>> {
+    let groups = sqlx::query_as("SELECT id, slug FROM groups WHERE parent IS NULL ORDER BY slug")
+        .fetch_all(pool)
+        .await?;
+    Ok(groups)
+}
+
+async fn write_counts(
+    pool: &SqlitePool,
+    counts: &[((String, Resource, i64), u64)],
+) -> Result<usize> {
+    let ids: HashMap<String, String> = top_level_groups(pool)
+        .await?
+        .into_iter()
+        .map(|(id, slug)| (slug, id))
+        .collect();
+    let mut tx = pool.begin().await?;
+    let mut written = 0;
+    for ((group, resource, day), amount) in counts {
+        let Some(group_id) = ids.get(group) else {
+            continue;
+        };
+        sqlx::query(
+            "INSERT INTO group_usage (group_id, day, resource, amount) VALUES (?, ?, ?, ?) \
+             ON CONFLICT(group_id, day, resource) DO UPDATE SET amount = amount + excluded.amount",
+        )
+        .bind(group_id)
+        .bind(day)
+        .bind(resource.as_str())
+        .bind(*amount as i64)
+        .execute(&mut *tx)
+        .await?;
+        written += 1;
+    }
+    tx.commit().await?;
+    Ok(written)
+}
+
+/// Publishes `usage.quota_warning` or `usage.quota_exceeded` unless it was already
+/// published for the group, resource and month.
+async fn raise_alert(
+    pool: &SqlitePool,
+    group: &GroupUsage,
+    usage: &ResourceUsage,
+    now: i64,
+) -> Result<()> {
+    let (level, kind, limit) = match usage.state {
+        QuotaState::Within => return Ok(()),
+        QuotaState::Warning => ("warning", "usage.quota_warning", usage.limit.soft),
+        QuotaState::Exceeded => ("exceeded", "usage.quota_exceeded", usage.limit.hard),
+    };
+    let mut tx = pool.begin().await?;
+    let raised = sqlx::query(
+        "INSERT INTO group_quota_alerts (group_id, resource, level, period, raised_at) \
+         VALUES (?, ?, ?, ?, ?) ON CONFLICT DO NOTHING",
+    )
+    .bind(&group.group_id)
+    .bind(usage.resource.as_str())
+    .bind(level)
+    .bind(&group.period)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if raised == 0 {
+        return Ok(());
+    }
+    tracing::warn!(
+        "group {} is {} its {} quota: {} of {} {}",
+        group.group,
+        if level == "warning" { "near" } else { "over" },
+        usage.resource.describe(),
+        usage.used,
+        limit.unwrap_or_default(),
+        usage.resource.unit()
+    );
+    publish(
+        &mut *tx,
+        crate::config::current().event_sink.as_ref(),
+        Event {
+            kind,
+            source: "core",
+            actor_did: None,
+            repository_id: None,
+            data: json!({
+                "groupId": group.group_id,
+                "group": group.group,
+                "period": group.period,
+                "resource": usage.resource.as_str(),
+                "used": usage.used,
+                "limit": limit,
+                "unit": usage.resource.unit(),
+            }),
+        },
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Bytes taken by the files under `dir`.
+fn directory_size(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => directory_size(&entry.path()),
+            Ok(kind) if kind.is_file() => entry.metadata().map_or(0, |meta| meta.len()),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Measures the local repositories of every top-level group and stores each group's
+/// total as its storage for the day of `now`. Returns how many groups were measured.
+pub async fn sample_storage(
+    pool: &SqlitePool,
+    storage: &RepositoryStorage,
+    now: i64,
+) -> Result<usize> {
+    let groups = top_level_groups(pool).await?;
+    let repositories: Vec<(String, String)> =
+        sqlx::query_as(REPOSITORY_ROOTS_SQL).fetch_all(pool).await?;
+    let storage = storage.clone();
+    let sizes = tokio::task::spawn_blocking(move || {
+        let mut sizes: HashMap<String, u64> = HashMap::new();
+        for (group_id, path) in repositories {
+            let segments: Vec<String> = path.split('/').map(str::to_string).collect();
+            let size = storage
+                .ensure_local_repository(&segments)
+                .map_or(0, |dir| directory_size(&dir));
+            *sizes.entry(group_id).or_default() += size;
+        }
+        sizes
+    })
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    for (group_id, _) in &groups {
+        sqlx::query(
+            "INSERT INTO group_usage (group_id, day, resource, amount) VALUES (?, ?, 'storage', ?) \
+             ON CONFLICT(group_id, day, resource) DO UPDATE SET amount = excluded.amount",
+        )
+        .bind(group_id)
+        .bind(day_of(now))
+        .bind(sizes.get(group_id).copied().unwrap_or(0) as i64)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(groups.len())
+}
+
+/// Deletes usage from before the retention window. Returns how many rows were removed.
+pub async fn prune_usage(pool: &SqlitePool, retention_days: u64, now: i64) -> Result<u64> {
+    if retention_days == 0 {
+        return Ok(0);
+    }
+    let result = sqlx::query("DELETE FROM group_usage WHERE day < ?")
+        .bind(day_of(now) - retention_days as i64)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+#[derive(Clone, Debug, PartialEq, sqlx::FromRow)]
+pub struct UsageExport {
+    pub id: String,
+    /// The UTC day covered, in days since 1970-01-01.
+    pub day: i64,
+    /// `csv` or `json`.
+    pub format: String,
+    pub row_count: i64,
+    pub size_bytes: i64,
+    pub created_at: i64,
+    /// When the file is deleted.
+    pub expires_at: i64,
+}
+
+impl UsageExport {
+    /// The day covered as `YYYY-MM-DD`.
+    pub fn date(&self) -> String {
+        date_of(self.day)
+    }
+
+    pub fn is_downloadable(&self, now: i64) -> bool {
+        self.expires_at > now
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self.format.as_str() {
+            "csv" => "text/csv; charset=utf-8",
+            _ => "application/json",
+        }
+    }
+}
+
+/// Where administrators download an export.
+pub fn download_url(id: &str) -> String {
+    format!(
+        "{}/usage/exports/{}",
+        crate::config::current()
+            .server
+            .public_base_url
+            .trim_end_matches('/'),
+        id
+    )
+}
+
+#[derive(sqlx::FromRow)]
+struct UsageRow {
+    group_id: String,
+    /// `None` once the group is deleted.
+    group_slug: Option<String>,
+    resource: String,
+    amount: i64,
+}
+
+fn unit_of(resource: &str) -> &'static str {
+    Resource::parse(resource).map_or("", Resource::unit)
+}
+
+fn render_csv(date: &str, rows: &[UsageRow]) -> Vec<u8> {
+    let mut out = String::from("date,group_id,group,resource,amount,unit\r\n");
+    for row in rows {
+        let fields = [
+            date,
+            &row.group_id,
+            row.group_slug.as_deref().unwrap_or(""),
+            &row.resource,
+            &row.amount.to_string(),
+            unit_of(&row.resource),
+        ]
+        .map(csv_field);
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out.into_bytes()
+}
+
+/// Exports `day` and publishes `usage.exported`. Returns `None` when the day was already
+/// exported.
+pub async fn export_day(
+    pool: &SqlitePool,
+    config: &MeteringConfig,
+    day: i64,
+    now: i64,
+) -> Result<Option<UsageExport>> {
+    let rows = sqlx::query_as::<_, UsageRow>(
+        "SELECT u.group_id, g.slug AS group_slug, u.resource, u.amount FROM group_usage AS u \
+         LEFT JOIN groups AS g ON g.id = u.group_id WHERE u.day = ? \
+         ORDER BY g.slug, u.group_id, u.resource",
+    )
+    .bind(day)
+    .fetch_all(pool)
+    .await?;
+    let date = date_of(day);
+    let id = cuid2::create_id();
+    let (format, bytes) = match config.export_format {
+        UsageExportFormat::Csv => ("csv", render_csv(&date, &rows)),
+        UsageExportFormat::Json => (
+            "json",
+            serde_json::to_vec_pretty(&json!({
+                "format": ARCHIVE_FORMAT,
+                "version": ARCHIVE_VERSION,
+                "exportId": id,
+                "date": date,
+                "generatedAt": now,
+                "rows": rows
+                    .iter()
+                    .map(|row| json!({
+                        "groupId": row.group_id,
+                        "group": row.group_slug,
+                        "resource": row.resource,
+                        "amount": row.amount,
+                        "unit": unit_of(&row.resource),
+                    }))
+                    .collect::<Vec<Value>>(),
+            }))?,
+        ),
+    };
+    let export = UsageExport {
+        id,
+        day,
+        format: format.to_string(),
+        row_count: rows.len() as i64,
+        size_bytes: bytes.len() as i64,
+        created_at: now,
+        expires_at: now.saturating_add(config.export_retention_secs as i64),
+    };
+
+    let mut tx = pool.begin().await?;
+    let inserted = sqlx::query(
+        "INSERT INTO usage_exports (id, day, format, archive, row_count, size_bytes, \
+         created_at, expires_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT(day) DO NOTHING",
+    )
+    .bind(&export.id)
+    .bind(export.day)
+    .bind(&export.format)
+    .bind(&bytes)
+    .bind(export.row_count)
+    .bind(export.size_bytes)
+    .bind(export.created_at)
+    .bind(export.expires_at)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if inserted == 0 {
+        return Ok(None);
+    }
+    publish(
+        &mut *tx,
+        crate::config::current().event_sink.as_ref(),
+        Event {
+            kind: "usage.exported",
+            source: "core",
+            actor_did: None,
+            repository_id: None,
+            data: json!({
+                "exportId": export.id,
+                "date": date,
+                "format": export.format,
+                "rowCount": export.row_count,
+                "sizeBytes": export.size_bytes,
+                "downloadUrl": download_url(&export.id),
+            }),
+        },
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(Some(export))
+}
+
+/// Exports the finished days that were not exported yet, oldest first and at most
+/// [`MAX_EXPORT_DAYS`] of them. The first export starts from the earliest recorded usage.
+pub async fn export_due_days(
+    pool: &SqlitePool,
+    config: &MeteringConfig,
+    now: i64,
+) -> Result<Vec<UsageExport>> {
+    let last_day = day_of(now - EXPORT_DELAY_SECS) - 1;
+    let exported: Option<i64> = sqlx::query_scalar("SELECT MAX(day) FROM usage_exports")
+        .fetch_one(pool)
+        .await?;
+    let first_day = match exported {
+        Some(day) => day + 1,
+        None => {
+            let recorded: Option<i64> = sqlx::query_scalar("SELECT MIN(day) FROM group_usage")
+                .fetch_one(pool)
+                .await?;
+            match recorded {
+                Some(day) => day,
+                None => return Ok(Vec::new()),
+            }
+        }
+    };
+    let mut exports = Vec::new();
+    for day in first_day..=last_day.min(first_day + MAX_EXPORT_DAYS - 1) {
+        if let Some(export) = export_day(pool, config, day, now).await? {
+            exports.push(export);
+        }
+    }
+    Ok(exports)
+}
+
+/// Recent exports, newest first.
+pub async fn list_usage_exports(pool: &SqlitePool) -> Result<Vec<UsageExport>> {
+    let exports = sqlx::query_as::<_, UsageExport>(&format!(
+        "SELECT {} FROM usage_exports ORDER BY day DESC LIMIT ?",
+        EXPORT_COLUMNS
+    ))
+    .bind(MAX_LISTED_EXPORTS)
+    .fetch_all(pool)
+    .await?;
+    Ok(exports)
+}
+
+/// The export's file, or `None` when it does not exist or has expired.
+pub async fn open_usage_export(
+    pool: &SqlitePool,
+    id: &str,
+    now: i64,
+) -> Result<Option<(UsageExport, Vec<u8>)>> {
+    let export = sqlx::query_as::<_, UsageExport>(&format!(
+        "SELECT {} FROM usage_exports WHERE id = ?",
+        EXPORT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(export) = export.filter(|export| export.is_downloadable(now)) else {
+        return Ok(None);
+    };
+    let archive: Vec<u8> = sqlx::query_scalar("SELECT archive FROM usage_exports WHERE id = ?")
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+    Ok(Some((export, archive)))
+}
+
+/// Background worker: writes counts and checks quotas every [`FLUSH_INTERVAL_SECS`],
+/// measures storage every `storage_sample_secs` and exports finished days. The last
+/// counts are written on shutdown.
+pub struct UsageMeter {
+    pool: SqlitePool,
+    storage: RepositoryStorage,
+    config: MeteringConfig,
+}
+
+impl UsageMeter {
+    pub fn new(pool: SqlitePool, storage: RepositoryStorage, config: MeteringConfig) -> Self {
+        Self {
+            pool,
+            storage,
+            config,
+        }
+    }
+
+    pub async fn run(self, shutdown: CancellationToken) -> Result<()> {
+        let mut ticker = tokio::time::interval(Duration::from_secs(FLUSH_INTERVAL_SECS));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut sampled_at = None;
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = ticker.tick() => {
+                    // A read-only standby leaves the database to the instance holding the lease.
+                    if crate::coordination::is_read_only() {
+                        continue;
+                    }
+                    self.tick(&mut sampled_at, unix_now()).await;
+                }
+            }
+        }
+        if !crate::coordination::is_read_only()
+            && let Err(e) = meter().flush(&self.pool).await
+        {
+            tracing::warn!("failed to write usage: {:#}", e);
+        }
+        Ok(())
+    }
+
+    async fn tick(&self, sampled_at: &mut Option<i64>, now: i64) {
+        let sample_every = self.config.storage_sample_secs.max(FLUSH_INTERVAL_SECS) as i64;
+        if sampled_at.is_none_or(|at| now - at >= sample_every) {
+            match sample_storage(&self.pool, &self.storage, now).await {
+                Ok(_) => *sampled_at = Some(now),
+                Err(e) => tracing::warn!("failed to measure group storage: {:#}", e),
+            }
+        }
+        if let Err(e) = meter().flush(&self.pool).await {
+            tracing::warn!("failed to write usage: {:#}", e);
+        }
+        if let Err(e) = meter().check_quotas(&self.pool, &self.config, now).await {
+            tracing::warn!("quota check failed: {:#}", e);
+        }
+
+        let exported = match export_due_days(&self.pool, &self.config, now).await {
+            Ok(exports) => exports,
+            Err(e) => {
+                tracing::warn!("usage export failed: {:#}", e);
+                return;
+            }
+        };
+        if exported.is_empty() {
+            return;
+        }
+        for export in &exported {
+            tracing::info!(
+                "exported usage of {} ({} rows)",
+                export.date(),
+                export.row_count
+            );
+        }
+        match prune_usage(&self.pool, self.config.retention_days, now).await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("pruned {} days of group usage", count),
+            Err(e) => tracing::warn!("group usage pruning failed: {:#}", e),
+        }
+        match prune_expired_exports(&self.pool, "usage_exports", now).await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("deleted {} expired usage exports", count),
+            Err(e) => tracing::warn!("usage export pruning failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::mutations::{CreateGroupInput, create_group_raw};
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::create_test_pool;
+
+    /// 2024-07-15 12:00 UTC
+    const NOW: i64 = 19_919 * SECS_PER_DAY + 12 * 60 * 60;
+
+    async fn group(pool: &SqlitePool, slug: &str, parent: Option<&str>) -> String {
+        create_group_raw(
+            pool,
+            CreateGroupInput {
+                slug: slug.into(),
+                parent: parent.map(str::to_string),
+            },
+        )
+        .await
+        .unwrap()
+        .id
+    }
+
+    fn limit(soft: u64, hard: u64) -> QuotaLimit {
+        QuotaLimit {
+            soft: Some(soft),
+            hard: Some(hard),
+        }
+    }
+
+    #[test]
+    fn days_and_quota_states() {
+        assert_eq!(date_of(0), "1970-01-01");
+        assert_eq!(date_of(day_of(NOW)), "2024-07-15");
+        assert_eq!(
+            month_of(day_of(NOW)),
+            (day_of(NOW) - 14, "2024-07".to_string())
+        );
+        assert_eq!(date_of(day_of(-1)), "1969-12-31");
+
+        assert_eq!(
+            QuotaState::of(10, QuotaLimit::default()),
+            QuotaState::Within
+        );
+        assert_eq!(QuotaState::of(79, limit(80, 100)), QuotaState::Within);
+        assert_eq!(QuotaState::of(80, limit(80, 100)), QuotaState::Warning);
+        assert_eq!(QuotaState::of(100, limit(80, 100)), QuotaState::Exceeded);
+
+        let segments = |path: &str| path.split('/').map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(metered_group(&segments("acme/web/app")), Some("acme"));
+        assert_eq!(metered_group(&segments("app")), None);
+    }
+
+    #[tokio::test]
+    async fn counts_are_summed_and_quotas_enforced() {
+        let pool = create_test_pool().await.unwrap();
+        let acme = group(&pool, "acme", None).await;
+        group(&pool, "web", Some(&acme)).await;
+        let meter = Meter::default();
+        for _ in 0..3 {
+            meter.record("acme", Resource::ApiCalls, 1, NOW);
+        }
+        meter.record("acme", Resource::Bandwidth, 4_096, NOW - 20 * SECS_PER_DAY);
+        meter.record("acme", Resource::Storage, 1, NOW);
+        meter.record("deleted", Resource::ApiCalls, 1, NOW);
+        assert_eq!(meter.flush(&pool).await.unwrap(), 2);
+        meter.record("acme", Resource::ApiCalls, 2, NOW);
+
+        let mut config = MeteringConfig::default();
+        config.groups.insert(
+            "acme".to_string(),
+            QuotaConfig {
+                api_calls: limit(4, 5),
+                bandwidth_bytes: limit(1, 2),
+                ..QuotaConfig::default()
+            },
+        );
+        // Pending counts count; bandwidth from last month does not
+        let usage = meter
+            .group_usage(&pool, &config, &acme, "acme", NOW)
+            .await
+            .unwrap();
+        assert_eq!(usage.period, "2024-07");
+        let api_calls = &usage.resources[2];
+        assert_eq!((api_calls.used, api_calls.state), (5, QuotaState::Exceeded));
+        assert_eq!(usage.resources[1].used, 0);
+        assert!(!meter.is_blocked("acme", Resource::ApiCalls));
+
+        meter.flush(&pool).await.unwrap();
+        for _ in 0..2 {
+            let over = meter.check_quotas(&pool, &config, NOW).await.unwrap();
+            assert_eq!(over.len(), 1);
+        }
+        assert!(meter.is_blocked("acme", Resource::ApiCalls));
+        assert!(!meter.is_blocked("acme", Resource::Bandwidth));
+        let alerts: Vec<String> =
+            sqlx::query_scalar("SELECT action FROM audit_log WHERE action LIKE 'usage.%'")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(alerts, ["usage.quota_exceeded"]);
+
+        // A new month starts over
+        let next_month = NOW + 20 * SECS_PER_DAY;
+        assert!(
+            meter
+                .check_quotas(&pool, &config, next_month)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(!meter.is_blocked("acme", Resource::ApiCalls));
+    }
+
+    #[tokio::test]
+    async fn storage_is_measured_per_top_level_group() {
+        let pool = create_test_pool().await.unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let storage = RepositoryStorage::new(dir.path().join("local"), dir.path().join("remote"));
+        let acme = group(&pool, "acme", None).await;
+        let web = group(&pool, "web", Some(&acme)).await;
+        let empty = group(&pool, "empty", None).await;
+        for (slug, group, path, size) in [
+            ("app", &acme, "acme/app.git", 100),
+            ("site", &web, "acme/web/site.git", 20),
+        ] {
+            create_repository_raw(
+                &pool,
+                CreateRepositoryInput {
+                    slug: slug.into(),
+                    group: Some(group.clone()),
+                },
+            )
+            .await
+            .unwrap();
+            let objects = storage.local_root.join(path).join("objects");
+            std::fs::create_dir_all(&objects).unwrap();
+            std::fs::write(objects.join("pack"), vec![0; size]).unwrap();
+        }
+
+        assert_eq!(sample_storage(&pool, &storage, NOW).await.unwrap(), 2);
+        let stored: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT group_id, amount FROM group_usage WHERE resource = 'storage' ORDER BY amount",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(stored, [(empty, 0), (acme, 120)]);
+    }
+
+    #[tokio::test]
+    async fn finished_days_are_exported_once() {
+        let pool = create_test_pool().await.unwrap();
+        let acme = group(&pool, "acme", None).await;
+        let meter = Meter::default();
+        meter.record("acme", Resource::ApiCalls, 7, NOW - 2 * SECS_PER_DAY);
+        meter.record("acme", Resource::Bandwidth, 512, NOW - SECS_PER_DAY);
+        meter.record("acme", Resource::ApiCalls, 1, NOW);
+        meter.flush(&pool).await.unwrap();
+
+        let config = MeteringConfig::default();
+        let exports = export_due_days(&pool, &config, NOW).await.unwrap();
+        assert_eq!(
+            exports.iter().map(UsageExport::date).collect::<Vec<_>>(),
+            ["2024-07-13", "2024-07-14"]
+        );
+        assert!(
+            export_due_days(&pool, &config, NOW)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let exported: Vec<String> =
+            sqlx::query_scalar("SELECT action FROM audit_log WHERE action = 'usage.exported'")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(exported.len(), 2);
+
+        let (export, bytes) = open_usage_export(&pool, &exports[1].id, NOW)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(export.row_count, 1);
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            format!(
+                "date,group_id,group,resource,amount,unit\r\n\
+                 \"2024-07-14\",\"{}\",\"acme\",\"bandwidth\",\"512\",\"bytes\"\r\n",
+                acme
+            )
+        );
+
+        let json_config = MeteringConfig {
+            export_format: UsageExportFormat::Json,
+            ..config.clone()
+        };
+        let tomorrow = NOW + SECS_PER_DAY;
+        let today = export_due_days(&pool, &json_config, tomorrow)
+            .await
+            .unwrap();
+        let (_, bytes) = open_usage_export(&pool, &today[0].id, tomorrow)
+            .await
+            .unwrap()
+            .unwrap();
+        let archive: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(archive["date"], "2024-07-15");
+        assert_eq!(archive["rows"][0]["resource"], "api_calls");
+        assert_eq!(archive["rows"][0]["unit"], "calls");
+
+        assert_eq!(list_usage_exports(&pool).await.unwrap().len(), 3);
+        let expired = tomorrow + config.export_retention_secs as i64;
+        assert!(
+            open_usage_export(&pool, &today[0].id, expired)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            prune_expired_exports(&pool, "usage_exports", expired)
+                .await
+                .unwrap(),
+            3
+        );
+        assert_eq!(prune_usage(&pool, 1, tomorrow).await.unwrap(), 2);
+    }
+}
@@ -0,0 +1,594 @@
+//! Spam and abuse screening of new content.
+//!
+//! With `abuse_check` configured, every new issue, comment and repository is shown to a
+//! checker before it is created: an extension exporting `abuse-check`, or an HTTP
+//! service. The checker allows the content, rejects it with a reason shown to the
+//! author, or flags it. Flagged content is created and queued for an administrator, who
+//! approves it (closing the entry) or removes it. When the checker fails or does not
+//! answer in time the content is allowed, unless `fail_open` is off.
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use anyhow::{Context, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+
+use crate::api::server::GraphQLRequest;
+use crate::config::{AbuseCheckConfig, AbuseChecker};
+use crate::extensions::ExtensionManager;
+use crate::extensions::wasm_runtime::Extension as WasmExtension;
+use crate::repository::mutations::delete_repository_raw;
+use crate::repository::queries::{get_repository_by_id, reconstruct_repository_path};
+use crate::repository::{PathResolver, RepositoryStorage};
+use crate::router::GraphQLExecutionRequest;
+use crate::user::db::unix_now;
+
+pub const MAX_LIST: i64 = 500;
+
+static SCREENER: OnceLock<Screener> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentKind {
+    Issue,
+    Comment,
+    Repository,
+}
+
+impl ContentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentKind::Issue => "issue",
+            ContentKind::Comment => "comment",
+            ContentKind::Repository => "repository",
+        }
+    }
+}
+
+/// Content about to be created, as the checker sees it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Content {
+    pub kind: ContentKind,
+    pub actor_did: Option<String>,
+    pub repository_id: Option<String>,
+    /// Issue title or repository name.
+    pub title: Option<String>,
+    pub body: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerdictKind {
+    Allow,
+    Flag,
+    Reject,
+}
+
+/// A checker's answer. HTTP services reply with `{"verdict": "flag", "reason": "..."}`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Verdict {
+    #[serde(rename = "verdict")]
+    pub kind: VerdictKind,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModerationStatus {
+    Pending,
+    Approved,
+    Removed,
+}
+
+impl ModerationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModerationStatus::Pending => "pending",
+            ModerationStatus::Approved => "approved",
+            ModerationStatus::Removed => "removed",
+        }
+    }
+
+    pub fn from_graphql(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "PENDING" => Ok(ModerationStatus::Pending),
+            "APPROVED" => Ok(ModerationStatus::Approved),
+            "REMOVED" => Ok(ModerationStatus::Removed),
+            other => Err(anyhow!("unknown moderation status `{}`", other)),
+        }
+    }
+}
+
+/// Flagged content in the moderation queue.
+#[derive(Clone, Debug, PartialEq, sqlx::FromRow)]
+pub struct ModerationItem {
+    pub id: String,
+    /// `issue`, `comment` or `repository`.
+    pub kind: String,
+    /// `core` or the name of the extension that created the content.
+    pub source: String,
+    /// Id of the created record.
+    pub subject_id: Option<String>,
+    pub actor_did: Option<String>,
+    pub repository_id: Option<String>,
+    pub title: Option<String>,
+    pub body: Option<String>,
+    pub reason: Option<String>,
+    /// JSON [`Removal`], for extension content.
+    pub removal: Option<String>,
+    /// `pending`, `approved` or `removed`.
+    pub status: String,
+    pub created_at: i64,
+    pub resolved_at: Option<i64>,
+    pub resolved_by: Option<String>,
+}
+
+/// The GraphQL mutation that deletes flagged extension content.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Removal {
+    pub query: String,
+    pub variables: Value,
+}
+
+/// Content the checker flagged; [`Flagged::record`] queues it once it has been created.
+#[derive(Debug)]
+pub struct Flagged {
+    content: Content,
+    reason: Option<String>,
+}
+
+enum Checker {
+    Extension(Arc<WasmExtension>),
+    Http {
+        client: reqwest::Client,
+        url: String,
+        token: Option<String>,
+    },
+}
+
+struct Screener {
+    checker: Checker,
+    timeout: Duration,
+    fail_open: bool,
+}
+
+/// Sets up the configured checker. Fails when the named extension is not loaded or does
+/// not export `abuse-check`. Later calls are ignored.
+pub fn install(config: &AbuseCheckConfig, extensions: &ExtensionManager) -> anyhow::Result<()> {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let checker = match &config.checker {
+        AbuseChecker::Extension(name) => {
+            let extensions = extensions.get_extensions();
+            let extension = extensions
+                .get(name)
+                .ok_or_else(|| anyhow!("extension `{}` is not loaded", name))?;
+            if !extension.runtime.interfaces().abuse_check {
+                bail!("extension `{}` does not export abuse-check", name);
+            }
+            Checker::Extension(extension.runtime.clone())
+        }
+        AbuseChecker::Http { url, token_env } => {
+            let token = match token_env {
+                Some(var) => {
+                    Some(std::env::var(var).with_context(|| format!("{} is not set", var))?)
+                }
+                None => None,
+            };
+            Checker::Http {
+                client: reqwest::Client::builder().timeout(timeout).build()?,
+                url: url.clone(),
+                token,
+            }
+        }
+    };
+    let _ = SCREENER.set(Screener {
+        checker,
+        timeout,
+        fail_open: config.fail_open,
+    });
+    Ok(())
+}
+
+impl Screener {
+    async fn check(&self, content: &Content) -> anyhow::Result<Verdict> {
+        match &self.checker {
+            Checker::Extension(extension) => {
+                tokio::time::timeout(self.timeout, extension.check_content(content.clone()))
+                    .await
+                    .map_err(|_| anyhow!("abuse check timed out"))?
+            }
+            Checker::Http { client, url, token } => {
+                let mut request = client.post(url).json(content);
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                let response = request.send().await?.error_for_status()?;
+                Ok(response.json().await?)
+            }
+        }
+    }
+}
+
+/// The verdict to act on when the checker answered `checked`.
+fn decide(checked: anyhow::Result<Verdict>, fail_open: bool) -> Verdict {
+    match checked {
+        Ok(verdict) => verdict,
+        Err(e) if fail_open => {
+            tracing::warn!(error = %e, "abuse check failed; allowing content");
+            Verdict {
+                kind: VerdictKind::Allow,
+                reason: None,
+            }
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "abuse check failed; rejecting content");
+            Verdict {
+                kind: VerdictKind::Reject,
+                reason: Some("content could not be checked, try again later".to_string()),
+            }
+        }
+    }
+}
+
+/// Asks the checker about `content`. Returns an error when it is rejected, and the
+/// flag to record once it has been created when it is flagged.
+pub async fn screen(content: Content) -> anyhow::Result<Option<Flagged>> {
+    let Some(screener) = SCREENER.get() else {
+        return Ok(None);
+    };
+    let verdict = decide(screener.check(&content).await, screener.fail_open);
+    match verdict.kind {
+        VerdictKind::Allow => Ok(None),
+        VerdictKind::Flag => Ok(Some(Flagged {
+            content,
+            reason: verdict.reason,
+        })),
+        VerdictKind::Reject => {
+            tracing::info!(
+                kind = content.kind.as_str(),
+                actor = ?content.actor_did,
+                reason = ?verdict.reason,
+                "abuse check rejected content"
+            );
+            Err(match verdict.reason {
+                Some(reason) => anyhow!("rejected by abuse check: {}", reason),
+                None => anyhow!("rejected by abuse check"),
+            })
+        }
+    }
+}
+
+/// What `field`, an extension mutation, creates, when it is one that is screened.
+pub fn mutation_content(
+    field: &str,
+    arguments: &Value,
+    actor_did: Option<&str>,
+) -> Option<Content> {
+    let text = |value: &Value| value.as_str().map(str::to_string);
+    match field {
+        "createIssue" => Some(Content {
+            kind: ContentKind::Issue,
+            actor_did: actor_did.map(str::to_string),
+            repository_id: text(&arguments["repositoryId"]),
+            title: text(&arguments["input"]["title"]),
+            body: text(&arguments["input"]["description"]),
+        }),
+        "addComment" => Some(Content {
+            kind: ContentKind::Comment,
+            actor_did: actor_did.map(str::to_string),
+            repository_id: text(&arguments["repositoryId"]),
+            title: None,
+            body: text(&arguments["body"]),
+        }),
+        _ => None,
+    }
+}
+
+/// The mutation that deletes what `field` created, given its arguments and result.
+pub fn mutation_removal(field: &str, arguments: &Value, created: &Value) -> Option<Removal> {
+    match field {
+        "createIssue" => Some(Removal {
+            query: "mutation RemoveFlaggedIssue($repositoryId: ID!, $issueNumber: Int!) { \
+                    deleteIssue(repositoryId: $repositoryId, issueNumber: $issueNumber) }"
+                .to_string(),
+            variables: json!({
+                "repositoryId": arguments.get("repositoryId")?,
+                "issueNumber": created.get("number")?,
+            }),
+        }),
+        "addComment" => Some(Removal {
+            query: "mutation RemoveFlaggedComment($repositoryId: ID!, $commentId: ID!) { \
+                    deleteComment(repositoryId: $repositoryId, commentId: $commentId) }"
+                .to_string(),
+            variables: json!({
+                "repositoryId": arguments.get("repositoryId")?,
+                "commentId": created.get("id")?,
+            }),
+        }),
+        _ => None,
+    }
+}
+
+impl Flagged {
+    /// Queues the created content for review.
+    pub async fn record(
+        self,
+        pool: &SqlitePool,
+        source: &str,
+        subject_id: Option<&str>,
+        removal: Option<Removal>,
+    ) -> anyhow::Result<ModerationItem> {
+        let item = ModerationItem {
+            id: cuid2::create_id(),
+            kind: self.content.kind.as_str().to_string(),
+            source: source.to_string(),
+            subject_id: subject_id.map(str::to_string),
+            actor_did: self.content.actor_did,
+            repository_id: self.content.repository_id,
+            title: self.content.title,
+            body: self.content.body,
+            reason: self.reason,
+            removal: removal
+                .map(|removal| serde_json::to_string(&removal))
+                .transpose()?,
+            status: ModerationStatus::Pending.as_str().to_string(),
+            created_at: unix_now(),
+            resolved_at: None,
+            resolved_by: None,
+        };
+        sqlx::query(
+            "INSERT INTO moderation_queue \
+             (id, kind, source, subject_id, actor_did, repository_id, title, body, reason, \
+              removal, status, created_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&item.id)
+        .bind(&item.kind)
+        .bind(&item.source)
+        .bind(&item.subject_id)
+        .bind(&item.actor_did)
+        .bind(&item.repository_id)
+        .bind(&item.title)
+        .bind(&item.body)
+        .bind(&item.reason)
+        .bind(&item.removal)
+        .bind(&item.status)
+        .bind(item.created_at)
+        .execute(pool)
+        .await?;
+        tracing::info!(
+            id = %item.id,
+            kind = %item.kind,
+            source = %item.source,
+            "abuse check flagged content for moderation"
+        );
+        Ok(item)
+    }
+}
+
+/// Queued items, newest first.
+pub async fn list_items(
+    pool: &SqlitePool,
+    status: Option<ModerationStatus>,
+    limit: i64,
+) -> Result<Vec<ModerationItem>, sqlx::Error> {
+    let status = status.map(|status| status.as_str());
+    sqlx::query_as::<_, ModerationItem>(
+        "SELECT id, kind, source, subject_id, actor_did, repository_id, title, body, reason, \
+                removal, status, created_at, resolved_at, resolved_by \
+         FROM moderation_queue \
+         WHERE (? IS NULL OR status = ?) \
+         ORDER BY created_at DESC, id DESC \
+         LIMIT ?",
+    )
+    .bind(status)
+    .bind(status)
+    .bind(limit.clamp(1, MAX_LIST))
+    .fetch_all(pool)
+    .await
+}
+
+async fn fetch_item(pool: &SqlitePool, id: &str) -> Result<Option<ModerationItem>, sqlx::Error> {
+    sqlx::query_as::<_, ModerationItem>(
+        "SELECT id, kind, source, subject_id, actor_did, repository_id, title, body, reason, \
+                removal, status, created_at, resolved_at, resolved_by \
+         FROM moderation_queue WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Closes a pending item with `status`.
+async fn resolve_item(
+    pool: &SqlitePool,
+    id: &str,
+    status: ModerationStatus,
+    admin_did: &str,
+) -> anyhow::Result<ModerationItem> {
+    let updated = sqlx::query(
+        "UPDATE moderation_queue SET status = ?, resolved_at = ?, resolved_by = ? \
+         WHERE id = ? AND status = 'pending'",
+    )
+    .bind(status.as_str())
+    .bind(unix_now())
+    .bind(admin_did)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    let item = fetch_item(pool, id)
+        .await?
+        .ok_or_else(|| anyhow!("moderation item not found"))?;
+    if updated.rows_affected() == 0 {
+        bail!("moderation item was already {}", item.status);
+    }
+    Ok(item)
+}
+
+/// Keeps flagged content.
+pub async fn approve_item(
+    pool: &SqlitePool,
+    id: &str,
+    admin_did: &str,
+) -> anyhow::Result<ModerationItem> {
+    resolve_item(pool, id, ModerationStatus::Approved, admin_did).await
+}
+
+/// Deletes flagged content: repositories directly, extension content with the item's
+/// removal mutation, run as the administrator.
+pub async fn remove_item(
+    pool: &SqlitePool,
+    resolver: &PathResolver,
+    storage: &RepositoryStorage,
+    id: &str,
+    admin_did: &str,
+) -> anyhow::Result<ModerationItem> {
+    let item = fetch_item(pool, id)
+        .await?
+        .ok_or_else(|| anyhow!("moderation item not found"))?;
+    if item.status != ModerationStatus::Pending.as_str() {
+        bail!("moderation item was already {}", item.status);
+    }
+
+    if item.source == "core" && item.kind == ContentKind::Repository.as_str() {
+        let subject_id = item.subject_id.as_deref().unwrap_or_default();
+        // Already gone when its owner deleted it first.
+        if let Some(record) = get_repository_by_id(pool, subject_id).await? {
+            let path = reconstruct_repository_path(pool, &record).await?;
+            delete_repository_raw(pool, resolver, storage, &path, Some(admin_did)).await?;
+        }
+    } else {
+        let removal: Removal = match &item.removal {
+            Some(removal) => serde_json::from_str(removal)?,
+            None => bail!(
+                "content from `{}` cannot be removed here; delete it in the extension",
+                item.source
+            ),
+        };
+        run_removal(removal).await?;
+    }
+    resolve_item(pool, id, ModerationStatus::Removed, admin_did).await
+}
+
+async fn run_removal(removal: Removal) -> anyhow::Result<()> {
+    let router = crate::router::shared().ok_or_else(|| anyhow!("GraphQL is not available"))?;
+    let request = GraphQLExecutionRequest::from_payload(&GraphQLRequest {
+        query: removal.query,
+        operation_name: None,
+        variables: removal.variables,
+    })?;
+    let response = router.execute(request).await?;
+    if let Some(error) = response["errors"].get(0) {
+        bail!(
+            "failed to remove content: {}",
+            error["message"].as_str().unwrap_or("unknown error")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    #[test]
+    fn failed_checks_follow_fail_open() {
+        let flagged = Verdict {
+            kind: VerdictKind::Flag,
+            reason: Some("links".to_string()),
+        };
+        assert_eq!(decide(Ok(flagged.clone()), false), flagged);
+        assert_eq!(
+            decide(Err(anyhow!("timed out")), true).kind,
+            VerdictKind::Allow
+        );
+        assert_eq!(
+            decide(Err(anyhow!("timed out")), false).kind,
+            VerdictKind::Reject
+        );
+
+        let answer: Verdict =
+            serde_json::from_str(r#"{"verdict": "reject", "reason": "spam"}"#).unwrap();
+        assert_eq!(answer.kind, VerdictKind::Reject);
+        assert_eq!(answer.reason.as_deref(), Some("spam"));
+    }
+
+    #[test]
+    fn issues_and_comments_are_screened_and_removable() {
+        let arguments = json!({
+            "repositoryId": "r1",
+            "input": { "title": "Cheap watches", "description": "buy now" },
+        });
+        let content = mutation_content("createIssue", &arguments, Some("did:plc:spam")).unwrap();
+        assert_eq!(content.kind, ContentKind::Issue);
+        assert_eq!(content.title.as_deref(), Some("Cheap watches"));
+        assert_eq!(content.body.as_deref(), Some("buy now"));
+        assert!(mutation_content("updateIssue", &arguments, None).is_none());
+
+        let removal = mutation_removal("createIssue", &arguments, &json!({ "number": 7 })).unwrap();
+        assert!(removal.query.contains("deleteIssue"));
+        assert_eq!(
+            removal.variables,
+            json!({ "repositoryId": "r1", "issueNumber": 7 })
+        );
+
+        let arguments = json!({ "repositoryId": "r1", "issueNumber": 7, "body": "buy now" });
+        let content = mutation_content("addComment", &arguments, Some("did:plc:spam")).unwrap();
+        assert_eq!(content.kind, ContentKind::Comment);
+        assert_eq!(content.body.as_deref(), Some("buy now"));
+        let removal = mutation_removal("addComment", &arguments, &json!({ "id": "c1" })).unwrap();
+        assert!(removal.query.contains("deleteComment"));
+        assert_eq!(
+            removal.variables,
+            json!({ "repositoryId": "r1", "commentId": "c1" })
+        );
+    }
+
+    #[tokio::test]
+    async fn flagged_items_are_resolved_once() {
+        let pool = create_test_pool().await.unwrap();
+        let flagged = Flagged {
+            content: Content {
+                kind: ContentKind::Issue,
+                actor_did: Some("did:plc:spam".to_string()),
+                repository_id: Some("r1".to_string()),
+                title: Some("Cheap watches".to_string()),
+                body: None,
+            },
+            reason: Some("links".to_string()),
+        };
+        let item = flagged
+            .record(&pool, "issues", Some("i1"), None)
+            .await
+            .unwrap();
+
+        let pending = list_items(&pool, Some(ModerationStatus::Pending), 10)
+            .await
+            .unwrap();
+        assert_eq!(pending, vec![item.clone()]);
+
+        let approved = approve_item(&pool, &item.id, "did:plc:admin")
+            .await
+            .unwrap();
+        assert_eq!(approved.status, "approved");
+        assert_eq!(approved.resolved_by.as_deref(), Some("did:plc:admin"));
+        assert!(
+            list_items(&pool, Some(ModerationStatus::Pending), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let again = approve_item(&pool, &item.id, "did:plc:admin").await;
+        assert!(again.unwrap_err().to_string().contains("already approved"));
+        assert!(
+            approve_item(&pool, "missing", "did:plc:admin")
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use sqlx::SqlitePool;
+
+use super::models::{DigestFrequency, NotificationRecord, PushSubscription};
+
+pub async fn insert_notification(
+    pool: &SqlitePool,
+    recipient_did: &str,
+    kind: &str,
+    title: &str,
+    url: Option<&str>,
+    created_at: i64,
+) -> Result<NotificationRecord, sqlx::Error> {
+    let id = cuid2::create_id();
+    sqlx::query(
+        "INSERT INTO notifications (id, recipient_did, kind, title, url, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(recipient_did)
+    .bind(kind)
+    .bind(title)
+    .bind(url)
+    .bind(created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(NotificationRecord {
+        id,
+        recipient_did: recipient_did.to_string(),
+        kind: kind.to_string(),
+        title: title.to_string(),
+        url: url.map(str::to_string),
+        created_at,
+        read_at: None,
+    })
+}
+
+pub async fn mark_notification_read(
+    pool: &SqlitePool,
+    id: &str,
+    read_at: i64,
+) -> Result<bool, sqlx::Error> {
+    let result =
+        sqlx::query("UPDATE notifications SET read_at = ? WHERE id = ? AND read_at IS NULL")
+            .bind(read_at)
+            .bind(id)
+            .execute(pool)
+            .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn set_digest_frequency(
+    pool: &SqlitePool,
+    user_did: &str,
+    frequency: DigestFrequency,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO notification_preferences (user_did, digest_frequency) VALUES (?, ?) \
+         ON CONFLICT(user_did) DO UPDATE SET digest_frequency = excluded.digest_frequency",
+    )
+    .bind(user_did)
+    .bind(frequency.as_str())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_digest_frequency(
+    pool: &SqlitePool,
+    user_did: &str,
+) -> anyhow::Result<DigestFrequency> {
+    let value: Option<String> = sqlx::query_scalar(
+        "SELECT digest_frequency FROM notification_preferences WHERE user_did = ?",
+    )
+    .bind(user_did)
+    .fetch_optional(pool)
+    .await?;
+
+    match value {
+        Some(value) => DigestFrequency::parse(&value),
+        None => Ok(DigestFrequency::default()),
+    }
+}
+
+/// Users with a non-immediate frequency whose next digest is due at `now`.
+pub async fn users_due_for_digest(
+    pool: &SqlitePool,
+    now: i64,
+) -> anyhow::Result<Vec<(String, DigestFrequency, Option<i64>)>> {
+    let rows: Vec<(String, String, Option<i64>)> = sqlx::query_as(
+        "SELECT user_did, digest_frequency, last_digest_at FROM notification_preferences \
+         WHERE digest_frequency != 'immediate'",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut due = Vec::new();
+    for (did, frequency, last) in rows {
+        let frequency = DigestFrequency::parse(&frequency)?;
+        let Some(interval) = frequency.interval_secs() else {
+            continue;
+        };
+        if last.map(|last| last + interval <= now).unwrap_or(true) {
+            due.push((did, frequency, last));
+        }
+    }
+    Ok(due)
+}
+
+pub async fn undigested_unread_notifications(
+    pool: &SqlitePool,
+    recipient_did: &str,
+) -> Result<Vec<NotificationRecord>, sqlx::Error> {
+    sqlx::query_as::<_, NotificationRecord>(
+        "SELECT id, recipient_did, kind, title, url, created_at, read_at FROM notifications \
+         WHERE recipient_did = ? AND read_at IS NULL AND digested_at IS NULL ORDER BY created_at",
+    )
+    .bind(recipient_did)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn mark_digested(
+    pool: &SqlitePool,
+    recipient_did: &str,
+    ids: &[String],
+    now: i64,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for id in ids {
+        sqlx::query("UPDATE notifications SET digested_at = ? WHERE id = ?")
+            .bind(now)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query("UPDATE notification_preferences SET last_digest_at = ? WHERE user_did = ?")
+        .bind(now)
+        .bind(recipient_did)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+/// Unread notifications created after `since` that the push worker has not handled yet.
+pub async fn unpushed_notifications(
+    pool: &SqlitePool,
+    since: i64,
+    limit: i64,
+) -> Result<Vec<NotificationRecord>, sqlx::Error> {
+    sqlx::query_as::<_, NotificationRecord>(
+        "SELECT id, recipient_did, kind, title, url, created_at, read_at FROM notifications \
+         WHERE pushed_at IS NULL AND read_at IS NULL AND created_at > ? ORDER BY created_at LIMIT ?",
+    )
+    .bind(since)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn mark_pushed(pool: &SqlitePool, ids: &[String], now: i64) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for id in ids {
+        sqlx::query("UPDATE notifications SET pushed_at = ? WHERE id = ?")
+            .bind(now)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+/// Marks notifications created at or before `before` as handled without pushing them.
+pub async fn skip_stale_pushes(
+    pool: &SqlitePool,
+    before: i64,
+    now: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE notifications SET pushed_at = ? \
+         WHERE pushed_at IS NULL AND (created_at <= ? OR read_at IS NOT NULL)",
+    )
+    .bind(now)
+    .bind(before)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+const PUSH_SUBSCRIPTION_COLUMNS: &str =
+    "id, user_did, endpoint, p256dh, auth, created_at, expires_at, last_success_at, failures";
+
+/// Stores a subscription. A browser that subscribes again keeps its endpoint, so an
+/// existing endpoint is taken over with the new keys, even from another account.
+pub async fn upsert_push_subscription(
+    pool: &SqlitePool,
+    user_did: &str,
+    endpoint: &str,
+    p256dh: &str,
+    auth: &str,
+    expires_at: Option<i64>,
+    now: i64,
+) -> Result<PushSubscription, sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO push_subscriptions (id, user_did, endpoint, p256dh, auth, created_at, expires_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(endpoint) DO UPDATE SET user_did = excluded.user_did, p256dh = excluded.p256dh, \
+         auth = excluded.auth, expires_at = excluded.expires_at, failures = 0",
+    )
+    .bind(cuid2::create_id())
+    .bind(user_did)
+    .bind(endpoint)
+    .bind(p256dh)
+    .bind(auth)
+    .bind(now)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    sqlx::query_as::<_, PushSubscription>(&format!(
+        "SELECT {} FROM push_subscriptions WHERE endpoint = ?",
+        PUSH_SUBSCRIPTION_COLUMNS
+    ))
+    .bind(endpoint)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn push_subscriptions_for(
+    pool: &SqlitePool,
+    user_did: &str,
+) -> Result<Vec<PushSubscription>, sqlx::Error> {
+    sqlx::query_as::<_, PushSubscription>(&format!(
+        "SELECT {} FROM push_subscriptions WHERE user_did = ? ORDER BY created_at",
+        PUSH_SUBSCRIPTION_COLUMNS
+    ))
+    .bind(user_did)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn delete_push_subscription(
+    pool: &SqlitePool,
+    user_did: &str,
+    endpoint: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM push_subscriptions WHERE user_did = ? AND endpoint = ?")
+        .bind(user_did)
+        .bind(endpoint)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn remove_push_subscription(pool: &SqlitePool, id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM push_subscriptions WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn record_push_success(pool: &SqlitePool, id: &str, now: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE push_subscriptions SET failures = 0, last_success_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Counts a failed delivery. Returns the number of consecutive failures so far.
+pub async fn record_push_failure(pool: &SqlitePool, id: &str) -> Result<i64, sqlx::Error> {
+    let failures: Option<i64> = sqlx::query_scalar(
+        "UPDATE push_subscriptions SET failures = failures + 1 WHERE id = ? RETURNING failures",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(failures.unwrap_or(0))
+}
+
+/// Drops subscriptions whose browser-reported expiry has passed.
+pub async fn delete_expired_push_subscriptions(
+    pool: &SqlitePool,
+    now: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM push_subscriptions WHERE expires_at IS NOT NULL AND expires_at <= ?",
+    )
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
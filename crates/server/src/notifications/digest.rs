@@ -0,0 +1,155 @@
+//! Digest aggregation: collects unread notifications for users who opted into
+//! daily/weekly delivery and renders them into a single summary.
+
+use sqlx::SqlitePool;
+
+use super::db::{mark_digested, undigested_unread_notifications, users_due_for_digest};
+use super::models::{DigestFrequency, DigestPayload, NotificationRecord};
+
+/// Placeholders: `{count}`, `{frequency}`.
+pub const DEFAULT_DIGEST_HEADER: &str =
+    "You have {count} unread notifications ({frequency} digest)";
+/// Placeholders: `{kind}`, `{title}`, `{url}`.
+pub const DEFAULT_DIGEST_ITEM: &str = "- [{kind}] {title} {url}";
+
+#[derive(Clone, Debug)]
+pub struct DigestTemplate {
+    pub header: String,
+    pub item: String,
+}
+
+impl Default for DigestTemplate {
+    fn default() -> Self {
+        DigestTemplate {
+            header: DEFAULT_DIGEST_HEADER.to_string(),
+            item: DEFAULT_DIGEST_ITEM.to_string(),
+        }
+    }
+}
+
+/// The first line of a digest, which also titles its push notification.
+pub fn render_header(
+    template: &DigestTemplate,
+    frequency: DigestFrequency,
+    count: usize,
+) -> String {
+    template
+        .header
+        .replace("{count}", &count.to_string())
+        .replace("{frequency}", frequency.as_str())
+}
+
+pub fn render_digest(
+    template: &DigestTemplate,
+    frequency: DigestFrequency,
+    notifications: &[NotificationRecord],
+) -> String {
+    let mut lines = vec![render_header(template, frequency, notifications.len())];
+    for notification in notifications {
+        let line = template
+            .item
+            .replace("{kind}", &notification.kind)
+            .replace("{title}", &notification.title)
+            .replace("{url}", notification.url.as_deref().unwrap_or(""));
+        lines.push(line.trim_end().to_string());
+    }
+    lines.join("\n")
+}
+
+/// Builds and records digests for every user that is due at `now`.
+///
+/// Users with nothing unread still have their `last_digest_at` advanced so the
+/// schedule does not drift, but no payload is returned for them.
+pub async fn run_digest_cycle(
+    pool: &SqlitePool,
+    template: &DigestTemplate,
+    now: i64,
+) -> anyhow::Result<Vec<DigestPayload>> {
+    let mut payloads = Vec::new();
+    for (did, frequency, last_digest_at) in users_due_for_digest(pool, now).await? {
+        let notifications = undigested_unread_notifications(pool, &did).await?;
+        let ids: Vec<String> = notifications.iter().map(|n| n.id.clone()).collect();
+        mark_digested(pool, &did, &ids, now).await?;
+
+        if notifications.is_empty() {
+            continue;
+        }
+
+        let summary = render_digest(template, frequency, &notifications);
+        payloads.push(DigestPayload {
+            recipient_did: did,
+            frequency,
+            period_start: last_digest_at,
+            period_end: now,
+            notifications,
+            summary,
+        });
+    }
+    Ok(payloads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::db::{
+        insert_notification, mark_notification_read, set_digest_frequency,
+    };
+    use crate::test_helpers::create_test_pool;
+
+    #[tokio::test]
+    async fn digest_collects_unread_notifications_once() {
+        let pool = create_test_pool().await.unwrap();
+        set_digest_frequency(&pool, "did:plc:alice", DigestFrequency::Daily)
+            .await
+            .unwrap();
+        set_digest_frequency(&pool, "did:plc:bob", DigestFrequency::Immediate)
+            .await
+            .unwrap();
+
+        insert_notification(
+            &pool,
+            "did:plc:alice",
+            "issue",
+            "Issue opened",
+            Some("/i/1"),
+            10,
+        )
+        .await
+        .unwrap();
+        let read = insert_notification(&pool, "did:plc:alice", "issue", "Already seen", None, 11)
+            .await
+            .unwrap();
+        mark_notification_read(&pool, &read.id, 12).await.unwrap();
+        insert_notification(&pool, "did:plc:bob", "issue", "Bob's", None, 10)
+            .await
+            .unwrap();
+
+        let template = DigestTemplate::default();
+        let digests = run_digest_cycle(&pool, &template, 100).await.unwrap();
+        assert_eq!(digests.len(), 1);
+        assert_eq!(digests[0].recipient_did, "did:plc:alice");
+        assert_eq!(digests[0].notifications.len(), 1);
+        assert!(
+            digests[0]
+                .summary
+                .contains("1 unread notifications (daily digest)")
+        );
+        assert!(digests[0].summary.contains("- [issue] Issue opened /i/1"));
+
+        // Not due again until a day has passed.
+        insert_notification(&pool, "did:plc:alice", "issue", "Later", None, 200)
+            .await
+            .unwrap();
+        assert!(
+            run_digest_cycle(&pool, &template, 300)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let next = run_digest_cycle(&pool, &template, 100 + 24 * 60 * 60)
+            .await
+            .unwrap();
+        assert_eq!(next[0].notifications.len(), 1);
+        assert_eq!(next[0].notifications[0].title, "Later");
+    }
+}
@@ -0,0 +1,9 @@
+//! User notifications, periodic digests and Web Push delivery.
+
+pub mod db;
+pub mod digest;
+pub mod models;
+pub mod push;
+
+pub use digest::{render_digest, run_digest_cycle};
+pub use models::{DigestFrequency, DigestPayload, NotificationRecord, PushSubscription};
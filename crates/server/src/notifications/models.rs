@@ -0,0 +1,74 @@
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestFrequency {
+    /// Notifications are delivered as they happen; no digest is built.
+    #[default]
+    Immediate,
+    Daily,
+    Weekly,
+}
+
+impl DigestFrequency {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "immediate" => Ok(DigestFrequency::Immediate),
+            "daily" => Ok(DigestFrequency::Daily),
+            "weekly" => Ok(DigestFrequency::Weekly),
+            other => Err(anyhow::anyhow!("unknown digest frequency: {}", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Immediate => "immediate",
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+        }
+    }
+
+    /// Seconds between digests, or `None` for immediate delivery.
+    pub fn interval_secs(&self) -> Option<i64> {
+        match self {
+            DigestFrequency::Immediate => None,
+            DigestFrequency::Daily => Some(24 * 60 * 60),
+            DigestFrequency::Weekly => Some(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow, Serialize)]
+pub struct NotificationRecord {
+    pub id: String,
+    pub recipient_did: String,
+    pub kind: String,
+    pub title: String,
+    pub url: Option<String>,
+    pub created_at: i64,
+    pub read_at: Option<i64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DigestPayload {
+    pub recipient_did: String,
+    pub frequency: DigestFrequency,
+    pub period_start: Option<i64>,
+    pub period_end: i64,
+    pub notifications: Vec<NotificationRecord>,
+    pub summary: String,
+}
+
+/// A browser's Web Push subscription.
+#[derive(Clone, Debug, sqlx::FromRow, Serialize)]
+pub struct PushSubscription {
+    pub id: String,
+    pub user_did: String,
+    pub endpoint: String,
+    pub p256dh: String,
+    pub auth: String,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub last_success_at: Option<i64>,
+    pub failures: i64,
+}
@@ -0,0 +1,970 @@
+//! Web Push delivery of notifications to browsers.
+//!
+//! The web UI subscribes the browser with the server's VAPID public key and registers
+//! the resulting endpoint and keys with `registerPushSubscription`. A supervised worker
+//! then looks for new notifications every `push.poll_interval_secs` and sends each one to
+//! every subscription of its recipient, encrypted as RFC 8291 (`aes128gcm`) and signed
+//! with the VAPID key (RFC 8292).
+//!
+//! Delivery is best effort: the notification stays in the database either way. Users on
+//! a daily or weekly digest get no push per notification. Every
+//! `push.digest_interval_secs` the worker builds the digests that are due and pushes each
+//! as one notification that only counts what is unread. Subscriptions are dropped
+//! when the push service reports them gone (`404`, `410`), when their expiry passes, and
+//! after `push.max_failures` failed deliveries in a row.
+
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use metrics::counter;
+use ring::aead::{AES_128_GCM, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::agreement::{ECDH_P256, EphemeralPrivateKey, UnparsedPublicKey, agree_ephemeral};
+use ring::hkdf::{HKDF_SHA256, KeyType, Prk, Salt};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair};
+use serde_json::json;
+use sqlx::SqlitePool;
+use tokio_util::sync::CancellationToken;
+
+use super::db::{
+    delete_expired_push_subscriptions, get_digest_frequency, mark_pushed, push_subscriptions_for,
+    record_push_failure, record_push_success, remove_push_subscription, skip_stale_pushes,
+    unpushed_notifications, upsert_push_subscription,
+};
+use super::digest::{DigestTemplate, render_header, run_digest_cycle};
+use super::models::{DigestFrequency, DigestPayload, NotificationRecord, PushSubscription};
+use crate::config::{Config, PushConfig};
+use crate::key_file;
+use crate::user::db::unix_now;
+
+pub const MAX_SUBSCRIPTIONS_PER_USER: usize = 20;
+const MAX_ENDPOINT_LEN: usize = 2048;
+/// Notifications handled per worker cycle
+const BATCH_SIZE: i64 = 100;
+/// VAPID tokens may be valid for at most a day; push services reject longer ones
+const TOKEN_LIFETIME_SECS: i64 = 12 * 60 * 60;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Size of the single `aes128gcm` record, which every push service must accept
+const RECORD_SIZE: u32 = 4096;
+/// Record size less the content coding header (86 bytes), padding delimiter and tag
+const MAX_PLAINTEXT: usize = RECORD_SIZE as usize - 86 - 1 - 16;
+const MAX_TITLE_CHARS: usize = 500;
+
+/// The server's VAPID signing key.
+pub struct VapidKey {
+    key_pair: EcdsaKeyPair,
+}
+
+impl VapidKey {
+    /// Reads the PKCS#8 key at `path`, generating and storing one when it is missing. Any
+    /// other read error is returned, as a new key would invalidate every subscription.
+    pub fn load_or_generate(path: &Path) -> Result<Self> {
+        let pkcs8 = key_file::load_or_generate(path, "VAPID key", || {
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(
+                &ECDSA_P256_SHA256_FIXED_SIGNING,
+                &SystemRandom::new(),
+            )
+            .map_err(|_| anyhow!("failed to generate VAPID key"))?;
+            Ok(pkcs8.as_ref().to_vec())
+        })?;
+        Self::from_pkcs8(&pkcs8).with_context(|| format!("invalid VAPID key in {}", path.display()))
+    }
+
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self> {
+        let key_pair = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_FIXED_SIGNING,
+            pkcs8,
+            &SystemRandom::new(),
+        )
+        .map_err(|_| anyhow!("not a P-256 PKCS#8 key"))?;
+        Ok(Self { key_pair })
+    }
+
+    /// The uncompressed public key, base64url: the `applicationServerKey` browsers
+    /// subscribe with.
+    pub fn public_key(&self) -> String {
+        URL_SAFE_NO_PAD.encode(self.key_pair.public_key().as_ref())
+    }
+
+    /// `Authorization` header value for a push to `endpoint`.
+    pub fn authorization(&self, endpoint: &str, subject: &str, now: i64) -> Result<String> {
+        let audience = url::Url::parse(endpoint)
+            .context("invalid push endpoint")?
+            .origin()
+            .ascii_serialization();
+        let header = URL_SAFE_NO_PAD.encode(br#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = URL_SAFE_NO_PAD.encode(
+            json!({ "aud": audience, "exp": now + TOKEN_LIFETIME_SECS, "sub": subject })
+                .to_string(),
+        );
+        let input = format!("{}.{}", header, claims);
+        let signature = self
+            .key_pair
+            .sign(&SystemRandom::new(), input.as_bytes())
+            .map_err(|_| anyhow!("failed to sign VAPID token"))?;
+        Ok(format!(
+            "vapid t={}.{}, k={}",
+            input,
+            URL_SAFE_NO_PAD.encode(signature.as_ref()),
+            self.public_key()
+        ))
+    }
+}
+
+static KEY: OnceLock<VapidKey> = OnceLock::new();
+static KEY_LOAD: Mutex<()> = Mutex::new(());
+
+/// The key from `push.vapid_key_path`, loaded once.
+pub fn vapid_key() -> Result<&'static VapidKey> {
+    if let Some(key) = KEY.get() {
+        return Ok(key);
+    }
+    // Serialise loading so two first uses cannot generate different keys.
+    let _guard = KEY_LOAD.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(key) = KEY.get() {
+        return Ok(key);
+    }
+    let key = VapidKey::load_or_generate(&crate::config::current().push.vapid_key_path)?;
+    Ok(KEY.get_or_init(|| key))
+}
+
+/// The VAPID public key for the web UI, or `None` when web push is disabled.
+pub fn public_key() -> Result<Option<String>> {
+    if !crate::config::current().push.enabled {
+        return Ok(None);
+    }
+    Ok(Some(vapid_key()?.public_key()))
+}
+
+/// Stores the viewer's subscription as the browser reported it (`PushSubscription.toJSON()`).
+/// `expires_at` is in Unix seconds.
+pub async fn register_subscription(
+    pool: &SqlitePool,
+    user_did: &str,
+    endpoint: &str,
+    p256dh: &str,
+    auth: &str,
+    expires_at: Option<i64>,
+) -> Result<PushSubscription> {
+    if !crate::config::current().push.enabled {
+        bail!("web push is not enabled on this server");
+    }
+    validate_endpoint(endpoint)?;
+    let p256dh = p256dh.trim_end_matches('=');
+    let public = decode_key(p256dh, "p256dh")?;
+    if public.len() != 65 || public[0] != 0x04 {
+        bail!("p256dh must be an uncompressed P-256 public key");
+    }
+    let auth = auth.trim_end_matches('=');
+    if decode_key(auth, "auth")?.len() != 16 {
+        bail!("auth must be 16 bytes");
+    }
+    let now = unix_now();
+    if expires_at.is_some_and(|at| at <= now) {
+        bail!("this subscription has already expired");
+    }
+
+    let existing = push_subscriptions_for(pool, user_did).await?;
+    if existing.len() >= MAX_SUBSCRIPTIONS_PER_USER
+        && !existing.iter().any(|s| s.endpoint == endpoint)
+    {
+        bail!(
+            "accounts can have at most {} push subscriptions",
+            MAX_SUBSCRIPTIONS_PER_USER
+        );
+    }
+    Ok(upsert_push_subscription(pool, user_did, endpoint, p256dh, auth, expires_at, now).await?)
+}
+
+/// Push services are public HTTPS endpoints; anything else is refused so subscriptions
+/// cannot point the server at hosts on its own network.
+fn validate_endpoint(endpoint: &str) -> Result<()> {
+    if endpoint.len() > MAX_ENDPOINT_LEN {
+        bail!("push endpoints are at most {} characters", MAX_ENDPOINT_LEN);
+    }
+    let url = url::Url::parse(endpoint).context("push endpoint is not a valid URL")?;
+    if url.scheme() != "https" {
+        bail!("push endpoints must use https");
+    }
+    match url.host() {
+        Some(url::Host::Domain(host))
+            if host != "localhost" && !host.ends_with(".localhost") && host.contains('.') =>
+        {
+            Ok(())
+        }
+        _ => bail!("push endpoints must name a public host"),
+    }
+}
+
+fn decode_key(value: &str, name: &str) -> Result<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| anyhow!("{} must be base64url", name))
+}
+
+/// Encrypts `plaintext` for a subscription as a single `aes128gcm` record (RFC 8291).
+pub fn encrypt(ua_public: &[u8], auth_secret: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    if plaintext.len() > MAX_PLAINTEXT {
+        bail!(
+            "push payload is {} bytes; at most {} fit",
+            plaintext.len(),
+            MAX_PLAINTEXT
+        );
+    }
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; 16];
+    rng.fill(&mut salt)
+        .map_err(|_| anyhow!("failed to generate salt"))?;
+    let private = EphemeralPrivateKey::generate(&ECDH_P256, &rng)
+        .map_err(|_| anyhow!("failed to generate ECDH key"))?;
+    let as_public = private
+        .compute_public_key()
+        .map_err(|_| anyhow!("failed to compute ECDH public key"))?;
+    let ecdh_secret = agree_ephemeral(
+        private,
+        &UnparsedPublicKey::new(&ECDH_P256, ua_public),
+        |secret| secret.to_vec(),
+    )
+    .map_err(|_| anyhow!("invalid subscription public key"))?;
+
+    seal(
+        &ecdh_secret,
+        auth_secret,
+        ua_public,
+        as_public.as_ref(),
+        &salt,
+        plaintext,
+    )
+}
+
+/// The `aes128gcm` body for one record: header, then ciphertext and tag.
+fn seal(
+    ecdh_secret: &[u8],
+    auth_secret: &[u8],
+    ua_public: &[u8],
+    as_public: &[u8],
+    salt: &[u8; 16],
+    plaintext: &[u8],
+) -> Result<Vec<u8>> {
+    let (cek, nonce) = derive_keys(ecdh_secret, auth_secret, ua_public, as_public, salt);
+    let key = LessSafeKey::new(
+        UnboundKey::new(&AES_128_GCM, &cek).map_err(|_| anyhow!("invalid content key"))?,
+    );
+    // A single record, so it is also the last: delimiter 0x02 and no padding
+    let mut record = plaintext.to_vec();
+    record.push(0x02);
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::empty(),
+        &mut record,
+    )
+    .map_err(|_| anyhow!("failed to encrypt push payload"))?;
+
+    let mut body = Vec::with_capacity(21 + as_public.len() + record.len());
+    body.extend_from_slice(salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.len() as u8);
+    body.extend_from_slice(as_public);
+    body.extend_from_slice(&record);
+    Ok(body)
+}
+
+/// The content encryption key and nonce for the first record.
+fn derive_keys(
+    ecdh_secret: &[u8],
+    auth_secret: &[u8],
+    ua_public: &[u8],
+    as_public: &[u8],
+    salt: &[u8],
+) -> ([u8; 16], [u8; 12]) {
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(ua_public);
+    key_info.extend_from_slice(as_public);
+    let mut ikm = [0u8; 32];
+    expand(
+        &Salt::new(HKDF_SHA256, auth_secret).extract(ecdh_secret),
+        &key_info,
+        &mut ikm,
+    );
+
+    let prk = Salt::new(HKDF_SHA256, salt).extract(&ikm);
+    let mut cek = [0u8; 16];
+    expand(&prk, b"Content-Encoding: aes128gcm\0", &mut cek);
+    let mut nonce = [0u8; 12];
+    expand(&prk, b"Content-Encoding: nonce\0", &mut nonce);
+    (cek, nonce)
+}
+
+struct OutputLen(usize);
+
+impl KeyType for OutputLen {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+fn expand(prk: &Prk, info: &[u8], out: &mut [u8]) {
+    prk.expand(&[info], OutputLen(out.len()))
+        .and_then(|okm| okm.fill(out))
+        .expect("HKDF output fits in one block");
+}
+
+/// The push for a digest. It only counts what is unread; titles stay in the app.
+fn digest_payload(template: &DigestTemplate, digest: &DigestPayload) -> serde_json::Value {
+    let count = digest.notifications.len();
+    json!({
+        "tag": format!("digest-{}", digest.frequency.as_str()),
+        "kind": "digest",
+        "title": render_header(template, digest.frequency, count),
+        "url": null,
+        "count": count,
+        "createdAt": digest.period_end,
+    })
+}
+
+/// What the push service made of one delivery
+enum Delivery {
+    Delivered,
+    Gone,
+    Failed(String),
+}
+
+/// Counts from one worker cycle
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PushReport {
+    pub delivered: usize,
+    pub failed: usize,
+    pub removed: usize,
+}
+
+/// Pushes new notifications and due digests to their recipients' browsers.
+pub struct PushWorker {
+    pool: SqlitePool,
+    key: &'static VapidKey,
+    subject: String,
+    config: PushConfig,
+    template: DigestTemplate,
+    client: reqwest::Client,
+}
+
+impl PushWorker {
+    pub fn new(
+        pool: SqlitePool,
+        key: &'static VapidKey,
+        subject: String,
+        config: PushConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            pool,
+            key,
+            subject,
+            config,
+            template: DigestTemplate::default(),
+            // Redirects are not followed: they could lead to hosts `validate_endpoint`
+            // refuses
+            client: reqwest::Client::builder()
+                .timeout(DELIVERY_TIMEOUT)
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .context("failed to create HTTP client")?,
+        })
+    }
+
+    /// A worker for the server key, which is generated here on first start.
+    pub fn from_config(pool: SqlitePool, config: &Config) -> Result<Self> {
+        let subject = config
+            .push
+            .subject
+            .clone()
+            .unwrap_or_else(|| config.server.public_base_url.clone());
+        Self::new(pool, vapid_key()?, subject, config.push.clone())
+    }
+
+    pub async fn run(self, shutdown: CancellationToken) -> Result<()> {
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs(self.config.poll_interval_secs.max(1)));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut digests =
+            tokio::time::interval(Duration::from_secs(self.config.digest_interval_secs.max(1)));
+        digests.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = ticker.tick() => {
+                    // A read-only standby leaves delivery to the instance holding the lease.
+                    if crate::coordination::is_read_only() {
+                        continue;
+                    }
+                    match self.deliver_pending(unix_now()).await {
+                        Ok(report) if report != PushReport::default() => {
+                            tracing::debug!(?report, "web push cycle finished");
+                        }
+                        Ok(_) => {}
+                        Err(e) => tracing::warn!("web push delivery failed: {:#}", e),
+                    }
+                }
+                _ = digests.tick() => {
+                    if crate::coordination::is_read_only() {
+                        continue;
+                    }
+                    match self.deliver_digests(unix_now()).await {
+                        Ok(report) if report != PushReport::default() => {
+                            tracing::debug!(?report, "notification digests pushed");
+                        }
+                        Ok(_) => {}
+                        Err(e) => tracing::warn!("notification digest failed: {:#}", e),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the digests due at `now` and pushes each to its recipient. A digest is
+    /// recorded as sent before it is pushed, so a failed push is not retried.
+    pub async fn deliver_digests(&self, now: i64) -> Result<PushReport> {
+        let mut report = PushReport::default();
+        for digest in run_digest_cycle(&self.pool, &self.template, now).await? {
+            let payload = digest_payload(&self.template, &digest).to_string();
+            self.push_to(&digest.recipient_did, payload.as_bytes(), now, &mut report)
+                .await?;
+        }
+        Ok(report)
+    }
+
+    /// Pushes notifications created within the TTL that have not been handled yet.
+    pub async fn deliver_pending(&self, now: i64) -> Result<PushReport> {
+        let mut report = PushReport::default();
+        let expired = delete_expired_push_subscriptions(&self.pool, now).await?;
+        if expired > 0 {
+            counter!("forge_push_subscriptions_removed_total", "reason" => "expired")
+                .increment(expired);
+            report.removed += expired as usize;
+        }
+
+        let since = now - self.config.ttl_secs as i64;
+        skip_stale_pushes(&self.pool, since, now).await?;
+        for notification in unpushed_notifications(&self.pool, since, BATCH_SIZE).await? {
+            let frequency = get_digest_frequency(&self.pool, &notification.recipient_did).await?;
+            if frequency == DigestFrequency::Immediate {
+                self.push(&notification, now, &mut report).await?;
+            }
+            // Marked one at a time, so a restart repeats at most one notification
+            mark_pushed(&self.pool, std::slice::from_ref(&notification.id), now).await?;
+        }
+        Ok(report)
+    }
+
+    async fn push(
+        &self,
+        notification: &NotificationRecord,
+        now: i64,
+        report: &mut PushReport,
+    ) -> Result<()> {
+        let title: String = notification.title.chars().take(MAX_TITLE_CHARS).collect();
+        let payload = json!({
+            "tag": notification.id,
+            "kind": notification.kind,
+            "title": title,
+            "url": notification.url,
+            "createdAt": notification.created_at,
+        })
+        .to_string();
+        self.push_to(&notification.recipient_did, payload.as_bytes(), now, report)
+            .await
+    }
+
+    /// Sends `payload` to every subscription of `recipient`.
+    async fn push_to(
+        &self,
+        recipient: &str,
+        payload: &[u8],
+        now: i64,
+        report: &mut PushReport,
+    ) -> Result<()> {
+        for subscription in push_subscriptions_for(&self.pool, recipient).await? {
+            match self.send(&subscription, payload, now).await {
+                Delivery::Delivered => {
+                    counter!("forge_push_deliveries_total", "outcome" => "delivered").increment(1);
+                    record_push_success(&self.pool, &subscription.id, now).await?;
+                    report.delivered += 1;
+                }
+                Delivery::Gone => {
+                    counter!("forge_push_deliveries_total", "outcome" => "gone").increment(1);
+                    counter!("forge_push_subscriptions_removed_total", "reason" => "gone")
+                        .increment(1);
+                    remove_push_subscription(&self.pool, &subscription.id).await?;
+                    report.removed += 1;
+                }
+                Delivery::Failed(error) => {
+                    counter!("forge_push_deliveries_total", "outcome" => "failed").increment(1);
+                    report.failed += 1;
+                    let failures = record_push_failure(&self.pool, &subscription.id).await?;
+                    tracing::debug!(
+                        subscription = %subscription.id,
+                        failures,
+                        "web push delivery failed: {}",
+                        error
+                    );
+                    if failures >= i64::from(self.config.max_failures.max(1)) {
+                        counter!("forge_push_subscriptions_removed_total", "reason" => "failing")
+                            .increment(1);
+                        remove_push_subscription(&self.pool, &subscription.id).await?;
+                        report.removed += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn send(&self, subscription: &PushSubscription, payload: &[u8], now: i64) -> Delivery {
+        let prepared = decode_key(&subscription.p256dh, "p256dh").and_then(|ua_public| {
+            let auth_secret = decode_key(&subscription.auth, "auth")?;
+            let body = encrypt(&ua_public, &auth_secret, payload)?;
+            let authorization =
+                self.key
+                    .authorization(&subscription.endpoint, &self.subject, now)?;
+            Ok((body, authorization))
+        });
+        let (body, authorization) = match prepared {
+            Ok(prepared) => prepared,
+            Err(e) => return Delivery::Failed(format!("{:#}", e)),
+        };
+
+        let response = self
+            .client
+            .post(&subscription.endpoint)
+            .header("TTL", self.config.ttl_secs.to_string())
+            .header(reqwest::header::CONTENT_ENCODING, "aes128gcm")
+            .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+            .header(reqwest::header::AUTHORIZATION, authorization)
+            .body(body)
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => Delivery::Delivered,
+            Ok(response) if matches!(response.status().as_u16(), 404 | 410) => Delivery::Gone,
+            Ok(response) => {
+                Delivery::Failed(format!("push service answered {}", response.status()))
+            }
+            Err(e) => Delivery::Failed(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::Router;
+    use axum::body::Bytes;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::Redirect;
+    use axum::routing::post;
+    use p256::elliptic_curve::point::AffineCoordinates;
+    use ring::signature::{ECDSA_P256_SHA256_FIXED, UnparsedPublicKey as SignatureKey};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::notifications::db::{insert_notification, set_digest_frequency};
+    use crate::test_helpers::create_test_pool;
+
+    /// A browser's side of a subscription
+    struct Browser {
+        private: EphemeralPrivateKey,
+        public: Vec<u8>,
+        auth: [u8; 16],
+    }
+
+    impl Browser {
+        fn new() -> Self {
+            let private = EphemeralPrivateKey::generate(&ECDH_P256, &SystemRandom::new()).unwrap();
+            let public = private.compute_public_key().unwrap().as_ref().to_vec();
+            Self {
+                private,
+                public,
+                auth: *b"sixteen byte key",
+            }
+        }
+
+        fn decrypt(self, body: &[u8]) -> Vec<u8> {
+            let salt = &body[..16];
+            assert_eq!(
+                u32::from_be_bytes(body[16..20].try_into().unwrap()),
+                RECORD_SIZE
+            );
+            let key_len = body[20] as usize;
+            let as_public = &body[21..21 + key_len];
+            let ecdh_secret = agree_ephemeral(
+                self.private,
+                &UnparsedPublicKey::new(&ECDH_P256, as_public),
+                |secret| secret.to_vec(),
+            )
+            .unwrap();
+            let (cek, nonce) = derive_keys(&ecdh_secret, &self.auth, &self.public, as_public, salt);
+            let key = LessSafeKey::new(UnboundKey::new(&AES_128_GCM, &cek).unwrap());
+            let mut record = body[21 + key_len..].to_vec();
+            let plain = key
+                .open_in_place(
+                    Nonce::assume_unique_for_key(nonce),
+                    Aad::empty(),
+                    &mut record,
+                )
+                .unwrap();
+            assert_eq!(plain.last(), Some(&0x02));
+            plain[..plain.len() - 1].to_vec()
+        }
+    }
+
+    fn test_key() -> &'static VapidKey {
+        let pkcs8 =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+                .unwrap();
+        Box::leak(Box::new(VapidKey::from_pkcs8(pkcs8.as_ref()).unwrap()))
+    }
+
+    #[test]
+    fn payloads_decrypt_with_the_subscription_keys() {
+        let browser = Browser::new();
+        let body = encrypt(&browser.public, &browser.auth, b"When I grow up").unwrap();
+        assert_eq!(browser.decrypt(&body), b"When I grow up");
+
+        assert!(encrypt(&Browser::new().public, b"sixteen byte key", &[b'x'; 4000]).is_err());
+    }
+
+    fn hex(value: &str) -> Vec<u8> {
+        (0..value.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&value[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn encryption_matches_the_rfc_8291_example() {
+        let as_private = p256::SecretKey::from_slice(&hex(
+            "c9f58f89813e9f8e872e71f42aa64e1757c9254dcc62b72ddc010bb4043ea11c",
+        ))
+        .unwrap();
+        let as_public = hex(
+            "04fe33f4ab0dea71914db55823f73b54948f41306d920732dbb9a59a53286482\
+             200e597a7b7bc260ba1c227998580992e93973002f3012a28ae8f06bbb78e5ec0f",
+        );
+        let ua_public = hex(
+            "042571b2becdfde360551aaf1ed0f4cd366c11cebe555f89bcb7b186a5333917\
+             3168ece2ebe018597bd30479b86e3c8f8eced577ca59187e9246990db682008b0e",
+        );
+        let ecdh_secret = (p256::PublicKey::from_sec1_bytes(&ua_public)
+            .unwrap()
+            .to_projective()
+            * *as_private.to_nonzero_scalar())
+        .to_affine()
+        .x();
+        let salt: [u8; 16] = hex("0c6bfaadad67958803092d454676f397").try_into().unwrap();
+
+        let body = seal(
+            &ecdh_secret,
+            &hex("05305932a1c7eabe13b6cec9fda48882"),
+            &ua_public,
+            &as_public,
+            &salt,
+            b"When I grow up, I want to be a watermelon",
+        )
+        .unwrap();
+        let expected = hex(
+            "0c6bfaadad67958803092d454676f397000010004104fe33f4ab0dea71914db558\
+             23f73b54948f41306d920732dbb9a59a53286482200e597a7b7bc260ba1c2279985\
+             80992e93973002f3012a28ae8f06bbb78e5ec0ff297de5b429bba7153d3a4ae0caa\
+             091fd425f3b4b5414add8ab37a19c1bbb05cf5cb5b2a2e0562d558635641ec52812\
+             c6c8ff42e95ccb86be7cd",
+        );
+        assert_eq!(body, expected);
+    }
+
+    #[test]
+    fn vapid_tokens_are_signed_for_the_endpoint_origin() {
+        let key = test_key();
+        let header = key
+            .authorization(
+                "https://push.example.net/send/abc?x=1",
+                "mailto:ops@example.com",
+                1000,
+            )
+            .unwrap();
+        let (token, public) = header
+            .strip_prefix("vapid t=")
+            .unwrap()
+            .split_once(", k=")
+            .unwrap();
+        assert_eq!(public, key.public_key());
+
+        let (input, signature) = token.rsplit_once('.').unwrap();
+        SignatureKey::new(
+            &ECDSA_P256_SHA256_FIXED,
+            URL_SAFE_NO_PAD.decode(public).unwrap(),
+        )
+        .verify(
+            input.as_bytes(),
+            &URL_SAFE_NO_PAD.decode(signature).unwrap(),
+        )
+        .unwrap();
+        let claims: serde_json::Value = serde_json::from_slice(
+            &URL_SAFE_NO_PAD
+                .decode(input.split_once('.').unwrap().1)
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(claims["aud"], "https://push.example.net");
+        assert_eq!(claims["exp"], 1000 + TOKEN_LIFETIME_SECS);
+        assert_eq!(claims["sub"], "mailto:ops@example.com");
+    }
+
+    #[test]
+    fn endpoints_must_be_public_https_urls() {
+        assert!(validate_endpoint("https://fcm.googleapis.com/fcm/send/abc").is_ok());
+        for endpoint in [
+            "http://fcm.googleapis.com/fcm/send/abc",
+            "https://127.0.0.1/push",
+            "https://[::1]/push",
+            "https://localhost/push",
+            "https://intranet/push",
+            "not a url",
+        ] {
+            assert!(validate_endpoint(endpoint).is_err(), "{endpoint}");
+        }
+    }
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, Bytes)>>>;
+
+    /// A push service that accepts `/ok`, reports `/gone` and `/broken` as such and
+    /// redirects `/moved` to `/ok`.
+    async fn push_service() -> (String, Received) {
+        async fn accept(
+            State(received): State<Received>,
+            headers: HeaderMap,
+            body: Bytes,
+        ) -> StatusCode {
+            received.lock().unwrap().push((headers, body));
+            StatusCode::CREATED
+        }
+
+        let received = Received::default();
+        let app = Router::new()
+            .route("/ok", post(accept))
+            .route("/gone", post(|| async { StatusCode::GONE }))
+            .route("/broken", post(|| async { StatusCode::BAD_GATEWAY }))
+            .route("/moved", post(|| async { Redirect::temporary("/ok") }))
+            .with_state(received.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (base, received)
+    }
+
+    #[tokio::test]
+    async fn notifications_are_pushed_and_dead_subscriptions_dropped() {
+        let pool = create_test_pool().await.unwrap();
+        let (base, received) = push_service().await;
+        let browser = Browser::new();
+        let (p256dh, auth) = (
+            URL_SAFE_NO_PAD.encode(&browser.public),
+            URL_SAFE_NO_PAD.encode(browser.auth),
+        );
+        for path in ["ok", "gone", "broken"] {
+            upsert_push_subscription(
+                &pool,
+                "did:plc:alice",
+                &format!("{}/{}", base, path),
+                &p256dh,
+                &auth,
+                None,
+                10,
+            )
+            .await
+            .unwrap();
+        }
+        // Expired before it was ever used
+        upsert_push_subscription(
+            &pool,
+            "did:plc:bob",
+            &format!("{}/ok?bob", base),
+            &p256dh,
+            &auth,
+            Some(50),
+            10,
+        )
+        .await
+        .unwrap();
+
+        let config = PushConfig {
+            max_failures: 2,
+            ..PushConfig::default()
+        };
+        let worker = PushWorker::new(
+            pool.clone(),
+            test_key(),
+            "mailto:ops@example.com".into(),
+            config,
+        )
+        .unwrap();
+        let now = 1_000_000;
+        insert_notification(
+            &pool,
+            "did:plc:alice",
+            "issue",
+            "Too old",
+            None,
+            now - 2 * 86_400,
+        )
+        .await
+        .unwrap();
+        insert_notification(
+            &pool,
+            "did:plc:alice",
+            "issue",
+            "Issue opened",
+            Some("/i/1"),
+            now,
+        )
+        .await
+        .unwrap();
+
+        let report = worker.deliver_pending(now).await.unwrap();
+        assert_eq!(
+            report,
+            PushReport {
+                delivered: 1,
+                failed: 1,
+                removed: 2
+            }
+        );
+        let (headers, body) = received.lock().unwrap().pop().unwrap();
+        assert_eq!(headers["content-encoding"], "aes128gcm");
+        assert_eq!(headers["ttl"], "86400");
+        assert!(
+            headers["authorization"]
+                .to_str()
+                .unwrap()
+                .starts_with("vapid t=")
+        );
+        let payload: serde_json::Value = serde_json::from_slice(&browser.decrypt(&body)).unwrap();
+        assert_eq!(payload["title"], "Issue opened");
+        assert_eq!(payload["url"], "/i/1");
+
+        // Nothing is pushed twice; the broken endpoint goes after its second failure
+        assert_eq!(
+            worker.deliver_pending(now).await.unwrap(),
+            PushReport::default()
+        );
+        insert_notification(&pool, "did:plc:alice", "issue", "Again", None, now + 1)
+            .await
+            .unwrap();
+        let report = worker.deliver_pending(now + 1).await.unwrap();
+        assert_eq!((report.failed, report.removed), (1, 1));
+        let left = push_subscriptions_for(&pool, "did:plc:alice")
+            .await
+            .unwrap();
+        assert_eq!(left.len(), 1);
+        assert!(left[0].endpoint.ends_with("/ok"));
+        assert_eq!(left[0].last_success_at, Some(now + 1));
+
+        // Digest users are not pushed to
+        set_digest_frequency(&pool, "did:plc:alice", DigestFrequency::Daily)
+            .await
+            .unwrap();
+        insert_notification(&pool, "did:plc:alice", "issue", "Quiet", None, now + 2)
+            .await
+            .unwrap();
+        assert_eq!(worker.deliver_pending(now + 2).await.unwrap().delivered, 0);
+    }
+
+    #[tokio::test]
+    async fn digests_are_pushed_without_titles() {
+        let pool = create_test_pool().await.unwrap();
+        let (base, received) = push_service().await;
+        let browser = Browser::new();
+        upsert_push_subscription(
+            &pool,
+            "did:plc:alice",
+            &format!("{}/ok", base),
+            &URL_SAFE_NO_PAD.encode(&browser.public),
+            &URL_SAFE_NO_PAD.encode(browser.auth),
+            None,
+            10,
+        )
+        .await
+        .unwrap();
+        set_digest_frequency(&pool, "did:plc:alice", DigestFrequency::Daily)
+            .await
+            .unwrap();
+        let worker = PushWorker::new(
+            pool.clone(),
+            test_key(),
+            "mailto:ops@example.com".into(),
+            PushConfig::default(),
+        )
+        .unwrap();
+        let now = 1_000_000;
+        for title in ["Private title", "Another"] {
+            insert_notification(&pool, "did:plc:alice", "issue", title, None, now)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(worker.deliver_pending(now).await.unwrap().delivered, 0);
+        assert_eq!(worker.deliver_digests(now).await.unwrap().delivered, 1);
+        let (_, body) = received.lock().unwrap().pop().unwrap();
+        let payload: serde_json::Value = serde_json::from_slice(&browser.decrypt(&body)).unwrap();
+        assert_eq!(payload["kind"], "digest");
+        assert_eq!(payload["count"], 2);
+        assert_eq!(
+            payload["title"],
+            "You have 2 unread notifications (daily digest)"
+        );
+        assert!(!payload.to_string().contains("Private title"));
+
+        // Not due again until the next day
+        assert_eq!(
+            worker.deliver_digests(now + 60).await.unwrap(),
+            PushReport::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn redirects_are_failed_deliveries() {
+        let pool = create_test_pool().await.unwrap();
+        let (base, received) = push_service().await;
+        let browser = Browser::new();
+        upsert_push_subscription(
+            &pool,
+            "did:plc:alice",
+            &format!("{}/moved", base),
+            &URL_SAFE_NO_PAD.encode(&browser.public),
+            &URL_SAFE_NO_PAD.encode(browser.auth),
+            None,
+            10,
+        )
+        .await
+        .unwrap();
+        let worker = PushWorker::new(
+            pool.clone(),
+            test_key(),
+            "mailto:ops@example.com".into(),
+            PushConfig::default(),
+        )
+        .unwrap();
+        let now = 1_000_000;
+        insert_notification(&pool, "did:plc:alice", "issue", "Moved", None, now)
+            .await
+            .unwrap();
+
+        let report = worker.deliver_pending(now).await.unwrap();
+        assert_eq!((report.delivered, report.failed), (0, 1));
+        assert!(received.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,382 @@
+//! OpenGraph preview images.
+//!
+//! Links to repositories and issues unfurl on social media and in chat with a summary
+//! card served from `/og/{kind}/{path}` (see `api::og_images`). A [`Card`] is laid out as
+//! SVG and rasterised with resvg. Rendered PNGs are kept in `og.cache_dir`, named by the
+//! SHA-256 of that SVG: an unchanged card is rendered once, an edit or a theme change
+//! produces a new file, and the hash doubles as the ETag.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use resvg::{tiny_skia, usvg};
+use sha2::{Digest, Sha256};
+
+use crate::config::{OgConfig, OgTheme};
+
+pub const WIDTH: u32 = 1200;
+pub const HEIGHT: u32 = 630;
+
+const MARGIN: u32 = 80;
+const TITLE_SIZE: u32 = 64;
+const TITLE_LINES: usize = 2;
+const DESCRIPTION_SIZE: u32 = 30;
+const DESCRIPTION_LINES: usize = 3;
+/// Average advance of a glyph relative to the font size. Text is wrapped by character
+/// count, which is close enough for proportional sans-serif fonts.
+const GLYPH_WIDTH: f32 = 0.55;
+
+/// What a card shows. All fields are plain text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Card {
+    /// Line above the title, such as the repository an issue belongs to.
+    pub eyebrow: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// Short label in the accent colour, such as an issue's status.
+    pub badge: Option<String>,
+}
+
+/// A rendered card.
+pub struct Image {
+    pub hash: String,
+    pub png: Vec<u8>,
+}
+
+pub struct OgRenderer {
+    fonts: Arc<usvg::fontdb::Database>,
+    cache_dir: PathBuf,
+    max_cached: usize,
+    font_family: String,
+    site_name: String,
+    theme: OgTheme,
+}
+
+impl OgRenderer {
+    /// Loads the system fonts and `config.font_dirs`, which takes a moment; build one
+    /// renderer at startup.
+    pub fn from_config(config: &OgConfig) -> Self {
+        let mut fonts = usvg::fontdb::Database::new();
+        fonts.load_system_fonts();
+        for dir in &config.font_dirs {
+            fonts.load_fonts_dir(dir);
+        }
+        if fonts.is_empty() {
+            tracing::warn!("no fonts found; preview images will be rendered without text");
+        }
+        Self {
+            fonts: Arc::new(fonts),
+            cache_dir: config.cache_dir.clone(),
+            max_cached: config.max_cached,
+            font_family: config.font_family.clone(),
+            site_name: config.site_name.clone(),
+            theme: config.theme.clone(),
+        }
+    }
+
+    /// Content hash of the image for `card`, without rendering it.
+    pub fn hash(&self, card: &Card) -> String {
+        content_hash(&self.svg(card))
+    }
+
+    /// The image for `card`, from the cache when it was rendered before. A cache that
+    /// cannot be written is logged and skipped.
+    pub async fn render(&self, card: &Card) -> Result<Image> {
+        let svg = self.svg(card);
+        let hash = content_hash(&svg);
+        let path = self.cache_dir.join(format!("{hash}.png"));
+        if let Ok(png) = tokio::fs::read(&path).await {
+            return Ok(Image { hash, png });
+        }
+
+        let fonts = self.fonts.clone();
+        let cache_dir = self.cache_dir.clone();
+        let max_cached = self.max_cached;
+        let png = tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+            let png = rasterize(&svg, fonts)?;
+            if let Err(err) = store(&cache_dir, &path, &png, max_cached) {
+                tracing::warn!("failed to cache preview image: {:#}", err);
+            }
+            Ok(png)
+        })
+        .await
+        .context("preview image rendering panicked")??;
+        Ok(Image { hash, png })
+    }
+
+    fn svg(&self, card: &Card) -> String {
+        let theme = &self.theme;
+        let font = escape(&self.font_family);
+        let x = MARGIN;
+        let mut body = String::new();
+
+        body.push_str(&format!(
+            r#"<text x="{x}" y="120" font-size="32" fill="{}">{}</text>"#,
+            escape(&theme.muted),
+            escape(&truncate(&card.eyebrow, chars_per_line(32)))
+        ));
+
+        let title = wrap(&card.title, chars_per_line(TITLE_SIZE), TITLE_LINES);
+        let mut y = 120 + 40 + TITLE_SIZE;
+        for line in &title {
+            body.push_str(&format!(
+                r#"<text x="{x}" y="{y}" font-size="{TITLE_SIZE}" font-weight="700" fill="{}">{}</text>"#,
+                escape(&theme.foreground),
+                escape(line)
+            ));
+            y += TITLE_SIZE + 16;
+        }
+
+        if let Some(description) = card.description.as_deref() {
+            y += 16;
+            for line in wrap(
+                description,
+                chars_per_line(DESCRIPTION_SIZE),
+                DESCRIPTION_LINES,
+            ) {
+                body.push_str(&format!(
+                    r#"<text x="{x}" y="{y}" font-size="{DESCRIPTION_SIZE}" fill="{}">{}</text>"#,
+                    escape(&theme.muted),
+                    escape(&line)
+                ));
+                y += DESCRIPTION_SIZE + 12;
+            }
+        }
+
+        let footer = HEIGHT - MARGIN + 10;
+        body.push_str(&format!(
+            r#"<text x="{x}" y="{footer}" font-size="28" font-weight="700" fill="{}">{}</text>"#,
+            escape(&theme.foreground),
+            escape(&truncate(&self.site_name, 40))
+        ));
+        if let Some(badge) = card.badge.as_deref() {
+            let label = truncate(badge, 20);
+            let width = (label.chars().count() as f32 * 26.0 * GLYPH_WIDTH) as u32 + 48;
+            let left = WIDTH - MARGIN - width;
+            body.push_str(&format!(
+                r#"<rect x="{left}" y="{}" width="{width}" height="52" rx="26" fill="none" stroke="{accent}" stroke-width="3"/><text x="{}" y="{}" font-size="26" font-weight="700" text-anchor="middle" fill="{accent}">{}</text>"#,
+                footer - 36,
+                left + width / 2,
+                footer - 1,
+                escape(&label),
+                accent = escape(&theme.accent),
+            ));
+        }
+
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}" font-family="{font}"><rect width="{WIDTH}" height="{HEIGHT}" fill="{}"/><rect width="{WIDTH}" height="12" fill="{}"/>{body}</svg>"#,
+            escape(&theme.background),
+            escape(&theme.accent),
+        )
+    }
+}
+
+fn content_hash(svg: &str) -> String {
+    Sha256::digest(svg.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn rasterize(svg: &str, fonts: Arc<usvg::fontdb::Database>) -> Result<Vec<u8>> {
+    let options = usvg::Options {
+        fontdb: fonts,
+        ..usvg::Options::default()
+    };
+    let tree = usvg::Tree::from_str(svg, &options).context("invalid preview image SVG")?;
+    let mut pixmap =
+        tiny_skia::Pixmap::new(WIDTH, HEIGHT).context("failed to allocate preview image")?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap
+        .encode_png()
+        .context("failed to encode preview image")
+}
+
+/// Writes `png` to `path` through a temporary file, then removes the oldest images
+/// beyond `max_cached`.
+fn store(cache_dir: &Path, path: &Path, png: &[u8], max_cached: usize) -> Result<()> {
+    std::fs::create_dir_all(cache_dir)?;
+    let partial = path.with_extension("png.partial");
+    std::fs::write(&partial, png)?;
+    std::fs::rename(&partial, path)?;
+
+    let mut cached = Vec::new();
+    for entry in std::fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        if entry.path().extension().is_some_and(|ext| ext == "png") {
+            cached.push((entry.metadata()?.modified()?, entry.path()));
+        }
+    }
+    if cached.len() > max_cached {
+        cached.sort();
+        for (_, stale) in &cached[..cached.len() - max_cached] {
+            let _ = std::fs::remove_file(stale);
+        }
+    }
+    Ok(())
+}
+
+/// The prose of a Markdown document as one line of text, for card descriptions. Code
+/// blocks, images and raw HTML are left out.
+pub fn plain_text(markdown: &str) -> String {
+    let mut text = String::new();
+    let mut skipping = 0usize;
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::CodeBlock(_) | Tag::Image { .. } | Tag::HtmlBlock) => skipping += 1,
+            Event::End(TagEnd::CodeBlock | TagEnd::Image | TagEnd::HtmlBlock) => {
+                skipping = skipping.saturating_sub(1)
+            }
+            _ if skipping > 0 => {}
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak | Event::End(_) => text.push(' '),
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn chars_per_line(font_size: u32) -> usize {
+    ((WIDTH - 2 * MARGIN) as f32 / (font_size as f32 * GLYPH_WIDTH)) as usize
+}
+
+/// Greedy word wrap into at most `max_lines` lines of `width` characters. Words longer
+/// than a line are split; text that does not fit ends in an ellipsis.
+fn wrap(text: &str, width: usize, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut overflow = false;
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        loop {
+            let used = current.chars().count();
+            let needed = if used == 0 {
+                word.len()
+            } else {
+                used + 1 + word.len()
+            };
+            if needed <= width {
+                if used > 0 {
+                    current.push(' ');
+                }
+                current.extend(word.iter());
+                break;
+            }
+            if used > 0 {
+                lines.push(std::mem::take(&mut current));
+            } else {
+                lines.push(word[..width].iter().collect());
+                word.drain(..width);
+            }
+            if lines.len() == max_lines {
+                overflow = true;
+                break;
+            }
+        }
+        if overflow {
+            break;
+        }
+    }
+    if !overflow && !current.is_empty() {
+        lines.push(current);
+    }
+    if overflow && let Some(last) = lines.last_mut() {
+        *last = truncate(&format!("{last}…"), width);
+    }
+    lines
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut out: String = text.chars().take(width.saturating_sub(1)).collect();
+    out.push('…');
+    out
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Not allowed in XML 1.0 at all.
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renderer(cache_dir: &Path) -> OgRenderer {
+        OgRenderer::from_config(&OgConfig {
+            cache_dir: cache_dir.to_path_buf(),
+            max_cached: 2,
+            ..OgConfig::default()
+        })
+    }
+
+    fn card(title: &str) -> Card {
+        Card {
+            eyebrow: "team/app".to_string(),
+            title: title.to_string(),
+            description: Some("Issue tracker <for> \"everyone\" & more".to_string()),
+            badge: Some("Open".to_string()),
+        }
+    }
+
+    #[test]
+    fn text_is_wrapped_and_cut_to_fit() {
+        assert_eq!(wrap("a bb ccc", 4, 3), vec!["a bb", "ccc"]);
+        assert_eq!(wrap("abcdefghij", 4, 3), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("one two three four", 7, 2), vec!["one two", "three…"]);
+        assert_eq!(wrap("aaaa bbbb cccc", 4, 2), vec!["aaaa", "bbb…"]);
+        assert!(wrap("   ", 10, 2).is_empty());
+        assert_eq!(truncate("forge", 3), "fo…");
+    }
+
+    #[test]
+    fn markdown_is_reduced_to_prose() {
+        let markdown = "# Crash\n\nRuns **fine** with `--safe`.\n\n```sh\nforge serve\n```\n\n![logs](a.png)\n<div>raw</div>\n\n- [docs](https://x)";
+        assert_eq!(plain_text(markdown), "Crash Runs fine with --safe. docs");
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        let renderer = renderer(Path::new("unused"));
+        let svg = renderer.svg(&card("<script>alert('x')</script>\u{1}"));
+        assert!(svg.contains("&lt;script&gt;alert(&apos;x&apos;)&lt;/script&gt;<"));
+        assert!(svg.contains("&lt;for&gt; &quot;everyone&quot; &amp; more"));
+        usvg::Tree::from_str(&svg, &usvg::Options::default()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn images_are_cached_by_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let renderer = renderer(dir.path());
+
+        let first = renderer.render(&card("Crash on startup")).await.unwrap();
+        assert!(first.png.starts_with(b"\x89PNG"));
+        let cached = dir.path().join(format!("{}.png", first.hash));
+        assert_eq!(std::fs::read(&cached).unwrap(), first.png);
+        assert_eq!(renderer.hash(&card("Crash on startup")), first.hash);
+        let again = renderer.render(&card("Crash on startup")).await.unwrap();
+        assert_eq!(again.hash, first.hash);
+
+        let edited = renderer.render(&card("Crash on shutdown")).await.unwrap();
+        assert_ne!(edited.hash, first.hash);
+        renderer.render(&card("Third")).await.unwrap();
+        let count = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(count, 2);
+    }
+}
@@ -0,0 +1,828 @@
+//! Continuous backup of the metadata database to object storage.
+//!
+//! The replicator copies `forge.db`'s write-ahead log to a bucket as it grows, the way
+//! Litestream does. Replicas are organised in generations. A generation starts with a
+//! byte-for-byte snapshot of the database file, taken right after a checkpoint that
+//! emptied the log, followed by segments: runs of whole transactions read from the log.
+//! Restoring a generation writes the snapshot back and replays its segments in order.
+//!
+//! SQLite only moves log frames into the database file during a checkpoint, and it only
+//! starts the log over once a checkpoint has copied every frame. With replication on, the
+//! pool turns automatic checkpoints off and the replicator runs them itself after
+//! shipping, so frames cannot be dropped before they are copied. Each log is numbered
+//! (its index) and recognised by its salts; when the salts change without the replicator
+//! having shipped everything the last checkpoint copied, some frames may be missing, and
+//! a new generation begins. A new generation also starts when the server starts and every
+//! `snapshot_interval_secs`, which bounds how much log a restore has to replay.
+//!
+//! Keys, below `prefix`:
+//!
+//! ```text
+//! generations/<generation>/snapshot.db
+//! generations/<generation>/wal/<index>-<offset>-<shipped at>.wal
+//! ```
+//!
+//! Generation names are the creation time in hexadecimal milliseconds, so they sort in
+//! creation order. Generations that ended before the retention window are deleted, but
+//! the one in use at the start of the window is kept so any point inside it can be
+//! restored. Encrypted databases are shipped as they are on disk and need the same key
+//! to restore.
+//!
+//! `forge_replication_lag_seconds` is how long ago the replica last caught up with
+//! everything committed.
+
+pub mod s3;
+pub mod store;
+pub mod wal;
+
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use metrics::{counter, gauge};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection, SqliteJournalMode};
+use sqlx::{ConnectOptions, Connection};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::sync::CancellationToken;
+
+use crate::config::ReplicationConfig;
+use crate::user::db::unix_now;
+use store::ObjectStore;
+use wal::{HEADER_SIZE, WalHeader};
+
+const SNAPSHOT_NAME: &str = "snapshot.db";
+
+/// How far the current log has been shipped.
+#[derive(Clone, Copy, Debug)]
+struct Position {
+    salt: (u32, u32),
+    frame_size: usize,
+    /// Bytes of the log shipped so far; `0` until the first transaction, whose segment
+    /// includes the log header.
+    offset: usize,
+    checksum: (u32, u32),
+}
+
+#[derive(Debug)]
+struct Generation {
+    name: String,
+    started_at: i64,
+    index: u32,
+    position: Option<Position>,
+    /// Every frame the last checkpoint copied had been shipped, so SQLite starting the
+    /// log over loses nothing.
+    safe_to_restart: bool,
+}
+
+enum Shipped {
+    /// Committed frames now in the log, all shipped.
+    CaughtUp { frames: usize },
+    /// The log started over before all of it was shipped.
+    Gap,
+}
+
+pub struct Replicator {
+    store: Box<dyn ObjectStore>,
+    config: ReplicationConfig,
+    prefix: String,
+    db_path: PathBuf,
+    wal_path: PathBuf,
+    /// Held open for the replicator's lifetime. SQLite checkpoints and deletes the log
+    /// when the last connection closes, which must not happen behind its back.
+    conn: SqliteConnection,
+    generation: Option<Generation>,
+    last_generation_ms: i64,
+    caught_up_at: i64,
+}
+
+impl Replicator {
+    /// Replicates the database at `db_path`, which the pool must have opened with
+    /// automatic checkpoints off.
+    pub async fn open(
+        config: &ReplicationConfig,
+        store: Box<dyn ObjectStore>,
+        db_path: &Path,
+    ) -> Result<Self> {
+        let conn = connect(db_path).await?;
+        let mut wal_path = db_path.as_os_str().to_owned();
+        wal_path.push("-wal");
+        Ok(Self {
+            store,
+            config: config.clone(),
+            prefix: config.prefix.trim_matches('/').to_string(),
+            db_path: db_path.to_path_buf(),
+            wal_path: PathBuf::from(wal_path),
+            conn,
+            generation: None,
+            last_generation_ms: 0,
+            caught_up_at: unix_now(),
+        })
+    }
+
+    pub async fn run(mut self, shutdown: CancellationToken) -> Result<()> {
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs(self.config.sync_interval_secs.max(1)));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = ticker.tick() => {
+                    // Only the instance holding the storage lease writes to the database.
+                    if crate::coordination::is_read_only() {
+                        continue;
+                    }
+                    self.sync_logged(unix_now()).await;
+                }
+            }
+        }
+        // Ship what the last requests committed
+        if !crate::coordination::is_read_only() {
+            self.sync_logged(unix_now()).await;
+        }
+        self.conn.close().await?;
+        Ok(())
+    }
+
+    async fn sync_logged(&mut self, now: i64) {
+        if let Err(e) = self.sync(now).await {
+            counter!("forge_replication_errors_total").increment(1);
+            tracing::warn!("database replication failed: {:#}", e);
+        }
+        gauge!("forge_replication_lag_seconds").set((now - self.caught_up_at).max(0) as f64);
+    }
+
+    /// One replication cycle: start a generation if one is due, ship new transactions
+    /// and checkpoint once the log is long enough.
+    pub async fn sync(&mut self, now: i64) -> Result<()> {
+        let due = match &self.generation {
+            None => Some("start"),
+            Some(generation)
+                if now - generation.started_at >= self.config.snapshot_interval_secs as i64 =>
+            {
+                Some("scheduled")
+            }
+            Some(_) => None,
+        };
+        if let Some(reason) = due
+            && !self.start_generation(now, reason).await?
+            && self.generation.is_none()
+        {
+            return Ok(());
+        }
+
+        let frames = match self.ship(now).await? {
+            Shipped::CaughtUp { frames } => frames,
+            Shipped::Gap => {
+                tracing::warn!("the write-ahead log restarted before it was fully shipped");
+                self.generation = None;
+                if self.start_generation(now, "gap").await? {
+                    self.ship(now).await?;
+                }
+                return Ok(());
+            }
+        };
+        if frames as u64 >= self.config.checkpoint_pages.max(1) {
+            self.checkpoint(now).await?;
+        }
+        Ok(())
+    }
+
+    /// Empties the log and uploads the database file as a new generation. Returns `false`
+    /// when readers kept the log from being emptied.
+    async fn start_generation(&mut self, now: i64, reason: &'static str) -> Result<bool> {
+        let (busy, _, _): (i64, i64, i64) = sqlx::query_as("PRAGMA wal_checkpoint(TRUNCATE)")
+            .fetch_one(&mut self.conn)
+            .await?;
+        if busy != 0 {
+            tracing::debug!("database busy; new replication generation postponed");
+            return Ok(false);
+        }
+        // Only checkpoints write the database file, and only this replicator runs them,
+        // so the file stays as it is while it is read.
+        let snapshot = tokio::fs::read(&self.db_path)
+            .await
+            .with_context(|| format!("failed to read {}", self.db_path.display()))?;
+        let millis = (now * 1000).max(self.last_generation_ms + 1);
+        let name = format!("{:016x}", millis);
+        self.store
+            .put(&snapshot_key(&self.prefix, &name), snapshot)
+            .await
+            .context("failed to upload database snapshot")?;
+        tracing::info!(generation = %name, reason, "started replication generation");
+        counter!("forge_replication_snapshots_total", "reason" => reason).increment(1);
+        self.last_generation_ms = millis;
+        self.generation = Some(Generation {
+            name,
+            started_at: now,
+            index: 0,
+            position: None,
+            safe_to_restart: false,
+        });
+        self.caught_up_at = now;
+
+        if let Err(e) = self.prune(now).await {
+            tracing::warn!("failed to delete expired replication generations: {:#}", e);
+        }
+        Ok(true)
+    }
+
+    /// Uploads the transactions committed since the last call.
+    async fn ship(&mut self, now: i64) -> Result<Shipped> {
+        let Some(generation) = self.generation.as_mut() else {
+            return Ok(Shipped::CaughtUp { frames: 0 });
+        };
+        let mut file = match tokio::fs::File::open(&self.wal_path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.caught_up_at = now;
+                return Ok(Shipped::CaughtUp { frames: 0 });
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut header_bytes = vec![0; HEADER_SIZE];
+        if file.read_exact(&mut header_bytes).await.is_err() {
+            self.caught_up_at = now;
+            return Ok(Shipped::CaughtUp { frames: 0 });
+        }
+        // A log SQLite has only begun to write holds nothing committed yet
+        let Some(header) = WalHeader::parse(&header_bytes) else {
+            self.caught_up_at = now;
+            return Ok(Shipped::CaughtUp { frames: 0 });
+        };
+
+        let position = match generation.position {
+            Some(position) if position.salt == header.salt => position,
+            Some(_) if !generation.safe_to_restart => return Ok(Shipped::Gap),
+            previous => {
+                if previous.is_some() {
+                    generation.index += 1;
+                    generation.safe_to_restart = false;
+                }
+                let position = Position {
+                    salt: header.salt,
+                    frame_size: header.frame_size(),
+                    offset: 0,
+                    checksum: header.checksum,
+                };
+                generation.position = Some(position);
+                position
+            }
+        };
+
+        let start = position.offset.max(HEADER_SIZE);
+        file.seek(SeekFrom::Start(start as u64)).await?;
+        let mut frames = Vec::new();
+        file.read_to_end(&mut frames).await?;
+        let committed = wal::scan_committed(&header, start, position.checksum, &frames);
+        if committed.end > start {
+            let mut segment = Vec::with_capacity(committed.end - position.offset);
+            if position.offset == 0 {
+                segment.extend_from_slice(&header_bytes);
+            }
+            segment.extend_from_slice(&frames[..committed.end - start]);
+            let key = segment_key(
+                &self.prefix,
+                &generation.name,
+                generation.index,
+                position.offset,
+                now,
+            );
+            let bytes = segment.len() as u64;
+            self.store
+                .put(&key, segment)
+                .await
+                .context("failed to upload log segment")?;
+            counter!("forge_replication_shipped_bytes_total").increment(bytes);
+            generation.position = Some(Position {
+                offset: committed.end,
+                checksum: committed.checksum,
+                ..position
+            });
+        }
+        self.caught_up_at = now;
+        Ok(Shipped::CaughtUp {
+            frames: header.frames_in(committed.end),
+        })
+    }
+
+    /// Copies shipped frames into the database file so SQLite can start the log over.
+    async fn checkpoint(&mut self, now: i64) -> Result<()> {
+        let shipped = self.shipped_frames();
+        if let Some(generation) = self.generation.as_mut() {
+            generation.safe_to_restart = false;
+        }
+        let (_, log, _): (i64, i64, i64) = sqlx::query_as("PRAGMA wal_checkpoint(PASSIVE)")
+            .fetch_one(&mut self.conn)
+            .await?;
+        if log < 0 {
+            return Ok(());
+        }
+        let mut safe = shipped >= log as usize;
+        if !safe {
+            // Transactions committed between shipping and the checkpoint; ship them
+            // before the log can start over.
+            if let Shipped::Gap = self.ship(now).await? {
+                return Ok(());
+            }
+            safe = self.shipped_frames() >= log as usize;
+        }
+        if let Some(generation) = self.generation.as_mut() {
+            generation.safe_to_restart = safe;
+        }
+        Ok(())
+    }
+
+    fn shipped_frames(&self) -> usize {
+        self.generation
+            .as_ref()
+            .and_then(|generation| generation.position)
+            .map_or(0, |position| {
+                position.offset.saturating_sub(HEADER_SIZE) / position.frame_size
+            })
+    }
+
+    /// Deletes generations that ended before the retention window.
+    async fn prune(&self, now: i64) -> Result<()> {
+        let generations = list_generations(self.store.as_ref(), &self.prefix).await?;
+        let cutoff = now - self.config.retention_secs as i64;
+        for generation in expired(&generations, cutoff) {
+            for key in self
+                .store
+                .list(&generation_prefix(&self.prefix, &generation.name))
+                .await?
+            {
+                self.store.delete(&key).await?;
+            }
+            tracing::info!(generation = %generation.name, "deleted expired replication generation");
+        }
+        Ok(())
+    }
+}
+
+/// A connection to `db_path` that never checkpoints on its own.
+async fn connect(db_path: &Path) -> Result<SqliteConnection> {
+    let options = crate::encryption::keyed(SqliteConnectOptions::new().filename(db_path))
+        .journal_mode(SqliteJournalMode::Wal)
+        .pragma("wal_autocheckpoint", "0");
+    options
+        .connect()
+        .await
+        .with_context(|| format!("failed to open {}", db_path.display()))
+}
+
+fn generations_prefix(prefix: &str) -> String {
+    format!("{}/generations/", prefix)
+}
+
+fn generation_prefix(prefix: &str, generation: &str) -> String {
+    format!("{}{}/", generations_prefix(prefix), generation)
+}
+
+fn snapshot_key(prefix: &str, generation: &str) -> String {
+    format!("{}{}", generation_prefix(prefix, generation), SNAPSHOT_NAME)
+}
+
+fn segment_key(
+    prefix: &str,
+    generation: &str,
+    index: u32,
+    offset: usize,
+    shipped_at: i64,
+) -> String {
+    format!(
+        "{}wal/{:08x}-{:016x}-{}.wal",
+        generation_prefix(prefix, generation),
+        index,
+        offset,
+        shipped_at
+    )
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Segment {
+    key: String,
+    index: u32,
+    offset: usize,
+    shipped_at: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct GenerationListing {
+    name: String,
+    created_at: i64,
+    has_snapshot: bool,
+    /// In replay order.
+    segments: Vec<Segment>,
+}
+
+async fn list_generations(store: &dyn ObjectStore, prefix: &str) -> Result<Vec<GenerationListing>> {
+    let root = generations_prefix(prefix);
+    let mut generations: Vec<GenerationListing> = Vec::new();
+    for key in store.list(&root).await? {
+        let Some((name, rest)) = key[root.len()..].split_once('/') else {
+            continue;
+        };
+        let Ok(millis) = i64::from_str_radix(name, 16) else {
+            continue;
+        };
+        if generations.last().is_none_or(|last| last.name != name) {
+            generations.push(GenerationListing {
+                name: name.to_string(),
+                created_at: millis / 1000,
+                has_snapshot: false,
+                segments: Vec::new(),
+            });
+        }
+        let generation = generations.last_mut().expect("just pushed");
+        if rest == SNAPSHOT_NAME {
+            generation.has_snapshot = true;
+        } else if let Some(segment) = parse_segment(&key, rest) {
+            generation.segments.push(segment);
+        }
+    }
+    for generation in &mut generations {
+        generation
+            .segments
+            .sort_by_key(|segment| (segment.index, segment.offset));
+    }
+    Ok(generations)
+}
+
+fn parse_segment(key: &str, rest: &str) -> Option<Segment> {
+    let name = rest.strip_prefix("wal/")?.strip_suffix(".wal")?;
+    let mut parts = name.splitn(3, '-');
+    Some(Segment {
+        key: key.to_string(),
+        index: u32::from_str_radix(parts.next()?, 16).ok()?,
+        offset: usize::from_str_radix(parts.next()?, 16).ok()?,
+        shipped_at: parts.next()?.parse().ok()?,
+    })
+}
+
+/// Generations, oldest first, that can go: each one followed by a generation that had
+/// already started at `cutoff`.
+fn expired(generations: &[GenerationListing], cutoff: i64) -> Vec<&GenerationListing> {
+    let restorable: Vec<&GenerationListing> =
+        generations.iter().filter(|g| g.has_snapshot).collect();
+    let Some(keep_from) = restorable.iter().rposition(|g| g.created_at <= cutoff) else {
+        return Vec::new();
+    };
+    let keep = &restorable[keep_from].name;
+    generations
+        .iter()
+        .filter(|generation| generation.name < *keep)
+        .collect()
+}
+
+/// `server restore-database` arguments.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RestoreOptions {
+    /// Restore this generation instead of the latest one
+    pub generation: Option<String>,
+    /// Stop at the last transaction shipped at or before this Unix time
+    pub until: Option<i64>,
+    /// Directory to write `forge.db` to; defaults to `db.path`
+    pub output: Option<PathBuf>,
+}
+
+impl RestoreOptions {
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| anyhow!("{} needs a value", name))
+            };
+            match arg.as_str() {
+                "--generation" => options.generation = Some(value(arg)?),
+                "--until" => {
+                    options.until = Some(
+                        value(arg)?
+                            .parse()
+                            .map_err(|_| anyhow!("--until takes Unix seconds"))?,
+                    )
+                }
+                "--output" => options.output = Some(PathBuf::from(value(arg)?)),
+                other => bail!(
+                    "unexpected argument `{}`; usage: server restore-database \
+                     [--generation <name>] [--until <unix seconds>] [--output <dir>]",
+                    other
+                ),
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RestoreReport {
+    pub path: PathBuf,
+    pub generation: String,
+    pub segments: usize,
+    /// When the last replayed segment was shipped, or when the generation started.
+    pub restored_to: i64,
+}
+
+/// Rebuilds `forge.db` in `dir` from the replica. Refuses to overwrite an existing
+/// database.
+pub async fn restore(
+    store: &dyn ObjectStore,
+    prefix: &str,
+    options: &RestoreOptions,
+    dir: &Path,
+) -> Result<RestoreReport> {
+    let prefix = prefix.trim_matches('/');
+    let target = dir.join(crate::db::FORGE_DB_FILENAME);
+    if target.exists() {
+        bail!(
+            "{} already exists; move it away before restoring",
+            target.display()
+        );
+    }
+
+    let generations = list_generations(store, prefix).await?;
+    let generation = generations
+        .iter()
+        .rev()
+        .filter(|g| g.has_snapshot)
+        .find(|g| match (&options.generation, options.until) {
+            (Some(name), _) => &g.name == name,
+            (None, Some(until)) => g.created_at <= until,
+            (None, None) => true,
+        })
+        .ok_or_else(|| anyhow!("no replicated generation matches"))?;
+
+    let work = dir.join(format!(".restore-{}", generation.name));
+    tokio::fs::create_dir_all(&work).await?;
+    let db_path = work.join(crate::db::FORGE_DB_FILENAME);
+    let snapshot = store.get(&snapshot_key(prefix, &generation.name)).await?;
+    tokio::fs::write(&db_path, snapshot).await?;
+
+    let mut restored_to = generation.created_at;
+    let mut segments = 0;
+    let wanted: Vec<&Segment> = generation
+        .segments
+        .iter()
+        .filter(|segment| {
+            options
+                .until
+                .is_none_or(|until| segment.shipped_at <= until)
+        })
+        .collect();
+    for (expected, log) in wanted.chunk_by(|a, b| a.index == b.index).enumerate() {
+        if log[0].index as usize != expected {
+            bail!(
+                "log {} of generation {} is missing; restore an earlier --until",
+                expected,
+                generation.name
+            );
+        }
+        let mut bytes = Vec::new();
+        for segment in log {
+            if segment.offset != bytes.len() {
+                bail!(
+                    "a segment of log {} of generation {} is missing at offset {}",
+                    segment.index,
+                    generation.name,
+                    bytes.len()
+                );
+            }
+            bytes.extend(store.get(&segment.key).await?);
+            restored_to = segment.shipped_at;
+        }
+        replay(&db_path, &bytes)
+            .await
+            .with_context(|| format!("failed to replay log {}", log[0].index))?;
+        segments += log.len();
+    }
+
+    let mut conn = connect(&db_path).await?;
+    let check: String = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_one(&mut conn)
+        .await?;
+    conn.close().await?;
+    if check != "ok" {
+        bail!(
+            "the restored database failed its integrity check: {}",
+            check
+        );
+    }
+    tokio::fs::rename(&db_path, &target).await?;
+    tokio::fs::remove_dir_all(&work).await?;
+    Ok(RestoreReport {
+        path: target,
+        generation: generation.name.clone(),
+        segments,
+        restored_to,
+    })
+}
+
+/// Applies one whole log to the database at `db_path`.
+async fn replay(db_path: &Path, log: &[u8]) -> Result<()> {
+    let header = WalHeader::parse(log).ok_or_else(|| anyhow!("invalid log header"))?;
+    let expected = header.frames_in(log.len());
+    let sidecar = |suffix: &str| {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
+    };
+    let _ = tokio::fs::remove_file(sidecar("-shm")).await;
+    tokio::fs::write(sidecar("-wal"), log).await?;
+
+    let mut conn = connect(db_path).await?;
+    let (_, log_frames, checkpointed): (i64, i64, i64) =
+        sqlx::query_as("PRAGMA wal_checkpoint(PASSIVE)")
+            .fetch_one(&mut conn)
+            .await?;
+    if log_frames != expected as i64 || checkpointed != log_frames {
+        bail!(
+            "SQLite accepted {} of {} frames and copied {}",
+            log_frames,
+            expected,
+            checkpointed
+        );
+    }
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(&mut conn)
+        .await?;
+    conn.close().await?;
+    let _ = tokio::fs::remove_file(sidecar("-wal")).await;
+    let _ = tokio::fs::remove_file(sidecar("-shm")).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::SqlitePool;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use store::FsStore;
+
+    fn listing(name_secs: i64, has_snapshot: bool) -> GenerationListing {
+        GenerationListing {
+            name: format!("{:016x}", name_secs * 1000),
+            created_at: name_secs,
+            has_snapshot,
+            segments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn the_generation_covering_the_window_start_is_kept() {
+        let generations = vec![
+            listing(100, true),
+            listing(200, true),
+            listing(250, false),
+            listing(300, true),
+            listing(400, true),
+        ];
+        let names = |cutoff| -> Vec<i64> {
+            expired(&generations, cutoff)
+                .iter()
+                .map(|g| g.created_at)
+                .collect()
+        };
+        assert_eq!(names(50), Vec::<i64>::new());
+        assert_eq!(names(150), Vec::<i64>::new());
+        assert_eq!(names(350), [100, 200, 250]);
+        assert_eq!(names(1000), [100, 200, 250, 300]);
+    }
+
+    #[test]
+    fn segment_keys_round_trip() {
+        let key = segment_key("forge", "00000190a1b2c3d4", 2, 4152, 1_700_000_000);
+        let rest = key
+            .strip_prefix(&generation_prefix("forge", "00000190a1b2c3d4"))
+            .unwrap();
+        assert_eq!(
+            parse_segment(&key, rest),
+            Some(Segment {
+                key: key.clone(),
+                index: 2,
+                offset: 4152,
+                shipped_at: 1_700_000_000
+            })
+        );
+        let args: Vec<String> = ["--until", "1700000000", "--output", "/tmp/r"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let options = RestoreOptions::from_args(&args).unwrap();
+        assert_eq!(options.until, Some(1_700_000_000));
+        assert_eq!(options.output, Some(PathBuf::from("/tmp/r")));
+        assert!(RestoreOptions::from_args(&["--until".to_string()]).is_err());
+    }
+
+    async fn open_pool(path: &Path) -> SqlitePool {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .pragma("wal_autocheckpoint", "0");
+        SqlitePoolOptions::new()
+            .max_connections(2)
+            .connect_with(options)
+            .await
+            .unwrap()
+    }
+
+    async fn insert(pool: &SqlitePool, from: i64, to: i64) {
+        for n in from..to {
+            sqlx::query("INSERT INTO items (n, body) VALUES (?, ?)")
+                .bind(n)
+                .bind("x".repeat(600))
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn count(path: &Path) -> i64 {
+        let pool = open_pool(path).await;
+        let n = sqlx::query_scalar("SELECT COUNT(*) FROM items")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+        n
+    }
+
+    #[tokio::test]
+    async fn replicas_restore_to_the_latest_and_to_earlier_points() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("forge.db");
+        let pool = open_pool(&db_path).await;
+        sqlx::query("CREATE TABLE items (n INTEGER PRIMARY KEY, body TEXT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        insert(&pool, 0, 10).await;
+
+        let config = ReplicationConfig {
+            checkpoint_pages: 8,
+            ..Default::default()
+        };
+        let bucket = tmp.path().join("bucket");
+        let mut replicator =
+            Replicator::open(&config, Box::new(FsStore::new(bucket.clone())), &db_path)
+                .await
+                .unwrap();
+        replicator.sync(1_000).await.unwrap();
+
+        // Enough writes to checkpoint and start the log over several times
+        insert(&pool, 10, 40).await;
+        replicator.sync(1_010).await.unwrap();
+        insert(&pool, 40, 80).await;
+        replicator.sync(1_020).await.unwrap();
+        insert(&pool, 80, 90).await;
+        replicator.sync(1_030).await.unwrap();
+        let generation = replicator.generation.as_ref().unwrap();
+        assert!(generation.index > 0, "the log never started over");
+        assert_eq!(generation.name, format!("{:016x}", 1_000_000));
+
+        let store = FsStore::new(bucket);
+        let latest = tmp.path().join("latest");
+        let report = restore(&store, "forge", &RestoreOptions::default(), &latest)
+            .await
+            .unwrap();
+        assert_eq!(report.restored_to, 1_030);
+        assert_eq!(count(&report.path).await, 90);
+
+        let earlier = tmp.path().join("earlier");
+        let options = RestoreOptions {
+            until: Some(1_015),
+            ..Default::default()
+        };
+        let report = restore(&store, "forge", &options, &earlier).await.unwrap();
+        assert_eq!(report.restored_to, 1_010);
+        assert_eq!(count(&report.path).await, 40);
+        assert!(restore(&store, "forge", &options, &earlier).await.is_err());
+
+        // Scheduled snapshots start new generations; the first one expires once the
+        // second covers the whole retention window
+        let later = 1_000 + config.snapshot_interval_secs as i64;
+        insert(&pool, 90, 95).await;
+        replicator.sync(later).await.unwrap();
+        assert_eq!(list_generations(&store, "forge").await.unwrap().len(), 2);
+        replicator
+            .sync(later + config.retention_secs as i64)
+            .await
+            .unwrap();
+        let generations = list_generations(&store, "forge").await.unwrap();
+        assert_eq!(generations.len(), 2);
+        assert_eq!(generations[0].created_at, later);
+        let restored = restore(
+            &store,
+            "forge",
+            &RestoreOptions::default(),
+            &tmp.path().join("new"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(count(&restored.path).await, 95);
+        pool.close().await;
+    }
+}
@@ -0,0 +1,166 @@
+//! Where replicas are kept: an S3-compatible bucket or, for tests and single-host setups,
+//! a directory.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+
+use super::s3::S3Store;
+use crate::config::ReplicationConfig;
+
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    /// Writes `body` to `key`, replacing what was there.
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<()>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Deleting a missing key is not an error.
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Every key starting with `prefix`, sorted.
+    async fn list(&self, prefix: &str) -> Result<Vec<String>>;
+}
+
+/// The store `config.endpoint` names.
+pub fn open(config: &ReplicationConfig) -> Result<Box<dyn ObjectStore>> {
+    let endpoint = url::Url::parse(&config.endpoint)
+        .map_err(|e| anyhow!("invalid replication endpoint `{}`: {}", config.endpoint, e))?;
+    match endpoint.scheme() {
+        "file" => {
+            let root = endpoint
+                .to_file_path()
+                .map_err(|_| anyhow!("`{}` is not a local directory", config.endpoint))?;
+            Ok(Box::new(FsStore::new(root.join(&config.bucket))))
+        }
+        "http" | "https" => {
+            if config.bucket.is_empty() {
+                bail!("replication needs a bucket");
+            }
+            let credentials = match &config.credentials_env {
+                Some(name) => std::env::var(name).with_context(|| {
+                    format!("replication credentials variable {} is not set", name)
+                })?,
+                None => bail!("replication to {} needs credentials_env", config.endpoint),
+            };
+            let (access_key, secret_key) = credentials
+                .split_once(':')
+                .ok_or_else(|| anyhow!("replication credentials must be `access_key_id:secret`"))?;
+            Ok(Box::new(S3Store::new(
+                endpoint,
+                &config.bucket,
+                &config.region,
+                access_key,
+                secret_key,
+            )?))
+        }
+        other => bail!("unsupported replication endpoint scheme `{}`", other),
+    }
+}
+
+/// Objects as files below `root`; `/` in keys becomes a directory.
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        if key
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+        {
+            bail!("invalid object key `{}`", key);
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl ObjectStore for FsStore {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Readers never see a half-written object
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, body).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let path = self.path(key)?;
+        tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let root = self.root.clone();
+        let prefix = prefix.to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut keys = Vec::new();
+            collect_keys(&root, &root, &mut keys)?;
+            keys.retain(|key| key.starts_with(&prefix));
+            keys.sort();
+            Ok(keys)
+        })
+        .await?
+    }
+}
+
+fn collect_keys(root: &Path, dir: &Path, keys: &mut Vec<String>) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_keys(root, &path, keys)?;
+        } else if path.extension().is_none_or(|ext| ext != "partial") {
+            let relative = path.strip_prefix(root)?;
+            let parts: Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+            keys.push(parts.join("/"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn directory_store_round_trips_objects() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = FsStore::new(tmp.path().join("bucket"));
+        assert!(store.list("").await.unwrap().is_empty());
+
+        store.put("a/b/one", b"1".to_vec()).await.unwrap();
+        store.put("a/two", b"2".to_vec()).await.unwrap();
+        store.put("a/two", b"22".to_vec()).await.unwrap();
+        assert_eq!(store.get("a/two").await.unwrap(), b"22");
+        assert_eq!(store.list("a/b").await.unwrap(), ["a/b/one"]);
+        assert_eq!(store.list("").await.unwrap(), ["a/b/one", "a/two"]);
+
+        store.delete("a/two").await.unwrap();
+        store.delete("a/two").await.unwrap();
+        assert!(store.get("a/two").await.is_err());
+        assert!(store.put("../escape", Vec::new()).await.is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! Reading committed frames out of a SQLite write-ahead log.
+//!
+//! The layout is the one in <https://www.sqlite.org/fileformat2.html#walformat>: a 32-byte
+//! header, then frames of a 24-byte frame header and one page. A frame belongs to the
+//! current log only when its salts match the header and its checksum continues the chain
+//! that starts at the header, which is also how a frame that is still being written is
+//! told apart from a finished one. A frame with a non-zero "database size" field ends a
+//! transaction; only whole transactions are shipped.
+
+pub const HEADER_SIZE: usize = 32;
+pub const FRAME_HEADER_SIZE: usize = 24;
+
+const MAGIC_LITTLE_ENDIAN: u32 = 0x377f_0682;
+const MAGIC_BIG_ENDIAN: u32 = 0x377f_0683;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WalHeader {
+    pub page_size: u32,
+    /// Changes every time SQLite starts the log over.
+    pub salt: (u32, u32),
+    pub checksum: (u32, u32),
+    big_endian: bool,
+}
+
+impl WalHeader {
+    /// `None` for anything but a complete header with a valid checksum, such as a log
+    /// SQLite has only just created.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..HEADER_SIZE)?;
+        let big_endian = match be_u32(bytes, 0) {
+            MAGIC_LITTLE_ENDIAN => false,
+            MAGIC_BIG_ENDIAN => true,
+            _ => return None,
+        };
+        let page_size = be_u32(bytes, 8);
+        let checksum = (be_u32(bytes, 24), be_u32(bytes, 28));
+        if page_size < 512 || !page_size.is_power_of_two() {
+            return None;
+        }
+        if checksum_words(big_endian, &bytes[..24], (0, 0)) != checksum {
+            return None;
+        }
+        Some(Self {
+            page_size,
+            salt: (be_u32(bytes, 16), be_u32(bytes, 20)),
+            checksum,
+            big_endian,
+        })
+    }
+
+    pub fn frame_size(&self) -> usize {
+        FRAME_HEADER_SIZE + self.page_size as usize
+    }
+
+    /// Number of frames in the first `len` bytes of the log.
+    pub fn frames_in(&self, len: usize) -> usize {
+        len.saturating_sub(HEADER_SIZE) / self.frame_size()
+    }
+}
+
+/// How far the committed part of the log reaches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Committed {
+    /// Offset just past the last commit frame.
+    pub end: usize,
+    /// Running checksum at `end`, to continue the scan from there later.
+    pub checksum: (u32, u32),
+}
+
+/// Scans `frames`, the bytes of the log from `start` onwards, where `checksum` is the
+/// running checksum at `start`. Stops at the first frame that is not part of the current
+/// log.
+pub fn scan_committed(
+    header: &WalHeader,
+    start: usize,
+    checksum: (u32, u32),
+    frames: &[u8],
+) -> Committed {
+    let mut committed = Committed {
+        end: start,
+        checksum,
+    };
+    let mut running = checksum;
+    for (index, frame) in frames.chunks_exact(header.frame_size()).enumerate() {
+        if (be_u32(frame, 8), be_u32(frame, 12)) != header.salt {
+            break;
+        }
+        running = checksum_words(header.big_endian, &frame[..8], running);
+        running = checksum_words(header.big_endian, &frame[FRAME_HEADER_SIZE..], running);
+        if (be_u32(frame, 16), be_u32(frame, 20)) != running {
+            break;
+        }
+        if be_u32(frame, 4) != 0 {
+            committed = Committed {
+                end: start + (index + 1) * header.frame_size(),
+                checksum: running,
+            };
+        }
+    }
+    committed
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// SQLite's WAL checksum over 32-bit words in the log's byte order.
+fn checksum_words(big_endian: bool, data: &[u8], (mut s1, mut s2): (u32, u32)) -> (u32, u32) {
+    let word = |bytes: &[u8]| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+    for pair in data.chunks_exact(8) {
+        s1 = s1.wrapping_add(word(&pair[..4])).wrapping_add(s2);
+        s2 = s2.wrapping_add(word(&pair[4..])).wrapping_add(s1);
+    }
+    (s1, s2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A log of `commits`, each transaction listed by the page numbers it writes.
+    fn build_wal(page_size: u32, salt: (u32, u32), commits: &[&[u32]]) -> Vec<u8> {
+        let mut wal = Vec::new();
+        wal.extend(MAGIC_BIG_ENDIAN.to_be_bytes());
+        wal.extend(3_007_000u32.to_be_bytes());
+        wal.extend(page_size.to_be_bytes());
+        wal.extend(0u32.to_be_bytes());
+        wal.extend(salt.0.to_be_bytes());
+        wal.extend(salt.1.to_be_bytes());
+        let mut running = checksum_words(true, &wal, (0, 0));
+        wal.extend(running.0.to_be_bytes());
+        wal.extend(running.1.to_be_bytes());
+
+        for pages in commits {
+            for (i, page) in pages.iter().enumerate() {
+                let commit: u32 = if i + 1 == pages.len() { 10 } else { 0 };
+                let mut frame = Vec::new();
+                frame.extend(page.to_be_bytes());
+                frame.extend(commit.to_be_bytes());
+                frame.extend(salt.0.to_be_bytes());
+                frame.extend(salt.1.to_be_bytes());
+                let data = vec![*page as u8; page_size as usize];
+                running = checksum_words(true, &frame[..8], running);
+                running = checksum_words(true, &data, running);
+                frame.extend(running.0.to_be_bytes());
+                frame.extend(running.1.to_be_bytes());
+                frame.extend(data);
+                wal.extend(frame);
+            }
+        }
+        wal
+    }
+
+    #[test]
+    fn only_whole_transactions_count_as_committed() {
+        let wal = build_wal(512, (7, 9), &[&[1, 2], &[3]]);
+        let header = WalHeader::parse(&wal).unwrap();
+        assert_eq!(header.salt, (7, 9));
+        let frame = header.frame_size();
+
+        let all = scan_committed(&header, HEADER_SIZE, header.checksum, &wal[HEADER_SIZE..]);
+        assert_eq!(all.end, wal.len());
+
+        // The second transaction's commit frame has not been written yet
+        let partial = &wal[HEADER_SIZE..HEADER_SIZE + 2 * frame + 100];
+        let first = scan_committed(&header, HEADER_SIZE, header.checksum, partial);
+        assert_eq!(first.end, HEADER_SIZE + 2 * frame);
+
+        // Continuing from there finds the rest
+        let rest = scan_committed(&header, first.end, first.checksum, &wal[first.end..]);
+        assert_eq!(rest, all);
+    }
+
+    #[test]
+    fn frames_of_an_older_log_are_ignored() {
+        let old = build_wal(512, (1, 1), &[&[1], &[2], &[3]]);
+        let mut wal = build_wal(512, (2, 5), &[&[4]]);
+        let header = WalHeader::parse(&wal).unwrap();
+        // A restarted log overwrites from the start and leaves old frames behind
+        wal.extend_from_slice(&old[wal.len()..]);
+        let committed = scan_committed(&header, HEADER_SIZE, header.checksum, &wal[HEADER_SIZE..]);
+        assert_eq!(committed.end, HEADER_SIZE + header.frame_size());
+
+        let mut torn = build_wal(512, (2, 5), &[&[4]]);
+        let last = torn.len() - 1;
+        torn[last] ^= 0xff;
+        let committed = scan_committed(&header, HEADER_SIZE, header.checksum, &torn[HEADER_SIZE..]);
+        assert_eq!(committed.end, HEADER_SIZE);
+
+        assert!(WalHeader::parse(&wal[..20]).is_none());
+        assert!(WalHeader::parse(&[0; HEADER_SIZE]).is_none());
+    }
+}
@@ -0,0 +1,434 @@
+//! Server-side cherry-pick and revert.
+//!
+//! Both operations are three-way tree merges done in the object database, so no worktree
+//! is checked out: a cherry-pick merges the commit's changes (parent → commit) onto the
+//! branch tip, a revert merges their inverse (commit → parent). The resulting commit goes
+//! through the repository's push policy like any pushed commit, and the branch is moved
+//! with a compare-and-swap so a concurrent push is never overwritten.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, anyhow, bail};
+use git_http::policy::{PolicyViolation, RefUpdate, check_push};
+use tokio::process::Command;
+use tokio::task;
+
+use super::merge::{MergeConflict, unresolved_conflicts};
+use super::policy::load_policy;
+use super::resolver::PathResolver;
+use super::storage::RepositoryStorage;
+use crate::auth::User;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommitOperation {
+    CherryPick,
+    Revert,
+}
+
+impl CommitOperation {
+    /// Activity and event kind recorded when the operation moves a branch.
+    pub fn event_kind(&self) -> &'static str {
+        match self {
+            CommitOperation::CherryPick => "repository.commit_cherry_picked",
+            CommitOperation::Revert => "repository.commit_reverted",
+        }
+    }
+
+    fn verb(&self) -> &'static str {
+        match self {
+            CommitOperation::CherryPick => "cherry-picked",
+            CommitOperation::Revert => "reverted",
+        }
+    }
+}
+
+/// What a cherry-pick or revert did. `commit_oid` is set only when the branch moved.
+#[derive(Clone, Debug)]
+pub struct CommitOperationOutcome {
+    pub operation: CommitOperation,
+    pub branch: String,
+    pub source_oid: String,
+    /// Tip of the branch before the operation
+    pub previous_oid: String,
+    pub commit_oid: Option<String>,
+    pub conflicts: Vec<MergeConflict>,
+    pub violations: Vec<PolicyViolation>,
+}
+
+impl CommitOperationOutcome {
+    pub fn applied(&self) -> bool {
+        self.commit_oid.is_some()
+    }
+
+    /// One-line description for the activity feed.
+    pub fn summary(&self, slug: &str) -> String {
+        format!(
+            "{} {} on {}:{}",
+            self.operation.verb(),
+            short_oid(&self.source_oid),
+            slug,
+            self.branch
+        )
+    }
+}
+
+/// Cherry-picks or reverts `oid` on `branch`. Returns `None` when the repository does not exist.
+///
+/// Conflicts and policy violations are reported in the outcome rather than as errors;
+/// the branch is left where it was in both cases.
+pub async fn apply_commit_operation(
+    resolver: &PathResolver,
+    storage: &RepositoryStorage,
+    path: &str,
+    branch: &str,
+    oid: &str,
+    operation: CommitOperation,
+    committer: &User,
+) -> anyhow::Result<Option<CommitOperationOutcome>> {
+    let Some(resolved) = resolver.resolve_repository(path).await? else {
+        return Ok(None);
+    };
+    if resolved.record.remote_url.is_some() {
+        bail!("linked repositories are read-only mirrors of their remote");
+    }
+    let repository_path = resolved.local_dir(storage)?;
+
+    let prepared = {
+        let repository_path = repository_path.clone();
+        let branch = branch.to_string();
+        let oid = oid.to_string();
+        let committer = signature(committer);
+        task::spawn_blocking(move || {
+            prepare_commit(repository_path, &branch, &oid, operation, committer)
+        })
+        .await
+        .map_err(|err| anyhow!(err))??
+    };
+
+    let mut outcome = CommitOperationOutcome {
+        operation,
+        branch: branch.to_string(),
+        source_oid: prepared.source_oid,
+        previous_oid: prepared.previous_oid,
+        commit_oid: None,
+        conflicts: prepared.conflicts,
+        violations: Vec::new(),
+    };
+    let Some(commit_oid) = prepared.commit_oid else {
+        return Ok(Some(outcome));
+    };
+
+    let message = format!("{}: {}", operation.verb(), short_oid(&outcome.source_oid));
+    outcome.violations = move_branch(
+        resolver,
+        &resolved.record.id,
+        &repository_path,
+        branch,
+        &outcome.previous_oid,
+        &commit_oid,
+        &message,
+    )
+    .await?;
+    if outcome.violations.is_empty() {
+        outcome.commit_oid = Some(commit_oid);
+    }
+    Ok(Some(outcome))
+}
+
+/// Moves `branch` from `old` to the prepared commit `new` if the repository's push policy
+/// allows it. Returns the violations, which leave the branch where it was.
+pub(super) async fn move_branch(
+    resolver: &PathResolver,
+    repository_id: &str,
+    repository_path: &Path,
+    branch: &str,
+    old: &str,
+    new: &str,
+    message: &str,
+) -> anyhow::Result<Vec<PolicyViolation>> {
+    let ref_name = format!("refs/heads/{}", branch);
+    let rules = load_policy(resolver.pool(), repository_id).await?;
+    let report = check_push(
+        repository_path,
+        &rules,
+        &[RefUpdate {
+            ref_name: ref_name.clone(),
+            old: old.to_string(),
+            new: new.to_string(),
+        }],
+    )
+    .await?;
+    if !report.allowed() {
+        return Ok(report.violations);
+    }
+    update_ref(repository_path, &ref_name, new, old, message).await?;
+    Ok(Vec::new())
+}
+
+pub(super) fn signature(user: &User) -> gix::actor::Signature {
+    gix::actor::Signature {
+        name: user.display_name.as_deref().unwrap_or(&user.handle).into(),
+        // Accounts have no email address; the handle identifies them the same way.
+        email: user.handle.as_str().into(),
+        time: gix::date::Time::now_local_or_utc(),
+    }
+}
+
+struct PreparedCommit {
+    source_oid: String,
+    previous_oid: String,
+    /// Written but not yet referenced; `None` when the merge conflicted
+    commit_oid: Option<String>,
+    conflicts: Vec<MergeConflict>,
+}
+
+fn prepare_commit(
+    repository_path: PathBuf,
+    branch: &str,
+    oid: &str,
+    operation: CommitOperation,
+    committer: gix::actor::Signature,
+) -> anyhow::Result<PreparedCommit> {
+    use gix::merge::tree::TreatAsUnresolved;
+
+    let repo = gix::open(&repository_path)?;
+    let tip = repo
+        .rev_parse_single(format!("refs/heads/{}", branch).as_str())
+        .map_err(|_| anyhow!("branch `{}` not found", branch))?
+        .object()?
+        .peel_to_commit()
+        .map_err(|_| anyhow!("branch `{}` does not point at a commit", branch))?;
+    let source = repo
+        .rev_parse_single(oid)
+        .map_err(|err| anyhow!("revision `{}` not found: {}", oid, err))?
+        .object()?
+        .peel_to_commit()
+        .map_err(|_| anyhow!("revision `{}` is not a commit", oid))?;
+    let source_oid = source.id.to_string();
+
+    let parents: Vec<gix::ObjectId> = source.parent_ids().map(|id| id.detach()).collect();
+    let parent = match parents.as_slice() {
+        [parent] => *parent,
+        [] => bail!(
+            "{} is a root commit and has no changes to apply",
+            source_oid
+        ),
+        _ => bail!(
+            "{} is a merge commit; only single-parent commits are supported",
+            source_oid
+        ),
+    };
+    let parent_tree = repo.find_commit(parent)?.tree_id()?.detach();
+    let source_tree = source.tree_id()?.detach();
+    let tip_tree = tip.tree_id()?.detach();
+
+    let (ancestor, theirs) = match operation {
+        CommitOperation::CherryPick => (parent_tree, source_tree),
+        CommitOperation::Revert => (source_tree, parent_tree),
+    };
+    let short = short_oid(&source_oid).to_string();
+    let labels = gix::merge::blob::builtin_driver::text::Labels {
+        ancestor: None,
+        current: Some(branch.into()),
+        other: Some(short.as_str().into()),
+    };
+    let mut merge = repo
+        .merge_trees(
+            ancestor,
+            tip_tree,
+            theirs,
+            labels,
+            repo.tree_merge_options()?,
+        )
+        .with_context(|| format!("cannot merge {} onto {}", source_oid, branch))?;
+
+    if merge.has_unresolved_conflicts(TreatAsUnresolved::git()) {
+        return Ok(PreparedCommit {
+            source_oid,
+            previous_oid: tip.id.to_string(),
+            commit_oid: None,
+            conflicts: unresolved_conflicts(&merge.conflicts),
+        });
+    }
+
+    let tree = merge.tree.write()?.detach();
+    if tree == tip_tree {
+        match operation {
+            CommitOperation::CherryPick => bail!("{} is already applied to {}", source_oid, branch),
+            CommitOperation::Revert => bail!("{} is not applied to {}", source_oid, branch),
+        }
+    }
+
+    let original = source.message_raw()?.to_string();
+    let (author, message) = match operation {
+        CommitOperation::CherryPick => (
+            source.author()?.to_owned()?,
+            cherry_pick_message(&original, &source_oid),
+        ),
+        CommitOperation::Revert => (committer.clone(), revert_message(&original, &source_oid)),
+    };
+    let commit = gix::objs::Commit {
+        tree,
+        parents: vec![tip.id].into(),
+        author,
+        committer,
+        encoding: None,
+        message: message.into(),
+        extra_headers: Vec::new(),
+    };
+    let commit_oid = repo.write_object(&commit)?.detach();
+
+    Ok(PreparedCommit {
+        source_oid,
+        previous_oid: tip.id.to_string(),
+        commit_oid: Some(commit_oid.to_string()),
+        conflicts: Vec::new(),
+    })
+}
+
+/// The original message with the same trailer `git cherry-pick -x` adds.
+fn cherry_pick_message(original: &str, source_oid: &str) -> String {
+    format!(
+        "{}\n\n(cherry picked from commit {})\n",
+        original.trim_end(),
+        source_oid
+    )
+}
+
+/// The message `git revert` writes.
+fn revert_message(original: &str, source_oid: &str) -> String {
+    let subject = original.lines().next().unwrap_or_default().trim();
+    format!(
+        "Revert \"{}\"\n\nThis reverts commit {}.\n",
+        subject, source_oid
+    )
+}
+
+pub(super) fn short_oid(oid: &str) -> &str {
+    &oid[..oid.len().min(7)]
+}
+
+/// Moves `ref_name` from `old` to `new`, failing if someone else moved it in between.
+async fn update_ref(
+    dir: &Path,
+    ref_name: &str,
+    new: &str,
+    old: &str,
+    message: &str,
+) -> anyhow::Result<()> {
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(dir)
+        .args(["update-ref", "-m", message, ref_name, new, old])
+        .output()
+        .await
+        .context("failed to spawn git update-ref")?;
+    if !output.status.success() {
+        bail!(
+            "{} moved while the commit was prepared, try again: {}",
+            ref_name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{commit_file, git};
+    use tempfile::TempDir;
+
+    fn committer() -> gix::actor::Signature {
+        signature(&User {
+            did: "did:plc:alice".to_string(),
+            handle: "alice.test".to_string(),
+            display_name: None,
+            avatar: None,
+        })
+    }
+
+    #[test]
+    fn messages_match_git() {
+        assert_eq!(
+            cherry_pick_message("Fix parser\n\nDetails.\n", "abc123"),
+            "Fix parser\n\nDetails.\n\n(cherry picked from commit abc123)\n"
+        );
+        assert_eq!(
+            revert_message("Fix parser\n\nDetails.\n", "abc123"),
+            "Revert \"Fix parser\"\n\nThis reverts commit abc123.\n"
+        );
+    }
+
+    #[test]
+    fn picks_reverts_and_reports_conflicts() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path();
+        git(dir, &["init", "-q", "-b", "main"]);
+        commit_file(dir, "a.txt", "one\n", "Add a");
+        git(dir, &["checkout", "-q", "-b", "topic"]);
+        let added_b = commit_file(dir, "b.txt", "bee\n", "Add b");
+        let changed_a = commit_file(dir, "a.txt", "uno\n", "Translate a");
+        git(dir, &["checkout", "-q", "main"]);
+        commit_file(dir, "a.txt", "eins\n", "Translate a differently");
+
+        let picked = prepare_commit(
+            dir.to_path_buf(),
+            "main",
+            &added_b,
+            CommitOperation::CherryPick,
+            committer(),
+        )
+        .unwrap();
+        assert!(picked.conflicts.is_empty());
+        let picked_oid = picked.commit_oid.unwrap();
+        assert_eq!(git(dir, &["show", &format!("{}:b.txt", picked_oid)]), "bee");
+        assert_eq!(
+            git(dir, &["log", "-1", "--format=%an", &picked_oid]),
+            "Tess"
+        );
+        // Preparing does not move the branch
+        assert_eq!(git(dir, &["rev-parse", "main"]), picked.previous_oid);
+
+        let conflicting = prepare_commit(
+            dir.to_path_buf(),
+            "main",
+            &changed_a,
+            CommitOperation::CherryPick,
+            committer(),
+        )
+        .unwrap();
+        assert!(conflicting.commit_oid.is_none());
+        assert_eq!(conflicting.conflicts.len(), 1);
+        assert_eq!(conflicting.conflicts[0].path, "a.txt");
+
+        let reverted = prepare_commit(
+            dir.to_path_buf(),
+            "topic",
+            &added_b,
+            CommitOperation::Revert,
+            committer(),
+        )
+        .unwrap();
+        let reverted_oid = reverted.commit_oid.unwrap();
+        assert_eq!(
+            git(dir, &["ls-tree", "--name-only", &reverted_oid]),
+            "a.txt"
+        );
+        assert_eq!(
+            git(dir, &["log", "-1", "--format=%an <%ae>", &reverted_oid]),
+            "alice.test <alice.test>"
+        );
+
+        assert!(
+            prepare_commit(
+                dir.to_path_buf(),
+                "topic",
+                &added_b,
+                CommitOperation::CherryPick,
+                committer()
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,883 @@
+//! Background cloning of linked remote repositories.
+//!
+//! `linkRemoteRepository` records the repository and enqueues a `remote-clone` job; the
+//! worker then fetches with the `git` CLI in stages: a depth-1 snapshot, progressively
+//! deeper history, and finally `--unshallow`. Every completed stage stays on disk, so a
+//! retry after a dropped connection negotiates from what already arrived instead of
+//! starting over. The CLI is used (rather than gix) for its machine-readable progress,
+//! which feeds the `cloneStatus` field.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, anyhow};
+use async_trait::async_trait;
+use git_http::GitPriority;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+
+use super::closing::{BranchUpdate, default_branch, enqueue_issue_closing};
+use super::contributor_stats::enqueue_contributor_stats;
+use super::discovery::record_push;
+use super::git::{git, run_git};
+use super::storage::{RepositoryStorage, git_scheduler};
+use super::symbols::enqueue_symbol_index;
+use super::visibility::can_view_repository;
+use super::watch::notify_new_tags;
+use crate::events::{Event, publish};
+use crate::jobs::{JobHandler, JobQueue, JobRecord, PermanentFailure};
+use crate::search::code::enqueue_code_index;
+use crate::user::db::unix_now;
+
+pub const JOB_KIND: &str = "remote-clone";
+
+/// Deepen rounds before fetching the rest of history in one go.
+const MAX_DEEPEN_ROUNDS: u32 = 4;
+const PROGRESS_WRITE_INTERVAL: Duration = Duration::from_secs(1);
+
+pub const STATE_PENDING: &str = "pending";
+pub const STATE_CLONING: &str = "cloning";
+pub const STATE_READY: &str = "ready";
+pub const STATE_FAILED: &str = "failed";
+
+#[derive(Clone, Debug)]
+pub struct CloneLimits {
+    /// Wall-clock limit for one attempt; on expiry the attempt is retried later.
+    pub max_duration: Duration,
+    /// Total transfer budget per attempt; exceeding it fails the clone permanently.
+    pub max_bytes: Option<u64>,
+    /// Average transfer rate cap, enforced by pausing the fetch process.
+    pub max_bytes_per_sec: Option<u64>,
+    pub max_attempts: i64,
+    /// Commits added per deepen round (doubles each round).
+    pub deepen_step: u32,
+}
+
+impl Default for CloneLimits {
+    fn default() -> Self {
+        Self {
+            max_duration: Duration::from_secs(30 * 60),
+            max_bytes: None,
+            max_bytes_per_sec: None,
+            max_attempts: 5,
+            deepen_step: 64,
+        }
+    }
+}
+
+impl CloneLimits {
+    /// Limits from the `clone` config section; zero byte limits mean unlimited.
+    pub fn from_config(config: &crate::config::CloneConfig) -> Self {
+        let nonzero = |value: u64| (value > 0).then_some(value);
+        Self {
+            max_duration: Duration::from_secs(config.timeout_secs),
+            max_bytes: nonzero(config.max_bytes),
+            max_bytes_per_sec: nonzero(config.max_bytes_per_sec),
+            max_attempts: config.max_attempts,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CloneJobPayload {
+    pub repository_id: String,
+    pub url: String,
+    pub segments: Vec<String>,
+    /// Refresh of a repository that already finished its initial clone.
+    #[serde(default)]
+    pub sync: bool,
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct RemoteCloneRecord {
+    pub repository_id: String,
+    pub job_id: String,
+    pub state: String,
+    pub objects_received: i64,
+    pub objects_total: i64,
+    pub bytes_received: i64,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub updated_at: i64,
+}
+
+impl RemoteCloneRecord {
+    /// Objects received in the current fetch stage, as a percentage.
+    pub fn progress_percent(&self) -> i64 {
+        if self.state == STATE_READY {
+            100
+        } else if self.objects_total > 0 {
+            (self.objects_received * 100 / self.objects_total).clamp(0, 100)
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FetchProgress {
+    pub objects_received: u64,
+    pub objects_total: u64,
+    pub bytes_received: u64,
+}
+
+/// Parses a `Receiving objects:  45% (450/1000), 1.20 MiB | 600.00 KiB/s` progress line.
+pub fn parse_progress_line(line: &str) -> Option<FetchProgress> {
+    let rest = line.trim().strip_prefix("Receiving objects:")?;
+    let open = rest.find('(')?;
+    let close = rest[open..].find(')')? + open;
+    let (received, total) = rest[open + 1..close].split_once('/')?;
+    let bytes = rest[close + 1..]
+        .trim_start_matches(',')
+        .split('|')
+        .next()
+        .and_then(parse_size)
+        .unwrap_or(0);
+    Some(FetchProgress {
+        objects_received: received.trim().parse().ok()?,
+        objects_total: total.trim().parse().ok()?,
+        bytes_received: bytes,
+    })
+}
+
+fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim().trim_end_matches(", done.");
+    let (number, unit) = text.split_once(' ')?;
+    let value: f64 = number.parse().ok()?;
+    let multiplier = match unit.trim() {
+        "bytes" | "byte" => 1.0,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((value * multiplier) as u64)
+}
+
+pub async fn fetch_remote_clone(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> Result<Option<RemoteCloneRecord>, sqlx::Error> {
+    sqlx::query_as::<_, RemoteCloneRecord>(
+        "SELECT repository_id, job_id, state, objects_received, objects_total, bytes_received, \
+         attempts, last_error, updated_at FROM remote_clones WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Queues the initial clone of a freshly linked remote repository.
+pub async fn enqueue_remote_clone(
+    pool: &SqlitePool,
+    payload: CloneJobPayload,
+    max_attempts: i64,
+) -> anyhow::Result<()> {
+    let job_id = JobQueue::new(pool.clone())
+        .enqueue(JOB_KIND, &payload, max_attempts)
+        .await?;
+    sqlx::query(
+        "INSERT INTO remote_clones (repository_id, job_id, state, updated_at) VALUES (?, ?, ?, ?) \
+         ON CONFLICT(repository_id) DO UPDATE SET job_id = excluded.job_id, state = excluded.state, \
+         objects_received = 0, objects_total = 0, bytes_received = 0, attempts = 0, last_error = NULL, \
+         updated_at = excluded.updated_at",
+    )
+    .bind(&payload.repository_id)
+    .bind(&job_id)
+    .bind(STATE_PENDING)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn set_clone_state(
+    pool: &SqlitePool,
+    repository_id: &str,
+    state: &str,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE remote_clones SET state = ?, last_error = ?, updated_at = ? WHERE repository_id = ?")
+        .bind(state)
+        .bind(error)
+        .bind(unix_now())
+        .bind(repository_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+async fn record_progress(
+    pool: &SqlitePool,
+    repository_id: &str,
+    progress: FetchProgress,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE remote_clones SET objects_received = ?, objects_total = ?, bytes_received = ?, updated_at = ? \
+         WHERE repository_id = ?",
+    )
+    .bind(progress.objects_received as i64)
+    .bind(progress.objects_total as i64)
+    .bind(progress.bytes_received as i64)
+    .bind(unix_now())
+    .bind(repository_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Queues a refresh of every linked remote that finished its initial clone.
+///
+/// Repositories whose clone job is still queued or running are skipped.
+pub async fn enqueue_remote_syncs(pool: &SqlitePool, max_attempts: i64) -> anyhow::Result<usize> {
+    enqueue_syncs(pool, None, max_attempts).await
+}
+
+/// Queues a refresh of one linked remote, e.g. after an upstream push webhook.
+///
+/// Returns `false` when the repository is not a ready remote or a job is already pending.
+pub async fn enqueue_remote_sync(
+    pool: &SqlitePool,
+    repository_id: &str,
+    max_attempts: i64,
+) -> anyhow::Result<bool> {
+    Ok(enqueue_syncs(pool, Some(repository_id), max_attempts).await? > 0)
+}
+
+async fn enqueue_syncs(
+    pool: &SqlitePool,
+    repository_id: Option<&str>,
+    max_attempts: i64,
+) -> anyhow::Result<usize> {
+    let rows: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT r.id, r.slug, r.remote_url FROM remote_clones c \
+         JOIN repositories r ON r.id = c.repository_id \
+         LEFT JOIN jobs j ON j.id = c.job_id \
+         WHERE c.state = ? AND r.remote_url IS NOT NULL \
+           AND (? IS NULL OR r.id = ?) \
+           AND (j.id IS NULL OR j.status NOT IN ('queued', 'running'))",
+    )
+    .bind(STATE_READY)
+    .bind(repository_id)
+    .bind(repository_id)
+    .fetch_all(pool)
+    .await?;
+
+    let queue = JobQueue::new(pool.clone());
+    for (repository_id, slug, url) in &rows {
+        let payload = CloneJobPayload {
+            repository_id: repository_id.clone(),
+            url: url.clone(),
+            segments: vec![slug.clone()],
+            sync: true,
+        };
+        let job_id = queue.enqueue(JOB_KIND, &payload, max_attempts).await?;
+        sqlx::query("UPDATE remote_clones SET job_id = ?, updated_at = ? WHERE repository_id = ?")
+            .bind(&job_id)
+            .bind(unix_now())
+            .bind(repository_id)
+            .execute(pool)
+            .await?;
+    }
+    Ok(rows.len())
+}
+
+/// Job handler for [`JOB_KIND`].
+pub struct RemoteCloneJob {
+    pool: SqlitePool,
+    storage: Arc<RepositoryStorage>,
+    limits: CloneLimits,
+}
+
+impl RemoteCloneJob {
+    pub fn new(pool: SqlitePool, storage: Arc<RepositoryStorage>, limits: CloneLimits) -> Self {
+        Self {
+            pool,
+            storage,
+            limits,
+        }
+    }
+
+    async fn clone_into(&self, payload: &CloneJobPayload, dir: &Path) -> anyhow::Result<()> {
+        // Imports and mirror syncs have their own budget, so they never hold the slots
+        // of clients waiting on a fetch or push.
+        let _permit = git_scheduler().wait(GitPriority::Sync).await;
+        prepare_repository(dir, &payload.url).await?;
+
+        let mut transfer = Transfer {
+            pool: &self.pool,
+            repository_id: &payload.repository_id,
+            limits: &self.limits,
+            deadline: Instant::now() + self.limits.max_duration,
+            started: Instant::now(),
+            bytes_before_stage: 0,
+        };
+
+        let mut rounds = 0;
+        let mut step = self.limits.deepen_step.max(1);
+        let mut stages = 0;
+        loop {
+            let args: Vec<String> = if !has_refs(dir).await? {
+                if stages > 0 {
+                    // the remote has no branches or tags yet
+                    break;
+                }
+                vec!["--depth=1".to_string()]
+            } else if dir.join("shallow").exists() {
+                if rounds < MAX_DEEPEN_ROUNDS {
+                    rounds += 1;
+                    let depth = step;
+                    step = step.saturating_mul(2);
+                    vec![format!("--deepen={depth}")]
+                } else {
+                    vec!["--unshallow".to_string()]
+                }
+            } else if stages == 0 {
+                // a previous attempt finished the transfer; just bring refs up to date
+                Vec::new()
+            } else {
+                break;
+            };
+            transfer.fetch(dir, &args).await?;
+            stages += 1;
+        }
+
+        set_default_branch(dir).await;
+        // Only public repositories are exported; one made private since the last sync
+        // stops being served over HTTP.
+        let marker = dir.join("git-daemon-export-ok");
+        if can_view_repository(&self.pool, None, &payload.repository_id).await? {
+            tokio::fs::write(&marker, b"").await?;
+        } else if tokio::fs::try_exists(&marker).await? {
+            tokio::fs::remove_file(&marker).await?;
+        }
+        Ok(())
+    }
+
+    /// Exports the refs a sync moved as a `repository.pushed` event.
+    async fn export_push(
+        &self,
+        payload: &CloneJobPayload,
+        changes: &[RefChange],
+    ) -> anyhow::Result<()> {
+        publish(
+            &self.pool,
+            crate::config::current().event_sink.as_ref(),
+            Event {
+                kind: "repository.pushed",
+                source: "core",
+                actor_did: None,
+                repository_id: Some(&payload.repository_id),
+                data: serde_json::json!({
+                    "via": "mirror",
+                    "remoteUrl": payload.url,
+                    "refs": changes,
+                }),
+            },
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl JobHandler for RemoteCloneJob {
+    async fn run(&self, job: &JobRecord) -> anyhow::Result<()> {
+        let payload: CloneJobPayload = job.payload()?;
+        sqlx::query(
+            "UPDATE remote_clones SET state = ?, attempts = ?, updated_at = ? WHERE repository_id = ?",
+        )
+        .bind(STATE_CLONING)
+        .bind(job.attempts)
+        .bind(unix_now())
+        .bind(&payload.repository_id)
+        .execute(&self.pool)
+        .await?;
+
+        let dir = self.storage.repository_path(&payload.segments);
+        let refs_before = if payload.sync {
+            list_refs(&dir).await
+        } else {
+            Default::default()
+        };
+        match self.clone_into(&payload, &dir).await {
+            Ok(()) => {
+                set_clone_state(&self.pool, &payload.repository_id, STATE_READY, None).await?;
+                if !payload.sync {
+                    record_push(&self.pool, &payload.repository_id, unix_now()).await?;
+                    if let Err(e) = enqueue_symbol_index(&self.pool, &payload.repository_id).await {
+                        tracing::warn!(repository = %payload.repository_id, "symbol indexing was not queued: {}", e);
+                    }
+                    if let Err(e) =
+                        enqueue_contributor_stats(&self.pool, &payload.repository_id).await
+                    {
+                        tracing::warn!(repository = %payload.repository_id, "contributor stats were not queued: {}", e);
+                    }
+                    if let Err(e) = enqueue_code_index(&self.pool, &payload.repository_id).await {
+                        tracing::warn!(repository = %payload.repository_id, "code indexing was not queued: {}", e);
+                    }
+                }
+                tracing::info!(repository = %payload.repository_id, url = %payload.url, "remote sync complete");
+
+                // Only syncs notify; tags seen during the initial clone are not news.
+                if payload.sync {
+                    let changes = ref_changes(&refs_before, &list_refs(&dir).await);
+                    if !changes.is_empty()
+                        && let Err(e) =
+                            record_push(&self.pool, &payload.repository_id, unix_now()).await
+                    {
+                        tracing::warn!(repository = %payload.repository_id, "push time was not recorded: {}", e);
+                    }
+                    let default_ref = default_branch(&dir)
+                        .await
+                        .map(|branch| format!("refs/heads/{}", branch));
+                    if changes
+                        .iter()
+                        .any(|change| Some(&change.name) == default_ref.as_ref())
+                    {
+                        if let Err(e) =
+                            enqueue_symbol_index(&self.pool, &payload.repository_id).await
+                        {
+                            tracing::warn!(repository = %payload.repository_id, "symbol indexing was not queued: {}", e);
+                        }
+                        if let Err(e) =
+                            enqueue_contributor_stats(&self.pool, &payload.repository_id).await
+                        {
+                            tracing::warn!(repository = %payload.repository_id, "contributor stats were not queued: {}", e);
+                        }
+                        if let Err(e) = enqueue_code_index(&self.pool, &payload.repository_id).await
+                        {
+                            tracing::warn!(repository = %payload.repository_id, "code indexing was not queued: {}", e);
+                        }
+                    }
+                    let new_tags: Vec<String> = changes
+                        .iter()
+                        .filter(|change| change.before.is_none())
+                        .filter_map(|change| change.name.strip_prefix("refs/tags/"))
+                        .map(str::to_string)
+                        .collect();
+                    let slug = payload.segments.join("/");
+                    if let Err(e) =
+                        notify_new_tags(&self.pool, &payload.repository_id, &slug, &new_tags).await
+                    {
+                        tracing::warn!(repository = %payload.repository_id, "tag notifications failed: {}", e);
+                    }
+                    // A retry would see no changes, so a failure here cannot be made up for.
+                    if !changes.is_empty()
+                        && let Err(e) = self.export_push(&payload, &changes).await
+                    {
+                        tracing::warn!(repository = %payload.repository_id, "push event export failed: {}", e);
+                    }
+                    let updates: Vec<BranchUpdate> = changes
+                        .iter()
+                        .filter_map(|change| {
+                            Some(BranchUpdate {
+                                ref_name: change.name.clone(),
+                                before: change.before.clone(),
+                                after: change.after.clone()?,
+                            })
+                        })
+                        .collect();
+                    if let Err(e) = enqueue_issue_closing(
+                        &self.pool,
+                        &payload.repository_id,
+                        &payload.segments,
+                        updates,
+                        self.limits.max_attempts,
+                    )
+                    .await
+                    {
+                        tracing::warn!(repository = %payload.repository_id, "issue closing was not queued: {}", e);
+                    }
+                }
+                Ok(())
+            }
+            Err(err) => {
+                // Partial data stays on disk for the next attempt; only the status changes.
+                let message = format!("{err:#}");
+                set_clone_state(
+                    &self.pool,
+                    &payload.repository_id,
+                    STATE_PENDING,
+                    Some(&message),
+                )
+                .await?;
+                Err(err)
+            }
+        }
+    }
+
+    async fn exhausted(&self, job: &JobRecord, error: &str) {
+        if let Ok(payload) = job.payload::<CloneJobPayload>() {
+            let _ = set_clone_state(
+                &self.pool,
+                &payload.repository_id,
+                STATE_FAILED,
+                Some(error),
+            )
+            .await;
+        }
+    }
+}
+
+struct Transfer<'a> {
+    pool: &'a SqlitePool,
+    repository_id: &'a str,
+    limits: &'a CloneLimits,
+    deadline: Instant,
+    started: Instant,
+    /// Bytes transferred by earlier stages of this attempt.
+    bytes_before_stage: u64,
+}
+
+impl Transfer<'_> {
+    async fn fetch(&mut self, dir: &Path, stage_args: &[String]) -> anyhow::Result<()> {
+        let mut child = git(dir)
+            .args([
+                "-c",
+                "http.lowSpeedLimit=1000",
+                "-c",
+                "http.lowSpeedTime=60",
+            ])
+            .args(["fetch", "--progress", "--prune", "--no-write-fetch-head"])
+            .args(stage_args)
+            .arg("origin")
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("failed to spawn git fetch")?;
+        let mut stderr = child
+            .stderr
+            .take()
+            .context("git fetch stderr unavailable")?;
+
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let mut messages = Vec::new();
+        let mut last_write: Option<Instant> = None;
+        let mut stage_bytes = 0;
+        loop {
+            let read = tokio::select! {
+                read = stderr.read(&mut chunk) => read?,
+                _ = tokio::time::sleep_until(self.deadline.into()) => {
+                    let _ = child.kill().await;
+                    return Err(anyhow!("clone exceeded {}s time limit", self.limits.max_duration.as_secs()));
+                }
+            };
+            if read == 0 {
+                break;
+            }
+            buffer.extend_from_slice(&chunk[..read]);
+
+            // git separates progress updates with `\r` and messages with `\n`
+            while let Some(pos) = buffer.iter().position(|b| *b == b'\r' || *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line).trim().to_string();
+                let Some(progress) = parse_progress_line(&line) else {
+                    if !line.is_empty() {
+                        messages.push(line);
+                    }
+                    continue;
+                };
+                stage_bytes = progress.bytes_received;
+                let total_bytes = self.bytes_before_stage + stage_bytes;
+
+                if let Some(max) = self.limits.max_bytes
+                    && total_bytes > max
+                {
+                    let _ = child.kill().await;
+                    return Err(anyhow::Error::new(PermanentFailure(format!(
+                        "clone exceeded the {max} byte transfer limit"
+                    ))));
+                }
+                if last_write.is_none_or(|at| at.elapsed() >= PROGRESS_WRITE_INTERVAL) {
+                    last_write = Some(Instant::now());
+                    let _ = record_progress(
+                        self.pool,
+                        self.repository_id,
+                        FetchProgress {
+                            bytes_received: total_bytes,
+                            ..progress
+                        },
+                    )
+                    .await;
+                }
+                if let Some(rate) = self.limits.max_bytes_per_sec {
+                    self.throttle(&child, total_bytes, rate).await;
+                }
+            }
+        }
+
+        let status = child.wait().await?;
+        self.bytes_before_stage += stage_bytes;
+        if !status.success() {
+            let detail = messages
+                .iter()
+                .rev()
+                .find(|m| m.starts_with("fatal:") || m.starts_with("error:"))
+                .cloned()
+                .unwrap_or_else(|| format!("git fetch exited with {status}"));
+            return Err(anyhow!(detail));
+        }
+        Ok(())
+    }
+
+    /// Pauses the fetch process until the average rate drops back under `rate`.
+    /// TCP backpressure then slows the sender down as well.
+    async fn throttle(&self, child: &tokio::process::Child, total_bytes: u64, rate: u64) {
+        let expected = Duration::from_secs_f64(total_bytes as f64 / rate.max(1) as f64);
+        let elapsed = self.started.elapsed();
+        if expected <= elapsed {
+            return;
+        }
+        let pause = (expected - elapsed).min(Duration::from_secs(5));
+        #[cfg(unix)]
+        {
+            if let Some(pid) = child.id() {
+                // SAFETY: signalling our own child process; SIGCONT always follows.
+                unsafe { libc::kill(pid as libc::pid_t, libc::SIGSTOP) };
+                tokio::time::sleep(pause).await;
+                unsafe { libc::kill(pid as libc::pid_t, libc::SIGCONT) };
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = child;
+            tokio::time::sleep(pause).await;
+        }
+    }
+}
+
+/// Creates the bare repository on first use; later attempts reuse whatever it holds.
+async fn prepare_repository(dir: &Path, url: &str) -> anyhow::Result<()> {
+    if !dir.join("HEAD").exists() {
+        tokio::fs::create_dir_all(dir).await?;
+        let output = Command::new("git")
+            .args(["init", "--bare", "--quiet"])
+            .arg(dir)
+            .output()
+            .await
+            .context("failed to spawn git init")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "git init failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        run_git(dir, &["remote", "add", "origin", url]).await?;
+    } else {
+        run_git(dir, &["remote", "set-url", "origin", url]).await?;
+    }
+    run_git(
+        dir,
+        &[
+            "config",
+            "--replace-all",
+            "remote.origin.fetch",
+            "+refs/heads/*:refs/heads/*",
+        ],
+    )
+    .await?;
+    run_git(
+        dir,
+        &[
+            "config",
+            "--add",
+            "remote.origin.fetch",
+            "+refs/tags/*:refs/tags/*",
+        ],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Tag names currently in the repository; empty when it does not exist yet.
+/// Ref name to object id.
+async fn list_refs(dir: &Path) -> BTreeMap<String, String> {
+    if !dir.join("HEAD").exists() {
+        return Default::default();
+    }
+    run_git(dir, &["for-each-ref", "--format=%(refname) %(objectname)"])
+        .await
+        .map(|out| {
+            out.lines()
+                .filter_map(|line| line.split_once(' '))
+                .map(|(name, oid)| (name.to_string(), oid.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A ref moved by a sync; `before` is `None` for new refs and `after` for deleted ones.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+struct RefChange {
+    name: String,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+fn ref_changes(
+    before: &BTreeMap<String, String>,
+    after: &BTreeMap<String, String>,
+) -> Vec<RefChange> {
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    names
+        .into_iter()
+        .filter(|name| before.get(*name) != after.get(*name))
+        .map(|name| RefChange {
+            name: name.clone(),
+            before: before.get(name).cloned(),
+            after: after.get(name).cloned(),
+        })
+        .collect()
+}
+
+async fn has_refs(dir: &Path) -> anyhow::Result<bool> {
+    Ok(!run_git(dir, &["for-each-ref", "--count=1"])
+        .await?
+        .trim()
+        .is_empty())
+}
+
+/// Points `HEAD` at the remote's default branch (best effort).
+async fn set_default_branch(dir: &Path) {
+    let Ok(listing) = run_git(dir, &["ls-remote", "--symref", "origin", "HEAD"]).await else {
+        return;
+    };
+    let target = listing.lines().find_map(|line| {
+        line.strip_prefix("ref: ")
+            .and_then(|rest| rest.split_whitespace().next())
+            .map(str::to_string)
+    });
+    if let Some(target) = target
+        && let Err(e) = run_git(dir, &["symbolic-ref", "HEAD", &target]).await
+    {
+        tracing::debug!("failed to set HEAD for {}: {}", dir.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_git_progress_lines() {
+        let progress =
+            parse_progress_line("Receiving objects:  45% (450/1000), 1.50 MiB | 600.00 KiB/s")
+                .unwrap();
+        assert_eq!(progress.objects_received, 450);
+        assert_eq!(progress.objects_total, 1000);
+        assert_eq!(progress.bytes_received, 1_572_864);
+
+        let done =
+            parse_progress_line("Receiving objects: 100% (12/12), 512 bytes | 512.00 KiB/s, done.")
+                .unwrap();
+        assert_eq!(done.bytes_received, 512);
+        assert!(parse_progress_line("Resolving deltas: 100% (3/3), done.").is_none());
+    }
+
+    #[test]
+    fn ref_changes_cover_new_moved_and_deleted_refs() {
+        let refs = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(name, oid)| (name.to_string(), oid.to_string()))
+                .collect()
+        };
+        let before = refs(&[
+            ("refs/heads/main", "a1"),
+            ("refs/heads/old", "b1"),
+            ("refs/tags/v1", "c1"),
+        ]);
+        let after = refs(&[
+            ("refs/heads/main", "a2"),
+            ("refs/tags/v1", "c1"),
+            ("refs/tags/v2", "d1"),
+        ]);
+
+        let changes = ref_changes(&before, &after);
+        let summary: Vec<(&str, Option<&str>, Option<&str>)> = changes
+            .iter()
+            .map(|c| (c.name.as_str(), c.before.as_deref(), c.after.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("refs/heads/main", Some("a1"), Some("a2")),
+                ("refs/heads/old", Some("b1"), None),
+                ("refs/tags/v2", None, Some("d1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn progress_percent_uses_objects() {
+        let record = RemoteCloneRecord {
+            repository_id: "r".into(),
+            job_id: "j".into(),
+            state: STATE_CLONING.into(),
+            objects_received: 25,
+            objects_total: 200,
+            bytes_received: 0,
+            attempts: 1,
+            last_error: None,
+            updated_at: 0,
+        };
+        assert_eq!(record.progress_percent(), 12);
+        let ready = RemoteCloneRecord {
+            state: STATE_READY.into(),
+            ..record
+        };
+        assert_eq!(ready.progress_percent(), 100);
+    }
+
+    #[tokio::test]
+    async fn syncs_skip_repositories_with_queued_jobs() {
+        let pool = crate::test_helpers::create_test_pool().await.unwrap();
+        sqlx::query("INSERT INTO repositories (id, slug, remote_url) VALUES ('r1', 'serde', 'https://example.com/serde.git')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let payload = CloneJobPayload {
+            repository_id: "r1".into(),
+            url: "https://example.com/serde.git".into(),
+            segments: vec!["serde".into()],
+            sync: false,
+        };
+        enqueue_remote_clone(&pool, payload, 3).await.unwrap();
+
+        // still cloning: nothing to sync
+        assert_eq!(enqueue_remote_syncs(&pool, 3).await.unwrap(), 0);
+
+        let job_id = fetch_remote_clone(&pool, "r1")
+            .await
+            .unwrap()
+            .unwrap()
+            .job_id;
+        sqlx::query("UPDATE jobs SET status = 'succeeded' WHERE id = ?")
+            .bind(&job_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        set_clone_state(&pool, "r1", STATE_READY, None)
+            .await
+            .unwrap();
+        assert_eq!(enqueue_remote_syncs(&pool, 3).await.unwrap(), 1);
+        // the sync job is queued now, so the next tick leaves it alone
+        assert_eq!(enqueue_remote_syncs(&pool, 3).await.unwrap(), 0);
+        assert_eq!(
+            fetch_remote_clone(&pool, "r1")
+                .await
+                .unwrap()
+                .unwrap()
+                .state,
+            STATE_READY
+        );
+    }
+}
@@ -0,0 +1,496 @@
+//! Closing issues from commit messages.
+//!
+//! When a push moves branches, an `issue-closing` job reads the messages of the new
+//! commits for a closing keyword (`close`, `fix` or `resolve`, in any tense) followed by
+//! an issue reference: `fixes #12`, `Closes: group/repo#7`. References are found with the
+//! same scanner as Markdown ([`crate::markdown`]), so code spans and escaped `\#12` never
+//! close anything. Every extension with the `issue-closing` capability is then asked to
+//! close the issues; the issues extension adds a timeline event linking the commit.
+//!
+//! Each repository can turn this off or pick the branches it applies to. Without branch
+//! patterns only pushes to the default branch close issues. A reference to another
+//! repository is honoured only if that repository has the same owner and has closing
+//! enabled, so pushing to one repository cannot close issues in someone else's.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, anyhow, bail};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use tokio::process::Command;
+
+use super::queries::get_repository_by_id;
+use super::resolver::PathResolver;
+use super::storage::RepositoryStorage;
+use crate::extensions::ExtensionManager;
+use crate::extensions::wit_bindings::{ContextScope, RepositoryContext, RequestContext};
+use crate::jobs::{JobHandler, JobQueue, JobRecord};
+use crate::markdown::parse_document;
+use crate::user::db::{fetch_repository_owner, unix_now};
+
+pub const JOB_KIND: &str = "issue-closing";
+/// Extensions listing this capability are asked to close referenced issues.
+pub const EXTENSION_CAPABILITY: &str = "issue-closing";
+
+const KEYWORDS: &[&str] = &[
+    "close", "closes", "closed", "fix", "fixes", "fixed", "resolve", "resolves", "resolved",
+];
+/// Commits read per branch update; older ones in a very large push are ignored.
+const MAX_COMMITS: usize = 500;
+
+/// Per-repository closing settings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IssueClosingSettings {
+    pub enabled: bool,
+    /// Branch globs (`release/*`); empty means the default branch only.
+    pub branches: Vec<String>,
+}
+
+impl Default for IssueClosingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            branches: Vec::new(),
+        }
+    }
+}
+
+impl IssueClosingSettings {
+    /// Whether a push to `branch` (without `refs/heads/`) closes issues.
+    pub fn applies_to(&self, branch: &str, default_branch: Option<&str>) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if self.branches.is_empty() {
+            return default_branch == Some(branch);
+        }
+        self.branches.iter().any(|pattern| {
+            // `main` must not match `feature/main`, unlike a forbidden-path pattern.
+            (pattern.contains('/') || !branch.contains('/'))
+                && git_http::policy::path_matches(pattern, branch)
+        })
+    }
+}
+
+pub async fn load_settings(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> anyhow::Result<IssueClosingSettings> {
+    let row: Option<(bool, String)> = sqlx::query_as(
+        "SELECT enabled, branches FROM repository_issue_closing WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .fetch_optional(pool)
+    .await?;
+    match row {
+        Some((enabled, branches)) => Ok(IssueClosingSettings {
+            enabled,
+            branches: serde_json::from_str(&branches).context("stored branch patterns")?,
+        }),
+        None => Ok(IssueClosingSettings::default()),
+    }
+}
+
+pub async fn save_settings(
+    pool: &SqlitePool,
+    repository_id: &str,
+    settings: &IssueClosingSettings,
+) -> anyhow::Result<()> {
+    if settings
+        .branches
+        .iter()
+        .any(|pattern| pattern.trim().is_empty())
+    {
+        bail!("branch patterns must not be empty");
+    }
+    if *settings == IssueClosingSettings::default() {
+        sqlx::query("DELETE FROM repository_issue_closing WHERE repository_id = ?")
+            .bind(repository_id)
+            .execute(pool)
+            .await?;
+        return Ok(());
+    }
+    sqlx::query(
+        "INSERT INTO repository_issue_closing (repository_id, enabled, branches, updated_at) \
+         VALUES (?, ?, ?, ?) ON CONFLICT(repository_id) DO UPDATE SET enabled = excluded.enabled, \
+         branches = excluded.branches, updated_at = excluded.updated_at",
+    )
+    .bind(repository_id)
+    .bind(settings.enabled)
+    .bind(serde_json::to_string(&settings.branches)?)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// An issue a commit message asks to close.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ClosingReference {
+    /// Repository path for `group/repo#7`; `None` for the pushed repository.
+    pub repository: Option<String>,
+    pub number: u64,
+}
+
+/// Issue references preceded by a closing keyword, in message order without repeats.
+pub fn closing_references(message: &str) -> Vec<ClosingReference> {
+    let mut seen = HashSet::new();
+    parse_document(message)
+        .issue_refs
+        .into_iter()
+        .filter(|issue_ref| follows_keyword(&message[..issue_ref.span.start]))
+        .map(|issue_ref| ClosingReference {
+            repository: issue_ref.repository,
+            number: issue_ref.number,
+        })
+        .filter(|reference| seen.insert(reference.clone()))
+        .collect()
+}
+
+/// Whether `before` ends with a keyword, optionally followed by `:`, and whitespace.
+fn follows_keyword(before: &str) -> bool {
+    let trimmed = before.trim_end();
+    if trimmed.len() == before.len() && !before.ends_with(':') {
+        return false;
+    }
+    let trimmed = trimmed.strip_suffix(':').unwrap_or(trimmed);
+    let word_start = trimmed
+        .rfind(|c: char| !c.is_ascii_alphabetic())
+        .map_or(0, |i| i + 1);
+    let word = &trimmed[word_start..];
+    KEYWORDS
+        .iter()
+        .any(|keyword| word.eq_ignore_ascii_case(keyword))
+}
+
+/// A branch moved by a push; `before` is `None` for a new branch.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BranchUpdate {
+    pub ref_name: String,
+    pub before: Option<String>,
+    pub after: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ClosingJob {
+    repository_id: String,
+    segments: Vec<String>,
+    updates: Vec<BranchUpdate>,
+}
+
+/// Queues closing for the branches in `updates`. Returns `false` when none were branches.
+pub async fn enqueue_issue_closing(
+    pool: &SqlitePool,
+    repository_id: &str,
+    segments: &[String],
+    updates: Vec<BranchUpdate>,
+    max_attempts: i64,
+) -> anyhow::Result<bool> {
+    let updates: Vec<BranchUpdate> = updates
+        .into_iter()
+        .filter(|update| update.ref_name.starts_with("refs/heads/"))
+        .collect();
+    if updates.is_empty() {
+        return Ok(false);
+    }
+    let job = ClosingJob {
+        repository_id: repository_id.to_string(),
+        segments: segments.to_vec(),
+        updates,
+    };
+    JobQueue::new(pool.clone())
+        .enqueue(JOB_KIND, &job, max_attempts)
+        .await?;
+    Ok(true)
+}
+
+struct Commit {
+    sha: String,
+    message: String,
+}
+
+/// Commits `update` added to its branch, oldest first.
+async fn new_commits(dir: &Path, update: &BranchUpdate) -> anyhow::Result<Vec<Commit>> {
+    let max_count = format!("--max-count={}", MAX_COMMITS);
+    let mut command = Command::new("git");
+    command
+        .arg("--git-dir")
+        .arg(dir)
+        .args(["log", "--format=%H%x00%B%x1e", &max_count]);
+    match &update.before {
+        Some(before) => command.arg(format!("{}..{}", before, update.after)),
+        // A new branch brings the commits no other branch has.
+        None => {
+            let branch = update.ref_name.trim_start_matches("refs/heads/");
+            command.arg(&update.after).args([
+                "--not",
+                &format!("--exclude={}", branch),
+                "--branches",
+            ])
+        }
+    };
+    let output = command.output().await.context("failed to spawn git log")?;
+    if !output.status.success() {
+        bail!(
+            "git log failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let mut commits: Vec<Commit> = String::from_utf8_lossy(&output.stdout)
+        .split('\x1e')
+        .filter_map(|entry| {
+            let (sha, message) = entry.trim_start_matches('\n').split_once('\0')?;
+            Some(Commit {
+                sha: sha.to_string(),
+                message: message.trim_end().to_string(),
+            })
+        })
+        .collect();
+    commits.reverse();
+    Ok(commits)
+}
+
+/// The branch `HEAD` points at, read from the bare repository.
+pub(crate) async fn default_branch(dir: &Path) -> Option<String> {
+    let head = tokio::fs::read_to_string(dir.join("HEAD")).await.ok()?;
+    head.trim()
+        .strip_prefix("ref: refs/heads/")
+        .map(str::to_string)
+}
+
+/// Job handler for [`JOB_KIND`].
+pub struct IssueClosingJob {
+    pool: SqlitePool,
+    storage: Arc<RepositoryStorage>,
+    resolver: PathResolver,
+    extensions: Arc<ExtensionManager>,
+}
+
+impl IssueClosingJob {
+    pub fn new(
+        pool: SqlitePool,
+        storage: Arc<RepositoryStorage>,
+        resolver: PathResolver,
+        extensions: Arc<ExtensionManager>,
+    ) -> Self {
+        Self {
+            pool,
+            storage,
+            resolver,
+            extensions,
+        }
+    }
+
+    /// The repository a reference points at, if the pushed repository may close its issues.
+    async fn target_repository(
+        &self,
+        source_id: &str,
+        reference: &ClosingReference,
+    ) -> anyhow::Result<Option<String>> {
+        let Some(path) = &reference.repository else {
+            return Ok(Some(source_id.to_string()));
+        };
+        let Some(target) = self.resolver.resolve_repository(path).await? else {
+            return Ok(None);
+        };
+        if target.record.id == source_id {
+            return Ok(Some(target.record.id));
+        }
+        let source_owner = fetch_repository_owner(&self.pool, source_id).await?;
+        let target_owner = fetch_repository_owner(&self.pool, &target.record.id).await?;
+        if source_owner.is_none() || source_owner != target_owner {
+            return Ok(None);
+        }
+        if !load_settings(&self.pool, &target.record.id).await?.enabled {
+            return Ok(None);
+        }
+        Ok(Some(target.record.id))
+    }
+
+    async fn close(
+        &self,
+        repository_id: &str,
+        closures: Vec<serde_json::Value>,
+    ) -> anyhow::Result<()> {
+        let record = get_repository_by_id(&self.pool, repository_id)
+            .await?
+            .ok_or_else(|| anyhow!("repository {} no longer exists", repository_id))?;
+        for (name, extension) in self.extensions.get_extensions().iter() {
+            if !extension
+                .runtime
+                .capabilities()
+                .iter()
+                .any(|capability| capability == EXTENSION_CAPABILITY)
+            {
+                continue;
+            }
+            let context = RequestContext {
+                scope: ContextScope::Repository,
+                repository: Some(RepositoryContext {
+                    id: record.id.clone(),
+                    slug: record.slug.clone(),
+                    group_id: record.group_id.clone(),
+                    full_path: None,
+                    is_remote: record.remote_url.is_some(),
+                    remote_url: record.remote_url.clone(),
+                }),
+                ..RequestContext::default()
+            };
+            extension
+                .runtime
+                .resolve_field(
+                    "closeIssuesFromCommits".to_string(),
+                    "Mutation".to_string(),
+                    json!({ "repositoryId": record.id, "closures": closures }),
+                    context,
+                    None,
+                )
+                .await
+                .with_context(|| format!("extension `{}` failed to close issues", name))?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl JobHandler for IssueClosingJob {
+    async fn run(&self, job: &JobRecord) -> anyhow::Result<()> {
+        let payload: ClosingJob = job.payload()?;
+        let settings = load_settings(&self.pool, &payload.repository_id).await?;
+        if !settings.enabled {
+            return Ok(());
+        }
+        let source_path = payload.segments.join("/");
+        let dir = self.storage.repository_path(&payload.segments);
+        let default_branch = default_branch(&dir).await;
+
+        // Closures per target repository; the oldest commit naming an issue wins.
+        let mut closures: BTreeMap<String, Vec<serde_json::Value>> = BTreeMap::new();
+        let mut closed = HashSet::new();
+        for update in &payload.updates {
+            let branch = update.ref_name.trim_start_matches("refs/heads/");
+            if !settings.applies_to(branch, default_branch.as_deref()) {
+                continue;
+            }
+            for commit in new_commits(&dir, update).await? {
+                for reference in closing_references(&commit.message) {
+                    let Some(target) = self
+                        .target_repository(&payload.repository_id, &reference)
+                        .await?
+                    else {
+                        continue;
+                    };
+                    if !closed.insert((target.clone(), reference.number)) {
+                        continue;
+                    }
+                    closures.entry(target).or_default().push(json!({
+                        "number": reference.number,
+                        "commitSha": commit.sha,
+                        "commitSummary": commit.message.lines().next().unwrap_or_default(),
+                        "commitRepositoryId": payload.repository_id,
+                        "commitRepositoryPath": source_path,
+                        "branch": branch,
+                    }));
+                }
+            }
+        }
+
+        for (repository_id, closures) in closures {
+            tracing::info!(
+                repository = %repository_id,
+                count = closures.len(),
+                "closing issues referenced by pushed commits"
+            );
+            self.close(&repository_id, closures).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    fn references(message: &str) -> Vec<(Option<String>, u64)> {
+        closing_references(message)
+            .into_iter()
+            .map(|reference| (reference.repository, reference.number))
+            .collect()
+    }
+
+    #[test]
+    fn keywords_must_precede_the_reference() {
+        assert_eq!(
+            references("Fixes #12\n\nAlso closes: team/app#7 and resolved #3, fixes #12."),
+            [(None, 12), (Some("team/app".to_string()), 7), (None, 3)]
+        );
+        assert_eq!(references("See #4; refixes #5; fix#6; closing #7"), []);
+        assert_eq!(
+            references("FIXED   #8 but `fixes #9` and fixes \\#10"),
+            [(None, 8)]
+        );
+    }
+
+    #[test]
+    fn branch_patterns_default_to_the_default_branch() {
+        let default = IssueClosingSettings::default();
+        assert!(default.applies_to("main", Some("main")));
+        assert!(!default.applies_to("dev", Some("main")));
+        assert!(!default.applies_to("main", None));
+
+        let patterns = IssueClosingSettings {
+            enabled: true,
+            branches: vec!["main".into(), "release/*".into()],
+        };
+        assert!(patterns.applies_to("main", Some("trunk")));
+        assert!(patterns.applies_to("release/1.2", None));
+        assert!(!patterns.applies_to("feature/main", None));
+        assert!(!patterns.applies_to("release/1.2/hotfix", None));
+
+        let disabled = IssueClosingSettings {
+            enabled: false,
+            ..default
+        };
+        assert!(!disabled.applies_to("main", Some("main")));
+    }
+
+    #[tokio::test]
+    async fn settings_round_trip_and_default_rows_are_removed() {
+        let pool = create_test_pool().await.unwrap();
+        sqlx::query("INSERT INTO repositories (id, slug) VALUES ('r1', 'app')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            load_settings(&pool, "r1").await.unwrap(),
+            IssueClosingSettings::default()
+        );
+
+        let custom = IssueClosingSettings {
+            enabled: false,
+            branches: vec!["release/*".into()],
+        };
+        save_settings(&pool, "r1", &custom).await.unwrap();
+        assert_eq!(load_settings(&pool, "r1").await.unwrap(), custom);
+
+        save_settings(&pool, "r1", &IssueClosingSettings::default())
+            .await
+            .unwrap();
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM repository_issue_closing")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 0);
+
+        let blank = IssueClosingSettings {
+            enabled: true,
+            branches: vec![" ".into()],
+        };
+        assert!(save_settings(&pool, "r1", &blank).await.is_err());
+    }
+}
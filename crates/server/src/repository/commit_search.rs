@@ -0,0 +1,416 @@
+//! Commit search by message and author.
+//!
+//! [`search_commits`] walks a branch newest first by committer time and keeps the commits
+//! whose message contains every query term and whose author name or email contains the
+//! author filter, both ignoring case. Filters run while the walk streams, so a page stops
+//! reading history as soon as it is full, and a `since` bound ends the walk at the first
+//! older commit instead of visiting the rest of the history.
+//!
+//! Cursors pin the tip the first page was read from. Later pages walk from that tip, not
+//! from wherever the branch has moved since, so pushes between pages neither repeat nor
+//! skip results.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail};
+use gix::revision::walk::Sorting;
+use gix::traverse::commit::simple::CommitTimeOrder;
+use tokio::task;
+
+use super::resolver::PathResolver;
+use super::storage::RepositoryStorage;
+use crate::graphql::pagination::{Direction, Page, PageRequest, encode_cursor};
+
+pub const COMMIT_CURSOR_KIND: &str = "commit";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommitSearch {
+    /// Whitespace-separated terms that must all appear in the message.
+    pub query: Option<String>,
+    /// Matched against the author's name and email.
+    pub author: Option<String>,
+    /// Inclusive committer time bounds, in Unix seconds.
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitMatch {
+    pub oid: String,
+    /// The tip the walk started from, kept for the cursor.
+    pub tip: String,
+    pub summary: String,
+    pub message: String,
+    pub author_name: String,
+    pub author_email: String,
+    /// Committer time, in Unix seconds.
+    pub committed_at: i64,
+    pub highlights: Vec<Highlight>,
+}
+
+impl CommitMatch {
+    pub fn cursor(&self) -> String {
+        encode_cursor(COMMIT_CURSOR_KIND, &self.tip, &self.oid)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HighlightField {
+    Message,
+    AuthorName,
+    AuthorEmail,
+}
+
+impl HighlightField {
+    pub fn as_graphql(self) -> &'static str {
+        match self {
+            HighlightField::Message => "MESSAGE",
+            HighlightField::AuthorName => "AUTHOR_NAME",
+            HighlightField::AuthorEmail => "AUTHOR_EMAIL",
+        }
+    }
+}
+
+/// A matched span of one field, as character offsets with `end` exclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Highlight {
+    pub field: HighlightField,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Commits on `branch` (default `HEAD`) of the repository at `path` that match `search`.
+/// Returns `None` when the repository does not exist and an empty page for a repository
+/// without commits.
+pub async fn search_commits(
+    resolver: &PathResolver,
+    storage: &RepositoryStorage,
+    path: &str,
+    branch: Option<String>,
+    search: CommitSearch,
+    request: PageRequest,
+) -> anyhow::Result<Option<Page<CommitMatch>>> {
+    if request.direction == Direction::Backward {
+        bail!("commit search only pages forward with `first` and `after`");
+    }
+    let Some(resolved) = resolver.resolve_repository(path).await? else {
+        return Ok(None);
+    };
+    let repository_path = resolved.local_dir(storage)?;
+    let page = task::spawn_blocking(move || {
+        search_at(repository_path, branch.as_deref(), &search, &request)
+    })
+    .await
+    .map_err(|err| anyhow!(err))??;
+    Ok(Some(page))
+}
+
+fn search_at(
+    repository_path: PathBuf,
+    branch: Option<&str>,
+    search: &CommitSearch,
+    request: &PageRequest,
+) -> anyhow::Result<Page<CommitMatch>> {
+    let repo = gix::open(&repository_path).map_err(|err| {
+        anyhow!(
+            "failed to open repository at {}: {}",
+            repository_path.display(),
+            err
+        )
+    })?;
+
+    let tip = match (&request.cursor, branch) {
+        (Some(cursor), _) => gix::ObjectId::from_hex(cursor.slug.as_bytes())
+            .map_err(|_| anyhow!("invalid commit search cursor"))?,
+        (None, Some(spec)) => {
+            repo.rev_parse_single(spec)
+                .map_err(|err| anyhow!("branch `{}` not found: {}", spec, err))?
+                .object()?
+                .peel_to_commit()
+                .map_err(|_| anyhow!("`{}` does not point to a commit", spec))?
+                .id
+        }
+        (None, None) => match repo.head_commit() {
+            Ok(commit) => commit.id,
+            // Unborn HEAD: nothing to search yet.
+            Err(_) => return Ok(Page::from_rows(request, Vec::new())),
+        },
+    };
+
+    let matcher = Matcher::new(search);
+    let order = CommitTimeOrder::NewestFirst;
+    let sorting = match search.since {
+        Some(seconds) => Sorting::ByCommitTimeCutoff { order, seconds },
+        None => Sorting::ByCommitTime(order),
+    };
+    let walk = repo
+        .rev_walk([tip])
+        .sorting(sorting)
+        .all()
+        .map_err(|err| anyhow!("failed to walk history from {}: {}", tip, err))?;
+
+    let mut after = request.cursor.as_ref().map(|cursor| cursor.id.as_str());
+    let mut rows = Vec::new();
+    for info in walk {
+        let info = info?;
+        if let Some(cursor_oid) = after {
+            if info.id.to_string() == cursor_oid {
+                after = None;
+            }
+            continue;
+        }
+        let commit = info.object()?;
+        let committed_at = commit.time()?.seconds;
+        if search.since.is_some_and(|since| committed_at < since)
+            || search.until.is_some_and(|until| committed_at > until)
+        {
+            continue;
+        }
+        let author = commit.author()?;
+        let author_name = author.name.to_string();
+        let author_email = author.email.to_string();
+        let message = commit.message_raw()?.to_string();
+        let Some(highlights) = matcher.matches(&message, &author_name, &author_email) else {
+            continue;
+        };
+        rows.push(CommitMatch {
+            oid: info.id.to_string(),
+            tip: tip.to_string(),
+            summary: commit.message()?.summary().to_string(),
+            message,
+            author_name,
+            author_email,
+            committed_at,
+            highlights,
+        });
+        if rows.len() as i64 == request.fetch_limit() {
+            break;
+        }
+    }
+    if after.is_some() {
+        bail!("the cursor's commit is no longer reachable; start the search again");
+    }
+    Ok(Page::from_rows(request, rows))
+}
+
+/// Query terms and the author filter, case-folded once per search.
+struct Matcher {
+    terms: Vec<Vec<char>>,
+    author: Option<Vec<char>>,
+}
+
+impl Matcher {
+    fn new(search: &CommitSearch) -> Self {
+        let terms = search
+            .query
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(fold)
+            .collect();
+        let author = search
+            .author
+            .as_deref()
+            .map(str::trim)
+            .filter(|author| !author.is_empty())
+            .map(fold);
+        Self { terms, author }
+    }
+
+    /// The highlights of a matching commit, or `None` when it does not match.
+    fn matches(&self, message: &str, name: &str, email: &str) -> Option<Vec<Highlight>> {
+        let mut highlights = Vec::new();
+        if let Some(author) = &self.author {
+            let in_name = occurrences(&fold(name), author);
+            let in_email = occurrences(&fold(email), author);
+            if in_name.is_empty() && in_email.is_empty() {
+                return None;
+            }
+            highlights.extend(spans(HighlightField::AuthorName, in_name));
+            highlights.extend(spans(HighlightField::AuthorEmail, in_email));
+        }
+
+        let message = fold(message);
+        let mut in_message = Vec::new();
+        for term in &self.terms {
+            let found = occurrences(&message, term);
+            if found.is_empty() {
+                return None;
+            }
+            in_message.extend(found);
+        }
+        highlights.extend(spans(HighlightField::Message, merge(in_message)));
+        Some(highlights)
+    }
+}
+
+/// Lowercases character by character, so offsets into the result are offsets into the
+/// original text.
+fn fold(text: &str) -> Vec<char> {
+    text.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
+
+/// Every non-overlapping `(start, end)` span of `needle` in `haystack`.
+fn occurrences(haystack: &[char], needle: &[char]) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    if needle.is_empty() {
+        return found;
+    }
+    let mut start = 0;
+    while start + needle.len() <= haystack.len() {
+        if haystack[start..start + needle.len()] == *needle {
+            found.push((start, start + needle.len()));
+            start += needle.len();
+        } else {
+            start += 1;
+        }
+    }
+    found
+}
+
+/// Sorts spans and joins the ones that overlap or touch.
+fn merge(mut spans: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    spans.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn spans(field: HighlightField, spans: Vec<(usize, usize)>) -> impl Iterator<Item = Highlight> {
+    spans
+        .into_iter()
+        .map(move |(start, end)| Highlight { field, start, end })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{git, git_with_env};
+    use std::path::Path;
+
+    #[test]
+    fn terms_must_all_match_and_overlapping_highlights_merge() {
+        let matcher = Matcher::new(&CommitSearch {
+            query: Some("Fix fixture".into()),
+            ..Default::default()
+        });
+        let highlights = matcher
+            .matches("fix: update Fixtures", "Tess", "t@e")
+            .unwrap();
+        let spans: Vec<_> = highlights.iter().map(|h| (h.start, h.end)).collect();
+        // `fix` inside `Fixtures` merges with the `fixture` span
+        assert_eq!(spans, vec![(0, 3), (12, 19)]);
+        assert!(matcher.matches("fix typo", "Tess", "t@e").is_none());
+    }
+
+    #[test]
+    fn author_filter_matches_name_or_email() {
+        let matcher = Matcher::new(&CommitSearch {
+            author: Some("TESS".into()),
+            ..Default::default()
+        });
+        let highlights = matcher
+            .matches("anything", "Tess Ng", "tess@example.org")
+            .unwrap();
+        assert_eq!(
+            highlights,
+            vec![
+                Highlight {
+                    field: HighlightField::AuthorName,
+                    start: 0,
+                    end: 4
+                },
+                Highlight {
+                    field: HighlightField::AuthorEmail,
+                    start: 0,
+                    end: 4
+                },
+            ]
+        );
+        assert!(matcher.matches("anything", "Ada", "ada@e").is_none());
+    }
+
+    #[test]
+    fn offsets_count_characters() {
+        let matcher = Matcher::new(&CommitSearch {
+            query: Some("café".into()),
+            ..Default::default()
+        });
+        let highlights = matcher.matches("Ünïcode CAFÉ", "Tess", "t@e").unwrap();
+        assert_eq!((highlights[0].start, highlights[0].end), (8, 12));
+    }
+
+    fn commit(dir: &Path, author: &str, message: &str, time: i64) {
+        let ident = format!("user.name={}", author);
+        let date = format!("@{} +0000", time);
+        git_with_env(
+            dir,
+            &[("GIT_AUTHOR_DATE", &date), ("GIT_COMMITTER_DATE", &date)],
+            &[
+                "-c",
+                &ident,
+                "-c",
+                "user.email=dev@example.org",
+                "commit",
+                "-q",
+                "--allow-empty",
+                "-m",
+                message,
+            ],
+        );
+    }
+
+    fn summaries(page: &Page<CommitMatch>) -> Vec<&str> {
+        page.items.iter().map(|m| m.summary.as_str()).collect()
+    }
+
+    #[test]
+    fn searches_a_branch_page_by_page() {
+        let tmp = tempfile::tempdir().unwrap();
+        let work = tmp.path();
+        git(work, &["init", "-q", "-b", "main"]);
+        commit(work, "Tess", "Add parser", 1_000);
+        commit(work, "Ada", "Fix parser crash", 2_000);
+        commit(work, "Tess", "Fix lexer", 3_000);
+        commit(work, "Tess", "Fix parser again", 4_000);
+        let git_dir = work.join(".git");
+
+        let fixes = CommitSearch {
+            query: Some("fix parser".into()),
+            ..Default::default()
+        };
+        let first = PageRequest::from_args(COMMIT_CURSOR_KIND, Some(1), None, None, None).unwrap();
+        let page = search_at(git_dir.clone(), Some("main"), &fixes, &first).unwrap();
+        assert_eq!(summaries(&page), ["Fix parser again"]);
+        assert!(page.has_next_page);
+
+        // A push between pages does not shift the second page.
+        commit(work, "Tess", "Fix parser once more", 5_000);
+        let cursor = page.items[0].cursor();
+        let next =
+            PageRequest::from_args(COMMIT_CURSOR_KIND, Some(5), Some(&cursor), None, None).unwrap();
+        let page = search_at(git_dir.clone(), Some("main"), &fixes, &next).unwrap();
+        assert_eq!(summaries(&page), ["Fix parser crash"]);
+        assert!(!page.has_next_page);
+        assert!(page.has_previous_page);
+
+        let by_tess = CommitSearch {
+            author: Some("tess".into()),
+            since: Some(1_500),
+            until: Some(4_000),
+            ..Default::default()
+        };
+        let all = PageRequest::from_args(COMMIT_CURSOR_KIND, None, None, None, None).unwrap();
+        let page = search_at(git_dir.clone(), None, &by_tess, &all).unwrap();
+        assert_eq!(summaries(&page), ["Fix parser again", "Fix lexer"]);
+
+        assert!(search_at(git_dir, Some("missing"), &fixes, &all).is_err());
+    }
+}
@@ -0,0 +1,351 @@
+//! Ahead/behind comparisons between two revisions.
+//!
+//! [`compare_refs`] compares two revisions of one repository; [`compare_across_forks`]
+//! compares a revision of one repository with a revision of another, such as a fork and
+//! its parent. Repositories do not share object storage on disk, so a cross-repository
+//! comparison walks a throwaway bare repository whose `objects/info/alternates` lists the
+//! object directories of both sides. Nothing is copied or fetched, and neither repository
+//! is written to.
+//!
+//! Both sides are painted in one walk by committer time, newest first, the way
+//! `git merge-base` does: commits reached only from `head` are ahead, commits reached
+//! only from `base` are behind, and the walk ends once every queued commit is reachable
+//! from both.
+
+use std::collections::{BinaryHeap, HashMap};
+use std::path::{Path, PathBuf};
+
+use tokio::task;
+
+use super::resolver::PathResolver;
+use super::storage::RepositoryStorage;
+use crate::scratch::ScratchDir;
+
+pub const DEFAULT_LIMIT: usize = 250;
+pub const MAX_LIMIT: usize = 1000;
+/// Commits a single comparison reads before giving up on exact counts.
+const MAX_WALK: usize = 100_000;
+
+const BASE: u8 = 1;
+const HEAD: u8 = 2;
+const BOTH: u8 = BASE | HEAD;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comparison {
+    pub base_oid: String,
+    pub head_oid: String,
+    /// The newest commit reachable from both sides, if they share history.
+    pub merge_base: Option<String>,
+    /// Commits reachable from `head` but not from `base`.
+    pub ahead_by: usize,
+    /// Commits reachable from `base` but not from `head`.
+    pub behind_by: usize,
+    /// The first `limit` commits ahead, newest first.
+    pub commits: Vec<ComparedCommit>,
+    /// Commits were left out of `commits`, or the walk stopped before the counts were
+    /// exact.
+    pub truncated: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ComparedCommit {
+    pub oid: String,
+    pub parents: Vec<String>,
+    pub summary: String,
+    pub author_name: String,
+    /// Committer time, in Unix seconds.
+    pub committed_at: i64,
+}
+
+/// Compares `base` with `head` in the repository at `path`. Returns `None` when the
+/// repository does not exist.
+pub async fn compare_refs(
+    resolver: &PathResolver,
+    storage: &RepositoryStorage,
+    path: &str,
+    base: String,
+    head: String,
+    limit: usize,
+) -> anyhow::Result<Option<Comparison>> {
+    let Some(resolved) = resolver.resolve_repository(path).await? else {
+        return Ok(None);
+    };
+    let repository_path = resolved.local_dir(storage)?;
+    let limit = limit.clamp(1, MAX_LIMIT);
+    let comparison = task::spawn_blocking(move || {
+        let repo = open(&repository_path)?;
+        let base = resolve_commit(&repo, &base)?;
+        let head = resolve_commit(&repo, &head)?;
+        compare(&repo, base, head, limit)
+    })
+    .await
+    .map_err(|err| anyhow::anyhow!(err))??;
+    Ok(Some(comparison))
+}
+
+/// Compares `base_ref` of the repository at `base_path` with `head_ref` of the one at
+/// `head_path`, resolving each ref in its own repository. Returns `None` when either
+/// repository does not exist.
+pub async fn compare_across_forks(
+    resolver: &PathResolver,
+    storage: &RepositoryStorage,
+    base_path: &str,
+    base_ref: String,
+    head_path: &str,
+    head_ref: String,
+    limit: usize,
+) -> anyhow::Result<Option<Comparison>> {
+    let Some(base_repository) = resolver.resolve_repository(base_path).await? else {
+        return Ok(None);
+    };
+    let Some(head_repository) = resolver.resolve_repository(head_path).await? else {
+        return Ok(None);
+    };
+    let base_dir = base_repository.local_dir(storage)?;
+    let head_dir = head_repository.local_dir(storage)?;
+    let limit = limit.clamp(1, MAX_LIMIT);
+    let comparison = task::spawn_blocking(move || {
+        compare_dirs(&base_dir, &base_ref, &head_dir, &head_ref, limit)
+    })
+    .await
+    .map_err(|err| anyhow::anyhow!(err))??;
+    Ok(Some(comparison))
+}
+
+fn compare_dirs(
+    base_dir: &Path,
+    base_ref: &str,
+    head_dir: &Path,
+    head_ref: &str,
+    limit: usize,
+) -> anyhow::Result<Comparison> {
+    let base_repo = open(base_dir)?;
+    let base = resolve_commit(&base_repo, base_ref)?;
+    if base_dir == head_dir {
+        let head = resolve_commit(&base_repo, head_ref)?;
+        return compare(&base_repo, base, head, limit);
+    }
+    let head_repo = open(head_dir)?;
+    let head = resolve_commit(&head_repo, head_ref)?;
+
+    let scratch = ScratchDir::create("compare")?;
+    let combined = combined_repository(
+        scratch.path(),
+        &[objects_dir(&base_repo), objects_dir(&head_repo)],
+    )?;
+    compare(&combined, base, head, limit)
+}
+
+/// A bare repository in `dir` that reads objects from `sources` and has none of its own.
+fn combined_repository(dir: &Path, sources: &[PathBuf]) -> anyhow::Result<gix::Repository> {
+    gix::init_bare(dir).map_err(|err| {
+        anyhow::anyhow!("failed to create repository at {}: {}", dir.display(), err)
+    })?;
+    let mut alternates = String::new();
+    for source in sources {
+        let source = source.canonicalize().map_err(|err| {
+            anyhow::anyhow!("failed to locate objects at {}: {}", source.display(), err)
+        })?;
+        alternates.push_str(&source.to_string_lossy());
+        alternates.push('\n');
+    }
+    std::fs::write(dir.join("objects/info/alternates"), alternates)?;
+    open(dir)
+}
+
+fn objects_dir(repo: &gix::Repository) -> PathBuf {
+    repo.common_dir().join("objects")
+}
+
+pub(super) fn open(path: &Path) -> anyhow::Result<gix::Repository> {
+    gix::open(path)
+        .map_err(|err| anyhow::anyhow!("failed to open repository at {}: {}", path.display(), err))
+}
+
+pub(super) fn resolve_commit(repo: &gix::Repository, spec: &str) -> anyhow::Result<gix::ObjectId> {
+    Ok(repo
+        .rev_parse_single(spec)
+        .map_err(|err| anyhow::anyhow!("revision `{}` not found: {}", spec, err))?
+        .object()?
+        .peel_to_commit()
+        .map_err(|_| anyhow::anyhow!("revision `{}` is not a commit", spec))?
+        .id)
+}
+
+/// The newest commit reachable from both `base` and `head`, if they share history.
+pub(super) fn merge_base(
+    repo: &gix::Repository,
+    base: gix::ObjectId,
+    head: gix::ObjectId,
+) -> anyhow::Result<Option<gix::ObjectId>> {
+    let comparison = compare(repo, base, head, 1)?;
+    Ok(comparison
+        .merge_base
+        .map(|oid| gix::ObjectId::from_hex(oid.as_bytes()))
+        .transpose()?)
+}
+
+fn compare(
+    repo: &gix::Repository,
+    base: gix::ObjectId,
+    head: gix::ObjectId,
+    limit: usize,
+) -> anyhow::Result<Comparison> {
+    let mut flags: HashMap<gix::ObjectId, u8> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    // Commits in the order they were first reached from `head` alone; some of them may
+    // turn out to be reachable from `base` later, through a commit with a skewed clock.
+    let mut head_only = Vec::new();
+    let mut merge_base = None;
+    let mut walked = 0;
+    let mut exhausted = false;
+
+    flags.insert(base, BASE);
+    *flags.entry(head).or_default() |= HEAD;
+    for tip in [base, head] {
+        queue.push((repo.find_commit(tip)?.time()?.seconds, tip));
+    }
+
+    // Once only commits reachable from both sides are queued, the counts are final; the
+    // walk goes on just long enough to pop the newest of them as the merge base.
+    loop {
+        if merge_base.is_some() && queue.iter().all(|(_, oid)| flags[oid] == BOTH) {
+            break;
+        }
+        let Some((_, oid)) = queue.pop() else {
+            break;
+        };
+        if walked == MAX_WALK {
+            exhausted = true;
+            break;
+        }
+        walked += 1;
+        let painted = flags[&oid];
+        if painted == BOTH && merge_base.is_none() {
+            merge_base = Some(oid);
+        }
+        if painted == HEAD {
+            head_only.push(oid);
+        }
+        let commit = repo.find_commit(oid)?;
+        for parent in commit.parent_ids() {
+            let parent = parent.detach();
+            let parent_flags = flags.entry(parent).or_default();
+            if *parent_flags | painted == *parent_flags {
+                continue;
+            }
+            *parent_flags |= painted;
+            // Shallow clones end at commits whose parents are missing.
+            if let Ok(parent_commit) = repo.find_commit(parent) {
+                queue.push((parent_commit.time()?.seconds, parent));
+            }
+        }
+    }
+
+    let ahead_by = flags.values().filter(|&&flag| flag == HEAD).count();
+    let behind_by = flags.values().filter(|&&flag| flag == BASE).count();
+    let mut commits = Vec::new();
+    for oid in head_only
+        .iter()
+        .filter(|oid| flags[*oid] == HEAD)
+        .take(limit)
+    {
+        let commit = repo.find_commit(*oid)?;
+        commits.push(ComparedCommit {
+            oid: oid.to_string(),
+            parents: commit.parent_ids().map(|id| id.to_string()).collect(),
+            summary: commit.message()?.summary().to_string(),
+            author_name: commit.author()?.name.to_string(),
+            committed_at: commit.time()?.seconds,
+        });
+    }
+
+    Ok(Comparison {
+        base_oid: base.to_string(),
+        head_oid: head.to_string(),
+        merge_base: merge_base.map(|oid| oid.to_string()),
+        ahead_by,
+        behind_by,
+        truncated: exhausted || ahead_by > commits.len(),
+        commits,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::git;
+
+    fn summaries(comparison: &Comparison) -> Vec<&str> {
+        comparison
+            .commits
+            .iter()
+            .map(|commit| commit.summary.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn counts_commits_on_each_side_of_the_merge_base() {
+        let tmp = tempfile::tempdir().unwrap();
+        let work = tmp.path();
+        git(work, &["init", "-q", "-b", "main"]);
+        git(work, &["commit", "-q", "--allow-empty", "-m", "root"]);
+        let root = git(work, &["rev-parse", "HEAD"]);
+        git(work, &["checkout", "-q", "-b", "topic"]);
+        git(work, &["commit", "-q", "--allow-empty", "-m", "topic one"]);
+        git(work, &["commit", "-q", "--allow-empty", "-m", "topic two"]);
+        git(work, &["checkout", "-q", "main"]);
+        git(work, &["commit", "-q", "--allow-empty", "-m", "main work"]);
+        let repo = open(&work.join(".git")).unwrap();
+        let main = resolve_commit(&repo, "main").unwrap();
+        let topic = resolve_commit(&repo, "topic").unwrap();
+
+        let comparison = compare(&repo, main, topic, 10).unwrap();
+        assert_eq!(comparison.merge_base.as_deref(), Some(root.as_str()));
+        assert_eq!((comparison.ahead_by, comparison.behind_by), (2, 1));
+        assert_eq!(summaries(&comparison), vec!["topic two", "topic one"]);
+        assert!(!comparison.truncated);
+
+        let limited = compare(&repo, main, topic, 1).unwrap();
+        assert_eq!(summaries(&limited), vec!["topic two"]);
+        assert_eq!(limited.ahead_by, 2);
+        assert!(limited.truncated);
+
+        let same = compare(&repo, main, main, 10).unwrap();
+        assert_eq!((same.ahead_by, same.behind_by), (0, 0));
+        assert_eq!(same.merge_base, Some(main.to_string()));
+    }
+
+    #[test]
+    fn forks_are_compared_through_both_object_stores() {
+        let tmp = tempfile::tempdir().unwrap();
+        let parent = tmp.path().join("parent");
+        std::fs::create_dir(&parent).unwrap();
+        git(&parent, &["init", "-q", "-b", "main"]);
+        git(&parent, &["commit", "-q", "--allow-empty", "-m", "root"]);
+        let root = git(&parent, &["rev-parse", "HEAD"]);
+        let fork = tmp.path().join("fork.git");
+        git(
+            tmp.path(),
+            &["clone", "-q", "--bare", "--no-local", "parent", "fork.git"],
+        );
+        git(
+            &parent,
+            &["commit", "-q", "--allow-empty", "-m", "upstream"],
+        );
+
+        // The fork's commit only exists in the fork, and upstream's only in the parent.
+        let work = tmp.path().join("work");
+        git(tmp.path(), &["clone", "-q", "fork.git", "work"]);
+        git(&work, &["commit", "-q", "--allow-empty", "-m", "fork fix"]);
+        git(&work, &["push", "-q", "origin", "main"]);
+
+        let comparison =
+            compare_dirs(&parent.join(".git"), "main", &fork, "refs/heads/main", 10).unwrap();
+        assert_eq!(comparison.merge_base.as_deref(), Some(root.as_str()));
+        assert_eq!((comparison.ahead_by, comparison.behind_by), (1, 1));
+        assert_eq!(summaries(&comparison), vec!["fork fix"]);
+
+        assert!(compare_dirs(&parent.join(".git"), "main", &fork, "missing", 10).is_err());
+    }
+}
@@ -0,0 +1,521 @@
+//! Contributor statistics and code frequency for the default branch.
+//!
+//! [`JOB_KIND`] jobs record the author, author time and line counts of each non-merge
+//! commit on the default branch, and `contributorStats` aggregates those rows for any
+//! time range. A push that moves the default branch queues a job, which only diffs the
+//! commits it has not seen. When the branch was rewritten, the whole history is walked
+//! again and commits that left it are dropped. Large histories are processed
+//! [`MAX_COMMITS_PER_RUN`] commits at a time, each batch queueing the next.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use gix::revision::walk::Sorting;
+use gix::traverse::commit::simple::CommitTimeOrder;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::task;
+
+use super::compare::merge_base;
+use super::diff::ChangeDetail;
+use super::queries::{commit_changes, get_repository_by_id, reconstruct_repository_path};
+use super::storage::RepositoryStorage;
+use crate::jobs::{JobHandler, JobRecord, enqueue_in};
+use crate::user::db::unix_now;
+
+pub const JOB_KIND: &str = "contributor-stats";
+const MAX_ATTEMPTS: i64 = 3;
+/// Commits diffed per job; the rest are left to the job it queues.
+pub const MAX_COMMITS_PER_RUN: usize = 2_000;
+/// Contributors returned, those with the most commits first
+pub const MAX_CONTRIBUTORS: i64 = 100;
+const WEEK_SECS: i64 = 7 * 86_400;
+
+/// Where a repository's statistics stand.
+#[derive(Clone, Debug, PartialEq, Eq, sqlx::FromRow)]
+pub struct ContributorStatsStatus {
+    /// `None` until the statistics were first computed.
+    pub commit_oid: Option<String>,
+    pub branch: Option<String>,
+    pub computed_at: Option<i64>,
+    pub requested_at: i64,
+}
+
+impl ContributorStatsStatus {
+    /// Whether new commits were pushed since the last computation finished.
+    pub fn pending(&self) -> bool {
+        self.computed_at.is_none_or(|at| at < self.requested_at)
+    }
+}
+
+/// Commits by one author, matched by email address ignoring case.
+#[derive(Clone, Debug, PartialEq, Eq, sqlx::FromRow)]
+pub struct ContributorStat {
+    /// The name on the author's latest commit
+    pub name: String,
+    pub email: String,
+    pub commits: i64,
+    pub additions: i64,
+    pub deletions: i64,
+    pub first_commit_at: i64,
+    pub last_commit_at: i64,
+}
+
+/// Commits authored in one week, Monday 00:00 UTC to the next.
+#[derive(Clone, Debug, PartialEq, Eq, sqlx::FromRow)]
+pub struct CodeFrequencyWeek {
+    pub week_start: i64,
+    pub commits: i64,
+    pub additions: i64,
+    pub deletions: i64,
+}
+
+/// A counted commit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CommitStat {
+    oid: String,
+    author_name: String,
+    author_email: String,
+    authored_at: i64,
+    additions: i64,
+    deletions: i64,
+}
+
+pub async fn contributor_stats_status(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> Result<Option<ContributorStatsStatus>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT commit_oid, branch, computed_at, requested_at FROM repository_contributor_stats \
+         WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .fetch_optional(pool)
+    .await
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ContributorStatsPayload {
+    repository_id: String,
+}
+
+/// Queues an update of the repository's statistics.
+pub async fn enqueue_contributor_stats(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO repository_contributor_stats (repository_id, requested_at) VALUES (?, ?) \
+         ON CONFLICT(repository_id) DO UPDATE SET requested_at = excluded.requested_at",
+    )
+    .bind(repository_id)
+    .bind(unix_now())
+    .execute(&mut *tx)
+    .await?;
+    let payload = ContributorStatsPayload {
+        repository_id: repository_id.to_string(),
+    };
+    enqueue_in(&mut *tx, JOB_KIND, &payload, MAX_ATTEMPTS).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// The repository's statistics status, queueing a first computation if there never was
+/// one, such as for a repository created before statistics existed.
+pub async fn ensure_contributor_stats(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> anyhow::Result<ContributorStatsStatus> {
+    if let Some(status) = contributor_stats_status(pool, repository_id).await? {
+        return Ok(status);
+    }
+    enqueue_contributor_stats(pool, repository_id).await?;
+    contributor_stats_status(pool, repository_id)
+        .await?
+        .ok_or_else(|| {
+            anyhow!(
+                "contributor stats state for {} was not recorded",
+                repository_id
+            )
+        })
+}
+
+/// Authors of commits made between `since` and `until` (Unix seconds, inclusive), the
+/// [`MAX_CONTRIBUTORS`] with the most commits.
+pub async fn contributors(
+    pool: &SqlitePool,
+    repository_id: &str,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<Vec<ContributorStat>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT (SELECT latest.author_name FROM repository_commit_stats latest \
+         WHERE latest.repository_id = stats.repository_id \
+         AND lower(latest.author_email) = lower(stats.author_email) \
+         ORDER BY latest.authored_at DESC LIMIT 1) AS name, \
+         lower(author_email) AS email, COUNT(*) AS commits, \
+         SUM(additions) AS additions, SUM(deletions) AS deletions, \
+         MIN(authored_at) AS first_commit_at, MAX(authored_at) AS last_commit_at \
+         FROM repository_commit_stats stats \
+         WHERE repository_id = ? AND (? IS NULL OR authored_at >= ?) \
+         AND (? IS NULL OR authored_at <= ?) \
+         GROUP BY lower(author_email) \
+         ORDER BY commits DESC, additions + deletions DESC, email \
+         LIMIT ?",
+    )
+    .bind(repository_id)
+    .bind(since)
+    .bind(since)
+    .bind(until)
+    .bind(until)
+    .bind(MAX_CONTRIBUTORS)
+    .fetch_all(pool)
+    .await
+}
+
+/// Weekly totals between `since` and `until`, oldest first. Weeks without commits are
+/// left out.
+pub async fn code_frequency(
+    pool: &SqlitePool,
+    repository_id: &str,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<Vec<CodeFrequencyWeek>, sqlx::Error> {
+    // 1970-01-05 was a Monday.
+    sqlx::query_as(
+        "SELECT (authored_at - ((authored_at - 4 * 86400) % ? + ?) % ?) AS week_start, \
+         COUNT(*) AS commits, SUM(additions) AS additions, SUM(deletions) AS deletions \
+         FROM repository_commit_stats \
+         WHERE repository_id = ? AND (? IS NULL OR authored_at >= ?) \
+         AND (? IS NULL OR authored_at <= ?) \
+         GROUP BY week_start ORDER BY week_start",
+    )
+    .bind(WEEK_SECS)
+    .bind(WEEK_SECS)
+    .bind(WEEK_SECS)
+    .bind(repository_id)
+    .bind(since)
+    .bind(since)
+    .bind(until)
+    .bind(until)
+    .fetch_all(pool)
+    .await
+}
+
+/// What a walk of the default branch found.
+struct Walk {
+    commit_oid: String,
+    branch: Option<String>,
+    /// Commits not counted before
+    counted: Vec<CommitStat>,
+    /// Every new commit was reached; otherwise another run continues
+    complete: bool,
+    /// Every commit on the branch, when it was rewritten and walked in full
+    reachable: Option<HashSet<String>>,
+}
+
+/// Walks HEAD's history and diffs the non-merge commits not in `known`, at most
+/// [`MAX_COMMITS_PER_RUN`] of them. When `previous` (the last commit fully counted) is
+/// still in the history, only what came after it is walked. `None` when HEAD has no
+/// commits yet.
+fn walk_history(
+    dir: &Path,
+    previous: Option<&str>,
+    known: &HashSet<String>,
+) -> anyhow::Result<Option<Walk>> {
+    let repo = gix::open(dir)
+        .map_err(|err| anyhow!("failed to open repository at {}: {}", dir.display(), err))?;
+    let Ok(head) = repo.head_commit() else {
+        return Ok(None);
+    };
+    let branch = repo.head_name()?.map(|name| name.shorten().to_string());
+    let tip = head.id;
+
+    // A previous tip that is gone or no longer an ancestor means the branch was rewritten.
+    let previous = previous
+        .and_then(|oid| gix::ObjectId::from_hex(oid.as_bytes()).ok())
+        .filter(|oid| repo.find_commit(*oid).is_ok());
+    let fast_forward = match previous {
+        Some(previous) => merge_base(&repo, previous, tip)? == Some(previous),
+        None => false,
+    };
+    let mut walk = repo
+        .rev_walk([tip])
+        .sorting(Sorting::ByCommitTime(CommitTimeOrder::NewestFirst));
+    if fast_forward {
+        walk = walk.with_hidden(previous);
+    }
+    let walk = walk
+        .all()
+        .map_err(|err| anyhow!("failed to walk history from {}: {}", tip, err))?;
+
+    let mut cache = repo.diff_resource_cache_for_tree_diff()?;
+    let mut counted = Vec::new();
+    let mut reachable = (!fast_forward).then(HashSet::new);
+    let mut complete = true;
+    for info in walk {
+        let info = info?;
+        let oid = info.id.to_string();
+        let is_new = !known.contains(&oid);
+        if let Some(reachable) = &mut reachable {
+            reachable.insert(oid.clone());
+        }
+        if !is_new || info.parent_ids.len() > 1 {
+            continue;
+        }
+        if counted.len() == MAX_COMMITS_PER_RUN {
+            complete = false;
+            break;
+        }
+        let commit = info.object()?;
+        let author = commit.author()?;
+        let changes = commit_changes(&repo, &commit, &mut cache, ChangeDetail::Stats)?;
+        counted.push(CommitStat {
+            oid,
+            author_name: author.name.to_string(),
+            author_email: author.email.to_string(),
+            authored_at: author.seconds(),
+            additions: changes.additions as i64,
+            deletions: changes.deletions as i64,
+        });
+    }
+    Ok(Some(Walk {
+        commit_oid: tip.to_string(),
+        branch,
+        counted,
+        complete,
+        reachable: reachable.filter(|_| complete),
+    }))
+}
+
+/// Counts the default branch's new commits. Returns `false` when commits were left for
+/// another run.
+pub async fn update_contributor_stats(
+    pool: &SqlitePool,
+    repository_id: &str,
+    dir: PathBuf,
+) -> anyhow::Result<bool> {
+    let status = contributor_stats_status(pool, repository_id).await?;
+    let previous = status.and_then(|status| status.commit_oid);
+    let known: HashSet<String> = sqlx::query_scalar(
+        "SELECT commit_oid FROM repository_commit_stats WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+    let started_at = unix_now();
+    let known_commits = known.clone();
+    let Some(walk) =
+        task::spawn_blocking(move || walk_history(&dir, previous.as_deref(), &known_commits))
+            .await??
+    else {
+        return Ok(true);
+    };
+
+    let mut tx = pool.begin().await?;
+    if let Some(reachable) = &walk.reachable {
+        for oid in known.iter().filter(|oid| !reachable.contains(*oid)) {
+            sqlx::query(
+                "DELETE FROM repository_commit_stats WHERE repository_id = ? AND commit_oid = ?",
+            )
+            .bind(repository_id)
+            .bind(oid)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    for commit in &walk.counted {
+        sqlx::query(
+            "INSERT OR IGNORE INTO repository_commit_stats (repository_id, commit_oid, \
+             author_name, author_email, authored_at, additions, deletions) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(repository_id)
+        .bind(&commit.oid)
+        .bind(&commit.author_name)
+        .bind(&commit.author_email)
+        .bind(commit.authored_at)
+        .bind(commit.additions)
+        .bind(commit.deletions)
+        .execute(&mut *tx)
+        .await?;
+    }
+    if walk.complete {
+        // A request made while this run was walking the history stays pending
+        sqlx::query(
+            "INSERT INTO repository_contributor_stats \
+             (repository_id, commit_oid, branch, computed_at, requested_at) \
+             VALUES (?, ?, ?, ?, ?) \
+             ON CONFLICT(repository_id) DO UPDATE SET commit_oid = excluded.commit_oid, \
+             branch = excluded.branch, computed_at = excluded.computed_at",
+        )
+        .bind(repository_id)
+        .bind(&walk.commit_oid)
+        .bind(&walk.branch)
+        .bind(started_at)
+        .bind(started_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(walk.complete)
+}
+
+/// Job handler for [`JOB_KIND`].
+pub struct ContributorStatsJob {
+    pool: SqlitePool,
+    storage: Arc<RepositoryStorage>,
+}
+
+impl ContributorStatsJob {
+    pub fn new(pool: SqlitePool, storage: Arc<RepositoryStorage>) -> Self {
+        Self { pool, storage }
+    }
+}
+
+#[async_trait]
+impl JobHandler for ContributorStatsJob {
+    async fn run(&self, job: &JobRecord) -> anyhow::Result<()> {
+        let payload: ContributorStatsPayload = job.payload()?;
+        // Deleted since the job was queued
+        let Some(record) = get_repository_by_id(&self.pool, &payload.repository_id).await? else {
+            return Ok(());
+        };
+        let path = reconstruct_repository_path(&self.pool, &record).await?;
+        let segments: Vec<String> = path.split('/').map(str::to_string).collect();
+        let dir = self.storage.repository_path(&segments);
+        if !tokio::fs::try_exists(&dir).await? {
+            return Ok(());
+        }
+        if !update_contributor_stats(&self.pool, &record.id, dir).await? {
+            enqueue_contributor_stats(&self.pool, &record.id).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::{create_test_pool, git_with_env};
+
+    /// Monday 2024-03-04 00:00 UTC
+    const MONDAY: i64 = 1_709_510_400;
+
+    fn git(dir: &Path, author: &str, time: i64, args: &[&str]) {
+        let email = format!("{}@Example.org", author.to_lowercase());
+        let date = format!("@{} +0000", time);
+        git_with_env(
+            dir,
+            &[
+                ("GIT_AUTHOR_NAME", author),
+                ("GIT_AUTHOR_EMAIL", &email),
+                ("GIT_AUTHOR_DATE", &date),
+                ("GIT_COMMITTER_DATE", &date),
+            ],
+            args,
+        );
+    }
+
+    fn commit(dir: &Path, author: &str, time: i64, file: &str, contents: &str) {
+        std::fs::write(dir.join(file), contents).unwrap();
+        git(dir, author, time, &["add", "."]);
+        git(dir, author, time, &["commit", "-q", "-m", file]);
+    }
+
+    fn summary(stats: &[ContributorStat]) -> Vec<(&str, i64, i64, i64)> {
+        stats
+            .iter()
+            .map(|stat| {
+                (
+                    stat.name.as_str(),
+                    stat.commits,
+                    stat.additions,
+                    stat.deletions,
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn stats_follow_the_default_branch() {
+        let pool = create_test_pool().await.unwrap();
+        let input = CreateRepositoryInput {
+            slug: "tools".into(),
+            group: None,
+        };
+        let id = create_repository_raw(&pool, input).await.unwrap().id;
+        let tmp = tempfile::tempdir().unwrap();
+        let work = tmp.path();
+        git(work, "Ada", MONDAY, &["init", "-q", "-b", "main"]);
+        commit(work, "Ada", MONDAY + 3_600, "a.txt", "1\n2\n3\n");
+        commit(work, "Brian", MONDAY + 86_400, "b.txt", "1\n");
+        commit(work, "Ada", MONDAY + WEEK_SECS + 60, "a.txt", "1\n3\n4\n");
+        let git_dir = work.join(".git");
+
+        let status = ensure_contributor_stats(&pool, &id).await.unwrap();
+        assert!(status.pending() && status.commit_oid.is_none());
+        assert!(
+            update_contributor_stats(&pool, &id, git_dir.clone())
+                .await
+                .unwrap()
+        );
+        let status = contributor_stats_status(&pool, &id).await.unwrap().unwrap();
+        assert!(!status.pending());
+        assert_eq!(status.branch.as_deref(), Some("main"));
+
+        let all = contributors(&pool, &id, None, None).await.unwrap();
+        assert_eq!(summary(&all), [("Ada", 2, 4, 1), ("Brian", 1, 1, 0)]);
+        assert_eq!(all[0].email, "ada@example.org");
+        assert_eq!(
+            (all[0].first_commit_at, all[0].last_commit_at),
+            (MONDAY + 3_600, MONDAY + WEEK_SECS + 60)
+        );
+        let weeks = code_frequency(&pool, &id, None, None).await.unwrap();
+        assert_eq!(
+            weeks,
+            [
+                CodeFrequencyWeek {
+                    week_start: MONDAY,
+                    commits: 2,
+                    additions: 4,
+                    deletions: 0,
+                },
+                CodeFrequencyWeek {
+                    week_start: MONDAY + WEEK_SECS,
+                    commits: 1,
+                    additions: 1,
+                    deletions: 1,
+                },
+            ]
+        );
+        let second_week = contributors(&pool, &id, Some(MONDAY + WEEK_SECS), None)
+            .await
+            .unwrap();
+        assert_eq!(summary(&second_week), [("Ada", 1, 1, 1)]);
+
+        // A new commit is counted on its own; rewriting history drops what left it.
+        commit(work, "Brian", MONDAY + WEEK_SECS + 120, "b.txt", "1\n2\n");
+        update_contributor_stats(&pool, &id, git_dir.clone())
+            .await
+            .unwrap();
+        let all = contributors(&pool, &id, None, None).await.unwrap();
+        assert_eq!(summary(&all), [("Ada", 2, 4, 1), ("Brian", 2, 2, 0)]);
+
+        git(work, "Ada", MONDAY, &["reset", "-q", "--hard", "HEAD~2"]);
+        commit(work, "Carol", MONDAY + WEEK_SECS + 180, "c.txt", "1\n");
+        update_contributor_stats(&pool, &id, git_dir).await.unwrap();
+        let all = contributors(&pool, &id, None, None).await.unwrap();
+        assert_eq!(
+            summary(&all),
+            [("Ada", 1, 3, 0), ("Brian", 1, 1, 0), ("Carol", 1, 1, 0)]
+        );
+    }
+}
@@ -9,7 +9,7 @@ pub async fn slug_conflicts_for_repository(
 ) -> Result<bool, sqlx::Error> {
     if let Some(group_id) = group_id {
         let exists: Option<i64> = sqlx::query_scalar(
-            "SELECT 1 FROM repositories WHERE slug = ? AND \"group\" = ? LIMIT 1",
+            "SELECT 1 FROM repositories WHERE slug = ? COLLATE NOCASE AND \"group\" = ? LIMIT 1",
         )
         .bind(slug)
         .bind(group_id)
@@ -18,7 +18,7 @@ pub async fn slug_conflicts_for_repository(
         Ok(exists.is_some())
     } else {
         let exists: Option<i64> = sqlx::query_scalar(
-            "SELECT 1 FROM repositories WHERE slug = ? AND \"group\" IS NULL LIMIT 1",
+            "SELECT 1 FROM repositories WHERE slug = ? COLLATE NOCASE AND \"group\" IS NULL LIMIT 1",
         )
         .bind(slug)
         .fetch_optional(pool)
@@ -52,7 +52,7 @@ pub async fn resolve_repository_by_path(
     match group_id.as_deref() {
         Some(group_id) => {
             sqlx::query_as::<_, RepositoryRecord>(
-                "SELECT id, slug, \"group\" as group_id, remote_url FROM repositories WHERE slug = ? AND \"group\" = ?",
+                "SELECT id, slug, \"group\" as group_id, remote_url FROM repositories WHERE slug = ? COLLATE NOCASE AND \"group\" = ?",
             )
             .bind(repo_slug)
             .bind(group_id)
@@ -61,7 +61,7 @@ pub async fn resolve_repository_by_path(
         }
         None => {
             sqlx::query_as::<_, RepositoryRecord>(
-                "SELECT id, slug, \"group\" as group_id, remote_url FROM repositories WHERE slug = ? AND \"group\" IS NULL",
+                "SELECT id, slug, \"group\" as group_id, remote_url FROM repositories WHERE slug = ? COLLATE NOCASE AND \"group\" IS NULL",
             )
             .bind(repo_slug)
             .fetch_optional(pool)
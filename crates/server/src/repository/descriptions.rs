@@ -0,0 +1,180 @@
+//! Per-language repository descriptions.
+//!
+//! A repository has at most one description per language tag plus a default, stored with
+//! an empty language. Lookups follow the visitor's preferences with the same fallback as
+//! localized READMEs, then the default, then an English description.
+
+use sqlx::SqlitePool;
+
+use super::discovery::index_repository;
+use super::language::{best_language_match, normalize_language_tag};
+use crate::user::db::unix_now;
+
+pub const MAX_DESCRIPTION_LEN: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, sqlx::FromRow)]
+pub struct RepositoryDescription {
+    /// Normalized tag, or `None` for the default description.
+    pub language: Option<String>,
+    pub description: String,
+    pub updated_at: i64,
+}
+
+/// All descriptions of a repository, the default first.
+pub async fn list_repository_descriptions(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> anyhow::Result<Vec<RepositoryDescription>> {
+    let rows = sqlx::query_as::<_, RepositoryDescription>(
+        "SELECT NULLIF(language, '') AS language, description, updated_at \
+         FROM repository_descriptions WHERE repository_id = ? ORDER BY language",
+    )
+    .bind(repository_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Sets the description for `language` (the default when `None`). An empty description
+/// removes it.
+pub async fn set_repository_description(
+    pool: &SqlitePool,
+    repository_id: &str,
+    language: Option<&str>,
+    description: &str,
+) -> anyhow::Result<()> {
+    let language = match language.map(str::trim).filter(|tag| !tag.is_empty()) {
+        Some(tag) => normalize_language_tag(tag)
+            .ok_or_else(|| anyhow::anyhow!("`{}` is not a valid language tag", tag))?,
+        None => String::new(),
+    };
+    let description = description.trim();
+    if description.chars().count() > MAX_DESCRIPTION_LEN {
+        return Err(anyhow::anyhow!(
+            "description must be at most {} characters",
+            MAX_DESCRIPTION_LEN
+        ));
+    }
+
+    if description.is_empty() {
+        sqlx::query("DELETE FROM repository_descriptions WHERE repository_id = ? AND language = ?")
+            .bind(repository_id)
+            .bind(&language)
+            .execute(pool)
+            .await?;
+        return index_repository(pool, repository_id).await;
+    }
+    sqlx::query(
+        "INSERT INTO repository_descriptions (repository_id, language, description, updated_at) \
+         VALUES (?, ?, ?, ?) \
+         ON CONFLICT(repository_id, language) DO UPDATE SET \
+         description = excluded.description, updated_at = excluded.updated_at",
+    )
+    .bind(repository_id)
+    .bind(&language)
+    .bind(description)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    index_repository(pool, repository_id).await
+}
+
+/// The description that best serves `preferences`, if the repository has any.
+pub fn select_description<'a>(
+    descriptions: &'a [RepositoryDescription],
+    preferences: &[String],
+) -> Option<&'a RepositoryDescription> {
+    let localized: Vec<&RepositoryDescription> = descriptions
+        .iter()
+        .filter(|d| d.language.is_some())
+        .collect();
+    let languages: Vec<String> = localized
+        .iter()
+        .filter_map(|d| d.language.clone())
+        .collect();
+
+    if let Some(index) = best_language_match(&languages, preferences) {
+        return Some(localized[index]);
+    }
+    if let Some(default) = descriptions.iter().find(|d| d.language.is_none()) {
+        return Some(default);
+    }
+    let index = best_language_match(&languages, &["en".to_string()]).unwrap_or(0);
+    localized.get(index).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    async fn repository(pool: &SqlitePool) -> String {
+        sqlx::query("INSERT INTO repositories (id, slug) VALUES ('repo1', 'widgets')")
+            .execute(pool)
+            .await
+            .unwrap();
+        "repo1".to_string()
+    }
+
+    fn text(found: Option<&RepositoryDescription>) -> Option<&str> {
+        found.map(|d| d.description.as_str())
+    }
+
+    #[tokio::test]
+    async fn descriptions_fall_back_to_language_then_default() {
+        let pool = create_test_pool().await.unwrap();
+        let id = repository(&pool).await;
+        set_repository_description(&pool, &id, None, "Widgets for everyone")
+            .await
+            .unwrap();
+        set_repository_description(&pool, &id, Some("de"), "Widgets für alle")
+            .await
+            .unwrap();
+        set_repository_description(&pool, &id, Some("pt_BR"), "Widgets para todos")
+            .await
+            .unwrap();
+
+        let all = list_repository_descriptions(&pool, &id).await.unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].language, None);
+        let prefs = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            text(select_description(&all, &prefs(&["de-at", "en"]))),
+            Some("Widgets für alle")
+        );
+        assert_eq!(
+            text(select_description(&all, &prefs(&["pt"]))),
+            Some("Widgets para todos")
+        );
+        assert_eq!(
+            text(select_description(&all, &prefs(&["fr"]))),
+            Some("Widgets for everyone")
+        );
+
+        set_repository_description(&pool, &id, None, "  ")
+            .await
+            .unwrap();
+        let all = list_repository_descriptions(&pool, &id).await.unwrap();
+        assert_eq!(
+            text(select_description(&all, &[])),
+            Some("Widgets für alle")
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_descriptions_are_rejected() {
+        let pool = create_test_pool().await.unwrap();
+        let id = repository(&pool).await;
+        assert!(
+            set_repository_description(&pool, &id, Some("not a tag"), "x")
+                .await
+                .is_err()
+        );
+        let long = "x".repeat(MAX_DESCRIPTION_LEN + 1);
+        assert!(
+            set_repository_description(&pool, &id, None, &long)
+                .await
+                .is_err()
+        );
+    }
+}
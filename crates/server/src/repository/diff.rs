@@ -0,0 +1,395 @@
+//! File-level diffs between two revisions, with unified patches.
+//!
+//! [`diff_revisions`] backs the `diff` query; [`tree_changes`] is shared with
+//! `listCommits` and `getCommit`, which diff a commit against its first parent. Renames
+//! are detected the way `git diff` does by default, and line counts match
+//! `git diff --numstat`, including for files that gain or lose their final newline.
+
+use std::ops::Range;
+
+use gix::diff::blob::intern::InternedInput;
+use gix::diff::blob::platform::prepare_diff::Operation;
+use gix::diff::blob::sources::byte_lines_with_terminator;
+use tokio::task;
+
+use super::compare::{merge_base, open, resolve_commit};
+use super::models::{ChangeStatus, ChangedFile, CommitChanges};
+use super::resolver::PathResolver;
+use super::storage::RepositoryStorage;
+
+/// Changed files listed per diff. The totals still count every file.
+pub const MAX_DIFF_FILES: usize = 300;
+/// A longer patch is cut at the last whole line before the limit.
+const MAX_PATCH_BYTES: usize = 100 * 1024;
+/// Patch text per diff. Files past it are listed without a patch.
+const MAX_TOTAL_PATCH_BYTES: usize = 1024 * 1024;
+/// Unchanged lines shown around each change, as with `git diff -U3`.
+const CONTEXT_LINES: u32 = 3;
+
+/// How much [`tree_changes`] reads about each changed file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChangeDetail {
+    /// Paths, statuses and line counts
+    Stats,
+    /// Everything in `Stats`, plus a unified patch for each text file
+    Patches,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepositoryDiff {
+    pub base_oid: String,
+    pub head_oid: String,
+    /// The commit `head` was compared with, when diffing from the merge base.
+    pub merge_base: Option<String>,
+    pub changes: CommitChanges,
+}
+
+/// Diffs `base` against `head` in the repository at `path`. With `from_merge_base`,
+/// `head` is compared with the newest commit both sides share, the way a pull request
+/// shows only the changes made on its branch. Returns `None` when the repository does
+/// not exist.
+pub async fn diff_revisions(
+    resolver: &PathResolver,
+    storage: &RepositoryStorage,
+    path: &str,
+    base: String,
+    head: String,
+    from_merge_base: bool,
+    detail: ChangeDetail,
+) -> anyhow::Result<Option<RepositoryDiff>> {
+    let Some(resolved) = resolver.resolve_repository(path).await? else {
+        return Ok(None);
+    };
+    let repository_path = resolved.local_dir(storage)?;
+    let diff = task::spawn_blocking(move || {
+        let repo = open(&repository_path)?;
+        let base_id = resolve_commit(&repo, &base)?;
+        let head_id = resolve_commit(&repo, &head)?;
+        let (from, merge_base) = match from_merge_base {
+            true => {
+                let merge_base = merge_base(&repo, base_id, head_id)?.ok_or_else(|| {
+                    anyhow::anyhow!("`{}` and `{}` have no common history", base, head)
+                })?;
+                (merge_base, Some(merge_base.to_string()))
+            }
+            false => (base_id, None),
+        };
+        let old_tree = repo.find_commit(from)?.tree()?;
+        let new_tree = repo.find_commit(head_id)?.tree()?;
+        let mut cache = repo.diff_resource_cache_for_tree_diff()?;
+        let changes = tree_changes(&old_tree, &new_tree, &mut cache, detail)?;
+        Ok::<_, anyhow::Error>(RepositoryDiff {
+            base_oid: base_id.to_string(),
+            head_oid: head_id.to_string(),
+            merge_base,
+            changes,
+        })
+    })
+    .await
+    .map_err(|err| anyhow::anyhow!(err))??;
+    Ok(Some(diff))
+}
+
+/// Files changed between two trees, at most [`MAX_DIFF_FILES`] of them.
+pub(super) fn tree_changes(
+    old_tree: &gix::Tree<'_>,
+    new_tree: &gix::Tree<'_>,
+    cache: &mut gix::diff::blob::Platform,
+    detail: ChangeDetail,
+) -> anyhow::Result<CommitChanges> {
+    // An external diff driver configured through attributes would leave us without hunks.
+    cache.options.skip_internal_diff_if_external_is_configured = false;
+    let mut changes = CommitChanges::default();
+    let mut patch_budget = MAX_TOTAL_PATCH_BYTES;
+    old_tree
+        .changes()?
+        .options(|options| {
+            options
+                .track_path()
+                .track_rewrites(Some(Default::default()));
+        })
+        .for_each_to_obtain_tree(new_tree, |change| {
+            use gix::object::tree::diff::{Action, Change};
+
+            if !change.entry_mode().is_blob_or_symlink() {
+                return Ok::<_, anyhow::Error>(Action::Continue);
+            }
+            let (status, previous_path) = match change {
+                Change::Addition { .. } => (ChangeStatus::Added, None),
+                Change::Deletion { .. } => (ChangeStatus::Deleted, None),
+                Change::Modification { .. } => (ChangeStatus::Modified, None),
+                Change::Rewrite {
+                    source_location,
+                    copy,
+                    ..
+                } => match copy {
+                    true => (ChangeStatus::Added, None),
+                    false => (ChangeStatus::Renamed, Some(source_location.to_string())),
+                },
+            };
+            let listed = changes.files.len() < MAX_DIFF_FILES;
+            let want_patch = listed && detail == ChangeDetail::Patches && patch_budget > 0;
+            let platform = change.diff(cache)?;
+            let prepared = platform.resource_cache.prepare_diff()?;
+            let text = match prepared.operation {
+                Operation::InternalDiff { algorithm } => Some((
+                    algorithm,
+                    prepared.old.data.as_slice().unwrap_or_default(),
+                    prepared.new.data.as_slice().unwrap_or_default(),
+                )),
+                Operation::ExternalCommand { .. } | Operation::SourceOrDestinationIsBinary => None,
+            };
+            let mut file = ChangedFile {
+                path: change.location().to_string(),
+                previous_path,
+                status,
+                additions: 0,
+                deletions: 0,
+                binary: text.is_none(),
+                patch: None,
+                patch_truncated: false,
+            };
+            if let Some((algorithm, old, new)) = text {
+                let input = InternedInput::new(
+                    byte_lines_with_terminator(old),
+                    byte_lines_with_terminator(new),
+                );
+                let mut hunks = Vec::new();
+                gix::diff::blob::diff(
+                    algorithm,
+                    &input,
+                    |before: Range<u32>, after: Range<u32>| {
+                        hunks.push((before, after));
+                    },
+                );
+                for (before, after) in &hunks {
+                    file.deletions += before.end - before.start;
+                    file.additions += after.end - after.start;
+                }
+                if want_patch {
+                    let old_lines: Vec<&[u8]> = input
+                        .before
+                        .iter()
+                        .map(|&token| input.interner[token])
+                        .collect();
+                    let new_lines: Vec<&[u8]> = input
+                        .after
+                        .iter()
+                        .map(|&token| input.interner[token])
+                        .collect();
+                    let mut patch = unified_patch(&old_lines, &new_lines, &hunks);
+                    let limit = MAX_PATCH_BYTES.min(patch_budget);
+                    if patch.len() > limit {
+                        patch.truncate(patch[..limit].rfind('\n').map_or(0, |end| end + 1));
+                        file.patch_truncated = true;
+                    }
+                    patch_budget -= patch.len().min(patch_budget);
+                    file.patch = Some(patch);
+                } else if listed && detail == ChangeDetail::Patches {
+                    file.patch_truncated = true;
+                }
+            }
+            changes.files_changed += 1;
+            changes.additions += u64::from(file.additions);
+            changes.deletions += u64::from(file.deletions);
+            match listed {
+                true => changes.files.push(file),
+                false => changes.truncated = true,
+            }
+            Ok(Action::Continue)
+        })?;
+    cache.clear_resource_cache();
+    Ok(changes)
+}
+
+/// The hunks of a unified diff, without the `---`/`+++` file header. Changes closer
+/// together than twice the context share a hunk.
+fn unified_patch(old: &[&[u8]], new: &[&[u8]], hunks: &[(Range<u32>, Range<u32>)]) -> String {
+    let mut patch = String::new();
+    let mut index = 0;
+    while index < hunks.len() {
+        let mut last = index;
+        while last + 1 < hunks.len()
+            && hunks[last + 1].0.start - hunks[last].0.end <= 2 * CONTEXT_LINES
+        {
+            last += 1;
+        }
+        let (first_before, first_after) = &hunks[index];
+        let (last_before, last_after) = &hunks[last];
+        let leading = first_before.start.min(CONTEXT_LINES);
+        let trailing = (old.len() as u32 - last_before.end).min(CONTEXT_LINES);
+        let old_start = first_before.start - leading;
+        let new_start = first_after.start - leading;
+        let old_len = last_before.end + trailing - old_start;
+        let new_len = last_after.end + trailing - new_start;
+        patch.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_len),
+            hunk_range(new_start, new_len)
+        ));
+
+        let mut position = old_start;
+        for (before, after) in &hunks[index..=last] {
+            push_lines(
+                &mut patch,
+                ' ',
+                &old[position as usize..before.start as usize],
+            );
+            push_lines(
+                &mut patch,
+                '-',
+                &old[before.start as usize..before.end as usize],
+            );
+            push_lines(
+                &mut patch,
+                '+',
+                &new[after.start as usize..after.end as usize],
+            );
+            position = before.end;
+        }
+        push_lines(
+            &mut patch,
+            ' ',
+            &old[position as usize..(position + trailing) as usize],
+        );
+        index = last + 1;
+    }
+    patch
+}
+
+/// `start,len` as in a hunk header: 1-based, with an empty range starting at the line
+/// before it and a length of one left out.
+fn hunk_range(start: u32, len: u32) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+fn push_lines(patch: &mut String, prefix: char, lines: &[&[u8]]) {
+    for line in lines {
+        patch.push(prefix);
+        patch.push_str(&String::from_utf8_lossy(line));
+        if !line.ends_with(b"\n") {
+            patch.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::git;
+    use std::path::Path;
+
+    fn diff(repo: &gix::Repository, base: &str, head: &str, detail: ChangeDetail) -> CommitChanges {
+        let old_tree = repo
+            .find_commit(resolve_commit(repo, base).unwrap())
+            .unwrap();
+        let new_tree = repo
+            .find_commit(resolve_commit(repo, head).unwrap())
+            .unwrap();
+        let mut cache = repo.diff_resource_cache_for_tree_diff().unwrap();
+        tree_changes(
+            &old_tree.tree().unwrap(),
+            &new_tree.tree().unwrap(),
+            &mut cache,
+            detail,
+        )
+        .unwrap()
+    }
+
+    /// The hunks of `git diff`, without the file header and the function context git
+    /// adds to hunk headers.
+    fn git_patch(dir: &Path, base: &str, head: &str, file: &str) -> String {
+        let full = git(dir, &["diff", "--no-color", "-U3", base, head, "--", file]);
+        let start = full.find("@@").unwrap();
+        full[start..]
+            .lines()
+            .map(|line| match line.strip_prefix("@@") {
+                Some(rest) => format!("@@{}@@\n", &rest[..rest.find("@@").unwrap()]),
+                None => format!("{}\n", line),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn patches_match_git_diff() {
+        let tmp = tempfile::tempdir().unwrap();
+        let work = tmp.path();
+        git(work, &["init", "-q", "-b", "main"]);
+        let lines: Vec<String> = (1..=30).map(|n| format!("line {}\n", n)).collect();
+        std::fs::write(work.join("list.txt"), lines.concat()).unwrap();
+        std::fs::write(work.join("tail.txt"), "one\ntwo").unwrap();
+        std::fs::write(work.join("gone.txt"), "bye\n").unwrap();
+        git(work, &["add", "."]);
+        git(work, &["commit", "-q", "-m", "base"]);
+
+        let mut changed = lines.clone();
+        changed[1] = "line two\n".to_string();
+        changed[6] = "line seven\n".to_string();
+        changed.remove(20);
+        changed.push("line 31\n".to_string());
+        std::fs::write(work.join("list.txt"), changed.concat()).unwrap();
+        std::fs::write(work.join("tail.txt"), "one\ntwo\n").unwrap();
+        std::fs::remove_file(work.join("gone.txt")).unwrap();
+        git(work, &["add", "-A"]);
+        git(work, &["commit", "-q", "-m", "head"]);
+
+        let repo = open(&work.join(".git")).unwrap();
+        let changes = diff(&repo, "HEAD~1", "HEAD", ChangeDetail::Patches);
+        assert_eq!(changes.files_changed, 3);
+        let file = |path: &str| changes.files.iter().find(|f| f.path == path).unwrap();
+        for path in ["list.txt", "tail.txt", "gone.txt"] {
+            assert_eq!(
+                file(path).patch.as_deref(),
+                Some(git_patch(work, "HEAD~1", "HEAD", path).as_str()),
+                "{}",
+                path
+            );
+        }
+        assert_eq!(
+            (file("list.txt").additions, file("list.txt").deletions),
+            (3, 3)
+        );
+        assert_eq!(
+            (file("tail.txt").additions, file("tail.txt").deletions),
+            (1, 1)
+        );
+        assert_eq!(file("gone.txt").status, ChangeStatus::Deleted);
+
+        let stats = diff(&repo, "HEAD~1", "HEAD", ChangeDetail::Stats);
+        assert!(stats.files.iter().all(|file| file.patch.is_none()));
+        assert_eq!((stats.additions, stats.deletions), (4, 5));
+    }
+
+    #[test]
+    fn renames_keep_their_previous_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let work = tmp.path();
+        git(work, &["init", "-q", "-b", "main"]);
+        let text: String = (1..=20).map(|n| format!("row {}\n", n)).collect();
+        std::fs::write(work.join("old.txt"), &text).unwrap();
+        git(work, &["add", "."]);
+        git(work, &["commit", "-q", "-m", "base"]);
+        git(work, &["mv", "old.txt", "new.txt"]);
+        std::fs::write(
+            work.join("new.txt"),
+            text.replace("row 20\n", "row twenty\n"),
+        )
+        .unwrap();
+        git(work, &["commit", "-q", "-am", "move"]);
+
+        let repo = open(&work.join(".git")).unwrap();
+        let changes = diff(&repo, "HEAD~1", "HEAD", ChangeDetail::Patches);
+        assert_eq!(changes.files.len(), 1);
+        let file = &changes.files[0];
+        assert_eq!(file.status, ChangeStatus::Renamed);
+        assert_eq!(file.previous_path.as_deref(), Some("old.txt"));
+        assert_eq!(
+            file.patch.as_deref(),
+            Some("@@ -17,4 +17,4 @@\n row 17\n row 18\n row 19\n-row 20\n+row twenty\n")
+        );
+    }
+}
@@ -0,0 +1,666 @@
+//! Repository discovery: `findRepositories`.
+//!
+//! Without a query, repositories are listed straight from the `repositories` table in the
+//! requested order, each sort backed by an index on `(key DESC, id)`. With a query,
+//! matching is typo tolerant: names and descriptions are split into words, and a query
+//! word matches a word whose trigrams are similar enough, the measure PostgreSQL's
+//! `pg_trgm` uses. The trigrams of every repository are kept in
+//! `repository_search_trigrams`, so candidates are found with an index lookup and only
+//! they are scored.
+//!
+//! [`score`] is the whole ranking function. `repositorySearchScore` returns its parts for
+//! one repository, which is how to find out why a repository ranks where it does.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashSet};
+
+use anyhow::bail;
+use sqlx::SqlitePool;
+
+use super::models::RepositoryRecord;
+use super::visibility::HIDDEN;
+use crate::graphql::pagination::{Direction, Page, PageRequest, encode_cursor};
+
+/// Two words match when their trigram similarity reaches this, as in `pg_trgm`.
+pub const MIN_SIMILARITY: f64 = 0.3;
+const NAME_WEIGHT: f64 = 2.0;
+const DESCRIPTION_WEIGHT: f64 = 1.0;
+const EXACT_WEIGHT: f64 = 1.0;
+/// Query words beyond this are ignored.
+const MAX_QUERY_WORDS: usize = 8;
+/// Candidates scored per query word; the rest are not considered.
+const MAX_CANDIDATES: i64 = 5000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiscoverySort {
+    Relevance,
+    Stars,
+    Pushed,
+    Created,
+    Name,
+}
+
+impl DiscoverySort {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        Ok(match value {
+            "RELEVANCE" => Self::Relevance,
+            "STARS" => Self::Stars,
+            "PUSHED" => Self::Pushed,
+            "CREATED" => Self::Created,
+            "NAME" => Self::Name,
+            other => bail!("unknown repository sort `{}`", other),
+        })
+    }
+
+    /// Cursors are only valid for the sort they came from.
+    pub fn cursor_kind(self) -> &'static str {
+        match self {
+            Self::Relevance => "discovery-relevance",
+            Self::Stars => "discovery-stars",
+            Self::Pushed => "discovery-pushed",
+            Self::Created => "discovery-created",
+            Self::Name => "discovery-name",
+        }
+    }
+
+    /// Sort column for the keyset queries; names use the `(slug, id)` index.
+    fn column(self) -> Option<&'static str> {
+        match self {
+            Self::Stars => Some("star_count"),
+            Self::Pushed => Some("pushed_at"),
+            Self::Created => Some("created_at"),
+            Self::Relevance | Self::Name => None,
+        }
+    }
+}
+
+/// How well a query matches one repository. Every part is between 0 and 1 except
+/// `total`, their weighted sum.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SearchScore {
+    /// Similarity of the query words to the closest words of the name.
+    pub name: f64,
+    /// Similarity of the query words to the closest words of the descriptions.
+    pub description: f64,
+    /// 1 when the name is the query, 0.5 when it starts with it.
+    pub exact: f64,
+    /// Rounded to six decimals, so it survives a round trip through a cursor.
+    pub total: f64,
+}
+
+impl SearchScore {
+    pub fn matches(&self) -> bool {
+        self.name >= MIN_SIMILARITY || self.description >= MIN_SIMILARITY
+    }
+}
+
+/// Scores `query` against a repository's name and the text of its descriptions.
+pub fn score(query: &str, name: &str, description: &str) -> SearchScore {
+    let query_words = query_words(query);
+    if query_words.is_empty() {
+        return SearchScore::default();
+    }
+    let name_similarity = field_similarity(&query_words, &words(name));
+    let description_similarity = field_similarity(&query_words, &words(description));
+    let query = query.trim().to_lowercase();
+    let name = name.to_lowercase();
+    let exact = if name == query {
+        1.0
+    } else if name.starts_with(&query) {
+        0.5
+    } else {
+        0.0
+    };
+    let total = NAME_WEIGHT * name_similarity
+        + DESCRIPTION_WEIGHT * description_similarity
+        + EXACT_WEIGHT * exact;
+    SearchScore {
+        name: name_similarity,
+        description: description_similarity,
+        exact,
+        total: (total * 1e6).round() / 1e6,
+    }
+}
+
+/// For each query word its best similarity to a word of the field, averaged.
+fn field_similarity(query_words: &[String], field_words: &[String]) -> f64 {
+    let field: Vec<BTreeSet<String>> = field_words.iter().map(|w| trigrams(w)).collect();
+    let sum: f64 = query_words
+        .iter()
+        .map(|word| {
+            let query = trigrams(word);
+            field
+                .iter()
+                .map(|candidate| similarity(&query, candidate))
+                .fold(0.0, f64::max)
+        })
+        .sum();
+    sum / query_words.len() as f64
+}
+
+/// Lowercase alphanumeric runs, so `forge-cli` is `forge` and `cli`.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn query_words(query: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    words(query)
+        .into_iter()
+        .filter(|word| seen.insert(word.clone()))
+        .take(MAX_QUERY_WORDS)
+        .collect()
+}
+
+/// Trigrams of one word, padded like `pg_trgm`: two spaces before and one after, so
+/// short words have trigrams too and the start of a word counts for more.
+fn trigrams(word: &str) -> BTreeSet<String> {
+    let padded: Vec<char> = format!("  {} ", word).chars().collect();
+    padded.windows(3).map(|w| w.iter().collect()).collect()
+}
+
+/// Shared trigrams over all distinct trigrams of the two words.
+fn similarity(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let shared = a.intersection(b).count();
+    let all = a.len() + b.len() - shared;
+    if all == 0 {
+        0.0
+    } else {
+        shared as f64 / all as f64
+    }
+}
+
+/// Rewrites the stored trigrams of a repository after its name or descriptions change.
+pub async fn index_repository(pool: &SqlitePool, repository_id: &str) -> anyhow::Result<()> {
+    let text: Option<(String, String)> = sqlx::query_as(
+        "SELECT slug, COALESCE((SELECT group_concat(description, ' ') \
+         FROM repository_descriptions WHERE repository_id = repositories.id), '') \
+         FROM repositories WHERE id = ?",
+    )
+    .bind(repository_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some((name, description)) = text else {
+        return Ok(());
+    };
+    let all: BTreeSet<String> = words(&name)
+        .iter()
+        .chain(words(&description).iter())
+        .flat_map(|word| trigrams(word))
+        .collect();
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM repository_search_trigrams WHERE repository_id = ?")
+        .bind(repository_id)
+        .execute(&mut *tx)
+        .await?;
+    for trigram in &all {
+        sqlx::query(
+            "INSERT INTO repository_search_trigrams (trigram, repository_id) VALUES (?, ?)",
+        )
+        .bind(trigram)
+        .bind(repository_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Indexes repositories that have no trigrams yet, such as those created before search
+/// existed. Returns how many were indexed.
+pub async fn index_missing(pool: &SqlitePool) -> anyhow::Result<usize> {
+    let ids: Vec<String> = sqlx::query_scalar(
+        "SELECT id FROM repositories WHERE NOT EXISTS \
+         (SELECT 1 FROM repository_search_trigrams WHERE repository_id = repositories.id)",
+    )
+    .fetch_all(pool)
+    .await?;
+    for id in &ids {
+        index_repository(pool, id).await?;
+    }
+    Ok(ids.len())
+}
+
+/// Records that refs of the repository moved at `now`.
+pub async fn record_push(
+    pool: &SqlitePool,
+    repository_id: &str,
+    now: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE repositories SET pushed_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(repository_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// A repository in discovery results.
+#[derive(Clone, Debug)]
+pub struct DiscoveredRepository {
+    pub record: RepositoryRecord,
+    pub star_count: i64,
+    /// Zero when unknown.
+    pub created_at: i64,
+    /// Zero when nothing was pushed yet.
+    pub pushed_at: i64,
+    /// Present when a query was given.
+    pub score: Option<SearchScore>,
+}
+
+impl DiscoveredRepository {
+    fn sort_key(&self, sort: DiscoverySort) -> String {
+        match sort {
+            DiscoverySort::Relevance => self.score.unwrap_or_default().total.to_string(),
+            DiscoverySort::Stars => self.star_count.to_string(),
+            DiscoverySort::Pushed => self.pushed_at.to_string(),
+            DiscoverySort::Created => self.created_at.to_string(),
+            DiscoverySort::Name => self.record.slug.clone(),
+        }
+    }
+
+    pub fn cursor(&self, sort: DiscoverySort) -> String {
+        encode_cursor(sort.cursor_kind(), &self.sort_key(sort), &self.record.id)
+    }
+
+    /// List order: the sort key (descending, except names) and then the id.
+    fn compare(&self, other: &Self, sort: DiscoverySort) -> Ordering {
+        let key = match sort {
+            DiscoverySort::Relevance => {
+                let total = |item: &Self| item.score.unwrap_or_default().total;
+                total(other).total_cmp(&total(self))
+            }
+            DiscoverySort::Stars => other.star_count.cmp(&self.star_count),
+            DiscoverySort::Pushed => other.pushed_at.cmp(&self.pushed_at),
+            DiscoverySort::Created => other.created_at.cmp(&self.created_at),
+            DiscoverySort::Name => self.record.slug.cmp(&other.record.slug),
+        };
+        key.then_with(|| self.record.id.cmp(&other.record.id))
+    }
+
+    /// Whether this repository comes after the cursor's position in list order.
+    fn is_after(&self, sort: DiscoverySort, key: &str, id: &str) -> anyhow::Result<bool> {
+        let key = match sort {
+            DiscoverySort::Relevance => {
+                let cursor: f64 = key.parse()?;
+                cursor.total_cmp(&self.score.unwrap_or_default().total)
+            }
+            DiscoverySort::Stars | DiscoverySort::Pushed | DiscoverySort::Created => {
+                let cursor: i64 = key.parse()?;
+                let value = match sort {
+                    DiscoverySort::Stars => self.star_count,
+                    DiscoverySort::Pushed => self.pushed_at,
+                    _ => self.created_at,
+                };
+                cursor.cmp(&value)
+            }
+            DiscoverySort::Name => self.record.slug.as_str().cmp(key),
+        };
+        Ok(key.then_with(|| self.record.id.as_str().cmp(id)) == Ordering::Greater)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct DiscoveryRow {
+    id: String,
+    slug: String,
+    group_id: Option<String>,
+    remote_url: Option<String>,
+    star_count: i64,
+    created_at: i64,
+    pushed_at: i64,
+    description: String,
+}
+
+impl DiscoveryRow {
+    fn into_repository(self, score: Option<SearchScore>) -> DiscoveredRepository {
+        DiscoveredRepository {
+            record: RepositoryRecord {
+                id: self.id,
+                slug: self.slug,
+                group_id: self.group_id,
+                remote_url: self.remote_url,
+            },
+            star_count: self.star_count,
+            created_at: self.created_at,
+            pushed_at: self.pushed_at,
+            score,
+        }
+    }
+}
+
+const ROW_COLUMNS: &str = "id, slug, \"group\" AS group_id, remote_url, star_count, \
+     created_at, pushed_at, COALESCE((SELECT group_concat(description, ' ') \
+     FROM repository_descriptions WHERE repository_id = repositories.id), '') AS description";
+
+/// Star count and times of one repository, for `RepositoryNode`.
+pub async fn discovery_stats(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> anyhow::Result<Option<DiscoveredRepository>> {
+    let row = sqlx::query_as::<_, DiscoveryRow>(&format!(
+        "SELECT {ROW_COLUMNS} FROM repositories WHERE id = ?"
+    ))
+    .bind(repository_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|row| row.into_repository(None)))
+}
+
+/// The score of `query` for one repository, whether or not it matches.
+pub async fn score_repository(
+    pool: &SqlitePool,
+    repository_id: &str,
+    query: &str,
+) -> anyhow::Result<Option<SearchScore>> {
+    let row = sqlx::query_as::<_, DiscoveryRow>(&format!(
+        "SELECT {ROW_COLUMNS} FROM repositories WHERE id = ?"
+    ))
+    .bind(repository_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|row| score(query, &row.slug, &row.description)))
+}
+
+/// One page of the repositories `viewer_did` may read, matching `query` if one is given.
+/// Only forward paging is supported.
+pub async fn find_repositories(
+    pool: &SqlitePool,
+    viewer_did: Option<&str>,
+    query: Option<&str>,
+    sort: DiscoverySort,
+    request: &PageRequest,
+) -> anyhow::Result<Page<DiscoveredRepository>> {
+    if request.direction == Direction::Backward {
+        bail!("findRepositories pages forward only; use `first` and `after`");
+    }
+    match query.map(str::trim).filter(|query| !query.is_empty()) {
+        Some(query) => search(pool, viewer_did, query, sort, request).await,
+        None if sort == DiscoverySort::Relevance => {
+            bail!("sorting by relevance needs a query")
+        }
+        None => list(pool, viewer_did, sort, request).await,
+    }
+}
+
+async fn list(
+    pool: &SqlitePool,
+    viewer_did: Option<&str>,
+    sort: DiscoverySort,
+    request: &PageRequest,
+) -> anyhow::Result<Page<DiscoveredRepository>> {
+    let (after, order) = match sort.column() {
+        Some(column) => (
+            format!("({column} < ?1 OR ({column} = ?1 AND id > ?2))"),
+            format!("ORDER BY {column} DESC, id"),
+        ),
+        None => (
+            "(slug, id) > (?1, ?2)".to_string(),
+            "ORDER BY slug, id".to_string(),
+        ),
+    };
+    let condition = match &request.cursor {
+        Some(_) => format!("{after} AND {}", HIDDEN.replace('?', "?3")),
+        None => HIDDEN.replace('?', "?3"),
+    };
+    let sql = format!("SELECT {ROW_COLUMNS} FROM repositories WHERE {condition} {order} LIMIT ?4");
+    let mut query = sqlx::query_as::<_, DiscoveryRow>(&sql);
+    query = match &request.cursor {
+        Some(cursor) if sort.column().is_some() => {
+            let key: i64 = cursor.slug.parse()?;
+            query.bind(key).bind(&cursor.id)
+        }
+        Some(cursor) => query.bind(&cursor.slug).bind(&cursor.id),
+        None => query.bind(None::<String>).bind(None::<String>),
+    };
+    let rows = query
+        .bind(viewer_did)
+        .bind(request.fetch_limit())
+        .fetch_all(pool)
+        .await?;
+    let items = rows
+        .into_iter()
+        .map(|row| row.into_repository(None))
+        .collect();
+    Ok(Page::from_rows(request, items))
+}
+
+async fn search(
+    pool: &SqlitePool,
+    viewer_did: Option<&str>,
+    query: &str,
+    sort: DiscoverySort,
+    request: &PageRequest,
+) -> anyhow::Result<Page<DiscoveredRepository>> {
+    // A word at least MIN_SIMILARITY alike shares at least that fraction of the query
+    // word's trigrams, so repositories sharing fewer cannot match and are not loaded.
+    let mut candidates = BTreeSet::new();
+    for word in query_words(query) {
+        let trigrams: Vec<String> = trigrams(&word).into_iter().collect();
+        let needed = (MIN_SIMILARITY * trigrams.len() as f64).ceil() as i64;
+        let placeholders = vec!["?"; trigrams.len()].join(", ");
+        let sql = format!(
+            "SELECT repository_id FROM repository_search_trigrams WHERE trigram IN ({placeholders}) \
+             GROUP BY repository_id HAVING COUNT(*) >= ? ORDER BY COUNT(*) DESC LIMIT ?"
+        );
+        let mut lookup = sqlx::query_scalar::<_, String>(&sql);
+        for trigram in &trigrams {
+            lookup = lookup.bind(trigram);
+        }
+        candidates.extend(
+            lookup
+                .bind(needed.max(1))
+                .bind(MAX_CANDIDATES)
+                .fetch_all(pool)
+                .await?,
+        );
+    }
+
+    let candidates: Vec<String> = candidates.into_iter().collect();
+    let mut matches = Vec::new();
+    for chunk in candidates.chunks(500) {
+        let placeholders = vec!["?"; chunk.len()].join(", ");
+        let sql = format!(
+            "SELECT {ROW_COLUMNS} FROM repositories WHERE id IN ({placeholders}) AND {HIDDEN}"
+        );
+        let mut rows = sqlx::query_as::<_, DiscoveryRow>(&sql);
+        for id in chunk {
+            rows = rows.bind(id);
+        }
+        for row in rows.bind(viewer_did).fetch_all(pool).await? {
+            let score = score(query, &row.slug, &row.description);
+            if score.matches() {
+                matches.push(row.into_repository(Some(score)));
+            }
+        }
+    }
+
+    matches.sort_by(|a, b| a.compare(b, sort));
+    if let Some(cursor) = &request.cursor {
+        let mut kept = Vec::with_capacity(matches.len());
+        for item in matches {
+            if item.is_after(sort, &cursor.slug, &cursor.id)? {
+                kept.push(item);
+            }
+        }
+        matches = kept;
+    }
+    matches.truncate(request.limit + 1);
+    Ok(Page::from_rows(request, matches))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::descriptions::set_repository_description;
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::create_test_pool;
+    use crate::user::db::set_star;
+
+    #[test]
+    fn scores_tolerate_typos_and_favour_names() {
+        let exact = score("widgets", "widgets", "");
+        assert_eq!(exact.name, 1.0);
+        assert_eq!(exact.exact, 1.0);
+        assert_eq!(exact.total, 3.0);
+
+        let typo = score("widgtes", "widgets", "");
+        assert!(typo.matches(), "{:?}", typo);
+        assert!(typo.total < exact.total);
+
+        let described = score("widgets", "gadgets", "A box of widgets");
+        assert!(described.matches());
+        assert_eq!(described.description, 1.0);
+        assert!(described.total < exact.total);
+
+        assert!(!score("kubernetes", "widgets", "A box of widgets").matches());
+        assert_eq!(score("", "widgets", "").total, 0.0);
+        // Words of a name are matched one by one
+        assert_eq!(score("cli", "forge-cli", "").name, 1.0);
+    }
+
+    async fn repository(pool: &SqlitePool, slug: &str, description: &str) -> String {
+        let input = CreateRepositoryInput {
+            slug: slug.into(),
+            group: None,
+        };
+        let id = create_repository_raw(pool, input).await.unwrap().id;
+        if !description.is_empty() {
+            set_repository_description(pool, &id, None, description)
+                .await
+                .unwrap();
+        }
+        id
+    }
+
+    fn slugs(page: &Page<DiscoveredRepository>) -> Vec<&str> {
+        page.items
+            .iter()
+            .map(|item| item.record.slug.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn queries_find_misspelled_names_and_descriptions() {
+        let pool = create_test_pool().await.unwrap();
+        repository(&pool, "widgets", "").await;
+        repository(&pool, "gadgets", "Spare parts for widgets").await;
+        repository(&pool, "forge", "A code forge").await;
+
+        let request =
+            PageRequest::from_args("discovery-relevance", None, None, None, None).unwrap();
+        let page = find_repositories(
+            &pool,
+            None,
+            Some("widgest"),
+            DiscoverySort::Relevance,
+            &request,
+        )
+        .await
+        .unwrap();
+        assert_eq!(slugs(&page), ["widgets", "gadgets"]);
+        assert!(page.items[0].score.unwrap().total > page.items[1].score.unwrap().total);
+
+        let one = PageRequest::from_args("discovery-relevance", Some(1), None, None, None).unwrap();
+        let first = find_repositories(&pool, None, Some("widgets"), DiscoverySort::Relevance, &one)
+            .await
+            .unwrap();
+        assert!(first.has_next_page);
+        let cursor = first.items[0].cursor(DiscoverySort::Relevance);
+        let next =
+            PageRequest::from_args("discovery-relevance", Some(1), Some(&cursor), None, None)
+                .unwrap();
+        let second = find_repositories(
+            &pool,
+            None,
+            Some("widgets"),
+            DiscoverySort::Relevance,
+            &next,
+        )
+        .await
+        .unwrap();
+        assert_eq!(slugs(&second), ["gadgets"]);
+        assert!(!second.has_next_page);
+
+        assert!(
+            find_repositories(&pool, None, None, DiscoverySort::Relevance, &request)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn listings_sort_by_stars_pushes_and_creation() {
+        let pool = create_test_pool().await.unwrap();
+        let older = repository(&pool, "older", "").await;
+        let newer = repository(&pool, "newer", "").await;
+        let starred = repository(&pool, "starred", "").await;
+        sqlx::query("UPDATE repositories SET created_at = ? WHERE id = ?")
+            .bind(100)
+            .bind(&older)
+            .execute(&pool)
+            .await
+            .unwrap();
+        for user in ["did:plc:a", "did:plc:b"] {
+            set_star(&pool, user, &starred, true).await.unwrap();
+        }
+        set_star(&pool, "did:plc:a", &newer, true).await.unwrap();
+        record_push(&pool, &older, 5_000).await.unwrap();
+
+        let all = |kind| PageRequest::from_args(kind, None, None, None, None).unwrap();
+        let stars = find_repositories(
+            &pool,
+            None,
+            None,
+            DiscoverySort::Stars,
+            &all("discovery-stars"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(slugs(&stars), ["starred", "newer", "older"]);
+        assert_eq!(stars.items[0].star_count, 2);
+
+        let pushed = find_repositories(
+            &pool,
+            None,
+            None,
+            DiscoverySort::Pushed,
+            &all("discovery-pushed"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(slugs(&pushed)[0], "older");
+
+        let created = find_repositories(
+            &pool,
+            None,
+            None,
+            DiscoverySort::Created,
+            &all("discovery-created"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(slugs(&created).last(), Some(&"older"));
+
+        // Keyset paging through the star sort
+        let one = PageRequest::from_args("discovery-stars", Some(1), None, None, None).unwrap();
+        let first = find_repositories(&pool, None, None, DiscoverySort::Stars, &one)
+            .await
+            .unwrap();
+        let cursor = first.items[0].cursor(DiscoverySort::Stars);
+        let next =
+            PageRequest::from_args("discovery-stars", Some(5), Some(&cursor), None, None).unwrap();
+        let rest = find_repositories(&pool, None, None, DiscoverySort::Stars, &next)
+            .await
+            .unwrap();
+        assert_eq!(slugs(&rest), ["newer", "older"]);
+
+        set_star(&pool, "did:plc:a", &starred, false).await.unwrap();
+        let stats = discovery_stats(&pool, &starred).await.unwrap().unwrap();
+        assert_eq!(stats.star_count, 1);
+    }
+}
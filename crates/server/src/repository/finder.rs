@@ -0,0 +1,446 @@
+//! Fuzzy "go to file" search over one revision's tree.
+//!
+//! The first search against a tree walks it once and keeps its file paths in memory,
+//! keyed by tree OID, so type-ahead requests only pay for scoring. Trees are immutable,
+//! so cached indexes never go stale; the cache only needs a size bound.
+//!
+//! Matching is subsequence-based: every query character must appear in the path in
+//! order, ignoring case. Scoring favours matches in the file name, at word boundaries
+//! and in consecutive runs, and penalises gaps.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use tokio::task;
+
+use super::entries::normalize_tree_path;
+use super::resolver::PathResolver;
+use super::storage::RepositoryStorage;
+
+/// Number of tree indexes kept in memory.
+const MAX_CACHED_TREES: usize = 32;
+pub const DEFAULT_RESULTS: usize = 20;
+pub const MAX_RESULTS: usize = 100;
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL: i64 = 6;
+const BONUS_CONSECUTIVE: i64 = 4;
+const BONUS_FILENAME: i64 = 24;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileMatch {
+    pub path: String,
+    pub score: i64,
+    /// Character offsets into `path` of the matched query characters, for highlighting.
+    pub positions: Vec<usize>,
+}
+
+impl FileMatch {
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+}
+
+/// File paths of one tree, sorted, with a per-path character mask for quick rejection.
+pub struct FileIndex {
+    paths: Vec<String>,
+    masks: Vec<u64>,
+}
+
+impl FileIndex {
+    pub fn new(mut paths: Vec<String>) -> Self {
+        paths.sort();
+        let masks = paths.iter().map(|path| char_mask(path)).collect();
+        Self { paths, masks }
+    }
+
+    pub fn paths(&self) -> &[String] {
+        &self.paths
+    }
+
+    /// Best `limit` matches for `query`, highest score first. An empty query lists paths
+    /// in order.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<FileMatch> {
+        self.search_in("", query, limit)
+    }
+
+    /// Like [`search`](Self::search), restricted to the files below the directory `root`.
+    /// Paths are scored relative to `root`, so its own name neither matches nor earns
+    /// boundary bonuses; results still carry full paths.
+    pub fn search_in(&self, root: &str, query: &str, limit: usize) -> Vec<FileMatch> {
+        let query: Vec<char> = query
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .flat_map(char::to_lowercase)
+            .collect();
+        let prefix = if root.is_empty() {
+            String::new()
+        } else {
+            format!("{root}/")
+        };
+        // Paths are sorted, so the files below `root` are one contiguous run.
+        let start = self
+            .paths
+            .partition_point(|path| path.as_str() < prefix.as_str());
+        let in_root = self.paths[start..]
+            .iter()
+            .zip(&self.masks[start..])
+            .take_while(|(path, _)| path.starts_with(&prefix));
+        if query.is_empty() {
+            return in_root
+                .take(limit)
+                .map(|(path, _)| FileMatch {
+                    path: path.clone(),
+                    score: 0,
+                    positions: Vec::new(),
+                })
+                .collect();
+        }
+
+        let needed = query.iter().fold(0, |mask, c| mask | char_bit(*c));
+        let offset = prefix.chars().count();
+        let mut matches: Vec<FileMatch> = in_root
+            .filter(|(_, mask)| *mask & needed == needed)
+            .filter_map(|(path, _)| {
+                let mut found = score_path(&path[prefix.len()..], &query)?;
+                found.path = path.clone();
+                found
+                    .positions
+                    .iter_mut()
+                    .for_each(|position| *position += offset);
+                Some(found)
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.path.len().cmp(&b.path.len()))
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        matches.truncate(limit);
+        matches
+    }
+}
+
+/// Bit set of the ASCII letters, digits and separators in `text`; everything else shares
+/// one bit, so the mask can only reject paths that certainly do not match.
+fn char_mask(text: &str) -> u64 {
+    text.chars()
+        .flat_map(char::to_lowercase)
+        .fold(0, |mask, c| mask | char_bit(c))
+}
+
+fn char_bit(c: char) -> u64 {
+    match c {
+        'a'..='z' => 1 << (c as u32 - 'a' as u32),
+        '0'..='9' => 1 << (26 + c as u32 - '0' as u32),
+        '/' => 1 << 36,
+        '.' => 1 << 37,
+        '_' => 1 << 38,
+        '-' => 1 << 39,
+        _ => 1 << 40,
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '/' | '_' | '-' | '.' | ' ')
+}
+
+/// Scores `path` against an already lower-cased `query`, or `None` when it does not match.
+///
+/// Finds the leftmost window that ends at the earliest possible position, then shrinks
+/// it from the right so the window is as tight as possible before scoring.
+fn score_path(path: &str, query: &[char]) -> Option<FileMatch> {
+    let original: Vec<char> = path.chars().collect();
+    let lowered: Vec<char> = original
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+
+    // Forward pass: earliest end of a full match.
+    let mut next = 0;
+    let mut end = None;
+    for (index, c) in lowered.iter().enumerate() {
+        if *c == query[next] {
+            next += 1;
+            if next == query.len() {
+                end = Some(index);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    // Backward pass: latest start that still matches the whole query.
+    let mut remaining = query.len();
+    let mut start = end;
+    for index in (0..=end).rev() {
+        if lowered[index] == query[remaining - 1] {
+            remaining -= 1;
+            if remaining == 0 {
+                start = index;
+                break;
+            }
+        }
+    }
+
+    let filename_start = lowered
+        .iter()
+        .rposition(|c| *c == '/')
+        .map_or(0, |slash| slash + 1);
+
+    let mut score = 0;
+    let mut positions = Vec::with_capacity(query.len());
+    let mut next = 0;
+    let mut previous_match: Option<usize> = None;
+    for index in start..=end {
+        if next == query.len() {
+            break;
+        }
+        if lowered[index] != query[next] {
+            continue;
+        }
+        score += SCORE_MATCH;
+        let previous = index.checked_sub(1).map(|i| original[i]);
+        match previous {
+            None => score += BONUS_BOUNDARY,
+            Some(prev) if is_separator(prev) => score += BONUS_BOUNDARY,
+            Some(prev) if prev.is_lowercase() && original[index].is_uppercase() => {
+                score += BONUS_CAMEL
+            }
+            _ => {}
+        }
+        match previous_match {
+            Some(last) if last + 1 == index => score += BONUS_CONSECUTIVE,
+            Some(last) => {
+                score -= PENALTY_GAP_START + PENALTY_GAP_EXTENSION * (index - last - 2) as i64
+            }
+            None => {}
+        }
+        previous_match = Some(index);
+        positions.push(index);
+        next += 1;
+    }
+
+    if start >= filename_start {
+        score += BONUS_FILENAME;
+    }
+
+    Some(FileMatch {
+        path: path.to_string(),
+        score,
+        positions,
+    })
+}
+
+#[derive(Default)]
+struct IndexCache {
+    indexes: HashMap<gix::ObjectId, Arc<FileIndex>>,
+    /// Least recently used first.
+    order: VecDeque<gix::ObjectId>,
+}
+
+impl IndexCache {
+    fn get(&mut self, tree: &gix::ObjectId) -> Option<Arc<FileIndex>> {
+        let index = self.indexes.get(tree)?.clone();
+        self.order.retain(|id| id != tree);
+        self.order.push_back(*tree);
+        Some(index)
+    }
+
+    fn insert(&mut self, tree: gix::ObjectId, index: Arc<FileIndex>) {
+        if self.indexes.insert(tree, index).is_none() {
+            self.order.push_back(tree);
+        }
+        while self.order.len() > MAX_CACHED_TREES {
+            if let Some(evicted) = self.order.pop_front() {
+                self.indexes.remove(&evicted);
+            }
+        }
+    }
+}
+
+static INDEX_CACHE: LazyLock<Mutex<IndexCache>> = LazyLock::new(Mutex::default);
+
+/// Fuzzy-matches file paths at `rev` (default `HEAD`), optionally only below the
+/// `project` directory. Returns `None` when the repository does not exist or `viewer_did`
+/// may not read it, and an empty list for a repository without commits.
+pub async fn find_files_raw(
+    resolver: &PathResolver,
+    viewer_did: Option<&str>,
+    storage: &RepositoryStorage,
+    path: String,
+    query: String,
+    rev: Option<String>,
+    project: Option<String>,
+    limit: usize,
+) -> anyhow::Result<Option<Vec<FileMatch>>> {
+    let Some(resolved) = resolver
+        .resolve_visible_repository(&path, viewer_did)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let repository_path = resolved.local_dir(storage)?;
+    let root = normalize_tree_path(project)?;
+
+    let matches = task::spawn_blocking(move || -> anyhow::Result<Vec<FileMatch>> {
+        let Some(index) = load_index(repository_path, rev.as_deref())? else {
+            return Ok(Vec::new());
+        };
+        Ok(index.search_in(&root, &query, limit.min(MAX_RESULTS)))
+    })
+    .await
+    .map_err(|err| anyhow::anyhow!(err))??;
+    Ok(Some(matches))
+}
+
+pub(super) fn load_index(
+    repository_path: PathBuf,
+    rev: Option<&str>,
+) -> anyhow::Result<Option<Arc<FileIndex>>> {
+    let repo = gix::open(&repository_path).map_err(|err| {
+        anyhow::anyhow!(
+            "failed to open repository at {}: {}",
+            repository_path.display(),
+            err
+        )
+    })?;
+
+    let tree = match rev {
+        Some(spec) => repo
+            .rev_parse_single(spec)
+            .map_err(|err| anyhow::anyhow!("revision `{}` not found: {}", spec, err))?
+            .object()?
+            .peel_to_tree()
+            .map_err(|_| anyhow::anyhow!("revision `{}` does not point to a tree", spec))?,
+        None => match repo.head_commit() {
+            Ok(commit) => commit.tree()?,
+            // Unborn HEAD: nothing to search yet.
+            Err(_) => return Ok(None),
+        },
+    };
+
+    if let Some(index) = INDEX_CACHE
+        .lock()
+        .ok()
+        .and_then(|mut cache| cache.get(&tree.id))
+    {
+        return Ok(Some(index));
+    }
+
+    let mut recorder = gix::traverse::tree::Recorder::default();
+    tree.traverse()
+        .breadthfirst(&mut recorder)
+        .map_err(|err| anyhow::anyhow!("failed to walk tree {}: {}", tree.id, err))?;
+    let paths = recorder
+        .records
+        .into_iter()
+        .filter(|entry| !entry.mode.is_tree() && !entry.mode.is_commit())
+        .map(|entry| entry.filepath.to_string())
+        .collect();
+
+    let index = Arc::new(FileIndex::new(paths));
+    if let Ok(mut cache) = INDEX_CACHE.lock() {
+        cache.insert(tree.id, index.clone());
+    }
+    Ok(Some(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(paths: &[&str]) -> FileIndex {
+        FileIndex::new(paths.iter().map(|p| p.to_string()).collect())
+    }
+
+    fn top(index: &FileIndex, query: &str) -> Vec<String> {
+        index
+            .search(query, 10)
+            .into_iter()
+            .map(|m| m.path)
+            .collect()
+    }
+
+    #[test]
+    fn filename_and_boundary_matches_rank_first() {
+        let index = index(&[
+            "crates/server/src/router/core_executor.rs",
+            "crates/server/src/repository/resolver.rs",
+            "docs/guides/creating-extensions.md",
+            "crates/server/src/extensions/wasm_runtime.rs",
+        ]);
+        assert_eq!(
+            top(&index, "coreexec")[0],
+            "crates/server/src/router/core_executor.rs"
+        );
+        assert_eq!(
+            top(&index, "resolver")[0],
+            "crates/server/src/repository/resolver.rs"
+        );
+        assert_eq!(
+            top(&index, "wasm"),
+            vec!["crates/server/src/extensions/wasm_runtime.rs"]
+        );
+        assert!(top(&index, "zzz").is_empty());
+    }
+
+    #[test]
+    fn positions_point_at_matched_characters() {
+        let index = index(&["src/FooBar.rs"]);
+        let found = index.search("fb", 1);
+        assert_eq!(found[0].positions, vec![4, 7]);
+        assert_eq!(found[0].name(), "FooBar.rs");
+    }
+
+    #[test]
+    fn tighter_matches_beat_scattered_ones() {
+        let index = index(&["a/main.rs", "m/a/i/n.rs"]);
+        assert_eq!(top(&index, "main")[0], "a/main.rs");
+    }
+
+    #[test]
+    fn empty_query_lists_paths_in_order() {
+        let index = index(&["b.rs", "a.rs", "c.rs"]);
+        assert_eq!(top(&index, "  "), vec!["a.rs", "b.rs", "c.rs"]);
+        assert_eq!(index.search("", 2).len(), 2);
+    }
+
+    #[test]
+    fn search_can_be_limited_to_a_directory() {
+        let index = index(&[
+            "apps/web/src/main.ts",
+            "apps/webhooks/main.go",
+            "crates/app/src/main.rs",
+            "main.rs",
+        ]);
+        let found = index.search_in("apps/web", "main", 10);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, "apps/web/src/main.ts");
+        assert_eq!(found[0].positions, vec![13, 14, 15, 16]);
+        // The root's own name does not match.
+        assert!(index.search_in("apps/web", "web", 10).is_empty());
+        assert_eq!(index.search_in("crates", "", 10).len(), 1);
+        assert_eq!(index.search_in("", "main", 10).len(), 4);
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let mut cache = IndexCache::default();
+        let ids: Vec<gix::ObjectId> = (0..=MAX_CACHED_TREES as u8)
+            .map(|n| gix::ObjectId::from_bytes_or_panic(&[n; 20]))
+            .collect();
+        for id in &ids[..MAX_CACHED_TREES] {
+            cache.insert(*id, Arc::new(index(&[])));
+        }
+        assert!(cache.get(&ids[0]).is_some());
+        cache.insert(ids[MAX_CACHED_TREES], Arc::new(index(&[])));
+        assert!(cache.get(&ids[0]).is_some());
+        assert!(cache.get(&ids[1]).is_none());
+    }
+}
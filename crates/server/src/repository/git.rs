@@ -0,0 +1,51 @@
+//! The `git` CLI, for the work gix does not cover: fetching with progress, cloning,
+//! `fsck`, bundles and ref transactions.
+//!
+//! Every command runs with `GIT_TERMINAL_PROMPT=0`, so a remote asking for credentials
+//! fails instead of waiting on a terminal that is not there.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow};
+use tokio::process::Command;
+
+/// `git`, not yet pointed at a repository (e.g. for `clone` or `init`).
+pub fn git_command() -> Command {
+    let mut cmd = Command::new("git");
+    cmd.env("GIT_TERMINAL_PROMPT", "0");
+    cmd
+}
+
+/// `git --git-dir <dir>`.
+pub fn git(dir: &Path) -> Command {
+    let mut cmd = git_command();
+    cmd.arg("--git-dir").arg(dir);
+    cmd
+}
+
+/// Runs `git` with `args` against the repository at `dir` and returns its stdout.
+pub async fn run_git(dir: &Path, args: &[&str]) -> Result<String> {
+    run(git(dir), args).await
+}
+
+/// Runs `cmd` with `args` and returns its stdout. A failure names the subcommand and
+/// the last lines git wrote to stderr.
+pub async fn run(mut cmd: Command, args: &[&str]) -> Result<String> {
+    let output = cmd
+        .args(args)
+        .output()
+        .await
+        .context("failed to spawn git")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let detail: Vec<&str> = stderr.lines().rev().take(5).collect();
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.iter()
+                .find(|arg| !arg.starts_with('-') && !arg.contains('='))
+                .unwrap_or(&""),
+            detail.into_iter().rev().collect::<Vec<_>>().join("; ")
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
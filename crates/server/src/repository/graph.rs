@@ -0,0 +1,383 @@
+//! Commit graph slices for the network view.
+//!
+//! [`commit_graph`] walks back from one revision, or from every branch and tag, and
+//! returns at most `limit` commits in topological order: children before parents, newer
+//! commits first among those that are ready. Each commit comes with its parents, the refs
+//! pointing at it and a lane, the column the UI draws it in. Lanes are assigned the way
+//! `git log --graph` does: a commit takes the lane that was waiting for it, its first
+//! parent inherits that lane, and every other parent opens a lane of its own unless one
+//! is already waiting for it. Slices are cached by their tip commits, so a repository is
+//! only walked again after one of its refs moves.
+
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use tokio::task;
+
+use super::resolver::PathResolver;
+use super::storage::RepositoryStorage;
+
+pub const DEFAULT_LIMIT: usize = 200;
+pub const MAX_LIMIT: usize = 1000;
+/// Cached slices are dropped wholesale beyond this many.
+const MAX_CACHED_GRAPHS: usize = 256;
+/// Refs shown as labels and, without a revision, walked from.
+const LABELLED_REFS: &[&str] = &["refs/heads/", "refs/tags/", "refs/remotes/"];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitGraph {
+    /// Commits the walk started from, sorted.
+    pub tips: Vec<String>,
+    pub nodes: Vec<GraphNode>,
+    /// Number of lanes any row uses.
+    pub lanes: usize,
+    /// More commits are reachable than `limit` allowed.
+    pub truncated: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphNode {
+    pub oid: String,
+    /// In commit order; parents beyond the slice are listed too.
+    pub parents: Vec<String>,
+    /// Full names of the refs pointing at the commit, sorted.
+    pub refs: Vec<String>,
+    pub lane: usize,
+    pub summary: String,
+    pub author_name: String,
+    /// Committer time, in Unix seconds.
+    pub committed_at: i64,
+}
+
+type CacheKey = (Vec<gix::ObjectId>, usize);
+
+static GRAPH_CACHE: LazyLock<Mutex<HashMap<CacheKey, Arc<CommitGraph>>>> =
+    LazyLock::new(Mutex::default);
+
+/// The graph of the repository at `path` from `rev`, or from every branch and tag when
+/// `rev` is `None`. Returns `None` when the repository does not exist or `viewer_did` may
+/// not read it.
+pub async fn commit_graph(
+    resolver: &PathResolver,
+    viewer_did: Option<&str>,
+    storage: &RepositoryStorage,
+    path: &str,
+    rev: Option<String>,
+    limit: usize,
+) -> anyhow::Result<Option<Arc<CommitGraph>>> {
+    let Some(resolved) = resolver
+        .resolve_visible_repository(path, viewer_did)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let repository_path = resolved.local_dir(storage)?;
+    let limit = limit.clamp(1, MAX_LIMIT);
+    let graph = task::spawn_blocking(move || graph_at(repository_path, rev.as_deref(), limit))
+        .await
+        .map_err(|err| anyhow::anyhow!(err))??;
+    Ok(Some(graph))
+}
+
+fn graph_at(
+    repository_path: PathBuf,
+    rev: Option<&str>,
+    limit: usize,
+) -> anyhow::Result<Arc<CommitGraph>> {
+    let repo = gix::open(&repository_path).map_err(|err| {
+        anyhow::anyhow!(
+            "failed to open repository at {}: {}",
+            repository_path.display(),
+            err
+        )
+    })?;
+
+    let labels = ref_labels(&repo)?;
+    let mut tips: Vec<gix::ObjectId> = match rev {
+        Some(spec) => vec![
+            repo.rev_parse_single(spec)
+                .map_err(|err| anyhow::anyhow!("revision `{}` not found: {}", spec, err))?
+                .object()?
+                .peel_to_commit()
+                .map_err(|_| anyhow::anyhow!("revision `{}` is not a commit", spec))?
+                .id,
+        ],
+        None => labels.keys().copied().collect(),
+    };
+    tips.sort();
+    tips.dedup();
+
+    let key = (tips.clone(), limit);
+    if let Some(graph) = GRAPH_CACHE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&key)
+    {
+        return Ok(graph.clone());
+    }
+
+    let (commits, truncated) = walk(&repo, &tips, limit)?;
+    let mut nodes = topo_order(commits);
+    let lanes = assign_lanes(&mut nodes);
+    for node in &mut nodes {
+        let oid = gix::ObjectId::from_hex(node.oid.as_bytes())?;
+        node.refs = labels.get(&oid).cloned().unwrap_or_default();
+    }
+    let graph = Arc::new(CommitGraph {
+        tips: tips.iter().map(|tip| tip.to_string()).collect(),
+        nodes,
+        lanes,
+        truncated,
+    });
+
+    let mut cache = GRAPH_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if cache.len() >= MAX_CACHED_GRAPHS {
+        cache.clear();
+    }
+    cache.insert(key, graph.clone());
+    Ok(graph)
+}
+
+/// Commits pointed at by branches, tags and remote branches, with the refs' full names.
+fn ref_labels(repo: &gix::Repository) -> anyhow::Result<HashMap<gix::ObjectId, Vec<String>>> {
+    let mut labels: HashMap<gix::ObjectId, Vec<String>> = HashMap::new();
+    let platform = repo.references()?;
+    for reference in platform.all()? {
+        let Ok(mut reference) = reference else {
+            continue;
+        };
+        let name = reference.name().as_bstr().to_string();
+        if !LABELLED_REFS.iter().any(|prefix| name.starts_with(prefix)) || name.ends_with("/HEAD") {
+            continue;
+        }
+        // Tags of trees or blobs have no place in the graph.
+        let Ok(commit) = reference.peel_to_commit() else {
+            continue;
+        };
+        labels.entry(commit.id).or_default().push(name);
+    }
+    for names in labels.values_mut() {
+        names.sort();
+    }
+    Ok(labels)
+}
+
+/// Up to `limit` commits reachable from `tips`, newest first by committer time, and
+/// whether any were left out.
+fn walk(
+    repo: &gix::Repository,
+    tips: &[gix::ObjectId],
+    limit: usize,
+) -> anyhow::Result<(Vec<GraphNode>, bool)> {
+    let mut queue = BinaryHeap::new();
+    let mut seen = HashSet::new();
+    for tip in tips {
+        if seen.insert(*tip) {
+            queue.push((repo.find_commit(*tip)?.time()?.seconds, *tip));
+        }
+    }
+
+    let mut commits = Vec::new();
+    while let Some((committed_at, oid)) = queue.pop() {
+        if commits.len() == limit {
+            return Ok((commits, true));
+        }
+        let commit = repo.find_commit(oid)?;
+        let parents: Vec<gix::ObjectId> = commit.parent_ids().map(|id| id.detach()).collect();
+        for parent in &parents {
+            // Shallow clones end at commits whose parents are missing.
+            if seen.insert(*parent)
+                && let Ok(parent_commit) = repo.find_commit(*parent)
+            {
+                queue.push((parent_commit.time()?.seconds, *parent));
+            }
+        }
+        commits.push(GraphNode {
+            oid: oid.to_string(),
+            parents: parents.iter().map(|parent| parent.to_string()).collect(),
+            refs: Vec::new(),
+            lane: 0,
+            summary: commit.message()?.summary().to_string(),
+            author_name: commit.author()?.name.to_string(),
+            committed_at,
+        });
+    }
+    Ok((commits, false))
+}
+
+/// Orders `commits` so that every commit comes after all of its children in the slice.
+/// Among commits whose children have all been placed, the newest goes first, so clock
+/// skew between machines cannot put a parent above its child.
+fn topo_order(commits: Vec<GraphNode>) -> Vec<GraphNode> {
+    let index: HashMap<&str, usize> = commits
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.oid.as_str(), i))
+        .collect();
+    let mut children = vec![0usize; commits.len()];
+    for node in &commits {
+        // A commit listing the same parent twice still counts as one child.
+        let parents: HashSet<&str> = node.parents.iter().map(String::as_str).collect();
+        for parent in parents {
+            if let Some(&i) = index.get(parent) {
+                children[i] += 1;
+            }
+        }
+    }
+
+    let mut ready: BinaryHeap<(i64, usize)> = (0..commits.len())
+        .filter(|&i| children[i] == 0)
+        .map(|i| (commits[i].committed_at, usize::MAX - i))
+        .collect();
+    let mut order = Vec::with_capacity(commits.len());
+    while let Some((_, rank)) = ready.pop() {
+        let i = usize::MAX - rank;
+        order.push(i);
+        let parents: HashSet<&str> = commits[i].parents.iter().map(String::as_str).collect();
+        for parent in parents {
+            if let Some(&p) = index.get(parent) {
+                children[p] -= 1;
+                if children[p] == 0 {
+                    ready.push((commits[p].committed_at, usize::MAX - p));
+                }
+            }
+        }
+    }
+
+    let mut slots: Vec<Option<GraphNode>> = commits.into_iter().map(Some).collect();
+    order.into_iter().filter_map(|i| slots[i].take()).collect()
+}
+
+/// Sets each node's lane and returns how many lanes the graph needs.
+fn assign_lanes(nodes: &mut [GraphNode]) -> usize {
+    // The commit each lane is waiting for, if the lane is in use.
+    let mut lanes: Vec<Option<String>> = Vec::new();
+    let mut width = 0;
+    for node in nodes.iter_mut() {
+        let lane = match lanes
+            .iter()
+            .position(|waiting| waiting.as_ref() == Some(&node.oid))
+        {
+            Some(lane) => lane,
+            None => open_lane(&mut lanes),
+        };
+        // Branches that forked from this commit end here.
+        for waiting in lanes.iter_mut() {
+            if waiting.as_ref() == Some(&node.oid) {
+                *waiting = None;
+            }
+        }
+        lanes[lane] = node.parents.first().cloned();
+        for parent in node.parents.iter().skip(1) {
+            if !lanes.iter().any(|waiting| waiting.as_ref() == Some(parent)) {
+                let opened = open_lane(&mut lanes);
+                lanes[opened] = Some(parent.clone());
+            }
+        }
+        while lanes.last().is_some_and(Option::is_none) {
+            lanes.pop();
+        }
+        node.lane = lane;
+        width = width.max(lane + 1).max(lanes.len());
+    }
+    width
+}
+
+/// The leftmost free lane, adding one when all are in use.
+fn open_lane(lanes: &mut Vec<Option<String>>) -> usize {
+    match lanes.iter().position(Option::is_none) {
+        Some(lane) => lane,
+        None => {
+            lanes.push(None);
+            lanes.len() - 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::git;
+
+    fn node(oid: &str, parents: &[&str], committed_at: i64) -> GraphNode {
+        GraphNode {
+            oid: oid.to_string(),
+            parents: parents.iter().map(|parent| parent.to_string()).collect(),
+            refs: Vec::new(),
+            lane: 0,
+            summary: String::new(),
+            author_name: String::new(),
+            committed_at,
+        }
+    }
+
+    fn oids(nodes: &[GraphNode]) -> Vec<&str> {
+        nodes.iter().map(|node| node.oid.as_str()).collect()
+    }
+
+    #[test]
+    fn children_come_before_parents_despite_clock_skew() {
+        // `b`'s clock was ahead of its child's.
+        let nodes = topo_order(vec![
+            node("b", &["a"], 300),
+            node("c", &["b"], 200),
+            node("a", &[], 100),
+        ]);
+        assert_eq!(oids(&nodes), vec!["c", "b", "a"]);
+    }
+
+    #[test]
+    fn branches_get_their_own_lane_until_they_merge() {
+        //   m     merge of x into e
+        //   |\
+        //   e x
+        //   |/
+        //   r
+        let mut nodes = topo_order(vec![
+            node("m", &["e", "x"], 40),
+            node("x", &["r"], 30),
+            node("e", &["r"], 20),
+            node("r", &[], 10),
+        ]);
+        assert_eq!(oids(&nodes), vec!["m", "x", "e", "r"]);
+        let width = assign_lanes(&mut nodes);
+        let lanes: Vec<usize> = nodes.iter().map(|node| node.lane).collect();
+        assert_eq!(lanes, vec![0, 1, 0, 0]);
+        assert_eq!(width, 2);
+    }
+
+    #[test]
+    fn walks_every_ref_and_labels_commits() {
+        let tmp = tempfile::tempdir().unwrap();
+        let work = tmp.path();
+        git(work, &["init", "-q", "-b", "main"]);
+        git(work, &["commit", "-q", "--allow-empty", "-m", "root"]);
+        git(work, &["checkout", "-q", "-b", "topic"]);
+        git(work, &["commit", "-q", "--allow-empty", "-m", "topic work"]);
+        git(work, &["checkout", "-q", "main"]);
+        git(work, &["commit", "-q", "--allow-empty", "-m", "main work"]);
+        git(work, &["tag", "v1"]);
+        let main = git(work, &["rev-parse", "main"]);
+        let git_dir = work.join(".git");
+
+        let graph = graph_at(git_dir.clone(), None, 10).unwrap();
+        assert_eq!(graph.nodes.len(), 3);
+        assert!(!graph.truncated);
+        assert_eq!(graph.lanes, 2);
+        assert_eq!(graph.tips.len(), 2);
+        let head = graph.nodes.iter().find(|node| node.oid == main).unwrap();
+        assert_eq!(head.refs, vec!["refs/heads/main", "refs/tags/v1"]);
+        assert_eq!(head.summary, "main work");
+        assert_eq!(head.author_name, "Tess");
+        assert_eq!(graph.nodes.last().unwrap().summary, "root");
+
+        let topic = graph_at(git_dir.clone(), Some("topic"), 1).unwrap();
+        assert_eq!(topic.nodes.len(), 1);
+        assert!(topic.truncated);
+        assert_eq!(topic.nodes[0].summary, "topic work");
+        assert_eq!(topic.nodes[0].refs, vec!["refs/heads/topic"]);
+
+        assert!(graph_at(git_dir, Some("missing"), 10).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+//! Imports git data that already exists on the server: bare repositories and bundles.
+//!
+//! Operators migrating from another forge point `importRepositoryFromPath` at a bare
+//! repository (or a bundle) under one of `repos.import_roots`. Bundles can also be
+//! uploaded with `importRepositoryBundle`. The source is checked first: bare
+//! repositories with `git fsck`, bundles with `git bundle verify`. Its branches and tags
+//! are then copied into a staging directory next to managed storage, with
+//! `transfer.fsckObjects` on, and the copy is checked again. Only then is the repository
+//! row inserted and the staging directory moved into place, in one transaction. A
+//! failed import leaves neither a row nor a directory behind.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use sqlx::SqlitePool;
+
+use super::db::slug_conflicts_for_repository;
+use super::git::{git_command, run, run_git};
+use super::models::RepositoryRecord;
+use super::queries::reconstruct_repository_path;
+use super::storage::RepositoryStorage;
+use crate::group::db::fetch_group_by_id;
+use crate::group::settings::inherit_group_settings;
+use crate::validation::slug::prepare_slug;
+
+/// Staging directories live here, under the storage root, so the final move is a rename.
+const STAGING_DIR: &str = ".imports";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImportSource {
+    BareRepository(PathBuf),
+    Bundle(PathBuf),
+}
+
+impl ImportSource {
+    /// Works out what `path` holds. A working copy's `.git` directory is accepted too.
+    pub fn detect(path: &Path) -> Result<Self> {
+        if path.is_file() {
+            let mut header = [0u8; 16];
+            let read = std::io::Read::read(&mut std::fs::File::open(path)?, &mut header)?;
+            if header[..read].starts_with(b"# v2 git bundle")
+                || header[..read].starts_with(b"# v3 git bundle")
+            {
+                return Ok(Self::Bundle(path.to_path_buf()));
+            }
+            bail!("{} is not a git bundle", path.display());
+        }
+        for candidate in [path.to_path_buf(), path.join(".git")] {
+            if candidate.join("HEAD").is_file() && candidate.join("objects").is_dir() {
+                return Ok(Self::BareRepository(candidate));
+            }
+        }
+        bail!("{} is not a git repository or bundle", path.display())
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Self::BareRepository(path) | Self::Bundle(path) => path,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ImportInput {
+    pub slug: String,
+    pub group: Option<String>,
+}
+
+/// Resolves `local_path` and checks it lies under one of `roots`.
+pub fn resolve_import_path(local_path: &str, roots: &[PathBuf]) -> Result<PathBuf> {
+    if roots.is_empty() {
+        bail!("imports from the server filesystem are disabled; set repos.import_roots");
+    }
+    let path =
+        std::fs::canonicalize(local_path).with_context(|| format!("cannot read {}", local_path))?;
+    let allowed = roots
+        .iter()
+        .any(|root| std::fs::canonicalize(root).is_ok_and(|root| path.starts_with(root)));
+    if !allowed {
+        bail!("{} is outside repos.import_roots", path.display());
+    }
+    Ok(path)
+}
+
+/// Copies `source` into managed storage and registers it as a new repository.
+pub async fn import_repository(
+    pool: &SqlitePool,
+    storage: &RepositoryStorage,
+    source: &ImportSource,
+    input: ImportInput,
+) -> Result<RepositoryRecord> {
+    let slug = prepare_slug(&input.slug)?;
+    if let Some(group) = &input.group
+        && fetch_group_by_id(pool, group).await?.is_none()
+    {
+        bail!("group not found");
+    }
+    if slug_conflicts_for_repository(pool, input.group.as_deref(), &slug).await? {
+        bail!("slug already exists in this group");
+    }
+
+    let record = RepositoryRecord {
+        id: cuid2::create_id(),
+        slug,
+        group_id: input.group,
+        remote_url: None,
+    };
+    let segments: Vec<String> = reconstruct_repository_path(pool, &record)
+        .await?
+        .split('/')
+        .map(str::to_string)
+        .collect();
+    let target = storage.repository_path(&segments);
+    if tokio::fs::try_exists(&target).await? {
+        bail!("{} already exists in storage", target.display());
+    }
+
+    verify_source(source).await?;
+    let staging = storage.local_root.join(STAGING_DIR).join(&record.id);
+    if let Err(err) = stage(source, &staging).await {
+        let _ = tokio::fs::remove_dir_all(&staging).await;
+        return Err(err);
+    }
+
+    let registered = register(pool, &record, &staging, &target).await;
+    if registered.is_err() {
+        let _ = tokio::fs::remove_dir_all(&staging).await;
+    }
+    registered?;
+    inherit_group_settings(pool, &record).await?;
+    tracing::info!(
+        "imported {} from {} into {}",
+        segments.join("/"),
+        source.path().display(),
+        target.display()
+    );
+    Ok(record)
+}
+
+async fn verify_source(source: &ImportSource) -> Result<()> {
+    match source {
+        ImportSource::BareRepository(path) => {
+            run_git(path, &["fsck", "--no-progress", "--no-dangling"])
+                .await
+                .context("the source repository failed fsck")?;
+        }
+        ImportSource::Bundle(path) => {
+            // `bundle verify` needs a repository to check prerequisites against; an empty
+            // one makes bundles that depend on other history fail.
+            let scratch = std::env::temp_dir().join(format!("forge-bundle-{}", cuid2::create_id()));
+            let verified = async {
+                run(
+                    git_command(),
+                    &["init", "--bare", "--quiet", &scratch.to_string_lossy()],
+                )
+                .await?;
+                run_git(
+                    &scratch,
+                    &["bundle", "verify", "--quiet", &path.to_string_lossy()],
+                )
+                .await
+            }
+            .await;
+            let _ = tokio::fs::remove_dir_all(&scratch).await;
+            verified.context("the bundle failed verification")?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies branches and tags of `source` into a new bare repository at `staging`.
+async fn stage(source: &ImportSource, staging: &Path) -> Result<()> {
+    if let Some(parent) = staging.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let source_path = source.path().to_string_lossy().into_owned();
+    let staging_path = staging.to_string_lossy().into_owned();
+    run(
+        git_command(),
+        &[
+            "-c",
+            "transfer.fsckObjects=true",
+            "clone",
+            "--bare",
+            "--no-local",
+            "--quiet",
+            &source_path,
+            &staging_path,
+        ],
+    )
+    .await
+    .context("copying objects failed")?;
+    run_git(staging, &["remote", "remove", "origin"]).await?;
+    run_git(staging, &["fsck", "--no-progress", "--no-dangling"])
+        .await
+        .context("the imported copy failed fsck")?;
+    Ok(())
+}
+
+/// Inserts the row and moves `staging` to `target`; either both happen or neither.
+async fn register(
+    pool: &SqlitePool,
+    record: &RepositoryRecord,
+    staging: &Path,
+    target: &Path,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO repositories (id, slug, \"group\", remote_url) VALUES (?, ?, ?, NULL)",
+    )
+    .bind(&record.id)
+    .bind(&record.slug)
+    .bind(record.group_id.as_ref())
+    .execute(&mut *tx)
+    .await?;
+    if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::rename(staging, target)
+        .await
+        .with_context(|| format!("failed to move the import into {}", target.display()))?;
+    if let Err(err) = tx.commit().await {
+        let _ = tokio::fs::remove_dir_all(target).await;
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{create_test_pool, git};
+
+    /// A working copy with one commit on `main` and a tag, plus a bare clone of it.
+    fn fixture(root: &Path) -> (PathBuf, PathBuf) {
+        let work = root.join("work");
+        std::fs::create_dir_all(&work).unwrap();
+        git(&work, &["init", "-q", "-b", "main"]);
+        std::fs::write(work.join("README.md"), "# Imported\n").unwrap();
+        git(&work, &["add", "README.md"]);
+        git(&work, &["commit", "-q", "-m", "initial"]);
+        git(&work, &["tag", "v1.0.0"]);
+        let bare = root.join("legacy.git");
+        git(root, &["clone", "-q", "--bare", "work", "legacy.git"]);
+        (work, bare)
+    }
+
+    fn storage(root: &Path) -> RepositoryStorage {
+        RepositoryStorage::new(root.join("repos"), root.join("cache"))
+    }
+
+    fn refs(dir: &Path) -> String {
+        git(dir, &["for-each-ref", "--format=%(refname)"])
+    }
+
+    #[tokio::test]
+    async fn imports_bare_repositories_and_bundles() {
+        let root = tempfile::tempdir().unwrap();
+        let (work, bare) = fixture(root.path());
+        git(
+            &work,
+            &["bundle", "create", "-q", "../legacy.bundle", "--all"],
+        );
+        let pool = create_test_pool().await.unwrap();
+        let storage = storage(root.path());
+
+        let source = ImportSource::detect(&bare).unwrap();
+        assert_eq!(source, ImportSource::BareRepository(bare.clone()));
+        let input = ImportInput {
+            slug: "legacy".into(),
+            group: None,
+        };
+        let record = import_repository(&pool, &storage, &source, input.clone())
+            .await
+            .unwrap();
+        let dir = storage.repository_path(&["legacy".to_string()]);
+        assert_eq!(refs(&dir), "refs/heads/main\nrefs/tags/v1.0.0");
+        assert!(
+            !storage
+                .local_root
+                .join(STAGING_DIR)
+                .join(&record.id)
+                .exists()
+        );
+
+        // The slug is taken now
+        assert!(
+            import_repository(&pool, &storage, &source, input)
+                .await
+                .is_err()
+        );
+
+        let bundle = ImportSource::detect(&root.path().join("legacy.bundle")).unwrap();
+        assert!(matches!(bundle, ImportSource::Bundle(_)));
+        let input = ImportInput {
+            slug: "from-bundle".into(),
+            group: None,
+        };
+        import_repository(&pool, &storage, &bundle, input)
+            .await
+            .unwrap();
+        let dir = storage.repository_path(&["from-bundle".to_string()]);
+        assert!(refs(&dir).contains("refs/tags/v1.0.0"));
+    }
+
+    #[tokio::test]
+    async fn corrupt_sources_are_rejected_without_side_effects() {
+        let root = tempfile::tempdir().unwrap();
+        let (_, bare) = fixture(root.path());
+        // Truncate every loose object
+        for entry in walk(&bare.join("objects")) {
+            std::fs::write(entry, b"broken").unwrap();
+        }
+        let pool = create_test_pool().await.unwrap();
+        let storage = storage(root.path());
+        let source = ImportSource::detect(&bare).unwrap();
+        let input = ImportInput {
+            slug: "broken".into(),
+            group: None,
+        };
+        assert!(
+            import_repository(&pool, &storage, &source, input)
+                .await
+                .is_err()
+        );
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM repositories")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+        assert!(!storage.repository_path(&["broken".to_string()]).exists());
+    }
+
+    fn walk(dir: &Path) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir).unwrap().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                files.extend(walk(&path));
+            } else if path
+                .parent()
+                .is_some_and(|p| p.file_name().is_some_and(|n| n.len() == 2))
+            {
+                files.push(path);
+            }
+        }
+        files
+    }
+
+    #[test]
+    fn import_paths_must_be_under_a_root() {
+        let root = tempfile::tempdir().unwrap();
+        let inside = root.path().join("legacy.git");
+        std::fs::create_dir_all(&inside).unwrap();
+        let roots = vec![root.path().to_path_buf()];
+
+        assert!(resolve_import_path(&inside.to_string_lossy(), &[]).is_err());
+        assert_eq!(
+            resolve_import_path(&inside.to_string_lossy(), &roots).unwrap(),
+            std::fs::canonicalize(&inside).unwrap()
+        );
+        let escape = format!("{}/../", inside.display());
+        let outside = format!("{}/..", root.path().display());
+        assert!(resolve_import_path(&escape, &roots).is_ok());
+        assert!(resolve_import_path(&outside, &roots).is_err());
+    }
+}
@@ -0,0 +1,517 @@
+//! Integrity checks and repair of repositories on disk, for operators.
+//!
+//! A check walks every object reachable from the repository's refs with gix. Each object
+//! is read, hashed again and parsed, so both missing objects and objects whose contents
+//! no longer match their id are found. History behind a shallow boundary is not followed.
+//!
+//! Repair moves corrupt loose objects aside into `forge-quarantine/` and then refetches
+//! everything from the sources that may still have the objects: the remote cache clone of
+//! a linked repository, then the upstream it mirrors. `git fetch --refetch` skips
+//! negotiation, so a repository with holes in its history can still be refilled. Refs
+//! are fetched into a scratch namespace that is removed again; the repository's own refs
+//! never move. Repositories without such a source can be checked but not repaired.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, anyhow};
+use gix::ObjectId;
+use tokio::task;
+
+use super::git::{git, run_git};
+use super::models::RepositoryRecord;
+use super::resolver::ResolvedRepository;
+use super::storage::RepositoryStorage;
+use crate::user::db::unix_now;
+
+/// Where fetched refs land during a repair; deleted once the fetch is done.
+const REPAIR_NAMESPACE: &str = "refs/forge-repair/";
+const QUARANTINE_DIR: &str = "forge-quarantine";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// Referenced by a ref or another object, but not in the object database.
+    MissingObject,
+    /// Present, but unreadable, unparsable or not matching its id.
+    CorruptObject,
+    /// A ref that could not be read.
+    BrokenRef,
+}
+
+impl ProblemKind {
+    pub fn as_graphql(&self) -> &'static str {
+        match self {
+            ProblemKind::MissingObject => "MISSING_OBJECT",
+            ProblemKind::CorruptObject => "CORRUPT_OBJECT",
+            ProblemKind::BrokenRef => "BROKEN_REF",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntegrityProblem {
+    pub kind: ProblemKind,
+    pub oid: Option<String>,
+    /// The ref the object was reached from, or the broken ref itself.
+    pub reference: Option<String>,
+    pub detail: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct IntegrityReport {
+    pub path: String,
+    pub checked_at: i64,
+    pub refs_checked: usize,
+    pub objects_checked: usize,
+    pub problems: Vec<IntegrityProblem>,
+}
+
+impl IntegrityReport {
+    pub fn healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// One source a repair fetched from, and why it failed if it did.
+#[derive(Clone, Debug)]
+pub struct RepairAttempt {
+    pub source: RepairSource,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RepairSource {
+    RemoteCache(PathBuf),
+    Mirror(String),
+}
+
+impl RepairSource {
+    pub fn kind_graphql(&self) -> &'static str {
+        match self {
+            RepairSource::RemoteCache(_) => "REMOTE_CACHE",
+            RepairSource::Mirror(_) => "MIRROR",
+        }
+    }
+
+    /// What `git fetch` is pointed at.
+    pub fn location(&self) -> String {
+        match self {
+            RepairSource::RemoteCache(dir) => dir.display().to_string(),
+            RepairSource::Mirror(url) => url.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RepairReport {
+    pub before: IntegrityReport,
+    /// Ids of corrupt loose objects moved into `forge-quarantine/`.
+    pub quarantined: Vec<String>,
+    pub attempts: Vec<RepairAttempt>,
+    pub after: IntegrityReport,
+}
+
+impl RepairReport {
+    pub fn repaired(&self) -> bool {
+        self.after.healthy()
+    }
+}
+
+pub async fn check_repository(
+    storage: &RepositoryStorage,
+    resolved: &ResolvedRepository,
+) -> anyhow::Result<IntegrityReport> {
+    let dir = resolved.local_dir(storage)?;
+    check_dir(dir, resolved.canonical_path()).await
+}
+
+pub async fn repair_repository(
+    storage: &RepositoryStorage,
+    resolved: &ResolvedRepository,
+) -> anyhow::Result<RepairReport> {
+    let dir = resolved.local_dir(storage)?;
+    let path = resolved.canonical_path();
+    let before = check_dir(dir.clone(), path.clone()).await?;
+    if before.healthy() {
+        return Ok(RepairReport {
+            after: before.clone(),
+            before,
+            quarantined: Vec::new(),
+            attempts: Vec::new(),
+        });
+    }
+
+    let quarantined = quarantine_corrupt_objects(&dir, &before.problems).await?;
+    let mut attempts = Vec::new();
+    let mut after = before.clone();
+    for source in recovery_sources(storage, &resolved.record) {
+        let error = refetch(&dir, &source)
+            .await
+            .err()
+            .map(|err| format!("{:#}", err));
+        if let Some(error) = &error {
+            tracing::warn!(repository = %path, source = %source.location(), "repair fetch failed: {}", error);
+        }
+        attempts.push(RepairAttempt { source, error });
+        after = check_dir(dir.clone(), path.clone()).await?;
+        if after.healthy() {
+            break;
+        }
+    }
+    tracing::info!(
+        repository = %path,
+        problems_before = before.problems.len(),
+        problems_after = after.problems.len(),
+        "repository repair finished"
+    );
+    Ok(RepairReport {
+        before,
+        quarantined,
+        attempts,
+        after,
+    })
+}
+
+/// Sources to refetch from, most local first.
+fn recovery_sources(storage: &RepositoryStorage, record: &RepositoryRecord) -> Vec<RepairSource> {
+    let mut sources = Vec::new();
+    let cache = storage.remote_cache_root.join(&record.id);
+    if cache.join("HEAD").is_file() || cache.join(".git").is_dir() {
+        sources.push(RepairSource::RemoteCache(cache));
+    }
+    if let Some(url) = &record.remote_url {
+        sources.push(RepairSource::Mirror(url.clone()));
+    }
+    sources
+}
+
+async fn check_dir(dir: PathBuf, path: String) -> anyhow::Result<IntegrityReport> {
+    let (refs_checked, objects_checked, problems) = task::spawn_blocking(move || walk(&dir))
+        .await
+        .map_err(|err| anyhow!(err))??;
+    Ok(IntegrityReport {
+        path,
+        checked_at: unix_now(),
+        refs_checked,
+        objects_checked,
+        problems,
+    })
+}
+
+/// Reads every object reachable from the refs; returns the number of refs and objects
+/// checked and what was wrong.
+fn walk(dir: &Path) -> anyhow::Result<(usize, usize, Vec<IntegrityProblem>)> {
+    let repo = gix::open(dir).with_context(|| format!("failed to open {}", dir.display()))?;
+    let shallow = shallow_commits(dir);
+    let mut problems = Vec::new();
+
+    // Objects to visit, with the ref they were reached from
+    let mut pending: Vec<(ObjectId, String)> = Vec::new();
+    let mut refs_checked = 0;
+    if let Ok(head) = repo.find_reference("HEAD")
+        && let Some(id) = head.target().try_id()
+    {
+        refs_checked += 1;
+        pending.push((id.to_owned(), "HEAD".to_string()));
+    }
+    for reference in repo.references()?.all()? {
+        let reference = match reference {
+            Ok(reference) => reference,
+            Err(err) => {
+                problems.push(IntegrityProblem {
+                    kind: ProblemKind::BrokenRef,
+                    oid: None,
+                    reference: None,
+                    detail: err.to_string(),
+                });
+                continue;
+            }
+        };
+        refs_checked += 1;
+        // Symbolic refs are checked through the refs they point to
+        if let Some(id) = reference.target().try_id() {
+            pending.push((id.to_owned(), reference.name().as_bstr().to_string()));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    while let Some((oid, reached_from)) = pending.pop() {
+        if !seen.insert(oid) {
+            continue;
+        }
+        let problem = |kind, detail: String| IntegrityProblem {
+            kind,
+            oid: Some(oid.to_string()),
+            reference: Some(reached_from.clone()),
+            detail,
+        };
+        let object = match repo.try_find_object(oid) {
+            Ok(Some(object)) => object,
+            Ok(None) => {
+                problems.push(problem(
+                    ProblemKind::MissingObject,
+                    "object is missing".into(),
+                ));
+                continue;
+            }
+            Err(err) => {
+                problems.push(problem(ProblemKind::CorruptObject, err.to_string()));
+                continue;
+            }
+        };
+        let hashed = gix::objs::compute_hash(oid.kind(), object.kind, &object.data);
+        if hashed.as_ref().ok() != Some(&oid) {
+            problems.push(problem(
+                ProblemKind::CorruptObject,
+                "contents do not match the object id".into(),
+            ));
+            continue;
+        }
+
+        let children: Result<Vec<ObjectId>, _> = match object.kind {
+            gix::objs::Kind::Commit => {
+                gix::objs::CommitRef::from_bytes(&object.data).map(|commit| {
+                    let mut children = vec![commit.tree()];
+                    if !shallow.contains(&oid) {
+                        children.extend(commit.parents());
+                    }
+                    children
+                })
+            }
+            gix::objs::Kind::Tree => gix::objs::TreeRef::from_bytes(&object.data).map(|tree| {
+                tree.entries
+                    .iter()
+                    // Submodule commits live in other repositories
+                    .filter(|entry| !entry.mode.is_commit())
+                    .map(|entry| entry.oid.to_owned())
+                    .collect()
+            }),
+            gix::objs::Kind::Tag => {
+                gix::objs::TagRef::from_bytes(&object.data).map(|tag| vec![tag.target()])
+            }
+            gix::objs::Kind::Blob => Ok(Vec::new()),
+        };
+        match children {
+            Ok(children) => pending.extend(
+                children
+                    .into_iter()
+                    .filter(|child| !seen.contains(child))
+                    .map(|child| (child, reached_from.clone())),
+            ),
+            Err(err) => problems.push(problem(
+                ProblemKind::CorruptObject,
+                format!("cannot parse {}: {}", object.kind, err),
+            )),
+        }
+    }
+
+    problems.sort_by(|a, b| (&a.reference, &a.oid).cmp(&(&b.reference, &b.oid)));
+    Ok((refs_checked, seen.len(), problems))
+}
+
+fn shallow_commits(dir: &Path) -> HashSet<ObjectId> {
+    std::fs::read_to_string(dir.join("shallow"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| ObjectId::from_hex(line.trim().as_bytes()).ok())
+        .collect()
+}
+
+/// Moves corrupt loose objects out of the object database, so that refetched copies are
+/// read instead. Corrupt objects inside packs stay; git falls back to other packs.
+async fn quarantine_corrupt_objects(
+    dir: &Path,
+    problems: &[IntegrityProblem],
+) -> anyhow::Result<Vec<String>> {
+    let mut moved = Vec::new();
+    for oid in problems
+        .iter()
+        .filter(|problem| problem.kind == ProblemKind::CorruptObject)
+        .filter_map(|problem| problem.oid.as_deref())
+    {
+        let loose = dir.join("objects").join(&oid[..2]).join(&oid[2..]);
+        if !tokio::fs::try_exists(&loose).await.unwrap_or(false) {
+            continue;
+        }
+        let quarantine = dir.join(QUARANTINE_DIR);
+        tokio::fs::create_dir_all(&quarantine).await?;
+        tokio::fs::rename(&loose, quarantine.join(oid))
+            .await
+            .with_context(|| format!("failed to quarantine {}", loose.display()))?;
+        moved.push(oid.to_string());
+    }
+    Ok(moved)
+}
+
+/// Fetches every branch and tag of `source` into a scratch namespace, then drops the refs.
+async fn refetch(dir: &Path, source: &RepairSource) -> anyhow::Result<()> {
+    let location = source.location();
+    let heads = format!("+refs/heads/*:{REPAIR_NAMESPACE}heads/*");
+    let tags = format!("+refs/tags/*:{REPAIR_NAMESPACE}tags/*");
+    let fetched = run_git(
+        dir,
+        &[
+            "fetch",
+            "--refetch",
+            "--no-tags",
+            "--no-write-fetch-head",
+            "--quiet",
+            &location,
+            &heads,
+            &tags,
+        ],
+    )
+    .await;
+
+    let scratch = run_git(
+        dir,
+        &[
+            "for-each-ref",
+            "--format=delete %(refname)",
+            REPAIR_NAMESPACE,
+        ],
+    )
+    .await?;
+    if !scratch.is_empty() {
+        let mut update = git(dir);
+        update
+            .args(["update-ref", "--stdin"])
+            .stdin(std::process::Stdio::piped());
+        let mut child = update.spawn().context("failed to spawn git update-ref")?;
+        if let Some(mut stdin) = child.stdin.take() {
+            use tokio::io::AsyncWriteExt;
+            stdin.write_all(scratch.as_bytes()).await?;
+        }
+        let status = child.wait().await?;
+        if !status.success() {
+            tracing::warn!(dir = %dir.display(), "failed to remove {} refs", REPAIR_NAMESPACE);
+        }
+    }
+    fetched.map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::git;
+
+    fn record(id: &str, remote_url: Option<String>) -> RepositoryRecord {
+        RepositoryRecord {
+            id: id.to_string(),
+            slug: "app".to_string(),
+            group_id: None,
+            remote_url,
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_and_corrupt_objects_are_refetched_from_the_mirror() {
+        let root = tempfile::tempdir().unwrap();
+        let upstream = root.path().join("upstream");
+        std::fs::create_dir_all(&upstream).unwrap();
+        git(&upstream, &["init", "-q", "-b", "main"]);
+        std::fs::write(upstream.join("README.md"), "# App\n").unwrap();
+        std::fs::write(upstream.join("LICENSE"), "MIT\n").unwrap();
+        git(&upstream, &["add", "."]);
+        git(&upstream, &["commit", "-q", "-m", "initial"]);
+        git(&upstream, &["tag", "-a", "v1", "-m", "v1"]);
+
+        let storage = RepositoryStorage::new(root.path().join("repos"), root.path().join("cache"));
+        let resolved = ResolvedRepository {
+            record: record("repo1", Some(upstream.display().to_string())),
+            segments: vec!["app".to_string()],
+        };
+        let dir = storage.repository_path(&resolved.segments);
+        git(
+            root.path(),
+            &[
+                "clone",
+                "-q",
+                "--bare",
+                "--no-local",
+                "upstream",
+                dir.to_str().unwrap(),
+            ],
+        );
+
+        let healthy = check_repository(&storage, &resolved).await.unwrap();
+        assert!(healthy.healthy(), "{:?}", healthy.problems);
+        // main and the tag; commit, tree, two blobs and the tag object
+        assert_eq!((healthy.refs_checked, healthy.objects_checked), (2, 5));
+
+        // Unpack everything loose, then lose one blob and garble another
+        let pack = std::fs::read_dir(dir.join("objects/pack"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "pack"))
+            .unwrap();
+        let moved = root.path().join("objects.pack");
+        std::fs::rename(&pack, &moved).unwrap();
+        std::fs::remove_file(pack.with_extension("idx")).unwrap();
+        let unpacked = std::process::Command::new("git")
+            .arg("--git-dir")
+            .arg(&dir)
+            .arg("unpack-objects")
+            .stdin(std::fs::File::open(&moved).unwrap())
+            .status()
+            .unwrap();
+        assert!(unpacked.success());
+        let readme = git(&upstream, &["rev-parse", "HEAD:README.md"]);
+        let license = git(&upstream, &["rev-parse", "HEAD:LICENSE"]);
+        std::fs::remove_file(dir.join("objects").join(&readme[..2]).join(&readme[2..])).unwrap();
+        let garbled = dir.join("objects").join(&license[..2]).join(&license[2..]);
+        std::fs::remove_file(&garbled).unwrap();
+        std::fs::write(&garbled, b"not zlib").unwrap();
+
+        let broken = check_repository(&storage, &resolved).await.unwrap();
+        let kinds: Vec<(ProblemKind, Option<&str>)> = broken
+            .problems
+            .iter()
+            .map(|problem| (problem.kind, problem.oid.as_deref()))
+            .collect();
+        assert_eq!(kinds.len(), 2, "{:?}", broken.problems);
+        assert!(kinds.contains(&(ProblemKind::MissingObject, Some(readme.as_str()))));
+        assert!(kinds.contains(&(ProblemKind::CorruptObject, Some(license.as_str()))));
+
+        let repair = repair_repository(&storage, &resolved).await.unwrap();
+        assert!(repair.repaired(), "{:?}", repair.after.problems);
+        assert_eq!(repair.quarantined, vec![license.clone()]);
+        assert_eq!(repair.attempts.len(), 1);
+        assert_eq!(
+            repair.attempts[0].source,
+            RepairSource::Mirror(upstream.display().to_string())
+        );
+        assert!(dir.join(QUARANTINE_DIR).join(&license).is_file());
+        assert_eq!(
+            git(
+                &dir,
+                &["for-each-ref", "--format=%(refname)", REPAIR_NAMESPACE]
+            ),
+            ""
+        );
+    }
+
+    #[tokio::test]
+    async fn local_repositories_have_no_recovery_source() {
+        let root = tempfile::tempdir().unwrap();
+        let storage = RepositoryStorage::new(root.path().join("repos"), root.path().join("cache"));
+        assert!(recovery_sources(&storage, &record("local", None)).is_empty());
+
+        std::fs::create_dir_all(root.path().join("cache/linked")).unwrap();
+        std::fs::write(
+            root.path().join("cache/linked/HEAD"),
+            "ref: refs/heads/main\n",
+        )
+        .unwrap();
+        assert_eq!(
+            recovery_sources(
+                &storage,
+                &record("linked", Some("https://example.com/app.git".into()))
+            ),
+            vec![
+                RepairSource::RemoteCache(root.path().join("cache/linked")),
+                RepairSource::Mirror("https://example.com/app.git".into()),
+            ]
+        );
+    }
+}
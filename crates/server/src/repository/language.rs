@@ -0,0 +1,121 @@
+//! Language preferences for localized repository content.
+//!
+//! Clients pass the visitor's preferences either as a single tag (`de-CH`) or as an
+//! `Accept-Language` header value (`de-CH, de;q=0.9, en;q=0.5`). Tags are compared
+//! case-insensitively and `_` is accepted in place of `-`, so `README.pt_BR.md` matches
+//! `pt-BR`.
+
+/// Longest tag accepted; BCP 47 tags are rarely longer than a handful of subtags.
+const MAX_TAG_LEN: usize = 35;
+
+/// Canonical lowercase form of a language tag, or `None` when `tag` is not a tag.
+pub fn normalize_language_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().replace('_', "-").to_ascii_lowercase();
+    if tag.is_empty() || tag.len() > MAX_TAG_LEN {
+        return None;
+    }
+    let mut subtags = tag.split('-');
+    let primary = subtags.next()?;
+    if !(2..=8).contains(&primary.len()) || !primary.bytes().all(|b| b.is_ascii_lowercase()) {
+        return None;
+    }
+    if !subtags.all(|s| (1..=8).contains(&s.len()) && s.bytes().all(|b| b.is_ascii_alphanumeric()))
+    {
+        return None;
+    }
+    Some(tag)
+}
+
+/// Parses an `Accept-Language` value (or a bare tag) into tags, most preferred first.
+/// Invalid entries, `*` and `q=0` are dropped; equal weights keep their order.
+pub fn parse_language_preferences(value: &str) -> Vec<String> {
+    let mut weighted: Vec<(f32, String)> = Vec::new();
+    for item in value.split(',') {
+        let mut parts = item.split(';');
+        let Some(tag) = parts.next().and_then(normalize_language_tag) else {
+            continue;
+        };
+        let weight = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if weight <= 0.0 || weighted.iter().any(|(_, seen)| *seen == tag) {
+            continue;
+        }
+        weighted.push((weight.min(1.0), tag));
+    }
+    // `sort_by` is stable, so ties stay in header order.
+    weighted.sort_by(|a, b| b.0.total_cmp(&a.0));
+    weighted.into_iter().map(|(_, tag)| tag).collect()
+}
+
+/// Picks the entry of `available` that best serves `preferences`.
+///
+/// Each preference is tried in turn: first an exact match, then its truncations
+/// (`zh-hant-tw` falls back to `zh-hant`, then `zh`), then any regional variant of its
+/// language (`de` accepts `de-at`). `available` must hold normalized tags. Returns the
+/// index into `available`.
+pub fn best_language_match(available: &[String], preferences: &[String]) -> Option<usize> {
+    for preference in preferences {
+        let mut candidate = preference.as_str();
+        loop {
+            if let Some(index) = available.iter().position(|tag| tag == candidate) {
+                return Some(index);
+            }
+            match candidate.rfind('-') {
+                Some(end) => candidate = &candidate[..end],
+                None => break,
+            }
+        }
+        let prefix = format!("{}-", candidate);
+        if let Some(index) = available.iter().position(|tag| tag.starts_with(&prefix)) {
+            return Some(index);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(values: &[&str]) -> Vec<String> {
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn accept_language_is_ordered_by_weight() {
+        assert_eq!(
+            parse_language_preferences("en;q=0.5, de-CH, fr;q=0.8, *;q=0.1, de;q=0.9"),
+            tags(&["de-ch", "de", "fr", "en"])
+        );
+        assert_eq!(parse_language_preferences("pt_BR"), tags(&["pt-br"]));
+        assert_eq!(
+            parse_language_preferences("en;q=0, x, ;q=1"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            normalize_language_tag("zh-Hant-TW").as_deref(),
+            Some("zh-hant-tw")
+        );
+        assert_eq!(normalize_language_tag("e"), None);
+        assert_eq!(normalize_language_tag("en-"), None);
+        assert_eq!(normalize_language_tag("../en"), None);
+    }
+
+    #[test]
+    fn matching_falls_back_from_region_to_language() {
+        let available = tags(&["de", "en-gb", "zh-hant"]);
+        assert_eq!(best_language_match(&available, &tags(&["de-ch"])), Some(0));
+        assert_eq!(best_language_match(&available, &tags(&["en"])), Some(1));
+        assert_eq!(
+            best_language_match(&available, &tags(&["zh-hant-tw"])),
+            Some(2)
+        );
+        assert_eq!(
+            best_language_match(&available, &tags(&["fr", "en-us"])),
+            Some(1)
+        );
+        assert_eq!(best_language_match(&available, &tags(&["fr"])), None);
+    }
+}
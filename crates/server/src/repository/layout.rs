@@ -0,0 +1,113 @@
+//! Moves repositories between storage layouts (flat <-> sharded).
+//!
+//! Every move is recorded in `repository_paths` so operators can audit or undo a
+//! migration. Resolution keeps working mid-migration because
+//! [`RepositoryStorage::ensure_local_repository`] checks both layouts.
+
+use std::path::PathBuf;
+
+use sqlx::SqlitePool;
+
+use super::models::RepositoryRecord;
+use super::queries::reconstruct_repository_path;
+use super::storage::{RepositoryStorage, StorageLayout};
+
+#[derive(Debug, Default)]
+pub struct LayoutMigrationReport {
+    pub moved: usize,
+    pub skipped: usize,
+    pub missing: usize,
+}
+
+/// Moves every local repository into `target` layout.
+///
+/// Moves use `rename`, so the repository root must live on a single filesystem.
+/// Repositories whose destination already exists are left untouched.
+pub async fn migrate_storage_layout(
+    pool: &SqlitePool,
+    storage: &RepositoryStorage,
+    target: StorageLayout,
+) -> anyhow::Result<LayoutMigrationReport> {
+    let records = sqlx::query_as::<_, RepositoryRecord>(
+        "SELECT id, slug, \"group\" as group_id, remote_url FROM repositories ORDER BY id",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut report = LayoutMigrationReport::default();
+    for record in records {
+        let path = reconstruct_repository_path(pool, &record).await?;
+        let segments: Vec<String> = path.split('/').map(str::to_string).collect();
+
+        let destination = storage.repository_path_for(target, &segments);
+        let source = [StorageLayout::Flat, StorageLayout::Sharded]
+            .into_iter()
+            .filter(|layout| *layout != target)
+            .map(|layout| storage.repository_path_for(layout, &segments))
+            .find(|candidate| candidate.is_dir());
+
+        let Some(source) = source else {
+            if destination.is_dir() {
+                report.skipped += 1;
+            } else {
+                report.missing += 1;
+            }
+            continue;
+        };
+
+        if destination.exists() {
+            tracing::warn!(
+                "skipping {}: destination {} already exists",
+                path,
+                destination.display()
+            );
+            report.skipped += 1;
+            continue;
+        }
+
+        move_repository_dir(source.clone(), destination.clone()).await?;
+
+        sqlx::query(
+            "INSERT INTO repository_paths (repository_id, layout, previous_path, current_path, moved_at) \
+             VALUES (?, ?, ?, ?, strftime('%s','now')) \
+             ON CONFLICT(repository_id) DO UPDATE SET layout = excluded.layout, \
+             previous_path = excluded.previous_path, current_path = excluded.current_path, \
+             moved_at = excluded.moved_at",
+        )
+        .bind(&record.id)
+        .bind(target.as_str())
+        .bind(source.to_string_lossy().to_string())
+        .bind(destination.to_string_lossy().to_string())
+        .execute(pool)
+        .await?;
+
+        tracing::info!(
+            "moved {} from {} to {}",
+            path,
+            source.display(),
+            destination.display()
+        );
+        report.moved += 1;
+    }
+
+    Ok(report)
+}
+
+async fn move_repository_dir(source: PathBuf, destination: PathBuf) -> anyhow::Result<()> {
+    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(&source, &destination).map_err(|err| {
+            anyhow::anyhow!(
+                "failed to move {} to {}: {}",
+                source.display(),
+                destination.display(),
+                err
+            )
+        })?;
+        Ok(())
+    })
+    .await
+    .map_err(|err| anyhow::anyhow!(err))?
+}
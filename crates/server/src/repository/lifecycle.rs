@@ -0,0 +1,224 @@
+//! Repository lifecycle events delivered to extensions.
+//!
+//! Extensions keep data keyed by repository id, and some also store repository paths.
+//! When a repository is renamed, transferred to another group, deleted, or the
+//! compliance record of a deleted repository is purged, a [`LifecycleEvent`] is queued
+//! in the transaction that makes the change, so it is never lost or sent for a change
+//! that rolled back. The [`JOB_KIND`] job then queues one [`DELIVERY_JOB_KIND`] job per
+//! loaded extension that exports `git-events`, which calls its `handle-lifecycle-event`.
+//! An extension that fails is retried with the queue's backoff without holding up the
+//! others.
+//!
+//! Every such extension sees an event at least once; `id` stays the same across retries.
+//! Events of one repository are not guaranteed to arrive in order; `occurred_at` tells
+//! which change came last.
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteExecutor, SqlitePool};
+
+use crate::extensions::ExtensionManager;
+use crate::jobs::{JobHandler, JobRecord, enqueue_in};
+use crate::user::db::unix_now;
+
+pub const JOB_KIND: &str = "repository-lifecycle";
+pub const DELIVERY_JOB_KIND: &str = "repository-lifecycle.delivery";
+/// With the queue's backoff, deliveries are retried for about six hours.
+const MAX_ATTEMPTS: i64 = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleKind {
+    Renamed,
+    Transferred,
+    Deleted,
+    Purged,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LifecycleEvent {
+    pub id: String,
+    pub kind: LifecycleKind,
+    pub repository_id: String,
+    /// Full path before the change; `None` for [`LifecycleKind::Purged`].
+    pub old_path: Option<String>,
+    /// Full path after a rename or transfer.
+    pub new_path: Option<String>,
+    /// Unix seconds.
+    pub occurred_at: i64,
+}
+
+impl LifecycleEvent {
+    pub fn new(
+        kind: LifecycleKind,
+        repository_id: &str,
+        old_path: Option<String>,
+        new_path: Option<String>,
+    ) -> Self {
+        Self {
+            id: cuid2::create_id(),
+            kind,
+            repository_id: repository_id.to_string(),
+            old_path,
+            new_path,
+            occurred_at: unix_now(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Delivery {
+    extension: String,
+    event: LifecycleEvent,
+}
+
+/// Queues `event` for every extension through `executor`; pass the transaction that
+/// makes the change.
+pub async fn enqueue_lifecycle_event<'e, E>(
+    executor: E,
+    event: &LifecycleEvent,
+) -> anyhow::Result<()>
+where
+    E: SqliteExecutor<'e>,
+{
+    enqueue_in(executor, JOB_KIND, event, MAX_ATTEMPTS).await?;
+    Ok(())
+}
+
+/// Job handler for [`JOB_KIND`]: one delivery job per loaded extension that exports
+/// `git-events`.
+pub struct LifecycleFanOutJob {
+    pool: SqlitePool,
+    extensions: Arc<ExtensionManager>,
+}
+
+impl LifecycleFanOutJob {
+    pub fn new(pool: SqlitePool, extensions: Arc<ExtensionManager>) -> Self {
+        Self { pool, extensions }
+    }
+}
+
+#[async_trait]
+impl JobHandler for LifecycleFanOutJob {
+    async fn run(&self, job: &JobRecord) -> anyhow::Result<()> {
+        let event: LifecycleEvent = job.payload()?;
+        let extensions = self.extensions.get_extensions();
+        let mut names: Vec<&String> = extensions
+            .iter()
+            .filter(|(_, extension)| extension.runtime.interfaces().git_events)
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        // All or nothing, so a retry does not deliver twice to the extensions queued first.
+        let mut tx = self.pool.begin().await?;
+        for name in names {
+            let delivery = Delivery {
+                extension: name.clone(),
+                event: event.clone(),
+            };
+            enqueue_in(&mut *tx, DELIVERY_JOB_KIND, &delivery, MAX_ATTEMPTS).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Job handler for [`DELIVERY_JOB_KIND`].
+pub struct LifecycleDeliveryJob {
+    extensions: Arc<ExtensionManager>,
+}
+
+impl LifecycleDeliveryJob {
+    pub fn new(extensions: Arc<ExtensionManager>) -> Self {
+        Self { extensions }
+    }
+}
+
+#[async_trait]
+impl JobHandler for LifecycleDeliveryJob {
+    async fn run(&self, job: &JobRecord) -> anyhow::Result<()> {
+        let Delivery { extension, event } = job.payload()?;
+        let extensions = self.extensions.get_extensions();
+        let Some(loaded) = extensions.get(&extension) else {
+            tracing::warn!(
+                extension = %extension,
+                event = %event.id,
+                "dropping lifecycle event for an extension that is no longer loaded"
+            );
+            return Ok(());
+        };
+        loaded
+            .runtime
+            .handle_lifecycle_event(event)
+            .await
+            .with_context(|| {
+                format!(
+                    "extension `{}` failed to handle a lifecycle event",
+                    extension
+                )
+            })
+    }
+
+    async fn exhausted(&self, job: &JobRecord, error: &str) {
+        tracing::error!(
+            job = %job.id,
+            "lifecycle event dropped after {} attempts: {}",
+            job.attempts,
+            error
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::JobQueue;
+    use crate::test_helpers::create_test_pool;
+    use std::path::PathBuf;
+
+    async fn queued(pool: &SqlitePool, kind: &str) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM jobs WHERE kind = ?")
+            .bind(kind)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn events_are_queued_with_their_change() {
+        let pool = create_test_pool().await.unwrap();
+        let event = LifecycleEvent::new(
+            LifecycleKind::Renamed,
+            "r1",
+            Some("team/app".to_string()),
+            Some("team/service".to_string()),
+        );
+
+        let mut tx = pool.begin().await.unwrap();
+        enqueue_lifecycle_event(&mut *tx, &event).await.unwrap();
+        tx.rollback().await.unwrap();
+        assert_eq!(queued(&pool, JOB_KIND).await, 0);
+
+        enqueue_lifecycle_event(&pool, &event).await.unwrap();
+        let job = JobQueue::new(pool.clone())
+            .claim_next(i64::MAX)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.kind, JOB_KIND);
+        assert!(job.payload.contains(r#""kind":"renamed""#));
+        assert_eq!(job.payload::<LifecycleEvent>().unwrap(), event);
+
+        // Without extensions there is nobody to deliver to.
+        let extensions =
+            ExtensionManager::new(PathBuf::from("extensions"), PathBuf::from("ext.db"));
+        LifecycleFanOutJob::new(pool.clone(), Arc::new(extensions))
+            .run(&job)
+            .await
+            .unwrap();
+        assert_eq!(queued(&pool, DELIVERY_JOB_KIND).await, 0);
+    }
+}
@@ -0,0 +1,312 @@
+//! Merge conflict prediction between two revisions.
+//!
+//! The merge runs through gix against an in-memory object store, so nothing is written
+//! to the repository. Results only depend on the two commits, so they are cached in
+//! `merge_previews` by OID pair and pull request lists can query them cheaply.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use tokio::task;
+
+use super::resolver::PathResolver;
+use super::storage::RepositoryStorage;
+use crate::user::db::unix_now;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    /// Both sides changed the same lines of a file.
+    Content,
+    /// Both sides added a different file at the same path.
+    AddAdd,
+    /// One side modified a file the other deleted.
+    ModifyDelete,
+    /// A rename on either side collides with the other side's change.
+    Rename,
+    Other,
+}
+
+impl ConflictKind {
+    pub fn as_graphql(&self) -> &'static str {
+        match self {
+            ConflictKind::Content => "CONTENT",
+            ConflictKind::AddAdd => "ADD_ADD",
+            ConflictKind::ModifyDelete => "MODIFY_DELETE",
+            ConflictKind::Rename => "RENAME",
+            ConflictKind::Other => "OTHER",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeConflict {
+    pub path: String,
+    pub kind: ConflictKind,
+}
+
+#[derive(Clone, Debug)]
+pub struct MergePreview {
+    pub base_oid: String,
+    pub head_oid: String,
+    pub mergeable: bool,
+    pub conflicts: Vec<MergeConflict>,
+    /// Served from `merge_previews` rather than computed for this request.
+    pub cached: bool,
+}
+
+/// How one side of a conflict touched the path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    Added,
+    Deleted,
+    Modified,
+    Renamed,
+}
+
+fn classify(ours: Side, theirs: Side) -> ConflictKind {
+    match (ours, theirs) {
+        (Side::Renamed, _) | (_, Side::Renamed) => ConflictKind::Rename,
+        (Side::Modified, Side::Modified) => ConflictKind::Content,
+        (Side::Added, Side::Added) => ConflictKind::AddAdd,
+        (Side::Deleted, Side::Modified) | (Side::Modified, Side::Deleted) => {
+            ConflictKind::ModifyDelete
+        }
+        _ => ConflictKind::Other,
+    }
+}
+
+/// Predicts merging `head` into `base`. Returns `None` when the repository does not exist
+/// or `viewer_did` may not read it.
+pub async fn merge_preview_raw(
+    pool: &SqlitePool,
+    resolver: &PathResolver,
+    viewer_did: Option<&str>,
+    storage: &RepositoryStorage,
+    path: String,
+    base: String,
+    head: String,
+) -> anyhow::Result<Option<MergePreview>> {
+    let Some(resolved) = resolver
+        .resolve_visible_repository(&path, viewer_did)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let repository_path = resolved.local_dir(storage)?;
+    let repository_id = resolved.record.id.clone();
+
+    let (base_oid, head_oid) = {
+        let repository_path = repository_path.clone();
+        task::spawn_blocking(move || resolve_commits(repository_path, &base, &head))
+            .await
+            .map_err(|err| anyhow::anyhow!(err))??
+    };
+
+    if let Some(preview) = load_cached_preview(pool, &repository_id, &base_oid, &head_oid).await? {
+        return Ok(Some(preview));
+    }
+
+    let preview = {
+        let (base_oid, head_oid) = (base_oid.clone(), head_oid.clone());
+        task::spawn_blocking(move || compute_merge_preview(repository_path, base_oid, head_oid))
+            .await
+            .map_err(|err| anyhow::anyhow!(err))??
+    };
+    store_preview(pool, &repository_id, &preview).await?;
+    Ok(Some(preview))
+}
+
+fn resolve_commits(
+    repository_path: PathBuf,
+    base: &str,
+    head: &str,
+) -> anyhow::Result<(String, String)> {
+    let repo = gix::open(&repository_path)?;
+    let resolve = |spec: &str| -> anyhow::Result<String> {
+        let commit = repo
+            .rev_parse_single(spec)
+            .map_err(|err| anyhow::anyhow!("revision `{}` not found: {}", spec, err))?
+            .object()?
+            .peel_to_commit()
+            .map_err(|_| anyhow::anyhow!("revision `{}` is not a commit", spec))?;
+        Ok(commit.id.to_string())
+    };
+    Ok((resolve(base)?, resolve(head)?))
+}
+
+fn compute_merge_preview(
+    repository_path: PathBuf,
+    base_oid: String,
+    head_oid: String,
+) -> anyhow::Result<MergePreview> {
+    use gix::merge::tree::TreatAsUnresolved;
+
+    // Merged blobs and trees land in memory only; the repository stays untouched.
+    let repo = gix::open(&repository_path)?.with_object_memory();
+    let ours = gix::ObjectId::from_hex(base_oid.as_bytes())?;
+    let theirs = gix::ObjectId::from_hex(head_oid.as_bytes())?;
+
+    let labels = gix::merge::blob::builtin_driver::text::Labels {
+        ancestor: None,
+        current: Some("base".into()),
+        other: Some("head".into()),
+    };
+    let options = repo.tree_merge_options()?;
+    let outcome = repo
+        .merge_commits(ours, theirs, labels, options.into())
+        .map_err(|err| anyhow::anyhow!("cannot merge {} into {}: {}", head_oid, base_oid, err))?;
+
+    let how = TreatAsUnresolved::git();
+    let conflicts = unresolved_conflicts(&outcome.tree_merge.conflicts);
+
+    Ok(MergePreview {
+        mergeable: !outcome.tree_merge.has_unresolved_conflicts(how),
+        base_oid,
+        head_oid,
+        conflicts,
+        cached: false,
+    })
+}
+
+/// The conflicts of a tree merge that git would leave for a person to resolve, by path.
+pub(super) fn unresolved_conflicts(conflicts: &[gix::merge::tree::Conflict]) -> Vec<MergeConflict> {
+    use gix::diff::tree_with_rewrites::Change;
+    use gix::merge::tree::TreatAsUnresolved;
+
+    let side = |change: &Change| match change {
+        Change::Addition { .. } => Side::Added,
+        Change::Deletion { .. } => Side::Deleted,
+        Change::Modification { .. } => Side::Modified,
+        Change::Rewrite { .. } => Side::Renamed,
+    };
+
+    let how = TreatAsUnresolved::git();
+    let mut unresolved: Vec<MergeConflict> = Vec::new();
+    for conflict in conflicts {
+        if !conflict.is_unresolved(how) {
+            continue;
+        }
+        let path = conflict.ours.location().to_string();
+        if unresolved.iter().any(|existing| existing.path == path) {
+            continue;
+        }
+        unresolved.push(MergeConflict {
+            path,
+            kind: classify(side(&conflict.ours), side(&conflict.theirs)),
+        });
+    }
+    unresolved.sort_by(|a, b| a.path.cmp(&b.path));
+    unresolved
+}
+
+async fn load_cached_preview(
+    pool: &SqlitePool,
+    repository_id: &str,
+    base_oid: &str,
+    head_oid: &str,
+) -> anyhow::Result<Option<MergePreview>> {
+    let row = sqlx::query(
+        "SELECT mergeable, conflicts FROM merge_previews \
+         WHERE repository_id = ? AND base_oid = ? AND head_oid = ?",
+    )
+    .bind(repository_id)
+    .bind(base_oid)
+    .bind(head_oid)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let conflicts: String = row.try_get("conflicts")?;
+    Ok(Some(MergePreview {
+        base_oid: base_oid.to_string(),
+        head_oid: head_oid.to_string(),
+        mergeable: row.try_get::<i64, _>("mergeable")? != 0,
+        conflicts: serde_json::from_str(&conflicts)?,
+        cached: true,
+    }))
+}
+
+async fn store_preview(
+    pool: &SqlitePool,
+    repository_id: &str,
+    preview: &MergePreview,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO merge_previews \
+         (repository_id, base_oid, head_oid, mergeable, conflicts, computed_at) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(repository_id)
+    .bind(&preview.base_oid)
+    .bind(&preview.head_oid)
+    .bind(preview.mergeable as i64)
+    .bind(serde_json::to_string(&preview.conflicts)?)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    #[test]
+    fn conflict_kinds_follow_both_sides() {
+        assert_eq!(
+            classify(Side::Modified, Side::Modified),
+            ConflictKind::Content
+        );
+        assert_eq!(
+            classify(Side::Deleted, Side::Modified),
+            ConflictKind::ModifyDelete
+        );
+        assert_eq!(classify(Side::Added, Side::Added), ConflictKind::AddAdd);
+        assert_eq!(
+            classify(Side::Modified, Side::Renamed),
+            ConflictKind::Rename
+        );
+        assert_eq!(classify(Side::Deleted, Side::Added), ConflictKind::Other);
+    }
+
+    #[tokio::test]
+    async fn previews_are_cached_by_oid_pair() {
+        let pool = create_test_pool().await.unwrap();
+        sqlx::query("INSERT INTO repositories (id, slug) VALUES ('repo1', 'demo')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let preview = MergePreview {
+            base_oid: "a".repeat(40),
+            head_oid: "b".repeat(40),
+            mergeable: false,
+            conflicts: vec![MergeConflict {
+                path: "src/lib.rs".into(),
+                kind: ConflictKind::Content,
+            }],
+            cached: false,
+        };
+        store_preview(&pool, "repo1", &preview).await.unwrap();
+
+        let cached = load_cached_preview(&pool, "repo1", &preview.base_oid, &preview.head_oid)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(cached.cached);
+        assert!(!cached.mergeable);
+        assert_eq!(cached.conflicts, preview.conflicts);
+
+        // the pair is ordered: head-into-base differs from base-into-head
+        assert!(
+            load_cached_preview(&pool, "repo1", &preview.head_oid, &preview.base_oid)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,400 @@
+//! Community standards of a repository: the README, license, code of conduct,
+//! contribution guide, security policy, CI configuration and language manifests on the
+//! default branch, as the web UI's checklist shows them.
+//!
+//! Community files are looked up at the root, in `.github/` and in `docs/`, in that
+//! order. File names come from the index of [`super::finder`]; only the license file is
+//! read, to name its license. Summaries are cached by head commit, so the tree is looked
+//! at again only once the default branch moves.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use tokio::task;
+
+use super::finder::load_index;
+use super::projects::detect_projects;
+use super::resolver::PathResolver;
+use super::storage::RepositoryStorage;
+
+/// Directories searched for community files, most preferred first.
+const COMMUNITY_DIRS: &[&str] = &["", ".github/", "docs/"];
+/// Extensions a community file may have besides none at all.
+const COMMUNITY_EXTENSIONS: &[&str] = &["md", "markdown", "txt", "rst", "adoc", "asciidoc"];
+/// License texts name themselves within their first few kilobytes.
+const MAX_LICENSE_BYTES: usize = 16 * 1024;
+/// Cached summaries are dropped wholesale beyond this many head commits.
+const MAX_CACHED_HEADS: usize = 256;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProjectMetadata {
+    /// Commit the summary describes.
+    pub head_oid: String,
+    pub readme: Option<String>,
+    pub license: Option<LicenseFile>,
+    pub code_of_conduct: Option<String>,
+    pub contributing: Option<String>,
+    pub security_policy: Option<String>,
+    pub ci_configs: Vec<CiConfig>,
+    /// Paths of build manifests, as `repositoryProjects` finds them.
+    pub manifests: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LicenseFile {
+    pub path: String,
+    /// SPDX identifier of a recognised license text.
+    pub spdx_id: Option<&'static str>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CiConfig {
+    pub provider: CiProvider,
+    pub path: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CiProvider {
+    GitHubActions,
+    ForgejoActions,
+    GiteaActions,
+    GitLabCi,
+    Woodpecker,
+    CircleCi,
+    TravisCi,
+    Jenkins,
+    AzurePipelines,
+    BitbucketPipelines,
+    Buildkite,
+}
+
+impl CiProvider {
+    pub fn as_graphql(&self) -> &'static str {
+        match self {
+            CiProvider::GitHubActions => "GITHUB_ACTIONS",
+            CiProvider::ForgejoActions => "FORGEJO_ACTIONS",
+            CiProvider::GiteaActions => "GITEA_ACTIONS",
+            CiProvider::GitLabCi => "GITLAB_CI",
+            CiProvider::Woodpecker => "WOODPECKER",
+            CiProvider::CircleCi => "CIRCLECI",
+            CiProvider::TravisCi => "TRAVIS_CI",
+            CiProvider::Jenkins => "JENKINS",
+            CiProvider::AzurePipelines => "AZURE_PIPELINES",
+            CiProvider::BitbucketPipelines => "BITBUCKET_PIPELINES",
+            CiProvider::Buildkite => "BUILDKITE",
+        }
+    }
+
+    /// The provider a file configures, if it is a CI configuration.
+    fn detect(path: &str) -> Option<Self> {
+        let provider = match path {
+            ".gitlab-ci.yml" => CiProvider::GitLabCi,
+            ".woodpecker.yml" => CiProvider::Woodpecker,
+            ".circleci/config.yml" => CiProvider::CircleCi,
+            ".travis.yml" => CiProvider::TravisCi,
+            "Jenkinsfile" => CiProvider::Jenkins,
+            "azure-pipelines.yml" => CiProvider::AzurePipelines,
+            "bitbucket-pipelines.yml" => CiProvider::BitbucketPipelines,
+            ".buildkite/pipeline.yml" => CiProvider::Buildkite,
+            _ => {
+                let (dir, file) = path.rsplit_once('/')?;
+                if !file.ends_with(".yml") && !file.ends_with(".yaml") {
+                    return None;
+                }
+                match dir {
+                    ".github/workflows" => CiProvider::GitHubActions,
+                    ".forgejo/workflows" => CiProvider::ForgejoActions,
+                    ".gitea/workflows" => CiProvider::GiteaActions,
+                    ".woodpecker" => CiProvider::Woodpecker,
+                    _ => return None,
+                }
+            }
+        };
+        Some(provider)
+    }
+}
+
+static METADATA_CACHE: LazyLock<Mutex<HashMap<gix::ObjectId, Arc<ProjectMetadata>>>> =
+    LazyLock::new(Mutex::default);
+
+/// Summarizes the default branch of the repository at `path`. Returns `None` when the
+/// repository does not exist, `viewer_did` may not read it or it has no commits yet.
+pub async fn detect_project_metadata(
+    resolver: &PathResolver,
+    viewer_did: Option<&str>,
+    storage: &RepositoryStorage,
+    path: String,
+) -> anyhow::Result<Option<Arc<ProjectMetadata>>> {
+    let Some(resolved) = resolver
+        .resolve_visible_repository(&path, viewer_did)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let repository_path = resolved.local_dir(storage)?;
+    task::spawn_blocking(move || metadata_at_head(repository_path))
+        .await
+        .map_err(|err| anyhow::anyhow!(err))?
+}
+
+fn metadata_at_head(repository_path: PathBuf) -> anyhow::Result<Option<Arc<ProjectMetadata>>> {
+    let repo = gix::open(&repository_path).map_err(|err| {
+        anyhow::anyhow!(
+            "failed to open repository at {}: {}",
+            repository_path.display(),
+            err
+        )
+    })?;
+    let Ok(head) = repo.head_commit() else {
+        return Ok(None);
+    };
+
+    if let Some(metadata) = METADATA_CACHE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&head.id)
+    {
+        return Ok(Some(metadata.clone()));
+    }
+
+    let Some(index) = load_index(repository_path, Some(&head.id.to_string()))? else {
+        return Ok(None);
+    };
+    let mut metadata = summarize(head.id.to_string(), index.paths());
+    if let Some(license) = &mut metadata.license
+        && let Some(entry) = head
+            .tree()?
+            .lookup_entry_by_path(Path::new(&license.path))?
+    {
+        let blob = repo.find_object(entry.oid())?.into_blob();
+        let text = &blob.data[..blob.data.len().min(MAX_LICENSE_BYTES)];
+        license.spdx_id = identify_license(&String::from_utf8_lossy(text));
+    }
+
+    let metadata = Arc::new(metadata);
+    let mut cache = METADATA_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if cache.len() >= MAX_CACHED_HEADS {
+        cache.clear();
+    }
+    cache.insert(head.id, metadata.clone());
+    Ok(Some(metadata))
+}
+
+/// Everything but the license identifier, from the sorted file paths of a tree.
+fn summarize(head_oid: String, paths: &[String]) -> ProjectMetadata {
+    let license = community_file(paths, &["LICENSE", "LICENCE", "COPYING"]);
+    ProjectMetadata {
+        head_oid,
+        readme: community_file(paths, &["README"]),
+        license: license.map(|path| LicenseFile {
+            path,
+            spdx_id: None,
+        }),
+        code_of_conduct: community_file(paths, &["CODE_OF_CONDUCT", "CODE-OF-CONDUCT"]),
+        contributing: community_file(paths, &["CONTRIBUTING"]),
+        security_policy: community_file(paths, &["SECURITY"]),
+        ci_configs: paths
+            .iter()
+            .filter_map(|path| {
+                CiProvider::detect(path).map(|provider| CiConfig {
+                    provider,
+                    path: path.clone(),
+                })
+            })
+            .collect(),
+        manifests: detect_projects(paths, &[], "")
+            .into_iter()
+            .flat_map(|project| {
+                project.manifests.into_iter().map(move |manifest| {
+                    if project.root.is_empty() {
+                        manifest
+                    } else {
+                        format!("{}/{}", project.root, manifest)
+                    }
+                })
+            })
+            .collect(),
+    }
+}
+
+/// First file named after one of `stems` in the community directories. Names compare
+/// case-insensitively and may carry a suffix (`LICENSE-MIT`) or an extension; within a
+/// directory the shortest name wins, so `README.md` beats `README.de.md`.
+fn community_file(paths: &[String], stems: &[&str]) -> Option<String> {
+    COMMUNITY_DIRS.iter().find_map(|dir| {
+        paths
+            .iter()
+            .filter(|path| {
+                path.strip_prefix(dir)
+                    .filter(|name| !name.contains('/'))
+                    .is_some_and(|name| is_community_name(name, stems))
+            })
+            .min_by_key(|path| path.len())
+            .cloned()
+    })
+}
+
+fn is_community_name(name: &str, stems: &[&str]) -> bool {
+    let upper = name.to_ascii_uppercase();
+    stems.iter().any(|stem| {
+        let Some(rest) = upper.strip_prefix(stem) else {
+            return false;
+        };
+        match rest.rsplit_once('.') {
+            None => rest.is_empty() || rest.starts_with(['-', '_']),
+            Some((_, ext)) => {
+                rest.starts_with(['.', '-', '_'])
+                    && COMMUNITY_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+            }
+        }
+    })
+}
+
+/// SPDX identifier of a common license text. Variants that embed others (the LGPL and
+/// AGPL mention the GPL) are checked first.
+fn identify_license(text: &str) -> Option<&'static str> {
+    let text = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_ascii_lowercase();
+    let has = |needle: &str| text.contains(needle);
+    let id = if has("gnu affero general public license") {
+        "AGPL-3.0"
+    } else if has("gnu lesser general public license") {
+        if has("version 2.1") {
+            "LGPL-2.1"
+        } else {
+            "LGPL-3.0"
+        }
+    } else if has("gnu general public license") {
+        if has("version 3") {
+            "GPL-3.0"
+        } else {
+            "GPL-2.0"
+        }
+    } else if has("apache license") && has("version 2.0") {
+        "Apache-2.0"
+    } else if has("mozilla public license") && has("2.0") {
+        "MPL-2.0"
+    } else if has("permission is hereby granted, free of charge") {
+        "MIT"
+    } else if has("permission to use, copy, modify, and/or distribute this software") {
+        "ISC"
+    } else if has("redistribution and use in source and binary forms") {
+        if has("neither the name") || has("names of its contributors") {
+            "BSD-3-Clause"
+        } else {
+            "BSD-2-Clause"
+        }
+    } else if has("this is free and unencumbered software released into the public domain") {
+        "Unlicense"
+    } else {
+        return None;
+    };
+    Some(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<String> {
+        let mut paths: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn community_files_prefer_the_root() {
+        let metadata = summarize(
+            "abc".into(),
+            &paths(&[
+                ".github/CODE_OF_CONDUCT.md",
+                ".github/CONTRIBUTING.md",
+                ".github/workflows/ci.yml",
+                ".github/workflows/README.md",
+                "CONTRIBUTING.md",
+                "LICENSE-APACHE",
+                "README.de.md",
+                "README.md",
+                "crates/cli/Cargo.toml",
+                "docs/security.md",
+                "node_modules/left-pad/package.json",
+                "Cargo.toml",
+                "Jenkinsfile",
+                "src/license.rs",
+            ]),
+        );
+        assert_eq!(metadata.readme.as_deref(), Some("README.md"));
+        assert_eq!(
+            metadata.license.map(|license| license.path).as_deref(),
+            Some("LICENSE-APACHE")
+        );
+        assert_eq!(
+            metadata.code_of_conduct.as_deref(),
+            Some(".github/CODE_OF_CONDUCT.md")
+        );
+        assert_eq!(metadata.contributing.as_deref(), Some("CONTRIBUTING.md"));
+        assert_eq!(
+            metadata.security_policy.as_deref(),
+            Some("docs/security.md")
+        );
+        assert_eq!(
+            metadata.ci_configs,
+            vec![
+                CiConfig {
+                    provider: CiProvider::GitHubActions,
+                    path: ".github/workflows/ci.yml".into(),
+                },
+                CiConfig {
+                    provider: CiProvider::Jenkins,
+                    path: "Jenkinsfile".into(),
+                },
+            ]
+        );
+        assert_eq!(
+            metadata.manifests,
+            vec!["Cargo.toml", "crates/cli/Cargo.toml"]
+        );
+    }
+
+    #[test]
+    fn missing_files_stay_empty() {
+        let metadata = summarize("abc".into(), &paths(&["src/main.rs", "LICENSES/MIT.txt"]));
+        assert_eq!(metadata.readme, None);
+        assert_eq!(metadata.license, None);
+        assert!(metadata.ci_configs.is_empty());
+        assert!(metadata.manifests.is_empty());
+        assert!(!is_community_name("READMEFIRST", &["README"]));
+        assert!(!is_community_name("LICENSE.rs", &["LICENSE"]));
+        assert!(is_community_name("copying", &["COPYING"]));
+    }
+
+    #[test]
+    fn common_licenses_are_named() {
+        assert_eq!(
+            identify_license(
+                "MIT License\n\nPermission is hereby granted, free of charge, to any person"
+            ),
+            Some("MIT")
+        );
+        assert_eq!(
+            identify_license("Apache License\n   Version 2.0, January 2004"),
+            Some("Apache-2.0")
+        );
+        assert_eq!(
+            identify_license(
+                "GNU LESSER GENERAL PUBLIC LICENSE\nVersion 3, 29 June 2007\n\
+                 ... the GNU General Public License ..."
+            ),
+            Some("LGPL-3.0")
+        );
+        assert_eq!(
+            identify_license("GNU GENERAL PUBLIC LICENSE\n   Version 3, 29 June 2007"),
+            Some("GPL-3.0")
+        );
+        assert_eq!(identify_license("All rights reserved."), None);
+    }
+}
@@ -0,0 +1,251 @@
+//! Inbound push webhooks from the forges that host linked remotes.
+//!
+//! An owner enables the hook per repository and receives a secret to paste into the
+//! upstream project's webhook settings. Deliveries go to `/hooks/mirror/<path>`. GitHub
+//! deliveries are signed with `X-Hub-Signature-256`. GitLab deliveries carry the secret in
+//! `X-Gitlab-Token`. A verified push queues an immediate sync instead of waiting for the
+//! next poll.
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use sqlx::SqlitePool;
+
+use crate::user::db::unix_now;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Provider {
+    GitHub,
+    GitLab,
+}
+
+/// What a verified delivery asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delivery {
+    /// New commits or tags upstream; the mirror should sync.
+    Push(Provider),
+    /// Setup pings and events that do not change refs.
+    Ignored(Provider),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// Neither GitHub nor GitLab headers are present.
+    UnknownSender,
+    /// The signature or token is missing or does not match the secret.
+    BadSecret,
+}
+
+/// Identifies the sender from its headers and checks the delivery against `secret`.
+///
+/// `header` looks up a request header by lower-case name.
+pub fn verify_delivery<'h>(
+    header: impl Fn(&str) -> Option<&'h str>,
+    body: &[u8],
+    secret: &str,
+) -> Result<Delivery, Rejection> {
+    if let Some(event) = header("x-github-event") {
+        let signature = header("x-hub-signature-256")
+            .and_then(|value| value.strip_prefix("sha256="))
+            .and_then(decode_hex)
+            .ok_or(Rejection::BadSecret)?;
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|_| Rejection::BadSecret)?;
+        mac.update(body);
+        mac.verify_slice(&signature)
+            .map_err(|_| Rejection::BadSecret)?;
+        return Ok(match event {
+            "push" => Delivery::Push(Provider::GitHub),
+            _ => Delivery::Ignored(Provider::GitHub),
+        });
+    }
+
+    if let Some(event) = header("x-gitlab-event") {
+        let token = header("x-gitlab-token").ok_or(Rejection::BadSecret)?;
+        if !constant_time_eq(token.as_bytes(), secret.as_bytes()) {
+            return Err(Rejection::BadSecret);
+        }
+        return Ok(match event {
+            "Push Hook" | "Tag Push Hook" => Delivery::Push(Provider::GitLab),
+            _ => Delivery::Ignored(Provider::GitLab),
+        });
+    }
+
+    Err(Rejection::UnknownSender)
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Creates or replaces the webhook secret for a repository and returns it.
+pub async fn rotate_mirror_webhook_secret(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> Result<String, sqlx::Error> {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let secret = URL_SAFE_NO_PAD.encode(bytes);
+
+    sqlx::query(
+        "INSERT INTO mirror_webhooks (repository_id, secret, created_at) VALUES (?, ?, ?) \
+         ON CONFLICT(repository_id) DO UPDATE SET secret = excluded.secret, \
+         created_at = excluded.created_at, last_delivery_at = NULL",
+    )
+    .bind(repository_id)
+    .bind(&secret)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    Ok(secret)
+}
+
+pub async fn disable_mirror_webhook(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM mirror_webhooks WHERE repository_id = ?")
+        .bind(repository_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn fetch_mirror_webhook_secret(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT secret FROM mirror_webhooks WHERE repository_id = ?")
+        .bind(repository_id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn record_delivery(pool: &SqlitePool, repository_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE mirror_webhooks SET last_delivery_at = ? WHERE repository_id = ?")
+        .bind(unix_now())
+        .bind(repository_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    fn headers<'a>(pairs: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<&'a str> {
+        move |name| {
+            pairs
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| *value)
+        }
+    }
+
+    fn github_signature(secret: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        let digest = mac.finalize().into_bytes();
+        format!(
+            "sha256={}",
+            digest
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        )
+    }
+
+    #[test]
+    fn github_pushes_need_a_valid_signature() {
+        let body = br#"{"ref":"refs/heads/main"}"#;
+        let signature = github_signature("s3cret", body);
+        let good = [
+            ("x-github-event", "push"),
+            ("x-hub-signature-256", signature.as_str()),
+        ];
+        assert_eq!(
+            verify_delivery(headers(&good), body, "s3cret"),
+            Ok(Delivery::Push(Provider::GitHub))
+        );
+        assert_eq!(
+            verify_delivery(headers(&good), body, "other"),
+            Err(Rejection::BadSecret)
+        );
+        assert_eq!(
+            verify_delivery(headers(&good), b"tampered", "s3cret"),
+            Err(Rejection::BadSecret)
+        );
+
+        let ping = [
+            ("x-github-event", "ping"),
+            ("x-hub-signature-256", signature.as_str()),
+        ];
+        assert_eq!(
+            verify_delivery(headers(&ping), body, "s3cret"),
+            Ok(Delivery::Ignored(Provider::GitHub))
+        );
+        let unsigned = [("x-github-event", "push")];
+        assert_eq!(
+            verify_delivery(headers(&unsigned), body, "s3cret"),
+            Err(Rejection::BadSecret)
+        );
+    }
+
+    #[test]
+    fn gitlab_pushes_need_the_token() {
+        let good = [
+            ("x-gitlab-event", "Tag Push Hook"),
+            ("x-gitlab-token", "s3cret"),
+        ];
+        assert_eq!(
+            verify_delivery(headers(&good), b"{}", "s3cret"),
+            Ok(Delivery::Push(Provider::GitLab))
+        );
+        let bad = [("x-gitlab-event", "Push Hook"), ("x-gitlab-token", "guess")];
+        assert_eq!(
+            verify_delivery(headers(&bad), b"{}", "s3cret"),
+            Err(Rejection::BadSecret)
+        );
+        assert_eq!(
+            verify_delivery(headers(&[]), b"{}", "s3cret"),
+            Err(Rejection::UnknownSender)
+        );
+    }
+
+    #[tokio::test]
+    async fn rotating_replaces_the_secret() {
+        let pool = create_test_pool().await.unwrap();
+        sqlx::query("INSERT INTO repositories (id, slug, remote_url) VALUES ('r1', 'serde', 'https://github.com/serde-rs/serde')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let first = rotate_mirror_webhook_secret(&pool, "r1").await.unwrap();
+        let second = rotate_mirror_webhook_secret(&pool, "r1").await.unwrap();
+        assert_ne!(first, second);
+        assert_eq!(
+            fetch_mirror_webhook_secret(&pool, "r1").await.unwrap(),
+            Some(second)
+        );
+
+        assert!(disable_mirror_webhook(&pool, "r1").await.unwrap());
+        assert_eq!(
+            fetch_mirror_webhook_secret(&pool, "r1").await.unwrap(),
+            None
+        );
+    }
+}
@@ -1,10 +1,41 @@
 pub mod cache;
+pub mod cherry_pick;
+pub mod clone;
+pub mod closing;
+pub mod commit_search;
+pub mod compare;
+pub mod contributor_stats;
 pub mod db;
+pub mod descriptions;
+pub mod diff;
+pub mod discovery;
 pub mod entries;
+pub mod finder;
+pub mod git;
+pub mod graph;
+pub mod import;
+pub mod integrity;
+pub mod language;
+pub mod layout;
+pub mod lifecycle;
+pub mod merge;
+pub mod metadata;
+pub mod mirror_hook;
 pub mod models;
 pub mod mutations;
+pub mod policy;
+pub mod projects;
 pub mod queries;
 pub mod readme;
+pub mod resolver;
+pub mod secrets;
+pub mod share_links;
 pub mod storage;
+pub mod suggestions;
+pub mod symbols;
+pub mod traffic;
+pub mod visibility;
+pub mod watch;
 
-pub use storage::RepositoryStorage;
+pub use resolver::PathResolver;
+pub use storage::{RepositoryStorage, StorageLayout};
@@ -61,6 +61,98 @@ pub struct RepositoryFilePayload {
     pub truncated: bool,
 }
 
+/// A commit as `listCommits` and `getCommit` return it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepositoryCommit {
+    pub oid: String,
+    pub summary: String,
+    pub message: String,
+    pub author: CommitSignature,
+    pub committer: CommitSignature,
+    pub parents: Vec<String>,
+    /// Changes against the first parent; `None` when they were not asked for.
+    pub changes: Option<CommitChanges>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitSignature {
+    pub name: String,
+    pub email: String,
+    /// Unix seconds
+    pub time: i64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommitChanges {
+    /// Capped; the totals below still count every file.
+    pub files: Vec<ChangedFile>,
+    pub files_changed: usize,
+    pub additions: u64,
+    pub deletions: u64,
+    pub truncated: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangedFile {
+    pub path: String,
+    /// Set for renames
+    pub previous_path: Option<String>,
+    pub status: ChangeStatus,
+    pub additions: u32,
+    pub deletions: u32,
+    /// Binary files have no line counts.
+    pub binary: bool,
+    /// Unified diff hunks, when patches were asked for and the file is text
+    pub patch: Option<String>,
+    /// The patch was cut short, or left out because the diff's patches were too large.
+    pub patch_truncated: bool,
+}
+
+/// Who last changed each line of a file, as `blameRepositoryFile` returns it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileBlame {
+    /// The commit the file was blamed at
+    pub oid: String,
+    pub path: String,
+    pub line_count: u32,
+    /// In line order, covering every line once
+    pub ranges: Vec<BlameRange>,
+}
+
+/// Consecutive lines last changed by the same commit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlameRange {
+    /// 1-based and inclusive
+    pub start_line: u32,
+    pub end_line: u32,
+    /// Where the range starts in the file as that commit left it
+    pub original_start_line: u32,
+    /// Set when the file had another path in that commit
+    pub original_path: Option<String>,
+    pub oid: String,
+    pub summary: String,
+    pub author: CommitSignature,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChangeStatus {
+    Added,
+    Deleted,
+    Modified,
+    Renamed,
+}
+
+impl ChangeStatus {
+    pub fn as_graphql(self) -> &'static str {
+        match self {
+            ChangeStatus::Added => "ADDED",
+            ChangeStatus::Deleted => "DELETED",
+            ChangeStatus::Modified => "MODIFIED",
+            ChangeStatus::Renamed => "RENAMED",
+        }
+    }
+}
+
 impl From<RepositorySummaryRow> for RepositorySummary {
     fn from(row: RepositorySummaryRow) -> Self {
         RepositorySummary {
@@ -1,10 +1,22 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
 use sqlx::SqlitePool;
 
+use super::clone::{CloneJobPayload, CloneLimits, enqueue_remote_clone};
 use super::db::{remote_url_exists, slug_conflicts_for_repository};
+use super::discovery::index_repository;
+use super::lifecycle::{LifecycleEvent, LifecycleKind, enqueue_lifecycle_event};
 use super::models::RepositoryRecord;
+use super::queries::reconstruct_repository_path;
+use super::resolver::PathResolver;
+use crate::compliance::{Deletion, archive_deletion};
+use crate::events::{Event, publish};
 use crate::group::db::fetch_group_by_id;
+use crate::group::settings::inherit_group_settings;
 use crate::repository::storage::RepositoryStorage;
-use crate::validation::slug::validate_slug;
+use crate::user::db::{fetch_repository_owner, unix_now};
+use crate::validation::slug::{prepare_slug, validate_slug};
 use crate::validation::url::normalize_remote_repository;
 
 #[derive(Clone, Debug)]
@@ -17,7 +29,7 @@ pub async fn create_repository_raw(
     pool: &SqlitePool,
     input: CreateRepositoryInput,
 ) -> anyhow::Result<RepositoryRecord> {
-    validate_slug(&input.slug)?;
+    let slug = prepare_slug(&input.slug)?;
 
     let group_id = match input.group {
         Some(ref id) => {
@@ -30,25 +42,31 @@ pub async fn create_repository_raw(
         None => None,
     };
 
-    if slug_conflicts_for_repository(pool, group_id.as_deref(), &input.slug).await? {
+    if slug_conflicts_for_repository(pool, group_id.as_deref(), &slug).await? {
         return Err(anyhow::anyhow!("slug already exists in this group"));
     }
 
     let id = cuid2::create_id();
-    sqlx::query("INSERT INTO repositories (id, slug, \"group\", remote_url) VALUES (?, ?, ?, ?) ")
-        .bind(&id)
-        .bind(&input.slug)
-        .bind(group_id.as_ref())
-        .bind::<Option<&str>>(None)
-        .execute(pool)
-        .await?;
+    sqlx::query(
+        "INSERT INTO repositories (id, slug, \"group\", remote_url, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(&slug)
+    .bind(group_id.as_ref())
+    .bind::<Option<&str>>(None)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    index_repository(pool, &id).await?;
 
-    Ok(RepositoryRecord {
+    let record = RepositoryRecord {
         id,
-        slug: input.slug,
+        slug,
         group_id,
         remote_url: None,
-    })
+    };
+    inherit_group_settings(pool, &record).await?;
+    Ok(record)
 }
 
 pub async fn link_remote_repository_raw(
@@ -70,43 +88,37 @@ pub async fn link_remote_repository_raw(
 
     let id = cuid2::create_id();
 
-    // Clone the repository to local storage as a bare repository
-    let local_path = storage.local_root.join(format!("{}.git", slug));
-    let url_clone = normalized_url.clone();
-    let local_path_clone = local_path.clone();
-
-    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
-        // Ensure parent directory exists
-        if let Some(parent) = local_path_clone.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-
-        // Remove existing directory if present (for re-linking scenarios)
-        if local_path_clone.exists() {
-            std::fs::remove_dir_all(&local_path_clone)?;
-        }
-
-        // Clone as bare repository using fetch_only which creates a bare repo
-        let mut prepare = gix::prepare_clone(url_clone, &local_path_clone)?;
-        let (_repo, _outcome) = prepare
-            .fetch_only(gix::progress::Discard, &gix::interrupt::IS_INTERRUPTED)?;
-
-        // Mark repository as public by creating git-daemon-export-ok
-        std::fs::write(local_path_clone.join("git-daemon-export-ok"), b"")?;
-
-        Ok(())
-    })
-    .await
-    .map_err(|e| anyhow::anyhow!("Failed to spawn clone task: {}", e))??;
+    // A fresh link must not resume from a directory left behind by an earlier link.
+    let local_path = storage.repository_path(std::slice::from_ref(&slug));
+    if tokio::fs::try_exists(&local_path).await? {
+        tokio::fs::remove_dir_all(&local_path).await?;
+    }
 
     sqlx::query(
-        "INSERT INTO repositories (id, slug, \"group\", remote_url) VALUES (?, ?, NULL, ?)",
+        "INSERT INTO repositories (id, slug, \"group\", remote_url, created_at) \
+         VALUES (?, ?, NULL, ?, ?)",
     )
     .bind(&id)
     .bind(&slug)
     .bind(&normalized_url)
+    .bind(unix_now())
     .execute(pool)
     .await?;
+    index_repository(pool, &id).await?;
+
+    // The clone itself runs on the job worker so it can be throttled and retried.
+    let limits = CloneLimits::from_config(&crate::config::current().clone);
+    enqueue_remote_clone(
+        pool,
+        CloneJobPayload {
+            repository_id: id.clone(),
+            url: normalized_url.clone(),
+            segments: vec![slug.clone()],
+            sync: false,
+        },
+        limits.max_attempts,
+    )
+    .await?;
 
     Ok(RepositoryRecord {
         id,
@@ -115,3 +127,301 @@ pub async fn link_remote_repository_raw(
         remote_url: Some(normalized_url),
     })
 }
+
+/// Deletes a repository and its git data, archiving a record of the deletion,
+/// exporting a `repository.deleted` event and telling extensions.
+/// Returns `false` when nothing exists at `path`.
+pub async fn delete_repository_raw(
+    pool: &SqlitePool,
+    resolver: &PathResolver,
+    storage: &RepositoryStorage,
+    path: &str,
+    actor_did: Option<&str>,
+) -> anyhow::Result<bool> {
+    let Some(resolved) = resolver.resolve_repository(path).await? else {
+        return Ok(false);
+    };
+    let record = &resolved.record;
+    let owner = fetch_repository_owner(pool, &record.id).await?;
+    let event_data = serde_json::json!({
+        "path": resolved.canonical_path(),
+        "slug": record.slug,
+    });
+    let summary = serde_json::json!({
+        "path": resolved.canonical_path(),
+        "slug": record.slug,
+        "groupId": record.group_id,
+        "remoteUrl": record.remote_url,
+        "owner": owner,
+    });
+
+    let mut tx = pool.begin().await?;
+    archive_deletion(
+        &mut *tx,
+        &crate::config::current().compliance,
+        Deletion {
+            subject_kind: "repository",
+            subject_id: &record.id,
+            source: "core",
+            actor_did,
+            summary,
+        },
+    )
+    .await?;
+    publish(
+        &mut *tx,
+        crate::config::current().event_sink.as_ref(),
+        Event {
+            kind: "repository.deleted",
+            source: "core",
+            actor_did,
+            repository_id: Some(&record.id),
+            data: event_data,
+        },
+    )
+    .await?;
+    enqueue_lifecycle_event(
+        &mut *tx,
+        &LifecycleEvent::new(
+            LifecycleKind::Deleted,
+            &record.id,
+            Some(resolved.canonical_path()),
+            None,
+        ),
+    )
+    .await?;
+    sqlx::query("DELETE FROM repositories WHERE id = ?")
+        .bind(&record.id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    resolver.invalidate_all();
+
+    // The row is gone, so a failure here only leaves an unreachable directory behind.
+    if let Ok(dir) = resolved.local_dir(storage)
+        && let Err(err) = tokio::fs::remove_dir_all(&dir).await
+    {
+        tracing::warn!("failed to remove {}: {}", dir.display(), err);
+    }
+    Ok(true)
+}
+
+/// Renames a repository, moves it to another group (`None` for the top level), or both,
+/// and moves its git data along. Moving between groups is a transfer even when the slug
+/// changes too; either way a `repository.renamed` or `repository.transferred` event is
+/// exported and extensions are told. Returns `None` when nothing exists at `path`.
+pub async fn move_repository_raw(
+    pool: &SqlitePool,
+    resolver: &PathResolver,
+    storage: &RepositoryStorage,
+    path: &str,
+    slug: &str,
+    group: Option<&str>,
+    actor_did: Option<&str>,
+) -> anyhow::Result<Option<RepositoryRecord>> {
+    let Some(resolved) = resolver.resolve_repository(path).await? else {
+        return Ok(None);
+    };
+    let slug = prepare_slug(slug)?;
+    if let Some(group) = group
+        && fetch_group_by_id(pool, group).await?.is_none()
+    {
+        return Err(anyhow::anyhow!("group not found"));
+    }
+    let current = &resolved.record;
+    let transferred = current.group_id.as_deref() != group;
+    if !transferred && current.slug == slug {
+        return Ok(Some(current.clone()));
+    }
+    if slug_conflicts_for_repository(pool, group, &slug).await? {
+        return Err(anyhow::anyhow!("slug already exists in this group"));
+    }
+
+    let record = RepositoryRecord {
+        id: current.id.clone(),
+        slug,
+        group_id: group.map(str::to_string),
+        remote_url: current.remote_url.clone(),
+    };
+    let old_path = resolved.canonical_path();
+    let new_path = reconstruct_repository_path(pool, &record).await?;
+    let segments: Vec<String> = new_path.split('/').map(str::to_string).collect();
+    // Linked remotes are cached by id, so only local repositories have data to move.
+    let source = match record.remote_url {
+        Some(_) => None,
+        None => resolved.local_dir(storage).ok().map(|dir| {
+            // A non-bare repository resolves to its `.git`; move the whole working copy.
+            if dir.file_name().is_some_and(|name| name == ".git") {
+                dir.parent().map(PathBuf::from).unwrap_or(dir)
+            } else {
+                dir
+            }
+        }),
+    };
+    let target = storage.repository_path(&segments);
+    if source.is_some() && tokio::fs::try_exists(&target).await? {
+        return Err(anyhow::anyhow!(
+            "{} already exists in storage",
+            target.display()
+        ));
+    }
+
+    let (event_kind, lifecycle_kind) = if transferred {
+        ("repository.transferred", LifecycleKind::Transferred)
+    } else {
+        ("repository.renamed", LifecycleKind::Renamed)
+    };
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE repositories SET slug = ?, \"group\" = ? WHERE id = ?")
+        .bind(&record.slug)
+        .bind(record.group_id.as_ref())
+        .bind(&record.id)
+        .execute(&mut *tx)
+        .await?;
+    publish(
+        &mut *tx,
+        crate::config::current().event_sink.as_ref(),
+        Event {
+            kind: event_kind,
+            source: "core",
+            actor_did,
+            repository_id: Some(&record.id),
+            data: serde_json::json!({
+                "oldPath": old_path,
+                "path": new_path,
+                "slug": record.slug,
+                "groupId": record.group_id,
+            }),
+        },
+    )
+    .await?;
+    enqueue_lifecycle_event(
+        &mut *tx,
+        &LifecycleEvent::new(
+            lifecycle_kind,
+            &record.id,
+            Some(old_path),
+            Some(new_path.clone()),
+        ),
+    )
+    .await?;
+    if let Some(source) = &source {
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::rename(source, &target).await.with_context(|| {
+            format!(
+                "failed to move {} to {}",
+                source.display(),
+                target.display()
+            )
+        })?;
+    }
+    if let Err(err) = tx.commit().await {
+        if let Some(source) = &source
+            && let Err(restore) = tokio::fs::rename(&target, source).await
+        {
+            tracing::error!("failed to move {} back: {}", target.display(), restore);
+        }
+        return Err(err.into());
+    }
+    if let Err(e) = index_repository(pool, &record.id).await {
+        tracing::warn!(repository = %record.id, "search index update failed: {}", e);
+    }
+    resolver.invalidate_all();
+    Ok(Some(record))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::mutations::{CreateGroupInput, create_group_raw};
+    use crate::repository::lifecycle::JOB_KIND as LIFECYCLE_JOB_KIND;
+    use crate::test_helpers::create_test_pool;
+
+    async fn lifecycle_payloads(pool: &SqlitePool) -> Vec<LifecycleEvent> {
+        let payloads: Vec<String> =
+            sqlx::query_scalar("SELECT payload FROM jobs WHERE kind = ? ORDER BY rowid")
+                .bind(LIFECYCLE_JOB_KIND)
+                .fetch_all(pool)
+                .await
+                .unwrap();
+        payloads
+            .iter()
+            .map(|payload| serde_json::from_str(payload).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn moving_a_repository_moves_its_data_and_tells_extensions() {
+        let pool = create_test_pool().await.unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let storage = RepositoryStorage::new(root.path().join("repos"), root.path().join("cache"));
+        let resolver = PathResolver::new(pool.clone());
+        let team = create_group_raw(
+            &pool,
+            CreateGroupInput {
+                slug: "team".into(),
+                parent: None,
+            },
+        )
+        .await
+        .unwrap();
+        for slug in ["app", "taken"] {
+            create_repository_raw(
+                &pool,
+                CreateRepositoryInput {
+                    slug: slug.into(),
+                    group: None,
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let original = storage.repository_path(&["app".to_string()]);
+        std::fs::create_dir_all(original.join("objects")).unwrap();
+
+        let renamed = move_repository_raw(&pool, &resolver, &storage, "app", "service", None, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(renamed.slug, "service");
+        assert!(!original.exists());
+        assert!(
+            storage
+                .repository_path(&["service".to_string()])
+                .join("objects")
+                .is_dir()
+        );
+        assert!(resolver.resolve_repository("app").await.unwrap().is_none());
+
+        let conflict =
+            move_repository_raw(&pool, &resolver, &storage, "service", "taken", None, None).await;
+        assert!(conflict.is_err());
+
+        let transferred = move_repository_raw(
+            &pool,
+            &resolver,
+            &storage,
+            "service",
+            "service",
+            Some(&team.id),
+            None,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(transferred.group_id.as_deref(), Some(team.id.as_str()));
+        let segments = ["team".to_string(), "service".to_string()];
+        assert!(storage.repository_path(&segments).is_dir());
+
+        let events = lifecycle_payloads(&pool).await;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, LifecycleKind::Renamed);
+        assert_eq!(events[0].old_path.as_deref(), Some("app"));
+        assert_eq!(events[0].new_path.as_deref(), Some("service"));
+        assert_eq!(events[1].kind, LifecycleKind::Transferred);
+        assert_eq!(events[1].new_path.as_deref(), Some("team/service"));
+        assert!(events.iter().all(|event| event.repository_id == renamed.id));
+    }
+}
@@ -0,0 +1,201 @@
+//! Per-repository push policies.
+//!
+//! Rules are stored as RON (the same format as `forge.ron`) so operators can copy a
+//! policy between the config file, the database and the API unchanged. Evaluation
+//! lives in [`git_http::policy`]; this module only loads, stores and dispatches.
+
+use anyhow::Context;
+use git_http::policy::{
+    PolicyReport, PolicyRules, RefUpdate, RuleViolation, check_branch_name, check_commit_message,
+    check_push,
+};
+use sqlx::SqlitePool;
+
+use super::resolver::PathResolver;
+use super::storage::RepositoryStorage;
+use crate::user::db::unix_now;
+
+/// Parses and validates RON policy source.
+pub fn parse_policy(source: &str) -> anyhow::Result<PolicyRules> {
+    let rules: PolicyRules = ron::from_str(source).context("policy is not valid RON")?;
+    rules.validate()?;
+    Ok(rules)
+}
+
+pub fn policy_source(rules: &PolicyRules) -> String {
+    ron::ser::to_string_pretty(rules, ron::ser::PrettyConfig::default()).unwrap_or_default()
+}
+
+/// The stored policy for a repository; repositories without one get the permissive default.
+pub async fn load_policy(pool: &SqlitePool, repository_id: &str) -> anyhow::Result<PolicyRules> {
+    let source: Option<String> =
+        sqlx::query_scalar("SELECT rules FROM repository_policies WHERE repository_id = ?")
+            .bind(repository_id)
+            .fetch_optional(pool)
+            .await?;
+    match source {
+        Some(source) => parse_policy(&source),
+        None => Ok(PolicyRules::default()),
+    }
+}
+
+pub async fn save_policy(
+    pool: &SqlitePool,
+    repository_id: &str,
+    source: &str,
+) -> anyhow::Result<PolicyRules> {
+    let rules = parse_policy(source)?;
+    if rules.is_empty() {
+        sqlx::query("DELETE FROM repository_policies WHERE repository_id = ?")
+            .bind(repository_id)
+            .execute(pool)
+            .await?;
+        return Ok(rules);
+    }
+    sqlx::query(
+        "INSERT INTO repository_policies (repository_id, rules, updated_at) VALUES (?, ?, ?) \
+         ON CONFLICT(repository_id) DO UPDATE SET rules = excluded.rules, updated_at = excluded.updated_at",
+    )
+    .bind(repository_id)
+    .bind(policy_source(&rules))
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    Ok(rules)
+}
+
+/// Dry-runs the repository's policy against hypothetical ref updates.
+pub async fn check_repository_policy(
+    resolver: &PathResolver,
+    storage: &RepositoryStorage,
+    path: &str,
+    updates: &[RefUpdate],
+) -> anyhow::Result<Option<PolicyReport>> {
+    let Some(resolved) = resolver.resolve_repository(path).await? else {
+        return Ok(None);
+    };
+    let rules = load_policy(resolver.pool(), &resolved.record.id).await?;
+    let repo_dir = resolved.local_dir(storage)?;
+    Ok(Some(check_push(&repo_dir, &rules, updates).await?))
+}
+
+/// Checks a commit message against the repository's policy before it is pushed.
+pub async fn validate_commit_message(
+    resolver: &PathResolver,
+    path: &str,
+    message: &str,
+) -> anyhow::Result<Option<Vec<RuleViolation>>> {
+    let Some(resolved) = resolver.resolve_repository(path).await? else {
+        return Ok(None);
+    };
+    let rules = load_policy(resolver.pool(), &resolved.record.id).await?;
+    Ok(Some(check_commit_message(&rules, message)?))
+}
+
+/// Checks the name of a branch that does not exist yet against the repository's policy.
+pub async fn validate_branch_name(
+    resolver: &PathResolver,
+    path: &str,
+    name: &str,
+) -> anyhow::Result<Option<Vec<RuleViolation>>> {
+    let Some(resolved) = resolver.resolve_repository(path).await? else {
+        return Ok(None);
+    };
+    let rules = load_policy(resolver.pool(), &resolved.record.id).await?;
+    Ok(Some(check_branch_name(&rules, name)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::create_test_pool;
+
+    #[tokio::test]
+    async fn stores_policies_as_ron() {
+        let pool = create_test_pool().await.unwrap();
+        let repo = create_repository_raw(
+            &pool,
+            CreateRepositoryInput {
+                slug: "app".into(),
+                group: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(load_policy(&pool, &repo.id).await.unwrap().is_empty());
+
+        let saved = save_policy(
+            &pool,
+            &repo.id,
+            r#"(max_file_size: Some(1048576), forbidden_paths: ["*.pem"], forbid_merge_commits: true)"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(saved.max_file_size, Some(1_048_576));
+        assert_eq!(load_policy(&pool, &repo.id).await.unwrap(), saved);
+
+        let invalid = save_policy(
+            &pool,
+            &repo.id,
+            r#"(commit_message_pattern: Some("(oops"))"#,
+        )
+        .await;
+        assert!(invalid.is_err());
+        assert_eq!(load_policy(&pool, &repo.id).await.unwrap(), saved);
+    }
+
+    #[tokio::test]
+    async fn validates_input_against_the_stored_policy() {
+        let pool = create_test_pool().await.unwrap();
+        let repo = create_repository_raw(
+            &pool,
+            CreateRepositoryInput {
+                slug: "app".into(),
+                group: None,
+            },
+        )
+        .await
+        .unwrap();
+        save_policy(
+            &pool,
+            &repo.id,
+            r#"(commit_message_pattern: Some("^fix: "), branch_name_pattern: Some("^topic/"))"#,
+        )
+        .await
+        .unwrap();
+        let resolver = PathResolver::new(pool.clone());
+
+        let message = validate_commit_message(&resolver, "app", "wip")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message[0].rule, "commit-message");
+        assert!(
+            validate_commit_message(&resolver, "app", "fix: it")
+                .await
+                .unwrap()
+                .unwrap()
+                .is_empty()
+        );
+        let branch = validate_branch_name(&resolver, "app", "main")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(branch[0].rule, "branch-name");
+        assert!(
+            validate_branch_name(&resolver, "app", "topic/x")
+                .await
+                .unwrap()
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            validate_branch_name(&resolver, "missing", "topic/x")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
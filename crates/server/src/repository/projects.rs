@@ -0,0 +1,297 @@
+//! Sub-project views of monorepos.
+//!
+//! A project is a directory of the tree that contains a build manifest (`Cargo.toml`,
+//! `package.json`, `go.mod`, ...), or one the owner configured as a project root. The
+//! configured roots are listed even without a manifest, which covers build systems
+//! detection does not know about. Detection reads the file index of [`super::finder`],
+//! so listing projects after a file search at the same revision does not walk the tree
+//! again.
+//!
+//! `browseRepository` and `findFiles` take an optional `project` root and then only see
+//! that directory.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, bail};
+use sqlx::SqlitePool;
+use tokio::task;
+
+use super::entries::normalize_tree_path;
+use super::finder::load_index;
+use super::resolver::PathResolver;
+use super::storage::RepositoryStorage;
+use crate::user::db::unix_now;
+
+/// Manifest file names that make their directory a project.
+pub const MANIFESTS: &[&str] = &[
+    "Cargo.toml",
+    "package.json",
+    "deno.json",
+    "go.mod",
+    "pyproject.toml",
+    "setup.py",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "Gemfile",
+    "composer.json",
+    "mix.exs",
+    "Package.swift",
+];
+/// Manifests below these directories belong to vendored or generated code.
+const SKIPPED_DIRS: &[&str] = &["node_modules", "vendor", "third_party", "target", "dist"];
+const MAX_ROOTS: usize = 100;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Project {
+    /// Directory relative to the repository root; empty for the root itself.
+    pub root: String,
+    /// Last segment of `root`, or the repository slug for the root.
+    pub name: String,
+    /// Manifest file names found in `root`.
+    pub manifests: Vec<String>,
+    /// Whether the owner listed `root` as a project root.
+    pub configured: bool,
+}
+
+/// Projects of a tree, given its file paths and the configured roots, ordered by root.
+pub fn detect_projects(
+    paths: &[String],
+    configured: &[String],
+    repository_slug: &str,
+) -> Vec<Project> {
+    let mut projects: BTreeMap<&str, Project> = BTreeMap::new();
+    let project = |root: &str| Project {
+        root: root.to_string(),
+        name: root
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .unwrap_or(repository_slug)
+            .to_string(),
+        manifests: Vec::new(),
+        configured: false,
+    };
+    for root in configured {
+        projects.insert(
+            root.as_str(),
+            Project {
+                configured: true,
+                ..project(root)
+            },
+        );
+    }
+    for path in paths {
+        let (dir, file) = path.rsplit_once('/').unwrap_or(("", path));
+        if !MANIFESTS.contains(&file)
+            || dir
+                .split('/')
+                .any(|segment| SKIPPED_DIRS.contains(&segment))
+        {
+            continue;
+        }
+        projects
+            .entry(dir)
+            .or_insert_with(|| project(dir))
+            .manifests
+            .push(file.to_string());
+    }
+    projects.into_values().collect()
+}
+
+/// Lists the projects of the repository at `path` at `rev` (default `HEAD`). Returns
+/// `None` when the repository does not exist or `viewer_did` may not read it.
+pub async fn list_repository_projects(
+    pool: &SqlitePool,
+    resolver: &PathResolver,
+    viewer_did: Option<&str>,
+    storage: &RepositoryStorage,
+    path: String,
+    rev: Option<String>,
+) -> anyhow::Result<Option<Vec<Project>>> {
+    let Some(resolved) = resolver
+        .resolve_visible_repository(&path, viewer_did)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let configured = load_project_roots(pool, &resolved.record.id).await?;
+    let repository_path = resolved.local_dir(storage)?;
+    let slug = resolved.record.slug.clone();
+
+    let projects = task::spawn_blocking(move || -> anyhow::Result<Vec<Project>> {
+        let paths = match load_index(repository_path, rev.as_deref())? {
+            Some(index) => index.paths().to_vec(),
+            None => Vec::new(),
+        };
+        Ok(detect_projects(&paths, &configured, &slug))
+    })
+    .await
+    .map_err(|err| anyhow::anyhow!(err))??;
+    Ok(Some(projects))
+}
+
+/// Joins a `project` root and a path inside it into a path from the repository root.
+pub fn scope_tree_path(
+    project: Option<String>,
+    tree_path: Option<String>,
+) -> anyhow::Result<Option<String>> {
+    let project = normalize_tree_path(project)?;
+    if project.is_empty() {
+        return Ok(tree_path);
+    }
+    let tree_path = normalize_tree_path(tree_path)?;
+    Ok(Some(if tree_path.is_empty() {
+        project
+    } else {
+        format!("{project}/{tree_path}")
+    }))
+}
+
+pub async fn load_project_roots(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> anyhow::Result<Vec<String>> {
+    let roots: Option<String> =
+        sqlx::query_scalar("SELECT roots FROM repository_project_roots WHERE repository_id = ?")
+            .bind(repository_id)
+            .fetch_optional(pool)
+            .await?;
+    match roots {
+        Some(roots) => serde_json::from_str(&roots).context("stored project roots"),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Replaces the configured roots. They are normalized, deduplicated and sorted; the
+/// stored list is returned.
+pub async fn save_project_roots(
+    pool: &SqlitePool,
+    repository_id: &str,
+    roots: Vec<String>,
+) -> anyhow::Result<Vec<String>> {
+    let mut normalized = Vec::with_capacity(roots.len());
+    for root in roots {
+        let root = normalize_tree_path(Some(root))?;
+        if root.is_empty() {
+            bail!("project roots must name a directory below the repository root");
+        }
+        normalized.push(root);
+    }
+    normalized.sort();
+    normalized.dedup();
+    if normalized.len() > MAX_ROOTS {
+        bail!("a repository can have at most {} project roots", MAX_ROOTS);
+    }
+
+    if normalized.is_empty() {
+        sqlx::query("DELETE FROM repository_project_roots WHERE repository_id = ?")
+            .bind(repository_id)
+            .execute(pool)
+            .await?;
+        return Ok(normalized);
+    }
+    sqlx::query(
+        "INSERT INTO repository_project_roots (repository_id, roots, updated_at) VALUES (?, ?, ?) \
+         ON CONFLICT(repository_id) DO UPDATE SET roots = excluded.roots, updated_at = excluded.updated_at",
+    )
+    .bind(repository_id)
+    .bind(serde_json::to_string(&normalized)?)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    fn paths(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn projects_are_detected_from_manifests() {
+        let tree = paths(&[
+            "Cargo.toml",
+            "README.md",
+            "crates/server/Cargo.toml",
+            "crates/server/src/main.rs",
+            "apps/web/package.json",
+            "apps/web/node_modules/left-pad/package.json",
+            "tools/gen/go.mod",
+            "docs/index.md",
+        ]);
+        let projects = detect_projects(&tree, &["docs".to_string()], "forge");
+        let summary: Vec<(&str, &str, bool)> = projects
+            .iter()
+            .map(|p| (p.root.as_str(), p.name.as_str(), p.configured))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("", "forge", false),
+                ("apps/web", "web", false),
+                ("crates/server", "server", false),
+                ("docs", "docs", true),
+                ("tools/gen", "gen", false),
+            ]
+        );
+        assert_eq!(projects[2].manifests, vec!["Cargo.toml"]);
+        assert!(projects[3].manifests.is_empty());
+    }
+
+    #[test]
+    fn tree_paths_are_scoped_to_the_project() {
+        let scope = |project: Option<&str>, path: Option<&str>| {
+            scope_tree_path(project.map(str::to_string), path.map(str::to_string))
+        };
+        assert_eq!(scope(None, Some("src")).unwrap().as_deref(), Some("src"));
+        assert_eq!(
+            scope(Some("apps/web/"), None).unwrap().as_deref(),
+            Some("apps/web")
+        );
+        assert_eq!(
+            scope(Some("apps/web"), Some("./src")).unwrap().as_deref(),
+            Some("apps/web/src")
+        );
+        assert!(scope(Some("apps/web"), Some("../../etc")).is_err());
+    }
+
+    #[tokio::test]
+    async fn roots_are_normalized_and_cleared() {
+        let pool = create_test_pool().await.unwrap();
+        sqlx::query("INSERT INTO repositories (id, slug) VALUES ('r1', 'app')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(load_project_roots(&pool, "r1").await.unwrap().is_empty());
+
+        let saved =
+            save_project_roots(&pool, "r1", paths(&["services/api/", "libs/ui", "libs/ui"]))
+                .await
+                .unwrap();
+        assert_eq!(saved, vec!["libs/ui", "services/api"]);
+        assert_eq!(load_project_roots(&pool, "r1").await.unwrap(), saved);
+
+        assert!(
+            save_project_roots(&pool, "r1", paths(&["/"]))
+                .await
+                .is_err()
+        );
+        assert!(
+            save_project_roots(&pool, "r1", paths(&["../up"]))
+                .await
+                .is_err()
+        );
+
+        save_project_roots(&pool, "r1", Vec::new()).await.unwrap();
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM repository_project_roots")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 0);
+    }
+}
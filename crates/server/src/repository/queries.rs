@@ -1,32 +1,104 @@
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use gix::diff::blob::intern::InternedInput;
+use gix::diff::blob::sources::byte_lines_with_terminator;
+use gix::revision::walk::Sorting;
+use gix::traverse::commit::simple::CommitTimeOrder;
 
 use sqlx::SqlitePool;
 use tokio::task;
 
-use super::db::resolve_repository_by_path;
+use super::diff::{ChangeDetail, tree_changes};
 use super::entries::{
     normalize_file_path, normalize_tree_path, read_repository_entries,
     read_repository_file_for_branch,
 };
 use super::models::{
-    RepositoryBranch, RepositoryEntriesPayload, RepositoryFilePayload, RepositoryRecord,
-    RepositorySummary, RepositorySummaryRow,
+    BlameRange, CommitChanges, CommitSignature, FileBlame, RepositoryBranch, RepositoryCommit,
+    RepositoryEntriesPayload, RepositoryFilePayload, RepositoryRecord, RepositorySummary,
+    RepositorySummaryRow,
 };
+use super::resolver::PathResolver;
 use super::storage::RepositoryStorage;
+use super::visibility::HIDDEN;
+use crate::graphql::pagination::{Page, PageRequest};
 use crate::group::queries::get_group_parent;
-use crate::validation::slug::validate_slug;
+use crate::search::code::enqueue_code_index;
 
-pub async fn get_all_repositories_raw(pool: &SqlitePool) -> anyhow::Result<Vec<RepositoryRecord>> {
-    let records = sqlx::query_as::<_, RepositoryRecord>(
-        "SELECT id, slug, \"group\" as group_id, remote_url FROM repositories ORDER BY slug",
-    )
+/// `listCommits` returns this many commits when no `limit` is given, and never more
+/// than the maximum.
+pub const DEFAULT_COMMIT_LIMIT: usize = 30;
+pub const MAX_COMMIT_LIMIT: usize = 100;
+/// Larger files are not blamed.
+const MAX_BLAME_BYTES: usize = 1024 * 1024;
+/// Cached blames are dropped wholesale beyond this many.
+const MAX_CACHED_BLAMES: usize = 256;
+
+/// Blames by repository, commit and file path. A commit never changes, so entries never
+/// go stale; pushes simply blame a new commit.
+type BlameKey = (PathBuf, gix::ObjectId, String);
+
+static BLAME_CACHE: LazyLock<Mutex<HashMap<BlameKey, Arc<FileBlame>>>> =
+    LazyLock::new(Mutex::default);
+
+/// Every repository `viewer_did` may read.
+pub async fn get_all_repositories_raw(
+    pool: &SqlitePool,
+    viewer_did: Option<&str>,
+) -> anyhow::Result<Vec<RepositoryRecord>> {
+    let records = sqlx::query_as::<_, RepositoryRecord>(&format!(
+        "SELECT id, slug, \"group\" as group_id, remote_url FROM repositories \
+         WHERE {HIDDEN} ORDER BY slug"
+    ))
+    .bind(viewer_did)
     .fetch_all(pool)
     .await?;
 
     Ok(records)
 }
 
+/// Cursors for repositories are tagged with this kind.
+pub const REPOSITORY_CURSOR_KIND: &str = "repository";
+
+/// One page of the repositories `viewer_did` may read across all groups, ordered by slug
+/// and then id.
+pub async fn page_repositories(
+    pool: &SqlitePool,
+    viewer_did: Option<&str>,
+    request: &PageRequest,
+) -> anyhow::Result<Page<RepositoryRecord>> {
+    let (condition, order) = request.keyset_sql();
+    let sql = format!(
+        "SELECT id, slug, \"group\" as group_id, remote_url FROM repositories \
+         WHERE {} {} {} LIMIT ?",
+        HIDDEN,
+        condition.map(|c| format!("AND {}", c)).unwrap_or_default(),
+        order
+    );
+    let mut query = sqlx::query_as::<_, RepositoryRecord>(&sql).bind(viewer_did);
+    if let Some(cursor) = &request.cursor {
+        query = query.bind(&cursor.slug).bind(&cursor.id);
+    }
+    let rows = query.bind(request.fetch_limit()).fetch_all(pool).await?;
+    Ok(Page::from_rows(request, rows))
+}
+
+pub async fn count_repositories(
+    pool: &SqlitePool,
+    viewer_did: Option<&str>,
+) -> anyhow::Result<i64> {
+    Ok(
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM repositories WHERE {HIDDEN}"))
+            .bind(viewer_did)
+            .fetch_one(pool)
+            .await?,
+    )
+}
+
 pub async fn get_repository_by_id(
     pool: &SqlitePool,
     id: &str,
@@ -41,45 +113,38 @@ pub async fn get_repository_by_id(
     Ok(record)
 }
 
+/// The repository at `path`, or `None` when it does not exist or `viewer_did` may not
+/// read it.
 pub async fn get_repository_raw(
-    pool: &SqlitePool,
+    resolver: &PathResolver,
+    viewer_did: Option<&str>,
     path: String,
 ) -> anyhow::Result<Option<RepositoryRecord>> {
-    resolve_repository_by_path(pool, &path)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+    Ok(resolver
+        .resolve_visible_repository(&path, viewer_did)
+        .await?
+        .map(|resolved| resolved.record))
 }
 
 pub async fn browse_repository_raw(
-    pool: &SqlitePool,
+    resolver: &PathResolver,
+    viewer_did: Option<&str>,
     storage: &RepositoryStorage,
     path: String,
     tree_path: Option<String>,
     branch: Option<String>,
 ) -> anyhow::Result<Option<RepositoryEntriesPayload>> {
-    let segments: Vec<String> = path
-        .split('/')
-        .filter(|segment| !segment.is_empty())
-        .map(|segment| segment.to_string())
-        .collect();
-
-    if segments.is_empty() {
-        return Ok(None);
-    }
-
-    for segment in &segments {
-        validate_slug(segment)?;
-    }
-
-    let record = resolve_repository_by_path(pool, &path).await?;
-    let Some(record) = record else {
+    let Some(resolved) = resolver
+        .resolve_visible_repository(&path, viewer_did)
+        .await?
+    else {
         return Ok(None);
     };
 
     let normalized_tree_path = normalize_tree_path(tree_path)?;
 
     // Both local and remote repositories are now in local storage
-    let repository_path = storage.ensure_local_repository(&segments)?;
+    let repository_path = resolved.local_dir(storage)?;
 
     let entries =
         read_repository_entries(repository_path, normalized_tree_path.clone(), branch).await?;
@@ -91,31 +156,20 @@ pub async fn browse_repository_raw(
 }
 
 pub async fn list_repository_branches_raw(
-    pool: &SqlitePool,
+    resolver: &PathResolver,
+    viewer_did: Option<&str>,
     storage: &RepositoryStorage,
     path: String,
 ) -> anyhow::Result<Option<Vec<RepositoryBranch>>> {
-    let segments: Vec<String> = path
-        .split('/')
-        .filter(|segment| !segment.is_empty())
-        .map(|segment| segment.to_string())
-        .collect();
-
-    if segments.is_empty() {
-        return Ok(None);
-    }
-
-    for segment in &segments {
-        validate_slug(segment)?;
-    }
-
-    let record = resolve_repository_by_path(pool, &path).await?;
-    let Some(record) = record else {
+    let Some(resolved) = resolver
+        .resolve_visible_repository(&path, viewer_did)
+        .await?
+    else {
         return Ok(None);
     };
 
     // Both local and remote repositories are now in local storage
-    let repository_path = storage.ensure_local_repository(&segments)?;
+    let repository_path = resolved.local_dir(storage)?;
 
     let branches = task::spawn_blocking(move || list_repository_branches_blocking(repository_path))
         .await
@@ -225,6 +279,451 @@ fn list_repository_branches_blocking(
     Ok(branches)
 }
 
+/// Commits reachable from `branch` (default `HEAD`), newest first by committer time.
+/// Returns `None` when the repository does not exist and an empty list before the first
+/// push. Changed files are only read when `detail` is given.
+pub async fn list_commits_raw(
+    resolver: &PathResolver,
+    storage: &RepositoryStorage,
+    path: String,
+    branch: Option<String>,
+    limit: usize,
+    offset: usize,
+    detail: Option<ChangeDetail>,
+) -> anyhow::Result<Option<Vec<RepositoryCommit>>> {
+    let Some(resolved) = resolver.resolve_repository(&path).await? else {
+        return Ok(None);
+    };
+    let repository_path = resolved.local_dir(storage)?;
+    let limit = limit.min(MAX_COMMIT_LIMIT);
+
+    let commits = task::spawn_blocking(move || {
+        list_commits_blocking(repository_path, branch, limit, offset, detail)
+    })
+    .await
+    .map_err(|err| anyhow::anyhow!(err))??;
+
+    Ok(Some(commits))
+}
+
+/// The commit `oid` (full or abbreviated) names, or `None` when the repository or the
+/// commit does not exist.
+pub async fn get_commit_raw(
+    resolver: &PathResolver,
+    storage: &RepositoryStorage,
+    path: String,
+    oid: String,
+    detail: Option<ChangeDetail>,
+) -> anyhow::Result<Option<RepositoryCommit>> {
+    let Some(resolved) = resolver.resolve_repository(&path).await? else {
+        return Ok(None);
+    };
+    let repository_path = resolved.local_dir(storage)?;
+
+    task::spawn_blocking(move || get_commit_blocking(repository_path, oid, detail))
+        .await
+        .map_err(|err| anyhow::anyhow!(err))?
+}
+
+/// Who last changed each line of `file_path` at `branch` (default `HEAD`), as ranges of
+/// lines that came from the same commit. Returns `None` when the repository does not
+/// exist or `viewer_did` may not read it. Blaming walks history back to where every line
+/// was introduced, so results are cached.
+pub async fn blame_repository_file_raw(
+    resolver: &PathResolver,
+    viewer_did: Option<&str>,
+    storage: &RepositoryStorage,
+    path: String,
+    file_path: String,
+    branch: Option<String>,
+) -> anyhow::Result<Option<Arc<FileBlame>>> {
+    let Some(resolved) = resolver
+        .resolve_visible_repository(&path, viewer_did)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let repository_path = resolved.local_dir(storage)?;
+    let file_path = normalize_file_path(file_path)?;
+
+    let blame = task::spawn_blocking(move || blame_blocking(repository_path, file_path, branch))
+        .await
+        .map_err(|err| anyhow::anyhow!(err))??;
+    Ok(Some(blame))
+}
+
+fn list_commits_blocking(
+    repository_path: PathBuf,
+    branch: Option<String>,
+    limit: usize,
+    offset: usize,
+    detail: Option<ChangeDetail>,
+) -> anyhow::Result<Vec<RepositoryCommit>> {
+    let repo = open_repository(&repository_path)?;
+    let tip = match branch.as_deref() {
+        Some(spec) => {
+            repo.rev_parse_single(spec)
+                .map_err(|err| anyhow::anyhow!("branch `{}` not found: {}", spec, err))?
+                .object()?
+                .peel_to_commit()
+                .map_err(|_| anyhow::anyhow!("`{}` does not point to a commit", spec))?
+                .id
+        }
+        None => match repo.head_commit() {
+            Ok(commit) => commit.id,
+            // Unborn HEAD: no history yet.
+            Err(_) => return Ok(Vec::new()),
+        },
+    };
+
+    let walk = repo
+        .rev_walk([tip])
+        .sorting(Sorting::ByCommitTime(CommitTimeOrder::NewestFirst))
+        .all()
+        .map_err(|err| anyhow::anyhow!("failed to walk history from {}: {}", tip, err))?;
+    let mut cache = repo.diff_resource_cache_for_tree_diff()?;
+    let mut commits = Vec::new();
+    for info in walk.skip(offset).take(limit) {
+        let commit = info?.object()?;
+        let changes = match detail {
+            Some(detail) => Some(commit_changes(&repo, &commit, &mut cache, detail)?),
+            None => None,
+        };
+        commits.push(describe_commit(&commit, changes)?);
+    }
+    Ok(commits)
+}
+
+fn get_commit_blocking(
+    repository_path: PathBuf,
+    oid: String,
+    detail: Option<ChangeDetail>,
+) -> anyhow::Result<Option<RepositoryCommit>> {
+    // Abbreviated IDs are fine, but names are not: `getCommit` is not a ref lookup.
+    if !(4..=40).contains(&oid.len()) || !oid.bytes().all(|b| b.is_ascii_hexdigit()) {
+        anyhow::bail!("`{}` is not a commit ID", oid);
+    }
+    let repo = open_repository(&repository_path)?;
+    let Ok(id) = repo.rev_parse_single(oid.as_str()) else {
+        return Ok(None);
+    };
+    let Ok(commit) = id.object()?.try_into_commit() else {
+        return Ok(None);
+    };
+    let changes = match detail {
+        Some(detail) => {
+            let mut cache = repo.diff_resource_cache_for_tree_diff()?;
+            Some(commit_changes(&repo, &commit, &mut cache, detail)?)
+        }
+        None => None,
+    };
+    describe_commit(&commit, changes).map(Some)
+}
+
+fn open_repository(repository_path: &Path) -> anyhow::Result<gix::Repository> {
+    gix::open(repository_path).map_err(|err| {
+        anyhow::anyhow!(
+            "failed to open repository at {}: {}",
+            repository_path.display(),
+            err
+        )
+    })
+}
+
+fn describe_commit(
+    commit: &gix::Commit<'_>,
+    changes: Option<CommitChanges>,
+) -> anyhow::Result<RepositoryCommit> {
+    let signature = |signature: gix::actor::SignatureRef<'_>| CommitSignature {
+        name: signature.name.to_string(),
+        email: signature.email.to_string(),
+        time: signature.seconds(),
+    };
+    Ok(RepositoryCommit {
+        oid: commit.id.to_string(),
+        summary: commit.message()?.summary().to_string(),
+        message: commit.message_raw()?.to_string(),
+        author: signature(commit.author()?),
+        committer: signature(commit.committer()?),
+        parents: commit.parent_ids().map(|id| id.to_string()).collect(),
+        changes,
+    })
+}
+
+/// Files changed against the first parent, or against the empty tree for a root commit.
+pub(super) fn commit_changes(
+    repo: &gix::Repository,
+    commit: &gix::Commit<'_>,
+    cache: &mut gix::diff::blob::Platform,
+    detail: ChangeDetail,
+) -> anyhow::Result<CommitChanges> {
+    let tree = commit.tree()?;
+    let parent_tree = match commit.parent_ids().next() {
+        Some(parent) => parent.object()?.into_commit().tree()?,
+        None => repo.empty_tree(),
+    };
+    tree_changes(&parent_tree, &tree, cache, detail)
+}
+
+/// A line of a file version that is waiting to be blamed on a commit or passed on to
+/// its parents: its index in that version, and in the blamed file.
+type BlameLine = (u32, u32);
+
+/// The lines a commit is suspected of having written, and the file version they are in.
+struct Suspect {
+    blob: gix::ObjectId,
+    lines: Vec<BlameLine>,
+}
+
+fn blame_blocking(
+    repository_path: PathBuf,
+    file_path: String,
+    branch: Option<String>,
+) -> anyhow::Result<Arc<FileBlame>> {
+    let repo = open_repository(&repository_path)?;
+    let tip = match branch.as_deref() {
+        Some(spec) => {
+            repo.rev_parse_single(spec)
+                .map_err(|err| anyhow::anyhow!("branch `{}` not found: {}", spec, err))?
+                .object()?
+                .peel_to_commit()
+                .map_err(|_| anyhow::anyhow!("`{}` does not point to a commit", spec))?
+                .id
+        }
+        None => {
+            repo.head_commit()
+                .map_err(|_| anyhow::anyhow!("repository has no commits"))?
+                .id
+        }
+    };
+
+    let key = (repository_path, tip, file_path.clone());
+    if let Some(blame) = BLAME_CACHE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&key)
+    {
+        return Ok(blame.clone());
+    }
+
+    let tip_commit = repo.find_commit(tip)?;
+    let blob = file_blob(&tip_commit.tree()?, &file_path)?
+        .ok_or_else(|| anyhow::anyhow!("path `{}` not found in repository", file_path))?;
+    let data = repo.find_object(blob)?.into_blob().take_data();
+    if data.len() > MAX_BLAME_BYTES {
+        anyhow::bail!("`{}` is too large to blame", file_path);
+    }
+    if data.iter().take(8000).any(|&byte| byte == 0) {
+        anyhow::bail!("`{}` is a binary file", file_path);
+    }
+    let line_count = byte_lines_with_terminator(data.as_slice()).count() as u32;
+
+    // Commits are visited newest first, so a commit has usually heard from all of its
+    // children by the time it is blamed.
+    let mut queue = BinaryHeap::new();
+    let mut suspects: HashMap<(gix::ObjectId, String), Suspect> = HashMap::new();
+    queue.push((tip_commit.time()?.seconds, tip, file_path.clone()));
+    suspects.insert(
+        (tip, file_path.clone()),
+        Suspect {
+            blob,
+            lines: (0..line_count).map(|line| (line, line)).collect(),
+        },
+    );
+    // For each line of the blamed file: the commit, the path there and the line there.
+    let mut origins: Vec<Option<(gix::ObjectId, String, u32)>> = vec![None; line_count as usize];
+
+    while let Some((_, id, path)) = queue.pop() {
+        let Some(suspect) = suspects.remove(&(id, path.clone())) else {
+            continue;
+        };
+        let commit = repo.find_commit(id)?;
+        let tree = commit.tree()?;
+
+        // The file in each parent, following a rename into this commit. Parents missing
+        // from a shallow clone are left out, so their lines stay with this commit.
+        let mut parents = Vec::new();
+        for parent_id in commit.parent_ids() {
+            let Ok(parent) = parent_id.object().map(|object| object.into_commit()) else {
+                continue;
+            };
+            let parent_tree = parent.tree()?;
+            let found = match file_blob(&parent_tree, &path)? {
+                Some(parent_blob) => Some((path.clone(), parent_blob)),
+                None => renamed_from(&parent_tree, &tree, &path)?,
+            };
+            if let Some((parent_path, parent_blob)) = found {
+                parents.push((parent.id, parent.time()?.seconds, parent_path, parent_blob));
+            }
+        }
+
+        let mut remaining = suspect.lines;
+        // A parent with the same version gets every line, like `git blame` following
+        // the side of a merge that did not touch the file.
+        let same = parents.iter().position(|parent| parent.3 == suspect.blob);
+        if let Some(index) = same {
+            parents = vec![parents.swap_remove(index)];
+        }
+        let mut data = None;
+        for (parent, time, parent_path, parent_blob) in parents {
+            if remaining.is_empty() {
+                break;
+            }
+            let passed = if parent_blob == suspect.blob {
+                std::mem::take(&mut remaining)
+            } else {
+                if data.is_none() {
+                    data = Some(repo.find_object(suspect.blob)?.into_blob().take_data());
+                }
+                let data = data.as_deref().unwrap_or_default();
+                let parent_data = repo.find_object(parent_blob)?.into_blob().take_data();
+                let unchanged = unchanged_lines(&parent_data, data);
+                let mut passed = Vec::new();
+                remaining.retain(|&(line, blamed)| {
+                    match unchanged.get(line as usize).copied().flatten() {
+                        Some(parent_line) => {
+                            passed.push((parent_line, blamed));
+                            false
+                        }
+                        None => true,
+                    }
+                });
+                passed
+            };
+            if passed.is_empty() {
+                continue;
+            }
+            let waiting = suspects
+                .entry((parent, parent_path.clone()))
+                .or_insert_with(|| {
+                    queue.push((time, parent, parent_path));
+                    Suspect {
+                        blob: parent_blob,
+                        lines: Vec::new(),
+                    }
+                });
+            waiting.lines.extend(passed);
+        }
+        for (line, blamed) in remaining {
+            origins[blamed as usize] = Some((id, path.clone(), line));
+        }
+    }
+
+    let mut commits: HashMap<gix::ObjectId, RepositoryCommit> = HashMap::new();
+    let mut ranges: Vec<BlameRange> = Vec::new();
+    for (blamed, origin) in origins.into_iter().enumerate() {
+        let (id, path, line) = origin.ok_or_else(|| anyhow::anyhow!("line left unblamed"))?;
+        let line_number = blamed as u32 + 1;
+        let oid = id.to_string();
+        let original_path = (path != file_path).then_some(path);
+        if let Some(last) = ranges.last_mut()
+            && last.oid == oid
+            && last.original_path == original_path
+            && last.end_line + 1 == line_number
+            && last.original_start_line + (last.end_line - last.start_line) == line
+        {
+            last.end_line = line_number;
+            continue;
+        }
+        let commit = match commits.entry(id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(describe_commit(&repo.find_commit(id)?, None)?),
+        };
+        ranges.push(BlameRange {
+            start_line: line_number,
+            end_line: line_number,
+            original_start_line: line + 1,
+            original_path,
+            oid,
+            summary: commit.summary.clone(),
+            author: commit.author.clone(),
+        });
+    }
+
+    let blame = Arc::new(FileBlame {
+        oid: tip.to_string(),
+        path: file_path,
+        line_count,
+        ranges,
+    });
+    let mut cache = BLAME_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if cache.len() >= MAX_CACHED_BLAMES {
+        cache.clear();
+    }
+    cache.insert(key, blame.clone());
+    Ok(blame)
+}
+
+/// The blob at `path` in `tree`, unless the path is missing or not a file.
+fn file_blob(tree: &gix::Tree<'_>, path: &str) -> anyhow::Result<Option<gix::ObjectId>> {
+    Ok(tree
+        .lookup_entry_by_path(Path::new(path))?
+        .filter(|entry| entry.mode().is_blob_or_symlink())
+        .map(|entry| entry.object_id()))
+}
+
+/// The path and blob `path` was renamed from between `old_tree` and `new_tree`, as
+/// `git diff` would detect the rename.
+fn renamed_from(
+    old_tree: &gix::Tree<'_>,
+    new_tree: &gix::Tree<'_>,
+    path: &str,
+) -> anyhow::Result<Option<(String, gix::ObjectId)>> {
+    let mut source = None;
+    old_tree
+        .changes()?
+        .options(|options| {
+            options
+                .track_path()
+                .track_rewrites(Some(Default::default()));
+        })
+        .for_each_to_obtain_tree(new_tree, |change| {
+            use gix::object::tree::diff::{Action, Change};
+
+            if let Change::Rewrite {
+                source_location,
+                source_id,
+                location,
+                ..
+            } = change
+                && location == path
+            {
+                source = Some((source_location.to_string(), source_id.detach()));
+                return Ok::<_, anyhow::Error>(Action::Cancel);
+            }
+            Ok(Action::Continue)
+        })?;
+    Ok(source)
+}
+
+/// For each line of `new`, the line of `old` it was kept from, or `None` if it was
+/// added or changed.
+fn unchanged_lines(old: &[u8], new: &[u8]) -> Vec<Option<u32>> {
+    let input = InternedInput::new(
+        byte_lines_with_terminator(old),
+        byte_lines_with_terminator(new),
+    );
+    let mut lines = Vec::with_capacity(input.after.len());
+    let mut old_line = 0;
+    gix::diff::blob::diff(
+        gix::diff::blob::Algorithm::Myers,
+        &input,
+        |before: Range<u32>, after: Range<u32>| {
+            while (lines.len() as u32) < after.start {
+                lines.push(Some(old_line));
+                old_line += 1;
+            }
+            lines.extend(after.map(|_| None));
+            old_line = before.end;
+        },
+    );
+    while lines.len() < input.after.len() {
+        lines.push(Some(old_line));
+        old_line += 1;
+    }
+    lines
+}
+
 fn full_name_to_string(name: &gix::refs::FullNameRef) -> String {
     use gix::bstr::ByteSlice;
 
@@ -242,35 +741,24 @@ fn short_branch_name(full_name: &str) -> String {
 }
 
 pub async fn read_repository_file_raw(
-    pool: &SqlitePool,
+    resolver: &PathResolver,
+    viewer_did: Option<&str>,
     storage: &RepositoryStorage,
     path: String,
     file_path: String,
     branch: Option<String>,
 ) -> anyhow::Result<Option<RepositoryFilePayload>> {
-    let segments: Vec<String> = path
-        .split('/')
-        .filter(|segment| !segment.is_empty())
-        .map(|segment| segment.to_string())
-        .collect();
-
-    if segments.is_empty() {
-        return Ok(None);
-    }
-
-    for segment in &segments {
-        validate_slug(segment)?;
-    }
-
-    let record = resolve_repository_by_path(pool, &path).await?;
-    let Some(record) = record else {
+    let Some(resolved) = resolver
+        .resolve_visible_repository(&path, viewer_did)
+        .await?
+    else {
         return Ok(None);
     };
 
     let normalized_file_path = normalize_file_path(file_path)?;
 
     // Both local and remote repositories are now in local storage
-    let repository_path = storage.ensure_local_repository(&segments)?;
+    let repository_path = resolved.local_dir(storage)?;
 
     let file =
         read_repository_file_for_branch(repository_path, normalized_file_path, branch).await?;
@@ -278,14 +766,18 @@ pub async fn read_repository_file_raw(
     Ok(Some(file))
 }
 
+/// The repositories directly in `group_id` that `viewer_did` may read.
 pub async fn get_repositories_for_group(
     pool: &SqlitePool,
     group_id: &str,
+    viewer_did: Option<&str>,
 ) -> anyhow::Result<Vec<RepositorySummary>> {
-    let rows = sqlx::query_as::<_, RepositorySummaryRow>(
-        "SELECT id, slug, remote_url FROM repositories WHERE \"group\" = ? ORDER BY slug",
-    )
+    let rows = sqlx::query_as::<_, RepositorySummaryRow>(&format!(
+        "SELECT id, slug, remote_url FROM repositories WHERE \"group\" = ? AND {HIDDEN} \
+         ORDER BY slug"
+    ))
     .bind(group_id)
+    .bind(viewer_did)
     .fetch_all(pool)
     .await?;
 
@@ -293,7 +785,7 @@ pub async fn get_repositories_for_group(
 }
 
 /// Reconstructs the full repository path from a repository record
-async fn reconstruct_repository_path(
+pub(crate) async fn reconstruct_repository_path(
     pool: &SqlitePool,
     record: &RepositoryRecord,
 ) -> anyhow::Result<String> {
@@ -321,12 +813,14 @@ async fn reconstruct_repository_path(
     }
 }
 
-/// Gets the README HTML for a repository at the root path
+/// Gets the README HTML for a repository at the root path, preferring a localized
+/// README for `languages` (most preferred first).
 pub async fn get_repository_readme_html(
     pool: &SqlitePool,
     storage: &RepositoryStorage,
     record: &RepositoryRecord,
     branch: Option<String>,
+    languages: &[String],
 ) -> anyhow::Result<Option<String>> {
     // Reconstruct full path
     let path = reconstruct_repository_path(pool, record).await?;
@@ -343,7 +837,12 @@ pub async fn get_repository_readme_html(
 
     // Get repository entries at root
     let repository_path = if record.remote_url.is_some() {
-        storage.ensure_remote_repository(record).await?
+        let cache = storage.ensure_remote_repository(record).await?;
+        // The cache was fetched again and may hold new commits
+        if let Err(e) = enqueue_code_index(pool, &record.id).await {
+            tracing::warn!(repository = %record.id, "code indexing was not queued: {}", e);
+        }
+        cache
     } else {
         storage.ensure_local_repository(&segments)?
     };
@@ -351,7 +850,7 @@ pub async fn get_repository_readme_html(
     let entries = read_repository_entries(repository_path.clone(), String::new(), branch.clone()).await?;
 
     // Detect README file
-    let readme_path = super::readme::detect_readme_file(&entries);
+    let readme_path = super::readme::detect_localized_readme(&entries, languages);
     let Some(readme_path) = readme_path else {
         return Ok(None);
     };
@@ -374,3 +873,229 @@ pub async fn get_repository_readme_html(
 
     Ok(Some(html))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphql::pagination::encode_cursor;
+    use crate::test_helpers::{create_test_pool, git_with_env};
+
+    async fn insert(pool: &SqlitePool, id: &str, slug: &str) {
+        sqlx::query("INSERT INTO repositories (id, slug) VALUES (?, ?)")
+            .bind(id)
+            .bind(slug)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    fn slugs(page: &Page<RepositoryRecord>) -> Vec<&str> {
+        page.items
+            .iter()
+            .map(|record| record.slug.as_str())
+            .collect()
+    }
+
+    fn cursor(record: &RepositoryRecord) -> String {
+        encode_cursor(REPOSITORY_CURSOR_KIND, &record.slug, &record.id)
+    }
+
+    #[tokio::test]
+    async fn repositories_page_by_keyset_in_both_directions() {
+        let pool = create_test_pool().await.unwrap();
+        for (id, slug) in [
+            ("r1", "alpha"),
+            ("r2", "bravo"),
+            ("r3", "charlie"),
+            ("r4", "delta"),
+        ] {
+            insert(&pool, id, slug).await;
+        }
+        let request = |first, after: Option<String>, last, before: Option<String>| {
+            PageRequest::from_args(
+                REPOSITORY_CURSOR_KIND,
+                first,
+                after.as_deref(),
+                last,
+                before.as_deref(),
+            )
+            .unwrap()
+        };
+
+        let first = page_repositories(&pool, &request(Some(2), None, None, None))
+            .await
+            .unwrap();
+        assert_eq!(slugs(&first), ["alpha", "bravo"]);
+        assert!(first.has_next_page);
+
+        // Rows added before the cursor do not shift the next page.
+        insert(&pool, "r0", "aardvark").await;
+        let after = cursor(first.items.last().unwrap());
+        let second = page_repositories(&pool, &request(Some(2), Some(after), None, None))
+            .await
+            .unwrap();
+        assert_eq!(slugs(&second), ["charlie", "delta"]);
+        assert!(!second.has_next_page);
+        assert!(second.has_previous_page);
+
+        let before = cursor(second.items.first().unwrap());
+        let back = page_repositories(&pool, &request(None, None, Some(2), Some(before)))
+            .await
+            .unwrap();
+        assert_eq!(slugs(&back), ["alpha", "bravo"]);
+        assert!(back.has_previous_page);
+
+        let last = page_repositories(&pool, &request(None, None, Some(1), None))
+            .await
+            .unwrap();
+        assert_eq!(slugs(&last), ["delta"]);
+        assert_eq!(count_repositories(&pool).await.unwrap(), 5);
+    }
+
+    /// Commits get fixed author and committer dates.
+    fn git(dir: &Path, args: &[&str]) -> String {
+        git_with_env(
+            dir,
+            &[
+                ("GIT_AUTHOR_DATE", "@1000 +0000"),
+                ("GIT_COMMITTER_DATE", "@2000 +0000"),
+            ],
+            args,
+        )
+    }
+
+    #[test]
+    fn commits_list_with_their_changes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let work = tmp.path();
+        git(work, &["init", "-q", "-b", "main"]);
+        std::fs::write(work.join("a.txt"), "one\ntwo\nthree\nfour\nfive\n").unwrap();
+        std::fs::write(work.join("logo.bin"), [0u8, 1, 2, 0, 3]).unwrap();
+        git(work, &["add", "."]);
+        git(
+            work,
+            &["commit", "-q", "-m", "Initial commit\n\nWith a body."],
+        );
+        std::fs::create_dir(work.join("docs")).unwrap();
+        git(work, &["mv", "a.txt", "docs/a.txt"]);
+        std::fs::write(work.join("docs/a.txt"), "one\ntwo\nthree\nfour\n5\n").unwrap();
+        std::fs::write(work.join("b.txt"), "new\n").unwrap();
+        std::fs::remove_file(work.join("logo.bin")).unwrap();
+        git(work, &["add", "-A"]);
+        git(work, &["commit", "-q", "-m", "Move a into docs"]);
+        let git_dir = work.join(".git");
+
+        let commits =
+            list_commits_blocking(git_dir.clone(), None, 10, 0, Some(ChangeDetail::Stats)).unwrap();
+        let summaries: Vec<_> = commits.iter().map(|c| c.summary.as_str()).collect();
+        assert_eq!(summaries, ["Move a into docs", "Initial commit"]);
+        let latest = &commits[0];
+        assert_eq!(latest.parents, [commits[1].oid.clone()]);
+        assert_eq!(latest.author.time, 1000);
+        assert_eq!(latest.committer.time, 2000);
+        assert_eq!(latest.author.email, "tess@example.org");
+        let changes = latest.changes.as_ref().unwrap();
+        assert_eq!(
+            (changes.files_changed, changes.additions, changes.deletions),
+            (3, 2, 1)
+        );
+        let renamed = changes
+            .files
+            .iter()
+            .find(|f| f.path == "docs/a.txt")
+            .unwrap();
+        assert_eq!(renamed.status, ChangeStatus::Renamed);
+        assert_eq!(renamed.previous_path.as_deref(), Some("a.txt"));
+        let removed = changes.files.iter().find(|f| f.path == "logo.bin").unwrap();
+        assert!(removed.binary && removed.status == ChangeStatus::Deleted);
+
+        let root = commits[1].changes.as_ref().unwrap();
+        assert_eq!((root.files_changed, root.additions), (2, 5));
+        assert_eq!(commits[1].message, "Initial commit\n\nWith a body.\n");
+
+        let page = list_commits_blocking(git_dir.clone(), Some("main".into()), 1, 1, None).unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].summary, "Initial commit");
+        assert!(page[0].changes.is_none());
+
+        let short = &commits[1].oid[..8];
+        let found = get_commit_blocking(git_dir.clone(), short.to_string(), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.oid, commits[1].oid);
+        assert!(
+            get_commit_blocking(git_dir.clone(), "deadbeef".into(), None)
+                .unwrap()
+                .is_none()
+        );
+        assert!(get_commit_blocking(git_dir.clone(), "main".into(), None).is_err());
+        assert!(list_commits_blocking(git_dir, Some("missing".into()), 10, 0, None).is_err());
+    }
+
+    /// The commit of each line, as `git blame` sees it.
+    fn git_blame(work: &Path, file: &str) -> Vec<String> {
+        git(work, &["blame", "-l", "-s", "--root", "--", file])
+            .lines()
+            .map(|line| line[..40].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn blame_matches_git_across_renames_and_merges() {
+        let tmp = tempfile::tempdir().unwrap();
+        let work = tmp.path();
+        git(work, &["init", "-q", "-b", "main"]);
+        std::fs::write(work.join("a.txt"), "one\ntwo\nthree\nfour\nfive\n").unwrap();
+        git(work, &["add", "."]);
+        git(work, &["commit", "-q", "-m", "Initial commit"]);
+        std::fs::write(work.join("a.txt"), "one\ntwo\nTHREE\nfour\nfive\nsix\n").unwrap();
+        git(work, &["commit", "-q", "-am", "Shout three"]);
+
+        git(work, &["checkout", "-q", "-b", "side"]);
+        std::fs::write(work.join("a.txt"), "ONE\ntwo\nTHREE\nfour\nfive\nsix\n").unwrap();
+        git(work, &["commit", "-q", "-am", "Shout one"]);
+        git(work, &["checkout", "-q", "main"]);
+        git(work, &["mv", "a.txt", "b.txt"]);
+        std::fs::write(
+            work.join("b.txt"),
+            "one\ntwo\nTHREE\nfour\nfive\nsix\nseven\n",
+        )
+        .unwrap();
+        git(work, &["commit", "-q", "-am", "Rename a to b"]);
+        git(work, &["merge", "-q", "--no-edit", "side"]);
+        let git_dir = work.join(".git");
+
+        let blame = blame_blocking(git_dir.clone(), "b.txt".into(), None).unwrap();
+        assert_eq!(blame.line_count, 7);
+        let mut lines = Vec::new();
+        for range in &blame.ranges {
+            assert_eq!(range.start_line as usize, lines.len() + 1);
+            lines.extend((range.start_line..=range.end_line).map(|_| range.oid.clone()));
+        }
+        assert_eq!(lines, git_blame(work, "b.txt"));
+
+        let summaries: Vec<_> = blame.ranges.iter().map(|r| r.summary.as_str()).collect();
+        assert_eq!(
+            summaries,
+            [
+                "Shout one",
+                "Initial commit",
+                "Shout three",
+                "Initial commit",
+                "Shout three",
+                "Rename a to b"
+            ]
+        );
+        let initial = &blame.ranges[1];
+        assert_eq!(initial.original_path.as_deref(), Some("a.txt"));
+        assert_eq!(
+            (initial.original_start_line, initial.author.time),
+            (2, 1000)
+        );
+        assert_eq!(blame.ranges[5].original_path, None);
+
+        let again = blame_blocking(git_dir.clone(), "b.txt".into(), Some("main".into())).unwrap();
+        assert!(Arc::ptr_eq(&blame, &again));
+        assert!(blame_blocking(git_dir, "missing.txt".into(), None).is_err());
+    }
+}
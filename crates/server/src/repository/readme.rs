@@ -1,5 +1,7 @@
 use pulldown_cmark::{html, Options, Parser};
 
+use super::language::{best_language_match, normalize_language_tag};
+
 /// Detects README file in a list of repository entries
 pub fn detect_readme_file(entries: &[super::models::RepositoryEntryNode]) -> Option<String> {
     let readme_names = [
@@ -25,6 +27,49 @@ pub fn detect_readme_file(entries: &[super::models::RepositoryEntryNode]) -> Opt
     None
 }
 
+/// Extensions a README may have, localized or not.
+const README_EXTENSIONS: [&str; 4] = ["md", "markdown", "adoc", "asciidoc"];
+
+/// Language tag of a localized README such as `README.de.md` or `readme.pt_BR.markdown`.
+fn localized_readme_language(name: &str) -> Option<String> {
+    let (stem, ext) = name.rsplit_once('.')?;
+    if !README_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()) {
+        return None;
+    }
+    let (base, tag) = stem.split_once('.')?;
+    if !base.eq_ignore_ascii_case("readme") {
+        return None;
+    }
+    normalize_language_tag(tag)
+}
+
+/// Detects the README to show for visitors with the given language preferences.
+///
+/// A localized README (`README.<lang>.md`) matching a preference wins. Otherwise the
+/// unlocalized README is used, and when a repository only ships localized READMEs the
+/// English one, or else the first by name, stands in for it.
+pub fn detect_localized_readme(
+    entries: &[super::models::RepositoryEntryNode],
+    preferences: &[String],
+) -> Option<String> {
+    let mut localized: Vec<(String, &super::models::RepositoryEntryNode)> = entries
+        .iter()
+        .filter(|e| e.kind == super::models::RepositoryEntryKind::File)
+        .filter_map(|e| localized_readme_language(&e.name).map(|tag| (tag, e)))
+        .collect();
+    localized.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+    let languages: Vec<String> = localized.iter().map(|(tag, _)| tag.clone()).collect();
+
+    if let Some(index) = best_language_match(&languages, preferences) {
+        return Some(localized[index].1.path.clone());
+    }
+    if let Some(path) = detect_readme_file(entries) {
+        return Some(path);
+    }
+    let index = best_language_match(&languages, &["en".to_string()]).unwrap_or(0);
+    localized.get(index).map(|(_, entry)| entry.path.clone())
+}
+
 /// Renders markdown content to HTML
 pub fn render_markdown(content: &str) -> String {
     let mut options = Options::empty();
@@ -125,6 +170,59 @@ mod tests {
         assert_eq!(result, None);
     }
 
+    fn files(names: &[&str]) -> Vec<RepositoryEntryNode> {
+        names
+            .iter()
+            .map(|name| RepositoryEntryNode {
+                name: name.to_string(),
+                path: name.to_string(),
+                kind: RepositoryEntryKind::File,
+                size: Some(100),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detect_localized_readme_prefers_language() {
+        let entries = files(&[
+            "README.md",
+            "README.de.md",
+            "README.pt_BR.md",
+            "README.fr.txt",
+        ]);
+        let prefs = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            detect_localized_readme(&entries, &prefs(&["de-ch", "en"])),
+            Some("README.de.md".to_string())
+        );
+        assert_eq!(
+            detect_localized_readme(&entries, &prefs(&["pt-br"])),
+            Some("README.pt_BR.md".to_string())
+        );
+        assert_eq!(
+            detect_localized_readme(&entries, &prefs(&["fr"])),
+            Some("README.md".to_string())
+        );
+        assert_eq!(
+            detect_localized_readme(&entries, &[]),
+            Some("README.md".to_string())
+        );
+    }
+
+    #[test]
+    fn test_detect_localized_readme_without_default() {
+        let entries = files(&["README.ja.md", "README.en.md"]);
+        assert_eq!(
+            detect_localized_readme(&entries, &["fr".to_string()]),
+            Some("README.en.md".to_string())
+        );
+        assert_eq!(
+            detect_localized_readme(&files(&["readme.ja.md"]), &[]),
+            Some("readme.ja.md".to_string())
+        );
+    }
+
     #[test]
     fn test_render_markdown_basic() {
         let content = "# Hello\n\nThis is **bold** text.";
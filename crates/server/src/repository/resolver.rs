@@ -0,0 +1,216 @@
+//! Single place that turns `group/subgroup/repo` paths into database records.
+//!
+//! Git handlers, the core executor and any future raw/archive or permalink endpoints
+//! should resolve through [`PathResolver`] so path parsing, slug matching and caching
+//! behave identically everywhere. Slugs match case-insensitively; a [`ResolvedRepository`]
+//! carries the stored spelling, which HTTP handlers redirect to.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use sqlx::SqlitePool;
+
+use super::db::resolve_repository_by_path;
+use super::models::RepositoryRecord;
+use super::queries::reconstruct_repository_path;
+use super::storage::RepositoryStorage;
+use super::visibility::can_view_repository;
+use crate::group::db::resolve_group_by_path;
+use crate::group::models::GroupRecord;
+use crate::validation::slug::validate_lookup_slug;
+
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// A repository path resolved against the database.
+#[derive(Clone, Debug)]
+pub struct ResolvedRepository {
+    pub record: RepositoryRecord,
+    /// Path segments as stored, e.g. `["team", "app"]` for a lookup of `Team/App`.
+    pub segments: Vec<String>,
+}
+
+impl ResolvedRepository {
+    pub fn canonical_path(&self) -> String {
+        self.segments.join("/")
+    }
+
+    pub fn local_dir(&self, storage: &RepositoryStorage) -> anyhow::Result<PathBuf> {
+        storage.ensure_local_repository(&self.segments)
+    }
+}
+
+#[derive(Clone)]
+pub struct PathResolver {
+    pool: SqlitePool,
+    ttl: Duration,
+    repositories: Arc<RwLock<HashMap<String, (Instant, ResolvedRepository)>>>,
+}
+
+impl PathResolver {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            ttl: DEFAULT_CACHE_TTL,
+            repositories: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    /// Splits and validates a path of any depth, keeping the requested spelling.
+    pub fn split(path: &str) -> anyhow::Result<Vec<String>> {
+        let segments = git_http::repo::split_repository_path(path);
+        for segment in &segments {
+            validate_lookup_slug(segment)?;
+        }
+        Ok(segments)
+    }
+
+    pub async fn resolve_repository(
+        &self,
+        path: &str,
+    ) -> anyhow::Result<Option<ResolvedRepository>> {
+        let segments = Self::split(path)?;
+        if segments.is_empty() {
+            return Ok(None);
+        }
+        let key = segments.join("/").to_ascii_lowercase();
+
+        if let Some(hit) = self.cached(&key) {
+            crate::metering::note_repository(&hit.segments)?;
+            return Ok(Some(hit));
+        }
+
+        let Some(record) = resolve_repository_by_path(&self.pool, &key).await? else {
+            return Ok(None);
+        };
+        // Spell the path the way it is stored, not the way it was requested.
+        let canonical = reconstruct_repository_path(&self.pool, &record).await?;
+        let resolved = ResolvedRepository {
+            record,
+            segments: canonical.split('/').map(str::to_string).collect(),
+        };
+
+        if let Ok(mut cache) = self.repositories.write() {
+            cache.insert(key, (Instant::now(), resolved.clone()));
+        }
+        // Counts towards the group's API calls when serving a GraphQL request
+        crate::metering::note_repository(&resolved.segments)?;
+        Ok(Some(resolved))
+    }
+
+    /// Like [`Self::resolve_repository`], but `None` for a repository `viewer_did` may not
+    /// read (see [`super::visibility`]), so hidden repositories look the same as missing ones.
+    pub async fn resolve_visible_repository(
+        &self,
+        path: &str,
+        viewer_did: Option<&str>,
+    ) -> anyhow::Result<Option<ResolvedRepository>> {
+        let Some(resolved) = self.resolve_repository(path).await? else {
+            return Ok(None);
+        };
+        if !can_view_repository(&self.pool, viewer_did, &resolved.record.id).await? {
+            return Ok(None);
+        }
+        Ok(Some(resolved))
+    }
+
+    pub async fn resolve_group(&self, path: &str) -> anyhow::Result<Option<GroupRecord>> {
+        let segments = Self::split(path)?;
+        if segments.is_empty() {
+            return Ok(None);
+        }
+        Ok(resolve_group_by_path(&self.pool, &segments.join("/")).await?)
+    }
+
+    /// Drops every cached resolution. Call after renames, moves or deletions.
+    pub fn invalidate_all(&self) {
+        if let Ok(mut cache) = self.repositories.write() {
+            cache.clear();
+        }
+    }
+
+    fn cached(&self, key: &str) -> Option<ResolvedRepository> {
+        let cache = self.repositories.read().ok()?;
+        let (at, resolved) = cache.get(key)?;
+        (at.elapsed() < self.ttl).then(|| resolved.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::mutations::{CreateGroupInput, create_group_raw};
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::create_test_pool;
+
+    #[tokio::test]
+    async fn resolves_nested_paths_case_insensitively() {
+        let pool = create_test_pool().await.unwrap();
+        let team = create_group_raw(
+            &pool,
+            CreateGroupInput {
+                slug: "team".into(),
+                parent: None,
+            },
+        )
+        .await
+        .unwrap();
+        let infra = create_group_raw(
+            &pool,
+            CreateGroupInput {
+                slug: "infra".into(),
+                parent: Some(team.id.clone()),
+            },
+        )
+        .await
+        .unwrap();
+        create_repository_raw(
+            &pool,
+            CreateRepositoryInput {
+                slug: "tools".into(),
+                group: Some(infra.id.clone()),
+            },
+        )
+        .await
+        .unwrap();
+
+        let resolver = PathResolver::new(pool);
+        let resolved = resolver
+            .resolve_repository("/Team/Infra/Tools.git")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(resolved.canonical_path(), "team/infra/tools");
+        assert!(resolver.cached("team/infra/tools").is_some());
+        assert_eq!(
+            resolver
+                .resolve_group("team/infra")
+                .await
+                .unwrap()
+                .unwrap()
+                .id,
+            infra.id
+        );
+        assert!(
+            resolver
+                .resolve_repository("team/missing")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(resolver.resolve_repository("bad--slug").await.is_err());
+
+        resolver.invalidate_all();
+        assert!(resolver.cached("team/infra/tools").is_none());
+    }
+}
@@ -0,0 +1,546 @@
+//! Repository secrets for extensions, such as deploy tokens for a CI extension.
+//!
+//! Owners set a secret with the names of the extensions that may read it. Values are
+//! encrypted with AES-256-GCM under the server's secrets key (`secrets.key_path`), bound
+//! to their repository and name so a ciphertext cannot be moved to another row. The
+//! API never returns values. An extension reads one through `host-secrets` while it runs
+//! in that repository's scope, and every change and read attempt is written to
+//! `repository_secret_audit`.
+
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context, Result, anyhow, bail};
+use rand::RngCore;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use sqlx::{Row, SqlitePool};
+
+use crate::key_file;
+use crate::user::db::unix_now;
+
+const MAX_NAME_LEN: usize = 64;
+const MAX_VALUE_BYTES: usize = 64 * 1024;
+/// Text written to logs in place of a secret value.
+pub const MASK: &str = "***";
+
+/// The key secret values are encrypted with. `Debug` never prints it.
+pub struct SecretKey(LessSafeKey);
+
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretKey(..)")
+    }
+}
+
+impl SecretKey {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let key = UnboundKey::new(&AES_256_GCM, bytes)
+            .map_err(|_| anyhow!("secrets key must be 32 bytes"))?;
+        Ok(Self(LessSafeKey::new(key)))
+    }
+
+    /// Reads the hex key at `path`, generating and storing one when it is missing. Any
+    /// other read error is returned: a fresh key would make every stored secret
+    /// undecryptable.
+    pub fn load_or_generate(path: &Path) -> Result<Self> {
+        let text = key_file::load_or_generate(path, "repository secrets key", || {
+            let mut bytes = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            Ok(hex.into_bytes())
+        })?;
+        String::from_utf8(text)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| Self::from_hex(text.trim()))
+            .with_context(|| format!("invalid secrets key in {}", path.display()))
+    }
+
+    fn from_hex(hex: &str) -> Result<Self> {
+        if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("secrets key must be 64 hex characters");
+        }
+        let bytes: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<_, _>>()?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub(crate) fn seal(&self, aad: &str, value: &str) -> Result<([u8; NONCE_LEN], Vec<u8>)> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let mut data = value.as_bytes().to_vec();
+        self.0
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad.as_bytes()),
+                &mut data,
+            )
+            .map_err(|_| anyhow!("failed to encrypt secret"))?;
+        Ok((nonce, data))
+    }
+
+    pub(crate) fn open(&self, aad: &str, nonce: &[u8], ciphertext: &[u8]) -> Result<String> {
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| anyhow!("stored secret has a malformed nonce"))?;
+        let mut data = ciphertext.to_vec();
+        let plain = self
+            .0
+            .open_in_place(nonce, Aad::from(aad.as_bytes()), &mut data)
+            .map_err(|_| anyhow!("secret cannot be decrypted with the current key"))?;
+        Ok(String::from_utf8(plain.to_vec())?)
+    }
+}
+
+static KEY: OnceLock<SecretKey> = OnceLock::new();
+static KEY_LOAD: Mutex<()> = Mutex::new(());
+
+/// The key from `secrets.key_path`, loaded once.
+pub fn server_key() -> Result<&'static SecretKey> {
+    if let Some(key) = KEY.get() {
+        return Ok(key);
+    }
+    // Serialise loading so two first uses cannot generate different keys.
+    let _guard = KEY_LOAD.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(key) = KEY.get() {
+        return Ok(key);
+    }
+    let key = SecretKey::load_or_generate(&crate::config::current().secrets.key_path)?;
+    Ok(KEY.get_or_init(|| key))
+}
+
+/// Binds a ciphertext to its repository and name.
+fn associated_data(repository_id: &str, name: &str) -> String {
+    format!("{}\n{}", repository_id, name)
+}
+
+/// Secret names look like environment variables: `DEPLOY_TOKEN`.
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        bail!(
+            "secret names use A-Z, 0-9 and _, start with a letter and are at most {} characters",
+            MAX_NAME_LEN
+        );
+    }
+    Ok(())
+}
+
+/// A secret without its value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepositorySecret {
+    pub name: String,
+    pub extensions: Vec<String>,
+    pub updated_by: Option<String>,
+    pub updated_at: i64,
+}
+
+/// What happened to a secret, as recorded in the audit log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecretAction {
+    Set,
+    Delete,
+    Read,
+    /// An extension asked for a secret it was not granted, or one that does not exist
+    Denied,
+}
+
+impl SecretAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecretAction::Set => "set",
+            SecretAction::Delete => "delete",
+            SecretAction::Read => "read",
+            SecretAction::Denied => "denied",
+        }
+    }
+
+    pub fn as_graphql(&self) -> &'static str {
+        match self {
+            SecretAction::Set => "SET",
+            SecretAction::Delete => "DELETE",
+            SecretAction::Read => "READ",
+            SecretAction::Denied => "DENIED",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "set" => Ok(SecretAction::Set),
+            "delete" => Ok(SecretAction::Delete),
+            "read" => Ok(SecretAction::Read),
+            "denied" => Ok(SecretAction::Denied),
+            other => Err(anyhow!("unknown secret audit action `{}`", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SecretAccessRecord {
+    pub id: String,
+    pub name: String,
+    pub action: SecretAction,
+    pub extension: Option<String>,
+    pub actor_did: Option<String>,
+    pub created_at: i64,
+}
+
+async fn record_access(
+    pool: &SqlitePool,
+    repository_id: &str,
+    name: &str,
+    action: SecretAction,
+    extension: Option<&str>,
+    actor_did: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO repository_secret_audit \
+         (id, repository_id, name, action, extension, actor_did, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(cuid2::create_id())
+    .bind(repository_id)
+    .bind(name)
+    .bind(action.as_str())
+    .bind(extension)
+    .bind(actor_did)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Creates or replaces a secret and the extensions it is granted to.
+pub async fn set_repository_secret(
+    pool: &SqlitePool,
+    key: &SecretKey,
+    repository_id: &str,
+    name: &str,
+    value: &str,
+    extensions: &[String],
+    actor_did: Option<&str>,
+) -> Result<RepositorySecret> {
+    validate_name(name)?;
+    if value.is_empty() || value.len() > MAX_VALUE_BYTES {
+        bail!(
+            "secret values must be between 1 byte and {} KiB",
+            MAX_VALUE_BYTES / 1024
+        );
+    }
+    let mut extensions = extensions.to_vec();
+    for extension in &extensions {
+        crate::config::validate_extension_name(extension).map_err(|e| anyhow!(e))?;
+    }
+    extensions.sort();
+    extensions.dedup();
+
+    let (nonce, ciphertext) = key.seal(&associated_data(repository_id, name), value)?;
+    let updated_at = unix_now();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO repository_secrets \
+         (repository_id, name, nonce, ciphertext, extensions, updated_by, updated_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(repository_id, name) DO UPDATE SET nonce = excluded.nonce, \
+         ciphertext = excluded.ciphertext, extensions = excluded.extensions, \
+         updated_by = excluded.updated_by, updated_at = excluded.updated_at",
+    )
+    .bind(repository_id)
+    .bind(name)
+    .bind(&nonce[..])
+    .bind(&ciphertext)
+    .bind(serde_json::to_string(&extensions)?)
+    .bind(actor_did)
+    .bind(updated_at)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO repository_secret_audit \
+         (id, repository_id, name, action, extension, actor_did, created_at) \
+         VALUES (?, ?, ?, 'set', NULL, ?, ?)",
+    )
+    .bind(cuid2::create_id())
+    .bind(repository_id)
+    .bind(name)
+    .bind(actor_did)
+    .bind(updated_at)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(RepositorySecret {
+        name: name.to_string(),
+        extensions,
+        updated_by: actor_did.map(str::to_string),
+        updated_at,
+    })
+}
+
+/// Removes a secret; returns whether it existed.
+pub async fn delete_repository_secret(
+    pool: &SqlitePool,
+    repository_id: &str,
+    name: &str,
+    actor_did: Option<&str>,
+) -> Result<bool> {
+    let deleted =
+        sqlx::query("DELETE FROM repository_secrets WHERE repository_id = ? AND name = ?")
+            .bind(repository_id)
+            .bind(name)
+            .execute(pool)
+            .await?
+            .rows_affected()
+            > 0;
+    if deleted {
+        record_access(
+            pool,
+            repository_id,
+            name,
+            SecretAction::Delete,
+            None,
+            actor_did,
+        )
+        .await?;
+    }
+    Ok(deleted)
+}
+
+/// Secrets of a repository by name, without values.
+pub async fn list_repository_secrets(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> Result<Vec<RepositorySecret>> {
+    let rows = sqlx::query(
+        "SELECT name, extensions, updated_by, updated_at FROM repository_secrets \
+         WHERE repository_id = ? ORDER BY name",
+    )
+    .bind(repository_id)
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|row| {
+            let extensions: String = row.try_get("extensions")?;
+            Ok(RepositorySecret {
+                name: row.try_get("name")?,
+                extensions: serde_json::from_str(&extensions)?,
+                updated_by: row.try_get("updated_by")?,
+                updated_at: row.try_get("updated_at")?,
+            })
+        })
+        .collect()
+}
+
+/// The value of `name` for `extension`, or `None` when the secret does not exist or is
+/// not granted to it. Both outcomes are audited.
+pub async fn read_secret_for_extension(
+    pool: &SqlitePool,
+    key: &SecretKey,
+    repository_id: &str,
+    name: &str,
+    extension: &str,
+    actor_did: Option<&str>,
+) -> Result<Option<String>> {
+    let row = sqlx::query(
+        "SELECT nonce, ciphertext, extensions FROM repository_secrets \
+         WHERE repository_id = ? AND name = ?",
+    )
+    .bind(repository_id)
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+    let granted = match &row {
+        Some(row) => {
+            let extensions: String = row.try_get("extensions")?;
+            serde_json::from_str::<Vec<String>>(&extensions)?
+                .iter()
+                .any(|granted| granted == extension)
+        }
+        None => false,
+    };
+    let action = if granted {
+        SecretAction::Read
+    } else {
+        SecretAction::Denied
+    };
+    record_access(
+        pool,
+        repository_id,
+        name,
+        action,
+        Some(extension),
+        actor_did,
+    )
+    .await?;
+    let Some(row) = row.filter(|_| granted) else {
+        return Ok(None);
+    };
+    let nonce: Vec<u8> = row.try_get("nonce")?;
+    let ciphertext: Vec<u8> = row.try_get("ciphertext")?;
+    key.open(&associated_data(repository_id, name), &nonce, &ciphertext)
+        .map(Some)
+}
+
+/// Audit records of a repository, newest first.
+pub async fn secret_audit(
+    pool: &SqlitePool,
+    repository_id: &str,
+    limit: i64,
+) -> Result<Vec<SecretAccessRecord>> {
+    let rows = sqlx::query(
+        "SELECT id, name, action, extension, actor_did, created_at FROM repository_secret_audit \
+         WHERE repository_id = ? ORDER BY created_at DESC, rowid DESC LIMIT ?",
+    )
+    .bind(repository_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|row| {
+            let action: String = row.try_get("action")?;
+            Ok(SecretAccessRecord {
+                id: row.try_get("id")?,
+                name: row.try_get("name")?,
+                action: SecretAction::parse(&action)?,
+                extension: row.try_get("extension")?,
+                actor_did: row.try_get("actor_did")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
+}
+
+/// Replaces every occurrence of the `revealed` values in `message` with [`MASK`].
+pub fn mask_secrets(message: &str, revealed: &[String]) -> String {
+    let mut masked = message.to_string();
+    for value in revealed {
+        if !value.is_empty() {
+            masked = masked.replace(value.as_str(), MASK);
+        }
+    }
+    masked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::create_test_pool;
+
+    fn test_key() -> SecretKey {
+        SecretKey::from_bytes(&[7u8; 32]).unwrap()
+    }
+
+    #[test]
+    fn ciphertexts_are_bound_to_their_row() {
+        let key = test_key();
+        let (nonce, ciphertext) = key.seal("repo-1\nTOKEN", "hunter2").unwrap();
+        assert_eq!(
+            key.open("repo-1\nTOKEN", &nonce, &ciphertext).unwrap(),
+            "hunter2"
+        );
+        assert!(key.open("repo-2\nTOKEN", &nonce, &ciphertext).is_err());
+        let other = SecretKey::from_bytes(&[8u8; 32]).unwrap();
+        assert!(other.open("repo-1\nTOKEN", &nonce, &ciphertext).is_err());
+    }
+
+    #[test]
+    fn keys_are_only_generated_when_missing() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("keys/secrets.key");
+        let key = SecretKey::load_or_generate(&path).unwrap();
+        let (nonce, ciphertext) = key.seal("aad", "value").unwrap();
+        let reloaded = SecretKey::load_or_generate(&path).unwrap();
+        assert_eq!(reloaded.open("aad", &nonce, &ciphertext).unwrap(), "value");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // An unreadable key is an error, not a reason to replace it
+        let unreadable = dir.path().join("keys");
+        assert!(SecretKey::load_or_generate(&unreadable).is_err());
+        assert!(unreadable.is_dir());
+    }
+
+    #[test]
+    fn names_and_masking() {
+        validate_name("DEPLOY_TOKEN").unwrap();
+        assert!(validate_name("deploy_token").is_err());
+        assert!(validate_name("1TOKEN").is_err());
+        assert_eq!(
+            mask_secrets("pushing with hunter2 to hunter2", &["hunter2".to_string()]),
+            "pushing with *** to ***"
+        );
+    }
+
+    #[tokio::test]
+    async fn only_granted_extensions_read_values() {
+        let pool = create_test_pool().await.unwrap();
+        let repo = create_repository_raw(
+            &pool,
+            CreateRepositoryInput {
+                slug: "app".into(),
+                group: None,
+            },
+        )
+        .await
+        .unwrap();
+        let key = test_key();
+        let owner = Some("did:plc:owner");
+        set_repository_secret(
+            &pool,
+            &key,
+            &repo.id,
+            "DEPLOY_TOKEN",
+            "s3cr3t",
+            &["ci".to_string()],
+            owner,
+        )
+        .await
+        .unwrap();
+
+        let listed = list_repository_secrets(&pool, &repo.id).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].extensions, vec!["ci".to_string()]);
+
+        let read = |extension: &'static str, name: &'static str| {
+            read_secret_for_extension(&pool, &key, &repo.id, name, extension, None)
+        };
+        assert_eq!(
+            read("ci", "DEPLOY_TOKEN").await.unwrap().as_deref(),
+            Some("s3cr3t")
+        );
+        assert_eq!(read("issues", "DEPLOY_TOKEN").await.unwrap(), None);
+        assert_eq!(read("ci", "MISSING").await.unwrap(), None);
+        assert!(
+            delete_repository_secret(&pool, &repo.id, "DEPLOY_TOKEN", owner)
+                .await
+                .unwrap()
+        );
+
+        let actions: Vec<(SecretAction, Option<String>)> = secret_audit(&pool, &repo.id, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .rev()
+            .map(|record| (record.action, record.extension))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (SecretAction::Set, None),
+                (SecretAction::Read, Some("ci".to_string())),
+                (SecretAction::Denied, Some("issues".to_string())),
+                (SecretAction::Denied, Some("ci".to_string())),
+                (SecretAction::Delete, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,458 @@
+//! Time-limited links that let anyone holding them read one repository.
+//!
+//! Owners share private repositories with people who have no account through a link
+//! such as `https://forge.example/share/forge_share_<64 hex digits>`. Opening it counts
+//! a use and sets the `forge_share` cookie, which GraphQL requests are then checked
+//! against (API clients can send the token in an `X-Forge-Share-Token` header instead).
+//! Links created with `allow_clone` also work as the password of an HTTP git fetch.
+//!
+//! Like access tokens, only a SHA-256 hash of the token is stored, so a link is shown
+//! once. Links expire, can be revoked early, and every creation, use and revocation is
+//! written to `repository_share_link_audit`.
+
+use std::future::Future;
+
+use anyhow::{Result, anyhow, bail};
+use rand::RngCore;
+use sqlx::{Row, SqlitePool};
+
+use crate::user::db::unix_now;
+use crate::user::security::token_hash;
+
+pub const TOKEN_PREFIX: &str = "forge_share_";
+pub const COOKIE_NAME: &str = "forge_share";
+pub const HEADER_NAME: &str = "x-forge-share-token";
+const MIN_EXPIRES_IN: i64 = 60;
+const MAX_EXPIRES_IN: i64 = 90 * 86_400;
+const MAX_ACTIVE_LINKS: i64 = 100;
+
+const LINK_COLUMNS: &str = "id, repository_id, created_by, hint, allow_clone, created_at, \
+     expires_at, revoked_at, use_count, last_used_at";
+
+#[derive(Clone, Debug, PartialEq, sqlx::FromRow)]
+pub struct ShareLink {
+    pub id: String,
+    pub repository_id: String,
+    pub created_by: Option<String>,
+    /// The first characters of the token
+    pub hint: String,
+    /// Whether the link also works for `git fetch`
+    pub allow_clone: bool,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub revoked_at: Option<i64>,
+    pub use_count: i64,
+    pub last_used_at: Option<i64>,
+}
+
+impl ShareLink {
+    pub fn is_active(&self, now: i64) -> bool {
+        self.revoked_at.is_none() && self.expires_at > now
+    }
+}
+
+/// What happened to a link, as recorded in the audit log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShareLinkAction {
+    Create,
+    /// Someone opened the link in a browser
+    Open,
+    /// Someone fetched the repository with git
+    Fetch,
+    Revoke,
+}
+
+impl ShareLinkAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareLinkAction::Create => "create",
+            ShareLinkAction::Open => "open",
+            ShareLinkAction::Fetch => "fetch",
+            ShareLinkAction::Revoke => "revoke",
+        }
+    }
+
+    pub fn as_graphql(&self) -> &'static str {
+        match self {
+            ShareLinkAction::Create => "CREATE",
+            ShareLinkAction::Open => "OPEN",
+            ShareLinkAction::Fetch => "FETCH",
+            ShareLinkAction::Revoke => "REVOKE",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "create" => Ok(ShareLinkAction::Create),
+            "open" => Ok(ShareLinkAction::Open),
+            "fetch" => Ok(ShareLinkAction::Fetch),
+            "revoke" => Ok(ShareLinkAction::Revoke),
+            other => Err(anyhow!("unknown share link audit action `{}`", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShareLinkEvent {
+    pub id: String,
+    pub link_id: String,
+    pub action: ShareLinkAction,
+    /// The signed-in user, for creation and revocation
+    pub actor_did: Option<String>,
+    /// The address a link was used from, when known
+    pub client: Option<String>,
+    pub created_at: i64,
+}
+
+/// What a valid link grants the request that presents it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShareGrant {
+    pub link_id: String,
+    pub repository_id: String,
+    pub allow_clone: bool,
+}
+
+tokio::task_local! {
+    static GRANT: Option<ShareGrant>;
+}
+
+/// Runs `fut` with `grant` in effect; see [`crate::repository::visibility`].
+pub async fn with_share_grant<F>(grant: Option<ShareGrant>, fut: F) -> F::Output
+where
+    F: Future,
+{
+    GRANT.scope(grant, fut).await
+}
+
+/// The share link the current request was made with, if any.
+pub fn current_share_grant() -> Option<ShareGrant> {
+    GRANT.try_with(|grant| grant.clone()).ok().flatten()
+}
+
+async fn record_event(
+    executor: impl sqlx::SqliteExecutor<'_>,
+    link: (&str, &str),
+    action: ShareLinkAction,
+    actor_did: Option<&str>,
+    client: Option<&str>,
+    at: i64,
+) -> Result<()> {
+    let (link_id, repository_id) = link;
+    sqlx::query(
+        "INSERT INTO repository_share_link_audit \
+         (id, link_id, repository_id, action, actor_did, client, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(cuid2::create_id())
+    .bind(link_id)
+    .bind(repository_id)
+    .bind(action.as_str())
+    .bind(actor_did)
+    .bind(client)
+    .bind(at)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Creates a link that expires after `expires_in` seconds and returns it with the only
+/// copy of its token.
+pub async fn create_share_link(
+    pool: &SqlitePool,
+    repository_id: &str,
+    created_by: Option<&str>,
+    expires_in: i64,
+    allow_clone: bool,
+) -> Result<(ShareLink, String)> {
+    if !(MIN_EXPIRES_IN..=MAX_EXPIRES_IN).contains(&expires_in) {
+        bail!(
+            "share links expire after {} seconds to {} days",
+            MIN_EXPIRES_IN,
+            MAX_EXPIRES_IN / 86_400
+        );
+    }
+    let now = unix_now();
+    let active: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM repository_share_links \
+         WHERE repository_id = ? AND revoked_at IS NULL AND expires_at > ?",
+    )
+    .bind(repository_id)
+    .bind(now)
+    .fetch_one(pool)
+    .await?;
+    if active >= MAX_ACTIVE_LINKS {
+        bail!(
+            "repositories can have at most {} active share links",
+            MAX_ACTIVE_LINKS
+        );
+    }
+
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let token = format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        secret
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    );
+    let link = ShareLink {
+        id: cuid2::create_id(),
+        repository_id: repository_id.to_string(),
+        created_by: created_by.map(str::to_string),
+        hint: token[..TOKEN_PREFIX.len() + 6].to_string(),
+        allow_clone,
+        created_at: now,
+        expires_at: now + expires_in,
+        revoked_at: None,
+        use_count: 0,
+        last_used_at: None,
+    };
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO repository_share_links \
+         (id, repository_id, token_hash, created_by, hint, allow_clone, created_at, expires_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&link.id)
+    .bind(repository_id)
+    .bind(token_hash(&token))
+    .bind(created_by)
+    .bind(&link.hint)
+    .bind(allow_clone)
+    .bind(link.created_at)
+    .bind(link.expires_at)
+    .execute(&mut *tx)
+    .await?;
+    record_event(
+        &mut *tx,
+        (&link.id, repository_id),
+        ShareLinkAction::Create,
+        created_by,
+        None,
+        now,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((link, token))
+}
+
+pub async fn fetch_share_link(pool: &SqlitePool, id: &str) -> Result<Option<ShareLink>> {
+    Ok(sqlx::query_as::<_, ShareLink>(&format!(
+        "SELECT {} FROM repository_share_links WHERE id = ?",
+        LINK_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?)
+}
+
+/// Links of a repository, newest first, including expired and revoked ones.
+pub async fn list_share_links(pool: &SqlitePool, repository_id: &str) -> Result<Vec<ShareLink>> {
+    Ok(sqlx::query_as::<_, ShareLink>(&format!(
+        "SELECT {} FROM repository_share_links WHERE repository_id = ? \
+         ORDER BY created_at DESC, id",
+        LINK_COLUMNS
+    ))
+    .bind(repository_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Revokes a link, returning it. Revoking a revoked link changes nothing.
+pub async fn revoke_share_link(
+    pool: &SqlitePool,
+    id: &str,
+    actor_did: Option<&str>,
+) -> Result<Option<ShareLink>> {
+    let now = unix_now();
+    let mut tx = pool.begin().await?;
+    let revoked = sqlx::query_as::<_, ShareLink>(&format!(
+        "UPDATE repository_share_links SET revoked_at = ? \
+         WHERE id = ? AND revoked_at IS NULL RETURNING {}",
+        LINK_COLUMNS
+    ))
+    .bind(now)
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(link) = revoked else {
+        drop(tx);
+        return fetch_share_link(pool, id).await;
+    };
+    record_event(
+        &mut *tx,
+        (&link.id, &link.repository_id),
+        ShareLinkAction::Revoke,
+        actor_did,
+        None,
+        now,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(Some(link))
+}
+
+/// The grant of an unexpired, unrevoked link. Checking a token does not count as a use;
+/// see [`record_share_use`].
+pub async fn verify_share_token(pool: &SqlitePool, token: &str) -> Result<Option<ShareGrant>> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+    let row = sqlx::query(
+        "SELECT id, repository_id, allow_clone FROM repository_share_links \
+         WHERE token_hash = ? AND revoked_at IS NULL AND expires_at > ?",
+    )
+    .bind(token_hash(token))
+    .bind(unix_now())
+    .fetch_optional(pool)
+    .await?;
+    row.map(|row| {
+        Ok(ShareGrant {
+            link_id: row.try_get("id")?,
+            repository_id: row.try_get("repository_id")?,
+            allow_clone: row.try_get("allow_clone")?,
+        })
+    })
+    .transpose()
+}
+
+/// Counts a use of a link and audits it.
+pub async fn record_share_use(
+    pool: &SqlitePool,
+    grant: &ShareGrant,
+    action: ShareLinkAction,
+    client: Option<&str>,
+) -> Result<()> {
+    let now = unix_now();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "UPDATE repository_share_links SET use_count = use_count + 1, last_used_at = ? \
+         WHERE id = ?",
+    )
+    .bind(now)
+    .bind(&grant.link_id)
+    .execute(&mut *tx)
+    .await?;
+    record_event(
+        &mut *tx,
+        (&grant.link_id, &grant.repository_id),
+        action,
+        None,
+        client,
+        now,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Audit records of a repository's links, newest first.
+pub async fn share_link_audit(
+    pool: &SqlitePool,
+    repository_id: &str,
+    limit: i64,
+) -> Result<Vec<ShareLinkEvent>> {
+    let rows = sqlx::query(
+        "SELECT id, link_id, action, actor_did, client, created_at \
+         FROM repository_share_link_audit WHERE repository_id = ? \
+         ORDER BY created_at DESC, rowid DESC LIMIT ?",
+    )
+    .bind(repository_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|row| {
+            let action: String = row.try_get("action")?;
+            Ok(ShareLinkEvent {
+                id: row.try_get("id")?,
+                link_id: row.try_get("link_id")?,
+                action: ShareLinkAction::parse(&action)?,
+                actor_did: row.try_get("actor_did")?,
+                client: row.try_get("client")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::settings::{Visibility, set_repository_settings};
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::repository::visibility::can_view_repository;
+    use crate::test_helpers::create_test_pool;
+
+    #[tokio::test]
+    async fn links_grant_access_until_revoked() {
+        let pool = create_test_pool().await.unwrap();
+        let input = CreateRepositoryInput {
+            slug: "secret-plans".into(),
+            group: None,
+        };
+        let repo = create_repository_raw(&pool, input).await.unwrap();
+        set_repository_settings(&pool, &repo.id, None, Some(Visibility::Private))
+            .await
+            .unwrap();
+        assert!(
+            create_share_link(&pool, &repo.id, None, 10, false)
+                .await
+                .is_err()
+        );
+
+        let (link, token) = create_share_link(&pool, &repo.id, Some("did:plc:owner"), 3600, true)
+            .await
+            .unwrap();
+        assert!(token.starts_with(&link.hint));
+        assert!(link.is_active(unix_now()));
+        let grant = verify_share_token(&pool, &token).await.unwrap().unwrap();
+        assert_eq!(grant.repository_id, repo.id);
+        assert!(grant.allow_clone);
+        assert_eq!(
+            verify_share_token(&pool, "forge_share_00").await.unwrap(),
+            None
+        );
+
+        assert!(!can_view_repository(&pool, None, &repo.id).await.unwrap());
+        let shared = with_share_grant(
+            Some(grant.clone()),
+            can_view_repository(&pool, None, &repo.id),
+        );
+        assert!(shared.await.unwrap());
+
+        record_share_use(&pool, &grant, ShareLinkAction::Open, Some("192.0.2.7"))
+            .await
+            .unwrap();
+        let revoked = revoke_share_link(&pool, &link.id, Some("did:plc:owner"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(revoked.use_count, 1);
+        assert!(!revoked.is_active(unix_now()));
+        assert_eq!(verify_share_token(&pool, &token).await.unwrap(), None);
+        assert!(
+            revoke_share_link(&pool, &link.id, None)
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        let actions: Vec<_> = share_link_audit(&pool, &repo.id, 10)
+            .await
+            .unwrap()
+            .iter()
+            .map(|event| (event.action, event.client.clone()))
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                (ShareLinkAction::Revoke, None),
+                (ShareLinkAction::Open, Some("192.0.2.7".to_string())),
+                (ShareLinkAction::Create, None),
+            ]
+        );
+    }
+}
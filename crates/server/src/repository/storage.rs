@@ -1,13 +1,63 @@
+use git_http::GitScheduler;
+use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 use tokio::task;
 
 use super::cache::refresh_remote_repository_cache;
 use super::models::RepositoryRecord;
 
+/// On-disk layout used for repositories under `local_root`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StorageLayout {
+    /// `<root>/<group>/<repo>.git`
+    #[default]
+    Flat,
+    /// `<root>/<aa>/<bb>/<group>/<repo>.git`, where `aabb` is the start of the
+    /// SHA-256 of the repository path. Keeps directory fan-out bounded.
+    Sharded,
+}
+
+impl StorageLayout {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "flat" => Ok(StorageLayout::Flat),
+            "sharded" => Ok(StorageLayout::Sharded),
+            other => Err(anyhow::anyhow!(
+                "unknown repository storage layout: {}",
+                other
+            )),
+        }
+    }
+
+    /// Parses `repos.layout` (`flat` or `sharded`), defaulting to flat.
+    pub fn from_config(config: &crate::config::ReposConfig) -> anyhow::Result<Self> {
+        match &config.layout {
+            Some(value) => StorageLayout::parse(value),
+            None => Ok(StorageLayout::Flat),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageLayout::Flat => "flat",
+            StorageLayout::Sharded => "sharded",
+        }
+    }
+
+    fn other(&self) -> StorageLayout {
+        match self {
+            StorageLayout::Flat => StorageLayout::Sharded,
+            StorageLayout::Sharded => StorageLayout::Flat,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct RepositoryStorage {
     pub local_root: PathBuf,
     pub remote_cache_root: PathBuf,
+    pub layout: StorageLayout,
 }
 
 impl RepositoryStorage {
@@ -15,38 +65,74 @@ impl RepositoryStorage {
         RepositoryStorage {
             local_root,
             remote_cache_root,
+            layout: StorageLayout::Flat,
         }
     }
 
-    pub fn ensure_local_repository(&self, segments: &[String]) -> anyhow::Result<PathBuf> {
+    pub fn with_layout(mut self, layout: StorageLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Path a repository should be created at under the configured layout.
+    pub fn repository_path(&self, segments: &[String]) -> PathBuf {
+        self.repository_path_for(self.layout, segments)
+    }
+
+    /// Path a repository occupies under an explicit layout.
+    pub fn repository_path_for(&self, layout: StorageLayout, segments: &[String]) -> PathBuf {
         let mut path = self.local_root.clone();
+        if layout == StorageLayout::Sharded {
+            let digest = Sha256::digest(segments.join("/").as_bytes());
+            path.push(format!("{:02x}", digest[0]));
+            path.push(format!("{:02x}", digest[1]));
+        }
         for segment in segments {
             path.push(segment);
         }
 
         // Repositories are stored with .git suffix (bare repositories)
-        // Append .git to the final path to match the storage format
-        let repo_path = path.with_extension("git");
+        path.with_extension("git")
+    }
 
-        tracing::info!("ensure_local_repository query: {}", repo_path.display());
+    /// Resolves a repository on disk. The configured layout is checked first, then the
+    /// alternative one, so repositories keep resolving while a layout migration is in
+    /// progress.
+    pub fn ensure_local_repository(&self, segments: &[String]) -> anyhow::Result<PathBuf> {
+        let primary = self.repository_path(segments);
+        if let Some(found) = Self::existing_repository_dir(&primary) {
+            return Ok(found);
+        }
+
+        let fallback = self.repository_path_for(self.layout.other(), segments);
+        if let Some(found) = Self::existing_repository_dir(&fallback) {
+            tracing::debug!(
+                "ensure_local_repository found repo in {} layout: {}",
+                self.layout.other().as_str(),
+                fallback.display()
+            );
+            return Ok(found);
+        }
+
+        tracing::info!("ensure_local_repository missing: {}", primary.display());
+        Err(anyhow::anyhow!(
+            "repository directory not found at {}",
+            primary.display()
+        ))
+    }
 
+    fn existing_repository_dir(repo_path: &Path) -> Option<PathBuf> {
         // Check if this is a non-bare repository (has .git subdirectory)
         let git_dir = repo_path.join(".git");
-        let actual_repo_path = if git_dir.is_dir() {
+        if git_dir.is_dir() {
             tracing::info!("ensure_local_repository found non-bare repo, using .git subdirectory: {}", git_dir.display());
-            git_dir
+            Some(git_dir)
         } else if repo_path.is_dir() {
             tracing::info!("ensure_local_repository found bare repo: {}", repo_path.display());
-            repo_path.clone()
+            Some(repo_path.to_path_buf())
         } else {
-            tracing::info!("ensure_local_repository missing: {}", repo_path.display());
-            return Err(anyhow::anyhow!(
-                "repository directory not found at {}",
-                repo_path.display()
-            ));
-        };
-
-        Ok(actual_repo_path)
+            None
+        }
     }
 
     pub async fn ensure_remote_repository(
@@ -106,8 +192,70 @@ impl RepositoryStorage {
     }
 }
 
+/// The git concurrency budgets from the `git` config section, shared by Smart HTTP and
+/// remote syncs.
+pub fn git_scheduler() -> &'static Arc<GitScheduler> {
+    static SCHEDULER: OnceLock<Arc<GitScheduler>> = OnceLock::new();
+    SCHEDULER.get_or_init(|| Arc::new(GitScheduler::new(&crate::config::current().git.limits())))
+}
+
 impl git_http::RepositoryProvider for RepositoryStorage {
     fn ensure_local_repository(&self, segments: &[String]) -> anyhow::Result<PathBuf> {
         RepositoryStorage::ensure_local_repository(self, segments)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments(path: &str) -> Vec<String> {
+        path.split('/').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn sharded_paths_are_prefixed_by_hash() {
+        let storage = RepositoryStorage::new(PathBuf::from("/repos"), PathBuf::from("/cache"))
+            .with_layout(StorageLayout::Sharded);
+        let path = storage.repository_path(&segments("team/app"));
+        let relative = path.strip_prefix("/repos").unwrap();
+        let parts: Vec<_> = relative
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0].len(), 2);
+        assert_eq!(parts[1].len(), 2);
+        assert_eq!(parts[2], "team");
+        assert_eq!(parts[3], "app.git");
+    }
+
+    #[test]
+    fn resolves_repositories_from_either_layout() {
+        let root = tempfile::tempdir().unwrap();
+        let flat = RepositoryStorage::new(root.path().to_path_buf(), root.path().join("cache"));
+        let sharded = flat.clone().with_layout(StorageLayout::Sharded);
+
+        let repo = segments("demo");
+        std::fs::create_dir_all(flat.repository_path(&repo)).unwrap();
+        assert_eq!(
+            sharded.ensure_local_repository(&repo).unwrap(),
+            flat.repository_path(&repo)
+        );
+        assert!(
+            sharded
+                .ensure_local_repository(&segments("missing"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parses_layout_names() {
+        assert_eq!(
+            StorageLayout::parse("Sharded").unwrap(),
+            StorageLayout::Sharded
+        );
+        assert_eq!(StorageLayout::parse("").unwrap(), StorageLayout::Flat);
+        assert!(StorageLayout::parse("nested").is_err());
+    }
+}
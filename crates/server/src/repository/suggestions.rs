@@ -0,0 +1,477 @@
+//! Suggested changes in review comments.
+//!
+//! A review comment may carry a fenced ```` ```suggestion ```` block: replacement text
+//! for the lines it is attached to. Applying it writes a commit on the pull request's
+//! branch through the same path as a server-side cherry-pick: the tree is edited in the
+//! object database, the commit goes through the push policy, and the branch moves with a
+//! compare-and-swap. The person applying the suggestion authors the commit, and the
+//! comment's author is credited with a `Co-authored-by` trailer.
+//!
+//! Review comments belong to the pull requests extension (RFC-0004), so the core reads
+//! them through the router's `reviewComment(id)` field. A suggestion whose lines changed
+//! on the branch since the comment was written is reported as outdated and not applied.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, anyhow, bail};
+use git_http::policy::PolicyViolation;
+use serde_json::json;
+use tokio::task;
+
+use super::cherry_pick::{move_branch, short_oid, signature};
+use super::resolver::PathResolver;
+use super::storage::RepositoryStorage;
+use crate::api::server::GraphQLRequest;
+use crate::auth::User;
+use crate::router::GraphQLExecutionRequest;
+
+/// Reads what applying a suggestion needs from the pull requests extension.
+const REVIEW_COMMENT_QUERY: &str = "query($id: ID!) { reviewComment(id: $id) { \
+     body repositoryPath branch filePath startLine endLine commitOid authorDid } }";
+
+/// A suggestion block and the lines it replaces, as read from a review comment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Suggestion {
+    /// Path of the repository the pull request's branch lives in
+    pub repository_path: String,
+    pub branch: String,
+    pub file_path: String,
+    /// First and last replaced line, 1-based and inclusive
+    pub start_line: usize,
+    pub end_line: usize,
+    /// The commit the comment was written against
+    pub commit_oid: String,
+    pub replacement: String,
+    pub author_did: String,
+}
+
+/// What applying a suggestion did. `commit_oid` is set only when the branch moved.
+#[derive(Clone, Debug)]
+pub struct SuggestionOutcome {
+    pub branch: String,
+    pub file_path: String,
+    /// Tip of the branch before the suggestion was applied
+    pub previous_oid: String,
+    pub commit_oid: Option<String>,
+    /// The suggested lines changed on the branch since the comment was written.
+    pub outdated: bool,
+    pub violations: Vec<PolicyViolation>,
+}
+
+impl SuggestionOutcome {
+    pub fn applied(&self) -> bool {
+        self.commit_oid.is_some()
+    }
+}
+
+/// The contents of the ```` ```suggestion ```` blocks in a comment body, in order.
+pub fn parse_suggestions(body: &str) -> Vec<String> {
+    let mut suggestions = Vec::new();
+    let mut open: Option<(char, usize, Vec<&str>)> = None;
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+        let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+        let fence_len = fence_char.map_or(0, |c| trimmed.chars().take_while(|&x| x == c).count());
+        match &mut open {
+            Some((c, len, lines)) => {
+                if fence_char == Some(*c)
+                    && fence_len >= *len
+                    && trimmed[fence_len..].trim().is_empty()
+                {
+                    suggestions.push(lines.join("\n"));
+                    open = None;
+                } else {
+                    lines.push(line);
+                }
+            }
+            None if fence_len >= 3 => {
+                let info = trimmed[fence_len..].trim();
+                if info.split_whitespace().next() == Some("suggestion") {
+                    open = Some((fence_char.unwrap_or('`'), fence_len, Vec::new()));
+                }
+            }
+            None => {}
+        }
+    }
+    suggestions
+}
+
+/// Reads review comment `comment_id` through the router. Fails when the pull requests
+/// extension is not loaded or the comment has no single suggestion.
+pub async fn fetch_suggestion(comment_id: &str) -> anyhow::Result<Suggestion> {
+    let router = crate::router::shared().ok_or_else(|| anyhow!("GraphQL is not available"))?;
+    if router.field_graph("Query.reviewComment").is_none() {
+        bail!("review comments need the pull requests extension");
+    }
+    let request = GraphQLExecutionRequest::from_payload(&GraphQLRequest {
+        query: REVIEW_COMMENT_QUERY.to_string(),
+        operation_name: None,
+        variables: json!({ "id": comment_id }),
+    })?;
+    let response = router.execute(request).await?;
+    if let Some(error) = response["errors"].get(0) {
+        bail!(
+            "cannot read review comment: {}",
+            error["message"].as_str().unwrap_or("unknown error")
+        );
+    }
+    let comment = &response["data"]["reviewComment"];
+    if comment.is_null() {
+        bail!("review comment not found");
+    }
+    let text = |name: &str| -> anyhow::Result<String> {
+        comment[name]
+            .as_str()
+            .map(str::to_string)
+            .with_context(|| format!("review comment has no {}", name))
+    };
+    let line = |name: &str| -> anyhow::Result<usize> {
+        comment[name]
+            .as_u64()
+            .filter(|line| *line > 0)
+            .map(|line| line as usize)
+            .with_context(|| format!("review comment has no {}", name))
+    };
+
+    let mut suggestions = parse_suggestions(&text("body")?);
+    let replacement = match suggestions.len() {
+        0 => bail!("the review comment has no suggestion"),
+        1 => suggestions.remove(0),
+        n => bail!(
+            "the review comment has {} suggestions; only one can be applied",
+            n
+        ),
+    };
+    let (start_line, end_line) = (line("startLine")?, line("endLine")?);
+    if end_line < start_line {
+        bail!("the review comment's line range is empty");
+    }
+    Ok(Suggestion {
+        repository_path: text("repositoryPath")?,
+        branch: text("branch")?,
+        file_path: text("filePath")?,
+        start_line,
+        end_line,
+        commit_oid: text("commitOid")?,
+        replacement,
+        author_did: text("authorDid")?,
+    })
+}
+
+/// Commits `suggestion` on its branch as `committer`, crediting `co_author`. Returns
+/// `None` when the repository does not exist.
+///
+/// An outdated suggestion and policy violations are reported in the outcome rather than
+/// as errors; the branch is left where it was in both cases.
+pub async fn apply_suggestion(
+    resolver: &PathResolver,
+    storage: &RepositoryStorage,
+    suggestion: &Suggestion,
+    committer: &User,
+    co_author: Option<&User>,
+) -> anyhow::Result<Option<SuggestionOutcome>> {
+    let Some(resolved) = resolver
+        .resolve_repository(&suggestion.repository_path)
+        .await?
+    else {
+        return Ok(None);
+    };
+    if resolved.record.remote_url.is_some() {
+        bail!("linked repositories are read-only mirrors of their remote");
+    }
+    let repository_path = resolved.local_dir(storage)?;
+
+    let message = suggestion_message(&suggestion.file_path, committer, co_author);
+    let prepared = {
+        let repository_path = repository_path.clone();
+        let suggestion = suggestion.clone();
+        let committer = signature(committer);
+        task::spawn_blocking(move || {
+            prepare_commit(repository_path, &suggestion, committer, &message)
+        })
+        .await
+        .map_err(|err| anyhow!(err))??
+    };
+
+    let mut outcome = SuggestionOutcome {
+        branch: suggestion.branch.clone(),
+        file_path: suggestion.file_path.clone(),
+        previous_oid: prepared.previous_oid,
+        commit_oid: None,
+        outdated: prepared.commit_oid.is_none(),
+        violations: Vec::new(),
+    };
+    let Some(commit_oid) = prepared.commit_oid else {
+        return Ok(Some(outcome));
+    };
+    let reflog = format!(
+        "applied suggestion to {} from {}",
+        suggestion.file_path,
+        short_oid(&suggestion.commit_oid)
+    );
+    outcome.violations = move_branch(
+        resolver,
+        &resolved.record.id,
+        &repository_path,
+        &suggestion.branch,
+        &outcome.previous_oid,
+        &commit_oid,
+        &reflog,
+    )
+    .await?;
+    if outcome.violations.is_empty() {
+        outcome.commit_oid = Some(commit_oid);
+    }
+    Ok(Some(outcome))
+}
+
+/// The commit message, with a trailer for the suggester unless they apply it themselves.
+fn suggestion_message(file_path: &str, committer: &User, co_author: Option<&User>) -> String {
+    let mut message = format!("Apply suggestion to {}\n", file_path);
+    if let Some(co_author) = co_author.filter(|user| user.did != committer.did) {
+        let name = co_author
+            .display_name
+            .as_deref()
+            .unwrap_or(&co_author.handle);
+        message.push_str(&format!(
+            "\nCo-authored-by: {} <{}>\n",
+            name, co_author.handle
+        ));
+    }
+    message
+}
+
+struct PreparedCommit {
+    previous_oid: String,
+    /// Written but not yet referenced; `None` when the suggestion is outdated
+    commit_oid: Option<String>,
+}
+
+fn prepare_commit(
+    repository_path: PathBuf,
+    suggestion: &Suggestion,
+    committer: gix::actor::Signature,
+    message: &str,
+) -> anyhow::Result<PreparedCommit> {
+    let repo = gix::open(&repository_path)?;
+    let branch = &suggestion.branch;
+    let tip = repo
+        .rev_parse_single(format!("refs/heads/{}", branch).as_str())
+        .map_err(|_| anyhow!("branch `{}` not found", branch))?
+        .object()?
+        .peel_to_commit()
+        .map_err(|_| anyhow!("branch `{}` does not point at a commit", branch))?;
+    let previous_oid = tip.id.to_string();
+    let base = repo
+        .rev_parse_single(suggestion.commit_oid.as_str())
+        .map_err(|_| anyhow!("commit {} not found", suggestion.commit_oid))?
+        .object()?
+        .peel_to_commit()
+        .map_err(|_| anyhow!("{} is not a commit", suggestion.commit_oid))?;
+
+    let path = suggestion.file_path.as_str();
+    let read =
+        |commit: &gix::Commit<'_>| -> anyhow::Result<Option<(gix::objs::tree::EntryMode, String)>> {
+            let Some(entry) = commit.tree()?.lookup_entry_by_path(path)? else {
+                return Ok(None);
+            };
+            if !entry.mode().is_blob() {
+                bail!("{} is not a file", path);
+            }
+            let blob = entry.object()?;
+            let text = String::from_utf8(blob.data.clone())
+                .map_err(|_| anyhow!("{} is not a text file", path))?;
+            Ok(Some((entry.mode(), text)))
+        };
+    let (_, base_text) =
+        read(&base)?.ok_or_else(|| anyhow!("{} does not exist at {}", path, base.id))?;
+    let Some((mode, tip_text)) = read(&tip)? else {
+        return Ok(PreparedCommit {
+            previous_oid,
+            commit_oid: None,
+        });
+    };
+
+    let (start, end) = (suggestion.start_line, suggestion.end_line);
+    let commented = line_range(&base_text, start, end)?;
+    if line_range(&tip_text, start, end).ok() != Some(commented) {
+        return Ok(PreparedCommit {
+            previous_oid,
+            commit_oid: None,
+        });
+    }
+    let updated = replace_lines(&tip_text, start, end, &suggestion.replacement)?;
+    if updated == tip_text {
+        bail!("the suggestion is already applied");
+    }
+
+    let blob = repo.write_blob(updated.as_bytes())?.detach();
+    let mut editor = repo.edit_tree(tip.tree_id()?)?;
+    editor.upsert(path, mode.kind(), blob)?;
+    let tree = editor.write()?.detach();
+    let commit = gix::objs::Commit {
+        tree,
+        parents: vec![tip.id].into(),
+        author: committer.clone(),
+        committer,
+        encoding: None,
+        message: message.into(),
+        extra_headers: Vec::new(),
+    };
+    let commit_oid = repo.write_object(&commit)?.detach();
+    Ok(PreparedCommit {
+        previous_oid,
+        commit_oid: Some(commit_oid.to_string()),
+    })
+}
+
+/// Lines `start..=end` (1-based) of `text`, with their line endings.
+fn line_range(text: &str, start: usize, end: usize) -> anyhow::Result<&str> {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    if start == 0 || end < start || end > lines.len() {
+        bail!(
+            "lines {}-{} are outside the file, which has {} lines",
+            start,
+            end,
+            lines.len()
+        );
+    }
+    let from: usize = lines[..start - 1].iter().map(|line| line.len()).sum();
+    let to: usize = from
+        + lines[start - 1..end]
+            .iter()
+            .map(|line| line.len())
+            .sum::<usize>();
+    Ok(&text[from..to])
+}
+
+/// Replaces lines `start..=end` (1-based) of `text` with `replacement`, keeping the
+/// file's line endings. An empty replacement deletes the lines.
+fn replace_lines(
+    text: &str,
+    start: usize,
+    end: usize,
+    replacement: &str,
+) -> anyhow::Result<String> {
+    let replaced = line_range(text, start, end)?;
+    let from = replaced.as_ptr() as usize - text.as_ptr() as usize;
+    let to = from + replaced.len();
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let lines: Vec<&str> = replacement.lines().collect();
+    let mut joined = lines.join(newline);
+    // The last line keeps whatever ending the replaced range had, including none at the
+    // end of a file without a trailing newline.
+    if !lines.is_empty() && replaced.ends_with('\n') {
+        joined.push_str(newline);
+    }
+    Ok(format!("{}{}{}", &text[..from], joined, &text[to..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::git;
+
+    fn user(did: &str, handle: &str, name: Option<&str>) -> User {
+        User {
+            did: did.to_string(),
+            handle: handle.to_string(),
+            display_name: name.map(str::to_string),
+            avatar: None,
+        }
+    }
+
+    #[test]
+    fn suggestion_blocks_are_found_in_comments() {
+        let body = "Nit:\n\n```suggestion\nlet x = 1;\nlet y = 2;\n```\n\n```rust\nnot this\n```\n\
+                    ~~~~ suggestion\n```\ninner fence\n```\n~~~~\n";
+        assert_eq!(
+            parse_suggestions(body),
+            ["let x = 1;\nlet y = 2;", "```\ninner fence\n```"]
+        );
+        assert_eq!(parse_suggestions("```suggestion\n```"), [""]);
+        assert!(parse_suggestions("```suggestion\nunclosed").is_empty());
+    }
+
+    #[test]
+    fn lines_are_replaced_with_the_file_endings() {
+        assert_eq!(replace_lines("a\nb\nc\n", 2, 2, "B").unwrap(), "a\nB\nc\n");
+        assert_eq!(
+            replace_lines("a\r\nb\r\nc", 2, 3, "x\ny").unwrap(),
+            "a\r\nx\r\ny"
+        );
+        assert_eq!(replace_lines("a\nb\nc\n", 1, 2, "").unwrap(), "c\n");
+        assert!(replace_lines("a\n", 1, 2, "x").is_err());
+    }
+
+    #[test]
+    fn messages_credit_the_suggester() {
+        let alice = user("did:plc:alice", "alice.test", Some("Alice"));
+        let bob = user("did:plc:bob", "bob.test", None);
+        assert_eq!(
+            suggestion_message("src/lib.rs", &bob, Some(&alice)),
+            "Apply suggestion to src/lib.rs\n\nCo-authored-by: Alice <alice.test>\n"
+        );
+        assert_eq!(
+            suggestion_message("src/lib.rs", &alice, Some(&alice)),
+            "Apply suggestion to src/lib.rs\n"
+        );
+    }
+
+    #[test]
+    fn suggestions_commit_unless_outdated() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let dir = temp.path();
+        git(dir, &["init", "-q", "-b", "main"]);
+        std::fs::write(dir.join("a.txt"), "one\ntwo\nthree\n").unwrap();
+        git(dir, &["add", "."]);
+        git(dir, &["commit", "-q", "-m", "Add a"]);
+        let commented = git(dir, &["rev-parse", "HEAD"]);
+        std::fs::write(dir.join("b.txt"), "bee\n").unwrap();
+        git(dir, &["add", "."]);
+        git(dir, &["commit", "-q", "-m", "Add b"]);
+
+        let mut suggestion = Suggestion {
+            repository_path: "team/app".to_string(),
+            branch: "main".to_string(),
+            file_path: "a.txt".to_string(),
+            start_line: 2,
+            end_line: 2,
+            commit_oid: commented.clone(),
+            replacement: "2".to_string(),
+            author_did: "did:plc:alice".to_string(),
+        };
+        let committer = signature(&user("did:plc:bob", "bob.test", None));
+        let prepared =
+            prepare_commit(dir.to_path_buf(), &suggestion, committer.clone(), "Apply\n").unwrap();
+        let oid = prepared.commit_oid.unwrap();
+        assert_eq!(
+            git(dir, &["show", &format!("{}:a.txt", oid)]),
+            "one\n2\nthree"
+        );
+        assert_eq!(
+            git(dir, &["rev-parse", &format!("{}^", oid)]),
+            prepared.previous_oid
+        );
+        assert_eq!(git(dir, &["log", "-1", "--format=%an", &oid]), "bob.test");
+        // Preparing does not move the branch
+        assert_eq!(git(dir, &["rev-parse", "main"]), prepared.previous_oid);
+
+        std::fs::write(dir.join("a.txt"), "one\nTWO\nthree\n").unwrap();
+        git(dir, &["commit", "-q", "-am", "Shout"]);
+        let outdated =
+            prepare_commit(dir.to_path_buf(), &suggestion, committer.clone(), "Apply\n").unwrap();
+        assert!(outdated.commit_oid.is_none());
+
+        // Lines elsewhere in the file may change.
+        suggestion.start_line = 3;
+        suggestion.end_line = 3;
+        suggestion.replacement = "3\n4".to_string();
+        let moved = prepare_commit(dir.to_path_buf(), &suggestion, committer, "Apply\n").unwrap();
+        let oid = moved.commit_oid.unwrap();
+        assert_eq!(
+            git(dir, &["show", &format!("{}:a.txt", oid)]),
+            "one\nTWO\n3\n4"
+        );
+    }
+}
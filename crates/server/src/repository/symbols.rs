@@ -0,0 +1,624 @@
+//! Symbol index for code navigation.
+//!
+//! Definitions are extracted with tree-sitter, using the `tags.scm` query each grammar
+//! ships: a symbol is whatever the grammar tags as `@definition.*`, which is what
+//! `tree-sitter tags` and ctags-style tools report. Only the default branch is indexed.
+//! [`JOB_KIND`] jobs rebuild a repository's index when its default branch moves, and a
+//! file is parsed again only when its blob changed, so a push touching a few files costs
+//! a few parses. The index is rebuilt and checked like code search's, by
+//! [`crate::search::maintenance`].
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::task;
+use tree_sitter::{Language, Parser, Query, QueryCursor, StreamingIterator};
+
+use super::queries::{get_repository_by_id, reconstruct_repository_path};
+use super::storage::RepositoryStorage;
+use crate::jobs::{JobHandler, JobRecord, enqueue_in};
+use crate::search::maintenance::Consistency;
+use crate::user::db::unix_now;
+
+pub const JOB_KIND: &str = "symbol-index";
+const MAX_ATTEMPTS: i64 = 3;
+/// Larger files are usually generated or minified and are skipped.
+const MAX_FILE_SIZE: usize = 512 * 1024;
+/// Files parsed per repository; the rest of a very large tree is not indexed.
+const MAX_FILES: usize = 20_000;
+pub const DEFAULT_RESULTS: usize = 50;
+pub const MAX_RESULTS: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SymbolLanguage {
+    Rust,
+    Python,
+    JavaScript,
+    TypeScript,
+    Tsx,
+    Go,
+}
+
+impl SymbolLanguage {
+    const ALL: [Self; 6] = [
+        Self::Rust,
+        Self::Python,
+        Self::JavaScript,
+        Self::TypeScript,
+        Self::Tsx,
+        Self::Go,
+    ];
+
+    pub fn for_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?;
+        Some(match extension {
+            "rs" => Self::Rust,
+            "py" | "pyi" => Self::Python,
+            "js" | "jsx" | "mjs" | "cjs" => Self::JavaScript,
+            "ts" | "mts" | "cts" => Self::TypeScript,
+            "tsx" => Self::Tsx,
+            "go" => Self::Go,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::Python => "python",
+            Self::JavaScript => "javascript",
+            Self::TypeScript => "typescript",
+            Self::Tsx => "tsx",
+            Self::Go => "go",
+        }
+    }
+
+    fn grammar(self) -> Language {
+        match self {
+            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Self::Python => tree_sitter_python::LANGUAGE.into(),
+            Self::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            Self::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Self::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Self::Go => tree_sitter_go::LANGUAGE.into(),
+        }
+    }
+
+    /// TypeScript's tags only cover what it adds to JavaScript, so both are used.
+    fn tags_query(self) -> String {
+        match self {
+            Self::Rust => tree_sitter_rust::TAGS_QUERY.to_string(),
+            Self::Python => tree_sitter_python::TAGS_QUERY.to_string(),
+            Self::JavaScript => tree_sitter_javascript::TAGS_QUERY.to_string(),
+            Self::TypeScript | Self::Tsx => format!(
+                "{}\n{}",
+                tree_sitter_javascript::TAGS_QUERY,
+                tree_sitter_typescript::TAGS_QUERY
+            ),
+            Self::Go => tree_sitter_go::TAGS_QUERY.to_string(),
+        }
+    }
+}
+
+/// Tags queries, compiled once.
+static QUERIES: LazyLock<HashMap<SymbolLanguage, Query>> = LazyLock::new(|| {
+    SymbolLanguage::ALL
+        .into_iter()
+        .map(|language| {
+            let query = Query::new(&language.grammar(), &language.tags_query())
+                .unwrap_or_else(|e| panic!("{} tags query: {}", language.as_str(), e));
+            (language, query)
+        })
+        .collect()
+});
+
+/// A definition found in a file. Lines and columns are 1-based; columns count characters.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct Symbol {
+    pub name: String,
+    /// The tag without its `definition.` prefix: `function`, `method`, `class`, ...
+    pub kind: String,
+    pub language: String,
+    pub file_path: String,
+    pub start_line: i64,
+    pub start_column: i64,
+    pub end_line: i64,
+}
+
+/// Definitions in `source`, in file order. Files in unsupported languages have none.
+pub fn extract_symbols(file_path: &str, source: &str) -> Vec<Symbol> {
+    let Some(language) = SymbolLanguage::for_path(file_path) else {
+        return Vec::new();
+    };
+    let mut parser = Parser::new();
+    if parser.set_language(&language.grammar()).is_err() {
+        return Vec::new();
+    }
+    let Some(tree) = parser.parse(source, None) else {
+        return Vec::new();
+    };
+    let query = &QUERIES[&language];
+    let names = query.capture_names();
+
+    let mut symbols = Vec::new();
+    let mut seen = HashSet::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(query, tree.root_node(), source.as_bytes());
+    while let Some(found) = matches.next() {
+        let mut name = None;
+        let mut definition = None;
+        for capture in found.captures {
+            match names[capture.index as usize] {
+                "name" => name = Some(capture.node),
+                tag => {
+                    if let Some(kind) = tag.strip_prefix("definition.") {
+                        definition = Some((kind, capture.node));
+                    }
+                }
+            }
+        }
+        let (Some(name), Some((kind, node))) = (name, definition) else {
+            continue;
+        };
+        let Ok(text) = name.utf8_text(source.as_bytes()) else {
+            continue;
+        };
+        // Several patterns can tag the same name; the first, more specific one wins
+        // (a Rust function inside an `impl` is a method, not also a function)
+        if !seen.insert(name.start_byte()) {
+            continue;
+        }
+        let start = name.start_position();
+        let line_start = source[..name.start_byte()]
+            .rfind('\n')
+            .map_or(0, |at| at + 1);
+        symbols.push(Symbol {
+            name: text.to_string(),
+            kind: kind.to_string(),
+            language: language.as_str().to_string(),
+            file_path: file_path.to_string(),
+            start_line: start.row as i64 + 1,
+            start_column: source[line_start..name.start_byte()].chars().count() as i64 + 1,
+            end_line: node.end_position().row as i64 + 1,
+        });
+    }
+    symbols.sort_by_key(|symbol| (symbol.start_line, symbol.start_column));
+    symbols
+}
+
+/// Where a repository's index stands.
+#[derive(Clone, Debug, PartialEq, Eq, sqlx::FromRow)]
+pub struct SymbolIndexStatus {
+    /// `None` until the first index is built.
+    pub commit_oid: Option<String>,
+    pub branch: Option<String>,
+    pub indexed_at: Option<i64>,
+    pub requested_at: i64,
+}
+
+impl SymbolIndexStatus {
+    /// Whether a rebuild was asked for after the last one finished.
+    pub fn pending(&self) -> bool {
+        self.indexed_at.is_none_or(|at| at < self.requested_at)
+    }
+}
+
+pub async fn symbol_index_status(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> Result<Option<SymbolIndexStatus>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT commit_oid, branch, indexed_at, requested_at FROM repository_symbol_index \
+         WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .fetch_optional(pool)
+    .await
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SymbolIndexPayload {
+    repository_id: String,
+}
+
+/// Queues a rebuild of the repository's index.
+pub async fn enqueue_symbol_index(pool: &SqlitePool, repository_id: &str) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO repository_symbol_index (repository_id, requested_at) VALUES (?, ?) \
+         ON CONFLICT(repository_id) DO UPDATE SET requested_at = excluded.requested_at",
+    )
+    .bind(repository_id)
+    .bind(unix_now())
+    .execute(&mut *tx)
+    .await?;
+    let payload = SymbolIndexPayload {
+        repository_id: repository_id.to_string(),
+    };
+    enqueue_in(&mut *tx, JOB_KIND, &payload, MAX_ATTEMPTS).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// The repository's index status, queueing a first index if it never had one, such as a
+/// repository created before symbols existed.
+pub async fn ensure_symbol_index(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> anyhow::Result<SymbolIndexStatus> {
+    if let Some(status) = symbol_index_status(pool, repository_id).await? {
+        return Ok(status);
+    }
+    enqueue_symbol_index(pool, repository_id).await?;
+    symbol_index_status(pool, repository_id)
+        .await?
+        .ok_or_else(|| anyhow!("symbol index state for {} was not recorded", repository_id))
+}
+
+/// Symbols whose name contains `query`, ignoring case. Exact names come first, then
+/// prefixes, then shorter names.
+pub async fn search_symbols(
+    pool: &SqlitePool,
+    repository_id: &str,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<Symbol>, sqlx::Error> {
+    let query = query.trim();
+    let escaped = query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    sqlx::query_as(
+        "SELECT name, kind, language, file_path, start_line, start_column, end_line \
+         FROM repository_symbols \
+         WHERE repository_id = ? AND name LIKE ? ESCAPE '\\' \
+         ORDER BY name = ? DESC, name LIKE ? ESCAPE '\\' DESC, length(name), name, \
+         file_path, start_line \
+         LIMIT ?",
+    )
+    .bind(repository_id)
+    .bind(format!("%{}%", escaped))
+    .bind(query)
+    .bind(format!("{}%", escaped))
+    .bind(limit.min(MAX_RESULTS) as i64)
+    .fetch_all(pool)
+    .await
+}
+
+/// Every symbol in one file, in file order.
+pub async fn definitions_in_file(
+    pool: &SqlitePool,
+    repository_id: &str,
+    file_path: &str,
+) -> Result<Vec<Symbol>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT name, kind, language, file_path, start_line, start_column, end_line \
+         FROM repository_symbols WHERE repository_id = ? AND file_path = ? \
+         ORDER BY start_line, start_column",
+    )
+    .bind(repository_id)
+    .bind(file_path)
+    .fetch_all(pool)
+    .await
+}
+
+/// What a walk of the default branch found.
+struct Extraction {
+    commit_oid: String,
+    branch: Option<String>,
+    /// Every supported file and its blob.
+    files: HashMap<String, String>,
+    /// Symbols of the files whose blob changed.
+    parsed: Vec<(String, Vec<Symbol>)>,
+}
+
+/// Walks HEAD's tree and parses the supported files whose blob is not in `known`.
+/// `None` when HEAD has no commits yet.
+fn extract_repository(
+    dir: &Path,
+    known: &HashMap<String, String>,
+) -> anyhow::Result<Option<Extraction>> {
+    let repo = gix::open(dir)
+        .map_err(|err| anyhow!("failed to open repository at {}: {}", dir.display(), err))?;
+    let Ok(commit) = repo.head_commit() else {
+        return Ok(None);
+    };
+    let branch = repo.head_name()?.map(|name| name.shorten().to_string());
+    let tree = commit.tree()?;
+    let mut recorder = gix::traverse::tree::Recorder::default();
+    tree.traverse()
+        .breadthfirst(&mut recorder)
+        .map_err(|err| anyhow!("failed to walk tree {}: {}", tree.id, err))?;
+
+    let mut files = HashMap::new();
+    let mut parsed = Vec::new();
+    for entry in recorder.records {
+        if !entry.mode.is_blob() || files.len() == MAX_FILES {
+            continue;
+        }
+        let path = entry.filepath.to_string();
+        if SymbolLanguage::for_path(&path).is_none() {
+            continue;
+        }
+        let oid = entry.oid.to_string();
+        if known.get(&path) != Some(&oid) {
+            let blob = repo.find_object(entry.oid)?;
+            let symbols = match std::str::from_utf8(&blob.data) {
+                Ok(source) if source.len() <= MAX_FILE_SIZE => extract_symbols(&path, source),
+                _ => Vec::new(),
+            };
+            parsed.push((path.clone(), symbols));
+        }
+        files.insert(path, oid);
+    }
+    Ok(Some(Extraction {
+        commit_oid: commit.id.to_string(),
+        branch,
+        files,
+        parsed,
+    }))
+}
+
+/// Brings the index of one repository up to date with its default branch.
+pub async fn index_repository_symbols(
+    pool: &SqlitePool,
+    repository_id: &str,
+    dir: PathBuf,
+) -> anyhow::Result<()> {
+    let known: HashMap<String, String> = sqlx::query_as::<_, (String, String)>(
+        "SELECT file_path, blob_oid FROM repository_symbol_files WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+    let started_at = unix_now();
+    let known_files = known.clone();
+    let Some(extraction) =
+        task::spawn_blocking(move || extract_repository(&dir, &known_files)).await??
+    else {
+        return Ok(());
+    };
+
+    let mut tx = pool.begin().await?;
+    let removed = known
+        .keys()
+        .filter(|path| !extraction.files.contains_key(*path));
+    let changed = extraction.parsed.iter().map(|(path, _)| path);
+    for path in removed.chain(changed) {
+        for table in ["repository_symbols", "repository_symbol_files"] {
+            sqlx::query(&format!(
+                "DELETE FROM {table} WHERE repository_id = ? AND file_path = ?"
+            ))
+            .bind(repository_id)
+            .bind(path)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    for (path, symbols) in &extraction.parsed {
+        sqlx::query(
+            "INSERT INTO repository_symbol_files (repository_id, file_path, blob_oid) \
+             VALUES (?, ?, ?)",
+        )
+        .bind(repository_id)
+        .bind(path)
+        .bind(&extraction.files[path])
+        .execute(&mut *tx)
+        .await?;
+        for symbol in symbols {
+            sqlx::query(
+                "INSERT INTO repository_symbols (repository_id, file_path, name, kind, \
+                 language, start_line, start_column, end_line) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(repository_id)
+            .bind(path)
+            .bind(&symbol.name)
+            .bind(&symbol.kind)
+            .bind(&symbol.language)
+            .bind(symbol.start_line)
+            .bind(symbol.start_column)
+            .bind(symbol.end_line)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    // A request made while this run was walking the tree stays pending
+    sqlx::query(
+        "INSERT INTO repository_symbol_index \
+         (repository_id, commit_oid, branch, indexed_at, requested_at) VALUES (?, ?, ?, ?, ?) \
+         ON CONFLICT(repository_id) DO UPDATE SET commit_oid = excluded.commit_oid, \
+         branch = excluded.branch, indexed_at = excluded.indexed_at",
+    )
+    .bind(repository_id)
+    .bind(&extraction.commit_oid)
+    .bind(&extraction.branch)
+    .bind(started_at)
+    .bind(started_at)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Makes the next update of a repository's index parse every file again, for a rebuild.
+/// The old rows keep answering lookups until then.
+pub async fn reset_symbol_index(pool: &SqlitePool, repository_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE repository_symbol_files SET blob_oid = '' WHERE repository_id = ?")
+        .bind(repository_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Compares a repository's index with the default branch in `dir`.
+pub async fn check_symbol_index(
+    pool: &SqlitePool,
+    repository_id: &str,
+    dir: PathBuf,
+) -> anyhow::Result<Consistency> {
+    let Some(status) = symbol_index_status(pool, repository_id).await? else {
+        return Ok(Consistency::Missing);
+    };
+    // An update is already on its way
+    if status.pending() {
+        return Ok(Consistency::Match);
+    }
+    let known: HashMap<String, String> = sqlx::query_as::<_, (String, String)>(
+        "SELECT file_path, blob_oid FROM repository_symbol_files WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+    let known_files = known.clone();
+    let extraction = task::spawn_blocking(move || extract_repository(&dir, &known_files)).await??;
+    Ok(Consistency::compare(
+        status.commit_oid.as_deref(),
+        &known,
+        extraction.map(|e| (e.commit_oid, e.files, e.parsed.len())),
+    ))
+}
+
+/// Job handler for [`JOB_KIND`].
+pub struct SymbolIndexJob {
+    pool: SqlitePool,
+    storage: Arc<RepositoryStorage>,
+}
+
+impl SymbolIndexJob {
+    pub fn new(pool: SqlitePool, storage: Arc<RepositoryStorage>) -> Self {
+        Self { pool, storage }
+    }
+
+    /// The Git directory a repository's index is built from. `None` when the repository
+    /// was deleted or has nothing on disk yet.
+    pub async fn repository_dir(&self, repository_id: &str) -> anyhow::Result<Option<PathBuf>> {
+        let Some(record) = get_repository_by_id(&self.pool, repository_id).await? else {
+            return Ok(None);
+        };
+        let path = reconstruct_repository_path(&self.pool, &record).await?;
+        let segments: Vec<String> = path.split('/').map(str::to_string).collect();
+        let dir = self.storage.repository_path(&segments);
+        Ok(tokio::fs::try_exists(&dir).await?.then_some(dir))
+    }
+}
+
+#[async_trait]
+impl JobHandler for SymbolIndexJob {
+    async fn run(&self, job: &JobRecord) -> anyhow::Result<()> {
+        let payload: SymbolIndexPayload = job.payload()?;
+        // Deleted since the job was queued
+        let Some(dir) = self.repository_dir(&payload.repository_id).await? else {
+            return Ok(());
+        };
+        index_repository_symbols(&self.pool, &payload.repository_id, dir).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::{create_test_pool, git};
+
+    fn names(symbols: &[Symbol]) -> Vec<(&str, &str)> {
+        symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.kind.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn definitions_are_extracted_per_language() {
+        let rust = extract_symbols(
+            "src/lib.rs",
+            "pub struct Widget;\n\nimpl Widget {\n    pub fn spin(&self) {}\n}\n\n\
+             fn helper() {\n    spin();\n}\n",
+        );
+        assert_eq!(
+            names(&rust),
+            [
+                ("Widget", "class"),
+                ("spin", "method"),
+                ("helper", "function")
+            ]
+        );
+        assert_eq!((rust[1].start_line, rust[1].start_column), (4, 12));
+        assert_eq!(rust[2].end_line, 9);
+
+        let python = extract_symbols("app.py", "class App:\n    def run(self):\n        pass\n");
+        assert_eq!(names(&python), [("App", "class"), ("run", "function")]);
+
+        let typescript = extract_symbols(
+            "web/api.ts",
+            "export interface Client { get(): void }\nexport function connect(): Client {}\n",
+        );
+        assert!(names(&typescript).contains(&("Client", "interface")));
+        assert!(names(&typescript).contains(&("connect", "function")));
+
+        let go = extract_symbols("main.go", "package main\n\nfunc main() {}\n");
+        assert_eq!(names(&go), [("main", "function")]);
+
+        assert!(extract_symbols("README.md", "# fn main() {}").is_empty());
+    }
+
+    #[tokio::test]
+    async fn index_follows_the_default_branch() {
+        let pool = create_test_pool().await.unwrap();
+        let input = CreateRepositoryInput {
+            slug: "tools".into(),
+            group: None,
+        };
+        let id = create_repository_raw(&pool, input).await.unwrap().id;
+        let tmp = tempfile::tempdir().unwrap();
+        let work = tmp.path();
+        git(work, &["init", "-q", "-b", "main"]);
+        std::fs::write(work.join("lib.rs"), "fn parse_config() {}\nfn parse() {}\n").unwrap();
+        std::fs::write(work.join("util.py"), "def parser_helper():\n    pass\n").unwrap();
+        git(work, &["add", "."]);
+        git(work, &["commit", "-q", "-m", "Add parsers"]);
+        let git_dir = work.join(".git");
+
+        let status = ensure_symbol_index(&pool, &id).await.unwrap();
+        assert!(status.pending() && status.commit_oid.is_none());
+        index_repository_symbols(&pool, &id, git_dir.clone())
+            .await
+            .unwrap();
+        let status = symbol_index_status(&pool, &id).await.unwrap().unwrap();
+        assert_eq!(status.branch.as_deref(), Some("main"));
+        assert!(status.commit_oid.is_some());
+
+        let found = search_symbols(&pool, &id, "PARSE", 10).await.unwrap();
+        let found: Vec<_> = found.iter().map(|symbol| symbol.name.as_str()).collect();
+        assert_eq!(found, ["parse", "parse_config", "parser_helper"]);
+        assert_eq!(search_symbols(&pool, &id, "_", 10).await.unwrap().len(), 2);
+
+        std::fs::write(work.join("lib.rs"), "fn render() {}\n").unwrap();
+        git(work, &["rm", "-q", "util.py"]);
+        git(work, &["commit", "-q", "-am", "Replace parsers"]);
+        index_repository_symbols(&pool, &id, git_dir).await.unwrap();
+        assert!(
+            search_symbols(&pool, &id, "parse", 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let defined = definitions_in_file(&pool, &id, "lib.rs").await.unwrap();
+        assert_eq!(names(&defined), [("render", "function")]);
+        assert!(
+            !symbol_index_status(&pool, &id)
+                .await
+                .unwrap()
+                .unwrap()
+                .pending()
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Anonymous clone counts.
+//!
+//! Smart HTTP reports each clone through `GitHttpState::record_clone` (see
+//! `api::git`). Clones are summed per repository and UTC day; who cloned is not stored.
+
+use sqlx::SqlitePool;
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+pub async fn record_clone(
+    pool: &SqlitePool,
+    repository_id: &str,
+    now: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO repository_clone_counts (repository_id, day, clones) VALUES (?, ?, 1) \
+         ON CONFLICT(repository_id, day) DO UPDATE SET clones = clones + 1",
+    )
+    .bind(repository_id)
+    .bind(now.div_euclid(SECS_PER_DAY))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Clones of a repository over the last `days` days, today included.
+pub async fn clone_count(
+    pool: &SqlitePool,
+    repository_id: &str,
+    days: i64,
+    now: i64,
+) -> Result<i64, sqlx::Error> {
+    let today = now.div_euclid(SECS_PER_DAY);
+    sqlx::query_scalar(
+        "SELECT COALESCE(SUM(clones), 0) FROM repository_clone_counts \
+         WHERE repository_id = ? AND day > ?",
+    )
+    .bind(repository_id)
+    .bind(today - days)
+    .fetch_one(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::create_test_pool;
+
+    #[tokio::test]
+    async fn clones_are_counted_per_day() {
+        let pool = create_test_pool().await.unwrap();
+        let repository = create_repository_raw(
+            &pool,
+            CreateRepositoryInput {
+                slug: "app".into(),
+                group: None,
+            },
+        )
+        .await
+        .unwrap();
+        let now = 100 * SECS_PER_DAY + 60;
+
+        record_clone(&pool, &repository.id, now - 40 * SECS_PER_DAY)
+            .await
+            .unwrap();
+        record_clone(&pool, &repository.id, now - SECS_PER_DAY)
+            .await
+            .unwrap();
+        record_clone(&pool, &repository.id, now).await.unwrap();
+        record_clone(&pool, &repository.id, now).await.unwrap();
+
+        assert_eq!(clone_count(&pool, &repository.id, 1, now).await.unwrap(), 2);
+        assert_eq!(
+            clone_count(&pool, &repository.id, 30, now).await.unwrap(),
+            3
+        );
+        assert_eq!(clone_count(&pool, "missing", 30, now).await.unwrap(), 0);
+    }
+}
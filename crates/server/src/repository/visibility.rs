@@ -0,0 +1,196 @@
+//! Which repositories a viewer may read.
+//!
+//! Results that span repositories, such as group or instance issue roll-ups, must be
+//! limited to this set. A repository's visibility setting (see [`crate::group::settings`])
+//! falls back to the instance's default visibility (see [`crate::instance`]), which is
+//! public unless an administrator changes it. Only their owner sees private repositories
+//! here. Further access rules belong here too, so that roll-ups pick them up
+//! without changes.
+//!
+//! A request made with a share link (see [`super::share_links`]) may also read the one
+//! repository the link is for. Roll-ups do not include it.
+
+use sqlx::SqlitePool;
+
+use super::share_links::current_share_grant;
+
+/// Excludes private repositories the viewer does not own: a condition on
+/// `repositories.id` that binds the viewer's DID.
+pub(crate) const HIDDEN: &str = "id NOT IN ( \
+     SELECT repositories.id FROM repositories \
+     LEFT JOIN repository_settings ON repository_settings.repository_id = repositories.id \
+     LEFT JOIN repository_owners ON repository_owners.repository_id = repositories.id \
+     WHERE COALESCE(repository_settings.visibility, \
+         (SELECT default_visibility FROM instance_settings WHERE id = 1), 'public') = 'private' \
+     AND (repository_owners.owner_did IS NULL OR repository_owners.owner_did IS NOT ?) \
+ )";
+
+/// Ids of the repositories `viewer_did` may read, inside `group_id` and its subgroups,
+/// or across the instance when no group is given.
+pub async fn visible_repository_ids(
+    pool: &SqlitePool,
+    viewer_did: Option<&str>,
+    group_id: Option<&str>,
+) -> Result<Vec<String>, sqlx::Error> {
+    match group_id {
+        Some(group_id) => {
+            sqlx::query_scalar(&format!(
+                "WITH RECURSIVE subtree(id) AS ( \
+                     SELECT id FROM groups WHERE id = ? \
+                     UNION ALL \
+                     SELECT groups.id FROM groups JOIN subtree ON groups.parent = subtree.id \
+                 ) \
+                 SELECT id FROM repositories WHERE \"group\" IN (SELECT id FROM subtree) \
+                 AND {HIDDEN} ORDER BY id"
+            ))
+            .bind(group_id)
+            .bind(viewer_did)
+            .fetch_all(pool)
+            .await
+        }
+        None => {
+            sqlx::query_scalar(&format!(
+                "SELECT id FROM repositories WHERE {HIDDEN} ORDER BY id"
+            ))
+            .bind(viewer_did)
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+/// Whether `viewer_did`, or the share link of the current request, may read the repository.
+pub async fn can_view_repository(
+    pool: &SqlitePool,
+    viewer_did: Option<&str>,
+    repository_id: &str,
+) -> Result<bool, sqlx::Error> {
+    if current_share_grant().is_some_and(|grant| grant.repository_id == repository_id) {
+        return Ok(true);
+    }
+    sqlx::query_scalar(&format!(
+        "SELECT EXISTS (SELECT 1 FROM repositories WHERE id = ? AND {HIDDEN})"
+    ))
+    .bind(repository_id)
+    .bind(viewer_did)
+    .fetch_one(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::mutations::{CreateGroupInput, create_group_raw};
+    use crate::group::settings::{Visibility, set_repository_settings};
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::create_test_pool;
+
+    #[tokio::test]
+    async fn group_scope_includes_subgroups_only() {
+        let pool = create_test_pool().await.unwrap();
+        let team = create_group_raw(
+            &pool,
+            CreateGroupInput {
+                slug: "team".into(),
+                parent: None,
+            },
+        )
+        .await
+        .unwrap();
+        let infra = create_group_raw(
+            &pool,
+            CreateGroupInput {
+                slug: "infra".into(),
+                parent: Some(team.id.clone()),
+            },
+        )
+        .await
+        .unwrap();
+        let mut ids = Vec::new();
+        for (slug, group) in [
+            ("app", Some(&team.id)),
+            ("tools", Some(&infra.id)),
+            ("scratch", None),
+        ] {
+            let input = CreateRepositoryInput {
+                slug: slug.into(),
+                group: group.cloned(),
+            };
+            ids.push(create_repository_raw(&pool, input).await.unwrap().id);
+        }
+        let [app, tools, scratch] = <[String; 3]>::try_from(ids).unwrap();
+
+        let mut in_team = visible_repository_ids(&pool, None, Some(&team.id))
+            .await
+            .unwrap();
+        in_team.sort();
+        let mut expected = vec![app, tools.clone()];
+        expected.sort();
+        assert_eq!(in_team, expected);
+        assert_eq!(
+            visible_repository_ids(&pool, None, Some(&infra.id))
+                .await
+                .unwrap(),
+            vec![tools]
+        );
+        let everything = visible_repository_ids(&pool, Some("did:plc:alice"), None)
+            .await
+            .unwrap();
+        assert_eq!(everything.len(), 3);
+        assert!(everything.contains(&scratch));
+    }
+
+    #[tokio::test]
+    async fn private_repositories_are_visible_to_their_owner_only() {
+        let pool = create_test_pool().await.unwrap();
+        let mut ids = Vec::new();
+        for slug in ["open", "secret", "orphan"] {
+            let input = CreateRepositoryInput {
+                slug: slug.into(),
+                group: None,
+            };
+            ids.push(create_repository_raw(&pool, input).await.unwrap().id);
+        }
+        let [open, secret, orphan] = <[String; 3]>::try_from(ids).unwrap();
+        sqlx::query("INSERT INTO repository_owners (repository_id, owner_did) VALUES (?, ?)")
+            .bind(&secret)
+            .bind("did:plc:alice")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for id in [&secret, &orphan] {
+            set_repository_settings(&pool, id, None, Some(Visibility::Private))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            visible_repository_ids(&pool, None, None).await.unwrap(),
+            vec![open.clone()]
+        );
+        assert_eq!(
+            visible_repository_ids(&pool, Some("did:plc:bob"), None)
+                .await
+                .unwrap(),
+            vec![open.clone()]
+        );
+        let mut alice = visible_repository_ids(&pool, Some("did:plc:alice"), None)
+            .await
+            .unwrap();
+        alice.sort();
+        let mut expected = vec![open, secret.clone()];
+        expected.sort();
+        assert_eq!(alice, expected);
+
+        assert!(
+            can_view_repository(&pool, Some("did:plc:alice"), &secret)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !can_view_repository(&pool, Some("did:plc:bob"), &secret)
+                .await
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Upstream tag notifications for linked remote repositories.
+//!
+//! Users opt in per repository, optionally with a semver range. Each remote sync diffs
+//! `refs/tags` before and after the fetch. Every new tag that matches a watch becomes a
+//! `remoteTagAdded` notification for that subscriber.
+
+use semver::{Version, VersionReq};
+use sqlx::SqlitePool;
+
+use crate::notifications::db::insert_notification;
+use crate::user::db::unix_now;
+
+pub const NOTIFICATION_KIND: &str = "remoteTagAdded";
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct TagWatchRecord {
+    pub subscriber_did: String,
+    pub semver_range: Option<String>,
+    pub created_at: i64,
+}
+
+impl TagWatchRecord {
+    /// Whether `tag` should notify this subscriber.
+    pub fn matches(&self, tag: &str) -> bool {
+        let Some(range) = &self.semver_range else {
+            return true;
+        };
+        let (Ok(req), Some(version)) = (VersionReq::parse(range), parse_tag_version(tag)) else {
+            return false;
+        };
+        req.matches(&version)
+    }
+}
+
+/// Parses `v1.2.3`, `1.2.3` or `release-1.2.3`-style tags as semver.
+pub fn parse_tag_version(tag: &str) -> Option<Version> {
+    let start = tag.find(|c: char| c.is_ascii_digit())?;
+    let prefix = &tag[..start];
+    if !(prefix.is_empty() || prefix == "v" || prefix.ends_with('-') || prefix.ends_with('/')) {
+        return None;
+    }
+    Version::parse(&tag[start..]).ok()
+}
+
+pub async fn watch_remote_tags(
+    pool: &SqlitePool,
+    repository_id: &str,
+    subscriber_did: &str,
+    semver_range: Option<String>,
+) -> anyhow::Result<TagWatchRecord> {
+    let semver_range = semver_range
+        .map(|range| range.trim().to_string())
+        .filter(|range| !range.is_empty());
+    if let Some(range) = &semver_range {
+        VersionReq::parse(range)
+            .map_err(|err| anyhow::anyhow!("invalid semver range `{}`: {}", range, err))?;
+    }
+
+    sqlx::query(
+        "INSERT INTO remote_tag_watches (repository_id, subscriber_did, semver_range, created_at) \
+         VALUES (?, ?, ?, ?) \
+         ON CONFLICT(repository_id, subscriber_did) DO UPDATE SET semver_range = excluded.semver_range",
+    )
+    .bind(repository_id)
+    .bind(subscriber_did)
+    .bind(&semver_range)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+
+    fetch_tag_watch(pool, repository_id, subscriber_did)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("tag watch disappeared after saving"))
+}
+
+pub async fn unwatch_remote_tags(
+    pool: &SqlitePool,
+    repository_id: &str,
+    subscriber_did: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM remote_tag_watches WHERE repository_id = ? AND subscriber_did = ?",
+    )
+    .bind(repository_id)
+    .bind(subscriber_did)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn fetch_tag_watch(
+    pool: &SqlitePool,
+    repository_id: &str,
+    subscriber_did: &str,
+) -> Result<Option<TagWatchRecord>, sqlx::Error> {
+    sqlx::query_as::<_, TagWatchRecord>(
+        "SELECT subscriber_did, semver_range, created_at FROM remote_tag_watches \
+         WHERE repository_id = ? AND subscriber_did = ?",
+    )
+    .bind(repository_id)
+    .bind(subscriber_did)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Notifies every watcher whose range matches one of `new_tags`. Returns notifications sent.
+pub async fn notify_new_tags(
+    pool: &SqlitePool,
+    repository_id: &str,
+    repository_slug: &str,
+    new_tags: &[String],
+) -> Result<usize, sqlx::Error> {
+    if new_tags.is_empty() {
+        return Ok(0);
+    }
+    let watches = sqlx::query_as::<_, TagWatchRecord>(
+        "SELECT subscriber_did, semver_range, created_at FROM remote_tag_watches \
+         WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .fetch_all(pool)
+    .await?;
+
+    let now = unix_now();
+    let mut sent = 0;
+    for watch in &watches {
+        for tag in new_tags.iter().filter(|tag| watch.matches(tag)) {
+            let title = format!("{} released {}", repository_slug, tag);
+            let url = format!("/{}", repository_slug);
+            insert_notification(
+                pool,
+                &watch.subscriber_did,
+                NOTIFICATION_KIND,
+                &title,
+                Some(&url),
+                now,
+            )
+            .await?;
+            sent += 1;
+        }
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    #[test]
+    fn tags_parse_as_semver() {
+        assert_eq!(parse_tag_version("v1.2.3"), Some(Version::new(1, 2, 3)));
+        assert_eq!(
+            parse_tag_version("release-2.0.0"),
+            Some(Version::new(2, 0, 0))
+        );
+        assert!(parse_tag_version("nightly").is_none());
+        assert!(parse_tag_version("build42").is_none());
+    }
+
+    #[tokio::test]
+    async fn only_matching_watchers_are_notified() {
+        let pool = create_test_pool().await.unwrap();
+        sqlx::query("INSERT INTO repositories (id, slug, remote_url) VALUES ('r1', 'serde', 'https://example.com/serde.git')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        watch_remote_tags(&pool, "r1", "did:plc:alice", Some("^1.2".into()))
+            .await
+            .unwrap();
+        watch_remote_tags(&pool, "r1", "did:plc:bob", None)
+            .await
+            .unwrap();
+        assert!(
+            watch_remote_tags(&pool, "r1", "did:plc:carol", Some("not a range".into()))
+                .await
+                .is_err()
+        );
+
+        let tags = vec![
+            "v1.3.0".to_string(),
+            "v2.0.0".to_string(),
+            "nightly".to_string(),
+        ];
+        let sent = notify_new_tags(&pool, "r1", "serde", &tags).await.unwrap();
+        // alice: v1.3.0 only; bob: all three
+        assert_eq!(sent, 4);
+
+        assert!(
+            unwatch_remote_tags(&pool, "r1", "did:plc:bob")
+                .await
+                .unwrap()
+        );
+        assert!(
+            fetch_tag_watch(&pool, "r1", "did:plc:bob")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -1,8 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use anyhow::{Context, Result, anyhow};
 use async_trait::async_trait;
 use bytes::Bytes;
+use git_http::policy::{PolicyReport, PolicyRules, PolicyViolation, RefUpdate, RuleViolation};
 use graphql_parser::query::{
     Definition, Field, FragmentDefinition, OperationDefinition, Selection, SelectionSet,
     TypeCondition, Value as AstValue,
@@ -11,37 +12,206 @@ use hive_router_plan_executor::executors::common::{HttpExecutionRequest, Subgrap
 use serde_json::{Map, Value as JsonValue};
 use sqlx::SqlitePool;
 
+use crate::announcements::{
+    Announcement, MaintenanceMode, NewAnnouncement, Severity, active_announcements,
+    create_announcement, delete_announcement, list_announcements, maintenance_mode,
+    set_maintenance_mode,
+};
+use crate::api::uploads::lookup_upload;
+use crate::audit::{
+    AUDIT_CURSOR_KIND, AuditEntry, AuditExport, AuditFilter, ExportFormat, count_audit_log,
+    list_audit_exports, page_audit_log, request_audit_export, retention_by_category,
+};
+use crate::auth::User;
+use crate::auth::session::Session;
+use crate::auth::viewer::{current_viewer, require_admin, require_session, require_viewer};
+use crate::compliance::{ArchivalRecord, ExportFilter, export_archive};
+use crate::events::{Envelope, Event, publish as publish_event};
+use crate::extensions::ExtensionReload;
+use crate::extensions::graphql_access::{QueryAuditRecord, query_audit};
+use crate::extensions::integrity::{CacheCheck, cache_status};
+use crate::extensions::metrics::{FieldStats, slowest_fields};
+use crate::extensions::scheduler::{TaskRun, TaskSchedule, list_runs, list_schedules};
+use crate::extensions::upgrade::{UpgradeRecord, list_upgrades};
+use crate::feature_flags::{
+    FeatureFlag, delete_flag, evaluate_flags, list_flags, set_flag, set_override,
+};
+use crate::federation::{
+    did::DidResolver,
+    links::ProfileLink,
+    references::{FederatedIssueReference, issue_references},
+};
+use crate::graphql::global_id;
+use crate::graphql::pagination::{Page, PageRequest, encode_cursor};
 use crate::group::mutations::{CreateGroupInput, create_group_raw};
 use crate::group::{
     models::GroupRecord,
-    queries::{get_all_groups_raw, get_group_parent, get_group_raw, repositories_for_group},
+    queries::{
+        GROUP_CURSOR_KIND, count_groups, get_all_groups_raw, get_group_parent, get_group_raw,
+        page_groups, repositories_for_group,
+    },
+    settings::{
+        EffectiveSettings, GroupSettings, Label, Provenance, Source, Visibility,
+        clear_inherited_branch_protection, effective_settings, load_group_settings,
+        save_group_settings, set_repository_settings,
+    },
+};
+use crate::instance::{
+    InstanceSettings, InstanceSettingsUpdate, complete_setup, instance_settings,
+    update_instance_settings,
+};
+use crate::jobs::{
+    JOB_CURSOR_KIND, JobFilter, JobQueue, JobRecord,
+    queue::{count_jobs, page_jobs},
+};
+use crate::metering::{GroupUsage, ResourceUsage, UsageExport, list_usage_exports};
+use crate::moderation::{
+    Content, ContentKind, ModerationItem, ModerationStatus, approve_item, list_items, remove_item,
+    screen,
+};
+use crate::notifications::{
+    DigestFrequency, PushSubscription,
+    db::{
+        delete_push_subscription, get_digest_frequency, push_subscriptions_for,
+        set_digest_frequency,
+    },
+    push,
 };
 use crate::repository::{
+    cherry_pick::{CommitOperation, CommitOperationOutcome, apply_commit_operation},
+    clone::{RemoteCloneRecord, fetch_remote_clone},
+    closing::{
+        IssueClosingSettings, load_settings as load_issue_closing,
+        save_settings as save_issue_closing,
+    },
+    commit_search::{COMMIT_CURSOR_KIND, CommitMatch, CommitSearch, search_commits},
+    compare::{
+        Comparison, DEFAULT_LIMIT as DEFAULT_COMPARE_LIMIT, compare_across_forks, compare_refs,
+    },
+    contributor_stats::{
+        CodeFrequencyWeek, ContributorStat, ContributorStatsStatus, code_frequency, contributors,
+        ensure_contributor_stats,
+    },
+    descriptions::{
+        RepositoryDescription, list_repository_descriptions, select_description,
+        set_repository_description,
+    },
+    diff::{ChangeDetail, RepositoryDiff, diff_revisions},
+    discovery::{
+        DiscoveredRepository, DiscoverySort, SearchScore, discovery_stats, find_repositories,
+        score_repository,
+    },
+    finder::{DEFAULT_RESULTS, FileMatch, find_files_raw},
+    graph::{CommitGraph, DEFAULT_LIMIT as DEFAULT_GRAPH_LIMIT, commit_graph},
+    import::{ImportInput, ImportSource, import_repository, resolve_import_path},
+    integrity::{IntegrityReport, RepairReport, check_repository, repair_repository},
+    language::parse_language_preferences,
+    merge::{MergeConflict, MergePreview, merge_preview_raw},
+    metadata::{ProjectMetadata, detect_project_metadata},
+    mirror_hook::{disable_mirror_webhook, rotate_mirror_webhook_secret},
     models::{
-        RepositoryBranch, RepositoryEntriesPayload, RepositoryEntryKind, RepositoryEntryNode,
-        RepositoryFilePayload, RepositoryRecord, RepositorySummary,
+        CommitChanges, CommitSignature, FileBlame, RepositoryBranch, RepositoryCommit,
+        RepositoryEntriesPayload, RepositoryEntryKind, RepositoryEntryNode, RepositoryFilePayload,
+        RepositoryRecord, RepositorySummary,
+    },
+    mutations::{
+        CreateRepositoryInput, create_repository_raw, delete_repository_raw,
+        link_remote_repository_raw, move_repository_raw,
+    },
+    policy::{
+        check_repository_policy, load_policy, parse_policy, policy_source, save_policy,
+        validate_branch_name, validate_commit_message,
+    },
+    projects::{
+        Project, list_repository_projects, load_project_roots, save_project_roots, scope_tree_path,
     },
-    mutations::{CreateRepositoryInput, create_repository_raw, link_remote_repository_raw},
     queries::{
-        browse_repository_raw, get_all_repositories_raw, get_repository_raw,
-        list_repository_branches_raw, read_repository_file_raw, get_repository_readme_html,
+        DEFAULT_COMMIT_LIMIT, REPOSITORY_CURSOR_KIND, blame_repository_file_raw,
+        browse_repository_raw, count_repositories, get_all_repositories_raw, get_commit_raw,
+        get_repository_by_id, get_repository_raw, get_repository_readme_html, list_commits_raw,
+        list_repository_branches_raw, page_repositories, read_repository_file_raw,
+        reconstruct_repository_path,
+    },
+    resolver::PathResolver,
+    secrets::{
+        RepositorySecret, SecretAccessRecord, delete_repository_secret, list_repository_secrets,
+        secret_audit, server_key, set_repository_secret,
+    },
+    share_links::{
+        ShareLink, ShareLinkEvent, create_share_link, fetch_share_link, list_share_links,
+        revoke_share_link, share_link_audit,
     },
     storage::RepositoryStorage,
+    suggestions::{SuggestionOutcome, apply_suggestion, fetch_suggestion},
+    symbols::{
+        DEFAULT_RESULTS as DEFAULT_SYMBOL_RESULTS, Symbol, SymbolIndexStatus, definitions_in_file,
+        ensure_symbol_index, search_symbols,
+    },
+    visibility::{can_view_repository, visible_repository_ids},
+    watch::{TagWatchRecord, fetch_tag_watch, unwatch_remote_tags, watch_remote_tags},
+};
+use crate::search::{
+    code::{
+        CodeLine, CodeMatch, DEFAULT_RESULTS as DEFAULT_CODE_RESULTS, ensure_code_indexes,
+        search_code,
+    },
+    db::{delete_saved_search, list_saved_searches},
+    maintenance::{SearchIndexScope, queue_rebuild},
+    models::{SavedSearchRecord, SearchScope},
+    saved::save_search,
+};
+use crate::user::{
+    db::{
+        fetch_repository_owner, fetch_user_by_did, fetch_user_by_handle, recent_activity,
+        record_activity, set_repository_owner, set_star, unix_now,
+    },
+    export::{AccountExport, download_url, list_account_exports, request_account_export},
+    models::{ActivityRecord, UserContributions, UserRecord},
+    queries::{contributions_for, repositories_owned_by, repositories_starred_by},
+    security::{
+        AccessToken, SshKey, add_ssh_key, create_access_token, list_access_tokens, list_ssh_keys,
+        notify_security_change, remove_ssh_key, revoke_access_token,
+    },
+};
+use crate::webhooks::{
+    DELIVERY_CURSOR_KIND, DeliveryFilter, DeliveryStatus, WebhookDelivery, WebhookInput,
+    WebhookRecord, create_webhook,
+    db::{
+        count_deliveries, delete_webhook, fetch_delivery, fetch_webhook, list_webhooks,
+        page_deliveries,
+    },
+    redeliver, test_webhook, update_webhook,
 };
 
+use super::subscriptions::SubscriptionField;
+use super::usage::{FieldUsage, recorder as usage_recorder, unused_types};
 use super::{graphql_error_body, sonic_to_serde};
 
 pub(crate) struct CoreSubgraphExecutor {
     pool: SqlitePool,
     storage: RepositoryStorage,
+    resolver: PathResolver,
+    /// Extension types that implement `Node`
+    extension_nodes: HashSet<String>,
 }
 
-type Vars = HashMap<String, JsonValue>;
+pub(super) type Vars = HashMap<String, JsonValue>;
 type FragmentMap<'a> = HashMap<&'a str, &'a FragmentDefinition<'a, String>>;
 
 impl CoreSubgraphExecutor {
-    pub fn new(pool: SqlitePool, storage: RepositoryStorage) -> Self {
-        Self { pool, storage }
+    pub fn new(pool: SqlitePool, storage: RepositoryStorage, resolver: PathResolver) -> Self {
+        Self {
+            pool,
+            storage,
+            resolver,
+            extension_nodes: HashSet::new(),
+        }
+    }
+
+    /// Lets `node(id:)` return references to these extension types.
+    pub fn with_extension_nodes(mut self, types: HashSet<String>) -> Self {
+        self.extension_nodes = types;
+        self
     }
 
     pub async fn execute_operation<'a>(
@@ -82,8 +252,9 @@ impl CoreSubgraphExecutor {
                     .await?;
                 JsonValue::Object(map)
             }
+            // See `router::subscriptions`
             OperationDefinition::Subscription(_) => {
-                return Err(anyhow!("subscriptions are not supported"));
+                return Err(anyhow!("subscriptions are only served over WebSocket"));
             }
             OperationDefinition::SelectionSet(selection_set) => {
                 let map = self
@@ -143,6 +314,120 @@ impl CoreSubgraphExecutor {
         Ok(out)
     }
 
+    /// The root field of a subscription operation and the repository it follows. Fails
+    /// unless the viewer may read the repository.
+    pub(super) async fn subscription_target(
+        &self,
+        query: &str,
+        operation_name: Option<&str>,
+        variables: &Vars,
+    ) -> Result<(SubscriptionField, String)> {
+        let document = graphql_parser::parse_query::<String>(query)
+            .context("failed to parse GraphQL document")?;
+        let fragments = collect_fragment_definitions(&document);
+        let field = self.subscription_root(&document, operation_name, &fragments)?;
+        let kind = SubscriptionField::parse(&field.name)
+            .ok_or_else(|| anyhow!("unknown subscription field `{}`", field.name))?;
+        let path = self
+            .get_required_argument(field, "path", variables)?
+            .as_str()
+            .ok_or_else(|| anyhow!("path argument must be a string"))?
+            .to_string();
+        let viewer = current_viewer();
+        let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+        match get_repository_raw(&self.resolver, viewer_did, path.clone()).await? {
+            Some(record) => Ok((kind, record.id)),
+            None => Err(anyhow!("repository `{}` not found", path)),
+        }
+    }
+
+    /// Projects `event` onto the selection of the subscription's root field. `None` once
+    /// the viewer may no longer read the repository.
+    pub(super) async fn resolve_subscription_event(
+        &self,
+        query: &str,
+        operation_name: Option<&str>,
+        variables: &Vars,
+        event: &Envelope,
+    ) -> Result<Option<JsonValue>> {
+        let document = graphql_parser::parse_query::<String>(query)
+            .context("failed to parse GraphQL document")?;
+        let fragments = collect_fragment_definitions(&document);
+        let field = self.subscription_root(&document, operation_name, &fragments)?;
+        let kind = SubscriptionField::parse(&field.name)
+            .ok_or_else(|| anyhow!("unknown subscription field `{}`", field.name))?;
+        let repository_id = event.repository_id.as_deref().unwrap_or_default();
+        let record = if event.kind == "repository.deleted" {
+            None
+        } else {
+            let viewer = current_viewer();
+            let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+            if !can_view_repository(&self.pool, viewer_did, repository_id).await? {
+                return Ok(None);
+            }
+            get_repository_by_id(&self.pool, repository_id).await?
+        };
+
+        let type_name = kind.type_name();
+        let mut map = Map::new();
+        for selected in selection_fields(&field.selection_set, type_name, &fragments)? {
+            let value = match selected.name.as_str() {
+                "__typename" => JsonValue::String(type_name.to_string()),
+                "kind" => JsonValue::String(event.kind.clone()),
+                "actor" => event
+                    .actor
+                    .clone()
+                    .map(JsonValue::String)
+                    .unwrap_or(JsonValue::Null),
+                "occurredAt" => JsonValue::from(event.time),
+                "repository" => match &record {
+                    Some(record) => {
+                        self.project_repository_node(
+                            record,
+                            &selected.selection_set,
+                            &fragments,
+                            variables,
+                            &[],
+                        )
+                        .await?
+                    }
+                    None => JsonValue::Null,
+                },
+                // `issue.created` carries the issue as the issues extension returns it
+                "number" | "title" | "status" => event
+                    .data
+                    .get(selected.name.as_str())
+                    .cloned()
+                    .unwrap_or(JsonValue::Null),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(selected), value);
+        }
+
+        let mut data = Map::new();
+        data.insert(response_key(field), JsonValue::Object(map));
+        let mut response = Map::new();
+        response.insert("data".to_string(), JsonValue::Object(data));
+        Ok(Some(JsonValue::Object(response)))
+    }
+
+    fn subscription_root<'a>(
+        &self,
+        document: &'a graphql_parser::query::Document<'a, String>,
+        operation_name: Option<&'a str>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<&'a Field<'a, String>> {
+        let Some(OperationDefinition::Subscription(subscription)) =
+            self.find_operation(document, operation_name)
+        else {
+            return Err(anyhow!("expected a subscription operation"));
+        };
+        match selection_fields(&subscription.selection_set, "Subscription", fragments)?.as_slice() {
+            [field] => Ok(field),
+            _ => Err(anyhow!("a subscription must select exactly one root field")),
+        }
+    }
+
     async fn resolve_query_selection_set<'a>(
         &self,
         selection_set: &'a SelectionSet<'a, String>,
@@ -189,6 +474,218 @@ impl CoreSubgraphExecutor {
     ) -> Result<JsonValue> {
         match field.name.as_str() {
             "__typename" => Ok(JsonValue::String("Query".to_string())),
+            "groups" => {
+                let request = self.page_request(field, variables, GROUP_CURSOR_KIND)?;
+                let page = page_groups(&self.pool, &request).await?;
+                let fields = selection_fields(&field.selection_set, "GroupConnection", fragments)?;
+                let mut map = Map::new();
+                for selected in fields {
+                    let value = match selected.name.as_str() {
+                        "__typename" => JsonValue::String("GroupConnection".to_string()),
+                        "totalCount" => JsonValue::from(count_groups(&self.pool).await?),
+                        "pageInfo" => project_page_info(
+                            &page,
+                            |record| encode_cursor(GROUP_CURSOR_KIND, &record.slug, &record.id),
+                            &selected.selection_set,
+                            fragments,
+                        )?,
+                        "nodes" => {
+                            let mut items = Vec::with_capacity(page.items.len());
+                            for record in &page.items {
+                                items.push(
+                                    self.project_group_node(
+                                        record,
+                                        &selected.selection_set,
+                                        fragments,
+                                    )
+                                    .await?,
+                                );
+                            }
+                            JsonValue::Array(items)
+                        }
+                        "edges" => {
+                            let edge_fields =
+                                selection_fields(&selected.selection_set, "GroupEdge", fragments)?;
+                            let mut items = Vec::with_capacity(page.items.len());
+                            for record in &page.items {
+                                let mut edge = Map::new();
+                                for edge_field in &edge_fields {
+                                    let value = match edge_field.name.as_str() {
+                                        "__typename" => JsonValue::String("GroupEdge".to_string()),
+                                        "cursor" => JsonValue::String(encode_cursor(
+                                            GROUP_CURSOR_KIND,
+                                            &record.slug,
+                                            &record.id,
+                                        )),
+                                        "node" => {
+                                            self.project_group_node(
+                                                record,
+                                                &edge_field.selection_set,
+                                                fragments,
+                                            )
+                                            .await?
+                                        }
+                                        _ => JsonValue::Null,
+                                    };
+                                    edge.insert(response_key(edge_field), value);
+                                }
+                                items.push(JsonValue::Object(edge));
+                            }
+                            JsonValue::Array(items)
+                        }
+                        _ => JsonValue::Null,
+                    };
+                    map.insert(response_key(selected), value);
+                }
+                Ok(JsonValue::Object(map))
+            }
+            "repositories" => {
+                let request = self.page_request(field, variables, REPOSITORY_CURSOR_KIND)?;
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                let page = page_repositories(&self.pool, viewer_did, &request).await?;
+                let fields =
+                    selection_fields(&field.selection_set, "RepositoryConnection", fragments)?;
+                let mut map = Map::new();
+                for selected in fields {
+                    let value = match selected.name.as_str() {
+                        "__typename" => JsonValue::String("RepositoryConnection".to_string()),
+                        "totalCount" => {
+                            JsonValue::from(count_repositories(&self.pool, viewer_did).await?)
+                        }
+                        "pageInfo" => project_page_info(
+                            &page,
+                            |record| {
+                                encode_cursor(REPOSITORY_CURSOR_KIND, &record.slug, &record.id)
+                            },
+                            &selected.selection_set,
+                            fragments,
+                        )?,
+                        "nodes" => {
+                            let mut items = Vec::with_capacity(page.items.len());
+                            for record in &page.items {
+                                items.push(
+                                    self.project_repository_node(
+                                        record,
+                                        &selected.selection_set,
+                                        fragments,
+                                        variables,
+                                        &[],
+                                    )
+                                    .await?,
+                                );
+                            }
+                            JsonValue::Array(items)
+                        }
+                        "edges" => {
+                            let edge_fields = selection_fields(
+                                &selected.selection_set,
+                                "RepositoryEdge",
+                                fragments,
+                            )?;
+                            let mut items = Vec::with_capacity(page.items.len());
+                            for record in &page.items {
+                                let mut edge = Map::new();
+                                for edge_field in &edge_fields {
+                                    let value = match edge_field.name.as_str() {
+                                        "__typename" => {
+                                            JsonValue::String("RepositoryEdge".to_string())
+                                        }
+                                        "cursor" => JsonValue::String(encode_cursor(
+                                            REPOSITORY_CURSOR_KIND,
+                                            &record.slug,
+                                            &record.id,
+                                        )),
+                                        "node" => {
+                                            self.project_repository_node(
+                                                record,
+                                                &edge_field.selection_set,
+                                                fragments,
+                                                variables,
+                                                &[],
+                                            )
+                                            .await?
+                                        }
+                                        _ => JsonValue::Null,
+                                    };
+                                    edge.insert(response_key(edge_field), value);
+                                }
+                                items.push(JsonValue::Object(edge));
+                            }
+                            JsonValue::Array(items)
+                        }
+                        _ => JsonValue::Null,
+                    };
+                    map.insert(response_key(selected), value);
+                }
+                Ok(JsonValue::Object(map))
+            }
+            "findRepositories" => {
+                let query = self
+                    .get_optional_argument(field, "query", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let sort = match self
+                    .get_optional_argument(field, "sort", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()))
+                {
+                    Some(sort) => DiscoverySort::parse(&sort)?,
+                    None if query.as_deref().is_some_and(|q| !q.trim().is_empty()) => {
+                        DiscoverySort::Relevance
+                    }
+                    None => DiscoverySort::Stars,
+                };
+                let request = self.page_request(field, variables, sort.cursor_kind())?;
+                let viewer = current_viewer();
+                let page = find_repositories(
+                    &self.pool,
+                    viewer.as_ref().map(|viewer| viewer.did.as_str()),
+                    query.as_deref(),
+                    sort,
+                    &request,
+                )
+                .await?;
+                self.project_repository_search(
+                    &page,
+                    sort,
+                    &field.selection_set,
+                    fragments,
+                    variables,
+                )
+                .await
+            }
+            "repositorySearchScore" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let query = self
+                    .get_required_argument(field, "query", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("query argument must be a string"))?
+                    .to_string();
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                let Some(record) = get_repository_raw(&self.resolver, viewer_did, path).await?
+                else {
+                    return Ok(JsonValue::Null);
+                };
+                match score_repository(&self.pool, &record.id, &query).await? {
+                    Some(score) => {
+                        self.project_search_score(&score, &field.selection_set, fragments)
+                    }
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "node" => {
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))?
+                    .to_string();
+                self.resolve_node(&id, &field.selection_set, fragments, variables)
+                    .await
+            }
             "getAllGroups" => {
                 let records = get_all_groups_raw(&self.pool).await?;
                 let mut items = Vec::with_capacity(records.len());
@@ -206,7 +703,7 @@ impl CoreSubgraphExecutor {
                     .as_str()
                     .ok_or_else(|| anyhow!("path argument must be a string"))?
                     .to_string();
-                let record = get_group_raw(&self.pool, path).await?;
+                let record = get_group_raw(&self.resolver, path).await?;
                 match record {
                     Some(record) => {
                         self.project_group_node(&record, &field.selection_set, fragments)
@@ -216,12 +713,20 @@ impl CoreSubgraphExecutor {
                 }
             }
             "getAllRepositories" => {
-                let records = get_all_repositories_raw(&self.pool).await?;
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                let records = get_all_repositories_raw(&self.pool, viewer_did).await?;
                 let mut items = Vec::with_capacity(records.len());
                 for record in records {
                     items.push(
-                        self.project_repository_node(&record, &field.selection_set, fragments, variables)
-                            .await?,
+                        self.project_repository_node(
+                            &record,
+                            &field.selection_set,
+                            fragments,
+                            variables,
+                            &[],
+                        )
+                        .await?,
                     );
                 }
                 Ok(JsonValue::Array(items))
@@ -232,11 +737,24 @@ impl CoreSubgraphExecutor {
                     .as_str()
                     .ok_or_else(|| anyhow!("path argument must be a string"))?
                     .to_string();
-                let record = get_repository_raw(&self.pool, path).await?;
+                // Clients usually forward their Accept-Language header here.
+                let languages = self
+                    .get_optional_argument(field, "readmeLanguage", variables)?
+                    .and_then(|v| v.as_str().map(parse_language_preferences))
+                    .unwrap_or_default();
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                let record = get_repository_raw(&self.resolver, viewer_did, path).await?;
                 match record {
                     Some(record) => {
-                        self.project_repository_node(&record, &field.selection_set, fragments, variables)
-                            .await
+                        self.project_repository_node(
+                            &record,
+                            &field.selection_set,
+                            fragments,
+                            variables,
+                            &languages,
+                        )
+                        .await
                     }
                     None => Ok(JsonValue::Null),
                 }
@@ -253,9 +771,21 @@ impl CoreSubgraphExecutor {
                 let branch = self
                     .get_optional_argument(field, "branch", variables)?
                     .and_then(|v| v.as_str().map(|s| s.to_string()));
-                let payload =
-                    browse_repository_raw(&self.pool, &self.storage, path, tree_path, branch)
-                        .await?;
+                // `treePath` is relative to the project root when one is given.
+                let project = self
+                    .get_optional_argument(field, "project", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let tree_path = scope_tree_path(project, tree_path)?;
+                let viewer = current_viewer();
+                let payload = browse_repository_raw(
+                    &self.resolver,
+                    viewer.as_ref().map(|viewer| viewer.did.as_str()),
+                    &self.storage,
+                    path,
+                    tree_path,
+                    branch,
+                )
+                .await?;
                 match payload {
                     Some(payload) => self.project_repository_entries_payload(
                         &payload,
@@ -271,8 +801,14 @@ impl CoreSubgraphExecutor {
                     .as_str()
                     .ok_or_else(|| anyhow!("path argument must be a string"))?
                     .to_string();
-                let branches =
-                    list_repository_branches_raw(&self.pool, &self.storage, path).await?;
+                let viewer = current_viewer();
+                let branches = list_repository_branches_raw(
+                    &self.resolver,
+                    viewer.as_ref().map(|viewer| viewer.did.as_str()),
+                    &self.storage,
+                    path,
+                )
+                .await?;
                 match branches {
                     Some(branches) => {
                         let mut items = Vec::with_capacity(branches.len());
@@ -288,280 +824,6083 @@ impl CoreSubgraphExecutor {
                     None => Ok(JsonValue::Null),
                 }
             }
-            "readRepositoryFile" => {
+            "mergePreview" => {
                 let path = self
                     .get_required_argument(field, "path", variables)?
                     .as_str()
                     .ok_or_else(|| anyhow!("path argument must be a string"))?
                     .to_string();
-                let file_path = self
-                    .get_required_argument(field, "filePath", variables)?
+                let base = self
+                    .get_required_argument(field, "base", variables)?
                     .as_str()
-                    .ok_or_else(|| anyhow!("filePath argument must be a string"))?
+                    .ok_or_else(|| anyhow!("base argument must be a string"))?
                     .to_string();
-                let branch = self
-                    .get_optional_argument(field, "branch", variables)?
+                let head = self
+                    .get_required_argument(field, "head", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("head argument must be a string"))?
+                    .to_string();
+                let viewer = current_viewer();
+                let preview = merge_preview_raw(
+                    &self.pool,
+                    &self.resolver,
+                    viewer.as_ref().map(|viewer| viewer.did.as_str()),
+                    &self.storage,
+                    path,
+                    base,
+                    head,
+                )
+                .await?;
+                match preview {
+                    Some(preview) => {
+                        self.project_merge_preview(&preview, &field.selection_set, fragments)
+                    }
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "findFiles" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let query = self
+                    .get_required_argument(field, "query", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("query argument must be a string"))?
+                    .to_string();
+                let rev = self
+                    .get_optional_argument(field, "rev", variables)?
                     .and_then(|v| v.as_str().map(|s| s.to_string()));
-                let payload =
-                    read_repository_file_raw(&self.pool, &self.storage, path, file_path, branch)
-                        .await?;
-                match payload {
-                    Some(payload) => self.project_repository_file_payload(
-                        &payload,
-                        &field.selection_set,
-                        fragments,
-                    ),
+                let first = self
+                    .get_optional_argument(field, "first", variables)?
+                    .and_then(|v| v.as_u64())
+                    .map_or(DEFAULT_RESULTS, |n| n as usize);
+                let project = self
+                    .get_optional_argument(field, "project", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let viewer = current_viewer();
+                let matches = find_files_raw(
+                    &self.resolver,
+                    viewer.as_ref().map(|viewer| viewer.did.as_str()),
+                    &self.storage,
+                    path,
+                    query,
+                    rev,
+                    project,
+                    first,
+                )
+                .await?;
+                match matches {
+                    Some(matches) => {
+                        let mut items = Vec::with_capacity(matches.len());
+                        for file in &matches {
+                            items.push(self.project_file_match(
+                                file,
+                                &field.selection_set,
+                                fragments,
+                            )?);
+                        }
+                        Ok(JsonValue::Array(items))
+                    }
                     None => Ok(JsonValue::Null),
                 }
             }
-            other => Err(anyhow!("Unsupported query field `{}`", other)),
-        }
-    }
-
-    async fn resolve_mutation_field<'a>(
-        &self,
-        field: &'a Field<'a, String>,
-        variables: &Vars,
-        fragments: &FragmentMap<'a>,
-    ) -> Result<JsonValue> {
-        match field.name.as_str() {
-            "__typename" => Ok(JsonValue::String("Mutation".to_string())),
-            "createGroup" => {
-                let input_value = self.get_required_argument(field, "input", variables)?;
-                let input = self.parse_create_group_input(&input_value)?;
-                let record = create_group_raw(&self.pool, input).await?;
-                self.project_group_node(&record, &field.selection_set, fragments)
-                    .await
+            "symbols" | "definitionsInFile" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                let Some(record) = get_repository_raw(&self.resolver, viewer_did, path).await?
+                else {
+                    return Ok(JsonValue::Null);
+                };
+                // Repositories from before the index existed are indexed on first use
+                ensure_symbol_index(&self.pool, &record.id).await?;
+                let symbols = if field.name == "symbols" {
+                    let query = self
+                        .get_required_argument(field, "query", variables)?
+                        .as_str()
+                        .ok_or_else(|| anyhow!("query argument must be a string"))?
+                        .to_string();
+                    let first = self
+                        .get_optional_argument(field, "first", variables)?
+                        .and_then(|v| v.as_u64())
+                        .map_or(DEFAULT_SYMBOL_RESULTS, |n| n as usize);
+                    search_symbols(&self.pool, &record.id, &query, first).await?
+                } else {
+                    let file_path = self
+                        .get_required_argument(field, "filePath", variables)?
+                        .as_str()
+                        .ok_or_else(|| anyhow!("filePath argument must be a string"))?
+                        .to_string();
+                    definitions_in_file(&self.pool, &record.id, &file_path).await?
+                };
+                let mut items = Vec::with_capacity(symbols.len());
+                for symbol in &symbols {
+                    items.push(self.project_code_symbol(
+                        symbol,
+                        &field.selection_set,
+                        fragments,
+                    )?);
+                }
+                Ok(JsonValue::Array(items))
             }
-            "createRepository" => {
-                let input_value = self.get_required_argument(field, "input", variables)?;
-                let input = self.parse_create_repository_input(&input_value)?;
-                let record = create_repository_raw(&self.pool, input).await?;
-                self.project_repository_node(&record, &field.selection_set, fragments, variables)
-                    .await
+            "symbolIndex" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                let Some(record) = get_repository_raw(&self.resolver, viewer_did, path).await?
+                else {
+                    return Ok(JsonValue::Null);
+                };
+                let status = ensure_symbol_index(&self.pool, &record.id).await?;
+                self.project_symbol_index(&status, &field.selection_set, fragments)
             }
-            "linkRemoteRepository" => {
-                let url_value = self.get_required_argument(field, "url", variables)?;
-                let url = url_value
+            "contributorStats" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
                     .as_str()
-                    .ok_or_else(|| anyhow!("url argument must be a string"))?
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
                     .to_string();
-                let record = link_remote_repository_raw(&self.pool, &self.storage, url).await?;
-                self.project_repository_node(&record, &field.selection_set, fragments, variables)
-                    .await
+                let since = self
+                    .get_optional_argument(field, "since", variables)?
+                    .and_then(|v| v.as_i64());
+                let until = self
+                    .get_optional_argument(field, "until", variables)?
+                    .and_then(|v| v.as_i64());
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                let Some(record) = get_repository_raw(&self.resolver, viewer_did, path).await?
+                else {
+                    return Ok(JsonValue::Null);
+                };
+                let status = ensure_contributor_stats(&self.pool, &record.id).await?;
+                let authors = contributors(&self.pool, &record.id, since, until).await?;
+                let weeks = code_frequency(&self.pool, &record.id, since, until).await?;
+                self.project_contributor_stats(
+                    &status,
+                    &authors,
+                    &weeks,
+                    &field.selection_set,
+                    fragments,
+                )
             }
-            other => Err(anyhow!("Unsupported mutation field `{}`", other)),
-        }
-    }
-
-    fn get_required_argument(
-        &self,
-        field: &Field<'_, String>,
-        name: &str,
-        variables: &Vars,
-    ) -> Result<JsonValue> {
-        field
-            .arguments
-            .iter()
-            .find(|(arg_name, _)| arg_name == name)
-            .map(|(_, value)| self.evaluate_value(value, variables))
-            .transpose()?
-            .ok_or_else(|| anyhow!("Missing required argument `{}`", name))
-    }
-
-    fn get_optional_argument(
-        &self,
-        field: &Field<'_, String>,
-        name: &str,
-        variables: &Vars,
-    ) -> Result<Option<JsonValue>> {
-        field
-            .arguments
-            .iter()
-            .find(|(arg_name, _)| arg_name == name)
-            .map(|(_, value)| self.evaluate_value(value, variables))
-            .transpose()
-    }
-
-    fn evaluate_value(&self, value: &AstValue<'_, String>, variables: &Vars) -> Result<JsonValue> {
-        Ok(match value {
-            AstValue::Variable(name) => variables
-                .get(name)
-                .cloned()
-                .ok_or_else(|| anyhow!("Variable `{}` not provided", name))?,
-            AstValue::Int(i) => {
-                let value = i
-                    .as_i64()
-                    .ok_or_else(|| anyhow!("integer value is out of range"))?;
-                JsonValue::Number(value.into())
+            "searchCode" => {
+                let query = self
+                    .get_required_argument(field, "query", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("query argument must be a string"))?
+                    .to_string();
+                let path = self
+                    .get_optional_argument(field, "path", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let language = self
+                    .get_optional_argument(field, "language", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let first = self
+                    .get_optional_argument(field, "first", variables)?
+                    .and_then(|v| v.as_u64())
+                    .map_or(DEFAULT_CODE_RESULTS, |n| n as usize);
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                // A repository path searches that repository, a group path every visible
+                // repository under the group, and no path the whole instance
+                let repository_ids = match path {
+                    Some(path) => {
+                        match get_repository_raw(&self.resolver, viewer_did, path.clone()).await? {
+                            Some(record) => vec![record.id],
+                            None => match get_group_raw(&self.resolver, path).await? {
+                                Some(group) => {
+                                    visible_repository_ids(&self.pool, viewer_did, Some(&group.id))
+                                        .await?
+                                }
+                                None => Vec::new(),
+                            },
+                        }
+                    }
+                    None => visible_repository_ids(&self.pool, viewer_did, None).await?,
+                };
+                // Repositories from before code search existed are indexed on first use
+                ensure_code_indexes(&self.pool, &repository_ids).await?;
+                let matches = search_code(
+                    &self.pool,
+                    &repository_ids,
+                    &query,
+                    language.as_deref(),
+                    first,
+                )
+                .await?;
+                let mut items = Vec::with_capacity(matches.len());
+                for found in &matches {
+                    items.push(
+                        self.project_code_match(found, &field.selection_set, fragments, variables)
+                            .await?,
+                    );
+                }
+                Ok(JsonValue::Array(items))
             }
-            AstValue::Float(f) => JsonValue::Number(
-                serde_json::Number::from_f64(*f)
-                    .ok_or_else(|| anyhow!("Failed to convert float value `{}`", f))?,
-            ),
-            AstValue::String(s) => JsonValue::String(s.clone()),
-            AstValue::Boolean(b) => JsonValue::Bool(*b),
-            AstValue::Null => JsonValue::Null,
-            AstValue::Enum(e) => JsonValue::String(e.clone()),
-            AstValue::List(items) => JsonValue::Array(
-                items
+            "repositoryProjects" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let rev = self
+                    .get_optional_argument(field, "rev", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let viewer = current_viewer();
+                let projects = list_repository_projects(
+                    &self.pool,
+                    &self.resolver,
+                    viewer.as_ref().map(|viewer| viewer.did.as_str()),
+                    &self.storage,
+                    path,
+                    rev,
+                )
+                .await?;
+                match projects {
+                    Some(projects) => {
+                        let mut items = Vec::with_capacity(projects.len());
+                        for project in &projects {
+                            items.push(self.project_repository_project(
+                                project,
+                                &field.selection_set,
+                                fragments,
+                            )?);
+                        }
+                        Ok(JsonValue::Array(items))
+                    }
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "detectProjectMetadata" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                match detect_project_metadata(&self.resolver, viewer_did, &self.storage, path)
+                    .await?
+                {
+                    Some(metadata) => {
+                        self.project_project_metadata(&metadata, &field.selection_set, fragments)
+                    }
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "commitGraph" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let rev = self
+                    .get_optional_argument(field, "ref", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let limit = self
+                    .get_optional_argument(field, "limit", variables)?
+                    .and_then(|v| v.as_u64())
+                    .map_or(DEFAULT_GRAPH_LIMIT, |n| n as usize);
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                match commit_graph(&self.resolver, viewer_did, &self.storage, &path, rev, limit)
+                    .await?
+                {
+                    Some(graph) => {
+                        self.project_commit_graph(&graph, &field.selection_set, fragments)
+                    }
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "compareRefs" | "compareAcrossForks" => {
+                let text = |name: &str| -> Result<String> {
+                    Ok(self
+                        .get_required_argument(field, name, variables)?
+                        .as_str()
+                        .ok_or_else(|| anyhow!("{} argument must be a string", name))?
+                        .to_string())
+                };
+                let limit = self
+                    .get_optional_argument(field, "limit", variables)?
+                    .and_then(|v| v.as_u64())
+                    .map_or(DEFAULT_COMPARE_LIMIT, |n| n as usize);
+                let (base_path, base, head_path, head) = if field.name == "compareRefs" {
+                    let path = text("path")?;
+                    (path.clone(), text("base")?, path, text("head")?)
+                } else {
+                    (
+                        text("basePath")?,
+                        text("baseRef")?,
+                        text("headPath")?,
+                        text("headRef")?,
+                    )
+                };
+                // A fork may be private while its parent is public, so both sides are
+                // checked.
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                for path in [&base_path, &head_path] {
+                    if get_repository_raw(&self.resolver, viewer_did, path.clone())
+                        .await?
+                        .is_none()
+                    {
+                        return Ok(JsonValue::Null);
+                    }
+                }
+                let comparison = if field.name == "compareRefs" {
+                    compare_refs(&self.resolver, &self.storage, &base_path, base, head, limit)
+                        .await?
+                } else {
+                    compare_across_forks(
+                        &self.resolver,
+                        &self.storage,
+                        &base_path,
+                        base,
+                        &head_path,
+                        head,
+                        limit,
+                    )
+                    .await?
+                };
+                match comparison {
+                    Some(comparison) => {
+                        self.project_comparison(&comparison, &field.selection_set, fragments)
+                    }
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "diff" => {
+                let text = |name: &str| -> Result<String> {
+                    Ok(self
+                        .get_required_argument(field, name, variables)?
+                        .as_str()
+                        .ok_or_else(|| anyhow!("{} argument must be a string", name))?
+                        .to_string())
+                };
+                let path = text("path")?;
+                let base = text("base")?;
+                let head = text("head")?;
+                let from_merge_base = self
+                    .get_optional_argument(field, "mergeBase", variables)?
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                let Some(record) =
+                    get_repository_raw(&self.resolver, viewer_did, path.clone()).await?
+                else {
+                    return Ok(JsonValue::Null);
+                };
+                let detail = self
+                    .change_detail(&field.selection_set, "Diff", fragments)?
+                    .unwrap_or(ChangeDetail::Stats);
+                match diff_revisions(
+                    &self.resolver,
+                    &self.storage,
+                    &path,
+                    base,
+                    head,
+                    from_merge_base,
+                    detail,
+                )
+                .await?
+                {
+                    Some(diff) => self.project_diff(&diff, &field.selection_set, fragments),
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "searchCommits" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let text = |name: &str| -> Result<Option<String>> {
+                    Ok(self
+                        .get_optional_argument(field, name, variables)?
+                        .and_then(|v| v.as_str().map(|s| s.to_string())))
+                };
+                let time = |name: &str| -> Result<Option<i64>> {
+                    Ok(self
+                        .get_optional_argument(field, name, variables)?
+                        .and_then(|v| v.as_i64()))
+                };
+                let search = CommitSearch {
+                    query: text("query")?,
+                    author: text("author")?,
+                    since: time("since")?,
+                    until: time("until")?,
+                };
+                let request = self.page_request(field, variables, COMMIT_CURSOR_KIND)?;
+                let branch = text("branch")?;
+                match search_commits(
+                    &self.resolver,
+                    &self.storage,
+                    &path,
+                    branch,
+                    search,
+                    request,
+                )
+                .await?
+                {
+                    Some(page) => {
+                        self.project_commit_search(&page, &field.selection_set, fragments)
+                    }
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "listCommits" | "getCommit" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                let Some(record) =
+                    get_repository_raw(&self.resolver, viewer_did, path.clone()).await?
+                else {
+                    return Ok(JsonValue::Null);
+                };
+                let detail = self.change_detail(&field.selection_set, "Commit", fragments)?;
+                if field.name == "getCommit" {
+                    let oid = self
+                        .get_required_argument(field, "oid", variables)?
+                        .as_str()
+                        .ok_or_else(|| anyhow!("oid argument must be a string"))?
+                        .to_string();
+                    return match get_commit_raw(&self.resolver, &self.storage, path, oid, detail)
+                        .await?
+                    {
+                        Some(commit) => {
+                            self.project_commit(&commit, &field.selection_set, fragments)
+                        }
+                        None => Ok(JsonValue::Null),
+                    };
+                }
+                let branch = self
+                    .get_optional_argument(field, "branch", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let limit = self
+                    .get_optional_argument(field, "limit", variables)?
+                    .and_then(|v| v.as_u64())
+                    .map_or(DEFAULT_COMMIT_LIMIT, |n| n as usize);
+                let offset = self
+                    .get_optional_argument(field, "offset", variables)?
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(0) as usize;
+                let commits = list_commits_raw(
+                    &self.resolver,
+                    &self.storage,
+                    path,
+                    branch,
+                    limit,
+                    offset,
+                    detail,
+                )
+                .await?;
+                match commits {
+                    Some(commits) => {
+                        let mut items = Vec::with_capacity(commits.len());
+                        for commit in &commits {
+                            items.push(self.project_commit(
+                                commit,
+                                &field.selection_set,
+                                fragments,
+                            )?);
+                        }
+                        Ok(JsonValue::Array(items))
+                    }
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "extensionFieldStats" => {
+                require_admin()?;
+                let limit = self
+                    .get_optional_argument(field, "limit", variables)?
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(20)
+                    .min(200) as usize;
+                let stats = slowest_fields(limit)
                     .iter()
-                    .map(|item| self.evaluate_value(item, variables))
-                    .collect::<Result<Vec<_>>>()?,
-            ),
-            AstValue::Object(obj) => {
-                let mut map = Map::new();
-                for (name, value) in obj {
-                    map.insert(name.clone(), self.evaluate_value(value, variables)?);
+                    .map(|stats| {
+                        self.project_extension_field_stats(stats, &field.selection_set, fragments)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(JsonValue::Array(stats))
+            }
+            "schemaFieldUsage" | "unusedSchemaTypes" => {
+                require_admin()?;
+                let since = self
+                    .get_optional_argument(field, "unusedForDays", variables)?
+                    .and_then(|v| v.as_i64())
+                    .map(|days| crate::user::db::unix_now() - days.max(0) * 86_400);
+                let usage = usage_recorder().field_usage(&self.pool).await?;
+                if field.name == "unusedSchemaTypes" {
+                    let types = unused_types(&usage, since);
+                    return Ok(JsonValue::Array(
+                        types.into_iter().map(JsonValue::String).collect(),
+                    ));
                 }
-                JsonValue::Object(map)
+                let deprecated_only = self
+                    .get_optional_argument(field, "deprecatedOnly", variables)?
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let fields = usage
+                    .iter()
+                    .filter(|usage| since.is_none() || usage.unused_since(since))
+                    .filter(|usage| !deprecated_only || usage.field.deprecation.is_some())
+                    .map(|usage| {
+                        self.project_schema_field_usage(usage, &field.selection_set, fragments)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(JsonValue::Array(fields))
             }
-        })
+            "extensionCacheHealth" => {
+                require_admin()?;
+                let status = cache_status(&crate::config::current().extensions)?;
+                let checks = status
+                    .iter()
+                    .map(|check| {
+                        self.project_extension_cache_check(check, &field.selection_set, fragments)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(JsonValue::Array(checks))
+            }
+            "extensionUpgrades" => {
+                require_admin()?;
+                let extension = self
+                    .get_optional_argument(field, "extension", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let first = self
+                    .get_optional_argument(field, "first", variables)?
+                    .and_then(|v| v.as_i64())
+                    .unwrap_or(20)
+                    .clamp(1, 100);
+                let upgrades = list_upgrades(&self.pool, extension.as_deref(), first).await?;
+                let items = upgrades
+                    .iter()
+                    .map(|upgrade| {
+                        self.project_extension_upgrade(upgrade, &field.selection_set, fragments)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(JsonValue::Array(items))
+            }
+            "extensionQueryAudit" => {
+                require_admin()?;
+                let extension = self
+                    .get_optional_argument(field, "extension", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let first = self
+                    .get_optional_argument(field, "first", variables)?
+                    .and_then(|v| v.as_i64())
+                    .unwrap_or(50)
+                    .clamp(1, 500);
+                let records = query_audit(&self.pool, extension.as_deref(), first).await?;
+                let items = records
+                    .iter()
+                    .map(|record| {
+                        self.project_extension_query_audit(record, &field.selection_set, fragments)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(JsonValue::Array(items))
+            }
+            "extensionSchedules" => {
+                require_admin()?;
+                let extension = self
+                    .get_optional_argument(field, "extension", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let schedules = list_schedules(&self.pool, extension.as_deref()).await?;
+                let items = schedules
+                    .iter()
+                    .map(|schedule| {
+                        self.project_extension_schedule(schedule, &field.selection_set, fragments)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(JsonValue::Array(items))
+            }
+            "extensionTaskRuns" => {
+                require_admin()?;
+                let extension = self
+                    .get_optional_argument(field, "extension", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let task = self
+                    .get_optional_argument(field, "task", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let first = self
+                    .get_optional_argument(field, "first", variables)?
+                    .and_then(|v| v.as_i64())
+                    .unwrap_or(20)
+                    .clamp(1, 100);
+                let runs =
+                    list_runs(&self.pool, extension.as_deref(), task.as_deref(), first).await?;
+                let items = runs
+                    .iter()
+                    .map(|run| {
+                        self.project_extension_task_run(run, &field.selection_set, fragments)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(JsonValue::Array(items))
+            }
+            "repositoryFsck" => {
+                require_admin()?;
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                let report = check_repository(&self.storage, &resolved).await?;
+                self.project_integrity_report(&report, &field.selection_set, fragments)
+            }
+            "activeAnnouncements" => {
+                let announcements = active_announcements(&self.pool, unix_now()).await?;
+                self.project_announcements(&announcements, &field.selection_set, fragments)
+            }
+            "announcements" => {
+                require_admin()?;
+                let announcements = list_announcements(&self.pool).await?;
+                self.project_announcements(&announcements, &field.selection_set, fragments)
+            }
+            "instanceSettings" => {
+                let settings = instance_settings(&self.pool).await?;
+                self.project_instance_settings(&settings, &field.selection_set, fragments)
+            }
+            "maintenanceMode" => {
+                let mode = maintenance_mode(&self.pool).await?;
+                self.project_maintenance_mode(&mode, &field.selection_set, fragments)
+            }
+            "featureFlags" => {
+                let repository_id = match self
+                    .get_optional_argument(field, "repositoryPath", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()))
+                {
+                    Some(path) => Some(
+                        self.resolver
+                            .resolve_repository(&path)
+                            .await?
+                            .ok_or_else(|| anyhow!("repository not found"))?
+                            .record
+                            .id,
+                    ),
+                    None => None,
+                };
+                let viewer = current_viewer();
+                let flags = evaluate_flags(
+                    &self.pool,
+                    viewer.as_ref().map(|viewer| viewer.did.as_str()),
+                    repository_id.as_deref(),
+                )
+                .await?;
+                let fields = selection_fields(&field.selection_set, "FeatureFlag", fragments)?;
+                let items = flags
+                    .into_iter()
+                    .map(|(key, enabled)| {
+                        let mut map = Map::new();
+                        for field in &fields {
+                            let value = match field.name.as_str() {
+                                "__typename" => JsonValue::String("FeatureFlag".to_string()),
+                                "key" => JsonValue::String(key.clone()),
+                                "enabled" => JsonValue::Bool(enabled),
+                                _ => JsonValue::Null,
+                            };
+                            map.insert(response_key(field), value);
+                        }
+                        JsonValue::Object(map)
+                    })
+                    .collect();
+                Ok(JsonValue::Array(items))
+            }
+            "featureFlagDefinitions" => {
+                require_admin()?;
+                let flags = list_flags(&self.pool).await?;
+                let items = flags
+                    .iter()
+                    .map(|flag| self.project_feature_flag(flag, &field.selection_set, fragments))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(JsonValue::Array(items))
+            }
+            "complianceArchive" => {
+                require_admin()?;
+                let filter = ExportFilter {
+                    subject_kind: self
+                        .get_optional_argument(field, "kind", variables)?
+                        .and_then(|v| v.as_str().map(|s| s.to_string())),
+                    since: self
+                        .get_optional_argument(field, "since", variables)?
+                        .and_then(|v| v.as_i64()),
+                    until: self
+                        .get_optional_argument(field, "until", variables)?
+                        .and_then(|v| v.as_i64()),
+                    limit: self
+                        .get_optional_argument(field, "first", variables)?
+                        .and_then(|v| v.as_i64())
+                        .unwrap_or(100),
+                };
+                let records = export_archive(&self.pool, &filter)
+                    .await?
+                    .iter()
+                    .map(|record| {
+                        self.project_compliance_record(record, &field.selection_set, fragments)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(JsonValue::Array(records))
+            }
+            "auditLog" => {
+                require_admin()?;
+                let filter = self.audit_filter(field, variables).await?;
+                let request = self.page_request(field, variables, AUDIT_CURSOR_KIND)?;
+                let page = page_audit_log(&self.pool, &filter, &request).await?;
+                self.project_audit_log(&page, &filter, &field.selection_set, fragments)
+                    .await
+            }
+            "auditLogExports" => {
+                require_admin()?;
+                let items = list_audit_exports(&self.pool)
+                    .await?
+                    .iter()
+                    .map(|export| {
+                        self.project_audit_export(export, &field.selection_set, fragments)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(JsonValue::Array(items))
+            }
+            "auditLogRetention" => {
+                require_admin()?;
+                let config = &crate::config::current().audit_log;
+                let fields =
+                    selection_fields(&field.selection_set, "AuditLogRetention", fragments)?;
+                let mut items = Vec::new();
+                for (category, days) in retention_by_category(&self.pool, config).await? {
+                    let mut map = Map::new();
+                    for selected in &fields {
+                        let value = match selected.name.as_str() {
+                            "__typename" => JsonValue::String("AuditLogRetention".to_string()),
+                            "category" => JsonValue::String(category.clone()),
+                            "retentionDays" => JsonValue::from(days),
+                            _ => JsonValue::Null,
+                        };
+                        map.insert(response_key(selected), value);
+                    }
+                    items.push(JsonValue::Object(map));
+                }
+                Ok(JsonValue::Array(items))
+            }
+            "groupUsage" => {
+                require_admin()?;
+                let config = crate::config::current()
+                    .metering
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("usage metering is not enabled"))?;
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?;
+                // Usage is accounted to top-level groups
+                let top_level = path.trim_matches('/').split('/').next().unwrap_or_default();
+                let Some(group) = get_group_raw(&self.resolver, top_level.to_string()).await?
+                else {
+                    return Ok(JsonValue::Null);
+                };
+                let usage = crate::metering::meter()
+                    .group_usage(&self.pool, config, &group.id, &group.slug, unix_now())
+                    .await?;
+                self.project_group_usage(&usage, &field.selection_set, fragments)
+            }
+            "usageExports" => {
+                require_admin()?;
+                let items = list_usage_exports(&self.pool)
+                    .await?
+                    .iter()
+                    .map(|export| {
+                        self.project_usage_export(export, &field.selection_set, fragments)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(JsonValue::Array(items))
+            }
+            "moderationQueue" => {
+                require_admin()?;
+                let status = self
+                    .get_optional_argument(field, "status", variables)?
+                    .and_then(|v| v.as_str().map(ModerationStatus::from_graphql))
+                    .transpose()?;
+                let first = self
+                    .get_optional_argument(field, "first", variables)?
+                    .and_then(|v| v.as_i64())
+                    .unwrap_or(100);
+                let items = list_items(&self.pool, status, first)
+                    .await?
+                    .iter()
+                    .map(|item| self.project_moderation_item(item, &field.selection_set, fragments))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(JsonValue::Array(items))
+            }
+            "readRepositoryFile" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let file_path = self
+                    .get_required_argument(field, "filePath", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("filePath argument must be a string"))?
+                    .to_string();
+                let branch = self
+                    .get_optional_argument(field, "branch", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let viewer = current_viewer();
+                let payload = read_repository_file_raw(
+                    &self.resolver,
+                    viewer.as_ref().map(|viewer| viewer.did.as_str()),
+                    &self.storage,
+                    path,
+                    file_path,
+                    branch,
+                )
+                .await?;
+                match payload {
+                    Some(payload) => self.project_repository_file_payload(
+                        &payload,
+                        &field.selection_set,
+                        fragments,
+                    ),
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "blameRepositoryFile" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let file_path = self
+                    .get_required_argument(field, "filePath", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("filePath argument must be a string"))?
+                    .to_string();
+                let branch = self
+                    .get_optional_argument(field, "branch", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let viewer = current_viewer();
+                let blame = blame_repository_file_raw(
+                    &self.resolver,
+                    viewer.as_ref().map(|viewer| viewer.did.as_str()),
+                    &self.storage,
+                    path,
+                    file_path,
+                    branch,
+                )
+                .await?;
+                match blame {
+                    Some(blame) => self.project_file_blame(&blame, &field.selection_set, fragments),
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "user" => {
+                let handle = self
+                    .get_required_argument(field, "handle", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("handle argument must be a string"))?
+                    .to_string();
+                match fetch_user_by_handle(&self.pool, &handle).await? {
+                    Some(user) => {
+                        self.project_user_profile(&user, &field.selection_set, fragments, variables)
+                            .await
+                    }
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "federatedIssueReferences" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let number = self
+                    .get_required_argument(field, "number", variables)?
+                    .as_i64()
+                    .ok_or_else(|| anyhow!("number argument must be an integer"))?;
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                let references = issue_references(&self.pool, &resolved.record.id, number).await?;
+                let mut items = Vec::with_capacity(references.len());
+                for reference in &references {
+                    items.push(self.project_federated_issue_reference(
+                        reference,
+                        &field.selection_set,
+                        fragments,
+                    )?);
+                }
+                Ok(JsonValue::Array(items))
+            }
+            "repositorySecrets" | "repositorySecretAudit" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                self.require_repository_owner(&resolved.record.id, "manage secrets for")
+                    .await?;
+                if field.name == "repositorySecretAudit" {
+                    let first = self
+                        .get_optional_argument(field, "first", variables)?
+                        .and_then(|v| v.as_i64())
+                        .unwrap_or(50)
+                        .clamp(1, 500);
+                    let records = secret_audit(&self.pool, &resolved.record.id, first).await?;
+                    let mut items = Vec::with_capacity(records.len());
+                    for record in &records {
+                        items.push(self.project_secret_access(
+                            record,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    return Ok(JsonValue::Array(items));
+                }
+                let secrets = list_repository_secrets(&self.pool, &resolved.record.id).await?;
+                let mut items = Vec::with_capacity(secrets.len());
+                for secret in &secrets {
+                    items.push(self.project_repository_secret(
+                        secret,
+                        &field.selection_set,
+                        fragments,
+                    )?);
+                }
+                Ok(JsonValue::Array(items))
+            }
+            "repositoryShareLinks" | "repositoryShareLinkAudit" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                self.require_repository_owner(&resolved.record.id, "share")
+                    .await?;
+                if field.name == "repositoryShareLinkAudit" {
+                    let first = self
+                        .get_optional_argument(field, "first", variables)?
+                        .and_then(|v| v.as_i64())
+                        .unwrap_or(50)
+                        .clamp(1, 500);
+                    let events = share_link_audit(&self.pool, &resolved.record.id, first).await?;
+                    let mut items = Vec::with_capacity(events.len());
+                    for event in &events {
+                        items.push(self.project_share_link_event(
+                            event,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    return Ok(JsonValue::Array(items));
+                }
+                let links = list_share_links(&self.pool, &resolved.record.id).await?;
+                let mut items = Vec::with_capacity(links.len());
+                for link in &links {
+                    items.push(self.project_share_link(link, &field.selection_set, fragments)?);
+                }
+                Ok(JsonValue::Array(items))
+            }
+            "webhooks" => {
+                let repository_id = self.webhook_scope(field, variables).await?;
+                self.require_webhook_manager(repository_id.as_deref())
+                    .await?;
+                let webhooks = list_webhooks(&self.pool, repository_id.as_deref()).await?;
+                let mut items = Vec::with_capacity(webhooks.len());
+                for webhook in &webhooks {
+                    items.push(
+                        self.project_webhook(webhook, &field.selection_set, fragments, variables)
+                            .await?,
+                    );
+                }
+                Ok(JsonValue::Array(items))
+            }
+            "jobs" => {
+                require_admin()?;
+                let status = match self.get_optional_argument(field, "status", variables)? {
+                    Some(JsonValue::String(status)) => Some(status.to_ascii_lowercase()),
+                    _ => None,
+                };
+                let kind = self
+                    .get_optional_argument(field, "kind", variables)?
+                    .and_then(|v| v.as_str().map(str::to_string));
+                let filter = JobFilter { kind, status };
+                let request = self.page_request(field, variables, JOB_CURSOR_KIND)?;
+                let page = page_jobs(&self.pool, &filter, &request).await?;
+                let total = count_jobs(&self.pool, &filter).await?;
+                self.project_jobs(&page, total, &field.selection_set, fragments)
+            }
+            "job" => {
+                require_admin()?;
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))
+                    .and_then(|id| global_id::internal_id("Job", id))?;
+                match JobQueue::new(self.pool.clone()).get(&id).await? {
+                    Some(job) => self.project_job(&job, &field.selection_set, fragments),
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "webhook" => {
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))
+                    .and_then(|id| global_id::internal_id("Webhook", id))?;
+                let Some(webhook) = fetch_webhook(&self.pool, &id).await? else {
+                    return Ok(JsonValue::Null);
+                };
+                self.require_webhook_manager(webhook.repository_id.as_deref())
+                    .await?;
+                self.project_webhook(&webhook, &field.selection_set, fragments, variables)
+                    .await
+            }
+            "viewerContributions" => match current_viewer() {
+                Some(viewer) => {
+                    let contributions = contributions_for(&self.pool, &viewer.did).await?;
+                    self.project_user_contributions(&contributions, &field.selection_set, fragments)
+                }
+                None => Ok(JsonValue::Null),
+            },
+            "checkRepositoryPolicy" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let updates_value = self.get_required_argument(field, "updates", variables)?;
+                let updates = self.parse_ref_updates(&updates_value)?;
+                match check_repository_policy(&self.resolver, &self.storage, &path, &updates)
+                    .await?
+                {
+                    Some(report) => {
+                        self.project_policy_check_result(&report, &field.selection_set, fragments)
+                    }
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "validateCommitMessage" | "validateBranchName" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let input_name = if field.name == "validateCommitMessage" {
+                    "message"
+                } else {
+                    "name"
+                };
+                let input = self
+                    .get_required_argument(field, input_name, variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("{} argument must be a string", input_name))?
+                    .to_string();
+                let violations = if field.name == "validateCommitMessage" {
+                    validate_commit_message(&self.resolver, &path, &input).await?
+                } else {
+                    validate_branch_name(&self.resolver, &path, &input).await?
+                };
+                match violations {
+                    Some(violations) => {
+                        self.project_policy_validation(&violations, &field.selection_set, fragments)
+                    }
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "savedSearches" => {
+                let Some(viewer) = current_viewer() else {
+                    return Ok(JsonValue::Array(Vec::new()));
+                };
+                let scope = self
+                    .get_optional_argument(field, "scope", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()))
+                    .map(|s| SearchScope::parse(&s))
+                    .transpose()?;
+                let records =
+                    list_saved_searches(&self.pool, &viewer.did, scope.map(|s| s.as_str())).await?;
+                let mut items = Vec::with_capacity(records.len());
+                for record in records {
+                    items.push(self.project_saved_search(
+                        &record,
+                        &field.selection_set,
+                        fragments,
+                    )?);
+                }
+                Ok(JsonValue::Array(items))
+            }
+            "accountExports" => {
+                let viewer = require_viewer()?;
+                let exports = list_account_exports(&self.pool, &viewer.did).await?;
+                let mut items = Vec::with_capacity(exports.len());
+                for export in exports {
+                    items.push(
+                        self.project_account_export(&export, &field.selection_set, fragments)
+                            .await?,
+                    );
+                }
+                Ok(JsonValue::Array(items))
+            }
+            "viewerSessions" => {
+                let session = require_session()?;
+                let viewer = require_viewer()?;
+                let mut sessions = session.sessions.sessions_for(&viewer.did)?;
+                sessions.sort_by_key(|s| std::cmp::Reverse(s.last_active_at));
+                let mut items = Vec::with_capacity(sessions.len());
+                for record in &sessions {
+                    items.push(self.project_viewer_session(
+                        record,
+                        record.id == session.id,
+                        &field.selection_set,
+                        fragments,
+                    )?);
+                }
+                Ok(JsonValue::Array(items))
+            }
+            "viewerSshKeys" => {
+                let viewer = require_viewer()?;
+                let keys = list_ssh_keys(&self.pool, &viewer.did).await?;
+                let mut items = Vec::with_capacity(keys.len());
+                for key in &keys {
+                    items.push(self.project_ssh_key(key, &field.selection_set, fragments)?);
+                }
+                Ok(JsonValue::Array(items))
+            }
+            "viewerPushSubscriptions" => {
+                let viewer = require_viewer()?;
+                let subscriptions = push_subscriptions_for(&self.pool, &viewer.did).await?;
+                let mut items = Vec::with_capacity(subscriptions.len());
+                for subscription in &subscriptions {
+                    items.push(self.project_push_subscription(
+                        subscription,
+                        &field.selection_set,
+                        fragments,
+                    )?);
+                }
+                Ok(JsonValue::Array(items))
+            }
+            "viewerNotificationDigestFrequency" => {
+                let viewer = require_viewer()?;
+                let frequency = get_digest_frequency(&self.pool, &viewer.did).await?;
+                Ok(JsonValue::String(frequency.as_str().to_uppercase()))
+            }
+            "webPushPublicKey" => {
+                Ok(push::public_key()?.map_or(JsonValue::Null, JsonValue::String))
+            }
+            "viewerAccessTokens" => {
+                let viewer = require_viewer()?;
+                let tokens = list_access_tokens(&self.pool, &viewer.did).await?;
+                let mut items = Vec::with_capacity(tokens.len());
+                for token in &tokens {
+                    items.push(self.project_access_token(
+                        token,
+                        &field.selection_set,
+                        fragments,
+                    )?);
+                }
+                Ok(JsonValue::Array(items))
+            }
+            other => Err(anyhow!("Unsupported query field `{}`", other)),
+        }
+    }
+
+    async fn resolve_mutation_field<'a>(
+        &self,
+        field: &'a Field<'a, String>,
+        variables: &Vars,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        match field.name.as_str() {
+            "__typename" => Ok(JsonValue::String("Mutation".to_string())),
+            "createGroup" => {
+                let input_value = self.get_required_argument(field, "input", variables)?;
+                let input = self.parse_create_group_input(&input_value)?;
+                let record = create_group_raw(&self.pool, input).await?;
+                self.project_group_node(&record, &field.selection_set, fragments)
+                    .await
+            }
+            "setGroupSettings" => {
+                require_admin()?;
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let input = self.get_required_argument(field, "settings", variables)?;
+                let record = get_group_raw(&self.resolver, path)
+                    .await?
+                    .ok_or_else(|| anyhow!("group not found"))?;
+                let settings = GroupSettings {
+                    labels: match input.get("labels") {
+                        Some(JsonValue::Null) | None => None,
+                        Some(labels) => Some(self.parse_labels(labels)?),
+                    },
+                    branch_protection: match input.get("branchProtection") {
+                        Some(JsonValue::String(source)) => Some(parse_policy(source)?),
+                        Some(JsonValue::Null) | None => None,
+                        Some(_) => {
+                            return Err(anyhow!("settings.branchProtection must be a string"));
+                        }
+                    },
+                    visibility: match input.get("visibility") {
+                        Some(JsonValue::String(value)) => Some(Visibility::from_graphql(value)?),
+                        Some(JsonValue::Null) | None => None,
+                        Some(_) => {
+                            return Err(anyhow!("settings.visibility must be an enum value"));
+                        }
+                    },
+                };
+                save_group_settings(&self.pool, &record.id, settings).await?;
+                self.project_group_node(&record, &field.selection_set, fragments)
+                    .await
+            }
+            "createRepository" => {
+                let input_value = self.get_required_argument(field, "input", variables)?;
+                let input = self.parse_create_repository_input(&input_value)?;
+                let flagged = screen(Content {
+                    kind: ContentKind::Repository,
+                    actor_did: current_viewer().map(|viewer| viewer.did),
+                    repository_id: None,
+                    title: Some(input.slug.clone()),
+                    body: None,
+                })
+                .await?;
+                let record = create_repository_raw(&self.pool, input).await?;
+                self.record_new_repository(&record, "repository.created")
+                    .await?;
+                if let Some(flagged) = flagged {
+                    flagged
+                        .record(&self.pool, "core", Some(&record.id), None)
+                        .await?;
+                }
+                self.project_repository_node(
+                    &record,
+                    &field.selection_set,
+                    fragments,
+                    variables,
+                    &[],
+                )
+                .await
+            }
+            "linkRemoteRepository" => {
+                let url_value = self.get_required_argument(field, "url", variables)?;
+                let url = url_value
+                    .as_str()
+                    .ok_or_else(|| anyhow!("url argument must be a string"))?
+                    .to_string();
+                let record = link_remote_repository_raw(&self.pool, &self.storage, url).await?;
+                self.record_new_repository(&record, "repository.linked")
+                    .await?;
+                self.project_repository_node(
+                    &record,
+                    &field.selection_set,
+                    fragments,
+                    variables,
+                    &[],
+                )
+                .await
+            }
+            "importRepositoryFromPath" | "importRepositoryBundle" => {
+                require_admin()?;
+                let source = if field.name == "importRepositoryFromPath" {
+                    let local_path = self
+                        .get_required_argument(field, "localPath", variables)?
+                        .as_str()
+                        .ok_or_else(|| anyhow!("localPath argument must be a string"))?
+                        .to_string();
+                    let roots = &crate::config::current().repos.import_roots;
+                    ImportSource::detect(&resolve_import_path(&local_path, roots)?)?
+                } else {
+                    let id = self
+                        .get_required_argument(field, "bundle", variables)?
+                        .as_str()
+                        .ok_or_else(|| anyhow!("bundle argument must be an upload"))?
+                        .to_string();
+                    let upload = lookup_upload(&id).ok_or_else(|| anyhow!("unknown upload"))?;
+                    match ImportSource::detect(&upload.path)? {
+                        ImportSource::Bundle(path) => ImportSource::Bundle(path),
+                        ImportSource::BareRepository(_) => {
+                            return Err(anyhow!("the upload is not a git bundle"));
+                        }
+                    }
+                };
+                let slug = self
+                    .get_required_argument(field, "slug", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("slug argument must be a string"))?
+                    .to_string();
+                let group = self
+                    .get_optional_argument(field, "group", variables)?
+                    .and_then(|v| v.as_str().map(|s| global_id::internal_id("Group", s)))
+                    .transpose()?;
+                let input = ImportInput { slug, group };
+                let record = import_repository(&self.pool, &self.storage, &source, input).await?;
+                self.record_new_repository(&record, "repository.imported")
+                    .await?;
+                self.project_repository_node(
+                    &record,
+                    &field.selection_set,
+                    fragments,
+                    variables,
+                    &[],
+                )
+                .await
+            }
+            "starRepository" | "unstarRepository" => {
+                let viewer = require_viewer()?;
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))
+                    .and_then(|id| global_id::internal_id("Repository", id))?;
+                let record = get_repository_by_id(&self.pool, &id)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                let starred = field.name == "starRepository";
+                set_star(&self.pool, &viewer.did, &record.id, starred).await?;
+                if starred {
+                    record_activity(
+                        &self.pool,
+                        &viewer.did,
+                        Some(&record.id),
+                        "repository.starred",
+                        &format!("starred {}", record.slug),
+                    )
+                    .await?;
+                }
+                self.project_repository_node(
+                    &record,
+                    &field.selection_set,
+                    fragments,
+                    variables,
+                    &[],
+                )
+                .await
+            }
+            "setRepositoryPolicy" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let source = self
+                    .get_required_argument(field, "rules", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("rules argument must be a string"))?
+                    .to_string();
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                self.require_repository_owner(&resolved.record.id, "configure")
+                    .await?;
+                let rules = save_policy(&self.pool, &resolved.record.id, &source).await?;
+                clear_inherited_branch_protection(&self.pool, &resolved.record.id).await?;
+                self.project_repository_policy(&rules, &field.selection_set, fragments)
+            }
+            "setRepositorySettings" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let labels = match self.get_optional_argument(field, "labels", variables)? {
+                    Some(JsonValue::Null) | None => None,
+                    Some(labels) => Some(self.parse_labels(&labels)?),
+                };
+                let visibility = self
+                    .get_optional_argument(field, "visibility", variables)?
+                    .and_then(|v| v.as_str().map(Visibility::from_graphql))
+                    .transpose()?;
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                self.require_repository_owner(&resolved.record.id, "configure")
+                    .await?;
+                set_repository_settings(&self.pool, &resolved.record.id, labels, visibility)
+                    .await?;
+                self.project_repository_node(
+                    &resolved.record,
+                    &field.selection_set,
+                    fragments,
+                    variables,
+                    &[],
+                )
+                .await
+            }
+            "setIssueClosing" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let enabled = self
+                    .get_required_argument(field, "enabled", variables)?
+                    .as_bool()
+                    .ok_or_else(|| anyhow!("enabled argument must be a boolean"))?;
+                let branches = match self.get_optional_argument(field, "branches", variables)? {
+                    Some(JsonValue::Array(items)) => items
+                        .iter()
+                        .map(|item| {
+                            item.as_str()
+                                .map(str::to_string)
+                                .ok_or_else(|| anyhow!("branches must be strings"))
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                    _ => Vec::new(),
+                };
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                self.require_repository_owner(&resolved.record.id, "configure")
+                    .await?;
+                let settings = IssueClosingSettings { enabled, branches };
+                save_issue_closing(&self.pool, &resolved.record.id, &settings).await?;
+                self.project_repository_node(
+                    &resolved.record,
+                    &field.selection_set,
+                    fragments,
+                    variables,
+                    &[],
+                )
+                .await
+            }
+            "setRepositoryProjectRoots" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let roots = match self.get_required_argument(field, "roots", variables)? {
+                    JsonValue::Array(items) => items
+                        .iter()
+                        .map(|item| {
+                            item.as_str()
+                                .map(str::to_string)
+                                .ok_or_else(|| anyhow!("roots must be strings"))
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                    _ => return Err(anyhow!("roots argument must be a list")),
+                };
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                self.require_repository_owner(&resolved.record.id, "configure")
+                    .await?;
+                save_project_roots(&self.pool, &resolved.record.id, roots).await?;
+                self.project_repository_node(
+                    &resolved.record,
+                    &field.selection_set,
+                    fragments,
+                    variables,
+                    &[],
+                )
+                .await
+            }
+            "setRepositoryDescription" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let description = self
+                    .get_optional_argument(field, "description", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()))
+                    .unwrap_or_default();
+                let language = self
+                    .get_optional_argument(field, "language", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                self.require_repository_owner(&resolved.record.id, "configure")
+                    .await?;
+                set_repository_description(
+                    &self.pool,
+                    &resolved.record.id,
+                    language.as_deref(),
+                    &description,
+                )
+                .await?;
+                // Echo back the language that was just written.
+                let languages = language
+                    .as_deref()
+                    .map(parse_language_preferences)
+                    .unwrap_or_default();
+                self.project_repository_node(
+                    &resolved.record,
+                    &field.selection_set,
+                    fragments,
+                    variables,
+                    &languages,
+                )
+                .await
+            }
+            "saveSearch" => {
+                let viewer = require_viewer()?;
+                let name = self
+                    .get_required_argument(field, "name", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("name argument must be a string"))?
+                    .to_string();
+                let scope = self
+                    .get_required_argument(field, "scope", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("scope argument must be a SearchScope"))
+                    .and_then(SearchScope::parse)?;
+                let query = self
+                    .get_required_argument(field, "query", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("query argument must be a string"))?
+                    .to_string();
+                let record = save_search(&self.pool, &viewer.did, &name, scope, &query).await?;
+                self.project_saved_search(&record, &field.selection_set, fragments)
+            }
+            "requestAccountExport" => {
+                let viewer = require_viewer()?;
+                let config = crate::config::current().account_export.clone();
+                let export = request_account_export(&self.pool, &viewer.did, &config).await?;
+                self.project_account_export(&export, &field.selection_set, fragments)
+                    .await
+            }
+            "requestAuditLogExport" => {
+                let admin = require_admin()?;
+                let format = self
+                    .get_required_argument(field, "format", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("format argument must be an AuditLogExportFormat"))
+                    .and_then(|format| ExportFormat::parse(&format.to_lowercase()))?;
+                let filter = self.audit_filter(field, variables).await?;
+                let config = crate::config::current().audit_log.clone();
+                let export =
+                    request_audit_export(&self.pool, &admin.did, &filter, format, &config).await?;
+                self.project_audit_export(&export, &field.selection_set, fragments)
+            }
+            "revokeSession" => {
+                let session = require_session()?;
+                let viewer = require_viewer()?;
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))
+                    .and_then(|id| global_id::internal_id("ViewerSession", id))?;
+                let Some(revoked) = session.sessions.revoke_by_fingerprint(&viewer.did, &id)?
+                else {
+                    return Ok(JsonValue::Bool(false));
+                };
+                notify_security_change(
+                    &self.pool,
+                    &viewer.did,
+                    "security.session_revoked",
+                    "A signed-in session was revoked",
+                    serde_json::json!({
+                        "session": id,
+                        "userAgent": revoked.user_agent,
+                        "current": revoked.id == session.id,
+                    }),
+                )
+                .await?;
+                Ok(JsonValue::Bool(true))
+            }
+            "addSshKey" => {
+                require_session()?;
+                let viewer = require_viewer()?;
+                let title = self
+                    .get_required_argument(field, "title", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("title argument must be a string"))?
+                    .to_string();
+                let public_key = self
+                    .get_required_argument(field, "publicKey", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("publicKey argument must be a string"))?
+                    .to_string();
+                let key = add_ssh_key(&self.pool, &viewer.did, &title, &public_key).await?;
+                notify_security_change(
+                    &self.pool,
+                    &viewer.did,
+                    "security.ssh_key_added",
+                    &format!("SSH key \"{}\" was added", key.title),
+                    serde_json::json!({ "id": key.id, "fingerprint": key.fingerprint }),
+                )
+                .await?;
+                self.project_ssh_key(&key, &field.selection_set, fragments)
+            }
+            "removeSshKey" => {
+                require_session()?;
+                let viewer = require_viewer()?;
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))
+                    .and_then(|id| global_id::internal_id("SshKey", id))?;
+                let Some(key) = remove_ssh_key(&self.pool, &viewer.did, &id).await? else {
+                    return Ok(JsonValue::Bool(false));
+                };
+                notify_security_change(
+                    &self.pool,
+                    &viewer.did,
+                    "security.ssh_key_removed",
+                    &format!("SSH key \"{}\" was removed", key.title),
+                    serde_json::json!({ "id": key.id, "fingerprint": key.fingerprint }),
+                )
+                .await?;
+                Ok(JsonValue::Bool(true))
+            }
+            "createAccessToken" => {
+                require_session()?;
+                let viewer = require_viewer()?;
+                let name = self
+                    .get_required_argument(field, "name", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("name argument must be a string"))?
+                    .to_string();
+                let expires_in_days = self
+                    .get_optional_argument(field, "expiresInDays", variables)?
+                    .and_then(|v| v.as_i64());
+                let (record, token) =
+                    create_access_token(&self.pool, &viewer.did, &name, expires_in_days).await?;
+                notify_security_change(
+                    &self.pool,
+                    &viewer.did,
+                    "security.access_token_created",
+                    &format!("Access token \"{}\" was created", record.name),
+                    serde_json::json!({
+                        "id": record.id,
+                        "name": record.name,
+                        "expiresAt": record.expires_at,
+                    }),
+                )
+                .await?;
+                self.project_new_access_token(&record, &token, &field.selection_set, fragments)
+            }
+            "revokeAccessToken" => {
+                require_session()?;
+                let viewer = require_viewer()?;
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))
+                    .and_then(|id| global_id::internal_id("AccessToken", id))?;
+                let Some(record) = revoke_access_token(&self.pool, &viewer.did, &id).await? else {
+                    return Ok(JsonValue::Bool(false));
+                };
+                notify_security_change(
+                    &self.pool,
+                    &viewer.did,
+                    "security.access_token_revoked",
+                    &format!("Access token \"{}\" was revoked", record.name),
+                    serde_json::json!({ "id": record.id, "name": record.name }),
+                )
+                .await?;
+                Ok(JsonValue::Bool(true))
+            }
+            "registerPushSubscription" => {
+                require_session()?;
+                let viewer = require_viewer()?;
+                let endpoint = self
+                    .get_required_argument(field, "endpoint", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("endpoint argument must be a string"))?
+                    .to_string();
+                let keys = self.get_required_argument(field, "keys", variables)?;
+                let key = |name: &str| {
+                    keys.get(name)
+                        .and_then(JsonValue::as_str)
+                        .map(str::to_string)
+                        .ok_or_else(|| anyhow!("keys.{} must be a string", name))
+                };
+                let (p256dh, auth) = (key("p256dh")?, key("auth")?);
+                let expires_at = self
+                    .get_optional_argument(field, "expiresAt", variables)?
+                    .and_then(|v| v.as_i64());
+                let subscription = push::register_subscription(
+                    &self.pool,
+                    &viewer.did,
+                    &endpoint,
+                    &p256dh,
+                    &auth,
+                    expires_at,
+                )
+                .await?;
+                self.project_push_subscription(&subscription, &field.selection_set, fragments)
+            }
+            "unregisterPushSubscription" => {
+                require_session()?;
+                let viewer = require_viewer()?;
+                let endpoint = self
+                    .get_required_argument(field, "endpoint", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("endpoint argument must be a string"))?
+                    .to_string();
+                Ok(JsonValue::Bool(
+                    delete_push_subscription(&self.pool, &viewer.did, &endpoint).await?,
+                ))
+            }
+            "setNotificationDigestFrequency" => {
+                require_session()?;
+                let viewer = require_viewer()?;
+                let frequency = self
+                    .get_required_argument(field, "frequency", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("frequency argument must be a string"))
+                    .and_then(DigestFrequency::parse)?;
+                set_digest_frequency(&self.pool, &viewer.did, frequency).await?;
+                Ok(JsonValue::String(frequency.as_str().to_uppercase()))
+            }
+            "deleteSavedSearch" => {
+                let viewer = require_viewer()?;
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))
+                    .and_then(|id| global_id::internal_id("SavedSearch", id))?;
+                Ok(JsonValue::Bool(
+                    delete_saved_search(&self.pool, &viewer.did, &id).await?,
+                ))
+            }
+            "watchRemoteTags" | "unwatchRemoteTags" => {
+                let viewer = require_viewer()?;
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                if resolved.record.remote_url.is_none() {
+                    return Err(anyhow!(
+                        "only linked remote repositories can be watched for tags"
+                    ));
+                }
+                if field.name == "unwatchRemoteTags" {
+                    return Ok(JsonValue::Bool(
+                        unwatch_remote_tags(&self.pool, &resolved.record.id, &viewer.did).await?,
+                    ));
+                }
+                let semver_range = self
+                    .get_optional_argument(field, "semverRange", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let watch =
+                    watch_remote_tags(&self.pool, &resolved.record.id, &viewer.did, semver_range)
+                        .await?;
+                self.project_remote_tag_watch(&watch, &field.selection_set, fragments)
+            }
+            "createShareLink" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let expires_in = self
+                    .get_required_argument(field, "expiresIn", variables)?
+                    .as_i64()
+                    .ok_or_else(|| anyhow!("expiresIn argument must be an integer"))?;
+                let allow_clone = self
+                    .get_optional_argument(field, "allowClone", variables)?
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                let actor = self
+                    .require_repository_owner(&resolved.record.id, "share")
+                    .await?;
+                let (link, token) = create_share_link(
+                    &self.pool,
+                    &resolved.record.id,
+                    Some(&actor.did),
+                    expires_in,
+                    allow_clone,
+                )
+                .await?;
+                self.project_new_share_link(
+                    &link,
+                    &token,
+                    &resolved.canonical_path(),
+                    &field.selection_set,
+                    fragments,
+                )
+            }
+            "revokeShareLink" => {
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))
+                    .and_then(|id| global_id::internal_id("ShareLink", id))?;
+                let Some(link) = fetch_share_link(&self.pool, &id).await? else {
+                    return Ok(JsonValue::Null);
+                };
+                let actor = self
+                    .require_repository_owner(&link.repository_id, "share")
+                    .await?;
+                match revoke_share_link(&self.pool, &id, Some(&actor.did)).await? {
+                    Some(link) => self.project_share_link(&link, &field.selection_set, fragments),
+                    None => Ok(JsonValue::Null),
+                }
+            }
+            "createWebhook" => {
+                let repository_id = self.webhook_scope(field, variables).await?;
+                let actor = self
+                    .require_webhook_manager(repository_id.as_deref())
+                    .await?;
+                let input = self.webhook_input(field, variables)?;
+                let webhook = create_webhook(
+                    &self.pool,
+                    server_key()?,
+                    &crate::config::current().webhooks,
+                    repository_id.as_deref(),
+                    input,
+                    Some(&actor),
+                )
+                .await?;
+                self.project_webhook(&webhook, &field.selection_set, fragments, variables)
+                    .await
+            }
+            "updateWebhook" | "deleteWebhook" => {
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))
+                    .and_then(|id| global_id::internal_id("Webhook", id))?;
+                let webhook = fetch_webhook(&self.pool, &id)
+                    .await?
+                    .ok_or_else(|| anyhow!("webhook not found"))?;
+                self.require_webhook_manager(webhook.repository_id.as_deref())
+                    .await?;
+                if field.name == "deleteWebhook" {
+                    return Ok(JsonValue::Bool(delete_webhook(&self.pool, &id).await?));
+                }
+                let input = self.webhook_input(field, variables)?;
+                let webhook = update_webhook(
+                    &self.pool,
+                    server_key()?,
+                    &crate::config::current().webhooks,
+                    webhook,
+                    input,
+                )
+                .await?;
+                self.project_webhook(&webhook, &field.selection_set, fragments, variables)
+                    .await
+            }
+            "testWebhook" => {
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))
+                    .and_then(|id| global_id::internal_id("Webhook", id))?;
+                let webhook = fetch_webhook(&self.pool, &id)
+                    .await?
+                    .ok_or_else(|| anyhow!("webhook not found"))?;
+                let actor = self
+                    .require_webhook_manager(webhook.repository_id.as_deref())
+                    .await?;
+                let delivery = test_webhook(&self.pool, &webhook, Some(&actor)).await?;
+                self.project_webhook_delivery(&delivery, &field.selection_set, fragments)
+            }
+            "redeliverWebhookDelivery" => {
+                let id = self
+                    .get_required_argument(field, "deliveryId", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("deliveryId argument must be a string"))
+                    .and_then(|id| global_id::internal_id("WebhookDelivery", id))?;
+                let delivery = fetch_delivery(&self.pool, &id)
+                    .await?
+                    .ok_or_else(|| anyhow!("webhook delivery not found"))?;
+                let webhook = fetch_webhook(&self.pool, &delivery.webhook_id)
+                    .await?
+                    .ok_or_else(|| anyhow!("webhook not found"))?;
+                self.require_webhook_manager(webhook.repository_id.as_deref())
+                    .await?;
+                let delivery = redeliver(&self.pool, &delivery).await?;
+                self.project_webhook_delivery(&delivery, &field.selection_set, fragments)
+            }
+            "setRepositorySecret" | "deleteRepositorySecret" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let name = self
+                    .get_required_argument(field, "name", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("name argument must be a string"))?
+                    .to_string();
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                let actor = self
+                    .require_repository_owner(&resolved.record.id, "manage secrets for")
+                    .await?
+                    .did;
+                if field.name == "deleteRepositorySecret" {
+                    let deleted = delete_repository_secret(
+                        &self.pool,
+                        &resolved.record.id,
+                        &name,
+                        Some(&actor),
+                    )
+                    .await?;
+                    return Ok(JsonValue::Bool(deleted));
+                }
+                let value = self
+                    .get_required_argument(field, "value", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("value argument must be a string"))?
+                    .to_string();
+                let extensions = match self.get_optional_argument(field, "extensions", variables)? {
+                    Some(JsonValue::Array(items)) => items
+                        .iter()
+                        .map(|item| {
+                            item.as_str()
+                                .map(str::to_string)
+                                .ok_or_else(|| anyhow!("extensions must be strings"))
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                    _ => Vec::new(),
+                };
+                let secret = set_repository_secret(
+                    &self.pool,
+                    server_key()?,
+                    &resolved.record.id,
+                    &name,
+                    &value,
+                    &extensions,
+                    Some(&actor),
+                )
+                .await?;
+                self.project_repository_secret(&secret, &field.selection_set, fragments)
+            }
+            "rotateMirrorWebhookSecret" | "disableMirrorWebhook" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                if resolved.record.remote_url.is_none() {
+                    return Err(anyhow!(
+                        "only linked remote repositories have mirror webhooks"
+                    ));
+                }
+                self.require_repository_owner(&resolved.record.id, "configure")
+                    .await?;
+                if field.name == "disableMirrorWebhook" {
+                    return Ok(JsonValue::Bool(
+                        disable_mirror_webhook(&self.pool, &resolved.record.id).await?,
+                    ));
+                }
+                let secret = rotate_mirror_webhook_secret(&self.pool, &resolved.record.id).await?;
+                let url = format!(
+                    "{}/hooks/mirror/{}",
+                    crate::config::current()
+                        .server
+                        .public_base_url
+                        .trim_end_matches('/'),
+                    path.trim_matches('/')
+                );
+                self.project_mirror_webhook(&url, &secret, &field.selection_set, fragments)
+            }
+            "deleteRepository" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                let viewer = self
+                    .require_repository_owner(&resolved.record.id, "delete")
+                    .await?;
+                let deleted = delete_repository_raw(
+                    &self.pool,
+                    &self.resolver,
+                    &self.storage,
+                    &path,
+                    Some(&viewer.did),
+                )
+                .await?;
+                Ok(JsonValue::Bool(deleted))
+            }
+            "renameRepository" | "transferRepository" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                let (slug, group) = if field.name == "renameRepository" {
+                    let slug = self
+                        .get_required_argument(field, "slug", variables)?
+                        .as_str()
+                        .ok_or_else(|| anyhow!("slug argument must be a string"))?
+                        .to_string();
+                    (slug, resolved.record.group_id.clone())
+                } else {
+                    let group = self
+                        .get_optional_argument(field, "group", variables)?
+                        .and_then(|v| v.as_str().map(|s| global_id::internal_id("Group", s)))
+                        .transpose()?;
+                    (resolved.record.slug.clone(), group)
+                };
+                let viewer = self
+                    .require_repository_owner(&resolved.record.id, "move")
+                    .await?;
+                let record = move_repository_raw(
+                    &self.pool,
+                    &self.resolver,
+                    &self.storage,
+                    &path,
+                    &slug,
+                    group.as_deref(),
+                    Some(&viewer.did),
+                )
+                .await?
+                .ok_or_else(|| anyhow!("repository not found"))?;
+                self.project_repository_node(
+                    &record,
+                    &field.selection_set,
+                    fragments,
+                    variables,
+                    &[],
+                )
+            }
+            "createAnnouncement" => {
+                let viewer = require_admin()?;
+                let input_value = self.get_required_argument(field, "input", variables)?;
+                let input = self.parse_create_announcement_input(&input_value)?;
+                let announcement =
+                    create_announcement(&self.pool, input, Some(&viewer.did)).await?;
+                self.project_announcement(&announcement, &field.selection_set, fragments)
+            }
+            "deleteAnnouncement" => {
+                require_admin()?;
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))
+                    .and_then(|id| global_id::internal_id("Announcement", id))?;
+                Ok(JsonValue::Bool(delete_announcement(&self.pool, &id).await?))
+            }
+            "completeSetup" => {
+                let token = self
+                    .get_required_argument(field, "token", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("token argument must be a string"))?
+                    .to_string();
+                let admin_handle = self
+                    .get_required_argument(field, "adminHandle", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("adminHandle argument must be a string"))?
+                    .to_string();
+                let admin = complete_setup(&self.pool, &token, &admin_handle).await?;
+                tracing::warn!(admin = %admin.did, "instance setup completed");
+                let settings = instance_settings(&self.pool).await?;
+                self.project_instance_settings(&settings, &field.selection_set, fragments)
+            }
+            "updateInstanceSettings" => {
+                require_admin()?;
+                let name = self
+                    .get_optional_argument(field, "name", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let description = self
+                    .get_optional_argument(field, "description", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let default_visibility = self
+                    .get_optional_argument(field, "defaultVisibility", variables)?
+                    .and_then(|v| v.as_str().map(Visibility::from_graphql))
+                    .transpose()?;
+                let update = InstanceSettingsUpdate {
+                    name,
+                    description,
+                    default_visibility,
+                };
+                let settings = update_instance_settings(&self.pool, update).await?;
+                self.project_instance_settings(&settings, &field.selection_set, fragments)
+            }
+            "setMaintenanceMode" => {
+                let viewer = require_admin()?;
+                let enabled = self
+                    .get_required_argument(field, "enabled", variables)?
+                    .as_bool()
+                    .ok_or_else(|| anyhow!("enabled argument must be a boolean"))?;
+                let message = self
+                    .get_optional_argument(field, "message", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let mode =
+                    set_maintenance_mode(&self.pool, enabled, message, Some(&viewer.did)).await?;
+                tracing::warn!(
+                    actor = %viewer.did,
+                    "maintenance mode {}",
+                    if enabled { "enabled" } else { "disabled" }
+                );
+                self.project_maintenance_mode(&mode, &field.selection_set, fragments)
+            }
+            "setFeatureFlag" => {
+                let viewer = require_admin()?;
+                let key = self
+                    .get_required_argument(field, "key", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("key argument must be a string"))?
+                    .to_string();
+                let enabled = self
+                    .get_required_argument(field, "enabled", variables)?
+                    .as_bool()
+                    .ok_or_else(|| anyhow!("enabled argument must be a boolean"))?;
+                let rollout_percent = self
+                    .get_optional_argument(field, "rolloutPercent", variables)?
+                    .and_then(|v| v.as_i64());
+                let description = self
+                    .get_optional_argument(field, "description", variables)?
+                    .and_then(|v| v.as_str().map(|s| s.to_string()));
+                let flag = set_flag(
+                    &self.pool,
+                    &key,
+                    enabled,
+                    rollout_percent,
+                    description,
+                    Some(&viewer.did),
+                )
+                .await?;
+                tracing::info!(
+                    actor = %viewer.did,
+                    flag = %flag.key,
+                    enabled = flag.enabled,
+                    rollout_percent = flag.rollout_percent,
+                    "feature flag updated"
+                );
+                self.project_feature_flag(&flag, &field.selection_set, fragments)
+            }
+            "deleteFeatureFlag" => {
+                let viewer = require_admin()?;
+                let key = self
+                    .get_required_argument(field, "key", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("key argument must be a string"))?
+                    .to_string();
+                let deleted = delete_flag(&self.pool, &key).await?;
+                if deleted {
+                    tracing::info!(actor = %viewer.did, flag = %key, "feature flag deleted");
+                }
+                Ok(JsonValue::Bool(deleted))
+            }
+            "setFeatureFlagOverride" => {
+                let viewer = require_admin()?;
+                let key = self
+                    .get_required_argument(field, "key", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("key argument must be a string"))?
+                    .to_string();
+                let path = self
+                    .get_required_argument(field, "repositoryPath", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("repositoryPath argument must be a string"))?
+                    .to_string();
+                let enabled = self
+                    .get_optional_argument(field, "enabled", variables)?
+                    .and_then(|v| v.as_bool());
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                let flag = set_override(
+                    &self.pool,
+                    &key,
+                    &resolved.record.id,
+                    enabled,
+                    Some(&viewer.did),
+                )
+                .await?;
+                self.project_feature_flag(&flag, &field.selection_set, fragments)
+            }
+            "repairRepository" => {
+                let viewer = require_admin()?;
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                tracing::info!(actor = %viewer.did, repository = %path, "repository repair requested");
+                let report = repair_repository(&self.storage, &resolved).await?;
+                self.project_repair_report(&report, &field.selection_set, fragments)
+            }
+            "rebuildSearchIndex" => {
+                let viewer = require_admin()?;
+                let scope = self
+                    .get_required_argument(field, "scope", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("scope argument must be a string"))
+                    .and_then(SearchIndexScope::parse)?;
+                let id = queue_rebuild(&self.pool, scope).await?;
+                tracing::info!(actor = %viewer.did, scope = scope.as_str(), job = %id, "search index rebuild requested");
+                let job = JobQueue::new(self.pool.clone())
+                    .get(&id)
+                    .await?
+                    .ok_or_else(|| anyhow!("the rebuild job was not stored"))?;
+                self.project_job(&job, &field.selection_set, fragments)
+            }
+            "reloadExtension" => {
+                let viewer = require_admin()?;
+                let name = self
+                    .get_required_argument(field, "name", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("name argument must be a string"))?
+                    .to_string();
+                let router =
+                    super::live().ok_or_else(|| anyhow!("extensions cannot be reloaded"))?;
+                tracing::warn!(actor = %viewer.did, extension = %name, "extension reload requested");
+                let reload = router.reload_extension(&name).await?;
+                self.project_extension_reload(&reload, &field.selection_set, fragments)
+            }
+            "approveModerationItem" | "removeModerationItem" => {
+                let viewer = require_admin()?;
+                let id = self
+                    .get_required_argument(field, "id", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("id argument must be a string"))
+                    .and_then(|id| global_id::internal_id("ModerationItem", id))?;
+                let item = if field.name == "approveModerationItem" {
+                    approve_item(&self.pool, &id, &viewer.did).await?
+                } else {
+                    remove_item(&self.pool, &self.resolver, &self.storage, &id, &viewer.did).await?
+                };
+                tracing::info!(actor = %viewer.did, item = %id, status = %item.status, "moderation item resolved");
+                self.project_moderation_item(&item, &field.selection_set, fragments)
+            }
+            "cherryPickCommit" | "revertCommit" => {
+                let path = self
+                    .get_required_argument(field, "path", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("path argument must be a string"))?
+                    .to_string();
+                let branch = self
+                    .get_required_argument(field, "branch", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("branch argument must be a string"))?
+                    .to_string();
+                let oid = self
+                    .get_required_argument(field, "oid", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("oid argument must be a string"))?
+                    .to_string();
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                // Writing to a branch is reserved for the owner, or an administrator when unowned.
+                let viewer = match fetch_repository_owner(&self.pool, &resolved.record.id).await? {
+                    Some(owner) => {
+                        let viewer = require_viewer()?;
+                        if viewer.did != owner {
+                            return Err(anyhow!(
+                                "only the repository owner can change its branches"
+                            ));
+                        }
+                        viewer
+                    }
+                    None => require_admin()?,
+                };
+                let operation = if field.name == "cherryPickCommit" {
+                    CommitOperation::CherryPick
+                } else {
+                    CommitOperation::Revert
+                };
+                let outcome = apply_commit_operation(
+                    &self.resolver,
+                    &self.storage,
+                    &path,
+                    &branch,
+                    &oid,
+                    operation,
+                    &viewer,
+                )
+                .await?
+                .ok_or_else(|| anyhow!("repository not found"))?;
+                if let Some(commit_oid) = &outcome.commit_oid {
+                    record_activity(
+                        &self.pool,
+                        &viewer.did,
+                        Some(&resolved.record.id),
+                        operation.event_kind(),
+                        &outcome.summary(&resolved.record.slug),
+                    )
+                    .await?;
+                    publish_event(
+                        &self.pool,
+                        crate::config::current().event_sink.as_ref(),
+                        Event {
+                            kind: operation.event_kind(),
+                            source: "core",
+                            actor_did: Some(&viewer.did),
+                            repository_id: Some(&resolved.record.id),
+                            data: serde_json::json!({
+                                "branch": outcome.branch,
+                                "sourceOid": outcome.source_oid,
+                                "previousOid": outcome.previous_oid,
+                                "commitOid": commit_oid,
+                            }),
+                        },
+                    )
+                    .await?;
+                }
+                self.project_commit_operation_result(&outcome, &field.selection_set, fragments)
+            }
+            "applySuggestion" => {
+                let comment_id = self
+                    .get_required_argument(field, "commentId", variables)?
+                    .as_str()
+                    .ok_or_else(|| anyhow!("commentId argument must be a string"))?
+                    .to_string();
+                let suggestion = fetch_suggestion(&comment_id).await?;
+                let resolved = self
+                    .resolver
+                    .resolve_repository(&suggestion.repository_path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?;
+                let viewer = match fetch_repository_owner(&self.pool, &resolved.record.id).await? {
+                    Some(owner) => {
+                        let viewer = require_viewer()?;
+                        if viewer.did != owner {
+                            return Err(anyhow!(
+                                "only the repository owner can change its branches"
+                            ));
+                        }
+                        viewer
+                    }
+                    None => require_admin()?,
+                };
+                // The comment's author is credited with a Co-authored-by trailer.
+                let co_author = fetch_user_by_did(&self.pool, &suggestion.author_did)
+                    .await?
+                    .map(|record| User {
+                        did: record.did,
+                        handle: record.handle,
+                        display_name: record.display_name,
+                        avatar: record.avatar,
+                    });
+                let outcome = apply_suggestion(
+                    &self.resolver,
+                    &self.storage,
+                    &suggestion,
+                    &viewer,
+                    co_author.as_ref(),
+                )
+                .await?
+                .ok_or_else(|| anyhow!("repository not found"))?;
+                if let Some(commit_oid) = &outcome.commit_oid {
+                    record_activity(
+                        &self.pool,
+                        &viewer.did,
+                        Some(&resolved.record.id),
+                        "repository.suggestion_applied",
+                        &format!(
+                            "applied a suggestion to {} on {}/{}",
+                            outcome.file_path, resolved.record.slug, outcome.branch
+                        ),
+                    )
+                    .await?;
+                    publish_event(
+                        &self.pool,
+                        crate::config::current().event_sink.as_ref(),
+                        Event {
+                            kind: "repository.suggestion_applied",
+                            source: "core",
+                            actor_did: Some(&viewer.did),
+                            repository_id: Some(&resolved.record.id),
+                            data: serde_json::json!({
+                                "commentId": comment_id,
+                                "branch": outcome.branch,
+                                "filePath": outcome.file_path,
+                                "previousOid": outcome.previous_oid,
+                                "commitOid": commit_oid,
+                            }),
+                        },
+                    )
+                    .await?;
+                }
+                self.project_suggestion_result(&outcome, &field.selection_set, fragments)
+            }
+            other => Err(anyhow!("Unsupported mutation field `{}`", other)),
+        }
+    }
+
+    /// The viewer, if they own the repository. Unowned repositories can only be changed
+    /// by an administrator.
+    async fn require_repository_owner(&self, repository_id: &str, action: &str) -> Result<User> {
+        match fetch_repository_owner(&self.pool, repository_id).await? {
+            Some(owner) => {
+                let viewer = require_viewer()?;
+                if viewer.did != owner {
+                    return Err(anyhow!("only the repository owner can {} it", action));
+                }
+                Ok(viewer)
+            }
+            None => require_admin(),
+        }
+    }
+
+    /// Exports the creation event and marks the viewer (when authenticated) as owner of a
+    /// freshly created repository.
+    async fn record_new_repository(&self, record: &RepositoryRecord, kind: &str) -> Result<()> {
+        let viewer = current_viewer();
+        publish_event(
+            &self.pool,
+            crate::config::current().event_sink.as_ref(),
+            Event {
+                kind,
+                source: "core",
+                actor_did: viewer.as_ref().map(|viewer| viewer.did.as_str()),
+                repository_id: Some(&record.id),
+                data: serde_json::json!({
+                    "slug": record.slug,
+                    "groupId": record.group_id,
+                    "remoteUrl": record.remote_url,
+                }),
+            },
+        )
+        .await?;
+        let Some(viewer) = viewer else {
+            return Ok(());
+        };
+        set_repository_owner(&self.pool, &record.id, &viewer.did).await?;
+        let verb = match kind {
+            "repository.linked" => "linked",
+            "repository.imported" => "imported",
+            _ => "created",
+        };
+        record_activity(
+            &self.pool,
+            &viewer.did,
+            Some(&record.id),
+            kind,
+            &format!("{} {}", verb, record.slug),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Repository webhooks are managed like the repository (see
+    /// [`Self::require_repository_owner`]); instance webhooks by admins. Returns the
+    /// viewer's DID.
+    async fn require_webhook_manager(&self, repository_id: Option<&str>) -> Result<String> {
+        let viewer = match repository_id {
+            Some(id) => {
+                self.require_repository_owner(id, "manage webhooks for")
+                    .await?
+            }
+            None => require_admin()?,
+        };
+        Ok(viewer.did)
+    }
+
+    /// The repository named by the optional `path` argument; `None` means the instance.
+    async fn webhook_scope(
+        &self,
+        field: &Field<'_, String>,
+        variables: &Vars,
+    ) -> Result<Option<String>> {
+        let Some(path) = self
+            .get_optional_argument(field, "path", variables)?
+            .filter(|value| !value.is_null())
+        else {
+            return Ok(None);
+        };
+        let path = path
+            .as_str()
+            .ok_or_else(|| anyhow!("path argument must be a string"))?;
+        let resolved = self
+            .resolver
+            .resolve_repository(path)
+            .await?
+            .ok_or_else(|| anyhow!("repository not found"))?;
+        Ok(Some(resolved.record.id))
+    }
+
+    fn webhook_input(&self, field: &Field<'_, String>, variables: &Vars) -> Result<WebhookInput> {
+        let text = |name: &str| -> Result<Option<String>> {
+            match self.get_optional_argument(field, name, variables)? {
+                None | Some(JsonValue::Null) => Ok(None),
+                Some(JsonValue::String(value)) => Ok(Some(value)),
+                Some(_) => Err(anyhow!("{} argument must be a string", name)),
+            }
+        };
+        let events = match self.get_optional_argument(field, "events", variables)? {
+            Some(JsonValue::Array(items)) => Some(
+                items
+                    .iter()
+                    .map(|item| {
+                        item.as_str()
+                            .map(str::to_string)
+                            .ok_or_else(|| anyhow!("events must be strings"))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            _ => None,
+        };
+        Ok(WebhookInput {
+            url: text("url")?,
+            secret: text("secret")?,
+            events,
+            active: self
+                .get_optional_argument(field, "active", variables)?
+                .and_then(|v| v.as_bool()),
+        })
+    }
+
+    fn get_required_argument(
+        &self,
+        field: &Field<'_, String>,
+        name: &str,
+        variables: &Vars,
+    ) -> Result<JsonValue> {
+        field
+            .arguments
+            .iter()
+            .find(|(arg_name, _)| arg_name == name)
+            .map(|(_, value)| self.evaluate_value(value, variables))
+            .transpose()?
+            .ok_or_else(|| anyhow!("Missing required argument `{}`", name))
+    }
+
+    /// Reads the `first`/`after`/`last`/`before` arguments of a connection field.
+    fn page_request(
+        &self,
+        field: &Field<'_, String>,
+        variables: &Vars,
+        kind: &str,
+    ) -> Result<PageRequest> {
+        let count = |name: &str| -> Result<Option<i64>> {
+            Ok(self
+                .get_optional_argument(field, name, variables)?
+                .and_then(|v| v.as_i64()))
+        };
+        let cursor = |name: &str| -> Result<Option<String>> {
+            Ok(self
+                .get_optional_argument(field, name, variables)?
+                .and_then(|v| v.as_str().map(|s| s.to_string())))
+        };
+        let after = cursor("after")?;
+        let before = cursor("before")?;
+        PageRequest::from_args(
+            kind,
+            count("first")?,
+            after.as_deref(),
+            count("last")?,
+            before.as_deref(),
+        )
+    }
+
+    /// Reads the `filter` argument of the audit log fields. The actor may be a DID or a
+    /// handle, and the repository a path or an ID; IDs still match deleted repositories.
+    async fn audit_filter(
+        &self,
+        field: &Field<'_, String>,
+        variables: &Vars,
+    ) -> Result<AuditFilter> {
+        let Some(input) = self
+            .get_optional_argument(field, "filter", variables)?
+            .filter(|value| !value.is_null())
+        else {
+            return Ok(AuditFilter::default());
+        };
+        let text = |name: &str| {
+            input
+                .get(name)
+                .and_then(JsonValue::as_str)
+                .map(str::to_string)
+        };
+        let number = |name: &str| input.get(name).and_then(JsonValue::as_i64);
+        let actor_did = match text("actor") {
+            Some(did) if did.starts_with("did:") => Some(did),
+            Some(handle) => Some(
+                fetch_user_by_handle(&self.pool, &handle)
+                    .await?
+                    .ok_or_else(|| anyhow!("no user with handle `{}`", handle))?
+                    .did,
+            ),
+            None => None,
+        };
+        let repository_id = match (text("repositoryPath"), text("repositoryId")) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!(
+                    "pass either `repositoryPath` or `repositoryId`, not both"
+                ));
+            }
+            (Some(path), None) => Some(
+                self.resolver
+                    .resolve_repository(&path)
+                    .await?
+                    .ok_or_else(|| anyhow!("repository not found"))?
+                    .record
+                    .id,
+            ),
+            (None, Some(id)) => Some(global_id::internal_id("Repository", &id)?),
+            (None, None) => None,
+        };
+        let actions = input
+            .get("actions")
+            .and_then(JsonValue::as_array)
+            .map(|actions| {
+                actions
+                    .iter()
+                    .filter_map(JsonValue::as_str)
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let filter = AuditFilter {
+            actor_did,
+            actions,
+            repository_id,
+            since: number("since"),
+            until: number("until"),
+        };
+        filter.validate()?;
+        Ok(filter)
+    }
+
+    fn get_optional_argument(
+        &self,
+        field: &Field<'_, String>,
+        name: &str,
+        variables: &Vars,
+    ) -> Result<Option<JsonValue>> {
+        field
+            .arguments
+            .iter()
+            .find(|(arg_name, _)| arg_name == name)
+            .map(|(_, value)| self.evaluate_value(value, variables))
+            .transpose()
+    }
+
+    fn evaluate_value(&self, value: &AstValue<'_, String>, variables: &Vars) -> Result<JsonValue> {
+        Ok(match value {
+            AstValue::Variable(name) => variables
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("Variable `{}` not provided", name))?,
+            AstValue::Int(i) => {
+                let value = i
+                    .as_i64()
+                    .ok_or_else(|| anyhow!("integer value is out of range"))?;
+                JsonValue::Number(value.into())
+            }
+            AstValue::Float(f) => JsonValue::Number(
+                serde_json::Number::from_f64(*f)
+                    .ok_or_else(|| anyhow!("Failed to convert float value `{}`", f))?,
+            ),
+            AstValue::String(s) => JsonValue::String(s.clone()),
+            AstValue::Boolean(b) => JsonValue::Bool(*b),
+            AstValue::Null => JsonValue::Null,
+            AstValue::Enum(e) => JsonValue::String(e.clone()),
+            AstValue::List(items) => JsonValue::Array(
+                items
+                    .iter()
+                    .map(|item| self.evaluate_value(item, variables))
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            AstValue::Object(obj) => {
+                let mut map = Map::new();
+                for (name, value) in obj {
+                    map.insert(name.clone(), self.evaluate_value(value, variables)?);
+                }
+                JsonValue::Object(map)
+            }
+        })
+    }
+
+    /// Resolves `node(id:)`. Objects owned by an extension are returned as references,
+    /// just `__typename` and `id`, and the planner fetches their other fields from the
+    /// extension. Legacy IDs name no type and resolve to `null`.
+    async fn resolve_node<'a>(
+        &self,
+        id: &str,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+        variables: &Vars,
+    ) -> Result<JsonValue> {
+        let Some((type_name, internal_id)) = global_id::decode(id) else {
+            return Ok(JsonValue::Null);
+        };
+        match type_name.as_str() {
+            "Group" => match get_group_parent(&self.pool, &internal_id).await? {
+                Some(record) => {
+                    self.project_group_node(&record, selection_set, fragments)
+                        .await
+                }
+                None => Ok(JsonValue::Null),
+            },
+            "Repository" => {
+                let viewer = current_viewer();
+                let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                match get_repository_by_id(&self.pool, &internal_id).await? {
+                    Some(record)
+                        if can_view_repository(&self.pool, viewer_did, &record.id).await? =>
+                    {
+                        self.project_repository_node(
+                            &record,
+                            selection_set,
+                            fragments,
+                            variables,
+                            &[],
+                        )
+                        .await
+                    }
+                    _ => Ok(JsonValue::Null),
+                }
+            }
+            other if self.extension_nodes.contains(other) => {
+                let mut map = Map::new();
+                for field in selection_fields(selection_set, other, fragments)? {
+                    let value = match field.name.as_str() {
+                        "__typename" => JsonValue::String(other.to_string()),
+                        "id" => JsonValue::String(id.to_string()),
+                        _ => JsonValue::Null,
+                    };
+                    map.insert(response_key(field), value);
+                }
+                Ok(JsonValue::Object(map))
+            }
+            _ => Ok(JsonValue::Null),
+        }
+    }
+
+    async fn project_group_node<'a>(
+        &self,
+        record: &GroupRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "GroupNode", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("GroupNode".to_string()),
+                "id" => JsonValue::String(global_id::encode("Group", &record.id)),
+                "slug" => JsonValue::String(record.slug.clone()),
+                "parent" => {
+                    if let Some(parent_id) = &record.parent {
+                        let parent =
+                            get_group_parent(&self.pool, parent_id)
+                                .await?
+                                .ok_or_else(|| {
+                                    anyhow!(
+                                        "Parent with id `{}` not found for group `{}`",
+                                        parent_id,
+                                        record.id
+                                    )
+                                })?;
+                        self.project_group_summary(&parent, &field.selection_set, fragments)?
+                    } else {
+                        JsonValue::Null
+                    }
+                }
+                "repositories" => {
+                    let viewer = current_viewer();
+                    let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                    let summaries =
+                        repositories_for_group(&self.pool, &record.id, viewer_did).await?;
+                    let mut items = Vec::with_capacity(summaries.len());
+                    for summary in summaries {
+                        items.push(self.project_repository_summary(
+                            &summary,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    JsonValue::Array(items)
+                }
+                "settings" => {
+                    let settings = load_group_settings(&self.pool, &record.id).await?;
+                    self.project_group_settings(&settings, &field.selection_set, fragments)?
+                }
+                other => {
+                    return Err(anyhow!("Unsupported field `{}` on GroupNode", other));
+                }
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_group_summary<'a>(
+        &self,
+        record: &GroupRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "GroupSummary", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("GroupSummary".to_string()),
+                "id" => JsonValue::String(global_id::encode("Group", &record.id)),
+                "slug" => JsonValue::String(record.slug.clone()),
+                other => {
+                    return Err(anyhow!("Unsupported field `{}` on GroupSummary", other));
+                }
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    /// `languages` are the visitor's language preferences, most preferred first. Fields
+    /// with a `language` argument override them.
+    async fn project_repository_node<'a>(
+        &self,
+        record: &RepositoryRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+        variables: &Vars,
+        languages: &[String],
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RepositoryNode", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RepositoryNode".to_string()),
+                "id" => JsonValue::String(global_id::encode("Repository", &record.id)),
+                "slug" => JsonValue::String(record.slug.clone()),
+                "isRemote" => JsonValue::Bool(record.remote_url.is_some()),
+                "remoteUrl" => match &record.remote_url {
+                    Some(url) => JsonValue::String(url.clone()),
+                    None => JsonValue::Null,
+                },
+                // Lookups are case-insensitive; clients compare this against the requested
+                // path and redirect to the canonical spelling when they differ.
+                "fullPath" => {
+                    JsonValue::String(reconstruct_repository_path(&self.pool, record).await?)
+                }
+                "group" => {
+                    if let Some(group_id) = &record.group_id {
+                        if let Some(group) = get_group_parent(&self.pool, group_id).await? {
+                            self.project_group_summary(&group, &field.selection_set, fragments)?
+                        } else {
+                            JsonValue::Null
+                        }
+                    } else {
+                        JsonValue::Null
+                    }
+                }
+                "readmeHtml" => {
+                    let branch = self
+                        .get_optional_argument(field, "branch", variables)?
+                        .and_then(|v| v.as_str().map(|s| s.to_string()));
+                    let languages = self.field_languages(field, variables, languages)?;
+
+                    match get_repository_readme_html(
+                        &self.pool,
+                        &self.storage,
+                        record,
+                        branch,
+                        &languages,
+                    )
+                    .await
+                    {
+                        Ok(Some(html)) => JsonValue::String(html),
+                        Ok(None) => JsonValue::Null,
+                        Err(_) => JsonValue::Null,
+                    }
+                }
+                "description" => {
+                    let languages = self.field_languages(field, variables, languages)?;
+                    let descriptions = list_repository_descriptions(&self.pool, &record.id).await?;
+                    match select_description(&descriptions, &languages) {
+                        Some(found) => JsonValue::String(found.description.clone()),
+                        None => JsonValue::Null,
+                    }
+                }
+                "descriptions" => {
+                    let descriptions = list_repository_descriptions(&self.pool, &record.id).await?;
+                    let mut items = Vec::with_capacity(descriptions.len());
+                    for description in &descriptions {
+                        items.push(self.project_repository_description(
+                            description,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    JsonValue::Array(items)
+                }
+                "pushPolicy" => {
+                    let rules = load_policy(&self.pool, &record.id).await?;
+                    self.project_repository_policy(&rules, &field.selection_set, fragments)?
+                }
+                "effectiveSettings" => {
+                    let settings = effective_settings(&self.pool, record).await?;
+                    self.project_effective_settings(&settings, &field.selection_set, fragments)
+                        .await?
+                }
+                "issueClosing" => {
+                    let settings = load_issue_closing(&self.pool, &record.id).await?;
+                    self.project_issue_closing(&settings, &field.selection_set, fragments)?
+                }
+                "projectRoots" => {
+                    JsonValue::from(load_project_roots(&self.pool, &record.id).await?)
+                }
+                // Only linked remotes have a clone record; local repositories resolve to null.
+                "starCount" | "createdAt" | "pushedAt" => {
+                    match discovery_stats(&self.pool, &record.id).await? {
+                        Some(stats) => match field.name.as_str() {
+                            "starCount" => JsonValue::from(stats.star_count),
+                            "createdAt" => {
+                                JsonValue::from(Some(stats.created_at).filter(|t| *t > 0))
+                            }
+                            _ => JsonValue::from(Some(stats.pushed_at).filter(|t| *t > 0)),
+                        },
+                        None => JsonValue::Null,
+                    }
+                }
+                "cloneStatus" => match fetch_remote_clone(&self.pool, &record.id).await? {
+                    Some(clone) => {
+                        self.project_remote_clone_status(&clone, &field.selection_set, fragments)?
+                    }
+                    None => JsonValue::Null,
+                },
+                "tagWatch" => {
+                    let watch = match current_viewer() {
+                        Some(viewer) => {
+                            fetch_tag_watch(&self.pool, &record.id, &viewer.did).await?
+                        }
+                        None => None,
+                    };
+                    match watch {
+                        Some(watch) => {
+                            self.project_remote_tag_watch(&watch, &field.selection_set, fragments)?
+                        }
+                        None => JsonValue::Null,
+                    }
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    /// Language preferences for a field: its own `language` argument when given,
+    /// otherwise those the enclosing query was asked for.
+    fn field_languages(
+        &self,
+        field: &Field<'_, String>,
+        variables: &Vars,
+        inherited: &[String],
+    ) -> Result<Vec<String>> {
+        Ok(self
+            .get_optional_argument(field, "language", variables)?
+            .and_then(|v| v.as_str().map(parse_language_preferences))
+            .unwrap_or_else(|| inherited.to_vec()))
+    }
+
+    fn project_repository_description<'a>(
+        &self,
+        description: &RepositoryDescription,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RepositoryDescription", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RepositoryDescription".to_string()),
+                "language" => match &description.language {
+                    Some(language) => JsonValue::String(language.clone()),
+                    None => JsonValue::Null,
+                },
+                "description" => JsonValue::String(description.description.clone()),
+                "updatedAt" => JsonValue::Number(description.updated_at.into()),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_issue_closing<'a>(
+        &self,
+        settings: &IssueClosingSettings,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "IssueClosingSettings", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("IssueClosingSettings".to_string()),
+                "enabled" => JsonValue::Bool(settings.enabled),
+                "branches" => JsonValue::Array(
+                    settings
+                        .branches
+                        .iter()
+                        .map(|b| JsonValue::String(b.clone()))
+                        .collect(),
+                ),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_repository_policy<'a>(
+        &self,
+        rules: &PolicyRules,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RepositoryPolicy", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RepositoryPolicy".to_string()),
+                "source" => JsonValue::String(policy_source(rules)),
+                "maxFileSize" => match rules.max_file_size {
+                    Some(limit) => JsonValue::Number(limit.into()),
+                    None => JsonValue::Null,
+                },
+                "forbiddenPaths" => JsonValue::Array(
+                    rules
+                        .forbidden_paths
+                        .iter()
+                        .map(|p| JsonValue::String(p.clone()))
+                        .collect(),
+                ),
+                "commitMessagePattern" => match &rules.commit_message_pattern {
+                    Some(pattern) => JsonValue::String(pattern.clone()),
+                    None => JsonValue::Null,
+                },
+                "branchNamePattern" => match &rules.branch_name_pattern {
+                    Some(pattern) => JsonValue::String(pattern.clone()),
+                    None => JsonValue::Null,
+                },
+                "requireSignedCommits" => JsonValue::Bool(rules.require_signed_commits),
+                "forbidMergeCommits" => JsonValue::Bool(rules.forbid_merge_commits),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_group_settings<'a>(
+        &self,
+        settings: &GroupSettings,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "GroupSettings", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("GroupSettings".to_string()),
+                "labels" => match &settings.labels {
+                    Some(labels) => self.project_labels(labels, &field.selection_set, fragments)?,
+                    None => JsonValue::Null,
+                },
+                "branchProtection" => match &settings.branch_protection {
+                    Some(rules) => {
+                        self.project_repository_policy(rules, &field.selection_set, fragments)?
+                    }
+                    None => JsonValue::Null,
+                },
+                "visibility" => match settings.visibility {
+                    Some(visibility) => JsonValue::String(visibility.as_graphql().to_string()),
+                    None => JsonValue::Null,
+                },
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    async fn project_effective_settings<'a>(
+        &self,
+        settings: &EffectiveSettings,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "EffectiveSettings", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("EffectiveSettings".to_string()),
+                "labels" => {
+                    self.project_labels(&settings.labels, &field.selection_set, fragments)?
+                }
+                "branchProtection" => self.project_repository_policy(
+                    &settings.branch_protection,
+                    &field.selection_set,
+                    fragments,
+                )?,
+                "visibility" => JsonValue::String(settings.visibility.as_graphql().to_string()),
+                "provenance" => {
+                    let mut items = Vec::with_capacity(settings.provenance.len());
+                    for provenance in &settings.provenance {
+                        items.push(
+                            self.project_setting_provenance(
+                                provenance,
+                                &field.selection_set,
+                                fragments,
+                            )
+                            .await?,
+                        );
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    async fn project_setting_provenance<'a>(
+        &self,
+        provenance: &Provenance,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "SettingProvenance", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let group_id = match field.name.as_str() {
+                "group" => match &provenance.source {
+                    Source::Group(group_id) => Some(group_id),
+                    _ => None,
+                },
+                "overrides" => provenance.overrides.as_ref(),
+                _ => None,
+            };
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("SettingProvenance".to_string()),
+                "setting" => JsonValue::String(provenance.setting.as_graphql().to_string()),
+                "source" => JsonValue::String(
+                    match provenance.source {
+                        Source::Default => "DEFAULT",
+                        Source::Group(_) => "GROUP",
+                        Source::Repository => "REPOSITORY",
+                    }
+                    .to_string(),
+                ),
+                // A group deleted since the value was copied resolves to null.
+                "group" | "overrides" => match group_id {
+                    Some(group_id) => match get_group_parent(&self.pool, group_id).await? {
+                        Some(group) => {
+                            self.project_group_summary(&group, &field.selection_set, fragments)?
+                        }
+                        None => JsonValue::Null,
+                    },
+                    None => JsonValue::Null,
+                },
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_labels<'a>(
+        &self,
+        labels: &[Label],
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let fields = selection_fields(selection_set, "RepositoryLabel", fragments)?;
+        let mut items = Vec::with_capacity(labels.len());
+        for label in labels {
+            let mut map = Map::new();
+            for field in &fields {
+                let value = match field.name.as_str() {
+                    "__typename" => JsonValue::String("RepositoryLabel".to_string()),
+                    "name" => JsonValue::String(label.name.clone()),
+                    "color" => JsonValue::String(label.color.clone()),
+                    "description" => match &label.description {
+                        Some(description) => JsonValue::String(description.clone()),
+                        None => JsonValue::Null,
+                    },
+                    _ => JsonValue::Null,
+                };
+                map.insert(response_key(field), value);
+            }
+            items.push(JsonValue::Object(map));
+        }
+        Ok(JsonValue::Array(items))
+    }
+
+    fn project_remote_clone_status<'a>(
+        &self,
+        record: &RemoteCloneRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RemoteCloneStatus", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RemoteCloneStatus".to_string()),
+                "state" => JsonValue::String(record.state.to_ascii_uppercase()),
+                "progressPercent" => JsonValue::Number(record.progress_percent().into()),
+                "objectsReceived" => JsonValue::Number(record.objects_received.into()),
+                "objectsTotal" => JsonValue::Number(record.objects_total.into()),
+                "bytesReceived" => JsonValue::Number(record.bytes_received.into()),
+                "attempts" => JsonValue::Number(record.attempts.into()),
+                "lastError" => match &record.last_error {
+                    Some(error) => JsonValue::String(error.clone()),
+                    None => JsonValue::Null,
+                },
+                "updatedAt" => JsonValue::Number(record.updated_at.into()),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_extension_field_stats<'a>(
+        &self,
+        stats: &FieldStats,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "ExtensionFieldStats", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ExtensionFieldStats".to_string()),
+                "extension" => JsonValue::String(stats.extension.clone()),
+                "field" => JsonValue::String(stats.field.clone()),
+                "calls" => JsonValue::Number(stats.calls.into()),
+                "errors" => JsonValue::Number(stats.errors.into()),
+                "avgMs" => JsonValue::from(stats.avg_ms()),
+                "maxMs" => JsonValue::from(stats.max_ms),
+                "fuelConsumed" => JsonValue::from(stats.fuel_consumed as f64),
+                "requestBytes" => JsonValue::from(stats.request_bytes as f64),
+                "responseBytes" => JsonValue::from(stats.response_bytes as f64),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_schema_field_usage<'a>(
+        &self,
+        usage: &FieldUsage,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "SchemaFieldUsage", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("SchemaFieldUsage".to_string()),
+                "coordinate" => JsonValue::String(usage.field.coordinate()),
+                "typeName" => JsonValue::String(usage.field.type_name.clone()),
+                "fieldName" => JsonValue::String(usage.field.field_name.clone()),
+                "count" => JsonValue::from(usage.count as f64),
+                "lastUsedAt" => usage.last_used_at.map_or(JsonValue::Null, JsonValue::from),
+                "clients" => JsonValue::Array(
+                    usage
+                        .clients
+                        .iter()
+                        .cloned()
+                        .map(JsonValue::String)
+                        .collect(),
+                ),
+                "isDeprecated" => JsonValue::Bool(usage.field.deprecation.is_some()),
+                "deprecationReason" => usage
+                    .field
+                    .deprecation
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_extension_upgrade<'a>(
+        &self,
+        upgrade: &UpgradeRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "ExtensionUpgrade", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ExtensionUpgrade".to_string()),
+                "id" => JsonValue::String(global_id::encode("ExtensionUpgrade", &upgrade.id)),
+                "extension" => JsonValue::String(upgrade.extension.clone()),
+                "fromVersion" => JsonValue::String(upgrade.from_version.clone()),
+                "toVersion" => JsonValue::String(upgrade.to_version.clone()),
+                "status" => JsonValue::String(upgrade.status.as_str().to_string()),
+                "backupPath" => JsonValue::String(upgrade.backup_path.clone()),
+                "error" => upgrade
+                    .error
+                    .clone()
+                    .map(JsonValue::String)
+                    .unwrap_or(JsonValue::Null),
+                "startedAt" => JsonValue::from(upgrade.started_at),
+                "finishedAt" => upgrade
+                    .finished_at
+                    .map(JsonValue::from)
+                    .unwrap_or(JsonValue::Null),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_extension_schedule<'a>(
+        &self,
+        schedule: &TaskSchedule,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "ExtensionSchedule", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ExtensionSchedule".to_string()),
+                "extension" => JsonValue::String(schedule.extension.clone()),
+                "name" => JsonValue::String(schedule.name.clone()),
+                "cron" => JsonValue::String(schedule.cron.clone()),
+                "jitterSecs" => JsonValue::from(schedule.jitter_secs),
+                "nextRunAt" => JsonValue::from(schedule.next_run_at),
+                "createdAt" => JsonValue::from(schedule.created_at),
+                "updatedAt" => JsonValue::from(schedule.updated_at),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_extension_task_run<'a>(
+        &self,
+        run: &TaskRun,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "ExtensionTaskRun", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ExtensionTaskRun".to_string()),
+                "id" => JsonValue::String(global_id::encode("ExtensionTaskRun", &run.id)),
+                "extension" => JsonValue::String(run.extension.clone()),
+                "task" => JsonValue::String(run.task.clone()),
+                "status" => JsonValue::String(run.status.as_str().to_string()),
+                "scheduledFor" => JsonValue::from(run.scheduled_for),
+                "startedAt" => JsonValue::from(run.started_at),
+                "finishedAt" => run
+                    .finished_at
+                    .map(JsonValue::from)
+                    .unwrap_or(JsonValue::Null),
+                "durationMs" => run
+                    .duration_ms
+                    .map(JsonValue::from)
+                    .unwrap_or(JsonValue::Null),
+                "error" => run
+                    .error
+                    .clone()
+                    .map(JsonValue::String)
+                    .unwrap_or(JsonValue::Null),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_extension_query_audit<'a>(
+        &self,
+        record: &QueryAuditRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "ExtensionQueryAudit", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ExtensionQueryAudit".to_string()),
+                "id" => JsonValue::String(global_id::encode("ExtensionQueryAudit", &record.id)),
+                "extension" => JsonValue::String(record.extension.clone()),
+                "actorDid" => record
+                    .actor_did
+                    .clone()
+                    .map(JsonValue::String)
+                    .unwrap_or(JsonValue::Null),
+                "repositoryId" => record
+                    .repository_id
+                    .as_deref()
+                    .map(|id| JsonValue::String(global_id::encode("Repository", id)))
+                    .unwrap_or(JsonValue::Null),
+                "query" => JsonValue::String(record.query.clone()),
+                "fields" => JsonValue::Array(
+                    record
+                        .fields
+                        .iter()
+                        .cloned()
+                        .map(JsonValue::String)
+                        .collect(),
+                ),
+                "allowed" => JsonValue::Bool(record.allowed),
+                "error" => record
+                    .error
+                    .clone()
+                    .map(JsonValue::String)
+                    .unwrap_or(JsonValue::Null),
+                "createdAt" => JsonValue::from(record.created_at),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_integrity_report<'a>(
+        &self,
+        report: &IntegrityReport,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "IntegrityReport", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("IntegrityReport".to_string()),
+                "path" => JsonValue::String(report.path.clone()),
+                "healthy" => JsonValue::Bool(report.healthy()),
+                "checkedAt" => JsonValue::from(report.checked_at),
+                "refsChecked" => JsonValue::from(report.refs_checked),
+                "objectsChecked" => JsonValue::from(report.objects_checked),
+                "problems" => {
+                    let mut items = Vec::with_capacity(report.problems.len());
+                    for problem in &report.problems {
+                        let mut problem_map = Map::new();
+                        for problem_field in
+                            selection_fields(&field.selection_set, "IntegrityProblem", fragments)?
+                        {
+                            let value = match problem_field.name.as_str() {
+                                "__typename" => JsonValue::String("IntegrityProblem".to_string()),
+                                "kind" => JsonValue::String(problem.kind.as_graphql().to_string()),
+                                "oid" => problem
+                                    .oid
+                                    .clone()
+                                    .map(JsonValue::String)
+                                    .unwrap_or(JsonValue::Null),
+                                "reference" => problem
+                                    .reference
+                                    .clone()
+                                    .map(JsonValue::String)
+                                    .unwrap_or(JsonValue::Null),
+                                "detail" => JsonValue::String(problem.detail.clone()),
+                                _ => JsonValue::Null,
+                            };
+                            problem_map.insert(response_key(problem_field), value);
+                        }
+                        items.push(JsonValue::Object(problem_map));
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_repair_report<'a>(
+        &self,
+        report: &RepairReport,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RepairReport", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RepairReport".to_string()),
+                "repaired" => JsonValue::Bool(report.repaired()),
+                "before" => {
+                    self.project_integrity_report(&report.before, &field.selection_set, fragments)?
+                }
+                "after" => {
+                    self.project_integrity_report(&report.after, &field.selection_set, fragments)?
+                }
+                "quarantined" => JsonValue::from(report.quarantined.clone()),
+                "attempts" => {
+                    let mut items = Vec::with_capacity(report.attempts.len());
+                    for attempt in &report.attempts {
+                        let mut attempt_map = Map::new();
+                        for attempt_field in
+                            selection_fields(&field.selection_set, "RepairAttempt", fragments)?
+                        {
+                            let value = match attempt_field.name.as_str() {
+                                "__typename" => JsonValue::String("RepairAttempt".to_string()),
+                                "source" => {
+                                    JsonValue::String(attempt.source.kind_graphql().to_string())
+                                }
+                                "location" => JsonValue::String(attempt.source.location()),
+                                "error" => attempt
+                                    .error
+                                    .clone()
+                                    .map(JsonValue::String)
+                                    .unwrap_or(JsonValue::Null),
+                                _ => JsonValue::Null,
+                            };
+                            attempt_map.insert(response_key(attempt_field), value);
+                        }
+                        items.push(JsonValue::Object(attempt_map));
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_extension_cache_check<'a>(
+        &self,
+        check: &CacheCheck,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "ExtensionCacheCheck", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ExtensionCacheCheck".to_string()),
+                "extension" => JsonValue::String(check.extension.clone()),
+                "state" => JsonValue::String(check.state.as_str().to_string()),
+                "checkedAt" => check
+                    .checked_at
+                    .map(JsonValue::from)
+                    .unwrap_or(JsonValue::Null),
+                "detail" => check
+                    .detail
+                    .clone()
+                    .map(JsonValue::String)
+                    .unwrap_or(JsonValue::Null),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_extension_reload<'a>(
+        &self,
+        reload: &ExtensionReload,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "ExtensionReload", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ExtensionReload".to_string()),
+                "extension" => JsonValue::String(reload.extension.clone()),
+                "loaded" => JsonValue::Bool(reload.loaded),
+                "version" => reload
+                    .version
+                    .clone()
+                    .map(JsonValue::String)
+                    .unwrap_or(JsonValue::Null),
+                "previousVersion" => reload
+                    .previous_version
+                    .clone()
+                    .map(JsonValue::String)
+                    .unwrap_or(JsonValue::Null),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_announcements<'a>(
+        &self,
+        announcements: &[Announcement],
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let items = announcements
+            .iter()
+            .map(|announcement| self.project_announcement(announcement, selection_set, fragments))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(JsonValue::Array(items))
+    }
+
+    fn project_announcement<'a>(
+        &self,
+        announcement: &Announcement,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "Announcement", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("Announcement".to_string()),
+                "id" => JsonValue::String(global_id::encode("Announcement", &announcement.id)),
+                "message" => JsonValue::String(announcement.message.clone()),
+                "severity" => JsonValue::String(announcement.severity.as_graphql().to_string()),
+                "startsAt" => announcement
+                    .starts_at
+                    .map(JsonValue::from)
+                    .unwrap_or(JsonValue::Null),
+                "endsAt" => announcement
+                    .ends_at
+                    .map(JsonValue::from)
+                    .unwrap_or(JsonValue::Null),
+                "createdAt" => JsonValue::from(announcement.created_at),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_instance_settings<'a>(
+        &self,
+        settings: &InstanceSettings,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "InstanceSettings", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("InstanceSettings".to_string()),
+                "name" => JsonValue::String(settings.name.clone()),
+                "description" => settings
+                    .description
+                    .clone()
+                    .map(JsonValue::String)
+                    .unwrap_or(JsonValue::Null),
+                "defaultVisibility" => {
+                    JsonValue::String(settings.default_visibility.as_graphql().to_string())
+                }
+                "setupRequired" => JsonValue::Bool(settings.setup_required()),
+                "setupCompletedAt" => settings
+                    .setup_completed_at
+                    .map(JsonValue::from)
+                    .unwrap_or(JsonValue::Null),
+                "updatedAt" => settings
+                    .updated_at
+                    .map(JsonValue::from)
+                    .unwrap_or(JsonValue::Null),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_maintenance_mode<'a>(
+        &self,
+        mode: &MaintenanceMode,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "MaintenanceMode", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("MaintenanceMode".to_string()),
+                "enabled" => JsonValue::Bool(mode.enabled),
+                "message" => JsonValue::String(mode.display_message().to_string()),
+                "updatedAt" => mode
+                    .updated_at
+                    .map(JsonValue::from)
+                    .unwrap_or(JsonValue::Null),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_feature_flag<'a>(
+        &self,
+        flag: &FeatureFlag,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "FeatureFlagDefinition", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("FeatureFlagDefinition".to_string()),
+                "key" => JsonValue::String(flag.key.clone()),
+                "description" => flag
+                    .description
+                    .clone()
+                    .map(JsonValue::String)
+                    .unwrap_or(JsonValue::Null),
+                "enabled" => JsonValue::Bool(flag.enabled),
+                "rolloutPercent" => JsonValue::from(flag.rollout_percent),
+                "overrides" => {
+                    let override_fields =
+                        selection_fields(&field.selection_set, "FeatureFlagOverride", fragments)?;
+                    let mut overrides: Vec<_> = flag.overrides.iter().collect();
+                    overrides.sort();
+                    let items = overrides
+                        .into_iter()
+                        .map(|(repository_id, enabled)| {
+                            let mut item = Map::new();
+                            for field in &override_fields {
+                                let value = match field.name.as_str() {
+                                    "__typename" => {
+                                        JsonValue::String("FeatureFlagOverride".to_string())
+                                    }
+                                    "repositoryId" => JsonValue::String(global_id::encode(
+                                        "Repository",
+                                        repository_id,
+                                    )),
+                                    "enabled" => JsonValue::Bool(*enabled),
+                                    _ => JsonValue::Null,
+                                };
+                                item.insert(response_key(field), value);
+                            }
+                            JsonValue::Object(item)
+                        })
+                        .collect();
+                    JsonValue::Array(items)
+                }
+                "updatedAt" => JsonValue::from(flag.updated_at),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_remote_tag_watch<'a>(
+        &self,
+        record: &TagWatchRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RemoteTagWatch", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RemoteTagWatch".to_string()),
+                "semverRange" => match &record.semver_range {
+                    Some(range) => JsonValue::String(range.clone()),
+                    None => JsonValue::Null,
+                },
+                "createdAt" => JsonValue::Number(record.created_at.into()),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_policy_check_result<'a>(
+        &self,
+        report: &PolicyReport,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "PolicyCheckResult", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("PolicyCheckResult".to_string()),
+                "allowed" => JsonValue::Bool(report.allowed()),
+                "commitsChecked" => JsonValue::Number(report.commits_checked.into()),
+                "violations" => {
+                    let mut items = Vec::with_capacity(report.violations.len());
+                    for violation in &report.violations {
+                        items.push(self.project_policy_violation(
+                            violation,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_policy_violation<'a>(
+        &self,
+        violation: &PolicyViolation,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "PolicyViolation", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("PolicyViolation".to_string()),
+                "refName" => JsonValue::String(violation.ref_name.clone()),
+                "commit" => match &violation.commit {
+                    Some(commit) => JsonValue::String(commit.clone()),
+                    None => JsonValue::Null,
+                },
+                "rule" => JsonValue::String(violation.rule.to_string()),
+                "message" => JsonValue::String(violation.message.clone()),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_policy_validation<'a>(
+        &self,
+        violations: &[RuleViolation],
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "PolicyValidation", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("PolicyValidation".to_string()),
+                "valid" => JsonValue::Bool(violations.is_empty()),
+                "violations" => {
+                    let mut items = Vec::with_capacity(violations.len());
+                    for violation in violations {
+                        items.push(self.project_rule_violation(
+                            violation,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_rule_violation<'a>(
+        &self,
+        violation: &RuleViolation,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "PolicyRuleViolation", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("PolicyRuleViolation".to_string()),
+                "rule" => JsonValue::String(violation.rule.to_string()),
+                "message" => JsonValue::String(violation.message.clone()),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_saved_search<'a>(
+        &self,
+        record: &SavedSearchRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "SavedSearch", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("SavedSearch".to_string()),
+                "id" => JsonValue::String(global_id::encode("SavedSearch", &record.id)),
+                "name" => JsonValue::String(record.name.clone()),
+                "scope" => match SearchScope::parse(&record.scope) {
+                    Ok(scope) => JsonValue::String(scope.as_graphql().to_string()),
+                    Err(_) => JsonValue::Null,
+                },
+                "query" => JsonValue::String(record.query.clone()),
+                "createdAt" => JsonValue::Number(record.created_at.into()),
+                "updatedAt" => JsonValue::Number(record.updated_at.into()),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    async fn project_account_export<'a>(
+        &self,
+        export: &AccountExport,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "AccountExport", fragments)?;
+        let optional =
+            |value: Option<i64>| value.map_or(JsonValue::Null, |v| JsonValue::Number(v.into()));
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("AccountExport".to_string()),
+                "id" => JsonValue::String(global_id::encode("AccountExport", &export.id)),
+                "status" => JsonValue::String(export.status.to_uppercase()),
+                "createdAt" => JsonValue::Number(export.created_at.into()),
+                "completedAt" => optional(export.completed_at),
+                "expiresAt" => optional(export.expires_at),
+                "sizeBytes" => optional(export.size_bytes),
+                "error" => export
+                    .error
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "downloadUrl" => {
+                    let config = crate::config::current();
+                    download_url(
+                        &self.pool,
+                        export,
+                        &config.server.public_base_url,
+                        &config.account_export,
+                        unix_now(),
+                    )
+                    .await?
+                    .map_or(JsonValue::Null, JsonValue::String)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_repository_summary<'a>(
+        &self,
+        summary: &RepositorySummary,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RepositorySummary", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RepositorySummary".to_string()),
+                "id" => JsonValue::String(global_id::encode("Repository", &summary.id)),
+                "slug" => JsonValue::String(summary.slug.clone()),
+                "isRemote" => JsonValue::Bool(summary.remote_url.is_some()),
+                "remoteUrl" => match &summary.remote_url {
+                    Some(url) => JsonValue::String(url.clone()),
+                    None => JsonValue::Null,
+                },
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_merge_preview<'a>(
+        &self,
+        preview: &MergePreview,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "MergePreview", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("MergePreview".to_string()),
+                "baseOid" => JsonValue::String(preview.base_oid.clone()),
+                "headOid" => JsonValue::String(preview.head_oid.clone()),
+                "mergeable" => JsonValue::Bool(preview.mergeable),
+                "cached" => JsonValue::Bool(preview.cached),
+                "conflicts" => {
+                    let mut items = Vec::with_capacity(preview.conflicts.len());
+                    for conflict in &preview.conflicts {
+                        items.push(self.project_merge_conflict(
+                            conflict,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_merge_conflict<'a>(
+        &self,
+        conflict: &MergeConflict,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "MergeConflict", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("MergeConflict".to_string()),
+                "path" => JsonValue::String(conflict.path.clone()),
+                "kind" => JsonValue::String(conflict.kind.as_graphql().to_string()),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_commit_operation_result<'a>(
+        &self,
+        outcome: &CommitOperationOutcome,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "CommitOperationResult", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("CommitOperationResult".to_string()),
+                "applied" => JsonValue::Bool(outcome.applied()),
+                "branch" => JsonValue::String(outcome.branch.clone()),
+                "sourceOid" => JsonValue::String(outcome.source_oid.clone()),
+                "previousOid" => JsonValue::String(outcome.previous_oid.clone()),
+                "commitOid" => match &outcome.commit_oid {
+                    Some(oid) => JsonValue::String(oid.clone()),
+                    None => JsonValue::Null,
+                },
+                "conflicts" => {
+                    let mut items = Vec::with_capacity(outcome.conflicts.len());
+                    for conflict in &outcome.conflicts {
+                        items.push(self.project_merge_conflict(
+                            conflict,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    JsonValue::Array(items)
+                }
+                "violations" => {
+                    let mut items = Vec::with_capacity(outcome.violations.len());
+                    for violation in &outcome.violations {
+                        items.push(self.project_policy_violation(
+                            violation,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_suggestion_result<'a>(
+        &self,
+        outcome: &SuggestionOutcome,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "SuggestionResult", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("SuggestionResult".to_string()),
+                "applied" => JsonValue::Bool(outcome.applied()),
+                "branch" => JsonValue::String(outcome.branch.clone()),
+                "filePath" => JsonValue::String(outcome.file_path.clone()),
+                "previousOid" => JsonValue::String(outcome.previous_oid.clone()),
+                "commitOid" => match &outcome.commit_oid {
+                    Some(oid) => JsonValue::String(oid.clone()),
+                    None => JsonValue::Null,
+                },
+                "outdated" => JsonValue::Bool(outcome.outdated),
+                "violations" => {
+                    let mut items = Vec::with_capacity(outcome.violations.len());
+                    for violation in &outcome.violations {
+                        items.push(self.project_policy_violation(
+                            violation,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_mirror_webhook<'a>(
+        &self,
+        url: &str,
+        secret: &str,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "MirrorWebhook", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("MirrorWebhook".to_string()),
+                "url" => JsonValue::String(url.to_string()),
+                "secret" => JsonValue::String(secret.to_string()),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_moderation_item<'a>(
+        &self,
+        item: &ModerationItem,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let optional = |value: &Option<String>| match value {
+            Some(value) => JsonValue::String(value.clone()),
+            None => JsonValue::Null,
+        };
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "ModerationItem", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ModerationItem".to_string()),
+                "id" => JsonValue::String(global_id::encode("ModerationItem", &item.id)),
+                "kind" => JsonValue::String(item.kind.to_uppercase()),
+                "source" => JsonValue::String(item.source.clone()),
+                "subjectId" => optional(&item.subject_id),
+                "actorDid" => optional(&item.actor_did),
+                "repositoryId" => match &item.repository_id {
+                    Some(id) => JsonValue::String(global_id::encode("Repository", id)),
+                    None => JsonValue::Null,
+                },
+                "title" => optional(&item.title),
+                "body" => optional(&item.body),
+                "reason" => optional(&item.reason),
+                "status" => JsonValue::String(item.status.to_uppercase()),
+                "createdAt" => JsonValue::from(item.created_at),
+                "resolvedAt" => match item.resolved_at {
+                    Some(at) => JsonValue::from(at),
+                    None => JsonValue::Null,
+                },
+                "resolvedBy" => optional(&item.resolved_by),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_compliance_record<'a>(
+        &self,
+        record: &ArchivalRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "ComplianceRecord", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ComplianceRecord".to_string()),
+                "id" => JsonValue::String(global_id::encode("ComplianceRecord", &record.id)),
+                "subjectKind" => JsonValue::String(record.subject_kind.clone()),
+                "subjectId" => JsonValue::String(record.subject_id.clone()),
+                "source" => JsonValue::String(record.source.clone()),
+                "actorDid" => match &record.actor_did {
+                    Some(did) => JsonValue::String(did.clone()),
+                    None => JsonValue::Null,
+                },
+                "summary" => JsonValue::String(record.summary.clone()),
+                "deletedAt" => JsonValue::from(record.deleted_at),
+                "expiresAt" => match record.expires_at {
+                    Some(at) => JsonValue::from(at),
+                    None => JsonValue::Null,
+                },
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    async fn project_audit_log<'a>(
+        &self,
+        page: &Page<AuditEntry>,
+        filter: &AuditFilter,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "AuditLogConnection", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("AuditLogConnection".to_string()),
+                "totalCount" => JsonValue::from(count_audit_log(&self.pool, filter).await?),
+                "pageInfo" => {
+                    project_page_info(page, AuditEntry::cursor, &field.selection_set, fragments)?
+                }
+                "nodes" => JsonValue::Array(
+                    page.items
+                        .iter()
+                        .map(|entry| {
+                            self.project_audit_entry(entry, &field.selection_set, fragments)
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                "edges" => {
+                    let edge_fields =
+                        selection_fields(&field.selection_set, "AuditLogEdge", fragments)?;
+                    let mut items = Vec::with_capacity(page.items.len());
+                    for entry in &page.items {
+                        let mut edge = Map::new();
+                        for edge_field in &edge_fields {
+                            let value = match edge_field.name.as_str() {
+                                "__typename" => JsonValue::String("AuditLogEdge".to_string()),
+                                "cursor" => JsonValue::String(entry.cursor()),
+                                "node" => self.project_audit_entry(
+                                    entry,
+                                    &edge_field.selection_set,
+                                    fragments,
+                                )?,
+                                _ => JsonValue::Null,
+                            };
+                            edge.insert(response_key(edge_field), value);
+                        }
+                        items.push(JsonValue::Object(edge));
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_audit_entry<'a>(
+        &self,
+        entry: &AuditEntry,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "AuditLogEntry", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("AuditLogEntry".to_string()),
+                "id" => JsonValue::String(global_id::encode("AuditLogEntry", &entry.id)),
+                "action" => JsonValue::String(entry.action.clone()),
+                "category" => JsonValue::String(entry.category.clone()),
+                "source" => JsonValue::String(entry.source.clone()),
+                "actorDid" => entry
+                    .actor_did
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "repositoryId" => entry
+                    .repository_id
+                    .as_deref()
+                    .map_or(JsonValue::Null, |id| {
+                        JsonValue::String(global_id::encode("Repository", id))
+                    }),
+                "data" => JsonValue::String(entry.data.clone()),
+                "createdAt" => JsonValue::from(entry.created_at),
+                "expiresAt" => entry.expires_at.map_or(JsonValue::Null, JsonValue::from),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_audit_export<'a>(
+        &self,
+        export: &AuditExport,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let optional = |value: Option<i64>| value.map_or(JsonValue::Null, JsonValue::from);
+        for field in selection_fields(selection_set, "AuditLogExport", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("AuditLogExport".to_string()),
+                "id" => JsonValue::String(global_id::encode("AuditLogExport", &export.id)),
+                "format" => JsonValue::String(export.format.to_uppercase()),
+                "status" => JsonValue::String(export.status.to_uppercase()),
+                "requestedBy" => JsonValue::String(export.requested_by.clone()),
+                "rowCount" => optional(export.row_count),
+                "sizeBytes" => optional(export.size_bytes),
+                "error" => export
+                    .error
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "createdAt" => JsonValue::from(export.created_at),
+                "completedAt" => optional(export.completed_at),
+                "expiresAt" => optional(export.expires_at),
+                "downloadUrl" if export.is_downloadable(unix_now()) => JsonValue::String(format!(
+                    "{}/audit/exports/{}",
+                    crate::config::current()
+                        .server
+                        .public_base_url
+                        .trim_end_matches('/'),
+                    export.id
+                )),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_group_usage<'a>(
+        &self,
+        usage: &GroupUsage,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "GroupUsage", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("GroupUsage".to_string()),
+                "group" => JsonValue::String(usage.group.clone()),
+                "period" => JsonValue::String(usage.period.clone()),
+                "resources" => JsonValue::Array(
+                    usage
+                        .resources
+                        .iter()
+                        .map(|resource| {
+                            self.project_resource_usage(resource, &field.selection_set, fragments)
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_resource_usage<'a>(
+        &self,
+        usage: &ResourceUsage,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let optional = |value: Option<u64>| value.map_or(JsonValue::Null, JsonValue::from);
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "ResourceUsage", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ResourceUsage".to_string()),
+                "resource" => JsonValue::String(usage.resource.as_graphql().to_string()),
+                "used" => JsonValue::from(usage.used),
+                "unit" => JsonValue::String(usage.resource.unit().to_string()),
+                "softLimit" => optional(usage.limit.soft),
+                "hardLimit" => optional(usage.limit.hard),
+                "state" => JsonValue::String(usage.state.as_graphql().to_string()),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_usage_export<'a>(
+        &self,
+        export: &UsageExport,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "UsageExport", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("UsageExport".to_string()),
+                "id" => JsonValue::String(global_id::encode("UsageExport", &export.id)),
+                "date" => JsonValue::String(export.date()),
+                "format" => JsonValue::String(export.format.to_uppercase()),
+                "rowCount" => JsonValue::from(export.row_count),
+                "sizeBytes" => JsonValue::from(export.size_bytes),
+                "createdAt" => JsonValue::from(export.created_at),
+                "expiresAt" => JsonValue::from(export.expires_at),
+                "downloadUrl" if export.is_downloadable(unix_now()) => {
+                    JsonValue::String(crate::metering::download_url(&export.id))
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_file_match<'a>(
+        &self,
+        file: &FileMatch,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "FileMatch", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("FileMatch".to_string()),
+                "path" => JsonValue::String(file.path.clone()),
+                "name" => JsonValue::String(file.name().to_string()),
+                "score" => JsonValue::from(file.score),
+                "matchedIndices" => JsonValue::from(file.positions.clone()),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_code_symbol<'a>(
+        &self,
+        symbol: &Symbol,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "CodeSymbol", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("CodeSymbol".to_string()),
+                "name" => JsonValue::String(symbol.name.clone()),
+                "kind" => JsonValue::String(symbol.kind.clone()),
+                "language" => JsonValue::String(symbol.language.clone()),
+                "filePath" => JsonValue::String(symbol.file_path.clone()),
+                "line" => JsonValue::from(symbol.start_line),
+                "column" => JsonValue::from(symbol.start_column),
+                "endLine" => JsonValue::from(symbol.end_line),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_symbol_index<'a>(
+        &self,
+        status: &SymbolIndexStatus,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "SymbolIndex", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("SymbolIndex".to_string()),
+                "commit" => status
+                    .commit_oid
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "branch" => status
+                    .branch
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "indexedAt" => status.indexed_at.map_or(JsonValue::Null, JsonValue::from),
+                "pending" => JsonValue::Bool(status.pending()),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    async fn project_code_match<'a>(
+        &self,
+        found: &CodeMatch,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+        variables: &Vars,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "CodeSearchMatch", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("CodeSearchMatch".to_string()),
+                "repository" => {
+                    match get_repository_by_id(&self.pool, &found.repository_id).await? {
+                        Some(record) => {
+                            self.project_repository_node(
+                                &record,
+                                &field.selection_set,
+                                fragments,
+                                variables,
+                                &[],
+                            )
+                            .await?
+                        }
+                        None => JsonValue::Null,
+                    }
+                }
+                "filePath" => JsonValue::String(found.file_path.clone()),
+                "language" => found
+                    .language
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "matchCount" => JsonValue::from(found.match_count),
+                "lines" => JsonValue::Array(
+                    found
+                        .lines
+                        .iter()
+                        .map(|line| self.project_code_line(line, &field.selection_set, fragments))
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_code_line<'a>(
+        &self,
+        line: &CodeLine,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "CodeSearchLine", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("CodeSearchLine".to_string()),
+                "number" => JsonValue::from(line.number),
+                "text" => JsonValue::String(line.text.clone()),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_contributor_stats<'a>(
+        &self,
+        status: &ContributorStatsStatus,
+        authors: &[ContributorStat],
+        weeks: &[CodeFrequencyWeek],
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "ContributorStats", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ContributorStats".to_string()),
+                "commit" => status
+                    .commit_oid
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "branch" => status
+                    .branch
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "computedAt" => status.computed_at.map_or(JsonValue::Null, JsonValue::from),
+                "pending" => JsonValue::Bool(status.pending()),
+                "contributors" => JsonValue::Array(
+                    authors
+                        .iter()
+                        .map(|author| {
+                            self.project_contributor_stat(author, &field.selection_set, fragments)
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                "weeks" => JsonValue::Array(
+                    weeks
+                        .iter()
+                        .map(|week| {
+                            self.project_code_frequency_week(week, &field.selection_set, fragments)
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_contributor_stat<'a>(
+        &self,
+        author: &ContributorStat,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "ContributorStat", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ContributorStat".to_string()),
+                "name" => JsonValue::String(author.name.clone()),
+                "email" => JsonValue::String(author.email.clone()),
+                "commits" => JsonValue::from(author.commits),
+                "additions" => JsonValue::from(author.additions),
+                "deletions" => JsonValue::from(author.deletions),
+                "firstCommitAt" => JsonValue::from(author.first_commit_at),
+                "lastCommitAt" => JsonValue::from(author.last_commit_at),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_code_frequency_week<'a>(
+        &self,
+        week: &CodeFrequencyWeek,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "CodeFrequencyWeek", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("CodeFrequencyWeek".to_string()),
+                "weekStart" => JsonValue::from(week.week_start),
+                "commits" => JsonValue::from(week.commits),
+                "additions" => JsonValue::from(week.additions),
+                "deletions" => JsonValue::from(week.deletions),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_repository_project<'a>(
+        &self,
+        project: &Project,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RepositoryProject", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RepositoryProject".to_string()),
+                "root" => JsonValue::String(project.root.clone()),
+                "name" => JsonValue::String(project.name.clone()),
+                "manifests" => JsonValue::from(project.manifests.clone()),
+                "configured" => JsonValue::Bool(project.configured),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_commit_graph<'a>(
+        &self,
+        graph: &CommitGraph,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "CommitGraph", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("CommitGraph".to_string()),
+                "tips" => JsonValue::from(graph.tips.clone()),
+                "nodes" => {
+                    let mut items = Vec::with_capacity(graph.nodes.len());
+                    for node in &graph.nodes {
+                        let mut node_map = Map::new();
+                        for node_field in
+                            selection_fields(&field.selection_set, "CommitGraphNode", fragments)?
+                        {
+                            let value = match node_field.name.as_str() {
+                                "__typename" => JsonValue::String("CommitGraphNode".to_string()),
+                                "oid" => JsonValue::String(node.oid.clone()),
+                                "parents" => JsonValue::from(node.parents.clone()),
+                                "refs" => JsonValue::from(node.refs.clone()),
+                                "lane" => JsonValue::from(node.lane),
+                                "summary" => JsonValue::String(node.summary.clone()),
+                                "authorName" => JsonValue::String(node.author_name.clone()),
+                                "committedAt" => JsonValue::from(node.committed_at),
+                                _ => JsonValue::Null,
+                            };
+                            node_map.insert(response_key(node_field), value);
+                        }
+                        items.push(JsonValue::Object(node_map));
+                    }
+                    JsonValue::Array(items)
+                }
+                "lanes" => JsonValue::from(graph.lanes),
+                "truncated" => JsonValue::Bool(graph.truncated),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_commit<'a>(
+        &self,
+        commit: &RepositoryCommit,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let changes = commit.changes.clone().unwrap_or_default();
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "Commit", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("Commit".to_string()),
+                "oid" => JsonValue::String(commit.oid.clone()),
+                "summary" => JsonValue::String(commit.summary.clone()),
+                "message" => JsonValue::String(commit.message.clone()),
+                "author" => {
+                    self.project_commit_signature(&commit.author, &field.selection_set, fragments)?
+                }
+                "committer" => self.project_commit_signature(
+                    &commit.committer,
+                    &field.selection_set,
+                    fragments,
+                )?,
+                "parents" => JsonValue::from(commit.parents.clone()),
+                "stats" => self.project_commit_stats(&changes, &field.selection_set, fragments)?,
+                "files" => self.project_commit_files(&changes, &field.selection_set, fragments)?,
+                "filesTruncated" => JsonValue::Bool(changes.truncated),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_commit_signature<'a>(
+        &self,
+        signature: &CommitSignature,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "CommitSignature", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("CommitSignature".to_string()),
+                "name" => JsonValue::String(signature.name.clone()),
+                "email" => JsonValue::String(signature.email.clone()),
+                "time" => JsonValue::from(signature.time),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_file_blame<'a>(
+        &self,
+        blame: &FileBlame,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "FileBlame", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("FileBlame".to_string()),
+                "oid" => JsonValue::String(blame.oid.clone()),
+                "path" => JsonValue::String(blame.path.clone()),
+                "lineCount" => JsonValue::from(blame.line_count),
+                "ranges" => {
+                    let mut ranges = Vec::with_capacity(blame.ranges.len());
+                    for range in &blame.ranges {
+                        let mut range_map = Map::new();
+                        for range_field in
+                            selection_fields(&field.selection_set, "BlameRange", fragments)?
+                        {
+                            let value = match range_field.name.as_str() {
+                                "__typename" => JsonValue::String("BlameRange".to_string()),
+                                "startLine" => JsonValue::from(range.start_line),
+                                "endLine" => JsonValue::from(range.end_line),
+                                "originalStartLine" => JsonValue::from(range.original_start_line),
+                                "originalPath" => range
+                                    .original_path
+                                    .clone()
+                                    .map_or(JsonValue::Null, JsonValue::String),
+                                "oid" => JsonValue::String(range.oid.clone()),
+                                "summary" => JsonValue::String(range.summary.clone()),
+                                "author" => self.project_commit_signature(
+                                    &range.author,
+                                    &range_field.selection_set,
+                                    fragments,
+                                )?,
+                                _ => JsonValue::Null,
+                            };
+                            range_map.insert(response_key(range_field), value);
+                        }
+                        ranges.push(JsonValue::Object(range_map));
+                    }
+                    JsonValue::Array(ranges)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_commit_stats<'a>(
+        &self,
+        changes: &CommitChanges,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "CommitStats", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("CommitStats".to_string()),
+                "filesChanged" => JsonValue::from(changes.files_changed),
+                "additions" => JsonValue::from(changes.additions),
+                "deletions" => JsonValue::from(changes.deletions),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_commit_files<'a>(
+        &self,
+        changes: &CommitChanges,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let fields = selection_fields(selection_set, "CommitFile", fragments)?;
+        let mut items = Vec::with_capacity(changes.files.len());
+        for file in &changes.files {
+            let mut map = Map::new();
+            for field in &fields {
+                let value = match field.name.as_str() {
+                    "__typename" => JsonValue::String("CommitFile".to_string()),
+                    "path" => JsonValue::String(file.path.clone()),
+                    "previousPath" => JsonValue::from(file.previous_path.clone()),
+                    "status" => JsonValue::String(file.status.as_graphql().to_string()),
+                    "additions" => JsonValue::from(file.additions),
+                    "deletions" => JsonValue::from(file.deletions),
+                    "binary" => JsonValue::Bool(file.binary),
+                    "patch" => JsonValue::from(file.patch.clone()),
+                    "patchTruncated" => JsonValue::Bool(file.patch_truncated),
+                    _ => JsonValue::Null,
+                };
+                map.insert(response_key(field), value);
+            }
+            items.push(JsonValue::Object(map));
+        }
+        Ok(JsonValue::Array(items))
+    }
+
+    fn project_diff<'a>(
+        &self,
+        diff: &RepositoryDiff,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "Diff", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("Diff".to_string()),
+                "baseOid" => JsonValue::String(diff.base_oid.clone()),
+                "headOid" => JsonValue::String(diff.head_oid.clone()),
+                "mergeBase" => JsonValue::from(diff.merge_base.clone()),
+                "stats" => {
+                    self.project_commit_stats(&diff.changes, &field.selection_set, fragments)?
+                }
+                "files" => {
+                    self.project_commit_files(&diff.changes, &field.selection_set, fragments)?
+                }
+                "filesTruncated" => JsonValue::Bool(diff.changes.truncated),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    /// How much of each changed file a `Commit` or `Diff` selection needs. Diffing is the
+    /// expensive part, so nothing is read unless the changes are selected, and patches
+    /// only when they are.
+    fn change_detail<'a>(
+        &self,
+        selection_set: &'a SelectionSet<'a, String>,
+        type_name: &str,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<Option<ChangeDetail>> {
+        let mut detail = None;
+        for field in selection_fields(selection_set, type_name, fragments)? {
+            match field.name.as_str() {
+                "files" => {
+                    let wants_patch =
+                        selection_fields(&field.selection_set, "CommitFile", fragments)?
+                            .iter()
+                            .any(|selected| {
+                                matches!(selected.name.as_str(), "patch" | "patchTruncated")
+                            });
+                    if wants_patch {
+                        return Ok(Some(ChangeDetail::Patches));
+                    }
+                    detail = Some(ChangeDetail::Stats);
+                }
+                "stats" | "filesTruncated" => detail = Some(ChangeDetail::Stats),
+                _ => {}
+            }
+        }
+        Ok(detail)
+    }
+
+    fn project_comparison<'a>(
+        &self,
+        comparison: &Comparison,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "RefComparison", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RefComparison".to_string()),
+                "baseOid" => JsonValue::String(comparison.base_oid.clone()),
+                "headOid" => JsonValue::String(comparison.head_oid.clone()),
+                "mergeBase" => JsonValue::from(comparison.merge_base.clone()),
+                "aheadBy" => JsonValue::from(comparison.ahead_by),
+                "behindBy" => JsonValue::from(comparison.behind_by),
+                "commits" => {
+                    let mut items = Vec::with_capacity(comparison.commits.len());
+                    for commit in &comparison.commits {
+                        let mut commit_map = Map::new();
+                        for commit_field in
+                            selection_fields(&field.selection_set, "ComparedCommit", fragments)?
+                        {
+                            let value = match commit_field.name.as_str() {
+                                "__typename" => JsonValue::String("ComparedCommit".to_string()),
+                                "oid" => JsonValue::String(commit.oid.clone()),
+                                "parents" => JsonValue::from(commit.parents.clone()),
+                                "summary" => JsonValue::String(commit.summary.clone()),
+                                "authorName" => JsonValue::String(commit.author_name.clone()),
+                                "committedAt" => JsonValue::from(commit.committed_at),
+                                _ => JsonValue::Null,
+                            };
+                            commit_map.insert(response_key(commit_field), value);
+                        }
+                        items.push(JsonValue::Object(commit_map));
+                    }
+                    JsonValue::Array(items)
+                }
+                "truncated" => JsonValue::Bool(comparison.truncated),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    async fn project_repository_search<'a>(
+        &self,
+        page: &Page<DiscoveredRepository>,
+        sort: DiscoverySort,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+        variables: &Vars,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "RepositorySearchConnection", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RepositorySearchConnection".to_string()),
+                "pageInfo" => project_page_info(
+                    page,
+                    |item| item.cursor(sort),
+                    &field.selection_set,
+                    fragments,
+                )?,
+                "nodes" => {
+                    let mut items = Vec::with_capacity(page.items.len());
+                    for item in &page.items {
+                        items.push(
+                            self.project_repository_node(
+                                &item.record,
+                                &field.selection_set,
+                                fragments,
+                                variables,
+                                &[],
+                            )
+                            .await?,
+                        );
+                    }
+                    JsonValue::Array(items)
+                }
+                "edges" => {
+                    let edge_fields =
+                        selection_fields(&field.selection_set, "RepositorySearchEdge", fragments)?;
+                    let mut items = Vec::with_capacity(page.items.len());
+                    for item in &page.items {
+                        let mut edge = Map::new();
+                        for edge_field in &edge_fields {
+                            let value = match edge_field.name.as_str() {
+                                "__typename" => {
+                                    JsonValue::String("RepositorySearchEdge".to_string())
+                                }
+                                "cursor" => JsonValue::String(item.cursor(sort)),
+                                "node" => {
+                                    self.project_repository_node(
+                                        &item.record,
+                                        &edge_field.selection_set,
+                                        fragments,
+                                        variables,
+                                        &[],
+                                    )
+                                    .await?
+                                }
+                                "score" => match &item.score {
+                                    Some(score) => self.project_search_score(
+                                        score,
+                                        &edge_field.selection_set,
+                                        fragments,
+                                    )?,
+                                    None => JsonValue::Null,
+                                },
+                                _ => JsonValue::Null,
+                            };
+                            edge.insert(response_key(edge_field), value);
+                        }
+                        items.push(JsonValue::Object(edge));
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_search_score<'a>(
+        &self,
+        score: &SearchScore,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "RepositorySearchScore", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RepositorySearchScore".to_string()),
+                "total" => JsonValue::from(score.total),
+                "name" => JsonValue::from(score.name),
+                "description" => JsonValue::from(score.description),
+                "exact" => JsonValue::from(score.exact),
+                "matches" => JsonValue::Bool(score.matches()),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_commit_search<'a>(
+        &self,
+        page: &Page<CommitMatch>,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "CommitSearchConnection", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("CommitSearchConnection".to_string()),
+                "pageInfo" => {
+                    project_page_info(page, CommitMatch::cursor, &field.selection_set, fragments)?
+                }
+                "nodes" => JsonValue::Array(
+                    page.items
+                        .iter()
+                        .map(|item| {
+                            self.project_commit_match(item, &field.selection_set, fragments)
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                "edges" => {
+                    let edge_fields =
+                        selection_fields(&field.selection_set, "CommitSearchEdge", fragments)?;
+                    let mut items = Vec::with_capacity(page.items.len());
+                    for item in &page.items {
+                        let mut edge = Map::new();
+                        for edge_field in &edge_fields {
+                            let value = match edge_field.name.as_str() {
+                                "__typename" => JsonValue::String("CommitSearchEdge".to_string()),
+                                "cursor" => JsonValue::String(item.cursor()),
+                                "node" => self.project_commit_match(
+                                    item,
+                                    &edge_field.selection_set,
+                                    fragments,
+                                )?,
+                                _ => JsonValue::Null,
+                            };
+                            edge.insert(response_key(edge_field), value);
+                        }
+                        items.push(JsonValue::Object(edge));
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_commit_match<'a>(
+        &self,
+        item: &CommitMatch,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "CommitSearchResult", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("CommitSearchResult".to_string()),
+                "oid" => JsonValue::String(item.oid.clone()),
+                "summary" => JsonValue::String(item.summary.clone()),
+                "message" => JsonValue::String(item.message.clone()),
+                "authorName" => JsonValue::String(item.author_name.clone()),
+                "authorEmail" => JsonValue::String(item.author_email.clone()),
+                "committedAt" => JsonValue::from(item.committed_at),
+                "highlights" => {
+                    let highlight_fields =
+                        selection_fields(&field.selection_set, "CommitSearchHighlight", fragments)?;
+                    let mut items = Vec::with_capacity(item.highlights.len());
+                    for highlight in &item.highlights {
+                        let mut highlight_map = Map::new();
+                        for highlight_field in &highlight_fields {
+                            let value = match highlight_field.name.as_str() {
+                                "__typename" => {
+                                    JsonValue::String("CommitSearchHighlight".to_string())
+                                }
+                                "field" => {
+                                    JsonValue::String(highlight.field.as_graphql().to_string())
+                                }
+                                "start" => JsonValue::from(highlight.start),
+                                "end" => JsonValue::from(highlight.end),
+                                _ => JsonValue::Null,
+                            };
+                            highlight_map.insert(response_key(highlight_field), value);
+                        }
+                        items.push(JsonValue::Object(highlight_map));
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_project_metadata<'a>(
+        &self,
+        metadata: &ProjectMetadata,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let optional = |value: &Option<String>| match value {
+            Some(value) => JsonValue::String(value.clone()),
+            None => JsonValue::Null,
+        };
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "ProjectMetadata", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ProjectMetadata".to_string()),
+                "headOid" => JsonValue::String(metadata.head_oid.clone()),
+                "readme" => optional(&metadata.readme),
+                "license" => match &metadata.license {
+                    Some(license) => {
+                        let mut license_map = Map::new();
+                        for license_field in
+                            selection_fields(&field.selection_set, "LicenseFile", fragments)?
+                        {
+                            let value = match license_field.name.as_str() {
+                                "__typename" => JsonValue::String("LicenseFile".to_string()),
+                                "path" => JsonValue::String(license.path.clone()),
+                                "spdxId" => license
+                                    .spdx_id
+                                    .map(|id| JsonValue::String(id.to_string()))
+                                    .unwrap_or(JsonValue::Null),
+                                _ => JsonValue::Null,
+                            };
+                            license_map.insert(response_key(license_field), value);
+                        }
+                        JsonValue::Object(license_map)
+                    }
+                    None => JsonValue::Null,
+                },
+                "codeOfConduct" => optional(&metadata.code_of_conduct),
+                "contributing" => optional(&metadata.contributing),
+                "securityPolicy" => optional(&metadata.security_policy),
+                "ciConfigs" => {
+                    let mut items = Vec::with_capacity(metadata.ci_configs.len());
+                    for config in &metadata.ci_configs {
+                        let mut config_map = Map::new();
+                        for config_field in
+                            selection_fields(&field.selection_set, "CiConfig", fragments)?
+                        {
+                            let value = match config_field.name.as_str() {
+                                "__typename" => JsonValue::String("CiConfig".to_string()),
+                                "provider" => {
+                                    JsonValue::String(config.provider.as_graphql().to_string())
+                                }
+                                "path" => JsonValue::String(config.path.clone()),
+                                _ => JsonValue::Null,
+                            };
+                            config_map.insert(response_key(config_field), value);
+                        }
+                        items.push(JsonValue::Object(config_map));
+                    }
+                    JsonValue::Array(items)
+                }
+                "manifests" => JsonValue::from(metadata.manifests.clone()),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_repository_branch<'a>(
+        &self,
+        branch: &RepositoryBranch,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RepositoryBranch", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RepositoryBranch".to_string()),
+                "name" => JsonValue::String(branch.name.clone()),
+                "reference" => JsonValue::String(branch.reference.clone()),
+                "target" => match &branch.target {
+                    Some(target) => JsonValue::String(target.clone()),
+                    None => JsonValue::Null,
+                },
+                "isDefault" => JsonValue::Bool(branch.is_default),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_repository_entries_payload<'a>(
+        &self,
+        payload: &RepositoryEntriesPayload,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RepositoryEntriesPayload", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RepositoryEntriesPayload".to_string()),
+                "treePath" => JsonValue::String(payload.tree_path.clone()),
+                "entries" => {
+                    let mut items = Vec::with_capacity(payload.entries.len());
+                    for entry in &payload.entries {
+                        items.push(self.project_repository_entry(
+                            entry,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_repository_file_payload<'a>(
+        &self,
+        payload: &RepositoryFilePayload,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RepositoryFilePayload", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RepositoryFilePayload".to_string()),
+                "path" => JsonValue::String(payload.path.clone()),
+                "name" => JsonValue::String(payload.name.clone()),
+                "size" => JsonValue::Number(payload.size.into()),
+                "isBinary" => JsonValue::Bool(payload.is_binary),
+                "text" => match &payload.text {
+                    Some(text) => JsonValue::String(text.clone()),
+                    None => JsonValue::Null,
+                },
+                "truncated" => JsonValue::Bool(payload.truncated),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_repository_entry<'a>(
+        &self,
+        entry: &RepositoryEntryNode,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RepositoryEntry", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("RepositoryEntry".to_string()),
+                "name" => JsonValue::String(entry.name.clone()),
+                "path" => JsonValue::String(entry.path.clone()),
+                "type" => JsonValue::String(match entry.kind {
+                    RepositoryEntryKind::File => "FILE".to_string(),
+                    RepositoryEntryKind::Directory => "DIRECTORY".to_string(),
+                }),
+                "size" => match entry.size {
+                    Some(size) => JsonValue::Number(size.into()),
+                    None => JsonValue::Null,
+                },
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    async fn project_user_profile<'a>(
+        &self,
+        user: &UserRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+        variables: &Vars,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "UserProfile", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("UserProfile".to_string()),
+                "did" => JsonValue::String(user.did.clone()),
+                "handle" => JsonValue::String(user.handle.clone()),
+                "displayName" => match &user.display_name {
+                    Some(name) => JsonValue::String(name.clone()),
+                    None => JsonValue::Null,
+                },
+                "avatar" => match &user.avatar {
+                    Some(avatar) => JsonValue::String(avatar.clone()),
+                    None => JsonValue::Null,
+                },
+                "repositories" | "starredRepositories" => {
+                    let viewer = current_viewer();
+                    let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                    let summaries = if field.name == "repositories" {
+                        repositories_owned_by(&self.pool, &user.did, viewer_did).await?
+                    } else {
+                        repositories_starred_by(&self.pool, &user.did, viewer_did).await?
+                    };
+                    let mut items = Vec::with_capacity(summaries.len());
+                    for summary in summaries {
+                        items.push(self.project_repository_summary(
+                            &summary,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    JsonValue::Array(items)
+                }
+                "recentActivity" => {
+                    let limit = self
+                        .get_optional_argument(field, "limit", variables)?
+                        .and_then(|v| v.as_i64())
+                        .unwrap_or(20)
+                        .clamp(1, 100);
+                    let viewer = current_viewer();
+                    let viewer_did = viewer.as_ref().map(|viewer| viewer.did.as_str());
+                    let events = recent_activity(&self.pool, &user.did, viewer_did, limit).await?;
+                    let mut items = Vec::with_capacity(events.len());
+                    for event in events {
+                        items.push(
+                            self.project_activity_event(&event, &field.selection_set, fragments)
+                                .await?,
+                        );
+                    }
+                    JsonValue::Array(items)
+                }
+                "contributions" => {
+                    let contributions = contributions_for(&self.pool, &user.did).await?;
+                    self.project_user_contributions(
+                        &contributions,
+                        &field.selection_set,
+                        fragments,
+                    )?
+                }
+                "links" => {
+                    let links = profile_links(&self.pool, &user.did).await;
+                    let mut items = Vec::with_capacity(links.len());
+                    for link in &links {
+                        items.push(self.project_profile_link(
+                            link,
+                            &field.selection_set,
+                            fragments,
+                        )?);
+                    }
+                    JsonValue::Array(items)
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_viewer_session<'a>(
+        &self,
+        session: &Session,
+        current: bool,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "ViewerSession", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ViewerSession".to_string()),
+                "id" => {
+                    JsonValue::String(global_id::encode("ViewerSession", &session.fingerprint()))
+                }
+                "current" => JsonValue::Bool(current),
+                "userAgent" => match &session.user_agent {
+                    Some(user_agent) => JsonValue::String(user_agent.clone()),
+                    None => JsonValue::Null,
+                },
+                "signedInAt" => JsonValue::Number(session.created_at.into()),
+                "lastActiveAt" => JsonValue::Number(session.last_active_at.into()),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_ssh_key<'a>(
+        &self,
+        ssh_key: &SshKey,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "SshKey", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("SshKey".to_string()),
+                "id" => JsonValue::String(global_id::encode("SshKey", &ssh_key.id)),
+                "title" => JsonValue::String(ssh_key.title.clone()),
+                "keyType" => JsonValue::String(ssh_key.key_type.clone()),
+                "publicKey" => JsonValue::String(ssh_key.public_key.clone()),
+                "fingerprint" => JsonValue::String(ssh_key.fingerprint.clone()),
+                "createdAt" => JsonValue::Number(ssh_key.created_at.into()),
+                "lastUsedAt" => ssh_key
+                    .last_used_at
+                    .map_or(JsonValue::Null, |at| JsonValue::Number(at.into())),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_push_subscription<'a>(
+        &self,
+        subscription: &PushSubscription,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "PushSubscription", fragments)?;
+        let optional =
+            |value: Option<i64>| value.map_or(JsonValue::Null, |v| JsonValue::Number(v.into()));
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("PushSubscription".to_string()),
+                "id" => JsonValue::String(global_id::encode("PushSubscription", &subscription.id)),
+                "endpoint" => JsonValue::String(subscription.endpoint.clone()),
+                "createdAt" => JsonValue::Number(subscription.created_at.into()),
+                "expiresAt" => optional(subscription.expires_at),
+                "lastDeliveredAt" => optional(subscription.last_success_at),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_access_token<'a>(
+        &self,
+        token: &AccessToken,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "AccessToken", fragments)?;
+        let optional =
+            |value: Option<i64>| value.map_or(JsonValue::Null, |v| JsonValue::Number(v.into()));
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("AccessToken".to_string()),
+                "id" => JsonValue::String(global_id::encode("AccessToken", &token.id)),
+                "name" => JsonValue::String(token.name.clone()),
+                "hint" => JsonValue::String(token.hint.clone()),
+                "createdAt" => JsonValue::Number(token.created_at.into()),
+                "expiresAt" => optional(token.expires_at),
+                "lastUsedAt" => optional(token.last_used_at),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_new_access_token<'a>(
+        &self,
+        record: &AccessToken,
+        token: &str,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "NewAccessToken", fragments)?;
+        for field in fields {
+            let key = response_key(field);
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("NewAccessToken".to_string()),
+                "token" => JsonValue::String(token.to_string()),
+                "accessToken" => {
+                    self.project_access_token(record, &field.selection_set, fragments)?
+                }
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
     }
 
-    async fn project_group_node<'a>(
+    fn project_repository_secret<'a>(
         &self,
-        record: &GroupRecord,
+        secret: &RepositorySecret,
         selection_set: &'a SelectionSet<'a, String>,
         fragments: &FragmentMap<'a>,
     ) -> Result<JsonValue> {
         let mut map = Map::new();
-        let fields = selection_fields(selection_set, "GroupNode", fragments)?;
+        let fields = selection_fields(selection_set, "RepositorySecret", fragments)?;
         for field in fields {
             let key = response_key(field);
             let value = match field.name.as_str() {
-                "__typename" => JsonValue::String("GroupNode".to_string()),
-                "id" => JsonValue::String(record.id.clone()),
-                "slug" => JsonValue::String(record.slug.clone()),
-                "parent" => {
-                    if let Some(parent_id) = &record.parent {
-                        let parent =
-                            get_group_parent(&self.pool, parent_id)
-                                .await?
-                                .ok_or_else(|| {
-                                    anyhow!(
-                                        "Parent with id `{}` not found for group `{}`",
-                                        parent_id,
-                                        record.id
-                                    )
-                                })?;
-                        self.project_group_summary(&parent, &field.selection_set, fragments)?
-                    } else {
-                        JsonValue::Null
-                    }
+                "__typename" => JsonValue::String("RepositorySecret".to_string()),
+                "name" => JsonValue::String(secret.name.clone()),
+                "extensions" => JsonValue::Array(
+                    secret
+                        .extensions
+                        .iter()
+                        .map(|name| JsonValue::String(name.clone()))
+                        .collect(),
+                ),
+                "updatedBy" => match &secret.updated_by {
+                    Some(did) => JsonValue::String(did.clone()),
+                    None => JsonValue::Null,
+                },
+                "updatedAt" => JsonValue::Number(secret.updated_at.into()),
+                _ => JsonValue::Null,
+            };
+            map.insert(key, value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    async fn project_webhook<'a>(
+        &self,
+        webhook: &WebhookRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+        variables: &Vars,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "Webhook", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("Webhook".to_string()),
+                "id" => JsonValue::String(global_id::encode("Webhook", &webhook.id)),
+                "repositoryId" => webhook
+                    .repository_id
+                    .as_deref()
+                    .map_or(JsonValue::Null, |id| {
+                        JsonValue::String(global_id::encode("Repository", id))
+                    }),
+                "url" => JsonValue::String(webhook.url.clone()),
+                "events" => JsonValue::from(webhook.event_filter()),
+                "active" => JsonValue::Bool(webhook.active),
+                "createdBy" => webhook
+                    .created_by
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "createdAt" => JsonValue::from(webhook.created_at),
+                "updatedAt" => JsonValue::from(webhook.updated_at),
+                "deliveries" => {
+                    let status = match self.get_optional_argument(field, "status", variables)? {
+                        Some(JsonValue::String(status)) => Some(DeliveryStatus::parse(&status)?),
+                        _ => None,
+                    };
+                    let event = self
+                        .get_optional_argument(field, "event", variables)?
+                        .and_then(|v| v.as_str().map(str::to_string));
+                    let filter = DeliveryFilter { status, event };
+                    let request = self.page_request(field, variables, DELIVERY_CURSOR_KIND)?;
+                    let page = page_deliveries(&self.pool, &webhook.id, &filter, &request).await?;
+                    let total = count_deliveries(&self.pool, &webhook.id, &filter).await?;
+                    self.project_webhook_deliveries(&page, total, &field.selection_set, fragments)?
                 }
-                "repositories" => {
-                    let summaries = repositories_for_group(&self.pool, &record.id).await?;
-                    let mut items = Vec::with_capacity(summaries.len());
-                    for summary in summaries {
-                        items.push(self.project_repository_summary(
-                            &summary,
-                            &field.selection_set,
-                            fragments,
-                        )?);
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_webhook_deliveries<'a>(
+        &self,
+        page: &Page<WebhookDelivery>,
+        total: i64,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "WebhookDeliveryConnection", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("WebhookDeliveryConnection".to_string()),
+                "totalCount" => JsonValue::from(total),
+                "pageInfo" => project_page_info(
+                    page,
+                    WebhookDelivery::cursor,
+                    &field.selection_set,
+                    fragments,
+                )?,
+                "nodes" => JsonValue::Array(
+                    page.items
+                        .iter()
+                        .map(|delivery| {
+                            self.project_webhook_delivery(delivery, &field.selection_set, fragments)
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                "edges" => {
+                    let edge_fields =
+                        selection_fields(&field.selection_set, "WebhookDeliveryEdge", fragments)?;
+                    let mut items = Vec::with_capacity(page.items.len());
+                    for delivery in &page.items {
+                        let mut edge = Map::new();
+                        for edge_field in &edge_fields {
+                            let value = match edge_field.name.as_str() {
+                                "__typename" => {
+                                    JsonValue::String("WebhookDeliveryEdge".to_string())
+                                }
+                                "cursor" => JsonValue::String(delivery.cursor()),
+                                "node" => self.project_webhook_delivery(
+                                    delivery,
+                                    &edge_field.selection_set,
+                                    fragments,
+                                )?,
+                                _ => JsonValue::Null,
+                            };
+                            edge.insert(response_key(edge_field), value);
+                        }
+                        items.push(JsonValue::Object(edge));
                     }
                     JsonValue::Array(items)
                 }
-                other => {
-                    return Err(anyhow!("Unsupported field `{}` on GroupNode", other));
-                }
+                _ => JsonValue::Null,
             };
-            map.insert(key, value);
+            map.insert(response_key(field), value);
         }
         Ok(JsonValue::Object(map))
     }
 
-    fn project_group_summary<'a>(
+    fn project_webhook_delivery<'a>(
         &self,
-        record: &GroupRecord,
+        delivery: &WebhookDelivery,
         selection_set: &'a SelectionSet<'a, String>,
         fragments: &FragmentMap<'a>,
     ) -> Result<JsonValue> {
+        let text =
+            |value: &Option<String>| value.clone().map_or(JsonValue::Null, JsonValue::String);
+        let number = |value: Option<i64>| value.map_or(JsonValue::Null, JsonValue::from);
         let mut map = Map::new();
-        let fields = selection_fields(selection_set, "GroupSummary", fragments)?;
-        for field in fields {
-            let key = response_key(field);
+        for field in selection_fields(selection_set, "WebhookDelivery", fragments)? {
             let value = match field.name.as_str() {
-                "__typename" => JsonValue::String("GroupSummary".to_string()),
-                "id" => JsonValue::String(record.id.clone()),
-                "slug" => JsonValue::String(record.slug.clone()),
-                other => {
-                    return Err(anyhow!("Unsupported field `{}` on GroupSummary", other));
-                }
+                "__typename" => JsonValue::String("WebhookDelivery".to_string()),
+                "id" => JsonValue::String(global_id::encode("WebhookDelivery", &delivery.id)),
+                "event" => JsonValue::String(delivery.event.clone()),
+                "eventId" => JsonValue::String(delivery.event_id.clone()),
+                "redeliveryOf" => delivery
+                    .redelivery_of
+                    .as_ref()
+                    .map_or(JsonValue::Null, |id| {
+                        JsonValue::String(global_id::encode("WebhookDelivery", id))
+                    }),
+                "status" => JsonValue::String(delivery.status().as_graphql().to_string()),
+                "attempt" => JsonValue::from(delivery.attempt),
+                "nextAttemptAt" => number(delivery.next_attempt_at),
+                "requestUrl" => JsonValue::String(delivery.request_url.clone()),
+                "requestHeaders" => text(&delivery.request_headers),
+                "requestBody" => text(&delivery.request_body),
+                "responseStatus" => number(delivery.response_status),
+                "responseHeaders" => text(&delivery.response_headers),
+                "responseBody" => text(&delivery.response_body),
+                "durationMs" => number(delivery.duration_ms),
+                "error" => text(&delivery.error),
+                "truncated" => JsonValue::Bool(delivery.truncated),
+                "createdAt" => JsonValue::from(delivery.created_at),
+                "completedAt" => number(delivery.completed_at),
+                _ => JsonValue::Null,
             };
-            map.insert(key, value);
+            map.insert(response_key(field), value);
         }
         Ok(JsonValue::Object(map))
     }
 
-    async fn project_repository_node<'a>(
+    fn project_jobs<'a>(
         &self,
-        record: &RepositoryRecord,
+        page: &Page<JobRecord>,
+        total: i64,
         selection_set: &'a SelectionSet<'a, String>,
         fragments: &FragmentMap<'a>,
-        variables: &Vars,
     ) -> Result<JsonValue> {
         let mut map = Map::new();
-        let fields = selection_fields(selection_set, "RepositoryNode", fragments)?;
-        for field in fields {
-            let key = response_key(field);
+        for field in selection_fields(selection_set, "JobConnection", fragments)? {
             let value = match field.name.as_str() {
-                "__typename" => JsonValue::String("RepositoryNode".to_string()),
-                "id" => JsonValue::String(record.id.clone()),
-                "slug" => JsonValue::String(record.slug.clone()),
-                "isRemote" => JsonValue::Bool(record.remote_url.is_some()),
-                "remoteUrl" => match &record.remote_url {
-                    Some(url) => JsonValue::String(url.clone()),
-                    None => JsonValue::Null,
-                },
-                "group" => {
-                    if let Some(group_id) = &record.group_id {
-                        if let Some(group) = get_group_parent(&self.pool, group_id).await? {
-                            self.project_group_summary(&group, &field.selection_set, fragments)?
-                        } else {
-                            JsonValue::Null
+                "__typename" => JsonValue::String("JobConnection".to_string()),
+                "totalCount" => JsonValue::from(total),
+                "pageInfo" => {
+                    project_page_info(page, JobRecord::cursor, &field.selection_set, fragments)?
+                }
+                "nodes" => JsonValue::Array(
+                    page.items
+                        .iter()
+                        .map(|job| self.project_job(job, &field.selection_set, fragments))
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                "edges" => {
+                    let edge_fields = selection_fields(&field.selection_set, "JobEdge", fragments)?;
+                    let mut items = Vec::with_capacity(page.items.len());
+                    for job in &page.items {
+                        let mut edge = Map::new();
+                        for edge_field in &edge_fields {
+                            let value = match edge_field.name.as_str() {
+                                "__typename" => JsonValue::String("JobEdge".to_string()),
+                                "cursor" => JsonValue::String(job.cursor()),
+                                "node" => {
+                                    self.project_job(job, &edge_field.selection_set, fragments)?
+                                }
+                                _ => JsonValue::Null,
+                            };
+                            edge.insert(response_key(edge_field), value);
                         }
-                    } else {
-                        JsonValue::Null
+                        items.push(JsonValue::Object(edge));
                     }
+                    JsonValue::Array(items)
                 }
-                "readmeHtml" => {
-                    let branch = self
-                        .get_optional_argument(field, "branch", variables)?
-                        .and_then(|v| v.as_str().map(|s| s.to_string()));
-                    
-                    match get_repository_readme_html(&self.pool, &self.storage, record, branch).await {
-                        Ok(Some(html)) => JsonValue::String(html),
-                        Ok(None) => JsonValue::Null,
-                        Err(_) => JsonValue::Null,
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    /// Jobs are shown without their payload, which can hold what a user asked for.
+    fn project_job<'a>(
+        &self,
+        job: &JobRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "Job", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("Job".to_string()),
+                "id" => JsonValue::String(global_id::encode("Job", &job.id)),
+                "kind" => JsonValue::String(job.kind.clone()),
+                "status" => JsonValue::String(job.status.to_ascii_uppercase()),
+                "attempts" => JsonValue::from(job.attempts),
+                "progress" if job.progress.is_none() => JsonValue::Null,
+                "progress" => {
+                    let progress = job.progress();
+                    let mut object = Map::new();
+                    for item in selection_fields(&field.selection_set, "JobProgress", fragments)? {
+                        let value = match item.name.as_str() {
+                            "__typename" => JsonValue::String("JobProgress".to_string()),
+                            "processed" => JsonValue::from(progress.processed),
+                            "total" => progress.total.map_or(JsonValue::Null, JsonValue::from),
+                            "mismatches" => {
+                                progress.mismatches.map_or(JsonValue::Null, JsonValue::from)
+                            }
+                            _ => JsonValue::Null,
+                        };
+                        object.insert(response_key(item), value);
                     }
+                    JsonValue::Object(object)
                 }
+                "lastError" => job
+                    .last_error
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "runAt" => JsonValue::from(job.run_at),
+                "createdAt" => JsonValue::from(job.created_at),
+                "updatedAt" => JsonValue::from(job.updated_at),
                 _ => JsonValue::Null,
             };
-            map.insert(key, value);
+            map.insert(response_key(field), value);
         }
         Ok(JsonValue::Object(map))
     }
 
-    fn project_repository_summary<'a>(
+    fn project_share_link<'a>(
         &self,
-        summary: &RepositorySummary,
+        link: &ShareLink,
         selection_set: &'a SelectionSet<'a, String>,
         fragments: &FragmentMap<'a>,
     ) -> Result<JsonValue> {
         let mut map = Map::new();
-        let fields = selection_fields(selection_set, "RepositorySummary", fragments)?;
+        for field in selection_fields(selection_set, "ShareLink", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ShareLink".to_string()),
+                "id" => JsonValue::String(global_id::encode("ShareLink", &link.id)),
+                "hint" => JsonValue::String(link.hint.clone()),
+                "allowClone" => JsonValue::Bool(link.allow_clone),
+                "createdBy" => link
+                    .created_by
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "createdAt" => JsonValue::from(link.created_at),
+                "expiresAt" => JsonValue::from(link.expires_at),
+                "revokedAt" => link.revoked_at.map_or(JsonValue::Null, JsonValue::from),
+                "useCount" => JsonValue::from(link.use_count),
+                "lastUsedAt" => link.last_used_at.map_or(JsonValue::Null, JsonValue::from),
+                "active" => JsonValue::Bool(link.is_active(unix_now())),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_new_share_link<'a>(
+        &self,
+        link: &ShareLink,
+        token: &str,
+        repository_path: &str,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let base = crate::config::current()
+            .server
+            .public_base_url
+            .trim_end_matches('/')
+            .to_string();
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "NewShareLink", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("NewShareLink".to_string()),
+                "token" => JsonValue::String(token.to_string()),
+                "url" => JsonValue::String(format!("{}/share/{}", base, token)),
+                // git sends the token as the basic auth password
+                "cloneUrl" if link.allow_clone => {
+                    let mut url = url::Url::parse(&format!("{}/{}.git", base, repository_path))?;
+                    url.set_username("share")
+                        .and_then(|()| url.set_password(Some(token)))
+                        .map_err(|()| anyhow!("{} cannot carry credentials", base))?;
+                    JsonValue::String(url.to_string())
+                }
+                "shareLink" => self.project_share_link(link, &field.selection_set, fragments)?,
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_share_link_event<'a>(
+        &self,
+        event: &ShareLinkEvent,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "ShareLinkEvent", fragments)? {
+            let value = match field.name.as_str() {
+                "__typename" => JsonValue::String("ShareLinkEvent".to_string()),
+                "id" => JsonValue::String(global_id::encode("ShareLinkEvent", &event.id)),
+                "linkId" => JsonValue::String(global_id::encode("ShareLink", &event.link_id)),
+                "action" => JsonValue::String(event.action.as_graphql().to_string()),
+                "actorDid" => event
+                    .actor_did
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "client" => event
+                    .client
+                    .clone()
+                    .map_or(JsonValue::Null, JsonValue::String),
+                "createdAt" => JsonValue::from(event.created_at),
+                _ => JsonValue::Null,
+            };
+            map.insert(response_key(field), value);
+        }
+        Ok(JsonValue::Object(map))
+    }
+
+    fn project_secret_access<'a>(
+        &self,
+        record: &SecretAccessRecord,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &FragmentMap<'a>,
+    ) -> Result<JsonValue> {
+        let mut map = Map::new();
+        let fields = selection_fields(selection_set, "RepositorySecretAccess", fragments)?;
         for field in fields {
             let key = response_key(field);
             let value = match field.name.as_str() {
-                "__typename" => JsonValue::String("RepositorySummary".to_string()),
-                "id" => JsonValue::String(summary.id.clone()),
-                "slug" => JsonValue::String(summary.slug.clone()),
-                "isRemote" => JsonValue::Bool(summary.remote_url.is_some()),
-                "remoteUrl" => match &summary.remote_url {
-                    Some(url) => JsonValue::String(url.clone()),
+                "__typename" => JsonValue::String("RepositorySecretAccess".to_string()),
+                "id" => JsonValue::String(global_id::encode("RepositorySecretAccess", &record.id)),
+                "name" => JsonValue::String(record.name.clone()),
+                "action" => JsonValue::String(record.action.as_graphql().to_string()),
+                "extension" => match &record.extension {
+                    Some(extension) => JsonValue::String(extension.clone()),
                     None => JsonValue::Null,
                 },
+                "actorDid" => match &record.actor_did {
+                    Some(did) => JsonValue::String(did.clone()),
+                    None => JsonValue::Null,
+                },
+                "createdAt" => JsonValue::Number(record.created_at.into()),
                 _ => JsonValue::Null,
             };
             map.insert(key, value);
@@ -569,25 +6908,21 @@ impl CoreSubgraphExecutor {
         Ok(JsonValue::Object(map))
     }
 
-    fn project_repository_branch<'a>(
+    fn project_profile_link<'a>(
         &self,
-        branch: &RepositoryBranch,
+        link: &ProfileLink,
         selection_set: &'a SelectionSet<'a, String>,
         fragments: &FragmentMap<'a>,
     ) -> Result<JsonValue> {
         let mut map = Map::new();
-        let fields = selection_fields(selection_set, "RepositoryBranch", fragments)?;
+        let fields = selection_fields(selection_set, "ProfileLink", fragments)?;
         for field in fields {
             let key = response_key(field);
             let value = match field.name.as_str() {
-                "__typename" => JsonValue::String("RepositoryBranch".to_string()),
-                "name" => JsonValue::String(branch.name.clone()),
-                "reference" => JsonValue::String(branch.reference.clone()),
-                "target" => match &branch.target {
-                    Some(target) => JsonValue::String(target.clone()),
-                    None => JsonValue::Null,
-                },
-                "isDefault" => JsonValue::Bool(branch.is_default),
+                "__typename" => JsonValue::String("ProfileLink".to_string()),
+                "url" => JsonValue::String(link.url.clone()),
+                "kind" => JsonValue::String(link.kind.as_graphql().to_string()),
+                "verified" => JsonValue::Bool(link.verified),
                 _ => JsonValue::Null,
             };
             map.insert(key, value);
@@ -595,30 +6930,33 @@ impl CoreSubgraphExecutor {
         Ok(JsonValue::Object(map))
     }
 
-    fn project_repository_entries_payload<'a>(
+    fn project_federated_issue_reference<'a>(
         &self,
-        payload: &RepositoryEntriesPayload,
+        reference: &FederatedIssueReference,
         selection_set: &'a SelectionSet<'a, String>,
         fragments: &FragmentMap<'a>,
     ) -> Result<JsonValue> {
         let mut map = Map::new();
-        let fields = selection_fields(selection_set, "RepositoryEntriesPayload", fragments)?;
+        let fields = selection_fields(selection_set, "FederatedIssueReference", fragments)?;
         for field in fields {
             let key = response_key(field);
             let value = match field.name.as_str() {
-                "__typename" => JsonValue::String("RepositoryEntriesPayload".to_string()),
-                "treePath" => JsonValue::String(payload.tree_path.clone()),
-                "entries" => {
-                    let mut items = Vec::with_capacity(payload.entries.len());
-                    for entry in &payload.entries {
-                        items.push(self.project_repository_entry(
-                            entry,
-                            &field.selection_set,
-                            fragments,
-                        )?);
-                    }
-                    JsonValue::Array(items)
+                "__typename" => JsonValue::String("FederatedIssueReference".to_string()),
+                "id" => {
+                    JsonValue::String(global_id::encode("FederatedIssueReference", &reference.id))
                 }
+                "issueNumber" => JsonValue::Number(reference.issue_number.into()),
+                "instance" => JsonValue::String(reference.instance_did.clone()),
+                "sourceUrl" => JsonValue::String(reference.source_url.clone()),
+                "title" => match &reference.title {
+                    Some(title) => JsonValue::String(title.clone()),
+                    None => JsonValue::Null,
+                },
+                "actorDid" => match &reference.actor_did {
+                    Some(did) => JsonValue::String(did.clone()),
+                    None => JsonValue::Null,
+                },
+                "createdAt" => JsonValue::Number(reference.created_at.into()),
                 _ => JsonValue::Null,
             };
             map.insert(key, value);
@@ -626,27 +6964,41 @@ impl CoreSubgraphExecutor {
         Ok(JsonValue::Object(map))
     }
 
-    fn project_repository_file_payload<'a>(
+    fn project_user_contributions<'a>(
         &self,
-        payload: &RepositoryFilePayload,
+        contributions: &UserContributions,
         selection_set: &'a SelectionSet<'a, String>,
         fragments: &FragmentMap<'a>,
     ) -> Result<JsonValue> {
         let mut map = Map::new();
-        let fields = selection_fields(selection_set, "RepositoryFilePayload", fragments)?;
+        let fields = selection_fields(selection_set, "UserContributions", fragments)?;
         for field in fields {
             let key = response_key(field);
             let value = match field.name.as_str() {
-                "__typename" => JsonValue::String("RepositoryFilePayload".to_string()),
-                "path" => JsonValue::String(payload.path.clone()),
-                "name" => JsonValue::String(payload.name.clone()),
-                "size" => JsonValue::Number(payload.size.into()),
-                "isBinary" => JsonValue::Bool(payload.is_binary),
-                "text" => match &payload.text {
-                    Some(text) => JsonValue::String(text.clone()),
-                    None => JsonValue::Null,
-                },
-                "truncated" => JsonValue::Bool(payload.truncated),
+                "__typename" => JsonValue::String("UserContributions".to_string()),
+                "did" => JsonValue::String(contributions.did.clone()),
+                "repositoriesOwned" => JsonValue::Number(contributions.repositories_owned.into()),
+                "starsGiven" => JsonValue::Number(contributions.stars_given.into()),
+                "activityCount" => JsonValue::Number(contributions.activity_count.into()),
+                "days" => {
+                    let day_fields =
+                        selection_fields(&field.selection_set, "ContributionDay", fragments)?;
+                    let mut items = Vec::with_capacity(contributions.days.len());
+                    for day in &contributions.days {
+                        let mut day_map = Map::new();
+                        for day_field in &day_fields {
+                            let day_value = match day_field.name.as_str() {
+                                "__typename" => JsonValue::String("ContributionDay".to_string()),
+                                "date" => JsonValue::String(day.date.clone()),
+                                "count" => JsonValue::Number(day.count.into()),
+                                _ => JsonValue::Null,
+                            };
+                            day_map.insert(response_key(day_field), day_value);
+                        }
+                        items.push(JsonValue::Object(day_map));
+                    }
+                    JsonValue::Array(items)
+                }
                 _ => JsonValue::Null,
             };
             map.insert(key, value);
@@ -654,26 +7006,35 @@ impl CoreSubgraphExecutor {
         Ok(JsonValue::Object(map))
     }
 
-    fn project_repository_entry<'a>(
+    async fn project_activity_event<'a>(
         &self,
-        entry: &RepositoryEntryNode,
+        event: &ActivityRecord,
         selection_set: &'a SelectionSet<'a, String>,
         fragments: &FragmentMap<'a>,
     ) -> Result<JsonValue> {
         let mut map = Map::new();
-        let fields = selection_fields(selection_set, "RepositoryEntry", fragments)?;
+        let fields = selection_fields(selection_set, "ActivityEvent", fragments)?;
         for field in fields {
             let key = response_key(field);
             let value = match field.name.as_str() {
-                "__typename" => JsonValue::String("RepositoryEntry".to_string()),
-                "name" => JsonValue::String(entry.name.clone()),
-                "path" => JsonValue::String(entry.path.clone()),
-                "type" => JsonValue::String(match entry.kind {
-                    RepositoryEntryKind::File => "FILE".to_string(),
-                    RepositoryEntryKind::Directory => "DIRECTORY".to_string(),
-                }),
-                "size" => match entry.size {
-                    Some(size) => JsonValue::Number(size.into()),
+                "__typename" => JsonValue::String("ActivityEvent".to_string()),
+                "id" => JsonValue::String(global_id::encode("ActivityEvent", &event.id)),
+                "kind" => JsonValue::String(event.kind.clone()),
+                "summary" => JsonValue::String(event.summary.clone()),
+                "createdAt" => JsonValue::Number(event.created_at.into()),
+                "repository" => match &event.repository_id {
+                    Some(id) => match get_repository_by_id(&self.pool, id).await? {
+                        Some(record) => self.project_repository_summary(
+                            &RepositorySummary {
+                                id: record.id,
+                                slug: record.slug,
+                                remote_url: record.remote_url,
+                            },
+                            &field.selection_set,
+                            fragments,
+                        )?,
+                        None => JsonValue::Null,
+                    },
                     None => JsonValue::Null,
                 },
                 _ => JsonValue::Null,
@@ -692,10 +7053,33 @@ impl CoreSubgraphExecutor {
         let parent = value
             .get("parent")
             .and_then(JsonValue::as_str)
-            .map(|s| s.to_string());
+            .map(|s| global_id::internal_id("Group", s))
+            .transpose()?;
         Ok(CreateGroupInput { slug, parent })
     }
 
+    /// Reads a `[LabelInput!]` value. Names and colours are checked when saved.
+    fn parse_labels(&self, value: &JsonValue) -> Result<Vec<Label>> {
+        let items = value
+            .as_array()
+            .ok_or_else(|| anyhow!("labels must be a list"))?;
+        items
+            .iter()
+            .map(|item| {
+                let text = |name: &str| item.get(name).and_then(JsonValue::as_str);
+                Ok(Label {
+                    name: text("name")
+                        .ok_or_else(|| anyhow!("label.name must be a string"))?
+                        .to_string(),
+                    color: text("color")
+                        .ok_or_else(|| anyhow!("label.color must be a string"))?
+                        .to_string(),
+                    description: text("description").map(str::to_string),
+                })
+            })
+            .collect()
+    }
+
     fn parse_create_repository_input(&self, value: &JsonValue) -> Result<CreateRepositoryInput> {
         let slug = value
             .get("slug")
@@ -705,9 +7089,70 @@ impl CoreSubgraphExecutor {
         let group = value
             .get("group")
             .and_then(JsonValue::as_str)
-            .map(|s| s.to_string());
+            .map(|s| global_id::internal_id("Group", s))
+            .transpose()?;
         Ok(CreateRepositoryInput { slug, group })
     }
+
+    fn parse_create_announcement_input(&self, value: &JsonValue) -> Result<NewAnnouncement> {
+        let message = value
+            .get("message")
+            .and_then(JsonValue::as_str)
+            .ok_or_else(|| anyhow!("createAnnouncement input.message must be a string"))?
+            .to_string();
+        let severity = value
+            .get("severity")
+            .and_then(JsonValue::as_str)
+            .ok_or_else(|| anyhow!("createAnnouncement input.severity must be a string"))?;
+        Ok(NewAnnouncement {
+            message,
+            severity: Severity::from_graphql(severity)?,
+            starts_at: value.get("startsAt").and_then(JsonValue::as_i64),
+            ends_at: value.get("endsAt").and_then(JsonValue::as_i64),
+        })
+    }
+
+    fn parse_ref_updates(&self, value: &JsonValue) -> Result<Vec<RefUpdate>> {
+        let items = value
+            .as_array()
+            .ok_or_else(|| anyhow!("updates argument must be a list"))?;
+        items
+            .iter()
+            .map(|item| {
+                let text = |name: &str| {
+                    item.get(name)
+                        .and_then(JsonValue::as_str)
+                        .map(str::to_string)
+                        .ok_or_else(|| anyhow!("RefUpdateInput.{} must be a string", name))
+                };
+                Ok(RefUpdate {
+                    ref_name: text("refName")?,
+                    old: text("old")?,
+                    new: text("new")?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Links from the user's DID document. Empty when federation is off or the document
+/// cannot be resolved, so a slow DID host never breaks the profile.
+async fn profile_links(pool: &SqlitePool, did: &str) -> Vec<ProfileLink> {
+    let config = &crate::config::current().federation;
+    if !config.enabled {
+        return Vec::new();
+    }
+    let resolved = match DidResolver::from_config(config) {
+        Ok(resolver) => resolver.resolve(pool, did).await,
+        Err(err) => Err(err),
+    };
+    match resolved {
+        Ok(resolved) => resolved.links,
+        Err(err) => {
+            tracing::warn!(%did, "profile links unavailable: {:#}", err);
+            Vec::new()
+        }
+    }
 }
 
 fn collect_fragment_definitions<'a>(
@@ -722,6 +7167,35 @@ fn collect_fragment_definitions<'a>(
     fragments
 }
 
+fn project_page_info<'a, T>(
+    page: &Page<T>,
+    cursor: impl Fn(&T) -> String,
+    selection_set: &'a SelectionSet<'a, String>,
+    fragments: &FragmentMap<'a>,
+) -> Result<JsonValue> {
+    let mut map = Map::new();
+    for field in selection_fields(selection_set, "PageInfo", fragments)? {
+        let value = match field.name.as_str() {
+            "__typename" => JsonValue::String("PageInfo".to_string()),
+            "hasNextPage" => JsonValue::Bool(page.has_next_page),
+            "hasPreviousPage" => JsonValue::Bool(page.has_previous_page),
+            "startCursor" => page
+                .items
+                .first()
+                .map(|item| JsonValue::String(cursor(item)))
+                .unwrap_or(JsonValue::Null),
+            "endCursor" => page
+                .items
+                .last()
+                .map(|item| JsonValue::String(cursor(item)))
+                .unwrap_or(JsonValue::Null),
+            _ => JsonValue::Null,
+        };
+        map.insert(response_key(field), value);
+    }
+    Ok(JsonValue::Object(map))
+}
+
 fn selection_fields<'a>(
     selection_set: &'a SelectionSet<'a, String>,
     type_name: &str,
@@ -773,7 +7247,8 @@ fn collect_selection_fields<'a>(
 
 fn type_condition_matches(type_name: &str, type_condition: &TypeCondition<'_, String>) -> bool {
     match type_condition {
-        TypeCondition::On(target) => target == type_name,
+        // Validation only lets `... on Node` reach objects that implement it
+        TypeCondition::On(target) => target == type_name || target == "Node",
     }
 }
 
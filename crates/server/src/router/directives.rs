@@ -0,0 +1,730 @@
+//! `@rateLimit` and `@cacheControl` on schema fields.
+//!
+//! Core and extension SDL can annotate fields, and `@cacheControl` also applies to types:
+//!
+//! ```graphql
+//! findFiles(path: String!, query: String!): [FileMatch!] @rateLimit(max: 30, window: 60)
+//! getRepository(path: String!): RepositoryNode @cacheControl(maxAge: 60, scope: PUBLIC)
+//! ```
+//!
+//! [`FieldPolicies`] reads both from the composed supergraph. Before execution the router
+//! walks the operation, fragments included, and charges each selected rate-limited field
+//! to the caller in a fixed window of `window` seconds. Every selection counts, so
+//! selecting a field under two aliases costs two calls. A request that would exceed any
+//! limit is rejected before anything runs and is not charged.
+//!
+//! Cache hints follow Apollo's rules. The response's max-age is the lowest max-age of any
+//! selected field. A root field, or a field returning an object, interface or union,
+//! counts as `0` unless it or its type has a hint; other fields inherit from their parent.
+//! One `PRIVATE` hint makes the whole response private. Mutations are never cacheable.
+//!
+//! The same walk lists every schema field the operation selects, and the ones marked
+//! `@deprecated`, for [`super::usage`].
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result, anyhow, bail};
+use graphql_parser::query::{
+    Definition as QueryDefinition, FragmentDefinition, OperationDefinition, Selection,
+    SelectionSet, TypeCondition,
+};
+use graphql_parser::schema::{Definition, Directive, Type, TypeDefinition, TypeExtension, Value};
+
+type FragmentMap<'a> = HashMap<&'a str, &'a FragmentDefinition<'a, String>>;
+
+/// `@deprecated` without a `reason`, as in the GraphQL spec.
+const DEFAULT_DEPRECATION_REASON: &str = "No longer supported";
+
+/// Rate-limit windows kept before expired ones are swept.
+const MAX_TRACKED_WINDOWS: usize = 10_000;
+
+/// `@rateLimit(max:, window:)`: at most `max` calls per caller every `window_secs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub max: u32,
+    pub window_secs: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheScope {
+    Public,
+    Private,
+}
+
+/// `@cacheControl(maxAge:, scope:)`; either argument may be omitted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct CacheHint {
+    max_age: Option<u32>,
+    scope: Option<CacheScope>,
+}
+
+struct FieldPolicy {
+    return_type: String,
+    rate_limit: Option<RateLimit>,
+    cache: Option<CacheHint>,
+    deprecation: Option<String>,
+    /// The subgraph named by `@join__field(graph:)`
+    graph: Option<String>,
+}
+
+/// An object, interface or union type.
+#[derive(Default)]
+struct TypePolicy {
+    fields: HashMap<String, FieldPolicy>,
+    cache: Option<CacheHint>,
+}
+
+/// The rate limits and cache hints declared in a composed schema.
+pub struct FieldPolicies {
+    types: HashMap<String, TypePolicy>,
+    query_type: String,
+    mutation_type: String,
+}
+
+impl FieldPolicies {
+    pub fn parse(supergraph_sdl: &str) -> Result<Self> {
+        let document = graphql_parser::parse_schema::<String>(supergraph_sdl)
+            .map_err(|e| anyhow!("Failed to parse composed supergraph: {e}"))?;
+
+        let mut policies = Self {
+            types: HashMap::new(),
+            query_type: "Query".to_string(),
+            mutation_type: "Mutation".to_string(),
+        };
+        for definition in &document.definitions {
+            match definition {
+                Definition::SchemaDefinition(definition) => {
+                    if let Some(query) = &definition.query {
+                        policies.query_type = query.clone();
+                    }
+                    if let Some(mutation) = &definition.mutation {
+                        policies.mutation_type = mutation.clone();
+                    }
+                }
+                Definition::TypeDefinition(TypeDefinition::Object(object)) => {
+                    policies.add_type(&object.name, &object.directives, &object.fields)?
+                }
+                Definition::TypeExtension(TypeExtension::Object(extension)) => {
+                    policies.add_type(&extension.name, &extension.directives, &extension.fields)?
+                }
+                Definition::TypeDefinition(TypeDefinition::Interface(interface)) => {
+                    policies.add_type(&interface.name, &interface.directives, &interface.fields)?
+                }
+                Definition::TypeDefinition(TypeDefinition::Union(union)) => {
+                    policies.add_type(&union.name, &union.directives, &[])?
+                }
+                _ => {}
+            }
+        }
+        Ok(policies)
+    }
+
+    fn add_type(
+        &mut self,
+        name: &str,
+        directives: &[Directive<'_, String>],
+        fields: &[graphql_parser::schema::Field<'_, String>],
+    ) -> Result<()> {
+        let type_cache = cache_hint(directives).with_context(|| format!("on type `{}`", name))?;
+        let policy = self.types.entry(name.to_string()).or_default();
+        if type_cache.is_some() {
+            policy.cache = type_cache;
+        }
+        for field in fields {
+            let coordinate = || format!("on field `{}.{}`", name, field.name);
+            policy.fields.insert(
+                field.name.clone(),
+                FieldPolicy {
+                    return_type: named_type(&field.field_type).to_string(),
+                    rate_limit: rate_limit(&field.directives).with_context(coordinate)?,
+                    cache: cache_hint(&field.directives).with_context(coordinate)?,
+                    deprecation: deprecation(&field.directives),
+                    graph: join_graph(&field.directives),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Every field of every object and interface type, in schema coordinate order.
+    pub fn schema_fields(&self) -> Vec<SchemaField> {
+        let mut fields: Vec<SchemaField> = self
+            .types
+            .iter()
+            .flat_map(|(type_name, policy)| {
+                policy.fields.iter().map(|(field_name, field)| SchemaField {
+                    type_name: type_name.clone(),
+                    field_name: field_name.clone(),
+                    deprecation: field.deprecation.clone(),
+                })
+            })
+            .collect();
+        fields.sort_by(|a, b| (&a.type_name, &a.field_name).cmp(&(&b.type_name, &b.field_name)));
+        fields
+    }
+
+    /// The subgraph that resolves `coordinate` (`Type.field`), if the schema names one.
+    pub fn field_graph(&self, coordinate: &str) -> Option<&str> {
+        let (type_name, field_name) = coordinate.split_once('.')?;
+        self.types
+            .get(type_name)?
+            .fields
+            .get(field_name)?
+            .graph
+            .as_deref()
+    }
+
+    /// The limits and cache policy for one operation of `query`. Documents that do not
+    /// parse get an empty policy; execution reports the syntax error.
+    pub fn operation(&self, query: &str, operation_name: Option<&str>) -> OperationPolicy {
+        let Ok(document) = graphql_parser::parse_query::<String>(query) else {
+            return OperationPolicy::default();
+        };
+
+        let mut fragments: FragmentMap<'_> = HashMap::new();
+        let mut operations = Vec::new();
+        for definition in &document.definitions {
+            match definition {
+                QueryDefinition::Fragment(fragment) => {
+                    fragments.insert(fragment.name.as_str(), fragment);
+                }
+                QueryDefinition::Operation(operation) => operations.push(operation),
+            }
+        }
+        let operation = operations.into_iter().find(|operation| {
+            let name = match operation {
+                OperationDefinition::Query(query) => query.name.as_deref(),
+                OperationDefinition::Mutation(mutation) => mutation.name.as_deref(),
+                OperationDefinition::Subscription(subscription) => subscription.name.as_deref(),
+                OperationDefinition::SelectionSet(_) => None,
+            };
+            operation_name.is_none() || name == operation_name
+        });
+        let (root_type, selection_set, cacheable) = match operation {
+            Some(OperationDefinition::Query(query)) => {
+                (&self.query_type, &query.selection_set, true)
+            }
+            Some(OperationDefinition::SelectionSet(selection_set)) => {
+                (&self.query_type, selection_set, true)
+            }
+            Some(OperationDefinition::Mutation(mutation)) => {
+                (&self.mutation_type, &mutation.selection_set, false)
+            }
+            Some(OperationDefinition::Subscription(_)) | None => {
+                return OperationPolicy::default();
+            }
+        };
+
+        let mut walk = Walk {
+            policies: self,
+            fragments: &fragments,
+            active_fragments: Vec::new(),
+            calls: BTreeMap::new(),
+            max_age: None,
+            private: false,
+            fields: BTreeSet::new(),
+            deprecated: BTreeMap::new(),
+        };
+        walk.selection_set(root_type, selection_set, None);
+
+        OperationPolicy {
+            rate_limits: walk
+                .calls
+                .into_iter()
+                .map(|(coordinate, (limit, calls))| FieldCalls {
+                    coordinate,
+                    limit,
+                    calls,
+                })
+                .collect(),
+            cache: cacheable.then(|| CachePolicy {
+                // Only leaf fields were selected, e.g. `{ __typename }`.
+                max_age: walk.max_age.unwrap_or(0),
+                scope: if walk.private {
+                    CacheScope::Private
+                } else {
+                    CacheScope::Public
+                },
+            }),
+            fields: walk.fields.into_iter().collect(),
+            deprecated: walk
+                .deprecated
+                .into_iter()
+                .map(|(coordinate, reason)| DeprecatedField { coordinate, reason })
+                .collect(),
+        }
+    }
+}
+
+struct Walk<'p, 'a> {
+    policies: &'p FieldPolicies,
+    fragments: &'p FragmentMap<'a>,
+    active_fragments: Vec<&'a str>,
+    calls: BTreeMap<String, (RateLimit, u32)>,
+    max_age: Option<u32>,
+    private: bool,
+    fields: BTreeSet<String>,
+    deprecated: BTreeMap<String, String>,
+}
+
+impl<'a> Walk<'_, 'a> {
+    /// `parent_max_age` is `None` at the root, where fields never inherit.
+    fn selection_set(
+        &mut self,
+        type_name: &str,
+        selection_set: &'a SelectionSet<'a, String>,
+        parent_max_age: Option<u32>,
+    ) {
+        for selection in &selection_set.items {
+            match selection {
+                Selection::Field(field) => {
+                    let Some(policy) = self
+                        .policies
+                        .types
+                        .get(type_name)
+                        .and_then(|t| t.fields.get(&field.name))
+                    else {
+                        // `__typename`, introspection, or a field execution will reject.
+                        continue;
+                    };
+                    let coordinate = format!("{}.{}", type_name, field.name);
+                    if let Some(limit) = policy.rate_limit {
+                        self.calls.entry(coordinate.clone()).or_insert((limit, 0)).1 += 1;
+                    }
+                    if let Some(reason) = &policy.deprecation {
+                        self.deprecated.insert(coordinate.clone(), reason.clone());
+                    }
+                    self.fields.insert(coordinate);
+
+                    let return_type = self.policies.types.get(&policy.return_type);
+                    let hint = policy
+                        .cache
+                        .or_else(|| return_type.and_then(|t| t.cache))
+                        .unwrap_or_default();
+                    if hint.scope == Some(CacheScope::Private) {
+                        self.private = true;
+                    }
+                    let max_age = match (hint.max_age, parent_max_age) {
+                        (Some(max_age), _) => max_age,
+                        (None, Some(inherited)) if return_type.is_none() => inherited,
+                        _ => 0,
+                    };
+                    self.max_age = Some(self.max_age.map_or(max_age, |lowest| lowest.min(max_age)));
+
+                    if !field.selection_set.items.is_empty() {
+                        self.selection_set(
+                            &policy.return_type,
+                            &field.selection_set,
+                            Some(max_age),
+                        );
+                    }
+                }
+                Selection::InlineFragment(fragment) => {
+                    let condition = match &fragment.type_condition {
+                        Some(TypeCondition::On(condition)) => condition.as_str(),
+                        None => type_name,
+                    };
+                    self.selection_set(condition, &fragment.selection_set, parent_max_age);
+                }
+                Selection::FragmentSpread(spread) => {
+                    let name = spread.fragment_name.as_str();
+                    let Some(fragment) = self.fragments.get(name) else {
+                        continue;
+                    };
+                    // Fragment cycles are invalid; execution rejects them.
+                    if self.active_fragments.contains(&name) {
+                        continue;
+                    }
+                    let TypeCondition::On(condition) = &fragment.type_condition;
+                    self.active_fragments.push(name);
+                    self.selection_set(condition, &fragment.selection_set, parent_max_age);
+                    self.active_fragments.pop();
+                }
+            }
+        }
+    }
+}
+
+/// A rate-limited field and how often one operation selects it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldCalls {
+    /// `Type.field`
+    pub coordinate: String,
+    pub limit: RateLimit,
+    pub calls: u32,
+}
+
+/// A selected field marked `@deprecated`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeprecatedField {
+    /// `Type.field`
+    pub coordinate: String,
+    pub reason: String,
+}
+
+/// A field declared on an object or interface type of the composed schema.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaField {
+    pub type_name: String,
+    pub field_name: String,
+    /// The `@deprecated` reason, if the field is deprecated.
+    pub deprecation: Option<String>,
+}
+
+impl SchemaField {
+    /// `Type.field`
+    pub fn coordinate(&self) -> String {
+        format!("{}.{}", self.type_name, self.field_name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CachePolicy {
+    pub max_age: u32,
+    pub scope: CacheScope,
+}
+
+impl CachePolicy {
+    /// The `Cache-Control` header value for a successful response.
+    pub fn header_value(&self) -> String {
+        match (self.max_age, self.scope) {
+            (0, _) => "no-store".to_string(),
+            (max_age, CacheScope::Public) => format!("public, max-age={}", max_age),
+            (max_age, CacheScope::Private) => format!("private, max-age={}", max_age),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OperationPolicy {
+    pub rate_limits: Vec<FieldCalls>,
+    /// `None` for mutations and documents without a usable operation.
+    pub cache: Option<CachePolicy>,
+    /// Coordinates of every schema field the operation selects, sorted.
+    pub fields: Vec<String>,
+    pub deprecated: Vec<DeprecatedField>,
+}
+
+/// A request refused by [`RateLimiter::charge`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RateLimited {
+    pub coordinate: String,
+    pub retry_after_secs: u64,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rate limit exceeded for `{}`; retry in {} seconds",
+            self.coordinate, self.retry_after_secs
+        )
+    }
+}
+
+struct Window {
+    resets_at: u64,
+    count: u32,
+}
+
+/// Fixed-window call counts per caller and field, kept in memory per instance.
+#[derive(Default)]
+pub struct RateLimiter {
+    windows: Mutex<HashMap<(String, String), Window>>,
+}
+
+impl RateLimiter {
+    /// Charges `caller` for every rate-limited field in `policy`, or charges nothing and
+    /// reports the field whose window resets last.
+    pub fn charge(
+        &self,
+        caller: &str,
+        policy: &OperationPolicy,
+        now: u64,
+    ) -> Result<(), RateLimited> {
+        if policy.rate_limits.is_empty() {
+            return Ok(());
+        }
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        if windows.len() > MAX_TRACKED_WINDOWS {
+            windows.retain(|_, window| window.resets_at > now);
+        }
+
+        let mut refused: Option<RateLimited> = None;
+        for field in &policy.rate_limits {
+            let key = (caller.to_string(), field.coordinate.clone());
+            let (used, resets_at) = match windows.get(&key) {
+                Some(window) if window.resets_at > now => (window.count, window.resets_at),
+                _ => (0, now + field.limit.window_secs),
+            };
+            if used.saturating_add(field.calls) > field.limit.max {
+                let retry_after_secs = resets_at - now;
+                if refused
+                    .as_ref()
+                    .is_none_or(|r| r.retry_after_secs < retry_after_secs)
+                {
+                    refused = Some(RateLimited {
+                        coordinate: field.coordinate.clone(),
+                        retry_after_secs,
+                    });
+                }
+            }
+        }
+        if let Some(refused) = refused {
+            return Err(refused);
+        }
+
+        for field in &policy.rate_limits {
+            let window = windows
+                .entry((caller.to_string(), field.coordinate.clone()))
+                .or_insert(Window {
+                    resets_at: 0,
+                    count: 0,
+                });
+            if window.resets_at <= now {
+                *window = Window {
+                    resets_at: now + field.limit.window_secs,
+                    count: 0,
+                };
+            }
+            window.count += field.calls;
+        }
+        Ok(())
+    }
+}
+
+fn named_type<'t>(field_type: &'t Type<'_, String>) -> &'t str {
+    match field_type {
+        Type::NamedType(name) => name,
+        Type::ListType(inner) | Type::NonNullType(inner) => named_type(inner),
+    }
+}
+
+fn find_directive<'d, 'a>(
+    directives: &'d [Directive<'a, String>],
+    name: &str,
+) -> Option<&'d Directive<'a, String>> {
+    directives.iter().find(|directive| directive.name == name)
+}
+
+fn argument<'d, 'a>(
+    directive: &'d Directive<'a, String>,
+    name: &str,
+) -> Option<&'d Value<'a, String>> {
+    directive
+        .arguments
+        .iter()
+        .find(|(argument, _)| argument == name)
+        .map(|(_, value)| value)
+}
+
+fn positive_int(directive: &Directive<'_, String>, name: &str) -> Result<Option<u64>> {
+    match argument(directive, name) {
+        None => Ok(None),
+        Some(Value::Int(number)) => match number.as_i64() {
+            Some(value) if value >= 0 => Ok(Some(value as u64)),
+            _ => bail!("@{}({}:) must not be negative", directive.name, name),
+        },
+        Some(_) => bail!("@{}({}:) must be an integer", directive.name, name),
+    }
+}
+
+fn deprecation(directives: &[Directive<'_, String>]) -> Option<String> {
+    let directive = find_directive(directives, "deprecated")?;
+    Some(match argument(directive, "reason") {
+        Some(Value::String(reason)) => reason.clone(),
+        _ => DEFAULT_DEPRECATION_REASON.to_string(),
+    })
+}
+
+fn join_graph(directives: &[Directive<'_, String>]) -> Option<String> {
+    match argument(find_directive(directives, "join__field")?, "graph") {
+        Some(Value::Enum(graph)) => Some(graph.clone()),
+        _ => None,
+    }
+}
+
+fn rate_limit(directives: &[Directive<'_, String>]) -> Result<Option<RateLimit>> {
+    let Some(directive) = find_directive(directives, "rateLimit") else {
+        return Ok(None);
+    };
+    let max = positive_int(directive, "max")?.context("@rateLimit needs `max`")?;
+    let window_secs = positive_int(directive, "window")?.context("@rateLimit needs `window`")?;
+    if window_secs == 0 {
+        bail!("@rateLimit(window:) must be at least one second");
+    }
+    Ok(Some(RateLimit {
+        max: u32::try_from(max).unwrap_or(u32::MAX),
+        window_secs,
+    }))
+}
+
+fn cache_hint(directives: &[Directive<'_, String>]) -> Result<Option<CacheHint>> {
+    let Some(directive) = find_directive(directives, "cacheControl") else {
+        return Ok(None);
+    };
+    let max_age = positive_int(directive, "maxAge")?
+        .map(|max_age| u32::try_from(max_age).unwrap_or(u32::MAX));
+    let scope = match argument(directive, "scope") {
+        None => None,
+        Some(Value::Enum(scope)) if scope == "PUBLIC" => Some(CacheScope::Public),
+        Some(Value::Enum(scope)) if scope == "PRIVATE" => Some(CacheScope::Private),
+        Some(_) => bail!("@cacheControl(scope:) must be PUBLIC or PRIVATE"),
+    };
+    Ok(Some(CacheHint { max_age, scope }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SDL: &str = r#"
+        type Query {
+          repository(path: String!): Repository @cacheControl(maxAge: 300)
+          search(query: String!): [Hit!]! @rateLimit(max: 2, window: 60) @cacheControl(maxAge: 30)
+          viewer: Viewer
+          ping: String
+        }
+        type Mutation {
+          star(id: ID!): Repository @rateLimit(max: 5, window: 10)
+        }
+        type Repository @cacheControl(maxAge: 120) {
+          slug: String!
+          name: String @deprecated(reason: "Use `slug`.")
+          owner: Viewer
+          readme: String @cacheControl(maxAge: 60)
+        }
+        type Viewer @cacheControl(maxAge: 10, scope: PRIVATE) {
+          handle: String!
+        }
+        union Hit = Repository | Viewer
+    "#;
+
+    fn policies() -> FieldPolicies {
+        FieldPolicies::parse(SDL).unwrap()
+    }
+
+    fn header(query: &str) -> Option<String> {
+        policies()
+            .operation(query, None)
+            .cache
+            .map(|c| c.header_value())
+    }
+
+    #[test]
+    fn cache_hints_take_the_lowest_max_age_and_any_private_scope() {
+        assert_eq!(
+            header("{ repository(path: \"a\") { slug } }").as_deref(),
+            Some("public, max-age=300")
+        );
+        assert_eq!(
+            header("{ repository(path: \"a\") { slug readme } }").as_deref(),
+            Some("public, max-age=60")
+        );
+        // The type's hint applies when the field has none, including its scope.
+        assert_eq!(
+            header(
+                "{ repository(path: \"a\") { ...R } } fragment R on Repository { owner { handle } }"
+            )
+            .as_deref(),
+            Some("private, max-age=10")
+        );
+        // Root fields without hints are not cacheable; mutations never are.
+        assert_eq!(header("{ ping }").as_deref(), Some("no-store"));
+        assert_eq!(header("mutation { star(id: \"1\") { slug } }"), None);
+        assert_eq!(
+            header("{ search(query: \"x\") { ... on Viewer { handle } } }").as_deref(),
+            Some("public, max-age=30")
+        );
+    }
+
+    #[test]
+    fn selections_of_rate_limited_fields_are_counted() {
+        let policy = policies().operation(
+            "query Two { a: search(query: \"x\") { __typename } b: search(query: \"y\") { __typename } }
+             query One { search(query: \"x\") { __typename } }",
+            Some("Two"),
+        );
+        assert_eq!(
+            policy.rate_limits,
+            [FieldCalls {
+                coordinate: "Query.search".to_string(),
+                limit: RateLimit {
+                    max: 2,
+                    window_secs: 60
+                },
+                calls: 2,
+            }]
+        );
+        assert!(FieldPolicies::parse("type Query { a: Int @rateLimit(max: 1) }").is_err());
+        assert!(
+            FieldPolicies::parse("type Query { a: Int @cacheControl(scope: SHARED) }").is_err()
+        );
+    }
+
+    #[test]
+    fn selected_and_deprecated_fields_are_listed() {
+        let policy = policies().operation(
+            "{ repository(path: \"a\") { slug ...R } } fragment R on Repository { name slug }",
+            None,
+        );
+        assert_eq!(
+            policy.fields,
+            ["Query.repository", "Repository.name", "Repository.slug"]
+        );
+        assert_eq!(
+            policy.deprecated,
+            [DeprecatedField {
+                coordinate: "Repository.name".to_string(),
+                reason: "Use `slug`.".to_string(),
+            }]
+        );
+
+        let fields = policies().schema_fields();
+        assert_eq!(fields.len(), 10);
+        assert_eq!(fields[0].coordinate(), "Mutation.star");
+        let name = fields.iter().find(|f| f.field_name == "name").unwrap();
+        assert_eq!(name.deprecation.as_deref(), Some("Use `slug`."));
+    }
+
+    #[test]
+    fn limits_apply_per_caller_and_reset_with_the_window() {
+        let policies = policies();
+        let one = policies.operation("{ search(query: \"x\") { __typename } }", None);
+        let two = policies.operation(
+            "{ a: search(query: \"x\") { __typename } b: search(query: \"y\") { __typename } }",
+            None,
+        );
+        let limiter = RateLimiter::default();
+
+        assert!(limiter.charge("alice", &one, 1000).is_ok());
+        // Refused requests charge nothing.
+        let refused = limiter.charge("alice", &two, 1010).unwrap_err();
+        assert_eq!(refused.coordinate, "Query.search");
+        assert_eq!(refused.retry_after_secs, 50);
+        assert!(limiter.charge("alice", &one, 1020).is_ok());
+        assert!(limiter.charge("alice", &one, 1030).is_err());
+        assert!(limiter.charge("bob", &two, 1030).is_ok());
+        assert!(limiter.charge("alice", &two, 1060).is_ok());
+    }
+
+    #[test]
+    fn fields_report_the_graph_that_resolves_them() {
+        let policies = FieldPolicies::parse(
+            r#"
+            type Query {
+              repository(path: String!): String @join__field(graph: CORE)
+              issues: [String!]! @join__field(graph: ISSUES)
+              ping: String
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(policies.field_graph("Query.repository"), Some("CORE"));
+        assert_eq!(policies.field_graph("Query.issues"), Some("ISSUES"));
+        assert_eq!(policies.field_graph("Query.ping"), None);
+        assert_eq!(policies.field_graph("Query.missing"), None);
+    }
+}
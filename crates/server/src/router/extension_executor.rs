@@ -4,6 +4,7 @@ use std::sync::Arc;
 use anyhow::{Context, Result, anyhow};
 use async_trait::async_trait;
 use bytes::Bytes;
+use futures::future::{BoxFuture, FutureExt};
 use graphql_parser::query::{
     Definition, Field, FragmentDefinition, OperationDefinition, Selection, SelectionSet,
     TypeCondition, Value as AstValue,
@@ -12,10 +13,18 @@ use hive_router_plan_executor::executors::common::{HttpExecutionRequest, Subgrap
 use serde_json::{Map, Value as JsonValue};
 use sqlx::SqlitePool;
 
-use crate::extensions::wasm_runtime::Extension as WasmExtension;
+use crate::auth::viewer::current_viewer;
+use crate::extensions::wasm_runtime::{
+    Extension as WasmExtension, ExtensionFieldError, FieldRequest,
+};
 use crate::extensions::wit_bindings::{
-    ContextScope, GlobalContext, RepositoryContext as RuntimeRepositoryContext, RequestContext,
+    ContextScope, GlobalContext, GroupContext as RuntimeGroupContext,
+    RepositoryContext as RuntimeRepositoryContext, RequestContext,
+    UserContext as RuntimeUserContext,
 };
+use crate::graphql::global_id;
+use crate::moderation;
+use crate::repository::PathResolver;
 use crate::repository::queries::get_repository_by_id;
 
 use super::{graphql_error_body, sonic_to_serde};
@@ -27,6 +36,11 @@ type FragmentMap<'a> = HashMap<&'a str, &'a FragmentDefinition<'a, String>>;
 struct FieldTypeMeta {
     base_type: String,
     is_list: bool,
+    /// Arguments of type `ID`, whose global IDs are decoded before the extension sees them
+    id_arguments: Vec<String>,
+    /// Whether the field takes arguments. Such fields on object types are resolved by the
+    /// extension rather than read from the parent object.
+    has_arguments: bool,
 }
 
 #[derive(Clone, Default)]
@@ -39,6 +53,8 @@ struct ExtensionSchemaMetadata {
     query_fields: HashMap<String, FieldTypeMeta>,
     mutation_fields: HashMap<String, FieldTypeMeta>,
     object_types: HashMap<String, ObjectTypeMeta>,
+    /// Object types that implement `Node`
+    node_types: HashSet<String>,
     enum_types: HashSet<String>,
     scalar_types: HashSet<String>,
 }
@@ -73,12 +89,6 @@ impl ExtensionSubgraphExecutor {
         &self,
         execution_request: HttpExecutionRequest<'a>,
     ) -> Result<JsonValue> {
-        if execution_request.representations.is_some() {
-            return Err(anyhow!(
-                "Entity representations are not supported yet for extension subgraphs"
-            ));
-        }
-
         let document = graphql_parser::parse_query::<String>(execution_request.query)
             .context("failed to parse extension operation")?;
         let operation = self
@@ -87,6 +97,33 @@ impl ExtensionSubgraphExecutor {
         let fragments = collect_fragment_definitions(&document);
         let variables = self.build_variables(execution_request.variables)?;
 
+        let representations = match execution_request.representations.as_deref() {
+            Some(bytes) => Some(
+                serde_json::from_slice::<Vec<JsonValue>>(bytes)
+                    .context("invalid entity representations")?,
+            ),
+            None => variables
+                .get("representations")
+                .and_then(JsonValue::as_array)
+                .cloned(),
+        };
+        if let Some(representations) = representations {
+            let OperationDefinition::Query(query) = operation else {
+                return Err(anyhow!("entities can only be fetched by a query"));
+            };
+            let data = self
+                .resolve_entities(
+                    &query.selection_set,
+                    &representations,
+                    &fragments,
+                    &variables,
+                )
+                .await?;
+            let mut response = Map::new();
+            response.insert("data".to_string(), JsonValue::Object(data));
+            return Ok(JsonValue::Object(response));
+        }
+
         let data_value = match operation {
             OperationDefinition::Query(query) => {
                 let map = self
@@ -190,6 +227,108 @@ impl ExtensionSubgraphExecutor {
         Ok(map)
     }
 
+    /// Resolves `_entities(representations:)`, the planner's request for objects another
+    /// subgraph referenced, such as a `node(id:)` result or every issue in a list. The
+    /// extension's `__resolveReference` resolver gets each representation as the parent,
+    /// its global `id` replaced by the internal one, and all of them are resolved as one
+    /// batch.
+    async fn resolve_entities<'a>(
+        &self,
+        selection_set: &'a SelectionSet<'a, String>,
+        representations: &[JsonValue],
+        fragments: &FragmentMap<'a>,
+        variables: &Vars,
+    ) -> Result<Map<String, JsonValue>> {
+        let mut map = Map::new();
+        for field in selection_fields(selection_set, "Query", fragments)? {
+            if field.name != "_entities" {
+                return Err(anyhow!("Unsupported entity field `{}`", field.name));
+            }
+            // `None` for a global ID of another type, which resolves to null
+            let references = representations
+                .iter()
+                .map(|representation| self.entity_reference(representation))
+                .collect::<Result<Vec<_>>>()?;
+            let requests = references
+                .iter()
+                .flatten()
+                .map(|(type_name, reference)| FieldRequest {
+                    field_name: "__resolveReference".to_string(),
+                    parent_type: type_name.clone(),
+                    arguments: JsonValue::Object(Map::new()),
+                    parent: Some(reference.clone()),
+                })
+                .collect();
+            let context = self.build_request_context(&Map::new()).await?;
+            let mut resolved = self
+                .runtime
+                .resolve_fields(requests, context)
+                .await
+                .with_context(|| {
+                    format!(
+                        "extension `{}` failed to resolve entity references",
+                        self.subgraph_name
+                    )
+                })?
+                .into_iter();
+
+            let mut entities = Vec::with_capacity(representations.len());
+            for reference in &references {
+                let Some((type_name, _)) = reference else {
+                    entities.push(JsonValue::Null);
+                    continue;
+                };
+                let result = resolved
+                    .next()
+                    .ok_or_else(|| anyhow!("missing entity result"))?
+                    .with_context(|| {
+                        format!(
+                            "extension `{}` failed to resolve a `{}` reference",
+                            self.subgraph_name, type_name
+                        )
+                    })?;
+                entities.push(
+                    self.project_single(
+                        &result,
+                        &field.selection_set,
+                        type_name,
+                        fragments,
+                        variables,
+                    )
+                    .await?,
+                );
+            }
+            map.insert(response_key(field), JsonValue::Array(entities));
+        }
+        Ok(map)
+    }
+
+    /// The type and `__resolveReference` parent for an entity representation, or `None`
+    /// when its global `id` belongs to another type.
+    fn entity_reference(&self, representation: &JsonValue) -> Result<Option<(String, JsonValue)>> {
+        let type_name = representation
+            .get("__typename")
+            .and_then(JsonValue::as_str)
+            .ok_or_else(|| anyhow!("entity representation has no `__typename`"))?;
+        if !self.schema.node_types.contains(type_name) {
+            return Err(anyhow!(
+                "`{}` is not an entity of extension `{}`",
+                type_name,
+                self.subgraph_name
+            ));
+        }
+        let mut reference = representation.clone();
+        if let Some(id) = representation.get("id").and_then(JsonValue::as_str)
+            && let Some((id_type, internal_id)) = global_id::decode(id)
+        {
+            if id_type != type_name {
+                return Ok(None);
+            }
+            reference["id"] = JsonValue::String(internal_id);
+        }
+        Ok(Some((type_name.to_string(), reference)))
+    }
+
     async fn resolve_mutation_selection_set<'a>(
         &self,
         selection_set: &'a SelectionSet<'a, String>,
@@ -220,7 +359,7 @@ impl ExtensionSubgraphExecutor {
             .query_fields
             .get(&field.name)
             .ok_or_else(|| anyhow!("Unsupported query field `{}`", field.name))?;
-        let args = self.build_argument_map(field, variables)?;
+        let args = self.build_argument_map(field, field_meta, variables)?;
         let context = self.build_request_context(&args).await?;
         let args_value = JsonValue::Object(args);
         let result = self
@@ -239,7 +378,14 @@ impl ExtensionSubgraphExecutor {
                     self.subgraph_name, field.name
                 )
             })?;
-        self.project_by_type(&result, &field.selection_set, field_meta, fragments)
+        self.project_by_type(
+            &result,
+            &field.selection_set,
+            field_meta,
+            fragments,
+            variables,
+        )
+        .await
     }
 
     async fn resolve_mutation_field<'a>(
@@ -253,9 +399,15 @@ impl ExtensionSubgraphExecutor {
             .mutation_fields
             .get(&field.name)
             .ok_or_else(|| anyhow!("Unsupported mutation field `{}`", field.name))?;
-        let args = self.build_argument_map(field, variables)?;
+        let args = self.build_argument_map(field, field_meta, variables)?;
         let context = self.build_request_context(&args).await?;
         let args_value = JsonValue::Object(args);
+        let actor_did = context.user.as_ref().map(|user| user.id.as_str());
+        let flagged = match moderation::mutation_content(&field.name, &args_value, actor_did) {
+            Some(content) => moderation::screen(content).await?,
+            None => None,
+        };
+        let arguments = flagged.is_some().then(|| args_value.clone());
         let result = self
             .runtime
             .resolve_field(
@@ -272,17 +424,38 @@ impl ExtensionSubgraphExecutor {
                     self.subgraph_name, field.name
                 )
             })?;
-        self.project_by_type(&result, &field.selection_set, field_meta, fragments)
+        if let (Some(flagged), Some(arguments)) = (flagged, arguments) {
+            let removal = moderation::mutation_removal(&field.name, &arguments, &result);
+            let subject_id = result.get("id").and_then(JsonValue::as_str);
+            flagged
+                .record(&self.pool, self.runtime.name(), subject_id, removal)
+                .await?;
+        }
+        self.project_by_type(
+            &result,
+            &field.selection_set,
+            field_meta,
+            fragments,
+            variables,
+        )
+        .await
     }
 
+    /// Evaluates the arguments of `field`. Global IDs in `ID` arguments are replaced by
+    /// internal IDs; other values, such as IDs the extension minted itself, pass through.
     fn build_argument_map(
         &self,
         field: &Field<'_, String>,
+        field_meta: &FieldTypeMeta,
         variables: &Vars,
     ) -> Result<Map<String, JsonValue>> {
         let mut map = Map::new();
         for (name, value) in &field.arguments {
-            map.insert(name.clone(), self.evaluate_value(value, variables)?);
+            let mut value = self.evaluate_value(value, variables)?;
+            if field_meta.id_arguments.contains(name) {
+                decode_ids(&mut value);
+            }
+            map.insert(name.clone(), value);
         }
         Ok(map)
     }
@@ -314,6 +487,30 @@ impl ExtensionSubgraphExecutor {
                 is_remote: record.remote_url.is_some(),
                 remote_url: record.remote_url,
             });
+        } else if let Some(group_path) = args.get("groupPath").and_then(|value| value.as_str()) {
+            let group = PathResolver::new(self.pool.clone())
+                .resolve_group(group_path)
+                .await?
+                .ok_or_else(|| anyhow!("group `{}` not found", group_path))?;
+            context.scope = ContextScope::Group;
+            context.group = Some(RuntimeGroupContext {
+                id: group.id,
+                path: PathResolver::split(group_path)?.join("/"),
+            });
+        }
+
+        if let Some(viewer) = current_viewer() {
+            context.scope = match context.scope {
+                ContextScope::Repository => ContextScope::RepositoryUser,
+                ContextScope::Group => ContextScope::GroupUser,
+                _ => ContextScope::User,
+            };
+            context.user = Some(RuntimeUserContext {
+                id: viewer.did,
+                username: viewer.handle,
+                display_name: viewer.display_name,
+                email: None,
+            });
         }
 
         Ok(context)
@@ -367,89 +564,173 @@ impl ExtensionSubgraphExecutor {
         })
     }
 
-    fn project_by_type<'a>(
-        &self,
-        value: &JsonValue,
+    fn project_by_type<'b, 'a: 'b>(
+        &'b self,
+        value: &'b JsonValue,
         selection_set: &'a SelectionSet<'a, String>,
-        field_type: &FieldTypeMeta,
-        fragments: &FragmentMap<'a>,
-    ) -> Result<JsonValue> {
-        if field_type.is_list {
-            let items = match value {
-                JsonValue::Array(items) => items,
-                JsonValue::Null => return Ok(JsonValue::Null),
-                _ => {
-                    return Err(anyhow!(
-                        "Field expected to resolve to a list but got {:?}",
-                        value
-                    ));
+        field_type: &'b FieldTypeMeta,
+        fragments: &'b FragmentMap<'a>,
+        variables: &'b Vars,
+    ) -> BoxFuture<'b, Result<JsonValue>> {
+        async move {
+            if field_type.is_list {
+                let items = match value {
+                    JsonValue::Array(items) => items,
+                    JsonValue::Null => return Ok(JsonValue::Null),
+                    _ => {
+                        return Err(anyhow!(
+                            "Field expected to resolve to a list but got {:?}",
+                            value
+                        ));
+                    }
+                };
+                let mut projected = Vec::with_capacity(items.len());
+                for item in items {
+                    projected.push(
+                        self.project_single(
+                            item,
+                            selection_set,
+                            &field_type.base_type,
+                            fragments,
+                            variables,
+                        )
+                        .await?,
+                    );
                 }
-            };
-            let mut projected = Vec::with_capacity(items.len());
-            for item in items {
-                projected.push(self.project_single(
-                    item,
+                Ok(JsonValue::Array(projected))
+            } else {
+                self.project_single(
+                    value,
                     selection_set,
                     &field_type.base_type,
                     fragments,
-                )?);
+                    variables,
+                )
+                .await
             }
-            Ok(JsonValue::Array(projected))
-        } else {
-            self.project_single(value, selection_set, &field_type.base_type, fragments)
         }
+        .boxed()
     }
 
-    fn project_single<'a>(
-        &self,
-        value: &JsonValue,
+    fn project_single<'b, 'a: 'b>(
+        &'b self,
+        value: &'b JsonValue,
         selection_set: &'a SelectionSet<'a, String>,
-        type_name: &str,
-        fragments: &FragmentMap<'a>,
-    ) -> Result<JsonValue> {
-        if selection_set.items.is_empty() || !self.schema.object_types.contains_key(type_name) {
-            return Ok(value.clone());
-        }
-
-        let object = match value {
-            JsonValue::Object(map) => map,
-            JsonValue::Null => return Ok(JsonValue::Null),
-            other => {
-                return Err(anyhow!(
-                    "Expected object value for type `{}` but got {:?}",
-                    type_name,
-                    other
-                ));
+        type_name: &'b str,
+        fragments: &'b FragmentMap<'a>,
+        variables: &'b Vars,
+    ) -> BoxFuture<'b, Result<JsonValue>> {
+        async move {
+            if selection_set.items.is_empty() || !self.schema.object_types.contains_key(type_name) {
+                return Ok(value.clone());
             }
-        };
 
-        let type_meta = self
-            .schema
-            .object_types
-            .get(type_name)
-            .ok_or_else(|| anyhow!("Unknown object type `{}`", type_name))?;
+            let object = match value {
+                JsonValue::Object(map) => map,
+                JsonValue::Null => return Ok(JsonValue::Null),
+                other => {
+                    return Err(anyhow!(
+                        "Expected object value for type `{}` but got {:?}",
+                        type_name,
+                        other
+                    ));
+                }
+            };
 
-        let mut map = Map::new();
-        let fields = selection_fields(selection_set, type_name, fragments)?;
-        for field in fields {
-            let key = response_key(field);
-            if field.name == "__typename" {
-                map.insert(key, JsonValue::String(type_name.to_string()));
-                continue;
+            let type_meta = self
+                .schema
+                .object_types
+                .get(type_name)
+                .ok_or_else(|| anyhow!("Unknown object type `{}`", type_name))?;
+
+            let mut map = Map::new();
+            let fields = selection_fields(selection_set, type_name, fragments)?;
+            for field in fields {
+                let key = response_key(field);
+                if field.name == "__typename" {
+                    map.insert(key, JsonValue::String(type_name.to_string()));
+                    continue;
+                }
+
+                let field_meta = type_meta.fields.get(&field.name).ok_or_else(|| {
+                    anyhow!("Unknown field `{}` on type `{}`", field.name, type_name)
+                })?;
+
+                let child_value = if field_meta.has_arguments {
+                    self.resolve_object_field(field, field_meta, type_name, object, variables)
+                        .await?
+                } else {
+                    match object.get(&field.name) {
+                        Some(JsonValue::String(id))
+                            if field.name == "id" && self.schema.node_types.contains(type_name) =>
+                        {
+                            JsonValue::String(global_id::encode(type_name, id))
+                        }
+                        Some(value) => value.clone(),
+                        None => JsonValue::Null,
+                    }
+                };
+                let projected_child = self
+                    .project_by_type(
+                        &child_value,
+                        &field.selection_set,
+                        field_meta,
+                        fragments,
+                        variables,
+                    )
+                    .await?;
+                map.insert(key, projected_child);
             }
 
-            let field_meta = type_meta
-                .fields
-                .get(&field.name)
-                .ok_or_else(|| anyhow!("Unknown field `{}` on type `{}`", field.name, type_name))?;
+            Ok(JsonValue::Object(map))
+        }
+        .boxed()
+    }
 
-            let child_value = object.get(&field.name).cloned().unwrap_or(JsonValue::Null);
-            let projected_child =
-                self.project_by_type(&child_value, &field.selection_set, field_meta, fragments)?;
-            map.insert(key, projected_child);
+    /// Resolves a field with arguments on one of the extension's object types, such as
+    /// `Issue.comments(first:)`, with the object as the parent. The call runs in the
+    /// object's repository when the object has a `repositoryId`.
+    async fn resolve_object_field(
+        &self,
+        field: &Field<'_, String>,
+        field_meta: &FieldTypeMeta,
+        type_name: &str,
+        parent: &Map<String, JsonValue>,
+        variables: &Vars,
+    ) -> Result<JsonValue> {
+        let args = self.build_argument_map(field, field_meta, variables)?;
+        let mut context_args = Map::new();
+        if let Some(repository_id) = parent.get("repositoryId") {
+            context_args.insert("repositoryId".to_string(), repository_id.clone());
         }
+        let context = self.build_request_context(&context_args).await?;
+        self.runtime
+            .resolve_field(
+                field.name.clone(),
+                type_name.to_string(),
+                JsonValue::Object(args),
+                context,
+                Some(JsonValue::Object(parent.clone())),
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "extension `{}` failed to resolve field `{}.{}`",
+                    self.subgraph_name, type_name, field.name
+                )
+            })
+    }
+}
 
-        Ok(JsonValue::Object(map))
+fn decode_ids(value: &mut JsonValue) {
+    match value {
+        JsonValue::String(id) => {
+            if let Some((_, internal_id)) = global_id::decode(id) {
+                *id = internal_id;
+            }
+        }
+        JsonValue::Array(items) => items.iter_mut().for_each(decode_ids),
+        _ => {}
     }
 }
 
@@ -543,7 +824,15 @@ impl SubgraphExecutor for ExtensionSubgraphExecutor {
                 }
             },
             Err(err) => {
-                let body = graphql_error_body(JsonValue::String(err.to_string()));
+                let body = match err.downcast_ref::<ExtensionFieldError>() {
+                    Some(structured) => serde_json::json!({
+                        "errors": [{
+                            "message": structured.message,
+                            "extensions": structured.extensions,
+                        }],
+                    }),
+                    None => graphql_error_body(JsonValue::String(err.to_string())),
+                };
                 Bytes::from(serde_json::to_vec(&body).expect("serialization failure"))
             }
         }
@@ -577,10 +866,23 @@ impl ExtensionSchemaMetadata {
         match &type_def.kind {
             TypeKind::Object(obj) => {
                 let type_name = type_def.name.node.to_string();
+                if obj.implements.iter().any(|name| name.node == "Node") {
+                    self.node_types.insert(type_name.clone());
+                }
                 let mut field_map = HashMap::new();
                 for field in &obj.fields {
                     let field_name = field.node.name.node.to_string();
-                    let field_type = FieldTypeMeta::from_type(&field.node.ty.node);
+                    let mut field_type = FieldTypeMeta::from_type(&field.node.ty.node);
+                    field_type.id_arguments = field
+                        .node
+                        .arguments
+                        .iter()
+                        .filter(|argument| {
+                            FieldTypeMeta::from_type(&argument.node.ty.node).base_type == "ID"
+                        })
+                        .map(|argument| argument.node.name.node.to_string())
+                        .collect();
+                    field_type.has_arguments = !field.node.arguments.is_empty();
                     if type_name == "Query" {
                         self.query_fields
                             .insert(field_name.clone(), field_type.clone());
@@ -618,6 +920,8 @@ impl FieldTypeMeta {
             BaseType::Named(name) => FieldTypeMeta {
                 base_type: name.to_string(),
                 is_list: false,
+                id_arguments: Vec::new(),
+                has_arguments: false,
             },
             BaseType::List(inner) => {
                 let mut inner_meta = FieldTypeMeta::from_type(inner);
@@ -0,0 +1,752 @@
+//! Generated responses for frontend development.
+//!
+//! With `server.mock.enabled`, [`super::RouterState::mocked`] composes the supergraph
+//! from the loaded extensions as usual but routes every subgraph to a
+//! [`MockSubgraphExecutor`]. Planning, projection and introspection are unchanged, so the
+//! web UI sees the exact schema it would see in production, extension types included.
+//!
+//! Values are derived from a hash of the configured seed, the field's path in the
+//! response and the field's arguments, so the same query always returns the same data and
+//! `getRepository(path: "a")` differs from `getRepository(path: "b")`. Scalars are shaped
+//! by the field name (`email`, `url`, `createdAt`, `title`, ...), enums pick one of
+//! their values, and interfaces and unions pick one of their concrete types.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use bytes::Bytes;
+use graphql_parser::query::{
+    Definition as QueryDefinition, Field, FragmentDefinition, OperationDefinition, Selection,
+    SelectionSet, TypeCondition, Value as AstValue,
+};
+use graphql_parser::schema::{Definition, Type, TypeDefinition, TypeExtension};
+use hive_router_plan_executor::executors::common::{HttpExecutionRequest, SubgraphExecutor};
+use serde_json::{Map, Value as JsonValue};
+
+use super::{graphql_error_body, sonic_to_serde};
+use crate::config::MockConfig;
+
+type Vars = HashMap<String, JsonValue>;
+type FragmentMap<'a> = HashMap<&'a str, &'a FragmentDefinition<'a, String>>;
+
+/// Output types of the composed schema, reduced to what generation needs.
+enum Shape {
+    Object {
+        fields: HashMap<String, Type<'static, String>>,
+        interfaces: Vec<String>,
+    },
+    Interface {
+        fields: HashMap<String, Type<'static, String>>,
+    },
+    Union {
+        members: Vec<String>,
+    },
+    Enum {
+        values: Vec<String>,
+    },
+}
+
+pub(crate) struct MockSchema {
+    shapes: HashMap<String, Shape>,
+    query_type: String,
+    mutation_type: String,
+}
+
+impl MockSchema {
+    pub fn parse(supergraph_sdl: &str) -> Result<Self> {
+        let document = graphql_parser::parse_schema::<String>(supergraph_sdl)
+            .map_err(|e| anyhow!("Failed to parse composed supergraph: {e}"))?
+            .into_static();
+
+        let mut schema = Self {
+            shapes: HashMap::new(),
+            query_type: "Query".to_string(),
+            mutation_type: "Mutation".to_string(),
+        };
+        for definition in document.definitions {
+            match definition {
+                Definition::SchemaDefinition(definition) => {
+                    if let Some(query) = definition.query {
+                        schema.query_type = query;
+                    }
+                    if let Some(mutation) = definition.mutation {
+                        schema.mutation_type = mutation;
+                    }
+                }
+                Definition::TypeDefinition(TypeDefinition::Object(object)) => schema.add_object(
+                    object.name,
+                    field_types(object.fields),
+                    object.implements_interfaces,
+                ),
+                Definition::TypeExtension(TypeExtension::Object(extension)) => schema.add_object(
+                    extension.name,
+                    field_types(extension.fields),
+                    extension.implements_interfaces,
+                ),
+                Definition::TypeDefinition(TypeDefinition::Interface(interface)) => {
+                    schema.shapes.insert(
+                        interface.name,
+                        Shape::Interface {
+                            fields: field_types(interface.fields),
+                        },
+                    );
+                }
+                Definition::TypeDefinition(TypeDefinition::Union(union)) => {
+                    schema.shapes.insert(
+                        union.name,
+                        Shape::Union {
+                            members: union.types,
+                        },
+                    );
+                }
+                Definition::TypeDefinition(TypeDefinition::Enum(enumeration)) => {
+                    schema.shapes.insert(
+                        enumeration.name,
+                        Shape::Enum {
+                            values: enumeration.values.into_iter().map(|v| v.name).collect(),
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
+        Ok(schema)
+    }
+
+    fn add_object(
+        &mut self,
+        name: String,
+        fields: HashMap<String, Type<'static, String>>,
+        implements: Vec<String>,
+    ) {
+        match self.shapes.get_mut(&name) {
+            Some(Shape::Object {
+                fields: existing,
+                interfaces,
+            }) => {
+                existing.extend(fields);
+                interfaces.extend(implements);
+            }
+            _ => {
+                self.shapes.insert(
+                    name,
+                    Shape::Object {
+                        fields,
+                        interfaces: implements,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Object types an abstract type can resolve to, in a stable order.
+    fn possible_types(&self, name: &str) -> Vec<&str> {
+        match self.shapes.get(name) {
+            Some(Shape::Union { members }) => members.iter().map(String::as_str).collect(),
+            Some(Shape::Interface { .. }) => {
+                let mut implementors: Vec<&str> = self
+                    .shapes
+                    .iter()
+                    .filter(|(_, shape)| {
+                        matches!(shape, Shape::Object { interfaces, .. } if interfaces.iter().any(|i| i == name))
+                    })
+                    .map(|(object, _)| object.as_str())
+                    .collect();
+                implementors.sort_unstable();
+                implementors
+            }
+            _ => vec![name],
+        }
+    }
+
+    /// Whether a fragment on `condition` applies to an object of type `object`.
+    fn condition_matches(&self, object: &str, condition: &str) -> bool {
+        if object == condition {
+            return true;
+        }
+        match self.shapes.get(object) {
+            Some(Shape::Object { interfaces, .. }) if interfaces.iter().any(|i| i == condition) => {
+                true
+            }
+            _ => matches!(
+                self.shapes.get(condition),
+                Some(Shape::Union { members }) if members.iter().any(|m| m == object)
+            ),
+        }
+    }
+
+    fn field_type(&self, object: &str, field: &str) -> Option<&Type<'static, String>> {
+        match self.shapes.get(object)? {
+            Shape::Object { fields, .. } | Shape::Interface { fields } => fields.get(field),
+            _ => None,
+        }
+    }
+}
+
+fn field_types(
+    fields: Vec<graphql_parser::schema::Field<'static, String>>,
+) -> HashMap<String, Type<'static, String>> {
+    fields
+        .into_iter()
+        .map(|field| (field.name, field.field_type))
+        .collect()
+}
+
+pub(crate) struct MockSubgraphExecutor {
+    schema: Arc<MockSchema>,
+    seed: u64,
+    list_length: usize,
+}
+
+impl MockSubgraphExecutor {
+    pub fn new(schema: Arc<MockSchema>, config: &MockConfig) -> Self {
+        Self {
+            schema,
+            seed: config.seed,
+            list_length: config.list_length.max(1),
+        }
+    }
+
+    pub fn execute_operation(
+        &self,
+        query: &str,
+        operation_name: Option<&str>,
+        variables: Vars,
+    ) -> Result<JsonValue> {
+        let document = graphql_parser::parse_query::<String>(query)
+            .context("failed to parse GraphQL document")?;
+
+        let mut fragments: FragmentMap<'_> = HashMap::new();
+        let mut operations = Vec::new();
+        for definition in &document.definitions {
+            match definition {
+                QueryDefinition::Fragment(fragment) => {
+                    fragments.insert(fragment.name.as_str(), fragment);
+                }
+                QueryDefinition::Operation(operation) => operations.push(operation),
+            }
+        }
+        let operation = operations
+            .into_iter()
+            .find(|operation| {
+                let name = match operation {
+                    OperationDefinition::Query(query) => query.name.as_deref(),
+                    OperationDefinition::Mutation(mutation) => mutation.name.as_deref(),
+                    OperationDefinition::Subscription(subscription) => subscription.name.as_deref(),
+                    OperationDefinition::SelectionSet(_) => None,
+                };
+                operation_name.is_none() || name == operation_name
+            })
+            .context("operation not found")?;
+
+        let (root_type, selection_set) = match operation {
+            OperationDefinition::Query(query) => (&self.schema.query_type, &query.selection_set),
+            OperationDefinition::Mutation(mutation) => {
+                (&self.schema.mutation_type, &mutation.selection_set)
+            }
+            OperationDefinition::Subscription(_) => {
+                return Err(anyhow!("subscriptions are not supported"));
+            }
+            OperationDefinition::SelectionSet(selection_set) => {
+                (&self.schema.query_type, selection_set)
+            }
+        };
+
+        let generator = Generator {
+            schema: &self.schema,
+            fragments: &fragments,
+            variables: &variables,
+            list_length: self.list_length,
+        };
+        let data = generator.object(root_type, selection_set, mix(self.seed, root_type))?;
+
+        let mut response = Map::new();
+        response.insert("data".to_string(), JsonValue::Object(data));
+        Ok(JsonValue::Object(response))
+    }
+}
+
+struct Generator<'s, 'a> {
+    schema: &'s MockSchema,
+    fragments: &'s FragmentMap<'a>,
+    variables: &'s Vars,
+    list_length: usize,
+}
+
+impl<'a> Generator<'_, 'a> {
+    fn object(
+        &self,
+        type_name: &str,
+        selection_set: &'a SelectionSet<'a, String>,
+        seed: u64,
+    ) -> Result<Map<String, JsonValue>> {
+        let mut fields = Vec::new();
+        self.collect_fields(type_name, selection_set, &mut fields)?;
+
+        let mut map = Map::new();
+        for field in fields {
+            let key = field.alias.clone().unwrap_or_else(|| field.name.clone());
+            let value = if field.name == "__typename" {
+                JsonValue::String(type_name.to_string())
+            } else {
+                let field_type = self
+                    .schema
+                    .field_type(type_name, &field.name)
+                    .ok_or_else(|| anyhow!("Unknown field `{}` on `{}`", field.name, type_name))?;
+                let seed = mix(mix(seed, &field.name), &self.argument_fingerprint(field));
+                self.value(field_type, field, seed)?
+            };
+            // A key selected twice gets the same seed, so merging keeps both selections.
+            match map.get_mut(&key) {
+                Some(existing) => merge(existing, value),
+                None => {
+                    map.insert(key, value);
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    fn collect_fields(
+        &self,
+        type_name: &str,
+        selection_set: &'a SelectionSet<'a, String>,
+        out: &mut Vec<&'a Field<'a, String>>,
+    ) -> Result<()> {
+        for selection in &selection_set.items {
+            match selection {
+                Selection::Field(field) => out.push(field),
+                Selection::FragmentSpread(spread) => {
+                    let fragment = self
+                        .fragments
+                        .get(spread.fragment_name.as_str())
+                        .ok_or_else(|| anyhow!("Unknown fragment `{}`", spread.fragment_name))?;
+                    let TypeCondition::On(condition) = &fragment.type_condition;
+                    if self.schema.condition_matches(type_name, condition) {
+                        self.collect_fields(type_name, &fragment.selection_set, out)?;
+                    }
+                }
+                Selection::InlineFragment(inline) => {
+                    let applies = match &inline.type_condition {
+                        Some(TypeCondition::On(condition)) => {
+                            self.schema.condition_matches(type_name, condition)
+                        }
+                        None => true,
+                    };
+                    if applies {
+                        self.collect_fields(type_name, &inline.selection_set, out)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn value(
+        &self,
+        field_type: &Type<'static, String>,
+        field: &'a Field<'a, String>,
+        seed: u64,
+    ) -> Result<JsonValue> {
+        match field_type {
+            Type::NonNullType(inner) => self.value(inner, field, seed),
+            Type::ListType(inner) => {
+                let length = 1 + (seed % self.list_length as u64) as usize;
+                (0..length)
+                    .map(|index| self.value(inner, field, mix(seed, &index.to_string())))
+                    .collect::<Result<Vec<_>>>()
+                    .map(JsonValue::Array)
+            }
+            Type::NamedType(name) => match self.schema.shapes.get(name) {
+                Some(Shape::Enum { values }) if !values.is_empty() => Ok(JsonValue::String(
+                    values[(seed % values.len() as u64) as usize].clone(),
+                )),
+                Some(Shape::Object { .. } | Shape::Interface { .. } | Shape::Union { .. }) => {
+                    let candidates = self.schema.possible_types(name);
+                    let Some(concrete) =
+                        candidates.get((seed % candidates.len().max(1) as u64) as usize)
+                    else {
+                        return Ok(JsonValue::Null);
+                    };
+                    self.object(concrete, &field.selection_set, seed)
+                        .map(JsonValue::Object)
+                }
+                _ => Ok(scalar(name, &field.name, seed)),
+            },
+        }
+    }
+
+    /// Arguments as text, so different arguments produce different data.
+    fn argument_fingerprint(&self, field: &Field<'a, String>) -> String {
+        field
+            .arguments
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, self.argument_json(value)))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn argument_json(&self, value: &AstValue<'a, String>) -> JsonValue {
+        match value {
+            AstValue::Variable(name) => self.variables.get(name).cloned().unwrap_or_default(),
+            AstValue::Int(number) => number.as_i64().map(JsonValue::from).unwrap_or_default(),
+            AstValue::Float(number) => JsonValue::from(*number),
+            AstValue::String(text) => JsonValue::String(text.clone()),
+            AstValue::Boolean(flag) => JsonValue::Bool(*flag),
+            AstValue::Null => JsonValue::Null,
+            AstValue::Enum(name) => JsonValue::String(name.clone()),
+            AstValue::List(items) => {
+                JsonValue::Array(items.iter().map(|item| self.argument_json(item)).collect())
+            }
+            AstValue::Object(fields) => JsonValue::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), self.argument_json(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+fn merge(into: &mut JsonValue, from: JsonValue) {
+    match (into, from) {
+        (JsonValue::Object(into), JsonValue::Object(from)) => {
+            for (key, value) in from {
+                match into.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        into.insert(key, value);
+                    }
+                }
+            }
+        }
+        (JsonValue::Array(into), JsonValue::Array(from)) => {
+            for (existing, value) in into.iter_mut().zip(from) {
+                merge(existing, value);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// FNV-1a over the previous seed and `part`; stable across platforms and releases.
+fn mix(seed: u64, part: &str) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64 ^ seed;
+    for byte in part.bytes().chain(seed.to_le_bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+const FIRST_NAMES: &[&str] = &[
+    "alice", "bruno", "chen", "dana", "emeka", "freya", "gustav", "hana", "ines", "jonas",
+];
+const WORDS: &[&str] = &[
+    "atlas", "beacon", "cedar", "delta", "ember", "forge", "granite", "harbor", "indigo",
+    "juniper", "kestrel", "lumen", "meadow", "nimbus", "orbit", "prism", "quartz", "ridge",
+    "summit", "tundra",
+];
+const SENTENCES: &[&str] = &[
+    "Fixes a crash when the cache directory is missing.",
+    "Adds a settings page for notification preferences.",
+    "The build fails on a clean checkout with the default toolchain.",
+    "Refactors the storage layer so backends can be swapped.",
+    "Documents the release process and the required permissions.",
+    "Clicking save twice creates duplicate entries.",
+];
+/// 2023-11-14T22:13:20Z; generated timestamps fall in the year after it.
+const EPOCH: i64 = 1_700_000_000;
+
+fn pick<'w>(words: &[&'w str], seed: u64) -> &'w str {
+    words[(seed % words.len() as u64) as usize]
+}
+
+fn timestamp(seed: u64) -> i64 {
+    EPOCH + (seed % 31_536_000) as i64
+}
+
+fn scalar(type_name: &str, field_name: &str, seed: u64) -> JsonValue {
+    let name = field_name.to_ascii_lowercase();
+    match type_name {
+        "ID" => JsonValue::String(format!("mock-{:012x}", seed & 0xffff_ffff_ffff)),
+        "Boolean" => JsonValue::Bool(seed & 1 == 1),
+        "Float" => JsonValue::from((seed % 10_000) as f64 / 100.0),
+        "Int" => {
+            let value = if name.ends_with("at") || name.ends_with("time") {
+                timestamp(seed)
+            } else if name == "number" || name.ends_with("number") {
+                1 + (seed % 500) as i64
+            } else if ["count", "total", "size", "bytes"]
+                .iter()
+                .any(|w| name.contains(w))
+            {
+                (seed % 1000) as i64
+            } else {
+                (seed % 100) as i64
+            };
+            JsonValue::from(value)
+        }
+        "JSON" | "JSONObject" => JsonValue::Object(Map::new()),
+        "Upload" => JsonValue::Null,
+        _ if type_name.contains("Date") || type_name.contains("Time") => {
+            JsonValue::String(rfc3339(timestamp(seed)))
+        }
+        _ => JsonValue::String(fake_string(&name, seed)),
+    }
+}
+
+/// A string shaped like what a field of this (lower-cased) name usually holds.
+fn fake_string(name: &str, seed: u64) -> String {
+    let first = pick(FIRST_NAMES, seed);
+    let word = pick(WORDS, seed >> 8);
+    let other = pick(WORDS, seed >> 16);
+    if name.contains("email") {
+        format!("{}@example.com", first)
+    } else if name == "did" || name.ends_with("did") {
+        format!("did:plc:{:016x}{:08x}", seed, mix(seed, "did") as u32)
+    } else if name.contains("url") || name.contains("href") || name.contains("avatar") {
+        format!("https://example.com/{}/{}", word, seed % 1000)
+    } else if name.contains("handle") {
+        format!("{}.example.com", first)
+    } else if ["sha", "oid", "commit", "hash", "digest"]
+        .iter()
+        .any(|w| name.contains(w))
+    {
+        format!(
+            "{:016x}{:016x}{:08x}",
+            seed,
+            mix(seed, "1"),
+            mix(seed, "2") as u32
+        )
+    } else if name.ends_with("at") || name.contains("date") || name.contains("time") {
+        rfc3339(timestamp(seed))
+    } else if ["slug", "path", "branch", "ref"]
+        .iter()
+        .any(|w| name.contains(w))
+    {
+        format!("{}-{}", word, other)
+    } else if name.contains("language") || name == "locale" {
+        pick(&["en", "de", "fr", "ja", "pt-BR"], seed).to_string()
+    } else if name.contains("color") || name.contains("colour") {
+        format!("#{:06x}", seed & 0xff_ffff)
+    } else if [
+        "description",
+        "body",
+        "content",
+        "message",
+        "summary",
+        "text",
+        "readme",
+    ]
+    .iter()
+    .any(|w| name.contains(w))
+    {
+        format!("{} {}", pick(SENTENCES, seed), pick(SENTENCES, seed >> 12))
+    } else if name.contains("name") || name.contains("title") || name.contains("label") {
+        let mut title = format!("{} {}", word, other);
+        title[..1].make_ascii_uppercase();
+        title
+    } else {
+        format!("{} {}", word, other)
+    }
+}
+
+/// Formats seconds since the Unix epoch as an RFC 3339 UTC timestamp.
+fn rfc3339(secs: i64) -> String {
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+    // Civil-from-days (Howard Hinnant), valid for the whole proleptic Gregorian calendar.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[async_trait]
+impl SubgraphExecutor for MockSubgraphExecutor {
+    async fn execute<'a>(&self, execution_request: HttpExecutionRequest<'a>) -> Bytes {
+        let result = execution_request
+            .variables
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, value)| Ok((name.to_string(), sonic_to_serde(value)?)))
+            .collect::<Result<Vars>>()
+            .and_then(|variables| {
+                self.execute_operation(
+                    execution_request.query,
+                    execution_request.operation_name,
+                    variables,
+                )
+            });
+        let body =
+            result.unwrap_or_else(|err| graphql_error_body(JsonValue::String(err.to_string())));
+        Bytes::from(serde_json::to_vec(&body).expect("serialization failed"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SDL: &str = r#"
+schema { query: Query mutation: Mutation }
+
+type Query {
+  repository(path: String!): Repository
+  repositories: [Repository!]!
+  search: [SearchResult!]!
+  node: Node
+}
+
+type Mutation {
+  createIssue(title: String!): Issue!
+}
+
+interface Node { id: ID! }
+
+type Repository implements Node {
+  id: ID!
+  slug: String!
+  createdAt: Int!
+  ownerEmail: String
+  visibility: Visibility!
+}
+
+type Issue implements Node {
+  id: ID!
+  title: String!
+  number: Int!
+}
+
+union SearchResult = Repository | Issue
+
+enum Visibility { PUBLIC PRIVATE }
+"#;
+
+    fn executor(seed: u64) -> MockSubgraphExecutor {
+        let schema = Arc::new(MockSchema::parse(SDL).unwrap());
+        let config = MockConfig {
+            enabled: true,
+            seed,
+            list_length: 4,
+        };
+        MockSubgraphExecutor::new(schema, &config)
+    }
+
+    fn run(executor: &MockSubgraphExecutor, query: &str) -> JsonValue {
+        executor
+            .execute_operation(query, None, Vars::new())
+            .unwrap()["data"]
+            .clone()
+    }
+
+    #[test]
+    fn generated_data_follows_the_schema_and_is_deterministic() {
+        let query = r#"{ repositories { id slug createdAt ownerEmail visibility __typename } }"#;
+        let data = run(&executor(7), query);
+        assert_eq!(data, run(&executor(7), query));
+        assert_ne!(data, run(&executor(8), query));
+
+        let repositories = data["repositories"].as_array().unwrap();
+        assert!((1..=4).contains(&repositories.len()));
+        for repository in repositories {
+            assert_eq!(repository["__typename"], "Repository");
+            assert!(repository["slug"].as_str().unwrap().contains('-'));
+            assert!(
+                repository["ownerEmail"]
+                    .as_str()
+                    .unwrap()
+                    .ends_with("@example.com")
+            );
+            assert!(repository["createdAt"].as_i64().unwrap() >= EPOCH);
+            assert!(["PUBLIC", "PRIVATE"].contains(&repository["visibility"].as_str().unwrap()));
+        }
+    }
+
+    #[test]
+    fn arguments_and_variables_change_the_data() {
+        let executor = executor(1);
+        let query = "query($path: String!) { repo: repository(path: $path) { id } }";
+        let with = |path: &str| {
+            let variables = Vars::from([("path".to_string(), JsonValue::from(path))]);
+            executor.execute_operation(query, None, variables).unwrap()["data"]["repo"]["id"]
+                .clone()
+        };
+        assert_eq!(with("a/b"), with("a/b"));
+        assert_ne!(with("a/b"), with("a/c"));
+    }
+
+    #[test]
+    fn abstract_types_resolve_to_a_concrete_member() {
+        let data = run(
+            &executor(3),
+            r#"
+            { search { __typename ...Repo ... on Issue { title } }
+              node { __typename ... on Node { id } } }
+            fragment Repo on Repository { slug }
+            "#,
+        );
+        for result in data["search"].as_array().unwrap() {
+            match result["__typename"].as_str().unwrap() {
+                "Repository" => {
+                    assert!(result.get("slug").is_some() && result.get("title").is_none())
+                }
+                "Issue" => assert!(result.get("title").is_some() && result.get("slug").is_none()),
+                other => panic!("unexpected member {}", other),
+            }
+        }
+        assert!(data["node"]["id"].is_string());
+    }
+
+    #[test]
+    fn repeated_fields_merge_their_selections() {
+        let data = run(
+            &executor(5),
+            "{ repositories { id } repositories { slug ... on Repository { id } } }",
+        );
+        for repository in data["repositories"].as_array().unwrap() {
+            assert!(repository["id"].is_string() && repository["slug"].is_string());
+        }
+    }
+
+    #[test]
+    fn mutations_and_unknown_fields() {
+        let executor = executor(0);
+        let data = run(
+            &executor,
+            r#"mutation { createIssue(title: "x") { number } }"#,
+        );
+        assert!(data["createIssue"]["number"].as_i64().unwrap() >= 1);
+        let err = executor
+            .execute_operation("{ repositories { nope } }", None, Vars::new())
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Unknown field `nope` on `Repository`")
+        );
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(EPOCH), "2023-11-14T22:13:20Z");
+        assert_eq!(rfc3339(951_782_400), "2000-02-29T00:00:00Z");
+    }
+}
@@ -1,10 +1,15 @@
 mod core_executor;
+pub mod directives;
 mod extension_executor;
+mod mock_executor;
+pub mod subscriptions;
+pub mod usage;
 
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock, RwLock};
 
 use anyhow::{Context, Result, anyhow};
+use bytes::Bytes;
 use hive_router_plan_executor::execute_query_plan;
 use hive_router_plan_executor::execution::plan::QueryPlanExecutionContext;
 use hive_router_plan_executor::executors::{common::SubgraphExecutor, map::SubgraphExecutorMap};
@@ -23,60 +28,236 @@ use serde_json::Value as JsonValue;
 use sonic_rs::Value as SonicValue;
 use sqlx::SqlitePool;
 
-use crate::extensions::ExtensionManager;
+use crate::auth::viewer::require_admin;
+use crate::config::MockConfig;
 use crate::extensions::wit_bindings::GlobalContext;
-use crate::graphql::schema_composer::SchemaComposer;
-use crate::repository::RepositoryStorage;
+use crate::extensions::{ExtensionManager, ExtensionReload};
+use crate::graphql::registry::{SchemaRegistryPublisher, publish_supergraph};
+use crate::graphql::schema_composer::{SchemaComposer, SchemaVisibility, extension_node_types};
+use crate::repository::{PathResolver, RepositoryStorage};
 
 use self::core_executor::CoreSubgraphExecutor;
+use self::directives::{FieldPolicies, OperationPolicy, RateLimited, RateLimiter};
 use self::extension_executor::ExtensionSubgraphExecutor;
+use self::mock_executor::{MockSchema, MockSubgraphExecutor};
+
+static SHARED: OnceLock<Arc<LiveRouter>> = OnceLock::new();
+
+/// Registers the router for extension host calls, which run queries of their own, and
+/// for `reloadExtension`. Later calls are ignored.
+pub fn install_shared(router: Arc<LiveRouter>) {
+    let _ = SHARED.set(router);
+}
+
+/// The current state of the router registered with [`install_shared`].
+pub fn shared() -> Option<Arc<RouterState>> {
+    SHARED.get().map(|router| router.current())
+}
+
+/// The router registered with [`install_shared`], which extensions are reloaded through.
+pub fn live() -> Option<&'static Arc<LiveRouter>> {
+    SHARED.get()
+}
+
+/// What each [`RouterState`] of a [`LiveRouter`] is built from
+enum RouterSource {
+    Database {
+        pool: SqlitePool,
+        storage: RepositoryStorage,
+        resolver: PathResolver,
+    },
+    Mocked(MockConfig),
+}
+
+/// The [`RouterState`] requests run against. Reloading an extension composes a new
+/// supergraph and executors and swaps them in at once: requests and subscriptions that
+/// already hold a state finish on it, and later ones get the new one.
+pub struct LiveRouter {
+    current: Arc<RwLock<Arc<RouterState>>>,
+    source: RouterSource,
+    extensions: Arc<ExtensionManager>,
+    /// Reloads run one at a time, whether from `reloadExtension` or the watcher
+    reloading: tokio::sync::Mutex<()>,
+    /// Where each supergraph is published, once [`Self::publish_schemas`] is called
+    publisher: OnceLock<Arc<SchemaRegistryPublisher>>,
+    /// Publishes run one at a time, each with the supergraph current when it starts
+    publishing: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl LiveRouter {
+    /// Router states resolve paths through `resolver`, so they share its cache with the
+    /// rest of the server.
+    pub fn new(
+        pool: SqlitePool,
+        storage: RepositoryStorage,
+        resolver: PathResolver,
+        extensions: Arc<ExtensionManager>,
+    ) -> Result<Self> {
+        let source = RouterSource::Database {
+            pool,
+            storage,
+            resolver,
+        };
+        Self::start(source, extensions)
+    }
+
+    pub fn mocked(extensions: Arc<ExtensionManager>, config: &MockConfig) -> Result<Self> {
+        Self::start(RouterSource::Mocked(config.clone()), extensions)
+    }
+
+    fn start(source: RouterSource, extensions: Arc<ExtensionManager>) -> Result<Self> {
+        let state = Self::build(&source, &extensions)?;
+        Ok(Self {
+            current: Arc::new(RwLock::new(Arc::new(state))),
+            source,
+            extensions,
+            reloading: tokio::sync::Mutex::new(()),
+            publisher: OnceLock::new(),
+            publishing: Arc::new(tokio::sync::Mutex::new(())),
+        })
+    }
+
+    fn build(source: &RouterSource, extensions: &Arc<ExtensionManager>) -> Result<RouterState> {
+        match source {
+            RouterSource::Database {
+                pool,
+                storage,
+                resolver,
+            } => RouterState::new(
+                pool.clone(),
+                storage.clone(),
+                resolver.clone(),
+                extensions.clone(),
+            ),
+            RouterSource::Mocked(config) => RouterState::mocked(extensions.clone(), config),
+        }
+    }
+
+    /// The state new requests run against
+    pub fn current(&self) -> Arc<RouterState> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn extensions(&self) -> &Arc<ExtensionManager> {
+        &self.extensions
+    }
+
+    /// Publishes the current supergraph to `publisher`, and from then on every supergraph
+    /// a reload composes. Later calls publish again but keep the first publisher.
+    pub async fn publish_schemas(&self, publisher: SchemaRegistryPublisher) {
+        let _ = self.publisher.set(Arc::new(publisher));
+        if let Some(publication) = self.publication() {
+            publication.await;
+        }
+    }
+
+    /// Publishes the supergraph that is current once earlier publishes are done, when
+    /// there is a publisher. The future holds no reference to the router.
+    fn publication(&self) -> Option<impl Future<Output = ()> + Send + 'static> {
+        let publisher = self.publisher.get()?.clone();
+        let current = self.current.clone();
+        let publishing = self.publishing.clone();
+        Some(async move {
+            let _publishing = publishing.lock().await;
+            let sdl = current
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .supergraph_sdl
+                .clone();
+            publish_supergraph(&publisher, &sdl).await;
+        })
+    }
+
+    /// Loads extension `name` again from its module, or unloads it when the module is
+    /// gone, then re-composes the supergraph and swaps in new executors. When the new
+    /// schema does not compose, the previous extension is put back and nothing changes.
+    /// Rate limit counters carry over, and the new supergraph is published to the schema
+    /// registry, if any.
+    pub async fn reload_extension(&self, name: &str) -> Result<ExtensionReload> {
+        let _reloading = self.reloading.lock().await;
+        let (reload, previous) = self.extensions.reload(name).await?;
+        let mut state = match Self::build(&self.source, &self.extensions) {
+            Ok(state) => state,
+            Err(err) => {
+                self.extensions.restore(name, previous);
+                return Err(err.context(format!("extension `{}` was not reloaded", name)));
+            }
+        };
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        state.rate_limiter = current.rate_limiter.clone();
+        *current = Arc::new(state);
+        drop(current);
+        tracing::info!(
+            extension = %name,
+            loaded = reload.loaded,
+            version = reload.version.as_deref().unwrap_or(""),
+            "extension reloaded"
+        );
+        // The registry hears about the new schema without holding up the reload.
+        if let Some(publication) = self.publication() {
+            tokio::spawn(publication);
+        }
+        Ok(reload)
+    }
+}
 
 /// Coordinates query planning and execution using Hive Router's planner and executor stacks.
 pub struct RouterState {
+    supergraph_sdl: String,
     planner: Planner,
     schema_metadata: SchemaMetadata,
+    /// What administrators query when extensions mark fields `@internal`
+    internal: Option<PlannedSchema>,
     subgraph_executors: Arc<SubgraphExecutorMap>,
+    field_policies: FieldPolicies,
+    rate_limiter: Arc<RateLimiter>,
+    /// Resolves subscription payloads; absent when mocked
+    subscriptions: Option<Arc<CoreSubgraphExecutor>>,
 }
 
 impl RouterState {
     pub fn new(
         pool: SqlitePool,
         storage: RepositoryStorage,
+        resolver: PathResolver,
         extension_manager: Arc<ExtensionManager>,
     ) -> Result<Self> {
-        // Compose the supergraph SDL from core + extensions
-        let mut composer = SchemaComposer::new();
-        for (name, extension) in extension_manager.get_extensions() {
-            let schema_sdl = extension.runtime.schema();
-            composer
-                .add_subgraph(name.clone(), schema_sdl.to_string())
-                .with_context(|| format!("failed to register schema for extension `{}`", name))?;
-        }
-        let supergraph_sdl = composer.compose()?;
-
-        // Parse SDL and initialise planner
-        let parsed_supergraph: SchemaDocument = graphql_parser::parse_schema(&supergraph_sdl)
-            .map_err(|e| anyhow!("Failed to parse composed supergraph: {e}"))?
-            .into_static();
-        let planner = Planner::new_from_supergraph(&parsed_supergraph)
-            .map_err(|e| anyhow!("Failed to create query planner: {e}"))?;
+        let supergraph = compose_supergraph(&extension_manager)?;
 
-        // Build schema metadata used by executor for projection / validation
-        let schema_metadata = planner.consumer_schema.schema_metadata();
+        let mut extension_nodes = HashSet::new();
+        for (name, extension) in extension_manager.graphql_extensions() {
+            extension_nodes.extend(
+                extension_node_types(extension.runtime.schema())
+                    .with_context(|| format!("invalid schema for extension `{}`", name))?,
+            );
+        }
 
+        // Both executor names, and subscriptions, share the server's resolver and so its
+        // cache.
         let mut executor_map = SubgraphExecutorMap::new();
         executor_map.insert_boxed_arc(
             "CORE".to_string(),
-            CoreSubgraphExecutor::new(pool.clone(), storage.clone()).to_boxed_arc(),
+            CoreSubgraphExecutor::new(pool.clone(), storage.clone(), resolver.clone())
+                .with_extension_nodes(extension_nodes.clone())
+                .to_boxed_arc(),
+        );
+        let subscriptions = Arc::new(
+            CoreSubgraphExecutor::new(pool.clone(), storage.clone(), resolver.clone())
+                .with_extension_nodes(extension_nodes.clone()),
         );
         executor_map.insert_boxed_arc(
             "core".to_string(),
-            CoreSubgraphExecutor::new(pool.clone(), storage.clone()).to_boxed_arc(),
+            CoreSubgraphExecutor::new(pool.clone(), storage.clone(), resolver)
+                .with_extension_nodes(extension_nodes)
+                .to_boxed_arc(),
         );
 
         let global_context = GlobalContext::default();
 
-        for (name, extension) in extension_manager.get_extensions() {
+        for (name, extension) in extension_manager.graphql_extensions() {
             let executor = ExtensionSubgraphExecutor::new(
                 name.clone(),
                 extension.runtime.clone(),
@@ -99,36 +280,125 @@ impl RouterState {
             executor_map.insert_boxed_arc(upper_name, executor_upper.to_boxed_arc());
         }
 
+        Self::plan(supergraph, executor_map, Some(subscriptions))
+    }
+
+    /// Serves generated data for the same composed schema; nothing is read or written.
+    pub fn mocked(extension_manager: Arc<ExtensionManager>, config: &MockConfig) -> Result<Self> {
+        let supergraph = compose_supergraph(&extension_manager)?;
+        let schema = Arc::new(MockSchema::parse(supergraph.full_sdl())?);
+
+        let mut names = vec!["CORE".to_string(), "core".to_string()];
+        for (name, _) in extension_manager.graphql_extensions() {
+            names.push(name.clone());
+            names.push(name.to_uppercase());
+        }
+        let mut executor_map = SubgraphExecutorMap::new();
+        for name in names {
+            executor_map.insert_boxed_arc(
+                name,
+                MockSubgraphExecutor::new(schema.clone(), config).to_boxed_arc(),
+            );
+        }
+
+        Self::plan(supergraph, executor_map, None)
+    }
+
+    fn plan(
+        supergraph: ComposedSupergraph,
+        executor_map: SubgraphExecutorMap,
+        subscriptions: Option<Arc<CoreSubgraphExecutor>>,
+    ) -> Result<Self> {
+        let PlannedSchema {
+            planner,
+            schema_metadata,
+        } = PlannedSchema::new(&supergraph.public)?;
+        let internal = supergraph
+            .internal
+            .as_deref()
+            .map(PlannedSchema::new)
+            .transpose()
+            .context("invalid internal supergraph")?;
+        // Policies cover internal fields too; the planner decides who may select them.
+        let field_policies = FieldPolicies::parse(supergraph.full_sdl())
+            .context("invalid @rateLimit or @cacheControl directive")?;
+        usage::recorder().register_schema(field_policies.schema_fields());
+
         Ok(Self {
+            supergraph_sdl: supergraph.public,
             planner,
             schema_metadata,
+            internal,
             subgraph_executors: Arc::new(executor_map),
+            field_policies,
+            rate_limiter: Arc::default(),
+            subscriptions,
         })
     }
 
+    /// The public supergraph SDL, without `@internal` fields.
+    pub fn supergraph_sdl(&self) -> &str {
+        &self.supergraph_sdl
+    }
+
+    /// The schema the current viewer executes against: administrators also see fields
+    /// extensions marked `@internal`.
+    fn visible_schema(&self) -> (&Planner, &SchemaMetadata) {
+        match &self.internal {
+            Some(internal) if require_admin().is_ok() => {
+                (&internal.planner, &internal.schema_metadata)
+            }
+            _ => (&self.planner, &self.schema_metadata),
+        }
+    }
+
+    /// Rate limits and cache hints that apply to `request`.
+    pub fn operation_policy(&self, request: &GraphQLExecutionRequest) -> OperationPolicy {
+        self.field_policies
+            .operation(&request.query, request.operation_name.as_deref())
+    }
+
+    /// The subgraph that resolves `coordinate` (`Type.field`): `CORE` or an extension.
+    pub fn field_graph(&self, coordinate: &str) -> Option<&str> {
+        self.field_policies.field_graph(coordinate)
+    }
+
+    /// Counts `policy`'s rate-limited fields against `caller`, or refuses the request.
+    pub fn charge(&self, caller: &str, policy: &OperationPolicy) -> Result<(), RateLimited> {
+        self.rate_limiter
+            .charge(caller, policy, crate::user::db::unix_now() as u64)
+    }
+
     /// Execute a GraphQL request and return the GraphQL response JSON.
     pub async fn execute(&self, request: GraphQLExecutionRequest) -> Result<JsonValue> {
+        self.execute_raw(request).await?.to_json()
+    }
+
+    /// Execute a GraphQL request and return the response as the executor serialized it,
+    /// for callers that pass it on without looking inside.
+    pub async fn execute_raw(&self, request: GraphQLExecutionRequest) -> Result<GraphQLResponse> {
         let operation_name = request.operation_name.clone();
+        let (planner, schema_metadata) = self.visible_schema();
 
         let parsed_operation = safe_parse_operation(&request.query)
             .map_err(|e| anyhow!("Failed to parse query: {e}"))?;
 
         let normalized = normalize_operation(
-            &self.planner.supergraph,
+            &planner.supergraph,
             &parsed_operation,
             operation_name.as_deref(),
         )
         .map_err(|e| anyhow!("Failed to normalize query: {e}"))?;
 
         let (root_type_name, projection_plan) =
-            FieldProjectionPlan::from_operation(&normalized.operation, &self.schema_metadata);
+            FieldProjectionPlan::from_operation(&normalized.operation, schema_metadata);
 
         let partitioned = partition_operation(normalized.operation.clone());
 
         let variable_values = collect_variables(
             &partitioned.downstream_operation,
             request.variables.clone(),
-            &self.schema_metadata,
+            schema_metadata,
         )
         .map_err(|err| anyhow!("Failed to collect variables: {err}"))?;
 
@@ -144,7 +414,7 @@ impl RouterState {
             }
         } else {
             let cancellation_token = CancellationToken::new();
-            self.planner
+            planner
                 .plan_from_normalized_operation(
                     &partitioned.downstream_operation,
                     Default::default(),
@@ -155,8 +425,8 @@ impl RouterState {
 
         let introspection_context = IntrospectionContext {
             query: partitioned.introspection_operation.as_ref(),
-            schema: &self.planner.consumer_schema.document,
-            metadata: &self.schema_metadata,
+            schema: &planner.consumer_schema.document,
+            metadata: schema_metadata,
         };
 
         let execution_context = QueryPlanExecutionContext {
@@ -170,19 +440,99 @@ impl RouterState {
         };
 
         match execute_query_plan(execution_context).await {
-            Ok(bytes) => {
-                let json: JsonValue = serde_json::from_slice(&bytes)
-                    .map_err(|e| anyhow!("Executor produced invalid JSON: {e}"))?;
-                Ok(json)
-            }
+            Ok(bytes) => Ok(GraphQLResponse {
+                bytes: Bytes::from(bytes),
+            }),
             Err(err) => {
                 let error_body = graphql_error_body(JsonValue::String(err.to_string()));
-                Ok(error_body)
+                Ok(GraphQLResponse {
+                    bytes: Bytes::from(serde_json::to_vec(&error_body)?),
+                })
             }
         }
     }
 }
 
+/// A serialized GraphQL response, parsed only when someone needs its values.
+pub struct GraphQLResponse {
+    bytes: Bytes,
+}
+
+impl GraphQLResponse {
+    /// Whether the response has a top-level `errors` entry. Only that key is looked up;
+    /// `data` is skipped over without being parsed.
+    pub fn has_errors(&self) -> bool {
+        sonic_rs::get_from_slice(&self.bytes, &["errors"]).is_ok()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.bytes
+    }
+
+    pub fn to_json(&self) -> Result<JsonValue> {
+        serde_json::from_slice(&self.bytes)
+            .map_err(|e| anyhow!("Executor produced invalid JSON: {e}"))
+    }
+}
+
+/// A query planner with the metadata the executor projects results with.
+struct PlannedSchema {
+    planner: Planner,
+    schema_metadata: SchemaMetadata,
+}
+
+impl PlannedSchema {
+    fn new(supergraph_sdl: &str) -> Result<Self> {
+        // Parse SDL and initialise planner
+        let parsed_supergraph: SchemaDocument = graphql_parser::parse_schema(supergraph_sdl)
+            .map_err(|e| anyhow!("Failed to parse composed supergraph: {e}"))?
+            .into_static();
+        let planner = Planner::new_from_supergraph(&parsed_supergraph)
+            .map_err(|e| anyhow!("Failed to create query planner: {e}"))?;
+
+        // Build schema metadata used by executor for projection / validation
+        let schema_metadata = planner.consumer_schema.schema_metadata();
+        Ok(Self {
+            planner,
+            schema_metadata,
+        })
+    }
+}
+
+/// The public supergraph and, when it differs, the internal one.
+struct ComposedSupergraph {
+    public: String,
+    internal: Option<String>,
+}
+
+impl ComposedSupergraph {
+    /// The SDL with every field, internal ones included.
+    fn full_sdl(&self) -> &str {
+        self.internal.as_deref().unwrap_or(&self.public)
+    }
+}
+
+/// Composes the supergraph SDL from core + extensions.
+fn compose_supergraph(extension_manager: &ExtensionManager) -> Result<ComposedSupergraph> {
+    let mut composer = SchemaComposer::new();
+    for (name, extension) in extension_manager.graphql_extensions() {
+        let schema_sdl = extension.runtime.schema();
+        composer
+            .add_subgraph(name.clone(), schema_sdl.to_string())
+            .with_context(|| format!("failed to register schema for extension `{}`", name))?;
+    }
+    let public = composer.compose()?;
+    let internal = composer.compose_for(SchemaVisibility::Internal)?;
+    Ok(ComposedSupergraph {
+        internal: (internal != public).then_some(internal),
+        public,
+    })
+}
+
 /// Representation of a GraphQL execution request with variables already converted to `sonic_rs` values.
 pub struct GraphQLExecutionRequest {
     pub query: String,
@@ -234,8 +584,8 @@ pub(super) fn graphql_error_body(message: JsonValue) -> JsonValue {
     )]))
 }
 
+/// Converts variables for the executors, which resolve arguments as `serde_json` values.
+/// The value is walked directly rather than printed and parsed again.
 pub(super) fn sonic_to_serde(value: &SonicValue) -> Result<JsonValue> {
-    let json_str = sonic_rs::to_string(value)?;
-    let json: JsonValue = serde_json::from_str(&json_str)?;
-    Ok(json)
+    Ok(sonic_rs::from_value(value)?)
 }
@@ -0,0 +1,161 @@
+//! GraphQL subscriptions, fed by [`crate::events`].
+//!
+//! Every subscription field is a core field that follows one repository, so operations
+//! are not planned. [`RouterState::subscribe`] checks the operation once, and each later
+//! event that matches is projected onto its selection by the core executor. The
+//! WebSocket transport is in `api::subscriptions`.
+//!
+//! A subscription sees events raised after it started, as long as the viewer it started
+//! with may still read the repository. One that falls more than the broadcast capacity
+//! behind skips what it missed.
+
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use serde_json::Value as JsonValue;
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+
+use super::core_executor::{CoreSubgraphExecutor, Vars};
+use super::{GraphQLExecutionRequest, RouterState, sonic_to_serde};
+use crate::events::{self, Envelope};
+
+/// Root fields of the `Subscription` type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubscriptionField {
+    RepositoryUpdated,
+    IssueCreated,
+}
+
+impl SubscriptionField {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "repositoryUpdated" => Some(Self::RepositoryUpdated),
+            "issueCreated" => Some(Self::IssueCreated),
+            _ => None,
+        }
+    }
+
+    /// The payload type the field returns.
+    pub fn type_name(self) -> &'static str {
+        match self {
+            Self::RepositoryUpdated => "RepositoryUpdate",
+            Self::IssueCreated => "IssueCreated",
+        }
+    }
+
+    /// Whether events of type `kind` are delivered to the field.
+    pub fn follows(self, kind: &str) -> bool {
+        match self {
+            Self::RepositoryUpdated => kind.starts_with("repository."),
+            Self::IssueCreated => kind == "issue.created",
+        }
+    }
+}
+
+/// A running subscription. Its payloads are resolved for the viewer in scope when
+/// [`Subscription::next`] is awaited.
+pub struct Subscription {
+    executor: Arc<CoreSubgraphExecutor>,
+    field: SubscriptionField,
+    repository_id: String,
+    query: String,
+    operation_name: Option<String>,
+    variables: Vars,
+    events: Receiver<Arc<Envelope>>,
+    finished: bool,
+}
+
+impl Subscription {
+    /// The next payload, a GraphQL response, or `None` once the subscription has ended:
+    /// the repository was deleted or the viewer may no longer read it.
+    pub async fn next(&mut self) -> Option<Result<JsonValue>> {
+        while !self.finished {
+            let event = match self.events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "subscription fell behind; events were skipped");
+                    continue;
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            if event.repository_id.as_deref() != Some(self.repository_id.as_str())
+                || !self.field.follows(&event.kind)
+            {
+                continue;
+            }
+            self.finished = event.kind == "repository.deleted";
+            let payload = self
+                .executor
+                .resolve_subscription_event(
+                    &self.query,
+                    self.operation_name.as_deref(),
+                    &self.variables,
+                    &event,
+                )
+                .await;
+            return match payload {
+                Ok(Some(payload)) => Some(Ok(payload)),
+                Ok(None) => {
+                    self.finished = true;
+                    None
+                }
+                Err(err) => Some(Err(err)),
+            };
+        }
+        None
+    }
+}
+
+impl RouterState {
+    /// Starts the subscription `request` describes for the viewer in scope. Fails for
+    /// other operations, or when the viewer may not read the repository.
+    pub async fn subscribe(&self, request: GraphQLExecutionRequest) -> Result<Subscription> {
+        let executor = self
+            .subscriptions
+            .clone()
+            .ok_or_else(|| anyhow!("subscriptions are not supported"))?;
+        let mut variables = Vars::new();
+        for (name, value) in request.variables.iter().flatten() {
+            variables.insert(name.clone(), sonic_to_serde(value)?);
+        }
+        // Listen first so nothing raised while the operation is checked is missed.
+        let events = events::listen();
+        let (field, repository_id) = executor
+            .subscription_target(
+                &request.query,
+                request.operation_name.as_deref(),
+                &variables,
+            )
+            .await?;
+        Ok(Subscription {
+            executor,
+            field,
+            repository_id,
+            query: request.query,
+            operation_name: request.operation_name,
+            variables,
+            events,
+            finished: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_follow_their_event_types() {
+        let updated = SubscriptionField::parse("repositoryUpdated").unwrap();
+        assert!(updated.follows("repository.pushed"));
+        assert!(updated.follows("repository.deleted"));
+        assert!(!updated.follows("issue.created"));
+
+        let created = SubscriptionField::parse("issueCreated").unwrap();
+        assert_eq!(created.type_name(), "IssueCreated");
+        assert!(created.follows("issue.created"));
+        assert!(!created.follows("issue.updated"));
+
+        assert_eq!(SubscriptionField::parse("issueUpdated"), None);
+    }
+}
@@ -0,0 +1,353 @@
+//! Which schema fields clients select, to find fields and types that can be retired.
+//!
+//! After each executed operation the API server passes the fields it selected
+//! ([`OperationPolicy::fields`]) and the calling client to [`UsageRecorder::record`]. The
+//! client is the `apollographql-client-name` header, or `unknown`. Counts stay in memory
+//! until [`UsageRecorder::flush`] adds them to `schema_field_usage`, about once a minute,
+//! so recording never waits on the database. The first time a client selects a field
+//! marked `@deprecated`, a warning naming the client is logged.
+//!
+//! [`UsageRecorder::field_usage`] lists every field of the current schema with its totals,
+//! so fields nobody selects show up with a count of zero. Rows for fields that are no
+//! longer in the schema are kept but not reported.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::{LazyLock, Mutex, RwLock};
+
+use anyhow::Result;
+use sqlx::SqlitePool;
+
+use super::directives::{OperationPolicy, SchemaField};
+
+/// Request header naming the client application.
+pub const CLIENT_HEADER: &str = "apollographql-client-name";
+
+/// Client label for requests without a client header.
+pub const UNKNOWN_CLIENT: &str = "unknown";
+
+/// Client label used once [`MAX_CLIENTS`] distinct clients have been seen.
+pub const OVERFLOW_CLIENT: &str = "__other";
+
+/// How often the API server writes pending counts to the database.
+pub const FLUSH_INTERVAL_SECS: u64 = 60;
+
+/// Clients are named by a request header, so the number of distinct labels is capped.
+const MAX_CLIENTS: usize = 100;
+const MAX_CLIENT_NAME: usize = 64;
+
+static RECORDER: LazyLock<UsageRecorder> = LazyLock::new(UsageRecorder::default);
+
+/// The process-wide recorder shared by the API server and admin queries.
+pub fn recorder() -> &'static UsageRecorder {
+    &RECORDER
+}
+
+/// The client label for a request's [`CLIENT_HEADER`] value.
+pub fn client_label(header: Option<&str>) -> String {
+    let name: String = header
+        .unwrap_or_default()
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_CLIENT_NAME)
+        .collect();
+    if name.is_empty() {
+        UNKNOWN_CLIENT.to_string()
+    } else {
+        name
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Tally {
+    count: u64,
+    first_used_at: i64,
+    last_used_at: i64,
+}
+
+#[derive(Default)]
+struct Clients {
+    known: HashSet<String>,
+    /// Client and deprecated coordinate pairs already logged.
+    warned: HashSet<(String, String)>,
+}
+
+/// Usage totals for one schema field.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldUsage {
+    pub field: SchemaField,
+    pub count: u64,
+    pub last_used_at: Option<i64>,
+    pub clients: Vec<String>,
+}
+
+impl FieldUsage {
+    /// Whether the field went unselected since `since`, or ever when `since` is `None`.
+    pub fn unused_since(&self, since: Option<i64>) -> bool {
+        match (self.last_used_at, since) {
+            (None, _) => true,
+            (Some(last_used_at), Some(since)) => last_used_at < since,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct UsageRecorder {
+    schema: RwLock<Vec<SchemaField>>,
+    pending: Mutex<HashMap<(String, String), Tally>>,
+    clients: Mutex<Clients>,
+}
+
+impl UsageRecorder {
+    /// Sets the fields [`field_usage`](Self::field_usage) reports on.
+    pub fn register_schema(&self, fields: Vec<SchemaField>) {
+        *self.schema.write().unwrap_or_else(|e| e.into_inner()) = fields;
+    }
+
+    /// Counts one execution of an operation with `policy` by `client`.
+    pub fn record(&self, client: &str, policy: &OperationPolicy, now: i64) {
+        if policy.fields.is_empty() {
+            return;
+        }
+        let client = {
+            let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+            let client = if clients.known.contains(client) || clients.known.len() < MAX_CLIENTS {
+                clients.known.insert(client.to_string());
+                client.to_string()
+            } else {
+                OVERFLOW_CLIENT.to_string()
+            };
+            for field in &policy.deprecated {
+                if clients
+                    .warned
+                    .insert((client.clone(), field.coordinate.clone()))
+                {
+                    tracing::warn!(
+                        "client `{}` selected deprecated field `{}`: {}",
+                        client,
+                        field.coordinate,
+                        field.reason
+                    );
+                }
+            }
+            client
+        };
+
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        for coordinate in &policy.fields {
+            let tally = pending
+                .entry((coordinate.clone(), client.clone()))
+                .or_insert(Tally {
+                    count: 0,
+                    first_used_at: now,
+                    last_used_at: now,
+                });
+            tally.count += 1;
+            tally.last_used_at = tally.last_used_at.max(now);
+        }
+    }
+
+    /// Adds pending counts to the database and returns how many rows were written. On
+    /// failure the counts are kept for the next flush.
+    pub async fn flush(&self, pool: &SqlitePool) -> Result<usize> {
+        let drained: Vec<_> = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            pending.drain().collect()
+        };
+        if drained.is_empty() {
+            return Ok(0);
+        }
+        match write_tallies(pool, &drained).await {
+            Ok(()) => Ok(drained.len()),
+            Err(err) => {
+                let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+                for (key, tally) in drained {
+                    merge(
+                        pending.entry(key).or_insert(Tally { count: 0, ..tally }),
+                        tally,
+                    );
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Every field of the registered schema with its stored and pending totals.
+    pub async fn field_usage(&self, pool: &SqlitePool) -> Result<Vec<FieldUsage>> {
+        let rows: Vec<(String, String, i64, i64)> = sqlx::query_as(
+            "SELECT coordinate, client, count, last_used_at FROM schema_field_usage",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut totals: HashMap<String, (u64, i64, BTreeSet<String>)> = HashMap::new();
+        let mut add = |coordinate: &str, client: &str, count: u64, last_used_at: i64| {
+            let entry = totals.entry(coordinate.to_string()).or_default();
+            entry.0 += count;
+            entry.1 = entry.1.max(last_used_at);
+            entry.2.insert(client.to_string());
+        };
+        for (coordinate, client, count, last_used_at) in &rows {
+            add(coordinate, client, (*count).max(0) as u64, *last_used_at);
+        }
+        let pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        for ((coordinate, client), tally) in pending.iter() {
+            add(coordinate, client, tally.count, tally.last_used_at);
+        }
+        drop(pending);
+
+        let schema = self.schema.read().unwrap_or_else(|e| e.into_inner());
+        Ok(schema
+            .iter()
+            .map(|field| match totals.remove(&field.coordinate()) {
+                Some((count, last_used_at, clients)) => FieldUsage {
+                    field: field.clone(),
+                    count,
+                    last_used_at: Some(last_used_at),
+                    clients: clients.into_iter().collect(),
+                },
+                None => FieldUsage {
+                    field: field.clone(),
+                    count: 0,
+                    last_used_at: None,
+                    clients: Vec::new(),
+                },
+            })
+            .collect())
+    }
+}
+
+/// Types none of whose fields were selected since `since` (or ever), sorted.
+pub fn unused_types(usage: &[FieldUsage], since: Option<i64>) -> Vec<String> {
+    let mut used = HashSet::new();
+    let mut types = BTreeSet::new();
+    for field in usage {
+        types.insert(field.field.type_name.as_str());
+        if !field.unused_since(since) {
+            used.insert(field.field.type_name.as_str());
+        }
+    }
+    types
+        .into_iter()
+        .filter(|name| !used.contains(name))
+        .map(str::to_string)
+        .collect()
+}
+
+fn merge(into: &mut Tally, tally: Tally) {
+    into.count += tally.count;
+    into.first_used_at = into.first_used_at.min(tally.first_used_at);
+    into.last_used_at = into.last_used_at.max(tally.last_used_at);
+}
+
+async fn write_tallies(pool: &SqlitePool, tallies: &[((String, String), Tally)]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for ((coordinate, client), tally) in tallies {
+        sqlx::query(
+            "INSERT INTO schema_field_usage (coordinate, client, count, first_used_at, last_used_at) \
+             VALUES (?, ?, ?, ?, ?) \
+             ON CONFLICT(coordinate, client) DO UPDATE SET count = count + excluded.count, \
+             last_used_at = MAX(last_used_at, excluded.last_used_at)",
+        )
+        .bind(coordinate)
+        .bind(client)
+        .bind(tally.count as i64)
+        .bind(tally.first_used_at)
+        .bind(tally.last_used_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::directives::DeprecatedField;
+    use crate::test_helpers::create_test_pool;
+
+    fn field(coordinate: &str, deprecation: Option<&str>) -> SchemaField {
+        let (type_name, field_name) = coordinate.split_once('.').unwrap();
+        SchemaField {
+            type_name: type_name.to_string(),
+            field_name: field_name.to_string(),
+            deprecation: deprecation.map(str::to_string),
+        }
+    }
+
+    fn selecting(fields: &[&str]) -> OperationPolicy {
+        OperationPolicy {
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn usage_is_aggregated_per_field_and_client() {
+        let pool = create_test_pool().await.unwrap();
+        let recorder = UsageRecorder::default();
+        recorder.register_schema(vec![
+            field("Query.legacy", Some("Use `repository`.")),
+            field("Query.repository", None),
+            field("Repository.slug", None),
+            field("Stale.id", None),
+        ]);
+
+        let repository = selecting(&["Query.repository", "Repository.slug"]);
+        recorder.record("web", &repository, 100);
+        recorder.record("cli", &repository, 150);
+        assert_eq!(recorder.flush(&pool).await.unwrap(), 4);
+        recorder.record("web", &repository, 200);
+        let legacy = OperationPolicy {
+            deprecated: vec![DeprecatedField {
+                coordinate: "Query.legacy".to_string(),
+                reason: "Use `repository`.".to_string(),
+            }],
+            ..selecting(&["Query.legacy"])
+        };
+        recorder.record("web", &legacy, 210);
+
+        // Pending counts are reported before they are flushed
+        let usage = recorder.field_usage(&pool).await.unwrap();
+        let slug = &usage[2];
+        assert_eq!(slug.field.coordinate(), "Repository.slug");
+        assert_eq!(slug.count, 3);
+        assert_eq!(slug.last_used_at, Some(200));
+        assert_eq!(slug.clients, ["cli", "web"]);
+        assert!(!usage[0].unused_since(None));
+        assert!(usage[0].unused_since(Some(300)));
+        assert!(usage[3].unused_since(None));
+
+        assert_eq!(unused_types(&usage, None), ["Stale"]);
+        assert_eq!(unused_types(&usage, Some(205)), ["Repository", "Stale"]);
+
+        recorder.flush(&pool).await.unwrap();
+        let stored: (i64, i64, i64) = sqlx::query_as(
+            "SELECT count, first_used_at, last_used_at FROM schema_field_usage \
+             WHERE coordinate = 'Repository.slug' AND client = 'web'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(stored, (2, 100, 200));
+    }
+
+    #[test]
+    fn client_labels_are_bounded() {
+        assert_eq!(client_label(None), UNKNOWN_CLIENT);
+        assert_eq!(client_label(Some("  ")), UNKNOWN_CLIENT);
+        assert_eq!(client_label(Some(" forge-cli\n")), "forge-cli");
+        assert_eq!(client_label(Some(&"x".repeat(200))).len(), MAX_CLIENT_NAME);
+
+        let recorder = UsageRecorder::default();
+        let policy = selecting(&["Query.a"]);
+        for client in 0..=MAX_CLIENTS {
+            recorder.record(&client.to_string(), &policy, 1);
+        }
+        let pending = recorder.pending.lock().unwrap();
+        assert_eq!(pending.len(), MAX_CLIENTS + 1);
+        assert!(pending.contains_key(&("Query.a".to_string(), OVERFLOW_CLIENT.to_string())));
+    }
+}
@@ -0,0 +1,86 @@
+//! Throwaway storage for in-memory mode.
+//!
+//! With `db.in_memory` set, repositories, extension databases and the remote cache live in
+//! a [`ScratchDir`] that is unique to the process and removed when the server exits, so
+//! parallel servers (for example in CI) never share files or storage locks.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Preferred parent for scratch directories: a RAM-backed tmpfs on most Linux systems.
+const TMPFS_ROOT: &str = "/dev/shm";
+
+/// A uniquely named directory that is deleted on drop.
+#[derive(Debug)]
+pub struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    /// Creates `forge-<label>-<pid>-<id>` under `/dev/shm` when it is writable, otherwise
+    /// under the system temporary directory.
+    pub fn create(label: &str) -> Result<Self> {
+        let name = format!(
+            "forge-{}-{}-{}",
+            label,
+            std::process::id(),
+            cuid2::create_id()
+        );
+        let tmpfs = Path::new(TMPFS_ROOT).join(&name);
+        let path = match std::fs::create_dir(&tmpfs) {
+            Ok(()) => tmpfs,
+            Err(_) => {
+                let path = std::env::temp_dir().join(&name);
+                std::fs::create_dir_all(&path).with_context(|| {
+                    format!("failed to create scratch directory: {}", path.display())
+                })?;
+                path
+            }
+        };
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Creates (if needed) and returns a subdirectory.
+    pub fn subdir(&self, name: &str) -> Result<PathBuf> {
+        let path = self.path.join(name);
+        std::fs::create_dir_all(&path)
+            .with_context(|| format!("failed to create scratch directory: {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.path) {
+            tracing::warn!(
+                "failed to remove scratch directory {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scratch_dirs_are_unique_and_removed_on_drop() {
+        let first = ScratchDir::create("test").unwrap();
+        let second = ScratchDir::create("test").unwrap();
+        assert_ne!(first.path(), second.path());
+
+        let repos = first.subdir("repos").unwrap();
+        std::fs::write(repos.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        let root = first.path().to_path_buf();
+        drop(first);
+        assert!(!root.exists());
+        assert!(second.path().is_dir());
+    }
+}
@@ -0,0 +1,584 @@
+//! Full-text search over the files of repositories.
+//!
+//! The files on each repository's default branch are indexed in an FTS5 table with the
+//! trigram tokenizer, so a query matches any substring of at least three characters, as
+//! code search needs, rather than whole words only. [`JOB_KIND`] jobs bring an index up to
+//! date after a push or a refresh of a linked remote's cache, and read again only the
+//! files whose blob changed. Full rebuilds and sampled checks against Git are run by
+//! [`crate::search::maintenance`].
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::task;
+
+use crate::jobs::{JobHandler, JobRecord, enqueue_in};
+use crate::repository::queries::{get_repository_by_id, reconstruct_repository_path};
+use crate::repository::storage::RepositoryStorage;
+use crate::search::maintenance::Consistency;
+use crate::user::db::unix_now;
+
+pub const JOB_KIND: &str = "code-index";
+const MAX_ATTEMPTS: i64 = 3;
+/// Larger files are usually generated, minified or data, and are not indexed.
+const MAX_FILE_SIZE: usize = 512 * 1024;
+/// Files indexed per repository; the rest of a very large tree is left out.
+const MAX_FILES: usize = 20_000;
+/// The trigram tokenizer cannot match anything shorter.
+pub const MIN_QUERY_CHARS: usize = 3;
+pub const DEFAULT_RESULTS: usize = 20;
+pub const MAX_RESULTS: usize = 100;
+/// Matching lines returned per file; `match_count` still counts every one.
+const MAX_LINES_PER_FILE: usize = 5;
+/// Longer matching lines are cut to this many characters.
+const MAX_LINE_CHARS: usize = 500;
+
+/// The language of a file, from its name. `None` for files that are not source code or
+/// markup this table knows about.
+pub fn language_for_path(path: &str) -> Option<&'static str> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    match name {
+        "Dockerfile" | "Containerfile" => return Some("dockerfile"),
+        "Makefile" | "GNUmakefile" => return Some("makefile"),
+        _ => {}
+    }
+    let extension = Path::new(name).extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "js" | "jsx" | "mjs" | "cjs" => "javascript",
+        "ts" | "tsx" | "mts" | "cts" => "typescript",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "scala" => "scala",
+        "swift" => "swift",
+        "rb" => "ruby",
+        "php" => "php",
+        "ex" | "exs" => "elixir",
+        "erl" | "hrl" => "erlang",
+        "hs" => "haskell",
+        "ml" | "mli" => "ocaml",
+        "lua" => "lua",
+        "dart" => "dart",
+        "zig" => "zig",
+        "nix" => "nix",
+        "sh" | "bash" | "zsh" => "shell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" | "sass" => "scss",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "astro" => "astro",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "md" | "markdown" => "markdown",
+        "wit" => "wit",
+        "graphql" | "gql" => "graphql",
+        "proto" => "protobuf",
+        _ => return None,
+    })
+}
+
+/// Where a repository's index stands.
+#[derive(Clone, Debug, PartialEq, Eq, sqlx::FromRow)]
+pub struct CodeIndexStatus {
+    /// `None` until the first index is built.
+    pub commit_oid: Option<String>,
+    pub branch: Option<String>,
+    pub indexed_at: Option<i64>,
+    pub requested_at: i64,
+}
+
+impl CodeIndexStatus {
+    /// Whether a rebuild was asked for after the last one finished.
+    pub fn pending(&self) -> bool {
+        self.indexed_at.is_none_or(|at| at < self.requested_at)
+    }
+}
+
+pub async fn code_index_status(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> Result<Option<CodeIndexStatus>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT commit_oid, branch, indexed_at, requested_at FROM code_search_index \
+         WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .fetch_optional(pool)
+    .await
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CodeIndexPayload {
+    repository_id: String,
+}
+
+/// Queues an update of the repository's index.
+pub async fn enqueue_code_index(pool: &SqlitePool, repository_id: &str) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO code_search_index (repository_id, requested_at) VALUES (?, ?) \
+         ON CONFLICT(repository_id) DO UPDATE SET requested_at = excluded.requested_at",
+    )
+    .bind(repository_id)
+    .bind(unix_now())
+    .execute(&mut *tx)
+    .await?;
+    let payload = CodeIndexPayload {
+        repository_id: repository_id.to_string(),
+    };
+    enqueue_in(&mut *tx, JOB_KIND, &payload, MAX_ATTEMPTS).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Queues a first index of the repositories that never had one, such as those created
+/// before code search existed.
+pub async fn ensure_code_indexes(
+    pool: &SqlitePool,
+    repository_ids: &[String],
+) -> anyhow::Result<()> {
+    let missing: Vec<String> = sqlx::query_scalar(
+        "SELECT ids.value FROM json_each(?) AS ids \
+         WHERE ids.value NOT IN (SELECT repository_id FROM code_search_index)",
+    )
+    .bind(serde_json::to_string(repository_ids)?)
+    .fetch_all(pool)
+    .await?;
+    for repository_id in missing {
+        enqueue_code_index(pool, &repository_id).await?;
+    }
+    Ok(())
+}
+
+/// A line that contains the query. Lines are 1-based.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeLine {
+    pub number: i64,
+    pub text: String,
+}
+
+/// A file that contains the query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeMatch {
+    pub repository_id: String,
+    pub file_path: String,
+    pub language: Option<String>,
+    /// The first [`MAX_LINES_PER_FILE`] matching lines
+    pub lines: Vec<CodeLine>,
+    /// Matching lines in the whole file
+    pub match_count: usize,
+}
+
+#[derive(sqlx::FromRow)]
+struct CodeRow {
+    repository_id: String,
+    file_path: String,
+    language: Option<String>,
+    content: String,
+}
+
+/// Lines of `content` containing `query`, ignoring case.
+fn matching_lines(content: &str, query: &str) -> (Vec<CodeLine>, usize) {
+    let query = query.to_lowercase();
+    let mut lines = Vec::new();
+    let mut count = 0;
+    for (index, line) in content.lines().enumerate() {
+        if !line.to_lowercase().contains(&query) {
+            continue;
+        }
+        count += 1;
+        if lines.len() < MAX_LINES_PER_FILE {
+            lines.push(CodeLine {
+                number: index as i64 + 1,
+                text: line.chars().take(MAX_LINE_CHARS).collect(),
+            });
+        }
+    }
+    (lines, count)
+}
+
+/// Files in `repository_ids` containing `query` as a substring, ignoring case, best
+/// matches first. `language` limits the results to files [`language_for_path`] gives
+/// that language.
+pub async fn search_code(
+    pool: &SqlitePool,
+    repository_ids: &[String],
+    query: &str,
+    language: Option<&str>,
+    limit: usize,
+) -> anyhow::Result<Vec<CodeMatch>> {
+    let query = query.trim();
+    if query.chars().count() < MIN_QUERY_CHARS {
+        bail!("query must be at least {} characters long", MIN_QUERY_CHARS);
+    }
+    // One FTS5 string, so the query is matched as written rather than parsed
+    let phrase = format!("\"{}\"", query.replace('"', "\"\""));
+    let language = language.map(str::to_ascii_lowercase);
+    let rows: Vec<CodeRow> = sqlx::query_as(
+        "SELECT files.repository_id, files.file_path, files.language, \
+         code_search_content.content \
+         FROM code_search_content JOIN code_search_files files \
+         ON files.id = code_search_content.rowid \
+         WHERE code_search_content MATCH ? \
+         AND files.repository_id IN (SELECT value FROM json_each(?)) \
+         AND (? IS NULL OR files.language = ?) \
+         ORDER BY rank, files.file_path \
+         LIMIT ?",
+    )
+    .bind(phrase)
+    .bind(serde_json::to_string(repository_ids)?)
+    .bind(&language)
+    .bind(&language)
+    .bind(limit.min(MAX_RESULTS) as i64)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let (lines, match_count) = matching_lines(&row.content, query);
+            CodeMatch {
+                repository_id: row.repository_id,
+                file_path: row.file_path,
+                language: row.language,
+                lines,
+                match_count,
+            }
+        })
+        .collect())
+}
+
+/// A file whose blob changed since it was last indexed.
+struct ReadFile {
+    path: String,
+    /// `None` for binary or oversized files, which stay out of the index
+    content: Option<String>,
+}
+
+/// What a walk of the default branch found.
+struct Extraction {
+    commit_oid: String,
+    branch: Option<String>,
+    /// Every file and its blob
+    files: HashMap<String, String>,
+    read: Vec<ReadFile>,
+}
+
+/// Walks HEAD's tree and reads the files whose blob is not in `known`. `None` when HEAD
+/// has no commits yet.
+fn extract_repository(
+    dir: &Path,
+    known: &HashMap<String, String>,
+) -> anyhow::Result<Option<Extraction>> {
+    let repo = gix::open(dir)
+        .map_err(|err| anyhow!("failed to open repository at {}: {}", dir.display(), err))?;
+    let Ok(commit) = repo.head_commit() else {
+        return Ok(None);
+    };
+    let branch = repo.head_name()?.map(|name| name.shorten().to_string());
+    let tree = commit.tree()?;
+    let mut recorder = gix::traverse::tree::Recorder::default();
+    tree.traverse()
+        .breadthfirst(&mut recorder)
+        .map_err(|err| anyhow!("failed to walk tree {}: {}", tree.id, err))?;
+
+    let mut files = HashMap::new();
+    let mut read = Vec::new();
+    for entry in recorder.records {
+        if !entry.mode.is_blob() || files.len() == MAX_FILES {
+            continue;
+        }
+        let path = entry.filepath.to_string();
+        let oid = entry.oid.to_string();
+        if known.get(&path) != Some(&oid) {
+            let blob = repo.find_object(entry.oid)?;
+            let content = match std::str::from_utf8(&blob.data) {
+                Ok(text) if text.len() <= MAX_FILE_SIZE && !text.contains('\0') => {
+                    Some(text.to_string())
+                }
+                _ => None,
+            };
+            read.push(ReadFile {
+                path: path.clone(),
+                content,
+            });
+        }
+        files.insert(path, oid);
+    }
+    Ok(Some(Extraction {
+        commit_oid: commit.id.to_string(),
+        branch,
+        files,
+        read,
+    }))
+}
+
+/// Brings the index of one repository up to date with its default branch.
+pub async fn index_repository_code(
+    pool: &SqlitePool,
+    repository_id: &str,
+    dir: PathBuf,
+) -> anyhow::Result<()> {
+    let known: HashMap<String, String> = sqlx::query_as::<_, (String, String)>(
+        "SELECT file_path, blob_oid FROM code_search_files WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+    let started_at = unix_now();
+    let known_files = known.clone();
+    let Some(extraction) =
+        task::spawn_blocking(move || extract_repository(&dir, &known_files)).await??
+    else {
+        return Ok(());
+    };
+
+    let mut tx = pool.begin().await?;
+    let removed = known
+        .keys()
+        .filter(|path| !extraction.files.contains_key(*path));
+    let changed = extraction.read.iter().map(|file| &file.path);
+    for path in removed.chain(changed) {
+        // The delete trigger drops the file's content from the FTS table
+        sqlx::query("DELETE FROM code_search_files WHERE repository_id = ? AND file_path = ?")
+            .bind(repository_id)
+            .bind(path)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for file in &extraction.read {
+        // Binary files are recorded too, so they are not read again until they change
+        let id: i64 = sqlx::query_scalar(
+            "INSERT INTO code_search_files (repository_id, file_path, blob_oid, language) \
+             VALUES (?, ?, ?, ?) RETURNING id",
+        )
+        .bind(repository_id)
+        .bind(&file.path)
+        .bind(&extraction.files[&file.path])
+        .bind(language_for_path(&file.path))
+        .fetch_one(&mut *tx)
+        .await?;
+        if let Some(content) = &file.content {
+            sqlx::query("INSERT INTO code_search_content (rowid, content) VALUES (?, ?)")
+                .bind(id)
+                .bind(content)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+    // A request made while this run was walking the tree stays pending
+    sqlx::query(
+        "INSERT INTO code_search_index \
+         (repository_id, commit_oid, branch, indexed_at, requested_at) VALUES (?, ?, ?, ?, ?) \
+         ON CONFLICT(repository_id) DO UPDATE SET commit_oid = excluded.commit_oid, \
+         branch = excluded.branch, indexed_at = excluded.indexed_at",
+    )
+    .bind(repository_id)
+    .bind(&extraction.commit_oid)
+    .bind(&extraction.branch)
+    .bind(started_at)
+    .bind(started_at)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Makes the next update of a repository's index read every file again, for a rebuild.
+/// The old rows keep answering searches until then.
+pub async fn reset_code_index(pool: &SqlitePool, repository_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE code_search_files SET blob_oid = '' WHERE repository_id = ?")
+        .bind(repository_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Compares a repository's index with the default branch in `dir`.
+pub async fn check_code_index(
+    pool: &SqlitePool,
+    repository_id: &str,
+    dir: PathBuf,
+) -> anyhow::Result<Consistency> {
+    let Some(status) = code_index_status(pool, repository_id).await? else {
+        return Ok(Consistency::Missing);
+    };
+    // An update is already on its way
+    if status.pending() {
+        return Ok(Consistency::Match);
+    }
+    let known: HashMap<String, String> = sqlx::query_as::<_, (String, String)>(
+        "SELECT file_path, blob_oid FROM code_search_files WHERE repository_id = ?",
+    )
+    .bind(repository_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+    let known_files = known.clone();
+    let extraction = task::spawn_blocking(move || extract_repository(&dir, &known_files)).await??;
+    Ok(Consistency::compare(
+        status.commit_oid.as_deref(),
+        &known,
+        extraction.map(|e| (e.commit_oid, e.files, e.read.len())),
+    ))
+}
+
+/// Job handler for [`JOB_KIND`].
+pub struct CodeIndexJob {
+    pool: SqlitePool,
+    storage: Arc<RepositoryStorage>,
+}
+
+impl CodeIndexJob {
+    pub fn new(pool: SqlitePool, storage: Arc<RepositoryStorage>) -> Self {
+        Self { pool, storage }
+    }
+
+    /// The Git directory a repository's index is built from. `None` when the repository
+    /// was deleted or has nothing on disk yet.
+    pub async fn repository_dir(&self, repository_id: &str) -> anyhow::Result<Option<PathBuf>> {
+        let Some(record) = get_repository_by_id(&self.pool, repository_id).await? else {
+            return Ok(None);
+        };
+        let path = reconstruct_repository_path(&self.pool, &record).await?;
+        let segments: Vec<String> = path.split('/').map(str::to_string).collect();
+        let mut dir = self.storage.repository_path(&segments);
+        // Linked remotes without a local clone are read from their cache
+        if !tokio::fs::try_exists(&dir).await? && record.remote_url.is_some() {
+            dir = self.storage.remote_cache_root.join(&record.id);
+        }
+        Ok(tokio::fs::try_exists(&dir).await?.then_some(dir))
+    }
+}
+
+#[async_trait]
+impl JobHandler for CodeIndexJob {
+    async fn run(&self, job: &JobRecord) -> anyhow::Result<()> {
+        let payload: CodeIndexPayload = job.payload()?;
+        // Deleted since the job was queued
+        let Some(dir) = self.repository_dir(&payload.repository_id).await? else {
+            return Ok(());
+        };
+        index_repository_code(&self.pool, &payload.repository_id, dir).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::{create_test_pool, git};
+
+    fn paths(matches: &[CodeMatch]) -> Vec<&str> {
+        matches.iter().map(|m| m.file_path.as_str()).collect()
+    }
+
+    #[test]
+    fn detects_languages_from_file_names() {
+        assert_eq!(language_for_path("src/main.rs"), Some("rust"));
+        assert_eq!(language_for_path("web/App.TSX"), Some("typescript"));
+        assert_eq!(language_for_path("deploy/Dockerfile"), Some("dockerfile"));
+        assert_eq!(language_for_path("LICENSE"), None);
+    }
+
+    #[tokio::test]
+    async fn finds_substrings_and_follows_the_default_branch() {
+        let pool = create_test_pool().await.unwrap();
+        let input = CreateRepositoryInput {
+            slug: "tools".into(),
+            group: None,
+        };
+        let id = create_repository_raw(&pool, input).await.unwrap().id;
+        let ids = [id.clone()];
+        let tmp = tempfile::tempdir().unwrap();
+        let work = tmp.path();
+        git(work, &["init", "-q", "-b", "main"]);
+        std::fs::create_dir(work.join("src")).unwrap();
+        std::fs::write(
+            work.join("src/lib.rs"),
+            "fn parse_config() {}\n\nfn main() {\n    parse_config();\n}\n",
+        )
+        .unwrap();
+        std::fs::write(work.join("notes.md"), "How ParseConfig works\n").unwrap();
+        std::fs::write(work.join("logo.bin"), b"parse_config\0\x01").unwrap();
+        git(work, &["add", "."]);
+        git(work, &["commit", "-q", "-m", "init"]);
+
+        ensure_code_indexes(&pool, &ids).await.unwrap();
+        let status = code_index_status(&pool, &id).await.unwrap().unwrap();
+        assert!(status.pending() && status.commit_oid.is_none());
+        index_repository_code(&pool, &id, work.join(".git"))
+            .await
+            .unwrap();
+        let status = code_index_status(&pool, &id).await.unwrap().unwrap();
+        assert!(!status.pending());
+        assert_eq!(status.branch.as_deref(), Some("main"));
+
+        let found = search_code(&pool, &ids, "PARSE_config", None, 10)
+            .await
+            .unwrap();
+        assert_eq!(paths(&found), ["src/lib.rs"]);
+        assert_eq!(found[0].language.as_deref(), Some("rust"));
+        assert_eq!(found[0].match_count, 2);
+        assert_eq!(
+            found[0].lines,
+            [
+                CodeLine {
+                    number: 1,
+                    text: "fn parse_config() {}".into(),
+                },
+                CodeLine {
+                    number: 4,
+                    text: "    parse_config();".into(),
+                },
+            ]
+        );
+        let found = search_code(&pool, &ids, "config", Some("markdown"), 10)
+            .await
+            .unwrap();
+        assert_eq!(paths(&found), ["notes.md"]);
+        assert!(search_code(&pool, &ids, "fn", None, 10).await.is_err());
+        let elsewhere = ["other".to_string()];
+        assert!(
+            search_code(&pool, &elsewhere, "config", None, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // Changed and deleted files leave the index with the next push.
+        std::fs::write(work.join("src/lib.rs"), "fn load_settings() {}\n").unwrap();
+        std::fs::remove_file(work.join("notes.md")).unwrap();
+        git(work, &["add", "-A"]);
+        git(work, &["commit", "-q", "-m", "rename"]);
+        index_repository_code(&pool, &id, work.join(".git"))
+            .await
+            .unwrap();
+        assert!(
+            search_code(&pool, &ids, "config", None, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let found = search_code(&pool, &ids, "load_settings", None, 10)
+            .await
+            .unwrap();
+        assert_eq!(paths(&found), ["src/lib.rs"]);
+    }
+}
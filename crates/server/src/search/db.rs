@@ -0,0 +1,70 @@
+use sqlx::SqlitePool;
+
+use super::models::SavedSearchRecord;
+use crate::user::db::unix_now;
+
+const COLUMNS: &str = "id, user_did, name, scope, query, created_at, updated_at";
+
+/// Inserts a saved search, or replaces the query of the viewer's search with the same name.
+pub async fn upsert_saved_search(
+    pool: &SqlitePool,
+    user_did: &str,
+    name: &str,
+    scope: &str,
+    query: &str,
+) -> Result<SavedSearchRecord, sqlx::Error> {
+    let now = unix_now();
+    sqlx::query(
+        "INSERT INTO saved_searches (id, user_did, name, scope, query, created_at, updated_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(user_did, scope, name) DO UPDATE SET query = excluded.query, updated_at = excluded.updated_at",
+    )
+    .bind(cuid2::create_id())
+    .bind(user_did)
+    .bind(name)
+    .bind(scope)
+    .bind(query)
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await?;
+
+    sqlx::query_as::<_, SavedSearchRecord>(&format!(
+        "SELECT {COLUMNS} FROM saved_searches WHERE user_did = ? AND scope = ? AND name = ?"
+    ))
+    .bind(user_did)
+    .bind(scope)
+    .bind(name)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn list_saved_searches(
+    pool: &SqlitePool,
+    user_did: &str,
+    scope: Option<&str>,
+) -> Result<Vec<SavedSearchRecord>, sqlx::Error> {
+    sqlx::query_as::<_, SavedSearchRecord>(&format!(
+        "SELECT {COLUMNS} FROM saved_searches WHERE user_did = ? AND (? IS NULL OR scope = ?) \
+         ORDER BY name COLLATE NOCASE"
+    ))
+    .bind(user_did)
+    .bind(scope)
+    .bind(scope)
+    .fetch_all(pool)
+    .await
+}
+
+/// Deletes one of the user's saved searches; returns whether anything was removed.
+pub async fn delete_saved_search(
+    pool: &SqlitePool,
+    user_did: &str,
+    id: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM saved_searches WHERE id = ? AND user_did = ?")
+        .bind(id)
+        .bind(user_did)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
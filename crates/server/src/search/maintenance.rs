@@ -0,0 +1,445 @@
+//! Rebuilds and consistency checks of the persistent search indexes, as RFC-0010
+//! describes.
+//!
+//! Code search ([`crate::search::code`]) and the symbol index
+//! ([`crate::repository::symbols`]) are built per repository from the default branch, so
+//! either can be rebuilt from Git alone. A [`REBUILD_JOB_KIND`] job reads every file of
+//! every repository again, a batch of repositories at a time, and records its cursor in
+//! the job's progress after each batch, so an interrupted rebuild resumes where it
+//! stopped. Each repository is replaced in one transaction, and searches keep working
+//! meanwhile.
+//!
+//! A [`CHECK_JOB_KIND`] job compares a random sample of repositories with their default
+//! branch and updates the ones that drifted. When more than 1% of a sample was wrong it
+//! also queues a full rebuild. Administrators start rebuilds with `rebuildSearchIndex`,
+//! and [`schedule_maintenance`] queues both kinds of job on the intervals in `search`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteExecutor, SqlitePool};
+
+use crate::config::SearchConfig;
+use crate::jobs::{JobHandler, JobProgress, JobRecord, enqueue_at_in, set_progress};
+use crate::repository::storage::RepositoryStorage;
+use crate::repository::symbols::{
+    SymbolIndexJob, check_symbol_index, index_repository_symbols, reset_symbol_index,
+};
+use crate::search::code::{
+    CodeIndexJob, check_code_index, index_repository_code, reset_code_index,
+};
+
+pub const REBUILD_JOB_KIND: &str = "search-rebuild";
+pub const CHECK_JOB_KIND: &str = "search-check";
+const MAX_ATTEMPTS: i64 = 3;
+/// Repositories handled between two progress records
+const BATCH_SIZE: i64 = 20;
+/// Share of a sample, in percent, that may be wrong before a check queues a rebuild
+const MAX_MISMATCH_PERCENT: i64 = 1;
+
+/// An index that can be rebuilt and checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchIndexScope {
+    /// File contents, for code search
+    Code,
+    /// Definitions, for code navigation
+    Symbols,
+}
+
+impl SearchIndexScope {
+    pub const ALL: [SearchIndexScope; 2] = [SearchIndexScope::Code, SearchIndexScope::Symbols];
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "code" => Ok(SearchIndexScope::Code),
+            "symbols" => Ok(SearchIndexScope::Symbols),
+            other => bail!("unknown search index scope: {}", other),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchIndexScope::Code => "code",
+            SearchIndexScope::Symbols => "symbols",
+        }
+    }
+}
+
+/// How a repository's index compares with the repository.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Consistency {
+    Match,
+    /// The repository was never indexed
+    Missing,
+    /// The index describes another commit, or files whose blob changed
+    Stale,
+}
+
+impl Consistency {
+    /// Compares the stored commit and blobs with `current`: the commit on the default
+    /// branch, its indexed files and their blobs, and how many of those differ from
+    /// `known`. `current` is `None` for a repository without commits.
+    pub(crate) fn compare(
+        stored_commit: Option<&str>,
+        known: &HashMap<String, String>,
+        current: Option<(String, HashMap<String, String>, usize)>,
+    ) -> Self {
+        match current {
+            None if known.is_empty() => Consistency::Match,
+            None => Consistency::Stale,
+            Some((commit, files, changed)) => {
+                if stored_commit == Some(commit.as_str())
+                    && changed == 0
+                    && files.len() == known.len()
+                {
+                    Consistency::Match
+                } else {
+                    Consistency::Stale
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MaintenancePayload {
+    scope: SearchIndexScope,
+}
+
+/// The queued or running job of `kind` for `scope`.
+async fn active_job(
+    executor: impl SqliteExecutor<'_>,
+    kind: &str,
+    scope: SearchIndexScope,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT id FROM jobs WHERE kind = ? AND status IN ('queued', 'running') \
+         AND json_extract(payload, '$.scope') = ? ORDER BY created_at LIMIT 1",
+    )
+    .bind(kind)
+    .bind(scope.as_str())
+    .fetch_optional(executor)
+    .await
+}
+
+/// Queues a job of `kind` for `scope` that runs at `run_at`, unless one is already queued
+/// or running. Returns the id of the job that will do the work.
+async fn queue_job(
+    pool: &SqlitePool,
+    kind: &str,
+    scope: SearchIndexScope,
+    run_at: i64,
+) -> Result<String> {
+    let mut tx = pool.begin().await?;
+    if let Some(id) = active_job(&mut *tx, kind, scope).await? {
+        return Ok(id);
+    }
+    let payload = MaintenancePayload { scope };
+    let id = enqueue_at_in(&mut *tx, kind, &payload, MAX_ATTEMPTS, run_at).await?;
+    tx.commit().await?;
+    Ok(id)
+}
+
+/// Queues a rebuild of `scope` now, or returns the rebuild already queued or running.
+pub async fn queue_rebuild(pool: &SqlitePool, scope: SearchIndexScope) -> Result<String> {
+    queue_job(pool, REBUILD_JOB_KIND, scope, crate::user::db::unix_now()).await
+}
+
+/// Queues the rebuilds and checks that are due at `now`. The first of each runs one
+/// interval after it was first scheduled, so a new server does not start with a rebuild.
+/// Returns how many jobs were queued.
+pub async fn schedule_maintenance(
+    pool: &SqlitePool,
+    config: &SearchConfig,
+    now: i64,
+) -> Result<usize> {
+    let mut queued = 0;
+    for scope in SearchIndexScope::ALL {
+        let intervals = [
+            (REBUILD_JOB_KIND, config.rebuild_interval_secs),
+            (CHECK_JOB_KIND, config.check_interval_secs),
+        ];
+        for (kind, interval) in intervals {
+            if interval == 0 || active_job(pool, kind, scope).await?.is_some() {
+                continue;
+            }
+            let interval = interval as i64;
+            let last: Option<i64> = sqlx::query_scalar(
+                "SELECT MAX(run_at) FROM jobs WHERE kind = ? \
+                 AND json_extract(payload, '$.scope') = ?",
+            )
+            .bind(kind)
+            .bind(scope.as_str())
+            .fetch_one(pool)
+            .await?;
+            let run_at = match last {
+                Some(last) if now - last < interval => continue,
+                Some(_) => now,
+                None => now + interval,
+            };
+            queue_job(pool, kind, scope, run_at).await?;
+            queued += 1;
+        }
+    }
+    Ok(queued)
+}
+
+/// Job handler for [`REBUILD_JOB_KIND`] and [`CHECK_JOB_KIND`].
+pub struct SearchMaintenanceJob {
+    pool: SqlitePool,
+    code: CodeIndexJob,
+    symbols: SymbolIndexJob,
+    config: SearchConfig,
+}
+
+impl SearchMaintenanceJob {
+    pub fn new(pool: SqlitePool, storage: Arc<RepositoryStorage>, config: SearchConfig) -> Self {
+        Self {
+            code: CodeIndexJob::new(pool.clone(), storage.clone()),
+            symbols: SymbolIndexJob::new(pool.clone(), storage),
+            pool,
+            config,
+        }
+    }
+
+    async fn repository_dir(
+        &self,
+        scope: SearchIndexScope,
+        repository_id: &str,
+    ) -> Result<Option<PathBuf>> {
+        match scope {
+            SearchIndexScope::Code => self.code.repository_dir(repository_id).await,
+            SearchIndexScope::Symbols => self.symbols.repository_dir(repository_id).await,
+        }
+    }
+
+    /// Reads every file of a repository again.
+    async fn reindex(
+        &self,
+        scope: SearchIndexScope,
+        repository_id: &str,
+        dir: PathBuf,
+    ) -> Result<()> {
+        match scope {
+            SearchIndexScope::Code => {
+                reset_code_index(&self.pool, repository_id).await?;
+                index_repository_code(&self.pool, repository_id, dir).await
+            }
+            SearchIndexScope::Symbols => {
+                reset_symbol_index(&self.pool, repository_id).await?;
+                index_repository_symbols(&self.pool, repository_id, dir).await
+            }
+        }
+    }
+
+    async fn rebuild(&self, job: &JobRecord, scope: SearchIndexScope) -> Result<()> {
+        // A retried or interrupted rebuild continues after its last batch
+        let mut progress = job.progress();
+        progress.total = Some(
+            sqlx::query_scalar("SELECT COUNT(*) FROM repositories")
+                .fetch_one(&self.pool)
+                .await?,
+        );
+        loop {
+            let batch: Vec<String> = sqlx::query_scalar(
+                "SELECT id FROM repositories WHERE ?1 IS NULL OR id > ?1 ORDER BY id LIMIT ?2",
+            )
+            .bind(progress.cursor.as_deref())
+            .bind(BATCH_SIZE)
+            .fetch_all(&self.pool)
+            .await?;
+            let Some(last) = batch.last().cloned() else {
+                break;
+            };
+            for repository_id in &batch {
+                let Some(dir) = self.repository_dir(scope, repository_id).await? else {
+                    continue;
+                };
+                // One broken repository should not hold up the others
+                if let Err(e) = self.reindex(scope, repository_id, dir).await {
+                    tracing::warn!(
+                        repository = %repository_id,
+                        scope = scope.as_str(),
+                        "search reindex failed: {:#}",
+                        e
+                    );
+                }
+            }
+            progress.processed += batch.len() as i64;
+            progress.cursor = Some(last);
+            set_progress(&self.pool, &job.id, &progress).await?;
+        }
+        tracing::info!(
+            scope = scope.as_str(),
+            repositories = progress.processed,
+            "search index rebuilt"
+        );
+        Ok(())
+    }
+
+    async fn check(&self, job: &JobRecord, scope: SearchIndexScope) -> Result<()> {
+        let sample: Vec<String> =
+            sqlx::query_scalar("SELECT id FROM repositories ORDER BY RANDOM() LIMIT ?")
+                .bind(i64::from(self.config.check_sample_size))
+                .fetch_all(&self.pool)
+                .await?;
+        let mut progress = JobProgress {
+            total: Some(sample.len() as i64),
+            mismatches: Some(0),
+            ..JobProgress::default()
+        };
+        let mut mismatches = 0;
+        for repository_id in &sample {
+            if let Some(dir) = self.repository_dir(scope, repository_id).await? {
+                let consistency = match scope {
+                    SearchIndexScope::Code => {
+                        check_code_index(&self.pool, repository_id, dir.clone()).await?
+                    }
+                    SearchIndexScope::Symbols => {
+                        check_symbol_index(&self.pool, repository_id, dir.clone()).await?
+                    }
+                };
+                if consistency != Consistency::Match {
+                    mismatches += 1;
+                    self.reindex(scope, repository_id, dir).await?;
+                }
+            }
+            progress.processed += 1;
+            progress.mismatches = Some(mismatches);
+            if progress.processed % BATCH_SIZE == 0 {
+                set_progress(&self.pool, &job.id, &progress).await?;
+            }
+        }
+        set_progress(&self.pool, &job.id, &progress).await?;
+        if mismatches * 100 > MAX_MISMATCH_PERCENT * sample.len() as i64 {
+            tracing::warn!(
+                scope = scope.as_str(),
+                mismatches,
+                sampled = sample.len(),
+                "search index drifted from Git; queueing a rebuild"
+            );
+            queue_rebuild(&self.pool, scope).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl JobHandler for SearchMaintenanceJob {
+    async fn run(&self, job: &JobRecord) -> Result<()> {
+        let payload: MaintenancePayload = job.payload()?;
+        if job.kind == REBUILD_JOB_KIND {
+            self.rebuild(job, payload.scope).await
+        } else {
+            self.check(job, payload.scope).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::JobQueue;
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::repository::symbols::search_symbols;
+    use crate::search::code::search_code;
+    use crate::test_helpers::{create_test_pool, git};
+    use crate::user::db::unix_now;
+
+    #[tokio::test]
+    async fn indexes_are_rebuilt_and_repaired_from_git() {
+        let pool = create_test_pool().await.unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let storage = RepositoryStorage::new(root.path().join("repos"), root.path().join("cache"));
+        let input = CreateRepositoryInput {
+            slug: "tools".into(),
+            group: None,
+        };
+        let id = create_repository_raw(&pool, input).await.unwrap().id;
+        let ids = [id.clone()];
+        let work = root.path().join("work");
+        std::fs::create_dir(&work).unwrap();
+        git(&work, &["init", "-q", "-b", "main"]);
+        std::fs::write(work.join("lib.rs"), "fn parse_config() {}\n").unwrap();
+        git(&work, &["add", "."]);
+        git(&work, &["commit", "-q", "-m", "init"]);
+        let dir = storage.repository_path(&["tools".to_string()]);
+        git(
+            root.path(),
+            &["clone", "-q", "--bare", "work", dir.to_str().unwrap()],
+        );
+
+        // Nothing runs before the first interval has passed
+        let config = SearchConfig {
+            check_sample_size: 10,
+            ..SearchConfig::default()
+        };
+        let now = unix_now();
+        assert_eq!(schedule_maintenance(&pool, &config, now).await.unwrap(), 4);
+        assert_eq!(schedule_maintenance(&pool, &config, now).await.unwrap(), 0);
+
+        let handler = SearchMaintenanceJob::new(pool.clone(), Arc::new(storage), config);
+        let queue = JobQueue::new(pool.clone());
+        for scope in SearchIndexScope::ALL {
+            let rebuild = queue_rebuild(&pool, scope).await.unwrap();
+            assert_eq!(queue_rebuild(&pool, scope).await.unwrap(), rebuild);
+            let job = queue.get(&rebuild).await.unwrap().unwrap();
+            handler.run(&job).await.unwrap();
+            queue.complete(&rebuild).await.unwrap();
+            let progress = queue.get(&rebuild).await.unwrap().unwrap().progress();
+            assert_eq!((progress.processed, progress.total), (1, Some(1)));
+            assert_eq!(progress.cursor.as_deref(), Some(id.as_str()));
+        }
+        let found = search_code(&pool, &ids, "parse_config", None, 10)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        let symbols = search_symbols(&pool, &id, "parse_config", 10)
+            .await
+            .unwrap();
+        assert_eq!(symbols.len(), 1);
+
+        // Rows lost behind the indexer's back are noticed and put back
+        sqlx::query("DELETE FROM code_search_files WHERE repository_id = ?")
+            .bind(&id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let check = queue_job(&pool, CHECK_JOB_KIND, SearchIndexScope::Code, now)
+            .await
+            .unwrap();
+        handler
+            .run(&queue.get(&check).await.unwrap().unwrap())
+            .await
+            .unwrap();
+        queue.complete(&check).await.unwrap();
+        let progress = queue.get(&check).await.unwrap().unwrap().progress();
+        assert_eq!(progress.mismatches, Some(1));
+        let found = search_code(&pool, &ids, "parse_config", None, 10)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        // One wrong repository out of one is more than 1%
+        assert!(
+            active_job(&pool, REBUILD_JOB_KIND, SearchIndexScope::Code)
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        let check = queue_job(&pool, CHECK_JOB_KIND, SearchIndexScope::Symbols, now)
+            .await
+            .unwrap();
+        handler
+            .run(&queue.get(&check).await.unwrap().unwrap())
+            .await
+            .unwrap();
+        let progress = queue.get(&check).await.unwrap().unwrap().progress();
+        assert_eq!((progress.processed, progress.mismatches), (1, Some(0)));
+    }
+}
@@ -0,0 +1,9 @@
+//! Search features: saved per-user filters, which span extensions, full-text search over
+//! repository files, and the rebuilds and checks that keep the persistent indexes in step
+//! with Git.
+
+pub mod code;
+pub mod db;
+pub mod maintenance;
+pub mod models;
+pub mod saved;
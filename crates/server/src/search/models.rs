@@ -0,0 +1,51 @@
+use serde::Serialize;
+
+/// What a saved search runs against. Each scope has its own filter schema.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum SearchScope {
+    Issues,
+    PullRequests,
+}
+
+impl SearchScope {
+    /// Accepts the GraphQL enum spelling (`PULL_REQUESTS`) as well as the stored one.
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "issues" => Ok(SearchScope::Issues),
+            "pull_requests" => Ok(SearchScope::PullRequests),
+            other => Err(anyhow::anyhow!("unknown search scope: {}", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchScope::Issues => "issues",
+            SearchScope::PullRequests => "pull_requests",
+        }
+    }
+
+    pub fn as_graphql(&self) -> &'static str {
+        match self {
+            SearchScope::Issues => "ISSUES",
+            SearchScope::PullRequests => "PULL_REQUESTS",
+        }
+    }
+
+    pub fn filter_schema(&self) -> &'static search_filter::Schema {
+        match self {
+            SearchScope::Issues => &search_filter::issues::SCHEMA,
+            SearchScope::PullRequests => &search_filter::pull_requests::SCHEMA,
+        }
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow, Serialize)]
+pub struct SavedSearchRecord {
+    pub id: String,
+    pub user_did: String,
+    pub name: String,
+    pub scope: String,
+    pub query: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
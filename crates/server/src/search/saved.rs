@@ -0,0 +1,118 @@
+//! Saved searches: named filter queries owned by a user.
+//!
+//! Queries are parsed with `search_filter` and stored in canonical form, so the string a
+//! client gets back is exactly what the owning extension will execute.
+
+use anyhow::{Context, anyhow};
+use sqlx::SqlitePool;
+
+use super::db::upsert_saved_search;
+use super::models::{SavedSearchRecord, SearchScope};
+
+const MAX_NAME_LEN: usize = 100;
+
+/// Validates `query` for `scope` and returns its canonical spelling.
+pub fn normalize_query(scope: SearchScope, query: &str) -> anyhow::Result<String> {
+    let filter = search_filter::parse(query).context("invalid search query")?;
+    filter
+        .validate(scope.filter_schema())
+        .context("invalid search query")?;
+    Ok(filter.to_string())
+}
+
+pub async fn save_search(
+    pool: &SqlitePool,
+    user_did: &str,
+    name: &str,
+    scope: SearchScope,
+    query: &str,
+) -> anyhow::Result<SavedSearchRecord> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(anyhow!(
+            "saved search name must be between 1 and {} characters",
+            MAX_NAME_LEN
+        ));
+    }
+    let query = normalize_query(scope, query)?;
+    Ok(upsert_saved_search(pool, user_did, name, scope.as_str(), &query).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::db::{delete_saved_search, list_saved_searches};
+    use crate::test_helpers::create_test_pool;
+
+    #[tokio::test]
+    async fn saves_lists_and_deletes_per_user() {
+        let pool = create_test_pool().await.unwrap();
+
+        let saved = save_search(
+            &pool,
+            "did:plc:alice",
+            "Open bugs",
+            SearchScope::Issues,
+            "IS:open  crash",
+        )
+        .await
+        .unwrap();
+        assert_eq!(saved.query, "is:open crash");
+
+        // Same name replaces the query instead of duplicating the entry.
+        let updated = save_search(
+            &pool,
+            "did:plc:alice",
+            "Open bugs",
+            SearchScope::Issues,
+            "is:open",
+        )
+        .await
+        .unwrap();
+        assert_eq!(updated.id, saved.id);
+
+        save_search(
+            &pool,
+            "did:plc:bob",
+            "Mine",
+            SearchScope::Issues,
+            "is:closed",
+        )
+        .await
+        .unwrap();
+        assert!(
+            save_search(
+                &pool,
+                "did:plc:alice",
+                "Bad",
+                SearchScope::Issues,
+                "is:merged"
+            )
+            .await
+            .is_err()
+        );
+
+        let alice = list_saved_searches(&pool, "did:plc:alice", Some("issues"))
+            .await
+            .unwrap();
+        assert_eq!(alice.len(), 1);
+        assert_eq!(alice[0].query, "is:open");
+
+        assert!(
+            !delete_saved_search(&pool, "did:plc:bob", &saved.id)
+                .await
+                .unwrap()
+        );
+        assert!(
+            delete_saved_search(&pool, "did:plc:alice", &saved.id)
+                .await
+                .unwrap()
+        );
+        assert!(
+            list_saved_searches(&pool, "did:plc:alice", None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,691 @@
+//! Declarative test data for local development.
+//!
+//! `seed.file` (`FORGE_SEED_FILE`) names a RON manifest of users, groups, repositories
+//! and issues. At startup, before the API listens, the server creates whatever the
+//! manifest lists and the database lacks. Users are matched by handle, groups and
+//! repositories by path, and issues by title within their repository. Anything that
+//! already exists is left as it is, so restarting with the same manifest changes nothing,
+//! and nothing is ever deleted.
+//!
+//! A repository can ask for a generated history: `commits` commits on `main`, plus one
+//! commit on each of its `branches`. The history is written with `git fast-import` when
+//! the repository has no directory yet. Issues are created through the router's
+//! `createIssue`, so they need the issues extension and are skipped without it.
+//!
+//! Seeding only runs with an in-memory database, or with one on disk when
+//! `seed.allow_persistent` is set. It never runs in mock mode or on a read-only instance.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::Deserialize;
+use serde_json::{Value as JsonValue, json};
+use sqlx::SqlitePool;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::api::server::GraphQLRequest;
+use crate::auth::User;
+use crate::auth::viewer::with_viewer;
+use crate::config::Config;
+use crate::group::mutations::{CreateGroupInput, create_group_raw};
+use crate::group::settings::{Visibility, set_repository_settings};
+use crate::repository::descriptions::set_repository_description;
+use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+use crate::repository::{PathResolver, RepositoryStorage};
+use crate::router::{GraphQLExecutionRequest, RouterState};
+use crate::user::db::{
+    fetch_repository_owner, fetch_user_by_did, fetch_user_by_handle, set_repository_owner,
+    unix_now, upsert_user,
+};
+use crate::user::models::UserRecord;
+
+/// Longest generated history per repository
+const MAX_COMMITS: u32 = 10_000;
+/// Generated commits are this far apart, the last one at the time of seeding
+const COMMIT_SPACING_SECS: i64 = 6 * 60 * 60;
+/// Author of generated commits in repositories without seeded users
+const FALLBACK_AUTHOR: (&str, &str) = ("Forge Seed", "seed@forge.invalid");
+
+const VERBS: &[&str] = &["Add", "Fix", "Refactor", "Document", "Test", "Speed up"];
+const TOPICS: &[&str] = &[
+    "the parser",
+    "config loading",
+    "command-line flags",
+    "error messages",
+    "the cache",
+    "logging",
+    "the HTTP client",
+];
+const FILES: &[&str] = &[
+    "src/lib.rs",
+    "src/parser.rs",
+    "src/config.rs",
+    "docs/guide.md",
+    "CHANGELOG.md",
+];
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SeedManifest {
+    #[serde(default)]
+    pub users: Vec<UserSeed>,
+    #[serde(default)]
+    pub groups: Vec<GroupSeed>,
+    #[serde(default)]
+    pub repositories: Vec<RepositorySeed>,
+    #[serde(default)]
+    pub issues: Vec<IssueSeed>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct UserSeed {
+    pub handle: String,
+    /// Defaults to `did:seed:<handle>`, which profile refreshes never try to resolve.
+    #[serde(default)]
+    pub did: Option<String>,
+    #[serde(default)]
+    pub display_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GroupSeed {
+    /// Full path, e.g. `acme/tools`. Missing parents are created too.
+    pub path: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RepositorySeed {
+    /// Full path, e.g. `acme/tools/cli`. Missing groups are created too.
+    pub path: String,
+    /// Handle of a seeded or existing user
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub private: bool,
+    /// Commits generated on `main`
+    #[serde(default)]
+    pub commits: u32,
+    /// Branches forked from the parent of `main`'s tip, with one commit each
+    #[serde(default)]
+    pub branches: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct IssueSeed {
+    /// Path of the repository
+    pub repository: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// `OPEN` (the default), `IN_PROGRESS` or `CLOSED`
+    #[serde(default)]
+    pub status: Option<String>,
+    /// Handle of the author; defaults to the repository owner
+    #[serde(default)]
+    pub author: Option<String>,
+}
+
+/// What one run created.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    pub users: usize,
+    pub groups: usize,
+    pub repositories: usize,
+    pub commits: usize,
+    pub issues: usize,
+}
+
+pub fn load_manifest(path: &Path) -> Result<SeedManifest> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read seed file {}", path.display()))?;
+    ron::from_str(&text).with_context(|| format!("invalid seed file {}", path.display()))
+}
+
+/// Applies `seed.file` when the server runs for development. Returns `None` when
+/// nothing was applied.
+pub async fn seed_from_config(
+    pool: &SqlitePool,
+    storage: &RepositoryStorage,
+    config: &Config,
+    router: Option<&RouterState>,
+) -> Result<Option<SeedReport>> {
+    let Some(path) = &config.seed.file else {
+        return Ok(None);
+    };
+    if !config.db.in_memory && !config.seed.allow_persistent {
+        tracing::warn!(
+            "ignoring seed file {}: set seed.allow_persistent to seed a database on disk",
+            path.display()
+        );
+        return Ok(None);
+    }
+    if config.server.mock.enabled || crate::coordination::is_read_only() {
+        tracing::warn!(
+            "ignoring seed file {}: the server is mocked or read-only",
+            path.display()
+        );
+        return Ok(None);
+    }
+    let manifest = load_manifest(path)?;
+    apply(pool, storage, &manifest, router).await.map(Some)
+}
+
+/// Creates what `manifest` lists and the database lacks. Issues are skipped when
+/// `router` is `None` or has no `createIssue`.
+pub async fn apply(
+    pool: &SqlitePool,
+    storage: &RepositoryStorage,
+    manifest: &SeedManifest,
+    router: Option<&RouterState>,
+) -> Result<SeedReport> {
+    let resolver = PathResolver::new(pool.clone());
+    let mut report = SeedReport::default();
+
+    for seed in &manifest.users {
+        if fetch_user_by_handle(pool, &seed.handle).await?.is_some() {
+            continue;
+        }
+        let did = seed
+            .did
+            .clone()
+            .unwrap_or_else(|| format!("did:seed:{}", seed.handle.to_ascii_lowercase()));
+        let mut user = User::new(did, seed.handle.clone());
+        user.display_name = seed.display_name.clone();
+        upsert_user(pool, &user).await?;
+        report.users += 1;
+    }
+
+    for seed in &manifest.groups {
+        let (_, created) = ensure_group(pool, &resolver, &seed.path).await?;
+        report.groups += created;
+    }
+
+    let mut authors: Vec<(String, String)> = Vec::new();
+    for seed in &manifest.users {
+        if let Some(user) = fetch_user_by_handle(pool, &seed.handle).await? {
+            let email = format!("{}@users.forge.invalid", user.handle);
+            authors.push((user.display_name.unwrap_or(user.handle), email));
+        }
+    }
+    for seed in &manifest.repositories {
+        let segments = PathResolver::split(&seed.path)?;
+        let Some((slug, groups)) = segments.split_last() else {
+            bail!("seeded repositories need a path");
+        };
+        if resolver.resolve_repository(&seed.path).await?.is_none() {
+            let group = match groups.is_empty() {
+                true => None,
+                false => {
+                    let (id, created) = ensure_group(pool, &resolver, &groups.join("/")).await?;
+                    report.groups += created;
+                    Some(id)
+                }
+            };
+            let record = create_repository_raw(
+                pool,
+                CreateRepositoryInput {
+                    slug: slug.clone(),
+                    group,
+                },
+            )
+            .await
+            .with_context(|| format!("failed to seed repository `{}`", seed.path))?;
+            if let Some(owner) = &seed.owner {
+                set_repository_owner(pool, &record.id, &user_by_handle(pool, owner).await?.did)
+                    .await?;
+            }
+            if let Some(description) = &seed.description {
+                set_repository_description(pool, &record.id, None, description).await?;
+            }
+            if seed.private {
+                set_repository_settings(pool, &record.id, None, Some(Visibility::Private)).await?;
+            }
+            report.repositories += 1;
+        }
+
+        if seed.commits == 0 {
+            continue;
+        }
+        let resolved = resolver
+            .resolve_repository(&seed.path)
+            .await?
+            .ok_or_else(|| anyhow!("repository `{}` disappeared while seeding", seed.path))?;
+        if storage.ensure_local_repository(&resolved.segments).is_ok() {
+            continue;
+        }
+        // The owner writes most of the history; other seeded users chip in.
+        let mut writers = authors.clone();
+        if let Some(owner) = &seed.owner
+            && let Some(position) = writers.iter().position(|(_, email)| {
+                email.eq_ignore_ascii_case(&format!("{}@users.forge.invalid", owner))
+            })
+        {
+            writers[..=position].rotate_right(1);
+        }
+        let stream = history_stream(
+            &resolved.record.slug,
+            seed.description.as_deref(),
+            seed.commits.min(MAX_COMMITS),
+            &seed.branches,
+            &writers,
+            unix_now(),
+        );
+        let dir = storage.repository_path(&resolved.segments);
+        write_history(&dir, &stream.text)
+            .await
+            .with_context(|| format!("failed to generate history for `{}`", seed.path))?;
+        report.commits += stream.commits;
+    }
+
+    if manifest.issues.is_empty() {
+        return Ok(report);
+    }
+    let Some(router) = router.filter(|router| router.field_graph("Mutation.createIssue").is_some())
+    else {
+        tracing::warn!(
+            "skipping {} seeded issues: the issues extension is not loaded",
+            manifest.issues.len()
+        );
+        return Ok(report);
+    };
+    for seed in &manifest.issues {
+        if seed_issue(pool, &resolver, router, seed)
+            .await
+            .with_context(|| format!("failed to seed issue `{}`", seed.title))?
+        {
+            report.issues += 1;
+        }
+    }
+    Ok(report)
+}
+
+/// Creates the groups along `path` that do not exist yet. Returns the last group's ID
+/// and how many were created.
+async fn ensure_group(
+    pool: &SqlitePool,
+    resolver: &PathResolver,
+    path: &str,
+) -> Result<(String, usize)> {
+    let segments = PathResolver::split(path)?;
+    let mut parent: Option<String> = None;
+    let mut created = 0;
+    for (depth, slug) in segments.iter().enumerate() {
+        let id = match resolver
+            .resolve_group(&segments[..=depth].join("/"))
+            .await?
+        {
+            Some(group) => group.id,
+            None => {
+                created += 1;
+                create_group_raw(
+                    pool,
+                    CreateGroupInput {
+                        slug: slug.clone(),
+                        parent: parent.clone(),
+                    },
+                )
+                .await
+                .with_context(|| format!("failed to seed group `{}`", path))?
+                .id
+            }
+        };
+        parent = Some(id);
+    }
+    let id = parent.ok_or_else(|| anyhow!("seeded groups need a path"))?;
+    Ok((id, created))
+}
+
+async fn user_by_handle(pool: &SqlitePool, handle: &str) -> Result<UserRecord> {
+    fetch_user_by_handle(pool, handle)
+        .await?
+        .ok_or_else(|| anyhow!("no user with handle `{}`; add it to `users`", handle))
+}
+
+/// Creates the issue unless its repository already has one with the same title.
+async fn seed_issue(
+    pool: &SqlitePool,
+    resolver: &PathResolver,
+    router: &RouterState,
+    seed: &IssueSeed,
+) -> Result<bool> {
+    let resolved = resolver
+        .resolve_repository(&seed.repository)
+        .await?
+        .ok_or_else(|| anyhow!("repository `{}` does not exist", seed.repository))?;
+    let repository_id = resolved.record.id;
+    let author = match &seed.author {
+        Some(handle) => Some(user_by_handle(pool, handle).await?),
+        None => match fetch_repository_owner(pool, &repository_id).await? {
+            Some(did) => fetch_user_by_did(pool, &did).await?,
+            None => None,
+        },
+    };
+    let viewer = author.map(|user| User {
+        did: user.did,
+        handle: user.handle,
+        display_name: user.display_name,
+        avatar: user.avatar,
+    });
+
+    let existing = execute(
+        router,
+        viewer.clone(),
+        "query($id: ID!) { getIssuesForRepository(repositoryId: $id) { title } }",
+        json!({ "id": repository_id }),
+    )
+    .await?;
+    let taken = existing["getIssuesForRepository"]
+        .as_array()
+        .is_some_and(|issues| {
+            issues
+                .iter()
+                .any(|issue| issue["title"] == seed.title.as_str())
+        });
+    if taken {
+        return Ok(false);
+    }
+
+    let created = execute(
+        router,
+        viewer.clone(),
+        "mutation($id: ID!, $input: CreateIssueInput!) { \
+         createIssue(repositoryId: $id, input: $input) { number } }",
+        json!({
+            "id": repository_id,
+            "input": { "title": seed.title, "description": seed.description },
+        }),
+    )
+    .await?;
+    let status = seed.status.as_deref().map(str::to_ascii_uppercase);
+    if let Some(status) = status.filter(|status| status != "OPEN") {
+        let number = created["createIssue"]["number"]
+            .as_i64()
+            .context("createIssue returned no number")?;
+        execute(
+            router,
+            viewer,
+            "mutation($id: ID!, $number: Int!, $input: UpdateIssueInput!) { \
+             updateIssue(repositoryId: $id, issueNumber: $number, input: $input) { number } }",
+            json!({
+                "id": repository_id,
+                "number": number,
+                "input": { "status": status },
+            }),
+        )
+        .await?;
+    }
+    Ok(true)
+}
+
+async fn execute(
+    router: &RouterState,
+    viewer: Option<User>,
+    query: &str,
+    variables: JsonValue,
+) -> Result<JsonValue> {
+    let request = GraphQLExecutionRequest::from_payload(&GraphQLRequest {
+        query: query.to_string(),
+        operation_name: None,
+        variables,
+    })?;
+    let response = with_viewer(viewer, router.execute(request)).await?;
+    if let Some(error) = response["errors"].get(0) {
+        bail!("{}", error["message"].as_str().unwrap_or("unknown error"));
+    }
+    Ok(response["data"].clone())
+}
+
+/// A `git fast-import` stream and the number of commits in it.
+struct HistoryStream {
+    text: String,
+    commits: usize,
+}
+
+/// Builds a history of `commits` commits on `main`, starting with a README, and one
+/// commit on each of `branches`. `authors` are `(name, email)` pairs taking turns, the
+/// first one writing the latest commit.
+fn history_stream(
+    slug: &str,
+    description: Option<&str>,
+    commits: u32,
+    branches: &[String],
+    authors: &[(String, String)],
+    now: i64,
+) -> HistoryStream {
+    let author = |index: usize| match authors.get(index % authors.len().max(1)) {
+        Some((name, email)) => (name.as_str(), email.as_str()),
+        None => FALLBACK_AUTHOR,
+    };
+    let mut text = String::new();
+    let commit = |text: &mut String,
+                  reference: &str,
+                  mark: Option<u32>,
+                  from: Option<u32>,
+                  (name, email): (&str, &str),
+                  time: i64,
+                  message: &str,
+                  (path, content): (&str, &str)| {
+        text.push_str(&format!("commit {}\n", reference));
+        if let Some(mark) = mark {
+            text.push_str(&format!("mark :{}\n", mark));
+        }
+        let signature = format!("{} <{}> {} +0000", name, email, time);
+        text.push_str(&format!("author {}\ncommitter {}\n", signature, signature));
+        text.push_str(&format!("data {}\n{}\n", message.len(), message));
+        if let Some(from) = from {
+            text.push_str(&format!("from :{}\n", from));
+        }
+        text.push_str(&format!("M 100644 inline {}\n", path));
+        text.push_str(&format!("data {}\n{}\n", content.len(), content));
+    };
+
+    let mut files: HashMap<&str, String> = HashMap::new();
+    for number in 1..=commits {
+        let index = number as usize - 1;
+        // Counted back from the tip, so the first author wrote the latest commit
+        let writer = author((commits - number) as usize);
+        let time = now - i64::from(commits - number) * COMMIT_SPACING_SECS;
+        if number == 1 {
+            let readme = match description {
+                Some(description) => format!("# {}\n\n{}\n", slug, description),
+                None => format!("# {}\n", slug),
+            };
+            commit(
+                &mut text,
+                "refs/heads/main",
+                Some(1),
+                None,
+                writer,
+                time,
+                "Initial commit",
+                ("README.md", &readme),
+            );
+            continue;
+        }
+        let verb = VERBS[index % VERBS.len()];
+        let topic = TOPICS[(index / VERBS.len()) % TOPICS.len()];
+        let path = FILES[index % FILES.len()];
+        let line = match path.ends_with(".rs") {
+            true => format!("pub fn step_{}() -> u32 {{\n    {}\n}}\n", number, number),
+            false => format!("- {} {}\n", verb, topic),
+        };
+        let content = files.entry(path).or_default();
+        content.push_str(&line);
+        commit(
+            &mut text,
+            "refs/heads/main",
+            Some(number),
+            Some(number - 1),
+            writer,
+            time,
+            &format!("{} {}", verb, topic),
+            (path, content.as_str()),
+        );
+    }
+
+    let mut total = commits as usize;
+    if commits > 0 {
+        let base = commits.saturating_sub(1).max(1);
+        for (index, branch) in branches.iter().enumerate() {
+            let note = format!("Work in progress on {}.\n", branch);
+            commit(
+                &mut text,
+                &format!("refs/heads/{}", branch),
+                None,
+                Some(base),
+                author(index + 1),
+                now,
+                &format!("Start {}", branch),
+                (&format!("notes/{}.md", branch.replace('/', "-")), &note),
+            );
+            total += 1;
+        }
+    }
+    text.push_str("done\n");
+    HistoryStream {
+        text,
+        commits: total,
+    }
+}
+
+/// Creates a bare repository at `dir` and imports `stream` into it.
+async fn write_history(dir: &Path, stream: &str) -> Result<()> {
+    if let Some(parent) = dir.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let init = Command::new("git")
+        .args(["init", "--bare", "--quiet", "--initial-branch=main"])
+        .arg(dir)
+        .output()
+        .await
+        .context("failed to spawn git")?;
+    if !init.status.success() {
+        bail!(
+            "git init failed: {}",
+            String::from_utf8_lossy(&init.stderr).trim()
+        );
+    }
+    let mut child = Command::new("git")
+        .arg("--git-dir")
+        .arg(dir)
+        .args(["fast-import", "--quiet", "--done"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to spawn git fast-import")?;
+    let mut stdin = child.stdin.take().context("git fast-import has no stdin")?;
+    stdin.write_all(stream.as_bytes()).await?;
+    drop(stdin);
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        let _ = tokio::fs::remove_dir_all(dir).await;
+        bail!(
+            "git fast-import failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{create_test_pool, git};
+
+    const MANIFEST: &str = r#"(
+        users: [
+            (handle: "alice.test", display_name: Some("Alice")),
+            (handle: "bob.test"),
+        ],
+        groups: [(path: "acme")],
+        repositories: [
+            (
+                path: "acme/tools/widgets",
+                owner: Some("bob.test"),
+                description: Some("Widgets for everyone"),
+                commits: 12,
+                branches: ["feature/login"],
+            ),
+            (path: "scratch", private: true),
+        ],
+        issues: [(repository: "acme/tools/widgets", title: "Crash on start")],
+    )"#;
+
+    #[tokio::test]
+    async fn manifests_are_applied_once() {
+        let pool = create_test_pool().await.unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let storage = RepositoryStorage::new(root.path().join("repos"), root.path().join("cache"));
+        let manifest: SeedManifest = ron::from_str(MANIFEST).unwrap();
+
+        let report = apply(&pool, &storage, &manifest, None).await.unwrap();
+        assert_eq!(
+            report,
+            SeedReport {
+                users: 2,
+                groups: 2,
+                repositories: 2,
+                commits: 13,
+                issues: 0,
+            }
+        );
+
+        let resolver = PathResolver::new(pool.clone());
+        let widgets = resolver
+            .resolve_repository("acme/tools/widgets")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            fetch_repository_owner(&pool, &widgets.record.id)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("did:seed:bob.test")
+        );
+        let dir = widgets.local_dir(&storage).unwrap();
+        assert_eq!(git(&dir, &["rev-list", "--count", "main"]), "12");
+        assert_eq!(git(&dir, &["rev-list", "--count", "feature/login"]), "12");
+        assert_eq!(git(&dir, &["symbolic-ref", "HEAD"]), "refs/heads/main");
+        assert_eq!(
+            git(&dir, &["log", "-1", "--format=%an", "main"]),
+            "bob.test",
+            "the owner writes the latest commit"
+        );
+        assert!(git(&dir, &["show", "main:README.md"]).contains("Widgets for everyone"));
+        // Repositories without commits get no directory, like newly created ones.
+        let scratch = resolver
+            .resolve_repository("scratch")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(scratch.local_dir(&storage).is_err());
+
+        let again = apply(&pool, &storage, &manifest, None).await.unwrap();
+        assert_eq!(again, SeedReport::default());
+        assert_eq!(git(&dir, &["rev-list", "--count", "main"]), "12");
+    }
+
+    #[tokio::test]
+    async fn unknown_owners_are_reported() {
+        let pool = create_test_pool().await.unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let storage = RepositoryStorage::new(root.path().join("repos"), root.path().join("cache"));
+        let manifest: SeedManifest =
+            ron::from_str(r#"(repositories: [(path: "widgets", owner: Some("nobody.test"))])"#)
+                .unwrap();
+        let err = apply(&pool, &storage, &manifest, None).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("nobody.test"), "{:#}", err);
+        assert!(ron::from_str::<SeedManifest>("(projects: [])").is_err());
+    }
+}
@@ -14,6 +14,12 @@ impl Supervisor {
         Self::default()
     }
 
+    /// Cancelling this token stops every task, as Ctrl-C does. Lets an embedding (such as a
+    /// test harness) shut the server down without a signal.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
     pub fn spawn<F, Fut>(&mut self, name: &'static str, factory: F)
     where
         F: FnOnce(CancellationToken) -> Fut + Send + 'static,
@@ -88,3 +94,22 @@ impl Default for Supervisor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cancelling_the_shutdown_token_stops_all_tasks() {
+        let mut supervisor = Supervisor::new();
+        supervisor.spawn("waiter", |shutdown| async move {
+            shutdown.cancelled().await;
+            Ok(())
+        });
+        supervisor.spawn("no-op", |_| async { Ok(()) });
+        let token = supervisor.shutdown_token();
+        let run = tokio::spawn(supervisor.run());
+        token.cancel();
+        run.await.unwrap().unwrap();
+    }
+}
@@ -3,18 +3,13 @@ use sqlx::{
     SqlitePool,
     sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
 };
+use std::path::Path;
 use std::str::FromStr;
 
-/// Creates an in-memory SQLite pool for testing
+/// Creates an in-memory SQLite pool for testing, set up the same way as the server's
+/// in-memory mode
 pub async fn create_test_pool() -> Result<SqlitePool> {
-    let connect_options = SqliteConnectOptions::from_str("sqlite::memory:")?
-        .journal_mode(SqliteJournalMode::Wal)
-        .foreign_keys(true);
-
-    let pool = SqlitePoolOptions::new()
-        .max_connections(1) // In-memory databases should use a single connection
-        .connect_with(connect_options)
-        .await?;
+    let pool = crate::db::memory_pool(&cuid2::create_id(), 1).await?;
 
     // Run migrations
     sqlx::migrate!("./migrations").run(&pool).await?;
@@ -35,3 +30,41 @@ pub async fn create_test_pool_no_migrations() -> Result<SqlitePool> {
 
     Ok(pool)
 }
+
+/// Runs `git` in `dir` as the test author and returns its trimmed stdout. Panics with
+/// git's stderr when it fails.
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    git_with_env(dir, &[], args)
+}
+
+/// Like [`git`], with extra environment variables such as `GIT_AUTHOR_DATE`.
+pub fn git_with_env(dir: &Path, env: &[(&str, &str)], args: &[&str]) -> String {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=Tess", "-c", "user.email=tess@example.org"])
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .envs(env.iter().copied())
+        .output()
+        .expect("failed to spawn git");
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Writes `name` into the work tree `dir` and commits it. Returns the commit id.
+pub fn commit_file(dir: &Path, name: &str, contents: &str, message: &str) -> String {
+    let path = dir.join(name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).unwrap();
+    }
+    std::fs::write(path, contents).unwrap();
+    git(dir, &["add", name]);
+    git(dir, &["commit", "-q", "-m", message]);
+    git(dir, &["rev-parse", "HEAD"])
+}
@@ -0,0 +1,177 @@
+use sqlx::SqlitePool;
+
+use super::models::{ActivityRecord, ContributionDay, UserRecord};
+use crate::auth::User;
+use crate::repository::visibility::HIDDEN;
+
+pub(crate) fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Records (or refreshes) the profile of an authenticated user so it can be found by handle.
+///
+/// Once [`super::profiles`] has resolved a user, their DID document is the source of truth
+/// for the handle, since the one a session carries may be older. Missing display names
+/// and avatars never clear the cached ones.
+pub async fn upsert_user(pool: &SqlitePool, user: &User) -> Result<(), sqlx::Error> {
+    let now = unix_now();
+    sqlx::query(
+        "INSERT INTO users (did, handle, display_name, avatar, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?) \
+         ON CONFLICT(did) DO UPDATE SET \
+         handle = CASE WHEN users.profile_checked_at IS NULL THEN excluded.handle ELSE users.handle END, \
+         display_name = COALESCE(excluded.display_name, users.display_name), \
+         avatar = COALESCE(excluded.avatar, users.avatar), updated_at = excluded.updated_at \
+         WHERE (users.profile_checked_at IS NULL AND users.handle != excluded.handle) \
+            OR users.display_name IS NOT COALESCE(excluded.display_name, users.display_name) \
+            OR users.avatar IS NOT COALESCE(excluded.avatar, users.avatar)",
+    )
+    .bind(&user.did)
+    .bind(&user.handle)
+    .bind(user.display_name.as_ref())
+    .bind(user.avatar.as_ref())
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn fetch_user_by_handle(
+    pool: &SqlitePool,
+    handle: &str,
+) -> Result<Option<UserRecord>, sqlx::Error> {
+    sqlx::query_as::<_, UserRecord>(
+        "SELECT did, handle, display_name, avatar FROM users WHERE handle = ? COLLATE NOCASE",
+    )
+    .bind(handle)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn fetch_user_by_did(
+    pool: &SqlitePool,
+    did: &str,
+) -> Result<Option<UserRecord>, sqlx::Error> {
+    sqlx::query_as::<_, UserRecord>(
+        "SELECT did, handle, display_name, avatar FROM users WHERE did = ?",
+    )
+    .bind(did)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn set_repository_owner(
+    pool: &SqlitePool,
+    repository_id: &str,
+    owner_did: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO repository_owners (repository_id, owner_did) VALUES (?, ?) \
+         ON CONFLICT(repository_id) DO UPDATE SET owner_did = excluded.owner_did",
+    )
+    .bind(repository_id)
+    .bind(owner_did)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn fetch_repository_owner(
+    pool: &SqlitePool,
+    repository_id: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT owner_did FROM repository_owners WHERE repository_id = ?")
+        .bind(repository_id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn record_activity(
+    pool: &SqlitePool,
+    actor_did: &str,
+    repository_id: Option<&str>,
+    kind: &str,
+    summary: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO activity_events (id, actor_did, repository_id, kind, summary, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(cuid2::create_id())
+    .bind(actor_did)
+    .bind(repository_id)
+    .bind(kind)
+    .bind(summary)
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_star(
+    pool: &SqlitePool,
+    user_did: &str,
+    repository_id: &str,
+    starred: bool,
+) -> Result<(), sqlx::Error> {
+    if starred {
+        sqlx::query(
+            "INSERT OR IGNORE INTO repository_stars (user_did, repository_id, starred_at) VALUES (?, ?, ?)",
+        )
+        .bind(user_did)
+        .bind(repository_id)
+        .bind(unix_now())
+        .execute(pool)
+        .await?;
+    } else {
+        sqlx::query("DELETE FROM repository_stars WHERE user_did = ? AND repository_id = ?")
+            .bind(user_did)
+            .bind(repository_id)
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+/// The latest activity of `actor_did`, leaving out events in repositories `viewer_did`
+/// may not read.
+pub async fn recent_activity(
+    pool: &SqlitePool,
+    actor_did: &str,
+    viewer_did: Option<&str>,
+    limit: i64,
+) -> Result<Vec<ActivityRecord>, sqlx::Error> {
+    sqlx::query_as::<_, ActivityRecord>(&format!(
+        "SELECT id, actor_did, repository_id, kind, summary, created_at FROM activity_events \
+         WHERE actor_did = ? \
+         AND (repository_id IS NULL \
+              OR repository_id IN (SELECT id FROM repositories WHERE {HIDDEN})) \
+         ORDER BY created_at DESC, id DESC LIMIT ?"
+    ))
+    .bind(actor_did)
+    .bind(viewer_did)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn count_for_user(pool: &SqlitePool, sql: &str, did: &str) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(sql).bind(did).fetch_one(pool).await
+}
+
+pub async fn contribution_days(
+    pool: &SqlitePool,
+    actor_did: &str,
+    since: i64,
+) -> Result<Vec<ContributionDay>, sqlx::Error> {
+    sqlx::query_as::<_, ContributionDay>(
+        "SELECT date(created_at, 'unixepoch') AS date, COUNT(*) AS count FROM activity_events \
+         WHERE actor_did = ? AND created_at >= ? GROUP BY date ORDER BY date",
+    )
+    .bind(actor_did)
+    .bind(since)
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,637 @@
+//! Self-service exports of a user's own data.
+//!
+//! `requestAccountExport` records a pending export and queues an `account.export` job.
+//! The job gathers the user's profile, repositories they own or starred, metadata about
+//! their signed-in sessions, their activity and the deletions they made, and asks every
+//! extension that declares the `account-export` capability for the records it holds about
+//! them. The result is one JSON document, stored until `account_export.retention_secs`
+//! has passed.
+//!
+//! Archives are downloaded from `GET /account/exports/{id}` with a link signed by an
+//! HMAC key generated once per instance. A link expires after
+//! `account_export.link_ttl_secs`, or with the archive if that comes first. Session ids
+//! are bearer credentials, so sessions appear only by fingerprint.
+
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use sha2::Sha256;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+
+use super::db::{record_activity, unix_now};
+use super::queries::{repositories_owned_by, repositories_starred_by};
+use crate::auth::session::SessionManager;
+use crate::config::AccountExportConfig;
+use crate::extensions::ExtensionManager;
+use crate::extensions::wit_bindings::{ContextScope, RequestContext, UserContext};
+use crate::jobs::{JobHandler, JobRecord, enqueue_in};
+
+pub const JOB_KIND: &str = "account.export";
+/// Extensions listing this capability are asked for their data about the user.
+pub const EXTENSION_CAPABILITY: &str = "account-export";
+pub const ARCHIVE_FORMAT: &str = "forge-account-export";
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const SIGNING_KEY_NAME: &str = "account_export_signing_key";
+
+#[derive(Clone, Debug, PartialEq, sqlx::FromRow)]
+pub struct AccountExport {
+    pub id: String,
+    pub user_did: String,
+    /// `pending`, `ready` or `failed`.
+    pub status: String,
+    pub size_bytes: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub completed_at: Option<i64>,
+    /// When the archive is deleted; set once it is ready.
+    pub expires_at: Option<i64>,
+}
+
+impl AccountExport {
+    pub fn is_downloadable(&self, now: i64) -> bool {
+        self.status == "ready" && self.expires_at.is_some_and(|at| at > now)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportJob {
+    export_id: String,
+}
+
+const EXPORT_COLUMNS: &str =
+    "id, user_did, status, size_bytes, error, created_at, completed_at, expires_at";
+
+/// Starts an export for `did`, or returns the one still being prepared.
+pub async fn request_account_export(
+    pool: &SqlitePool,
+    did: &str,
+    config: &AccountExportConfig,
+) -> Result<AccountExport> {
+    let mut tx = pool.begin().await?;
+    let pending = sqlx::query_as::<_, AccountExport>(&format!(
+        "SELECT {} FROM account_exports WHERE user_did = ? AND status = 'pending' \
+         ORDER BY created_at DESC LIMIT 1",
+        EXPORT_COLUMNS
+    ))
+    .bind(did)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(pending) = pending {
+        return Ok(pending);
+    }
+
+    let export = AccountExport {
+        id: cuid2::create_id(),
+        user_did: did.to_string(),
+        status: "pending".to_string(),
+        size_bytes: None,
+        error: None,
+        created_at: unix_now(),
+        completed_at: None,
+        expires_at: None,
+    };
+    sqlx::query(
+        "INSERT INTO account_exports (id, user_did, status, created_at) VALUES (?, ?, 'pending', ?)",
+    )
+    .bind(&export.id)
+    .bind(did)
+    .bind(export.created_at)
+    .execute(&mut *tx)
+    .await?;
+    let job = ExportJob {
+        export_id: export.id.clone(),
+    };
+    enqueue_in(&mut *tx, JOB_KIND, &job, config.max_attempts).await?;
+    tx.commit().await?;
+
+    record_activity(
+        pool,
+        did,
+        None,
+        "account.export_requested",
+        "requested a data export",
+    )
+    .await?;
+    Ok(export)
+}
+
+/// Exports of `did`, newest first.
+pub async fn list_account_exports(pool: &SqlitePool, did: &str) -> Result<Vec<AccountExport>> {
+    let exports = sqlx::query_as::<_, AccountExport>(&format!(
+        "SELECT {} FROM account_exports WHERE user_did = ? ORDER BY created_at DESC, id DESC",
+        EXPORT_COLUMNS
+    ))
+    .bind(did)
+    .fetch_all(pool)
+    .await?;
+    Ok(exports)
+}
+
+async fn fetch_account_export(pool: &SqlitePool, id: &str) -> Result<Option<AccountExport>> {
+    let export = sqlx::query_as::<_, AccountExport>(&format!(
+        "SELECT {} FROM account_exports WHERE id = ?",
+        EXPORT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(export)
+}
+
+/// Deletes the rows of an export table (`account_exports`, `audit_log_exports` or
+/// `usage_exports`) whose retention ended before `now`. Returns how many were removed.
+pub async fn prune_expired_exports(
+    pool: &SqlitePool,
+    table: &'static str,
+    now: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(&format!(
+        "DELETE FROM {} WHERE expires_at IS NOT NULL AND expires_at <= ?",
+        table
+    ))
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// The instance's link signing key, generated on first use.
+async fn signing_key(pool: &SqlitePool) -> Result<Vec<u8>> {
+    let mut key = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    // Another request may create the key first; whichever insert wins is used by both.
+    sqlx::query(
+        "INSERT OR IGNORE INTO instance_secrets (name, value, created_at) VALUES (?, ?, ?)",
+    )
+    .bind(SIGNING_KEY_NAME)
+    .bind(key.as_slice())
+    .bind(unix_now())
+    .execute(pool)
+    .await?;
+    load_signing_key(pool)
+        .await?
+        .ok_or_else(|| anyhow!("account export signing key missing after insert"))
+}
+
+/// The signing key if one exists; never writes, so it is safe on read-only replicas.
+async fn load_signing_key(pool: &SqlitePool) -> Result<Option<Vec<u8>>> {
+    let key = sqlx::query_scalar("SELECT value FROM instance_secrets WHERE name = ?")
+        .bind(SIGNING_KEY_NAME)
+        .fetch_optional(pool)
+        .await?;
+    Ok(key)
+}
+
+fn signature(key: &[u8], id: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(format!("{}:{}", id, expires).as_bytes());
+    mac
+}
+
+/// A signed download link, or `None` while the archive is not available.
+pub async fn download_url(
+    pool: &SqlitePool,
+    export: &AccountExport,
+    base_url: &str,
+    config: &AccountExportConfig,
+    now: i64,
+) -> Result<Option<String>> {
+    if !export.is_downloadable(now) {
+        return Ok(None);
+    }
+    let link_expires = now.saturating_add(config.link_ttl_secs as i64);
+    let expires = export
+        .expires_at
+        .map_or(link_expires, |at| at.min(link_expires));
+    let key = signing_key(pool).await?;
+    let signed = signature(&key, &export.id, expires).finalize().into_bytes();
+    Ok(Some(format!(
+        "{}/account/exports/{}?expires={}&signature={}",
+        base_url.trim_end_matches('/'),
+        export.id,
+        expires,
+        hex(&signed)
+    )))
+}
+
+/// The archive behind a signed link, or `None` when the link is invalid or expired.
+pub async fn open_signed_download(
+    pool: &SqlitePool,
+    id: &str,
+    expires: i64,
+    signature_hex: &str,
+    now: i64,
+) -> Result<Option<(AccountExport, Vec<u8>)>> {
+    if expires <= now {
+        return Ok(None);
+    }
+    let Some(provided) = unhex(signature_hex) else {
+        return Ok(None);
+    };
+    let Some(key) = load_signing_key(pool).await? else {
+        return Ok(None);
+    };
+    if signature(&key, id, expires)
+        .verify_slice(&provided)
+        .is_err()
+    {
+        return Ok(None);
+    }
+    let Some(export) = fetch_account_export(pool, id).await? else {
+        return Ok(None);
+    };
+    if !export.is_downloadable(now) {
+        return Ok(None);
+    }
+    let archive: Option<Vec<u8>> =
+        sqlx::query_scalar("SELECT archive FROM account_exports WHERE id = ?")
+            .bind(id)
+            .fetch_one(pool)
+            .await?;
+    Ok(archive.map(|archive| (export, archive)))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Everything the core database holds about `did`.
+pub async fn collect_core_data(pool: &SqlitePool, did: &str) -> Result<Map<String, Value>> {
+    let profile = sqlx::query_as::<_, (String, String, Option<String>, Option<String>, i64, i64)>(
+        "SELECT did, handle, display_name, avatar, created_at, updated_at FROM users WHERE did = ?",
+    )
+    .bind(did)
+    .fetch_optional(pool)
+    .await?;
+    let profile = match profile {
+        Some((did, handle, display_name, avatar, created_at, updated_at)) => json!({
+            "did": did,
+            "handle": handle,
+            "displayName": display_name,
+            "avatar": avatar,
+            "createdAt": created_at,
+            "updatedAt": updated_at,
+        }),
+        None => json!({ "did": did }),
+    };
+
+    let starred_at: Vec<(String, i64)> =
+        sqlx::query_as("SELECT repository_id, starred_at FROM repository_stars WHERE user_did = ?")
+            .bind(did)
+            .fetch_all(pool)
+            .await?;
+    let starred: Vec<Value> = repositories_starred_by(pool, did, Some(did))
+        .await?
+        .into_iter()
+        .map(|repository| {
+            let at = starred_at
+                .iter()
+                .find(|(id, _)| *id == repository.id)
+                .map(|(_, at)| *at);
+            json!({ "id": repository.id, "slug": repository.slug, "starredAt": at })
+        })
+        .collect();
+    let owned: Vec<Value> = repositories_owned_by(pool, did, Some(did))
+        .await?
+        .into_iter()
+        .map(|repository| json!({ "id": repository.id, "slug": repository.slug }))
+        .collect();
+
+    let activity: Vec<(String, Option<String>, String, String, i64)> = sqlx::query_as(
+        "SELECT id, repository_id, kind, summary, created_at FROM activity_events \
+         WHERE actor_did = ? ORDER BY created_at, id",
+    )
+    .bind(did)
+    .fetch_all(pool)
+    .await?;
+    let activity: Vec<Value> = activity
+        .into_iter()
+        .map(|(id, repository_id, kind, summary, created_at)| {
+            json!({
+                "id": id,
+                "repositoryId": repository_id,
+                "kind": kind,
+                "summary": summary,
+                "createdAt": created_at,
+            })
+        })
+        .collect();
+
+    let deletions: Vec<(String, String, String, String, String, i64)> = sqlx::query_as(
+        "SELECT id, subject_kind, subject_id, source, summary, deleted_at FROM compliance_archive \
+         WHERE actor_did = ? ORDER BY deleted_at, id",
+    )
+    .bind(did)
+    .fetch_all(pool)
+    .await?;
+    let deletions: Vec<Value> = deletions
+        .into_iter()
+        .map(|(id, subject_kind, subject_id, source, summary, deleted_at)| {
+            json!({
+                "id": id,
+                "subjectKind": subject_kind,
+                "subjectId": subject_id,
+                "source": source,
+                // Already redacted when it was archived.
+                "summary": serde_json::from_str::<Value>(&summary).unwrap_or(Value::String(summary)),
+                "deletedAt": deleted_at,
+            })
+        })
+        .collect();
+
+    let mut data = Map::new();
+    data.insert("profile".to_string(), profile);
+    data.insert(
+        "repositories".to_string(),
+        json!({ "owned": owned, "starred": starred }),
+    );
+    data.insert("activity".to_string(), Value::Array(activity));
+    data.insert("deletions".to_string(), Value::Array(deletions));
+    Ok(data)
+}
+
+fn session_metadata(sessions: &SessionManager, did: &str) -> Result<Value> {
+    let mut sessions = sessions.sessions_for(did)?;
+    sessions.sort_by_key(|session| session.created_at);
+    Ok(Value::Array(
+        sessions
+            .iter()
+            .map(|session| {
+                json!({
+                    "fingerprint": session.fingerprint(),
+                    "signedInAt": session.created_at,
+                    "dpopBound": session.dpop_jwk.is_some(),
+                    "refreshable": session.refresh_token.is_some(),
+                })
+            })
+            .collect(),
+    ))
+}
+
+/// Job handler for [`JOB_KIND`].
+pub struct AccountExportJob {
+    pool: SqlitePool,
+    sessions: Option<SessionManager>,
+    extensions: Arc<ExtensionManager>,
+    config: AccountExportConfig,
+}
+
+impl AccountExportJob {
+    pub fn new(
+        pool: SqlitePool,
+        sessions: Option<SessionManager>,
+        extensions: Arc<ExtensionManager>,
+        config: AccountExportConfig,
+    ) -> Self {
+        Self {
+            pool,
+            sessions,
+            extensions,
+            config,
+        }
+    }
+
+    async fn extension_data(&self, did: &str) -> Result<Map<String, Value>> {
+        let handle: Option<String> = sqlx::query_scalar("SELECT handle FROM users WHERE did = ?")
+            .bind(did)
+            .fetch_optional(&self.pool)
+            .await?;
+        let mut data = Map::new();
+        for (name, extension) in self.extensions.get_extensions().iter() {
+            if !extension
+                .runtime
+                .capabilities()
+                .iter()
+                .any(|capability| capability == EXTENSION_CAPABILITY)
+            {
+                continue;
+            }
+            let context = RequestContext {
+                scope: ContextScope::User,
+                user: Some(UserContext {
+                    id: did.to_string(),
+                    username: handle.clone().unwrap_or_else(|| did.to_string()),
+                    display_name: None,
+                    email: None,
+                }),
+                ..RequestContext::default()
+            };
+            let value = extension
+                .runtime
+                .resolve_field(
+                    "accountExport".to_string(),
+                    "AccountExport".to_string(),
+                    json!({ "did": did }),
+                    context,
+                    None,
+                )
+                .await
+                .with_context(|| format!("extension `{}` failed to export", name))?;
+            data.insert(name.clone(), value);
+        }
+        Ok(data)
+    }
+}
+
+#[async_trait]
+impl JobHandler for AccountExportJob {
+    async fn run(&self, job: &JobRecord) -> Result<()> {
+        let payload: ExportJob = job.payload()?;
+        let export = fetch_account_export(&self.pool, &payload.export_id)
+            .await?
+            .ok_or_else(|| anyhow!("export {} no longer exists", payload.export_id))?;
+        if export.status != "pending" {
+            return Ok(());
+        }
+        let did = export.user_did.as_str();
+
+        let mut archive = Map::new();
+        archive.insert("format".to_string(), json!(ARCHIVE_FORMAT));
+        archive.insert("version".to_string(), json!(ARCHIVE_VERSION));
+        archive.insert("exportId".to_string(), json!(export.id));
+        archive.insert("generatedAt".to_string(), json!(unix_now()));
+        archive.extend(collect_core_data(&self.pool, did).await?);
+        let sessions = match &self.sessions {
+            Some(sessions) => session_metadata(sessions, did)?,
+            None => Value::Array(Vec::new()),
+        };
+        archive.insert("sessions".to_string(), sessions);
+        archive.insert(
+            "extensions".to_string(),
+            Value::Object(self.extension_data(did).await?),
+        );
+        let bytes = serde_json::to_vec_pretty(&Value::Object(archive))?;
+
+        let now = unix_now();
+        sqlx::query(
+            "UPDATE account_exports SET status = 'ready', archive = ?, size_bytes = ?, error = NULL, \
+             completed_at = ?, expires_at = ? WHERE id = ?",
+        )
+        .bind(&bytes)
+        .bind(bytes.len() as i64)
+        .bind(now)
+        .bind(now.saturating_add(self.config.retention_secs as i64))
+        .bind(&export.id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn exhausted(&self, job: &JobRecord, error: &str) {
+        let Ok(payload) = job.payload::<ExportJob>() else {
+            return;
+        };
+        let result = sqlx::query(
+            "UPDATE account_exports SET status = 'failed', error = ?, completed_at = ? WHERE id = ?",
+        )
+        .bind(error)
+        .bind(unix_now())
+        .bind(&payload.export_id)
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = result {
+            tracing::warn!(export = %payload.export_id, "failed to record export failure: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::User;
+    use crate::jobs::JobQueue;
+    use crate::test_helpers::create_test_pool;
+    use crate::user::db::{set_star, upsert_user};
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn exports_are_built_by_the_job_and_served_through_signed_links() {
+        let pool = create_test_pool().await.unwrap();
+        let config = AccountExportConfig::default();
+        let alice = User::new("did:plc:alice".to_string(), "alice.test".to_string());
+        upsert_user(&pool, &alice).await.unwrap();
+        sqlx::query("INSERT INTO repositories (id, slug) VALUES ('repo1', 'widgets')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        set_star(&pool, &alice.did, "repo1", true).await.unwrap();
+        let sessions = SessionManager::new();
+        let session_id = sessions
+            .create_session(alice.clone(), "token".to_string(), None, None, None)
+            .unwrap();
+
+        let export = request_account_export(&pool, &alice.did, &config)
+            .await
+            .unwrap();
+        let again = request_account_export(&pool, &alice.did, &config)
+            .await
+            .unwrap();
+        assert_eq!(again.id, export.id);
+        assert_eq!(
+            download_url(&pool, &export, "https://forge.test", &config, unix_now())
+                .await
+                .unwrap(),
+            None
+        );
+
+        let job = JobQueue::new(pool.clone())
+            .claim_next(unix_now())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.kind, JOB_KIND);
+        let extensions = ExtensionManager::new(PathBuf::from("extensions"), PathBuf::from("db"));
+        AccountExportJob::new(
+            pool.clone(),
+            Some(sessions),
+            Arc::new(extensions),
+            config.clone(),
+        )
+        .run(&job)
+        .await
+        .unwrap();
+
+        let exports = list_account_exports(&pool, &alice.did).await.unwrap();
+        assert_eq!(exports[0].status, "ready");
+        let now = unix_now();
+        let url = download_url(&pool, &exports[0], "https://forge.test/", &config, now)
+            .await
+            .unwrap()
+            .unwrap();
+        let query = url
+            .strip_prefix(&format!(
+                "https://forge.test/account/exports/{}?",
+                export.id
+            ))
+            .unwrap();
+        let (expires, signature) = query
+            .strip_prefix("expires=")
+            .and_then(|rest| rest.split_once("&signature="))
+            .unwrap();
+        let expires: i64 = expires.parse().unwrap();
+        assert_eq!(expires, now + config.link_ttl_secs as i64);
+
+        let (_, archive) = open_signed_download(&pool, &export.id, expires, signature, now)
+            .await
+            .unwrap()
+            .unwrap();
+        let archive: Value = serde_json::from_slice(&archive).unwrap();
+        assert_eq!(archive["format"], ARCHIVE_FORMAT);
+        assert_eq!(archive["profile"]["handle"], "alice.test");
+        assert_eq!(archive["repositories"]["starred"][0]["slug"], "widgets");
+        assert_eq!(archive["activity"][0]["kind"], "account.export_requested");
+        assert_eq!(archive["sessions"].as_array().unwrap().len(), 1);
+        assert!(!archive.to_string().contains(&session_id));
+
+        // Tampered, expired or foreign links open nothing.
+        let flipped = if signature.starts_with('0') { "1" } else { "0" };
+        let forged = format!("{}{}", flipped, &signature[1..]);
+        for (id, expires, signature) in [
+            (export.id.as_str(), expires, forged.as_str()),
+            (export.id.as_str(), expires + 1, signature),
+            ("other", expires, signature),
+        ] {
+            assert!(
+                open_signed_download(&pool, id, expires, signature, now)
+                    .await
+                    .unwrap()
+                    .is_none()
+            );
+        }
+        assert!(
+            open_signed_download(&pool, &export.id, expires, signature, expires)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let retention = config.retention_secs as i64;
+        assert_eq!(
+            prune_expired_exports(&pool, "account_exports", now + retention + 1)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(
+            list_account_exports(&pool, &alice.did)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
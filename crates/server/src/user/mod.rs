@@ -0,0 +1,6 @@
+pub mod db;
+pub mod export;
+pub mod models;
+pub mod profiles;
+pub mod queries;
+pub mod security;
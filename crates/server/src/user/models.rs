@@ -0,0 +1,34 @@
+use serde::Serialize;
+
+#[derive(Clone, Debug, sqlx::FromRow, Serialize)]
+pub struct UserRecord {
+    pub did: String,
+    pub handle: String,
+    pub display_name: Option<String>,
+    pub avatar: Option<String>,
+}
+
+#[derive(Clone, Debug, sqlx::FromRow, Serialize)]
+pub struct ActivityRecord {
+    pub id: String,
+    pub actor_did: String,
+    pub repository_id: Option<String>,
+    pub kind: String,
+    pub summary: String,
+    pub created_at: i64,
+}
+
+#[derive(Clone, Debug, sqlx::FromRow, Serialize)]
+pub struct ContributionDay {
+    pub date: String,
+    pub count: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct UserContributions {
+    pub did: String,
+    pub repositories_owned: i64,
+    pub stars_given: i64,
+    pub activity_count: i64,
+    pub days: Vec<ContributionDay>,
+}
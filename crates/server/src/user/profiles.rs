@@ -0,0 +1,608 @@
+//! Background refresh of the handles, display names and avatars cached in `users`.
+//!
+//! A user's row is written when they sign in, from what their session carried. Handles
+//! and profiles change on the user's PDS afterwards, so the `profile-refresh` scheduler
+//! queues a `users.profile_refresh` job for every user whose profile was last resolved
+//! more than `profile_refresh.interval_secs` ago. The job reads the DID document, checks
+//! that the handle it claims points back at the DID, and reads the
+//! `app.bsky.actor.profile` record from the PDS.
+//!
+//! Each tick queues at most `refreshes_per_minute` jobs, spaced evenly over the minute,
+//! and the handler never starts refreshes closer together than that spacing, so a large
+//! user base does not turn into a burst of requests to the PLC directory or PDSes. A
+//! changed handle raises a `user.handle_changed` event. A user whose refresh keeps
+//! failing is left alone until the next interval.
+
+use anyhow::{Context, Result, bail};
+use async_trait::async_trait;
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use super::db::{fetch_user_by_did, unix_now};
+use crate::config::{FederationConfig, ProfileRefreshConfig};
+use crate::events::{Event, publish};
+use crate::federation::did::{DidDocument, DidResolver};
+use crate::jobs::{JobHandler, JobRecord, PermanentFailure, enqueue_at_in};
+
+pub const JOB_KIND: &str = "users.profile_refresh";
+pub const HANDLE_CHANGED_EVENT: &str = "user.handle_changed";
+
+const PROFILE_COLLECTION: &str = "app.bsky.actor.profile";
+const MAX_HANDLE_LEN: usize = 253;
+
+#[derive(Serialize, Deserialize)]
+struct RefreshJob {
+    did: String,
+}
+
+/// What a refresh found out about a user.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedProfile {
+    /// The handle from the DID document, or `None` when it does not resolve back to the
+    /// DID; the cached handle is kept then.
+    pub handle: Option<String>,
+    pub display_name: Option<String>,
+    pub avatar: Option<String>,
+}
+
+/// The first `at://` handle a DID document claims, lowercased.
+pub fn claimed_handle(document: &DidDocument) -> Option<String> {
+    document
+        .also_known_as
+        .iter()
+        .filter_map(|alias| alias.strip_prefix("at://"))
+        .map(str::to_ascii_lowercase)
+        .find(|handle| is_valid_handle(handle))
+}
+
+/// The PDS a DID document names as `#atproto_pds`.
+pub fn pds_endpoint(document: &DidDocument) -> Option<String> {
+    let full = format!("{}#atproto_pds", document.id);
+    document
+        .service
+        .iter()
+        .find(|service| {
+            (service.id == "#atproto_pds" || service.id == full)
+                && service.has_type("AtprotoPersonalDataServer")
+        })
+        .and_then(|service| service.endpoint())
+        .filter(|endpoint| endpoint.starts_with("https://") || endpoint.starts_with("http://"))
+        .map(|endpoint| endpoint.trim_end_matches('/').to_string())
+}
+
+/// A domain name of at least two labels, as ATProto handles are.
+fn is_valid_handle(handle: &str) -> bool {
+    let labels: Vec<&str> = handle.split('.').collect();
+    handle.len() <= MAX_HANDLE_LEN
+        && labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Display name and avatar URL from a `com.atproto.repo.getRecord` response for the
+/// profile record. Avatars are served by the PDS's `getBlob`.
+pub fn parse_profile_record(
+    pds: &str,
+    did: &str,
+    response: &Value,
+) -> (Option<String>, Option<String>) {
+    let record = &response["value"];
+    let display_name = record["displayName"]
+        .as_str()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string);
+    // Current blobs link their CID as `ref.$link`; older records carry a plain `cid`.
+    let avatar = record["avatar"]["ref"]["$link"]
+        .as_str()
+        .or_else(|| record["avatar"]["cid"].as_str())
+        .filter(|cid| cid.chars().all(|c| c.is_ascii_alphanumeric()))
+        .map(|cid| {
+            format!(
+                "{}/xrpc/com.atproto.sync.getBlob?did={}&cid={}",
+                pds,
+                urlencoding::encode(did),
+                cid
+            )
+        });
+    (display_name, avatar)
+}
+
+/// Queues refreshes for the users whose profiles are the most out of date.
+///
+/// Users with a refresh still queued or running are skipped. At most
+/// `refreshes_per_minute` jobs are queued, due at even intervals over the next minute,
+/// so the scheduler is meant to run once a minute.
+pub async fn enqueue_profile_refreshes(
+    pool: &SqlitePool,
+    config: &ProfileRefreshConfig,
+    now: i64,
+) -> Result<usize> {
+    let per_minute = config.refreshes_per_minute.max(1);
+    let stale_before = now - config.interval_secs as i64;
+    let dids: Vec<String> = sqlx::query_scalar(
+        "SELECT u.did FROM users u LEFT JOIN jobs j ON j.id = u.profile_job_id \
+         WHERE (u.profile_checked_at IS NULL OR u.profile_checked_at <= ?) \
+           AND (j.id IS NULL OR j.status NOT IN ('queued', 'running')) \
+         ORDER BY COALESCE(u.profile_checked_at, 0), u.did LIMIT ?",
+    )
+    .bind(stale_before)
+    .bind(per_minute as i64)
+    .fetch_all(pool)
+    .await?;
+
+    let spacing_ms = 60_000 / per_minute as i64;
+    for (i, did) in dids.iter().enumerate() {
+        let run_at = now + i as i64 * spacing_ms / 1000;
+        let mut tx = pool.begin().await?;
+        let job_id = enqueue_at_in(
+            &mut *tx,
+            JOB_KIND,
+            &RefreshJob { did: did.clone() },
+            config.max_attempts,
+            run_at,
+        )
+        .await?;
+        sqlx::query("UPDATE users SET profile_job_id = ? WHERE did = ?")
+            .bind(&job_id)
+            .bind(did)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+    }
+    Ok(dids.len())
+}
+
+/// Stores a refreshed profile and raises `user.handle_changed` when the handle moved.
+///
+/// A user who still holds the new handle in the cache has evidently given it up, so
+/// their handle is set to their DID until their own refresh finds the new one.
+pub async fn apply_profile(
+    pool: &SqlitePool,
+    did: &str,
+    profile: &ResolvedProfile,
+    now: i64,
+) -> Result<bool> {
+    let Some(current) = fetch_user_by_did(pool, did).await? else {
+        return Ok(false);
+    };
+    let handle = profile.handle.as_deref().unwrap_or(&current.handle);
+    let handle_changed = !handle.eq_ignore_ascii_case(&current.handle);
+    let changed = handle != current.handle
+        || profile.display_name != current.display_name
+        || profile.avatar != current.avatar;
+
+    let mut tx = pool.begin().await?;
+    if handle_changed {
+        sqlx::query(
+            "UPDATE users SET handle = did, profile_checked_at = NULL, updated_at = ? \
+             WHERE handle = ? COLLATE NOCASE AND did != ?",
+        )
+        .bind(now)
+        .bind(handle)
+        .bind(did)
+        .execute(&mut *tx)
+        .await?;
+    }
+    if changed {
+        sqlx::query(
+            "UPDATE users SET handle = ?, display_name = ?, avatar = ?, updated_at = ?, \
+             profile_checked_at = ? WHERE did = ?",
+        )
+        .bind(handle)
+        .bind(profile.display_name.as_deref())
+        .bind(profile.avatar.as_deref())
+        .bind(now)
+        .bind(now)
+        .bind(did)
+        .execute(&mut *tx)
+        .await?;
+    } else {
+        mark_checked(&mut *tx, did, now).await?;
+    }
+    tx.commit().await?;
+    if handle_changed {
+        publish(
+            pool,
+            crate::config::current().event_sink.as_ref(),
+            Event {
+                kind: HANDLE_CHANGED_EVENT,
+                source: "core",
+                actor_did: Some(did),
+                repository_id: None,
+                data: json!({
+                    "oldHandle": current.handle,
+                    "handle": handle,
+                    "displayName": profile.display_name,
+                }),
+            },
+        )
+        .await?;
+    }
+    Ok(changed)
+}
+
+async fn mark_checked(
+    executor: impl sqlx::SqliteExecutor<'_>,
+    did: &str,
+    now: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET profile_checked_at = ? WHERE did = ?")
+        .bind(now)
+        .bind(did)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// Starts refreshes no closer together than `spacing`.
+struct Pacer {
+    spacing: Duration,
+    next: Mutex<Instant>,
+}
+
+impl Pacer {
+    fn new(per_minute: u32) -> Self {
+        Self {
+            spacing: Duration::from_secs(60) / per_minute.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait(&self) {
+        let mut next = self.next.lock().await;
+        tokio::time::sleep_until(*next).await;
+        *next = Instant::now() + self.spacing;
+    }
+}
+
+pub struct ProfileRefreshJob {
+    pool: SqlitePool,
+    resolver: DidResolver,
+    pacer: Pacer,
+}
+
+impl ProfileRefreshJob {
+    pub fn new(
+        pool: SqlitePool,
+        federation: &FederationConfig,
+        config: &ProfileRefreshConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            pool,
+            resolver: DidResolver::from_config(federation)?,
+            pacer: Pacer::new(config.refreshes_per_minute),
+        })
+    }
+
+    async fn resolve(&self, did: &str) -> Result<ResolvedProfile> {
+        let document = self.resolver.fetch(did).await?;
+        let pds = pds_endpoint(&document).context("DID document names no PDS")?;
+        let handle = match claimed_handle(&document) {
+            Some(handle) if self.handle_points_to(&handle, did, &pds).await => Some(handle),
+            Some(handle) => {
+                tracing::warn!(%did, %handle, "handle does not resolve back to the DID");
+                None
+            }
+            None => None,
+        };
+
+        let response = self
+            .resolver
+            .http()
+            .get(format!("{}/xrpc/com.atproto.repo.getRecord", pds))
+            .query(&[
+                ("repo", did),
+                ("collection", PROFILE_COLLECTION),
+                ("rkey", "self"),
+            ])
+            .send()
+            .await
+            .with_context(|| format!("failed to fetch the profile record from {}", pds))?;
+        let (display_name, avatar) = if response.status().is_success() {
+            parse_profile_record(&pds, did, &response.json().await?)
+        } else if response.status() == reqwest::StatusCode::BAD_REQUEST {
+            // `RecordNotFound`: the account never set up a profile
+            (None, None)
+        } else {
+            bail!("profile fetch from {} failed: {}", pds, response.status());
+        };
+        Ok(ResolvedProfile {
+            handle,
+            display_name,
+            avatar,
+        })
+    }
+
+    /// Bidirectional handle check: the handle's `/.well-known/atproto-did`, or the PDS's
+    /// `resolveHandle` for handles published only in DNS.
+    async fn handle_points_to(&self, handle: &str, did: &str, pds: &str) -> bool {
+        let http = self.resolver.http();
+        if let Ok(response) = http
+            .get(format!("https://{}/.well-known/atproto-did", handle))
+            .send()
+            .await
+            && response.status().is_success()
+            && let Ok(body) = response.text().await
+        {
+            return body.trim() == did;
+        }
+        let resolved = async {
+            http.get(format!("{}/xrpc/com.atproto.identity.resolveHandle", pds))
+                .query(&[("handle", handle)])
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Value>()
+                .await
+        };
+        matches!(resolved.await, Ok(body) if body["did"].as_str() == Some(did))
+    }
+}
+
+#[async_trait]
+impl JobHandler for ProfileRefreshJob {
+    async fn run(&self, job: &JobRecord) -> Result<()> {
+        let payload: RefreshJob = job.payload()?;
+        if !payload.did.starts_with("did:plc:") && !payload.did.starts_with("did:web:") {
+            return Err(PermanentFailure(format!("cannot resolve {}", payload.did)).into());
+        }
+        self.pacer.wait().await;
+        let profile = match self.resolve(&payload.did).await {
+            Ok(profile) => profile,
+            Err(e) => {
+                counter!("forge_profile_refreshes_total", "outcome" => "failed").increment(1);
+                return Err(e);
+            }
+        };
+        let changed = apply_profile(&self.pool, &payload.did, &profile, unix_now()).await?;
+        let outcome = if changed { "updated" } else { "unchanged" };
+        counter!("forge_profile_refreshes_total", "outcome" => outcome).increment(1);
+        Ok(())
+    }
+
+    async fn exhausted(&self, job: &JobRecord, error: &str) {
+        let Ok(payload) = job.payload::<RefreshJob>() else {
+            return;
+        };
+        tracing::warn!(did = %payload.did, "giving up on profile refresh: {}", error);
+        if let Err(e) = mark_checked(&self.pool, &payload.did, unix_now()).await {
+            tracing::warn!(did = %payload.did, "failed to record profile refresh: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::User;
+    use crate::jobs::JobQueue;
+    use crate::test_helpers::create_test_pool;
+    use crate::user::db::upsert_user;
+
+    fn user(did: &str, handle: &str) -> User {
+        User {
+            did: did.to_string(),
+            handle: handle.to_string(),
+            display_name: None,
+            avatar: None,
+        }
+    }
+
+    #[test]
+    fn documents_and_profile_records_are_read() {
+        let document = DidDocument::parse(
+            "did:plc:alice",
+            r##"{
+                "id": "did:plc:alice",
+                "alsoKnownAs": ["https://alice.example", "at://not_a_handle", "at://Alice.Example.com"],
+                "service": [
+                    { "id": "#forge", "type": "ForgeInstance", "serviceEndpoint": "https://forge.example" },
+                    { "id": "#atproto_pds", "type": "AtprotoPersonalDataServer", "serviceEndpoint": "https://pds.example/" }
+                ]
+            }"##,
+        )
+        .unwrap();
+        assert_eq!(
+            claimed_handle(&document).as_deref(),
+            Some("alice.example.com")
+        );
+        assert_eq!(
+            pds_endpoint(&document).as_deref(),
+            Some("https://pds.example")
+        );
+
+        let record = json!({
+            "uri": "at://did:plc:alice/app.bsky.actor.profile/self",
+            "value": {
+                "$type": "app.bsky.actor.profile",
+                "displayName": "  Alice  ",
+                "avatar": { "$type": "blob", "ref": { "$link": "bafkreiabc" }, "mimeType": "image/jpeg" }
+            }
+        });
+        assert_eq!(
+            parse_profile_record("https://pds.example", "did:plc:alice", &record),
+            (
+                Some("Alice".to_string()),
+                Some(
+                    "https://pds.example/xrpc/com.atproto.sync.getBlob?did=did%3Aplc%3Aalice&cid=bafkreiabc"
+                        .to_string()
+                )
+            )
+        );
+        let bare = json!({ "value": { "displayName": "", "avatar": { "cid": "../x" } } });
+        assert_eq!(
+            parse_profile_record("https://pds.example", "did:plc:alice", &bare),
+            (None, None)
+        );
+
+        assert!(is_valid_handle("a.bsky.social"));
+        assert!(!is_valid_handle("localhost"));
+        assert!(!is_valid_handle("evil.example/path"));
+        assert!(!is_valid_handle("-bad.example"));
+    }
+
+    #[tokio::test]
+    async fn stale_profiles_are_queued_once_and_spread_over_the_minute() {
+        let pool = create_test_pool().await.unwrap();
+        for (did, handle) in [
+            ("did:plc:a", "a.example"),
+            ("did:plc:b", "b.example"),
+            ("did:plc:c", "c.example"),
+        ] {
+            upsert_user(&pool, &user(did, handle)).await.unwrap();
+        }
+        let now = unix_now();
+        mark_checked(&pool, "did:plc:c", now).await.unwrap();
+        let config = ProfileRefreshConfig {
+            refreshes_per_minute: 1,
+            ..Default::default()
+        };
+
+        // one per tick, never-refreshed users first; `c` is fresh
+        assert_eq!(
+            enqueue_profile_refreshes(&pool, &config, now)
+                .await
+                .unwrap(),
+            1
+        );
+        let config = ProfileRefreshConfig {
+            refreshes_per_minute: 2,
+            ..config
+        };
+        assert_eq!(
+            enqueue_profile_refreshes(&pool, &config, now)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            enqueue_profile_refreshes(&pool, &config, now)
+                .await
+                .unwrap(),
+            0
+        );
+
+        let run_at: Vec<i64> =
+            sqlx::query_scalar("SELECT run_at FROM jobs WHERE kind = ? ORDER BY run_at")
+                .bind(JOB_KIND)
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(run_at, vec![now, now]);
+
+        // refreshed users are due again once the interval has passed
+        sqlx::query("UPDATE jobs SET status = 'succeeded'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        mark_checked(&pool, "did:plc:a", now).await.unwrap();
+        mark_checked(&pool, "did:plc:b", now).await.unwrap();
+        assert_eq!(
+            enqueue_profile_refreshes(&pool, &config, now)
+                .await
+                .unwrap(),
+            0
+        );
+        let later = now + config.interval_secs as i64;
+        assert_eq!(
+            enqueue_profile_refreshes(&pool, &config, later)
+                .await
+                .unwrap(),
+            2
+        );
+        let queue = JobQueue::new(pool.clone());
+        assert!(queue.claim_next(later).await.unwrap().is_some());
+        assert!(queue.claim_next(later).await.unwrap().is_none());
+        assert!(queue.claim_next(later + 30).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn handle_changes_are_stored_and_announced() {
+        let pool = create_test_pool().await.unwrap();
+        upsert_user(&pool, &user("did:plc:alice", "alice.example"))
+            .await
+            .unwrap();
+        upsert_user(&pool, &user("did:plc:bob", "bob.example"))
+            .await
+            .unwrap();
+        let now = unix_now();
+
+        // alice takes over the handle bob gave up
+        let profile = ResolvedProfile {
+            handle: Some("bob.example".to_string()),
+            display_name: Some("Alice".to_string()),
+            avatar: None,
+        };
+        assert!(
+            apply_profile(&pool, "did:plc:alice", &profile, now)
+                .await
+                .unwrap()
+        );
+        let alice = fetch_user_by_did(&pool, "did:plc:alice")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (alice.handle.as_str(), alice.display_name.as_deref()),
+            ("bob.example", Some("Alice"))
+        );
+        let bob = fetch_user_by_did(&pool, "did:plc:bob")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(bob.handle, "did:plc:bob");
+
+        let events: Vec<(String, String)> =
+            sqlx::query_as("SELECT action, data FROM audit_log WHERE actor_did = 'did:plc:alice'")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, HANDLE_CHANGED_EVENT);
+        let data: Value = serde_json::from_str(&events[0].1).unwrap();
+        assert_eq!(
+            (data["oldHandle"].as_str(), data["handle"].as_str()),
+            (Some("alice.example"), Some("bob.example"))
+        );
+
+        // an unverified handle keeps the cached one, and nothing is announced
+        let unverified = ResolvedProfile {
+            handle: None,
+            ..profile.clone()
+        };
+        assert!(
+            !apply_profile(&pool, "did:plc:alice", &unverified, now)
+                .await
+                .unwrap()
+        );
+
+        // sessions carry no profile and a possibly older handle; neither overwrites it
+        upsert_user(&pool, &user("did:plc:alice", "alice.example"))
+            .await
+            .unwrap();
+        let alice = fetch_user_by_did(&pool, "did:plc:alice")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (alice.handle.as_str(), alice.display_name.as_deref()),
+            ("bob.example", Some("Alice"))
+        );
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_log")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+}
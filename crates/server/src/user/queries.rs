@@ -0,0 +1,140 @@
+use sqlx::SqlitePool;
+
+use super::db::{contribution_days, count_for_user, unix_now};
+use super::models::UserContributions;
+use crate::repository::models::{RepositorySummary, RepositorySummaryRow};
+use crate::repository::visibility::HIDDEN;
+
+/// Contribution calendars cover the last year, like most forge profile pages.
+const CONTRIBUTION_WINDOW_SECS: i64 = 365 * 24 * 60 * 60;
+
+/// The repositories `did` owns that `viewer_did` may read.
+pub async fn repositories_owned_by(
+    pool: &SqlitePool,
+    did: &str,
+    viewer_did: Option<&str>,
+) -> anyhow::Result<Vec<RepositorySummary>> {
+    let rows = sqlx::query_as::<_, RepositorySummaryRow>(&format!(
+        "SELECT r.id, r.slug, r.remote_url FROM repositories r \
+         JOIN repository_owners o ON o.repository_id = r.id \
+         WHERE o.owner_did = ? AND {HIDDEN} ORDER BY r.slug"
+    ))
+    .bind(did)
+    .bind(viewer_did)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(RepositorySummary::from).collect())
+}
+
+/// The repositories `did` starred that `viewer_did` may read, most recent first.
+pub async fn repositories_starred_by(
+    pool: &SqlitePool,
+    did: &str,
+    viewer_did: Option<&str>,
+) -> anyhow::Result<Vec<RepositorySummary>> {
+    let rows = sqlx::query_as::<_, RepositorySummaryRow>(&format!(
+        "SELECT r.id, r.slug, r.remote_url FROM repositories r \
+         JOIN repository_stars s ON s.repository_id = r.id \
+         WHERE s.user_did = ? AND {HIDDEN} ORDER BY s.starred_at DESC"
+    ))
+    .bind(did)
+    .bind(viewer_did)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(RepositorySummary::from).collect())
+}
+
+pub async fn contributions_for(pool: &SqlitePool, did: &str) -> anyhow::Result<UserContributions> {
+    let repositories_owned = count_for_user(
+        pool,
+        "SELECT COUNT(*) FROM repository_owners WHERE owner_did = ?",
+        did,
+    )
+    .await?;
+    let stars_given = count_for_user(
+        pool,
+        "SELECT COUNT(*) FROM repository_stars WHERE user_did = ?",
+        did,
+    )
+    .await?;
+    let activity_count = count_for_user(
+        pool,
+        "SELECT COUNT(*) FROM activity_events WHERE actor_did = ?",
+        did,
+    )
+    .await?;
+    let days = contribution_days(pool, did, unix_now() - CONTRIBUTION_WINDOW_SECS).await?;
+
+    Ok(UserContributions {
+        did: did.to_string(),
+        repositories_owned,
+        stars_given,
+        activity_count,
+        days,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::User;
+    use crate::repository::mutations::{CreateRepositoryInput, create_repository_raw};
+    use crate::test_helpers::create_test_pool;
+    use crate::user::db::{
+        fetch_user_by_handle, record_activity, set_repository_owner, set_star, upsert_user,
+    };
+
+    #[tokio::test]
+    async fn profile_data_is_keyed_by_did() {
+        let pool = create_test_pool().await.unwrap();
+        let alice = User::new("did:plc:alice".into(), "alice.test".into());
+        upsert_user(&pool, &alice).await.unwrap();
+
+        let repo = create_repository_raw(
+            &pool,
+            CreateRepositoryInput {
+                slug: "widgets".into(),
+                group: None,
+            },
+        )
+        .await
+        .unwrap();
+        set_repository_owner(&pool, &repo.id, &alice.did)
+            .await
+            .unwrap();
+        set_star(&pool, &alice.did, &repo.id, true).await.unwrap();
+        set_star(&pool, &alice.did, &repo.id, true).await.unwrap();
+        record_activity(
+            &pool,
+            &alice.did,
+            Some(&repo.id),
+            "repository.created",
+            "created widgets",
+        )
+        .await
+        .unwrap();
+
+        let found = fetch_user_by_handle(&pool, "Alice.Test")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.did, "did:plc:alice");
+        assert_eq!(
+            repositories_owned_by(&pool, &alice.did)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            repositories_starred_by(&pool, &alice.did).await.unwrap()[0].slug,
+            "widgets"
+        );
+
+        let contributions = contributions_for(&pool, &alice.did).await.unwrap();
+        assert_eq!(contributions.repositories_owned, 1);
+        assert_eq!(contributions.stars_given, 1);
+        assert_eq!(contributions.activity_count, 1);
+        assert_eq!(contributions.days.iter().map(|d| d.count).sum::<i64>(), 1);
+    }
+}
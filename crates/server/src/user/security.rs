@@ -0,0 +1,462 @@
+//! Credentials users manage for their own account: SSH keys and personal access tokens.
+//!
+//! Tokens look like `forge_pat_<64 hex digits>` and are sent as `Authorization: Bearer`.
+//! Only a SHA-256 hash is stored, so a token is shown once, when it is created. Browser
+//! sessions live in [`crate::auth::session`]. Every change to any of these is reported
+//! through [`notify_security_change`].
+
+use anyhow::{Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use rand::RngCore;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+use super::db::{fetch_user_by_did, unix_now};
+use crate::auth::User;
+use crate::events::{Event, publish};
+use crate::notifications::db::insert_notification;
+
+pub const TOKEN_PREFIX: &str = "forge_pat_";
+pub const NOTIFICATION_KIND: &str = "securityChange";
+
+const KEY_TYPES: &[&str] = &[
+    "ssh-ed25519",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "ssh-rsa",
+    "sk-ssh-ed25519@openssh.com",
+    "sk-ecdsa-sha2-nistp256@openssh.com",
+];
+const MIN_RSA_BITS: usize = 2048;
+const MAX_TITLE_LEN: usize = 100;
+const MAX_KEYS_PER_USER: i64 = 100;
+const MAX_TOKENS_PER_USER: i64 = 50;
+const MAX_TOKEN_DAYS: i64 = 366;
+
+#[derive(Clone, Debug, PartialEq, sqlx::FromRow)]
+pub struct SshKey {
+    pub id: String,
+    pub user_did: String,
+    pub title: String,
+    pub key_type: String,
+    /// `<type> <base64>`, without the comment
+    pub public_key: String,
+    /// `SHA256:...`, as printed by `ssh-keygen -l`
+    pub fingerprint: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, sqlx::FromRow)]
+pub struct AccessToken {
+    pub id: String,
+    pub user_did: String,
+    pub name: String,
+    /// The first characters of the token
+    pub hint: String,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+}
+
+/// A parsed OpenSSH public key line.
+#[derive(Debug, PartialEq)]
+pub struct ParsedKey {
+    pub key_type: String,
+    pub public_key: String,
+    pub fingerprint: String,
+}
+
+/// Parses an `authorized_keys`-style line (`<type> <base64> [comment]`).
+pub fn parse_public_key(line: &str) -> Result<ParsedKey> {
+    let mut parts = line.split_whitespace();
+    let (Some(key_type), Some(encoded)) = (parts.next(), parts.next()) else {
+        bail!("expected an OpenSSH public key such as `ssh-ed25519 AAAA... comment`");
+    };
+    if !KEY_TYPES.contains(&key_type) {
+        bail!("unsupported key type `{}`", key_type);
+    }
+    let blob = STANDARD
+        .decode(encoded)
+        .map_err(|_| anyhow!("public key is not valid base64"))?;
+
+    let mut reader = WireReader(&blob);
+    if reader.string()? != key_type.as_bytes() {
+        bail!("public key data does not match its type `{}`", key_type);
+    }
+    if key_type == "ssh-rsa" {
+        let _exponent = reader.string()?;
+        let modulus = reader.string()?;
+        let significant = modulus.iter().skip_while(|b| **b == 0).count();
+        if significant * 8 < MIN_RSA_BITS {
+            bail!("RSA keys must be at least {} bits", MIN_RSA_BITS);
+        }
+    }
+
+    Ok(ParsedKey {
+        key_type: key_type.to_string(),
+        public_key: format!("{} {}", key_type, encoded),
+        fingerprint: format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(&blob))),
+    })
+}
+
+/// Reads length-prefixed strings from the SSH wire format.
+struct WireReader<'a>(&'a [u8]);
+
+impl<'a> WireReader<'a> {
+    fn string(&mut self) -> Result<&'a [u8]> {
+        let truncated = || anyhow!("public key data is truncated");
+        let len_bytes: [u8; 4] = self.0.get(..4).ok_or_else(truncated)?.try_into()?;
+        let len = u32::from_be_bytes(len_bytes) as usize;
+        let value = self.0.get(4..4 + len).ok_or_else(truncated)?;
+        self.0 = &self.0[4 + len..];
+        Ok(value)
+    }
+}
+
+fn clean_title(title: &str) -> Result<String> {
+    let title = title.trim();
+    if title.is_empty() {
+        bail!("a name is required");
+    }
+    Ok(title.chars().take(MAX_TITLE_LEN).collect())
+}
+
+const SSH_KEY_COLUMNS: &str =
+    "id, user_did, title, key_type, public_key, fingerprint, created_at, last_used_at";
+const TOKEN_COLUMNS: &str = "id, user_did, name, hint, created_at, expires_at, last_used_at";
+
+pub async fn add_ssh_key(pool: &SqlitePool, did: &str, title: &str, line: &str) -> Result<SshKey> {
+    let title = clean_title(title)?;
+    let parsed = parse_public_key(line)?;
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ssh_keys WHERE user_did = ?")
+        .bind(did)
+        .fetch_one(pool)
+        .await?;
+    if count >= MAX_KEYS_PER_USER {
+        bail!("accounts can have at most {} SSH keys", MAX_KEYS_PER_USER);
+    }
+
+    let key = SshKey {
+        id: cuid2::create_id(),
+        user_did: did.to_string(),
+        title,
+        key_type: parsed.key_type,
+        public_key: parsed.public_key,
+        fingerprint: parsed.fingerprint,
+        created_at: unix_now(),
+        last_used_at: None,
+    };
+    let inserted = sqlx::query(
+        "INSERT INTO ssh_keys (id, user_did, title, key_type, public_key, fingerprint, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?) ON CONFLICT(fingerprint) DO NOTHING",
+    )
+    .bind(&key.id)
+    .bind(did)
+    .bind(&key.title)
+    .bind(&key.key_type)
+    .bind(&key.public_key)
+    .bind(&key.fingerprint)
+    .bind(key.created_at)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if inserted == 0 {
+        bail!("this key is already registered");
+    }
+    Ok(key)
+}
+
+/// SSH keys of `did`, oldest first.
+pub async fn list_ssh_keys(pool: &SqlitePool, did: &str) -> Result<Vec<SshKey>> {
+    Ok(sqlx::query_as::<_, SshKey>(&format!(
+        "SELECT {} FROM ssh_keys WHERE user_did = ? ORDER BY created_at, id",
+        SSH_KEY_COLUMNS
+    ))
+    .bind(did)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Removes one of `did`'s keys, returning it if it existed.
+pub async fn remove_ssh_key(pool: &SqlitePool, did: &str, id: &str) -> Result<Option<SshKey>> {
+    Ok(sqlx::query_as::<_, SshKey>(&format!(
+        "DELETE FROM ssh_keys WHERE id = ? AND user_did = ? RETURNING {}",
+        SSH_KEY_COLUMNS
+    ))
+    .bind(id)
+    .bind(did)
+    .fetch_optional(pool)
+    .await?)
+}
+
+/// Hex SHA-256 of a secret token; only this is stored, for access tokens, share links and
+/// setup tokens alike.
+pub(crate) fn token_hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Creates a token and returns it with the only copy of its secret.
+pub async fn create_access_token(
+    pool: &SqlitePool,
+    did: &str,
+    name: &str,
+    expires_in_days: Option<i64>,
+) -> Result<(AccessToken, String)> {
+    let name = clean_title(name)?;
+    if let Some(days) = expires_in_days
+        && !(1..=MAX_TOKEN_DAYS).contains(&days)
+    {
+        bail!("tokens expire after 1 to {} days", MAX_TOKEN_DAYS);
+    }
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM access_tokens WHERE user_did = ?")
+        .bind(did)
+        .fetch_one(pool)
+        .await?;
+    if count >= MAX_TOKENS_PER_USER {
+        bail!(
+            "accounts can have at most {} access tokens",
+            MAX_TOKENS_PER_USER
+        );
+    }
+
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let token = format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        secret
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    );
+    let now = unix_now();
+    let record = AccessToken {
+        id: cuid2::create_id(),
+        user_did: did.to_string(),
+        name,
+        hint: token[..TOKEN_PREFIX.len() + 6].to_string(),
+        created_at: now,
+        expires_at: expires_in_days.map(|days| now + days * 86_400),
+        last_used_at: None,
+    };
+    sqlx::query(
+        "INSERT INTO access_tokens (id, user_did, name, token_hash, hint, created_at, expires_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&record.id)
+    .bind(did)
+    .bind(&record.name)
+    .bind(token_hash(&token))
+    .bind(&record.hint)
+    .bind(record.created_at)
+    .bind(record.expires_at)
+    .execute(pool)
+    .await?;
+    Ok((record, token))
+}
+
+/// Tokens of `did`, newest first. Expired tokens are listed until they are revoked.
+pub async fn list_access_tokens(pool: &SqlitePool, did: &str) -> Result<Vec<AccessToken>> {
+    Ok(sqlx::query_as::<_, AccessToken>(&format!(
+        "SELECT {} FROM access_tokens WHERE user_did = ? ORDER BY created_at DESC, id",
+        TOKEN_COLUMNS
+    ))
+    .bind(did)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Revokes one of `did`'s tokens, returning it if it existed.
+pub async fn revoke_access_token(
+    pool: &SqlitePool,
+    did: &str,
+    id: &str,
+) -> Result<Option<AccessToken>> {
+    Ok(sqlx::query_as::<_, AccessToken>(&format!(
+        "DELETE FROM access_tokens WHERE id = ? AND user_did = ? RETURNING {}",
+        TOKEN_COLUMNS
+    ))
+    .bind(id)
+    .bind(did)
+    .fetch_optional(pool)
+    .await?)
+}
+
+/// The user a token belongs to, if it is valid and unexpired. Records the use.
+pub async fn authenticate_access_token(pool: &SqlitePool, token: &str) -> Result<Option<User>> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+    let now = unix_now();
+    let did: Option<String> = sqlx::query_scalar(
+        "UPDATE access_tokens SET last_used_at = ? \
+         WHERE token_hash = ? AND (expires_at IS NULL OR expires_at > ?) RETURNING user_did",
+    )
+    .bind(now)
+    .bind(token_hash(token))
+    .bind(now)
+    .fetch_optional(pool)
+    .await?;
+    let Some(did) = did else {
+        return Ok(None);
+    };
+    Ok(Some(match fetch_user_by_did(pool, &did).await? {
+        Some(record) => User {
+            did: record.did,
+            handle: record.handle,
+            display_name: record.display_name,
+            avatar: record.avatar,
+        },
+        None => User::new(did.clone(), did),
+    }))
+}
+
+/// Tells `did` about a change to their credentials and publishes it as `kind`.
+pub async fn notify_security_change(
+    pool: &SqlitePool,
+    did: &str,
+    kind: &str,
+    title: &str,
+    data: Value,
+) -> Result<()> {
+    insert_notification(pool, did, NOTIFICATION_KIND, title, None, unix_now()).await?;
+    let event = Event {
+        kind,
+        source: "core",
+        actor_did: Some(did),
+        repository_id: None,
+        data,
+    };
+    publish(pool, crate::config::current().event_sink.as_ref(), event).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::create_test_pool;
+
+    const ED25519: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl alice@laptop";
+
+    #[test]
+    fn parses_openssh_public_keys() {
+        let parsed = parse_public_key(ED25519).unwrap();
+        assert_eq!(parsed.key_type, "ssh-ed25519");
+        assert!(!parsed.public_key.ends_with("alice@laptop"));
+        assert!(parsed.fingerprint.starts_with("SHA256:"));
+        assert_eq!(parsed.fingerprint.len(), "SHA256:".len() + 43);
+
+        assert!(parse_public_key("ssh-dss AAAAB3NzaC1kc3M=").is_err());
+        assert!(parse_public_key("ssh-ed25519 not-base64!").is_err());
+        // The blob says ssh-ed25519, the line says ecdsa
+        let mismatched = ED25519.replace("ssh-ed25519", "ecdsa-sha2-nistp256");
+        assert!(parse_public_key(&mismatched).is_err());
+
+        let mut small_rsa = Vec::new();
+        for part in [&b"ssh-rsa"[..], &[1, 0, 1], &[0xc5; 128]] {
+            small_rsa.extend_from_slice(&(part.len() as u32).to_be_bytes());
+            small_rsa.extend_from_slice(part);
+        }
+        let line = format!("ssh-rsa {}", STANDARD.encode(&small_rsa));
+        assert!(
+            parse_public_key(&line)
+                .unwrap_err()
+                .to_string()
+                .contains("2048")
+        );
+    }
+
+    #[tokio::test]
+    async fn keys_are_unique_and_owned() {
+        let pool = create_test_pool().await.unwrap();
+        let key = add_ssh_key(&pool, "did:plc:alice", "Laptop", ED25519)
+            .await
+            .unwrap();
+        assert!(
+            add_ssh_key(&pool, "did:plc:bob", "Copied", ED25519)
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            list_ssh_keys(&pool, "did:plc:alice").await.unwrap(),
+            vec![key.clone()]
+        );
+
+        assert!(
+            remove_ssh_key(&pool, "did:plc:bob", &key.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            remove_ssh_key(&pool, "did:plc:alice", &key.id)
+                .await
+                .unwrap(),
+            Some(key)
+        );
+        assert!(
+            list_ssh_keys(&pool, "did:plc:alice")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn tokens_authenticate_until_revoked_or_expired() {
+        let pool = create_test_pool().await.unwrap();
+        let (record, token) = create_access_token(&pool, "did:plc:alice", "CI", Some(30))
+            .await
+            .unwrap();
+        assert!(token.starts_with(&record.hint));
+
+        let user = authenticate_access_token(&pool, &token)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.did, "did:plc:alice");
+        let listed = list_access_tokens(&pool, "did:plc:alice").await.unwrap();
+        assert!(listed[0].last_used_at.is_some());
+        assert!(
+            authenticate_access_token(&pool, &format!("{}00", TOKEN_PREFIX))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        sqlx::query("UPDATE access_tokens SET expires_at = 1")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(
+            authenticate_access_token(&pool, &token)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        assert!(
+            revoke_access_token(&pool, "did:plc:alice", &record.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            list_access_tokens(&pool, "did:plc:alice")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            create_access_token(&pool, "did:plc:alice", "x", Some(0))
+                .await
+                .is_err()
+        );
+    }
+}
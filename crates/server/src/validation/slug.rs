@@ -13,3 +13,85 @@ pub fn validate_slug(slug: &str) -> anyhow::Result<()> {
         Err(anyhow::anyhow!("slug must be lowercase kebab-case"))
     }
 }
+
+/// Validates a slug taken from a lookup path. Lookups match slugs case-insensitively, so
+/// `Team` is accepted and finds `team`; callers redirect to the stored spelling.
+pub fn validate_lookup_slug(slug: &str) -> anyhow::Result<()> {
+    validate_slug(&slug.to_ascii_lowercase())
+}
+
+/// Turns free-form (possibly unicode) text into a kebab-case slug, e.g. `Café Crème` becomes
+/// `cafe-creme`. Characters without an ASCII equivalent are treated as separators.
+pub fn transliterate_slug(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars().flat_map(char::to_lowercase) {
+        let mapped = match c {
+            'a'..='z' | '0'..='9' => Some(c.to_string()),
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => Some("a".into()),
+            'æ' => Some("ae".into()),
+            'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => Some("c".into()),
+            'ď' | 'đ' | 'ð' => Some("d".into()),
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => Some("e".into()),
+            'ĝ' | 'ğ' | 'ġ' | 'ģ' => Some("g".into()),
+            'ĥ' | 'ħ' => Some("h".into()),
+            'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => Some("i".into()),
+            'ĵ' => Some("j".into()),
+            'ķ' => Some("k".into()),
+            'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => Some("l".into()),
+            'ñ' | 'ń' | 'ņ' | 'ň' => Some("n".into()),
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => Some("o".into()),
+            'œ' => Some("oe".into()),
+            'ŕ' | 'ŗ' | 'ř' => Some("r".into()),
+            'ś' | 'ŝ' | 'ş' | 'š' => Some("s".into()),
+            'ß' => Some("ss".into()),
+            'ţ' | 'ť' | 'ŧ' => Some("t".into()),
+            'þ' => Some("th".into()),
+            'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => Some("u".into()),
+            'ŵ' => Some("w".into()),
+            'ý' | 'ÿ' | 'ŷ' => Some("y".into()),
+            'ź' | 'ż' | 'ž' => Some("z".into()),
+            _ => None,
+        };
+        match mapped {
+            Some(text) => out.push_str(&text),
+            None => {
+                if !out.is_empty() && !out.ends_with('-') {
+                    out.push('-');
+                }
+            }
+        }
+    }
+    out.trim_end_matches('-').to_string()
+}
+
+/// Validates a slug supplied at creation time, transliterating it first when
+/// `repos.transliterate_slugs` is set.
+pub fn prepare_slug(input: &str) -> anyhow::Result<String> {
+    let slug = if crate::config::current().repos.transliterate_slugs {
+        transliterate_slug(input)
+    } else {
+        input.to_string()
+    };
+    validate_slug(&slug)?;
+    Ok(slug)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transliterates_unicode_into_kebab_case() {
+        assert_eq!(transliterate_slug("Café Crème"), "cafe-creme");
+        assert_eq!(transliterate_slug("  Straße__42 "), "strasse-42");
+        assert_eq!(transliterate_slug("日本"), "");
+        assert!(validate_slug(&transliterate_slug("Ørsted Øresund!")).is_ok());
+    }
+
+    #[test]
+    fn lookup_slugs_may_differ_by_case() {
+        assert!(validate_lookup_slug("Team-App").is_ok());
+        assert!(validate_slug("Team-App").is_err());
+        assert!(validate_lookup_slug("Team--App").is_err());
+    }
+}
@@ -0,0 +1,356 @@
+use anyhow::anyhow;
+use sqlx::{SqliteExecutor, SqlitePool};
+
+use super::models::{DeliveryStatus, WebhookDelivery, WebhookRecord};
+use crate::graphql::pagination::{Direction, Page, PageRequest, encode_cursor};
+
+/// Cursors for the delivery log are tagged with this kind.
+pub const DELIVERY_CURSOR_KIND: &str = "webhook_delivery";
+
+const WEBHOOK_COLUMNS: &str =
+    "id, repository_id, url, events, active, created_by, created_at, updated_at";
+const DELIVERY_COLUMNS: &str = "id, webhook_id, event, event_id, redelivery_of, status, attempt, \
+     next_attempt_at, request_url, request_headers, request_body, response_status, \
+     response_headers, response_body, duration_ms, error, truncated, created_at, completed_at";
+
+#[allow(clippy::too_many_arguments)]
+pub async fn insert_webhook(
+    pool: &SqlitePool,
+    id: &str,
+    repository_id: Option<&str>,
+    url: &str,
+    secret: (&[u8], &[u8]),
+    events: &[String],
+    active: bool,
+    created_by: Option<&str>,
+    now: i64,
+) -> anyhow::Result<WebhookRecord> {
+    let events = serde_json::to_string(events)?;
+    sqlx::query(
+        "INSERT INTO webhooks (id, repository_id, url, secret_nonce, secret_ciphertext, events, \
+         active, created_by, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(id)
+    .bind(repository_id)
+    .bind(url)
+    .bind(secret.0)
+    .bind(secret.1)
+    .bind(&events)
+    .bind(active)
+    .bind(created_by)
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok(WebhookRecord {
+        id: id.to_string(),
+        repository_id: repository_id.map(str::to_string),
+        url: url.to_string(),
+        events,
+        active,
+        created_by: created_by.map(str::to_string),
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+/// Writes the editable fields of `webhook`, and the secret when one is given.
+pub async fn update_webhook(
+    pool: &SqlitePool,
+    webhook: &WebhookRecord,
+    secret: Option<(&[u8], &[u8])>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE webhooks SET url = ?, events = ?, active = ?, updated_at = ?, \
+         secret_nonce = COALESCE(?, secret_nonce), \
+         secret_ciphertext = COALESCE(?, secret_ciphertext) WHERE id = ?",
+    )
+    .bind(&webhook.url)
+    .bind(&webhook.events)
+    .bind(webhook.active)
+    .bind(webhook.updated_at)
+    .bind(secret.map(|secret| secret.0))
+    .bind(secret.map(|secret| secret.1))
+    .bind(&webhook.id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn fetch_webhook(
+    pool: &SqlitePool,
+    id: &str,
+) -> Result<Option<WebhookRecord>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookRecord>(&format!(
+        "SELECT {WEBHOOK_COLUMNS} FROM webhooks WHERE id = ?"
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// The webhooks of a repository, or the instance webhooks for `None`, oldest first.
+pub async fn list_webhooks(
+    pool: &SqlitePool,
+    repository_id: Option<&str>,
+) -> Result<Vec<WebhookRecord>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookRecord>(&format!(
+        "SELECT {WEBHOOK_COLUMNS} FROM webhooks WHERE repository_id IS ? \
+         ORDER BY created_at, id"
+    ))
+    .bind(repository_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Removes a webhook and its delivery log; returns whether it existed.
+pub async fn delete_webhook(pool: &SqlitePool, id: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM webhooks WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Active webhooks that receive the events of `repository_id`: its own and the instance
+/// webhooks. Events without a repository only reach instance webhooks.
+pub async fn webhooks_for_event(
+    executor: impl SqliteExecutor<'_>,
+    repository_id: Option<&str>,
+) -> Result<Vec<WebhookRecord>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookRecord>(&format!(
+        "SELECT {WEBHOOK_COLUMNS} FROM webhooks \
+         WHERE active = 1 AND (repository_id IS NULL OR repository_id = ?)"
+    ))
+    .bind(repository_id)
+    .fetch_all(executor)
+    .await
+}
+
+/// Queues a delivery of `body`; `redelivery_of` names the delivery it repeats.
+#[allow(clippy::too_many_arguments)]
+pub async fn insert_delivery(
+    executor: impl SqliteExecutor<'_>,
+    webhook: &WebhookRecord,
+    event: &str,
+    event_id: &str,
+    body: &str,
+    redelivery_of: Option<&str>,
+    now: i64,
+) -> Result<String, sqlx::Error> {
+    let id = cuid2::create_id();
+    sqlx::query(
+        "INSERT INTO webhook_deliveries (id, webhook_id, event, event_id, redelivery_of, \
+         status, attempt, next_attempt_at, request_url, request_body, created_at) \
+         VALUES (?, ?, ?, ?, ?, 'pending', 0, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(&webhook.id)
+    .bind(event)
+    .bind(event_id)
+    .bind(redelivery_of)
+    .bind(now)
+    .bind(&webhook.url)
+    .bind(body)
+    .bind(now)
+    .execute(executor)
+    .await?;
+    Ok(id)
+}
+
+pub async fn fetch_delivery(
+    pool: &SqlitePool,
+    id: &str,
+) -> Result<Option<WebhookDelivery>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookDelivery>(&format!(
+        "SELECT {DELIVERY_COLUMNS} FROM webhook_deliveries WHERE id = ?"
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Narrows the delivery log of one webhook.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeliveryFilter {
+    pub status: Option<DeliveryStatus>,
+    pub event: Option<String>,
+}
+
+const DELIVERY_FILTER_SQL: &str =
+    "webhook_id = ?1 AND (?2 IS NULL OR status = ?2) AND (?3 IS NULL OR event = ?3)";
+
+impl WebhookDelivery {
+    pub fn cursor(&self) -> String {
+        encode_cursor(DELIVERY_CURSOR_KIND, &self.created_at.to_string(), &self.id)
+    }
+}
+
+/// One page of a webhook's deliveries, newest first.
+pub async fn page_deliveries(
+    pool: &SqlitePool,
+    webhook_id: &str,
+    filter: &DeliveryFilter,
+    request: &PageRequest,
+) -> anyhow::Result<Page<WebhookDelivery>> {
+    let (after, order) = match request.direction {
+        Direction::Forward => (
+            "(created_at, id) < (?4, ?5)",
+            "ORDER BY created_at DESC, id DESC",
+        ),
+        Direction::Backward => ("(created_at, id) > (?4, ?5)", "ORDER BY created_at, id"),
+    };
+    let sql = format!(
+        "SELECT {DELIVERY_COLUMNS} FROM webhook_deliveries WHERE {DELIVERY_FILTER_SQL} \
+         AND (?4 IS NULL OR {after}) {order} LIMIT ?6"
+    );
+    let query = sqlx::query_as::<_, WebhookDelivery>(&sql)
+        .bind(webhook_id)
+        .bind(filter.status.map(|status| status.as_str()))
+        .bind(filter.event.as_deref());
+    let query = match &request.cursor {
+        Some(cursor) => {
+            let created_at: i64 = cursor
+                .slug
+                .parse()
+                .map_err(|_| anyhow!("invalid delivery cursor"))?;
+            query.bind(Some(created_at)).bind(Some(cursor.id.as_str()))
+        }
+        None => query.bind(None::<i64>).bind(None::<&str>),
+    };
+    let rows = query.bind(request.fetch_limit()).fetch_all(pool).await?;
+    Ok(Page::from_rows(request, rows))
+}
+
+pub async fn count_deliveries(
+    pool: &SqlitePool,
+    webhook_id: &str,
+    filter: &DeliveryFilter,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM webhook_deliveries WHERE {DELIVERY_FILTER_SQL}"
+    ))
+    .bind(webhook_id)
+    .bind(filter.status.map(|status| status.as_str()))
+    .bind(filter.event.as_deref())
+    .fetch_one(pool)
+    .await
+}
+
+/// Test deliveries and redeliveries of a webhook created after `since`.
+pub async fn count_manual_deliveries(
+    pool: &SqlitePool,
+    webhook_id: &str,
+    ping_event: &str,
+    since: i64,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT COUNT(*) FROM webhook_deliveries WHERE webhook_id = ? AND created_at > ? \
+         AND (redelivery_of IS NOT NULL OR event = ?)",
+    )
+    .bind(webhook_id)
+    .bind(since)
+    .bind(ping_event)
+    .fetch_one(pool)
+    .await
+}
+
+/// A pending delivery whose next attempt is due, with what sending it needs.
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct DueDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+    pub attempt: i64,
+    pub request_body: Option<String>,
+    pub url: String,
+    pub active: bool,
+    pub secret_nonce: Vec<u8>,
+    pub secret_ciphertext: Vec<u8>,
+}
+
+pub async fn due_deliveries(
+    pool: &SqlitePool,
+    now: i64,
+    limit: i64,
+) -> Result<Vec<DueDelivery>, sqlx::Error> {
+    sqlx::query_as::<_, DueDelivery>(
+        "SELECT d.id, d.webhook_id, d.event, d.attempt, d.request_body, w.url, w.active, \
+         w.secret_nonce, w.secret_ciphertext FROM webhook_deliveries d \
+         JOIN webhooks w ON w.id = d.webhook_id \
+         WHERE d.status = 'pending' AND d.next_attempt_at <= ? \
+         ORDER BY d.next_attempt_at, d.created_at LIMIT ?",
+    )
+    .bind(now)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// The outcome of one attempt, as stored on the delivery.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttemptRecord {
+    pub attempt: i64,
+    pub status: Option<DeliveryStatus>,
+    pub next_attempt_at: Option<i64>,
+    pub request_url: String,
+    pub request_headers: Option<String>,
+    pub response_status: Option<i64>,
+    pub response_headers: Option<String>,
+    pub response_body: Option<String>,
+    pub duration_ms: Option<i64>,
+    pub error: Option<String>,
+    pub truncated: bool,
+    pub completed_at: Option<i64>,
+}
+
+pub async fn record_attempt(
+    pool: &SqlitePool,
+    delivery_id: &str,
+    record: &AttemptRecord,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE webhook_deliveries SET status = ?, attempt = ?, next_attempt_at = ?, \
+         request_url = ?, request_headers = ?, response_status = ?, response_headers = ?, \
+         response_body = ?, duration_ms = ?, error = ?, truncated = ?, completed_at = ? \
+         WHERE id = ?",
+    )
+    .bind(record.status.unwrap_or(DeliveryStatus::Pending).as_str())
+    .bind(record.attempt)
+    .bind(record.next_attempt_at)
+    .bind(&record.request_url)
+    .bind(&record.request_headers)
+    .bind(record.response_status)
+    .bind(&record.response_headers)
+    .bind(&record.response_body)
+    .bind(record.duration_ms)
+    .bind(&record.error)
+    .bind(record.truncated)
+    .bind(record.completed_at)
+    .bind(delivery_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Clears the headers and bodies of finished deliveries created before `before`, or not
+/// among the `keep` most recent of their webhook. Returns how many were cleared.
+pub async fn prune_delivery_details(
+    pool: &SqlitePool,
+    before: i64,
+    keep: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE webhook_deliveries SET request_headers = NULL, request_body = NULL, \
+         response_headers = NULL, response_body = NULL \
+         WHERE status != 'pending' AND (request_body IS NOT NULL OR response_body IS NOT NULL) \
+         AND (created_at < ? OR id IN (SELECT id FROM (SELECT id, ROW_NUMBER() OVER \
+         (PARTITION BY webhook_id ORDER BY created_at DESC, id DESC) AS position \
+         FROM webhook_deliveries) WHERE position > ?))",
+    )
+    .bind(before)
+    .bind(keep)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
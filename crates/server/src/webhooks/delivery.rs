@@ -0,0 +1,677 @@
+//! The supervised worker that sends queued webhook deliveries.
+//!
+//! Every `webhooks.poll_interval_secs` the worker picks up pending deliveries whose next
+//! attempt is due and sends up to `webhooks.concurrency` of them at a time. A `2xx`
+//! answer completes a delivery. Anything else, including a timeout, is retried after
+//! `jobs::queue::backoff_secs` until `webhooks.max_attempts` is reached. Redirects are
+//! not followed. Each cycle also clears the headers and bodies of deliveries older than
+//! 30 days or beyond the 100 most recent of their webhook.
+//!
+//! Unless `webhooks.allow_private_targets` is set, target hosts are resolved when a
+//! delivery is sent and only public addresses are connected to. A public name that
+//! resolves to a loopback, private or link-local address fails like an unreachable
+//! target.
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use metrics::counter;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use sqlx::SqlitePool;
+use tokio_util::sync::CancellationToken;
+
+use super::db::{
+    AttemptRecord, DueDelivery, due_deliveries, prune_delivery_details, record_attempt,
+};
+use super::models::DeliveryStatus;
+use super::{DELIVERY_HEADER, EVENT_HEADER, PING_EVENT, SIGNATURE_HEADER, associated_data, sign};
+use crate::config::{Config, WebhooksConfig};
+use crate::jobs::queue::backoff_secs;
+use crate::repository::secrets::{SecretKey, server_key};
+use crate::user::db::unix_now;
+
+/// Deliveries picked up per worker cycle
+const BATCH_SIZE: i64 = 100;
+/// Response bodies are cut to this size in the delivery log
+const MAX_BODY_BYTES: usize = 64 * 1024;
+/// Stored request or response headers, counted as names plus values
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+const DETAIL_RETENTION_SECS: i64 = 30 * 24 * 60 * 60;
+const DETAILS_KEPT_PER_WEBHOOK: i64 = 100;
+const USER_AGENT: &str = concat!("Forge-Webhooks/", env!("CARGO_PKG_VERSION"));
+
+/// Counts from one worker cycle
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DeliveryReport {
+    pub delivered: usize,
+    pub retrying: usize,
+    pub failed: usize,
+}
+
+/// Sends due webhook deliveries.
+pub struct DeliveryWorker {
+    pool: SqlitePool,
+    key: &'static SecretKey,
+    config: WebhooksConfig,
+    client: reqwest::Client,
+}
+
+impl DeliveryWorker {
+    pub fn new(pool: SqlitePool, key: &'static SecretKey, config: WebhooksConfig) -> Result<Self> {
+        let mut client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs.max(1)))
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(USER_AGENT);
+        if !config.allow_private_targets {
+            client = client.dns_resolver(Arc::new(PublicResolver));
+        }
+        Ok(Self {
+            pool,
+            key,
+            client: client.build().context("failed to create HTTP client")?,
+            config,
+        })
+    }
+
+    /// A worker signing with the secrets key from `secrets.key_path`.
+    pub fn from_config(pool: SqlitePool, config: &Config) -> Result<Self> {
+        Self::new(pool, server_key()?, config.webhooks.clone())
+    }
+
+    pub async fn run(self, shutdown: CancellationToken) -> Result<()> {
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs(self.config.poll_interval_secs.max(1)));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = ticker.tick() => {
+                    // A read-only standby leaves delivery to the instance holding the lease.
+                    if crate::coordination::is_read_only() {
+                        continue;
+                    }
+                    match self.deliver_pending(unix_now()).await {
+                        Ok(report) if report != DeliveryReport::default() => {
+                            tracing::debug!(?report, "webhook delivery cycle finished");
+                        }
+                        Ok(_) => {}
+                        Err(e) => tracing::warn!("webhook delivery failed: {:#}", e),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends every delivery due at `now` and prunes old delivery details.
+    pub async fn deliver_pending(&self, now: i64) -> Result<DeliveryReport> {
+        prune_delivery_details(
+            &self.pool,
+            now - DETAIL_RETENTION_SECS,
+            DETAILS_KEPT_PER_WEBHOOK,
+        )
+        .await?;
+
+        let mut report = DeliveryReport::default();
+        let due = due_deliveries(&self.pool, now, BATCH_SIZE).await?;
+        let mut attempts = futures::stream::iter(due)
+            .map(|delivery| self.attempt(delivery, now))
+            .buffer_unordered(self.config.concurrency.max(1));
+        while let Some(status) = attempts.next().await {
+            let outcome = match status? {
+                DeliveryStatus::Succeeded => {
+                    report.delivered += 1;
+                    "delivered"
+                }
+                DeliveryStatus::Pending => {
+                    report.retrying += 1;
+                    "retrying"
+                }
+                DeliveryStatus::Failed => {
+                    report.failed += 1;
+                    "failed"
+                }
+            };
+            counter!("forge_webhook_deliveries_total", "outcome" => outcome).increment(1);
+        }
+        Ok(report)
+    }
+
+    /// Makes one attempt and stores its outcome, which is the delivery's new status.
+    async fn attempt(&self, delivery: DueDelivery, now: i64) -> Result<DeliveryStatus> {
+        let attempt = delivery.attempt + 1;
+        let mut record = AttemptRecord {
+            attempt,
+            request_url: delivery.url.clone(),
+            ..AttemptRecord::default()
+        };
+
+        // Test deliveries also go to disabled webhooks
+        let active = delivery.active || delivery.event == PING_EVENT;
+        let sent = if !active {
+            Err("the webhook was disabled".to_string())
+        } else if let Some(body) = delivery.request_body.as_deref() {
+            self.send(&delivery, body, &mut record).await
+        } else {
+            Err("the delivery has no payload".to_string())
+        };
+        let retry = match sent {
+            Ok(()) => false,
+            Err(error) => {
+                record.error = Some(error);
+                active
+                    && delivery.request_body.is_some()
+                    && attempt < self.config.max_attempts.max(1)
+            }
+        };
+        let status = if record.error.is_none() {
+            DeliveryStatus::Succeeded
+        } else if retry {
+            DeliveryStatus::Pending
+        } else {
+            DeliveryStatus::Failed
+        };
+        record.status = Some(status);
+        match status {
+            DeliveryStatus::Pending => record.next_attempt_at = Some(now + backoff_secs(attempt)),
+            _ => record.completed_at = Some(unix_now()),
+        }
+        record_attempt(&self.pool, &delivery.id, &record).await?;
+        Ok(status)
+    }
+
+    /// POSTs the payload and fills in the request and response parts of `record`. Errors
+    /// are what the delivery log shows.
+    async fn send(
+        &self,
+        delivery: &DueDelivery,
+        body: &str,
+        record: &mut AttemptRecord,
+    ) -> Result<(), String> {
+        let secret = self
+            .key
+            .open(
+                &associated_data(&delivery.webhook_id),
+                &delivery.secret_nonce,
+                &delivery.secret_ciphertext,
+            )
+            .map_err(|e| format!("{:#}", e))?;
+        let headers = [
+            ("Content-Type", "application/json".to_string()),
+            ("User-Agent", USER_AGENT.to_string()),
+            (EVENT_HEADER, delivery.event.clone()),
+            (DELIVERY_HEADER, delivery.id.clone()),
+            (SIGNATURE_HEADER, sign(&secret, body.as_bytes())),
+        ];
+        let (stored, _) = header_json(headers.iter().map(|(name, value)| (*name, value.as_str())));
+        record.request_headers = Some(stored);
+        // Addresses in the URL itself are not resolved, so they are checked here
+        if !self.config.allow_private_targets {
+            let literal = url::Url::parse(&delivery.url)
+                .ok()
+                .and_then(|url| match url.host()? {
+                    url::Host::Ipv4(ip) => Some(IpAddr::V4(ip)),
+                    url::Host::Ipv6(ip) => Some(IpAddr::V6(ip)),
+                    url::Host::Domain(_) => None,
+                });
+            if literal.is_some_and(|ip| !is_public_address(ip)) {
+                return Err("the target is not a public address".to_string());
+            }
+        }
+
+        let mut request = self.client.post(&delivery.url).body(body.to_string());
+        for (name, value) in &headers {
+            request = request.header(*name, value);
+        }
+        let started = Instant::now();
+        let response = request.send().await;
+        let mut response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                record.duration_ms = Some(started.elapsed().as_millis() as i64);
+                return Err(e.without_url().to_string());
+            }
+        };
+        let status = response.status();
+        record.response_status = Some(i64::from(status.as_u16()));
+        let (stored, headers_truncated) = header_json(
+            response
+                .headers()
+                .iter()
+                .filter(|(name, _)| *name != reqwest::header::SET_COOKIE)
+                .map(|(name, value)| (name.as_str(), value.to_str().unwrap_or("<binary>"))),
+        );
+        record.response_headers = Some(stored);
+        let mut body = Vec::new();
+        let mut body_truncated = false;
+        loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    let room = MAX_BODY_BYTES - body.len();
+                    body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+                    if chunk.len() > room {
+                        body_truncated = true;
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    record.error = Some(e.without_url().to_string());
+                    break;
+                }
+            }
+        }
+        record.duration_ms = Some(started.elapsed().as_millis() as i64);
+        record.response_body = Some(String::from_utf8_lossy(&body).into_owned());
+        record.truncated = headers_truncated || body_truncated;
+        if !status.is_success() {
+            return Err(format!("the target answered {}", status));
+        }
+        // A body that broke off still counts; the target has answered.
+        record.error = None;
+        Ok(())
+    }
+}
+
+/// Resolves target hosts with the system resolver and keeps only public addresses.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(resolve_public(name.as_str().to_string()))
+    }
+}
+
+async fn resolve_public(host: String) -> Result<Addrs, Box<dyn std::error::Error + Send + Sync>> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+        .await?
+        .filter(|addr| is_public_address(addr.ip()))
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{} does not resolve to a public address", host).into());
+    }
+    Ok(Box::new(addrs.into_iter()))
+}
+
+/// Whether `ip` is reachable on the public internet: not loopback, private, link-local,
+/// shared (CGNAT), documentation, benchmarking, multicast or reserved.
+fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_public_v4(mapped);
+            }
+            let segments = ip.segments();
+            // NAT64 addresses embed the IPv4 address they translate to
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [a, b] = segments[6].to_be_bytes();
+                let [c, d] = segments[7].to_be_bytes();
+                return is_public_v4(Ipv4Addr::new(a, b, c, d));
+            }
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local()
+                || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+        }
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+/// Headers as a JSON object, leaving out those beyond [`MAX_HEADER_BYTES`]. Repeated
+/// names keep their last value.
+fn header_json<'a>(headers: impl Iterator<Item = (&'a str, &'a str)>) -> (String, bool) {
+    let mut map = BTreeMap::new();
+    let mut size = 0;
+    let mut truncated = false;
+    for (name, value) in headers {
+        size += name.len() + value.len();
+        if size > MAX_HEADER_BYTES {
+            truncated = true;
+            break;
+        }
+        map.insert(name.to_ascii_lowercase(), value.to_string());
+    }
+    (serde_json::to_string(&map).unwrap_or_default(), truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::Router;
+    use axum::body::Bytes;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::events::{Event, publish};
+    use crate::test_helpers::create_test_pool;
+    use crate::webhooks::db::fetch_delivery;
+    use crate::webhooks::{WebhookInput, create_webhook, redeliver, test_webhook};
+
+    #[derive(Default)]
+    struct Target {
+        /// Status codes to answer with, in order; `200` once they run out.
+        answers: Mutex<Vec<u16>>,
+        received: Mutex<Vec<(HeaderMap, Bytes)>>,
+    }
+
+    async fn receive(
+        State(target): State<Arc<Target>>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> (StatusCode, &'static str) {
+        target.received.lock().unwrap().push((headers, body));
+        let mut answers = target.answers.lock().unwrap();
+        let status = if answers.is_empty() {
+            200
+        } else {
+            answers.remove(0)
+        };
+        (StatusCode::from_u16(status).unwrap(), "thanks")
+    }
+
+    async fn serve(target: Arc<Target>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/hook", post(receive))
+            .with_state(target);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/hook", addr)
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_signed_and_retried_until_they_succeed() {
+        let pool = create_test_pool().await.unwrap();
+        let key: &'static SecretKey = Box::leak(Box::new(SecretKey::from_bytes(&[3; 32]).unwrap()));
+        let config = WebhooksConfig {
+            allow_private_targets: true,
+            max_attempts: 3,
+            ..WebhooksConfig::default()
+        };
+        let target = Arc::new(Target {
+            answers: Mutex::new(vec![500]),
+            ..Target::default()
+        });
+        let url = serve(target.clone()).await;
+        let webhook = create_webhook(
+            &pool,
+            key,
+            &config,
+            None,
+            WebhookInput {
+                url: Some(url),
+                secret: Some("topsecret".to_string()),
+                events: Some(vec!["issue.*".to_string()]),
+                active: None,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        let event = Event {
+            kind: "issue.created",
+            source: "issues",
+            actor_did: None,
+            repository_id: None,
+            data: serde_json::json!({ "number": 7 }),
+        };
+        publish(&pool, None, event).await.unwrap();
+
+        let worker = DeliveryWorker::new(pool.clone(), key, config).unwrap();
+        let now = unix_now();
+        let report = worker.deliver_pending(now).await.unwrap();
+        assert_eq!(report.retrying, 1);
+        // Not due again until the backoff has passed.
+        assert_eq!(
+            worker.deliver_pending(now).await.unwrap(),
+            DeliveryReport::default()
+        );
+        let report = worker.deliver_pending(now + backoff_secs(1)).await.unwrap();
+        assert_eq!(report.delivered, 1);
+
+        let (headers, body) = {
+            let received = target.received.lock().unwrap();
+            assert_eq!(received.len(), 2);
+            received[1].clone()
+        };
+        assert_eq!(headers[EVENT_HEADER], "issue.created");
+        assert_eq!(
+            headers[SIGNATURE_HEADER].to_str().unwrap(),
+            sign("topsecret", &body)
+        );
+        let envelope: crate::events::Envelope = serde_json::from_slice(&body).unwrap();
+        assert_eq!(envelope.data["number"], 7);
+
+        let delivery_id = headers[DELIVERY_HEADER].to_str().unwrap().to_string();
+        let delivery = fetch_delivery(&pool, &delivery_id).await.unwrap().unwrap();
+        assert_eq!(delivery.webhook_id, webhook.id);
+        assert_eq!(delivery.status(), DeliveryStatus::Succeeded);
+        assert_eq!(delivery.attempt, 2);
+        assert_eq!(delivery.response_status, Some(200));
+        assert_eq!(delivery.response_body.as_deref(), Some("thanks"));
+        assert!(delivery.completed_at.is_some());
+        // The signature is logged, the secret is not.
+        let logged = delivery.request_headers.unwrap();
+        assert!(logged.contains("x-forge-signature-256"));
+        assert!(!logged.contains("topsecret"));
+    }
+
+    #[tokio::test]
+    async fn tests_and_redeliveries_are_sent_by_hand() {
+        let pool = create_test_pool().await.unwrap();
+        let key: &'static SecretKey = Box::leak(Box::new(SecretKey::from_bytes(&[6; 32]).unwrap()));
+        let config = WebhooksConfig {
+            allow_private_targets: true,
+            max_attempts: 1,
+            ..WebhooksConfig::default()
+        };
+        let target = Arc::new(Target::default());
+        let url = serve(target.clone()).await;
+        let webhook = create_webhook(
+            &pool,
+            key,
+            &config,
+            None,
+            WebhookInput {
+                url: Some(url),
+                secret: Some("topsecret".to_string()),
+                events: Some(vec!["issue.*".to_string()]),
+                active: Some(false),
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+        // Pings reach disabled webhooks, whatever they follow
+        let ping = test_webhook(&pool, &webhook, Some("did:plc:owner"))
+            .await
+            .unwrap();
+        assert_eq!(ping.event, PING_EVENT);
+        assert!(redeliver(&pool, &ping).await.is_err());
+        let worker = DeliveryWorker::new(pool.clone(), key, config).unwrap();
+        assert_eq!(
+            worker.deliver_pending(unix_now()).await.unwrap().delivered,
+            1
+        );
+
+        let ping = fetch_delivery(&pool, &ping.id).await.unwrap().unwrap();
+        let again = redeliver(&pool, &ping).await.unwrap();
+        assert_eq!(again.redelivery_of.as_deref(), Some(ping.id.as_str()));
+        assert_eq!(again.event_id, ping.event_id);
+        assert_eq!(
+            worker.deliver_pending(unix_now()).await.unwrap().delivered,
+            1
+        );
+        {
+            let received = target.received.lock().unwrap();
+            assert_eq!(received.len(), 2);
+            assert_eq!(received[0].1, received[1].1);
+            assert_eq!(received[1].0[DELIVERY_HEADER], again.id.as_str());
+        }
+
+        // Payloads that were pruned cannot be sent again
+        sqlx::query("UPDATE webhook_deliveries SET request_body = NULL WHERE id = ?")
+            .bind(&ping.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(redeliver(&pool, &ping).await.is_err());
+
+        for _ in 2..10 {
+            test_webhook(&pool, &webhook, None).await.unwrap();
+        }
+        assert!(test_webhook(&pool, &webhook, None).await.is_err());
+    }
+
+    #[test]
+    fn only_public_addresses_are_targets() {
+        for ip in ["93.184.215.14", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn private_targets_are_refused_when_delivering() {
+        assert!(resolve_public("localhost".to_string()).await.is_err());
+
+        let pool = create_test_pool().await.unwrap();
+        let key: &'static SecretKey = Box::leak(Box::new(SecretKey::from_bytes(&[5; 32]).unwrap()));
+        let config = WebhooksConfig {
+            allow_private_targets: true,
+            max_attempts: 1,
+            ..WebhooksConfig::default()
+        };
+        let target = Arc::new(Target::default());
+        let url = serve(target.clone()).await;
+        create_webhook(
+            &pool,
+            key,
+            &config,
+            None,
+            WebhookInput {
+                url: Some(url),
+                secret: Some("topsecret".to_string()),
+                ..WebhookInput::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        let event = Event {
+            kind: "repository.created",
+            source: "core",
+            actor_did: None,
+            repository_id: Some("r1"),
+            data: serde_json::json!({}),
+        };
+        publish(&pool, None, event).await.unwrap();
+
+        // The webhook was created while private targets were allowed
+        let config = WebhooksConfig {
+            allow_private_targets: false,
+            ..config
+        };
+        let worker = DeliveryWorker::new(pool.clone(), key, config).unwrap();
+        assert_eq!(worker.deliver_pending(unix_now()).await.unwrap().failed, 1);
+        assert!(target.received.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn deliveries_fail_after_the_last_attempt() {
+        let pool = create_test_pool().await.unwrap();
+        let key: &'static SecretKey = Box::leak(Box::new(SecretKey::from_bytes(&[4; 32]).unwrap()));
+        let config = WebhooksConfig {
+            allow_private_targets: true,
+            max_attempts: 2,
+            ..WebhooksConfig::default()
+        };
+        let target = Arc::new(Target {
+            answers: Mutex::new(vec![502, 503]),
+            ..Target::default()
+        });
+        let url = serve(target.clone()).await;
+        create_webhook(
+            &pool,
+            key,
+            &config,
+            None,
+            WebhookInput {
+                url: Some(url),
+                secret: Some("topsecret".to_string()),
+                ..WebhookInput::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        let event = Event {
+            kind: "repository.created",
+            source: "core",
+            actor_did: None,
+            repository_id: Some("r1"),
+            data: serde_json::json!({}),
+        };
+        publish(&pool, None, event).await.unwrap();
+
+        let worker = DeliveryWorker::new(pool.clone(), key, config).unwrap();
+        let now = unix_now();
+        assert_eq!(worker.deliver_pending(now).await.unwrap().retrying, 1);
+        let later = now + backoff_secs(1);
+        assert_eq!(worker.deliver_pending(later).await.unwrap().failed, 1);
+        assert_eq!(
+            worker.deliver_pending(later + 3600).await.unwrap(),
+            DeliveryReport::default()
+        );
+
+        let (status, error): (String, String) =
+            sqlx::query_as("SELECT status, error FROM webhook_deliveries")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(status, "failed");
+        assert!(error.contains("503"), "{}", error);
+    }
+}
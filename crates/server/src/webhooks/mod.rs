@@ -0,0 +1,477 @@
+//! Outgoing webhooks.
+//!
+//! Repository owners register webhooks with a target URL, a secret and the event types
+//! they follow; administrators register instance webhooks, which follow every
+//! repository and events that belong to none, such as `repository.created`. When
+//! [`crate::events::publish`] is called, every matching active webhook gets a pending
+//! row in `webhook_deliveries`, inside the publisher's transaction. The supervised
+//! [`delivery::DeliveryWorker`] POSTs the event's [`Envelope`] as JSON, signed with
+//! HMAC-SHA256 over the body in `X-Forge-Signature-256`, and retries failed attempts with
+//! the job queue's backoff.
+//!
+//! Each delivery keeps the request and response of its last attempt for the delivery
+//! log, as RFC-0009 describes. The secret is stored encrypted under the repository
+//! secrets key and never appears in the log. [`test_webhook`] and [`redeliver`] queue
+//! deliveries by hand for debugging a target, a few per webhook each minute.
+
+pub mod db;
+pub mod delivery;
+pub mod models;
+
+use anyhow::{Context, Result, bail};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::SqliteConnection;
+
+use crate::config::WebhooksConfig;
+use crate::events::{Envelope, SCHEMA_VERSION, is_valid_kind};
+use crate::repository::secrets::SecretKey;
+use crate::user::db::unix_now;
+
+pub use db::{DELIVERY_CURSOR_KIND, DeliveryFilter};
+pub use models::{DeliveryStatus, WebhookDelivery, WebhookInput, WebhookRecord};
+
+pub const EVENT_HEADER: &str = "X-Forge-Event";
+pub const DELIVERY_HEADER: &str = "X-Forge-Delivery";
+pub const SIGNATURE_HEADER: &str = "X-Forge-Signature-256";
+/// The event type of test deliveries. The server never publishes it, so no filter
+/// selects it.
+pub const PING_EVENT: &str = "ping";
+
+pub const MAX_WEBHOOKS_PER_REPOSITORY: usize = 20;
+const MAX_URL_LEN: usize = 2048;
+const MAX_SECRET_BYTES: usize = 1024;
+const MAX_EVENT_FILTERS: usize = 50;
+/// Test deliveries and redeliveries allowed per webhook in each window
+const MAX_MANUAL_DELIVERIES: i64 = 10;
+const MANUAL_DELIVERY_WINDOW_SECS: i64 = 60;
+
+/// Creates a webhook for `repository_id`, or an instance webhook for `None`. New
+/// webhooks are active unless `input.active` says otherwise.
+pub async fn create_webhook(
+    pool: &sqlx::SqlitePool,
+    key: &SecretKey,
+    config: &WebhooksConfig,
+    repository_id: Option<&str>,
+    input: WebhookInput,
+    actor_did: Option<&str>,
+) -> Result<WebhookRecord> {
+    if !config.enabled {
+        bail!("webhooks are not enabled on this server");
+    }
+    let url = input.url.context("a webhook needs a url")?;
+    validate_url(&url, config.allow_private_targets)?;
+    let secret = input.secret.context("a webhook needs a secret")?;
+    validate_secret(&secret)?;
+    let events = normalize_events(input.events.unwrap_or_default())?;
+    if db::list_webhooks(pool, repository_id).await?.len() >= MAX_WEBHOOKS_PER_REPOSITORY {
+        bail!(
+            "at most {} webhooks can be registered here",
+            MAX_WEBHOOKS_PER_REPOSITORY
+        );
+    }
+
+    let id = cuid2::create_id();
+    let (nonce, ciphertext) = key.seal(&associated_data(&id), &secret)?;
+    let webhook = db::insert_webhook(
+        pool,
+        &id,
+        repository_id,
+        &url,
+        (&nonce[..], &ciphertext),
+        &events,
+        input.active.unwrap_or(true),
+        actor_did,
+        unix_now(),
+    )
+    .await?;
+    Ok(webhook)
+}
+
+/// Applies the fields set in `input` to `webhook`.
+pub async fn update_webhook(
+    pool: &sqlx::SqlitePool,
+    key: &SecretKey,
+    config: &WebhooksConfig,
+    mut webhook: WebhookRecord,
+    input: WebhookInput,
+) -> Result<WebhookRecord> {
+    if let Some(url) = input.url {
+        validate_url(&url, config.allow_private_targets)?;
+        webhook.url = url;
+    }
+    if let Some(events) = input.events {
+        webhook.events = serde_json::to_string(&normalize_events(events)?)?;
+    }
+    if let Some(active) = input.active {
+        webhook.active = active;
+    }
+    let sealed = match &input.secret {
+        Some(secret) => {
+            validate_secret(secret)?;
+            Some(key.seal(&associated_data(&webhook.id), secret)?)
+        }
+        None => None,
+    };
+    webhook.updated_at = unix_now();
+    db::update_webhook(
+        pool,
+        &webhook,
+        sealed
+            .as_ref()
+            .map(|(nonce, ciphertext)| (&nonce[..], ciphertext.as_slice())),
+    )
+    .await?;
+    Ok(webhook)
+}
+
+/// Queues a delivery of `envelope` for every active webhook that follows it.
+pub async fn queue_deliveries(
+    connection: &mut SqliteConnection,
+    envelope: &Envelope,
+) -> Result<usize> {
+    let webhooks =
+        db::webhooks_for_event(&mut *connection, envelope.repository_id.as_deref()).await?;
+    let mut queued = 0;
+    let mut body = None;
+    for webhook in webhooks {
+        if !crate::events::matches_filter(&webhook.event_filter(), &envelope.kind) {
+            continue;
+        }
+        let body = match &body {
+            Some(body) => body,
+            None => body.insert(serde_json::to_string(envelope)?),
+        };
+        db::insert_delivery(
+            &mut *connection,
+            &webhook,
+            &envelope.kind,
+            &envelope.id,
+            body,
+            None,
+            unix_now(),
+        )
+        .await?;
+        queued += 1;
+    }
+    Ok(queued)
+}
+
+/// Queues a `ping` delivery to `webhook`. It is sent whatever the event filter and even
+/// while the webhook is disabled, so a target can be checked before it is turned on.
+pub async fn test_webhook(
+    pool: &sqlx::SqlitePool,
+    webhook: &WebhookRecord,
+    actor_did: Option<&str>,
+) -> Result<WebhookDelivery> {
+    check_manual_deliveries(pool, &webhook.id).await?;
+    let envelope = Envelope {
+        version: SCHEMA_VERSION,
+        id: cuid2::create_id(),
+        kind: PING_EVENT.to_string(),
+        source: "core".to_string(),
+        time: unix_now(),
+        actor: actor_did.map(str::to_string),
+        repository_id: webhook.repository_id.clone(),
+        data: serde_json::json!({
+            "webhookId": webhook.id,
+            "events": webhook.event_filter(),
+        }),
+    };
+    let body = serde_json::to_string(&envelope)?;
+    let id = db::insert_delivery(
+        pool,
+        webhook,
+        PING_EVENT,
+        &envelope.id,
+        &body,
+        None,
+        unix_now(),
+    )
+    .await?;
+    db::fetch_delivery(pool, &id)
+        .await?
+        .context("the test delivery was not stored")
+}
+
+/// Queues the stored body of `delivery` again. It goes to the webhook's current URL and is
+/// signed with its current secret, and it keeps the event id so receivers can recognise
+/// the duplicate.
+pub async fn redeliver(
+    pool: &sqlx::SqlitePool,
+    delivery: &WebhookDelivery,
+) -> Result<WebhookDelivery> {
+    if delivery.status() == DeliveryStatus::Pending {
+        bail!("the delivery has not finished yet");
+    }
+    let body = delivery
+        .request_body
+        .as_deref()
+        .context("the delivery's payload has been cleared, so it cannot be redelivered")?;
+    let webhook = db::fetch_webhook(pool, &delivery.webhook_id)
+        .await?
+        .context("webhook not found")?;
+    check_manual_deliveries(pool, &webhook.id).await?;
+    let id = db::insert_delivery(
+        pool,
+        &webhook,
+        &delivery.event,
+        &delivery.event_id,
+        body,
+        Some(&delivery.id),
+        unix_now(),
+    )
+    .await?;
+    db::fetch_delivery(pool, &id)
+        .await?
+        .context("the redelivery was not stored")
+}
+
+/// Refuses another test delivery or redelivery once a webhook has had its share, so the
+/// mutations cannot be used to flood a target.
+async fn check_manual_deliveries(pool: &sqlx::SqlitePool, webhook_id: &str) -> Result<()> {
+    let since = unix_now() - MANUAL_DELIVERY_WINDOW_SECS;
+    let recent = db::count_manual_deliveries(pool, webhook_id, PING_EVENT, since).await?;
+    if recent >= MAX_MANUAL_DELIVERIES {
+        bail!(
+            "at most {} test deliveries and redeliveries can be sent to a webhook every {} seconds",
+            MAX_MANUAL_DELIVERIES,
+            MANUAL_DELIVERY_WINDOW_SECS
+        );
+    }
+    Ok(())
+}
+
+/// The `X-Forge-Signature-256` value for `body`: `sha256=` and the hex HMAC-SHA256.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256={}", hex)
+}
+
+/// Binds a sealed secret to its webhook.
+fn associated_data(webhook_id: &str) -> String {
+    format!("webhook\n{}", webhook_id)
+}
+
+/// Targets are HTTP(S) URLs. Unless private targets are allowed, the host must be a
+/// public name, so a webhook cannot point the server at hosts on its own network.
+fn validate_url(url: &str, allow_private_targets: bool) -> Result<()> {
+    if url.len() > MAX_URL_LEN {
+        bail!("webhook URLs are at most {} characters", MAX_URL_LEN);
+    }
+    let parsed = url::Url::parse(url).context("webhook URL is not a valid URL")?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        bail!("webhook URLs must use http or https");
+    }
+    if !parsed.username().is_empty() || parsed.password().is_some() {
+        bail!("webhook URLs cannot contain credentials; use the secret instead");
+    }
+    match parsed.host() {
+        Some(_) if allow_private_targets => Ok(()),
+        Some(url::Host::Domain(host))
+            if host != "localhost" && !host.ends_with(".localhost") && host.contains('.') =>
+        {
+            Ok(())
+        }
+        _ => bail!("webhook URLs must name a public host"),
+    }
+}
+
+fn validate_secret(secret: &str) -> Result<()> {
+    if secret.is_empty() || secret.len() > MAX_SECRET_BYTES {
+        bail!(
+            "webhook secrets must be between 1 and {} bytes",
+            MAX_SECRET_BYTES
+        );
+    }
+    Ok(())
+}
+
+/// Sorts and deduplicates event filters, which are event types or prefixes such as
+/// `issue.*`.
+fn normalize_events(mut events: Vec<String>) -> Result<Vec<String>> {
+    if events.len() > MAX_EVENT_FILTERS {
+        bail!(
+            "webhooks can follow at most {} event types",
+            MAX_EVENT_FILTERS
+        );
+    }
+    for event in &events {
+        let kind = match event.strip_suffix(".*") {
+            Some(prefix) => format!("{}.any", prefix),
+            None => event.clone(),
+        };
+        if !is_valid_kind(&kind) {
+            bail!(
+                "`{}` is not an event type such as `repository.pushed` or `issue.*`",
+                event
+            );
+        }
+    }
+    events.sort();
+    events.dedup();
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{Event, publish};
+    use crate::test_helpers::create_test_pool;
+
+    fn key() -> SecretKey {
+        SecretKey::from_bytes(&[7; 32]).unwrap()
+    }
+
+    fn input(url: &str, events: &[&str]) -> WebhookInput {
+        WebhookInput {
+            url: Some(url.to_string()),
+            secret: Some("s3cret".to_string()),
+            events: Some(events.iter().map(|event| event.to_string()).collect()),
+            active: None,
+        }
+    }
+
+    #[test]
+    fn signatures_match_the_receiver_side_check() {
+        let signature = sign("It's a Secret to Everybody", b"Hello, World!");
+        // The example from GitHub's webhook documentation.
+        assert_eq!(
+            signature,
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        );
+    }
+
+    #[test]
+    fn targets_and_filters_are_validated() {
+        assert!(validate_url("https://ci.example.com/hook", false).is_ok());
+        assert!(validate_url("http://localhost:9000/hook", false).is_err());
+        assert!(validate_url("http://10.0.0.5/hook", false).is_err());
+        assert!(validate_url("http://ci/hook", false).is_err());
+        assert!(validate_url("http://127.0.0.1:9000/hook", true).is_ok());
+        assert!(validate_url("ftp://ci.example.com/hook", true).is_err());
+        assert!(validate_url("https://user:pw@ci.example.com/hook", false).is_err());
+
+        let events = normalize_events(vec![
+            "repository.pushed".into(),
+            "issue.*".into(),
+            "issue.*".into(),
+        ])
+        .unwrap();
+        assert_eq!(events, vec!["issue.*", "repository.pushed"]);
+        assert!(normalize_events(vec!["*".into()]).is_err());
+        assert!(normalize_events(vec!["Push".into()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn published_events_are_queued_for_matching_webhooks() {
+        let pool = create_test_pool().await.unwrap();
+        sqlx::query("INSERT INTO repositories (id, slug) VALUES ('r1', 'widgets')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let config = WebhooksConfig::default();
+        let pushes = create_webhook(
+            &pool,
+            &key(),
+            &config,
+            Some("r1"),
+            input("https://ci.example.com/push", &["repository.pushed"]),
+            None,
+        )
+        .await
+        .unwrap();
+        let instance = create_webhook(
+            &pool,
+            &key(),
+            &config,
+            None,
+            input("https://audit.example.com/all", &[]),
+            None,
+        )
+        .await
+        .unwrap();
+
+        for kind in ["repository.pushed", "issue.created"] {
+            let event = Event {
+                kind,
+                source: "core",
+                actor_did: None,
+                repository_id: Some("r1"),
+                data: serde_json::json!({}),
+            };
+            publish(&pool, None, event).await.unwrap();
+        }
+        let created = Event {
+            kind: "repository.created",
+            source: "core",
+            actor_did: None,
+            repository_id: Some("r2"),
+            data: serde_json::json!({}),
+        };
+        publish(&pool, None, created).await.unwrap();
+
+        let request = crate::graphql::pagination::PageRequest::from_args(
+            DELIVERY_CURSOR_KIND,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        let filter = DeliveryFilter::default();
+        let page = db::page_deliveries(&pool, &pushes.id, &filter, &request)
+            .await
+            .unwrap();
+        let events: Vec<&str> = page.items.iter().map(|d| d.event.as_str()).collect();
+        assert_eq!(events, vec!["repository.pushed"]);
+        assert_eq!(page.items[0].status(), DeliveryStatus::Pending);
+        let body: Envelope =
+            serde_json::from_str(page.items[0].request_body.as_deref().unwrap()).unwrap();
+        assert_eq!(body.kind, "repository.pushed");
+        assert_eq!(body.id, page.items[0].event_id);
+
+        let all = db::count_deliveries(&pool, &instance.id, &filter)
+            .await
+            .unwrap();
+        assert_eq!(all, 3);
+
+        // Disabled webhooks are skipped.
+        update_webhook(
+            &pool,
+            &key(),
+            &config,
+            instance.clone(),
+            WebhookInput {
+                active: Some(false),
+                ..WebhookInput::default()
+            },
+        )
+        .await
+        .unwrap();
+        let event = Event {
+            kind: "repository.pushed",
+            source: "core",
+            actor_did: None,
+            repository_id: Some("r1"),
+            data: serde_json::json!({}),
+        };
+        publish(&pool, None, event).await.unwrap();
+        assert_eq!(
+            db::count_deliveries(&pool, &instance.id, &filter)
+                .await
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            db::count_deliveries(&pool, &pushes.id, &filter)
+                .await
+                .unwrap(),
+            2
+        );
+    }
+}
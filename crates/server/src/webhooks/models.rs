@@ -0,0 +1,102 @@
+use serde::Serialize;
+
+#[derive(Clone, Debug, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct WebhookRecord {
+    pub id: String,
+    /// `None` for an instance webhook.
+    pub repository_id: Option<String>,
+    pub url: String,
+    /// JSON array of event types and prefixes such as `issue.*`; empty follows everything.
+    pub events: String,
+    pub active: bool,
+    pub created_by: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl WebhookRecord {
+    pub fn event_filter(&self) -> Vec<String> {
+        serde_json::from_str(&self.events).unwrap_or_default()
+    }
+}
+
+/// What a caller sets when creating or editing a webhook; `None` keeps the current value.
+#[derive(Clone, Debug, Default)]
+pub struct WebhookInput {
+    pub url: Option<String>,
+    pub secret: Option<String>,
+    pub events: Option<Vec<String>>,
+    pub active: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Waiting for its first attempt or a retry.
+    Pending,
+    Succeeded,
+    /// Every attempt failed, or the webhook was disabled before it could be sent.
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "succeeded" => Ok(DeliveryStatus::Succeeded),
+            "failed" => Ok(DeliveryStatus::Failed),
+            other => Err(anyhow::anyhow!("unknown delivery status: {}", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Succeeded => "succeeded",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+
+    pub fn as_graphql(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "PENDING",
+            DeliveryStatus::Succeeded => "SUCCEEDED",
+            DeliveryStatus::Failed => "FAILED",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+    /// The event's `id`; the same for every delivery of one event.
+    pub event_id: String,
+    pub redelivery_of: Option<String>,
+    /// `pending`, `succeeded` or `failed`.
+    pub status: String,
+    /// Attempts made so far.
+    pub attempt: i64,
+    pub next_attempt_at: Option<i64>,
+    pub request_url: String,
+    /// JSON object; cleared with the body when details are pruned.
+    pub request_headers: Option<String>,
+    pub request_body: Option<String>,
+    pub response_status: Option<i64>,
+    pub response_headers: Option<String>,
+    pub response_body: Option<String>,
+    pub duration_ms: Option<i64>,
+    /// Transport error, or why the response counts as a failure.
+    pub error: Option<String>,
+    /// Response headers or body were cut to fit the limits.
+    pub truncated: bool,
+    pub created_at: i64,
+    pub completed_at: Option<i64>,
+}
+
+impl WebhookDelivery {
+    pub fn status(&self) -> DeliveryStatus {
+        DeliveryStatus::parse(&self.status).unwrap_or(DeliveryStatus::Failed)
+    }
+}
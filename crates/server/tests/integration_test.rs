@@ -1,5 +1,7 @@
 //! Integration test for WASM extensions
 
+use server::extensions::wasm_runtime::FieldRequest;
+use server::extensions::wit_bindings::{ContextScope, RepositoryContext};
 use server::extensions::{loader::ExtensionLimits, wasm_runtime, wit_bindings::RequestContext};
 use server::test_helpers;
 use std::path::Path;
@@ -161,3 +163,107 @@ async fn test_extension_error_handling() {
     let err = result.unwrap_err();
     assert!(err.to_string().contains("Unknown field"));
 }
+
+/// Compares resolving the issue references of a 200-issue list one by one with resolving
+/// them as a batch. Run with `cargo test --test integration_test -- --ignored --nocapture`
+/// after `just install-local`.
+#[tokio::test]
+#[ignore]
+async fn bench_issue_reference_batches() {
+    const ISSUES: usize = 200;
+    const RUNS: u32 = 5;
+    let wasm_path = Path::new("extensions/issues.wasm");
+
+    if !wasm_path.exists() {
+        eprintln!("Skipping benchmark - issues extension not installed");
+        return;
+    }
+
+    let temp_dir = TempDir::new().unwrap();
+    let pool = test_helpers::create_test_pool_no_migrations()
+        .await
+        .unwrap();
+    let extension = wasm_runtime::Extension::load_with_pool(
+        wasm_path,
+        temp_dir.path(),
+        "issues".to_string(),
+        pool,
+        &ExtensionLimits::default(),
+    )
+    .await
+    .expect("Failed to load extension");
+
+    let context = RequestContext {
+        scope: ContextScope::Repository,
+        repository: Some(RepositoryContext {
+            id: "repo-1".to_string(),
+            slug: "bench".to_string(),
+            group_id: None,
+            full_path: Some("bench".to_string()),
+            is_remote: false,
+            remote_url: None,
+        }),
+        ..RequestContext::default()
+    };
+    for i in 0..ISSUES {
+        extension
+            .resolve_field(
+                "createIssue".to_string(),
+                "Mutation".to_string(),
+                serde_json::json!({
+                    "repositoryId": "repo-1",
+                    "input": { "title": format!("Issue {i}") },
+                }),
+                context.clone(),
+                None,
+            )
+            .await
+            .expect("Failed to create issue");
+    }
+    let reference = |number: usize| FieldRequest {
+        field_name: "__resolveReference".to_string(),
+        parent_type: "Issue".to_string(),
+        arguments: serde_json::json!({}),
+        parent: Some(serde_json::json!({ "id": format!("repo-1:{}", number) })),
+    };
+
+    let mut single = std::time::Duration::ZERO;
+    let mut batched = std::time::Duration::ZERO;
+    for _ in 0..RUNS {
+        let started = std::time::Instant::now();
+        for number in 1..=ISSUES {
+            let request = reference(number);
+            let issue = extension
+                .resolve_field(
+                    request.field_name,
+                    request.parent_type,
+                    request.arguments,
+                    context.clone(),
+                    request.parent,
+                )
+                .await
+                .expect("Failed to resolve issue");
+            assert!(issue.is_object());
+        }
+        single += started.elapsed();
+
+        let started = std::time::Instant::now();
+        let issues = extension
+            .resolve_fields((1..=ISSUES).map(reference).collect(), context.clone())
+            .await
+            .expect("Failed to resolve issues");
+        batched += started.elapsed();
+        assert_eq!(issues.len(), ISSUES);
+        assert!(
+            issues
+                .iter()
+                .all(|issue| issue.as_ref().unwrap().is_object())
+        );
+    }
+    println!(
+        "{ISSUES} issues: one by one {:?}/run, batched {:?}/run (batch-resolver exported: {})",
+        single / RUNS,
+        batched / RUNS,
+        extension.interfaces().batch_resolver
+    );
+}
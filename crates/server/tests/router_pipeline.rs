@@ -4,7 +4,8 @@ use std::sync::Arc;
 use anyhow::Result;
 use cuid2::create_id;
 use server::extensions::ExtensionManager;
-use server::repository::RepositoryStorage;
+use server::graphql::global_id;
+use server::repository::{PathResolver, RepositoryStorage};
 use server::router::{GraphQLExecutionRequest, RouterState};
 use server::test_helpers;
 use tempfile::TempDir;
@@ -37,7 +38,12 @@ async fn setup_router_state() -> Result<RouterTestContext> {
         extensions_db_dir.path().to_path_buf(),
     );
 
-    let router = RouterState::new(pool.clone(), storage, Arc::new(extension_manager))?;
+    let router = RouterState::new(
+        pool.clone(),
+        storage,
+        PathResolver::new(pool.clone()),
+        Arc::new(extension_manager),
+    )?;
 
     Ok(RouterTestContext {
         router,
@@ -76,7 +82,7 @@ async fn query_core_data_through_router() -> Result<()> {
     assert_eq!(groups.len(), 1);
     assert_eq!(
         groups[0].get("id").and_then(|v| v.as_str()),
-        Some(group_id.as_str())
+        Some(global_id::encode("Group", &group_id).as_str())
     );
     assert_eq!(
         groups[0].get("slug").and_then(|v| v.as_str()),
@@ -86,6 +92,76 @@ async fn query_core_data_through_router() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn groups_connection_pages_through_router() -> Result<()> {
+    let ctx = setup_router_state().await?;
+
+    for slug in ["alpha", "bravo", "charlie"] {
+        sqlx::query("INSERT INTO groups (id, slug, parent) VALUES (?, ?, NULL)")
+            .bind(create_id())
+            .bind(slug)
+            .execute(&ctx.pool)
+            .await?;
+    }
+
+    let query = "query Groups($after: String) { groups(first: 2, after: $after) { totalCount edges { cursor node { slug } } pageInfo { hasNextPage endCursor } } }";
+    let page = |response: &serde_json::Value| -> (Vec<String>, bool, Option<String>, Option<i64>) {
+        let connection = response
+            .get("data")
+            .and_then(|data| data.get("groups"))
+            .expect("groups should be a connection");
+        let slugs = connection
+            .get("edges")
+            .and_then(|edges| edges.as_array())
+            .expect("edges should be an array")
+            .iter()
+            .filter_map(|edge| edge.get("node")?.get("slug")?.as_str().map(str::to_string))
+            .collect();
+        let page_info = connection.get("pageInfo").expect("pageInfo");
+        (
+            slugs,
+            page_info
+                .get("hasNextPage")
+                .and_then(|v| v.as_bool())
+                .unwrap_or_default(),
+            page_info
+                .get("endCursor")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            connection.get("totalCount").and_then(|v| v.as_i64()),
+        )
+    };
+
+    let response = ctx
+        .router
+        .execute(GraphQLExecutionRequest {
+            query: query.to_string(),
+            operation_name: Some("Groups".to_string()),
+            variables: None,
+        })
+        .await?;
+    let (slugs, has_next, end_cursor, total) = page(&response);
+    assert_eq!(slugs, ["alpha", "bravo"]);
+    assert!(has_next);
+    assert_eq!(total, Some(3));
+
+    let mut variables: HashMap<String, SonicValue> = HashMap::new();
+    variables.insert("after".to_string(), sonic_rs::to_value(&end_cursor)?);
+    let response = ctx
+        .router
+        .execute(GraphQLExecutionRequest {
+            query: query.to_string(),
+            operation_name: Some("Groups".to_string()),
+            variables: Some(variables),
+        })
+        .await?;
+    let (slugs, has_next, _, _) = page(&response);
+    assert_eq!(slugs, ["charlie"]);
+    assert!(!has_next);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn mutation_creates_group_via_router() -> Result<()> {
     let ctx = setup_router_state().await?;
@@ -158,7 +234,7 @@ async fn repository_group_field_is_projected() -> Result<()> {
 
     assert_eq!(
         repo.get("id").and_then(|v| v.as_str()),
-        Some(repo_id.as_str())
+        Some(global_id::encode("Repository", &repo_id).as_str())
     );
     let group = repo
         .get("group")
@@ -166,9 +242,452 @@ async fn repository_group_field_is_projected() -> Result<()> {
         .expect("group field should be projected");
     assert_eq!(
         group.get("id").and_then(|v| v.as_str()),
-        Some(group_id.as_str())
+        Some(global_id::encode("Group", &group_id).as_str())
     );
     assert_eq!(group.get("slug").and_then(|v| v.as_str()), Some("apps"));
 
     Ok(())
 }
+
+#[tokio::test]
+async fn node_resolves_objects_by_global_id() -> Result<()> {
+    let ctx = setup_router_state().await?;
+
+    let group_id = create_id();
+    sqlx::query("INSERT INTO groups (id, slug, parent) VALUES (?, ?, NULL)")
+        .bind(&group_id)
+        .bind("apps")
+        .execute(&ctx.pool)
+        .await?;
+    let repo_id = create_id();
+    sqlx::query(
+        "INSERT INTO repositories (id, slug, \"group\", remote_url) VALUES (?, ?, ?, NULL)",
+    )
+    .bind(&repo_id)
+    .bind("portal")
+    .bind(&group_id)
+    .execute(&ctx.pool)
+    .await?;
+
+    let node = |id: String| {
+        let router = &ctx.router;
+        async move {
+            let mut variables: HashMap<String, SonicValue> = HashMap::new();
+            variables.insert("id".to_string(), sonic_rs::to_value(&id)?);
+            let response = router
+                .execute(GraphQLExecutionRequest {
+                    query: "query Node($id: ID!) { node(id: $id) { __typename id ... on RepositoryNode { fullPath } ... on GroupNode { slug } } }".to_string(),
+                    operation_name: Some("Node".to_string()),
+                    variables: Some(variables),
+                })
+                .await?;
+            anyhow::Ok(response["data"]["node"].clone())
+        }
+    };
+
+    let repository = node(global_id::encode("Repository", &repo_id)).await?;
+    assert_eq!(repository["__typename"], "RepositoryNode");
+    assert_eq!(repository["fullPath"], "apps/portal");
+    assert_eq!(repository["id"], global_id::encode("Repository", &repo_id));
+
+    let group = node(global_id::encode("Group", &group_id)).await?;
+    assert_eq!(group["__typename"], "GroupNode");
+    assert_eq!(group["slug"], "apps");
+
+    // Legacy IDs and IDs of missing objects resolve to nothing
+    assert!(node(repo_id.clone()).await?.is_null());
+    assert!(
+        node(global_id::encode("Repository", "missing"))
+            .await?
+            .is_null()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn arguments_accept_global_and_legacy_ids() -> Result<()> {
+    let ctx = setup_router_state().await?;
+
+    let group_id = create_id();
+    sqlx::query("INSERT INTO groups (id, slug, parent) VALUES (?, ?, NULL)")
+        .bind(&group_id)
+        .bind("apps")
+        .execute(&ctx.pool)
+        .await?;
+
+    let create = |slug: &str, group: String| {
+        let router = &ctx.router;
+        let query = format!(
+            "mutation {{ createRepository(input: {{ slug: \"{}\", group: \"{}\" }}) {{ fullPath }} }}",
+            slug, group
+        );
+        async move {
+            router
+                .execute(GraphQLExecutionRequest {
+                    query,
+                    operation_name: None,
+                    variables: None,
+                })
+                .await
+        }
+    };
+
+    let response = create("global", global_id::encode("Group", &group_id)).await?;
+    assert_eq!(
+        response["data"]["createRepository"]["fullPath"],
+        "apps/global"
+    );
+    let response = create("legacy", group_id.clone()).await?;
+    assert_eq!(
+        response["data"]["createRepository"]["fullPath"],
+        "apps/legacy"
+    );
+
+    // A global ID of the wrong type is never taken for a group
+    let response = create("wrong", global_id::encode("Repository", &group_id)).await?;
+    assert!(response.get("errors").is_some(), "{response}");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn raw_responses_parse_to_the_same_values() -> Result<()> {
+    let ctx = setup_router_state().await?;
+
+    sqlx::query("INSERT INTO groups (id, slug, parent) VALUES (?, ?, NULL)")
+        .bind(create_id())
+        .bind("platform")
+        .execute(&ctx.pool)
+        .await?;
+
+    let request = || GraphQLExecutionRequest {
+        query: "query { getAllGroups { id slug } }".to_string(),
+        operation_name: None,
+        variables: None,
+    };
+    let raw = ctx.router.execute_raw(request()).await?;
+    assert!(!raw.has_errors());
+    assert_eq!(raw.to_json()?, ctx.router.execute(request()).await?);
+
+    // Admin-only fields fail for anonymous callers.
+    let refused = ctx
+        .router
+        .execute_raw(GraphQLExecutionRequest {
+            query: "query { extensionFieldStats { field } }".to_string(),
+            operation_name: None,
+            variables: None,
+        })
+        .await?;
+    assert!(refused.has_errors());
+
+    Ok(())
+}
+
+/// Inserts a public `open` repository and a private `secret` one owned by someone else,
+/// and returns the private one's id.
+async fn insert_private_repository(ctx: &RouterTestContext) -> Result<String> {
+    let mut secret_id = String::new();
+    for (slug, visibility) in [("open", "public"), ("secret", "private")] {
+        let id = create_id();
+        sqlx::query(
+            "INSERT INTO repositories (id, slug, \"group\", remote_url) VALUES (?, ?, NULL, NULL)",
+        )
+        .bind(&id)
+        .bind(slug)
+        .execute(&ctx.pool)
+        .await?;
+        sqlx::query(
+            "INSERT INTO repository_settings (repository_id, visibility, updated_at) VALUES (?, ?, 0)",
+        )
+        .bind(&id)
+        .bind(visibility)
+        .execute(&ctx.pool)
+        .await?;
+        sqlx::query("INSERT INTO repository_owners (repository_id, owner_did) VALUES (?, ?)")
+            .bind(&id)
+            .bind("did:plc:owner")
+            .execute(&ctx.pool)
+            .await?;
+        secret_id = id;
+    }
+    Ok(secret_id)
+}
+
+/// The `data` of `query` run without a viewer, which must not fail.
+async fn anonymous_data(ctx: &RouterTestContext, query: &str) -> Result<serde_json::Value> {
+    let response = ctx
+        .router
+        .execute(GraphQLExecutionRequest {
+            query: query.to_string(),
+            operation_name: None,
+            variables: None,
+        })
+        .await?;
+    assert!(response.get("errors").is_none(), "{query}: {response}");
+    Ok(response["data"].clone())
+}
+
+#[tokio::test]
+async fn get_all_repositories_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    let data = anonymous_data(&ctx, "query { getAllRepositories { slug } }").await?;
+    assert_eq!(
+        data["getAllRepositories"],
+        serde_json::json!([{ "slug": "open" }])
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn get_repository_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    let data = anonymous_data(&ctx, r#"query { getRepository(path: "secret") { slug } }"#).await?;
+    assert!(data["getRepository"].is_null());
+    let data = anonymous_data(&ctx, r#"query { getRepository(path: "open") { slug } }"#).await?;
+    assert_eq!(data["getRepository"]["slug"], "open");
+    Ok(())
+}
+
+#[tokio::test]
+async fn repositories_connection_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    let data = anonymous_data(
+        &ctx,
+        "query { repositories(first: 10) { totalCount nodes { slug } } }",
+    )
+    .await?;
+    assert_eq!(data["repositories"]["totalCount"], 1);
+    assert_eq!(
+        data["repositories"]["nodes"],
+        serde_json::json!([{ "slug": "open" }])
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn node_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    let secret_id = insert_private_repository(&ctx).await?;
+    let id = global_id::encode("Repository", &secret_id);
+    let data = anonymous_data(&ctx, &format!(r#"query {{ node(id: "{id}") {{ id }} }}"#)).await?;
+    assert!(data["node"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn browse_repository_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    let data = anonymous_data(
+        &ctx,
+        r#"query { browseRepository(path: "secret") { __typename } }"#,
+    )
+    .await?;
+    assert!(data["browseRepository"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn list_repository_branches_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    let data = anonymous_data(
+        &ctx,
+        r#"query { listRepositoryBranches(path: "secret") { __typename } }"#,
+    )
+    .await?;
+    assert!(data["listRepositoryBranches"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn merge_preview_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    let data = anonymous_data(
+        &ctx,
+        r#"query { mergePreview(path: "secret", base: "main", head: "topic") { __typename } }"#,
+    )
+    .await?;
+    assert!(data["mergePreview"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn find_files_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    let data = anonymous_data(
+        &ctx,
+        r#"query { findFiles(path: "secret", query: "main") { __typename } }"#,
+    )
+    .await?;
+    assert!(data["findFiles"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn repository_projects_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    let data = anonymous_data(
+        &ctx,
+        r#"query { repositoryProjects(path: "secret") { __typename } }"#,
+    )
+    .await?;
+    assert!(data["repositoryProjects"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn detect_project_metadata_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    let data = anonymous_data(
+        &ctx,
+        r#"query { detectProjectMetadata(path: "secret") { __typename } }"#,
+    )
+    .await?;
+    assert!(data["detectProjectMetadata"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn commit_graph_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    let data = anonymous_data(
+        &ctx,
+        r#"query { commitGraph(path: "secret") { __typename } }"#,
+    )
+    .await?;
+    assert!(data["commitGraph"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_repository_file_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    let data = anonymous_data(
+        &ctx,
+        r#"query { readRepositoryFile(path: "secret", filePath: "README.md") { __typename } }"#,
+    )
+    .await?;
+    assert!(data["readRepositoryFile"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn blame_repository_file_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    let data = anonymous_data(
+        &ctx,
+        r#"query { blameRepositoryFile(path: "secret", filePath: "README.md") { __typename } }"#,
+    )
+    .await?;
+    assert!(data["blameRepositoryFile"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn user_profile_hides_private_repositories() -> Result<()> {
+    let ctx = setup_router_state().await?;
+    insert_private_repository(&ctx).await?;
+    sqlx::query(
+        "INSERT INTO users (did, handle, created_at, updated_at) \
+         VALUES ('did:plc:owner', 'owner.test', 0, 0)",
+    )
+    .execute(&ctx.pool)
+    .await?;
+    for (event_id, slug) in [("star-open", "open"), ("star-secret", "secret")] {
+        sqlx::query(
+            "INSERT INTO repository_stars (user_did, repository_id, starred_at) \
+             SELECT 'did:plc:owner', id, 0 FROM repositories WHERE slug = ?",
+        )
+        .bind(slug)
+        .execute(&ctx.pool)
+        .await?;
+        sqlx::query(
+            "INSERT INTO activity_events (id, actor_did, repository_id, kind, summary, created_at) \
+             SELECT ?, 'did:plc:owner', id, 'star', ?, 0 FROM repositories WHERE slug = ?",
+        )
+        .bind(event_id)
+        .bind(format!("starred {slug}"))
+        .bind(slug)
+        .execute(&ctx.pool)
+        .await?;
+    }
+
+    let data = anonymous_data(
+        &ctx,
+        r#"query { user(handle: "owner.test") {
+            repositories { slug }
+            starredRepositories { slug }
+            recentActivity { summary }
+        } }"#,
+    )
+    .await?;
+    let open = serde_json::json!([{ "slug": "open" }]);
+    assert_eq!(data["user"]["repositories"], open);
+    assert_eq!(data["user"]["starredRepositories"], open);
+    assert_eq!(
+        data["user"]["recentActivity"],
+        serde_json::json!([{ "summary": "starred open" }])
+    );
+    Ok(())
+}
+
+/// Compares parsing and re-serializing a 10k-row response with passing on the executor's
+/// bytes. Run with `cargo test --test router_pipeline -- --ignored --nocapture`.
+#[tokio::test]
+#[ignore]
+async fn bench_large_list_response() -> Result<()> {
+    const ROWS: usize = 10_000;
+    const RUNS: u32 = 5;
+    let ctx = setup_router_state().await?;
+
+    let mut tx = ctx.pool.begin().await?;
+    for i in 0..ROWS {
+        sqlx::query("INSERT INTO groups (id, slug, parent) VALUES (?, ?, NULL)")
+            .bind(create_id())
+            .bind(format!("group-{i:05}"))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    let request = || GraphQLExecutionRequest {
+        query: "query { getAllGroups { id slug } }".to_string(),
+        operation_name: None,
+        variables: None,
+    };
+
+    let mut parsed = std::time::Duration::ZERO;
+    let mut raw = std::time::Duration::ZERO;
+    for _ in 0..RUNS {
+        let started = std::time::Instant::now();
+        let json = ctx.router.execute(request()).await?;
+        let body = serde_json::to_vec(&json)?;
+        parsed += started.elapsed();
+
+        let started = std::time::Instant::now();
+        let response = ctx.router.execute_raw(request()).await?;
+        assert!(!response.has_errors());
+        let bytes = response.into_bytes();
+        raw += started.elapsed();
+        assert_eq!(bytes.len(), body.len());
+    }
+    println!(
+        "{ROWS} rows: parsed {:?}/run, raw {:?}/run",
+        parsed / RUNS,
+        raw / RUNS
+    );
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+//! Issue comments.
+//!
+//! A comment belongs to one issue and is listed oldest first. Bodies are Markdown; the
+//! host renders `bodyHtml` on every read, so stored comments pick up changes to its
+//! sanitizer. Only a comment's author can edit or delete it.
+
+use serde_json::{Value, json};
+
+pub const MAX_COMMENT_BODY: usize = 65_536;
+pub const DEFAULT_PAGE_SIZE: i64 = 30;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub id: String,
+    pub repository_id: String,
+    pub issue_number: i64,
+    pub author_did: Option<String>,
+    pub body: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Comment {
+    /// The stored comment; the API adds `bodyHtml`.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "repositoryId": self.repository_id,
+            "issueNumber": self.issue_number,
+            "authorDid": self.author_did,
+            "body": self.body,
+            "createdAt": self.created_at,
+            "updatedAt": self.updated_at,
+        })
+    }
+
+    /// Refuses anyone but the author; `action` names what they tried, as in "edit".
+    pub fn require_author(&self, viewer: Option<&str>, action: &str) -> Result<(), String> {
+        if viewer.is_none() || self.author_did.as_deref() != viewer {
+            return Err(format!("Only the author can {} a comment", action));
+        }
+        Ok(())
+    }
+
+    /// Where the next page starts when this is the last comment of a page.
+    pub fn cursor(&self) -> String {
+        format!("{}|{}", self.created_at, self.id)
+    }
+}
+
+/// Rejects empty and oversized bodies. Surrounding whitespace is dropped.
+pub fn validate_body(body: &str) -> Result<String, String> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err("Comments cannot be empty".to_string());
+    }
+    if body.len() > MAX_COMMENT_BODY {
+        return Err(format!(
+            "Comments are limited to {} bytes",
+            MAX_COMMENT_BODY
+        ));
+    }
+    Ok(body.to_string())
+}
+
+/// `first` as a page size: [`DEFAULT_PAGE_SIZE`] when omitted, at most
+/// [`MAX_PAGE_SIZE`].
+pub fn page_size(first: Option<i64>) -> Result<i64, String> {
+    match first {
+        None => Ok(DEFAULT_PAGE_SIZE),
+        Some(first) if first < 0 => Err("`first` cannot be negative".to_string()),
+        Some(first) => Ok(first.min(MAX_PAGE_SIZE)),
+    }
+}
+
+/// The creation time and id in a cursor from [`Comment::cursor`].
+pub fn parse_cursor(cursor: &str) -> Result<(String, String), String> {
+    match cursor.split_once('|') {
+        Some((created_at, id)) if !created_at.is_empty() && !id.is_empty() => {
+            Ok((created_at.to_string(), id.to_string()))
+        }
+        _ => Err(format!("Invalid cursor `{}`", cursor)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_are_trimmed_and_bounded() {
+        assert_eq!(validate_body("  LGTM\n").unwrap(), "LGTM");
+        assert!(validate_body(" \n ").is_err());
+        assert!(validate_body(&"x".repeat(MAX_COMMENT_BODY + 1)).is_err());
+    }
+
+    #[test]
+    fn pages_are_bounded() {
+        assert_eq!(page_size(None), Ok(DEFAULT_PAGE_SIZE));
+        assert_eq!(page_size(Some(0)), Ok(0));
+        assert_eq!(page_size(Some(1_000)), Ok(MAX_PAGE_SIZE));
+        assert!(page_size(Some(-1)).is_err());
+    }
+
+    #[test]
+    fn only_the_author_may_change_a_comment() {
+        let comment = Comment {
+            id: "comment_1".into(),
+            repository_id: "r1".into(),
+            issue_number: 3,
+            author_did: Some("did:plc:author".into()),
+            body: "Hi".into(),
+            created_at: "2024-07-01T10:00:00+00:00".into(),
+            updated_at: "2024-07-01T10:00:00+00:00".into(),
+        };
+        assert!(
+            comment
+                .require_author(Some("did:plc:author"), "delete")
+                .is_ok()
+        );
+        assert_eq!(
+            comment.require_author(Some("did:plc:other"), "delete"),
+            Err("Only the author can delete a comment".to_string())
+        );
+        assert!(comment.require_author(None, "delete").is_err());
+
+        let anonymous = Comment {
+            author_did: None,
+            ..comment
+        };
+        assert!(anonymous.require_author(None, "edit").is_err());
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let comment = Comment {
+            id: "comment_1".into(),
+            repository_id: "r1".into(),
+            issue_number: 3,
+            author_did: None,
+            body: "Hi".into(),
+            created_at: "2024-07-01T10:00:00+00:00".into(),
+            updated_at: "2024-07-01T10:00:00+00:00".into(),
+        };
+        assert_eq!(
+            parse_cursor(&comment.cursor()).unwrap(),
+            (comment.created_at.clone(), comment.id.clone())
+        );
+        assert!(parse_cursor("comment_1").is_err());
+    }
+}
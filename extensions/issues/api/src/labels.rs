@@ -0,0 +1,197 @@
+//! Issue labels and milestones.
+//!
+//! Both belong to a repository. An issue carries any number of its repository's labels
+//! and at most one of its milestones. Label names and milestone titles are unique per
+//! repository, ignoring case.
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Deserializer};
+use serde_json::{Value, json};
+
+pub const MAX_LABEL_NAME: usize = 50;
+pub const MAX_MILESTONE_TITLE: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub id: String,
+    pub name: String,
+    /// `#rrggbb`, lowercase.
+    pub color: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Milestone {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// `YYYY-MM-DD`.
+    pub due_on: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LabelInput {
+    pub name: String,
+    pub color: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MilestoneInput {
+    pub title: String,
+    pub description: Option<String>,
+    pub due_on: Option<String>,
+}
+
+impl Label {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "color": self.color,
+            "description": self.description,
+        })
+    }
+}
+
+impl Milestone {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "title": self.title,
+            "description": self.description,
+            "dueOn": self.due_on,
+            "createdAt": self.created_at,
+        })
+    }
+}
+
+/// Tells an explicit GraphQL `null` (`Some(None)`) apart from an omitted input field
+/// (`None`); use with `#[serde(default)]`.
+pub fn explicit_null<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(Some(Option::<T>::deserialize(deserializer)?))
+}
+
+/// Trims the input and checks the name and colour. `id` is left empty.
+pub fn validate_label(input: LabelInput) -> Result<Label, String> {
+    let name = input.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_LABEL_NAME {
+        return Err(format!(
+            "Label names must be 1 to {} characters",
+            MAX_LABEL_NAME
+        ));
+    }
+    let color = input.color.trim().to_ascii_lowercase();
+    let hex = color.strip_prefix('#').unwrap_or_default();
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Label `{}` needs a colour like #d73a4a", name));
+    }
+    Ok(Label {
+        id: String::new(),
+        name,
+        color,
+        description: non_empty(input.description),
+    })
+}
+
+/// Trims the input and checks the title and due date. `id` and `created_at` are left
+/// empty.
+pub fn validate_milestone(input: MilestoneInput) -> Result<Milestone, String> {
+    let title = input.title.trim().to_string();
+    if title.is_empty() || title.chars().count() > MAX_MILESTONE_TITLE {
+        return Err(format!(
+            "Milestone titles must be 1 to {} characters",
+            MAX_MILESTONE_TITLE
+        ));
+    }
+    let due_on = match non_empty(input.due_on) {
+        Some(due_on) => match NaiveDate::parse_from_str(&due_on, "%Y-%m-%d") {
+            Ok(date) => Some(date.format("%Y-%m-%d").to_string()),
+            Err(_) => {
+                return Err(format!(
+                    "Milestone due date `{}` must look like 2024-06-30",
+                    due_on
+                ));
+            }
+        },
+        None => None,
+    };
+    Ok(Milestone {
+        id: String::new(),
+        title,
+        description: non_empty(input.description),
+        due_on,
+        created_at: String::new(),
+    })
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(name: &str, color: &str) -> LabelInput {
+        LabelInput {
+            name: name.into(),
+            color: color.into(),
+            description: Some("  ".into()),
+        }
+    }
+
+    #[test]
+    fn labels_are_normalized() {
+        let label = validate_label(input(" bug ", "#D73A4A")).unwrap();
+        assert_eq!(label.name, "bug");
+        assert_eq!(label.color, "#d73a4a");
+        assert_eq!(label.description, None);
+
+        assert!(validate_label(input("", "#d73a4a")).is_err());
+        assert!(validate_label(input("bug", "d73a4a")).is_err());
+        assert!(validate_label(input("bug", "#d73a4")).is_err());
+        assert!(validate_label(input(&"x".repeat(51), "#d73a4a")).is_err());
+    }
+
+    #[test]
+    fn milestone_due_dates_are_checked() {
+        let milestone = |due_on: Option<&str>| {
+            validate_milestone(MilestoneInput {
+                title: " v1.0 ".into(),
+                description: None,
+                due_on: due_on.map(str::to_string),
+            })
+        };
+        let v1 = milestone(Some("2024-06-30")).unwrap();
+        assert_eq!(v1.title, "v1.0");
+        assert_eq!(v1.due_on.as_deref(), Some("2024-06-30"));
+        assert_eq!(milestone(Some("")).unwrap().due_on, None);
+        assert!(milestone(Some("2024-02-30")).is_err());
+        assert!(milestone(Some("30/06/2024")).is_err());
+    }
+
+    #[test]
+    fn explicit_null_differs_from_a_missing_field() {
+        #[derive(Deserialize)]
+        struct Input {
+            #[serde(default, deserialize_with = "explicit_null")]
+            milestone_id: Option<Option<String>>,
+        }
+        let parse = |json: Value| serde_json::from_value::<Input>(json).unwrap().milestone_id;
+        assert_eq!(parse(json!({})), None);
+        assert_eq!(parse(json!({ "milestone_id": null })), Some(None));
+        assert_eq!(
+            parse(json!({ "milestone_id": "m" })),
+            Some(Some("m".to_string()))
+        );
+    }
+}
@@ -4,20 +4,50 @@
 
 #![allow(unsafe_op_in_unsafe_fn)]
 
+mod comments;
+mod labels;
+mod templates;
+
 use serde::Deserialize;
 use serde_json::json;
 use std::collections::HashMap;
 
+// A GraphQL extension that also keeps its data in step with repository changes and
+// looks up the issues in a list all at once.
 wit_bindgen::generate!({
-    world: "extension",
+    inline: r#"
+        package forgepoint:issues;
+
+        world issues {
+            include forge:extension/extension@0.3.0;
+            export forge:extension/batch-resolver@0.3.0;
+            export forge:extension/git-events@0.3.0;
+        }
+    "#,
     path: "../../../packages/wit/extension.wit",
+    world: "forgepoint:issues/issues",
 });
 
-use exports::forge::extension::extension_api::{
-    Config, ContextScope, ExtensionInfo, Guest, ResolveInfo, ResolveResult,
+use comments::{Comment, page_size, parse_cursor, validate_body};
+use exports::forge::extension::batch_resolver::Guest as BatchResolverGuest;
+use exports::forge::extension::extension_api::{Config, ExtensionInfo, Guest};
+use exports::forge::extension::git_events::{
+    Guest as GitEventsGuest, LifecycleEvent, LifecycleKind,
+};
+use exports::forge::extension::resolver::{
+    ContextScope, Guest as ResolverGuest, ResolveInfo, ResolveResult,
 };
+use forge::extension::host_compliance;
 use forge::extension::host_database::{self, RecordValue};
+use forge::extension::host_events;
 use forge::extension::host_log::{self, LogLevel};
+use forge::extension::host_markdown;
+use forge::extension::host_repositories;
+use labels::{Label, LabelInput, Milestone, MilestoneInput, validate_label, validate_milestone};
+use templates::{
+    CustomFieldInput, CustomFieldValue, IssueTemplate, TemplateField, validate_custom_fields,
+    validate_template_fields,
+};
 
 const SCHEMA: &str = include_str!("../../shared/schema.graphql");
 
@@ -30,19 +60,56 @@ struct Issue {
     description: Option<String>,
     status: String,
     created_at: String,
+    template_id: Option<String>,
+    custom_fields: Vec<CustomFieldValue>,
+    version: i64,
+    updated_at: String,
+    milestone_id: Option<String>,
+    labels: Vec<Label>,
+    milestone: Option<Milestone>,
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct CreateIssueInput {
     title: String,
     description: Option<String>,
+    template_id: Option<String>,
+    #[serde(default, deserialize_with = "templates::null_as_default")]
+    custom_fields: Vec<CustomFieldInput>,
+}
+
+#[derive(Deserialize)]
+struct IssueTemplateInput {
+    name: String,
+    body: Option<String>,
+    fields: Vec<TemplateField>,
 }
 
+/// Most issues a group or instance roll-up returns.
+const ROLLUP_LIMIT: usize = 500;
+
+/// An issue's repository id and number.
+type IssueKey = (String, i64);
+
+const COMMENT_COLUMNS: &str =
+    "id, repository_id, issue_number, author_did, body, created_at, updated_at";
+
+const ISSUE_COLUMNS: &str = "id, repository_id, number, title, description, status, created_at, \
+     template_id, custom_fields, version, COALESCE(updated_at, created_at), milestone_id";
+
 #[derive(Deserialize)]
 struct UpdateIssueInput {
     title: Option<String>,
     description: Option<String>,
     status: Option<String>,
+    /// `null` removes the issue from its milestone.
+    #[serde(
+        rename = "milestoneId",
+        default,
+        deserialize_with = "labels::explicit_null"
+    )]
+    milestone_id: Option<Option<String>>,
 }
 
 struct IssuesExtension;
@@ -61,6 +128,67 @@ impl Guest for IssuesExtension {
 
             CREATE INDEX IF NOT EXISTS idx_issues_status ON issues(status);
             CREATE INDEX IF NOT EXISTS idx_issues_created_at ON issues(created_at);
+
+            CREATE TABLE IF NOT EXISTS issue_templates (
+                id TEXT PRIMARY KEY,
+                repository_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                body TEXT,
+                fields TEXT NOT NULL DEFAULT '[]',
+                UNIQUE (repository_id, name)
+            );
+
+            CREATE TABLE IF NOT EXISTS issue_events (
+                id TEXT PRIMARY KEY,
+                repository_id TEXT NOT NULL,
+                number INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                commit_sha TEXT,
+                commit_summary TEXT,
+                commit_repository_id TEXT,
+                commit_repository_path TEXT,
+                created_at TEXT NOT NULL,
+                UNIQUE (repository_id, number, kind, commit_sha)
+            );
+
+            CREATE TABLE IF NOT EXISTS issue_labels (
+                id TEXT PRIMARY KEY,
+                repository_id TEXT NOT NULL,
+                name TEXT NOT NULL COLLATE NOCASE,
+                color TEXT NOT NULL,
+                description TEXT,
+                UNIQUE (repository_id, name)
+            );
+
+            CREATE TABLE IF NOT EXISTS issue_label_assignments (
+                issue_id TEXT NOT NULL,
+                label_id TEXT NOT NULL,
+                PRIMARY KEY (issue_id, label_id)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_issue_label_assignments_label ON issue_label_assignments(label_id);
+
+            CREATE TABLE IF NOT EXISTS issue_milestones (
+                id TEXT PRIMARY KEY,
+                repository_id TEXT NOT NULL,
+                title TEXT NOT NULL COLLATE NOCASE,
+                description TEXT,
+                due_on TEXT,
+                created_at TEXT NOT NULL,
+                UNIQUE (repository_id, title)
+            );
+
+            CREATE TABLE IF NOT EXISTS issue_comments (
+                id TEXT PRIMARY KEY,
+                repository_id TEXT NOT NULL,
+                issue_number INTEGER NOT NULL,
+                author_did TEXT,
+                body TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_issue_comments_issue ON issue_comments(repository_id, issue_number, created_at, id);
         "#;
 
         match host_database::migrate(migrations) {
@@ -72,14 +200,34 @@ impl Guest for IssuesExtension {
         }
     }
 
+    fn upgrade(from_version: String) -> Result<(), String> {
+        // `init` already brings any earlier schema up to date.
+        host_log::log(
+            LogLevel::Info,
+            &format!("Issues extension upgraded from {}", from_version),
+        );
+        Ok(())
+    }
+
     fn get_info() -> ExtensionInfo {
         ExtensionInfo {
             name: "issues".to_string(),
             version: "0.2.0".to_string(),
-            capabilities: vec!["basic".to_string(), "database".to_string()],
+            capabilities: vec![
+                "basic".to_string(),
+                "database".to_string(),
+                "account-export".to_string(),
+                "issue-closing".to_string(),
+            ],
         }
     }
 
+    fn shutdown() {
+        host_log::log(LogLevel::Info, "Issues extension shutting down");
+    }
+}
+
+impl ResolverGuest for IssuesExtension {
     fn get_schema() -> String {
         SCHEMA.trim().to_string()
     }
@@ -87,43 +235,1326 @@ impl Guest for IssuesExtension {
     fn resolve_field(info: ResolveInfo) -> ResolveResult {
         let ResolveInfo {
             field_name,
+            parent_type,
             arguments,
             context,
-            ..
+            parent,
         } = info;
 
         let scope = context.scope;
+        let user_context_id = context.user.map(|ctx| ctx.id);
         let repository_context_id = context.repository.map(|ctx| ctx.id);
+        let group_context_id = context.group.map(|ctx| ctx.id);
+
+        if matches!(
+            field_name.as_str(),
+            "getIssuesForRepository"
+                | "getIssue"
+                | "getIssueTimeline"
+                | "createIssue"
+                | "updateIssue"
+                | "deleteIssue"
+                | "getIssueTemplates"
+                | "saveIssueTemplate"
+                | "deleteIssueTemplate"
+                | "getIssueLabels"
+                | "getIssueMilestones"
+                | "createLabel"
+                | "assignLabel"
+                | "unassignLabel"
+                | "createMilestone"
+                | "comments"
+                | "addComment"
+                | "editComment"
+                | "deleteComment"
+                | "closeIssuesFromCommits"
+        ) && !matches!(
+            scope,
+            ContextScope::Repository | ContextScope::RepositoryUser
+        ) {
+            return ResolveResult::Error(
+                "Repository-scoped context required for issues operations".to_string(),
+            );
+        }
+        if field_name == "issuesForGroup"
+            && !matches!(scope, ContextScope::Group | ContextScope::GroupUser)
+        {
+            return ResolveResult::Error(
+                "Group-scoped context required for group issue queries".to_string(),
+            );
+        }
+        if field_name == "accountExport" && !matches!(scope, ContextScope::User) {
+            return ResolveResult::Error(
+                "User-scoped context required for account exports".to_string(),
+            );
+        }
+
+        match field_name.as_str() {
+            "getIssuesForRepository" => {
+                resolve_get_issues_for_repository(&arguments, repository_context_id.as_deref())
+            }
+            "issuesForGroup" => resolve_issue_rollup(&arguments, group_context_id.as_deref()),
+            "allIssues" => resolve_issue_rollup(&arguments, None),
+            "getIssue" => resolve_get_issue(&arguments, repository_context_id.as_deref()),
+            "getIssueTimeline" => {
+                resolve_get_issue_timeline(&arguments, repository_context_id.as_deref())
+            }
+            "createIssue" => resolve_create_issue(
+                &arguments,
+                repository_context_id.as_deref(),
+                user_context_id.as_deref(),
+            ),
+            "updateIssue" => resolve_update_issue(&arguments, repository_context_id.as_deref()),
+            "deleteIssue" => resolve_delete_issue(&arguments, repository_context_id.as_deref()),
+            "getIssueTemplates" => {
+                resolve_get_issue_templates(&arguments, repository_context_id.as_deref())
+            }
+            "saveIssueTemplate" => {
+                resolve_save_issue_template(&arguments, repository_context_id.as_deref())
+            }
+            "deleteIssueTemplate" => {
+                resolve_delete_issue_template(&arguments, repository_context_id.as_deref())
+            }
+            "getIssueLabels" => {
+                resolve_get_issue_labels(&arguments, repository_context_id.as_deref())
+            }
+            "getIssueMilestones" => {
+                resolve_get_issue_milestones(&arguments, repository_context_id.as_deref())
+            }
+            "createLabel" => resolve_create_label(&arguments, repository_context_id.as_deref()),
+            "assignLabel" => {
+                resolve_label_assignment(&arguments, repository_context_id.as_deref(), true)
+            }
+            "unassignLabel" => {
+                resolve_label_assignment(&arguments, repository_context_id.as_deref(), false)
+            }
+            "createMilestone" => {
+                resolve_create_milestone(&arguments, repository_context_id.as_deref())
+            }
+            "accountExport" => resolve_account_export(&arguments, user_context_id.as_deref()),
+            "closeIssuesFromCommits" => {
+                resolve_close_issues_from_commits(&arguments, repository_context_id.as_deref())
+            }
+            "comments" if parent_type == "Issue" => resolve_issue_comments(
+                &arguments,
+                parent.as_deref(),
+                repository_context_id.as_deref(),
+            ),
+            "addComment" => resolve_add_comment(
+                &arguments,
+                repository_context_id.as_deref(),
+                user_context_id.as_deref(),
+            ),
+            "editComment" => resolve_edit_comment(
+                &arguments,
+                repository_context_id.as_deref(),
+                user_context_id.as_deref(),
+            ),
+            "deleteComment" => resolve_delete_comment(
+                &arguments,
+                repository_context_id.as_deref(),
+                user_context_id.as_deref(),
+            ),
+            "__resolveReference" if parent_type == "Issue" => {
+                resolve_issue_reference(parent.as_deref())
+            }
+            _ => ResolveResult::Error(format!("Unknown field: {}", field_name)),
+        }
+    }
+}
+
+impl BatchResolverGuest for IssuesExtension {
+    /// Looks up every issue reference in the batch with one query, for lists of issues
+    /// fetched through `node(id:)` or the router; anything else is resolved one at a time.
+    fn resolve_fields(batch: Vec<ResolveInfo>) -> Vec<ResolveResult> {
+        let references: Vec<Option<Result<Option<IssueKey>, String>>> = batch
+            .iter()
+            .map(|info| {
+                (info.field_name == "__resolveReference" && info.parent_type == "Issue")
+                    .then(|| parse_issue_reference(info.parent.as_deref()))
+            })
+            .collect();
+        let keys: Vec<IssueKey> = references
+            .iter()
+            .filter_map(|reference| reference.clone()?.ok()?)
+            .collect();
+        let issues = query_issues_by_reference(&keys);
+
+        batch
+            .into_iter()
+            .zip(references)
+            .map(|(info, reference)| match reference {
+                None => <Self as ResolverGuest>::resolve_field(info),
+                Some(Err(err)) => ResolveResult::Error(err),
+                Some(Ok(None)) => ResolveResult::Success("null".to_string()),
+                Some(Ok(Some(key))) => match &issues {
+                    Ok(issues) => match issues.get(&key) {
+                        Some(issue) => serialize_issue(issue.clone()),
+                        None => ResolveResult::Success("null".to_string()),
+                    },
+                    Err(err) => ResolveResult::Error(err.clone()),
+                },
+            })
+            .collect()
+    }
+}
+
+impl GitEventsGuest for IssuesExtension {
+    fn handle_lifecycle_event(event: LifecycleEvent) -> Result<(), String> {
+        match event.kind {
+            LifecycleKind::Deleted => {
+                delete_repository_issues(&event.repository_id, event.old_path.as_deref())
+            }
+            // Normally gone already, unless every delivery of the deletion failed.
+            LifecycleKind::Purged => delete_repository_issues(&event.repository_id, None),
+            LifecycleKind::Renamed | LifecycleKind::Transferred => match event.new_path {
+                Some(path) => rename_commit_repository(&event.repository_id, &path),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+/// Removes the issues, templates, timelines, labels and milestones of a deleted
+/// repository. With `path`, the removal is archived first; nothing is archived when there
+/// is nothing left to remove, so a repeated delivery is harmless.
+fn delete_repository_issues(repository_id: &str, path: Option<&str>) -> Result<(), String> {
+    let sql = "SELECT (SELECT COUNT(*) FROM issues WHERE repository_id = ?), \
+               (SELECT COUNT(*) FROM issue_templates WHERE repository_id = ?), \
+               (SELECT COUNT(*) FROM issue_comments WHERE repository_id = ?)";
+    let params = vec![RecordValue::Text(repository_id.to_string()); 3];
+    let (issues, templates, comments) = match host_database::query(sql, &params) {
+        host_database::QueryResult::Success(rows) => {
+            let values = rows.first().map(|row| row.values.as_slice()).unwrap_or(&[]);
+            (
+                values.first().map(extract_integer).unwrap_or(0),
+                values.get(1).map(extract_integer).unwrap_or(0),
+                values.get(2).map(extract_integer).unwrap_or(0),
+            )
+        }
+        host_database::QueryResult::Error(e) => return Err(format!("Database error: {}", e)),
+    };
+
+    if let Some(path) = path
+        && issues + templates + comments > 0
+    {
+        let summary = json!({
+            "path": path,
+            "issues": issues,
+            "templates": templates,
+            "comments": comments,
+        });
+        host_compliance::archive_deletion("repository-issues", repository_id, &summary.to_string())
+            .map_err(|err| format!("Failed to archive issues: {}", err))?;
+    }
+
+    let params = vec![RecordValue::Text(repository_id.to_string())];
+    for sql in [
+        "DELETE FROM issue_events WHERE repository_id = ?",
+        "DELETE FROM issue_comments WHERE repository_id = ?",
+        "DELETE FROM issue_label_assignments WHERE issue_id IN (SELECT id FROM issues WHERE repository_id = ?)",
+        "DELETE FROM issues WHERE repository_id = ?",
+        "DELETE FROM issue_templates WHERE repository_id = ?",
+        "DELETE FROM issue_labels WHERE repository_id = ?",
+        "DELETE FROM issue_milestones WHERE repository_id = ?",
+    ] {
+        if let host_database::ExecResult::Error(e) = host_database::execute(sql, &params) {
+            return Err(format!("Database error: {}", e));
+        }
+    }
+    if issues > 0 {
+        host_log::log(
+            LogLevel::Info,
+            &format!(
+                "Removed {} issues of deleted repository {}",
+                issues, repository_id
+            ),
+        );
+    }
+    Ok(())
+}
+
+/// Points "closed by commit" events at the repository's new path.
+fn rename_commit_repository(repository_id: &str, path: &str) -> Result<(), String> {
+    let sql = "UPDATE issue_events SET commit_repository_path = ? WHERE commit_repository_id = ?";
+    let params = vec![
+        RecordValue::Text(path.to_string()),
+        RecordValue::Text(repository_id.to_string()),
+    ];
+    match host_database::execute(sql, &params) {
+        host_database::ExecResult::Success(_) => Ok(()),
+        host_database::ExecResult::Error(e) => Err(format!("Database error: {}", e)),
+    }
+}
+
+fn resolve_get_issues_for_repository(
+    arguments: &str,
+    context_repository: Option<&str>,
+) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        #[serde(rename = "repositoryId")]
+        repository_id: String,
+        #[serde(default)]
+        filter: Option<String>,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+
+    let filter = match search_filter::parse(args.filter.as_deref().unwrap_or_default())
+        .and_then(|filter| filter.to_sql(&search_filter::issues::SCHEMA))
+    {
+        Ok(filter) => filter,
+        Err(e) => return ResolveResult::Error(format!("Invalid filter: {}", e)),
+    };
+
+    let sql = format!(
+        "SELECT {} FROM issues WHERE repository_id = ? AND {} ORDER BY {}",
+        ISSUE_COLUMNS, filter.where_clause, filter.order_by
+    );
+    let mut params = vec![RecordValue::Text(args.repository_id.clone())];
+    params.extend(filter.params.into_iter().map(filter_param));
+
+    match host_database::query(&sql, &params) {
+        host_database::QueryResult::Success(rows) => {
+            let issues: Vec<Issue> = rows
+                .into_iter()
+                .map(|row| issue_from_values(&row.values))
+                .collect();
+            match load_issue_relations(issues) {
+                Ok(issues) => serialize_issues(issues),
+                Err(err) => ResolveResult::Error(err),
+            }
+        }
+        host_database::QueryResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
+    }
+}
+
+/// Issues across the repositories the viewer can see, within a group or the instance.
+fn resolve_issue_rollup(arguments: &str, group_id: Option<&str>) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        #[serde(default)]
+        filter: Option<String>,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    let filter = match search_filter::parse(args.filter.as_deref().unwrap_or_default())
+        .and_then(|filter| filter.to_sql(&search_filter::issues::SCHEMA))
+    {
+        Ok(filter) => filter,
+        Err(e) => return ResolveResult::Error(format!("Invalid filter: {}", e)),
+    };
+
+    // The host decides visibility; never widen past the ids it returns
+    let repository_ids = match host_repositories::visible_repository_ids(group_id) {
+        Ok(ids) => ids,
+        Err(e) => return ResolveResult::Error(format!("Failed to list repositories: {}", e)),
+    };
+    if repository_ids.is_empty() {
+        return serialize_issues(Vec::new());
+    }
+    let repository_ids = match serde_json::to_string(&repository_ids) {
+        Ok(json) => json,
+        Err(e) => return ResolveResult::Error(format!("Serialization error: {}", e)),
+    };
+
+    let sql = format!(
+        "SELECT {} FROM issues WHERE repository_id IN (SELECT value FROM json_each(?)) AND {} ORDER BY {} LIMIT {}",
+        ISSUE_COLUMNS, filter.where_clause, filter.order_by, ROLLUP_LIMIT
+    );
+    let mut params = vec![RecordValue::Text(repository_ids)];
+    params.extend(filter.params.into_iter().map(filter_param));
+
+    match host_database::query(&sql, &params) {
+        host_database::QueryResult::Success(rows) => match load_issue_relations(
+            rows.into_iter()
+                .map(|row| issue_from_values(&row.values))
+                .collect(),
+        ) {
+            Ok(issues) => serialize_issues(issues),
+            Err(err) => ResolveResult::Error(err),
+        },
+        host_database::QueryResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
+    }
+}
+
+fn filter_param(param: search_filter::SqlValue) -> RecordValue {
+    match param {
+        search_filter::SqlValue::Text(text) => RecordValue::Text(text),
+        search_filter::SqlValue::Integer(number) => RecordValue::Integer(number),
+    }
+}
+
+fn resolve_get_issue(arguments: &str, context_repository: Option<&str>) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        #[serde(rename = "repositoryId")]
+        repository_id: String,
+        #[serde(rename = "issueNumber")]
+        issue_number: i64,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+
+    match query_issue_by_number(&args.repository_id, args.issue_number) {
+        Ok(Some(issue)) => serialize_issue(issue),
+        Ok(None) => ResolveResult::Success("null".to_string()),
+        Err(err) => ResolveResult::Error(err),
+    }
+}
+
+/// Looks an issue up by its `id` (`<repository id>:<number>`), for `node(id:)`.
+fn resolve_issue_reference(parent: Option<&str>) -> ResolveResult {
+    let (repository_id, number) = match parse_issue_reference(parent) {
+        Ok(Some(key)) => key,
+        Ok(None) => return ResolveResult::Success("null".to_string()),
+        Err(err) => return ResolveResult::Error(err),
+    };
+
+    match query_issue_by_number(&repository_id, number) {
+        Ok(Some(issue)) => serialize_issue(issue),
+        Ok(None) => ResolveResult::Success("null".to_string()),
+        Err(err) => ResolveResult::Error(err),
+    }
+}
+
+/// The repository and number in an issue reference; `None` when the id cannot name an
+/// issue.
+fn parse_issue_reference(parent: Option<&str>) -> Result<Option<IssueKey>, String> {
+    #[derive(Deserialize)]
+    struct Reference {
+        id: String,
+    }
+
+    let reference: Reference = match parent.map(serde_json::from_str::<Reference>).transpose() {
+        Ok(Some(reference)) => reference,
+        Ok(None) => return Err("Missing issue reference".to_string()),
+        Err(e) => return Err(format!("Invalid issue reference: {}", e)),
+    };
+    Ok(reference
+        .id
+        .rsplit_once(':')
+        .and_then(|(repository_id, number)| {
+            Some((repository_id.to_string(), number.parse().ok()?))
+        }))
+}
+
+fn resolve_create_issue(
+    arguments: &str,
+    context_repository: Option<&str>,
+    author: Option<&str>,
+) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        #[serde(rename = "repositoryId")]
+        repository_id: String,
+        input: CreateIssueInput,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+
+    let template = match &args.input.template_id {
+        Some(template_id) => match query_template(&args.repository_id, template_id) {
+            Ok(Some(template)) => Some(template),
+            Ok(None) => {
+                return ResolveResult::Error(format!("Issue template `{}` not found", template_id));
+            }
+            Err(err) => return ResolveResult::Error(err),
+        },
+        None => None,
+    };
+    let custom_fields = match validate_custom_fields(template.as_ref(), &args.input.custom_fields) {
+        Ok(values) => values,
+        Err(err) => return ResolveResult::Error(err),
+    };
+    let custom_fields_json = match serde_json::to_string(&custom_fields) {
+        Ok(json) => json,
+        Err(e) => return ResolveResult::Error(format!("Serialization error: {}", e)),
+    };
+
+    let number = match next_issue_number(&args.repository_id) {
+        Ok(num) => num,
+        Err(err) => return ResolveResult::Error(err),
+    };
+
+    let db_id = format!("issue_{}_{}", chrono::Utc::now().timestamp_millis(), number);
+    let created_at = chrono::Utc::now().to_rfc3339();
+
+    let sql = "INSERT INTO issues (id, repository_id, number, title, description, status, created_at, updated_at, template_id, custom_fields, author_did) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+    let params = vec![
+        RecordValue::Text(db_id.clone()),
+        RecordValue::Text(args.repository_id.clone()),
+        RecordValue::Integer(number),
+        RecordValue::Text(args.input.title.clone()),
+        match &args.input.description {
+            Some(d) => RecordValue::Text(d.clone()),
+            None => RecordValue::Null,
+        },
+        RecordValue::Text("OPEN".to_string()),
+        RecordValue::Text(created_at.clone()),
+        RecordValue::Text(created_at.clone()),
+        match &args.input.template_id {
+            Some(id) => RecordValue::Text(id.clone()),
+            None => RecordValue::Null,
+        },
+        RecordValue::Text(custom_fields_json),
+        match author {
+            Some(did) => RecordValue::Text(did.to_string()),
+            None => RecordValue::Null,
+        },
+    ];
+
+    match host_database::execute(sql, &params) {
+        host_database::ExecResult::Success(_) => {
+            let issue = Issue {
+                db_id,
+                repository_id: args.repository_id,
+                number,
+                title: args.input.title,
+                description: args.input.description,
+                status: "OPEN".to_string(),
+                created_at: created_at.clone(),
+                template_id: args.input.template_id,
+                custom_fields,
+                version: 1,
+                updated_at: created_at,
+                milestone_id: None,
+                labels: Vec::new(),
+                milestone: None,
+            };
+            publish_issue_event("issue.created", &issue);
+            serialize_issue(issue)
+        }
+        host_database::ExecResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
+    }
+}
+
+/// `Issue.comments(first, after)`: a page of the issue's comments, oldest first.
+fn resolve_issue_comments(
+    arguments: &str,
+    parent: Option<&str>,
+    context_repository: Option<&str>,
+) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        first: Option<i64>,
+        after: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Parent {
+        repository_id: String,
+        number: i64,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+    let issue: Parent = match parent.map(serde_json::from_str::<Parent>).transpose() {
+        Ok(Some(issue)) => issue,
+        Ok(None) => return ResolveResult::Error("Missing issue".to_string()),
+        Err(e) => return ResolveResult::Error(format!("Invalid issue: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &issue.repository_id) {
+        return ResolveResult::Error(err);
+    }
+
+    let first = match page_size(args.first) {
+        Ok(first) => first,
+        Err(err) => return ResolveResult::Error(err),
+    };
+    let after = match args.after.as_deref().map(parse_cursor).transpose() {
+        Ok(after) => after,
+        Err(err) => return ResolveResult::Error(err),
+    };
+
+    // One extra comment tells whether there is another page
+    let mut comments =
+        match query_issue_comments(&issue.repository_id, issue.number, after, Some(first + 1)) {
+            Ok(comments) => comments,
+            Err(err) => return ResolveResult::Error(err),
+        };
+    let has_next_page = comments.len() as i64 > first;
+    comments.truncate(first as usize);
+
+    let sql = "SELECT COUNT(*) FROM issue_comments WHERE repository_id = ? AND issue_number = ?";
+    let params = vec![
+        RecordValue::Text(issue.repository_id),
+        RecordValue::Integer(issue.number),
+    ];
+    let total_count = match host_database::query(sql, &params) {
+        host_database::QueryResult::Success(rows) => rows
+            .first()
+            .and_then(|row| row.values.first())
+            .map(extract_integer)
+            .unwrap_or(0),
+        host_database::QueryResult::Error(e) => {
+            return ResolveResult::Error(format!("Database error: {}", e));
+        }
+    };
+
+    let payload = json!({
+        "nodes": comments.iter().map(comment_to_json).collect::<Vec<_>>(),
+        "totalCount": total_count,
+        "endCursor": comments.last().map(Comment::cursor),
+        "hasNextPage": has_next_page,
+    });
+    match serde_json::to_string(&payload) {
+        Ok(json) => ResolveResult::Success(json),
+        Err(e) => ResolveResult::Error(format!("Serialization error: {}", e)),
+    }
+}
+
+fn resolve_add_comment(
+    arguments: &str,
+    context_repository: Option<&str>,
+    author: Option<&str>,
+) -> ResolveResult {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Args {
+        repository_id: String,
+        issue_number: i64,
+        body: String,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+    let Some(author) = author else {
+        return ResolveResult::Error("Sign in to comment".to_string());
+    };
+    let body = match validate_body(&args.body) {
+        Ok(body) => body,
+        Err(err) => return ResolveResult::Error(err),
+    };
+    match query_issue_by_number(&args.repository_id, args.issue_number) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return ResolveResult::Error(format!("Issue #{} not found", args.issue_number));
+        }
+        Err(err) => return ResolveResult::Error(err),
+    }
+
+    let now = chrono::Utc::now();
+    let comment = Comment {
+        id: format!(
+            "comment_{}_{}",
+            now.timestamp_nanos_opt().unwrap_or_default(),
+            args.issue_number
+        ),
+        repository_id: args.repository_id,
+        issue_number: args.issue_number,
+        author_did: Some(author.to_string()),
+        body,
+        created_at: now.to_rfc3339(),
+        updated_at: now.to_rfc3339(),
+    };
+
+    let sql = format!(
+        "INSERT INTO issue_comments ({}) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        COMMENT_COLUMNS
+    );
+    let params = vec![
+        RecordValue::Text(comment.id.clone()),
+        RecordValue::Text(comment.repository_id.clone()),
+        RecordValue::Integer(comment.issue_number),
+        RecordValue::Text(author.to_string()),
+        RecordValue::Text(comment.body.clone()),
+        RecordValue::Text(comment.created_at.clone()),
+        RecordValue::Text(comment.updated_at.clone()),
+    ];
+    match host_database::execute(&sql, &params) {
+        host_database::ExecResult::Success(_) => {
+            publish_comment_event("comment.created", &comment);
+            serialize_comment(&comment)
+        }
+        host_database::ExecResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
+    }
+}
+
+/// Replaces a comment's body. Only its author may.
+fn resolve_edit_comment(
+    arguments: &str,
+    context_repository: Option<&str>,
+    editor: Option<&str>,
+) -> ResolveResult {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Args {
+        repository_id: String,
+        comment_id: String,
+        body: String,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+    let body = match validate_body(&args.body) {
+        Ok(body) => body,
+        Err(err) => return ResolveResult::Error(err),
+    };
+    let mut comment = match query_comment(&args.repository_id, &args.comment_id) {
+        Ok(Some(comment)) => comment,
+        Ok(None) => return ResolveResult::Success("null".to_string()),
+        Err(err) => return ResolveResult::Error(err),
+    };
+    if let Err(err) = comment.require_author(editor, "edit") {
+        return ResolveResult::Error(err);
+    }
+
+    comment.body = body;
+    comment.updated_at = chrono::Utc::now().to_rfc3339();
+    let sql = "UPDATE issue_comments SET body = ?, updated_at = ? WHERE id = ?";
+    let params = vec![
+        RecordValue::Text(comment.body.clone()),
+        RecordValue::Text(comment.updated_at.clone()),
+        RecordValue::Text(comment.id.clone()),
+    ];
+    match host_database::execute(sql, &params) {
+        host_database::ExecResult::Success(_) => {
+            publish_comment_event("comment.updated", &comment);
+            serialize_comment(&comment)
+        }
+        host_database::ExecResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
+    }
+}
+
+/// Archives and removes a comment. Only its author may.
+fn resolve_delete_comment(
+    arguments: &str,
+    context_repository: Option<&str>,
+    deleter: Option<&str>,
+) -> ResolveResult {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Args {
+        repository_id: String,
+        comment_id: String,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+
+    let comment = match query_comment(&args.repository_id, &args.comment_id) {
+        Ok(Some(comment)) => comment,
+        Ok(None) => return ResolveResult::Success("false".to_string()),
+        Err(err) => return ResolveResult::Error(err),
+    };
+    if let Err(err) = comment.require_author(deleter, "delete") {
+        return ResolveResult::Error(err);
+    }
+
+    let summary = comment.to_json().to_string();
+    if let Err(err) = host_compliance::archive_deletion("issue-comment", &comment.id, &summary) {
+        return ResolveResult::Error(format!("Failed to archive comment: {}", err));
+    }
+
+    let sql = "DELETE FROM issue_comments WHERE id = ?";
+    match host_database::execute(sql, &[RecordValue::Text(comment.id.clone())]) {
+        host_database::ExecResult::Success(info) => {
+            let deleted = info.rows_affected > 0;
+            if deleted {
+                publish_comment_event("comment.deleted", &comment);
+            }
+            ResolveResult::Success(deleted.to_string())
+        }
+        host_database::ExecResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
+    }
+}
+
+/// Issues the user opened and comments they wrote, for the host's account export.
+fn resolve_account_export(arguments: &str, context_user: Option<&str>) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        did: String,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+    if context_user != Some(args.did.as_str()) {
+        return ResolveResult::Error("Account exports cover only the requesting user".to_string());
+    }
+
+    let sql = format!(
+        "SELECT {} FROM issues WHERE author_did = ? ORDER BY created_at",
+        ISSUE_COLUMNS
+    );
+    let params = vec![RecordValue::Text(args.did.clone())];
+    let issues = match host_database::query(&sql, &params) {
+        host_database::QueryResult::Success(rows) => rows
+            .into_iter()
+            .map(|row| issue_from_values(&row.values))
+            .collect::<Vec<_>>(),
+        host_database::QueryResult::Error(e) => {
+            return ResolveResult::Error(format!("Database error: {}", e));
+        }
+    };
+    let issues = match load_issue_relations(issues) {
+        Ok(issues) => issues.iter().map(issue_to_json).collect::<Vec<_>>(),
+        Err(err) => return ResolveResult::Error(err),
+    };
+
+    let sql = format!(
+        "SELECT {} FROM issue_comments WHERE author_did = ? ORDER BY created_at, id",
+        COMMENT_COLUMNS
+    );
+    let params = vec![RecordValue::Text(args.did)];
+    let comments = match host_database::query(&sql, &params) {
+        host_database::QueryResult::Success(rows) => rows
+            .iter()
+            .map(|row| comment_from_values(&row.values).to_json())
+            .collect::<Vec<_>>(),
+        host_database::QueryResult::Error(e) => {
+            return ResolveResult::Error(format!("Database error: {}", e));
+        }
+    };
+    match serde_json::to_string(&json!({ "issues": issues, "comments": comments })) {
+        Ok(json) => ResolveResult::Success(json),
+        Err(e) => ResolveResult::Error(format!("Serialization error: {}", e)),
+    }
+}
+
+/// Closes issues named by pushed commit messages (`fixes #12`), for the host's
+/// issue-closing job. Issues that are already closed are left alone; each closed issue
+/// gets a timeline event linking the commit.
+fn resolve_close_issues_from_commits(
+    arguments: &str,
+    context_repository: Option<&str>,
+) -> ResolveResult {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Closure {
+        number: i64,
+        commit_sha: String,
+        commit_summary: String,
+        commit_repository_id: String,
+        commit_repository_path: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Args {
+        repository_id: String,
+        closures: Vec<Closure>,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+
+    let mut closed = Vec::new();
+    for closure in args.closures {
+        let mut issue = match query_issue_by_number(&args.repository_id, closure.number) {
+            Ok(Some(issue)) if issue.status != "CLOSED" => issue,
+            Ok(_) => continue,
+            Err(err) => return ResolveResult::Error(err),
+        };
+
+        let created_at = chrono::Utc::now().to_rfc3339();
+        let event_id = format!(
+            "event_{}_{}",
+            chrono::Utc::now().timestamp_millis(),
+            issue.number
+        );
+        let sql = "INSERT OR IGNORE INTO issue_events (id, repository_id, number, kind, commit_sha, commit_summary, commit_repository_id, commit_repository_path, created_at) VALUES (?, ?, ?, 'CLOSED_BY_COMMIT', ?, ?, ?, ?, ?)";
+        let params = vec![
+            RecordValue::Text(event_id),
+            RecordValue::Text(issue.repository_id.clone()),
+            RecordValue::Integer(issue.number),
+            RecordValue::Text(closure.commit_sha),
+            RecordValue::Text(closure.commit_summary),
+            RecordValue::Text(closure.commit_repository_id),
+            RecordValue::Text(closure.commit_repository_path),
+            RecordValue::Text(created_at),
+        ];
+        if let host_database::ExecResult::Error(e) = host_database::execute(sql, &params) {
+            return ResolveResult::Error(format!("Database error: {}", e));
+        }
+
+        let updated_at = chrono::Utc::now().to_rfc3339();
+        let params = vec![
+            RecordValue::Text(updated_at.clone()),
+            RecordValue::Text(issue.db_id.clone()),
+        ];
+        let sql = "UPDATE issues SET status = 'CLOSED', version = version + 1, updated_at = ? WHERE id = ?";
+        match host_database::execute(sql, &params) {
+            host_database::ExecResult::Success(_) => {
+                issue.status = "CLOSED".to_string();
+                issue.version += 1;
+                issue.updated_at = updated_at;
+                publish_issue_event("issue.updated", &issue);
+                closed.push(issue.number);
+            }
+            host_database::ExecResult::Error(e) => {
+                return ResolveResult::Error(format!("Database error: {}", e));
+            }
+        }
+    }
+
+    ResolveResult::Success(json!({ "closed": closed }).to_string())
+}
+
+fn resolve_get_issue_timeline(arguments: &str, context_repository: Option<&str>) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        #[serde(rename = "repositoryId")]
+        repository_id: String,
+        #[serde(rename = "issueNumber")]
+        issue_number: i64,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+
+    let sql = "SELECT id, kind, commit_sha, commit_summary, commit_repository_id, commit_repository_path, created_at FROM issue_events WHERE repository_id = ? AND number = ? ORDER BY created_at, id";
+    let params = vec![
+        RecordValue::Text(args.repository_id),
+        RecordValue::Integer(args.issue_number),
+    ];
+    let events: Vec<_> = match host_database::query(sql, &params) {
+        host_database::QueryResult::Success(rows) => rows
+            .iter()
+            .map(|row| {
+                json!({
+                    "id": extract_string(&row.values[0]),
+                    "kind": extract_string(&row.values[1]),
+                    "commitSha": extract_optional_string(&row.values[2]),
+                    "commitSummary": extract_optional_string(&row.values[3]),
+                    "commitRepositoryId": extract_optional_string(&row.values[4]),
+                    "commitRepositoryPath": extract_optional_string(&row.values[5]),
+                    "createdAt": extract_string(&row.values[6]),
+                })
+            })
+            .collect(),
+        host_database::QueryResult::Error(e) => {
+            return ResolveResult::Error(format!("Database error: {}", e));
+        }
+    };
+    match serde_json::to_string(&events) {
+        Ok(json) => ResolveResult::Success(json),
+        Err(e) => ResolveResult::Error(format!("Serialization error: {}", e)),
+    }
+}
+
+fn resolve_update_issue(arguments: &str, context_repository: Option<&str>) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        #[serde(rename = "repositoryId")]
+        repository_id: String,
+        #[serde(rename = "issueNumber")]
+        issue_number: i64,
+        input: UpdateIssueInput,
+        #[serde(rename = "expectedVersion", default)]
+        expected_version: Option<i64>,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+
+    let mut updates = Vec::new();
+    let mut params = Vec::new();
+
+    if let Some(title) = args.input.title {
+        updates.push("title = ?");
+        params.push(RecordValue::Text(title));
+    }
+    if let Some(description) = args.input.description {
+        updates.push("description = ?");
+        params.push(RecordValue::Text(description));
+    }
+    if let Some(status) = args.input.status {
+        updates.push("status = ?");
+        params.push(RecordValue::Text(status));
+    }
+    if let Some(milestone_id) = args.input.milestone_id {
+        if let Some(milestone_id) = &milestone_id {
+            match query_milestone(&args.repository_id, milestone_id) {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    return ResolveResult::Error(format!("Milestone `{}` not found", milestone_id));
+                }
+                Err(err) => return ResolveResult::Error(err),
+            }
+        }
+        updates.push("milestone_id = ?");
+        params.push(match milestone_id {
+            Some(id) => RecordValue::Text(id),
+            None => RecordValue::Null,
+        });
+    }
+
+    if updates.is_empty() {
+        return ResolveResult::Error("No fields to update".to_string());
+    }
+
+    // Every write bumps the version; with `expectedVersion` the write only lands if
+    // nobody else saved in between.
+    updates.push("version = version + 1");
+    updates.push("updated_at = ?");
+    params.push(RecordValue::Text(chrono::Utc::now().to_rfc3339()));
+    let mut sql = format!(
+        "UPDATE issues SET {} WHERE repository_id = ? AND number = ?",
+        updates.join(", ")
+    );
+    params.push(RecordValue::Text(args.repository_id.clone()));
+    params.push(RecordValue::Integer(args.issue_number));
+    if let Some(expected) = args.expected_version {
+        sql.push_str(" AND version = ?");
+        params.push(RecordValue::Integer(expected));
+    }
+
+    match host_database::execute(&sql, &params) {
+        host_database::ExecResult::Success(info) => {
+            if info.rows_affected == 0 {
+                return match query_issue_by_number(&args.repository_id, args.issue_number) {
+                    Ok(Some(latest)) => conflict_error(&latest),
+                    Ok(None) => ResolveResult::Success("null".to_string()),
+                    Err(err) => ResolveResult::Error(err),
+                };
+            }
+
+            match query_issue_by_number(&args.repository_id, args.issue_number) {
+                Ok(Some(issue)) => {
+                    publish_issue_event("issue.updated", &issue);
+                    serialize_issue(issue)
+                }
+                Ok(None) => ResolveResult::Success("null".to_string()),
+                Err(err) => ResolveResult::Error(err),
+            }
+        }
+        host_database::ExecResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
+    }
+}
+
+fn resolve_get_issue_templates(arguments: &str, context_repository: Option<&str>) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        #[serde(rename = "repositoryId")]
+        repository_id: String,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+
+    let sql =
+        "SELECT id, name, body, fields FROM issue_templates WHERE repository_id = ? ORDER BY name";
+    let params = vec![RecordValue::Text(args.repository_id)];
+    match host_database::query(sql, &params) {
+        host_database::QueryResult::Success(rows) => {
+            let payload: Vec<_> = rows
+                .iter()
+                .map(|row| template_from_values(&row.values).to_json())
+                .collect();
+            match serde_json::to_string(&payload) {
+                Ok(json) => ResolveResult::Success(json),
+                Err(e) => ResolveResult::Error(format!("Serialization error: {}", e)),
+            }
+        }
+        host_database::QueryResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
+    }
+}
+
+fn resolve_save_issue_template(arguments: &str, context_repository: Option<&str>) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        #[serde(rename = "repositoryId")]
+        repository_id: String,
+        input: IssueTemplateInput,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+
+    let name = args.input.name.trim().to_string();
+    if name.is_empty() {
+        return ResolveResult::Error("Template name must not be empty".to_string());
+    }
+    if let Err(err) = validate_template_fields(&args.input.fields) {
+        return ResolveResult::Error(err);
+    }
+    let fields = match serde_json::to_string(&args.input.fields) {
+        Ok(json) => json,
+        Err(e) => return ResolveResult::Error(format!("Serialization error: {}", e)),
+    };
+
+    // Saving under an existing name replaces that template but keeps its id
+    let sql = "INSERT INTO issue_templates (id, repository_id, name, body, fields) VALUES (?, ?, ?, ?, ?) \
+               ON CONFLICT(repository_id, name) DO UPDATE SET body = excluded.body, fields = excluded.fields";
+    let params = vec![
+        RecordValue::Text(format!(
+            "template_{}",
+            chrono::Utc::now().timestamp_millis()
+        )),
+        RecordValue::Text(args.repository_id.clone()),
+        RecordValue::Text(name.clone()),
+        match &args.input.body {
+            Some(body) => RecordValue::Text(body.clone()),
+            None => RecordValue::Null,
+        },
+        RecordValue::Text(fields),
+    ];
+    if let host_database::ExecResult::Error(e) = host_database::execute(sql, &params) {
+        return ResolveResult::Error(format!("Database error: {}", e));
+    }
+
+    let sql =
+        "SELECT id, name, body, fields FROM issue_templates WHERE repository_id = ? AND name = ?";
+    let params = vec![
+        RecordValue::Text(args.repository_id),
+        RecordValue::Text(name),
+    ];
+    match host_database::query(sql, &params) {
+        host_database::QueryResult::Success(rows) => match rows.first() {
+            Some(row) => {
+                match serde_json::to_string(&template_from_values(&row.values).to_json()) {
+                    Ok(json) => ResolveResult::Success(json),
+                    Err(e) => ResolveResult::Error(format!("Serialization error: {}", e)),
+                }
+            }
+            None => ResolveResult::Error("Template disappeared after saving".to_string()),
+        },
+        host_database::QueryResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
+    }
+}
+
+fn resolve_delete_issue(arguments: &str, context_repository: Option<&str>) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        #[serde(rename = "repositoryId")]
+        repository_id: String,
+        #[serde(rename = "issueNumber")]
+        issue_number: i64,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+
+    let issue = match query_issue_by_number(&args.repository_id, args.issue_number) {
+        Ok(Some(issue)) => issue,
+        Ok(None) => return ResolveResult::Success("false".to_string()),
+        Err(err) => return ResolveResult::Error(err),
+    };
+
+    let comments = match query_issue_comments(&issue.repository_id, issue.number, None, None) {
+        Ok(comments) => comments,
+        Err(err) => return ResolveResult::Error(err),
+    };
+
+    // Archive first: an issue must never disappear without a compliance record, and its
+    // comments go with it
+    let mut summary = issue_to_json(&issue);
+    summary["comments"] = comments.iter().map(Comment::to_json).collect();
+    let summary = summary.to_string();
+    if let Err(err) = host_compliance::archive_deletion("issue", &issue.db_id, &summary) {
+        return ResolveResult::Error(format!("Failed to archive issue: {}", err));
+    }
+
+    let sql = "DELETE FROM issues WHERE id = ?";
+    match host_database::execute(sql, &[RecordValue::Text(issue.db_id.clone())]) {
+        host_database::ExecResult::Success(info) => {
+            let deleted = info.rows_affected > 0;
+            if deleted {
+                // A later issue may reuse the number; it must not inherit this timeline.
+                let params = vec![
+                    RecordValue::Text(issue.repository_id.clone()),
+                    RecordValue::Integer(issue.number),
+                ];
+                let _ = host_database::execute(
+                    "DELETE FROM issue_events WHERE repository_id = ? AND number = ?",
+                    &params,
+                );
+                let _ = host_database::execute(
+                    "DELETE FROM issue_comments WHERE repository_id = ? AND issue_number = ?",
+                    &params,
+                );
+                let _ = host_database::execute(
+                    "DELETE FROM issue_label_assignments WHERE issue_id = ?",
+                    &[RecordValue::Text(issue.db_id.clone())],
+                );
+                publish_issue_event("issue.deleted", &issue);
+            }
+            ResolveResult::Success(deleted.to_string())
+        }
+        host_database::ExecResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
+    }
+}
+
+fn resolve_delete_issue_template(
+    arguments: &str,
+    context_repository: Option<&str>,
+) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        #[serde(rename = "repositoryId")]
+        repository_id: String,
+        #[serde(rename = "templateId")]
+        template_id: String,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
+
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
+
+    // Issues keep their stored field values, so deleting a template is safe
+    let sql = "DELETE FROM issue_templates WHERE repository_id = ? AND id = ?";
+    let params = vec![
+        RecordValue::Text(args.repository_id),
+        RecordValue::Text(args.template_id),
+    ];
+    match host_database::execute(sql, &params) {
+        host_database::ExecResult::Success(info) => {
+            ResolveResult::Success((info.rows_affected > 0).to_string())
+        }
+        host_database::ExecResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
+    }
+}
+
+fn resolve_get_issue_labels(arguments: &str, context_repository: Option<&str>) -> ResolveResult {
+    #[derive(Deserialize)]
+    struct Args {
+        #[serde(rename = "repositoryId")]
+        repository_id: String,
+    }
+
+    let args: Args = match serde_json::from_str(arguments) {
+        Ok(a) => a,
+        Err(e) => return ResolveResult::Error(format!("Invalid arguments: {}", e)),
+    };
 
-        if matches!(
-            field_name.as_str(),
-            "getIssuesForRepository" | "getIssue" | "createIssue" | "updateIssue"
-        ) && !matches!(
-            scope,
-            ContextScope::Repository | ContextScope::RepositoryUser
-        ) {
-            return ResolveResult::Error(
-                "Repository-scoped context required for issues operations".to_string(),
-            );
-        }
+    if let Err(err) = assert_repository_context(context_repository, &args.repository_id) {
+        return ResolveResult::Error(err);
+    }
 
-        match field_name.as_str() {
-            "getIssuesForRepository" => {
-                resolve_get_issues_for_repository(&arguments, repository_context_id.as_deref())
+    let sql = "SELECT id, name, color, description FROM issue_labels WHERE repository_id = ? ORDER BY name";
+    let params = vec![RecordValue::Text(args.repository_id)];
+    match host_database::query(sql, &params) {
+        host_database::QueryResult::Success(rows) => {
+            let payload: Vec<_> = rows
+                .iter()
+                .map(|row| label_from_values(&row.values).to_json())
+                .collect();
+            match serde_json::to_string(&payload) {
+                Ok(json) => ResolveResult::Success(json),
+                Err(e) => ResolveResult::Error(format!("Serialization error: {}", e)),
             }
-            "getIssue" => resolve_get_issue(&arguments, repository_context_id.as_deref()),
-            "createIssue" => resolve_create_issue(&arguments, repository_context_id.as_deref()),
-            "updateIssue" => resolve_update_issue(&arguments, repository_context_id.as_deref()),
-            _ => ResolveResult::Error(format!("Unknown field: {}", field_name)),
         }
-    }
-
-    fn shutdown() {
-        host_log::log(LogLevel::Info, "Issues extension shutting down");
+        host_database::QueryResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
     }
 }
 
-fn resolve_get_issues_for_repository(
+fn resolve_get_issue_milestones(
     arguments: &str,
     context_repository: Option<&str>,
 ) -> ResolveResult {
@@ -142,16 +1573,20 @@ fn resolve_get_issues_for_repository(
         return ResolveResult::Error(err);
     }
 
-    let sql = "SELECT id, repository_id, number, title, description, status, created_at FROM issues WHERE repository_id = ? ORDER BY number DESC";
-    let params = vec![RecordValue::Text(args.repository_id.clone())];
-
+    // Milestones without a due date come last
+    let sql = "SELECT id, title, description, due_on, created_at FROM issue_milestones \
+               WHERE repository_id = ? ORDER BY due_on IS NULL, due_on, title";
+    let params = vec![RecordValue::Text(args.repository_id)];
     match host_database::query(sql, &params) {
         host_database::QueryResult::Success(rows) => {
-            let issues: Vec<Issue> = rows
-                .into_iter()
-                .map(|row| issue_from_values(&row.values))
+            let payload: Vec<_> = rows
+                .iter()
+                .map(|row| milestone_from_values(&row.values).to_json())
                 .collect();
-            serialize_issues(issues)
+            match serde_json::to_string(&payload) {
+                Ok(json) => ResolveResult::Success(json),
+                Err(e) => ResolveResult::Error(format!("Serialization error: {}", e)),
+            }
         }
         host_database::QueryResult::Error(e) => {
             ResolveResult::Error(format!("Database error: {}", e))
@@ -159,13 +1594,12 @@ fn resolve_get_issues_for_repository(
     }
 }
 
-fn resolve_get_issue(arguments: &str, context_repository: Option<&str>) -> ResolveResult {
+fn resolve_create_label(arguments: &str, context_repository: Option<&str>) -> ResolveResult {
     #[derive(Deserialize)]
     struct Args {
         #[serde(rename = "repositoryId")]
         repository_id: String,
-        #[serde(rename = "issueNumber")]
-        issue_number: i64,
+        input: LabelInput,
     }
 
     let args: Args = match serde_json::from_str(arguments) {
@@ -177,19 +1611,45 @@ fn resolve_get_issue(arguments: &str, context_repository: Option<&str>) -> Resol
         return ResolveResult::Error(err);
     }
 
-    match query_issue_by_number(&args.repository_id, args.issue_number) {
-        Ok(Some(issue)) => serialize_issue(issue),
-        Ok(None) => ResolveResult::Success("null".to_string()),
-        Err(err) => ResolveResult::Error(err),
+    let mut label = match validate_label(args.input) {
+        Ok(label) => label,
+        Err(err) => return ResolveResult::Error(err),
+    };
+    label.id = format!("label_{}", chrono::Utc::now().timestamp_millis());
+
+    // Names are unique per repository regardless of case; the column collates NOCASE
+    let sql = "INSERT INTO issue_labels (id, repository_id, name, color, description) \
+               VALUES (?, ?, ?, ?, ?) ON CONFLICT(repository_id, name) DO NOTHING";
+    let params = vec![
+        RecordValue::Text(label.id.clone()),
+        RecordValue::Text(args.repository_id),
+        RecordValue::Text(label.name.clone()),
+        RecordValue::Text(label.color.clone()),
+        match &label.description {
+            Some(description) => RecordValue::Text(description.clone()),
+            None => RecordValue::Null,
+        },
+    ];
+    match host_database::execute(sql, &params) {
+        host_database::ExecResult::Success(info) if info.rows_affected == 0 => {
+            ResolveResult::Error(format!("Label `{}` already exists", label.name))
+        }
+        host_database::ExecResult::Success(_) => match serde_json::to_string(&label.to_json()) {
+            Ok(json) => ResolveResult::Success(json),
+            Err(e) => ResolveResult::Error(format!("Serialization error: {}", e)),
+        },
+        host_database::ExecResult::Error(e) => {
+            ResolveResult::Error(format!("Database error: {}", e))
+        }
     }
 }
 
-fn resolve_create_issue(arguments: &str, context_repository: Option<&str>) -> ResolveResult {
+fn resolve_create_milestone(arguments: &str, context_repository: Option<&str>) -> ResolveResult {
     #[derive(Deserialize)]
     struct Args {
         #[serde(rename = "repositoryId")]
         repository_id: String,
-        input: CreateIssueInput,
+        input: MilestoneInput,
     }
 
     let args: Args = match serde_json::from_str(arguments) {
@@ -201,40 +1661,38 @@ fn resolve_create_issue(arguments: &str, context_repository: Option<&str>) -> Re
         return ResolveResult::Error(err);
     }
 
-    let number = match next_issue_number(&args.repository_id) {
-        Ok(num) => num,
+    let mut milestone = match validate_milestone(args.input) {
+        Ok(milestone) => milestone,
         Err(err) => return ResolveResult::Error(err),
     };
+    milestone.id = format!("milestone_{}", chrono::Utc::now().timestamp_millis());
+    milestone.created_at = chrono::Utc::now().to_rfc3339();
 
-    let db_id = format!("issue_{}_{}", chrono::Utc::now().timestamp_millis(), number);
-    let created_at = chrono::Utc::now().to_rfc3339();
-
-    let sql = "INSERT INTO issues (id, repository_id, number, title, description, status, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)";
+    let sql = "INSERT INTO issue_milestones (id, repository_id, title, description, due_on, created_at) \
+               VALUES (?, ?, ?, ?, ?, ?) ON CONFLICT(repository_id, title) DO NOTHING";
     let params = vec![
-        RecordValue::Text(db_id.clone()),
-        RecordValue::Text(args.repository_id.clone()),
-        RecordValue::Integer(number),
-        RecordValue::Text(args.input.title.clone()),
-        match &args.input.description {
-            Some(d) => RecordValue::Text(d.clone()),
+        RecordValue::Text(milestone.id.clone()),
+        RecordValue::Text(args.repository_id),
+        RecordValue::Text(milestone.title.clone()),
+        match &milestone.description {
+            Some(description) => RecordValue::Text(description.clone()),
             None => RecordValue::Null,
         },
-        RecordValue::Text("OPEN".to_string()),
-        RecordValue::Text(created_at.clone()),
+        match &milestone.due_on {
+            Some(due_on) => RecordValue::Text(due_on.clone()),
+            None => RecordValue::Null,
+        },
+        RecordValue::Text(milestone.created_at.clone()),
     ];
-
     match host_database::execute(sql, &params) {
+        host_database::ExecResult::Success(info) if info.rows_affected == 0 => {
+            ResolveResult::Error(format!("Milestone `{}` already exists", milestone.title))
+        }
         host_database::ExecResult::Success(_) => {
-            let issue = Issue {
-                db_id,
-                repository_id: args.repository_id,
-                number,
-                title: args.input.title,
-                description: args.input.description,
-                status: "OPEN".to_string(),
-                created_at,
-            };
-            serialize_issue(issue)
+            match serde_json::to_string(&milestone.to_json()) {
+                Ok(json) => ResolveResult::Success(json),
+                Err(e) => ResolveResult::Error(format!("Serialization error: {}", e)),
+            }
         }
         host_database::ExecResult::Error(e) => {
             ResolveResult::Error(format!("Database error: {}", e))
@@ -242,14 +1700,19 @@ fn resolve_create_issue(arguments: &str, context_repository: Option<&str>) -> Re
     }
 }
 
-fn resolve_update_issue(arguments: &str, context_repository: Option<&str>) -> ResolveResult {
+/// Adds a label to an issue, or with `assign` false removes it. A change counts as an
+/// edit of the issue: it bumps the version and publishes `issue.updated`.
+fn resolve_label_assignment(
+    arguments: &str,
+    context_repository: Option<&str>,
+    assign: bool,
+) -> ResolveResult {
     #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
     struct Args {
-        #[serde(rename = "repositoryId")]
         repository_id: String,
-        #[serde(rename = "issueNumber")]
         issue_number: i64,
-        input: UpdateIssueInput,
+        label_id: String,
     }
 
     let args: Args = match serde_json::from_str(arguments) {
@@ -261,48 +1724,103 @@ fn resolve_update_issue(arguments: &str, context_repository: Option<&str>) -> Re
         return ResolveResult::Error(err);
     }
 
-    let mut updates = Vec::new();
-    let mut params = Vec::new();
+    let issue = match query_issue_by_number(&args.repository_id, args.issue_number) {
+        Ok(Some(issue)) => issue,
+        Ok(None) => return ResolveResult::Success("null".to_string()),
+        Err(err) => return ResolveResult::Error(err),
+    };
+    match query_label(&args.repository_id, &args.label_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return ResolveResult::Error(format!("Label `{}` not found", args.label_id)),
+        Err(err) => return ResolveResult::Error(err),
+    }
 
-    if let Some(title) = args.input.title {
-        updates.push("title = ?");
-        params.push(RecordValue::Text(title));
+    let sql = if assign {
+        "INSERT OR IGNORE INTO issue_label_assignments (issue_id, label_id) VALUES (?, ?)"
+    } else {
+        "DELETE FROM issue_label_assignments WHERE issue_id = ? AND label_id = ?"
+    };
+    let params = vec![
+        RecordValue::Text(issue.db_id.clone()),
+        RecordValue::Text(args.label_id),
+    ];
+    let changed = match host_database::execute(sql, &params) {
+        host_database::ExecResult::Success(info) => info.rows_affected > 0,
+        host_database::ExecResult::Error(e) => {
+            return ResolveResult::Error(format!("Database error: {}", e));
+        }
+    };
+    if !changed {
+        return serialize_issue(issue);
     }
-    if let Some(description) = args.input.description {
-        updates.push("description = ?");
-        params.push(RecordValue::Text(description));
+
+    let params = vec![
+        RecordValue::Text(chrono::Utc::now().to_rfc3339()),
+        RecordValue::Text(issue.db_id.clone()),
+    ];
+    let sql = "UPDATE issues SET version = version + 1, updated_at = ? WHERE id = ?";
+    if let host_database::ExecResult::Error(e) = host_database::execute(sql, &params) {
+        return ResolveResult::Error(format!("Database error: {}", e));
     }
-    if let Some(status) = args.input.status {
-        updates.push("status = ?");
-        params.push(RecordValue::Text(status));
+    match query_issue_by_number(&args.repository_id, args.issue_number) {
+        Ok(Some(issue)) => {
+            publish_issue_event("issue.updated", &issue);
+            serialize_issue(issue)
+        }
+        Ok(None) => ResolveResult::Success("null".to_string()),
+        Err(err) => ResolveResult::Error(err),
     }
+}
 
-    if updates.is_empty() {
-        return ResolveResult::Error("No fields to update".to_string());
+/// A structured error for an update based on an outdated version. The host passes
+/// `extensions` through to the GraphQL error, so clients can merge against `issue`.
+fn conflict_error(latest: &Issue) -> ResolveResult {
+    let error = json!({
+        "message": format!(
+            "Issue #{} was changed by someone else; it is now at version {}",
+            latest.number, latest.version
+        ),
+        "extensions": {
+            "code": "CONFLICT",
+            "currentVersion": latest.version,
+            "issue": issue_to_json(latest),
+        },
+    });
+    ResolveResult::Error(error.to_string())
+}
+
+/// Exports an issue change. The change is already saved, so a failure is only logged.
+fn publish_issue_event(kind: &str, issue: &Issue) {
+    let data = issue_to_json(issue).to_string();
+    if let Err(err) = host_events::publish(kind, Some(&issue.repository_id), &data) {
+        host_log::log(
+            LogLevel::Warn,
+            &format!("Failed to publish {}: {}", kind, err),
+        );
     }
+}
 
-    let sql = format!(
-        "UPDATE issues SET {} WHERE repository_id = ? AND number = ?",
-        updates.join(", ")
-    );
-    params.push(RecordValue::Text(args.repository_id.clone()));
-    params.push(RecordValue::Integer(args.issue_number));
+fn publish_comment_event(kind: &str, comment: &Comment) {
+    let data = comment.to_json().to_string();
+    if let Err(err) = host_events::publish(kind, Some(&comment.repository_id), &data) {
+        host_log::log(
+            LogLevel::Warn,
+            &format!("Failed to publish {}: {}", kind, err),
+        );
+    }
+}
 
-    match host_database::execute(&sql, &params) {
-        host_database::ExecResult::Success(info) => {
-            if info.rows_affected == 0 {
-                return ResolveResult::Success("null".to_string());
-            }
+/// The comment with its body rendered by the host.
+fn comment_to_json(comment: &Comment) -> serde_json::Value {
+    let mut value = comment.to_json();
+    value["bodyHtml"] = host_markdown::render_html(&comment.body).into();
+    value
+}
 
-            match query_issue_by_number(&args.repository_id, args.issue_number) {
-                Ok(Some(issue)) => serialize_issue(issue),
-                Ok(None) => ResolveResult::Success("null".to_string()),
-                Err(err) => ResolveResult::Error(err),
-            }
-        }
-        host_database::ExecResult::Error(e) => {
-            ResolveResult::Error(format!("Database error: {}", e))
-        }
+fn serialize_comment(comment: &Comment) -> ResolveResult {
+    match serde_json::to_string(&comment_to_json(comment)) {
+        Ok(json) => ResolveResult::Success(json),
+        Err(e) => ResolveResult::Error(format!("Serialization error: {}", e)),
     }
 }
 
@@ -346,6 +1864,57 @@ fn issue_from_values(values: &[RecordValue]) -> Issue {
         description: extract_optional_string(&values[4]),
         status: extract_string(&values[5]),
         created_at: extract_string(&values[6]),
+        template_id: extract_optional_string(&values[7]),
+        custom_fields: extract_optional_string(&values[8])
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+        version: extract_integer(&values[9]),
+        updated_at: extract_string(&values[10]),
+        milestone_id: extract_optional_string(&values[11]),
+        labels: Vec::new(),
+        milestone: None,
+    }
+}
+
+fn comment_from_values(values: &[RecordValue]) -> Comment {
+    Comment {
+        id: extract_string(&values[0]),
+        repository_id: extract_string(&values[1]),
+        issue_number: extract_integer(&values[2]),
+        author_did: extract_optional_string(&values[3]),
+        body: extract_string(&values[4]),
+        created_at: extract_string(&values[5]),
+        updated_at: extract_string(&values[6]),
+    }
+}
+
+fn template_from_values(values: &[RecordValue]) -> IssueTemplate {
+    IssueTemplate {
+        id: extract_string(&values[0]),
+        name: extract_string(&values[1]),
+        body: extract_optional_string(&values[2]),
+        fields: extract_optional_string(&values[3])
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+    }
+}
+
+fn label_from_values(values: &[RecordValue]) -> Label {
+    Label {
+        id: extract_string(&values[0]),
+        name: extract_string(&values[1]),
+        color: extract_string(&values[2]),
+        description: extract_optional_string(&values[3]),
+    }
+}
+
+fn milestone_from_values(values: &[RecordValue]) -> Milestone {
+    Milestone {
+        id: extract_string(&values[0]),
+        title: extract_string(&values[1]),
+        description: extract_optional_string(&values[2]),
+        due_on: extract_optional_string(&values[3]),
+        created_at: extract_string(&values[4]),
     }
 }
 
@@ -357,26 +1926,231 @@ fn issue_to_json(issue: &Issue) -> serde_json::Value {
         "description": issue.description,
         "status": issue.status,
         "createdAt": issue.created_at,
+        "updatedAt": issue.updated_at,
+        "version": issue.version,
         "repositoryId": issue.repository_id,
+        "templateId": issue.template_id,
+        "customFields": issue
+            .custom_fields
+            .iter()
+            .map(CustomFieldValue::to_json)
+            .collect::<Vec<_>>(),
+        "labels": issue.labels.iter().map(Label::to_json).collect::<Vec<_>>(),
+        "milestone": issue.milestone.as_ref().map(Milestone::to_json),
     })
 }
 
 fn query_issue_by_number(repository_id: &str, number: i64) -> Result<Option<Issue>, String> {
-    let sql = "SELECT id, repository_id, number, title, description, status, created_at FROM issues WHERE repository_id = ? AND number = ?";
+    let sql = format!(
+        "SELECT {} FROM issues WHERE repository_id = ? AND number = ?",
+        ISSUE_COLUMNS
+    );
     let params = vec![
         RecordValue::Text(repository_id.to_string()),
         RecordValue::Integer(number),
     ];
 
+    let issue = match host_database::query(&sql, &params) {
+        host_database::QueryResult::Success(rows) => rows
+            .into_iter()
+            .next()
+            .map(|row| issue_from_values(&row.values)),
+        host_database::QueryResult::Error(e) => return Err(format!("Database error: {}", e)),
+    };
+    match issue {
+        Some(issue) => Ok(load_issue_relations(vec![issue])?.pop()),
+        None => Ok(None),
+    }
+}
+
+/// Issues by repository and number, for many references at once. Missing issues are
+/// left out.
+fn query_issues_by_reference(keys: &[IssueKey]) -> Result<HashMap<IssueKey, Issue>, String> {
+    if keys.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let keys = serde_json::to_string(keys).map_err(|e| format!("Serialization error: {}", e))?;
+    let sql = format!(
+        "SELECT {} FROM issues WHERE (repository_id, number) IN \
+         (SELECT json_extract(value, '$[0]'), json_extract(value, '$[1]') FROM json_each(?))",
+        ISSUE_COLUMNS
+    );
+    let issues = match host_database::query(&sql, &[RecordValue::Text(keys)]) {
+        host_database::QueryResult::Success(rows) => rows
+            .into_iter()
+            .map(|row| issue_from_values(&row.values))
+            .collect(),
+        host_database::QueryResult::Error(e) => return Err(format!("Database error: {}", e)),
+    };
+    Ok(load_issue_relations(issues)?
+        .into_iter()
+        .map(|issue| ((issue.repository_id.clone(), issue.number), issue))
+        .collect())
+}
+
+/// An issue's comments, oldest first, starting after the `(created_at, id)` of `after`.
+fn query_issue_comments(
+    repository_id: &str,
+    issue_number: i64,
+    after: Option<(String, String)>,
+    limit: Option<i64>,
+) -> Result<Vec<Comment>, String> {
+    let mut sql = format!(
+        "SELECT {} FROM issue_comments WHERE repository_id = ? AND issue_number = ?",
+        COMMENT_COLUMNS
+    );
+    let mut params = vec![
+        RecordValue::Text(repository_id.to_string()),
+        RecordValue::Integer(issue_number),
+    ];
+    if let Some((created_at, id)) = after {
+        sql.push_str(" AND (created_at, id) > (?, ?)");
+        params.push(RecordValue::Text(created_at));
+        params.push(RecordValue::Text(id));
+    }
+    sql.push_str(" ORDER BY created_at, id");
+    if let Some(limit) = limit {
+        sql.push_str(" LIMIT ?");
+        params.push(RecordValue::Integer(limit));
+    }
+
+    match host_database::query(&sql, &params) {
+        host_database::QueryResult::Success(rows) => Ok(rows
+            .iter()
+            .map(|row| comment_from_values(&row.values))
+            .collect()),
+        host_database::QueryResult::Error(e) => Err(format!("Database error: {}", e)),
+    }
+}
+
+fn query_comment(repository_id: &str, comment_id: &str) -> Result<Option<Comment>, String> {
+    let sql = format!(
+        "SELECT {} FROM issue_comments WHERE repository_id = ? AND id = ?",
+        COMMENT_COLUMNS
+    );
+    let params = vec![
+        RecordValue::Text(repository_id.to_string()),
+        RecordValue::Text(comment_id.to_string()),
+    ];
+    match host_database::query(&sql, &params) {
+        host_database::QueryResult::Success(rows) => {
+            Ok(rows.first().map(|row| comment_from_values(&row.values)))
+        }
+        host_database::QueryResult::Error(e) => Err(format!("Database error: {}", e)),
+    }
+}
+
+fn query_template(repository_id: &str, template_id: &str) -> Result<Option<IssueTemplate>, String> {
+    let sql =
+        "SELECT id, name, body, fields FROM issue_templates WHERE repository_id = ? AND id = ?";
+    let params = vec![
+        RecordValue::Text(repository_id.to_string()),
+        RecordValue::Text(template_id.to_string()),
+    ];
+
+    match host_database::query(sql, &params) {
+        host_database::QueryResult::Success(rows) => Ok(rows
+            .into_iter()
+            .next()
+            .map(|row| template_from_values(&row.values))),
+        host_database::QueryResult::Error(e) => Err(format!("Database error: {}", e)),
+    }
+}
+
+fn query_label(repository_id: &str, label_id: &str) -> Result<Option<Label>, String> {
+    let sql =
+        "SELECT id, name, color, description FROM issue_labels WHERE repository_id = ? AND id = ?";
+    let params = vec![
+        RecordValue::Text(repository_id.to_string()),
+        RecordValue::Text(label_id.to_string()),
+    ];
+
+    match host_database::query(sql, &params) {
+        host_database::QueryResult::Success(rows) => Ok(rows
+            .into_iter()
+            .next()
+            .map(|row| label_from_values(&row.values))),
+        host_database::QueryResult::Error(e) => Err(format!("Database error: {}", e)),
+    }
+}
+
+fn query_milestone(repository_id: &str, milestone_id: &str) -> Result<Option<Milestone>, String> {
+    let sql = "SELECT id, title, description, due_on, created_at FROM issue_milestones \
+               WHERE repository_id = ? AND id = ?";
+    let params = vec![
+        RecordValue::Text(repository_id.to_string()),
+        RecordValue::Text(milestone_id.to_string()),
+    ];
+
     match host_database::query(sql, &params) {
         host_database::QueryResult::Success(rows) => Ok(rows
             .into_iter()
             .next()
-            .map(|row| issue_from_values(&row.values))),
+            .map(|row| milestone_from_values(&row.values))),
         host_database::QueryResult::Error(e) => Err(format!("Database error: {}", e)),
     }
 }
 
+/// Fills in the labels and milestones of `issues`, with one query for each no matter how
+/// many issues there are.
+fn load_issue_relations(mut issues: Vec<Issue>) -> Result<Vec<Issue>, String> {
+    if issues.is_empty() {
+        return Ok(issues);
+    }
+
+    let issue_ids: Vec<&str> = issues.iter().map(|issue| issue.db_id.as_str()).collect();
+    let issue_ids =
+        serde_json::to_string(&issue_ids).map_err(|e| format!("Serialization error: {}", e))?;
+    let sql = "SELECT issue_label_assignments.issue_id, issue_labels.id, issue_labels.name, \
+               issue_labels.color, issue_labels.description FROM issue_label_assignments \
+               JOIN issue_labels ON issue_labels.id = issue_label_assignments.label_id \
+               WHERE issue_label_assignments.issue_id IN (SELECT value FROM json_each(?)) \
+               ORDER BY issue_labels.name";
+    let mut labels: HashMap<String, Vec<Label>> = HashMap::new();
+    match host_database::query(sql, &[RecordValue::Text(issue_ids)]) {
+        host_database::QueryResult::Success(rows) => {
+            for row in rows {
+                labels
+                    .entry(extract_string(&row.values[0]))
+                    .or_default()
+                    .push(label_from_values(&row.values[1..]));
+            }
+        }
+        host_database::QueryResult::Error(e) => return Err(format!("Database error: {}", e)),
+    }
+
+    let mut milestones: HashMap<String, Milestone> = HashMap::new();
+    let milestone_ids: Vec<&str> = issues
+        .iter()
+        .filter_map(|issue| issue.milestone_id.as_deref())
+        .collect();
+    if !milestone_ids.is_empty() {
+        let milestone_ids = serde_json::to_string(&milestone_ids)
+            .map_err(|e| format!("Serialization error: {}", e))?;
+        let sql = "SELECT id, title, description, due_on, created_at FROM issue_milestones \
+                   WHERE id IN (SELECT value FROM json_each(?))";
+        match host_database::query(sql, &[RecordValue::Text(milestone_ids)]) {
+            host_database::QueryResult::Success(rows) => {
+                for row in rows {
+                    let milestone = milestone_from_values(&row.values);
+                    milestones.insert(milestone.id.clone(), milestone);
+                }
+            }
+            host_database::QueryResult::Error(e) => return Err(format!("Database error: {}", e)),
+        }
+    }
+
+    for issue in &mut issues {
+        issue.labels = labels.remove(&issue.db_id).unwrap_or_default();
+        issue.milestone = issue
+            .milestone_id
+            .as_ref()
+            .and_then(|id| milestones.get(id).cloned());
+    }
+    Ok(issues)
+}
+
 fn next_issue_number(repository_id: &str) -> Result<i64, String> {
     let sql = "SELECT COALESCE(MAX(number), 0) FROM issues WHERE repository_id = ?";
     let params = vec![RecordValue::Text(repository_id.to_string())];
@@ -491,6 +2265,33 @@ fn ensure_repository_schema() -> Result<(), String> {
         }
     }
 
+    for (column, definition) in [
+        ("template_id", "TEXT"),
+        ("custom_fields", "TEXT NOT NULL DEFAULT '[]'"),
+        ("author_did", "TEXT"),
+        ("version", "INTEGER NOT NULL DEFAULT 1"),
+        ("updated_at", "TEXT"),
+        ("milestone_id", "TEXT"),
+    ] {
+        let present = columns.iter().any(
+            |row| matches!(row.values.get(1), Some(RecordValue::Text(name)) if name == column),
+        );
+        if present {
+            continue;
+        }
+        host_log::log(
+            LogLevel::Info,
+            &format!("Migrating issues table to add {} column", column),
+        );
+        let statement = format!("ALTER TABLE issues ADD COLUMN {} {}", column, definition);
+        if let host_database::ExecResult::Error(e) = host_database::execute(&statement, &[]) {
+            return Err(format!(
+                "Failed to add {} column to issues table: {}",
+                column, e
+            ));
+        }
+    }
+
     ensure_index(
         "CREATE INDEX IF NOT EXISTS idx_issues_repository ON issues(repository_id, created_at DESC)",
         "repository index",
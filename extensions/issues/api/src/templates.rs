@@ -0,0 +1,302 @@
+//! Issue templates and the structured fields they require.
+//!
+//! A template declares typed fields. `createIssue` checks the submitted values against
+//! the selected template and stores the coerced result as a JSON array on the issue.
+//! Each stored entry keeps the field's label and type, so later edits to the template
+//! do not change how existing issues render.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Value, json};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FieldType {
+    Text,
+    Number,
+    Boolean,
+    Select,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateField {
+    pub key: String,
+    pub label: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub required: bool,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub options: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IssueTemplate {
+    pub id: String,
+    pub name: String,
+    pub body: Option<String>,
+    pub fields: Vec<TemplateField>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CustomFieldInput {
+    pub key: String,
+    pub value: String,
+}
+
+/// A validated value as stored on the issue.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomFieldValue {
+    pub key: String,
+    pub label: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    pub value: Value,
+}
+
+impl CustomFieldValue {
+    /// GraphQL shape: one typed slot is set, the others are null.
+    pub fn to_json(&self) -> Value {
+        let value = self.value.clone();
+        let (text, number, boolean) = match self.field_type {
+            FieldType::Text | FieldType::Select => (value, Value::Null, Value::Null),
+            FieldType::Number => (Value::Null, value, Value::Null),
+            FieldType::Boolean => (Value::Null, Value::Null, value),
+        };
+        json!({
+            "key": self.key,
+            "label": self.label,
+            "type": self.field_type,
+            "textValue": text,
+            "numberValue": number,
+            "booleanValue": boolean,
+        })
+    }
+}
+
+impl IssueTemplate {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "body": self.body,
+            "fields": self.fields,
+        })
+    }
+}
+
+/// Treats an explicit GraphQL `null` like an omitted optional input field.
+pub fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Checks a template definition before it is saved.
+pub fn validate_template_fields(fields: &[TemplateField]) -> Result<(), String> {
+    for (index, field) in fields.iter().enumerate() {
+        let key = field.key.as_str();
+        if key.is_empty() {
+            return Err(format!("Template field #{} has an empty key", index + 1));
+        }
+        if !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(format!(
+                "Template field key `{}` may only contain letters, digits, `_` and `-`",
+                key
+            ));
+        }
+        if fields[..index].iter().any(|other| other.key == field.key) {
+            return Err(format!("Template field key `{}` is declared twice", key));
+        }
+        if field.field_type == FieldType::Select && field.options.is_empty() {
+            return Err(format!("Select field `{}` needs at least one option", key));
+        }
+    }
+    Ok(())
+}
+
+/// Validates submitted values against `template` and coerces them to their field types.
+///
+/// Values are returned in template order. Without a template no custom fields are accepted.
+pub fn validate_custom_fields(
+    template: Option<&IssueTemplate>,
+    inputs: &[CustomFieldInput],
+) -> Result<Vec<CustomFieldValue>, String> {
+    let Some(template) = template else {
+        return match inputs.first() {
+            Some(input) => Err(format!(
+                "Custom field `{}` requires a templateId",
+                input.key
+            )),
+            None => Ok(Vec::new()),
+        };
+    };
+
+    if let Some(unknown) = inputs
+        .iter()
+        .find(|input| !template.fields.iter().any(|field| field.key == input.key))
+    {
+        return Err(format!(
+            "Template `{}` has no field `{}`",
+            template.name, unknown.key
+        ));
+    }
+
+    let mut values = Vec::new();
+    for field in &template.fields {
+        let mut matching = inputs.iter().filter(|input| input.key == field.key);
+        let input = matching.next();
+        if matching.next().is_some() {
+            return Err(format!("Field `{}` was given more than once", field.key));
+        }
+
+        let raw = input.map(|input| input.value.trim()).unwrap_or_default();
+        if raw.is_empty() {
+            if field.required {
+                return Err(format!("Field `{}` is required", field.label));
+            }
+            continue;
+        }
+
+        let value = match field.field_type {
+            FieldType::Text => Value::String(raw.to_string()),
+            FieldType::Number => raw
+                .parse::<f64>()
+                .ok()
+                .filter(|number| number.is_finite())
+                .map(Value::from)
+                .ok_or_else(|| format!("Field `{}` must be a number", field.label))?,
+            FieldType::Boolean => match raw {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => return Err(format!("Field `{}` must be true or false", field.label)),
+            },
+            FieldType::Select => {
+                if !field.options.iter().any(|option| option == raw) {
+                    return Err(format!(
+                        "Field `{}` must be one of: {}",
+                        field.label,
+                        field.options.join(", ")
+                    ));
+                }
+                Value::String(raw.to_string())
+            }
+        };
+        values.push(CustomFieldValue {
+            key: field.key.clone(),
+            label: field.label.clone(),
+            field_type: field.field_type,
+            value,
+        });
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template() -> IssueTemplate {
+        IssueTemplate {
+            id: "template_1".into(),
+            name: "Bug report".into(),
+            body: None,
+            fields: vec![
+                TemplateField {
+                    key: "severity".into(),
+                    label: "Severity".into(),
+                    field_type: FieldType::Select,
+                    required: true,
+                    options: vec!["low".into(), "high".into()],
+                },
+                TemplateField {
+                    key: "version".into(),
+                    label: "Version".into(),
+                    field_type: FieldType::Text,
+                    required: true,
+                    options: vec![],
+                },
+                TemplateField {
+                    key: "users".into(),
+                    label: "Affected users".into(),
+                    field_type: FieldType::Number,
+                    required: false,
+                    options: vec![],
+                },
+            ],
+        }
+    }
+
+    fn input(key: &str, value: &str) -> CustomFieldInput {
+        CustomFieldInput {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+
+    #[test]
+    fn required_fields_are_enforced_and_values_typed() {
+        let template = template();
+        let err =
+            validate_custom_fields(Some(&template), &[input("severity", "high")]).unwrap_err();
+        assert!(err.contains("Version"), "{err}");
+
+        let values = validate_custom_fields(
+            Some(&template),
+            &[
+                input("version", "1.4.2"),
+                input("severity", "high"),
+                input("users", "12"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[0].key, "severity");
+        assert_eq!(values[2].value, json!(12.0));
+        assert_eq!(values[2].to_json()["numberValue"], json!(12.0));
+        assert_eq!(values[2].to_json()["textValue"], Value::Null);
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let template = template();
+        let check = |key: &str, value: &str| {
+            let mut inputs = vec![input("version", "1.0"), input(key, value)];
+            if key != "severity" {
+                inputs.push(input("severity", "low"));
+            }
+            validate_custom_fields(Some(&template), &inputs)
+        };
+        assert!(check("severity", "low").is_ok());
+        assert!(check("severity", "critical").is_err());
+        assert!(check("users", "many").is_err());
+        assert!(check("unknown", "x").is_err());
+
+        assert!(validate_custom_fields(None, &[input("version", "1.0")]).is_err());
+        assert!(validate_custom_fields(None, &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn null_optional_inputs_deserialize() {
+        let field: TemplateField = serde_json::from_value(json!({
+            "key": "os", "label": "OS", "type": "TEXT", "required": null, "options": null
+        }))
+        .unwrap();
+        assert!(!field.required);
+        assert!(field.options.is_empty());
+    }
+
+    #[test]
+    fn template_definitions_are_checked() {
+        let mut fields = template().fields;
+        assert!(validate_template_fields(&fields).is_ok());
+        fields[2].key = "severity".into();
+        assert!(validate_template_fields(&fields).is_err());
+        fields[2].key = "has space".into();
+        assert!(validate_template_fields(&fields).is_err());
+    }
+}